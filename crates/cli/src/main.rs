@@ -64,12 +64,15 @@ async fn main() -> std::io::Result<()> {
             command.exec(client).await;
         }
         Commands::Server(command) => command.exec(client).await,
+        Commands::Store(command) => command.exec(client).await,
         /*Commands::Account(command) => command.exec(client).await,
         Commands::Domain(command) => command.exec(client).await,
         Commands::List(command) => command.exec(client).await,
         Commands::Group(command) => command.exec(client).await,*/
         Commands::Queue(command) => command.exec(client).await,
         Commands::Report(command) => command.exec(client).await,
+        Commands::Dkim(command) => command.exec(client).await,
+        Commands::Debug(command) => command.exec(client).await,
     }
 
     Ok(())
@@ -56,6 +56,10 @@ pub enum Commands {
     #[clap(subcommand)]
     Server(ServerCommands),
 
+    /// Inspect data store usage
+    #[clap(subcommand)]
+    Store(StoreCommands),
+
     /// Manage SMTP message queue
     #[clap(subcommand)]
     Queue(QueueCommands),
@@ -63,6 +67,14 @@ pub enum Commands {
     /// Manage SMTP DMARC/TLS report queue
     #[clap(subcommand)]
     Report(ReportCommands),
+
+    /// Diagnose DKIM keys and their DNS records
+    #[clap(subcommand)]
+    Dkim(DkimCommands),
+
+    /// Inspect internal data for troubleshooting
+    #[clap(subcommand)]
+    Debug(DebugCommands),
 }
 
 pub struct Client {
@@ -425,6 +437,15 @@ pub enum ServerCommands {
     },
 }
 
+#[derive(Subcommand)]
+pub enum StoreCommands {
+    /// Show the estimated size of each subspace
+    Stats {
+        /// Store id, defaults to the data store
+        store: Option<String>,
+    },
+}
+
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 pub enum MailboxFormat {
     /// Mbox format
@@ -504,6 +525,9 @@ pub enum QueueCommands {
         #[clap(short, long)]
         #[arg(value_parser = parse_datetime)]
         after: Option<DateTime>,
+        /// Notify the sender with a delivery status notification
+        #[clap(long)]
+        bounce: bool,
         // Cancel one or multiple message ids
         ids: Vec<String>,
     },
@@ -538,6 +562,28 @@ pub enum ReportCommands {
     },
 }
 
+#[derive(Subcommand)]
+pub enum DkimCommands {
+    /// Verify that the DKIM, SPF and DMARC records of a domain are published correctly
+    Verify {
+        /// Domain name to verify
+        domain: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum DebugCommands {
+    /// Show the index terms stored for a document
+    IndexDump {
+        /// Account name
+        account: String,
+        /// Collection name or id
+        collection: String,
+        /// Document id
+        document_id: u32,
+    },
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Deserialize)]
 pub enum ReportFormat {
     /// DMARC report
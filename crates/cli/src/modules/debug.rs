@@ -0,0 +1,58 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use prettytable::{Attr, Cell, Row, Table};
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+
+use super::cli::{Client, DebugCommands};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IndexField {
+    pub field: u8,
+    pub terms: Vec<String>,
+}
+
+impl DebugCommands {
+    pub async fn exec(self, client: Client) {
+        match self {
+            DebugCommands::IndexDump {
+                account,
+                collection,
+                document_id,
+            } => {
+                let fields = client
+                    .http_request::<Vec<IndexField>, String>(
+                        Method::GET,
+                        &format!("/api/store/index/{account}/{collection}/{document_id}"),
+                        None,
+                    )
+                    .await;
+
+                if fields.is_empty() {
+                    eprintln!("No index entries found for document {document_id}.");
+                    return;
+                }
+
+                let mut table = Table::new();
+                table.add_row(Row::new(vec![
+                    Cell::new("Field").with_style(Attr::Bold),
+                    Cell::new("Terms").with_style(Attr::Bold),
+                ]));
+                for field in &fields {
+                    table.add_row(Row::new(vec![
+                        Cell::new(&field.field.to_string()),
+                        Cell::new(&field.terms.join("\n")),
+                    ]));
+                }
+
+                eprintln!();
+                table.printstd();
+                eprintln!();
+            }
+        }
+    }
+}
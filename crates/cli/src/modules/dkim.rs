@@ -0,0 +1,110 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use prettytable::{Attr, Cell, Row, Table};
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+
+use super::cli::{Client, DkimCommands};
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DkimDomainCheck {
+    pub domain: String,
+    pub keys: Vec<DkimKeyCheck>,
+    pub spf: RecordCheck,
+    pub dmarc: RecordCheck,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DkimKeyCheck {
+    pub selector: String,
+    pub algorithm: String,
+    pub active: bool,
+    pub dns_name: String,
+    pub dns_record: String,
+    pub record: RecordCheck,
+    pub signature: RecordCheck,
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecordCheck {
+    pub status: String,
+    pub reason: Option<String>,
+}
+
+impl DkimCommands {
+    pub async fn exec(self, client: Client) {
+        match self {
+            DkimCommands::Verify { domain } => {
+                let check = client
+                    .http_request::<DkimDomainCheck, String>(
+                        Method::GET,
+                        &format!("/api/dkim/check/{domain}"),
+                        None,
+                    )
+                    .await;
+                let mut failed = false;
+
+                if !check.keys.is_empty() {
+                    let mut table = Table::new();
+                    table.add_row(Row::new(vec![
+                        Cell::new("Selector").with_style(Attr::Bold),
+                        Cell::new("Algorithm").with_style(Attr::Bold),
+                        Cell::new("Active").with_style(Attr::Bold),
+                        Cell::new("DNS Record").with_style(Attr::Bold),
+                        Cell::new("Signature").with_style(Attr::Bold),
+                    ]));
+
+                    for key in &check.keys {
+                        failed |= key.active
+                            && (key.record.status != "valid" || key.signature.status != "pass");
+                        table.add_row(Row::new(vec![
+                            Cell::new(&key.selector),
+                            Cell::new(&key.algorithm),
+                            Cell::new(if key.active { "Yes" } else { "No" }),
+                            Cell::new(&key.record.to_string()),
+                            Cell::new(&key.signature.to_string()),
+                        ]));
+                    }
+
+                    eprintln!();
+                    table.printstd();
+                    eprintln!();
+
+                    for key in &check.keys {
+                        if key.record.status == "missing" {
+                            eprintln!("Publish {} TXT \"{}\"", key.dns_name, key.dns_record);
+                        }
+                        for warning in &key.warnings {
+                            eprintln!("Warning ({}): {warning}", key.selector);
+                        }
+                    }
+                } else {
+                    eprintln!("\nNo DKIM keys found for {}.", check.domain);
+                }
+
+                eprintln!("\nSPF record: {}", check.spf);
+                eprintln!("DMARC record: {}\n", check.dmarc);
+
+                if failed {
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for RecordCheck {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.reason {
+            Some(reason) => write!(f, "{} ({reason})", self.status),
+            None => f.write_str(&self.status),
+        }
+    }
+}
@@ -15,6 +15,8 @@ use serde::{Deserialize, Serialize};
 pub mod account;
 pub mod cli;
 pub mod database;
+pub mod debug;
+pub mod dkim;
 pub mod domain;
 pub mod export;
 pub mod group;
@@ -22,6 +24,7 @@ pub mod import;
 pub mod list;
 pub mod queue;
 pub mod report;
+pub mod store;
 
 const RETRY_ATTEMPTS: usize = 5;
 
@@ -334,6 +334,7 @@ impl QueueCommands {
                 rcpt,
                 before,
                 after,
+                bounce,
                 ids,
             } => {
                 let (parsed_ids, ids) = if ids.is_empty() {
@@ -364,6 +365,9 @@ impl QueueCommands {
                     if let Some(filter) = &rcpt {
                         query.append_pair("filter", filter);
                     }
+                    if bounce {
+                        query.append_pair("bounce", "true");
+                    }
 
                     if client
                         .try_http_request::<bool, String>(Method::DELETE, &query.finish(), None)
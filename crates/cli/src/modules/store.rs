@@ -0,0 +1,87 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use prettytable::{format::Alignment, Attr, Cell, Row, Table};
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+
+use super::cli::{Client, StoreCommands};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SubspaceStats {
+    pub subspace: String,
+    pub name: String,
+    pub size: u64,
+    pub rows: Option<u64>,
+}
+
+impl StoreCommands {
+    pub async fn exec(self, client: Client) {
+        match self {
+            StoreCommands::Stats { store } => {
+                let url = match &store {
+                    Some(store) => format!("/api/store/stats/{store}"),
+                    None => "/api/store/stats".to_string(),
+                };
+                let mut stats = client
+                    .try_http_request::<Vec<SubspaceStats>, String>(Method::GET, &url, None)
+                    .await
+                    .unwrap_or_else(|| {
+                        eprintln!("Store {} not found.", store.unwrap_or_default());
+                        std::process::exit(1);
+                    });
+                stats.sort_unstable_by(|a, b| b.size.cmp(&a.size).then(a.name.cmp(&b.name)));
+
+                let mut table = Table::new();
+                table.add_row(Row::new(vec![
+                    Cell::new("Subspace").with_style(Attr::Bold),
+                    Cell::new("Name").with_style(Attr::Bold),
+                    Cell::new("Size").with_style(Attr::Bold),
+                    Cell::new("Rows").with_style(Attr::Bold),
+                ]));
+
+                let mut total_size = 0;
+                for subspace in &stats {
+                    total_size += subspace.size;
+                    table.add_row(Row::new(vec![
+                        Cell::new(&subspace.subspace),
+                        Cell::new(&subspace.name),
+                        Cell::new_align(&format_size(subspace.size), Alignment::RIGHT),
+                        Cell::new_align(
+                            &subspace
+                                .rows
+                                .map(|rows| rows.to_string())
+                                .unwrap_or_else(|| "-".to_string()),
+                            Alignment::RIGHT,
+                        ),
+                    ]));
+                }
+
+                eprintln!();
+                table.printstd();
+                eprintln!();
+                eprintln!("Total estimated size: {}\n", format_size(total_size));
+            }
+        }
+    }
+}
+
+fn format_size(size: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut value = size as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{size} B")
+    } else {
+        format!("{value:.2} {}", UNITS[unit])
+    }
+}
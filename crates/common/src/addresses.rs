@@ -13,10 +13,50 @@ use crate::{
     config::smtp::session::AddressMapping,
     expr::{
         functions::ResolveVariable, if_block::IfBlock, tokenizer::TokenMap, Variable, V_RECIPIENT,
+        V_RECIPIENTS, V_RECIPIENT_DOMAIN,
     },
     Server,
 };
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubAddress {
+    pub user: String,
+    pub detail: String,
+    pub domain: String,
+}
+
+impl SubAddress {
+    // The Sieve subaddress extension always splits on '+'
+    pub fn to_sieve_address(&self) -> String {
+        format!("{}+{}@{}", self.user, self.detail, self.domain)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecipientMatch {
+    Exact,
+    Wildcard,
+    SubAddress,
+    CatchAll,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedRecipient {
+    pub account_id: u32,
+    pub subaddress: Option<SubAddress>,
+    pub matched: RecipientMatch,
+}
+
+impl ResolvedRecipient {
+    // Whether the recipient address is not one of the account's own addresses
+    pub fn is_indirect(&self) -> bool {
+        matches!(
+            self.matched,
+            RecipientMatch::Wildcard | RecipientMatch::CatchAll
+        )
+    }
+}
+
 impl Server {
     pub async fn email_to_id(
         &self,
@@ -24,32 +64,38 @@ impl Server {
         email: &str,
         session_id: u64,
     ) -> trc::Result<Option<u32>> {
-        let mut address = self
-            .core
-            .smtp
-            .session
-            .rcpt
-            .subaddressing
-            .to_subaddress(self, email, session_id)
-            .await;
+        self.resolve_recipient(directory, email, session_id)
+            .await
+            .map(|result| result.map(|rcpt| rcpt.account_id))
+    }
 
-        for _ in 0..2 {
-            let result = directory.email_to_id(address.as_ref()).await?;
-
-            if result.is_some() {
-                return Ok(result);
-            } else if let Some(catch_all) = self
-                .core
-                .smtp
-                .session
-                .rcpt
-                .catch_all
-                .to_catch_all(self, email, session_id)
-                .await
-            {
-                address = catch_all;
-            } else {
-                break;
+    // Resolves an address to an account id, trying in order the exact address,
+    // wildcard aliases, the sub-address base and the domain catch-all
+    pub async fn resolve_recipient(
+        &self,
+        directory: &Directory,
+        email: &str,
+        session_id: u64,
+    ) -> trc::Result<Option<ResolvedRecipient>> {
+        for (address, matched) in self.recipient_candidates(email, session_id).await {
+            if let Some(account_id) = directory.email_to_id(&address).await? {
+                let subaddress = if matched == RecipientMatch::SubAddress {
+                    self.core
+                        .smtp
+                        .session
+                        .rcpt
+                        .subaddressing
+                        .to_detail(self, email, session_id)
+                        .await
+                } else {
+                    None
+                };
+
+                return Ok(Some(ResolvedRecipient {
+                    account_id,
+                    subaddress,
+                    matched,
+                }));
             }
         }
 
@@ -62,36 +108,86 @@ impl Server {
         email: &str,
         session_id: u64,
     ) -> trc::Result<RcptType> {
-        // Expand subaddress
-        let mut address = self
-            .core
-            .smtp
-            .session
-            .rcpt
+        for (address, _) in self.recipient_candidates(email, session_id).await {
+            let rcpt_type = directory.rcpt(&address).await?;
+            if rcpt_type != RcptType::Invalid {
+                return Ok(rcpt_type);
+            }
+        }
+
+        Ok(RcptType::Invalid)
+    }
+
+    async fn recipient_candidates(
+        &self,
+        email: &str,
+        session_id: u64,
+    ) -> Vec<(String, RecipientMatch)> {
+        let rcpt = &self.core.smtp.session.rcpt;
+        let mut candidates = vec![(email.to_string(), RecipientMatch::Exact)];
+
+        // Wildcard aliases, longest prefix first
+        if let Some((local_part, domain_part)) = email.rsplit_once('@') {
+            candidates.extend(
+                local_part
+                    .char_indices()
+                    .rev()
+                    .filter(|(pos, ch)| {
+                        *pos > 0 && *pos + 1 < local_part.len() && matches!(ch, '-' | '.' | '_')
+                    })
+                    .map(|(pos, ch)| {
+                        (
+                            format!("{}{ch}*@{domain_part}", &local_part[..pos]),
+                            RecipientMatch::Wildcard,
+                        )
+                    }),
+            );
+        }
+
+        // Sub-address base
+        let address = rcpt
             .subaddressing
             .to_subaddress(self, email, session_id)
             .await;
+        if address.as_ref() != email {
+            candidates.push((address.into_owned(), RecipientMatch::SubAddress));
+        }
 
-        for _ in 0..2 {
-            let rcpt_type = directory.rcpt(address.as_ref()).await?;
-            if rcpt_type != RcptType::Invalid {
-                return Ok(rcpt_type);
-            } else if let Some(catch_all) = self
-                .core
-                .smtp
-                .session
-                .rcpt
-                .catch_all
-                .to_catch_all(self, email, session_id)
-                .await
+        // Domain catch-all
+        if let Some(catch_all) = rcpt.catch_all.to_catch_all(self, email, session_id).await {
+            if !candidates
+                .iter()
+                .any(|(address, _)| address == catch_all.as_ref())
             {
-                address = catch_all;
-            } else {
-                break;
+                candidates.push((catch_all.into_owned(), RecipientMatch::CatchAll));
             }
         }
 
-        Ok(RcptType::Invalid)
+        candidates
+    }
+
+    async fn has_catch_all(
+        &self,
+        directory: &Directory,
+        email: &str,
+        session_id: u64,
+    ) -> trc::Result<bool> {
+        if let Some(catch_all) = self
+            .core
+            .smtp
+            .session
+            .rcpt
+            .catch_all
+            .to_catch_all(self, email, session_id)
+            .await
+        {
+            directory
+                .rcpt(catch_all.as_ref())
+                .await
+                .map(|rcpt_type| rcpt_type != RcptType::Invalid)
+        } else {
+            Ok(false)
+        }
     }
 
     pub async fn vrfy(
@@ -100,6 +196,12 @@ impl Server {
         address: &str,
         session_id: u64,
     ) -> trc::Result<Vec<String>> {
+        // Every address is deliverable on catch-all domains, avoid disclosing
+        // which ones belong to an account
+        if self.has_catch_all(directory, address, session_id).await? {
+            return Ok(vec![address.to_string()]);
+        }
+
         directory
             .vrfy(
                 self.core
@@ -174,6 +276,22 @@ impl ResolveVariable for Address<'_> {
     }
 }
 
+struct Recipient<'x>(&'x str);
+
+impl ResolveVariable for Recipient<'_> {
+    fn resolve_variable(&self, variable: u32) -> crate::expr::Variable {
+        match variable {
+            V_RECIPIENT_DOMAIN => self
+                .0
+                .rsplit_once('@')
+                .map(|(_, domain)| Variable::from(domain))
+                .unwrap_or_default(),
+            V_RECIPIENT | V_RECIPIENTS => Variable::from(self.0),
+            _ => Variable::default(),
+        }
+    }
+}
+
 impl AddressMapping {
     pub async fn to_subaddress<'x, 'y: 'x>(
         &'x self,
@@ -183,10 +301,8 @@ impl AddressMapping {
     ) -> Cow<'x, str> {
         match self {
             AddressMapping::Enable => {
-                if let Some((local_part, domain_part)) = address.rsplit_once('@') {
-                    if let Some((local_part, _)) = local_part.split_once('+') {
-                        return format!("{}@{}", local_part, domain_part).into();
-                    }
+                if let Some(subaddress) = split_subaddress(core, address, session_id).await {
+                    return format!("{}@{}", subaddress.user, subaddress.domain).into();
                 }
             }
             AddressMapping::Custom(if_block) => {
@@ -203,6 +319,18 @@ impl AddressMapping {
         address.into()
     }
 
+    pub async fn to_detail(
+        &self,
+        core: &Server,
+        address: &str,
+        session_id: u64,
+    ) -> Option<SubAddress> {
+        match self {
+            AddressMapping::Enable => split_subaddress(core, address, session_id).await,
+            AddressMapping::Custom(_) | AddressMapping::Disable => None,
+        }
+    }
+
     pub async fn to_catch_all<'x, 'y: 'x>(
         &'x self,
         core: &Server,
@@ -222,3 +350,26 @@ impl AddressMapping {
         }
     }
 }
+
+async fn split_subaddress(core: &Server, address: &str, session_id: u64) -> Option<SubAddress> {
+    let separator = core
+        .eval_if::<String, _>(
+            &core.core.smtp.session.rcpt.subaddressing_separator,
+            &Recipient(address),
+            session_id,
+        )
+        .await
+        .and_then(|separator| separator.chars().next())?;
+    let (local_part, domain_part) = address.rsplit_once('@')?;
+    let (user, detail) = local_part.split_once(separator)?;
+
+    if !user.is_empty() {
+        Some(SubAddress {
+            user: user.to_string(),
+            detail: detail.to_string(),
+            domain: domain_part.to_string(),
+        })
+    } else {
+        None
+    }
+}
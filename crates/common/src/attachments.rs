@@ -0,0 +1,320 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::io::{Cursor, Read};
+
+use ahash::AHashSet;
+use flate2::read::GzDecoder;
+use mail_parser::{Message, MimeHeaders};
+
+use crate::config::spamfilter::{AttachmentConfig, FilterAction};
+
+// Extensions of files that can be executed or run scripts when opened
+static EXECUTABLE_EXTENSIONS: &[&str] = &[
+    "ade", "adp", "app", "apk", "appx", "bat", "cab", "chm", "cmd", "com", "cpl", "dll", "dmg",
+    "elf", "exe", "gadget", "hta", "inf", "ins", "iso", "isp", "jar", "js", "jse", "lib", "lnk",
+    "mde", "msc", "msi", "msix", "msp", "mst", "nsh", "pif", "ps1", "psm1", "reg", "scr", "sct",
+    "shb", "shs", "sys", "vb", "vbe", "vbs", "vxd", "wsc", "wsf", "wsh",
+];
+
+// Compression ratios are only checked for entries larger than this size
+const MIN_RATIO_CHECK_SIZE: u64 = 64 * 1024;
+const TAR_BLOCK_SIZE: usize = 512;
+
+#[derive(Debug, Default)]
+pub struct AttachmentReport {
+    pub files: Vec<AttachmentFile>,
+    pub encrypted: bool,
+    pub macros: bool,
+    pub double_extension: bool,
+    pub bomb: bool,
+    pub truncated: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttachmentFile {
+    pub name: String,
+    pub depth: usize,
+    pub extension: Option<String>,
+    pub file_type: Option<&'static str>,
+}
+
+struct Inspector<'x> {
+    config: &'x AttachmentConfig,
+    report: AttachmentReport,
+    remaining_size: u64,
+    remaining_entries: usize,
+}
+
+impl AttachmentConfig {
+    // Walks the MIME tree and any archives found in it, all decompression is bounded
+    // by the configured depth, entry count, total size and compression ratio limits
+    pub fn inspect(&self, message: &Message<'_>) -> AttachmentReport {
+        let mut inspector = Inspector {
+            config: self,
+            report: AttachmentReport::default(),
+            remaining_size: self.max_size as u64,
+            remaining_entries: self.max_entries,
+        };
+        inspector.inspect_message(message, 0);
+        inspector.report
+    }
+}
+
+impl Inspector<'_> {
+    fn inspect_message(&mut self, message: &Message<'_>, depth: usize) {
+        for part in message.attachments() {
+            if let Some(message) = part.message() {
+                if depth < self.config.max_depth {
+                    self.inspect_message(message, depth + 1);
+                } else {
+                    self.report.truncated = true;
+                }
+            } else {
+                self.inspect_file(
+                    part.attachment_name().unwrap_or_default(),
+                    part.contents(),
+                    depth,
+                );
+            }
+        }
+    }
+
+    fn inspect_file(&mut self, name: &str, contents: &[u8], depth: usize) {
+        let file_type = infer::get(contents).map(|t| t.extension());
+        let name = name.trim();
+        let extension = file_extension(name);
+        if is_double_extension(name) {
+            self.report.double_extension = true;
+        }
+        self.report.files.push(AttachmentFile {
+            name: name.to_string(),
+            depth,
+            extension,
+            file_type,
+        });
+
+        // Office documents and most archives are ZIP files regardless of the detected type
+        let is_zip = contents.starts_with(b"PK\x03\x04") || contents.starts_with(b"PK\x05\x06");
+        let is_gzip = file_type == Some("gz");
+        let is_tar = file_type == Some("tar");
+        if is_zip || is_gzip || is_tar {
+            if depth < self.config.max_depth {
+                if is_zip {
+                    self.inspect_zip(contents, depth + 1);
+                } else if is_gzip {
+                    let name = name
+                        .strip_suffix(".gz")
+                        .or_else(|| name.strip_suffix(".tgz"))
+                        .unwrap_or(name);
+                    self.inspect_gzip(name, contents, depth + 1);
+                } else {
+                    self.inspect_tar(contents, depth + 1);
+                }
+            } else {
+                self.report.truncated = true;
+            }
+        }
+    }
+
+    fn inspect_zip(&mut self, contents: &[u8], depth: usize) {
+        let Ok(mut archive) = zip::ZipArchive::new(Cursor::new(contents)) else {
+            return;
+        };
+
+        for index in 0..archive.len() {
+            if self.remaining_entries == 0 {
+                self.report.truncated = true;
+                return;
+            }
+            self.remaining_entries -= 1;
+
+            // Obtain the entry metadata without decrypting or decompressing it
+            let Ok(entry) = archive.by_index_raw(index) else {
+                continue;
+            };
+            if entry.is_dir() {
+                continue;
+            }
+            let name = entry.name().to_string();
+            let size = entry.size();
+            let compressed_size = entry.compressed_size();
+            let encrypted = entry.encrypted();
+            drop(entry);
+
+            if name
+                .rsplit('/')
+                .next()
+                .is_some_and(|name| name.eq_ignore_ascii_case("vbaProject.bin"))
+            {
+                self.report.macros = true;
+            }
+
+            if encrypted {
+                self.report.encrypted = true;
+                self.inspect_file(file_name(&name), &[], depth);
+                continue;
+            } else if size > self.remaining_size || self.exceeds_ratio(size, compressed_size) {
+                self.report.bomb = true;
+                self.inspect_file(file_name(&name), &[], depth);
+                continue;
+            }
+
+            // The declared size can't be trusted, read at most the remaining size
+            if let Some(data) = archive
+                .by_index(index)
+                .ok()
+                .and_then(|entry| self.read_limited(entry))
+            {
+                self.inspect_file(file_name(&name), &data, depth);
+            }
+        }
+    }
+
+    fn inspect_gzip(&mut self, name: &str, contents: &[u8], depth: usize) {
+        if self.remaining_entries == 0 {
+            self.report.truncated = true;
+            return;
+        }
+        self.remaining_entries -= 1;
+
+        if let Some(data) = self.read_limited(GzDecoder::new(contents)) {
+            if self.exceeds_ratio(data.len() as u64, contents.len() as u64) {
+                self.report.bomb = true;
+                self.inspect_file(file_name(name), &[], depth);
+            } else {
+                self.inspect_file(file_name(name), &data, depth);
+            }
+        }
+    }
+
+    fn inspect_tar(&mut self, contents: &[u8], depth: usize) {
+        let mut offset = 0;
+        while let Some(header) = contents.get(offset..offset + TAR_BLOCK_SIZE) {
+            if header.iter().all(|&ch| ch == 0) {
+                break;
+            } else if self.remaining_entries == 0 {
+                self.report.truncated = true;
+                return;
+            }
+            self.remaining_entries -= 1;
+
+            let Some(size) = std::str::from_utf8(&header[124..136])
+                .ok()
+                .map(|size| size.trim_matches(|ch: char| ch == '\0' || ch == ' '))
+                .and_then(|size| usize::from_str_radix(size, 8).ok())
+            else {
+                return;
+            };
+            let name = String::from_utf8_lossy(
+                header[..100]
+                    .split(|&ch| ch == 0)
+                    .next()
+                    .unwrap_or_default(),
+            );
+            let start = offset + TAR_BLOCK_SIZE;
+            let data = contents
+                .get(start..start.saturating_add(size))
+                .unwrap_or_default();
+
+            // Only regular files are inspected
+            if matches!(header[156], b'0' | 0) {
+                self.inspect_file(file_name(&name), data, depth);
+            }
+
+            offset = start.saturating_add(size.div_ceil(TAR_BLOCK_SIZE) * TAR_BLOCK_SIZE);
+        }
+    }
+
+    fn exceeds_ratio(&self, size: u64, compressed_size: u64) -> bool {
+        size > MIN_RATIO_CHECK_SIZE && size / compressed_size.max(1) > self.config.max_ratio
+    }
+
+    fn read_limited(&mut self, reader: impl Read) -> Option<Vec<u8>> {
+        let mut data = Vec::new();
+        let read = reader
+            .take(self.remaining_size + 1)
+            .read_to_end(&mut data)
+            .ok()? as u64;
+        if read <= self.remaining_size {
+            self.remaining_size -= read;
+            Some(data)
+        } else {
+            // Exhaust the size budget so no further content gets decompressed
+            self.report.bomb = true;
+            self.remaining_size = 0;
+            None
+        }
+    }
+}
+
+impl AttachmentReport {
+    // Returns the extensions, detected file types and flags found in the message
+    pub fn types(&self) -> Vec<&str> {
+        let mut types = AHashSet::new();
+        for file in &self.files {
+            if let Some(extension) = &file.extension {
+                types.insert(extension.as_str());
+            }
+            if let Some(file_type) = file.file_type {
+                types.insert(file_type);
+            }
+        }
+        for (flag, name) in [
+            (self.encrypted, "encrypted"),
+            (self.macros, "macro"),
+            (self.double_extension, "double-extension"),
+            (self.bomb, "bomb"),
+            (self.truncated, "truncated"),
+        ] {
+            if flag {
+                types.insert(name);
+            }
+        }
+
+        let mut types = types.into_iter().collect::<Vec<_>>();
+        types.sort_unstable();
+        types
+    }
+
+    // Returns the first policy matching any of the types found in the message
+    pub fn matching_policy(&self, config: &AttachmentConfig) -> Option<(FilterAction, Vec<&str>)> {
+        let types = self.types();
+        config.policies.iter().find_map(|(action, policy)| {
+            let matched = types
+                .iter()
+                .filter(|t| policy.contains(**t))
+                .copied()
+                .collect::<Vec<_>>();
+            (!matched.is_empty()).then_some((*action, matched))
+        })
+    }
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit(['/', '\\']).next().unwrap_or(path)
+}
+
+fn file_extension(name: &str) -> Option<String> {
+    name.rsplit_once('.')
+        .map(|(_, ext)| ext.trim().to_lowercase())
+        .filter(|ext| !ext.is_empty())
+}
+
+fn is_double_extension(name: &str) -> bool {
+    // Detects names such as "invoice.pdf.exe" where the real extension is hidden
+    let mut parts = name.rsplit('.').map(|part| part.trim());
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(ext), Some(inner_ext), Some(stem)) => {
+            !stem.is_empty()
+                && (1..=5).contains(&inner_ext.len())
+                && inner_ext.chars().all(|ch| ch.is_ascii_alphanumeric())
+                && EXECUTABLE_EXTENSIONS.contains(&ext.to_lowercase().as_str())
+                && !EXECUTABLE_EXTENSIONS.contains(&inner_ext.to_lowercase().as_str())
+        }
+        _ => false,
+    }
+}
@@ -7,20 +7,48 @@
 use std::{net::IpAddr, sync::Arc, time::Instant};
 
 use directory::{
-    core::secret::verify_secret_hash, Directory, Permission, Permissions, Principal, QueryBy,
+    backend::internal::{
+        manage::{ManageDirectory, UpdatePrincipal},
+        PrincipalField, PrincipalUpdate, PrincipalValue,
+    },
+    core::{
+        secret::verify_secret_hash,
+        totp::{is_recovery_code, is_totp_code},
+    },
+    Directory, Permission, Permissions, Principal, QueryBy,
 };
 use jmap_proto::types::collection::Collection;
 use mail_send::Credentials;
 use oauth::GrantType;
+use store::{blake3, write::now};
+use tokio::sync::broadcast;
+use trc::AddContext;
 use utils::map::{bitmap::Bitmap, ttl_dashmap::TtlMap, vec_map::VecMap};
 
-use crate::Server;
+use crate::{
+    config::server::ServerProtocol,
+    ipc::StateEvent,
+    telemetry::audit::{AuditEvent, AuditRecord},
+    Server,
+};
 
 pub mod access_token;
 pub mod oauth;
 pub mod roles;
 pub mod sasl;
 
+// Accepted TOTP time steps are remembered for the length of the skew window
+const TOTP_REPLAY_EXPIRY: u64 = 90;
+
+// Claimed recovery codes are remembered for a day, well after their removal
+const RECOVERY_CODE_CLAIM_EXPIRY: u64 = 86400;
+
+// Generated TOTP secrets have to be verified within 10 minutes
+const TOTP_ENROLLMENT_EXPIRY: u64 = 600;
+
+// Revoked app passwords are remembered for a week, sessions are notified right away
+const APP_PASSWORD_REVOCATION_EXPIRY: u64 = 7 * 86400;
+
 #[derive(Debug, Clone, Default)]
 pub struct AccessToken {
     pub primary_id: u32,
@@ -53,15 +81,34 @@ pub struct AuthRequest<'x> {
     remote_ip: IpAddr,
     return_member_of: bool,
     directory: Option<&'x Directory>,
+    protocol: ServerProtocol,
+}
+
+// App password a session authenticated with
+#[derive(Debug, Clone)]
+pub struct SessionAppPassword {
+    pub account_id: u32,
+    pub secret: String,
 }
 
 impl Server {
     pub async fn authenticate(&self, req: &AuthRequest<'_>) -> trc::Result<Arc<AccessToken>> {
+        self.authenticate_session(req)
+            .await
+            .map(|(access_token, _)| access_token)
+    }
+
+    // Also returns the app password used to authenticate, if any
+    pub async fn authenticate_session(
+        &self,
+        req: &AuthRequest<'_>,
+    ) -> trc::Result<(Arc<AccessToken>, Option<SessionAppPassword>)> {
         // Resolve directory
         let directory = req.directory.unwrap_or(&self.core.storage.directory);
 
         // Validate credentials
-        match &req.credentials {
+        let mut app_password = None;
+        let result = match &req.credentials {
             Credentials::OAuthBearer { token } if !directory.has_bearer_token_support() => {
                 match self
                     .validate_access_token(GrantType::AccessToken.into(), token)
@@ -72,50 +119,113 @@ impl Server {
                 }
             }
             _ => match self.authenticate_credentials(req, directory).await {
-                Ok(principal) => {
-                    if let Some(access_token) =
-                        self.inner.data.access_tokens.get_with_ttl(&principal.id())
-                    {
-                        Ok(access_token)
-                    } else {
-                        self.build_access_token(principal)
-                            .await
-                            .map(|access_token| {
-                                let access_token = Arc::new(access_token);
-                                self.cache_access_token(access_token.clone());
-                                access_token
-                            })
-                    }
+                Ok((principal, secret)) => {
+                    app_password = secret.map(|secret| SessionAppPassword {
+                        account_id: principal.id(),
+                        secret,
+                    });
+                    self.principal_access_token(principal).await
                 }
                 Err(err) => Err(err),
             },
+        };
+
+        self.finish_authentication(
+            result,
+            req.credentials.login().unwrap_or_default(),
+            req.credentials.mechanism(),
+            req.remote_ip,
+        )
+        .await
+        .map(|access_token| (access_token, app_password))
+    }
+
+    pub(crate) async fn principal_access_token(
+        &self,
+        principal: Principal,
+    ) -> trc::Result<Arc<AccessToken>> {
+        if let Some(access_token) = self.inner.data.access_tokens.get_with_ttl(&principal.id()) {
+            Ok(access_token)
+        } else {
+            self.build_access_token(principal)
+                .await
+                .map(|access_token| {
+                    let access_token = Arc::new(access_token);
+                    self.cache_access_token(access_token.clone());
+                    access_token
+                })
         }
-        .and_then(|token| {
+    }
+
+    pub(crate) async fn finish_authentication(
+        &self,
+        result: trc::Result<Arc<AccessToken>>,
+        login: &str,
+        mechanism: &str,
+        remote_ip: IpAddr,
+    ) -> trc::Result<Arc<AccessToken>> {
+        let result = result.and_then(|token| {
             token
                 .assert_has_permission(Permission::Authenticate)
                 .map(|_| token)
-        })
+        });
+
+        // Record the login attempt
+        if self.core.audit.enable {
+            let actor = match &result {
+                Ok(token) => token.name.as_str(),
+                Err(_) => login,
+            };
+            self.audit(
+                AuditRecord::new(
+                    actor,
+                    AuditEvent::Login {
+                        mechanism: mechanism.to_string(),
+                    },
+                )
+                .with_remote_ip(remote_ip)
+                .with_result(&result),
+            )
+            .await;
+        }
+
+        // Exempt addresses with successful logins from automatic bans
+        if result.is_ok() {
+            if let Err(err) = self.record_authenticated_ip(remote_ip).await {
+                trc::error!(err
+                    .caused_by(trc::location!())
+                    .details("Failed to record authenticated address"));
+            }
+        }
+
+        result
     }
 
     async fn authenticate_credentials(
         &self,
         req: &AuthRequest<'_>,
         directory: &Directory,
-    ) -> trc::Result<Principal> {
+    ) -> trc::Result<(Principal, Option<String>)> {
         // First try to authenticate the user against the default directory
         let result = match directory
             .query(QueryBy::Credentials(&req.credentials), req.return_member_of)
             .await
         {
             Ok(Some(principal)) => {
+                let app_password = self.verify_app_password(req, &principal).await?;
+                if app_password.is_none() {
+                    self.verify_second_factor(req, &principal).await?;
+                }
+
                 trc::event!(
                     Auth(trc::AuthEvent::Success),
                     AccountName = principal.name().to_string(),
                     AccountId = principal.id(),
                     SpanId = req.session_id,
+                    Mechanism = req.credentials.mechanism(),
                 );
 
-                return Ok(principal);
+                return Ok((principal, app_password));
             }
             Ok(None) => Ok(()),
             Err(err) => {
@@ -141,9 +251,10 @@ impl Server {
                         Auth(trc::AuthEvent::Success),
                         AccountName = username.clone(),
                         SpanId = req.session_id,
+                        Mechanism = req.credentials.mechanism(),
                     );
 
-                    return Ok(Principal::fallback_admin(fallback_pass));
+                    return Ok((Principal::fallback_admin(fallback_pass), None));
                 }
             }
             (_, Some((master_user, master_pass)), Credentials::Plain { username, secret })
@@ -163,9 +274,10 @@ impl Server {
                             SpanId = req.session_id,
                             AccountId = principal.id(),
                             Type = principal.typ().as_str(),
+                            Mechanism = req.credentials.mechanism(),
                         );
 
-                        return Ok(principal);
+                        return Ok((principal, None));
                     }
                 }
             }
@@ -174,28 +286,303 @@ impl Server {
 
         if let Err(err) = result {
             Err(err)
-        } else if self.has_auth_fail2ban() {
-            let login = req.credentials.login();
-            if self.is_auth_fail2banned(req.remote_ip, login).await? {
-                Err(trc::SecurityEvent::AuthenticationBan
-                    .into_err()
-                    .ctx(trc::Key::RemoteIp, req.remote_ip)
-                    .ctx_opt(trc::Key::AccountName, login.map(|s| s.to_string())))
-            } else {
-                Err(trc::AuthEvent::Failed
-                    .ctx(trc::Key::RemoteIp, req.remote_ip)
-                    .ctx_opt(trc::Key::AccountName, login.map(|s| s.to_string())))
+        } else {
+            Err(self
+                .authentication_failed(
+                    req.remote_ip,
+                    req.credentials.login(),
+                    req.credentials.mechanism(),
+                )
+                .await)
+        }
+    }
+
+    // Enforces app password restrictions and the primary password policy
+    async fn verify_app_password(
+        &self,
+        req: &AuthRequest<'_>,
+        principal: &Principal,
+    ) -> trc::Result<Option<String>> {
+        let Credentials::Plain { secret, .. } = &req.credentials else {
+            return Ok(None);
+        };
+
+        if let Some(app_password) = principal.verify_app_password(secret).await? {
+            if !app_password.is_allowed(req.protocol.as_str(), req.remote_ip) {
+                return Err(self
+                    .authentication_failed(
+                        req.remote_ip,
+                        req.credentials.login(),
+                        req.credentials.mechanism(),
+                    )
+                    .await
+                    .details("App password not allowed for this protocol or address"));
+            }
+
+            // Record the last time the app password was used
+            if let Err(err) = self
+                .lookup_store()
+                .key_set(
+                    app_password_key(principal.id(), app_password.name),
+                    now().to_string().into_bytes(),
+                    None,
+                )
+                .await
+            {
+                trc::error!(err
+                    .caused_by(trc::location!())
+                    .details("Failed to record app password use"));
+            }
+
+            return Ok(Some(app_password.to_string()));
+        }
+
+        if self.is_app_password_required(req.protocol) {
+            Err(self
+                .authentication_failed(
+                    req.remote_ip,
+                    req.credentials.login(),
+                    req.credentials.mechanism(),
+                )
+                .await
+                .details("An app password is required for this protocol"))
+        } else {
+            Ok(None)
+        }
+    }
+
+    // Rejects replayed TOTP codes and consumes used recovery codes
+    async fn verify_second_factor(
+        &self,
+        req: &AuthRequest<'_>,
+        principal: &Principal,
+    ) -> trc::Result<()> {
+        let Credentials::Plain { secret, .. } = &req.credentials else {
+            return Ok(());
+        };
+        let Some((_, token)) = secret.rsplit_once('$').filter(|_| principal.has_totp()) else {
+            return Ok(());
+        };
+
+        if is_totp_code(token) {
+            let key = totp_key(principal.id());
+            let last_step = self
+                .lookup_store()
+                .key_get::<String>(key.clone())
+                .await?
+                .and_then(|step| step.parse().ok());
+            if let Some(step) = principal.totp_time_step(token, now(), last_step)? {
+                // Concurrent logins with the same code race for the step
+                if self.claim_totp_step(principal.id(), step).await? {
+                    return self.record_totp_step(principal.id(), step).await;
+                }
+            }
+            Err(self
+                .authentication_failed(
+                    req.remote_ip,
+                    req.credentials.login(),
+                    req.credentials.mechanism(),
+                )
+                .await
+                .details("TOTP code has already been used"))
+        } else if is_recovery_code(token) {
+            if let Some(recovery_code) = principal.verify_recovery_code(token).await? {
+                // The principal may be stale, so the code is claimed before removing it
+                if !self
+                    .claim_recovery_code(principal.id(), recovery_code)
+                    .await?
+                {
+                    return Err(self
+                        .authentication_failed(
+                            req.remote_ip,
+                            req.credentials.login(),
+                            req.credentials.mechanism(),
+                        )
+                        .await
+                        .details("Recovery code has already been used"));
+                }
+                self.core
+                    .storage
+                    .data
+                    .update_principal(UpdatePrincipal::by_id(principal.id()).with_updates(vec![
+                        PrincipalUpdate::remove_item(
+                            PrincipalField::Secrets,
+                            PrincipalValue::String(recovery_code.to_string()),
+                        ),
+                    ]))
+                    .await
+                    .caused_by(trc::location!())?;
             }
+            Ok(())
         } else {
-            Err(trc::AuthEvent::Failed
-                .ctx(trc::Key::RemoteIp, req.remote_ip)
-                .ctx_opt(
-                    trc::Key::AccountName,
-                    req.credentials.login().map(|s| s.to_string()),
-                ))
+            Ok(())
         }
     }
 
+    // Returns true only for the first caller claiming the time step
+    pub async fn claim_totp_step(&self, account_id: u32, step: u64) -> trc::Result<bool> {
+        self.lookup_store()
+            .counter_incr(
+                format!("totp-step:{account_id}:{step}").into_bytes(),
+                1,
+                Some(TOTP_REPLAY_EXPIRY),
+                true,
+            )
+            .await
+            .map(|claims| claims == 1)
+    }
+
+    // Returns true only for the first caller claiming the recovery code
+    pub async fn claim_recovery_code(&self, account_id: u32, code: &str) -> trc::Result<bool> {
+        self.lookup_store()
+            .counter_incr(
+                format!(
+                    "recovery-code:{account_id}:{}",
+                    blake3::hash(code.as_bytes()).to_hex()
+                )
+                .into_bytes(),
+                1,
+                Some(RECOVERY_CODE_CLAIM_EXPIRY),
+                true,
+            )
+            .await
+            .map(|claims| claims == 1)
+    }
+
+    // Codes for this or earlier time steps are rejected until they expire
+    pub async fn record_totp_step(&self, account_id: u32, step: u64) -> trc::Result<()> {
+        self.lookup_store()
+            .key_set(
+                totp_key(account_id),
+                step.to_string().into_bytes(),
+                Some(TOTP_REPLAY_EXPIRY),
+            )
+            .await
+    }
+
+    pub async fn begin_totp_enrollment(&self, account_id: u32, url: &str) -> trc::Result<()> {
+        self.lookup_store()
+            .key_set(
+                totp_enrollment_key(account_id),
+                url.as_bytes().to_vec(),
+                Some(TOTP_ENROLLMENT_EXPIRY),
+            )
+            .await
+    }
+
+    pub async fn pending_totp_enrollment(&self, account_id: u32) -> trc::Result<Option<String>> {
+        self.lookup_store()
+            .key_get::<String>(totp_enrollment_key(account_id))
+            .await
+    }
+
+    pub async fn clear_totp_enrollment(&self, account_id: u32) -> trc::Result<()> {
+        self.lookup_store()
+            .key_delete(totp_enrollment_key(account_id))
+            .await
+    }
+
+    pub fn is_app_password_required(&self, protocol: ServerProtocol) -> bool {
+        self.core.jmap.app_password_required.contains(&protocol)
+    }
+
+    pub async fn is_app_password_revoked(&self, app_password: &SessionAppPassword) -> bool {
+        match self
+            .lookup_store()
+            .key_exists(app_password_revocation_key(&app_password.secret))
+            .await
+        {
+            Ok(is_revoked) => is_revoked,
+            Err(err) => {
+                trc::error!(err
+                    .caused_by(trc::location!())
+                    .details("Failed to check app password revocation"));
+                false
+            }
+        }
+    }
+
+    // Sessions on this and other nodes re-check their app password when notified
+    pub async fn revoke_app_passwords(
+        &self,
+        account_id: u32,
+        app_passwords: impl IntoIterator<Item = String>,
+    ) -> trc::Result<()> {
+        for app_password in app_passwords {
+            self.lookup_store()
+                .key_set(
+                    app_password_revocation_key(&app_password),
+                    now().to_string().into_bytes(),
+                    Some(APP_PASSWORD_REVOCATION_EXPIRY),
+                )
+                .await?;
+        }
+
+        if self
+            .inner
+            .ipc
+            .state_tx
+            .send(StateEvent::RevokeAppPasswords {
+                account_id,
+                broadcast: true,
+            })
+            .await
+            .is_err()
+        {
+            trc::event!(
+                Server(trc::ServerEvent::ThreadError),
+                Details = "Error sending app password revocation.",
+                CausedBy = trc::location!()
+            );
+        }
+
+        Ok(())
+    }
+
+    pub fn subscribe_app_password_revocations(&self) -> broadcast::Receiver<u32> {
+        self.inner.data.app_password_revocations.subscribe()
+    }
+
+    pub async fn app_password_last_used(&self, account_id: u32, name: &str) -> Option<u64> {
+        self.lookup_store()
+            .key_get::<String>(app_password_key(account_id, name))
+            .await
+            .ok()
+            .flatten()
+            .and_then(|last_used| last_used.parse().ok())
+    }
+
+    pub async fn clear_app_password_use(&self, account_id: u32, name: &str) -> trc::Result<()> {
+        self.lookup_store()
+            .key_delete(app_password_key(account_id, name))
+            .await
+    }
+
+    pub(crate) async fn authentication_failed(
+        &self,
+        remote_ip: IpAddr,
+        login: Option<&str>,
+        mechanism: &'static str,
+    ) -> trc::Error {
+        if self.has_auth_fail2ban() {
+            match self.is_auth_fail2banned(remote_ip, login).await {
+                Ok(true) => {
+                    return trc::SecurityEvent::AuthenticationBan
+                        .into_err()
+                        .ctx(trc::Key::RemoteIp, remote_ip)
+                        .ctx_opt(trc::Key::AccountName, login.map(|s| s.to_string()));
+                }
+                Ok(false) => (),
+                Err(err) => return err,
+            }
+        }
+
+        trc::AuthEvent::Failed
+            .ctx(trc::Key::RemoteIp, remote_ip)
+            .ctx(trc::Key::Mechanism, mechanism)
+            .ctx_opt(trc::Key::AccountName, login.map(|s| s.to_string()))
+    }
+
     pub fn cache_session(&self, session_id: String, access_token: &AccessToken) {
         self.inner.data.http_auth_cache.insert_with_ttl(
             session_id,
@@ -210,6 +597,7 @@ impl<'x> AuthRequest<'x> {
         credentials: Credentials<String>,
         session_id: u64,
         remote_ip: IpAddr,
+        protocol: ServerProtocol,
     ) -> Self {
         Self {
             credentials,
@@ -217,6 +605,7 @@ impl<'x> AuthRequest<'x> {
             remote_ip,
             return_member_of: true,
             directory: None,
+            protocol,
         }
     }
 
@@ -225,6 +614,7 @@ impl<'x> AuthRequest<'x> {
         pass: impl Into<String>,
         session_id: u64,
         remote_ip: IpAddr,
+        protocol: ServerProtocol,
     ) -> Self {
         Self::from_credentials(
             Credentials::Plain {
@@ -233,6 +623,7 @@ impl<'x> AuthRequest<'x> {
             },
             session_id,
             remote_ip,
+            protocol,
         )
     }
 
@@ -247,8 +638,29 @@ impl<'x> AuthRequest<'x> {
     }
 }
 
+fn app_password_key(account_id: u32, name: &str) -> Vec<u8> {
+    format!("app:{account_id}:{name}").into_bytes()
+}
+
+fn app_password_revocation_key(app_password: &str) -> Vec<u8> {
+    format!(
+        "app-revoked:{}",
+        blake3::hash(app_password.as_bytes()).to_hex()
+    )
+    .into_bytes()
+}
+
+fn totp_key(account_id: u32) -> Vec<u8> {
+    format!("totp:{account_id}").into_bytes()
+}
+
+fn totp_enrollment_key(account_id: u32) -> Vec<u8> {
+    format!("totp-pending:{account_id}").into_bytes()
+}
+
 pub(crate) trait CredentialsUsername {
     fn login(&self) -> Option<&str>;
+    fn mechanism(&self) -> &'static str;
 }
 
 impl CredentialsUsername for Credentials<String> {
@@ -260,4 +672,12 @@ impl CredentialsUsername for Credentials<String> {
             Credentials::OAuthBearer { .. } => None,
         }
     }
+
+    fn mechanism(&self) -> &'static str {
+        match self {
+            Credentials::Plain { .. } => "plain",
+            Credentials::XOauth2 { .. } => "xoauth2",
+            Credentials::OAuthBearer { .. } => "oauthbearer",
+        }
+    }
 }
@@ -4,8 +4,266 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+use std::{net::IpAddr, sync::Arc};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use directory::{
+    core::scram::{cram_md5_challenge, verify_cram_md5, ScramAlgorithm, ScramSecret, ScramServer},
+    Directory, Principal, QueryBy,
+};
 use mail_send::Credentials;
 
+use crate::Server;
+
+use super::AccessToken;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SaslMechanism {
+    CramMd5,
+    Scram {
+        algorithm: ScramAlgorithm,
+        plus: bool,
+    },
+}
+
+// State of a challenge-response SASL exchange
+#[derive(Debug)]
+pub enum SaslExchange {
+    CramMd5 {
+        challenge: String,
+    },
+    ScramClientFirst {
+        server: ScramServer,
+    },
+    ScramClientFinal {
+        server: ScramServer,
+        secret: ScramSecret,
+        principal: Option<Principal>,
+    },
+    ScramServerFinal {
+        server: ScramServer,
+        access_token: Arc<AccessToken>,
+    },
+}
+
+pub enum SaslStep {
+    Challenge(Vec<u8>),
+    Authenticated(Arc<AccessToken>),
+}
+
+impl SaslMechanism {
+    // Challenge-response mechanisms offered on a connection, the -PLUS
+    // variants require channel binding data from the TLS session
+    pub fn available(has_channel_binding: bool, allow_cram_md5: bool) -> Vec<SaslMechanism> {
+        let mut mechanisms = Vec::with_capacity(5);
+        for plus in [true, false] {
+            if !plus || has_channel_binding {
+                for algorithm in [ScramAlgorithm::Sha256, ScramAlgorithm::Sha1] {
+                    mechanisms.push(SaslMechanism::Scram { algorithm, plus });
+                }
+            }
+        }
+        if allow_cram_md5 {
+            mechanisms.push(SaslMechanism::CramMd5);
+        }
+        mechanisms
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SaslMechanism::CramMd5 => "cram-md5",
+            SaslMechanism::Scram {
+                algorithm: ScramAlgorithm::Sha1,
+                plus: false,
+            } => "scram-sha-1",
+            SaslMechanism::Scram {
+                algorithm: ScramAlgorithm::Sha1,
+                plus: true,
+            } => "scram-sha-1-plus",
+            SaslMechanism::Scram {
+                algorithm: ScramAlgorithm::Sha256,
+                plus: false,
+            } => "scram-sha-256",
+            SaslMechanism::Scram {
+                algorithm: ScramAlgorithm::Sha256,
+                plus: true,
+            } => "scram-sha-256-plus",
+        }
+    }
+}
+
+impl SaslExchange {
+    pub fn mechanism(&self) -> &'static str {
+        match self {
+            SaslExchange::CramMd5 { .. } => SaslMechanism::CramMd5.name(),
+            SaslExchange::ScramClientFirst { server }
+            | SaslExchange::ScramClientFinal { server, .. }
+            | SaslExchange::ScramServerFinal { server, .. } => SaslMechanism::Scram {
+                algorithm: server.algorithm(),
+                plus: server.is_plus(),
+            }
+            .name(),
+        }
+    }
+}
+
+impl Server {
+    // Starts an exchange, returning the initial server challenge
+    pub async fn sasl_start(
+        &self,
+        mechanism: SaslMechanism,
+        channel_binding: Option<Vec<u8>>,
+    ) -> trc::Result<(SaslExchange, Vec<u8>)> {
+        match mechanism {
+            SaslMechanism::CramMd5 => {
+                let hostname = self
+                    .core
+                    .storage
+                    .config
+                    .get("lookup.default.hostname")
+                    .await?
+                    .unwrap_or_else(|| "localhost".to_string());
+                let challenge = cram_md5_challenge(&hostname);
+                let response = challenge.as_bytes().to_vec();
+
+                Ok((SaslExchange::CramMd5 { challenge }, response))
+            }
+            SaslMechanism::Scram { algorithm, plus } => Ok((
+                SaslExchange::ScramClientFirst {
+                    server: ScramServer::new(algorithm, plus, channel_binding),
+                },
+                Vec::new(),
+            )),
+        }
+    }
+
+    // Processes a decoded client response of a challenge-response exchange
+    pub async fn sasl_step(
+        &self,
+        exchange: SaslExchange,
+        response: &[u8],
+        session_id: u64,
+        remote_ip: IpAddr,
+        directory: Option<&Directory>,
+    ) -> trc::Result<(Option<SaslExchange>, SaslStep)> {
+        let directory = directory.unwrap_or(&self.core.storage.directory);
+        let mechanism = exchange.mechanism();
+
+        match exchange {
+            SaslExchange::CramMd5 { challenge } => {
+                let (username, digest) = std::str::from_utf8(response)
+                    .ok()
+                    .and_then(|response| response.rsplit_once(' '))
+                    .ok_or_else(|| {
+                        trc::AuthEvent::Error
+                            .into_err()
+                            .details("Invalid CRAM-MD5 response")
+                    })?;
+                let principal = directory
+                    .query(QueryBy::Name(username), true)
+                    .await?
+                    .filter(|principal| {
+                        principal
+                            .plaintext_secret()
+                            .is_some_and(|secret| verify_cram_md5(secret, &challenge, digest))
+                    });
+
+                self.complete_sasl(principal, username, mechanism, session_id, remote_ip)
+                    .await
+                    .map(|access_token| (None, SaslStep::Authenticated(access_token)))
+            }
+            SaslExchange::ScramClientFirst { mut server } => {
+                let username = server.client_first(response)?.to_string();
+                let principal = directory.query(QueryBy::Name(&username), true).await?;
+                let server_key = self.core.oauth.oauth_key.as_bytes();
+                let secret = principal
+                    .as_ref()
+                    .and_then(|principal| principal.scram_secret(server.algorithm(), server_key))
+                    .unwrap_or_else(|| {
+                        ScramSecret::unknown(server.algorithm(), server_key, &username)
+                    });
+                let challenge = server.server_first(&secret);
+
+                Ok((
+                    Some(SaslExchange::ScramClientFinal {
+                        server,
+                        secret,
+                        principal,
+                    }),
+                    SaslStep::Challenge(challenge),
+                ))
+            }
+            SaslExchange::ScramClientFinal {
+                server,
+                secret,
+                principal,
+            } => {
+                let server_final = server.client_final(response, &secret)?;
+                let principal = principal.filter(|_| server_final.is_some());
+                let access_token = self
+                    .complete_sasl(
+                        principal,
+                        server.username(),
+                        mechanism,
+                        session_id,
+                        remote_ip,
+                    )
+                    .await?;
+
+                Ok((
+                    Some(SaslExchange::ScramServerFinal {
+                        server,
+                        access_token,
+                    }),
+                    SaslStep::Challenge(server_final.unwrap_or_default()),
+                ))
+            }
+            SaslExchange::ScramServerFinal { access_token, .. } => {
+                // The client acknowledges the server signature with an empty response
+                if response.is_empty() {
+                    Ok((None, SaslStep::Authenticated(access_token)))
+                } else {
+                    Err(trc::AuthEvent::Error
+                        .into_err()
+                        .details("Unexpected SCRAM client response"))
+                }
+            }
+        }
+    }
+
+    async fn complete_sasl(
+        &self,
+        principal: Option<Principal>,
+        username: &str,
+        mechanism: &'static str,
+        session_id: u64,
+        remote_ip: IpAddr,
+    ) -> trc::Result<Arc<AccessToken>> {
+        let result = if let Some(principal) = principal {
+            trc::event!(
+                Auth(trc::AuthEvent::Success),
+                AccountName = principal.name().to_string(),
+                AccountId = principal.id(),
+                SpanId = session_id,
+                Mechanism = mechanism,
+            );
+
+            self.principal_access_token(principal).await
+        } else {
+            Err(self
+                .authentication_failed(remote_ip, username.into(), mechanism)
+                .await)
+        };
+
+        self.finish_authentication(result, username, mechanism, remote_ip)
+            .await
+    }
+}
+
+pub fn sasl_encode_challenge(challenge: &[u8]) -> String {
+    STANDARD.encode(challenge)
+}
+
 pub fn sasl_decode_challenge_plain(challenge: &[u8]) -> Option<Credentials<String>> {
     let mut username = Vec::new();
     let mut secret = Vec::new();
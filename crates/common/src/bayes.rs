@@ -0,0 +1,307 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use mail_parser::{parsers::fields::thread::thread_name, Message};
+use nlp::{
+    bayes::{tokenize::BayesTokenizer, BayesClassifier, BayesModel, TokenHash, Weights},
+    tokenizers::osb::{OsbToken, OsbTokenizer},
+};
+use store::{write::key::KeySerializer, LookupStore, U32_LEN, U64_LEN};
+use trc::AddContext;
+
+use crate::Server;
+
+impl Server {
+    pub fn bayes_store(&self) -> &LookupStore {
+        self.core
+            .spam
+            .bayes
+            .store
+            .as_ref()
+            .and_then(|id| self.core.storage.lookups.get(id))
+            .unwrap_or(&self.core.storage.lookup)
+    }
+
+    // Trains the global model and, when per-account models are enabled,
+    // the model of the account
+    pub async fn bayes_train(
+        &self,
+        store: &LookupStore,
+        account_id: Option<u32>,
+        text: &str,
+        is_spam: bool,
+        session_id: u64,
+    ) -> trc::Result<()> {
+        let model = bayes_model(text, is_spam)?;
+
+        trc::event!(
+            Spam(trc::SpamEvent::Train),
+            SpanId = session_id,
+            AccountId = account_id,
+            Details = is_spam,
+            Total = model.weights.len(),
+        );
+
+        for account_id in self.bayes_models(account_id) {
+            self.bayes_update(store, account_id, &model, is_spam, true)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    // Reverses a previous training, token counts never go below zero
+    // so untraining a message that was never trained is harmless
+    pub async fn bayes_untrain(
+        &self,
+        store: &LookupStore,
+        account_id: Option<u32>,
+        text: &str,
+        is_spam: bool,
+        session_id: u64,
+    ) -> trc::Result<()> {
+        let model = bayes_model(text, is_spam)?;
+
+        trc::event!(
+            Spam(trc::SpamEvent::Untrain),
+            SpanId = session_id,
+            AccountId = account_id,
+            Details = is_spam,
+            Total = model.weights.len(),
+        );
+
+        for account_id in self.bayes_models(account_id) {
+            self.bayes_update(store, account_id, &model, is_spam, false)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    // Classifies the text using the global model, the account model is consulted
+    // when enabled and its result is combined using the configured weight
+    pub async fn bayes_classify(
+        &self,
+        store: &LookupStore,
+        account_id: Option<u32>,
+        text: &str,
+        classifier: &BayesClassifier,
+    ) -> trc::Result<Option<f64>> {
+        let tokens =
+            OsbTokenizer::<_, TokenHash>::new(BayesTokenizer::new(text), 5).collect::<Vec<_>>();
+        let global = self
+            .bayes_classify_model(store, None, &tokens, classifier)
+            .await?;
+
+        if let Some(account_id) = account_id.filter(|_| self.core.spam.bayes.account_models) {
+            let account = self
+                .bayes_classify_model(store, Some(account_id), &tokens, classifier)
+                .await?;
+            let weight = self.core.spam.bayes.account_weight;
+
+            Ok(match (global, account) {
+                (Some(global), Some(account)) => Some(global * (1.0 - weight) + account * weight),
+                (global, account) => global.or(account),
+            })
+        } else {
+            Ok(global)
+        }
+    }
+
+    // Returns the token weights of a model, a default hash returns the training counts
+    pub async fn bayes_weights(
+        &self,
+        store: &LookupStore,
+        account_id: Option<u32>,
+        hash: TokenHash,
+    ) -> trc::Result<Weights> {
+        let bayes_cache = &self.inner.data.bayes_cache;
+        let cache_hash = bayes_cache_hash(account_id, &hash);
+
+        if let Some(weights) = bayes_cache.get(&cache_hash) {
+            Ok(weights.unwrap_or_default())
+        } else {
+            let num = store
+                .counter_get(bayes_key(account_id, &hash))
+                .await
+                .caused_by(trc::location!())?;
+            Ok(if num != 0 {
+                let weights = Weights::from(num);
+                bayes_cache.insert_positive(cache_hash, weights);
+                weights
+            } else {
+                bayes_cache.insert_negative(cache_hash);
+                Weights::default()
+            })
+        }
+    }
+
+    async fn bayes_classify_model(
+        &self,
+        store: &LookupStore,
+        account_id: Option<u32>,
+        tokens: &[OsbToken<TokenHash>],
+        classifier: &BayesClassifier,
+    ) -> trc::Result<Option<f64>> {
+        let learns = self
+            .bayes_weights(store, account_id, TokenHash::default())
+            .await?;
+        if learns.spam < classifier.min_learns || learns.ham < classifier.min_learns {
+            return Ok(None);
+        }
+
+        let mut weights = Vec::with_capacity(tokens.len());
+        for token in tokens {
+            weights.push(OsbToken {
+                inner: self.bayes_weights(store, account_id, token.inner).await?,
+                idx: token.idx,
+            });
+        }
+
+        Ok(classifier.classify(weights.into_iter(), learns.ham, learns.spam))
+    }
+
+    async fn bayes_update(
+        &self,
+        store: &LookupStore,
+        account_id: Option<u32>,
+        model: &BayesModel,
+        is_spam: bool,
+        is_train: bool,
+    ) -> trc::Result<()> {
+        let bayes_cache = &self.inner.data.bayes_cache;
+        let learns_key = bayes_key(account_id, &TokenHash::default());
+
+        if !is_train {
+            // Nothing to reverse if the model was never trained with this class
+            let learns = Weights::from(
+                store
+                    .counter_get(learns_key.clone())
+                    .await
+                    .caused_by(trc::location!())?,
+            );
+            if (is_spam && learns.spam == 0) || (!is_spam && learns.ham == 0) {
+                return Ok(());
+            }
+        }
+
+        // Token expiry is refreshed every time a token is trained
+        let expires = self
+            .core
+            .spam
+            .bayes
+            .token_expiry
+            .map(|expiry| expiry.as_secs());
+        for (hash, weights) in &model.weights {
+            let key = bayes_key(account_id, hash);
+            if is_train {
+                store
+                    .counter_incr(key, (*weights).into(), expires, false)
+                    .await
+                    .caused_by(trc::location!())?;
+            } else {
+                let current = Weights::from(
+                    store
+                        .counter_get(key.clone())
+                        .await
+                        .caused_by(trc::location!())?,
+                );
+                let weights = Weights {
+                    spam: weights.spam.min(current.spam),
+                    ham: weights.ham.min(current.ham),
+                };
+                if weights != Weights::default() {
+                    store
+                        .counter_incr(key, -i64::from(weights), None, false)
+                        .await
+                        .caused_by(trc::location!())?;
+                }
+            }
+
+            bayes_cache.invalidate(&bayes_cache_hash(account_id, hash));
+        }
+
+        // Update training counts
+        let learns = i64::from(if is_spam {
+            Weights { spam: 1, ham: 0 }
+        } else {
+            Weights { spam: 0, ham: 1 }
+        });
+        store
+            .counter_incr(
+                learns_key,
+                if is_train { learns } else { -learns },
+                None,
+                false,
+            )
+            .await
+            .caused_by(trc::location!())?;
+        bayes_cache.invalidate(&bayes_cache_hash(account_id, &TokenHash::default()));
+
+        Ok(())
+    }
+
+    fn bayes_models(&self, account_id: Option<u32>) -> impl Iterator<Item = Option<u32>> {
+        std::iter::once(None).chain(
+            account_id
+                .filter(|_| self.core.spam.bayes.account_models)
+                .map(Some),
+        )
+    }
+}
+
+// Returns the text used to train and classify a message
+pub fn bayes_text(message: &Message<'_>) -> String {
+    let mut text = thread_name(message.subject().unwrap_or_default()).to_string();
+    for pos in 0..message.text_body.len() {
+        if let Some(body) = message.body_text(pos) {
+            text.push(' ');
+            text.push_str(body.as_ref());
+        }
+    }
+    text
+}
+
+fn bayes_model(text: &str, is_spam: bool) -> trc::Result<BayesModel> {
+    if text.is_empty() {
+        trc::bail!(trc::SpamEvent::TrainError
+            .into_err()
+            .reason("Empty message"));
+    }
+
+    let mut model = BayesModel::default();
+    model.train(OsbTokenizer::new(BayesTokenizer::new(text), 5), is_spam);
+    if model.weights.is_empty() {
+        trc::bail!(trc::SpamEvent::TrainError
+            .into_err()
+            .reason("No weights found"));
+    }
+
+    Ok(model)
+}
+
+fn bayes_key(account_id: Option<u32>, hash: &TokenHash) -> Vec<u8> {
+    if let Some(account_id) = account_id {
+        KeySerializer::new(U32_LEN + U64_LEN * 2)
+            .write(account_id)
+            .write(hash.h1)
+            .write(hash.h2)
+            .finalize()
+    } else {
+        KeySerializer::new(U64_LEN * 2)
+            .write(hash.h1)
+            .write(hash.h2)
+            .finalize()
+    }
+}
+
+fn bayes_cache_hash(account_id: Option<u32>, hash: &TokenHash) -> TokenHash {
+    if let Some(account_id) = account_id {
+        hash.for_account(account_id)
+    } else {
+        *hash
+    }
+}
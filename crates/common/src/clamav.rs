@@ -0,0 +1,223 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{path::PathBuf, time::Duration};
+
+use async_trait::async_trait;
+use deadpool::managed;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::TcpStream,
+};
+
+use crate::config::spamfilter::ClamAvConfig;
+
+const MAX_RESPONSE_SIZE: usize = 4096;
+
+#[derive(Debug, Clone)]
+pub enum ClamAvAddress {
+    Tcp(String),
+    Unix(PathBuf),
+}
+
+pub struct ClamAvConnectionManager {
+    pub address: ClamAvAddress,
+    pub timeout: Duration,
+}
+
+pub struct ClamAvConnection {
+    stream: Box<dyn ClamAvStream>,
+}
+
+trait ClamAvStream: AsyncRead + AsyncWrite + Unpin + Send + Sync {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send + Sync> ClamAvStream for T {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClamAvVerdict {
+    Clean,
+    Infected(String),
+    Error,
+}
+
+impl ClamAvConfig {
+    // Scans the message using a pooled connection, connections are discarded
+    // after any error as clamd closes the session when a command fails
+    pub async fn scan(&self, message: &[u8]) -> Result<ClamAvVerdict, String> {
+        let mut conn = self
+            .pool
+            .get()
+            .await
+            .map_err(|err| format!("Failed to obtain ClamAV connection: {err}"))?;
+
+        // clamd aborts scans exceeding its StreamMaxLength setting
+        let message = &message[..message.len().min(self.max_stream_size)];
+        let result =
+            match tokio::time::timeout(self.timeout, conn.scan(message, self.chunk_size)).await {
+                Ok(result) => result,
+                Err(_) => Err("ClamAV scan timed out".to_string()),
+            };
+        if result.is_err() {
+            let _ = managed::Object::take(conn);
+        }
+
+        result
+    }
+}
+
+impl ClamAvConnection {
+    pub async fn connect(address: &ClamAvAddress, timeout: Duration) -> Result<Self, String> {
+        let stream: Box<dyn ClamAvStream> = match address {
+            ClamAvAddress::Tcp(addr) => Box::new(
+                tokio::time::timeout(timeout, TcpStream::connect(addr))
+                    .await
+                    .map_err(|_| format!("Connection to ClamAV at {addr} timed out"))?
+                    .map_err(|err| format!("Failed to connect to ClamAV at {addr}: {err}"))?,
+            ),
+            #[cfg(unix)]
+            ClamAvAddress::Unix(path) => Box::new(
+                tokio::time::timeout(timeout, tokio::net::UnixStream::connect(path))
+                    .await
+                    .map_err(|_| format!("Connection to ClamAV at {path:?} timed out"))?
+                    .map_err(|err| format!("Failed to connect to ClamAV at {path:?}: {err}"))?,
+            ),
+            #[cfg(not(unix))]
+            ClamAvAddress::Unix(path) => {
+                return Err(format!(
+                    "Unix sockets are not supported on this platform: {path:?}"
+                ));
+            }
+        };
+
+        // Sessions keep the connection open between commands
+        let mut conn = ClamAvConnection { stream };
+        conn.write(b"zIDSESSION\0").await?;
+        Ok(conn)
+    }
+
+    pub async fn ping(&mut self) -> Result<(), String> {
+        self.write(b"zPING\0").await?;
+        match self.read_response().await?.as_str() {
+            "PONG" => Ok(()),
+            response => Err(format!("Unexpected ClamAV response: {response:?}")),
+        }
+    }
+
+    pub async fn scan(
+        &mut self,
+        message: &[u8],
+        chunk_size: usize,
+    ) -> Result<ClamAvVerdict, String> {
+        let result = self.write_stream(message, chunk_size).await;
+
+        // clamd replies with an error and closes the connection when it aborts a
+        // scan mid-stream, which is more useful than the write error
+        let response = match (result, self.read_response().await) {
+            (_, Ok(response)) => response,
+            (Err(err), Err(_)) | (Ok(_), Err(err)) => return Err(err),
+        };
+
+        let response = response.strip_prefix("stream: ").unwrap_or(&response);
+        if response == "OK" {
+            Ok(ClamAvVerdict::Clean)
+        } else if let Some(signature) = response.strip_suffix(" FOUND") {
+            Ok(ClamAvVerdict::Infected(signature.trim().to_string()))
+        } else if let Some(err) = response.strip_suffix(" ERROR") {
+            Err(format!("ClamAV scan failed: {}", err.trim()))
+        } else {
+            Err(format!("Unexpected ClamAV response: {response:?}"))
+        }
+    }
+
+    async fn write_stream(&mut self, message: &[u8], chunk_size: usize) -> Result<(), String> {
+        // Each chunk is prefixed by its length, a zero length chunk ends the stream
+        self.write(b"zINSTREAM\0").await?;
+        for chunk in message.chunks(chunk_size.max(1)) {
+            self.write(&(chunk.len() as u32).to_be_bytes()).await?;
+            self.write(chunk).await?;
+        }
+        self.write(&0u32.to_be_bytes()).await?;
+        self.stream
+            .flush()
+            .await
+            .map_err(|err| format!("Failed to write to ClamAV: {err}"))
+    }
+
+    async fn write(&mut self, bytes: &[u8]) -> Result<(), String> {
+        self.stream
+            .write_all(bytes)
+            .await
+            .map_err(|err| format!("Failed to write to ClamAV: {err}"))
+    }
+
+    async fn read_response(&mut self) -> Result<String, String> {
+        let mut response = Vec::with_capacity(64);
+        let mut buf = [0u8; 256];
+
+        loop {
+            let bytes_read = self
+                .stream
+                .read(&mut buf)
+                .await
+                .map_err(|err| format!("Failed to read from ClamAV: {err}"))?;
+            if bytes_read == 0 {
+                return Err("ClamAV closed the connection".to_string());
+            }
+            response.extend_from_slice(&buf[..bytes_read]);
+
+            if let Some(pos) = response.iter().position(|&ch| ch == 0) {
+                response.truncate(pos);
+                break;
+            } else if response.len() > MAX_RESPONSE_SIZE {
+                return Err("ClamAV response too large".to_string());
+            }
+        }
+
+        // Replies within a session are prefixed by the request number
+        let response = String::from_utf8_lossy(&response);
+        let response = response
+            .split_once(": ")
+            .filter(|(id, _)| id.chars().all(|ch| ch.is_ascii_digit()))
+            .map_or(response.as_ref(), |(_, response)| response);
+
+        Ok(response.trim().to_string())
+    }
+}
+
+#[async_trait]
+impl managed::Manager for ClamAvConnectionManager {
+    type Type = ClamAvConnection;
+    type Error = String;
+
+    async fn create(&self) -> Result<ClamAvConnection, String> {
+        ClamAvConnection::connect(&self.address, self.timeout).await
+    }
+
+    async fn recycle(
+        &self,
+        conn: &mut ClamAvConnection,
+        _: &managed::Metrics,
+    ) -> managed::RecycleResult<String> {
+        conn.ping().await.map_err(managed::RecycleError::Backend)
+    }
+}
+
+impl ClamAvVerdict {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ClamAvVerdict::Clean => "clean",
+            ClamAvVerdict::Infected(_) => "infected",
+            ClamAvVerdict::Error => "error",
+        }
+    }
+
+    pub fn signature(&self) -> Option<&str> {
+        match self {
+            ClamAvVerdict::Infected(signature) => Some(signature),
+            _ => None,
+        }
+    }
+}
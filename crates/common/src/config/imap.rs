@@ -6,7 +6,7 @@
 
 use std::time::Duration;
 
-use utils::config::{Config, Rate};
+use utils::config::{Config, Rate, RateWindow};
 
 #[derive(Default, Clone)]
 pub struct ImapConfig {
@@ -19,6 +19,7 @@ pub struct ImapConfig {
     pub timeout_idle: Duration,
 
     pub rate_requests: Option<Rate>,
+    pub rate_window: RateWindow,
     pub rate_concurrent: Option<u64>,
 }
 
@@ -43,6 +44,9 @@ impl ImapConfig {
             rate_requests: config
                 .property_or_default::<Option<Rate>>("imap.rate-limit.requests", "2000/1m")
                 .unwrap_or_default(),
+            rate_window: config
+                .property_or_default::<RateWindow>("imap.rate-limit.window", "fixed")
+                .unwrap_or_default(),
             rate_concurrent: config
                 .property::<Option<u64>>("imap.rate-limit.concurrent")
                 .unwrap_or_default(),
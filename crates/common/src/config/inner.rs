@@ -12,6 +12,7 @@ use dashmap::DashMap;
 use mail_send::smtp::tls::build_tls_connector;
 use nlp::bayes::cache::BayesTokenCache;
 use parking_lot::RwLock;
+use tokio::sync::broadcast;
 use utils::{
     config::Config,
     lru_cache::{LruCache, LruCached},
@@ -21,7 +22,7 @@ use utils::{
 
 use crate::{
     listener::blocked::BlockedIps, manager::webadmin::WebAdminManager, Data,
-    ThrottleKeyHasherBuilder, TlsConnectors,
+    ThrottleKeyHasherBuilder, TlsConnectors, IPC_CHANNEL_BUFFER,
 };
 
 use super::server::tls::{build_self_signed_cert, parse_certificates};
@@ -62,8 +63,13 @@ impl Data {
             .map(Arc::new),
             access_tokens: TtlDashMap::with_capacity(capacity, shard_amount),
             http_auth_cache: TtlDashMap::with_capacity(capacity, shard_amount),
+            bitmap_cardinalities: TtlDashMap::with_capacity(capacity, shard_amount),
+            flag_document_counts: TtlDashMap::with_capacity(capacity, shard_amount),
+            key_counts: TtlDashMap::with_capacity(capacity, shard_amount),
             blocked_ips: RwLock::new(BlockedIps::parse(config).blocked_ip_addresses),
             blocked_ips_version: 0.into(),
+            app_password_revocations: broadcast::channel(IPC_CHANNEL_BUFFER).0,
+            dkim_keys: Default::default(),
             permissions: Default::default(),
             permissions_version: 0.into(),
             jmap_id_gen: id_generator.clone(),
@@ -94,6 +100,10 @@ impl Data {
                 config.property("cache.thread.size").unwrap_or(2048),
             ),
             logos: Default::default(),
+            retention_stats: Default::default(),
+            import_jobs: Default::default(),
+            migrations: Default::default(),
+            apns_token: Default::default(),
             smtp_session_throttle: DashMap::with_capacity_and_hasher_and_shard_amount(
                 capacity,
                 ThrottleKeyHasherBuilder::default(),
@@ -105,6 +115,7 @@ impl Data {
                 shard_amount,
             ),
             smtp_connectors: TlsConnectors::default(),
+            smtp_connection_pool: Default::default(),
             bayes_cache: BayesTokenCache::new(
                 config
                     .property_or_default("cache.bayes.capacity", "8192")
@@ -128,8 +139,13 @@ impl Default for Data {
             tls_self_signed_cert: Default::default(),
             access_tokens: Default::default(),
             http_auth_cache: Default::default(),
+            bitmap_cardinalities: Default::default(),
+            flag_document_counts: Default::default(),
+            key_counts: Default::default(),
             blocked_ips: Default::default(),
             blocked_ips_version: 0.into(),
+            app_password_revocations: broadcast::channel(IPC_CHANNEL_BUFFER).0,
+            dkim_keys: Default::default(),
             permissions: Default::default(),
             permissions_version: 0.into(),
             remote_lists: Default::default(),
@@ -144,9 +160,14 @@ impl Default for Data {
             mailbox_cache: LruCache::with_capacity(2048),
             threads_cache: LruCache::with_capacity(2048),
             logos: Default::default(),
+            retention_stats: Default::default(),
+            import_jobs: Default::default(),
+            migrations: Default::default(),
+            apns_token: Default::default(),
             smtp_session_throttle: Default::default(),
             smtp_queue_throttle: Default::default(),
             smtp_connectors: Default::default(),
+            smtp_connection_pool: Default::default(),
             bayes_cache: BayesTokenCache::new(
                 8192,
                 Duration::from_secs(3600),
@@ -0,0 +1,80 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use ahash::AHashSet;
+use utils::{
+    config::{cron::SimpleCron, utils::ParseValue, Config},
+    glob::GlobPattern,
+};
+
+use super::{retention::MailboxSelector, settings::SpecialUse};
+
+#[derive(Debug, Clone)]
+pub struct ArchiveConfig {
+    pub frequency: SimpleCron,
+    pub mailboxes: Vec<MailboxSelector>,
+    pub max_age: Duration,
+    pub folder: String,
+    pub batch_size: usize,
+    pub opt_out: AHashSet<String>,
+    pub dry_run: bool,
+}
+
+impl ArchiveConfig {
+    pub fn parse(config: &mut Config) -> Option<Self> {
+        if !config
+            .property_or_default("jmap.email.archive.enable", "false")
+            .unwrap_or(false)
+        {
+            return None;
+        }
+
+        // Mailboxes can be selected either by role or by a name pattern
+        let mut mailboxes = config
+            .values("jmap.email.archive.mailboxes")
+            .map(|(_, value)| match SpecialUse::parse_value(value) {
+                Ok(role) => MailboxSelector::Role(role),
+                Err(_) => MailboxSelector::Name(GlobPattern::compile(value, true)),
+            })
+            .collect::<Vec<_>>();
+        if mailboxes.is_empty() {
+            mailboxes.push(MailboxSelector::Role(SpecialUse::Inbox));
+        }
+
+        Some(ArchiveConfig {
+            frequency: config
+                .property_or_default::<SimpleCron>("jmap.email.archive.frequency", "30 1 *")
+                .unwrap_or_else(|| SimpleCron::parse_value("30 1 *").unwrap()),
+            mailboxes,
+            max_age: config
+                .property_or_default("jmap.email.archive.max-age", "365d")
+                .unwrap_or_else(|| Duration::from_secs(365 * 86400)),
+            folder: config
+                .value("jmap.email.archive.folder")
+                .map(|folder| folder.trim_matches('/'))
+                .filter(|folder| !folder.is_empty())
+                .unwrap_or("Archive")
+                .to_string(),
+            batch_size: config
+                .property_or_default::<usize>("jmap.email.archive.batch-size", "500")
+                .unwrap_or(500)
+                .max(1),
+            opt_out: config
+                .values("jmap.email.archive.opt-out")
+                .map(|(_, account)| account.to_lowercase())
+                .collect(),
+            dry_run: config
+                .property_or_default("jmap.email.archive.dry-run", "false")
+                .unwrap_or(false),
+        })
+    }
+
+    pub fn is_opted_out(&self, account_name: &str) -> bool {
+        self.opt_out.contains(&account_name.to_lowercase())
+    }
+}
@@ -111,7 +111,11 @@ impl JmapConfig {
         let mut capabilities: AHashSet<sieve::compiler::grammar::Capability> =
             AHashSet::from_iter(sieve::compiler::grammar::Capability::all().iter().cloned());
 
-        for (_, capability) in config.values("sieve.untrusted.disabled-capabilities") {
+        // Advertise only the extensions left enabled in the untrusted runtime
+        for (_, capability) in config
+            .values("sieve.untrusted.disable-capabilities")
+            .chain(config.values("sieve.untrusted.disabled-capabilities"))
+        {
             capabilities.remove(&sieve::compiler::grammar::Capability::parse(capability));
         }
 
@@ -130,11 +134,11 @@ impl JmapConfig {
             Capabilities::SieveAccount(SieveAccountCapabilities {
                 max_script_name: self.sieve_max_script_name,
                 max_script_size: config
-                    .property("sieve.untrusted.max-script-size")
+                    .property("sieve.untrusted.limits.script-size")
                     .unwrap_or(1024 * 1024),
                 max_scripts: self.sieve_max_scripts,
                 max_redirects: config
-                    .property("sieve.untrusted.max-redirects")
+                    .property("sieve.untrusted.limits.redirects")
                     .unwrap_or(1),
                 extensions,
                 notification_methods: if !notification_methods.is_empty() {
@@ -4,5 +4,8 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+pub mod archive;
 pub mod capabilities;
+pub mod push;
+pub mod retention;
 pub mod settings;
@@ -0,0 +1,169 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{sync::Arc, time::Duration};
+
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine,
+};
+use reqwest::header::{HeaderMap, CONTENT_TYPE};
+use ring::{
+    hmac,
+    rand::SystemRandom,
+    signature::{EcdsaKeyPair, ECDSA_P256_SHA256_FIXED_SIGNING},
+};
+use utils::config::Config;
+
+use crate::config::{build_ecdsa_pem, parse_http_headers};
+
+// Apple Push Notification service gateway used to notify devices registered
+// with the IMAP XAPPLEPUSHSERVICE command
+#[derive(Clone)]
+pub struct ApnsConfig {
+    pub url: String,
+    pub topic: String,
+    pub key_id: String,
+    pub team_id: String,
+    pub key: Arc<EcdsaKeyPair>,
+    pub registration_ttl: Duration,
+    pub timeout: Duration,
+}
+
+// Operator endpoint that receives new message events
+#[derive(Clone)]
+pub struct PushWebhookConfig {
+    pub url: String,
+    pub key: String,
+    pub headers: HeaderMap,
+    pub timeout: Duration,
+    pub max_attempts: u32,
+    pub retry_interval: Duration,
+    pub tls_allow_invalid_certs: bool,
+}
+
+// Provider tokens are reused as APNs rejects tokens renewed more than once
+// every 20 minutes, and expires them after an hour
+#[derive(Debug, Clone)]
+pub struct ApnsToken {
+    pub key_id: String,
+    pub issued_at: u64,
+    pub token: String,
+}
+
+impl ApnsConfig {
+    pub fn parse(config: &mut Config) -> Option<Self> {
+        if !config
+            .property_or_default("jmap.push.apns.enable", "false")
+            .unwrap_or(false)
+        {
+            return None;
+        }
+
+        let key = match build_ecdsa_pem(
+            &ECDSA_P256_SHA256_FIXED_SIGNING,
+            config.value_require("jmap.push.apns.key")?,
+        ) {
+            Ok(key) => key,
+            Err(err) => {
+                config.new_build_error(
+                    "jmap.push.apns.key",
+                    format!("Failed to build APNs signing key: {err}"),
+                );
+                return None;
+            }
+        };
+
+        Some(ApnsConfig {
+            url: config
+                .value("jmap.push.apns.url")
+                .unwrap_or("https://api.push.apple.com")
+                .trim_end_matches('/')
+                .to_string(),
+            topic: config.value_require("jmap.push.apns.topic")?.to_string(),
+            key_id: config.value_require("jmap.push.apns.key-id")?.to_string(),
+            team_id: config.value_require("jmap.push.apns.team-id")?.to_string(),
+            key: Arc::new(key),
+            registration_ttl: config
+                .property_or_default("jmap.push.apns.registration-ttl", "30d")
+                .unwrap_or_else(|| Duration::from_secs(30 * 86400)),
+            timeout: config
+                .property_or_default("jmap.push.apns.timeout", "10s")
+                .unwrap_or_else(|| Duration::from_secs(10)),
+        })
+    }
+
+    // Builds an ES256 signed JWT identifying the provider
+    pub fn provider_token(&self, issued_at: u64) -> Result<ApnsToken, String> {
+        let header = URL_SAFE_NO_PAD
+            .encode(serde_json::json!({ "alg": "ES256", "kid": self.key_id }).to_string());
+        let claims = URL_SAFE_NO_PAD
+            .encode(serde_json::json!({ "iss": self.team_id, "iat": issued_at }).to_string());
+        let payload = format!("{header}.{claims}");
+        let signature = self
+            .key
+            .sign(&SystemRandom::new(), payload.as_bytes())
+            .map_err(|err| format!("Failed to sign APNs provider token: {err}"))?;
+
+        Ok(ApnsToken {
+            key_id: self.key_id.clone(),
+            issued_at,
+            token: format!("{payload}.{}", URL_SAFE_NO_PAD.encode(signature.as_ref())),
+        })
+    }
+}
+
+impl ApnsToken {
+    pub fn is_valid(&self, key_id: &str, now: u64) -> bool {
+        self.key_id == key_id && now.saturating_sub(self.issued_at) < 45 * 60
+    }
+}
+
+impl PushWebhookConfig {
+    pub fn parse(config: &mut Config) -> Option<Self> {
+        if !config
+            .property_or_default("jmap.push.webhook.enable", "false")
+            .unwrap_or(false)
+        {
+            return None;
+        }
+
+        let mut headers = parse_http_headers(config, "jmap.push.webhook");
+        headers.insert(CONTENT_TYPE, "application/json".parse().unwrap());
+
+        Some(PushWebhookConfig {
+            url: config.value_require("jmap.push.webhook.url")?.to_string(),
+            key: config
+                .value("jmap.push.webhook.signature-key")
+                .unwrap_or_default()
+                .to_string(),
+            headers,
+            timeout: config
+                .property_or_default("jmap.push.webhook.timeout", "30s")
+                .unwrap_or_else(|| Duration::from_secs(30)),
+            max_attempts: config
+                .property_or_default::<u32>("jmap.push.webhook.retry.attempts", "5")
+                .unwrap_or(5)
+                .max(1),
+            retry_interval: config
+                .property_or_default("jmap.push.webhook.retry.interval", "1s")
+                .unwrap_or_else(|| Duration::from_secs(1)),
+            tls_allow_invalid_certs: config
+                .property_or_default("jmap.push.webhook.allow-invalid-certs", "false")
+                .unwrap_or_default(),
+        })
+    }
+
+    // HMAC-SHA256 signature of the request body, sent as X-Signature
+    pub fn sign(&self, body: &[u8]) -> Option<String> {
+        if !self.key.is_empty() {
+            let key = hmac::Key::new(hmac::HMAC_SHA256, self.key.as_bytes());
+            Some(STANDARD.encode(hmac::sign(&key, body).as_ref()))
+        } else {
+            None
+        }
+    }
+}
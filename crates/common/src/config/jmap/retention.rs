@@ -0,0 +1,178 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use ahash::{AHashMap, AHashSet};
+use utils::{
+    config::{utils::ParseValue, Config},
+    glob::GlobPattern,
+};
+
+use super::settings::SpecialUse;
+
+#[derive(Debug, Clone, Default)]
+pub struct RetentionConfig {
+    pub policies: Vec<RetentionPolicy>,
+    pub legal_hold: AHashSet<String>,
+    pub batch_size: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct RetentionPolicy {
+    pub id: String,
+    pub scope: RetentionScope,
+    pub mailbox: MailboxSelector,
+    pub max_age: Duration,
+    pub date: RetentionDate,
+}
+
+// Policies with a narrower scope take precedence over broader ones
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RetentionScope {
+    Global,
+    Domain(String),
+    Account(String),
+}
+
+#[derive(Debug, Clone)]
+pub enum MailboxSelector {
+    Role(SpecialUse),
+    Name(GlobPattern),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetentionDate {
+    // Time the message was saved to the mailbox (SAVEDATE)
+    Saved,
+    // Time the message was received (INTERNALDATE)
+    Received,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RetentionStats {
+    pub started: u64,
+    pub finished: u64,
+    pub accounts: u64,
+    pub accounts_on_hold: u64,
+    pub expunged: u64,
+    pub policies: AHashMap<String, u64>,
+}
+
+impl RetentionConfig {
+    pub fn parse(config: &mut Config) -> Self {
+        let mut policies = Vec::new();
+        for id in config
+            .sub_keys("jmap.email.retention.policy", ".max-age")
+            .map(|id| id.to_string())
+            .collect::<Vec<_>>()
+        {
+            if let Some(policy) = RetentionPolicy::parse(config, &id) {
+                policies.push(policy);
+            }
+        }
+
+        RetentionConfig {
+            policies,
+            legal_hold: config
+                .values("jmap.email.retention.legal-hold")
+                .map(|(_, account)| account.to_lowercase())
+                .collect(),
+            batch_size: config
+                .property("jmap.email.retention.batch-size")
+                .unwrap_or(500),
+        }
+    }
+
+    pub fn is_on_hold(&self, account_name: &str) -> bool {
+        self.legal_hold.contains(&account_name.to_lowercase())
+    }
+}
+
+impl RetentionPolicy {
+    fn parse(config: &mut Config, id: &str) -> Option<Self> {
+        let prefix = ("jmap.email.retention.policy", id);
+        let mailbox = match (
+            config.value((prefix.0, prefix.1, "role")),
+            config.value((prefix.0, prefix.1, "name")),
+        ) {
+            (Some(role), None) => match SpecialUse::parse_value(role) {
+                Ok(role) => MailboxSelector::Role(role),
+                Err(err) => {
+                    config.new_parse_error((prefix.0, prefix.1, "role"), err);
+                    return None;
+                }
+            },
+            (None, Some(name)) => MailboxSelector::Name(GlobPattern::compile(name, true)),
+            _ => {
+                config.new_parse_error(
+                    (prefix.0, prefix.1, "role"),
+                    "Either a mailbox role or name pattern must be specified",
+                );
+                return None;
+            }
+        };
+        let scope = match (
+            config.value((prefix.0, prefix.1, "account")),
+            config.value((prefix.0, prefix.1, "domain")),
+        ) {
+            (None, None) => RetentionScope::Global,
+            (Some(account), None) => RetentionScope::Account(account.to_lowercase()),
+            (None, Some(domain)) => RetentionScope::Domain(domain.to_lowercase()),
+            (Some(_), Some(_)) => {
+                config.new_parse_error(
+                    (prefix.0, prefix.1, "account"),
+                    "A policy can be scoped to either an account or a domain",
+                );
+                return None;
+            }
+        };
+        let date = match config
+            .value((prefix.0, prefix.1, "date"))
+            .unwrap_or("saved")
+        {
+            "saved" => RetentionDate::Saved,
+            "received" => RetentionDate::Received,
+            other => {
+                let err = format!("Invalid retention date {other:?}");
+                config.new_parse_error((prefix.0, prefix.1, "date"), err);
+                return None;
+            }
+        };
+
+        Some(RetentionPolicy {
+            id: id.to_string(),
+            scope,
+            mailbox,
+            max_age: config.property((prefix.0, prefix.1, "max-age"))?,
+            date,
+        })
+    }
+
+    pub fn applies_to(&self, account_name: &str, account_domain: Option<&str>) -> bool {
+        match &self.scope {
+            RetentionScope::Global => true,
+            RetentionScope::Domain(domain) => {
+                account_domain.map_or(false, |d| d.eq_ignore_ascii_case(domain))
+            }
+            RetentionScope::Account(account) => account.eq_ignore_ascii_case(account_name),
+        }
+    }
+
+    pub fn matches_mailbox(&self, role: Option<SpecialUse>, path: &str) -> bool {
+        self.mailbox.matches(role, path)
+    }
+}
+
+impl MailboxSelector {
+    pub fn matches(&self, role: Option<SpecialUse>, path: &str) -> bool {
+        match self {
+            MailboxSelector::Role(selector_role) => role == Some(*selector_role),
+            MailboxSelector::Name(pattern) => pattern.matches(path),
+        }
+    }
+}
@@ -6,10 +6,18 @@
 
 use std::{str::FromStr, time::Duration};
 
-use jmap_proto::request::capability::BaseCapabilities;
+use jmap_proto::{request::capability::BaseCapabilities, types::keyword::Keyword};
 use mail_parser::HeaderName;
 use nlp::language::Language;
-use utils::config::{cron::SimpleCron, utils::ParseValue, Config, Rate};
+use utils::config::{cron::SimpleCron, utils::ParseValue, Config, Rate, RateWindow};
+
+use crate::config::server::ServerProtocol;
+
+use super::{
+    archive::ArchiveConfig,
+    push::{ApnsConfig, PushWebhookConfig},
+    retention::RetentionConfig,
+};
 
 #[derive(Default, Clone)]
 pub struct JmapConfig {
@@ -40,6 +48,12 @@ pub struct JmapConfig {
     pub mail_parse_max_items: usize,
     pub mail_max_size: usize,
     pub mail_autoexpunge_after: Option<Duration>,
+    pub mail_retention: RetentionConfig,
+    pub mail_archive: Option<ArchiveConfig>,
+    pub mail_snooze_interval: Duration,
+    pub mail_private_keywords: Vec<Keyword>,
+    pub mail_autofile_subaddress: bool,
+    pub mail_autofile_create: bool,
 
     pub sieve_max_script_name: usize,
     pub sieve_max_scripts: usize,
@@ -48,6 +62,7 @@ pub struct JmapConfig {
     pub rate_authenticated: Option<Rate>,
     pub rate_authenticate_req: Option<Rate>,
     pub rate_anonymous: Option<Rate>,
+    pub rate_window: RateWindow,
 
     pub event_source_throttle: Duration,
     pub push_max_total: usize,
@@ -57,6 +72,8 @@ pub struct JmapConfig {
     pub push_timeout: Duration,
     pub push_verify_timeout: Duration,
     pub push_throttle: Duration,
+    pub push_apns: Option<ApnsConfig>,
+    pub push_webhook: Option<PushWebhookConfig>,
 
     pub web_socket_throttle: Duration,
     pub web_socket_timeout: Duration,
@@ -64,6 +81,7 @@ pub struct JmapConfig {
 
     pub fallback_admin: Option<(String, String)>,
     pub master_user: Option<(String, String)>,
+    pub app_password_required: Vec<ServerProtocol>,
 
     pub spam_header: Option<(HeaderName<'static>, String)>,
     pub default_folders: Vec<DefaultFolder>,
@@ -133,6 +151,19 @@ impl JmapConfig {
             .map_err(|e| config.new_parse_error("server.http.headers", e))
             .unwrap_or_default();
 
+        // Parse keywords tracked per user on shared mailboxes
+        let mut mail_private_keywords = config
+            .values("jmap.email.shared.private-keywords")
+            .map(|(_, keyword)| keyword.trim().to_lowercase())
+            .filter(|keyword| !keyword.is_empty())
+            .map(Keyword::from)
+            .collect::<Vec<_>>();
+        if mail_private_keywords.is_empty()
+            && config.value("jmap.email.shared.private-keywords").is_none()
+        {
+            mail_private_keywords.push(Keyword::Seen);
+        }
+
         // Parse default folders
         let mut default_folders = Vec::new();
         let mut shared_folder = "Shared Folders".to_string();
@@ -294,6 +325,18 @@ impl JmapConfig {
             mail_autoexpunge_after: config
                 .property_or_default::<Option<Duration>>("jmap.email.auto-expunge", "30d")
                 .unwrap_or_default(),
+            mail_retention: RetentionConfig::parse(config),
+            mail_archive: ArchiveConfig::parse(config),
+            mail_snooze_interval: config
+                .property_or_default::<Duration>("jmap.email.snooze.interval", "1m")
+                .unwrap_or_else(|| Duration::from_secs(60)),
+            mail_private_keywords,
+            mail_autofile_subaddress: config
+                .property("jmap.email.sub-addressing.auto-file")
+                .unwrap_or(false),
+            mail_autofile_create: config
+                .property("jmap.email.sub-addressing.create-folder")
+                .unwrap_or(false),
             sieve_max_script_name: config
                 .property("sieve.untrusted.limits.name-length")
                 .unwrap_or(512),
@@ -313,6 +356,9 @@ impl JmapConfig {
             rate_anonymous: config
                 .property_or_default::<Option<Rate>>("jmap.rate-limit.anonymous", "100/1m")
                 .unwrap_or_default(),
+            rate_window: config
+                .property_or_default::<RateWindow>("jmap.rate-limit.window", "fixed")
+                .unwrap_or_default(),
             event_source_throttle: config
                 .property_or_default("jmap.event-source.throttle", "1s")
                 .unwrap_or_else(|| Duration::from_secs(1)),
@@ -367,6 +413,8 @@ impl JmapConfig {
             push_throttle: config
                 .property_or_default("jmap.push.throttle", "1s")
                 .unwrap_or_else(|| Duration::from_secs(1)),
+            push_apns: ApnsConfig::parse(config),
+            push_webhook: PushWebhookConfig::parse(config),
             session_purge_frequency: config
                 .property_or_default::<SimpleCron>("jmap.session.purge.frequency", "15 * *")
                 .unwrap_or_else(|| SimpleCron::parse_value("15 * *").unwrap()),
@@ -385,6 +433,11 @@ impl JmapConfig {
                     .value("authentication.master.secret")
                     .map(|p| (u.to_string(), p.to_string()))
             }),
+            app_password_required: config
+                .properties::<ServerProtocol>("authentication.app-password.required")
+                .into_iter()
+                .map(|(_, protocol)| protocol)
+                .collect(),
             default_folders,
             shared_folder,
         };
@@ -15,7 +15,7 @@ use hyper::{
 };
 use ring::signature::{EcdsaKeyPair, RsaKeyPair};
 use store::{BlobBackend, BlobStore, FtsStore, LookupStore, Store, Stores};
-use telemetry::Metrics;
+use telemetry::{AuditConfig, Metrics};
 use utils::config::{utils::AsKey, Config};
 
 use crate::{
@@ -25,7 +25,7 @@ use crate::{
 
 use self::{
     imap::ImapConfig, jmap::settings::JmapConfig, scripts::Scripting, smtp::SmtpConfig,
-    storage::Storage,
+    spamfilter::SpamFilterConfig, storage::Storage,
 };
 
 pub mod imap;
@@ -35,6 +35,7 @@ pub mod network;
 pub mod scripts;
 pub mod server;
 pub mod smtp;
+pub mod spamfilter;
 pub mod storage;
 pub mod telemetry;
 
@@ -133,6 +134,21 @@ impl Core {
                 }
             })
             .unwrap_or_default();
+        let pubsub = config
+            .value("cluster.event-bus")
+            .map(|id| id.to_string())
+            .and_then(|id| {
+                if let Some(store) = stores.pubsub_stores.get(&id) {
+                    store.clone().into()
+                } else {
+                    config.new_parse_error(
+                        "cluster.event-bus",
+                        format!("Event bus store {id:?} not found"),
+                    );
+                    None
+                }
+            })
+            .unwrap_or_default();
         let mut directories =
             Directories::parse(config, &stores, data.clone(), is_enterprise).await;
         let directory = config
@@ -178,22 +194,29 @@ impl Core {
             smtp: SmtpConfig::parse(config).await,
             jmap: JmapConfig::parse(config),
             imap: ImapConfig::parse(config),
+            spam: SpamFilterConfig::parse(config),
             oauth: OAuthConfig::parse(config),
             acme: AcmeProviders::parse(config),
             metrics: Metrics::parse(config),
+            audit: AuditConfig::parse(config, &stores),
             storage: Storage {
                 data,
                 blob,
                 fts,
                 lookup,
+                pubsub,
                 directory,
                 directories: directories.directories,
                 purge_schedules: stores.purge_schedules,
-                config: config_manager,
+                config: ConfigManager {
+                    cfg_snapshot: config.keys.clone().into(),
+                    ..config_manager
+                },
                 stores: stores.stores,
                 lookups: stores.lookup_stores,
                 blobs: stores.blob_stores,
                 ftss: stores.fts_stores,
+                pubsubs: stores.pubsub_stores,
             },
         }
     }
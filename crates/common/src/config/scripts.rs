@@ -26,6 +26,8 @@ pub struct Scripting {
     pub untrusted_compiler: Compiler,
     pub untrusted_runtime: Runtime,
     pub trusted_runtime: Runtime,
+    pub outbound_runtime: Runtime,
+    pub outbound_user_runtime: Runtime,
     pub from_addr: IfBlock,
     pub from_name: IfBlock,
     pub return_path: IfBlock,
@@ -151,6 +153,7 @@ impl Scripting {
             .without_capabilities(
                 config
                     .values("sieve.untrusted.disable-capabilities")
+                    .chain(config.values("sieve.untrusted.disabled-capabilities"))
                     .map(|(_, v)| v),
             )
             .with_valid_notification_uris({
@@ -280,6 +283,37 @@ impl Scripting {
             .to_string();
         trusted_runtime.set_local_hostname(hostname.clone());
 
+        // Outbound scripts may file a copy into the sender's mailboxes but cannot
+        // send vacation responses or access other accounts
+        let outbound_restrictions = [
+            Capability::Vacation,
+            Capability::VacationSeconds,
+            Capability::Fcc,
+            Capability::Mailbox,
+            Capability::MailboxId,
+            Capability::MboxMetadata,
+            Capability::ServerMetadata,
+            Capability::ImapSieve,
+            Capability::Duplicate,
+        ];
+        let outbound_runtime = trusted_runtime
+            .clone()
+            .with_capability(Capability::FileInto)
+            .without_capabilities(
+                config
+                    .values("sieve.outbound.disable-capabilities")
+                    .map(|(_, v)| v),
+            );
+        let mut outbound_user_runtime = untrusted_runtime
+            .clone()
+            .without_capabilities(outbound_restrictions)
+            .without_capabilities(
+                config
+                    .values("sieve.outbound.disable-capabilities")
+                    .map(|(_, v)| v),
+            );
+        outbound_user_runtime.set_local_hostname(hostname.clone());
+
         // Parse trusted scripts
         let mut trusted_scripts = AHashMap::new();
         for id in config
@@ -332,6 +366,8 @@ impl Scripting {
             untrusted_compiler,
             untrusted_runtime,
             trusted_runtime,
+            outbound_runtime,
+            outbound_user_runtime,
             from_addr: IfBlock::try_parse(config, "sieve.trusted.from-addr", &token_map)
                 .unwrap_or_else(|| {
                     IfBlock::new::<()>(
@@ -370,6 +406,8 @@ impl Default for Scripting {
             untrusted_compiler: Compiler::new(),
             untrusted_runtime: Runtime::new(),
             trusted_runtime: Runtime::new(),
+            outbound_runtime: Runtime::new(),
+            outbound_user_runtime: Runtime::new(),
             from_addr: IfBlock::new::<()>(
                 "sieve.trusted.from-addr",
                 [],
@@ -397,6 +435,8 @@ impl Clone for Scripting {
             untrusted_compiler: self.untrusted_compiler.clone(),
             untrusted_runtime: self.untrusted_runtime.clone(),
             trusted_runtime: self.trusted_runtime.clone(),
+            outbound_runtime: self.outbound_runtime.clone(),
+            outbound_user_runtime: self.outbound_user_runtime.clone(),
             from_addr: self.from_addr.clone(),
             from_name: self.from_name.clone(),
             return_path: self.return_path.clone(),
@@ -4,7 +4,7 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::{net::SocketAddr, sync::Arc, time::Duration};
+use std::{net::SocketAddr, path::PathBuf, sync::Arc, time::Duration};
 
 use rustls::{
     crypto::ring::{default_provider, ALL_CIPHER_SUITES},
@@ -28,7 +28,7 @@ use crate::{
 
 use super::{
     tls::{TLS12_VERSION, TLS13_VERSION},
-    Listener, Listeners, ServerProtocol, TcpListener,
+    Listener, Listeners, ServerProtocol, TcpListener, UnixListener,
 };
 
 impl Listeners {
@@ -173,10 +173,56 @@ impl Listeners {
             });
         }
 
-        if listeners.is_empty() {
+        // Build Unix domain socket listeners
+        let mut unix_listeners = Vec::new();
+        if let Some(path) = config.value(("server.listener", id, "unix.path")) {
+            let path = PathBuf::from(path);
+            if cfg!(unix) {
+                let mode = config
+                    .value(("server.listener", id, "unix.mode"))
+                    .map(|mode| mode.to_string());
+                let mode = match mode {
+                    Some(mode) => match u32::from_str_radix(mode.trim_start_matches("0o"), 8) {
+                        Ok(mode) if mode <= 0o777 => Some(mode),
+                        _ => {
+                            config.new_parse_error(
+                                ("server.listener", id, "unix.mode"),
+                                format!("Invalid octal file mode {mode:?}"),
+                            );
+                            None
+                        }
+                    },
+                    None => None,
+                };
+                let allowed_uids = config
+                    .properties::<u32>(("server.listener", id, "unix.allow-uid"))
+                    .into_iter()
+                    .map(|(_, uid)| uid)
+                    .collect();
+                let allowed_gids = config
+                    .properties::<u32>(("server.listener", id, "unix.allow-gid"))
+                    .into_iter()
+                    .map(|(_, gid)| gid)
+                    .collect();
+
+                unix_listeners.push(UnixListener {
+                    path,
+                    mode,
+                    allowed_uids,
+                    allowed_gids,
+                });
+            } else {
+                config.new_build_error(
+                    ("server.listener", id, "unix.path"),
+                    "Unix domain sockets are not supported on this platform",
+                );
+            }
+        }
+
+        if listeners.is_empty() && unix_listeners.is_empty() {
             config.new_build_error(
                 ("server.listener", id),
-                "No 'bind' directive found for listener",
+                "No 'bind' or 'unix.path' directive found for listener",
             );
             return;
         }
@@ -195,6 +241,18 @@ impl Listeners {
         for (_, network) in config.properties(proxy_keys) {
             proxy_networks.push(network);
         }
+        let proxy_required = config
+            .property_or_else(
+                ("server.listener", id, "proxy.require"),
+                "server.proxy.require",
+                "false",
+            )
+            .unwrap_or(false);
+
+        // CRAM-MD5 requires plaintext passwords, only enabled on request
+        let allow_cram_md5 = config
+            .property_or_default(("server.listener", id, "auth.cram-md5"), "false")
+            .unwrap_or(false);
 
         let span_id_gen = self.span_id_gen.clone();
         self.servers.push(Listener {
@@ -208,7 +266,10 @@ impl Listeners {
             id: id_,
             protocol,
             listeners,
+            unix_listeners,
             proxy_networks,
+            proxy_required,
+            allow_cram_md5,
             span_id_gen,
         });
     }
@@ -4,7 +4,7 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::{fmt::Display, net::SocketAddr, sync::Arc, time::Duration};
+use std::{fmt::Display, net::SocketAddr, path::PathBuf, sync::Arc, time::Duration};
 
 use ahash::AHashMap;
 use serde::{Deserialize, Serialize};
@@ -28,7 +28,10 @@ pub struct Listener {
     pub id: String,
     pub protocol: ServerProtocol,
     pub listeners: Vec<TcpListener>,
+    pub unix_listeners: Vec<UnixListener>,
     pub proxy_networks: Vec<IpAddrMask>,
+    pub proxy_required: bool,
+    pub allow_cram_md5: bool,
     pub max_connections: u64,
     pub span_id_gen: Arc<SnowflakeIdGenerator>,
 }
@@ -45,6 +48,16 @@ pub struct TcpListener {
     pub nodelay: bool,
 }
 
+#[derive(Debug)]
+pub struct UnixListener {
+    pub path: PathBuf,
+    pub mode: Option<u32>,
+
+    // Peer credentials, any peer is accepted when both are empty
+    pub allowed_uids: Vec<u32>,
+    pub allowed_gids: Vec<u32>,
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Serialize, Deserialize)]
 pub enum ServerProtocol {
     #[default]
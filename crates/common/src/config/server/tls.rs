@@ -70,9 +70,10 @@ impl AcmeProviders {
                     }
                 })
                 .collect::<Vec<_>>();
-            let renew_before: Duration = config
-                .property_or_default(("acme", acme_id, "renew-before"), "30d")
-                .unwrap_or_else(|| Duration::from_secs(30 * 24 * 60 * 60));
+            let renew_before: Option<Duration> = config.property(("acme", acme_id, "renew-before"));
+            let max_retries = config
+                .property_or_default::<u32>(("acme", acme_id, "max-retries"), "16")
+                .unwrap_or(16);
 
             if directory.is_empty() {
                 config.new_parse_error(format!("acme.{acme_id}.directory"), "Missing property");
@@ -176,6 +177,7 @@ impl AcmeProviders {
                     challenge,
                     eab,
                     renew_before,
+                    max_retries,
                     default,
                 ) {
                     Ok(acme_provider) => {
@@ -6,19 +6,23 @@
 
 use std::{sync::Arc, time::Duration};
 
-use ahash::AHashMap;
+use ahash::{AHashMap, AHashSet};
 use mail_auth::{
     common::crypto::{Algorithm, Ed25519Key, HashAlgorithm, RsaKey, Sha256, SigningKey},
     dkim::{Canonicalization, Done},
 };
 use mail_parser::decoders::base64::base64_decode;
 use utils::config::{
+    ipmask::IpAddrMask,
     utils::{AsKey, ParseValue},
     Config,
 };
 
+use store::rand::{distributions::Alphanumeric, thread_rng, Rng};
+
 use crate::{
     config::CONNECTION_VARS,
+    dkim::DkimSignPolicy,
     expr::{self, if_block::IfBlock, tokenizer::TokenMap, Constant, ConstantValue},
 };
 
@@ -31,6 +35,7 @@ pub struct MailAuthConfig {
     pub spf: SpfAuthConfig,
     pub dmarc: DmarcAuthConfig,
     pub iprev: IpRevAuthConfig,
+    pub trusted_forwarder: TrustedForwarderConfig,
 
     pub signers: AHashMap<String, Arc<DkimSigner>>,
     pub sealers: AHashMap<String, Arc<ArcSealer>>,
@@ -41,6 +46,19 @@ pub struct DkimAuthConfig {
     pub verify: IfBlock,
     pub sign: IfBlock,
     pub strict: bool,
+    pub managed: ManagedDkimConfig,
+}
+
+// Keys generated and rotated through the management API, stored encrypted
+// and selected by the domain of the message author
+#[derive(Clone)]
+pub struct ManagedDkimConfig {
+    pub sign: IfBlock,
+    pub key: String,
+    pub policy: DkimSignPolicy,
+    pub rotation_overlap: Duration,
+    // How often the published DNS records of the keys are verified
+    pub check_interval: Option<Duration>,
 }
 
 #[derive(Clone)]
@@ -65,6 +83,15 @@ pub struct IpRevAuthConfig {
     pub verify: IfBlock,
 }
 
+// Edge MTAs whose Authentication-Results are imported instead of
+// evaluating SPF, DKIM, ARC and DMARC locally
+#[derive(Clone, Default)]
+pub struct TrustedForwarderConfig {
+    pub networks: Vec<IpAddrMask>,
+    pub hosts: AHashSet<String>,
+    pub authserv_id: Option<String>,
+}
+
 #[derive(Debug, Clone, Copy, Default)]
 pub enum VerifyStrategy {
     #[default]
@@ -103,6 +130,17 @@ impl Default for MailAuthConfig {
                     "false",
                 ),
                 strict: true,
+                managed: ManagedDkimConfig {
+                    sign: IfBlock::new::<()>(
+                        "auth.dkim.managed.sign",
+                        [("is_local_domain('*', sender_domain)", "true")],
+                        "false",
+                    ),
+                    key: Default::default(),
+                    policy: DkimSignPolicy::default(),
+                    rotation_overlap: Duration::from_secs(7 * 86400),
+                    check_interval: Some(Duration::from_secs(12 * 3600)),
+                },
             },
             arc: ArcAuthConfig {
                 verify: IfBlock::new::<VerifyStrategy>("auth.arc.verify", [], "relaxed"),
@@ -150,6 +188,7 @@ impl Default for MailAuthConfig {
                     "relaxed",
                 ),
             },
+            trusted_forwarder: Default::default(),
             signers: Default::default(),
             sealers: Default::default(),
         }
@@ -169,6 +208,11 @@ impl MailAuthConfig {
         for (value, key, token_map) in [
             (&mut mail_auth.dkim.verify, "auth.dkim.verify", &rcpt_vars),
             (&mut mail_auth.dkim.sign, "auth.dkim.sign", &rcpt_vars),
+            (
+                &mut mail_auth.dkim.managed.sign,
+                "auth.dkim.managed.sign",
+                &rcpt_vars,
+            ),
             (&mut mail_auth.arc.verify, "auth.arc.verify", &rcpt_vars),
             (&mut mail_auth.arc.seal, "auth.arc.seal", &rcpt_vars),
             (
@@ -191,6 +235,8 @@ impl MailAuthConfig {
         mail_auth.dkim.strict = config
             .property_or_default("auth.dkim.strict", "true")
             .unwrap_or(true);
+        mail_auth.dkim.managed.parse(config);
+        mail_auth.trusted_forwarder = TrustedForwarderConfig::parse(config);
 
         // Parse signatures
         for id in config
@@ -209,6 +255,103 @@ impl MailAuthConfig {
     }
 }
 
+impl ManagedDkimConfig {
+    fn parse(&mut self, config: &mut Config) {
+        self.key = config
+            .value("auth.dkim.managed.key")
+            .filter(|key| !key.is_empty())
+            .map(|key| key.to_string())
+            .unwrap_or_else(|| {
+                thread_rng()
+                    .sample_iter(Alphanumeric)
+                    .take(64)
+                    .map(char::from)
+                    .collect::<String>()
+            });
+
+        for (key, list) in [
+            ("auth.dkim.managed.headers", &mut self.policy.headers),
+            ("auth.dkim.managed.oversign", &mut self.policy.oversign),
+        ] {
+            let values = config
+                .values(key)
+                .map(|(_, v)| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+                .collect::<Vec<_>>();
+            if !values.is_empty() {
+                *list = values;
+            }
+        }
+        if let Some(canonicalization) = config.value("auth.dkim.managed.canonicalization") {
+            let canonicalization = canonicalization.trim().to_string();
+            if config
+                .try_parse_value::<DkimCanonicalization>(
+                    "auth.dkim.managed.canonicalization",
+                    &canonicalization,
+                )
+                .is_some()
+            {
+                self.policy.canonicalization = canonicalization;
+            }
+        }
+        self.policy.subdomains = config
+            .property_or_default("auth.dkim.managed.subdomains", "false")
+            .unwrap_or_default();
+        self.policy.expire = config
+            .property::<Duration>("auth.dkim.managed.expire")
+            .map(|expire| expire.as_secs());
+        self.rotation_overlap = config
+            .property_or_default::<Duration>("auth.dkim.managed.rotation-overlap", "7d")
+            .unwrap_or(self.rotation_overlap);
+        self.check_interval = config
+            .property_or_default::<Option<Duration>>("auth.dkim.managed.check-interval", "12h")
+            .unwrap_or(self.check_interval);
+    }
+}
+
+impl TrustedForwarderConfig {
+    pub fn parse(config: &mut Config) -> Self {
+        let mut forwarder = TrustedForwarderConfig {
+            authserv_id: config
+                .value("auth.trusted-forwarder.authserv-id")
+                .map(|id| id.trim().to_lowercase())
+                .filter(|id| !id.is_empty()),
+            ..Default::default()
+        };
+
+        for host in config
+            .values("auth.trusted-forwarder.hosts")
+            .map(|(_, host)| host.trim().to_string())
+            .collect::<Vec<_>>()
+        {
+            if host.parse::<std::net::IpAddr>().is_ok() || host.contains('/') {
+                if let Some(network) =
+                    config.try_parse_value::<IpAddrMask>("auth.trusted-forwarder.hosts", &host)
+                {
+                    forwarder.networks.push(network);
+                }
+            } else if !host.is_empty() {
+                forwarder
+                    .hosts
+                    .insert(host.trim_end_matches('.').to_lowercase());
+            }
+        }
+
+        if forwarder.authserv_id.is_none() && forwarder.is_enabled() {
+            config.new_build_error(
+                "auth.trusted-forwarder.authserv-id",
+                "Trusted forwarders require an authserv-id",
+            );
+        }
+
+        forwarder
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.networks.is_empty() || !self.hosts.is_empty()
+    }
+}
+
 fn build_signature(config: &mut Config, id: &str) -> Option<(DkimSigner, ArcSealer)> {
     match config.property_require::<Algorithm>(("signature", id, "algorithm"))? {
         Algorithm::RsaSha256 => {
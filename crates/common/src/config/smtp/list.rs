@@ -0,0 +1,45 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use utils::config::{cron::SimpleCron, utils::ParseValue, Config};
+
+#[derive(Debug, Clone)]
+pub struct MailingListConfig {
+    pub bounce_threshold: u64,
+    pub bounce_expire: Duration,
+    pub digest_frequency: SimpleCron,
+    pub digest_max_messages: usize,
+}
+
+impl MailingListConfig {
+    pub fn parse(config: &mut Config) -> Option<Self> {
+        if !config
+            .property_or_default("mailing-list.enable", "false")
+            .unwrap_or(false)
+        {
+            return None;
+        }
+
+        Some(MailingListConfig {
+            bounce_threshold: config
+                .property_or_default::<u64>("mailing-list.bounce.threshold", "5")
+                .unwrap_or(5)
+                .max(1),
+            bounce_expire: config
+                .property_or_default("mailing-list.bounce.expire", "30d")
+                .unwrap_or_else(|| Duration::from_secs(30 * 86400)),
+            digest_frequency: config
+                .property_or_default::<SimpleCron>("mailing-list.digest.frequency", "0 8 *")
+                .unwrap_or_else(|| SimpleCron::parse_value("0 8 *").unwrap()),
+            digest_max_messages: config
+                .property_or_default::<usize>("mailing-list.digest.max-messages", "100")
+                .unwrap_or(100)
+                .max(1),
+        })
+    }
+}
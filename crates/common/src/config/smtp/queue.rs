@@ -4,6 +4,8 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+use std::time::Duration;
+
 use ahash::AHashMap;
 use mail_auth::IpLookupStrategy;
 use mail_send::Credentials;
@@ -31,6 +33,7 @@ pub struct QueueConfig {
     // Outbound
     pub hostname: IfBlock,
     pub next_hop: IfBlock,
+    pub transport: IfBlock,
     pub max_mx: IfBlock,
     pub max_multihomed: IfBlock,
     pub ip_strategy: IfBlock,
@@ -41,12 +44,18 @@ pub struct QueueConfig {
     // Timeouts
     pub timeout: QueueOutboundTimeout,
 
+    // Connection reuse
+    pub connection: QueueOutboundConnection,
+
     // Throttle and Quotas
     pub throttle: QueueThrottle,
     pub quota: QueueQuotas,
 
     // Relay hosts
     pub relay_hosts: AHashMap<String, RelayHost>,
+
+    // Retry strategies
+    pub retry_strategies: AHashMap<String, RetryStrategy>,
 }
 
 #[derive(Clone)]
@@ -82,6 +91,12 @@ pub struct QueueOutboundTimeout {
     pub mta_sts: IfBlock,
 }
 
+#[derive(Clone)]
+pub struct QueueOutboundConnection {
+    pub idle_timeout: IfBlock,
+    pub max_messages: IfBlock,
+}
+
 #[derive(Debug, Clone)]
 pub struct QueueThrottle {
     pub sender: Vec<Throttle>,
@@ -113,6 +128,31 @@ pub struct RelayHost {
     pub auth: Option<Credentials<String>>,
     pub tls_implicit: bool,
     pub tls_allow_invalid_certs: bool,
+    pub fallback: RelayFallback,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RelayFallback {
+    #[default]
+    Queue,
+    Reject,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RetryStrategy {
+    Fixed(Vec<Duration>),
+    Exponential {
+        initial: Duration,
+        factor: f64,
+        max: Duration,
+        jitter: f64,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RetrySchedule {
+    Intervals(Vec<Duration>),
+    Strategy(String),
 }
 
 #[derive(Debug, Clone, Copy, Default)]
@@ -146,6 +186,7 @@ impl Default for QueueConfig {
                 [],
                 "false",
             ),
+            transport: IfBlock::empty("queue.outbound.transport"),
             max_mx: IfBlock::new::<()>("queue.outbound.limits.mx", [], "5"),
             max_multihomed: IfBlock::new::<()>("queue.outbound.limits.multihomed", [], "2"),
             ip_strategy: IfBlock::new::<IpLookupStrategy>(
@@ -204,6 +245,14 @@ impl Default for QueueConfig {
                 data: IfBlock::new::<()>("queue.outbound.timeouts.data", [], "10m"),
                 mta_sts: IfBlock::new::<()>("queue.outbound.timeouts.mta-sts", [], "10m"),
             },
+            connection: QueueOutboundConnection {
+                idle_timeout: IfBlock::empty("queue.outbound.connection.idle-timeout"),
+                max_messages: IfBlock::new::<()>(
+                    "queue.outbound.connection.max-messages",
+                    [],
+                    "100",
+                ),
+            },
             throttle: QueueThrottle {
                 sender: Default::default(),
                 rcpt: Default::default(),
@@ -215,6 +264,7 @@ impl Default for QueueConfig {
                 rcpt_domain: Default::default(),
             },
             relay_hosts: Default::default(),
+            retry_strategies: Default::default(),
         }
     }
 }
@@ -223,6 +273,7 @@ impl QueueConfig {
     pub fn parse(config: &mut Config) -> Self {
         let mut queue = QueueConfig::default();
         let rcpt_vars = TokenMap::default().with_variables(SMTP_QUEUE_RCPT_VARS);
+        let transport_vars = TokenMap::default().with_variables(SMTP_QUEUE_TRANSPORT_VARS);
         let sender_vars = TokenMap::default().with_variables(SMTP_QUEUE_SENDER_VARS);
         let mx_vars = TokenMap::default().with_variables(SMTP_QUEUE_MX_VARS);
         let host_vars = TokenMap::default().with_variables(SMTP_QUEUE_HOST_VARS);
@@ -234,7 +285,7 @@ impl QueueConfig {
             (&mut queue.retry, "queue.schedule.retry", &host_vars),
             (&mut queue.notify, "queue.schedule.notify", &rcpt_vars),
             (&mut queue.expire, "queue.schedule.expire", &rcpt_vars),
-            (&mut queue.hostname, "queue.outbound.hostname", &sender_vars),
+            (&mut queue.hostname, "queue.outbound.hostname", &mx_vars),
             (&mut queue.max_mx, "queue.outbound.limits.mx", &rcpt_vars),
             (
                 &mut queue.max_multihomed,
@@ -257,6 +308,11 @@ impl QueueConfig {
                 &mx_vars,
             ),
             (&mut queue.next_hop, "queue.outbound.next-hop", &rcpt_vars),
+            (
+                &mut queue.transport,
+                "queue.outbound.transport",
+                &transport_vars,
+            ),
             (&mut queue.tls.dane, "queue.outbound.tls.dane", &dane_vars),
             (
                 &mut queue.tls.mta_sts,
@@ -313,6 +369,16 @@ impl QueueConfig {
                 "queue.outbound.timeouts.mta-sts",
                 &host_vars,
             ),
+            (
+                &mut queue.connection.idle_timeout,
+                "queue.outbound.connection.idle-timeout",
+                &host_vars,
+            ),
+            (
+                &mut queue.connection.max_messages,
+                "queue.outbound.connection.max-messages",
+                &host_vars,
+            ),
             (&mut queue.dsn.name, "report.dsn.from-name", &sender_vars),
             (
                 &mut queue.dsn.address,
@@ -349,13 +415,80 @@ impl QueueConfig {
                 tls_implicit: Default::default(),
                 tls_allow_invalid_certs: Default::default(),
                 auth: None,
+                fallback: RelayFallback::Queue,
             },
         );
 
+        // Parse retry strategies
+        queue.retry_strategies = config
+            .sub_keys("queue.retry-strategy", ".type")
+            .map(|id| id.to_string())
+            .collect::<Vec<_>>()
+            .into_iter()
+            .filter_map(|id| parse_retry_strategy(config, &id).map(|strategy| (id, strategy)))
+            .collect();
+
         queue
     }
 }
 
+fn parse_retry_strategy(config: &mut Config, id: &str) -> Option<RetryStrategy> {
+    match config
+        .value_require(("queue.retry-strategy", id, "type"))?
+        .to_string()
+        .as_str()
+    {
+        "fixed" => {
+            let intervals = config
+                .properties::<Duration>(("queue.retry-strategy", id, "intervals"))
+                .into_iter()
+                .map(|(_, interval)| interval)
+                .collect::<Vec<_>>();
+            if !intervals.is_empty() {
+                Some(RetryStrategy::Fixed(intervals))
+            } else {
+                config.new_parse_error(
+                    ("queue.retry-strategy", id, "intervals"),
+                    "Fixed retry strategy requires at least one interval.",
+                );
+                None
+            }
+        }
+        "exponential" => {
+            let strategy = RetryStrategy::Exponential {
+                initial: config
+                    .property_or_default(("queue.retry-strategy", id, "initial"), "2m")?,
+                factor: config.property_or_default(("queue.retry-strategy", id, "factor"), "2")?,
+                max: config.property_or_default(("queue.retry-strategy", id, "max"), "4h")?,
+                jitter: config
+                    .property_or_default(("queue.retry-strategy", id, "jitter"), "0.1")?,
+            };
+            match &strategy {
+                RetryStrategy::Exponential { factor, .. } if *factor < 1.0 => {
+                    config.new_parse_error(
+                        ("queue.retry-strategy", id, "factor"),
+                        "Backoff factor must be greater than or equal to 1.",
+                    );
+                    None
+                }
+                RetryStrategy::Exponential { jitter, .. } if !(0.0..=1.0).contains(jitter) => {
+                    config.new_parse_error(
+                        ("queue.retry-strategy", id, "jitter"),
+                        "Jitter must be a fraction between 0 and 1.",
+                    );
+                    None
+                }
+                _ => Some(strategy),
+            }
+        }
+        other => {
+            let err = format!("Invalid retry strategy type {other:?}.");
+            config.new_parse_error(("queue.retry-strategy", id, "type"), err);
+            None
+        }
+    }
+}
+
 fn parse_relay_host(config: &mut Config, id: &str) -> Option<RelayHost> {
     Some(RelayHost {
         address: config.property_require(("remote", id, "address"))?,
@@ -379,6 +512,9 @@ fn parse_relay_host(config: &mut Config, id: &str) -> Option<RelayHost> {
         tls_allow_invalid_certs: config
             .property(("remote", id, "tls.allow-invalid-certs"))
             .unwrap_or(false),
+        fallback: config
+            .property(("remote", id, "fallback"))
+            .unwrap_or_default(),
     })
 }
 
@@ -546,6 +682,16 @@ impl ParseValue for RequireOptional {
     }
 }
 
+impl ParseValue for RelayFallback {
+    fn parse_value(value: &str) -> Result<Self, String> {
+        match value {
+            "queue" | "retry" => Ok(RelayFallback::Queue),
+            "reject" | "bounce" => Ok(RelayFallback::Reject),
+            _ => Err(format!("Invalid relay fallback value {:?}.", value,)),
+        }
+    }
+}
+
 impl<'x> TryFrom<Variable<'x>> for RequireOptional {
     type Error = ();
 
@@ -559,6 +705,30 @@ impl<'x> TryFrom<Variable<'x>> for RequireOptional {
     }
 }
 
+impl<'x> TryFrom<Variable<'x>> for RetrySchedule {
+    type Error = ();
+
+    fn try_from(value: Variable<'x>) -> Result<Self, Self::Error> {
+        match value {
+            // Strings that do not parse as a duration name a retry strategy
+            Variable::String(value) => Duration::parse_value(&value)
+                .map(|interval| RetrySchedule::Intervals(vec![interval]))
+                .or_else(|_| {
+                    if !value.is_empty() {
+                        Ok(RetrySchedule::Strategy(value.into_owned()))
+                    } else {
+                        Err(())
+                    }
+                }),
+            value => Vec::<Duration>::try_from(value)
+                .ok()
+                .filter(|intervals| !intervals.is_empty())
+                .map(RetrySchedule::Intervals)
+                .ok_or(()),
+        }
+    }
+}
+
 impl From<RequireOptional> for Constant {
     fn from(value: RequireOptional) -> Self {
         Constant::Integer(match value {
@@ -629,6 +799,7 @@ impl std::fmt::Debug for RelayHost {
             .field("protocol", &self.protocol)
             .field("tls_implicit", &self.tls_implicit)
             .field("tls_allow_invalid_certs", &self.tls_allow_invalid_certs)
+            .field("fallback", &self.fallback)
             .finish()
     }
 }
@@ -6,7 +6,9 @@
 
 use std::{
     net::{SocketAddr, ToSocketAddrs},
+    path::PathBuf,
     str::FromStr,
+    sync::Arc,
     time::Duration,
 };
 
@@ -17,7 +19,11 @@ use hyper::{
     HeaderMap,
 };
 use smtp_proto::*;
-use utils::config::{utils::ParseValue, Config};
+use tokio::sync::Semaphore;
+use utils::{
+    config::{utils::ParseValue, Config},
+    map::ttl_dashmap::TtlDashMap,
+};
 
 use crate::{
     config::CONNECTION_VARS,
@@ -117,6 +123,19 @@ pub struct Rcpt {
     // Catch-all and sub-addressing
     pub catch_all: AddressMapping,
     pub subaddressing: AddressMapping,
+    pub subaddressing_separator: IfBlock,
+
+    // Greylisting
+    pub greylist: Greylist,
+}
+
+#[derive(Clone)]
+pub struct Greylist {
+    pub enable: IfBlock,
+    pub min_delay: Duration,
+    pub expire: Duration,
+    pub allowlist_after: u64,
+    pub allowlist_expire: Duration,
 }
 
 #[derive(Debug, Default, Clone)]
@@ -144,6 +163,14 @@ pub struct Data {
     pub add_auth_results: IfBlock,
     pub add_message_id: IfBlock,
     pub add_date: IfBlock,
+
+    // LMTP final delivery
+    pub local_delivery: IfBlock,
+    pub spam_filter: IfBlock,
+
+    // Submission filtering
+    pub outbound_script: IfBlock,
+    pub outbound_user_script: IfBlock,
 }
 
 // Ceci n'est pas une pipe
@@ -161,6 +188,7 @@ pub struct Milter {
     pub addrs: Vec<SocketAddr>,
     pub hostname: String,
     pub port: u16,
+    pub path: Option<PathBuf>,
     pub timeout_connect: Duration,
     pub timeout_command: Duration,
     pub timeout_data: Duration,
@@ -185,12 +213,18 @@ pub struct MTAHook {
     pub enable: IfBlock,
     pub id: String,
     pub url: String,
+    pub client: reqwest::Client,
     pub timeout: Duration,
     pub headers: HeaderMap,
     pub tls_allow_invalid_certs: bool,
     pub tempfail_on_error: bool,
+    pub include_body: bool,
     pub run_on_stage: AHashSet<Stage>,
     pub max_response_size: usize,
+    pub max_concurrency: Option<Arc<Semaphore>>,
+    pub cache_key: IfBlock,
+    pub cache_ttl: Duration,
+    pub cache: Arc<TtlDashMap<String, Arc<Vec<u8>>>>,
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash)]
@@ -215,8 +249,9 @@ impl SessionConfig {
         let mut session = SessionConfig::default();
         session.rcpt.catch_all = AddressMapping::parse(config, "session.rcpt.catch-all");
         session.rcpt.subaddressing = AddressMapping::parse(config, "session.rcpt.sub-addressing");
+        session.rcpt.greylist = Greylist::parse(config);
         session.milters = config
-            .sub_keys("session.milter", ".hostname")
+            .sub_keys("session.milter", "")
             .map(|s| s.to_string())
             .collect::<Vec<_>>()
             .into_iter()
@@ -407,6 +442,16 @@ impl SessionConfig {
                 "session.rcpt.rewrite",
                 &has_rcpt_vars,
             ),
+            (
+                &mut session.rcpt.subaddressing_separator,
+                "session.rcpt.sub-addressing-separator",
+                &has_rcpt_vars,
+            ),
+            (
+                &mut session.rcpt.greylist.enable,
+                "session.rcpt.greylist.enable",
+                &has_rcpt_vars,
+            ),
             (
                 &mut session.data.script,
                 "session.data.script",
@@ -457,6 +502,26 @@ impl SessionConfig {
                 "session.data.add-headers.date",
                 &has_rcpt_vars,
             ),
+            (
+                &mut session.data.local_delivery,
+                "session.data.local-delivery",
+                &has_rcpt_vars,
+            ),
+            (
+                &mut session.data.spam_filter,
+                "session.data.spam-filter",
+                &has_rcpt_vars,
+            ),
+            (
+                &mut session.data.outbound_script,
+                "session.data.outbound.script",
+                &has_rcpt_vars,
+            ),
+            (
+                &mut session.data.outbound_user_script,
+                "session.data.outbound.user-script",
+                &has_rcpt_vars,
+            ),
         ] {
             if let Some(if_block) = IfBlock::try_parse(config, key, token_map) {
                 *value = if_block;
@@ -467,6 +532,40 @@ impl SessionConfig {
     }
 }
 
+impl Greylist {
+    pub fn parse(config: &mut Config) -> Self {
+        let default = Greylist::default();
+
+        Greylist {
+            enable: default.enable,
+            min_delay: config
+                .property("session.rcpt.greylist.min-delay")
+                .unwrap_or(default.min_delay),
+            expire: config
+                .property("session.rcpt.greylist.expire")
+                .unwrap_or(default.expire),
+            allowlist_after: config
+                .property("session.rcpt.greylist.allowlist.after")
+                .unwrap_or(default.allowlist_after),
+            allowlist_expire: config
+                .property("session.rcpt.greylist.allowlist.expire")
+                .unwrap_or(default.allowlist_expire),
+        }
+    }
+}
+
+impl Default for Greylist {
+    fn default() -> Self {
+        Self {
+            enable: IfBlock::new::<()>("session.rcpt.greylist.enable", [], "false"),
+            min_delay: Duration::from_secs(5 * 60),
+            expire: Duration::from_secs(86400),
+            allowlist_after: 5,
+            allowlist_expire: Duration::from_secs(30 * 86400),
+        }
+    }
+}
+
 impl SessionThrottle {
     pub fn parse(config: &mut Config) -> Self {
         let mut throttle = SessionThrottle::default();
@@ -531,17 +630,16 @@ fn parse_pipe(config: &mut Config, id: &str, token_map: &TokenMap) -> Option<Pip
 }
 
 fn parse_milter(config: &mut Config, id: &str, token_map: &TokenMap) -> Option<Milter> {
-    let hostname = config
-        .value_require(("session.milter", id, "hostname"))?
-        .to_string();
-    let port = config.property_require(("session.milter", id, "port"))?;
-    Some(Milter {
-        enable: IfBlock::try_parse(config, ("session.milter", id, "enable"), token_map)
-            .unwrap_or_else(|| {
-                IfBlock::new::<()>(format!("session.milter.{id}.enable"), [], "false")
-            }),
-        id: id.to_string().into(),
-        addrs: format!("{}:{}", hostname, port)
+    // Milters listening on a Unix domain socket do not require a hostname
+    let path = config
+        .value(("session.milter", id, "path"))
+        .map(PathBuf::from);
+    let (hostname, port, addrs) = if path.is_none() {
+        let hostname = config
+            .value_require(("session.milter", id, "hostname"))?
+            .to_string();
+        let port = config.property_require(("session.milter", id, "port"))?;
+        let addrs = format!("{}:{}", hostname, port)
             .to_socket_addrs()
             .map_err(|err| {
                 config.new_build_error(
@@ -550,9 +648,39 @@ fn parse_milter(config: &mut Config, id: &str, token_map: &TokenMap) -> Option<M
                 )
             })
             .ok()?
-            .collect(),
+            .collect();
+        (hostname, port, addrs)
+    } else {
+        (
+            config
+                .value(("session.milter", id, "hostname"))
+                .unwrap_or("localhost")
+                .to_string(),
+            0,
+            Vec::new(),
+        )
+    };
+    let tls = config
+        .property_or_default(("session.milter", id, "tls"), "false")
+        .unwrap_or_default();
+    if tls && path.is_some() {
+        config.new_build_warning(
+            ("session.milter", id, "tls"),
+            "TLS is not supported for milters listening on a Unix domain socket",
+        );
+    }
+
+    Some(Milter {
+        enable: IfBlock::try_parse(config, ("session.milter", id, "enable"), token_map)
+            .unwrap_or_else(|| {
+                IfBlock::new::<()>(format!("session.milter.{id}.enable"), [], "false")
+            }),
+        id: id.to_string().into(),
+        addrs,
         hostname,
         port,
+        tls: tls && path.is_none(),
+        path,
         timeout_connect: config
             .property_or_default(("session.milter", id, "timeout.connect"), "30s")
             .unwrap_or_else(|| Duration::from_secs(30)),
@@ -562,9 +690,6 @@ fn parse_milter(config: &mut Config, id: &str, token_map: &TokenMap) -> Option<M
         timeout_data: config
             .property_or_default(("session.milter", id, "timeout.data"), "60s")
             .unwrap_or_else(|| Duration::from_secs(60)),
-        tls: config
-            .property_or_default(("session.milter", id, "tls"), "false")
-            .unwrap_or_default(),
         tls_allow_invalid_certs: config
             .property_or_default(("session.milter", id, "allow-invalid-certs"), "false")
             .unwrap_or_default(),
@@ -642,24 +767,67 @@ fn parse_hooks(config: &mut Config, id: &str, token_map: &TokenMap) -> Option<MT
         );
     }
 
+    let url = config
+        .value_require(("session.hook", id, "url"))?
+        .to_string();
+    let timeout = config
+        .property_or_default(("session.hook", id, "timeout"), "30s")
+        .unwrap_or_else(|| Duration::from_secs(30));
+    let tls_allow_invalid_certs = config
+        .property_or_default(("session.hook", id, "allow-invalid-certs"), "false")
+        .unwrap_or_default();
+
+    // Build HTTP client, using a client certificate if configured
+    let mut client = reqwest::Client::builder()
+        .timeout(timeout)
+        .danger_accept_invalid_certs(tls_allow_invalid_certs);
+    if let (Some(cert), Some(pk)) = (
+        config
+            .value(("session.hook", id, "tls.certificate"))
+            .map(|s| s.to_string()),
+        config
+            .value(("session.hook", id, "tls.private-key"))
+            .map(|s| s.to_string()),
+    ) {
+        match reqwest::Identity::from_pem(format!("{cert}\n{pk}").as_bytes()) {
+            Ok(identity) => {
+                client = client.identity(identity);
+            }
+            Err(err) => {
+                config.new_build_error(
+                    ("session.hook", id, "tls.certificate"),
+                    format!("Invalid client certificate: {err}"),
+                );
+                return None;
+            }
+        }
+    }
+    let client = client
+        .build()
+        .map_err(|err| {
+            config.new_build_error(
+                ("session.hook", id, "url"),
+                format!("Failed to create HTTP client: {err}"),
+            )
+        })
+        .ok()?;
+
     Some(MTAHook {
         enable: IfBlock::try_parse(config, ("session.hook", id, "enable"), token_map)
             .unwrap_or_else(|| {
                 IfBlock::new::<()>(format!("session.hook.{id}.enable"), [], "false")
             }),
         id: id.to_string(),
-        url: config
-            .value_require(("session.hook", id, "url"))?
-            .to_string(),
-        timeout: config
-            .property_or_default(("session.hook", id, "timeout"), "30s")
-            .unwrap_or_else(|| Duration::from_secs(30)),
-        tls_allow_invalid_certs: config
-            .property_or_default(("session.hook", id, "allow-invalid-certs"), "false")
-            .unwrap_or_default(),
+        url,
+        client,
+        timeout,
+        tls_allow_invalid_certs,
         tempfail_on_error: config
             .property_or_default(("session.hook", id, "options.tempfail-on-error"), "true")
             .unwrap_or(true),
+        include_body: config
+            .property_or_default(("session.hook", id, "options.include-body"), "true")
+            .unwrap_or(true),
         run_on_stage: parse_stages(config, "session.hook", id),
         max_response_size: config
             .property_or_default(
@@ -667,6 +835,15 @@ fn parse_hooks(config: &mut Config, id: &str, token_map: &TokenMap) -> Option<MT
                 "52428800",
             )
             .unwrap_or(52428800),
+        max_concurrency: config
+            .property::<usize>(("session.hook", id, "options.max-concurrency"))
+            .map(|permits| Arc::new(Semaphore::new(permits))),
+        cache_key: IfBlock::try_parse(config, ("session.hook", id, "cache.key"), token_map)
+            .unwrap_or_else(|| IfBlock::empty(format!("session.hook.{id}.cache.key"))),
+        cache_ttl: config
+            .property_or_default(("session.hook", id, "cache.ttl"), "5m")
+            .unwrap_or_else(|| Duration::from_secs(300)),
+        cache: Default::default(),
         headers,
     })
 }
@@ -797,6 +974,12 @@ impl Default for SessionConfig {
                 max_recipients: IfBlock::new::<()>("session.rcpt.max-recipients", [], "100"),
                 catch_all: AddressMapping::Enable,
                 subaddressing: AddressMapping::Enable,
+                subaddressing_separator: IfBlock::new::<()>(
+                    "session.rcpt.sub-addressing-separator",
+                    [],
+                    "'+'",
+                ),
+                greylist: Greylist::default(),
             },
             data: Data {
                 #[cfg(feature = "test_mode")]
@@ -845,6 +1028,21 @@ impl Default for SessionConfig {
                     [("local_port == 25", "true")],
                     "false",
                 ),
+                local_delivery: IfBlock::new::<()>("session.data.local-delivery", [], "false"),
+                spam_filter: IfBlock::new::<()>("session.data.spam-filter", [], "true"),
+                outbound_script: IfBlock::empty("session.data.outbound.script"),
+                #[cfg(feature = "test_mode")]
+                outbound_user_script: IfBlock::new::<()>(
+                    "session.data.outbound.user-script",
+                    [],
+                    "false",
+                ),
+                #[cfg(not(feature = "test_mode"))]
+                outbound_user_script: IfBlock::new::<()>(
+                    "session.data.outbound.user-script",
+                    [],
+                    "true",
+                ),
             },
             extensions: Extensions {
                 pipelining: IfBlock::new::<()>("session.extensions.pipelining", [], "true"),
@@ -899,11 +1097,12 @@ impl ParseValue for Mechanism {
             "PLAIN" => AUTH_PLAIN,
             "XOAUTH2" => AUTH_XOAUTH2,
             "OAUTHBEARER" => AUTH_OAUTHBEARER,
-            /*"SCRAM-SHA-256-PLUS" => AUTH_SCRAM_SHA_256_PLUS,
+            "SCRAM-SHA-256-PLUS" => AUTH_SCRAM_SHA_256_PLUS,
             "SCRAM-SHA-256" => AUTH_SCRAM_SHA_256,
             "SCRAM-SHA-1-PLUS" => AUTH_SCRAM_SHA_1_PLUS,
             "SCRAM-SHA-1" => AUTH_SCRAM_SHA_1,
-            "XOAUTH" => AUTH_XOAUTH,
+            "CRAM-MD5" => AUTH_CRAM_MD5,
+            /*"XOAUTH" => AUTH_XOAUTH,
             "9798-M-DSA-SHA1" => AUTH_9798_M_DSA_SHA1,
             "9798-M-ECDSA-SHA1" => AUTH_9798_M_ECDSA_SHA1,
             "9798-M-RSA-SHA1-ENC" => AUTH_9798_M_RSA_SHA1_ENC,
@@ -934,7 +1133,6 @@ impl ParseValue for Mechanism {
             "SPNEGO" => AUTH_SPNEGO,
             "SPNEGO-PLUS" => AUTH_SPNEGO_PLUS,
             "SXOVER-PLUS" => AUTH_SXOVER_PLUS,
-            "CRAM-MD5" => AUTH_CRAM_MD5,
             "DIGEST-MD5" => AUTH_DIGEST_MD5,
             "ANONYMOUS" => AUTH_ANONYMOUS,*/
             _ => return Err(format!("Unsupported mechanism {:?}.", value)),
@@ -977,7 +1175,12 @@ impl ConstantValue for Mechanism {
             .add_constant("login", Mechanism(AUTH_LOGIN))
             .add_constant("plain", Mechanism(AUTH_PLAIN))
             .add_constant("xoauth2", Mechanism(AUTH_XOAUTH2))
-            .add_constant("oauthbearer", Mechanism(AUTH_OAUTHBEARER));
+            .add_constant("oauthbearer", Mechanism(AUTH_OAUTHBEARER))
+            .add_constant("scram_sha_256_plus", Mechanism(AUTH_SCRAM_SHA_256_PLUS))
+            .add_constant("scram_sha_256", Mechanism(AUTH_SCRAM_SHA_256))
+            .add_constant("scram_sha_1_plus", Mechanism(AUTH_SCRAM_SHA_1_PLUS))
+            .add_constant("scram_sha_1", Mechanism(AUTH_SCRAM_SHA_1))
+            .add_constant("cram_md5", Mechanism(AUTH_CRAM_MD5));
     }
 }
 
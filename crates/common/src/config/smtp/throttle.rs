@@ -4,7 +4,7 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use utils::config::{utils::AsKey, Config, Rate};
+use utils::config::{utils::AsKey, Config, Rate, RateWindow};
 
 use crate::expr::{tokenizer::TokenMap, Expression};
 
@@ -89,6 +89,9 @@ fn parse_throttle_item(
             .property::<Option<Rate>>((prefix.as_str(), "rate"))
             .filter(|v| v.as_ref().map_or(false, |r| r.requests > 0))
             .unwrap_or_default(),
+        window: config
+            .property_or_default::<RateWindow>((prefix.as_str(), "window"), "fixed")
+            .unwrap_or_default(),
     };
 
     // Validate
@@ -0,0 +1,382 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{path::PathBuf, time::Duration};
+
+use ahash::AHashSet;
+use deadpool::{managed::Pool, Runtime};
+use nlp::bayes::BayesClassifier;
+use utils::config::{utils::ParseValue, Config};
+
+use crate::{
+    clamav::{ClamAvAddress, ClamAvConnectionManager},
+    expr::{if_block::IfBlock, tokenizer::TokenMap},
+};
+
+use super::smtp::SMTP_RCPT_TO_VARS;
+
+#[derive(Default, Clone)]
+pub struct SpamFilterConfig {
+    pub bayes: BayesConfig,
+    pub rspamd: Option<RspamdConfig>,
+    pub attachments: AttachmentConfig,
+    pub clamav: Option<ClamAvConfig>,
+    pub quarantine: QuarantineConfig,
+}
+
+#[derive(Clone)]
+pub struct BayesConfig {
+    pub store: Option<String>,
+    pub account_models: bool,
+    pub account_weight: f64,
+    pub token_expiry: Option<Duration>,
+    pub train_keywords: bool,
+    pub classifier: BayesClassifier,
+    pub spam_threshold: f64,
+}
+
+#[derive(Clone)]
+pub struct RspamdConfig {
+    pub enable: IfBlock,
+    pub url: String,
+    pub controller_url: String,
+    pub password: Option<String>,
+    pub client: reqwest::Client,
+    pub timeout: Duration,
+    pub tempfail_on_error: bool,
+    pub max_response_size: usize,
+    pub train: bool,
+}
+
+#[derive(Clone)]
+pub struct AttachmentConfig {
+    pub enable: IfBlock,
+    pub max_depth: usize,
+    pub max_entries: usize,
+    pub max_size: usize,
+    pub max_ratio: u64,
+    pub policies: Vec<(FilterAction, AHashSet<String>)>,
+}
+
+#[derive(Clone)]
+pub struct ClamAvConfig {
+    pub enable: IfBlock,
+    pub pool: Pool<ClamAvConnectionManager>,
+    pub timeout: Duration,
+    pub max_stream_size: usize,
+    pub chunk_size: usize,
+    pub tempfail_on_error: bool,
+    pub action: FilterAction,
+    pub quarantine_account: Option<String>,
+    pub quarantine_folder: String,
+}
+
+#[derive(Clone)]
+pub struct QuarantineConfig {
+    pub enable: bool,
+    pub retention: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterAction {
+    Reject,
+    Quarantine,
+    Tag,
+}
+
+impl SpamFilterConfig {
+    pub fn parse(config: &mut Config) -> Self {
+        SpamFilterConfig {
+            bayes: BayesConfig::parse(config),
+            rspamd: RspamdConfig::parse(config),
+            attachments: AttachmentConfig::parse(config),
+            clamav: ClamAvConfig::parse(config),
+            quarantine: QuarantineConfig::parse(config),
+        }
+    }
+}
+
+impl BayesConfig {
+    pub fn parse(config: &mut Config) -> Self {
+        let mut classifier = BayesClassifier::default();
+        classifier.min_token_hits = config
+            .property_or_default("spam-filter.bayes.classify.min-token-hits", "2")
+            .unwrap_or(2);
+        classifier.min_tokens = config
+            .property_or_default("spam-filter.bayes.classify.min-tokens", "11")
+            .unwrap_or(11);
+        classifier.min_prob_strength = config
+            .property_or_default("spam-filter.bayes.classify.min-prob-strength", "0.05")
+            .unwrap_or(0.05);
+        classifier.min_learns = config
+            .property_or_default("spam-filter.bayes.classify.min-learns", "200")
+            .unwrap_or(200);
+
+        BayesConfig {
+            store: config
+                .value("spam-filter.bayes.store")
+                .filter(|id| !id.is_empty())
+                .map(|id| id.to_string()),
+            account_models: config
+                .property_or_default("spam-filter.bayes.account.enable", "false")
+                .unwrap_or(false),
+            account_weight: config
+                .property_or_default::<f64>("spam-filter.bayes.account.weight", "0.5")
+                .unwrap_or(0.5)
+                .clamp(0.0, 1.0),
+            token_expiry: config
+                .property_or_default::<Option<Duration>>("spam-filter.bayes.auto-expire", "90d")
+                .unwrap_or_default(),
+            train_keywords: config
+                .property_or_default("spam-filter.bayes.train.keywords", "true")
+                .unwrap_or(true),
+            spam_threshold: config
+                .property_or_default("spam-filter.bayes.classify.spam-threshold", "0.7")
+                .unwrap_or(0.7),
+            classifier,
+        }
+    }
+}
+
+impl Default for BayesConfig {
+    fn default() -> Self {
+        BayesConfig {
+            store: None,
+            account_models: false,
+            account_weight: 0.5,
+            token_expiry: Some(Duration::from_secs(90 * 24 * 60 * 60)),
+            train_keywords: true,
+            classifier: BayesClassifier::default(),
+            spam_threshold: 0.7,
+        }
+    }
+}
+
+impl RspamdConfig {
+    pub fn parse(config: &mut Config) -> Option<Self> {
+        let url = config
+            .value("spam-filter.rspamd.url")?
+            .trim_end_matches('/')
+            .to_string();
+        let controller_url = config
+            .value("spam-filter.rspamd.controller-url")
+            .map(|url| url.trim_end_matches('/').to_string())
+            .unwrap_or_else(|| url.clone());
+        let timeout = config
+            .property_or_default("spam-filter.rspamd.timeout", "15s")
+            .unwrap_or_else(|| Duration::from_secs(15));
+        let client = reqwest::Client::builder()
+            .timeout(timeout)
+            .danger_accept_invalid_certs(
+                config
+                    .property_or_default("spam-filter.rspamd.allow-invalid-certs", "false")
+                    .unwrap_or_default(),
+            )
+            .build()
+            .map_err(|err| {
+                config.new_build_error(
+                    "spam-filter.rspamd.url",
+                    format!("Failed to create HTTP client: {err}"),
+                )
+            })
+            .ok()?;
+
+        Some(RspamdConfig {
+            enable: IfBlock::try_parse(
+                config,
+                "spam-filter.rspamd.enable",
+                &TokenMap::default().with_variables(SMTP_RCPT_TO_VARS),
+            )
+            .unwrap_or_else(|| IfBlock::new::<()>("spam-filter.rspamd.enable", [], "true")),
+            url,
+            controller_url,
+            password: config
+                .value("spam-filter.rspamd.password")
+                .map(|password| password.to_string()),
+            client,
+            timeout,
+            tempfail_on_error: config
+                .property_or_default("spam-filter.rspamd.tempfail-on-error", "false")
+                .unwrap_or(false),
+            max_response_size: config
+                .property_or_default("spam-filter.rspamd.max-response-size", "1048576")
+                .unwrap_or(1048576),
+            train: config
+                .property_or_default("spam-filter.rspamd.train", "true")
+                .unwrap_or(true),
+        })
+    }
+}
+
+impl AttachmentConfig {
+    pub fn parse(config: &mut Config) -> Self {
+        let mut policies = Vec::new();
+        for action in [
+            FilterAction::Reject,
+            FilterAction::Quarantine,
+            FilterAction::Tag,
+        ] {
+            let types = config
+                .values(("spam-filter.attachments.policy", action.as_str()))
+                .map(|(_, value)| value.trim().trim_start_matches('.').to_lowercase())
+                .filter(|value| !value.is_empty())
+                .collect::<AHashSet<_>>();
+            if !types.is_empty() {
+                policies.push((action, types));
+            }
+        }
+
+        AttachmentConfig {
+            enable: IfBlock::try_parse(
+                config,
+                "spam-filter.attachments.enable",
+                &TokenMap::default().with_variables(SMTP_RCPT_TO_VARS),
+            )
+            .unwrap_or_else(|| IfBlock::new::<()>("spam-filter.attachments.enable", [], "true")),
+            max_depth: config
+                .property_or_default("spam-filter.attachments.max-depth", "3")
+                .unwrap_or(3),
+            max_entries: config
+                .property_or_default("spam-filter.attachments.max-entries", "1000")
+                .unwrap_or(1000),
+            max_size: config
+                .property_or_default("spam-filter.attachments.max-size", "52428800")
+                .unwrap_or(52428800),
+            max_ratio: config
+                .property_or_default::<u64>("spam-filter.attachments.max-ratio", "100")
+                .unwrap_or(100)
+                .max(1),
+            policies,
+        }
+    }
+}
+
+impl Default for AttachmentConfig {
+    fn default() -> Self {
+        AttachmentConfig {
+            enable: IfBlock::new::<()>("spam-filter.attachments.enable", [], "true"),
+            max_depth: 3,
+            max_entries: 1000,
+            max_size: 52428800,
+            max_ratio: 100,
+            policies: Vec::new(),
+        }
+    }
+}
+
+impl ClamAvConfig {
+    pub fn parse(config: &mut Config) -> Option<Self> {
+        // Addresses starting with a slash are Unix domain sockets
+        let address = config.value("spam-filter.clamav.address")?.trim();
+        let address = if address.starts_with('/') {
+            ClamAvAddress::Unix(PathBuf::from(address))
+        } else {
+            ClamAvAddress::Tcp(address.to_string())
+        };
+        let manager = ClamAvConnectionManager {
+            address,
+            timeout: config
+                .property_or_default("spam-filter.clamav.pool.timeout.create", "30s")
+                .unwrap_or_else(|| Duration::from_secs(30)),
+        };
+        let pool = Pool::builder(manager)
+            .runtime(Runtime::Tokio1)
+            .max_size(
+                config
+                    .property_or_default("spam-filter.clamav.pool.max-connections", "10")
+                    .unwrap_or(10),
+            )
+            .wait_timeout(config.property_or_default("spam-filter.clamav.pool.timeout.wait", "30s"))
+            .recycle_timeout(
+                config.property_or_default("spam-filter.clamav.pool.timeout.recycle", "30s"),
+            )
+            .build()
+            .map_err(|err| {
+                config.new_build_error(
+                    "spam-filter.clamav.address",
+                    format!("Failed to build ClamAV pool: {err}"),
+                )
+            })
+            .ok()?;
+
+        Some(ClamAvConfig {
+            enable: IfBlock::try_parse(
+                config,
+                "spam-filter.clamav.enable",
+                &TokenMap::default().with_variables(SMTP_RCPT_TO_VARS),
+            )
+            .unwrap_or_else(|| IfBlock::new::<()>("spam-filter.clamav.enable", [], "true")),
+            pool,
+            timeout: config
+                .property_or_default("spam-filter.clamav.timeout", "30s")
+                .unwrap_or_else(|| Duration::from_secs(30)),
+            max_stream_size: config
+                .property_or_default("spam-filter.clamav.max-stream-size", "26214400")
+                .unwrap_or(26214400),
+            chunk_size: config
+                .property_or_default::<usize>("spam-filter.clamav.chunk-size", "65536")
+                .unwrap_or(65536)
+                .max(1),
+            tempfail_on_error: config
+                .property_or_default("spam-filter.clamav.tempfail-on-error", "false")
+                .unwrap_or(false),
+            action: config
+                .property_or_default("spam-filter.clamav.action", "reject")
+                .unwrap_or(FilterAction::Reject),
+            quarantine_account: config
+                .value("spam-filter.clamav.quarantine.account")
+                .filter(|account| !account.is_empty())
+                .map(|account| account.trim().to_lowercase()),
+            quarantine_folder: config
+                .value("spam-filter.clamav.quarantine.folder")
+                .unwrap_or("Quarantine")
+                .to_string(),
+        })
+    }
+}
+
+impl QuarantineConfig {
+    pub fn parse(config: &mut Config) -> Self {
+        QuarantineConfig {
+            enable: config
+                .property_or_default("spam-filter.quarantine.enable", "false")
+                .unwrap_or(false),
+            retention: config
+                .property_or_default("spam-filter.quarantine.retention", "30d")
+                .unwrap_or_else(|| Duration::from_secs(30 * 86400)),
+        }
+    }
+}
+
+impl Default for QuarantineConfig {
+    fn default() -> Self {
+        QuarantineConfig {
+            enable: false,
+            retention: Duration::from_secs(30 * 86400),
+        }
+    }
+}
+
+impl FilterAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FilterAction::Reject => "reject",
+            FilterAction::Quarantine => "quarantine",
+            FilterAction::Tag => "tag",
+        }
+    }
+}
+
+impl ParseValue for FilterAction {
+    fn parse_value(value: &str) -> Result<Self, String> {
+        match value {
+            "reject" => Ok(FilterAction::Reject),
+            "quarantine" => Ok(FilterAction::Quarantine),
+            "tag" => Ok(FilterAction::Tag),
+            _ => Err(format!("Invalid action value {:?}.", value)),
+        }
+    }
+}
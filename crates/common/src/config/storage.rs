@@ -8,7 +8,7 @@ use std::sync::Arc;
 
 use ahash::AHashMap;
 use directory::Directory;
-use store::{write::purge::PurgeSchedule, BlobStore, FtsStore, LookupStore, Store};
+use store::{write::purge::PurgeSchedule, BlobStore, FtsStore, LookupStore, PubSubStore, Store};
 
 use crate::manager::config::ConfigManager;
 
@@ -18,6 +18,7 @@ pub struct Storage {
     pub blob: BlobStore,
     pub fts: FtsStore,
     pub lookup: LookupStore,
+    pub pubsub: PubSubStore,
     pub directory: Arc<Directory>,
     pub directories: AHashMap<String, Arc<Directory>>,
     pub purge_schedules: Vec<PurgeSchedule>,
@@ -27,4 +28,5 @@ pub struct Storage {
     pub blobs: AHashMap<String, BlobStore>,
     pub lookups: AHashMap<String, LookupStore>,
     pub ftss: AHashMap<String, FtsStore>,
+    pub pubsubs: AHashMap<String, PubSubStore>,
 }
@@ -22,7 +22,7 @@ use opentelemetry_sdk::{
 use opentelemetry_semantic_conventions::resource::{SERVICE_NAME, SERVICE_VERSION};
 use store::Stores;
 use trc::{ipc::subscriber::Interests, EventType, Level, TelemetryEvent};
-use utils::config::{utils::ParseValue, Config};
+use utils::config::{ipmask::IpAddrMask, utils::ParseValue, Config};
 
 use super::parse_http_headers;
 
@@ -53,6 +53,13 @@ pub struct OtelTracer {
     pub log_exporter: Box<dyn LogExporter>,
     pub log_exporter_enable: bool,
     pub throttle: Duration,
+    pub sampler: OtelSampler,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct OtelSampler {
+    pub ratio: f64,
+    pub always_on_error: bool,
 }
 
 pub struct OtelMetrics {
@@ -107,6 +114,7 @@ pub enum RotationStrategy {
 pub struct Telemetry {
     pub tracers: Tracers,
     pub metrics: Interests,
+    pub metric_label_limit: usize,
 }
 
 #[derive(Debug)]
@@ -123,9 +131,19 @@ pub struct Metrics {
     pub log_path: Option<String>,
 }
 
+#[derive(Debug, Clone, Default)]
+pub struct AuditConfig {
+    pub enable: bool,
+    pub store: Option<store::Store>,
+    pub retention: Option<Duration>,
+    pub file: Option<String>,
+    pub syslog: bool,
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct PrometheusMetrics {
     pub auth: Option<String>,
+    pub allowed_ips: Vec<IpAddrMask>,
 }
 
 impl Telemetry {
@@ -133,6 +151,9 @@ impl Telemetry {
         let mut telemetry = Telemetry {
             tracers: Tracers::parse(config, stores),
             metrics: Interests::default(),
+            metric_label_limit: config
+                .property_or_default("metrics.prometheus.max-label-values", "100")
+                .unwrap_or(100),
         };
 
         // Parse metrics
@@ -271,6 +292,15 @@ impl Tracers {
                     let span_exporter_enable = config
                         .property_or_default(("tracer", id, "enable.span-exporter"), "true")
                         .unwrap_or(true);
+                    let sampler = OtelSampler {
+                        ratio: config
+                            .property_or_default::<f64>(("tracer", id, "sampling.ratio"), "1.0")
+                            .unwrap_or(1.0)
+                            .clamp(0.0, 1.0),
+                        always_on_error: config
+                            .property_or_default(("tracer", id, "sampling.always-on-error"), "true")
+                            .unwrap_or(true),
+                    };
 
                     match config
                         .value_require(("tracer", id, "transport"))
@@ -299,6 +329,7 @@ impl Tracers {
                                         span_exporter: Box::new(span_exporter),
                                         log_exporter: Box::new(log_exporter),
                                         throttle,
+                                        sampler,
                                         span_exporter_enable,
                                         log_exporter_enable,
                                     })
@@ -368,6 +399,7 @@ impl Tracers {
                                             span_exporter: Box::new(span_exporter),
                                             log_exporter: Box::new(log_exporter),
                                             throttle,
+                                            sampler,
                                             span_exporter_enable,
                                             log_exporter_enable,
                                         })
@@ -592,6 +624,34 @@ impl Tracers {
     }
 }
 
+impl AuditConfig {
+    pub fn parse(config: &mut Config, stores: &Stores) -> Self {
+        let store_id = config.value("audit.store").map(|id| id.to_string());
+        let store = store_id.and_then(|store_id| {
+            let store = stores.stores.get(&store_id).cloned();
+            if store.is_none() {
+                let err = format!("Store {store_id} not found");
+                config.new_build_error("audit.store", err);
+            }
+            store
+        });
+
+        AuditConfig {
+            enable: config
+                .property_or_default("audit.enable", "false")
+                .unwrap_or(false),
+            store,
+            retention: config
+                .property_or_default::<Option<Duration>>("audit.retention", "365d")
+                .unwrap_or_default(),
+            file: config.value("audit.file.path").map(|path| path.to_string()),
+            syslog: config
+                .property_or_default("audit.syslog.enable", "false")
+                .unwrap_or(false),
+        }
+    }
+}
+
 impl Metrics {
     pub fn parse(config: &mut Config) -> Self {
         let mut metrics = Metrics {
@@ -633,6 +693,11 @@ impl Metrics {
                             .value("metrics.prometheus.auth.secret")
                             .map(|secret| STANDARD.encode(format!("{user}:{secret}")))
                     }),
+                allowed_ips: config
+                    .properties::<IpAddrMask>("metrics.prometheus.allowed-ip")
+                    .into_iter()
+                    .map(|(_, ip)| ip)
+                    .collect(),
             });
         }
 
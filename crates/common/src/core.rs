@@ -4,24 +4,30 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::sync::Arc;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use directory::{backend::internal::manage::ManageDirectory, Directory, Type};
 use sieve::Sieve;
 use store::{
     write::{QueueClass, ValueClass},
-    BlobStore, FtsStore, IterateParams, LookupStore, Store, ValueKey,
+    BlobStore, FtsStore, Key, LookupStore, Store, ValueKey,
 };
 use trc::AddContext;
+use utils::map::ttl_dashmap::TtlMap;
 
 use crate::{
     config::smtp::{
         auth::{ArcSealer, DkimSigner},
-        queue::RelayHost,
+        queue::{RelayHost, RetryStrategy},
     },
     ImapId, Inner, MailboxState, Server,
 };
 
+pub const KEY_COUNT_TTL: Duration = Duration::from_secs(1);
+
 impl Server {
     #[inline(always)]
     pub fn store(&self) -> &Store {
@@ -152,24 +158,40 @@ impl Server {
         })
     }
 
+    pub fn get_retry_strategy(&self, name: &str, session_id: u64) -> Option<&RetryStrategy> {
+        self.core.smtp.queue.retry_strategies.get(name).or_else(|| {
+            trc::event!(
+                Queue(trc::QueueEvent::RetryStrategyNotFound),
+                Id = name.to_string(),
+                SpanId = session_id,
+            );
+
+            None
+        })
+    }
+
     pub async fn total_queued_messages(&self) -> trc::Result<u64> {
-        let mut total = 0;
-        self.store()
-            .iterate(
-                IterateParams::new(
-                    ValueKey::from(ValueClass::Queue(QueueClass::Message(0))),
-                    ValueKey::from(ValueClass::Queue(QueueClass::Message(u64::MAX))),
-                )
-                .no_values(),
-                |_, _| {
-                    total += 1;
-
-                    Ok(true)
-                },
-            )
+        self.get_keys_count(
+            ValueKey::from(ValueClass::Queue(QueueClass::Message(0))),
+            ValueKey::from(ValueClass::Queue(QueueClass::Message(u64::MAX))),
+        )
+        .await
+    }
+
+    // Counts are cached briefly to absorb repeated lookups of the same range
+    pub async fn get_keys_count(&self, from: impl Key, to: impl Key) -> trc::Result<u64> {
+        let cache_key = (from.subspace(), from.serialize(0), to.serialize(0));
+        let cache = &self.inner.data.key_counts;
+        if let Some(count) = cache.get_with_ttl(&cache_key) {
+            return Ok(count);
+        }
+        let count = self
+            .store()
+            .get_keys_count(from, to)
             .await
-            .caused_by(trc::location!())
-            .map(|_| total)
+            .caused_by(trc::location!())?;
+
+        Ok(cache.insert_with_ttl(cache_key, count, Instant::now() + KEY_COUNT_TTL))
     }
 
     pub async fn total_accounts(&self) -> trc::Result<u64> {
@@ -0,0 +1,769 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use ahash::AHashMap;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use directory::backend::internal::manage;
+use mail_auth::{
+    common::{
+        crypto::{Ed25519Key, RsaKey, Sha256, SigningKey},
+        headers::HeaderWriter,
+        verify::DomainKey,
+    },
+    dmarc::Dmarc,
+    spf::Spf,
+    AuthenticatedMessage, DkimResult,
+};
+use mail_parser::DateTime;
+use rsa::{
+    pkcs1::DecodeRsaPublicKey, pkcs8::DecodePublicKey, traits::PublicKeyParts, RsaPublicKey,
+};
+use serde::{Deserialize, Serialize};
+use store::{
+    rand::{thread_rng, Rng},
+    write::{now, BatchBuilder, Bincode, QueueClass, ValueClass},
+    Deserialize as _, IterateParams, Serialize as _, ValueKey,
+};
+use trc::AddContext;
+use utils::config::utils::ParseValue;
+
+use crate::{
+    auth::oauth::crypto::SymmetricEncrypt,
+    config::smtp::auth::{DkimCanonicalization, DkimSigner},
+    Server,
+};
+
+// Keys are reloaded from the store at least this often, so that keys
+// created or rotated on other nodes are eventually picked up
+const DKIM_CACHE_TTL: Duration = Duration::from_secs(60);
+const DKIM_ENCRYPTION_CONTEXT: &str = "dkim private key";
+const MIN_RSA_KEY_BITS: usize = 2048;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DkimKeyAlgorithm {
+    Rsa,
+    Ed25519,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DkimSignPolicy {
+    pub headers: Vec<String>,
+    // Signed once more than they appear, so that copies added in transit
+    // invalidate the signature
+    pub oversign: Vec<String>,
+    pub canonicalization: String,
+    // Sign messages from subdomains that have no keys of their own
+    pub subdomains: bool,
+    pub expire: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DkimKeyRecord {
+    pub domain: String,
+    pub selector: String,
+    pub algorithm: DkimKeyAlgorithm,
+    pub public_key: String,
+    pub private_key: Vec<u8>,
+    pub nonce: Vec<u8>,
+    pub created: u64,
+    pub retire: Option<u64>,
+    pub policy: DkimSignPolicy,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DkimKeyInfo {
+    pub domain: String,
+    pub selector: String,
+    pub algorithm: DkimKeyAlgorithm,
+    pub created: u64,
+    pub retire: Option<u64>,
+    pub active: bool,
+    pub policy: DkimSignPolicy,
+    pub dns_name: String,
+    pub dns_record: String,
+}
+
+pub struct ManagedDkimSigner {
+    pub selector: String,
+    pub retire: Option<u64>,
+    pub subdomains: bool,
+    pub signer: DkimSigner,
+}
+
+#[derive(Default)]
+pub struct DkimKeyCache {
+    loaded: Option<Instant>,
+    keys: AHashMap<String, Vec<Arc<ManagedDkimSigner>>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DkimDomainCheck {
+    pub domain: String,
+    pub keys: Vec<DkimKeyCheck>,
+    pub spf: DnsRecordCheck,
+    pub dmarc: DnsRecordCheck,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DkimKeyCheck {
+    pub selector: String,
+    pub algorithm: DkimKeyAlgorithm,
+    pub active: bool,
+    pub dns_name: String,
+    pub dns_record: String,
+    pub record: DnsRecordCheck,
+    pub signature: DkimSignatureCheck,
+    // Key length and algorithm policy violations
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "camelCase")]
+pub enum DnsRecordCheck {
+    Valid,
+    Missing,
+    Invalid { reason: String },
+    // Temporary lookup failure
+    Error { reason: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "camelCase")]
+pub enum DkimSignatureCheck {
+    Pass,
+    // The published public key does not belong to the stored private key
+    Mismatch,
+    Fail { reason: String },
+    // Not attempted, the record is missing or could not be parsed
+    Skipped,
+}
+
+impl Server {
+    #[allow(clippy::too_many_arguments)]
+    pub async fn store_dkim_key(
+        &self,
+        domain: &str,
+        selector: &str,
+        algorithm: DkimKeyAlgorithm,
+        private_key: &[u8],
+        public_key: String,
+        policy: DkimSignPolicy,
+        retire: Option<u64>,
+    ) -> trc::Result<DkimKeyRecord> {
+        let domain = domain.to_lowercase();
+        if self.get_dkim_key(&domain, selector).await?.is_some() {
+            return Err(manage::err_exists(
+                "selector",
+                format!("{selector}._domainkey.{domain}"),
+            ));
+        }
+
+        let nonce = thread_rng()
+            .gen::<[u8; SymmetricEncrypt::NONCE_LEN]>()
+            .to_vec();
+        let record = DkimKeyRecord {
+            private_key: SymmetricEncrypt::new(
+                self.core.smtp.mail_auth.dkim.managed.key.as_bytes(),
+                DKIM_ENCRYPTION_CONTEXT,
+            )
+            .encrypt(private_key, &nonce)
+            .map_err(|err| {
+                trc::StoreEvent::CryptoError
+                    .into_err()
+                    .details("Failed to encrypt DKIM private key")
+                    .reason(err)
+            })?,
+            nonce,
+            domain,
+            selector: selector.to_string(),
+            algorithm,
+            public_key,
+            created: now(),
+            retire,
+            policy,
+        };
+        self.write_dkim_key(&record).await?;
+
+        Ok(record)
+    }
+
+    // Creates a new key and schedules the retirement of the domain's other
+    // active keys of the same algorithm, both sign until the overlap ends
+    #[allow(clippy::too_many_arguments)]
+    pub async fn rotate_dkim_key(
+        &self,
+        domain: &str,
+        selector: &str,
+        algorithm: DkimKeyAlgorithm,
+        private_key: &[u8],
+        public_key: String,
+        policy: DkimSignPolicy,
+        overlap: Duration,
+    ) -> trc::Result<DkimKeyRecord> {
+        let record = self
+            .store_dkim_key(
+                domain,
+                selector,
+                algorithm,
+                private_key,
+                public_key,
+                policy,
+                None,
+            )
+            .await?;
+
+        let now = now();
+        let retire = now + overlap.as_secs();
+        for mut key in self.list_dkim_keys(Some(&record.domain)).await? {
+            if key.selector != record.selector
+                && key.algorithm == algorithm
+                && key.retire.map_or(true, |key_retire| key_retire > retire)
+            {
+                key.retire = Some(retire);
+                self.write_dkim_key(&key).await?;
+            }
+        }
+
+        Ok(record)
+    }
+
+    pub async fn retire_dkim_key(
+        &self,
+        domain: &str,
+        selector: &str,
+        retire: u64,
+    ) -> trc::Result<bool> {
+        if let Some(mut key) = self.get_dkim_key(domain, selector).await? {
+            key.retire = Some(retire);
+            self.write_dkim_key(&key).await.map(|_| true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    pub async fn delete_dkim_key(&self, domain: &str, selector: &str) -> trc::Result<bool> {
+        if self.get_dkim_key(domain, selector).await?.is_some() {
+            let mut batch = BatchBuilder::new();
+            batch.clear(ValueClass::Queue(QueueClass::DkimKey(dkim_key_id(
+                domain, selector,
+            ))));
+            self.store()
+                .write(batch.build())
+                .await
+                .caused_by(trc::location!())?;
+            self.inner.data.dkim_keys.write().loaded = None;
+
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    pub async fn get_dkim_key(
+        &self,
+        domain: &str,
+        selector: &str,
+    ) -> trc::Result<Option<DkimKeyRecord>> {
+        self.store()
+            .get_value::<Bincode<DkimKeyRecord>>(ValueKey::from(ValueClass::Queue(
+                QueueClass::DkimKey(dkim_key_id(domain, selector)),
+            )))
+            .await
+            .map(|record| record.map(|record| record.inner))
+            .caused_by(trc::location!())
+    }
+
+    pub async fn list_dkim_keys(&self, domain: Option<&str>) -> trc::Result<Vec<DkimKeyRecord>> {
+        let mut prefix = domain
+            .map(|domain| dkim_key_id(domain, ""))
+            .unwrap_or_default();
+        let from_key = ValueKey::from(ValueClass::Queue(QueueClass::DkimKey(prefix.clone())));
+        prefix.push(u8::MAX);
+        let to_key = ValueKey::from(ValueClass::Queue(QueueClass::DkimKey(prefix)));
+
+        let mut keys = Vec::new();
+        self.store()
+            .iterate(
+                IterateParams::new(from_key, to_key).ascending(),
+                |_, value| {
+                    keys.push(Bincode::<DkimKeyRecord>::deserialize(value)?.inner);
+                    Ok(true)
+                },
+            )
+            .await
+            .caused_by(trc::location!())?;
+
+        Ok(keys)
+    }
+
+    // Returns the active signers for a domain, falling back to the keys of
+    // parent domains that allow signing for their subdomains
+    pub async fn dkim_signers(&self, domain: &str) -> trc::Result<Vec<Arc<ManagedDkimSigner>>> {
+        let is_stale = self
+            .inner
+            .data
+            .dkim_keys
+            .read()
+            .loaded
+            .map_or(true, |loaded| loaded.elapsed() >= DKIM_CACHE_TTL);
+        if is_stale {
+            self.reload_dkim_keys().await?;
+        }
+
+        let domain = domain.trim_end_matches('.').to_lowercase();
+        let base_domain = psl::domain_str(&domain).unwrap_or(domain.as_str());
+        let now = now();
+        let cache = self.inner.data.dkim_keys.read();
+        let mut name = domain.as_str();
+        let mut is_parent = false;
+        loop {
+            let signers = cache
+                .keys
+                .get(name)
+                .map(|keys| {
+                    keys.iter()
+                        .filter(|key| {
+                            key.retire.map_or(true, |retire| retire > now)
+                                && (!is_parent || key.subdomains)
+                        })
+                        .cloned()
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default();
+            if !signers.is_empty() || name == base_domain {
+                return Ok(signers);
+            }
+            match name.split_once('.') {
+                Some((_, parent)) => {
+                    name = parent;
+                    is_parent = true;
+                }
+                None => return Ok(signers),
+            }
+        }
+    }
+
+    // Verifies that the DNS records of a domain's keys are published and match
+    // their private keys, by signing a test message and verifying it through DNS
+    pub async fn check_dkim_domain(&self, domain: &str) -> trc::Result<DkimDomainCheck> {
+        let domain = domain.trim().trim_end_matches('.').to_lowercase();
+        let records = self.list_dkim_keys(Some(&domain)).await?;
+        let now = now();
+        let has_rsa = records
+            .iter()
+            .any(|record| record.algorithm == DkimKeyAlgorithm::Rsa && record.is_active(now));
+        let mut keys = Vec::with_capacity(records.len());
+        for record in &records {
+            keys.push(self.check_dkim_key(record, has_rsa).await);
+        }
+
+        let resolver = &self.core.smtp.resolvers.dns;
+        Ok(DkimDomainCheck {
+            spf: resolver
+                .txt_lookup::<Spf>(format!("{domain}."))
+                .await
+                .into(),
+            dmarc: resolver
+                .txt_lookup::<Dmarc>(format!("_dmarc.{domain}."))
+                .await
+                .into(),
+            domain,
+            keys,
+        })
+    }
+
+    // Checks the records of all active keys, reporting the ones that are missing
+    // or stopped matching, for example after moving the zone to another provider
+    pub async fn check_dkim_keys(&self) -> trc::Result<()> {
+        let now = now();
+        let mut domains = self
+            .list_dkim_keys(None)
+            .await?
+            .into_iter()
+            .filter(|record| record.is_active(now))
+            .map(|record| record.domain)
+            .collect::<Vec<_>>();
+        domains.dedup();
+
+        for domain in domains {
+            for key in self.check_dkim_domain(&domain).await?.keys {
+                if !key.active {
+                    continue;
+                }
+                match (&key.record, &key.signature) {
+                    (DnsRecordCheck::Missing, _) => {
+                        trc::event!(
+                            Dkim(trc::DkimEvent::RecordMissing),
+                            Domain = domain.clone(),
+                            Id = key.dns_name,
+                        );
+                    }
+                    (DnsRecordCheck::Invalid { reason }, _)
+                    | (_, DkimSignatureCheck::Fail { reason }) => {
+                        trc::event!(
+                            Dkim(trc::DkimEvent::RecordMismatch),
+                            Domain = domain.clone(),
+                            Id = key.dns_name,
+                            Reason = reason.clone(),
+                        );
+                    }
+                    (_, DkimSignatureCheck::Mismatch) => {
+                        trc::event!(
+                            Dkim(trc::DkimEvent::RecordMismatch),
+                            Domain = domain.clone(),
+                            Id = key.dns_name,
+                            Details = "Published public key does not match the private key",
+                        );
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn check_dkim_key(&self, record: &DkimKeyRecord, has_rsa: bool) -> DkimKeyCheck {
+        let dns_name = record.dns_name();
+        let record_check: DnsRecordCheck = self
+            .core
+            .smtp
+            .resolvers
+            .dns
+            .txt_lookup::<DomainKey>(format!("{dns_name}."))
+            .await
+            .into();
+        let signature = if record_check == DnsRecordCheck::Valid {
+            match self.dkim_self_test(record).await {
+                Ok(signature) => signature,
+                Err(err) => DkimSignatureCheck::Fail {
+                    reason: err.to_string(),
+                },
+            }
+        } else {
+            DkimSignatureCheck::Skipped
+        };
+        let active = record.is_active(now());
+
+        DkimKeyCheck {
+            selector: record.selector.clone(),
+            algorithm: record.algorithm,
+            active,
+            dns_record: record.dns_record(),
+            dns_name,
+            record: record_check,
+            signature,
+            warnings: record.policy_warnings(has_rsa),
+        }
+    }
+
+    async fn dkim_self_test(&self, record: &DkimKeyRecord) -> trc::Result<DkimSignatureCheck> {
+        let message = format!(
+            concat!(
+                "From: <postmaster@{domain}>\r\n",
+                "To: <postmaster@{domain}>\r\n",
+                "Date: {date}\r\n",
+                "Message-ID: <{selector}.{now}@{domain}>\r\n",
+                "Subject: DKIM self-test\r\n",
+                "\r\n",
+                "This message verifies the DKIM key {selector}.\r\n"
+            ),
+            domain = record.domain,
+            selector = record.selector,
+            date = DateTime::from_timestamp(now() as i64).to_rfc822(),
+            now = now(),
+        );
+        let signature = match self.build_dkim_signer(record)?.signer {
+            DkimSigner::RsaSha256(signer) => signer.sign(message.as_bytes()),
+            DkimSigner::Ed25519Sha256(signer) => signer.sign(message.as_bytes()),
+        }?;
+        let mut signed_message = Vec::with_capacity(message.len() + 512);
+        signature.write_header(&mut signed_message);
+        signed_message.extend_from_slice(message.as_bytes());
+        let signed_message = AuthenticatedMessage::parse(&signed_message).ok_or_else(|| {
+            trc::EventType::MailAuth(trc::MailAuthEvent::ParseError)
+                .into_err()
+                .details("Failed to parse signed test message")
+        })?;
+
+        Ok(
+            match self
+                .core
+                .smtp
+                .resolvers
+                .dns
+                .verify_dkim(&signed_message)
+                .await
+                .first()
+                .map(|output| output.result())
+            {
+                Some(DkimResult::Pass) => DkimSignatureCheck::Pass,
+                Some(DkimResult::Fail(
+                    mail_auth::Error::FailedVerification | mail_auth::Error::IncompatibleAlgorithms,
+                )) => DkimSignatureCheck::Mismatch,
+                Some(
+                    DkimResult::Fail(err)
+                    | DkimResult::Neutral(err)
+                    | DkimResult::PermError(err)
+                    | DkimResult::TempError(err),
+                ) => DkimSignatureCheck::Fail {
+                    reason: err.to_string(),
+                },
+                Some(DkimResult::None) | None => DkimSignatureCheck::Fail {
+                    reason: "Test message has no DKIM signature".to_string(),
+                },
+            },
+        )
+    }
+
+    async fn reload_dkim_keys(&self) -> trc::Result<()> {
+        let mut keys: AHashMap<String, Vec<Arc<ManagedDkimSigner>>> = AHashMap::new();
+        for record in self.list_dkim_keys(None).await? {
+            match self.build_dkim_signer(&record) {
+                Ok(signer) => {
+                    keys.entry(record.domain)
+                        .or_default()
+                        .push(Arc::new(signer));
+                }
+                Err(err) => {
+                    trc::error!(err
+                        .details("Failed to build managed DKIM signer")
+                        .id(format!("{}._domainkey.{}", record.selector, record.domain)));
+                }
+            }
+        }
+
+        *self.inner.data.dkim_keys.write() = DkimKeyCache {
+            loaded: Some(Instant::now()),
+            keys,
+        };
+
+        Ok(())
+    }
+
+    fn build_dkim_signer(&self, record: &DkimKeyRecord) -> trc::Result<ManagedDkimSigner> {
+        let der = SymmetricEncrypt::new(
+            self.core.smtp.mail_auth.dkim.managed.key.as_bytes(),
+            DKIM_ENCRYPTION_CONTEXT,
+        )
+        .decrypt(&record.private_key, &record.nonce)
+        .map_err(|err| {
+            trc::StoreEvent::CryptoError
+                .into_err()
+                .details("Failed to decrypt DKIM private key")
+                .reason(err)
+        })?;
+        let signer = match record.algorithm {
+            DkimKeyAlgorithm::Rsa => {
+                DkimSigner::RsaSha256(record.build_signer(RsaKey::<Sha256>::from_der(&der)?)?)
+            }
+            DkimKeyAlgorithm::Ed25519 => DkimSigner::Ed25519Sha256(
+                record.build_signer(Ed25519Key::from_pkcs8_maybe_unchecked_der(&der)?)?,
+            ),
+        };
+
+        Ok(ManagedDkimSigner {
+            selector: record.selector.clone(),
+            retire: record.retire,
+            subdomains: record.policy.subdomains,
+            signer,
+        })
+    }
+
+    async fn write_dkim_key(&self, record: &DkimKeyRecord) -> trc::Result<()> {
+        let mut batch = BatchBuilder::new();
+        batch.set(
+            ValueClass::Queue(QueueClass::DkimKey(dkim_key_id(
+                &record.domain,
+                &record.selector,
+            ))),
+            Bincode::new(record.clone()).serialize(),
+        );
+        self.store()
+            .write(batch.build())
+            .await
+            .caused_by(trc::location!())?;
+
+        // Make the change visible to the signer right away
+        self.inner.data.dkim_keys.write().loaded = None;
+
+        Ok(())
+    }
+}
+
+impl DkimKeyRecord {
+    pub fn dns_name(&self) -> String {
+        format!("{}._domainkey.{}", self.selector, self.domain)
+    }
+
+    pub fn dns_record(&self) -> String {
+        format!(
+            "v=DKIM1; k={}; p={}",
+            match self.algorithm {
+                DkimKeyAlgorithm::Rsa => "rsa",
+                DkimKeyAlgorithm::Ed25519 => "ed25519",
+            },
+            self.public_key
+        )
+    }
+
+    pub fn is_active(&self, now: u64) -> bool {
+        self.retire.map_or(true, |retire| retire > now)
+    }
+
+    pub fn info(&self) -> DkimKeyInfo {
+        DkimKeyInfo {
+            domain: self.domain.clone(),
+            selector: self.selector.clone(),
+            algorithm: self.algorithm,
+            created: self.created,
+            retire: self.retire,
+            active: self.is_active(now()),
+            policy: self.policy.clone(),
+            dns_name: self.dns_name(),
+            dns_record: self.dns_record(),
+        }
+    }
+
+    fn policy_warnings(&self, has_rsa: bool) -> Vec<String> {
+        let mut warnings = Vec::new();
+        match self.algorithm {
+            DkimKeyAlgorithm::Rsa => match STANDARD
+                .decode(&self.public_key)
+                .ok()
+                .and_then(|der| {
+                    RsaPublicKey::from_public_key_der(&der)
+                        .or_else(|_| RsaPublicKey::from_pkcs1_der(&der))
+                        .ok()
+                })
+                .map(|key| key.size() * 8)
+            {
+                Some(bits) if bits < MIN_RSA_KEY_BITS => {
+                    warnings.push(format!(
+                        "RSA key is {bits} bits long, use at least {MIN_RSA_KEY_BITS} bits"
+                    ));
+                }
+                Some(_) => {}
+                None => {
+                    warnings.push("Failed to decode RSA public key".to_string());
+                }
+            },
+            DkimKeyAlgorithm::Ed25519 if !has_rsa => {
+                warnings.push(
+                    "Ed25519 signatures are not verified by all receivers, add an RSA key as well"
+                        .to_string(),
+                );
+            }
+            DkimKeyAlgorithm::Ed25519 => {}
+        }
+        if !self
+            .policy
+            .headers
+            .iter()
+            .any(|header| header.eq_ignore_ascii_case("From"))
+        {
+            warnings.push("The From header must be signed".to_string());
+        }
+
+        warnings
+    }
+
+    fn build_signer<T: SigningKey>(
+        &self,
+        key: T,
+    ) -> trc::Result<mail_auth::dkim::DkimSigner<T, mail_auth::dkim::Done>> {
+        let canonicalization = DkimCanonicalization::parse_value(&self.policy.canonicalization)
+            .map_err(|err| {
+                trc::EventType::Resource(trc::ResourceEvent::BadParameters)
+                    .into_err()
+                    .details("Invalid DKIM canonicalization")
+                    .reason(err)
+            })?;
+        let headers = self
+            .policy
+            .headers
+            .iter()
+            .chain(self.policy.oversign.iter())
+            .cloned()
+            .collect::<Vec<_>>();
+        let mut signer = mail_auth::dkim::DkimSigner::from_key(key)
+            .domain(&self.domain)
+            .selector(&self.selector)
+            .headers(headers)
+            .header_canonicalization(canonicalization.headers)
+            .body_canonicalization(canonicalization.body);
+        if let Some(expire) = self.policy.expire {
+            signer = signer.expiration(expire);
+        }
+
+        Ok(signer)
+    }
+}
+
+impl Default for DkimSignPolicy {
+    fn default() -> Self {
+        Self {
+            headers: [
+                "From",
+                "To",
+                "Cc",
+                "Date",
+                "Subject",
+                "Message-ID",
+                "Reply-To",
+                "MIME-Version",
+                "Content-Type",
+            ]
+            .into_iter()
+            .map(String::from)
+            .collect(),
+            oversign: ["From", "Subject", "Reply-To"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            canonicalization: "relaxed/relaxed".to_string(),
+            subdomains: false,
+            expire: None,
+        }
+    }
+}
+
+// Keys are ordered by domain, so a domain's keys can be listed by prefix
+fn dkim_key_id(domain: &str, selector: &str) -> Vec<u8> {
+    let domain = domain.to_lowercase();
+    let mut key = Vec::with_capacity(domain.len() + selector.len() + 1);
+    key.extend_from_slice(domain.as_bytes());
+    key.push(0);
+    key.extend_from_slice(selector.as_bytes());
+    key
+}
+
+impl<T> From<mail_auth::Result<T>> for DnsRecordCheck {
+    fn from(result: mail_auth::Result<T>) -> Self {
+        match result {
+            Ok(_) => DnsRecordCheck::Valid,
+            Err(mail_auth::Error::DnsRecordNotFound(_)) => DnsRecordCheck::Missing,
+            Err(err @ (mail_auth::Error::DnsError(_) | mail_auth::Error::Io(_))) => {
+                DnsRecordCheck::Error {
+                    reason: err.to_string(),
+                }
+            }
+            Err(err) => DnsRecordCheck::Invalid {
+                reason: err.to_string(),
+            },
+        }
+    }
+}
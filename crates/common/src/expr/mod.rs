@@ -35,6 +35,7 @@ pub const V_URL: u32 = 21;
 pub const V_URL_PATH: u32 = 22;
 pub const V_HEADERS: u32 = 23;
 pub const V_METHOD: u32 = 24;
+pub const V_SIZE: u32 = 25;
 
 pub const VARIABLES_MAP: &[(&str, u32)] = &[
     ("rcpt", V_RECIPIENT),
@@ -62,6 +63,7 @@ pub const VARIABLES_MAP: &[(&str, u32)] = &[
     ("url_path", V_URL_PATH),
     ("headers", V_HEADERS),
     ("method", V_METHOD),
+    ("size", V_SIZE),
 ];
 
 use regex::Regex;
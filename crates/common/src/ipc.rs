@@ -13,6 +13,8 @@ use mail_auth::{
     mta_sts::TlsRpt,
     report::{tlsrpt::FailureDetails, Record},
 };
+use serde::{Deserialize, Serialize};
+use sieve::Sieve;
 use store::{BlobStore, LookupStore, Store};
 use tokio::sync::{mpsc, oneshot};
 use utils::{map::bitmap::Bitmap, BlobHash};
@@ -29,6 +31,7 @@ use crate::{
 pub enum DeliveryResult {
     Success,
     TemporaryFailure {
+        code: [u8; 3],
         reason: Cow<'static, str>,
     },
     PermanentFailure {
@@ -43,6 +46,14 @@ pub enum DeliveryEvent {
         message: IngestMessage,
         result_tx: oneshot::Sender<Vec<DeliveryResult>>,
     },
+    OutboundScript {
+        account_id: u32,
+        result_tx: oneshot::Sender<Option<Arc<Sieve>>>,
+    },
+    FileInto {
+        message: FileIntoMessage,
+        result_tx: oneshot::Sender<DeliveryResult>,
+    },
     Stop,
 }
 
@@ -55,6 +66,18 @@ pub struct IngestMessage {
     pub session_id: u64,
 }
 
+// Copy of a submitted message filed by an outbound Sieve script
+#[derive(Debug)]
+pub struct FileIntoMessage {
+    pub account_id: u32,
+    pub mailbox: String,
+    pub special_use: Option<String>,
+    pub create: bool,
+    pub flags: Vec<String>,
+    pub raw_message: Vec<u8>,
+    pub session_id: u64,
+}
+
 pub enum HousekeeperEvent {
     AcmeReschedule {
         provider_id: String,
@@ -81,7 +104,17 @@ pub enum StateEvent {
     },
     Publish {
         state_change: StateChange,
+        broadcast: bool,
     },
+    InvalidateAccessTokens {
+        account_ids: Vec<u32>,
+        broadcast: bool,
+    },
+    RevokeAppPasswords {
+        account_id: u32,
+        broadcast: bool,
+    },
+    Resync,
     UpdateSharedAccounts {
         account_id: u32,
     },
@@ -92,6 +125,29 @@ pub enum StateEvent {
     Stop,
 }
 
+// Events exchanged with other nodes through the cluster event bus
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ClusterEvent {
+    StateChange {
+        account_id: u32,
+        types: Vec<(u8, u64)>,
+    },
+    InvalidateAccessTokens {
+        account_ids: Vec<u32>,
+    },
+    RevokeAppPasswords {
+        account_id: u32,
+    },
+}
+
+// Each node numbers its messages so that subscribers can detect gaps
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClusterMessage {
+    pub node_id: u64,
+    pub seq: u64,
+    pub event: ClusterEvent,
+}
+
 #[derive(Debug)]
 pub enum UpdateSubscription {
     Unverified {
@@ -231,3 +287,26 @@ impl From<(&Option<Arc<Policy>>, &Option<Arc<Tlsa>>)> for PolicyType {
         }
     }
 }
+
+impl From<StateChange> for ClusterEvent {
+    fn from(state_change: StateChange) -> Self {
+        ClusterEvent::StateChange {
+            account_id: state_change.account_id,
+            types: state_change
+                .types
+                .into_iter()
+                .map(|(typ, change_id)| (u64::from(typ) as u8, change_id))
+                .collect(),
+        }
+    }
+}
+
+impl ClusterMessage {
+    pub fn serialize(&self) -> Vec<u8> {
+        bincode::serialize(self).unwrap_or_default()
+    }
+
+    pub fn deserialize(bytes: &[u8]) -> Option<Self> {
+        bincode::deserialize(bytes).ok()
+    }
+}
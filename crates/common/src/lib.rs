@@ -8,35 +8,46 @@ use std::{
     collections::BTreeMap,
     hash::{BuildHasher, Hasher},
     net::IpAddr,
-    sync::{atomic::AtomicU8, Arc},
+    sync::{
+        atomic::{AtomicU64, AtomicU8},
+        Arc,
+    },
 };
 
-use ahash::{AHashMap, AHashSet, RandomState};
+use ahash::{AHashMap, RandomState};
 use arc_swap::ArcSwap;
 use auth::{oauth::config::OAuthConfig, roles::RolePermissions, AccessToken};
 use config::{
     imap::ImapConfig,
-    jmap::settings::JmapConfig,
+    jmap::{push::ApnsToken, retention::RetentionStats, settings::JmapConfig},
     network::Network,
     scripts::{RemoteList, Scripting},
     smtp::SmtpConfig,
+    spamfilter::SpamFilterConfig,
     storage::Storage,
-    telemetry::Metrics,
+    telemetry::{AuditConfig, Metrics},
 };
 use dashmap::DashMap;
+use dkim::DkimKeyCache;
 
 use futures::StreamExt;
 use imap_proto::protocol::list::Attribute;
 use ipc::{DeliveryEvent, HousekeeperEvent, QueueEvent, ReportingEvent, StateEvent};
 use listener::{blocked::Security, limiter::ConcurrencyLimiter, tls::AcmeProviders};
 
+use mail_send::Credentials;
 use manager::webadmin::{Resource, WebAdminManager};
 use nlp::bayes::cache::BayesTokenCache;
 use parking_lot::{Mutex, RwLock};
 use reqwest::Response;
 use rustls::sign::CertifiedKey;
-use tokio::sync::{mpsc, Notify};
-use tokio_rustls::TlsConnector;
+use smtp_proto::EhloResponse;
+use store::{write::BitmapClass, BitmapKey};
+use tokio::{
+    net::TcpStream,
+    sync::{broadcast, mpsc, Notify},
+};
+use tokio_rustls::{client::TlsStream, TlsConnector};
 use utils::{
     lru_cache::LruCache,
     map::ttl_dashmap::{ADashMap, TtlDashMap},
@@ -44,15 +55,20 @@ use utils::{
 };
 
 pub mod addresses;
+pub mod attachments;
 pub mod auth;
+pub mod bayes;
+pub mod clamav;
 pub mod config;
 pub mod core;
+pub mod dkim;
 #[cfg(feature = "enterprise")]
 pub mod enterprise;
 pub mod expr;
 pub mod ipc;
 pub mod listener;
 pub mod manager;
+pub mod rspamd;
 pub mod scripts;
 pub mod telemetry;
 
@@ -81,10 +97,20 @@ pub struct Data {
 
     pub access_tokens: TtlDashMap<u32, Arc<AccessToken>>,
     pub http_auth_cache: TtlDashMap<String, u32>,
+    pub bitmap_cardinalities: TtlDashMap<BitmapKey<BitmapClass<u32>>, u64>,
+    pub flag_document_counts: TtlDashMap<BitmapKey<BitmapClass<u32>>, u64>,
+    pub key_counts: TtlDashMap<(u8, Vec<u8>, Vec<u8>), u64>,
 
-    pub blocked_ips: RwLock<AHashSet<IpAddr>>,
+    // Blocked addresses and the time their ban expires, u64::MAX for permanent bans
+    pub blocked_ips: RwLock<AHashMap<IpAddr, u64>>,
     pub blocked_ips_version: AtomicU8,
 
+    // Accounts whose app passwords were revoked, sessions using them re-check and close
+    pub app_password_revocations: broadcast::Sender<u32>,
+
+    // Managed DKIM signers by domain, reloaded periodically from the store
+    pub dkim_keys: RwLock<DkimKeyCache>,
+
     pub permissions: ADashMap<u32, Arc<RolePermissions>>,
     pub permissions_version: AtomicU8,
 
@@ -106,10 +132,15 @@ pub struct Data {
     pub threads_cache: LruCache<u32, Arc<Threads>>,
 
     pub logos: Mutex<AHashMap<String, Option<Resource<Vec<u8>>>>>,
+    pub retention_stats: Mutex<RetentionStats>,
+    pub import_jobs: Mutex<AHashMap<u64, ImportJob>>,
+    pub migrations: Mutex<AHashMap<u32, MigrationStatus>>,
+    pub apns_token: Mutex<Option<ApnsToken>>,
 
     pub smtp_session_throttle: DashMap<ThrottleKey, ConcurrencyLimiter, ThrottleKeyHasherBuilder>,
     pub smtp_queue_throttle: DashMap<ThrottleKey, ConcurrencyLimiter, ThrottleKeyHasherBuilder>,
     pub smtp_connectors: TlsConnectors,
+    pub smtp_connection_pool: SmtpConnectionPool,
 }
 
 pub struct Ipc {
@@ -126,6 +157,45 @@ pub struct TlsConnectors {
     pub dummy_verify: TlsConnector,
 }
 
+#[derive(Default)]
+pub struct SmtpConnectionPool {
+    pub sessions: DashMap<SmtpConnectionKey, Vec<SmtpPooledSession>, RandomState>,
+    pub deferred: DashMap<String, Vec<SmtpDeferredMessage>, RandomState>,
+    pub id_gen: AtomicU64,
+}
+
+// Message that was deferred after a host failure, retried as soon as
+// a connection to the same host is known to be healthy again
+pub struct SmtpDeferredMessage {
+    pub queue_id: u64,
+    pub expires: u64,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct SmtpConnectionKey {
+    pub hostname: String,
+    pub local_ip: Option<IpAddr>,
+    pub remote_ip: IpAddr,
+    pub port: u16,
+    pub is_smtp: bool,
+    pub is_tls: bool,
+    pub is_dane_verified: bool,
+    pub allow_invalid_certs: bool,
+    pub credentials: Option<Credentials<String>>,
+}
+
+pub struct SmtpPooledSession {
+    pub id: u64,
+    pub stream: SmtpPooledStream,
+    pub capabilities: EhloResponse<String>,
+    pub num_messages: usize,
+}
+
+pub enum SmtpPooledStream {
+    Plain(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+}
+
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub struct AccountId {
     pub account_id: u32,
@@ -191,6 +261,55 @@ pub struct Threads {
     pub modseq: Option<u64>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportFormat {
+    Mbox,
+    Maildir,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportJob {
+    pub account_id: u32,
+    pub format: ImportFormat,
+    pub started: u64,
+    pub finished: u64,
+    pub imported: u64,
+    pub duplicates: u64,
+    pub failed: u64,
+    pub mailboxes: u64,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MigrationStatus {
+    pub account_id: u32,
+    pub host: String,
+    pub username: String,
+    pub dry_run: bool,
+    pub cancelled: bool,
+    pub started: u64,
+    pub finished: u64,
+    pub folders: Vec<MigrationFolder>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MigrationFolder {
+    pub remote_name: String,
+    pub local_name: String,
+    pub pending: u64,
+    pub imported: u64,
+    pub duplicates: u64,
+    pub failed: u64,
+    pub bytes: u64,
+    pub too_large: Vec<u32>,
+    pub error: Option<String>,
+}
+
 pub struct ConcurrencyLimiters {
     pub concurrent_requests: ConcurrencyLimiter,
     pub concurrent_uploads: ConcurrencyLimiter,
@@ -206,7 +325,9 @@ pub struct Core {
     pub smtp: SmtpConfig,
     pub jmap: JmapConfig,
     pub imap: ImapConfig,
+    pub spam: SpamFilterConfig,
     pub metrics: Metrics,
+    pub audit: AuditConfig,
     #[cfg(feature = "enterprise")]
     pub enterprise: Option<enterprise::Enterprise>,
 }
@@ -27,7 +27,8 @@ pub struct AcmeProvider {
     pub contact: Vec<String>,
     pub challenge: ChallengeSettings,
     pub eab: Option<EabSettings>,
-    renew_before: chrono::Duration,
+    renew_before: Option<chrono::Duration>,
+    max_retries: u32,
     account_key: ArcSwap<Vec<u8>>,
     default: bool,
 }
@@ -64,7 +65,8 @@ impl AcmeProvider {
         contact: Vec<String>,
         challenge: ChallengeSettings,
         eab: Option<EabSettings>,
-        renew_before: Duration,
+        renew_before: Option<Duration>,
+        max_retries: u32,
         default: bool,
     ) -> trc::Result<Self> {
         Ok(AcmeProvider {
@@ -80,7 +82,8 @@ impl AcmeProvider {
                     }
                 })
                 .collect(),
-            renew_before: chrono::Duration::from_std(renew_before).unwrap(),
+            renew_before: renew_before.map(|d| chrono::Duration::from_std(d).unwrap()),
+            max_retries,
             domains,
             account_key: Default::default(),
             challenge,
@@ -151,6 +154,7 @@ impl Clone for AcmeProvider {
             contact: self.contact.clone(),
             challenge: self.challenge.clone(),
             renew_before: self.renew_before,
+            max_retries: self.max_retries,
             account_key: ArcSwap::from_pointee(self.account_key.load().as_ref().clone()),
             eab: self.eab.clone(),
             default: self.default,
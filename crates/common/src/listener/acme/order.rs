@@ -30,11 +30,15 @@ impl Server {
 
         self.set_cert(provider, Arc::new(cert));
 
-        let renew_at = (validity[1] - provider.renew_before - Utc::now())
+        // Renew when a third of the certificate lifetime remains, unless configured otherwise
+        let renew_before = provider
+            .renew_before
+            .unwrap_or_else(|| (validity[1] - validity[0]) / 3);
+        let renewal_date = validity[1] - renew_before;
+        let renew_at = (renewal_date - Utc::now())
             .max(chrono::Duration::zero())
             .to_std()
             .unwrap_or_default();
-        let renewal_date = validity[1] - provider.renew_before;
 
         trc::event!(
             Acme(AcmeEvent::ProcessCert),
@@ -58,7 +62,8 @@ impl Server {
             match self.order(provider).await {
                 Ok(pem) => return self.process_cert(provider, pem, false).await,
                 Err(err)
-                    if !err.matches(EventType::Acme(AcmeEvent::OrderInvalid)) && backoff < 16 =>
+                    if !err.matches(EventType::Acme(AcmeEvent::OrderInvalid))
+                        && backoff < provider.max_retries =>
                 {
                     trc::event!(
                         Acme(AcmeEvent::RenewBackoff),
@@ -81,6 +86,29 @@ impl Server {
         }
     }
 
+    pub async fn renew_failed(&self, provider: &AcmeProvider, err: trc::Error) -> Duration {
+        // The current certificate, if any, is kept in use until it expires
+        let expires = self
+            .load_cert(provider)
+            .await
+            .ok()
+            .flatten()
+            .and_then(|pem| parse_cert(&pem).ok())
+            .map(|(_, validity)| validity[1].timestamp().max(0) as u64);
+        let retry_in = Duration::from_secs(3600);
+
+        trc::event!(
+            Acme(AcmeEvent::RenewFailed),
+            Id = provider.id.to_string(),
+            Hostname = provider.domains.as_slice(),
+            ValidTo = expires.map(trc::Value::Timestamp),
+            NextRetry = retry_in.as_secs(),
+            CausedBy = err,
+        );
+
+        retry_in
+    }
+
     async fn order(&self, provider: &AcmeProvider) -> trc::Result<Vec<u8>> {
         let directory = Directory::discover(&provider.directory_url).await?;
         let account = Account::create_with_keypair(directory, provider).await?;
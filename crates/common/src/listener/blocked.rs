@@ -4,9 +4,14 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::{fmt::Debug, net::IpAddr};
+use std::{
+    fmt::Debug,
+    net::{IpAddr, Ipv6Addr},
+    time::Duration,
+};
 
-use ahash::AHashSet;
+use ahash::{AHashMap, AHashSet};
+use store::write::now;
 use utils::{
     config::{
         ipmask::{IpAddrMask, IpAddrOrMask},
@@ -33,6 +38,10 @@ pub struct Security {
     auth_fail_rate: Option<Rate>,
     rcpt_fail_rate: Option<Rate>,
     loiter_fail_rate: Option<Rate>,
+
+    ban_duration: Option<Duration>,
+    ban_max_duration: Duration,
+    allow_authenticated: bool,
 }
 
 pub const BLOCKED_IP_KEY: &str = "server.blocked-ip";
@@ -41,7 +50,7 @@ pub const ALLOWED_IP_KEY: &str = "server.allowed-ip";
 pub const ALLOWED_IP_PREFIX: &str = "server.allowed-ip.";
 
 pub struct BlockedIps {
-    pub blocked_ip_addresses: AHashSet<IpAddr>,
+    pub blocked_ip_addresses: AHashMap<IpAddr, u64>,
     pub blocked_ip_networks: Vec<IpAddrMask>,
 }
 
@@ -129,6 +138,15 @@ impl Security {
             scanner_fail_rate: config
                 .property_or_default::<Option<Rate>>("server.auto-ban.scan.rate", "30/1d")
                 .unwrap_or_default(),
+            ban_duration: config
+                .property_or_default::<Option<Duration>>("server.auto-ban.duration", "false")
+                .unwrap_or_default(),
+            ban_max_duration: config
+                .property_or_default::<Duration>("server.auto-ban.max-duration", "30d")
+                .unwrap_or(Duration::from_secs(30 * 86400)),
+            allow_authenticated: config
+                .property_or_default::<bool>("server.auto-ban.allow-authenticated", "false")
+                .unwrap_or_default(),
         }
     }
 }
@@ -139,7 +157,7 @@ impl Server {
             let is_allowed = self.is_ip_allowed(&ip)
                 || (self
                     .lookup_store()
-                    .is_rate_allowed(format!("r:{ip}").as_bytes(), rate, false)
+                    .is_rate_allowed(format!("r:{}", ban_address(ip)).as_bytes(), rate, false)
                     .await?
                     .is_none()
                     && self
@@ -149,7 +167,7 @@ impl Server {
                         .is_none());
 
             if !is_allowed {
-                return self.block_ip(ip).await.map(|_| true);
+                return self.auto_ban(ip).await;
             }
         }
 
@@ -161,12 +179,12 @@ impl Server {
             let is_allowed = self.is_ip_allowed(&ip)
                 || self
                     .lookup_store()
-                    .is_rate_allowed(format!("h:{ip}").as_bytes(), rate, false)
+                    .is_rate_allowed(format!("h:{}", ban_address(ip)).as_bytes(), rate, false)
                     .await?
                     .is_none();
 
             if !is_allowed {
-                return self.block_ip(ip).await.map(|_| true);
+                return self.auto_ban(ip).await;
             }
         }
 
@@ -177,7 +195,7 @@ impl Server {
         let paths = &self.core.network.security.http_banned_paths;
 
         if !paths.is_empty() && paths.iter().any(|p| p.matches(path)) && !self.is_ip_allowed(&ip) {
-            self.block_ip(ip).await.map(|_| true)
+            self.auto_ban(ip).await
         } else {
             Ok(false)
         }
@@ -188,12 +206,12 @@ impl Server {
             let is_allowed = self.is_ip_allowed(&ip)
                 || self
                     .lookup_store()
-                    .is_rate_allowed(format!("l:{ip}").as_bytes(), rate, false)
+                    .is_rate_allowed(format!("l:{}", ban_address(ip)).as_bytes(), rate, false)
                     .await?
                     .is_none();
 
             if !is_allowed {
-                return self.block_ip(ip).await.map(|_| true);
+                return self.auto_ban(ip).await;
             }
         }
 
@@ -206,7 +224,7 @@ impl Server {
             let is_allowed = self.is_ip_allowed(&ip)
                 || (self
                     .lookup_store()
-                    .is_rate_allowed(format!("b:{ip}").as_bytes(), rate, false)
+                    .is_rate_allowed(format!("b:{}", ban_address(ip)).as_bytes(), rate, false)
                     .await?
                     .is_none()
                     && (login.is_empty()
@@ -216,16 +234,58 @@ impl Server {
                             .await?
                             .is_none()));
             if !is_allowed {
-                return self.block_ip(ip).await.map(|_| true);
+                return self.auto_ban(ip).await;
             }
         }
 
         Ok(false)
     }
 
-    async fn block_ip(&self, ip: IpAddr) -> trc::Result<()> {
+    // Bans the address, or its /64 network for IPv6, returning false when the
+    // address is exempt because it authenticated successfully before.
+    async fn auto_ban(&self, ip: IpAddr) -> trc::Result<bool> {
+        let security = &self.core.network.security;
+        let ip = ban_address(ip);
+
+        if security.allow_authenticated
+            && self
+                .lookup_store()
+                .key_exists(format!("a:{ip}").into_bytes())
+                .await?
+        {
+            return Ok(false);
+        }
+
+        // Ban durations double with every offence within the maximum ban duration
+        let expires = if let Some(duration) = security.ban_duration {
+            let offences = self
+                .lookup_store()
+                .counter_incr(
+                    format!("o:{ip}").into_bytes(),
+                    1,
+                    security.ban_max_duration.as_secs().into(),
+                    true,
+                )
+                .await?;
+            let duration = duration
+                .as_secs()
+                .saturating_mul(1 << offences.clamp(1, 32).saturating_sub(1))
+                .min(security.ban_max_duration.as_secs());
+            Some(now() + duration)
+        } else {
+            None
+        };
+
+        self.block_ip(ip, expires).await.map(|_| true)
+    }
+
+    pub async fn block_ip(&self, ip: IpAddr, expires: Option<u64>) -> trc::Result<()> {
         // Add IP to blocked list
-        self.inner.data.blocked_ips.write().insert(ip);
+        self.inner
+            .data
+            .blocked_ips
+            .write()
+            .insert(ip, expires.unwrap_or(u64::MAX));
 
         // Write blocked IP to config
         self.core
@@ -233,7 +293,9 @@ impl Server {
             .config
             .set([ConfigKey {
                 key: format!("{}.{}", BLOCKED_IP_KEY, ip),
-                value: String::new(),
+                value: expires
+                    .map(|expires| expires.to_string())
+                    .unwrap_or_default(),
             }])
             .await?;
 
@@ -243,12 +305,84 @@ impl Server {
         Ok(())
     }
 
+    pub async fn unblock_ip(&self, ip: IpAddr) -> trc::Result<bool> {
+        let was_blocked = self.inner.data.blocked_ips.write().remove(&ip).is_some();
+
+        self.core
+            .storage
+            .config
+            .clear(format!("{}.{}", BLOCKED_IP_KEY, ip))
+            .await?;
+        self.increment_blocked_version();
+
+        Ok(was_blocked)
+    }
+
+    // Active bans and their expiration, None for permanent bans
+    pub fn blocked_ips(&self) -> Vec<(IpAddr, Option<u64>)> {
+        let now = now();
+        self.inner
+            .data
+            .blocked_ips
+            .read()
+            .iter()
+            .filter(|(_, expires)| **expires > now)
+            .map(|(ip, expires)| (*ip, (*expires != u64::MAX).then_some(*expires)))
+            .collect()
+    }
+
+    // Remembers addresses with successful logins so they are not banned automatically
+    pub async fn record_authenticated_ip(&self, ip: IpAddr) -> trc::Result<()> {
+        let security = &self.core.network.security;
+        if security.allow_authenticated {
+            self.lookup_store()
+                .key_set(
+                    format!("a:{}", ban_address(ip)).into_bytes(),
+                    vec![],
+                    security.ban_max_duration.as_secs().into(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    // Removes expired bans from the configuration
+    pub async fn purge_expired_bans(&self) -> trc::Result<()> {
+        let now = now();
+        let expired = self
+            .inner
+            .data
+            .blocked_ips
+            .read()
+            .iter()
+            .filter(|(_, expires)| **expires <= now)
+            .map(|(ip, _)| *ip)
+            .collect::<Vec<_>>();
+
+        for ip in expired {
+            self.unblock_ip(ip).await?;
+        }
+
+        Ok(())
+    }
+
     pub fn has_auth_fail2ban(&self) -> bool {
         self.core.network.security.auth_fail_rate.is_some()
     }
 
     pub fn is_ip_blocked(&self, ip: &IpAddr) -> bool {
-        self.inner.data.blocked_ips.read().contains(ip)
+        if self.is_ip_allowed(ip) {
+            return false;
+        }
+
+        let now = now();
+        let blocked_ips = self.inner.data.blocked_ips.read();
+        blocked_ips.get(ip).is_some_and(|expires| *expires > now)
+            || (ip.is_ipv6()
+                && blocked_ips
+                    .get(&ban_address(*ip))
+                    .is_some_and(|expires| *expires > now))
             || (self.core.network.security.has_blocked_networks
                 && self
                     .core
@@ -281,17 +415,24 @@ impl Server {
 
 impl BlockedIps {
     pub fn parse(config: &mut Config) -> Self {
-        let mut blocked_ip_addresses = AHashSet::new();
+        let mut blocked_ip_addresses = AHashMap::new();
         let mut blocked_ip_networks = Vec::new();
-
-        for ip in config
-            .set_values(BLOCKED_IP_KEY)
-            .map(IpAddrOrMask::parse_value)
+        let now = now();
+
+        for (ip, expires) in config
+            .iterate_prefix(BLOCKED_IP_KEY)
+            .map(|(ip, expires)| {
+                (
+                    IpAddrOrMask::parse_value(ip),
+                    expires.parse::<u64>().unwrap_or(u64::MAX),
+                )
+            })
             .collect::<Vec<_>>()
         {
             match ip {
                 Ok(IpAddrOrMask::Ip(ip)) => {
-                    blocked_ip_addresses.insert(ip);
+                    // Expired bans are kept until purged so they can be removed from the config
+                    blocked_ip_addresses.insert(ip, expires);
                 }
                 Ok(IpAddrOrMask::Mask(ip)) => {
                     blocked_ip_networks.push(ip);
@@ -330,6 +471,19 @@ impl Default for Security {
             loiter_fail_rate: Default::default(),
             scanner_fail_rate: Default::default(),
             http_banned_paths: Default::default(),
+            ban_duration: Default::default(),
+            ban_max_duration: Duration::from_secs(30 * 86400),
+            allow_authenticated: Default::default(),
+        }
+    }
+}
+
+// IPv6 clients usually control a whole /64 network, so they are tracked and banned by prefix
+pub fn ban_address(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(ip) if ip.to_ipv4_mapped().is_none() => {
+            IpAddr::V6(Ipv6Addr::from(u128::from(ip) & !(u64::MAX as u128)))
         }
+        ip => ip,
     }
 }
@@ -10,7 +10,6 @@ use std::{
     time::Duration,
 };
 
-use proxy_header::io::ProxiedStream;
 use rustls::crypto::ring::cipher_suite::TLS13_AES_128_GCM_SHA256;
 use tokio::{net::TcpStream, sync::watch};
 use tokio_rustls::server::TlsStream;
@@ -24,8 +23,9 @@ use crate::{
 };
 
 use super::{
-    limiter::ConcurrencyLimiter, ServerInstance, SessionData, SessionManager, SessionStream,
-    TcpAcceptor,
+    limiter::ConcurrencyLimiter,
+    proxy::{accept_proxied, proxy_policy, ProxyAccept, ProxyPolicy},
+    ServerInstance, SessionData, SessionManager, SessionStream, TcpAcceptor,
 };
 
 impl Listener {
@@ -41,14 +41,15 @@ impl Listener {
             id: self.id,
             protocol: self.protocol,
             proxy_networks: self.proxy_networks,
+            proxy_required: self.proxy_required,
+            allow_cram_md5: self.allow_cram_md5,
             limiter: ConcurrencyLimiter::new(self.max_connections),
             acceptor,
             shutdown_rx,
             span_id_gen: self.span_id_gen,
         });
         let is_tls = matches!(instance.acceptor, TcpAcceptor::Tls { implicit, .. } if implicit);
-        let is_https = is_tls && self.protocol == ServerProtocol::Http;
-        let has_proxies = !instance.proxy_networks.is_empty();
+        let has_proxies = !instance.proxy_networks.is_empty() || instance.proxy_required;
 
         // Spawn listeners
         for listener in self.listeners {
@@ -94,28 +95,7 @@ impl Listener {
             let instance = instance.clone();
             let inner = inner.clone();
             tokio::spawn(async move {
-                let (span_start, span_end) = match self.protocol {
-                    ServerProtocol::Smtp | ServerProtocol::Lmtp => (
-                        EventType::Smtp(SmtpEvent::ConnectionStart),
-                        EventType::Smtp(SmtpEvent::ConnectionEnd),
-                    ),
-                    ServerProtocol::Imap => (
-                        EventType::Imap(ImapEvent::ConnectionStart),
-                        EventType::Imap(ImapEvent::ConnectionEnd),
-                    ),
-                    ServerProtocol::Pop3 => (
-                        EventType::Pop3(Pop3Event::ConnectionStart),
-                        EventType::Pop3(Pop3Event::ConnectionEnd),
-                    ),
-                    ServerProtocol::Http => (
-                        EventType::Http(HttpEvent::ConnectionStart),
-                        EventType::Http(HttpEvent::ConnectionEnd),
-                    ),
-                    ServerProtocol::ManageSieve => (
-                        EventType::ManageSieve(ManageSieveEvent::ConnectionStart),
-                        EventType::ManageSieve(ManageSieveEvent::ConnectionEnd),
-                    ),
-                };
+                let (span_start, span_end) = connection_span(self.protocol);
 
                 loop {
                     tokio::select! {
@@ -123,9 +103,25 @@ impl Listener {
                             match stream {
                                 Ok((stream, remote_addr)) => {
                                     let server = inner.build_server();
-                                    let enable_acme = (is_https && server.has_acme_tls_providers()).then(|| server.clone());
+                                    let enable_acme = (is_tls && server.has_acme_tls_providers()).then(|| server.clone());
 
-                                    if has_proxies && instance.proxy_networks.iter().any(|network| network.matches(&remote_addr.ip())) {
+                                    let policy = if has_proxies {
+                                        proxy_policy(&instance.proxy_networks, instance.proxy_required, &remote_addr.ip())
+                                    } else {
+                                        ProxyPolicy::Direct
+                                    };
+
+                                    if policy == ProxyPolicy::Reject {
+                                        trc::event!(
+                                            Network(trc::NetworkEvent::ProxyError),
+                                            ListenerId = instance.id.clone(),
+                                            LocalIp = local_addr.ip(),
+                                            LocalPort = local_addr.port(),
+                                            RemoteIp = remote_addr.ip(),
+                                            Tls = is_tls,
+                                            Reason = "Connection from untrusted proxy",
+                                        );
+                                    } else if policy == ProxyPolicy::Proxied {
                                         let instance = instance.clone();
                                         let manager = manager.clone();
 
@@ -133,23 +129,23 @@ impl Listener {
                                         opts.apply(&stream);
 
                                         tokio::spawn(async move {
-                                            match ProxiedStream::create_from_tokio(stream, Default::default()).await {
-                                                Ok(stream) =>{
-                                                    let remote_addr = stream.proxy_header()
-                                                                            .proxied_address()
-                                                                            .map(|addr| addr.source)
-                                                                            .unwrap_or(remote_addr);
+                                            match accept_proxied(stream).await {
+                                                ProxyAccept::Proxied { stream, remote_addr } => {
                                                     if let Some(session) = instance.build_session(stream, local_addr, remote_addr, &server) {
                                                         // Spawn session
                                                         manager.spawn(session, is_tls, enable_acme, span_start, span_end);
                                                     }
                                                 }
-                                                Err(err) => {
+                                                ProxyAccept::Local => {
+                                                    // Health check from the proxy, close the connection
+                                                }
+                                                ProxyAccept::Error(err) => {
                                                     trc::event!(
                                                         Network(trc::NetworkEvent::ProxyError),
                                                         ListenerId = instance.id.clone(),
                                                         LocalIp = local_addr.ip(),
                                                         LocalPort = local_addr.port(),
+                                                        RemoteIp = remote_addr.ip(),
                                                         Tls = is_tls,
                                                         Reason = err.to_string(),
                                                     );
@@ -193,6 +189,137 @@ impl Listener {
                 }
             });
         }
+
+        // Spawn Unix domain socket listeners
+        #[cfg(unix)]
+        for listener in self.unix_listeners {
+            let path = listener.path.display().to_string();
+
+            // Bind socket
+            let unix_listener = match listener.listen() {
+                Ok(unix_listener) => {
+                    trc::event!(
+                        Network(trc::NetworkEvent::ListenStart),
+                        ListenerId = instance.id.clone(),
+                        Path = path.clone(),
+                        Tls = is_tls,
+                    );
+
+                    unix_listener
+                }
+                Err(err) => {
+                    trc::event!(
+                        Network(trc::NetworkEvent::ListenError),
+                        ListenerId = instance.id.clone(),
+                        Path = path,
+                        Tls = is_tls,
+                        Reason = err,
+                    );
+
+                    continue;
+                }
+            };
+
+            // Spawn listener
+            let mut shutdown_rx = instance.shutdown_rx.clone();
+            let manager = manager.clone();
+            let instance = instance.clone();
+            let inner = inner.clone();
+            tokio::spawn(async move {
+                let (span_start, span_end) = connection_span(self.protocol);
+
+                // Unix domain socket peers are seen as local connections
+                let local_addr = SocketAddr::from((std::net::Ipv4Addr::LOCALHOST, 0));
+
+                loop {
+                    tokio::select! {
+                        stream = unix_listener.accept() => {
+                            match stream {
+                                Ok((stream, _)) => {
+                                    match stream.peer_cred() {
+                                        Ok(cred) if listener.is_peer_allowed(cred.uid(), cred.gid()) => {
+                                            let server = inner.build_server();
+                                            if let Some(session) = instance.build_session(stream, local_addr, local_addr, &server) {
+                                                // Spawn session
+                                                manager.spawn(session, is_tls, None, span_start, span_end);
+                                            }
+                                        }
+                                        Ok(cred) => {
+                                            trc::event!(
+                                                Security(trc::SecurityEvent::Unauthorized),
+                                                ListenerId = instance.id.clone(),
+                                                Path = path.clone(),
+                                                Reason = format!(
+                                                    "Peer with uid {} and gid {} is not allowed",
+                                                    cred.uid(),
+                                                    cred.gid()
+                                                ),
+                                            );
+                                        }
+                                        Err(err) => {
+                                            trc::event!(
+                                                Network(trc::NetworkEvent::AcceptError),
+                                                ListenerId = instance.id.clone(),
+                                                Path = path.clone(),
+                                                Reason = err.to_string(),
+                                                Details = "Failed to obtain peer credentials",
+                                            );
+                                        }
+                                    }
+                                }
+                                Err(err) => {
+                                    trc::event!(
+                                        Network(trc::NetworkEvent::AcceptError),
+                                        ListenerId = instance.id.clone(),
+                                        Path = path.clone(),
+                                        Tls = is_tls,
+                                        Reason = err.to_string(),
+                                    );
+                                }
+                            }
+                        },
+                        _ = shutdown_rx.changed() => {
+
+                            trc::event!(
+                                Network(trc::NetworkEvent::ListenStop),
+                                ListenerId = instance.id.clone(),
+                                Path = path.clone(),
+                                Tls = is_tls,
+                            );
+
+                            let _ = std::fs::remove_file(&listener.path);
+                            manager.shutdown().await;
+                            break;
+                        }
+                    };
+                }
+            });
+        }
+    }
+}
+
+fn connection_span(protocol: ServerProtocol) -> (EventType, EventType) {
+    match protocol {
+        ServerProtocol::Smtp | ServerProtocol::Lmtp => (
+            EventType::Smtp(SmtpEvent::ConnectionStart),
+            EventType::Smtp(SmtpEvent::ConnectionEnd),
+        ),
+        ServerProtocol::Imap => (
+            EventType::Imap(ImapEvent::ConnectionStart),
+            EventType::Imap(ImapEvent::ConnectionEnd),
+        ),
+        ServerProtocol::Pop3 => (
+            EventType::Pop3(Pop3Event::ConnectionStart),
+            EventType::Pop3(Pop3Event::ConnectionEnd),
+        ),
+        ServerProtocol::Http => (
+            EventType::Http(HttpEvent::ConnectionStart),
+            EventType::Http(HttpEvent::ConnectionEnd),
+        ),
+        ServerProtocol::ManageSieve => (
+            EventType::ManageSieve(ManageSieveEvent::ConnectionStart),
+            EventType::ManageSieve(ManageSieveEvent::ConnectionEnd),
+        ),
     }
 }
 
@@ -357,6 +484,40 @@ impl TcpListener {
     }
 }
 
+#[cfg(unix)]
+impl crate::config::server::UnixListener {
+    pub fn listen(&self) -> Result<tokio::net::UnixListener, String> {
+        use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+        // Remove stale sockets left behind by a previous run
+        if std::fs::symlink_metadata(&self.path).is_ok_and(|m| m.file_type().is_socket()) {
+            let _ = std::fs::remove_file(&self.path);
+        }
+
+        let listener = tokio::net::UnixListener::bind(&self.path)
+            .map_err(|err| format!("Failed to listen on {}: {}", self.path.display(), err))?;
+        if let Some(mode) = self.mode {
+            std::fs::set_permissions(&self.path, std::fs::Permissions::from_mode(mode)).map_err(
+                |err| {
+                    format!(
+                        "Failed to set permissions on {}: {}",
+                        self.path.display(),
+                        err
+                    )
+                },
+            )?;
+        }
+
+        Ok(listener)
+    }
+
+    pub fn is_peer_allowed(&self, uid: u32, gid: u32) -> bool {
+        (self.allowed_uids.is_empty() && self.allowed_gids.is_empty())
+            || self.allowed_uids.contains(&uid)
+            || self.allowed_gids.contains(&gid)
+    }
+}
+
 impl ServerInstance {
     pub async fn tls_accept<T: SessionStream>(
         &self,
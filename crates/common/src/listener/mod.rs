@@ -28,6 +28,7 @@ pub mod acme;
 pub mod blocked;
 pub mod limiter;
 pub mod listen;
+pub mod proxy;
 pub mod stream;
 pub mod tls;
 
@@ -37,6 +38,8 @@ pub struct ServerInstance {
     pub acceptor: TcpAcceptor,
     pub limiter: ConcurrencyLimiter,
     pub proxy_networks: Vec<IpAddrMask>,
+    pub proxy_required: bool,
+    pub allow_cram_md5: bool,
     pub shutdown_rx: watch::Receiver<bool>,
     pub span_id_gen: Arc<SnowflakeIdGenerator>,
 }
@@ -77,6 +80,11 @@ pub struct SessionData<T: SessionStream> {
 pub trait SessionStream: AsyncRead + AsyncWrite + Unpin + 'static + Sync + Send {
     fn is_tls(&self) -> bool;
     fn tls_version_and_cipher(&self) -> (Cow<'static, str>, Cow<'static, str>);
+
+    // Channel binding data for SCRAM-*-PLUS mechanisms (RFC 9266)
+    fn tls_exporter(&self) -> Option<Vec<u8>> {
+        None
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
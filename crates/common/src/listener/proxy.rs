@@ -0,0 +1,75 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::net::{IpAddr, SocketAddr};
+
+use proxy_header::{io::ProxiedStream, ParseConfig};
+use tokio::io::AsyncRead;
+use utils::config::ipmask::IpAddrMask;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProxyPolicy {
+    // Connection does not come from a proxy
+    Direct,
+    // Connection comes from a trusted proxy, a header is expected
+    Proxied,
+    // Listener requires a proxy and the source is not trusted
+    Reject,
+}
+
+pub enum ProxyAccept<T> {
+    // Header parsed, the stream carries the conveyed source address
+    Proxied {
+        stream: ProxiedStream<T>,
+        remote_addr: SocketAddr,
+    },
+    // PROXY v2 LOCAL command (or v1 UNKNOWN), used by load balancer health checks
+    Local,
+    // Malformed or truncated header
+    Error(std::io::Error),
+}
+
+pub fn proxy_policy(
+    proxy_networks: &[IpAddrMask],
+    proxy_required: bool,
+    ip: &IpAddr,
+) -> ProxyPolicy {
+    if proxy_networks.iter().any(|network| network.matches(ip)) {
+        ProxyPolicy::Proxied
+    } else if proxy_required {
+        ProxyPolicy::Reject
+    } else {
+        ProxyPolicy::Direct
+    }
+}
+
+pub async fn accept_proxied<T: AsyncRead + Unpin>(stream: T) -> ProxyAccept<T> {
+    match ProxiedStream::create_from_tokio(
+        stream,
+        ParseConfig {
+            include_tlvs: true,
+            ..Default::default()
+        },
+    )
+    .await
+    {
+        Ok(stream) => {
+            if let Some(remote_addr) = stream
+                .proxy_header()
+                .proxied_address()
+                .map(|addr| addr.source)
+            {
+                ProxyAccept::Proxied {
+                    stream,
+                    remote_addr,
+                }
+            } else {
+                ProxyAccept::Local
+            }
+        }
+        Err(err) => ProxyAccept::Error(err),
+    }
+}
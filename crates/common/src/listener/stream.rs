@@ -25,6 +25,17 @@ impl SessionStream for TcpStream {
     }
 }
 
+#[cfg(unix)]
+impl SessionStream for tokio::net::UnixStream {
+    fn is_tls(&self) -> bool {
+        false
+    }
+
+    fn tls_version_and_cipher(&self) -> (Cow<'static, str>, Cow<'static, str>) {
+        (Cow::Borrowed(""), Cow::Borrowed(""))
+    }
+}
+
 impl<T: SessionStream> SessionStream for TlsStream<T> {
     fn is_tls(&self) -> bool {
         true
@@ -62,6 +73,19 @@ impl<T: SessionStream> SessionStream for TlsStream<T> {
             .into(),
         )
     }
+
+    fn tls_exporter(&self) -> Option<Vec<u8>> {
+        let (_, conn) = self.get_ref();
+
+        // tls-exporter is only defined for TLS 1.3 connections
+        if conn.protocol_version() == Some(rustls::ProtocolVersion::TLSv1_3) {
+            conn.export_keying_material([0u8; 32], b"EXPORTER-Channel-Binding", Some(&[]))
+                .ok()
+                .map(|cb| cb.to_vec())
+        } else {
+            None
+        }
+    }
 }
 
 impl SessionStream for ProxiedStream<TcpStream> {
@@ -5,18 +5,23 @@
  */
 
 use std::{
-    collections::BTreeSet,
+    collections::{BTreeMap, BTreeSet},
     io::{BufWriter, Write},
     ops::Range,
     path::{Path, PathBuf},
-    sync::mpsc::{self, SyncSender},
+    sync::{
+        mpsc::{self, SyncSender},
+        Arc,
+    },
 };
 
 use ahash::{AHashMap, AHashSet};
 use jmap_proto::types::{collection::Collection, property::Property};
+use serde::{Deserialize as SerdeDeserialize, Serialize as SerdeSerialize};
+use sha2::{Digest, Sha256};
 use store::{
     write::{
-        key::DeserializeBigEndian, AnyKey, BitmapClass, BitmapHash, BlobOp, DirectoryClass,
+        key::DeserializeBigEndian, now, AnyKey, BitmapClass, BitmapHash, BlobOp, DirectoryClass,
         LookupClass, QueueClass, QueueEvent, TagValue, ValueClass,
     },
     BitmapKey, Deserialize, IndexKey, IterateParams, LogKey, Serialize, ValueKey,
@@ -32,6 +37,8 @@ use crate::Core;
 
 pub(super) const MAGIC_MARKER: u8 = 123;
 pub(super) const FILE_VERSION: u8 = 2;
+pub(super) const MANIFEST_FILE: &str = "manifest.json";
+pub(super) const BLOB_INDEX_FILE: &str = "blob_index";
 
 #[derive(Debug)]
 pub(super) enum Op {
@@ -64,9 +71,35 @@ type TaskHandle = (tokio::task::JoinHandle<()>, std::thread::JoinHandle<()>);
 #[derive(Debug, Default, PartialEq, Eq)]
 pub struct BackupParams {
     dest: PathBuf,
+    since: Option<PathBuf>,
     families: AHashSet<Family>,
 }
 
+#[derive(Debug, Default, SerdeSerialize, SerdeDeserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupManifest {
+    pub version: u8,
+    pub created: u64,
+    // Backup this one is an increment of, none for full backups
+    pub base: Option<PathBuf>,
+    // Last change id logged for each account when the backup was taken
+    pub change_ids: BTreeMap<u32, u64>,
+    // Accounts captured by an incremental backup, none for full backups
+    pub accounts: Option<Vec<u32>>,
+    pub sections: Vec<BackupSection>,
+}
+
+#[derive(Debug, Default, SerdeSerialize, SerdeDeserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupSection {
+    pub name: String,
+    pub size: u64,
+    pub checksum: String,
+}
+
+#[derive(Debug, Clone, Default)]
+struct AccountFilter(Option<Arc<AHashSet<u32>>>);
+
 impl Core {
     pub async fn backup(&self, params: BackupParams) {
         if !params.dest.exists() {
@@ -76,21 +109,48 @@ impl Core {
             std::process::exit(1);
         }
 
+        // Incremental backups only capture the accounts that logged changes since their base,
+        // along with the blobs that were not committed yet when the base was taken
+        let change_ids = self.last_change_ids().await;
+        let (base, accounts, known_blobs) = if let Some(since) = &params.since {
+            let base = BackupManifest::read(since).failed("Failed to read base backup");
+            let accounts = change_ids
+                .iter()
+                .filter(|(account_id, change_id)| {
+                    base.change_ids.get(*account_id) != Some(*change_id)
+                })
+                .map(|(account_id, _)| *account_id)
+                .chain([u32::MAX])
+                .collect::<AHashSet<_>>();
+
+            (
+                Some(
+                    since
+                        .canonicalize()
+                        .failed("Failed to resolve base backup path"),
+                ),
+                AccountFilter(Some(Arc::new(accounts))),
+                Arc::new(read_blob_index(since)),
+            )
+        } else {
+            (None, AccountFilter::default(), Arc::new(AHashSet::new()))
+        };
+
         let mut sync_handles = Vec::new();
 
         for (async_handle, sync_handle) in [
             params
                 .has_family(Family::Property)
-                .then(|| self.backup_properties(&params.dest)),
+                .then(|| self.backup_properties(&params.dest, accounts.clone())),
             params
                 .has_family(Family::FtsIndex)
-                .then(|| self.backup_fts_index(&params.dest)),
+                .then(|| self.backup_fts_index(&params.dest, accounts.clone())),
             params
                 .has_family(Family::Acl)
-                .then(|| self.backup_acl(&params.dest)),
+                .then(|| self.backup_acl(&params.dest, accounts.clone())),
             params
                 .has_family(Family::Blob)
-                .then(|| self.backup_blob(&params.dest)),
+                .then(|| self.backup_blob(&params.dest, accounts.clone(), known_blobs.clone())),
             params
                 .has_family(Family::Config)
                 .then(|| self.backup_config(&params.dest)),
@@ -105,13 +165,13 @@ impl Core {
                 .then(|| self.backup_queue(&params.dest)),
             params
                 .has_family(Family::Index)
-                .then(|| self.backup_index(&params.dest)),
+                .then(|| self.backup_index(&params.dest, accounts.clone())),
             params
                 .has_family(Family::Bitmap)
-                .then(|| self.backup_bitmaps(&params.dest)),
+                .then(|| self.backup_bitmaps(&params.dest, accounts.clone())),
             params
                 .has_family(Family::Log)
-                .then(|| self.backup_logs(&params.dest)),
+                .then(|| self.backup_logs(&params.dest, accounts.clone())),
         ]
         .into_iter()
         .flatten()
@@ -123,9 +183,88 @@ impl Core {
         for handle in sync_handles {
             handle.join().expect("Failed to join thread");
         }
+
+        // Account data is consistent as long as no changes were logged while exporting
+        if self.last_change_ids().await != change_ids {
+            eprintln!("Backup failed: the store was modified during the backup, please try again.");
+            std::process::exit(1);
+        }
+
+        let mut manifest = BackupManifest {
+            version: FILE_VERSION,
+            created: now(),
+            base,
+            change_ids,
+            accounts: accounts.0.map(|accounts| {
+                let mut accounts = accounts.iter().copied().collect::<Vec<_>>();
+                accounts.sort_unstable();
+                accounts
+            }),
+            sections: Vec::new(),
+        };
+        for entry in std::fs::read_dir(&params.dest).failed("Failed to read backup directory") {
+            let path = entry.failed("Failed to read entry").path();
+            let name = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .unwrap_or_default()
+                .to_string();
+            if path.is_file() && name != MANIFEST_FILE {
+                let (size, checksum) = file_checksum(&path).failed("Failed to checksum section");
+                manifest.sections.push(BackupSection {
+                    name,
+                    size,
+                    checksum,
+                });
+            }
+        }
+        manifest
+            .sections
+            .sort_unstable_by(|a, b| a.name.cmp(&b.name));
+        std::fs::write(
+            params.dest.join(MANIFEST_FILE),
+            serde_json::to_vec_pretty(&manifest).failed("Failed to serialize manifest"),
+        )
+        .failed("Failed to write manifest");
+    }
+
+    async fn last_change_ids(&self) -> BTreeMap<u32, u64> {
+        let mut change_ids = BTreeMap::new();
+
+        self.storage
+            .data
+            .iterate(
+                IterateParams::new(
+                    LogKey {
+                        account_id: 0,
+                        collection: 0,
+                        change_id: 0,
+                    },
+                    LogKey {
+                        account_id: u32::MAX,
+                        collection: u8::MAX,
+                        change_id: u64::MAX,
+                    },
+                )
+                .no_values(),
+                |key, _| {
+                    let account_id = key.deserialize_be_u32(0)?;
+                    let change_id = key.deserialize_be_u64(U32_LEN + 1)?;
+                    let last_change_id = change_ids.entry(account_id).or_insert(change_id);
+                    if change_id > *last_change_id {
+                        *last_change_id = change_id;
+                    }
+
+                    Ok(true)
+                },
+            )
+            .await
+            .failed("Failed to iterate over data store");
+
+        change_ids
     }
 
-    fn backup_properties(&self, dest: &Path) -> TaskHandle {
+    fn backup_properties(&self, dest: &Path, accounts: AccountFilter) -> TaskHandle {
         let store = self.storage.data.clone();
         let (handle, writer) = spawn_writer(dest.join("property"));
         (
@@ -159,7 +298,9 @@ impl Core {
                             let field = key.deserialize_u8(U32_LEN + 1)?;
                             let document_id = key.deserialize_be_u32(U32_LEN + 2)?;
 
-                            keys.insert((account_id, collection, document_id, field));
+                            if accounts.contains(account_id) {
+                                keys.insert((account_id, collection, document_id, field));
+                            }
 
                             Ok(true)
                         },
@@ -237,7 +378,7 @@ impl Core {
         )
     }
 
-    fn backup_fts_index(&self, dest: &Path) -> TaskHandle {
+    fn backup_fts_index(&self, dest: &Path, accounts: AccountFilter) -> TaskHandle {
         let store = self.storage.data.clone();
         let (handle, writer) = spawn_writer(dest.join("fts_index"));
         (
@@ -276,6 +417,10 @@ impl Core {
                             let collection = key.deserialize_u8(key.len() - U32_LEN - 1)?;
                             let document_id = key.deserialize_be_u32(key.len() - U32_LEN)?;
 
+                            if !accounts.contains(account_id) {
+                                return Ok(true);
+                            }
+
                             if account_id != last_account_id {
                                 writer
                                     .send(Op::AccountId(account_id))
@@ -311,7 +456,7 @@ impl Core {
         )
     }
 
-    fn backup_acl(&self, dest: &Path) -> TaskHandle {
+    fn backup_acl(&self, dest: &Path, accounts: AccountFilter) -> TaskHandle {
         let store = self.storage.data.clone();
         let (handle, writer) = spawn_writer(dest.join("acl"));
         (
@@ -346,6 +491,10 @@ impl Core {
                             let collection = key.deserialize_u8(U32_LEN * 2)?;
                             let document_id = key.deserialize_be_u32((U32_LEN * 2) + 1)?;
 
+                            if !accounts.contains(account_id) {
+                                return Ok(true);
+                            }
+
                             if account_id != last_account_id {
                                 writer
                                     .send(Op::AccountId(account_id))
@@ -384,9 +533,15 @@ impl Core {
         )
     }
 
-    fn backup_blob(&self, dest: &Path) -> TaskHandle {
+    fn backup_blob(
+        &self,
+        dest: &Path,
+        accounts: AccountFilter,
+        known_blobs: Arc<AHashSet<Vec<u8>>>,
+    ) -> TaskHandle {
         let store = self.storage.data.clone();
         let blob_store = self.storage.blob.clone();
+        let index_path = dest.join(BLOB_INDEX_FILE);
         let (handle, writer) = spawn_writer(dest.join("blob"));
         (
             tokio::spawn(async move {
@@ -425,6 +580,10 @@ impl Core {
                             let hash = key.range(0..BLOB_HASH_LEN)?.to_vec();
 
                             if account_id != u32::MAX && document_id != u32::MAX {
+                                if !accounts.contains(account_id) {
+                                    return Ok(true);
+                                }
+
                                 writer
                                     .send(Op::AccountId(account_id))
                                     .failed("Failed to send account id");
@@ -447,6 +606,10 @@ impl Core {
                     .await
                     .failed("Failed to iterate over data store");
 
+                // The index lists every committed blob, only new ones are exported
+                std::fs::write(index_path, hashes.concat()).failed("Failed to write blob index");
+                hashes.retain(|hash| !known_blobs.contains(hash));
+
                 if !hashes.is_empty() {
                     writer
                         .send(Op::AccountId(u32::MAX))
@@ -596,10 +759,7 @@ impl Core {
                         )
                         .no_values(),
                         |key, _| {
-                            if (key.len() != (U32_LEN * 2) + 2)
-                                || key[U32_LEN + 1] != 84
-                                || key[U32_LEN] != 1
-                            {
+                            if !is_mailbox_counter(key) {
                                 counters.push(key.to_vec());
                             }
 
@@ -781,7 +941,7 @@ impl Core {
         )
     }
 
-    fn backup_index(&self, dest: &Path) -> TaskHandle {
+    fn backup_index(&self, dest: &Path, accounts: AccountFilter) -> TaskHandle {
         let store = self.storage.data.clone();
         let (handle, writer) = spawn_writer(dest.join("index"));
         (
@@ -817,6 +977,10 @@ impl Core {
                             let collection = key.deserialize_u8(U32_LEN)?;
                             let document_id = key.deserialize_be_u32(key.len() - U32_LEN)?;
 
+                            if !accounts.contains(account_id) {
+                                return Ok(true);
+                            }
+
                             let key = key.range(U32_LEN + 1..key.len() - U32_LEN)?.to_vec();
 
                             if account_id != last_account_id {
@@ -851,7 +1015,7 @@ impl Core {
         )
     }
 
-    fn backup_bitmaps(&self, dest: &Path) -> TaskHandle {
+    fn backup_bitmaps(&self, dest: &Path, accounts: AccountFilter) -> TaskHandle {
         let store = self.storage.data.clone();
 
         let (handle, writer) = spawn_writer(dest.join("bitmap"));
@@ -886,6 +1050,10 @@ impl Core {
                             |key, _| {
                                 let account_id = key.deserialize_be_u32(0)?;
 
+                                if !accounts.contains(account_id) {
+                                    return Ok(true);
+                                }
+
                                 let key = key.range(0..key.len() - U32_LEN)?;
 
                                 match subspace {
@@ -1023,7 +1191,7 @@ impl Core {
         )
     }
 
-    fn backup_logs(&self, dest: &Path) -> TaskHandle {
+    fn backup_logs(&self, dest: &Path, accounts: AccountFilter) -> TaskHandle {
         let store = self.storage.data.clone();
         let (handle, writer) = spawn_writer(dest.join("log"));
         (
@@ -1054,7 +1222,12 @@ impl Core {
                             let collection = key.deserialize_u8(U32_LEN)?;
                             let key = key.range(U32_LEN + 1..usize::MAX)?.to_vec();
 
-                            if key.len() != U64_LEN {
+                            if !accounts.contains(account_id) {
+                                return Ok(true);
+                            }
+
+                            // Entries are keyed by change id, optionally followed by a timestamp
+                            if key.len() != U64_LEN && key.len() != U64_LEN * 2 {
                                 failed(&format!("Found invalid log entry {key:?} {value:?}"));
                             }
 
@@ -1164,10 +1337,90 @@ impl DeserializeBytes for &[u8] {
     }
 }
 
+impl AccountFilter {
+    fn contains(&self, account_id: u32) -> bool {
+        self.0
+            .as_ref()
+            .map_or(true, |accounts| accounts.contains(&account_id))
+    }
+}
+
+impl BackupManifest {
+    pub fn read(dir: &Path) -> Result<Self, String> {
+        let path = dir.join(MANIFEST_FILE);
+        let bytes = std::fs::read(&path)
+            .map_err(|err| format!("Failed to read manifest {path:?}: {err}"))?;
+        serde_json::from_slice(&bytes)
+            .map_err(|err| format!("Failed to parse manifest {path:?}: {err}"))
+    }
+
+    // Returns the backups to restore in order, starting with the full backup
+    pub fn chain(dir: &Path) -> Result<Vec<(PathBuf, Self)>, String> {
+        let mut chain: Vec<(PathBuf, Self)> = Vec::new();
+        let mut next = Some(dir.to_path_buf());
+
+        while let Some(dir) = next {
+            if chain.iter().any(|(path, _)| path == &dir) {
+                return Err(format!("Backup {dir:?} is referenced twice in the chain"));
+            }
+            let manifest = Self::read(&dir)?;
+            if manifest.version > FILE_VERSION {
+                return Err(format!("Unsupported backup version in {dir:?}"));
+            }
+            next = manifest.base.clone();
+            chain.push((dir, manifest));
+        }
+
+        chain.reverse();
+        Ok(chain)
+    }
+
+    pub fn verify(&self, dir: &Path) -> Result<(), String> {
+        for section in &self.sections {
+            let (size, checksum) = file_checksum(&dir.join(&section.name))?;
+            if size != section.size || checksum != section.checksum {
+                return Err(format!(
+                    "Checksum mismatch for section {:?} in {dir:?}",
+                    section.name
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub(super) fn read_blob_index(dir: &Path) -> AHashSet<Vec<u8>> {
+    std::fs::read(dir.join(BLOB_INDEX_FILE))
+        .map(|bytes| {
+            bytes
+                .chunks_exact(BLOB_HASH_LEN)
+                .map(|hash| hash.to_vec())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn file_checksum(path: &Path) -> Result<(u64, String), String> {
+    let mut file = std::fs::File::open(path)
+        .map_err(|err| format!("Failed to open section {path:?}: {err}"))?;
+    let mut hasher = Sha256::new();
+    let size = std::io::copy(&mut file, &mut hasher)
+        .map_err(|err| format!("Failed to read section {path:?}: {err}"))?;
+
+    Ok((size, format!("{:x}", hasher.finalize())))
+}
+
+// Mailbox UID counters share the counter subspace with lookup counters
+pub(super) fn is_mailbox_counter(key: &[u8]) -> bool {
+    key.len() == (U32_LEN * 2) + 2 && key[U32_LEN] == 1 && key[U32_LEN + 1] == 84
+}
+
 impl BackupParams {
     pub fn new(dest: PathBuf) -> Self {
         let mut params = Self {
             dest,
+            since: std::env::var("EXPORT_SINCE").ok().map(PathBuf::from),
             families: AHashSet::new(),
         };
 
@@ -1178,6 +1431,11 @@ impl BackupParams {
         params
     }
 
+    pub fn with_since(mut self, since: PathBuf) -> Self {
+        self.since = Some(since);
+        self
+    }
+
     fn parse_families(&mut self, families: &str) {
         for family in families.split(',') {
             let family = family.trim();
@@ -28,6 +28,7 @@ use super::{
     backup::BackupParams,
     config::{ConfigManager, Patterns},
     console::store_console,
+    restore::RestoreParams,
     WEBADMIN_KEY,
 };
 
@@ -67,7 +68,7 @@ Options:
 #[derive(PartialEq, Eq)]
 enum StoreOp {
     Export(BackupParams),
-    Import(PathBuf),
+    Import(RestoreParams),
     Console,
     None,
 }
@@ -111,7 +112,7 @@ impl BootManager {
                         import_export = StoreOp::Export(BackupParams::new(value.into()));
                     }
                     ("import" | "i", Some(value)) => {
-                        import_export = StoreOp::Import(value.into());
+                        import_export = StoreOp::Import(RestoreParams::new(value.into()));
                     }
                     ("console" | "o", None) => {
                         import_export = StoreOp::Console;
@@ -173,6 +174,7 @@ impl BootManager {
                 .and_then(|id| stores.stores.get(id))
                 .cloned()
                 .unwrap_or_default(),
+            cfg_snapshot: Default::default(),
         };
 
         // Extend configuration with settings stored in the db
@@ -235,6 +237,22 @@ impl BootManager {
                     )));
                 }
 
+                // Generate a DKIM private key encryption key if missing
+                if config
+                    .value("auth.dkim.managed.key")
+                    .filter(|v| !v.is_empty())
+                    .is_none()
+                {
+                    insert_keys.push(ConfigKey::from((
+                        "auth.dkim.managed.key",
+                        thread_rng()
+                            .sample_iter(Alphanumeric)
+                            .take(64)
+                            .map(char::from)
+                            .collect::<String>(),
+                    )));
+                }
+
                 // Download SPAM filters if missing
                 if config
                     .value("version.spam-filter")
@@ -28,6 +28,7 @@ pub struct ConfigManager {
     pub cfg_local_path: PathBuf,
     pub cfg_local_patterns: Arc<Patterns>,
     pub cfg_store: Store,
+    pub cfg_snapshot: Arc<BTreeMap<String, String>>,
 }
 
 #[derive(Default)]
@@ -326,6 +327,31 @@ impl ConfigManager {
             })
     }
 
+    pub async fn reload_local(&self) -> trc::Result<()> {
+        if self.cfg_local_path.as_os_str().is_empty() {
+            return Ok(());
+        }
+
+        let cfg_text = tokio::fs::read_to_string(&self.cfg_local_path)
+            .await
+            .map_err(|err| {
+                trc::EventType::Config(trc::ConfigEvent::FetchError)
+                    .reason(err)
+                    .details("Failed to read local configuration")
+                    .ctx(trc::Key::Path, self.cfg_local_path.display().to_string())
+            })?;
+        let mut config = Config::default();
+        config.parse(&cfg_text).map_err(|err| {
+            trc::EventType::Config(trc::ConfigEvent::ParseError)
+                .into_err()
+                .details(err)
+                .ctx(trc::Key::Path, self.cfg_local_path.display().to_string())
+        })?;
+        self.cfg_local.store(config.keys.into());
+
+        Ok(())
+    }
+
     pub async fn update_config_resource(&self, resource_id: &str) -> trc::Result<Option<String>> {
         let external = self
             .fetch_config_resource(resource_id)
@@ -565,6 +591,7 @@ impl Clone for ConfigManager {
             cfg_local_path: self.cfg_local_path.clone(),
             cfg_local_patterns: self.cfg_local_patterns.clone(),
             cfg_store: self.cfg_store.clone(),
+            cfg_snapshot: self.cfg_snapshot.clone(),
         }
     }
 }
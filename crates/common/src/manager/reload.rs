@@ -4,6 +4,8 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+use std::collections::BTreeMap;
+
 use ahash::AHashMap;
 use arc_swap::ArcSwap;
 use store::Stores;
@@ -20,10 +22,28 @@ use crate::{
 
 use super::config::{ConfigManager, Patterns};
 
+// Settings that are only read on startup
+static RESTART_REQUIRED: &[&str] = &[
+    "server.listener.",
+    "server.socket.",
+    "server.run-as.",
+    "server.max-connections",
+    "store.",
+    "storage.",
+    "cluster.",
+];
+
 pub struct ReloadResult {
     pub config: Config,
     pub new_core: Option<Core>,
     pub tracers: Option<Telemetry>,
+    pub changes: ConfigChanges,
+}
+
+#[derive(Debug, Default, serde::Serialize)]
+pub struct ConfigChanges {
+    pub changed: Vec<String>,
+    pub restart_required: Vec<String>,
 }
 
 impl Server {
@@ -64,6 +84,7 @@ impl Server {
             config,
             new_core: core.into(),
             tracers: None,
+            changes: ConfigChanges::default(),
         })
     }
 
@@ -76,6 +97,7 @@ impl Server {
             blob_stores: self.core.storage.blobs.clone(),
             fts_stores: self.core.storage.ftss.clone(),
             lookup_stores: self.core.storage.lookups.clone(),
+            pubsub_stores: self.core.storage.pubsubs.clone(),
             purge_schedules: Default::default(),
         };
         stores.parse_stores(&mut config).await;
@@ -100,6 +122,7 @@ impl Server {
                 .and_then(|id| stores.stores.get(id))
                 .cloned()
                 .unwrap_or_default(),
+            cfg_snapshot: Default::default(),
         };
 
         // Parse settings and build shared core
@@ -129,6 +152,10 @@ impl Server {
 
         Ok(if config.errors.is_empty() {
             ReloadResult {
+                changes: ConfigChanges::new(
+                    &self.core.storage.config.cfg_snapshot,
+                    &core.storage.config.cfg_snapshot,
+                ),
                 config,
                 new_core: core.into(),
                 tracers: tracers.into(),
@@ -145,6 +172,52 @@ impl From<Config> for ReloadResult {
             config,
             new_core: None,
             tracers: None,
+            changes: ConfigChanges::default(),
+        }
+    }
+}
+
+impl ConfigChanges {
+    pub fn new(old: &BTreeMap<String, String>, new: &BTreeMap<String, String>) -> Self {
+        let mut changes = ConfigChanges::default();
+        let mut old_keys = old.iter().peekable();
+        let mut new_keys = new.iter().peekable();
+
+        loop {
+            let key = match (old_keys.peek(), new_keys.peek()) {
+                (Some((old_key, old_value)), Some((new_key, new_value))) => {
+                    match old_key.cmp(new_key) {
+                        std::cmp::Ordering::Less => old_keys.next().unwrap().0,
+                        std::cmp::Ordering::Greater => new_keys.next().unwrap().0,
+                        std::cmp::Ordering::Equal => {
+                            let is_changed = old_value != new_value;
+                            old_keys.next();
+                            let key = new_keys.next().unwrap().0;
+                            if !is_changed {
+                                continue;
+                            }
+                            key
+                        }
+                    }
+                }
+                (Some(_), None) => old_keys.next().unwrap().0,
+                (None, Some(_)) => new_keys.next().unwrap().0,
+                (None, None) => break,
+            };
+
+            if RESTART_REQUIRED
+                .iter()
+                .any(|prefix| key.starts_with(prefix))
+            {
+                changes.restart_required.push(key.clone());
+            }
+            changes.changed.push(key.clone());
         }
+
+        changes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty()
     }
 }
@@ -7,18 +7,24 @@
 use std::{
     io::ErrorKind,
     path::{Path, PathBuf},
+    sync::Arc,
 };
 
 use crate::Core;
+use ahash::AHashSet;
 use jmap_proto::types::{collection::Collection, property::Property};
 use store::{
     roaring::RoaringBitmap,
     write::{
-        key::DeserializeBigEndian, BatchBuilder, BitmapClass, BitmapHash, BlobOp, DirectoryClass,
-        FtsQueueClass, LookupClass, MaybeDynamicId, MaybeDynamicValue, Operation, TagValue,
-        ValueClass,
+        key::DeserializeBigEndian, AnyClass, AnyKey, BatchBuilder, BitmapClass, BitmapHash, BlobOp,
+        DirectoryClass, FtsQueueClass, LookupClass, MaybeDynamicId, MaybeDynamicValue, Operation,
+        TagValue, ValueClass,
     },
-    BlobStore, Serialize, Store, U32_LEN,
+    BlobStore, IterateParams, Serialize, Store, SUBSPACE_ACL, SUBSPACE_BITMAP_ID,
+    SUBSPACE_BITMAP_TAG, SUBSPACE_BITMAP_TEXT, SUBSPACE_BLOB_LINK, SUBSPACE_COUNTER,
+    SUBSPACE_DIRECTORY, SUBSPACE_FTS_INDEX, SUBSPACE_INDEXES, SUBSPACE_LOGS, SUBSPACE_LOOKUP_VALUE,
+    SUBSPACE_PROPERTY, SUBSPACE_QUEUE_EVENT, SUBSPACE_QUEUE_MESSAGE, SUBSPACE_QUOTA,
+    SUBSPACE_SETTINGS, U32_LEN,
 };
 use store::{
     write::{QueueClass, QueueEvent},
@@ -28,14 +34,32 @@ use tokio::{
     fs::File,
     io::{AsyncReadExt, BufReader},
 };
-use utils::{failed, BlobHash, UnwrapFailure};
+use utils::{failed, BlobHash, UnwrapFailure, BLOB_HASH_LEN};
 
-use super::backup::{DeserializeBytes, Family, Op, FILE_VERSION, MAGIC_MARKER};
+use super::backup::{
+    is_mailbox_counter, read_blob_index, BackupManifest, DeserializeBytes, Family, Op,
+    BLOB_INDEX_FILE, FILE_VERSION, MAGIC_MARKER, MANIFEST_FILE,
+};
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct RestoreParams {
+    src: PathBuf,
+    account_id: Option<u32>,
+}
+
+#[derive(Debug, Default)]
+struct RestoreFilter {
+    account_id: Option<u32>,
+    blobs: AHashSet<Vec<u8>>,
+}
 
 impl Core {
-    pub async fn restore(&self, src: PathBuf) {
-        // Backup the core
-        if src.is_dir() {
+    pub async fn restore(&self, params: RestoreParams) {
+        let src = params.src;
+
+        if src.join(MANIFEST_FILE).exists() {
+            self.restore_chain(&src, params.account_id).await;
+        } else if src.is_dir() {
             // Iterate directory and spawn a task for each file
             let mut tasks = Vec::new();
             for entry in std::fs::read_dir(&src).failed("Failed to read directory") {
@@ -45,7 +69,7 @@ impl Core {
                     let storage = self.storage.clone();
                     let blob_store = self.storage.blob.clone();
                     tasks.push(tokio::spawn(async move {
-                        restore_file(storage.data, blob_store, &path).await;
+                        restore_file(storage.data, blob_store, &path, Default::default()).await;
                     }));
                 }
             }
@@ -54,12 +78,116 @@ impl Core {
                 task.await.failed("Failed to wait for task");
             }
         } else {
-            restore_file(self.storage.data.clone(), self.storage.blob.clone(), &src).await;
+            restore_file(
+                self.storage.data.clone(),
+                self.storage.blob.clone(),
+                &src,
+                Default::default(),
+            )
+            .await;
+        }
+    }
+
+    async fn restore_chain(&self, src: &Path, account_id: Option<u32>) {
+        let store = &self.storage.data;
+        let chain = BackupManifest::chain(src).failed("Failed to read backup chain");
+
+        // Verify all sections before writing anything
+        for (path, manifest) in &chain {
+            manifest
+                .verify(path)
+                .failed(&format!("Backup {path:?} is corrupted"));
+        }
+
+        let filter = Arc::new(RestoreFilter {
+            account_id,
+            blobs: match account_id {
+                Some(account_id) => linked_blobs(&chain, account_id).await,
+                None => AHashSet::new(),
+            },
+        });
+
+        for (idx, (path, manifest)) in chain.iter().enumerate() {
+            println!("Restoring backup {}.", path.to_str().unwrap());
+
+            // Incremental backups replace the data of the accounts they captured or that were
+            // deleted since their base, the rest of the store is left untouched
+            let mut clear_accounts = AHashSet::new();
+            if let Some((base_path, base)) = idx.checked_sub(1).map(|idx| &chain[idx]) {
+                clear_accounts.extend(manifest.accounts.iter().flatten().copied());
+                clear_accounts.extend(
+                    base.change_ids
+                        .keys()
+                        .filter(|account_id| !manifest.change_ids.contains_key(*account_id)),
+                );
+
+                if account_id.is_none() {
+                    clear_global(store).await;
+
+                    let blobs = read_blob_index(path);
+                    let mut batch = BatchBuilder::new();
+                    for hash in read_blob_index(base_path) {
+                        if !blobs.contains(&hash) {
+                            self.storage
+                                .blob
+                                .delete_blob(&hash)
+                                .await
+                                .failed("Failed to delete blob");
+                            batch.clear(ValueClass::Blob(BlobOp::Commit {
+                                hash: BlobHash::try_from_hash_slice(&hash)
+                                    .expect("Invalid blob hash"),
+                            }));
+                            if batch.ops.len() >= 1000 {
+                                store
+                                    .write(std::mem::take(&mut batch).build())
+                                    .await
+                                    .failed("Failed to write batch");
+                            }
+                        }
+                    }
+                    if !batch.is_empty() {
+                        store
+                            .write(batch.build())
+                            .await
+                            .failed("Failed to write batch");
+                    }
+                }
+            } else if let Some(account_id) = account_id {
+                clear_accounts.insert(account_id);
+            }
+
+            for clear_account_id in clear_accounts {
+                if account_id.map_or(true, |account_id| account_id == clear_account_id) {
+                    clear_account(store, clear_account_id).await;
+                }
+            }
+
+            let mut tasks = Vec::new();
+            for section in &manifest.sections {
+                if section.name != BLOB_INDEX_FILE {
+                    let store = store.clone();
+                    let blob_store = self.storage.blob.clone();
+                    let path = path.join(&section.name);
+                    let filter = filter.clone();
+                    tasks.push(tokio::spawn(async move {
+                        restore_file(store, blob_store, &path, filter).await;
+                    }));
+                }
+            }
+
+            for task in tasks {
+                task.await.failed("Failed to wait for task");
+            }
         }
     }
 }
 
-async fn restore_file(store: Store, blob_store: BlobStore, path: &Path) {
+async fn restore_file(
+    store: Store,
+    blob_store: BlobStore,
+    path: &Path,
+    filter: Arc<RestoreFilter>,
+) {
     println!("Importing database dump from {}.", path.to_str().unwrap());
 
     let mut reader = OpReader::new(path).await;
@@ -89,6 +217,10 @@ async fn restore_file(store: Store, blob_store: BlobStore, path: &Path) {
                 batch.update_document(document_id);
             }
             Op::KeyValue((key, value)) => {
+                if !filter.includes(family, account_id, &key) {
+                    continue;
+                }
+
                 batch_size += key.len() + value.len() + U32_LEN * 2;
 
                 match family {
@@ -350,9 +482,19 @@ async fn restore_file(store: Store, blob_store: BlobStore, path: &Path) {
                                 .deserialize_be_u64(0)
                                 .expect("Failed to deserialize change id"),
                         });
-                        batch.ops.push(Operation::Log {
-                            set: MaybeDynamicValue::Static(value),
-                        });
+                        if key.len() == U64_LEN * 2 {
+                            batch.ops.push(Operation::LogWithTimestamp {
+                                timestamp: key
+                                    .as_slice()
+                                    .deserialize_be_u64(U64_LEN)
+                                    .expect("Failed to deserialize timestamp"),
+                                set: MaybeDynamicValue::Static(value),
+                            });
+                        } else {
+                            batch.ops.push(Operation::Log {
+                                set: MaybeDynamicValue::Static(value),
+                            });
+                        }
                     }
                     Family::None => failed("No family specified in file"),
                 }
@@ -381,6 +523,188 @@ async fn restore_file(store: Store, blob_store: BlobStore, path: &Path) {
     }
 }
 
+impl RestoreFilter {
+    // Single account restores skip global data and the data of other accounts
+    fn includes(&self, family: Family, account_id: u32, key: &[u8]) -> bool {
+        match self.account_id {
+            None => true,
+            Some(_) if family == Family::Blob && account_id == u32::MAX => self.blobs.contains(key),
+            Some(filter_account_id) => {
+                matches!(
+                    family,
+                    Family::Property
+                        | Family::FtsIndex
+                        | Family::Acl
+                        | Family::Blob
+                        | Family::Index
+                        | Family::Bitmap
+                        | Family::Log
+                ) && account_id == filter_account_id
+            }
+        }
+    }
+}
+
+impl RestoreParams {
+    pub fn new(src: PathBuf) -> Self {
+        let account_id = std::env::var("IMPORT_ACCOUNT").ok().map(|account_id| {
+            account_id.parse().unwrap_or_else(|_| {
+                eprintln!("Restore failed: invalid account id {account_id:?}.");
+                std::process::exit(1);
+            })
+        });
+
+        Self { src, account_id }
+    }
+
+    pub fn with_account_id(mut self, account_id: u32) -> Self {
+        self.account_id = Some(account_id);
+        self
+    }
+}
+
+// Blobs linked by an account anywhere in the chain
+async fn linked_blobs(chain: &[(PathBuf, BackupManifest)], account_id: u32) -> AHashSet<Vec<u8>> {
+    let mut blobs = AHashSet::new();
+
+    for (path, manifest) in chain {
+        for section in &manifest.sections {
+            if section.name != "blob" {
+                continue;
+            }
+
+            let mut reader = OpReader::new(&path.join(&section.name)).await;
+            let mut current_account_id = u32::MAX;
+            while let Some(op) = reader.next().await {
+                match op {
+                    Op::AccountId(id) => current_account_id = id,
+                    Op::KeyValue((key, _)) if current_account_id == account_id => {
+                        blobs.insert(key);
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    blobs
+}
+
+async fn clear_account(store: &Store, account_id: u32) {
+    let from_key = account_id.to_be_bytes().to_vec();
+    let mut to_key = from_key.clone();
+    to_key.extend_from_slice(&[u8::MAX; 32]);
+
+    for subspace in [
+        SUBSPACE_BITMAP_ID,
+        SUBSPACE_BITMAP_TAG,
+        SUBSPACE_BITMAP_TEXT,
+        SUBSPACE_LOGS,
+        SUBSPACE_INDEXES,
+        SUBSPACE_PROPERTY,
+        SUBSPACE_FTS_INDEX,
+    ] {
+        store
+            .delete_range(
+                AnyKey {
+                    subspace,
+                    key: from_key.clone(),
+                },
+                AnyKey {
+                    subspace,
+                    key: to_key.clone(),
+                },
+            )
+            .await
+            .failed("Failed to delete account data");
+    }
+
+    clear_keys(store, SUBSPACE_COUNTER, |key| {
+        key.starts_with(&from_key) && is_mailbox_counter(key)
+    })
+    .await;
+    clear_keys(store, SUBSPACE_ACL, |key| {
+        key.get(U32_LEN..U32_LEN * 2) == Some(&from_key[..])
+    })
+    .await;
+    if account_id != u32::MAX {
+        clear_keys(store, SUBSPACE_BLOB_LINK, |key| {
+            key.get(BLOB_HASH_LEN..BLOB_HASH_LEN + U32_LEN) == Some(&from_key[..])
+        })
+        .await;
+    }
+}
+
+async fn clear_global(store: &Store) {
+    for (subspace, from_key, to_key) in [
+        (SUBSPACE_SETTINGS, vec![0u8], vec![u8::MAX; 32]),
+        (SUBSPACE_LOOKUP_VALUE, vec![0u8], vec![u8::MAX; 32]),
+        (SUBSPACE_DIRECTORY, vec![0u8], vec![u8::MAX; 32]),
+        (SUBSPACE_QUEUE_MESSAGE, vec![0u8], vec![u8::MAX; 32]),
+        (SUBSPACE_QUEUE_EVENT, vec![0u8], vec![u8::MAX; 32]),
+        // Directory quotas, queue quotas are not part of backups
+        (SUBSPACE_QUOTA, vec![4u8], vec![5u8]),
+        (SUBSPACE_QUOTA, vec![7u8], vec![8u8]),
+    ] {
+        store
+            .delete_range(
+                AnyKey {
+                    subspace,
+                    key: from_key,
+                },
+                AnyKey {
+                    subspace,
+                    key: to_key,
+                },
+            )
+            .await
+            .failed("Failed to delete global data");
+    }
+
+    clear_keys(store, SUBSPACE_COUNTER, |key| !is_mailbox_counter(key)).await;
+}
+
+async fn clear_keys(store: &Store, subspace: u8, filter: impl Fn(&[u8]) -> bool) {
+    let mut keys = Vec::new();
+    store
+        .iterate(
+            IterateParams::new(
+                AnyKey {
+                    subspace,
+                    key: vec![0u8],
+                },
+                AnyKey {
+                    subspace,
+                    key: vec![u8::MAX; 32],
+                },
+            )
+            .no_values(),
+            |key, _| {
+                if filter(key) {
+                    keys.push(key.to_vec());
+                }
+
+                Ok(true)
+            },
+        )
+        .await
+        .failed("Failed to iterate over data store");
+
+    for keys in keys.chunks(1000) {
+        let mut batch = BatchBuilder::new();
+        for key in keys {
+            batch.clear(ValueClass::Any(AnyClass {
+                subspace,
+                key: key.clone(),
+            }));
+        }
+        store
+            .write(batch.build())
+            .await
+            .failed("Failed to write batch");
+    }
+}
+
 struct OpReader {
     version: u8,
     file: BufReader<File>,
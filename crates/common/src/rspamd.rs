@@ -0,0 +1,263 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{net::IpAddr, time::Instant};
+
+use ahash::AHashMap;
+use hyper::{header::HeaderValue, HeaderMap};
+use serde::{Deserialize, Serialize};
+
+use crate::{config::spamfilter::RspamdConfig, HttpLimitResponse, Server};
+
+// MTA metadata sent along with the message, see the Rspamd protocol documentation
+#[derive(Debug, Default)]
+pub struct RspamdRequest<'x> {
+    pub ip: Option<IpAddr>,
+    pub helo: Option<&'x str>,
+    pub hostname: Option<&'x str>,
+    pub from: Option<&'x str>,
+    pub rcpt: Vec<&'x str>,
+    pub user: Option<&'x str>,
+    pub queue_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RspamdResponse {
+    pub action: RspamdAction,
+    #[serde(default)]
+    pub score: f64,
+    #[serde(default)]
+    pub required_score: f64,
+    #[serde(default)]
+    pub symbols: AHashMap<String, RspamdSymbol>,
+    #[serde(default)]
+    pub subject: Option<String>,
+    #[serde(default)]
+    pub messages: RspamdMessages,
+    #[serde(default)]
+    pub milter: RspamdMilter,
+    #[serde(default)]
+    #[serde(rename = "dkim-signature")]
+    pub dkim_signature: Option<RspamdValues>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RspamdAction {
+    #[serde(rename = "no action")]
+    NoAction,
+    #[serde(rename = "greylist")]
+    Greylist,
+    #[serde(rename = "add header")]
+    AddHeader,
+    #[serde(rename = "rewrite subject")]
+    RewriteSubject,
+    #[serde(rename = "soft reject")]
+    SoftReject,
+    #[serde(rename = "reject")]
+    Reject,
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RspamdSymbol {
+    #[serde(default)]
+    pub score: f64,
+    #[serde(default)]
+    pub options: Vec<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RspamdMessages {
+    #[serde(default)]
+    pub smtp_message: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RspamdMilter {
+    #[serde(default)]
+    pub add_headers: AHashMap<String, RspamdValues>,
+    #[serde(default)]
+    pub remove_headers: AHashMap<String, u32>,
+}
+
+// Rspamd encodes header values either as a string, an object or a list of them
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum RspamdValues {
+    One(RspamdValue),
+    Many(Vec<RspamdValue>),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum RspamdValue {
+    Text(String),
+    Header { value: String },
+}
+
+impl RspamdConfig {
+    pub async fn check(
+        &self,
+        request: RspamdRequest<'_>,
+        message: &[u8],
+    ) -> Result<RspamdResponse, String> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in [
+            ("IP", request.ip.map(|ip| ip.to_string())),
+            ("Helo", request.helo.map(|helo| helo.to_string())),
+            ("Hostname", request.hostname.map(|host| host.to_string())),
+            ("From", request.from.map(|from| from.to_string())),
+            ("User", request.user.map(|user| user.to_string())),
+            ("Queue-Id", request.queue_id),
+        ]
+        .into_iter()
+        .chain(
+            request
+                .rcpt
+                .iter()
+                .map(|rcpt| ("Rcpt", Some(rcpt.to_string()))),
+        ) {
+            if let Some(value) = value
+                .filter(|value| !value.is_empty())
+                .and_then(|value| HeaderValue::from_str(&value).ok())
+            {
+                headers.append(name, value);
+            }
+        }
+
+        let response = self
+            .client
+            .post(format!("{}/checkv2", self.url))
+            .headers(headers)
+            .body(message.to_vec())
+            .send()
+            .await
+            .map_err(|err| format!("Rspamd request failed: {err}"))?;
+
+        if response.status().is_success() {
+            let bytes = response
+                .bytes_with_limit(self.max_response_size)
+                .await
+                .map_err(|err| format!("Failed to read Rspamd response: {err}"))?
+                .ok_or_else(|| "Rspamd response too large".to_string())?;
+            serde_json::from_slice(&bytes)
+                .map_err(|err| format!("Failed to parse Rspamd response: {err}"))
+        } else {
+            Err(format!(
+                "Rspamd request failed with code {}: {}",
+                response.status().as_u16(),
+                response.status().canonical_reason().unwrap_or("Unknown")
+            ))
+        }
+    }
+
+    pub async fn learn(&self, message: &[u8], is_spam: bool) -> Result<(), String> {
+        let mut request = self
+            .client
+            .post(format!(
+                "{}/{}",
+                self.controller_url,
+                if is_spam { "learnspam" } else { "learnham" }
+            ))
+            .body(message.to_vec());
+        if let Some(password) = &self.password {
+            request = request.header("Password", password);
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|err| format!("Rspamd request failed: {err}"))?;
+
+        // Rspamd replies 208 when the message was already learned
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(format!(
+                "Rspamd learn request failed with code {}: {}",
+                response.status().as_u16(),
+                response.status().canonical_reason().unwrap_or("Unknown")
+            ))
+        }
+    }
+}
+
+impl Server {
+    // Forwards a training request to the Rspamd controller, returns false
+    // when Rspamd is not configured or training is disabled
+    pub async fn rspamd_learn(
+        &self,
+        message: &[u8],
+        is_spam: bool,
+        session_id: u64,
+    ) -> trc::Result<bool> {
+        let Some(rspamd) = self.core.spam.rspamd.as_ref().filter(|rspamd| rspamd.train) else {
+            return Ok(false);
+        };
+
+        let time = Instant::now();
+        rspamd.learn(message, is_spam).await.map_err(|err| {
+            trc::SpamEvent::RspamdError
+                .into_err()
+                .span_id(session_id)
+                .reason(err)
+                .details(if is_spam { "learnspam" } else { "learnham" })
+        })?;
+
+        trc::event!(
+            Spam(trc::SpamEvent::Train),
+            SpanId = session_id,
+            Details = is_spam,
+            Id = "rspamd",
+            Elapsed = time.elapsed(),
+        );
+
+        Ok(true)
+    }
+}
+
+impl RspamdValues {
+    pub fn into_values(self) -> Vec<String> {
+        match self {
+            RspamdValues::One(value) => vec![value.into_value()],
+            RspamdValues::Many(values) => values.into_iter().map(|v| v.into_value()).collect(),
+        }
+    }
+}
+
+impl RspamdValue {
+    pub fn into_value(self) -> String {
+        match self {
+            RspamdValue::Text(value) => value,
+            RspamdValue::Header { value } => value,
+        }
+    }
+}
+
+impl RspamdAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RspamdAction::NoAction => "no action",
+            RspamdAction::Greylist => "greylist",
+            RspamdAction::AddHeader => "add header",
+            RspamdAction::RewriteSubject => "rewrite subject",
+            RspamdAction::SoftReject => "soft reject",
+            RspamdAction::Reject => "reject",
+            RspamdAction::Unknown => "unknown",
+        }
+    }
+
+    pub fn is_spam(&self) -> bool {
+        matches!(
+            self,
+            RspamdAction::AddHeader
+                | RspamdAction::RewriteSubject
+                | RspamdAction::SoftReject
+                | RspamdAction::Reject
+        )
+    }
+}
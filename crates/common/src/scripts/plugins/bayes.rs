@@ -5,15 +5,10 @@
  */
 
 use nlp::{
-    bayes::{
-        cache::BayesTokenCache, tokenize::BayesTokenizer, BayesClassifier, BayesModel, TokenHash,
-        Weights,
-    },
+    bayes::{tokenize::BayesTokenizer, BayesClassifier, TokenHash},
     tokenizers::osb::{OsbToken, OsbTokenizer},
 };
 use sieve::{runtime::Variable, FunctionMap};
-use store::{write::key::KeySerializer, LookupStore, U64_LEN};
-use trc::AddContext;
 
 use super::PluginContext;
 
@@ -54,76 +49,18 @@ async fn train(ctx: PluginContext<'_>, is_train: bool) -> trc::Result<Variable>
 
     let text = ctx.arguments[1].to_string();
     let is_spam = ctx.arguments[2].to_bool();
-    if text.is_empty() {
-        trc::bail!(trc::SpamEvent::TrainError
-            .into_err()
-            .reason("Empty message"));
-    }
-
-    // Train the model
-    let mut model = BayesModel::default();
-    model.train(
-        OsbTokenizer::new(BayesTokenizer::new(text.as_ref()), 5),
-        is_spam,
-    );
-    if model.weights.is_empty() {
-        trc::bail!(trc::SpamEvent::TrainError
-            .into_err()
-            .reason("No weights found"));
-    }
-
-    trc::event!(
-        Spam(trc::SpamEvent::Train),
-        SpanId = ctx.session_id,
-        Details = is_spam,
-        Total = model.weights.len(),
-    );
 
-    // Update weight and invalidate cache
-    let bayes_cache = &ctx.server.inner.data.bayes_cache;
+    // Update weights of the global model
     if is_train {
-        for (hash, weights) in model.weights {
-            store
-                .counter_incr(
-                    KeySerializer::new(U64_LEN)
-                        .write(hash.h1)
-                        .write(hash.h2)
-                        .finalize(),
-                    weights.into(),
-                    None,
-                    false,
-                )
-                .await
-                .caused_by(trc::location!())?;
-
-            bayes_cache.invalidate(&hash);
-        }
-
-        // Update training counts
-        let weights = if is_spam {
-            Weights { spam: 1, ham: 0 }
-        } else {
-            Weights { spam: 0, ham: 1 }
-        };
-        store
-            .counter_incr(
-                KeySerializer::new(U64_LEN)
-                    .write(0u64)
-                    .write(0u64)
-                    .finalize(),
-                weights.into(),
-                None,
-                false,
-            )
-            .await
-            .caused_by(trc::location!())?;
+        ctx.server
+            .bayes_train(store, None, text.as_ref(), is_spam, ctx.session_id)
+            .await?;
     } else {
-        //TODO: Implement untrain
-        return Ok(false.into());
+        ctx.server
+            .bayes_untrain(store, None, text.as_ref(), is_spam, ctx.session_id)
+            .await?;
     }
 
-    bayes_cache.invalidate(&TokenHash::default());
-
     Ok(true.into())
 }
 
@@ -162,9 +99,9 @@ pub async fn exec_classify(ctx: PluginContext<'_>) -> trc::Result<Variable> {
     }
 
     // Obtain training counts
-    let bayes_cache = &ctx.server.inner.data.bayes_cache;
-    let (spam_learns, ham_learns) = bayes_cache
-        .get_or_update(TokenHash::default(), store)
+    let (spam_learns, ham_learns) = ctx
+        .server
+        .bayes_weights(store, None, TokenHash::default())
         .await
         .map(|w| (w.spam, w.ham))?;
 
@@ -185,7 +122,7 @@ pub async fn exec_classify(ctx: PluginContext<'_>) -> trc::Result<Variable> {
     // Classify the text
     let mut tokens = Vec::new();
     for token in OsbTokenizer::<_, TokenHash>::new(BayesTokenizer::new(text.as_ref()), 5) {
-        let weights = bayes_cache.get_or_update(token.inner, store).await?;
+        let weights = ctx.server.bayes_weights(store, None, token.inner).await?;
         tokens.push(OsbToken {
             inner: weights,
             idx: token.idx,
@@ -231,9 +168,9 @@ pub async fn exec_is_balanced(ctx: PluginContext<'_>) -> trc::Result<Variable> {
     let learn_spam = ctx.arguments[1].to_bool();
 
     // Obtain training counts
-    let bayes_cache = &ctx.server.inner.data.bayes_cache;
-    let (spam_learns, ham_learns) = bayes_cache
-        .get_or_update(TokenHash::default(), store)
+    let (spam_learns, ham_learns) = ctx
+        .server
+        .bayes_weights(store, None, TokenHash::default())
         .await
         .map(|w| (w.spam as f64, w.ham as f64))?;
 
@@ -261,38 +198,3 @@ pub async fn exec_is_balanced(ctx: PluginContext<'_>) -> trc::Result<Variable> {
 
     Ok(result.into())
 }
-
-trait LookupOrInsert {
-    async fn get_or_update(&self, hash: TokenHash, get_token: &LookupStore)
-        -> trc::Result<Weights>;
-}
-
-impl LookupOrInsert for BayesTokenCache {
-    async fn get_or_update(
-        &self,
-        hash: TokenHash,
-        get_token: &LookupStore,
-    ) -> trc::Result<Weights> {
-        if let Some(weights) = self.get(&hash) {
-            Ok(weights.unwrap_or_default())
-        } else {
-            let num = get_token
-                .counter_get(
-                    KeySerializer::new(U64_LEN)
-                        .write(hash.h1)
-                        .write(hash.h2)
-                        .finalize(),
-                )
-                .await
-                .caused_by(trc::location!())?;
-            Ok(if num != 0 {
-                let weights = Weights::from(num);
-                self.insert_positive(hash, weights);
-                weights
-            } else {
-                self.insert_negative(hash);
-                Weights::default()
-            })
-        }
-    }
-}
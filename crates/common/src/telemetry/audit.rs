@@ -0,0 +1,249 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::net::IpAddr;
+
+use serde::{Deserialize, Serialize};
+use store::{
+    write::{now, BatchBuilder, TelemetryClass, ValueClass},
+    IterateParams, Store, ValueKey,
+};
+use tokio::io::AsyncWriteExt;
+use trc::AddContext;
+
+use crate::Server;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "camelCase")]
+pub enum AuditEvent {
+    Login {
+        mechanism: String,
+    },
+    SecretChange {
+        account: String,
+    },
+    AclChange {
+        grantee: String,
+        resource: String,
+        rights: Vec<String>,
+    },
+    AdminRequest {
+        method: String,
+        path: String,
+    },
+    SieveScriptChange {
+        account: String,
+        script: String,
+        action: String,
+    },
+    MessageDeletion {
+        source: String,
+        id: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status")]
+#[serde(rename_all = "camelCase")]
+pub enum AuditResult {
+    Success,
+    Failure { reason: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditRecord {
+    pub id: u64,
+    pub timestamp: u64,
+    pub actor: String,
+    pub remote_ip: Option<IpAddr>,
+    pub event: AuditEvent,
+    pub result: AuditResult,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AuditFilter {
+    pub from: u64,
+    pub to: u64,
+    pub actor: Option<String>,
+    pub limit: usize,
+}
+
+impl AuditRecord {
+    pub fn new(actor: impl Into<String>, event: AuditEvent) -> Self {
+        AuditRecord {
+            id: 0,
+            timestamp: 0,
+            actor: actor.into(),
+            remote_ip: None,
+            event,
+            result: AuditResult::Success,
+        }
+    }
+
+    pub fn with_remote_ip(mut self, remote_ip: impl Into<Option<IpAddr>>) -> Self {
+        self.remote_ip = remote_ip.into();
+        self
+    }
+
+    pub fn with_result<T>(mut self, result: &trc::Result<T>) -> Self {
+        if let Err(err) = result {
+            self.result = AuditResult::Failure {
+                reason: err.inner.description().to_string(),
+            };
+        }
+        self
+    }
+
+    pub fn with_failure(mut self, reason: impl Into<String>) -> Self {
+        self.result = AuditResult::Failure {
+            reason: reason.into(),
+        };
+        self
+    }
+
+    pub fn is_success(&self) -> bool {
+        matches!(self.result, AuditResult::Success)
+    }
+}
+
+impl AuditFilter {
+    pub fn matches(&self, record: &AuditRecord) -> bool {
+        self.actor
+            .as_ref()
+            .map_or(true, |actor| record.actor.eq_ignore_ascii_case(actor))
+    }
+}
+
+impl Server {
+    fn audit_store(&self) -> &Store {
+        self.core.audit.store.as_ref().unwrap_or(self.store())
+    }
+
+    // Audit records are written even when the audited operation failed, errors
+    // writing the record itself are logged but never returned to the caller.
+    pub async fn audit(&self, mut record: AuditRecord) {
+        let config = &self.core.audit;
+        if !config.enable {
+            return;
+        }
+        record.timestamp = now();
+        record.id = self
+            .inner
+            .data
+            .queue_id_gen
+            .generate()
+            .unwrap_or(record.timestamp);
+        let json = serde_json::to_string(&record).unwrap_or_default();
+
+        let mut batch = BatchBuilder::new();
+        batch.set(
+            ValueClass::Telemetry(TelemetryClass::Audit {
+                timestamp: record.timestamp,
+                id: record.id,
+            }),
+            json.as_bytes().to_vec(),
+        );
+        if let Err(err) = self.audit_store().write(batch.build()).await {
+            trc::error!(err
+                .caused_by(trc::location!())
+                .details("Failed to write audit record"));
+        }
+
+        if let Some(path) = &config.file {
+            let result = async {
+                let mut file = tokio::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .await?;
+                file.write_all(format!("{json}\n").as_bytes()).await
+            }
+            .await;
+            if let Err(err) = result {
+                trc::event!(
+                    Telemetry(trc::TelemetryEvent::LogError),
+                    Details = "Failed to write audit record to file",
+                    Path = path.clone(),
+                    Reason = err.to_string(),
+                );
+            }
+        }
+
+        #[cfg(unix)]
+        if config.syslog {
+            // RFC 3164 message using the authpriv facility and info severity
+            let message = format!("<86>stalwart-audit: {json}");
+            if let Err(err) = std::os::unix::net::UnixDatagram::unbound()
+                .and_then(|socket| socket.send_to(message.as_bytes(), "/dev/log"))
+            {
+                trc::event!(
+                    Telemetry(trc::TelemetryEvent::LogError),
+                    Details = "Failed to send audit record to syslog",
+                    Reason = err.to_string(),
+                );
+            }
+        }
+    }
+
+    pub async fn query_audit_log(&self, filter: &AuditFilter) -> trc::Result<Vec<AuditRecord>> {
+        let from_key = ValueKey::from(ValueClass::Telemetry(TelemetryClass::Audit {
+            timestamp: filter.from,
+            id: 0,
+        }));
+        let to_key = ValueKey::from(ValueClass::Telemetry(TelemetryClass::Audit {
+            timestamp: filter.to,
+            id: u64::MAX,
+        }));
+        let limit = if filter.limit > 0 {
+            filter.limit
+        } else {
+            usize::MAX
+        };
+        let mut records = Vec::new();
+
+        self.audit_store()
+            .iterate(
+                IterateParams::new(from_key, to_key).ascending(),
+                |_, value| {
+                    let record = serde_json::from_slice::<AuditRecord>(value).map_err(|err| {
+                        trc::StoreEvent::DataCorruption
+                            .caused_by(trc::location!())
+                            .reason(err)
+                    })?;
+                    if filter.matches(&record) {
+                        records.push(record);
+                    }
+                    Ok(records.len() < limit)
+                },
+            )
+            .await
+            .caused_by(trc::location!())?;
+
+        Ok(records)
+    }
+
+    pub async fn purge_audit_log(&self) -> trc::Result<()> {
+        if let Some(retention) = self.core.audit.retention {
+            self.audit_store()
+                .delete_range(
+                    ValueKey::from(ValueClass::Telemetry(TelemetryClass::Audit {
+                        timestamp: 0,
+                        id: 0,
+                    })),
+                    ValueKey::from(ValueClass::Telemetry(TelemetryClass::Audit {
+                        timestamp: now().saturating_sub(retention.as_secs()),
+                        id: 0,
+                    })),
+                )
+                .await
+                .caused_by(trc::location!())?;
+        }
+
+        Ok(())
+    }
+}
@@ -5,13 +5,26 @@
  */
 
 use prometheus::{
-    proto::{Bucket, Counter, Gauge, Histogram, Metric, MetricFamily, MetricType},
+    proto::{Bucket, Counter, Gauge, Histogram, LabelPair, Metric, MetricFamily, MetricType},
     TextEncoder,
 };
-use trc::{atomics::histogram::AtomicHistogram, Collector};
+use store::{
+    write::{now, QueueClass, ValueClass},
+    Deserialize, IterateParams, ValueKey,
+};
+use trc::{atomics::histogram::AtomicHistogram, AddContext, Collector};
+use utils::snowflake::SnowflakeIdGenerator;
 
 use crate::Server;
 
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct QueueStatus {
+    pub scheduled: u64,
+    pub due: u64,
+    pub in_flight: u64,
+    pub oldest_age: u64,
+}
+
 impl Server {
     pub async fn export_prometheus_metrics(&self) -> trc::Result<String> {
         let mut metrics = Vec::new();
@@ -52,10 +65,135 @@ impl Server {
             metrics.push(metric);
         }
 
+        // Add labeled counters
+        for counter in Collector::collect_labeled_counters() {
+            let values = counter.values();
+            if values.is_empty() {
+                continue;
+            }
+            let mut metric = MetricFamily::default();
+            metric.set_name(counter.name().to_string());
+            metric.set_help(counter.description().into());
+            metric.set_field_type(MetricType::COUNTER);
+            metric.set_metric(
+                values
+                    .into_iter()
+                    .map(|(label, value)| {
+                        let mut m = new_counter(value);
+                        m.set_label(vec![new_label(counter.label(), label)]);
+                        m
+                    })
+                    .collect(),
+            );
+            metrics.push(metric);
+        }
+
+        // Add labeled histograms
+        for histogram in Collector::collect_labeled_histograms() {
+            let mut values = Vec::new();
+            histogram.for_each(|label, h| {
+                let mut m = new_histogram(h);
+                m.set_label(vec![new_label(histogram.label(), label.to_string())]);
+                values.push(m);
+            });
+            if values.is_empty() {
+                continue;
+            }
+            let mut metric = MetricFamily::default();
+            metric.set_name(histogram.name().to_string());
+            metric.set_help(histogram.description().into());
+            metric.set_field_type(MetricType::HISTOGRAM);
+            metric.set_metric(values);
+            metrics.push(metric);
+        }
+
+        // Add queue gauges
+        let status = self.queue_status().await?;
+        let mut metric = MetricFamily::default();
+        metric.set_name("queue_messages".to_string());
+        metric.set_help("Messages in the queue, by status".into());
+        metric.set_field_type(MetricType::GAUGE);
+        metric.set_metric(
+            [
+                ("scheduled", status.scheduled),
+                ("due", status.due),
+                ("in_flight", status.in_flight),
+            ]
+            .into_iter()
+            .map(|(label, value)| {
+                let mut m = new_gauge(value);
+                m.set_label(vec![new_label("status", label.to_string())]);
+                m
+            })
+            .collect(),
+        );
+        metrics.push(metric);
+        let mut metric = MetricFamily::default();
+        metric.set_name("queue_oldest_message_age_seconds".to_string());
+        metric.set_help("Age of the oldest message in the queue".into());
+        metric.set_field_type(MetricType::GAUGE);
+        metric.set_metric(vec![new_gauge(status.oldest_age)]);
+        metrics.push(metric);
+
         TextEncoder::new().encode_to_string(&metrics).map_err(|e| {
             trc::EventType::Telemetry(trc::TelemetryEvent::OtelExporterError).reason(e)
         })
     }
+
+    // Due events are split by whether a delivery task currently holds their lock
+    pub async fn queue_status(&self) -> trc::Result<QueueStatus> {
+        let mut status = QueueStatus::default();
+        let now = now();
+        let from_key = ValueKey::from(ValueClass::Queue(QueueClass::MessageEvent(
+            store::write::QueueEvent {
+                due: 0,
+                queue_id: 0,
+            },
+        )));
+        let to_key = ValueKey::from(ValueClass::Queue(QueueClass::MessageEvent(
+            store::write::QueueEvent {
+                due: u64::MAX,
+                queue_id: u64::MAX,
+            },
+        )));
+        self.store()
+            .iterate(
+                IterateParams::new(from_key, to_key).ascending(),
+                |key, value| {
+                    let due = key.deserialize_be_u64(0)?;
+                    if u64::deserialize(value)? > now {
+                        status.in_flight += 1;
+                    } else if due <= now {
+                        status.due += 1;
+                    } else {
+                        status.scheduled += 1;
+                    }
+                    Ok(true)
+                },
+            )
+            .await
+            .caused_by(trc::location!())?;
+
+        // Queue ids are snowflakes, so the lowest one belongs to the oldest message
+        let from_key = ValueKey::from(ValueClass::Queue(QueueClass::Message(0)));
+        let to_key = ValueKey::from(ValueClass::Queue(QueueClass::Message(u64::MAX)));
+        self.store()
+            .iterate(
+                IterateParams::new(from_key, to_key)
+                    .ascending()
+                    .only_first()
+                    .no_values(),
+                |key, _| {
+                    let created = SnowflakeIdGenerator::to_timestamp(key.deserialize_be_u64(0)?);
+                    status.oldest_age = now.saturating_sub(created);
+                    Ok(false)
+                },
+            )
+            .await
+            .caused_by(trc::location!())?;
+
+        Ok(status)
+    }
 }
 
 fn metric_name(id: impl AsRef<str>) -> String {
@@ -79,6 +217,13 @@ fn new_counter(value: u64) -> Metric {
     m
 }
 
+fn new_label(name: &str, value: String) -> LabelPair {
+    let mut label = LabelPair::default();
+    label.set_name(name.to_string());
+    label.set_value(value);
+    label
+}
+
 fn new_gauge(value: u64) -> Metric {
     let mut m = Metric::default();
     let mut gauge = Gauge::default();
@@ -4,6 +4,7 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+pub mod audit;
 pub mod metrics;
 pub mod tracers;
 pub mod webhooks;
@@ -36,6 +37,7 @@ impl Telemetry {
         Collector::set_interests(self.tracers.interests);
         Collector::update_custom_levels(self.tracers.levels);
         Collector::set_metrics(self.metrics);
+        Collector::set_metric_label_limit(self.metric_label_limit);
         Collector::reload();
     }
 
@@ -71,6 +73,7 @@ impl Telemetry {
         Collector::set_interests(self.tracers.interests);
         Collector::update_custom_levels(self.tracers.levels);
         Collector::set_metrics(self.metrics);
+        Collector::set_metric_label_limit(self.metric_label_limit);
         Collector::reload();
     }
 
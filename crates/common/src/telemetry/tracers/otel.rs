@@ -6,6 +6,7 @@
 
 use std::{
     borrow::Cow,
+    sync::Arc,
     time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
@@ -13,7 +14,7 @@ use ahash::AHashMap;
 use mail_parser::DateTime;
 use opentelemetry::{
     logs::{AnyValue, Severity},
-    trace::{SpanContext, SpanKind, Status, TraceFlags, TraceState},
+    trace::{Link, SpanContext, SpanId, SpanKind, Status, TraceFlags, TraceId, TraceState},
     InstrumentationLibrary, Key, KeyValue, Value,
 };
 use opentelemetry_sdk::{
@@ -22,11 +23,18 @@ use opentelemetry_sdk::{
     Resource,
 };
 use opentelemetry_semantic_conventions::resource::{SERVICE_NAME, SERVICE_VERSION};
-use trc::{ipc::subscriber::SubscriberBuilder, Event, EventDetails, Level, TelemetryEvent};
+use trc::{
+    ipc::subscriber::SubscriberBuilder, DeliveryEvent, Event, EventDetails, EventType, Level,
+    QueueEvent, SmtpEvent, TelemetryEvent,
+};
 
-use crate::{config::telemetry::OtelTracer, telemetry::LONG_SLUMBER};
+use crate::{
+    config::telemetry::{OtelSampler, OtelTracer},
+    telemetry::LONG_SLUMBER,
+};
 
 const MAX_EVENTS: usize = 2048;
+const MAX_QUEUE_LINKS: usize = 8192;
 
 pub(crate) fn spawn_otel_tracer(builder: SubscriberBuilder, mut otel: OtelTracer) {
     let (_, mut rx) = builder.register();
@@ -46,9 +54,7 @@ pub(crate) fn spawn_otel_tracer(builder: SubscriberBuilder, mut otel: OtelTracer
         let mut next_delivery = Instant::now();
 
         let mut pending_logs = Vec::new();
-        let mut pending_spans = Vec::new();
-
-        let mut active_spans = AHashMap::new();
+        let mut spans = SpanAssembler::new(instrumentation.clone(), otel.sampler);
 
         loop {
             // Wait for the next event or timeout
@@ -62,23 +68,7 @@ pub(crate) fn spawn_otel_tracer(builder: SubscriberBuilder, mut otel: OtelTracer
                         }
 
                         if otel.span_exporter_enable {
-                            if let Some(span) = event.inner.span.as_ref() {
-                                let span_id = span.span_id().unwrap();
-                                if !event.inner.typ.is_span_end() {
-                                    let events =
-                                        active_spans.entry(span_id).or_insert_with(Vec::new);
-                                    if events.len() < MAX_EVENTS {
-                                        events.push(event);
-                                    }
-                                } else if let Some(events) = active_spans.remove(&span_id) {
-                                    pending_spans.push(build_span_data(
-                                        span,
-                                        &event,
-                                        events.iter().chain(std::iter::once(&event)),
-                                        &instrumentation,
-                                    ));
-                                }
-                            }
+                            spans.ingest(event);
                         }
                     }
                 }
@@ -92,15 +82,11 @@ pub(crate) fn spawn_otel_tracer(builder: SubscriberBuilder, mut otel: OtelTracer
             let mut next_retry = None;
             let now = Instant::now();
             if next_delivery <= now {
-                if !pending_spans.is_empty() || !pending_logs.is_empty() {
+                if spans.has_pending() || !pending_logs.is_empty() {
                     next_delivery = now + otel.throttle;
 
-                    if !pending_spans.is_empty() {
-                        if let Err(err) = otel
-                            .span_exporter
-                            .export(std::mem::take(&mut pending_spans))
-                            .await
-                        {
+                    if spans.has_pending() {
+                        if let Err(err) = otel.span_exporter.export(spans.take_pending()).await {
                             trc::event!(
                                 Telemetry(TelemetryEvent::OtelExporterError),
                                 Details = "Failed to export spans",
@@ -124,7 +110,7 @@ pub(crate) fn spawn_otel_tracer(builder: SubscriberBuilder, mut otel: OtelTracer
                         pending_logs.clear();
                     }
                 }
-            } else if !pending_logs.is_empty() || !pending_spans.is_empty() {
+            } else if !pending_logs.is_empty() || spans.has_pending() {
                 // Retry later
                 let this_retry = next_delivery - now;
                 match next_retry {
@@ -139,53 +125,346 @@ pub(crate) fn spawn_otel_tracer(builder: SubscriberBuilder, mut otel: OtelTracer
     });
 }
 
-fn build_span_data<I, T>(
-    start_span: &Event<EventDetails>,
-    end_span: &Event<EventDetails>,
-    span_events: I,
-    instrumentation: &InstrumentationLibrary,
-) -> SpanData
-where
-    I: IntoIterator<Item = T>,
-    T: AsRef<Event<EventDetails>>,
-{
-    let span_id = start_span.span_id().unwrap();
-
-    let mut events = SpanEvents::default();
-    events.events = span_events
-        .into_iter()
-        .map(|event| {
-            let event = event.as_ref();
-
-            opentelemetry::trace::Event::new(
-                event.inner.typ.name(),
-                UNIX_EPOCH + Duration::from_secs(event.inner.timestamp),
-                event.keys.iter().filter_map(build_key_value).collect(),
-                0,
-            )
-        })
-        .collect();
+// Builds OpenTelemetry spans out of trc events. Each session or delivery attempt
+// becomes a root span, timed operations and protocol stages within it become child
+// spans, and delivery attempts are attached to the trace of the session that queued
+// the message.
+pub struct SpanAssembler {
+    instrumentation: InstrumentationLibrary,
+    sampler: OtelSampler,
+    active_spans: AHashMap<u64, Vec<Arc<Event<EventDetails>>>>,
+    queue_links: AHashMap<u64, QueueLink>,
+    pending: Vec<SpanData>,
+}
+
+struct QueueLink {
+    context: SpanContext,
+    expires: u64,
+}
+
+impl SpanAssembler {
+    pub fn new(instrumentation: InstrumentationLibrary, sampler: OtelSampler) -> Self {
+        Self {
+            instrumentation,
+            sampler,
+            active_spans: AHashMap::new(),
+            queue_links: AHashMap::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn ingest(&mut self, event: Arc<Event<EventDetails>>) {
+        let Some(span) = event.inner.span.clone() else {
+            return;
+        };
+        let Some(span_id) = span.span_id() else {
+            return;
+        };
+
+        if !event.inner.typ.is_span_end() {
+            let seq = self.active_spans.get(&span_id).map_or(0, Vec::len);
+            if seq < MAX_EVENTS {
+                if is_queue_event(&event.inner.typ) {
+                    if let Some(queue_id) = event.value_as_uint(trc::Key::QueueId) {
+                        let context = SpanContext::new(
+                            self.trace_id(&span, span_id),
+                            child_span_id(span_id, seq).into(),
+                            TraceFlags::SAMPLED,
+                            false,
+                            TraceState::default(),
+                        );
+                        let expires = match event.value(trc::Key::Expires) {
+                            Some(trc::Value::Timestamp(expires)) => *expires,
+                            _ => event.inner.timestamp + 86400,
+                        };
+                        self.link_queue(queue_id, context, expires, event.inner.timestamp);
+                    }
+                }
+
+                self.active_spans.entry(span_id).or_default().push(event);
+            }
+        } else if let Some(events) = self.active_spans.remove(&span_id) {
+            self.finish_span(&span, span_id, &event, &events);
+        }
+    }
+
+    pub fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    pub fn take_pending(&mut self) -> Vec<SpanData> {
+        std::mem::take(&mut self.pending)
+    }
 
-    SpanData {
-        span_context: SpanContext::new(
-            (span_id as u128).into(),
+    fn finish_span(
+        &mut self,
+        start_span: &Event<EventDetails>,
+        span_id: u64,
+        end_span: &Event<EventDetails>,
+        events: &[Arc<Event<EventDetails>>],
+    ) {
+        let error = events
+            .iter()
+            .map(|event| event.as_ref())
+            .chain(std::iter::once(end_span))
+            .find(|event| event.inner.level == Level::Error);
+        let parent = self.linked_parent(start_span);
+        let trace_id = self.trace_id(start_span, span_id);
+
+        if !self.sampler.should_sample(trace_id, error.is_some()) {
+            return;
+        }
+
+        let context = SpanContext::new(
+            trace_id,
             span_id.into(),
-            TraceFlags::default(),
+            TraceFlags::SAMPLED,
             false,
             TraceState::default(),
-        ),
-        dropped_attributes_count: 0,
-        parent_span_id: 0.into(),
-        name: start_span.inner.typ.name().into(),
-        start_time: UNIX_EPOCH + Duration::from_secs(start_span.inner.timestamp),
-        end_time: UNIX_EPOCH + Duration::from_secs(end_span.inner.timestamp),
-        attributes: start_span.keys.iter().filter_map(build_key_value).collect(),
-        events,
-        links: SpanLinks::default(),
-        status: Status::default(),
-        span_kind: SpanKind::Server,
-        instrumentation_lib: instrumentation.clone(),
+        );
+
+        for (seq, event) in events.iter().enumerate() {
+            if is_child_span(event) {
+                let child = self.build_child_span(&context, child_span_id(span_id, seq), event);
+                self.pending.push(child);
+            }
+        }
+
+        let mut span_events = SpanEvents::default();
+        span_events.events = events
+            .iter()
+            .map(|event| event.as_ref())
+            .chain(std::iter::once(end_span))
+            .map(|event| {
+                opentelemetry::trace::Event::new(
+                    event.inner.typ.name(),
+                    UNIX_EPOCH + Duration::from_secs(event.inner.timestamp),
+                    event.keys.iter().filter_map(build_key_value).collect(),
+                    0,
+                )
+            })
+            .collect();
+
+        self.pending.push(SpanData {
+            span_context: context,
+            dropped_attributes_count: 0,
+            parent_span_id: parent.map_or(SpanId::INVALID, |parent| parent.span_id()),
+            name: start_span.inner.typ.name().into(),
+            start_time: UNIX_EPOCH + Duration::from_secs(start_span.inner.timestamp),
+            end_time: UNIX_EPOCH + Duration::from_secs(end_span.inner.timestamp),
+            attributes: start_span.keys.iter().filter_map(build_key_value).collect(),
+            events: span_events,
+            links: SpanLinks::default(),
+            status: build_status(error),
+            span_kind: span_kind(&start_span.inner.typ),
+            instrumentation_lib: self.instrumentation.clone(),
+        });
     }
+
+    fn build_child_span(
+        &self,
+        parent: &SpanContext,
+        span_id: u64,
+        event: &Event<EventDetails>,
+    ) -> SpanData {
+        let end_time = UNIX_EPOCH + Duration::from_secs(event.inner.timestamp);
+        let start_time = match event.value(trc::Key::Elapsed) {
+            Some(trc::Value::Duration(elapsed)) => end_time
+                .checked_sub(Duration::from_millis(*elapsed))
+                .unwrap_or(end_time),
+            _ => end_time,
+        };
+
+        // Spans carrying a W3C trace context are attached to the remote trace
+        // and linked back to the local session span.
+        let mut links = SpanLinks::default();
+        let (span_context, parent_span_id, span_kind) = match event
+            .value_as_str(trc::Key::TraceId)
+            .and_then(parse_traceparent)
+        {
+            Some((trace_id, remote_parent, _)) => {
+                links.links.push(Link::with_context(parent.clone()));
+                (
+                    SpanContext::new(
+                        trace_id,
+                        span_id.into(),
+                        TraceFlags::SAMPLED,
+                        false,
+                        TraceState::default(),
+                    ),
+                    remote_parent,
+                    SpanKind::Server,
+                )
+            }
+            None => (
+                SpanContext::new(
+                    parent.trace_id(),
+                    span_id.into(),
+                    TraceFlags::SAMPLED,
+                    false,
+                    TraceState::default(),
+                ),
+                parent.span_id(),
+                span_kind(&event.inner.typ),
+            ),
+        };
+
+        SpanData {
+            span_context,
+            dropped_attributes_count: 0,
+            parent_span_id,
+            name: event.inner.typ.name().into(),
+            start_time,
+            end_time,
+            attributes: event.keys.iter().filter_map(build_key_value).collect(),
+            events: SpanEvents::default(),
+            links,
+            status: build_status(Some(event).filter(|event| event.inner.level == Level::Error)),
+            span_kind,
+            instrumentation_lib: self.instrumentation.clone(),
+        }
+    }
+
+    fn linked_parent(&self, start_span: &Event<EventDetails>) -> Option<SpanContext> {
+        if matches!(
+            start_span.inner.typ,
+            EventType::Delivery(DeliveryEvent::AttemptStart)
+        ) {
+            start_span
+                .value_as_uint(trc::Key::QueueId)
+                .and_then(|queue_id| self.queue_links.get(&queue_id))
+                .map(|link| link.context.clone())
+        } else {
+            None
+        }
+    }
+
+    fn trace_id(&self, start_span: &Event<EventDetails>, span_id: u64) -> TraceId {
+        self.linked_parent(start_span)
+            .map_or_else(|| (span_id as u128).into(), |parent| parent.trace_id())
+    }
+
+    fn link_queue(&mut self, queue_id: u64, context: SpanContext, expires: u64, now: u64) {
+        if self.queue_links.len() >= MAX_QUEUE_LINKS {
+            self.queue_links.retain(|_, link| link.expires > now);
+            if self.queue_links.len() >= MAX_QUEUE_LINKS {
+                self.queue_links.clear();
+            }
+        }
+        self.queue_links
+            .insert(queue_id, QueueLink { context, expires });
+    }
+}
+
+impl OtelSampler {
+    pub fn should_sample(&self, trace_id: TraceId, has_error: bool) -> bool {
+        if (has_error && self.always_on_error) || self.ratio >= 1.0 {
+            true
+        } else if self.ratio <= 0.0 {
+            false
+        } else {
+            // Local trace ids are not random, hash them before comparing
+            let bytes = trace_id.to_bytes();
+            let hash = mix64(
+                u64::from_be_bytes(bytes[..8].try_into().unwrap())
+                    ^ u64::from_be_bytes(bytes[8..].try_into().unwrap()),
+            );
+            (hash >> 11) < (self.ratio * (1u64 << 53) as f64) as u64
+        }
+    }
+}
+
+// Parses a W3C traceparent header, returning the trace id, parent span id and flags.
+pub fn parse_traceparent(value: &str) -> Option<(TraceId, SpanId, TraceFlags)> {
+    let mut parts = value.trim().split('-');
+    let version = parts.next()?;
+    let trace_id = parts.next()?;
+    let span_id = parts.next()?;
+    let flags = parts.next()?;
+
+    if version.len() != 2
+        || version == "ff"
+        || (version == "00" && parts.next().is_some())
+        || trace_id.len() != 32
+        || span_id.len() != 16
+        || flags.len() != 2
+        || ![version, trace_id, span_id, flags].iter().all(|part| {
+            part.bytes()
+                .all(|ch| matches!(ch, b'0'..=b'9' | b'a'..=b'f'))
+        })
+    {
+        return None;
+    }
+
+    let trace_id = TraceId::from_hex(trace_id)
+        .ok()
+        .filter(|id| *id != TraceId::INVALID)?;
+    let span_id = SpanId::from_hex(span_id)
+        .ok()
+        .filter(|id| *id != SpanId::INVALID)?;
+    let flags = u8::from_str_radix(flags, 16).ok()?;
+
+    Some((trace_id, span_id, TraceFlags::new(flags)))
+}
+
+fn is_queue_event(typ: &EventType) -> bool {
+    matches!(
+        typ,
+        EventType::Queue(
+            QueueEvent::QueueMessage
+                | QueueEvent::QueueMessageAuthenticated
+                | QueueEvent::QueueReport
+                | QueueEvent::QueueDsn
+                | QueueEvent::QueueAutogenerated
+        )
+    )
+}
+
+fn is_child_span(event: &Event<EventDetails>) -> bool {
+    let typ = &event.inner.typ;
+    !typ.is_span_start()
+        && !typ.is_raw_io()
+        && (event.value(trc::Key::Elapsed).is_some()
+            || is_queue_event(typ)
+            || matches!(
+                typ,
+                EventType::Smtp(SmtpEvent::Ehlo | SmtpEvent::MailFrom | SmtpEvent::RcptTo)
+                    | EventType::Delivery(
+                        DeliveryEvent::DomainDeliveryStart
+                            | DeliveryEvent::Connect
+                            | DeliveryEvent::Ehlo
+                            | DeliveryEvent::MailFrom
+                            | DeliveryEvent::RcptTo
+                            | DeliveryEvent::Delivered
+                            | DeliveryEvent::Completed
+                            | DeliveryEvent::Failed
+                    )
+            ))
+}
+
+fn span_kind(typ: &EventType) -> SpanKind {
+    match typ {
+        EventType::Delivery(_) => SpanKind::Client,
+        _ if typ.is_span_start() => SpanKind::Server,
+        _ => SpanKind::Internal,
+    }
+}
+
+fn build_status(error: Option<&Event<EventDetails>>) -> Status {
+    match error {
+        Some(event) => Status::error(event.inner.typ.description()),
+        None => Status::default(),
+    }
+}
+
+fn child_span_id(parent: u64, seq: usize) -> u64 {
+    mix64(parent ^ ((seq as u64 + 1) << 48)).max(1)
+}
+
+fn mix64(mut value: u64) -> u64 {
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d049bb133111eb);
+    value ^ (value >> 31)
 }
 
 fn build_log_record(event: &Event<EventDetails>) -> opentelemetry_sdk::logs::LogRecord {
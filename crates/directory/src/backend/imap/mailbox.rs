@@ -0,0 +1,525 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use mail_send::Credentials;
+use smtp_proto::{response::generate::BitToString, IntoString, AUTH_OAUTHBEARER, AUTH_XOAUTH2};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite};
+
+use super::{ImapClient, ImapError};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteFolder {
+    pub name: String,
+    pub delimiter: Option<char>,
+    pub attributes: Vec<String>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RemoteFolderStatus {
+    pub uid_validity: u32,
+    pub uid_next: u32,
+    pub exists: u32,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RemoteMessage {
+    pub uid: u32,
+    pub size: u64,
+    pub flags: Vec<String>,
+    pub internal_date: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    Atom(String),
+    String(Vec<u8>),
+    List(Vec<Token>),
+    Nil,
+}
+
+impl<T: AsyncRead + AsyncWrite + Unpin> ImapClient<T> {
+    pub async fn login(&mut self, credentials: &Credentials<String>) -> Result<(), ImapError> {
+        let command = match credentials {
+            Credentials::Plain { username, secret } => {
+                format!("LOGIN {} {}", quote(username), quote(secret))
+            }
+            Credentials::XOauth2 { .. } | Credentials::OAuthBearer { .. } => {
+                let mechanism = if matches!(credentials, Credentials::XOauth2 { .. }) {
+                    AUTH_XOAUTH2
+                } else {
+                    AUTH_OAUTHBEARER
+                };
+                format!(
+                    "AUTHENTICATE {} {}",
+                    mechanism.to_mechanism(),
+                    credentials
+                        .encode(mechanism, "")
+                        .map_err(|err| ImapError::InvalidChallenge(err.to_string()))?
+                )
+            }
+        };
+
+        match self.command("M0", &command).await {
+            Ok(_) => Ok(()),
+            Err(ImapError::InvalidResponse(response))
+                if response.starts_with("M0 NO") || response.starts_with("M0 BAD") =>
+            {
+                Err(ImapError::AuthenticationFailed)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    pub async fn capabilities(&mut self) -> Result<Vec<String>, ImapError> {
+        Ok(self
+            .command("M1", "CAPABILITY")
+            .await?
+            .into_iter()
+            .filter_map(|response| {
+                response
+                    .get(..13)
+                    .filter(|prefix| prefix.eq_ignore_ascii_case(b"* CAPABILITY "))
+                    .map(|_| response[13..].into_string())
+            })
+            .flat_map(|line| {
+                line.split_ascii_whitespace()
+                    .map(|capability| capability.to_ascii_uppercase())
+                    .collect::<Vec<_>>()
+            })
+            .collect())
+    }
+
+    pub async fn list(&mut self, special_use: bool) -> Result<Vec<RemoteFolder>, ImapError> {
+        let command = if special_use {
+            "LIST \"\" \"*\" RETURN (SPECIAL-USE)"
+        } else {
+            "LIST \"\" \"*\""
+        };
+        let mut folders = Vec::new();
+
+        for response in self.command("M2", command).await? {
+            let mut tokens = untagged(&response, "LIST").into_iter();
+            let (Some(Token::List(attributes)), Some(delimiter), Some(name)) =
+                (tokens.next(), tokens.next(), tokens.next())
+            else {
+                continue;
+            };
+            let Some(name) = name.as_bytes() else {
+                continue;
+            };
+
+            folders.push(RemoteFolder {
+                name: String::from_utf8_lossy(name).into_owned(),
+                delimiter: delimiter
+                    .as_bytes()
+                    .and_then(|delimiter| delimiter.first())
+                    .map(|delimiter| char::from(*delimiter)),
+                attributes: attributes
+                    .iter()
+                    .filter_map(|attribute| attribute.as_bytes())
+                    .map(|attribute| String::from_utf8_lossy(attribute).into_owned())
+                    .collect(),
+            });
+        }
+
+        Ok(folders)
+    }
+
+    // EXAMINE opens the folder read-only, so \Recent and \Seen are left untouched
+    pub async fn examine(&mut self, name: &str) -> Result<RemoteFolderStatus, ImapError> {
+        let mut status = RemoteFolderStatus::default();
+
+        for response in self
+            .command("M3", &format!("EXAMINE {}", quote(name)))
+            .await?
+        {
+            let response = response.into_string();
+            let mut words = response.split_ascii_whitespace().skip(1);
+            match (words.next(), words.next()) {
+                (Some("OK"), Some(code)) if code.eq_ignore_ascii_case("[UIDVALIDITY") => {
+                    status.uid_validity = parse_code_number(words.next());
+                }
+                (Some("OK"), Some(code)) if code.eq_ignore_ascii_case("[UIDNEXT") => {
+                    status.uid_next = parse_code_number(words.next());
+                }
+                (Some(count), Some(kind)) if kind.eq_ignore_ascii_case("EXISTS") => {
+                    status.exists = count.parse().unwrap_or_default();
+                }
+                _ => (),
+            }
+        }
+
+        Ok(status)
+    }
+
+    // Returns the messages with a UID equal or greater than `from_uid`
+    pub async fn fetch_messages(&mut self, from_uid: u32) -> Result<Vec<RemoteMessage>, ImapError> {
+        let mut messages = Vec::new();
+
+        for response in self
+            .command(
+                "M4",
+                &format!("UID FETCH {from_uid}:* (UID FLAGS INTERNALDATE RFC822.SIZE)"),
+            )
+            .await?
+        {
+            let mut message = RemoteMessage::default();
+            for (item, value) in fetch_items(&response) {
+                match item.to_ascii_uppercase().as_str() {
+                    "UID" => {
+                        message.uid = value.as_number().unwrap_or_default() as u32;
+                    }
+                    "RFC822.SIZE" => {
+                        message.size = value.as_number().unwrap_or_default();
+                    }
+                    "INTERNALDATE" => {
+                        message.internal_date = value
+                            .as_bytes()
+                            .map(|date| String::from_utf8_lossy(date).into_owned());
+                    }
+                    "FLAGS" => {
+                        if let Token::List(flags) = value {
+                            message.flags = flags
+                                .iter()
+                                .filter_map(|flag| flag.as_bytes())
+                                .map(|flag| String::from_utf8_lossy(flag).into_owned())
+                                .collect();
+                        }
+                    }
+                    _ => (),
+                }
+            }
+
+            // "n:*" always matches the last message, even when its UID is lower than n
+            if message.uid >= from_uid {
+                messages.push(message);
+            }
+        }
+
+        messages.sort_unstable_by_key(|message| message.uid);
+
+        Ok(messages)
+    }
+
+    pub async fn fetch_message(&mut self, uid: u32) -> Result<Option<Vec<u8>>, ImapError> {
+        for response in self
+            .command("M5", &format!("UID FETCH {uid} BODY.PEEK[]"))
+            .await?
+        {
+            for (item, value) in fetch_items(&response) {
+                if item.eq_ignore_ascii_case("BODY[]") {
+                    if let Token::String(contents) = value {
+                        return Ok(Some(contents));
+                    }
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    // Sends a command and returns its untagged responses once it completes successfully
+    async fn command(&mut self, tag: &str, command: &str) -> Result<Vec<Vec<u8>>, ImapError> {
+        tokio::time::timeout(self.timeout, async {
+            self.write(format!("{tag} {command}\r\n").as_bytes())
+                .await?;
+
+            let mut responses = Vec::new();
+            loop {
+                let response = self.read_response().await?;
+                if let Some(status) = response
+                    .strip_prefix(tag.as_bytes())
+                    .and_then(|status| status.strip_prefix(b" "))
+                {
+                    return if status
+                        .get(..2)
+                        .is_some_and(|status| status.eq_ignore_ascii_case(b"OK"))
+                    {
+                        Ok(responses)
+                    } else {
+                        Err(ImapError::InvalidResponse(response.into_string()))
+                    };
+                } else if response.starts_with(b"+") {
+                    // Cancel any server challenge, such as a failed OAuth exchange
+                    self.write(b"\r\n").await?;
+                } else {
+                    responses.push(response);
+                }
+            }
+        })
+        .await
+        .map_err(|_| ImapError::Timeout)?
+    }
+
+    // Reads a full response, literals are kept inline after their "{size}" marker
+    async fn read_response(&mut self) -> Result<Vec<u8>, ImapError> {
+        let mut response = Vec::new();
+
+        loop {
+            let line = self.read_buffered_line().await?;
+            let literal_size = literal_size(&line);
+            response.extend_from_slice(&line);
+
+            if let Some(size) = literal_size {
+                while self.buf.len() < size {
+                    self.fill_buf().await?;
+                }
+                response.extend(self.buf.drain(..size));
+            } else {
+                return Ok(response);
+            }
+        }
+    }
+
+    async fn read_buffered_line(&mut self) -> Result<Vec<u8>, ImapError> {
+        let mut offset = 0;
+
+        loop {
+            if let Some(pos) = self.buf[offset..].iter().position(|&ch| ch == b'\n') {
+                return Ok(self.buf.drain(..offset + pos + 1).collect());
+            }
+            offset = self.buf.len();
+            self.fill_buf().await?;
+        }
+    }
+
+    async fn fill_buf(&mut self) -> Result<(), ImapError> {
+        let mut buf = [0u8; 8192];
+        let br = self.stream.read(&mut buf).await?;
+
+        if br > 0 {
+            self.buf.extend_from_slice(&buf[..br]);
+            Ok(())
+        } else {
+            Err(ImapError::Disconnected)
+        }
+    }
+}
+
+impl Token {
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Token::Atom(value) => Some(value.as_bytes()),
+            Token::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_number(&self) -> Option<u64> {
+        match self {
+            Token::Atom(value) => value.parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+pub fn tokenize(bytes: &[u8]) -> Vec<Token> {
+    let mut pos = 0;
+    parse_tokens(bytes, &mut pos, false)
+}
+
+fn parse_tokens(bytes: &[u8], pos: &mut usize, nested: bool) -> Vec<Token> {
+    let mut tokens = Vec::new();
+
+    while let Some(&ch) = bytes.get(*pos) {
+        match ch {
+            b' ' | b'\r' | b'\n' => {
+                *pos += 1;
+            }
+            b'(' => {
+                *pos += 1;
+                tokens.push(Token::List(parse_tokens(bytes, pos, true)));
+            }
+            b')' => {
+                *pos += 1;
+                if nested {
+                    return tokens;
+                }
+            }
+            b'"' => {
+                *pos += 1;
+                let mut value = Vec::new();
+                while let Some(&ch) = bytes.get(*pos) {
+                    *pos += 1;
+                    match ch {
+                        b'\\' => {
+                            if let Some(&ch) = bytes.get(*pos) {
+                                value.push(ch);
+                                *pos += 1;
+                            }
+                        }
+                        b'"' => break,
+                        _ => value.push(ch),
+                    }
+                }
+                tokens.push(Token::String(value));
+            }
+            b'{' => {
+                let Some(end) = bytes[*pos..].iter().position(|&ch| ch == b'\n') else {
+                    break;
+                };
+                let start = *pos + end + 1;
+                let size = std::str::from_utf8(&bytes[*pos + 1..*pos + end])
+                    .ok()
+                    .and_then(|size| size.trim_end().trim_end_matches(['}', '+']).parse().ok())
+                    .unwrap_or(0usize);
+                let end = (start + size).min(bytes.len());
+                tokens.push(Token::String(bytes[start..end].to_vec()));
+                *pos = end;
+            }
+            _ => {
+                // Atoms may contain bracketed sections such as BODY[HEADER.FIELDS (From)]
+                let start = *pos;
+                let mut depth = 0u32;
+                while let Some(&ch) = bytes.get(*pos) {
+                    match ch {
+                        b'[' => depth += 1,
+                        b']' => depth = depth.saturating_sub(1),
+                        b' ' | b'(' | b')' | b'\r' | b'\n' if depth == 0 => break,
+                        _ => (),
+                    }
+                    *pos += 1;
+                }
+                let atom = String::from_utf8_lossy(&bytes[start..*pos]).into_owned();
+                tokens.push(if atom.eq_ignore_ascii_case("NIL") {
+                    Token::Nil
+                } else {
+                    Token::Atom(atom)
+                });
+            }
+        }
+    }
+
+    tokens
+}
+
+// Returns the tokens of an untagged response of the given type, such as "* LIST ..."
+fn untagged(response: &[u8], kind: &str) -> Vec<Token> {
+    let mut tokens = tokenize(response).into_iter();
+    match (tokens.next(), tokens.next()) {
+        (Some(Token::Atom(star)), Some(Token::Atom(name)))
+            if star == "*" && name.eq_ignore_ascii_case(kind) =>
+        {
+            tokens.collect()
+        }
+        _ => vec![],
+    }
+}
+
+// Returns the item name and value pairs of a "* n FETCH (...)" response
+fn fetch_items(response: &[u8]) -> Vec<(String, Token)> {
+    let mut tokens = tokenize(response).into_iter().skip(1);
+    let mut items = Vec::new();
+
+    if let (Some(_), Some(Token::Atom(kind)), Some(Token::List(list))) =
+        (tokens.next(), tokens.next(), tokens.next())
+    {
+        if kind.eq_ignore_ascii_case("FETCH") {
+            let mut list = list.into_iter();
+            while let (Some(Token::Atom(item)), Some(value)) = (list.next(), list.next()) {
+                items.push((item, value));
+            }
+        }
+    }
+
+    items
+}
+
+fn literal_size(line: &[u8]) -> Option<usize> {
+    let line = line.strip_suffix(b"\n")?;
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    let line = line.strip_suffix(b"}")?;
+    let start = line.iter().rposition(|&ch| ch == b'{')?;
+    let size = &line[start + 1..];
+    let size = size.strip_suffix(b"+").unwrap_or(size);
+
+    std::str::from_utf8(size).ok()?.parse().ok()
+}
+
+fn parse_code_number(value: Option<&str>) -> u32 {
+    value
+        .and_then(|value| value.trim_end_matches(']').parse().ok())
+        .unwrap_or_default()
+}
+
+fn quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for ch in value.chars() {
+        if matches!(ch, '"' | '\\') {
+            quoted.push('\\');
+        }
+        quoted.push(ch);
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod test {
+    use super::{fetch_items, literal_size, tokenize, Token};
+
+    #[test]
+    fn parse_imap_responses() {
+        assert_eq!(
+            tokenize(b"* LIST (\\HasNoChildren \\Sent) \"/\" \"Sent \\\"Items\\\"\"\r\n"),
+            vec![
+                Token::Atom("*".into()),
+                Token::Atom("LIST".into()),
+                Token::List(vec![
+                    Token::Atom("\\HasNoChildren".into()),
+                    Token::Atom("\\Sent".into())
+                ]),
+                Token::String(b"/".to_vec()),
+                Token::String(b"Sent \"Items\"".to_vec()),
+            ]
+        );
+        assert_eq!(
+            tokenize(b"* LIST () NIL {5}\r\nIN)BX\r\n"),
+            vec![
+                Token::Atom("*".into()),
+                Token::Atom("LIST".into()),
+                Token::List(vec![]),
+                Token::Nil,
+                Token::String(b"IN)BX".to_vec()),
+            ]
+        );
+
+        assert_eq!(literal_size(b"* 1 FETCH (UID 4 BODY[] {12}\r\n"), Some(12));
+        assert_eq!(literal_size(b"A1 APPEND INBOX {310+}\r\n"), Some(310));
+        assert_eq!(literal_size(b"* OK [UIDNEXT 4] Next UID\r\n"), None);
+
+        assert_eq!(
+            fetch_items(
+                concat!(
+                    "* 3 FETCH (UID 7 FLAGS (\\Seen $Label) ",
+                    "INTERNALDATE \"17-Jul-1996 02:44:25 -0700\" RFC822.SIZE 12 ",
+                    "BODY[] {12}\r\nSubject: a\r\n)\r\n"
+                )
+                .as_bytes()
+            ),
+            vec![
+                ("UID".to_string(), Token::Atom("7".into())),
+                (
+                    "FLAGS".to_string(),
+                    Token::List(vec![
+                        Token::Atom("\\Seen".into()),
+                        Token::Atom("$Label".into())
+                    ])
+                ),
+                (
+                    "INTERNALDATE".to_string(),
+                    Token::String(b"17-Jul-1996 02:44:25 -0700".to_vec())
+                ),
+                ("RFC822.SIZE".to_string(), Token::Atom("12".into())),
+                (
+                    "BODY[]".to_string(),
+                    Token::String(b"Subject: a\r\n".to_vec())
+                ),
+            ]
+        );
+    }
+}
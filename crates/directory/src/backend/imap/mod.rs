@@ -7,6 +7,7 @@
 pub mod client;
 pub mod config;
 pub mod lookup;
+pub mod mailbox;
 pub mod pool;
 pub mod tls;
 
@@ -33,6 +34,7 @@ pub struct ImapConnectionManager {
 
 pub struct ImapClient<T: AsyncRead + AsyncWrite> {
     stream: T,
+    buf: Vec<u8>,
     mechanisms: u64,
     is_valid: bool,
     timeout: Duration,
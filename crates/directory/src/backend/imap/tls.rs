@@ -48,6 +48,7 @@ impl ImapClient<TcpStream> {
                         self.stream,
                     )
                     .await?,
+                buf: self.buf,
                 timeout: self.timeout,
                 mechanisms: self.mechanisms,
                 is_valid: true,
@@ -70,6 +71,7 @@ impl ImapClient<TlsStream<TcpStream>> {
             match TcpStream::connect(addr).await {
                 Ok(stream) => Ok(ImapClient {
                     stream,
+                    buf: Vec::new(),
                     timeout,
                     mechanisms: 0,
                     is_valid: true,
@@ -17,8 +17,10 @@ use trc::AddContext;
 use utils::sanitize_email;
 
 use crate::{
-    backend::RcptType, Permission, Permissions, Principal, QueryBy, Type, MAX_TYPE_ID, ROLE_ADMIN,
-    ROLE_TENANT_ADMIN, ROLE_USER,
+    backend::RcptType,
+    core::scram::{generate_scram_secrets, plaintext_password, ScramSecret},
+    Permission, Permissions, Principal, QueryBy, Type, MAX_TYPE_ID, ROLE_ADMIN, ROLE_TENANT_ADMIN,
+    ROLE_USER,
 };
 
 use super::{
@@ -622,18 +624,8 @@ impl ManageDirectory for Store {
         }
         // SPDX-SnippetEnd
 
-        // Unlink all principal's blobs
-        self.blob_hash_unlink_account(principal_id)
-            .await
-            .caused_by(trc::location!())?;
-
-        // Revoke ACLs
-        self.acl_revoke_all(principal_id)
-            .await
-            .caused_by(trc::location!())?;
-
-        // Delete principal data
-        self.purge_account(principal_id)
+        // Delete principal data, ACLs and blob links
+        self.delete_account(principal_id)
             .await
             .caused_by(trc::location!())?;
 
@@ -941,7 +933,17 @@ impl ManageDirectory for Store {
                             // Add OTP Auth URLs to the beginning of the list
                             principal.inner.prepend_str(PrincipalField::Secrets, secret);
                         } else {
+                            let scram_secrets = plaintext_password(&secret)
+                                .map(|password| {
+                                    generate_scram_secrets(password).collect::<Vec<_>>()
+                                })
+                                .unwrap_or_default();
                             principal.inner.append_str(PrincipalField::Secrets, secret);
+                            for scram_secret in scram_secrets {
+                                principal
+                                    .inner
+                                    .append_str(PrincipalField::Secrets, scram_secret);
+                            }
                         }
                     }
                 }
@@ -950,18 +952,37 @@ impl ManageDirectory for Store {
                     PrincipalField::Secrets,
                     PrincipalValue::String(secret),
                 ) => {
-                    if secret.is_app_password() || secret.is_otp_auth() {
+                    if secret.is_app_password() || secret.is_otp_auth() || secret.is_recovery_code()
+                    {
                         principal.inner.retain_str(PrincipalField::Secrets, |v| {
                             *v != secret && !v.starts_with(&secret)
                         });
+
+                        // Recovery codes are useless once TOTP is disabled
+                        if secret.is_otp_auth()
+                            && !principal
+                                .inner
+                                .iter_str(PrincipalField::Secrets)
+                                .any(|v| v.is_otp_auth())
+                        {
+                            principal
+                                .inner
+                                .retain_str(PrincipalField::Secrets, |v| !v.is_recovery_code());
+                        }
                     } else if !secret.is_empty() {
-                        principal
-                            .inner
-                            .retain_str(PrincipalField::Secrets, |v| *v != secret);
+                        // Also remove the SCRAM credentials derived from this password
+                        let password = plaintext_password(&secret).map(|p| p.to_string());
+                        principal.inner.retain_str(PrincipalField::Secrets, |v| {
+                            *v != secret
+                                && !password.as_deref().is_some_and(|password| {
+                                    ScramSecret::parse(v)
+                                        .is_some_and(|scram| scram.is_derived_from(password))
+                                })
+                        });
                     } else {
-                        principal
-                            .inner
-                            .retain_str(PrincipalField::Secrets, |v| !v.is_password());
+                        principal.inner.retain_str(PrincipalField::Secrets, |v| {
+                            !v.is_password() && !v.is_scram()
+                        });
                     }
                 }
                 (
@@ -16,7 +16,8 @@ use store::{
     write::{
         key::KeySerializer, AnyClass, BatchBuilder, DirectoryClass, MaybeDynamicId, ValueClass,
     },
-    Deserialize, IterateParams, Serialize, Store, ValueKey, SUBSPACE_DIRECTORY, U32_LEN,
+    Deserialize, IterateParams, QuotaLimit, Serialize, Store, ValueKey, SUBSPACE_DIRECTORY,
+    U32_LEN,
 };
 use trc::AddContext;
 use utils::codec::leb128::{Leb128Iterator, Leb128Reader};
@@ -98,6 +99,12 @@ impl Deserialize for Principal {
     }
 }
 
+impl QuotaLimit for Principal {
+    fn quota_limit(&self) -> u64 {
+        self.quota()
+    }
+}
+
 #[cfg(feature = "enterprise")]
 impl PrincipalInfo {
     // SPDX-SnippetBegin
@@ -577,6 +584,8 @@ fn deserialize_string(bytes: &mut Iter<'_, u8>) -> Option<String> {
 pub trait SpecialSecrets {
     fn is_otp_auth(&self) -> bool;
     fn is_app_password(&self) -> bool;
+    fn is_scram(&self) -> bool;
+    fn is_recovery_code(&self) -> bool;
     fn is_password(&self) -> bool;
 }
 
@@ -592,7 +601,18 @@ where
         self.as_ref().starts_with("$app$")
     }
 
+    fn is_scram(&self) -> bool {
+        self.as_ref().starts_with("$scram-")
+    }
+
+    fn is_recovery_code(&self) -> bool {
+        self.as_ref().starts_with("$recovery$")
+    }
+
     fn is_password(&self) -> bool {
-        !self.is_otp_auth() && !self.is_app_password()
+        !self.is_otp_auth()
+            && !self.is_app_password()
+            && !self.is_scram()
+            && !self.is_recovery_code()
     }
 }
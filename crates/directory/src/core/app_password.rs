@@ -0,0 +1,119 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{fmt::Display, net::IpAddr};
+
+use pwhash::sha512_crypt;
+use utils::config::{ipmask::IpAddrMask, utils::ParseValue};
+
+use crate::{backend::internal::PrincipalField, Principal};
+
+use super::secret::verify_secret_hash;
+
+// App passwords are stored as "$app$<name>[;protocols=<list>][;ips=<list>]$<hash>"
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppPassword<'x> {
+    pub name: &'x str,
+    pub protocols: Vec<&'x str>,
+    pub allowed_ips: Vec<&'x str>,
+    pub hash: &'x str,
+}
+
+impl<'x> AppPassword<'x> {
+    pub fn new(name: &'x str, hash: &'x str) -> Self {
+        AppPassword {
+            name,
+            protocols: Vec::new(),
+            allowed_ips: Vec::new(),
+            hash,
+        }
+    }
+
+    pub fn with_protocols(mut self, protocols: impl IntoIterator<Item = &'x str>) -> Self {
+        self.protocols.extend(protocols);
+        self
+    }
+
+    pub fn with_allowed_ips(mut self, allowed_ips: impl IntoIterator<Item = &'x str>) -> Self {
+        self.allowed_ips.extend(allowed_ips);
+        self
+    }
+
+    pub fn parse(secret: &'x str) -> Option<Self> {
+        let (params, hash) = secret.strip_prefix("$app$")?.split_once('$')?;
+        let mut params = params.split(';');
+        let mut app_password = AppPassword::new(params.next()?, hash);
+
+        for param in params {
+            match param.split_once('=') {
+                Some(("protocols", value)) => {
+                    app_password.protocols = value.split(',').filter(|v| !v.is_empty()).collect();
+                }
+                Some(("ips", value)) => {
+                    app_password.allowed_ips = value.split(',').filter(|v| !v.is_empty()).collect();
+                }
+                _ => (),
+            }
+        }
+
+        Some(app_password)
+    }
+
+    pub fn is_allowed(&self, protocol: &str, remote_ip: IpAddr) -> bool {
+        (self.protocols.is_empty() || self.protocols.contains(&protocol))
+            && (self.allowed_ips.is_empty()
+                || self.allowed_ips.iter().any(|ip| {
+                    IpAddrMask::parse_value(ip).is_ok_and(|mask| mask.matches(&remote_ip))
+                }))
+    }
+
+    // Names are used as separators in the stored secret
+    pub fn is_valid_name(name: &str) -> bool {
+        !name.is_empty() && !name.contains(['$', ';', '=', ','])
+    }
+}
+
+impl Display for AppPassword<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "$app${}", self.name)?;
+        if !self.protocols.is_empty() {
+            write!(f, ";protocols={}", self.protocols.join(","))?;
+        }
+        if !self.allowed_ips.is_empty() {
+            write!(f, ";ips={}", self.allowed_ips.join(","))?;
+        }
+        write!(f, "${}", self.hash)
+    }
+}
+
+impl Principal {
+    // Returns the app password matching the provided secret, if any
+    pub async fn verify_app_password(&self, code: &str) -> trc::Result<Option<AppPassword<'_>>> {
+        for secret in self.iter_str(PrincipalField::Secrets) {
+            if let Some(app_password) = AppPassword::parse(secret) {
+                if verify_secret_hash(app_password.hash, code).await? {
+                    return Ok(Some(app_password));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    pub fn app_passwords(&self) -> impl Iterator<Item = AppPassword<'_>> {
+        self.iter_str(PrincipalField::Secrets)
+            .filter_map(|secret| AppPassword::parse(secret))
+    }
+}
+
+pub fn hash_app_password(password: &str) -> trc::Result<String> {
+    sha512_crypt::hash(password).map_err(|err| {
+        trc::AuthEvent::Error
+            .into_err()
+            .reason(err)
+            .details("Failed to hash app password")
+    })
+}
@@ -6,11 +6,14 @@
 
 use crate::Permission;
 
+pub mod app_password;
 pub mod cache;
 pub mod config;
 pub mod dispatch;
 pub mod principal;
+pub mod scram;
 pub mod secret;
+pub mod totp;
 
 impl Permission {
     pub fn description(&self) -> &'static str {
@@ -199,6 +202,18 @@ impl Permission {
             Permission::OauthClientDelete => "Remove OAuth clients",
             Permission::AiModelInteract => "Interact with AI models",
             Permission::Troubleshoot => "Perform troubleshooting",
+            Permission::StoreStats => "View storage usage statistics",
+            Permission::RateLimitGet => "View rate limit usage",
+            Permission::SpamFilterTrain => "Train the spam filter",
+            Permission::QuarantineList => "List quarantined messages of any account",
+            Permission::QuarantineGet => "Retrieve quarantined messages of any account",
+            Permission::QuarantineRelease => "Release quarantined messages of any account",
+            Permission::QuarantineDelete => "Delete quarantined messages of any account",
+            Permission::ManageQuarantine => "Manage own quarantined messages",
+            Permission::ImapXApplePushService => "Register devices for push notifications via IMAP",
+            Permission::AuditLogView => "View the audit log",
+            Permission::AccountImport => "Import mbox and Maildir mailboxes into accounts",
+            Permission::AccountMigrate => "Migrate accounts from remote IMAP servers",
         }
     }
 }
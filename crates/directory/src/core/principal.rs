@@ -1003,6 +1003,7 @@ impl Permission {
                 | Permission::EmailReceive
                 | Permission::ManageEncryption
                 | Permission::ManagePasswords
+                | Permission::ManageQuarantine
                 | Permission::JmapEmailGet
                 | Permission::JmapMailboxGet
                 | Permission::JmapThreadGet
@@ -1073,6 +1074,7 @@ impl Permission {
                 | Permission::ImapStore
                 | Permission::ImapSubscribe
                 | Permission::ImapThread
+                | Permission::ImapXApplePushService
                 | Permission::Pop3Authenticate
                 | Permission::Pop3List
                 | Permission::Pop3Uidl
@@ -0,0 +1,655 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::fmt::Display;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hmac::{Hmac, Mac};
+use pbkdf2::pbkdf2_hmac;
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
+use store::{
+    rand::{distributions::Alphanumeric, thread_rng, Rng},
+    write::now,
+};
+
+use crate::{
+    backend::internal::{PrincipalField, SpecialSecrets},
+    Principal,
+};
+
+const SCRAM_ITERATIONS: u32 = 4096;
+const SCRAM_SALT_LEN: usize = 16;
+const SCRAM_NONCE_LEN: usize = 24;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScramAlgorithm {
+    Sha1,
+    Sha256,
+}
+
+// SCRAM credentials as stored in the secrets of a principal
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScramSecret {
+    pub algorithm: ScramAlgorithm,
+    pub iterations: u32,
+    pub salt: Vec<u8>,
+    pub stored_key: Vec<u8>,
+    pub server_key: Vec<u8>,
+}
+
+// Server side of a SCRAM exchange (RFC 5802, RFC 7677). Channel binding
+// uses tls-exporter (RFC 9266), tls-unique is not available with rustls.
+#[derive(Debug)]
+pub struct ScramServer {
+    algorithm: ScramAlgorithm,
+    plus: bool,
+    channel_binding: Option<Vec<u8>>,
+    gs2_header: String,
+    client_first_bare: String,
+    server_first: String,
+    nonce: String,
+    username: String,
+}
+
+impl ScramAlgorithm {
+    pub fn mechanism(&self, plus: bool) -> &'static str {
+        match (self, plus) {
+            (ScramAlgorithm::Sha1, false) => "SCRAM-SHA-1",
+            (ScramAlgorithm::Sha1, true) => "SCRAM-SHA-1-PLUS",
+            (ScramAlgorithm::Sha256, false) => "SCRAM-SHA-256",
+            (ScramAlgorithm::Sha256, true) => "SCRAM-SHA-256-PLUS",
+        }
+    }
+
+    fn secret_prefix(&self) -> &'static str {
+        match self {
+            ScramAlgorithm::Sha1 => "$scram-sha-1$",
+            ScramAlgorithm::Sha256 => "$scram-sha-256$",
+        }
+    }
+
+    pub fn hmac(&self, key: &[u8], data: &[u8]) -> Vec<u8> {
+        match self {
+            ScramAlgorithm::Sha1 => {
+                let mut mac = Hmac::<Sha1>::new_from_slice(key).unwrap();
+                mac.update(data);
+                mac.finalize().into_bytes().to_vec()
+            }
+            ScramAlgorithm::Sha256 => {
+                let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+                mac.update(data);
+                mac.finalize().into_bytes().to_vec()
+            }
+        }
+    }
+
+    pub fn hash(&self, data: &[u8]) -> Vec<u8> {
+        match self {
+            ScramAlgorithm::Sha1 => Sha1::digest(data).to_vec(),
+            ScramAlgorithm::Sha256 => Sha256::digest(data).to_vec(),
+        }
+    }
+
+    // Salt of credentials that are not stored, keyed with a server secret
+    fn derived_salt(&self, server_key: &[u8], username: &str) -> Vec<u8> {
+        let mut salt = self.hmac(server_key, username.as_bytes());
+        salt.truncate(SCRAM_SALT_LEN);
+        salt
+    }
+
+    pub fn salted_password(&self, password: &str, salt: &[u8], iterations: u32) -> Vec<u8> {
+        match self {
+            ScramAlgorithm::Sha1 => {
+                let mut result = vec![0u8; 20];
+                pbkdf2_hmac::<Sha1>(password.as_bytes(), salt, iterations, &mut result);
+                result
+            }
+            ScramAlgorithm::Sha256 => {
+                let mut result = vec![0u8; 32];
+                pbkdf2_hmac::<Sha256>(password.as_bytes(), salt, iterations, &mut result);
+                result
+            }
+        }
+    }
+}
+
+impl ScramSecret {
+    pub fn generate(algorithm: ScramAlgorithm, password: &str) -> Self {
+        Self::derive(
+            algorithm,
+            password,
+            thread_rng().gen::<[u8; SCRAM_SALT_LEN]>().to_vec(),
+            SCRAM_ITERATIONS,
+        )
+    }
+
+    pub fn derive(
+        algorithm: ScramAlgorithm,
+        password: &str,
+        salt: Vec<u8>,
+        iterations: u32,
+    ) -> Self {
+        let salted_password = algorithm.salted_password(password, &salt, iterations);
+        let client_key = algorithm.hmac(&salted_password, b"Client Key");
+
+        ScramSecret {
+            algorithm,
+            iterations,
+            salt,
+            stored_key: algorithm.hash(&client_key),
+            server_key: algorithm.hmac(&salted_password, b"Server Key"),
+        }
+    }
+
+    // Credentials for unknown users, the exchange fails once the proof is received.
+    // The salt is derived from the username so that repeated attempts do not reveal
+    // whether the account exists.
+    pub fn unknown(algorithm: ScramAlgorithm, server_key: &[u8], username: &str) -> Self {
+        let key_len = algorithm.hash(b"").len();
+
+        ScramSecret {
+            algorithm,
+            iterations: SCRAM_ITERATIONS,
+            salt: algorithm.derived_salt(server_key, username),
+            stored_key: vec![0; key_len],
+            server_key: vec![0; key_len],
+        }
+    }
+
+    pub fn parse(secret: &str) -> Option<Self> {
+        let (algorithm, secret) = [ScramAlgorithm::Sha256, ScramAlgorithm::Sha1]
+            .into_iter()
+            .find_map(|algorithm| {
+                secret
+                    .strip_prefix(algorithm.secret_prefix())
+                    .map(|secret| (algorithm, secret))
+            })?;
+        let mut parts = secret.split('$');
+        let secret = ScramSecret {
+            algorithm,
+            iterations: parts.next()?.parse().ok()?,
+            salt: STANDARD.decode(parts.next()?).ok()?,
+            stored_key: STANDARD.decode(parts.next()?).ok()?,
+            server_key: STANDARD.decode(parts.next()?).ok()?,
+        };
+
+        if parts.next().is_none() {
+            Some(secret)
+        } else {
+            None
+        }
+    }
+
+    pub fn is_derived_from(&self, password: &str) -> bool {
+        Self::derive(self.algorithm, password, self.salt.clone(), self.iterations) == *self
+    }
+}
+
+impl Display for ScramSecret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}{}${}${}${}",
+            self.algorithm.secret_prefix(),
+            self.iterations,
+            STANDARD.encode(&self.salt),
+            STANDARD.encode(&self.stored_key),
+            STANDARD.encode(&self.server_key)
+        )
+    }
+}
+
+impl ScramServer {
+    // The channel binding data is the tls-exporter value of the connection, if any
+    pub fn new(algorithm: ScramAlgorithm, plus: bool, channel_binding: Option<Vec<u8>>) -> Self {
+        ScramServer {
+            algorithm,
+            plus,
+            channel_binding,
+            gs2_header: String::new(),
+            client_first_bare: String::new(),
+            server_first: String::new(),
+            nonce: String::new(),
+            username: String::new(),
+        }
+    }
+
+    pub fn algorithm(&self) -> ScramAlgorithm {
+        self.algorithm
+    }
+
+    pub fn is_plus(&self) -> bool {
+        self.plus
+    }
+
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+
+    // Parses the client-first message and returns the authentication identity
+    pub fn client_first(&mut self, message: &[u8]) -> trc::Result<&str> {
+        let message = std::str::from_utf8(message).map_err(|_| invalid_message())?;
+        let (cbind_flag, message) = message.split_once(',').ok_or_else(invalid_message)?;
+        let (authzid, client_first_bare) = message.split_once(',').ok_or_else(invalid_message)?;
+
+        match (cbind_flag, self.plus) {
+            ("n", false) => (),
+            ("y", false) if self.channel_binding.is_none() => (),
+            ("p=tls-exporter", true) if self.channel_binding.is_some() => (),
+            ("y", false) => {
+                // The client supports channel binding but believes the server does not
+                return Err(scram_error("Channel binding downgrade detected"));
+            }
+            (flag, true) if flag.starts_with("p=") => {
+                return Err(scram_error("Unsupported channel binding type"));
+            }
+            _ => return Err(scram_error("Channel binding mismatch")),
+        }
+
+        let mut username = None;
+        let mut nonce = None;
+        for (pos, attribute) in client_first_bare.split(',').enumerate() {
+            match (pos, attribute.split_once('=')) {
+                (0, Some(("n", value))) => username = decode_sasl_name(value),
+                (1, Some(("r", value))) if !value.is_empty() => nonce = Some(value),
+                (2.., _) => (),
+                _ => return Err(invalid_message()),
+            }
+        }
+        let (Some(username), Some(nonce)) = (username, nonce) else {
+            return Err(invalid_message());
+        };
+
+        // Authorization identities other than the authenticated user are not supported
+        if !authzid.is_empty()
+            && authzid
+                .strip_prefix("a=")
+                .and_then(decode_sasl_name)
+                .map_or(true, |authzid| authzid != username)
+        {
+            return Err(scram_error("Authorization identity not supported"));
+        }
+
+        self.gs2_header = format!("{cbind_flag},{authzid},");
+        self.client_first_bare = client_first_bare.to_string();
+        self.nonce = nonce.to_string();
+        self.username = username;
+
+        Ok(&self.username)
+    }
+
+    pub fn server_first(&mut self, secret: &ScramSecret) -> Vec<u8> {
+        self.nonce.extend(
+            thread_rng()
+                .sample_iter(Alphanumeric)
+                .take(SCRAM_NONCE_LEN)
+                .map(char::from),
+        );
+        self.server_first = format!(
+            "r={},s={},i={}",
+            self.nonce,
+            STANDARD.encode(&secret.salt),
+            secret.iterations
+        );
+        self.server_first.as_bytes().to_vec()
+    }
+
+    // Returns the server-final message, or None if the client proof is invalid
+    pub fn client_final(
+        &self,
+        message: &[u8],
+        secret: &ScramSecret,
+    ) -> trc::Result<Option<Vec<u8>>> {
+        let message = std::str::from_utf8(message).map_err(|_| invalid_message())?;
+        let (without_proof, proof) = message.rsplit_once(",p=").ok_or_else(invalid_message)?;
+        let mut attributes = without_proof.split(',');
+        let channel_binding = attributes
+            .next()
+            .and_then(|value| value.strip_prefix("c="))
+            .and_then(|value| STANDARD.decode(value).ok())
+            .ok_or_else(invalid_message)?;
+        let nonce = attributes
+            .next()
+            .and_then(|value| value.strip_prefix("r="))
+            .ok_or_else(invalid_message)?;
+        let proof = STANDARD.decode(proof).map_err(|_| invalid_message())?;
+
+        // Verify channel binding
+        let mut expected_binding = self.gs2_header.as_bytes().to_vec();
+        if self.plus {
+            expected_binding.extend_from_slice(self.channel_binding.as_deref().unwrap_or_default());
+        }
+        if channel_binding != expected_binding {
+            return Err(scram_error("Channel binding mismatch"));
+        } else if nonce != self.nonce {
+            return Err(scram_error("Nonce mismatch"));
+        }
+
+        // Verify proof
+        let auth_message = format!(
+            "{},{},{}",
+            self.client_first_bare, self.server_first, without_proof
+        );
+        let client_signature = self
+            .algorithm
+            .hmac(&secret.stored_key, auth_message.as_bytes());
+        if proof.len() != client_signature.len() {
+            return Ok(None);
+        }
+        let client_key = proof
+            .iter()
+            .zip(client_signature)
+            .map(|(a, b)| a ^ b)
+            .collect::<Vec<_>>();
+        if !constant_time_eq(&self.algorithm.hash(&client_key), &secret.stored_key) {
+            return Ok(None);
+        }
+
+        let server_signature = self
+            .algorithm
+            .hmac(&secret.server_key, auth_message.as_bytes());
+        Ok(Some(
+            format!("v={}", STANDARD.encode(server_signature)).into_bytes(),
+        ))
+    }
+}
+
+impl Principal {
+    // Returns the SCRAM credentials of the principal. Credentials are stored when
+    // a plaintext password is set; for passwords set before that, they are derived
+    // on every attempt, which runs PBKDF2 for SCRAM_ITERATIONS rounds, using the
+    // same salt an unknown user would get. Principals with hashed passwords cannot
+    // use SCRAM, and principals with TOTP enabled cannot use challenge-response
+    // mechanisms as there is no way to send the code.
+    pub fn scram_secret(
+        &self,
+        algorithm: ScramAlgorithm,
+        server_key: &[u8],
+    ) -> Option<ScramSecret> {
+        if self.has_otp_auth() {
+            return None;
+        }
+
+        self.iter_str(PrincipalField::Secrets)
+            .filter_map(|secret| ScramSecret::parse(secret))
+            .find(|secret| secret.algorithm == algorithm)
+            .or_else(|| {
+                self.plaintext_secret().map(|password| {
+                    ScramSecret::derive(
+                        algorithm,
+                        password,
+                        algorithm.derived_salt(server_key, self.name()),
+                        SCRAM_ITERATIONS,
+                    )
+                })
+            })
+    }
+
+    // Returns the password of the principal if it is stored in plain text
+    pub fn plaintext_secret(&self) -> Option<&str> {
+        if self.has_otp_auth() {
+            return None;
+        }
+
+        self.iter_str(PrincipalField::Secrets)
+            .filter(|secret| secret.is_password())
+            .find_map(|secret| plaintext_password(secret))
+    }
+
+    fn has_otp_auth(&self) -> bool {
+        self.iter_str(PrincipalField::Secrets)
+            .any(|secret| secret.is_otp_auth())
+    }
+}
+
+pub fn plaintext_password(secret: &str) -> Option<&str> {
+    if let Some(secret) = secret.strip_prefix('{') {
+        let (algorithm, secret) = secret.split_once('}')?;
+        matches!(algorithm, "PLAIN" | "plain" | "CLEAR" | "clear").then_some(secret)
+    } else if secret.is_empty() || secret.starts_with('$') || secret.starts_with('_') {
+        None
+    } else {
+        Some(secret)
+    }
+}
+
+// SCRAM credentials stored when a plaintext password is set
+pub fn generate_scram_secrets(password: &str) -> impl Iterator<Item = String> + '_ {
+    [ScramAlgorithm::Sha1, ScramAlgorithm::Sha256]
+        .into_iter()
+        .map(move |algorithm| ScramSecret::generate(algorithm, password).to_string())
+}
+
+pub fn cram_md5_challenge(hostname: &str) -> String {
+    format!("<{}.{}@{}>", thread_rng().gen::<u32>(), now(), hostname)
+}
+
+// Verifies the hex encoded HMAC-MD5 digest of a CRAM-MD5 response (RFC 2195)
+pub fn verify_cram_md5(password: &str, challenge: &str, digest: &str) -> bool {
+    let expected = hmac_md5(password.as_bytes(), challenge.as_bytes())
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+    constant_time_eq(expected.as_bytes(), digest.to_ascii_lowercase().as_bytes())
+}
+
+pub fn hmac_md5(key: &[u8], data: &[u8]) -> [u8; 16] {
+    let mut block = [0u8; 64];
+    if key.len() > block.len() {
+        block[..16].copy_from_slice(&md5::compute(key).0);
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+
+    let mut inner = md5::Context::new();
+    inner.consume(block.map(|byte| byte ^ 0x36));
+    inner.consume(data);
+    let mut outer = md5::Context::new();
+    outer.consume(block.map(|byte| byte ^ 0x5c));
+    outer.consume(inner.compute().0);
+    outer.compute().0
+}
+
+fn decode_sasl_name(value: &str) -> Option<String> {
+    let mut parts = value.split('=');
+    let mut name = parts.next()?.to_string();
+    for part in parts {
+        if let Some(part) = part.strip_prefix("2C") {
+            name.push(',');
+            name.push_str(part);
+        } else if let Some(part) = part.strip_prefix("3D") {
+            name.push('=');
+            name.push_str(part);
+        } else {
+            return None;
+        }
+    }
+
+    Some(name).filter(|name| !name.is_empty())
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+fn invalid_message() -> trc::Error {
+    scram_error("Invalid SCRAM message")
+}
+
+fn scram_error(details: &'static str) -> trc::Error {
+    trc::AuthEvent::Error.into_err().details(details)
+}
+
+#[cfg(test)]
+mod test {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+
+    use super::{verify_cram_md5, ScramAlgorithm, ScramSecret, ScramServer, SCRAM_SALT_LEN};
+    use crate::{backend::internal::PrincipalField, Principal, Type};
+
+    fn exchange(
+        algorithm: ScramAlgorithm,
+        client_first: &str,
+        server_nonce: &str,
+        salt: &str,
+        client_final: &str,
+    ) -> Option<String> {
+        let secret = ScramSecret::derive(algorithm, "pencil", STANDARD.decode(salt).unwrap(), 4096);
+        let mut server = ScramServer::new(algorithm, false, None);
+        assert_eq!(
+            server.client_first(client_first.as_bytes()).unwrap(),
+            "user"
+        );
+        server.server_first(&secret);
+
+        // Replace the random nonce with the one from the test vector
+        server.nonce = server_nonce.to_string();
+        server.server_first = format!("r={server_nonce},s={salt},i=4096");
+
+        server
+            .client_final(client_final.as_bytes(), &secret)
+            .unwrap()
+            .map(|response| String::from_utf8(response).unwrap())
+    }
+
+    #[test]
+    fn scram_exchange() {
+        // RFC 5802 test vector
+        assert_eq!(
+            exchange(
+                ScramAlgorithm::Sha1,
+                "n,,n=user,r=fyko+d2lbbFgONRv9qkxdawL",
+                "fyko+d2lbbFgONRv9qkxdawL3rfcNHYJY1ZVvWVs7j",
+                "QSXCR+Q6sek8bf92",
+                concat!(
+                    "c=biws,r=fyko+d2lbbFgONRv9qkxdawL3rfcNHYJY1ZVvWVs7j,",
+                    "p=v0X8v3Bz2T0CJGbJQyF0X+HI4Ts="
+                ),
+            )
+            .as_deref(),
+            Some("v=rmF9pqV8S7suAoZWja4dJRkFsKQ=")
+        );
+
+        // RFC 7677 test vector
+        assert_eq!(
+            exchange(
+                ScramAlgorithm::Sha256,
+                "n,,n=user,r=rOprNGfwEbeRWgbNEkqO",
+                "rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0",
+                "W22ZaJ0SNY7soEsUEjb6gQ==",
+                concat!(
+                    "c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,",
+                    "p=dHzbZapWIk4jUhN+Ute9ytag9zjfMHgsqmmiz7AndVQ="
+                ),
+            )
+            .as_deref(),
+            Some("v=6rriTRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4=")
+        );
+
+        // Invalid proof
+        assert_eq!(
+            exchange(
+                ScramAlgorithm::Sha1,
+                "n,,n=user,r=fyko+d2lbbFgONRv9qkxdawL",
+                "fyko+d2lbbFgONRv9qkxdawL3rfcNHYJY1ZVvWVs7j",
+                "QSXCR+Q6sek8bf92",
+                concat!(
+                    "c=biws,r=fyko+d2lbbFgONRv9qkxdawL3rfcNHYJY1ZVvWVs7j,",
+                    "p=v1X8v3Bz2T0CJGbJQyF0X+HI4Ts="
+                ),
+            ),
+            None
+        );
+
+        // Stored secrets
+        let secret = ScramSecret::generate(ScramAlgorithm::Sha256, "pencil");
+        let parsed = ScramSecret::parse(&secret.to_string()).unwrap();
+        assert_eq!(parsed, secret);
+        assert!(parsed.is_derived_from("pencil"));
+        assert!(!parsed.is_derived_from("pencil2"));
+    }
+
+    #[test]
+    fn scram_unknown_user() {
+        // Unknown users get the same salt on every attempt
+        let secret = ScramSecret::unknown(ScramAlgorithm::Sha256, b"key", "john");
+        assert_eq!(secret.salt.len(), SCRAM_SALT_LEN);
+        assert_eq!(
+            secret,
+            ScramSecret::unknown(ScramAlgorithm::Sha256, b"key", "john")
+        );
+        assert_ne!(
+            secret.salt,
+            ScramSecret::unknown(ScramAlgorithm::Sha256, b"key", "jane").salt
+        );
+        assert_ne!(
+            secret.salt,
+            ScramSecret::unknown(ScramAlgorithm::Sha256, b"other", "john").salt
+        );
+
+        // Credentials derived from a plaintext password use the same salt
+        let principal = Principal::new(0, Type::Individual)
+            .with_field(PrincipalField::Name, "john")
+            .with_field(PrincipalField::Secrets, "pencil");
+        let derived = principal
+            .scram_secret(ScramAlgorithm::Sha256, b"key")
+            .unwrap();
+        assert_eq!(derived.salt, secret.salt);
+        assert!(derived.is_derived_from("pencil"));
+    }
+
+    #[test]
+    fn scram_channel_binding() {
+        let cb = Some(vec![1, 2, 3]);
+        for (plus, channel_binding, message, expect_ok) in [
+            (true, cb.clone(), "p=tls-exporter,,n=user,r=abc", true),
+            (true, cb.clone(), "p=tls-unique,,n=user,r=abc", false),
+            (true, cb.clone(), "n,,n=user,r=abc", false),
+            (false, cb.clone(), "y,,n=user,r=abc", false),
+            (false, None, "y,,n=user,r=abc", true),
+            (false, cb.clone(), "n,a=user,n=user,r=abc", true),
+            (false, cb.clone(), "n,a=admin,n=user,r=abc", false),
+        ] {
+            let mut server = ScramServer::new(ScramAlgorithm::Sha256, plus, channel_binding);
+            assert_eq!(
+                server.client_first(message.as_bytes()).is_ok(),
+                expect_ok,
+                "{message}"
+            );
+        }
+
+        // Channel binding data must match the TLS connection
+        let secret = ScramSecret::generate(ScramAlgorithm::Sha256, "pencil");
+        let mut server = ScramServer::new(ScramAlgorithm::Sha256, true, cb);
+        server
+            .client_first(b"p=tls-exporter,,n=user,r=abc")
+            .unwrap();
+        server.server_first(&secret);
+        let message = format!(
+            "c={},r={},p=AAAA",
+            STANDARD.encode(b"p=tls-exporter,,\x01\x02\x04"),
+            server.nonce
+        );
+        assert!(server.client_final(message.as_bytes(), &secret).is_err());
+    }
+
+    #[test]
+    fn cram_md5() {
+        // RFC 2195 test vector
+        let challenge = "<1896.697170952@postoffice.reston.mci.net>";
+        assert!(verify_cram_md5(
+            "tanstaaftanstaaf",
+            challenge,
+            "b913a602c7eda7a495b4e6e7334d3890"
+        ));
+        assert!(!verify_cram_md5(
+            "tanstaaftanstaaF",
+            challenge,
+            "b913a602c7eda7a495b4e6e7334d3890"
+        ));
+    }
+}
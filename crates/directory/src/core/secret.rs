@@ -22,6 +22,8 @@ use crate::backend::internal::PrincipalField;
 use crate::backend::internal::SpecialSecrets;
 use crate::Principal;
 
+use super::totp::{is_recovery_code, is_totp_code};
+
 impl Principal {
     pub async fn verify_secret(&self, mut code: &str) -> trc::Result<bool> {
         let mut totp_token = None;
@@ -38,12 +40,9 @@ impl Principal {
 
                     let totp_token = if let Some(totp_token) = totp_token {
                         totp_token
-                    } else if let Some((_code, _totp_token)) =
-                        code.rsplit_once('$').filter(|(c, t)| {
-                            !c.is_empty()
-                                && (6..=8).contains(&t.len())
-                                && t.as_bytes().iter().all(|b| b.is_ascii_digit())
-                        })
+                    } else if let Some((_code, _totp_token)) = code
+                        .rsplit_once('$')
+                        .filter(|(c, t)| !c.is_empty() && (is_totp_code(t) || is_recovery_code(t)))
                     {
                         totp_token = Some(_totp_token);
                         code = _code;
@@ -53,6 +52,11 @@ impl Principal {
                         continue;
                     };
 
+                    // Recovery codes are verified once all secrets have been checked
+                    if !is_totp_code(totp_token) {
+                        continue;
+                    }
+
                     // Token needs to validate with at least one of the TOTP secrets
                     is_totp_verified = TOTP::from_url(secret)
                         .map_err(|err| {
@@ -63,7 +67,11 @@ impl Principal {
                         .check_current(totp_token)
                         .unwrap_or(false);
                 }
-            } else if !is_authenticated && !is_app_authenticated {
+            } else if !is_authenticated
+                && !is_app_authenticated
+                && !secret.is_scram()
+                && !secret.is_recovery_code()
+            {
                 if let Some((_, app_secret)) =
                     secret.strip_prefix("$app$").and_then(|s| s.split_once('$'))
                 {
@@ -74,6 +82,13 @@ impl Principal {
             }
         }
 
+        // Recovery codes can be used in place of a TOTP code
+        if is_totp_required && !is_totp_verified && !is_app_authenticated {
+            if let Some(recovery_code) = totp_token.filter(|token| is_recovery_code(token)) {
+                is_totp_verified = self.verify_recovery_code(recovery_code).await?.is_some();
+            }
+        }
+
         if is_authenticated {
             if !is_totp_required {
                 // Authenticated without TOTP enabled
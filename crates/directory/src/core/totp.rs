@@ -0,0 +1,216 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use pwhash::sha512_crypt;
+use store::rand::{distributions::Alphanumeric, thread_rng, Rng};
+use totp_rs::{Algorithm, TOTP};
+
+use crate::{
+    backend::internal::{PrincipalField, SpecialSecrets},
+    Principal,
+};
+
+use super::secret::verify_secret_hash;
+
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+// Recovery codes are stored as "$recovery$<hash>" and have the form "xxxxx-xxxxx"
+pub fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let code = thread_rng()
+                .sample_iter(Alphanumeric)
+                .take(10)
+                .map(|ch| char::from(ch).to_ascii_lowercase())
+                .collect::<String>();
+            format!("{}-{}", &code[..5], &code[5..])
+        })
+        .collect()
+}
+
+pub fn hash_recovery_code(code: &str) -> trc::Result<String> {
+    sha512_crypt::hash(code)
+        .map(|hash| format!("$recovery${hash}"))
+        .map_err(|err| {
+            trc::AuthEvent::Error
+                .into_err()
+                .reason(err)
+                .details("Failed to hash recovery code")
+        })
+}
+
+pub fn is_recovery_code(code: &str) -> bool {
+    code.len() == 11
+        && code.bytes().enumerate().all(|(pos, ch)| {
+            if pos == 5 {
+                ch == b'-'
+            } else {
+                ch.is_ascii_alphanumeric()
+            }
+        })
+}
+
+pub fn is_totp_code(code: &str) -> bool {
+    (6..=8).contains(&code.len()) && code.bytes().all(|ch| ch.is_ascii_digit())
+}
+
+// Generates a new TOTP secret, returned as an "otpauth://" provisioning URL
+pub fn generate_totp_url(issuer: &str, account_name: &str) -> trc::Result<String> {
+    let mut secret = vec![0u8; 20];
+    thread_rng().fill(secret.as_mut_slice());
+
+    TOTP::new(
+        Algorithm::SHA1,
+        6,
+        1,
+        30,
+        secret,
+        Some(issuer.to_string()),
+        account_name.to_string(),
+    )
+    .map(|totp| totp.get_url())
+    .map_err(|err| {
+        trc::AuthEvent::Error
+            .into_err()
+            .reason(err)
+            .details("Failed to generate TOTP secret")
+    })
+}
+
+// Returns the time step of a code that is valid at the provided time, allowing
+// for the configured skew and rejecting steps at or before the last accepted one
+pub fn totp_time_step(
+    url: &str,
+    code: &str,
+    now: u64,
+    last_step: Option<u64>,
+) -> trc::Result<Option<u64>> {
+    let totp = TOTP::from_url(url).map_err(|err| {
+        trc::AuthEvent::Error
+            .into_err()
+            .reason(err)
+            .details(url.to_string())
+    })?;
+    let skew = totp.skew as u64 * totp.step;
+
+    Ok((now.saturating_sub(skew)..=now + skew)
+        .step_by(totp.step as usize)
+        .map(|time| time / totp.step)
+        .filter(|step| last_step.map_or(true, |last_step| *step > last_step))
+        .find(|step| totp.generate(step * totp.step) == code))
+}
+
+impl Principal {
+    pub fn has_totp(&self) -> bool {
+        self.iter_str(PrincipalField::Secrets)
+            .any(|secret| secret.is_otp_auth())
+    }
+
+    pub fn totp_time_step(
+        &self,
+        code: &str,
+        now: u64,
+        last_step: Option<u64>,
+    ) -> trc::Result<Option<u64>> {
+        for secret in self.iter_str(PrincipalField::Secrets) {
+            if secret.is_otp_auth() {
+                if let Some(step) = totp_time_step(secret, code, now, last_step)? {
+                    return Ok(Some(step));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    // Returns the stored secret of the recovery code matching the provided one
+    pub async fn verify_recovery_code(&self, code: &str) -> trc::Result<Option<&str>> {
+        for secret in self.iter_str(PrincipalField::Secrets) {
+            if let Some(hash) = secret.strip_prefix("$recovery$") {
+                if verify_secret_hash(hash, code).await? {
+                    return Ok(Some(secret));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    pub fn recovery_codes(&self) -> usize {
+        self.iter_str(PrincipalField::Secrets)
+            .filter(|secret| secret.is_recovery_code())
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        generate_recovery_codes, generate_totp_url, is_recovery_code, totp_time_step,
+        RECOVERY_CODE_COUNT,
+    };
+
+    // RFC 6238 test secret "12345678901234567890"
+    const URL: &str = concat!(
+        "otpauth://totp/Test:user?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ",
+        "&issuer=Test&digits=8&period=30&algorithm=SHA1"
+    );
+
+    #[test]
+    fn totp_time_steps() {
+        // RFC 6238 test vectors
+        for (time, code, step) in [
+            (59, "94287082", 1),
+            (1111111109, "07081804", 37037036),
+            (1111111111, "14050471", 37037037),
+            (1234567890, "89005924", 41152263),
+        ] {
+            assert_eq!(totp_time_step(URL, code, time, None).unwrap(), Some(step));
+        }
+
+        // Codes are accepted one step before and after the current one
+        assert_eq!(totp_time_step(URL, "94287082", 29, None).unwrap(), Some(1));
+        assert_eq!(totp_time_step(URL, "94287082", 89, None).unwrap(), Some(1));
+        assert_eq!(
+            totp_time_step(URL, "07081804", 1111111111, None).unwrap(),
+            Some(37037036)
+        );
+
+        // Expired codes are rejected
+        assert_eq!(totp_time_step(URL, "94287082", 119, None).unwrap(), None);
+        assert_eq!(
+            totp_time_step(URL, "07081804", 1234567890, None).unwrap(),
+            None
+        );
+
+        // Replayed codes are rejected
+        assert_eq!(totp_time_step(URL, "94287082", 59, Some(1)).unwrap(), None);
+        assert_eq!(
+            totp_time_step(URL, "94287082", 59, Some(0)).unwrap(),
+            Some(1)
+        );
+        assert_eq!(
+            totp_time_step(URL, "14050471", 1111111111, Some(37037036)).unwrap(),
+            Some(37037037)
+        );
+
+        // Invalid codes are rejected
+        assert_eq!(totp_time_step(URL, "12345678", 59, None).unwrap(), None);
+    }
+
+    #[test]
+    fn generated_secrets() {
+        let url = generate_totp_url("mail.example.org", "john@example.org").unwrap();
+        assert!(url.starts_with("otpauth://totp/"), "{url}");
+        assert!(totp_time_step(&url, "", 0, None).unwrap().is_none());
+
+        let codes = generate_recovery_codes();
+        assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+        assert!(codes.iter().all(|code| is_recovery_code(code)), "{codes:?}");
+        assert!(!is_recovery_code("123456"));
+        assert!(!is_recovery_code("abcde_fghij"));
+    }
+}
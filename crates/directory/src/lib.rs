@@ -264,7 +264,22 @@ pub enum Permission {
     OauthClientOverride,
 
     AiModelInteract,
-    Troubleshoot, // WARNING: add new ids at the end (TODO: use static ids)
+    Troubleshoot,
+    StoreStats,
+    RateLimitGet,
+    SpamFilterTrain,
+
+    // Quarantine
+    QuarantineList,
+    QuarantineGet,
+    QuarantineRelease,
+    QuarantineDelete,
+    ManageQuarantine,
+
+    ImapXApplePushService,
+    AuditLogView,
+    AccountImport,
+    AccountMigrate, // WARNING: add new ids at the end (TODO: use static ids)
 }
 
 pub type Permissions = Bitset<{ Permission::COUNT.div_ceil(std::mem::size_of::<usize>()) }>;
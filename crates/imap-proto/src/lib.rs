@@ -72,6 +72,9 @@ pub enum Command {
 
     // RFC 2971
     Id,
+
+    // Apple push notifications
+    XApplePushService,
 }
 
 impl Command {
@@ -23,6 +23,7 @@ pub mod status;
 pub mod store;
 pub mod subscribe;
 pub mod thread;
+pub mod xapplepushservice;
 
 use std::{borrow::Cow, str::FromStr};
 
@@ -77,6 +78,7 @@ impl CommandParser for Command {
             b"MYRIGHTS" => Some(Command::MyRights),
             b"UNAUTHENTICATE" => Some(Command::Unauthenticate),
             b"ID" => Some(Command::Id),
+            b"XAPPLEPUSHSERVICE" => Some(Command::XApplePushService),
             _ => None,
         }
     }
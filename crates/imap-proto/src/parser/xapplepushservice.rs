@@ -0,0 +1,178 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use crate::{
+    protocol::{xapplepushservice, ProtocolVersion},
+    receiver::{bad, Request, Token},
+    utf7::utf7_maybe_decode,
+    Command,
+};
+
+use super::parse_number;
+
+impl Request<Command> {
+    pub fn parse_xapplepushservice(
+        self,
+        version: ProtocolVersion,
+    ) -> trc::Result<xapplepushservice::Arguments> {
+        let mut arguments = xapplepushservice::Arguments {
+            tag: self.tag,
+            version: 0,
+            account_id: String::new(),
+            device_token: String::new(),
+            subtopic: String::new(),
+            mailboxes: Vec::new(),
+        };
+        let mut tokens = self.tokens.into_iter();
+
+        while let Some(token) = tokens.next() {
+            let param = token.unwrap_bytes();
+            let value = tokens.next().ok_or_else(|| {
+                bad(
+                    arguments.tag.clone(),
+                    format!(
+                        "Missing value for parameter '{}'.",
+                        String::from_utf8_lossy(&param)
+                    ),
+                )
+            })?;
+
+            if param.eq_ignore_ascii_case(b"aps-version") {
+                arguments.version = parse_number::<u32>(&value.unwrap_bytes())
+                    .map_err(|v| bad(arguments.tag.clone(), v))?;
+            } else if param.eq_ignore_ascii_case(b"aps-account-id") {
+                arguments.account_id = value
+                    .unwrap_string()
+                    .map_err(|v| bad(arguments.tag.clone(), v))?;
+            } else if param.eq_ignore_ascii_case(b"aps-device-token") {
+                arguments.device_token = value
+                    .unwrap_string()
+                    .map_err(|v| bad(arguments.tag.clone(), v))?;
+            } else if param.eq_ignore_ascii_case(b"aps-subtopic") {
+                arguments.subtopic = value
+                    .unwrap_string()
+                    .map_err(|v| bad(arguments.tag.clone(), v))?;
+            } else if param.eq_ignore_ascii_case(b"mailboxes") {
+                if !value.is_parenthesis_open() {
+                    return Err(bad(
+                        arguments.tag,
+                        "Expected parenthesis after 'mailboxes'.",
+                    ));
+                }
+
+                #[allow(clippy::while_let_on_iterator)]
+                while let Some(token) = tokens.next() {
+                    match token {
+                        Token::ParenthesisClose => break,
+                        Token::Argument(value) => {
+                            arguments.mailboxes.push(utf7_maybe_decode(
+                                String::from_utf8(value).map_err(|_| {
+                                    bad(arguments.tag.clone(), "Invalid UTF-8 mailbox name.")
+                                })?,
+                                version,
+                            ));
+                        }
+                        _ => {
+                            return Err(bad(arguments.tag, "Invalid mailbox name."));
+                        }
+                    }
+                }
+            } else {
+                return Err(bad(
+                    arguments.tag,
+                    format!(
+                        "Unsupported parameter '{}'.",
+                        String::from_utf8_lossy(&param)
+                    ),
+                ));
+            }
+        }
+
+        if !matches!(arguments.version, 1 | 2) {
+            Err(bad(arguments.tag, "Unsupported aps-version."))
+        } else if arguments.account_id.is_empty() || arguments.device_token.is_empty() {
+            Err(bad(
+                arguments.tag,
+                "Missing aps-account-id or aps-device-token.",
+            ))
+        } else {
+            // Version 1 clients only receive notifications for the inbox
+            if arguments.mailboxes.is_empty() {
+                arguments.mailboxes.push("INBOX".to_string());
+            }
+            Ok(arguments)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        protocol::{xapplepushservice, ProtocolVersion},
+        receiver::Receiver,
+    };
+
+    #[test]
+    fn parse_xapplepushservice() {
+        let mut receiver = Receiver::new();
+
+        for (command, arguments) in [
+            (
+                concat!(
+                    "a XAPPLEPUSHSERVICE aps-version 2 ",
+                    "aps-account-id 0715A26B-CA09-4730-A419-793000CA982E ",
+                    "aps-device-token 2918390218931890821908309283098109381029309829018310983092892829 ",
+                    "aps-subtopic com.apple.mobilemail mailboxes (INBOX \"Sent Items\")\r\n"
+                ),
+                xapplepushservice::Arguments {
+                    tag: "a".to_string(),
+                    version: 2,
+                    account_id: "0715A26B-CA09-4730-A419-793000CA982E".to_string(),
+                    device_token:
+                        "2918390218931890821908309283098109381029309829018310983092892829"
+                            .to_string(),
+                    subtopic: "com.apple.mobilemail".to_string(),
+                    mailboxes: vec!["INBOX".to_string(), "Sent Items".to_string()],
+                },
+            ),
+            (
+                concat!(
+                    "b XAPPLEPUSHSERVICE aps-version 1 aps-account-id 1234 ",
+                    "aps-device-token abcd aps-subtopic com.apple.mobilemail\r\n"
+                ),
+                xapplepushservice::Arguments {
+                    tag: "b".to_string(),
+                    version: 1,
+                    account_id: "1234".to_string(),
+                    device_token: "abcd".to_string(),
+                    subtopic: "com.apple.mobilemail".to_string(),
+                    mailboxes: vec!["INBOX".to_string()],
+                },
+            ),
+        ] {
+            assert_eq!(
+                receiver
+                    .parse(&mut command.as_bytes().iter())
+                    .unwrap()
+                    .parse_xapplepushservice(ProtocolVersion::Rev2)
+                    .unwrap(),
+                arguments
+            );
+        }
+
+        for command in [
+            "c XAPPLEPUSHSERVICE aps-version 3 aps-account-id 1 aps-device-token 2\r\n",
+            "d XAPPLEPUSHSERVICE aps-version 2 aps-account-id 1\r\n",
+            "e XAPPLEPUSHSERVICE aps-version 2 aps-account-id 1 aps-device-token 2 foo bar\r\n",
+        ] {
+            assert!(receiver
+                .parse(&mut command.as_bytes().iter())
+                .unwrap()
+                .parse_xapplepushservice(ProtocolVersion::Rev2)
+                .is_err());
+        }
+    }
+}
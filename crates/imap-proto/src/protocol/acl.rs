@@ -233,7 +233,7 @@ impl From<Rights> for Acl {
         match value {
             Rights::Lookup => Acl::Read,
             Rights::Read => Acl::ReadItems,
-            Rights::Seen => Acl::ModifyItems,
+            Rights::Seen => Acl::SetSeen,
             Rights::Write => Acl::ModifyItems,
             Rights::Insert => Acl::AddItems,
             Rights::Post => Acl::Submit,
@@ -48,6 +48,7 @@ pub enum Capability {
     ObjectId,
     Preview,
     Utf8Accept,
+    XApplePushService,
     Auth(Mechanism),
 }
 
@@ -94,6 +95,7 @@ impl Capability {
             Capability::CreateSpecialUse => b"CREATE-SPECIAL-USE",
             Capability::Move => b"MOVE",
             Capability::Utf8Accept => b"UTF8=ACCEPT",
+            Capability::XApplePushService => b"XAPPLEPUSHSERVICE",
         });
     }
 
@@ -0,0 +1,58 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use super::{quoted_string, ImapResponse};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Arguments {
+    pub tag: String,
+    pub version: u32,
+    pub account_id: String,
+    pub device_token: String,
+    pub subtopic: String,
+    pub mailboxes: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    pub version: u32,
+    pub topic: String,
+}
+
+impl ImapResponse for Response {
+    fn serialize(self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(64);
+        buf.extend_from_slice(b"* XAPPLEPUSHSERVICE aps-version ");
+        quoted_string(&mut buf, &self.version.to_string());
+        buf.extend_from_slice(b" aps-topic ");
+        quoted_string(&mut buf, &self.topic);
+        buf.extend_from_slice(b"\r\n");
+        buf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::{xapplepushservice::Response, ImapResponse};
+
+    #[test]
+    fn serialize_xapplepushservice() {
+        assert_eq!(
+            String::from_utf8(
+                Response {
+                    version: 2,
+                    topic: "com.apple.mail.XServer.1234".to_string(),
+                }
+                .serialize()
+            )
+            .unwrap(),
+            concat!(
+                "* XAPPLEPUSHSERVICE aps-version \"2\" ",
+                "aps-topic \"com.apple.mail.XServer.1234\"\r\n"
+            )
+        );
+    }
+}
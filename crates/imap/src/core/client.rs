@@ -27,6 +27,11 @@ impl<T: SessionStream> Session<T> {
             Contents = trc::Value::from_maybe_string(bytes),
         );
 
+        // Terminate sessions authenticated with a revoked app password
+        if self.is_app_password_revoked().await {
+            return SessionResult::Close;
+        }
+
         let mut bytes = bytes.iter();
         let mut requests = Vec::with_capacity(2);
         let mut needs_literal = None;
@@ -238,6 +243,10 @@ impl<T: SessionStream> Session<T> {
                     .handle_id(request)
                     .await
                     .map(|_| SessionResult::Continue),
+                Command::XApplePushService => self
+                    .handle_xapplepushservice(request)
+                    .await
+                    .map(|_| SessionResult::Continue),
             };
 
             match result {
@@ -263,6 +272,31 @@ impl<T: SessionStream> Session<T> {
 
         SessionResult::Continue
     }
+
+    pub async fn is_app_password_revoked(&self) -> bool {
+        match &self.app_password {
+            Some(app_password) if self.server.is_app_password_revoked(app_password).await => {
+                trc::event!(
+                    Security(SecurityEvent::Unauthorized),
+                    SpanId = self.session_id,
+                    Details = "App password revoked",
+                );
+
+                self.write_bytes(&b"* BYE App password revoked.\r\n"[..])
+                    .await
+                    .ok();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Only sessions using an app password of the notified account have to check
+    pub fn is_app_password_account(&self, account_id: u32) -> bool {
+        self.app_password
+            .as_ref()
+            .is_some_and(|app_password| app_password.account_id == account_id)
+    }
 }
 
 pub fn group_requests(
@@ -287,16 +321,22 @@ impl<T: SessionStream> Session<T> {
         // Rate limit request
         if let State::Authenticated { data } | State::Selected { data, .. } = state {
             if let Some(rate) = &self.server.core.imap.rate_requests {
-                if data
+                if let Some(retry_after) = data
                     .server
                     .core
                     .storage
                     .lookup
-                    .is_rate_allowed(format!("ireq:{}", data.account_id).as_bytes(), rate, true)
+                    .check_rate(
+                        format!("ireq:{}", data.account_id).as_bytes(),
+                        rate,
+                        self.server.core.imap.rate_window,
+                        true,
+                    )
                     .await?
-                    .is_some()
                 {
-                    return Err(trc::LimitEvent::TooManyRequests.into_err());
+                    return Err(trc::LimitEvent::TooManyRequests
+                        .into_err()
+                        .ctx(trc::Key::Expires, retry_after));
                 }
             }
         }
@@ -366,7 +406,8 @@ impl<T: SessionStream> Session<T> {
             | Command::GetAcl
             | Command::ListRights
             | Command::MyRights
-            | Command::Unauthenticate => {
+            | Command::Unauthenticate
+            | Command::XApplePushService => {
                 if let State::Authenticated { .. } | State::Selected { .. } = state {
                     Ok(request)
                 } else {
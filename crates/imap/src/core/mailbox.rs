@@ -267,7 +267,12 @@ impl<T: SessionStream> SessionData<T> {
                                 .into(),
                             total_unseen: self
                                 .server
-                                .mailbox_unread_tags(account_id, *mailbox_id, &message_ids)
+                                .mailbox_unread_tags(
+                                    account_id,
+                                    access_token,
+                                    *mailbox_id,
+                                    &message_ids,
+                                )
                                 .await
                                 .caused_by(trc::location!())?
                                 .map(|v| v.len() as u32)
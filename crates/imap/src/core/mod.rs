@@ -10,7 +10,7 @@ use std::{
 };
 
 use common::{
-    auth::AccessToken,
+    auth::{sasl::SaslExchange, AccessToken, SessionAppPassword},
     listener::{limiter::InFlight, ServerInstance, SessionStream},
     Account, ImapId, Inner, MailboxId, MailboxState, Server,
 };
@@ -51,6 +51,9 @@ pub struct Session<T: SessionStream> {
     pub in_flight: InFlight,
     pub remote_addr: IpAddr,
     pub session_id: u64,
+    pub channel_binding: Option<Vec<u8>>,
+    pub sasl: Option<SaslExchange>,
+    pub app_password: Option<SessionAppPassword>,
 }
 
 pub struct SessionData<T: SessionStream> {
@@ -48,6 +48,7 @@ impl<T: SessionStream> Session<T> {
     pub async fn handle_conn(&mut self) -> bool {
         let mut buf = vec![0; 8192];
         let mut shutdown_rx = self.instance.shutdown_rx.clone();
+        let mut revocations = self.server.subscribe_app_password_revocations();
 
         loop {
             tokio::select! {
@@ -108,6 +109,13 @@ impl<T: SessionStream> Session<T> {
                     );
                     self.write_bytes(&b"* BYE Server shutting down.\r\n"[..]).await.ok();
                     break;
+                },
+                Ok(account_id) = revocations.recv() => {
+                    if self.is_app_password_account(account_id)
+                        && self.is_app_password_revoked().await
+                    {
+                        break;
+                    }
                 }
             };
         }
@@ -139,6 +147,7 @@ impl<T: SessionStream> Session<T> {
         let _ = session.stream.flush().await;
 
         // Split stream into read and write halves
+        let channel_binding = session.stream.tls_exporter();
         let (stream_rx, stream_tx) = tokio::io::split(session.stream);
         let server = manager.inner.build_server();
 
@@ -154,6 +163,9 @@ impl<T: SessionStream> Session<T> {
             session_id: session.session_id,
             in_flight: session.in_flight,
             remote_addr: session.remote_ip,
+            channel_binding,
+            sasl: None,
+            app_password: None,
             stream_rx,
             stream_tx: Arc::new(tokio::sync::Mutex::new(stream_tx)),
         })
@@ -192,8 +204,9 @@ impl<T: SessionStream> Session<T> {
         };
 
         // Upgrade to TLS
-        let (stream_rx, stream_tx) =
-            tokio::io::split(self.instance.tls_accept(stream, self.session_id).await?);
+        let stream = self.instance.tls_accept(stream, self.session_id).await?;
+        let channel_binding = stream.tls_exporter();
+        let (stream_rx, stream_tx) = tokio::io::split(stream);
         let stream_tx = Arc::new(tokio::sync::Mutex::new(stream_tx));
 
         Ok(Session {
@@ -208,6 +221,9 @@ impl<T: SessionStream> Session<T> {
             session_id: self.session_id,
             in_flight: self.in_flight,
             remote_addr: self.remote_addr,
+            channel_binding,
+            sasl: None,
+            app_password: self.app_password,
             stream_rx,
             stream_tx,
         })
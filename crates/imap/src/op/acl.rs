@@ -6,7 +6,12 @@
 
 use std::{sync::Arc, time::Instant};
 
-use common::{auth::AccessToken, listener::SessionStream, MailboxId};
+use common::{
+    auth::AccessToken,
+    listener::SessionStream,
+    telemetry::audit::{AuditEvent, AuditRecord},
+    MailboxId,
+};
 use directory::{backend::internal::PrincipalField, Permission, QueryBy};
 use imap_proto::{
     protocol::acl::{
@@ -17,8 +22,8 @@ use imap_proto::{
 };
 
 use jmap::{
-    auth::acl::EffectiveAcl, changes::write::ChangeLog, mailbox::set::SCHEMA,
-    services::state::StateManager, JmapMethods,
+    auth::acl::EffectiveAcl, changes::write::ChangeLog, email::private::EmailPrivateKeywords,
+    mailbox::set::SCHEMA, services::state::StateManager, JmapMethods,
 };
 use jmap_proto::{
     object::{index::ObjectIndexBuilder, Object},
@@ -110,6 +115,11 @@ impl<T: SessionStream> Session<T> {
                                 Acl::Submit => {
                                     rights.push(Rights::Post);
                                 }
+                                Acl::SetSeen => {
+                                    if !item.grants.contains(Acl::ModifyItems) {
+                                        rights.push(Rights::Seen);
+                                    }
+                                }
                                 Acl::None => (),
                             }
                         }
@@ -172,8 +182,10 @@ impl<T: SessionStream> Session<T> {
                     rights.push(Rights::DeleteMessages);
                     rights.push(Rights::Expunge);
                 }
-                if acl.contains(Acl::ModifyItems) {
+                if acl.contains(Acl::SetSeen) {
                     rights.push(Rights::Seen);
+                }
+                if acl.contains(Acl::ModifyItems) {
                     rights.push(Rights::Write);
                 }
                 if acl.contains(Acl::CreateChild) {
@@ -237,148 +249,199 @@ impl<T: SessionStream> Session<T> {
         let command = request.command;
         let arguments = request.parse_acl(self.version)?;
         let data = self.state.session_data();
+        let remote_ip = self.remote_addr;
 
         spawn_op!(data, {
-            // Validate mailbox
-            let (mailbox, values, _) = data
-                .get_acl_mailbox(&arguments, false)
-                .await
-                .imap_ctx(&arguments.tag, trc::location!())?;
-
-            // Obtain principal id
-            let acl_account_id = data
-                .server
-                .core
-                .storage
-                .directory
-                .query(QueryBy::Name(arguments.identifier.as_ref().unwrap()), false)
-                .await
-                .imap_ctx(&arguments.tag, trc::location!())?
-                .ok_or_else(|| {
-                    trc::ImapEvent::Error
-                        .into_err()
-                        .details("Account does not exist")
-                        .id(arguments.tag.to_string())
-                        .caused_by(trc::location!())
-                })?
-                .id();
-
-            // Prepare changes
-            let mut changes = Object::with_capacity(1);
-            let (op, rights) = arguments
+            let requested_rights = arguments
                 .mod_rights
+                .as_ref()
                 .map(|mr| {
-                    (
-                        mr.op,
-                        Bitmap::from_iter(mr.rights.into_iter().map(Acl::from)),
-                    )
+                    mr.rights
+                        .iter()
+                        .map(|right| Acl::from(*right).to_string())
+                        .collect::<Vec<_>>()
                 })
-                .unwrap_or_else(|| (ModRightsOp::Replace, Bitmap::new()));
-            let acl = if let Value::Acl(acl) =
-                changes
-                    .properties
-                    .get_mut_or_insert_with(Property::Acl, || {
-                        values
-                            .inner
-                            .properties
-                            .get(&Property::Acl)
-                            .cloned()
-                            .unwrap_or_else(|| Value::Acl(Vec::new()))
-                    }) {
-                acl
-            } else {
-                return Err(trc::StoreEvent::DataCorruption
-                    .into_err()
-                    .id(arguments.tag)
-                    .ctx(trc::Key::Reason, "Invalid mailbox ACL")
-                    .caused_by(trc::location!()));
-            };
+                .unwrap_or_default();
+            let result: trc::Result<Vec<String>> = async {
+                // Validate mailbox
+                let (mailbox, values, _) = data
+                    .get_acl_mailbox(&arguments, false)
+                    .await
+                    .imap_ctx(&arguments.tag, trc::location!())?;
 
-            if let Some(item) = acl
-                .iter_mut()
-                .find(|item| item.account_id == acl_account_id)
-            {
-                match op {
-                    ModRightsOp::Replace => {
-                        if !rights.is_empty() {
-                            item.grants = rights;
-                        } else {
-                            acl.retain(|item| item.account_id != acl_account_id);
+                // Obtain principal id
+                let acl_account_id = data
+                    .server
+                    .core
+                    .storage
+                    .directory
+                    .query(QueryBy::Name(arguments.identifier.as_ref().unwrap()), false)
+                    .await
+                    .imap_ctx(&arguments.tag, trc::location!())?
+                    .ok_or_else(|| {
+                        trc::ImapEvent::Error
+                            .into_err()
+                            .details("Account does not exist")
+                            .id(arguments.tag.to_string())
+                            .caused_by(trc::location!())
+                    })?
+                    .id();
+
+                // Prepare changes
+                let mut changes = Object::with_capacity(1);
+                let (op, rights) = arguments
+                    .mod_rights
+                    .as_ref()
+                    .map(|mr| {
+                        (
+                            mr.op,
+                            Bitmap::from_iter(mr.rights.iter().copied().map(Acl::from)),
+                        )
+                    })
+                    .unwrap_or_else(|| (ModRightsOp::Replace, Bitmap::new()));
+                let acl = if let Value::Acl(acl) =
+                    changes
+                        .properties
+                        .get_mut_or_insert_with(Property::Acl, || {
+                            values
+                                .inner
+                                .properties
+                                .get(&Property::Acl)
+                                .cloned()
+                                .unwrap_or_else(|| Value::Acl(Vec::new()))
+                        }) {
+                    acl
+                } else {
+                    return Err(trc::StoreEvent::DataCorruption
+                        .into_err()
+                        .id(arguments.tag.clone())
+                        .ctx(trc::Key::Reason, "Invalid mailbox ACL")
+                        .caused_by(trc::location!()));
+                };
+
+                if let Some(item) = acl
+                    .iter_mut()
+                    .find(|item| item.account_id == acl_account_id)
+                {
+                    match op {
+                        ModRightsOp::Replace => {
+                            if !rights.is_empty() {
+                                item.grants = rights;
+                            } else {
+                                acl.retain(|item| item.account_id != acl_account_id);
+                            }
                         }
-                    }
-                    ModRightsOp::Add => {
-                        item.grants.union(&rights);
-                    }
-                    ModRightsOp::Remove => {
-                        for right in rights {
-                            item.grants.remove(right);
+                        ModRightsOp::Add => {
+                            item.grants.union(&rights);
+                        }
+                        ModRightsOp::Remove => {
+                            for right in rights {
+                                item.grants.remove(right);
+                            }
+                            if item.grants.is_empty() {
+                                acl.retain(|item| item.account_id != acl_account_id);
+                            }
                         }
-                        if item.grants.is_empty() {
-                            acl.retain(|item| item.account_id != acl_account_id);
+                    }
+                } else if !rights.is_empty() {
+                    match op {
+                        ModRightsOp::Add | ModRightsOp::Replace => {
+                            acl.push(AclGrant {
+                                account_id: acl_account_id,
+                                grants: rights,
+                            });
                         }
+                        ModRightsOp::Remove => (),
                     }
                 }
-            } else if !rights.is_empty() {
-                match op {
-                    ModRightsOp::Add | ModRightsOp::Replace => {
-                        acl.push(AclGrant {
-                            account_id: acl_account_id,
-                            grants: rights,
-                        });
-                    }
-                    ModRightsOp::Remove => (),
+
+                let grants = acl
+                    .iter()
+                    .map(|r| trc::Value::from(r.account_id))
+                    .collect::<Vec<_>>();
+                let granted_rights = acl
+                    .iter()
+                    .find(|item| item.account_id == acl_account_id)
+                    .map(|item| {
+                        item.grants
+                            .clone()
+                            .into_iter()
+                            .map(|right| right.to_string())
+                            .collect::<Vec<_>>()
+                    })
+                    .unwrap_or_default();
+
+                // Write changes
+                let mailbox_id = mailbox.mailbox_id;
+                let mut batch = BatchBuilder::new();
+                batch
+                    .with_account_id(mailbox.account_id)
+                    .with_collection(Collection::Mailbox)
+                    .update_document(mailbox_id)
+                    .custom(
+                        ObjectIndexBuilder::new(SCHEMA)
+                            .with_changes(changes)
+                            .with_current(values),
+                    );
+                if !batch.is_empty() {
+                    data.server
+                        .write_batch(batch)
+                        .await
+                        .imap_ctx(&arguments.tag, trc::location!())?;
+                    let mut changes = ChangeLogBuilder::new();
+                    changes.log_update(Collection::Mailbox, mailbox_id);
+                    let change_id = data
+                        .server
+                        .commit_changes(mailbox.account_id, changes)
+                        .await
+                        .imap_ctx(&arguments.tag, trc::location!())?;
+                    data.server
+                        .broadcast_state_change(
+                            StateChange::new(mailbox.account_id)
+                                .with_change(DataType::Mailbox, change_id),
+                        )
+                        .await;
                 }
-            }
 
-            let grants = acl
-                .iter()
-                .map(|r| trc::Value::from(r.account_id))
-                .collect::<Vec<_>>();
-
-            // Write changes
-            let mailbox_id = mailbox.mailbox_id;
-            let mut batch = BatchBuilder::new();
-            batch
-                .with_account_id(mailbox.account_id)
-                .with_collection(Collection::Mailbox)
-                .update_document(mailbox_id)
-                .custom(
-                    ObjectIndexBuilder::new(SCHEMA)
-                        .with_changes(changes)
-                        .with_current(values),
-                );
-            if !batch.is_empty() {
+                // Invalidate ACLs
+                data.server.invalidate_access_tokens(vec![acl_account_id]);
+
+                // Remove private keywords on messages that are no longer shared
                 data.server
-                    .write_batch(batch)
-                    .await
-                    .imap_ctx(&arguments.tag, trc::location!())?;
-                let mut changes = ChangeLogBuilder::new();
-                changes.log_update(Collection::Mailbox, mailbox_id);
-                let change_id = data
-                    .server
-                    .commit_changes(mailbox.account_id, changes)
+                    .private_keywords_revoke(mailbox.account_id, mailbox_id, acl_account_id)
                     .await
                     .imap_ctx(&arguments.tag, trc::location!())?;
-                data.server
-                    .broadcast_state_change(
-                        StateChange::new(mailbox.account_id)
-                            .with_change(DataType::Mailbox, change_id),
-                    )
-                    .await;
-            }
 
-            // Invalidate ACLs
-            data.server.inner.data.access_tokens.remove(&acl_account_id);
+                trc::event!(
+                    Imap(trc::ImapEvent::SetAcl),
+                    SpanId = data.session_id,
+                    MailboxName = arguments.mailbox_name.clone(),
+                    AccountId = mailbox.account_id,
+                    MailboxId = mailbox.mailbox_id,
+                    Details = grants,
+                    Elapsed = op_start.elapsed()
+                );
 
-            trc::event!(
-                Imap(trc::ImapEvent::SetAcl),
-                SpanId = data.session_id,
-                MailboxName = arguments.mailbox_name.clone(),
-                AccountId = mailbox.account_id,
-                MailboxId = mailbox.mailbox_id,
-                Details = grants,
-                Elapsed = op_start.elapsed()
-            );
+                Ok(granted_rights)
+            }
+            .await;
+
+            // Record the ACL change, including failed attempts
+            data.server
+                .audit(
+                    AuditRecord::new(
+                        data.access_token.name.as_str(),
+                        AuditEvent::AclChange {
+                            grantee: arguments.identifier.clone().unwrap_or_default(),
+                            resource: arguments.mailbox_name.clone(),
+                            rights: result.as_ref().cloned().unwrap_or(requested_rights),
+                        },
+                    )
+                    .with_remote_ip(remote_ip)
+                    .with_result(&result),
+                )
+                .await;
+            result?;
 
             data.write_bytes(
                 StatusResponse::completed(command)
@@ -413,7 +476,8 @@ impl<T: SessionStream> Session<T> {
                         permissions: vec![
                             vec![Rights::Read],
                             vec![Rights::Lookup],
-                            vec![Rights::Write, Rights::Seen],
+                            vec![Rights::Write],
+                            vec![Rights::Seen],
                             vec![Rights::Insert],
                             vec![Rights::Expunge, Rights::DeleteMessages],
                             vec![Rights::CreateMailbox],
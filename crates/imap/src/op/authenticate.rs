@@ -6,14 +6,18 @@
 
 use common::{
     auth::{
-        sasl::{sasl_decode_challenge_oauth, sasl_decode_challenge_plain},
-        AuthRequest,
+        sasl::{
+            sasl_decode_challenge_oauth, sasl_decode_challenge_plain, sasl_encode_challenge,
+            SaslMechanism, SaslStep,
+        },
+        AccessToken, AuthRequest, SessionAppPassword,
     },
+    config::server::ServerProtocol,
     listener::SessionStream,
 };
-use directory::Permission;
+use directory::{core::scram::ScramAlgorithm, Permission};
 use imap_proto::{
-    protocol::{authenticate::Mechanism, capability::Capability},
+    protocol::authenticate::{Arguments, Mechanism},
     receiver::{self, Request},
     Command, ResponseCode, StatusResponse,
 };
@@ -63,11 +67,109 @@ impl<T: SessionStream> Session<T> {
                     self.write_bytes(b"+ \"\"\r\n".to_vec()).await
                 }
             }
-            _ => Err(trc::AuthEvent::Error
-                .into_err()
-                .details("Authentication mechanism not supported.")
-                .id(args.tag)
-                .code(ResponseCode::Cannot)),
+            _ => {
+                if let Some(mechanism) = sasl_mechanism(&args.mechanism)
+                    .filter(|mechanism| self.sasl_mechanisms().contains(mechanism))
+                {
+                    self.handle_sasl_exchange(mechanism, args).await
+                } else {
+                    Err(trc::AuthEvent::Error
+                        .into_err()
+                        .details("Authentication mechanism not supported.")
+                        .id(args.tag)
+                        .code(ResponseCode::Cannot))
+                }
+            }
+        }
+    }
+
+    async fn handle_sasl_exchange(
+        &mut self,
+        mechanism: SaslMechanism,
+        mut args: Arguments,
+    ) -> trc::Result<()> {
+        let tag = args.tag;
+        let response = args.params.pop();
+        let (exchange, response) = match (self.sasl.take(), response) {
+            (Some(exchange), response) => (exchange, response.unwrap_or_default()),
+            (None, response) => {
+                // Throttle authentication requests
+                self.server
+                    .is_auth_allowed_soft(&self.remote_addr)
+                    .await
+                    .map_err(|err| err.id(tag.clone()))?;
+
+                let (exchange, challenge) = self
+                    .server
+                    .sasl_start(mechanism, self.channel_binding.clone())
+                    .await
+                    .map_err(|err| err.id(tag.clone()))?;
+
+                match response {
+                    Some(response) if mechanism != SaslMechanism::CramMd5 => (exchange, response),
+                    _ => {
+                        self.sasl = Some(exchange);
+                        return self
+                            .write_sasl_challenge(args.mechanism, tag, challenge)
+                            .await;
+                    }
+                }
+            }
+        };
+
+        let response = if !response.is_empty() {
+            base64_decode(response.as_bytes()).ok_or_else(|| {
+                trc::AuthEvent::Error
+                    .into_err()
+                    .details("Failed to decode challenge.")
+                    .id(tag.clone())
+                    .code(ResponseCode::Parse)
+            })?
+        } else {
+            Vec::new()
+        };
+
+        match self
+            .server
+            .sasl_step(exchange, &response, self.session_id, self.remote_addr, None)
+            .await
+        {
+            Ok((exchange, SaslStep::Challenge(challenge))) => {
+                self.sasl = exchange;
+                self.write_sasl_challenge(args.mechanism, tag, challenge)
+                    .await
+            }
+            Ok((_, SaslStep::Authenticated(access_token))) => {
+                self.complete_authentication(Ok((access_token, None)), tag)
+                    .await
+            }
+            Err(err) => self.complete_authentication(Err(err), tag).await,
+        }
+    }
+
+    async fn write_sasl_challenge(
+        &mut self,
+        mechanism: Mechanism,
+        tag: String,
+        challenge: Vec<u8>,
+    ) -> trc::Result<()> {
+        self.receiver.request = receiver::Request {
+            tag,
+            command: Command::Authenticate,
+            tokens: vec![receiver::Token::Argument(mechanism.into_bytes())],
+        };
+        self.receiver.state = receiver::State::Argument { last_ch: b' ' };
+        self.write_bytes(format!("+ {}\r\n", sasl_encode_challenge(&challenge)).into_bytes())
+            .await
+    }
+
+    // Challenge-response mechanisms available on this connection
+    pub fn sasl_mechanisms(&self) -> Vec<SaslMechanism> {
+        // These mechanisms can only verify the primary password
+        if self.server.is_app_password_required(ServerProtocol::Imap) {
+            Vec::new()
+        } else {
+            SaslMechanism::available(self.channel_binding.is_some(), self.instance.allow_cram_md5)
         }
     }
 
@@ -83,14 +185,25 @@ impl<T: SessionStream> Session<T> {
             .map_err(|err| err.id(tag.clone()))?;
 
         // Authenticate
-        let access_token = self
+        let result = self
             .server
-            .authenticate(&AuthRequest::from_credentials(
+            .authenticate_session(&AuthRequest::from_credentials(
                 credentials,
                 self.session_id,
                 self.remote_addr,
+                ServerProtocol::Imap,
             ))
-            .await
+            .await;
+
+        self.complete_authentication(result, tag).await
+    }
+
+    async fn complete_authentication(
+        &mut self,
+        result: trc::Result<(Arc<AccessToken>, Option<SessionAppPassword>)>,
+        tag: String,
+    ) -> trc::Result<()> {
+        let (access_token, app_password) = result
             .map_err(|err| {
                 if err.matches(trc::EventType::Auth(trc::AuthEvent::Failed)) {
                     let auth_failures = self.state.auth_failures();
@@ -105,10 +218,10 @@ impl<T: SessionStream> Session<T> {
 
                 err.id(tag.clone())
             })
-            .and_then(|token| {
+            .and_then(|(token, app_password)| {
                 token
                     .assert_has_permission(Permission::ImapAuthenticate)
-                    .map(|_| token)
+                    .map(|_| (token, app_password))
             })?;
 
         // Enforce concurrency limits
@@ -133,13 +246,11 @@ impl<T: SessionStream> Session<T> {
                     .map_err(|err| err.id(tag.clone()))?,
             ),
         };
+        self.app_password = app_password;
         self.write_bytes(
             StatusResponse::ok("Authentication successful")
                 .with_code(ResponseCode::Capability {
-                    capabilities: Capability::all_capabilities(
-                        true,
-                        !self.is_tls && self.instance.acceptor.is_tls(),
-                    ),
+                    capabilities: self.capabilities(true),
                 })
                 .with_tag(tag)
                 .into_bytes(),
@@ -149,6 +260,7 @@ impl<T: SessionStream> Session<T> {
 
     pub async fn handle_unauthenticate(&mut self, request: Request<Command>) -> trc::Result<()> {
         self.state = State::NotAuthenticated { auth_failures: 0 };
+        self.app_password = None;
 
         self.write_bytes(
             StatusResponse::completed(Command::Unauthenticate)
@@ -158,3 +270,28 @@ impl<T: SessionStream> Session<T> {
         .await
     }
 }
+
+pub fn sasl_mechanism(mechanism: &Mechanism) -> Option<SaslMechanism> {
+    let (algorithm, plus) = match mechanism {
+        Mechanism::CramMd5 => return Some(SaslMechanism::CramMd5),
+        Mechanism::ScramSha1 => (ScramAlgorithm::Sha1, false),
+        Mechanism::ScramSha256 => (ScramAlgorithm::Sha256, false),
+        Mechanism::ScramSha1Plus => (ScramAlgorithm::Sha1, true),
+        Mechanism::ScramSha256Plus => (ScramAlgorithm::Sha256, true),
+        _ => return None,
+    };
+
+    Some(SaslMechanism::Scram { algorithm, plus })
+}
+
+pub fn imap_mechanism(mechanism: SaslMechanism) -> Mechanism {
+    match mechanism {
+        SaslMechanism::CramMd5 => Mechanism::CramMd5,
+        SaslMechanism::Scram { algorithm, plus } => match (algorithm, plus) {
+            (ScramAlgorithm::Sha1, false) => Mechanism::ScramSha1,
+            (ScramAlgorithm::Sha256, false) => Mechanism::ScramSha256,
+            (ScramAlgorithm::Sha1, true) => Mechanism::ScramSha1Plus,
+            (ScramAlgorithm::Sha256, true) => Mechanism::ScramSha256Plus,
+        },
+    }
+}
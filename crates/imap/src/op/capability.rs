@@ -6,7 +6,7 @@
 
 use std::time::Instant;
 
-use crate::core::Session;
+use crate::{core::Session, op::authenticate::imap_mechanism};
 use common::listener::SessionStream;
 use directory::Permission;
 use imap_proto::{
@@ -37,10 +37,7 @@ impl<T: SessionStream> Session<T> {
                 .with_tag(request.tag)
                 .serialize(
                     Response {
-                        capabilities: Capability::all_capabilities(
-                            self.state.is_authenticated(),
-                            !self.is_tls && self.instance.acceptor.is_tls(),
-                        ),
+                        capabilities: self.capabilities(self.state.is_authenticated()),
                     }
                     .serialize(),
                 ),
@@ -48,6 +45,24 @@ impl<T: SessionStream> Session<T> {
         .await
     }
 
+    pub fn capabilities(&self, is_authenticated: bool) -> Vec<Capability> {
+        let mut capabilities = Capability::all_capabilities(
+            is_authenticated,
+            !self.is_tls && self.instance.acceptor.is_tls(),
+        );
+        if is_authenticated && self.server.core.jmap.push_apns.is_some() {
+            capabilities.push(Capability::XApplePushService);
+        }
+        if !is_authenticated {
+            capabilities.extend(
+                self.sasl_mechanisms()
+                    .into_iter()
+                    .map(|mechanism| Capability::Auth(imap_mechanism(mechanism))),
+            );
+        }
+        capabilities
+    }
+
     pub async fn handle_id(&mut self, request: Request<Command>) -> trc::Result<()> {
         // Validate access
         self.assert_has_permission(Permission::ImapId)?;
@@ -29,7 +29,7 @@ use imap_proto::{
 use jmap::{
     blob::download::BlobDownload,
     changes::{get::ChangesLookup, write::ChangeLog},
-    email::metadata::MessageMetadata,
+    email::{metadata::MessageDataGet, private::EmailPrivateKeywords},
     services::state::StateManager,
     JmapMethods,
 };
@@ -40,7 +40,7 @@ use jmap_proto::types::{
 use mail_parser::{Address, GetHeader, HeaderName, Message, PartType};
 use store::{
     query::log::{Change, Query},
-    write::{assert::HashedValue, BatchBuilder, Bincode, F_BITMAP, F_VALUE},
+    write::{BatchBuilder, F_BITMAP, F_VALUE},
 };
 
 use super::{FromModSeq, ImapContext};
@@ -251,16 +251,16 @@ impl<T: SessionStream> SessionData<T> {
 
         if set_seen_flags
             && !self
-                .check_mailbox_acl(
-                    mailbox.id.account_id,
-                    mailbox.id.mailbox_id,
-                    Acl::ModifyItems,
-                )
+                .check_mailbox_acl(mailbox.id.account_id, mailbox.id.mailbox_id, Acl::SetSeen)
                 .await
                 .imap_ctx(&arguments.tag, trc::location!())?
         {
             set_seen_flags = false;
         }
+        let access_token = self
+            .get_access_token()
+            .await
+            .imap_ctx(&arguments.tag, trc::location!())?;
 
         if is_uid {
             if arguments.attributes.is_empty() {
@@ -285,27 +285,13 @@ impl<T: SessionStream> SessionData<T> {
 
         for (seqnum, uid, id) in ids {
             // Obtain attributes and keywords
-            let (email, keywords) = if let (Some(email), Some(keywords)) = (
-                self.server
-                    .get_property::<Bincode<MessageMetadata>>(
-                        account_id,
-                        Collection::Email,
-                        id,
-                        &Property::BodyStructure,
-                    )
-                    .await
-                    .imap_ctx(&arguments.tag, trc::location!())?,
-                self.server
-                    .get_property::<HashedValue<Vec<Keyword>>>(
-                        account_id,
-                        Collection::Email,
-                        id,
-                        &Property::Keywords,
-                    )
-                    .await
-                    .imap_ctx(&arguments.tag, trc::location!())?,
-            ) {
-                (email.inner, keywords)
+            let (email, keywords) = if let Some(data) = self
+                .server
+                .get_message_data(account_id, id)
+                .await
+                .imap_ctx(&arguments.tag, trc::location!())?
+            {
+                (data.metadata, data.keywords)
             } else {
                 trc::event!(
                     Store(trc::StoreEvent::NotFound),
@@ -347,10 +333,21 @@ impl<T: SessionStream> SessionData<T> {
             };
             let message = email.contents.into_message(&raw_message);
 
+            // Private keywords of the account on shared mailboxes
+            let overlay = self
+                .server
+                .keyword_overlay(account_id, &access_token, id)
+                .await
+                .imap_ctx(&arguments.tag, trc::location!())?;
+            let view = overlay
+                .as_ref()
+                .map(|overlay| overlay.view(&keywords.inner));
+            let current_keywords = view.as_deref().unwrap_or(&keywords.inner);
+
             // Build response
             let mut items = Vec::with_capacity(arguments.attributes.len());
             let set_seen_flag =
-                set_seen_flags && !keywords.inner.iter().any(|k| k == &Keyword::Seen);
+                set_seen_flags && !current_keywords.iter().any(|k| k == &Keyword::Seen);
             let thread_id = if needs_thread_id || set_seen_flag {
                 if let Some(thread_id) = self
                     .server
@@ -373,8 +370,7 @@ impl<T: SessionStream> SessionData<T> {
                         });
                     }
                     Attribute::Flags => {
-                        let mut flags = keywords
-                            .inner
+                        let mut flags = current_keywords
                             .iter()
                             .map(|k| Flag::from(k.clone()))
                             .collect::<Vec<_>>();
@@ -507,8 +503,7 @@ impl<T: SessionStream> SessionData<T> {
 
             // Add flags to the response if the message was unseen
             if set_seen_flag && !arguments.attributes.contains(&Attribute::Flags) {
-                let mut flags = keywords
-                    .inner
+                let mut flags = current_keywords
                     .iter()
                     .map(|k| Flag::from(k.clone()))
                     .collect::<Vec<_>>();
@@ -523,7 +518,7 @@ impl<T: SessionStream> SessionData<T> {
 
             // Add to set flags
             if set_seen_flag {
-                set_seen_ids.push((Id::from_parts(thread_id, id), keywords));
+                set_seen_ids.push((Id::from_parts(thread_id, id), keywords, overlay));
             }
         }
 
@@ -534,17 +529,26 @@ impl<T: SessionStream> SessionData<T> {
                 .begin_changes(account_id)
                 .await
                 .imap_ctx(&arguments.tag, trc::location!())?;
-            for (id, mut keywords) in set_seen_ids {
-                keywords.inner.push(Keyword::Seen);
+            for (id, mut keywords, overlay) in set_seen_ids {
                 let mut batch = BatchBuilder::new();
                 batch
                     .with_account_id(account_id)
                     .with_collection(Collection::Email)
-                    .update_document(id.document_id())
-                    .assert_value(Property::Keywords, &keywords)
-                    .value(Property::Keywords, keywords.inner, F_VALUE)
-                    .value(Property::Keywords, Keyword::Seen, F_BITMAP)
-                    .value(Property::Cid, changelog.change_id, F_VALUE);
+                    .update_document(id.document_id());
+                match overlay {
+                    Some(mut overlay) if overlay.is_private(&Keyword::Seen) => {
+                        overlay.update(Keyword::Seen, true);
+                        overlay.update_batch(&mut batch);
+                    }
+                    _ => {
+                        keywords.inner.push(Keyword::Seen);
+                        batch
+                            .assert_value(Property::Keywords, &keywords)
+                            .value(Property::Keywords, keywords.inner, F_VALUE)
+                            .value(Property::Keywords, Keyword::Seen, F_BITMAP);
+                    }
+                }
+                batch.value(Property::Cid, changelog.change_id, F_VALUE);
                 match self.server.write_batch(batch).await {
                     Ok(_) => {
                         changelog.log_update(Collection::Email, id);
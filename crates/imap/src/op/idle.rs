@@ -71,6 +71,7 @@ impl<T: SessionStream> Session<T> {
 
         let op_start = Instant::now();
         let mut buf = vec![0; 4];
+        let mut revocations = self.server.subscribe_app_password_revocations();
         loop {
             tokio::select! {
                 result = tokio::time::timeout(self.server.core.imap.timeout_idle, self.stream_rx.read_exact(&mut buf)) => {
@@ -121,6 +122,13 @@ impl<T: SessionStream> Session<T> {
                         return Err(trc::NetworkEvent::Closed.into_err().details("IDLE channel closed.").id(request.tag));
                     }
                 }
+                Ok(account_id) = revocations.recv() => {
+                    if self.is_app_password_account(account_id)
+                        && self.is_app_password_revoked().await
+                    {
+                        return Err(trc::NetworkEvent::Closed.into_err().details("App password revoked.").id(request.tag));
+                    }
+                }
             }
         }
     }
@@ -31,6 +31,7 @@ pub mod status;
 pub mod store;
 pub mod subscribe;
 pub mod thread;
+pub mod xapplepushservice;
 
 trait FromModSeq {
     fn from_modseq(modseq: u64) -> Self;
@@ -16,7 +16,7 @@ use imap_proto::{
     receiver::Request,
     Command, StatusResponse,
 };
-use jmap::{changes::get::ChangesLookup, JmapMethods};
+use jmap::{changes::get::ChangesLookup, email::private::EmailPrivateKeywords, JmapMethods};
 use jmap_proto::types::{collection::Collection, id::Id, keyword::Keyword, property::Property};
 use mail_parser::HeaderName;
 use nlp::language::Language;
@@ -273,6 +273,7 @@ impl<T: SessionStream> SessionData<T> {
         filters.push(query::Filter::is_in_set(message_ids.clone()));
 
         // Convert query
+        let access_token = self.get_access_token().await?;
         let mut include_highest_modseq = false;
         for filter_group in imap_filter.into_filter_group() {
             match filter_group {
@@ -451,8 +452,9 @@ impl<T: SessionStream> SessionData<T> {
                         filters.push(query::Filter::is_in_set(message_ids.clone()));
                     }
                     search::Filter::Answered => {
-                        filters.push(query::Filter::is_in_bitmap(
-                            Property::Keywords,
+                        filters.push(self.server.keyword_filter(
+                            mailbox.id.account_id,
+                            &access_token,
                             Keyword::Answered,
                         ));
                     }
@@ -460,26 +462,30 @@ impl<T: SessionStream> SessionData<T> {
                         filters.push(query::Filter::lt(Property::ReceivedAt, date as u64));
                     }
                     search::Filter::Deleted => {
-                        filters.push(query::Filter::is_in_bitmap(
-                            Property::Keywords,
+                        filters.push(self.server.keyword_filter(
+                            mailbox.id.account_id,
+                            &access_token,
                             Keyword::Deleted,
                         ));
                     }
                     search::Filter::Draft => {
-                        filters.push(query::Filter::is_in_bitmap(
-                            Property::Keywords,
+                        filters.push(self.server.keyword_filter(
+                            mailbox.id.account_id,
+                            &access_token,
                             Keyword::Draft,
                         ));
                     }
                     search::Filter::Flagged => {
-                        filters.push(query::Filter::is_in_bitmap(
-                            Property::Keywords,
+                        filters.push(self.server.keyword_filter(
+                            mailbox.id.account_id,
+                            &access_token,
                             Keyword::Flagged,
                         ));
                     }
                     search::Filter::Keyword(keyword) => {
-                        filters.push(query::Filter::is_in_bitmap(
-                            Property::Keywords,
+                        filters.push(self.server.keyword_filter(
+                            mailbox.id.account_id,
+                            &access_token,
                             Keyword::from(keyword),
                         ));
                     }
@@ -496,8 +502,9 @@ impl<T: SessionStream> SessionData<T> {
                         filters.push(query::Filter::End);
                     }
                     search::Filter::Seen => {
-                        filters.push(query::Filter::is_in_bitmap(
-                            Property::Keywords,
+                        filters.push(self.server.keyword_filter(
+                            mailbox.id.account_id,
+                            &access_token,
                             Keyword::Seen,
                         ));
                     }
@@ -521,48 +528,54 @@ impl<T: SessionStream> SessionData<T> {
                     }
                     search::Filter::Unanswered => {
                         filters.push(query::Filter::Not);
-                        filters.push(query::Filter::is_in_bitmap(
-                            Property::Keywords,
+                        filters.push(self.server.keyword_filter(
+                            mailbox.id.account_id,
+                            &access_token,
                             Keyword::Answered,
                         ));
                         filters.push(query::Filter::End);
                     }
                     search::Filter::Undeleted => {
                         filters.push(query::Filter::Not);
-                        filters.push(query::Filter::is_in_bitmap(
-                            Property::Keywords,
+                        filters.push(self.server.keyword_filter(
+                            mailbox.id.account_id,
+                            &access_token,
                             Keyword::Deleted,
                         ));
                         filters.push(query::Filter::End);
                     }
                     search::Filter::Undraft => {
                         filters.push(query::Filter::Not);
-                        filters.push(query::Filter::is_in_bitmap(
-                            Property::Keywords,
+                        filters.push(self.server.keyword_filter(
+                            mailbox.id.account_id,
+                            &access_token,
                             Keyword::Draft,
                         ));
                         filters.push(query::Filter::End);
                     }
                     search::Filter::Unflagged => {
                         filters.push(query::Filter::Not);
-                        filters.push(query::Filter::is_in_bitmap(
-                            Property::Keywords,
+                        filters.push(self.server.keyword_filter(
+                            mailbox.id.account_id,
+                            &access_token,
                             Keyword::Flagged,
                         ));
                         filters.push(query::Filter::End);
                     }
                     search::Filter::Unkeyword(keyword) => {
                         filters.push(query::Filter::Not);
-                        filters.push(query::Filter::is_in_bitmap(
-                            Property::Keywords,
+                        filters.push(self.server.keyword_filter(
+                            mailbox.id.account_id,
+                            &access_token,
                             Keyword::from(keyword),
                         ));
                         filters.push(query::Filter::End);
                     }
                     search::Filter::Unseen => {
                         filters.push(query::Filter::Not);
-                        filters.push(query::Filter::is_in_bitmap(
-                            Property::Keywords,
+                        filters.push(self.server.keyword_filter(
+                            mailbox.id.account_id,
+                            &access_token,
                             Keyword::Seen,
                         ));
                         filters.push(query::Filter::End);
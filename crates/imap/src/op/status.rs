@@ -19,7 +19,7 @@ use imap_proto::{
     receiver::Request,
     Command, ResponseCode, StatusResponse,
 };
-use jmap::JmapMethods;
+use jmap::{email::private::EmailPrivateKeywords, JmapMethods};
 use jmap_proto::{
     object::Object,
     types::{collection::Collection, id::Id, keyword::Keyword, property::Property, value::Value},
@@ -211,26 +211,44 @@ impl<T: SessionStream> SessionData<T> {
         if !items_update.is_empty() {
             // Retrieve latest values
             let mut values_update = Vec::with_capacity(items_update.len());
-            let mailbox_message_ids = self
-                .server
-                .get_tag(
-                    mailbox.account_id,
-                    Collection::Email,
-                    Property::MailboxIds,
-                    mailbox.mailbox_id,
+            let (mailbox_message_ids, message_ids) = if items_update
+                .iter()
+                .any(|item| matches!(item, Status::Unseen | Status::Deleted | Status::Size))
+            {
+                (
+                    self.server
+                        .get_tag(
+                            mailbox.account_id,
+                            Collection::Email,
+                            Property::MailboxIds,
+                            mailbox.mailbox_id,
+                        )
+                        .await
+                        .caused_by(trc::location!())?
+                        .map(Arc::new),
+                    self.server
+                        .get_document_ids(mailbox.account_id, Collection::Email)
+                        .await
+                        .caused_by(trc::location!())?,
                 )
-                .await
-                .caused_by(trc::location!())?
-                .map(Arc::new);
-            let message_ids = self
-                .server
-                .get_document_ids(mailbox.account_id, Collection::Email)
-                .await
-                .caused_by(trc::location!())?;
+            } else {
+                (None, None)
+            };
+
+            let access_token = self.get_access_token().await?;
 
             for item in items_update {
                 let result = match item {
-                    Status::Messages => mailbox_message_ids.as_ref().map(|v| v.len()).unwrap_or(0),
+                    Status::Messages => self
+                        .server
+                        .get_tag_cardinality(
+                            mailbox.account_id,
+                            Collection::Email,
+                            Property::MailboxIds,
+                            mailbox.mailbox_id,
+                        )
+                        .await
+                        .caused_by(trc::location!())?,
                     Status::UidNext => {
                         (self
                             .server
@@ -272,10 +290,9 @@ impl<T: SessionStream> SessionData<T> {
                         {
                             if let Some(mut seen) = self
                                 .server
-                                .get_tag(
+                                .keyword_document_ids(
                                     mailbox.account_id,
-                                    Collection::Email,
-                                    Property::Keywords,
+                                    &access_token,
                                     Keyword::Seen,
                                 )
                                 .await
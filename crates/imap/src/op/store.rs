@@ -24,7 +24,11 @@ use imap_proto::{
 };
 use jmap::{
     changes::{get::ChangesLookup, write::ChangeLog},
-    email::set::TagManager,
+    email::{
+        private::EmailPrivateKeywords,
+        set::TagManager,
+        spam::{SpamTrainer, SpamTraining},
+    },
     mailbox::UidMailbox,
     services::state::StateManager,
     JmapMethods,
@@ -90,12 +94,23 @@ impl<T: SessionStream> SessionData<T> {
                 .into_bytes());
         }
 
-        // Verify that the user can modify messages in this mailbox.
+        // Verify that the user can modify messages in this mailbox,
+        // adding or removing only the \Seen flag requires the 's' right.
+        let is_seen_only = arguments.operation != Operation::Set
+            && !arguments.keywords.is_empty()
+            && arguments
+                .keywords
+                .iter()
+                .all(|keyword| matches!(keyword, Flag::Seen));
         if !self
             .check_mailbox_acl(
                 mailbox.id.account_id,
                 mailbox.id.mailbox_id,
-                Acl::ModifyItems,
+                if is_seen_only {
+                    Acl::SetSeen
+                } else {
+                    Acl::ModifyItems
+                },
             )
             .await
             .imap_ctx(&arguments.tag, trc::location!())?
@@ -186,6 +201,10 @@ impl<T: SessionStream> SessionData<T> {
         };
 
         // Process each change
+        let access_token = self
+            .get_access_token()
+            .await
+            .imap_ctx(response.tag.as_ref().unwrap(), trc::location!())?;
         let set_keywords = arguments
             .keywords
             .iter()
@@ -193,6 +212,7 @@ impl<T: SessionStream> SessionData<T> {
             .collect::<Vec<_>>();
         let mut changelog = ChangeLogBuilder::new();
         let mut changed_mailboxes = AHashSet::new();
+        let mut spam_training = Vec::new();
         'outer: for (id, imap_id) in &ids {
             let mut try_count = 0;
             loop {
@@ -212,11 +232,22 @@ impl<T: SessionStream> SessionData<T> {
                         .await
                         .imap_ctx(response.tag.as_ref().unwrap(), trc::location!())?,
                 ) {
-                    (TagManager::new(keywords), thread_id)
+                    (keywords, thread_id)
                 } else {
                     continue 'outer;
                 };
 
+                // Private keywords are applied to the account's overlay
+                let overlay = self
+                    .server
+                    .keyword_overlay(account_id, &access_token, *id)
+                    .await
+                    .imap_ctx(response.tag.as_ref().unwrap(), trc::location!())?;
+                let (mut keywords, owner_keywords) = match &overlay {
+                    Some(overlay) => (overlay.view_manager(&keywords), Some(keywords)),
+                    None => (TagManager::new(keywords), None),
+                };
+
                 // Apply changes
                 match arguments.operation {
                     Operation::Set => {
@@ -239,6 +270,11 @@ impl<T: SessionStream> SessionData<T> {
                     let seen_changed = keywords
                         .changed_tags()
                         .any(|keyword| keyword == &Keyword::Seen);
+                    let training = if self.server.core.spam.bayes.train_keywords {
+                        SpamTraining::from_keywords(keywords.added(), keywords.removed())
+                    } else {
+                        vec![]
+                    };
                     let flags = if !arguments.is_silent {
                         keywords
                             .current()
@@ -256,7 +292,17 @@ impl<T: SessionStream> SessionData<T> {
                         .with_account_id(account_id)
                         .with_collection(Collection::Email)
                         .update_document(*id);
-                    keywords.update_batch(&mut batch, Property::Keywords);
+                    if let (Some(mut overlay), Some(owner_keywords)) = (overlay, owner_keywords) {
+                        let keywords = overlay.split(keywords, owner_keywords);
+                        if keywords.has_changes() {
+                            keywords.update_batch(&mut batch, Property::Keywords);
+                        }
+                        if overlay.has_changes() {
+                            overlay.update_batch(&mut batch);
+                        }
+                    } else {
+                        keywords.update_batch(&mut batch, Property::Keywords);
+                    }
                     if changelog.change_id == u64::MAX {
                         changelog.change_id = self
                             .server
@@ -286,6 +332,9 @@ impl<T: SessionStream> SessionData<T> {
                                 }
                             }
                             changelog.log_update(Collection::Email, Id::from_parts(thread_id, *id));
+                            if !training.is_empty() {
+                                spam_training.push((*id, training));
+                            }
 
                             // Add item to response
                             let modseq = changelog.change_id + 1;
@@ -356,6 +405,21 @@ impl<T: SessionStream> SessionData<T> {
                 .await;
         }
 
+        // Train the spam filter with messages flagged as $Junk or $NotJunk
+        for (document_id, training) in spam_training {
+            if let Err(err) = self
+                .server
+                .spam_train_message(account_id, document_id, &training, self.session_id)
+                .await
+            {
+                trc::error!(err
+                    .account_id(account_id)
+                    .document_id(document_id)
+                    .span_id(self.session_id)
+                    .caused_by(trc::location!()));
+            }
+        }
+
         trc::event!(
             Imap(trc::ImapEvent::Store),
             SpanId = self.session_id,
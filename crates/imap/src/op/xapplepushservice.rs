@@ -0,0 +1,115 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Instant;
+
+use crate::{
+    core::{Session, SessionData},
+    spawn_op,
+};
+use common::listener::SessionStream;
+use directory::Permission;
+use imap_proto::{
+    protocol::{
+        xapplepushservice::{Arguments, Response},
+        ImapResponse,
+    },
+    receiver::Request,
+    Command, ResponseCode, StatusResponse,
+};
+use jmap::push::apns::{ApnsPush, ApnsRegistration};
+use store::write::now;
+
+use super::ImapContext;
+
+impl<T: SessionStream> Session<T> {
+    pub async fn handle_xapplepushservice(&mut self, request: Request<Command>) -> trc::Result<()> {
+        // Validate access
+        self.assert_has_permission(Permission::ImapXApplePushService)?;
+
+        let op_start = Instant::now();
+        let arguments = request.parse_xapplepushservice(self.version)?;
+        let data = self.state.session_data();
+
+        spawn_op!(data, {
+            let response = data.register_push_device(arguments, op_start).await?;
+
+            data.write_bytes(response).await
+        })
+    }
+}
+
+impl<T: SessionStream> SessionData<T> {
+    pub async fn register_push_device(
+        &self,
+        arguments: Arguments,
+        op_start: Instant,
+    ) -> trc::Result<Vec<u8>> {
+        let config = if let Some(config) = &self.server.core.jmap.push_apns {
+            config
+        } else {
+            return Err(trc::ImapEvent::Error
+                .into_err()
+                .details("Push notifications are not available.")
+                .code(ResponseCode::Unavailable)
+                .id(arguments.tag));
+        };
+
+        // Refresh mailboxes
+        self.synchronize_mailboxes(false)
+            .await
+            .imap_ctx(&arguments.tag, trc::location!())?;
+
+        // Notifications are only sent for mailboxes owned by the account,
+        // unknown mailboxes are ignored as clients register them all at once
+        let mut mailbox_ids = Vec::with_capacity(arguments.mailboxes.len());
+        for mailbox_name in &arguments.mailboxes {
+            if let Some(mailbox) = self
+                .get_mailbox_by_name(mailbox_name)
+                .filter(|mailbox| mailbox.account_id == self.account_id)
+            {
+                if !mailbox_ids.contains(&mailbox.mailbox_id) {
+                    mailbox_ids.push(mailbox.mailbox_id);
+                }
+            }
+        }
+
+        trc::event!(
+            Imap(trc::ImapEvent::XApplePushService),
+            SpanId = self.session_id,
+            AccountId = self.account_id,
+            MailboxId = mailbox_ids
+                .iter()
+                .map(|id| trc::Value::from(*id))
+                .collect::<Vec<_>>(),
+            Elapsed = op_start.elapsed()
+        );
+
+        self.server
+            .apns_register(
+                self.account_id,
+                ApnsRegistration {
+                    aps_account_id: arguments.account_id,
+                    device_token: arguments.device_token,
+                    subtopic: arguments.subtopic,
+                    mailbox_ids,
+                    expires: now() + config.registration_ttl.as_secs(),
+                },
+            )
+            .await
+            .imap_ctx(&arguments.tag, trc::location!())?;
+
+        Ok(StatusResponse::completed(Command::XApplePushService)
+            .with_tag(arguments.tag)
+            .serialize(
+                Response {
+                    version: arguments.version,
+                    topic: config.topic.clone(),
+                }
+                .serialize(),
+            ))
+    }
+}
@@ -151,6 +151,13 @@ impl Serialize for MethodErrorWrapper {
                         "between the old and new states."
                     ),
                 ),
+                trc::JmapEvent::TooManyChanges => (
+                    "tooManyChanges",
+                    concat!(
+                        "There are more changes than the client's ",
+                        "maxChanges argument."
+                    ),
+                ),
                 trc::JmapEvent::UnknownCapability
                 | trc::JmapEvent::NotJson
                 | trc::JmapEvent::NotRequest => (
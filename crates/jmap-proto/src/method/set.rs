@@ -318,6 +318,7 @@ impl JsonObjectParser for Object<SetValue> {
                     | Property::References
                     | Property::ReplyTo
                     | Property::Sender
+                    | Property::Snoozed
                     | Property::SubParts
                     | Property::To
                     | Property::UndoStatus
@@ -134,6 +134,23 @@ impl ObjectIndexBuilder {
     pub fn current(&self) -> Option<&HashedValue<Object<Value>>> {
         self.current.as_ref()
     }
+
+    // Returns the index entries of an object, used by Store::rebuild_index
+    pub fn index_entries(
+        index: &'static [IndexProperty],
+        object: &Object<Value>,
+    ) -> Vec<(u8, Vec<u8>)> {
+        let mut batch = BatchBuilder::new();
+        build_batch(&mut batch, index, object, true);
+        batch
+            .ops
+            .into_iter()
+            .filter_map(|op| match op {
+                Operation::Index { field, key, .. } => Some((field, key)),
+                _ => None,
+            })
+            .collect()
+    }
 }
 
 impl IntoOperations for ObjectIndexBuilder {
@@ -23,7 +23,8 @@ pub enum Acl {
     CreateChild = 7,
     Administer = 8,
     Submit = 9,
-    None = 10,
+    SetSeen = 10,
+    None = 11,
 }
 
 impl JsonObjectParser for Acl {
@@ -54,6 +55,7 @@ impl JsonObjectParser for Acl {
             0x0064_6c69_6843_6574_6165_7263 => Ok(Acl::CreateChild),
             0x7265_7473_696e_696d_6461 => Ok(Acl::Administer),
             0x7469_6d62_7573 => Ok(Acl::Submit),
+            0x006e_6565_5374_6573 => Ok(Acl::SetSeen),
             _ => Err(parser.error_value()),
         }
     }
@@ -72,6 +74,7 @@ impl Acl {
             Acl::CreateChild => "createChild",
             Acl::Administer => "administer",
             Acl::Submit => "submit",
+            Acl::SetSeen => "setSeen",
             Acl::None => "",
         }
     }
@@ -121,6 +124,7 @@ impl From<u64> for Acl {
             7 => Acl::CreateChild,
             8 => Acl::Administer,
             9 => Acl::Submit,
+            10 => Acl::SetSeen,
             _ => Acl::None,
         }
     }
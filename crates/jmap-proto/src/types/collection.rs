@@ -24,7 +24,9 @@ pub enum Collection {
     SieveScript = 5,
     PushSubscription = 6,
     Principal = 7,
-    None = 8,
+    AddressBook = 8,
+    ContactCard = 9,
+    None = 10,
 }
 
 impl From<u8> for Collection {
@@ -38,6 +40,8 @@ impl From<u8> for Collection {
             5 => Collection::SieveScript,
             6 => Collection::PushSubscription,
             7 => Collection::Principal,
+            8 => Collection::AddressBook,
+            9 => Collection::ContactCard,
             _ => Collection::None,
         }
     }
@@ -54,6 +58,8 @@ impl From<u64> for Collection {
             5 => Collection::SieveScript,
             6 => Collection::PushSubscription,
             7 => Collection::Principal,
+            8 => Collection::AddressBook,
+            9 => Collection::ContactCard,
             _ => Collection::None,
         }
     }
@@ -105,6 +111,8 @@ impl Collection {
             Collection::EmailSubmission => "emailSubmission",
             Collection::SieveScript => "sieveScript",
             Collection::Principal => "principal",
+            Collection::AddressBook => "addressBook",
+            Collection::ContactCard => "contactCard",
             Collection::None => "",
         }
     }
@@ -123,6 +131,8 @@ impl FromStr for Collection {
             "emailSubmission" => Ok(Collection::EmailSubmission),
             "sieveScript" => Ok(Collection::SieveScript),
             "principal" => Ok(Collection::Principal),
+            "addressBook" => Ok(Collection::AddressBook),
+            "contactCard" => Ok(Collection::ContactCard),
             _ => Err(()),
         }
     }
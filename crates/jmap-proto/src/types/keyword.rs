@@ -116,7 +116,7 @@ impl From<String> for Keyword {
             let mut hash = 0;
             let mut shift = 0;
 
-            for &ch in value.as_bytes() {
+            for &ch in value.as_bytes().iter().skip(1) {
                 if shift < 128 {
                     hash |= (ch as u128) << shift;
                     shift += 8;
@@ -119,6 +119,10 @@ pub enum Property {
     WarnLimit,
     SoftLimit,
     Scope,
+    PrivateKeywords,
+    Snoozed,
+    Until,
+    SetMailboxId,
     Digest(DigestProperty),
     Data(DataProperty),
     _T(String),
@@ -436,6 +440,7 @@ fn parse_property(first_char: u8, hash: u128) -> Option<Property> {
             0x0072_6564_6e65 => Property::Sender,
             0x0074_4174_6e65 => Property::SentAt,
             0x0065_7a69 => Property::Size,
+            0x6465_7a6f_6f6e => Property::Snoozed,
             0x7265_6472_4f74_726f => Property::SortOrder,
             0x7463_656a_6275 => Property::Subject,
             0x7374_7261_5062_7573 => Property::SubParts,
@@ -683,6 +688,7 @@ impl JsonObjectParser for ObjectProperty {
                 0x796c_7065_5270_746d => Property::SmtpReply,
                 0x7469_6d69_4c74_666f => Property::SoftLimit,
                 0x6570_6f63 => Property::Scope,
+                0x0064_4978_6f62_6c69_614d_7465 => Property::SetMailboxId,
                 _ => parser.invalid_property()?,
             },
             b't' => match hash {
@@ -691,6 +697,7 @@ impl JsonObjectParser for ObjectProperty {
             },
             b'u' => match hash {
                 0x0064_6573 => Property::Used,
+                0x6c69_746e => Property::Until,
                 _ => parser.invalid_property()?,
             },
             b'v' => match hash {
@@ -881,6 +888,10 @@ impl Display for Property {
             Property::Used => write!(f, "used"),
             Property::HardLimit => write!(f, "hardLimit"),
             Property::Scope => write!(f, "scope"),
+            Property::PrivateKeywords => write!(f, "privateKeywords"),
+            Property::Snoozed => write!(f, "snoozed"),
+            Property::Until => write!(f, "until"),
+            Property::SetMailboxId => write!(f, "setMailboxId"),
             Property::WarnLimit => write!(f, "warnLimit"),
             Property::SoftLimit => write!(f, "softLimit"),
             Property::_T(s) => write!(f, "{s}"),
@@ -1063,6 +1074,10 @@ impl From<&Property> for u8 {
             Property::WarnLimit => 101,
             Property::SoftLimit => 102,
             Property::Scope => 103,
+            Property::PrivateKeywords => 104,
+            Property::Snoozed => 105,
+            Property::Until => 106,
+            Property::SetMailboxId => 107,
             Property::Digest(_) | Property::Data(_) => unreachable!("invalid property"),
         }
     }
@@ -1205,6 +1220,10 @@ impl SerializeInto for Property {
             Property::WarnLimit => 101,
             Property::SoftLimit => 102,
             Property::Scope => 103,
+            Property::PrivateKeywords => 104,
+            Property::Snoozed => 105,
+            Property::Until => 106,
+            Property::SetMailboxId => 107,
             Property::Digest(_) | Property::Data(_) => {
                 unreachable!("Property::Digest and Property::Data are not serializable")
             }
@@ -1323,6 +1342,10 @@ impl DeserializeFrom for Property {
             101 => Some(Property::WarnLimit),
             102 => Some(Property::SoftLimit),
             103 => Some(Property::Scope),
+            104 => Some(Property::PrivateKeywords),
+            105 => Some(Property::Snoozed),
+            106 => Some(Property::Until),
+            107 => Some(Property::SetMailboxId),
             _ => None,
         }
     }
@@ -123,6 +123,16 @@ impl Value {
                 .unwrap_uint_or_null("")?
                 .map(Value::UnsignedInt)
                 .unwrap_or(Value::Null)),
+            Property::Until => Ok(parser
+                .next_token::<UTCDate>()?
+                .unwrap_string_or_null("")?
+                .map(Value::Date)
+                .unwrap_or(Value::Null)),
+            Property::SetMailboxId => Ok(parser
+                .next_token::<Id>()?
+                .unwrap_string_or_null("")?
+                .map(Value::Id)
+                .unwrap_or(Value::Null)),
             Property::PartId
             | Property::Name
             | Property::Email
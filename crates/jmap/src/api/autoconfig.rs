@@ -8,14 +8,17 @@ use std::fmt::Write;
 
 use common::{manager::webadmin::Resource, Server};
 use directory::{backend::internal::PrincipalField, QueryBy};
+use hyper::StatusCode;
+use quick_xml::escape::escape;
 use quick_xml::events::Event;
 use quick_xml::Reader;
+use serde_json::json;
 use trc::AddContext;
 use utils::url_params::UrlParams;
 
 use crate::api::http::ToHttpResponse;
 
-use super::{HttpRequest, HttpResponse};
+use super::{management::decode_path_element, HttpRequest, HttpResponse, JsonResponse};
 use std::future::Future;
 
 pub trait Autoconfig: Sync + Send {
@@ -27,12 +30,29 @@ pub trait Autoconfig: Sync + Send {
         &self,
         body: Option<Vec<u8>>,
     ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+    fn handle_autodiscover_v2_request(
+        &self,
+        req: &HttpRequest,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
     fn autoconfig_parameters<'x>(
         &self,
         emailaddress: &'x str,
-    ) -> impl Future<Output = trc::Result<(String, String, &'x str)>> + Send;
+    ) -> impl Future<Output = trc::Result<AutoconfigParameters<'x>>> + Send;
+}
+
+pub struct AutoconfigParameters<'x> {
+    pub account_name: String,
+    pub server_name: String,
+    pub domain: &'x str,
+    // Primary address of the account, when the requested one is an alias
+    pub redirect: Option<String>,
 }
 
+const SCHEMA_OUTLOOK: &str =
+    "http://schemas.microsoft.com/exchange/autodiscover/outlook/responseschema/2006a";
+const SCHEMA_MOBILESYNC: &str =
+    "http://schemas.microsoft.com/exchange/autodiscover/mobilesync/responseschema/2006";
+
 impl Autoconfig for Server {
     async fn handle_autoconfig_request(&self, req: &HttpRequest) -> trc::Result<HttpResponse> {
         // Obtain parameters
@@ -41,7 +61,12 @@ impl Autoconfig for Server {
             .get("emailaddress")
             .unwrap_or_default()
             .to_lowercase();
-        let (account_name, server_name, domain) = self.autoconfig_parameters(&emailaddress).await?;
+        let AutoconfigParameters {
+            account_name,
+            server_name,
+            domain,
+            ..
+        } = self.autoconfig_parameters(&emailaddress).await?;
         let services = self.core.storage.config.get_services().await?;
 
         // Build XML response
@@ -95,21 +120,89 @@ impl Autoconfig for Server {
         body: Option<Vec<u8>>,
     ) -> trc::Result<HttpResponse> {
         // Obtain parameters
-        let emailaddress = parse_autodiscover_request(body.as_deref().unwrap_or_default())
-            .map_err(|err| {
+        let (emailaddress, schema) =
+            parse_autodiscover_request(body.as_deref().unwrap_or_default()).map_err(|err| {
                 trc::ResourceEvent::BadParameters
                     .into_err()
                     .details("Failed to parse autodiscover request")
                     .ctx(trc::Key::Reason, err)
             })?;
-        let (account_name, server_name, _) = self.autoconfig_parameters(&emailaddress).await?;
-        let services = self.core.storage.config.get_services().await?;
+        let AutoconfigParameters {
+            account_name,
+            server_name,
+            redirect,
+            ..
+        } = self.autoconfig_parameters(&emailaddress).await?;
+
+        // The address comes from the request and may contain markup
+        let emailaddress = escape(emailaddress.as_str());
+        let account_name = escape(account_name.as_str());
 
         // Build XML response
         let mut config = String::with_capacity(1024);
         let _ = writeln!(&mut config, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>");
         let _ = writeln!(&mut config, "<Autodiscover xmlns=\"http://schemas.microsoft.com/exchange/autodiscover/responseschema/2006\">");
-        let _ = writeln!(&mut config, "\t<Response xmlns=\"http://schemas.microsoft.com/exchange/autodiscover/outlook/responseschema/2006a\">");
+
+        match schema.as_deref() {
+            Some(SCHEMA_MOBILESYNC) => {
+                // ActiveSync is not available, clients are either redirected to
+                // the primary address or told to fall back to other protocols.
+                let _ = writeln!(&mut config, "\t<Response xmlns=\"{SCHEMA_MOBILESYNC}\">");
+                let _ = writeln!(&mut config, "\t\t<Culture>en:us</Culture>");
+                let _ = writeln!(&mut config, "\t\t<User>");
+                let _ = writeln!(
+                    &mut config,
+                    "\t\t\t<EMailAddress>{emailaddress}</EMailAddress>"
+                );
+                let _ = writeln!(&mut config, "\t\t</User>");
+                let _ = writeln!(&mut config, "\t\t<Action>");
+                if let Some(redirect) = redirect {
+                    let _ = writeln!(&mut config, "\t\t\t<Redirect>{redirect}</Redirect>");
+                } else {
+                    let _ = writeln!(&mut config, "\t\t\t<Error>");
+                    let _ = writeln!(&mut config, "\t\t\t\t<Status>1</Status>");
+                    let _ = writeln!(
+                        &mut config,
+                        "\t\t\t\t<Message>ActiveSync is not supported</Message>"
+                    );
+                    let _ = writeln!(&mut config, "\t\t\t\t<DebugData>MobileSync</DebugData>");
+                    let _ = writeln!(&mut config, "\t\t\t</Error>");
+                }
+                let _ = writeln!(&mut config, "\t\t</Action>");
+                let _ = writeln!(&mut config, "\t</Response>");
+                let _ = writeln!(&mut config, "</Autodiscover>");
+
+                return Ok(
+                    Resource::new("application/xml; charset=utf-8", config.into_bytes())
+                        .into_http_response(),
+                );
+            }
+            Some(SCHEMA_OUTLOOK) | None => (),
+            Some(schema) => {
+                return Err(trc::ResourceEvent::BadParameters
+                    .into_err()
+                    .details("Unsupported autodiscover response schema")
+                    .ctx(trc::Key::Reason, schema.to_string()));
+            }
+        }
+
+        let _ = writeln!(&mut config, "\t<Response xmlns=\"{SCHEMA_OUTLOOK}\">");
+        if let Some(redirect) = redirect {
+            let _ = writeln!(&mut config, "\t\t<Account>");
+            let _ = writeln!(&mut config, "\t\t\t<AccountType>email</AccountType>");
+            let _ = writeln!(&mut config, "\t\t\t<Action>redirectAddr</Action>");
+            let _ = writeln!(&mut config, "\t\t\t<RedirectAddr>{redirect}</RedirectAddr>");
+            let _ = writeln!(&mut config, "\t\t</Account>");
+            let _ = writeln!(&mut config, "\t</Response>");
+            let _ = writeln!(&mut config, "</Autodiscover>");
+
+            return Ok(
+                Resource::new("application/xml; charset=utf-8", config.into_bytes())
+                    .into_http_response(),
+            );
+        }
+
+        let services = self.core.storage.config.get_services().await?;
         let _ = writeln!(&mut config, "\t\t<User>");
         let _ = writeln!(
             &mut config,
@@ -169,31 +262,93 @@ impl Autoconfig for Server {
         )
     }
 
+    async fn handle_autodiscover_v2_request(&self, req: &HttpRequest) -> trc::Result<HttpResponse> {
+        // Both /autodiscover/autodiscover.json?Email=<addr> and
+        // /autodiscover/autodiscover.json/v1.0/<addr> are in use
+        let params = UrlParams::new(req.uri().query());
+        let emailaddress = params
+            .get("Email")
+            .or_else(|| {
+                req.uri()
+                    .path()
+                    .strip_prefix("/autodiscover/autodiscover.json/v1.0/")
+            })
+            .map(|email| decode_path_element(email).to_lowercase())
+            .unwrap_or_default();
+        let protocol = params.get("Protocol").unwrap_or_default();
+        let AutoconfigParameters { server_name, .. } =
+            self.autoconfig_parameters(&emailaddress).await?;
+
+        Ok(if protocol.eq_ignore_ascii_case("AutodiscoverV1") {
+            JsonResponse::new(json!({
+                "Protocol": "AutodiscoverV1",
+                "Url": format!("https://{server_name}/autodiscover/autodiscover.xml"),
+            }))
+            .into_http_response()
+        } else {
+            JsonResponse::with_status(
+                StatusCode::BAD_REQUEST,
+                json!({
+                    "ErrorCode": "InvalidProtocol",
+                    "ErrorMessage": format!(
+                        "The given protocol value '{protocol}' is invalid. Supported values are 'AutodiscoverV1'."
+                    ),
+                }),
+            )
+            .into_http_response()
+        })
+    }
+
     async fn autoconfig_parameters<'x>(
         &self,
         emailaddress: &'x str,
-    ) -> trc::Result<(String, String, &'x str)> {
+    ) -> trc::Result<AutoconfigParameters<'x>> {
         let (_, domain) = emailaddress.rsplit_once('@').ok_or_else(|| {
             trc::ResourceEvent::BadParameters
                 .into_err()
                 .details("Missing domain in email address")
         })?;
 
-        // Obtain server name
-        let server_name = self
+        // Settings are only provided for domains hosted on this server
+        if !self
+            .core
+            .storage
+            .directory
+            .is_local_domain(domain)
+            .await
+            .caused_by(trc::location!())?
+        {
+            return Err(trc::ResourceEvent::NotFound
+                .into_err()
+                .details("Domain not hosted on this server")
+                .ctx(trc::Key::Domain, domain.to_string()));
+        }
+
+        // Obtain server name, domains can override the default hostname
+        let server_name = if let Some(server_name) = self
             .core
             .storage
             .config
-            .get("lookup.default.hostname")
+            .get(format!("autoconfig.{domain}.hostname"))
             .await?
-            .ok_or_else(|| {
-                trc::EventType::Config(trc::ConfigEvent::BuildError)
-                    .caused_by(trc::location!())
-                    .details("Server name not configured")
-            })?;
+        {
+            server_name
+        } else {
+            self.core
+                .storage
+                .config
+                .get("lookup.default.hostname")
+                .await?
+                .ok_or_else(|| {
+                    trc::EventType::Config(trc::ConfigEvent::BuildError)
+                        .caused_by(trc::location!())
+                        .details("Server name not configured")
+                })?
+        };
 
         // Find the account name by e-mail address
         let mut account_name = emailaddress.to_string();
+        let mut redirect = None;
         if let Some(id) = self
             .core
             .storage
@@ -209,21 +364,31 @@ impl Autoconfig for Server {
                 .query(QueryBy::Id(id), false)
                 .await
             {
-                if principal
+                match principal
                     .get_str_array(PrincipalField::Emails)
                     .and_then(|emails| emails.first())
-                    .map_or(false, |email| email.eq_ignore_ascii_case(emailaddress))
                 {
-                    account_name = principal.take_str(PrincipalField::Name).unwrap_or_default();
+                    Some(email) if email.eq_ignore_ascii_case(emailaddress) => {
+                        account_name = principal.take_str(PrincipalField::Name).unwrap_or_default();
+                    }
+                    Some(email) => {
+                        redirect = Some(email.to_lowercase());
+                    }
+                    None => (),
                 }
             }
         }
 
-        Ok((account_name, server_name, domain))
+        Ok(AutoconfigParameters {
+            account_name,
+            server_name,
+            domain,
+            redirect,
+        })
     }
 }
 
-fn parse_autodiscover_request(bytes: &[u8]) -> Result<String, String> {
+fn parse_autodiscover_request(bytes: &[u8]) -> Result<(String, Option<String>), String> {
     if bytes.is_empty() {
         return Err("Empty request body".to_string());
     }
@@ -231,81 +396,59 @@ fn parse_autodiscover_request(bytes: &[u8]) -> Result<String, String> {
     let mut reader = Reader::from_reader(bytes);
     reader.config_mut().trim_text(true);
     let mut buf = Vec::with_capacity(128);
+    let mut path = Vec::with_capacity(3);
+    let mut emailaddress = None;
+    let mut schema = None;
 
-    'outer: for tag_name in ["Autodiscover", "Request", "EMailAddress"] {
-        loop {
-            match reader.read_event_into(&mut buf) {
-                Ok(Event::Start(e)) => {
-                    let found_tag_name = e.name();
-                    if tag_name
-                        .as_bytes()
-                        .eq_ignore_ascii_case(found_tag_name.as_ref())
-                    {
-                        continue 'outer;
-                    } else if tag_name == "EMailAddress" {
-                        // Skip unsupported tags under Request, such as AcceptableResponseSchema
-                        let mut tag_count = 0;
-                        loop {
-                            match reader.read_event_into(&mut buf) {
-                                Ok(Event::End(_)) => {
-                                    if tag_count == 0 {
-                                        break;
-                                    } else {
-                                        tag_count -= 1;
-                                    }
-                                }
-                                Ok(Event::Start(_)) => {
-                                    tag_count += 1;
-                                }
-                                Ok(Event::Eof) => {
-                                    return Err(format!(
-                                        "Expected value, found unexpected EOF at position {}.",
-                                        reader.buffer_position()
-                                    ))
-                                }
-                                _ => (),
-                            }
-                        }
-                    } else {
-                        return Err(format!(
-                            "Expected tag {}, found unexpected tag {} at position {}.",
-                            tag_name,
-                            String::from_utf8_lossy(found_tag_name.as_ref()),
-                            reader.buffer_position()
-                        ));
-                    }
-                }
-                Ok(Event::Decl(_) | Event::Text(_)) => (),
-                Err(e) => {
-                    return Err(format!(
-                        "Error at position {}: {:?}",
-                        reader.buffer_position(),
-                        e
-                    ))
-                }
-                Ok(event) => {
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) => {
+                let name = String::from_utf8_lossy(e.local_name().as_ref()).into_owned();
+                let is_expected = match path.len() {
+                    0 => name.eq_ignore_ascii_case("Autodiscover"),
+                    1 => name.eq_ignore_ascii_case("Request"),
+                    _ => true,
+                };
+                if !is_expected {
                     return Err(format!(
-                        "Expected tag {}, found unexpected event {event:?} at position {}.",
-                        tag_name,
+                        "Unexpected tag {name} at position {}.",
                         reader.buffer_position()
-                    ))
+                    ));
                 }
+                path.push(name);
             }
-        }
-    }
-
-    if let Ok(Event::Text(text)) = reader.read_event_into(&mut buf) {
-        if let Ok(text) = text.unescape() {
-            if text.contains('@') {
-                return Ok(text.trim().to_lowercase());
+            Ok(Event::End(_)) => {
+                path.pop();
+            }
+            Ok(Event::Text(text)) if path.len() == 3 => {
+                let text = text
+                    .unescape()
+                    .map_err(|err| err.to_string())?
+                    .trim()
+                    .to_string();
+                if path[2].eq_ignore_ascii_case("EMailAddress") {
+                    emailaddress = Some(text.to_lowercase());
+                } else if path[2].eq_ignore_ascii_case("AcceptableResponseSchema") {
+                    schema = Some(text);
+                }
+            }
+            Ok(Event::Eof) => break,
+            Ok(_) => (),
+            Err(e) => {
+                return Err(format!(
+                    "Error at position {}: {:?}",
+                    reader.buffer_position(),
+                    e
+                ))
             }
         }
+        buf.clear();
     }
 
-    Err(format!(
-        "Expected email address, found unexpected value at position {}.",
-        reader.buffer_position()
-    ))
+    match emailaddress {
+        Some(emailaddress) if emailaddress.contains('@') => Ok((emailaddress, schema)),
+        _ => Err("Missing or invalid email address.".to_string()),
+    }
 }
 
 #[cfg(test)]
@@ -323,7 +466,37 @@ mod tests {
 
         assert_eq!(
             super::parse_autodiscover_request(r.as_bytes()).unwrap(),
-            "email@example.com"
+            (
+                "email@example.com".to_string(),
+                Some(super::SCHEMA_OUTLOOK.to_string())
+            )
         );
+
+        let r = r#"<?xml version="1.0" encoding="utf-8"?>
+            <Autodiscover xmlns="http://schemas.microsoft.com/exchange/autodiscover/mobilesync/requestschema/2006">
+                <Request>
+                    <EMailAddress>Email@Example.com</EMailAddress>
+                    <AcceptableResponseSchema>http://schemas.microsoft.com/exchange/autodiscover/mobilesync/responseschema/2006</AcceptableResponseSchema>
+                </Request>
+            </Autodiscover>"#;
+
+        assert_eq!(
+            super::parse_autodiscover_request(r.as_bytes()).unwrap(),
+            (
+                "email@example.com".to_string(),
+                Some(super::SCHEMA_MOBILESYNC.to_string())
+            )
+        );
+
+        for r in [
+            "",
+            "<Autodiscover><Request></Request></Autodiscover>",
+            "<Request><EMailAddress>email@example.com</EMailAddress></Request>",
+        ] {
+            assert!(
+                super::parse_autodiscover_request(r.as_bytes()).is_err(),
+                "{r}"
+            );
+        }
     }
 }
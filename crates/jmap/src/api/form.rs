@@ -212,7 +212,7 @@ impl FormHandler for Server {
                     DeliveryResult::Success => {
                         has_success = true;
                     }
-                    DeliveryResult::TemporaryFailure { reason }
+                    DeliveryResult::TemporaryFailure { reason, .. }
                     | DeliveryResult::PermanentFailure { reason, .. } => failure = Some(reason),
                 }
             }
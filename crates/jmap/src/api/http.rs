@@ -13,6 +13,7 @@ use common::{
     ipc::StateEvent,
     listener::{ServerInstance, SessionData, SessionManager, SessionStream},
     manager::webadmin::Resource,
+    telemetry::tracers::otel::parse_traceparent,
     Inner, Server,
 };
 use directory::Permission;
@@ -32,6 +33,7 @@ use jmap_proto::{
     types::{blob::BlobId, id::Id},
 };
 use std::future::Future;
+use store::backend::circuit_breaker::CircuitState;
 use trc::SecurityEvent;
 use utils::url_params::UrlParams;
 
@@ -48,6 +50,7 @@ use crate::{
         rate_limit::RateLimiter,
     },
     blob::{download::BlobDownload, upload::BlobUpload, DownloadResponse, UploadResponse},
+    dav::{DavRequestHandler, DAV_ROOT},
     websocket::upgrade::WebSocketUpgrade,
 };
 
@@ -69,6 +72,7 @@ pub struct HttpSessionData {
     pub remote_port: u16,
     pub is_tls: bool,
     pub session_id: u64,
+    pub trace_parent: Option<String>,
 }
 
 pub trait ParseHttp: Sync + Send {
@@ -218,6 +222,9 @@ impl ParseHttp for Server {
                     _ => (),
                 }
             }
+            "dav" => {
+                return self.handle_dav_request(req, &session).await;
+            }
             ".well-known" => match (path.next().unwrap_or_default(), req.method()) {
                 ("jmap", &Method::GET) => {
                     // Authenticate request
@@ -274,6 +281,10 @@ impl ParseHttp for Server {
                         return self.handle_autoconfig_request(&req).await;
                     }
                 }
+                ("carddav", _) => {
+                    return Ok(HttpResponse::new_empty(StatusCode::MOVED_PERMANENTLY)
+                        .with_header(header::LOCATION, DAV_ROOT));
+                }
                 (_, &Method::OPTIONS) => {
                     return Ok(StatusCode::NO_CONTENT.into_http_response());
                 }
@@ -410,17 +421,19 @@ impl ParseHttp for Server {
                     return self.handle_autoconfig_request(&req).await;
                 }
             }
-            "autodiscover" => {
-                if req.method() == Method::POST
-                    && path.next().unwrap_or_default() == "autodiscover.xml"
-                {
+            "autodiscover" => match (path.next().unwrap_or_default(), req.method()) {
+                ("autodiscover.xml", &Method::POST) => {
                     return self
                         .handle_autodiscover_request(
                             fetch_body(&mut req, 8192, session.session_id).await,
                         )
                         .await;
                 }
-            }
+                ("autodiscover.json", &Method::GET) => {
+                    return self.handle_autodiscover_v2_request(&req).await;
+                }
+                _ => (),
+            },
             "robots.txt" => {
                 return Ok(
                     Resource::new("text/plain", b"User-agent: *\nDisallow: /\n".to_vec())
@@ -432,20 +445,45 @@ impl ParseHttp for Server {
                     return Ok(StatusCode::OK.into_http_response());
                 }
                 "ready" => {
-                    return Ok({
-                        if !self.core.storage.data.is_none() {
-                            StatusCode::OK
-                        } else {
-                            StatusCode::SERVICE_UNAVAILABLE
-                        }
-                    }
-                    .into_http_response());
+                    let store = &self.core.storage.data;
+                    let circuit_state = store.circuit_state();
+                    let status = if !store.is_none() && circuit_state != Some(CircuitState::Open) {
+                        StatusCode::OK
+                    } else {
+                        StatusCode::SERVICE_UNAVAILABLE
+                    };
+
+                    return Ok(if let Some(circuit_state) = circuit_state {
+                        JsonResponse::with_status(
+                            status,
+                            serde_json::json!({
+                                "store": {
+                                    "circuit": circuit_state.as_str(),
+                                }
+                            }),
+                        )
+                        .into_http_response()
+                    } else {
+                        status.into_http_response()
+                    });
                 }
                 _ => (),
             },
             "metrics" => match path.next().unwrap_or_default() {
-                "prometheus" => {
+                "prometheus" | "" => {
                     if let Some(prometheus) = &self.core.metrics.prometheus {
+                        if !prometheus.allowed_ips.is_empty()
+                            && !prometheus
+                                .allowed_ips
+                                .iter()
+                                .any(|mask| mask.matches(&session.remote_ip))
+                        {
+                            return Err(SecurityEvent::Unauthorized
+                                .into_err()
+                                .details("Remote address not allowed to scrape metrics.")
+                                .ctx(trc::Key::RemoteIp, session.remote_ip)
+                                .caused_by(trc::location!()));
+                        }
                         if let Some(auth) = &prometheus.auth {
                             if req
                                 .authorization_basic()
@@ -629,6 +667,14 @@ async fn handle_session<T: SessionStream>(inner: Arc<Inner>, session: SessionDat
                         session.remote_ip
                     };
 
+                    // Obtain W3C trace context
+                    let trace_parent = req
+                        .headers()
+                        .get("traceparent")
+                        .and_then(|h| h.to_str().ok())
+                        .filter(|h| parse_traceparent(h).is_some())
+                        .map(|h| h.to_string());
+
                     // Parse HTTP request
                     let response = match server
                         .parse_http_request(
@@ -641,6 +687,7 @@ async fn handle_session<T: SessionStream>(inner: Arc<Inner>, session: SessionDat
                                 remote_port: session.remote_port,
                                 is_tls,
                                 session_id: session.session_id,
+                                trace_parent,
                             },
                         )
                         .await
@@ -845,6 +892,7 @@ impl HttpResponse {
             content_type: "".into(),
             content_disposition: "".into(),
             cache_control: "".into(),
+            headers: Vec::new(),
             body: HttpResponseBody::Empty,
         }
     }
@@ -859,6 +907,7 @@ impl HttpResponse {
             content_type: content_type.into(),
             content_disposition: "".into(),
             cache_control: "".into(),
+            headers: Vec::new(),
             body: HttpResponseBody::Text(body.into()),
         }
     }
@@ -873,10 +922,16 @@ impl HttpResponse {
             content_type: content_type.into(),
             content_disposition: "".into(),
             cache_control: "".into(),
+            headers: Vec::new(),
             body: HttpResponseBody::Binary(body.into()),
         }
     }
 
+    pub fn with_header(mut self, name: header::HeaderName, value: impl Into<String>) -> Self {
+        self.headers.push((name, value.into()));
+        self
+    }
+
     pub fn size(&self) -> usize {
         match &self.body {
             HttpResponseBody::Text(value) => value.len(),
@@ -889,7 +944,10 @@ impl HttpResponse {
         self,
     ) -> hyper::Response<http_body_util::combinators::BoxBody<hyper::body::Bytes, hyper::Error>>
     {
-        let builder = hyper::Response::builder().status(self.status);
+        let mut builder = hyper::Response::builder().status(self.status);
+        for (name, value) in self.headers {
+            builder = builder.header(name, value);
+        }
 
         match self.body {
             HttpResponseBody::Text(body) => builder
@@ -955,6 +1013,7 @@ impl<T: serde::Serialize> ToHttpResponse for JsonResponse<T> {
                 "no-store, no-cache, must-revalidate"
             }
             .into(),
+            headers: Vec::new(),
             body: HttpResponseBody::Text(serde_json::to_string(&self.inner).unwrap_or_default()),
         }
     }
@@ -992,6 +1051,14 @@ impl ToHttpResponse for &trc::Error {
                 }
             }
             .into_http_response(),
+            trc::EventType::Limit(trc::LimitEvent::TooManyRequests) => {
+                let response = self.to_request_error().into_http_response();
+                if let Some(retry_after) = self.value_as_uint(trc::Key::Expires) {
+                    response.with_header(header::RETRY_AFTER, retry_after.to_string())
+                } else {
+                    response
+                }
+            }
 
             _ => self.to_request_error().into_http_response(),
         }
@@ -1134,6 +1201,7 @@ impl ToHttpResponse for DownloadResponse {
             )
             .into(),
             cache_control: "private, immutable, max-age=31536000".into(),
+            headers: Vec::new(),
             body: HttpResponseBody::Binary(self.blob),
         }
     }
@@ -0,0 +1,109 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use common::{
+    auth::AccessToken,
+    telemetry::audit::{AuditEvent, AuditFilter},
+    Server,
+};
+use directory::{
+    backend::internal::{PrincipalField, PrincipalUpdate},
+    Permission,
+};
+use hyper::Method;
+use serde_json::json;
+use utils::url_params::UrlParams;
+
+use crate::api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse};
+
+use super::decode_path_element;
+
+pub trait AuditManagement: Sync + Send {
+    fn handle_manage_audit(
+        &self,
+        req: &HttpRequest,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl AuditManagement for Server {
+    async fn handle_manage_audit(
+        &self,
+        req: &HttpRequest,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        if req.method() != Method::GET {
+            return Err(trc::ResourceEvent::NotFound.into_err());
+        }
+
+        // Validate the access token
+        access_token.assert_has_permission(Permission::AuditLogView)?;
+
+        let params = UrlParams::new(req.uri().query());
+        let records = self
+            .query_audit_log(&AuditFilter {
+                from: params.parse::<u64>("from").unwrap_or_default(),
+                to: params.parse::<u64>("to").unwrap_or(u64::MAX),
+                actor: params.get("actor").map(|actor| actor.to_string()),
+                limit: params.parse::<usize>("limit").unwrap_or_default(),
+            })
+            .await?;
+
+        Ok(JsonResponse::new(json!({
+                "data": {
+                    "items": records,
+                    "total": records.len(),
+                },
+        }))
+        .into_http_response())
+    }
+}
+
+// Maps a mutating management API request to the audit event describing it
+pub(super) fn admin_audit_event(
+    method: &Method,
+    path: &[&str],
+    body: Option<&[u8]>,
+    access_token: &AccessToken,
+) -> Option<AuditEvent> {
+    if *method == Method::GET {
+        return None;
+    }
+
+    match (path.first().copied(), path.get(1).copied(), method) {
+        (Some("account"), Some("auth"), &Method::POST) => AuditEvent::SecretChange {
+            account: access_token.name.clone(),
+        },
+        (Some("principal"), Some(name), &Method::PATCH)
+            if body
+                .and_then(|body| serde_json::from_slice::<Vec<PrincipalUpdate>>(body).ok())
+                .is_some_and(|changes| {
+                    changes
+                        .iter()
+                        .any(|change| change.field == PrincipalField::Secrets)
+                }) =>
+        {
+            AuditEvent::SecretChange {
+                account: decode_path_element(name).into_owned(),
+            }
+        }
+        (Some("queue"), Some("messages"), &Method::DELETE) => AuditEvent::MessageDeletion {
+            source: "queue".to_string(),
+            id: path.get(2).copied().unwrap_or_default().to_string(),
+        },
+        (Some("quarantine"), Some(id), &Method::DELETE) => AuditEvent::MessageDeletion {
+            source: "quarantine".to_string(),
+            id: decode_path_element(id).into_owned(),
+        },
+        _ => AuditEvent::AdminRequest {
+            method: method.to_string(),
+            path: path.join("/"),
+        },
+    }
+    .into()
+}
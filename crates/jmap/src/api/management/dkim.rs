@@ -6,7 +6,12 @@
 
 use std::str::FromStr;
 
-use common::{auth::AccessToken, config::smtp::auth::simple_pem_parse, Server};
+use common::{
+    auth::AccessToken,
+    config::smtp::auth::{simple_pem_parse, DkimCanonicalization},
+    dkim::{DkimKeyAlgorithm, DkimKeyRecord, DkimSignPolicy},
+    Server,
+};
 use directory::{backend::internal::manage, Permission};
 use hyper::Method;
 use mail_auth::{
@@ -20,6 +25,7 @@ use rsa::pkcs1::DecodeRsaPublicKey;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use store::write::now;
+use utils::config::utils::ParseValue;
 
 use crate::api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse};
 
@@ -40,6 +46,24 @@ struct DkimSignature {
     selector: Option<String>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DkimKeyRequest {
+    pub domain: Option<String>,
+    pub algorithm: DkimKeyAlgorithm,
+    pub selector: Option<String>,
+    pub policy: Option<DkimSignPolicy>,
+    // Seconds the replaced keys keep signing after a rotation
+    pub overlap: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DkimRetireRequest {
+    // Retirement time, immediate when missing
+    pub at: Option<u64>,
+}
+
 pub trait DkimManagement: Sync + Send {
     fn handle_manage_dkim(
         &self,
@@ -54,6 +78,14 @@ pub trait DkimManagement: Sync + Send {
         path: Vec<&str>,
     ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
 
+    fn handle_manage_dkim_keys(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+
     fn handle_create_signature(
         &self,
         body: Option<Vec<u8>>,
@@ -76,6 +108,27 @@ impl DkimManagement for Server {
         body: Option<Vec<u8>>,
         access_token: &AccessToken,
     ) -> trc::Result<HttpResponse> {
+        if path.get(1).copied() == Some("keys") {
+            return self
+                .handle_manage_dkim_keys(req, path, body, access_token)
+                .await;
+        }
+
+        if path.get(1).copied() == Some("check") {
+            let domain = match (path.get(2), req.method()) {
+                (Some(domain), &Method::GET) => decode_path_element(domain),
+                _ => return Err(trc::ResourceEvent::NotFound.into_err()),
+            };
+
+            // Validate the access token
+            access_token.assert_has_permission(Permission::DkimSignatureGet)?;
+
+            return Ok(JsonResponse::new(json!({
+                "data": self.check_dkim_domain(&domain).await?,
+            }))
+            .into_http_response());
+        }
+
         match *req.method() {
             Method::GET => {
                 // Validate the access token
@@ -125,6 +178,161 @@ impl DkimManagement for Server {
         .into_http_response())
     }
 
+    async fn handle_manage_dkim_keys(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        let domain = path.get(2).map(|domain| decode_path_element(domain));
+        let selector = path.get(3).map(|selector| decode_path_element(selector));
+
+        match (
+            domain.as_deref(),
+            selector.as_deref(),
+            path.get(4).copied(),
+            req.method(),
+        ) {
+            (domain, None, None, &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::DkimSignatureGet)?;
+
+                let items = self
+                    .list_dkim_keys(domain)
+                    .await?
+                    .iter()
+                    .map(DkimKeyRecord::info)
+                    .collect::<Vec<_>>();
+
+                Ok(JsonResponse::new(json!({
+                    "data": {
+                        "total": items.len(),
+                        "items": items,
+                    },
+                }))
+                .into_http_response())
+            }
+            (path_domain, action, None, &Method::POST)
+                if matches!(
+                    (path_domain, action),
+                    (None, None) | (Some(_), Some("rotate"))
+                ) =>
+            {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::DkimSignatureCreate)?;
+
+                let is_rotation = path_domain.is_some();
+                let request =
+                    serde_json::from_slice::<DkimKeyRequest>(body.as_deref().unwrap_or_default())
+                        .map_err(|err| {
+                        trc::EventType::Resource(trc::ResourceEvent::BadParameters)
+                            .from_json_error(err)
+                    })?;
+                let domain = path_domain
+                    .or(request.domain.as_deref())
+                    .map(|domain| domain.trim().trim_end_matches('.').to_lowercase())
+                    .filter(|domain| is_valid_dns_label(domain))
+                    .ok_or_else(|| {
+                        trc::EventType::Resource(trc::ResourceEvent::BadParameters)
+                            .into_err()
+                            .details("Invalid or missing domain")
+                    })?;
+                let selector = request
+                    .selector
+                    .unwrap_or_else(|| default_selector(request.algorithm.into(), true));
+                if !is_valid_dns_label(&selector) {
+                    return Err(trc::EventType::Resource(trc::ResourceEvent::BadParameters)
+                        .into_err()
+                        .details("Invalid selector"));
+                }
+                let managed = &self.core.smtp.mail_auth.dkim.managed;
+                let policy = request.policy.unwrap_or_else(|| managed.policy.clone());
+                if let Err(err) = DkimCanonicalization::parse_value(&policy.canonicalization) {
+                    return Err(trc::EventType::Resource(trc::ResourceEvent::BadParameters)
+                        .into_err()
+                        .details("Invalid canonicalization")
+                        .reason(err));
+                }
+
+                // Generate key pair
+                let algorithm = Algorithm::from(request.algorithm);
+                let private_key = generate_dkim_private_key(algorithm)?;
+                let public_key = dkim_public_key_from_der(algorithm, &private_key)?;
+                let record = if is_rotation {
+                    self.rotate_dkim_key(
+                        &domain,
+                        &selector,
+                        request.algorithm,
+                        &private_key,
+                        public_key,
+                        policy,
+                        request
+                            .overlap
+                            .map(std::time::Duration::from_secs)
+                            .unwrap_or(managed.rotation_overlap),
+                    )
+                    .await?
+                } else {
+                    self.store_dkim_key(
+                        &domain,
+                        &selector,
+                        request.algorithm,
+                        &private_key,
+                        public_key,
+                        policy,
+                        None,
+                    )
+                    .await?
+                };
+
+                Ok(JsonResponse::new(json!({
+                    "data": record.info(),
+                }))
+                .into_http_response())
+            }
+            (Some(domain), Some(selector), Some("retire"), &Method::POST) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::DkimSignatureCreate)?;
+
+                let request = match body.as_deref() {
+                    Some(body) if !body.is_empty() => {
+                        serde_json::from_slice::<DkimRetireRequest>(body).map_err(|err| {
+                            trc::EventType::Resource(trc::ResourceEvent::BadParameters)
+                                .from_json_error(err)
+                        })?
+                    }
+                    _ => DkimRetireRequest { at: None },
+                };
+                if !self
+                    .retire_dkim_key(domain, selector, request.at.unwrap_or_else(now))
+                    .await?
+                {
+                    return Err(trc::ResourceEvent::NotFound.into_err());
+                }
+
+                Ok(JsonResponse::new(json!({
+                    "data": (),
+                }))
+                .into_http_response())
+            }
+            (Some(domain), Some(selector), None, &Method::DELETE) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::DkimSignatureCreate)?;
+
+                if !self.delete_dkim_key(domain, selector).await? {
+                    return Err(trc::ResourceEvent::NotFound.into_err());
+                }
+
+                Ok(JsonResponse::new(json!({
+                    "data": (),
+                }))
+                .into_http_response())
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+
     async fn handle_create_signature(&self, body: Option<Vec<u8>>) -> trc::Result<HttpResponse> {
         let request =
             match serde_json::from_slice::<DkimSignature>(body.as_deref().unwrap_or_default()) {
@@ -143,19 +351,9 @@ impl DkimManagement for Server {
         let id = request
             .id
             .unwrap_or_else(|| format!("{algo_str}-{}", request.domain));
-        let selector = request.selector.unwrap_or_else(|| {
-            let dt = DateTime::from_timestamp(now() as i64);
-            format!(
-                "{:04}{:02}{}",
-                dt.year,
-                dt.month,
-                if Algorithm::Rsa == request.algorithm {
-                    "r"
-                } else {
-                    "e"
-                }
-            )
-        });
+        let selector = request
+            .selector
+            .unwrap_or_else(|| default_selector(request.algorithm, false));
 
         // Make sure the signature does not exist already
         if let Some(value) = self
@@ -195,19 +393,7 @@ impl DkimManagement for Server {
         };
         let mut pk = format!("-----BEGIN {pk_type}-----\n").into_bytes();
         let mut lf_count = 65;
-        for ch in base64_encode(
-            match algo {
-                Algorithm::Rsa => DkimKeyPair::generate_rsa(2048),
-                Algorithm::Ed25519 => DkimKeyPair::generate_ed25519(),
-            }
-            .map_err(|err| {
-                manage::error("Failed to generate key", err.to_string().into())
-                    .caused_by(trc::location!())
-            })?
-            .private_key(),
-        )
-        .unwrap_or_default()
-        {
+        for ch in base64_encode(&generate_dkim_private_key(algo)?).unwrap_or_default() {
             pk.push(ch);
             lf_count -= 1;
             if lf_count == 0 {
@@ -251,36 +437,83 @@ impl DkimManagement for Server {
 
 pub fn obtain_dkim_public_key(algo: Algorithm, pk: &str) -> trc::Result<String> {
     match simple_pem_parse(pk) {
-        Some(der) => match algo {
-            Algorithm::Rsa => match RsaKey::<Sha256>::from_der(&der).and_then(|key| {
-                Document::from_pkcs1_der(&key.public_key())
-                    .map_err(|err| mail_auth::Error::CryptoError(err.to_string()))
-            }) {
-                Ok(pk) => Ok(
-                    String::from_utf8(base64_encode(pk.as_bytes()).unwrap_or_default())
-                        .unwrap_or_default(),
-                ),
-                Err(err) => Err(manage::error(
-                    "Failed to read RSA DER",
-                    err.to_string().into(),
-                )),
-            },
-            Algorithm::Ed25519 => {
-                match Ed25519Key::from_pkcs8_maybe_unchecked_der(&der)
-                    .map_err(|err| mail_auth::Error::CryptoError(err.to_string()))
-                {
-                    Ok(pk) => Ok(String::from_utf8(
-                        base64_encode(&pk.public_key()).unwrap_or_default(),
-                    )
-                    .unwrap_or_default()),
-                    Err(err) => Err(manage::error("Crypto error", err.to_string().into())),
-                }
-            }
-        },
+        Some(der) => dkim_public_key_from_der(algo, &der),
         None => Err(manage::error("Failed to decode private key", None::<u32>)),
     }
 }
 
+fn dkim_public_key_from_der(algo: Algorithm, der: &[u8]) -> trc::Result<String> {
+    match algo {
+        Algorithm::Rsa => match RsaKey::<Sha256>::from_der(der).and_then(|key| {
+            Document::from_pkcs1_der(&key.public_key())
+                .map_err(|err| mail_auth::Error::CryptoError(err.to_string()))
+        }) {
+            Ok(pk) => Ok(
+                String::from_utf8(base64_encode(pk.as_bytes()).unwrap_or_default())
+                    .unwrap_or_default(),
+            ),
+            Err(err) => Err(manage::error(
+                "Failed to read RSA DER",
+                err.to_string().into(),
+            )),
+        },
+        Algorithm::Ed25519 => {
+            match Ed25519Key::from_pkcs8_maybe_unchecked_der(der)
+                .map_err(|err| mail_auth::Error::CryptoError(err.to_string()))
+            {
+                Ok(pk) => Ok(String::from_utf8(
+                    base64_encode(&pk.public_key()).unwrap_or_default(),
+                )
+                .unwrap_or_default()),
+                Err(err) => Err(manage::error("Crypto error", err.to_string().into())),
+            }
+        }
+    }
+}
+
+// Private keys are returned in PKCS#1 DER for RSA and PKCS#8 DER for Ed25519
+fn generate_dkim_private_key(algo: Algorithm) -> trc::Result<Vec<u8>> {
+    match algo {
+        Algorithm::Rsa => DkimKeyPair::generate_rsa(2048),
+        Algorithm::Ed25519 => DkimKeyPair::generate_ed25519(),
+    }
+    .map(|key_pair| key_pair.private_key().to_vec())
+    .map_err(|err| {
+        manage::error("Failed to generate key", err.to_string().into()).caused_by(trc::location!())
+    })
+}
+
+// Rotated keys may be created more than once a month, include the day in their selectors
+fn default_selector(algo: Algorithm, with_day: bool) -> String {
+    let dt = DateTime::from_timestamp(now() as i64);
+    let mut selector = format!("{:04}{:02}", dt.year, dt.month);
+    if with_day {
+        selector.push_str(&format!("{:02}", dt.day));
+    }
+    selector.push(if Algorithm::Rsa == algo { 'r' } else { 'e' });
+    selector
+}
+
+fn is_valid_dns_label(value: &str) -> bool {
+    !value.is_empty()
+        && value.len() <= 253
+        && value
+            .split('.')
+            .all(|label| !label.is_empty() && label.len() <= 63)
+        && value
+            .bytes()
+            .all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, b'-' | b'_' | b'.'))
+}
+
+impl From<DkimKeyAlgorithm> for Algorithm {
+    fn from(algo: DkimKeyAlgorithm) -> Self {
+        match algo {
+            DkimKeyAlgorithm::Rsa => Algorithm::Rsa,
+            DkimKeyAlgorithm::Ed25519 => Algorithm::Ed25519,
+        }
+    }
+}
+
 impl FromStr for Algorithm {
     type Err = ();
 
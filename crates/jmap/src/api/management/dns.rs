@@ -183,7 +183,7 @@ impl DnsManagement for Server {
                             "_submission{}._tcp.{domain_name}.",
                             if is_tls { "s" } else { "" }
                         ),
-                        content: format!("0 1 {port} {server_name}."),
+                        content: format!("{} 1 {port} {server_name}.", srv_priority(is_tls)),
                     });
                 }
                 ("imap" | "pop3", port @ 1..=u16::MAX) => {
@@ -193,7 +193,7 @@ impl DnsManagement for Server {
                             "_{protocol}{}._tcp.{domain_name}.",
                             if is_tls { "s" } else { "" }
                         ),
-                        content: format!("0 1 {port} {server_name}."),
+                        content: format!("{} 1 {port} {server_name}.", srv_priority(is_tls)),
                     });
                 }
                 ("http", _) if is_tls => {
@@ -300,3 +300,12 @@ impl DnsManagement for Server {
         Ok(records)
     }
 }
+
+// RFC 8314, section 5.1: implicit TLS services are preferred over STARTTLS
+fn srv_priority(is_tls: bool) -> u16 {
+    if is_tls {
+        0
+    } else {
+        10
+    }
+}
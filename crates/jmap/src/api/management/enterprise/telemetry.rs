@@ -210,6 +210,7 @@ impl TelemetryApi for Server {
                     content_type: "text/event-stream".into(),
                     content_disposition: "".into(),
                     cache_control: "no-store".into(),
+                    headers: Vec::new(),
                     body: HttpResponseBody::Stream(BoxBody::new(StreamBody::new(
                         async_stream::stream! {
                             let mut last_message = Instant::now() - throttle;
@@ -475,6 +476,7 @@ impl TelemetryApi for Server {
                     content_type: "text/event-stream".into(),
                     content_disposition: "".into(),
                     cache_control: "no-store".into(),
+                    headers: Vec::new(),
                     body: HttpResponseBody::Stream(BoxBody::new(StreamBody::new(
                         async_stream::stream! {
 
@@ -0,0 +1,144 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::path::PathBuf;
+
+use common::{auth::AccessToken, ImportFormat, ImportJob, Server};
+use directory::{backend::internal::manage::ManageDirectory, Permission};
+use hyper::Method;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use store::write::now;
+
+use crate::{
+    api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse},
+    changes::write::ChangeLog,
+    email::bulk_import::EmailBulkImport,
+};
+
+use super::decode_path_element;
+use std::future::Future;
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportRequest {
+    pub format: ImportFormat,
+    pub path: PathBuf,
+    #[serde(default)]
+    pub mailbox: Option<String>,
+}
+
+pub trait ManageImport: Sync + Send {
+    fn handle_manage_import(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl ManageImport for Server {
+    async fn handle_manage_import(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        // Validate the access token
+        access_token.assert_has_permission(Permission::AccountImport)?;
+
+        match (path.get(1).copied(), req.method()) {
+            (Some(account), &Method::POST) => {
+                let request =
+                    serde_json::from_slice::<ImportRequest>(body.as_deref().unwrap_or_default())
+                        .map_err(|err| {
+                            trc::EventType::Resource(trc::ResourceEvent::BadParameters)
+                                .from_json_error(err)
+                        })?;
+                let account_id = self
+                    .core
+                    .storage
+                    .data
+                    .get_principal_id(decode_path_element(account).as_ref())
+                    .await?
+                    .ok_or_else(|| trc::ManageEvent::NotFound.into_err())?;
+
+                // Register the job, its status is updated as the import progresses
+                let job_id = self.generate_snowflake_id()?;
+                self.inner.data.import_jobs.lock().insert(
+                    job_id,
+                    ImportJob {
+                        account_id,
+                        format: request.format,
+                        started: now(),
+                        finished: 0,
+                        imported: 0,
+                        duplicates: 0,
+                        failed: 0,
+                        mailboxes: 0,
+                        error: None,
+                    },
+                );
+
+                let server = self.clone();
+                tokio::spawn(async move {
+                    let result = server
+                        .email_bulk_import(
+                            job_id,
+                            account_id,
+                            request.format,
+                            &request.path,
+                            request.mailbox.as_deref(),
+                        )
+                        .await;
+                    let mut jobs = server.inner.data.import_jobs.lock();
+                    match result {
+                        Ok(result) => {
+                            jobs.insert(job_id, result);
+                        }
+                        Err(err) => {
+                            if let Some(job) = jobs.get_mut(&job_id) {
+                                job.finished = now();
+                                job.error = err.to_string().into();
+                            }
+                            trc::error!(err
+                                .details("Failed to import messages")
+                                .account_id(account_id));
+                        }
+                    }
+                });
+
+                Ok(JsonResponse::new(json!({
+                    "data": job_id,
+                }))
+                .into_http_response())
+            }
+            (Some(job_id), &Method::GET) => {
+                let job = job_id
+                    .parse::<u64>()
+                    .ok()
+                    .and_then(|job_id| self.inner.data.import_jobs.lock().get(&job_id).cloned())
+                    .ok_or_else(|| trc::ResourceEvent::NotFound.into_err())?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": job,
+                }))
+                .into_http_response())
+            }
+            (None, &Method::GET) => {
+                let jobs = self.inner.data.import_jobs.lock().clone();
+
+                Ok(JsonResponse::new(json!({
+                    "data": jobs,
+                }))
+                .into_http_response())
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+}
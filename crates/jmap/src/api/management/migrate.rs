@@ -0,0 +1,162 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use common::{auth::AccessToken, MigrationStatus, Server};
+use directory::{backend::internal::manage::ManageDirectory, Permission};
+use hyper::Method;
+use serde_json::json;
+use store::write::now;
+
+use crate::{
+    api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse},
+    email::migrate::{EmailMigrate, MigrationRequest},
+};
+
+use super::decode_path_element;
+use std::future::Future;
+
+pub trait ManageMigration: Sync + Send {
+    fn handle_manage_migration(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl ManageMigration for Server {
+    async fn handle_manage_migration(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        // Validate the access token
+        access_token.assert_has_permission(Permission::AccountMigrate)?;
+
+        match (path.get(1).copied(), req.method()) {
+            (Some(account), &Method::POST) => {
+                let request =
+                    serde_json::from_slice::<MigrationRequest>(body.as_deref().unwrap_or_default())
+                        .map_err(|err| {
+                            trc::EventType::Resource(trc::ResourceEvent::BadParameters)
+                                .from_json_error(err)
+                        })?;
+                let account_id = self.migration_account_id(account).await?;
+
+                // Only one migration may run per account, its status is updated as it progresses
+                {
+                    let mut migrations = self.inner.data.migrations.lock();
+                    if migrations
+                        .get(&account_id)
+                        .is_some_and(|status| status.finished == 0)
+                    {
+                        return Err(trc::ManageEvent::Error
+                            .into_err()
+                            .details("A migration is already running for this account"));
+                    }
+                    migrations.insert(
+                        account_id,
+                        MigrationStatus {
+                            account_id,
+                            host: request.host.clone(),
+                            username: request.username.clone(),
+                            dry_run: request.dry_run,
+                            started: now(),
+                            ..Default::default()
+                        },
+                    );
+                }
+
+                let server = self.clone();
+                tokio::spawn(async move {
+                    if let Err(err) = server.email_migrate(account_id, &request).await {
+                        if let Some(status) =
+                            server.inner.data.migrations.lock().get_mut(&account_id)
+                        {
+                            status.finished = now();
+                            status.error = err.to_string().into();
+                        }
+                        trc::error!(err
+                            .details("Failed to migrate account")
+                            .account_id(account_id));
+                    }
+                });
+
+                Ok(JsonResponse::new(json!({
+                    "data": (),
+                }))
+                .into_http_response())
+            }
+            (Some(account), &Method::GET) => {
+                let account_id = self.migration_account_id(account).await?;
+                let status = self
+                    .inner
+                    .data
+                    .migrations
+                    .lock()
+                    .get(&account_id)
+                    .cloned()
+                    .ok_or_else(|| trc::ResourceEvent::NotFound.into_err())?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": status,
+                }))
+                .into_http_response())
+            }
+            (Some(account), &Method::DELETE) => {
+                // Folders stop after their current message, their progress is kept
+                let account_id = self.migration_account_id(account).await?;
+                self.inner
+                    .data
+                    .migrations
+                    .lock()
+                    .get_mut(&account_id)
+                    .filter(|status| status.finished == 0)
+                    .ok_or_else(|| trc::ResourceEvent::NotFound.into_err())?
+                    .cancelled = true;
+
+                Ok(JsonResponse::new(json!({
+                    "data": (),
+                }))
+                .into_http_response())
+            }
+            (None, &Method::GET) => {
+                let migrations = self
+                    .inner
+                    .data
+                    .migrations
+                    .lock()
+                    .values()
+                    .cloned()
+                    .collect::<Vec<_>>();
+
+                Ok(JsonResponse::new(json!({
+                    "data": migrations,
+                }))
+                .into_http_response())
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+}
+
+trait MigrationAccount: Sync + Send {
+    fn migration_account_id(&self, account: &str) -> impl Future<Output = trc::Result<u32>> + Send;
+}
+
+impl MigrationAccount for Server {
+    async fn migration_account_id(&self, account: &str) -> trc::Result<u32> {
+        self.core
+            .storage
+            .data
+            .get_principal_id(decode_path_element(account).as_ref())
+            .await?
+            .ok_or_else(|| trc::ManageEvent::NotFound.into_err())
+    }
+}
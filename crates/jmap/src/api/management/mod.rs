@@ -4,38 +4,52 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+pub mod audit;
 pub mod dkim;
 pub mod dns;
 #[cfg(feature = "enterprise")]
 pub mod enterprise;
+pub mod import;
 pub mod log;
+pub mod migrate;
 pub mod principal;
+pub mod quarantine;
 pub mod queue;
+pub mod rate_limit;
 pub mod reload;
 pub mod report;
+pub mod security;
 pub mod settings;
 pub mod sieve;
+pub mod spam;
 pub mod stores;
 pub mod troubleshoot;
 
 use std::{borrow::Cow, str::FromStr, sync::Arc};
 
-use common::{auth::AccessToken, Server};
+use audit::{admin_audit_event, AuditManagement};
+use common::{auth::AccessToken, telemetry::audit::AuditRecord, Server};
 use directory::{backend::internal::manage, Permission};
 use dkim::DkimManagement;
 use dns::DnsManagement;
 #[cfg(feature = "enterprise")]
 use enterprise::telemetry::TelemetryApi;
 use hyper::Method;
+use import::ManageImport;
 use log::LogManagement;
 use mail_parser::DateTime;
+use migrate::ManageMigration;
 use principal::PrincipalManager;
+use quarantine::QuarantineManagement;
 use queue::QueueManagement;
+use rate_limit::ManageRateLimit;
 use reload::ManageReload;
 use report::ManageReports;
+use security::ManageSecurity;
 use serde::Serialize;
 use settings::ManageSettings;
 use sieve::SieveHandler;
+use spam::ManageSpamHandler;
 use store::write::now;
 use stores::ManageStore;
 use troubleshoot::TroubleshootApi;
@@ -91,14 +105,29 @@ impl ManagementApi for Server {
     ) -> trc::Result<HttpResponse> {
         let body = fetch_body(req, 1024 * 1024, session.session_id).await;
         let path = req.uri().path().split('/').skip(2).collect::<Vec<_>>();
+        let audit_record = if self.core.audit.enable {
+            admin_audit_event(req.method(), &path, body.as_deref(), &access_token).map(|event| {
+                AuditRecord::new(access_token.name.clone(), event).with_remote_ip(session.remote_ip)
+            })
+        } else {
+            None
+        };
 
-        match path.first().copied().unwrap_or_default() {
+        let result = match path.first().copied().unwrap_or_default() {
             "queue" => self.handle_manage_queue(req, path, &access_token).await,
+            "quarantine" => {
+                self.handle_manage_quarantine(req, path, &access_token)
+                    .await
+            }
             "settings" => {
                 self.handle_manage_settings(req, path, body, &access_token)
                     .await
             }
             "reports" => self.handle_manage_reports(req, path, &access_token).await,
+            "security" => {
+                self.handle_manage_security(req, path, body, &access_token)
+                    .await
+            }
             "principal" => {
                 self.handle_manage_principal(req, path, body, &access_token)
                     .await
@@ -109,15 +138,31 @@ impl ManagementApi for Server {
                     .await
             }
             "reload" => self.handle_manage_reload(req, path, &access_token).await,
+            "rate-limit" => {
+                self.handle_manage_rate_limit(req, path, &access_token)
+                    .await
+            }
             "dkim" => {
                 self.handle_manage_dkim(req, path, body, &access_token)
                     .await
             }
             "update" => self.handle_manage_update(req, path, &access_token).await,
+            "import" => {
+                self.handle_manage_import(req, path, body, &access_token)
+                    .await
+            }
+            "migrate" => {
+                self.handle_manage_migration(req, path, body, &access_token)
+                    .await
+            }
             "logs" if req.method() == Method::GET => {
                 self.handle_view_logs(req, &access_token).await
             }
             "sieve" => self.handle_run_sieve(req, path, body, &access_token).await,
+            "spam-filter" => {
+                self.handle_manage_spam(req, path, body, session.session_id, &access_token)
+                    .await
+            }
             "restart" if req.method() == Method::GET => {
                 // Validate the access token
                 access_token.assert_has_permission(Permission::Restart)?;
@@ -186,8 +231,16 @@ impl ManagementApi for Server {
                 }
             }
             // SPDX-SnippetEnd
+            "audit" => self.handle_manage_audit(req, &access_token).await,
             _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        };
+
+        // Mutating requests are recorded even when they failed
+        if let Some(record) = audit_record {
+            self.audit(record.with_result(&result)).await;
         }
+
+        result
     }
 }
 
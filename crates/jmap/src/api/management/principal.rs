@@ -6,22 +6,35 @@
 
 use std::sync::{atomic::Ordering, Arc};
 
-use common::{auth::AccessToken, Server};
+use common::{auth::AccessToken, config::server::ServerProtocol, Server};
 use directory::{
     backend::internal::{
         lookup::DirectoryStore,
         manage::{self, not_found, ManageDirectory, UpdatePrincipal},
         PrincipalAction, PrincipalField, PrincipalUpdate, PrincipalValue, SpecialSecrets,
     },
+    core::{
+        app_password::{hash_app_password, AppPassword},
+        scram::plaintext_password,
+        totp::{generate_recovery_codes, generate_totp_url, hash_recovery_code, totp_time_step},
+    },
     DirectoryInner, Permission, Principal, QueryBy, Type,
 };
 
 use hyper::{header, Method};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
 use serde_json::json;
+use store::write::now;
 use trc::AddContext;
-use utils::url_params::UrlParams;
+use utils::{
+    config::{ipmask::IpAddrMask, utils::ParseValue},
+    url_params::UrlParams,
+};
 
-use crate::api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse};
+use crate::{
+    api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse},
+    services::state::StateManager,
+};
 
 use super::decode_path_element;
 use std::future::Future;
@@ -30,11 +43,36 @@ use std::future::Future;
 #[serde(tag = "type")]
 #[serde(rename_all = "camelCase")]
 pub enum AccountAuthRequest {
-    SetPassword { password: String },
-    EnableOtpAuth { url: String },
-    DisableOtpAuth { url: Option<String> },
-    AddAppPassword { name: String, password: String },
-    RemoveAppPassword { name: String },
+    SetPassword {
+        password: String,
+    },
+    EnableOtpAuth {
+        url: String,
+    },
+    DisableOtpAuth {
+        url: Option<String>,
+    },
+    GenerateOtpAuth,
+    VerifyOtpAuth {
+        code: String,
+    },
+    GenerateRecoveryCodes,
+    AddAppPassword {
+        name: String,
+        #[serde(default)]
+        password: Option<String>,
+        #[serde(default)]
+        protocols: Vec<String>,
+        #[serde(default)]
+        #[serde(rename = "allowedIps")]
+        allowed_ips: Vec<String>,
+    },
+    RemoveAppPassword {
+        name: String,
+        #[serde(default)]
+        #[serde(rename = "killSessions")]
+        kill_sessions: bool,
+    },
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
@@ -43,6 +81,22 @@ pub struct AccountAuthResponse {
     pub otp_auth: bool,
     #[serde(rename = "appPasswords")]
     pub app_passwords: Vec<String>,
+    #[serde(default)]
+    #[serde(rename = "appPasswordDetails")]
+    pub app_password_details: Vec<AppPasswordDetails>,
+    #[serde(default)]
+    #[serde(rename = "recoveryCodes")]
+    pub recovery_codes: usize,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct AppPasswordDetails {
+    pub name: String,
+    pub protocols: Vec<String>,
+    #[serde(rename = "allowedIps")]
+    pub allowed_ips: Vec<String>,
+    #[serde(rename = "lastUsed")]
+    pub last_used: Option<u64>,
 }
 
 pub trait PrincipalManager: Sync + Send {
@@ -529,7 +583,7 @@ impl PrincipalManager for Server {
                         }
 
                         if expire_token {
-                            self.inner.data.access_tokens.remove(&account_id);
+                            self.invalidate_access_tokens(vec![account_id]);
                         }
 
                         Ok(JsonResponse::new(json!({
@@ -552,6 +606,8 @@ impl PrincipalManager for Server {
         let mut response = AccountAuthResponse {
             otp_auth: false,
             app_passwords: Vec::new(),
+            app_password_details: Vec::new(),
+            recovery_codes: 0,
         };
 
         if access_token.primary_id() != u32::MAX {
@@ -566,12 +622,27 @@ impl PrincipalManager for Server {
             for secret in principal.iter_str(PrincipalField::Secrets) {
                 if secret.is_otp_auth() {
                     response.otp_auth = true;
-                } else if let Some((app_name, _)) =
-                    secret.strip_prefix("$app$").and_then(|s| s.split_once('$'))
-                {
-                    response.app_passwords.push(app_name.to_string());
+                } else if let Some(app_password) = AppPassword::parse(secret) {
+                    response.app_passwords.push(app_password.name.to_string());
+                    response.app_password_details.push(AppPasswordDetails {
+                        name: app_password.name.to_string(),
+                        protocols: app_password
+                            .protocols
+                            .iter()
+                            .map(|p| p.to_string())
+                            .collect(),
+                        allowed_ips: app_password
+                            .allowed_ips
+                            .iter()
+                            .map(|ip| ip.to_string())
+                            .collect(),
+                        last_used: self
+                            .app_password_last_used(access_token.primary_id(), app_password.name)
+                            .await,
+                    });
                 }
             }
+            response.recovery_codes = principal.recovery_codes();
         }
 
         Ok(JsonResponse::new(json!({
@@ -605,6 +676,9 @@ impl PrincipalManager for Server {
                 r,
                 AccountAuthRequest::DisableOtpAuth { .. }
                     | AccountAuthRequest::EnableOtpAuth { .. }
+                    | AccountAuthRequest::GenerateOtpAuth
+                    | AccountAuthRequest::VerifyOtpAuth { .. }
+                    | AccountAuthRequest::GenerateRecoveryCodes
                     | AccountAuthRequest::SetPassword { .. }
             )
         }) && req
@@ -652,8 +726,22 @@ impl PrincipalManager for Server {
         // Make sure the current directory supports updates
         self.assert_supported_directory()?;
 
+        // Fetch the current app passwords
+        let principal = self
+            .core
+            .storage
+            .directory
+            .query(QueryBy::Id(access_token.primary_id()), false)
+            .await?
+            .ok_or_else(|| trc::ManageEvent::NotFound.into_err())?;
+
         // Build actions
         let mut actions = Vec::with_capacity(requests.len());
+        let mut generated = Vec::new();
+        let mut revoked = Vec::new();
+        let mut removed = Vec::new();
+        let mut otp_url = None;
+        let mut recovery_codes = Vec::new();
         for request in requests {
             let (action, secret) = match request {
                 AccountAuthRequest::SetPassword { password } => {
@@ -666,15 +754,133 @@ impl PrincipalManager for Server {
                     (PrincipalAction::AddItem, password)
                 }
                 AccountAuthRequest::EnableOtpAuth { url } => (PrincipalAction::AddItem, url),
+                AccountAuthRequest::GenerateOtpAuth => {
+                    // The secret is only enabled once a valid code is provided
+                    let issuer = self
+                        .core
+                        .storage
+                        .config
+                        .get("lookup.default.hostname")
+                        .await?
+                        .unwrap_or_else(|| "localhost".to_string());
+                    let url = generate_totp_url(&issuer, principal.name())?;
+                    self.begin_totp_enrollment(access_token.primary_id(), &url)
+                        .await?;
+                    otp_url = Some(url);
+                    continue;
+                }
+                AccountAuthRequest::VerifyOtpAuth { code } => {
+                    let url = self
+                        .pending_totp_enrollment(access_token.primary_id())
+                        .await?
+                        .ok_or_else(|| {
+                            manage::error("No pending TOTP enrollment found", None::<u32>)
+                        })?;
+                    let step = totp_time_step(&url, &code, now(), None)?
+                        .ok_or_else(|| manage::error("Invalid TOTP code", None::<u32>))?;
+                    self.clear_totp_enrollment(access_token.primary_id())
+                        .await?;
+                    self.record_totp_step(access_token.primary_id(), step)
+                        .await?;
+                    actions.push(PrincipalUpdate::add_item(
+                        PrincipalField::Secrets,
+                        PrincipalValue::String(url),
+                    ));
+                    recovery_codes = add_recovery_codes(&mut actions)?;
+                    continue;
+                }
+                AccountAuthRequest::GenerateRecoveryCodes => {
+                    if !principal.has_totp() {
+                        return Err(manage::error(
+                            "Recovery codes require TOTP to be enabled",
+                            None::<u32>,
+                        ));
+                    }
+                    recovery_codes = add_recovery_codes(&mut actions)?;
+                    continue;
+                }
                 AccountAuthRequest::DisableOtpAuth { url } => (
                     PrincipalAction::RemoveItem,
                     url.unwrap_or_else(|| "otpauth://".to_string()),
                 ),
-                AccountAuthRequest::AddAppPassword { name, password } => {
-                    (PrincipalAction::AddItem, format!("$app${name}${password}"))
+                AccountAuthRequest::AddAppPassword {
+                    name,
+                    password,
+                    protocols,
+                    allowed_ips,
+                } => {
+                    if !AppPassword::is_valid_name(&name) {
+                        return Err(manage::error("Invalid app password name", Some(name)));
+                    } else if principal.app_passwords().any(|app| app.name == name) {
+                        return Err(manage::error(
+                            "An app password with this name already exists",
+                            Some(name),
+                        ));
+                    }
+                    let protocols = protocols
+                        .iter()
+                        .map(|protocol| {
+                            ServerProtocol::parse_value(protocol)
+                                .map(|protocol| protocol.as_str())
+                                .map_err(|err| manage::error(err, Some(protocol.clone())))
+                        })
+                        .collect::<trc::Result<Vec<&str>>>()?;
+                    if let Some(ip) = allowed_ips
+                        .iter()
+                        .find(|ip| IpAddrMask::parse_value(ip).is_err())
+                    {
+                        return Err(manage::error(
+                            "Invalid IP address or mask",
+                            Some(ip.clone()),
+                        ));
+                    }
+
+                    // Passwords are generated by the server unless provided
+                    let hash = match password {
+                        Some(password) => match plaintext_password(&password) {
+                            Some(password) => hash_app_password(password)?,
+                            None => password,
+                        },
+                        None => {
+                            let password = thread_rng()
+                                .sample_iter(Alphanumeric)
+                                .take(24)
+                                .map(char::from)
+                                .collect::<String>();
+                            let hash = hash_app_password(&password)?;
+                            generated.push((name.clone(), password));
+                            hash
+                        }
+                    };
+
+                    (
+                        PrincipalAction::AddItem,
+                        AppPassword::new(&name, &hash)
+                            .with_protocols(protocols)
+                            .with_allowed_ips(allowed_ips.iter().map(|ip| ip.as_str()))
+                            .to_string(),
+                    )
                 }
-                AccountAuthRequest::RemoveAppPassword { name } => {
-                    (PrincipalAction::RemoveItem, format!("$app${name}"))
+                AccountAuthRequest::RemoveAppPassword {
+                    name,
+                    kill_sessions,
+                } => {
+                    for secret in principal.iter_str(PrincipalField::Secrets) {
+                        if let Some(app_password) =
+                            AppPassword::parse(secret).filter(|app| app.name == name)
+                        {
+                            if kill_sessions {
+                                revoked.push(app_password.to_string());
+                            }
+                            removed.push(app_password.name.to_string());
+                            actions.push(PrincipalUpdate {
+                                action: PrincipalAction::RemoveItem,
+                                field: PrincipalField::Secrets,
+                                value: PrincipalValue::String(secret.to_string()),
+                            });
+                        }
+                    }
+                    continue;
                 }
             };
 
@@ -702,10 +908,44 @@ impl PrincipalManager for Server {
             .http_auth_cache
             .retain(|_, id| id.item != access_token.primary_id());
 
-        Ok(JsonResponse::new(json!({
-            "data": (),
-        }))
-        .into_http_response())
+        // Terminate existing sessions using the revoked app passwords
+        if !revoked.is_empty() {
+            self.revoke_app_passwords(access_token.primary_id(), revoked)
+                .await?;
+        }
+        for name in removed {
+            self.clear_app_password_use(access_token.primary_id(), &name)
+                .await?;
+        }
+
+        // Return the generated secrets, these are not stored in plain text
+        let mut data = serde_json::Map::new();
+        if !generated.is_empty() {
+            data.insert(
+                "appPasswords".to_string(),
+                json!(generated
+                    .into_iter()
+                    .collect::<std::collections::HashMap<_, _>>()),
+            );
+        }
+        if let Some(otp_url) = otp_url {
+            data.insert("otpUrl".to_string(), json!(otp_url));
+        }
+        if !recovery_codes.is_empty() {
+            data.insert("recoveryCodes".to_string(), json!(recovery_codes));
+        }
+
+        if !data.is_empty() {
+            Ok(JsonResponse::new(json!({
+                "data": data,
+            }))
+            .into_http_response())
+        } else {
+            Ok(JsonResponse::new(json!({
+                "data": (),
+            }))
+            .into_http_response())
+        }
     }
 
     fn assert_supported_directory(&self) -> trc::Result<()> {
@@ -730,3 +970,21 @@ impl PrincipalManager for Server {
         )))
     }
 }
+
+// Replaces any existing recovery codes, returning the new codes in plain text
+fn add_recovery_codes(actions: &mut Vec<PrincipalUpdate>) -> trc::Result<Vec<String>> {
+    actions.push(PrincipalUpdate::remove_item(
+        PrincipalField::Secrets,
+        PrincipalValue::String("$recovery$".to_string()),
+    ));
+
+    let recovery_codes = generate_recovery_codes();
+    for code in &recovery_codes {
+        actions.push(PrincipalUpdate::add_item(
+            PrincipalField::Secrets,
+            PrincipalValue::String(hash_recovery_code(code)?),
+        ));
+    }
+
+    Ok(recovery_codes)
+}
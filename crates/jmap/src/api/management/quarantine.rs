@@ -0,0 +1,244 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use common::{auth::AccessToken, Server};
+use directory::{Permission, QueryBy};
+use hyper::Method;
+use mail_parser::{DateTime, MessageParser};
+use serde_json::json;
+use smtp::queue::quarantine::{
+    QuarantineRelease, QuarantineStage, QuarantinedMessage, SmtpQuarantine, GLOBAL_QUARANTINE_ID,
+};
+use trc::AddContext;
+use utils::url_params::UrlParams;
+
+use crate::api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse};
+
+use super::decode_path_element;
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Quarantined {
+    pub id: u64,
+    pub stage: String,
+    pub reason: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<String>,
+    pub return_path: String,
+    pub recipients: Vec<String>,
+    pub size: usize,
+    pub created: String,
+    pub expires: String,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub headers: Vec<(String, String)>,
+}
+
+pub trait QuarantineManagement: Sync + Send {
+    fn handle_manage_quarantine(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl QuarantineManagement for Server {
+    async fn handle_manage_quarantine(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        let params = UrlParams::new(req.uri().query());
+
+        match (
+            path.get(1).copied().map(decode_path_element),
+            path.get(2).copied(),
+            req.method(),
+        ) {
+            (None, None, &Method::GET) => {
+                let account_id =
+                    quarantine_account_id(self, &params, access_token, Permission::QuarantineList)
+                        .await?;
+                let page = params.parse::<usize>("page").unwrap_or_default();
+                let limit = params.parse::<usize>("limit").unwrap_or_default();
+
+                let messages = self.list_quarantined(account_id).await?;
+                let total = messages.len();
+                let offset = page.saturating_sub(1) * limit;
+                let items = messages
+                    .iter()
+                    .skip(offset)
+                    .take(if limit > 0 { limit } else { usize::MAX })
+                    .map(Quarantined::from)
+                    .collect::<Vec<_>>();
+
+                Ok(JsonResponse::new(json!({
+                        "data": {
+                            "items": items,
+                            "total": total,
+                        },
+                }))
+                .into_http_response())
+            }
+            (Some(id), None, &Method::GET) => {
+                let account_id =
+                    quarantine_account_id(self, &params, access_token, Permission::QuarantineGet)
+                        .await?;
+                let message = read_quarantined(self, account_id, id.as_ref()).await?;
+
+                // Include the message headers as a preview
+                let mut result = Quarantined::from(&message);
+                if let Some(raw_message) = self
+                    .blob_store()
+                    .get_blob(message.blob_hash.as_slice(), 0..usize::MAX)
+                    .await
+                    .caused_by(trc::location!())?
+                {
+                    if let Some(parsed) = MessageParser::default().parse(&raw_message) {
+                        result.headers = parsed
+                            .headers()
+                            .iter()
+                            .map(|header| {
+                                (
+                                    header.name().to_string(),
+                                    String::from_utf8_lossy(
+                                        raw_message
+                                            .get(header.offset_start()..header.offset_end())
+                                            .unwrap_or_default(),
+                                    )
+                                    .trim()
+                                    .to_string(),
+                                )
+                            })
+                            .collect();
+                    }
+                }
+
+                Ok(JsonResponse::new(json!({
+                        "data": result,
+                }))
+                .into_http_response())
+            }
+            (Some(id), Some("release"), &Method::POST) => {
+                let account_id = quarantine_account_id(
+                    self,
+                    &params,
+                    access_token,
+                    Permission::QuarantineRelease,
+                )
+                .await?;
+                let message = read_quarantined(self, account_id, id.as_ref()).await?;
+
+                Ok(JsonResponse::new(json!({
+                        "data": match self.release_quarantined(message).await? {
+                            QuarantineRelease::Queued(queue_id) => json!({
+                                "status": "queued",
+                                "queueId": queue_id,
+                            }),
+                            QuarantineRelease::Quarantined => json!({
+                                "status": "quarantined",
+                            }),
+                            QuarantineRelease::Rejected(reason) => json!({
+                                "status": "rejected",
+                                "reason": reason,
+                            }),
+                        },
+                }))
+                .into_http_response())
+            }
+            (Some(id), None, &Method::DELETE) => {
+                let account_id = quarantine_account_id(
+                    self,
+                    &params,
+                    access_token,
+                    Permission::QuarantineDelete,
+                )
+                .await?;
+                let message = read_quarantined(self, account_id, id.as_ref()).await?;
+                self.delete_quarantined(&message).await?;
+
+                Ok(JsonResponse::new(json!({
+                        "data": true,
+                }))
+                .into_http_response())
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+}
+
+// Users can manage their own quarantine, accessing the quarantine of other
+// accounts or the global quarantine requires an administrative permission
+async fn quarantine_account_id(
+    server: &Server,
+    params: &UrlParams<'_>,
+    access_token: &AccessToken,
+    permission: Permission,
+) -> trc::Result<u32> {
+    let account_id = if params.has_key("global") {
+        GLOBAL_QUARANTINE_ID
+    } else if let Some(account) = params.get("account") {
+        // Quarantine areas belong to the accounts recipients resolve to
+        server
+            .core
+            .storage
+            .directory
+            .query(QueryBy::Name(account), false)
+            .await?
+            .ok_or_else(|| trc::ManageEvent::NotFound.into_err())?
+            .id()
+    } else {
+        access_token.primary_id()
+    };
+
+    if account_id != access_token.primary_id() {
+        access_token.assert_has_permission(permission)?;
+    } else if !access_token.has_permission(permission) {
+        access_token.assert_has_permission(Permission::ManageQuarantine)?;
+    }
+
+    Ok(account_id)
+}
+
+async fn read_quarantined(
+    server: &Server,
+    account_id: u32,
+    id: &str,
+) -> trc::Result<QuarantinedMessage> {
+    server
+        .read_quarantined(account_id, id.parse().unwrap_or_default())
+        .await?
+        .ok_or_else(|| trc::ResourceEvent::NotFound.into_err())
+}
+
+impl From<&QuarantinedMessage> for Quarantined {
+    fn from(message: &QuarantinedMessage) -> Self {
+        Quarantined {
+            id: message.id,
+            stage: match message.stage {
+                QuarantineStage::ClamAv => "clamav",
+                QuarantineStage::Attachments => "attachments",
+            }
+            .to_string(),
+            reason: message.reason.clone(),
+            details: message.details.clone(),
+            return_path: message.return_path.clone(),
+            recipients: message
+                .recipients
+                .iter()
+                .map(|rcpt| rcpt.address.clone())
+                .collect(),
+            size: message.size,
+            created: DateTime::from_timestamp(message.created as i64).to_rfc3339(),
+            expires: DateTime::from_timestamp(message.expires as i64).to_rfc3339(),
+            headers: Vec::new(),
+        }
+    }
+}
@@ -7,7 +7,11 @@
 use std::future::Future;
 
 use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
-use common::{auth::AccessToken, ipc::QueueEvent, Server};
+use common::{
+    auth::AccessToken,
+    ipc::{QueueEvent, QueueEventLock},
+    Server,
+};
 use directory::{
     backend::internal::{manage::ManageDirectory, PrincipalField},
     Permission, Type,
@@ -22,11 +26,11 @@ use mail_parser::DateTime;
 use serde::{Deserializer, Serializer};
 use serde_json::json;
 use smtp::{
-    queue::{self, spool::SmtpSpool, ErrorDetails, HostResponse, QueueId, Status},
+    queue::{self, dsn::SendDsn, spool::SmtpSpool, ErrorDetails, HostResponse, QueueId, Status},
     reporting::{dmarc::DmarcReporting, tls::TlsReporting},
 };
 use store::{
-    write::{key::DeserializeBigEndian, now, Bincode, QueueClass, ReportEvent, ValueClass},
+    write::{key::DeserializeBigEndian, now, QueueClass, ReportEvent, ValueClass},
     Deserialize, IterateParams, ValueKey,
 };
 use trc::AddContext;
@@ -50,6 +54,11 @@ pub struct Message {
     pub priority: i16,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub env_id: Option<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(deserialize_with = "deserialize_maybe_datetime")]
+    #[serde(serialize_with = "serialize_maybe_datetime")]
+    pub hold_until: Option<DateTime>,
     pub blob_hash: String,
 }
 
@@ -58,6 +67,9 @@ pub struct Domain {
     pub name: String,
     pub status: Status<String, String>,
     pub recipients: Vec<Recipient>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transport: Option<String>,
 
     pub retry_num: u32,
     #[serde(deserialize_with = "deserialize_maybe_datetime")]
@@ -174,6 +186,7 @@ impl QueueManagement for Server {
                 let text = params.get("text");
                 let from = params.get("from");
                 let to = params.get("to");
+                let domain = params.get("domain").map(|d| d.to_lowercase());
                 let before = params
                     .parse::<FutureTimestamp>("before")
                     .map(|t| t.into_inner());
@@ -195,6 +208,7 @@ impl QueueManagement for Server {
                 let has_filters = text.is_some()
                     || from.is_some()
                     || to.is_some()
+                    || domain.is_some()
                     || before.is_some()
                     || after.is_some();
                 let mut offset = page.saturating_sub(1) * limit;
@@ -206,9 +220,8 @@ impl QueueManagement for Server {
                     .iterate(
                         IterateParams::new(from_key, to_key).ascending(),
                         |key, value| {
-                            let message = Bincode::<queue::Message>::deserialize(value)
-                                .add_context(|ctx| ctx.ctx(trc::Key::Key, key))?
-                                .inner;
+                            let message = queue::Message::deserialize(value)
+                                .add_context(|ctx| ctx.ctx(trc::Key::Key, key))?;
                             let matches = tenant_domains
                                 .as_ref()
                                 .map_or(true, |domains| message.has_domain(domains))
@@ -232,6 +245,9 @@ impl QueueManagement for Server {
                                                     .any(|r| r.address_lcase.contains(to))
                                             })
                                         })
+                                        && domain.as_ref().map_or(true, |domain| {
+                                            message.domains.iter().any(|d| &d.domain == domain)
+                                        })
                                         && before.as_ref().map_or(true, |before| {
                                             message.next_delivery_event() < *before
                                         })
@@ -300,7 +316,10 @@ impl QueueManagement for Server {
                     Err(trc::ResourceEvent::NotFound.into_err())
                 }
             }
-            ("messages", Some(queue_id), &Method::PATCH) => {
+            ("messages", Some(queue_id), &Method::PATCH)
+            | ("messages", Some(queue_id), &Method::POST)
+                if req.method() == Method::PATCH || path.get(3).copied() == Some("retry") =>
+            {
                 // Validate the access token
                 access_token.assert_has_permission(Permission::MessageQueueUpdate)?;
 
@@ -309,137 +328,140 @@ impl QueueManagement for Server {
                     .map(|t| t.into_inner())
                     .unwrap_or_else(now);
                 let item = params.get("filter");
-
-                if let Some(mut message) = self
-                    .read_message(queue_id.parse().unwrap_or_default())
-                    .await
-                    .filter(|message| {
-                        tenant_domains
-                            .as_ref()
-                            .map_or(true, |domains| message.has_domain(domains))
-                    })
-                {
-                    let prev_event = message.next_event().unwrap_or_default();
-                    let mut found = false;
-
-                    for domain in &mut message.domains {
-                        if matches!(
-                            domain.status,
-                            Status::Scheduled | Status::TemporaryFailure(_)
-                        ) && item
-                            .as_ref()
-                            .map_or(true, |item| domain.domain.contains(item))
-                        {
-                            domain.retry.due = time;
-                            if domain.expires > time {
-                                domain.expires = time + 10;
-                            }
-                            found = true;
+                let domains = params.get("domains").map(parse_list);
+                let recipients = params.get("recipients").map(parse_list);
+
+                let (mut message, event) =
+                    lock_message(self, queue_id.as_ref(), &tenant_domains).await?;
+                let mut found = false;
+
+                for (domain_idx, domain) in message.domains.iter_mut().enumerate() {
+                    if matches!(
+                        domain.status,
+                        Status::Scheduled | Status::TemporaryFailure(_)
+                    ) && item
+                        .as_ref()
+                        .map_or(true, |item| domain.domain.contains(item))
+                        && ((domains.is_none() && recipients.is_none())
+                            || domains
+                                .as_ref()
+                                .map_or(false, |domains| domains.contains(&domain.domain))
+                            || recipients.as_ref().map_or(false, |recipients| {
+                                message.recipients.iter().any(|rcpt| {
+                                    rcpt.domain_idx == domain_idx
+                                        && recipients.contains(&rcpt.address_lcase)
+                                })
+                            }))
+                    {
+                        domain.retry.due = time;
+                        if domain.expires > time {
+                            domain.expires = time + 10;
                         }
+                        found = true;
                     }
+                }
 
-                    if found {
-                        let next_event = message.next_event().unwrap_or_default();
-                        message
-                            .save_changes(self, prev_event.into(), next_event.into())
-                            .await;
-                        let _ = self.inner.ipc.queue_tx.send(QueueEvent::Reload).await;
+                if found {
+                    // Rescheduling a held message releases it
+                    if message.hold_until > time {
+                        message.hold_until = 0;
                     }
 
-                    Ok(JsonResponse::new(json!({
-                            "data": found,
-                    }))
-                    .into_http_response())
+                    let next_event = message.next_event().unwrap_or_default();
+                    message
+                        .save_changes(self, event.due.into(), next_event.into())
+                        .await;
+                    let _ = self.inner.ipc.queue_tx.send(QueueEvent::Reload).await;
                 } else {
-                    Err(trc::ResourceEvent::NotFound.into_err())
+                    self.unlock_event(event).await;
                 }
+
+                Ok(JsonResponse::new(json!({
+                        "data": found,
+                }))
+                .into_http_response())
             }
             ("messages", Some(queue_id), &Method::DELETE) => {
                 // Validate the access token
                 access_token.assert_has_permission(Permission::MessageQueueDelete)?;
 
-                if let Some(mut message) = self
-                    .read_message(queue_id.parse().unwrap_or_default())
-                    .await
-                    .filter(|message| {
-                        tenant_domains
-                            .as_ref()
-                            .map_or(true, |domains| message.has_domain(domains))
-                    })
-                {
-                    let mut found = false;
-                    let prev_event = message.next_event().unwrap_or_default();
-
-                    if let Some(item) = params.get("filter") {
-                        // Cancel delivery for all recipients that match
-                        for rcpt in &mut message.recipients {
-                            if rcpt.address_lcase.contains(item) {
-                                rcpt.status = Status::PermanentFailure(HostResponse {
-                                    hostname: ErrorDetails::default(),
-                                    response: smtp_proto::Response {
-                                        code: 0,
-                                        esc: [0, 0, 0],
-                                        message: "Delivery canceled.".to_string(),
-                                    },
-                                });
-                                found = true;
-                            }
-                        }
-                        if found {
-                            // Mark as completed domains without any pending deliveries
-                            for (domain_idx, domain) in message.domains.iter_mut().enumerate() {
-                                if matches!(
-                                    domain.status,
-                                    Status::TemporaryFailure(_) | Status::Scheduled
-                                ) {
-                                    let mut total_rcpt = 0;
-                                    let mut total_completed = 0;
-
-                                    for rcpt in &message.recipients {
-                                        if rcpt.domain_idx == domain_idx {
-                                            total_rcpt += 1;
-                                            if matches!(
-                                                rcpt.status,
-                                                Status::PermanentFailure(_) | Status::Completed(_)
-                                            ) {
-                                                total_completed += 1;
-                                            }
-                                        }
-                                    }
-
-                                    if total_rcpt == total_completed {
-                                        domain.status = Status::Completed(());
-                                    }
-                                }
-                            }
+                let item = params.get("filter");
+                let bounce = params.parse::<bool>("bounce").unwrap_or_default();
+
+                let (mut message, event) =
+                    lock_message(self, queue_id.as_ref(), &tenant_domains).await?;
+                let mut found = false;
+
+                // Cancel delivery for all pending recipients that match
+                for rcpt in &mut message.recipients {
+                    if matches!(
+                        message.domains[rcpt.domain_idx].status,
+                        Status::Scheduled | Status::TemporaryFailure(_)
+                    ) && matches!(rcpt.status, Status::Scheduled | Status::TemporaryFailure(_))
+                        && item.map_or(true, |item| rcpt.address_lcase.contains(item))
+                    {
+                        rcpt.status = Status::PermanentFailure(HostResponse {
+                            hostname: ErrorDetails::default(),
+                            response: smtp_proto::Response {
+                                code: 550,
+                                esc: [5, 0, 0],
+                                message: "Delivery canceled.".to_string(),
+                            },
+                        });
+                        found = true;
+                    }
+                }
 
-                            // Delete message if there are no pending deliveries
-                            if message.domains.iter().any(|domain| {
-                                matches!(
-                                    domain.status,
-                                    Status::TemporaryFailure(_) | Status::Scheduled
+                if found {
+                    // Mark as completed domains without any pending deliveries
+                    for (domain_idx, domain) in message.domains.iter_mut().enumerate() {
+                        if matches!(
+                            domain.status,
+                            Status::TemporaryFailure(_) | Status::Scheduled
+                        ) && message.recipients.iter().all(|rcpt| {
+                            rcpt.domain_idx != domain_idx
+                                || matches!(
+                                    rcpt.status,
+                                    Status::PermanentFailure(_) | Status::Completed(_)
                                 )
-                            }) {
-                                let next_event = message.next_event().unwrap_or_default();
-                                message
-                                    .save_changes(self, next_event.into(), prev_event.into())
-                                    .await;
-                            } else {
-                                message.remove(self, prev_event).await;
-                            }
+                        }) {
+                            domain.status = Status::Completed(());
                         }
-                    } else {
-                        message.remove(self, prev_event).await;
-                        found = true;
                     }
 
-                    Ok(JsonResponse::new(json!({
-                            "data": found,
-                    }))
-                    .into_http_response())
+                    // Notify the sender about the canceled recipients
+                    if bounce {
+                        message.span_id =
+                            self.inner.data.span_id_gen.generate().unwrap_or_else(now);
+                        self.send_dsn(&mut message).await;
+                    }
+                }
+
+                if item.is_none()
+                    || (found
+                        && !message.domains.iter().any(|domain| {
+                            matches!(
+                                domain.status,
+                                Status::TemporaryFailure(_) | Status::Scheduled
+                            )
+                        }))
+                {
+                    // Delete message if there are no pending deliveries
+                    message.remove(self, event.due).await;
+                    found = true;
+                } else if found {
+                    let next_event = message.next_event().unwrap_or_default();
+                    message
+                        .save_changes(self, event.due.into(), next_event.into())
+                        .await;
                 } else {
-                    Err(trc::ResourceEvent::NotFound.into_err())
+                    self.unlock_event(event).await;
                 }
+
+                Ok(JsonResponse::new(json!({
+                        "data": found,
+                }))
+                .into_http_response())
             }
             ("reports", None, &Method::GET) => {
                 // Validate the access token
@@ -621,12 +643,22 @@ impl From<&queue::Message> for Message {
             size: message.size,
             priority: message.priority,
             env_id: message.env_id.clone(),
+            hold_until: if message.hold_until > now {
+                DateTime::from_timestamp(message.hold_until as i64).into()
+            } else {
+                None
+            },
             domains: message
                 .domains
                 .iter()
                 .enumerate()
                 .map(|(idx, domain)| Domain {
                     name: domain.domain.clone(),
+                    transport: message
+                        .transports
+                        .get(idx)
+                        .filter(|transport| !transport.is_empty())
+                        .cloned(),
                     status: match &domain.status {
                         Status::Scheduled => Status::Scheduled,
                         Status::Completed(_) => Status::Completed(String::new()),
@@ -715,6 +747,48 @@ impl GenerateQueueId for QueueClass {
     }
 }
 
+async fn lock_message(
+    server: &Server,
+    queue_id: &str,
+    tenant_domains: &Option<Vec<String>>,
+) -> trc::Result<(queue::Message, QueueEventLock)> {
+    let queue_id = queue_id.parse().unwrap_or_default();
+    if !server
+        .read_message(queue_id)
+        .await
+        .map_or(false, |message| {
+            tenant_domains
+                .as_ref()
+                .map_or(true, |domains| message.has_domain(domains))
+        })
+    {
+        return Err(trc::ResourceEvent::NotFound.into_err());
+    }
+
+    // Lock the queue event to prevent the queue manager from
+    // attempting delivery while the message is being modified
+    let event = server.try_lock_message(queue_id).await.ok_or_else(|| {
+        trc::ManageEvent::Error
+            .into_err()
+            .details("Message is being processed by the queue manager, try again later.")
+    })?;
+
+    // Read the message again, as it might have changed before the lock was acquired
+    if let Some(message) = server.read_message(queue_id).await {
+        Ok((message, event))
+    } else {
+        server.unlock_event(event).await;
+        Err(trc::ResourceEvent::NotFound.into_err())
+    }
+}
+
+fn parse_list(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(|item| item.trim().to_lowercase())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
 fn parse_queued_report_id(id: &str) -> Option<QueueClass> {
     let mut parts = id.split('!');
     let type_ = parts.next()?;
@@ -0,0 +1,109 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use common::{auth::AccessToken, Server};
+use directory::Permission;
+use hyper::Method;
+use serde::Serialize;
+use serde_json::json;
+use utils::config::{Rate, RateWindow};
+
+use crate::api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse};
+
+use super::decode_path_element;
+use std::future::Future;
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct RateLimitUsage {
+    pub requests: u64,
+    pub limit: u64,
+    pub period: u64,
+    pub window: &'static str,
+}
+
+pub trait ManageRateLimit: Sync + Send {
+    fn handle_manage_rate_limit(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl ManageRateLimit for Server {
+    async fn handle_manage_rate_limit(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        match (path.get(1), req.method()) {
+            (Some(id), &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::RateLimitGet)?;
+
+                let account_id = self
+                    .core
+                    .storage
+                    .data
+                    .get_principal_id(decode_path_element(id).as_ref())
+                    .await?
+                    .ok_or_else(|| trc::ManageEvent::NotFound.into_err())?;
+
+                let jmap = rate_limit_usage(
+                    self,
+                    format!("j:{account_id}").as_bytes(),
+                    self.core.jmap.rate_authenticated.as_ref(),
+                    self.core.jmap.rate_window,
+                )
+                .await?;
+                let imap = rate_limit_usage(
+                    self,
+                    format!("ireq:{account_id}").as_bytes(),
+                    self.core.imap.rate_requests.as_ref(),
+                    self.core.imap.rate_window,
+                )
+                .await?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": {
+                        "jmap": jmap,
+                        "imap": imap,
+                    },
+                }))
+                .into_http_response())
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+}
+
+async fn rate_limit_usage(
+    server: &Server,
+    key: &[u8],
+    rate: Option<&Rate>,
+    window: RateWindow,
+) -> trc::Result<Option<RateLimitUsage>> {
+    if let Some(rate) = rate {
+        Ok(Some(RateLimitUsage {
+            requests: server
+                .core
+                .storage
+                .lookup
+                .rate_usage(key, rate, window)
+                .await?,
+            limit: rate.requests,
+            period: rate.period.as_secs(),
+            window: match window {
+                RateWindow::Fixed => "fixed",
+                RateWindow::Sliding => "sliding",
+            },
+        }))
+    } else {
+        Ok(None)
+    }
+}
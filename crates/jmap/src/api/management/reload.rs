@@ -4,7 +4,7 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use common::{auth::AccessToken, ipc::HousekeeperEvent, Server};
+use common::{auth::AccessToken, ipc::HousekeeperEvent, manager::reload::ReloadResult, Server};
 use directory::Permission;
 use hyper::Method;
 use serde_json::json;
@@ -24,6 +24,11 @@ pub trait ManageReload: Sync + Send {
         access_token: &AccessToken,
     ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
 
+    fn reload_config(
+        &self,
+        dry_run: bool,
+    ) -> impl Future<Output = trc::Result<ReloadResult>> + Send;
+
     fn handle_manage_update(
         &self,
         req: &HttpRequest,
@@ -70,41 +75,21 @@ impl ManageReload for Server {
                 }))
                 .into_http_response())
             }
-            (_, &Method::GET) => {
-                let result = self.reload().await?;
-                if !UrlParams::new(req.uri().query()).has_key("dry-run") {
-                    if let Some(core) = result.new_core {
-                        // Update core
-                        self.inner.shared_core.store(core.into());
-
-                        // Increment version counter
-                        self.increment_config_version();
-                    }
-
-                    if let Some(tracers) = result.tracers {
-                        // Update tracers
-                        #[cfg(feature = "enterprise")]
-                        tracers.update(self.inner.shared_core.load().is_enterprise_edition());
-                        #[cfg(not(feature = "enterprise"))]
-                        tracers.update(false);
-                    }
-
-                    // Reload settings
-                    self.inner
-                        .ipc
-                        .housekeeper_tx
-                        .send(HousekeeperEvent::ReloadSettings)
-                        .await
-                        .map_err(|err| {
-                            trc::EventType::Server(trc::ServerEvent::ThreadError)
-                                .reason(err)
-                                .details("Failed to send settings reload event to housekeeper")
-                                .caused_by(trc::location!())
-                        })?;
+            (None, &Method::POST) | (_, &Method::GET) => {
+                let result = self
+                    .reload_config(UrlParams::new(req.uri().query()).has_key("dry-run"))
+                    .await?;
+                let mut response = serde_json::to_value(&result.config).unwrap_or_default();
+                if let Some(response) = response.as_object_mut() {
+                    response.insert("changed".into(), result.changes.changed.into());
+                    response.insert(
+                        "restart_required".into(),
+                        result.changes.restart_required.into(),
+                    );
                 }
 
                 Ok(JsonResponse::new(json!({
-                    "data": result.config,
+                    "data": response,
                 }))
                 .into_http_response())
             }
@@ -112,6 +97,56 @@ impl ManageReload for Server {
         }
     }
 
+    async fn reload_config(&self, dry_run: bool) -> trc::Result<ReloadResult> {
+        // Pick up changes to the local configuration file
+        self.core.storage.config.reload_local().await?;
+
+        let mut result = self.reload().await?;
+        if !dry_run {
+            if let Some(core) = result.new_core.take() {
+                // Update core
+                self.inner.shared_core.store(core.into());
+
+                // Increment version counter
+                self.increment_config_version();
+
+                trc::event!(
+                    Config(trc::ConfigEvent::Reloaded),
+                    Total = result.changes.changed.len(),
+                    Details = result
+                        .changes
+                        .restart_required
+                        .iter()
+                        .map(|key| trc::Value::from(key.clone()))
+                        .collect::<Vec<_>>(),
+                );
+            }
+
+            if let Some(tracers) = result.tracers.take() {
+                // Update tracers
+                #[cfg(feature = "enterprise")]
+                tracers.update(self.inner.shared_core.load().is_enterprise_edition());
+                #[cfg(not(feature = "enterprise"))]
+                tracers.update(false);
+            }
+
+            // Reload settings
+            self.inner
+                .ipc
+                .housekeeper_tx
+                .send(HousekeeperEvent::ReloadSettings)
+                .await
+                .map_err(|err| {
+                    trc::EventType::Server(trc::ServerEvent::ThreadError)
+                        .reason(err)
+                        .details("Failed to send settings reload event to housekeeper")
+                        .caused_by(trc::location!())
+                })?;
+        }
+
+        Ok(result)
+    }
+
     async fn handle_manage_update(
         &self,
         req: &HttpRequest,
@@ -4,7 +4,7 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::future::Future;
+use std::{collections::BTreeMap, future::Future};
 
 use common::{auth::AccessToken, Server};
 use directory::{
@@ -14,16 +14,17 @@ use directory::{
 use hyper::Method;
 use mail_auth::report::{
     tlsrpt::{FailureDetails, Policy, TlsReport},
-    Feedback,
+    DmarcResult, Feedback,
 };
 use serde_json::json;
 use smtp::reporting::analysis::IncomingReport;
 use store::{
-    write::{key::DeserializeBigEndian, BatchBuilder, Bincode, ReportClass, ValueClass},
+    ahash::{AHashMap, AHashSet},
+    write::{key::DeserializeBigEndian, now, BatchBuilder, Bincode, ReportClass, ValueClass},
     Deserialize, IterateParams, ValueKey, U64_LEN,
 };
 use trc::AddContext;
-use utils::url_params::UrlParams;
+use utils::{snowflake::SnowflakeIdGenerator, url_params::UrlParams};
 
 use crate::api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse};
 
@@ -100,53 +101,33 @@ impl ManageReports for Server {
                 let filter = params.get("text");
                 let page: usize = params.parse::<usize>("page").unwrap_or_default();
                 let limit: usize = params.parse::<usize>("limit").unwrap_or_default();
+                let domain = params.get("domain").map(|d| d.to_lowercase());
+                let range_from = params.parse::<u64>("from").unwrap_or_default();
+                let range_to = params.parse::<u64>("to").unwrap_or(u64::MAX);
+                let cursor = params.get("cursor").and_then(|cursor| {
+                    let mut parts = cursor.split('_');
+                    Some((
+                        parts.next()?.parse::<u64>().ok()?,
+                        parts.next()?.parse::<u64>().ok()?,
+                    ))
+                });
 
                 let range_start = params.parse::<u64>("range-start").unwrap_or_default();
                 let range_end = params.parse::<u64>("range-end").unwrap_or(u64::MAX);
                 let max_total = params.parse::<usize>("max-total").unwrap_or_default();
 
-                let (from_key, to_key, typ) = match class {
-                    "dmarc" => (
-                        ValueKey::from(ValueClass::Report(ReportClass::Dmarc {
-                            id: range_start,
-                            expires: 0,
-                        })),
-                        ValueKey::from(ValueClass::Report(ReportClass::Dmarc {
-                            id: range_end,
-                            expires: u64::MAX,
-                        })),
-                        ReportType::Dmarc,
-                    ),
-                    "tls" => (
-                        ValueKey::from(ValueClass::Report(ReportClass::Tls {
-                            id: range_start,
-                            expires: 0,
-                        })),
-                        ValueKey::from(ValueClass::Report(ReportClass::Tls {
-                            id: range_end,
-                            expires: u64::MAX,
-                        })),
-                        ReportType::Tls,
-                    ),
-                    "arf" => (
-                        ValueKey::from(ValueClass::Report(ReportClass::Arf {
-                            id: range_start,
-                            expires: 0,
-                        })),
-                        ValueKey::from(ValueClass::Report(ReportClass::Arf {
-                            id: range_end,
-                            expires: u64::MAX,
-                        })),
-                        ReportType::Arf,
-                    ),
-                    _ => unreachable!(),
-                };
+                let typ = ReportType::from(class);
+                let from_key = ValueKey::from(ValueClass::Report(typ.class(range_start, 0)));
+                let to_key = ValueKey::from(ValueClass::Report(match cursor {
+                    Some((id, expires)) => typ.class(id, expires),
+                    None => typ.class(range_end, u64::MAX),
+                }));
 
                 let mut results = Vec::new();
                 let mut offset = page.saturating_sub(1) * limit;
                 let mut total = 0;
-                let mut last_id = 0;
-                let has_filters = filter.is_some() || tenant_domains.is_some();
+                let mut last_id = cursor.map(|(id, _)| id).unwrap_or_default();
+                let has_filters = filter.is_some() || domain.is_some() || tenant_domains.is_some();
                 self.core
                     .storage
                     .data
@@ -162,6 +143,12 @@ impl ManageReports for Server {
                             }
                             last_id = id;
 
+                            // Filter by the time the report was received
+                            let received = SnowflakeIdGenerator::to_timestamp(id);
+                            if received < range_from || received > range_to {
+                                return Ok(true);
+                            }
+
                             // TODO: Support filtering chunked records (over 10MB) on FDB
                             let matches = if has_filters {
                                 match typ {
@@ -175,6 +162,9 @@ impl ManageReports for Server {
                                         .inner;
 
                                         filter.map_or(true, |f| report.contains(f))
+                                            && domain.as_ref().map_or(true, |d| {
+                                                report.report.has_report_domain(d)
+                                            })
                                             && tenant_domains
                                                 .as_ref()
                                                 .map_or(true, |domains| report.has_domain(domains))
@@ -188,6 +178,9 @@ impl ManageReports for Server {
                                             .inner;
 
                                         filter.map_or(true, |f| report.contains(f))
+                                            && domain.as_ref().map_or(true, |d| {
+                                                report.report.has_report_domain(d)
+                                            })
                                             && tenant_domains
                                                 .as_ref()
                                                 .map_or(true, |domains| report.has_domain(domains))
@@ -199,6 +192,9 @@ impl ManageReports for Server {
                                                 .inner;
 
                                         filter.map_or(true, |f| report.contains(f))
+                                            && domain.as_ref().map_or(true, |d| {
+                                                report.report.has_report_domain(d)
+                                            })
                                             && tenant_domains
                                                 .as_ref()
                                                 .map_or(true, |domains| report.has_domain(domains))
@@ -230,10 +226,157 @@ impl ManageReports for Server {
                     .await
                     .caused_by(trc::location!())?;
 
+                // Return a cursor to the next page when there are more results
+                let cursor = if limit > 0
+                    && results.len() == limit
+                    && total > page.saturating_sub(1) * limit + limit
+                {
+                    results.last().cloned()
+                } else {
+                    None
+                };
+
                 Ok(JsonResponse::new(json!({
                         "data": {
                             "items": results,
                             "total": total,
+                            "cursor": cursor,
+                        },
+                }))
+                .into_http_response())
+            }
+            ("summary", None, &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::IncomingReportList)?;
+
+                let params = UrlParams::new(req.uri().query());
+                let days = params.parse::<u64>("days").unwrap_or(30);
+                let domain = params.get("domain").map(|d| d.to_lowercase());
+                let filter = ReportFilter {
+                    domain: domain.as_deref(),
+                    from: now().saturating_sub(days * 86400),
+                    to: u64::MAX,
+                    tenant_domains: tenant_domains.as_deref(),
+                };
+
+                let mut summary: BTreeMap<String, DomainSummary> = BTreeMap::new();
+                for typ in [ReportType::Dmarc, ReportType::Tls, ReportType::Arf] {
+                    let cb = |entries: Vec<ReportEntry>| {
+                        let mut seen = AHashSet::new();
+                        for entry in entries {
+                            let stats = summary
+                                .entry(entry.domain.clone())
+                                .or_default()
+                                .stats_mut(&typ);
+                            if seen.insert(entry.domain.clone()) {
+                                stats.reports += 1;
+                            }
+                            stats.add(&entry);
+                        }
+                    };
+
+                    match typ {
+                        ReportType::Dmarc => {
+                            scan_incoming_reports::<mail_auth::report::Report>(
+                                self, &typ, &filter, cb,
+                            )
+                            .await?
+                        }
+                        ReportType::Tls => {
+                            scan_incoming_reports::<TlsReport>(self, &typ, &filter, cb).await?
+                        }
+                        ReportType::Arf => {
+                            scan_incoming_reports::<Feedback>(self, &typ, &filter, cb).await?
+                        }
+                    }
+                }
+
+                let total = summary.len();
+                let items = summary
+                    .into_iter()
+                    .map(|(domain, summary)| {
+                        json!({
+                            "domain": domain,
+                            "dmarc": summary.dmarc,
+                            "tls": summary.tls,
+                            "arf": summary.arf,
+                        })
+                    })
+                    .collect::<Vec<_>>();
+
+                Ok(JsonResponse::new(json!({
+                        "data": {
+                            "items": items,
+                            "total": total,
+                        },
+                }))
+                .into_http_response())
+            }
+            (class @ ("dmarc" | "tls" | "arf"), Some(sub), &Method::GET) if sub == "sources" => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::IncomingReportList)?;
+
+                let params = UrlParams::new(req.uri().query());
+                let page: usize = params.parse::<usize>("page").unwrap_or_default();
+                let limit: usize = params.parse::<usize>("limit").unwrap_or_default();
+                let domain = params.get("domain").map(|d| d.to_lowercase());
+                let filter = ReportFilter {
+                    domain: domain.as_deref(),
+                    from: params.parse::<u64>("from").unwrap_or_default(),
+                    to: params.parse::<u64>("to").unwrap_or(u64::MAX),
+                    tenant_domains: tenant_domains.as_deref(),
+                };
+
+                let mut sources: AHashMap<String, ReportStats> = AHashMap::new();
+                let cb = |entries: Vec<ReportEntry>| {
+                    let mut seen = AHashSet::new();
+                    for entry in entries {
+                        let stats = sources.entry(entry.source.clone()).or_default();
+                        if seen.insert(entry.source.clone()) {
+                            stats.reports += 1;
+                        }
+                        stats.add(&entry);
+                    }
+                };
+                let typ = ReportType::from(class);
+                match typ {
+                    ReportType::Dmarc => {
+                        scan_incoming_reports::<mail_auth::report::Report>(self, &typ, &filter, cb)
+                            .await?
+                    }
+                    ReportType::Tls => {
+                        scan_incoming_reports::<TlsReport>(self, &typ, &filter, cb).await?
+                    }
+                    ReportType::Arf => {
+                        scan_incoming_reports::<Feedback>(self, &typ, &filter, cb).await?
+                    }
+                }
+
+                // Sort by volume
+                let mut sources = sources.into_iter().collect::<Vec<_>>();
+                sources.sort_unstable_by(|(a_source, a), (b_source, b)| {
+                    b.total.cmp(&a.total).then_with(|| a_source.cmp(b_source))
+                });
+                let total = sources.len();
+                let items = sources
+                    .into_iter()
+                    .skip(page.saturating_sub(1) * limit)
+                    .take(if limit > 0 { limit } else { total })
+                    .map(|(source, stats)| {
+                        json!({
+                            "source": source,
+                            "reports": stats.reports,
+                            "total": stats.total,
+                            "pass": stats.pass,
+                            "fail": stats.fail,
+                        })
+                    })
+                    .collect::<Vec<_>>();
+
+                Ok(JsonResponse::new(json!({
+                        "data": {
+                            "items": items,
+                            "total": total,
                         },
                 }))
                 .into_http_response())
@@ -394,6 +537,191 @@ impl From<&str> for ReportType {
     }
 }
 
+impl ReportType {
+    fn class(&self, id: u64, expires: u64) -> ReportClass {
+        match self {
+            ReportType::Dmarc => ReportClass::Dmarc { id, expires },
+            ReportType::Tls => ReportClass::Tls { id, expires },
+            ReportType::Arf => ReportClass::Arf { id, expires },
+        }
+    }
+}
+
+struct ReportFilter<'x> {
+    domain: Option<&'x str>,
+    from: u64,
+    to: u64,
+    tenant_domains: Option<&'x [String]>,
+}
+
+// A single (domain, source) data point extracted from a report
+struct ReportEntry {
+    domain: String,
+    source: String,
+    total: u64,
+    pass: u64,
+}
+
+#[derive(Debug, Default, serde::Serialize)]
+struct ReportStats {
+    reports: u64,
+    total: u64,
+    pass: u64,
+    fail: u64,
+}
+
+#[derive(Debug, Default)]
+struct DomainSummary {
+    dmarc: ReportStats,
+    tls: ReportStats,
+    arf: ReportStats,
+}
+
+impl ReportStats {
+    fn add(&mut self, entry: &ReportEntry) {
+        self.total += entry.total;
+        self.pass += entry.pass;
+        self.fail += entry.total.saturating_sub(entry.pass);
+    }
+}
+
+impl DomainSummary {
+    fn stats_mut(&mut self, typ: &ReportType) -> &mut ReportStats {
+        match typ {
+            ReportType::Dmarc => &mut self.dmarc,
+            ReportType::Tls => &mut self.tls,
+            ReportType::Arf => &mut self.arf,
+        }
+    }
+}
+
+// Scans the unexpired reports of a type, passing the entries matching the filter to the callback
+async fn scan_incoming_reports<T>(
+    server: &Server,
+    typ: &ReportType,
+    filter: &ReportFilter<'_>,
+    mut cb: impl FnMut(Vec<ReportEntry>) + Sync + Send,
+) -> trc::Result<()>
+where
+    T: Summarize + serde::Serialize + serde::de::DeserializeOwned + Sync + Send,
+{
+    let mut last_id = 0;
+    server
+        .core
+        .storage
+        .data
+        .iterate(
+            IterateParams::new(
+                ValueKey::from(ValueClass::Report(typ.class(0, now()))),
+                ValueKey::from(ValueClass::Report(typ.class(u64::MAX, u64::MAX))),
+            ),
+            |key, value| {
+                // Skip chunked records
+                let id = key.deserialize_be_u64(U64_LEN + 1)?;
+                if id == last_id {
+                    return Ok(true);
+                }
+                last_id = id;
+
+                let received = SnowflakeIdGenerator::to_timestamp(id);
+                if received < filter.from || received > filter.to {
+                    return Ok(true);
+                }
+
+                let report = Bincode::<IncomingReport<T>>::deserialize(value)
+                    .caused_by(trc::location!())?
+                    .inner;
+                if filter
+                    .tenant_domains
+                    .map_or(true, |domains| report.has_domain(domains))
+                {
+                    let mut entries = report.report.entries();
+                    if let Some(domain) = filter.domain {
+                        entries.retain(|entry| entry.domain == domain);
+                    }
+                    if !entries.is_empty() {
+                        cb(entries);
+                    }
+                }
+
+                Ok(true)
+            },
+        )
+        .await
+        .caused_by(trc::location!())
+}
+
+trait Summarize {
+    fn entries(&self) -> Vec<ReportEntry>;
+
+    fn has_report_domain(&self, domain: &str) -> bool {
+        self.entries().iter().any(|entry| entry.domain == domain)
+    }
+}
+
+impl Summarize for mail_auth::report::Report {
+    fn entries(&self) -> Vec<ReportEntry> {
+        let domain = self.domain().to_lowercase();
+        self.records()
+            .iter()
+            .map(|record| {
+                let total = record.count() as u64;
+                ReportEntry {
+                    domain: domain.clone(),
+                    source: record
+                        .source_ip()
+                        .map_or_else(|| "unknown".to_string(), |ip| ip.to_string()),
+                    total,
+                    pass: if record.dmarc_dkim_result() == DmarcResult::Pass
+                        || record.dmarc_spf_result() == DmarcResult::Pass
+                    {
+                        total
+                    } else {
+                        0
+                    },
+                }
+            })
+            .collect()
+    }
+}
+
+// TLS reports do not include the address of successful sessions,
+// so their sources are the policy domains.
+impl Summarize for TlsReport {
+    fn entries(&self) -> Vec<ReportEntry> {
+        self.policies
+            .iter()
+            .map(|policy| {
+                let domain = policy.policy.policy_domain.to_lowercase();
+                ReportEntry {
+                    source: domain.clone(),
+                    domain,
+                    total: policy.summary.total_success as u64
+                        + policy.summary.total_failure as u64,
+                    pass: policy.summary.total_success as u64,
+                }
+            })
+            .collect()
+    }
+}
+
+impl Summarize for Feedback<'_> {
+    fn entries(&self) -> Vec<ReportEntry> {
+        let source = self
+            .source_ip()
+            .map_or_else(|| "unknown".to_string(), |ip| ip.to_string());
+        self.reported_domain()
+            .iter()
+            .map(|domain| ReportEntry {
+                domain: domain.to_lowercase(),
+                source: source.clone(),
+                total: std::cmp::max(self.incidents(), 1) as u64,
+                pass: 0,
+            })
+            .collect()
+    }
+}
+
 trait Contains {
     fn contains(&self, text: &str) -> bool;
 }
@@ -0,0 +1,204 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{future::Future, net::IpAddr};
+
+use common::{
+    auth::AccessToken,
+    listener::blocked::{ALLOWED_IP_KEY, ALLOWED_IP_PREFIX},
+    Server,
+};
+use directory::Permission;
+use hyper::Method;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use store::write::now;
+use utils::config::{ipmask::IpAddrOrMask, utils::ParseValue, ConfigKey};
+
+use crate::api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse};
+
+use super::{decode_path_element, reload::ManageReload};
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockedIp {
+    pub ip: IpAddr,
+    pub expires: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockIpRequest {
+    pub ip: IpAddr,
+    // Ban duration in seconds, permanent when missing
+    pub duration: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AllowIpRequest {
+    pub network: String,
+}
+
+pub trait ManageSecurity: Sync + Send {
+    fn handle_manage_security(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl ManageSecurity for Server {
+    async fn handle_manage_security(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        match (path.get(1).copied(), path.get(2).copied(), req.method()) {
+            (Some("blocked"), None, &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::SettingsList)?;
+
+                let mut items = self
+                    .blocked_ips()
+                    .into_iter()
+                    .map(|(ip, expires)| BlockedIp { ip, expires })
+                    .collect::<Vec<_>>();
+                items.sort_unstable_by_key(|item| item.ip);
+
+                Ok(JsonResponse::new(json!({
+                    "data": {
+                        "total": items.len(),
+                        "items": items,
+                    },
+                }))
+                .into_http_response())
+            }
+            (Some("blocked"), None, &Method::POST) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::SettingsUpdate)?;
+
+                let request =
+                    serde_json::from_slice::<BlockIpRequest>(body.as_deref().unwrap_or_default())
+                        .map_err(|err| {
+                        trc::EventType::Resource(trc::ResourceEvent::BadParameters)
+                            .from_json_error(err)
+                    })?;
+                self.block_ip(
+                    request.ip,
+                    request.duration.map(|duration| now() + duration),
+                )
+                .await?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": (),
+                }))
+                .into_http_response())
+            }
+            (Some("blocked"), Some(ip), &Method::DELETE) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::SettingsDelete)?;
+
+                let ip = decode_path_element(ip).parse::<IpAddr>().map_err(|_| {
+                    trc::EventType::Resource(trc::ResourceEvent::BadParameters)
+                        .into_err()
+                        .details("Invalid IP address")
+                })?;
+                if !self.unblock_ip(ip).await? {
+                    return Err(trc::ResourceEvent::NotFound.into_err());
+                }
+
+                Ok(JsonResponse::new(json!({
+                    "data": (),
+                }))
+                .into_http_response())
+            }
+            (Some("allowed"), None, &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::SettingsList)?;
+
+                let items = self
+                    .core
+                    .storage
+                    .config
+                    .list(ALLOWED_IP_PREFIX, true)
+                    .await?
+                    .into_keys()
+                    .collect::<Vec<_>>();
+
+                Ok(JsonResponse::new(json!({
+                    "data": {
+                        "total": items.len(),
+                        "items": items,
+                    },
+                }))
+                .into_http_response())
+            }
+            (Some("allowed"), None, &Method::POST) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::SettingsUpdate)?;
+
+                let request =
+                    serde_json::from_slice::<AllowIpRequest>(body.as_deref().unwrap_or_default())
+                        .map_err(|err| {
+                        trc::EventType::Resource(trc::ResourceEvent::BadParameters)
+                            .from_json_error(err)
+                    })?;
+                let network = parse_network(&request.network)?;
+                self.core
+                    .storage
+                    .config
+                    .set([ConfigKey {
+                        key: format!("{ALLOWED_IP_KEY}.{network}"),
+                        value: String::new(),
+                    }])
+                    .await?;
+
+                // Allowed networks are part of the core configuration
+                self.reload_config(false).await?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": (),
+                }))
+                .into_http_response())
+            }
+            (Some("allowed"), Some(network), &Method::DELETE) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::SettingsDelete)?;
+
+                let network = decode_path_element(network);
+                let network = parse_network(&network)?;
+                self.core
+                    .storage
+                    .config
+                    .clear(format!("{ALLOWED_IP_KEY}.{network}"))
+                    .await?;
+                self.reload_config(false).await?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": (),
+                }))
+                .into_http_response())
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+}
+
+fn parse_network(network: &str) -> trc::Result<&str> {
+    let network = network.trim();
+    IpAddrOrMask::parse_value(network)
+        .map(|_| network)
+        .map_err(|err| {
+            trc::EventType::Resource(trc::ResourceEvent::BadParameters)
+                .into_err()
+                .details(err)
+        })
+}
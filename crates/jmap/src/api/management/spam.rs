@@ -0,0 +1,93 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use common::{auth::AccessToken, Server};
+use directory::Permission;
+use hyper::Method;
+use serde_json::json;
+use std::future::Future;
+
+use crate::{
+    api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse},
+    email::spam::{SpamTrainer, SpamTraining},
+};
+
+use super::decode_path_element;
+
+pub trait ManageSpamHandler: Sync + Send {
+    fn handle_manage_spam(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        session_id: u64,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl ManageSpamHandler for Server {
+    async fn handle_manage_spam(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        session_id: u64,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        match (
+            path.get(1).copied(),
+            path.get(2).copied(),
+            path.get(3),
+            req.method(),
+        ) {
+            (
+                Some(action @ ("train" | "untrain")),
+                Some(class @ ("spam" | "ham")),
+                account,
+                &Method::POST,
+            ) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::SpamFilterTrain)?;
+
+                let body = body.filter(|body| !body.is_empty()).ok_or_else(|| {
+                    trc::ResourceEvent::BadParameters
+                        .into_err()
+                        .details("Missing message body")
+                })?;
+
+                // Train the account model as well when an account is provided
+                let account_id = if let Some(account) = account {
+                    self.core
+                        .storage
+                        .data
+                        .get_principal_id(decode_path_element(account).as_ref())
+                        .await?
+                        .ok_or_else(|| trc::ManageEvent::NotFound.into_err())?
+                        .into()
+                } else {
+                    None
+                };
+
+                self.spam_train_raw(
+                    account_id,
+                    &body,
+                    SpamTraining {
+                        is_spam: class == "spam",
+                        is_train: action == "train",
+                    },
+                    session_id,
+                )
+                .await?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": (),
+                }))
+                .into_http_response())
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+}
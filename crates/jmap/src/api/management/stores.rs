@@ -16,7 +16,9 @@ use directory::{
     Permission,
 };
 use hyper::Method;
+use jmap_proto::types::collection::Collection;
 use serde_json::json;
+use store::subspace_name;
 use utils::url_params::UrlParams;
 
 use crate::{
@@ -24,13 +26,14 @@ use crate::{
         http::{HttpSessionData, ToHttpResponse},
         HttpRequest, HttpResponse, JsonResponse,
     },
+    email::archive::EmailArchive,
     services::index::Indexer,
 };
 
 use super::decode_path_element;
 #[cfg(feature = "enterprise")]
 use super::enterprise::undelete::UndeleteApi;
-use std::future::Future;
+use std::{future::Future, str::FromStr};
 
 pub trait ManageStore: Sync + Send {
     fn handle_manage_store(
@@ -157,6 +160,132 @@ impl ManageStore for Server {
                 self.housekeeper_request(HousekeeperEvent::Purge(PurgeType::Account(account_id)))
                     .await
             }
+            (Some("stats"), id, None, &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::StoreStats)?;
+
+                let store = if let Some(id) = id {
+                    if let Some(store) = self.core.storage.stores.get(id) {
+                        store.clone()
+                    } else {
+                        return Err(trc::ResourceEvent::NotFound.into_err());
+                    }
+                } else {
+                    self.core.storage.data.clone()
+                };
+
+                let stats = store.get_storage_statistics().await?;
+                let mut subspaces = stats
+                    .subspace_sizes
+                    .iter()
+                    .map(|(subspace, size)| {
+                        json!({
+                            "subspace": char::from(*subspace).to_string(),
+                            "name": subspace_name(*subspace),
+                            "size": size,
+                            "rows": stats.total_rows.get(subspace),
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                subspaces.sort_unstable_by(|a, b| a["name"].as_str().cmp(&b["name"].as_str()));
+
+                Ok(JsonResponse::new(json!({
+                    "data": subspaces,
+                }))
+                .into_http_response())
+            }
+            (Some("index"), Some(account), Some(collection), &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::StoreStats)?;
+
+                let account_id = self
+                    .core
+                    .storage
+                    .data
+                    .get_principal_id(decode_path_element(account).as_ref())
+                    .await?
+                    .ok_or_else(|| trc::ManageEvent::NotFound.into_err())?;
+                let collection = Collection::from_str(collection)
+                    .ok()
+                    .or_else(|| collection.parse::<u8>().ok().map(Collection::from))
+                    .filter(|collection| *collection != Collection::None)
+                    .ok_or_else(|| {
+                        trc::EventType::Resource(trc::ResourceEvent::BadParameters)
+                            .into_err()
+                            .details("Invalid collection")
+                    })?;
+                let document_id = path
+                    .get(4)
+                    .and_then(|id| id.parse::<u32>().ok())
+                    .ok_or_else(|| {
+                        trc::EventType::Resource(trc::ResourceEvent::BadParameters)
+                            .into_err()
+                            .details("Invalid document id")
+                    })?;
+
+                let fields = self
+                    .core
+                    .storage
+                    .data
+                    .get_full_text_index(account_id, collection, document_id)
+                    .await?
+                    .into_iter()
+                    .map(|(field, terms)| {
+                        json!({
+                            "field": field,
+                            "terms": terms
+                                .into_iter()
+                                .map(|term| match String::from_utf8(term) {
+                                    Ok(term) => term,
+                                    Err(err) => err
+                                        .as_bytes()
+                                        .iter()
+                                        .fold("0x".to_string(), |hex, byte| {
+                                            format!("{hex}{byte:02x}")
+                                        }),
+                                })
+                                .collect::<Vec<_>>(),
+                        })
+                    })
+                    .collect::<Vec<_>>();
+
+                Ok(JsonResponse::new(json!({
+                    "data": fields,
+                }))
+                .into_http_response())
+            }
+            (Some("archive"), Some(account), None, &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::PurgeAccount)?;
+
+                let account_id = self
+                    .core
+                    .storage
+                    .data
+                    .get_principal_id(decode_path_element(account).as_ref())
+                    .await?
+                    .ok_or_else(|| trc::ManageEvent::NotFound.into_err())?;
+                let dry_run = UrlParams::new(req.uri().query())
+                    .parse::<bool>("dry-run")
+                    .unwrap_or(false);
+                let report = self.emails_archive(account_id, dry_run).await?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": report,
+                }))
+                .into_http_response())
+            }
+            (Some("retention"), None, None, &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::StoreStats)?;
+
+                let stats = self.inner.data.retention_stats.lock().clone();
+
+                Ok(JsonResponse::new(json!({
+                    "data": stats,
+                }))
+                .into_http_response())
+            }
             (Some("reindex"), id, None, &Method::GET) => {
                 // Validate the access token
                 access_token.assert_has_permission(Permission::FtsReindex)?;
@@ -95,6 +95,7 @@ impl TroubleshootApi for Server {
                     content_type: "text/event-stream".into(),
                     content_disposition: "".into(),
                     cache_control: "no-store".into(),
+                    headers: Vec::new(),
                     body: HttpResponseBody::Stream(BoxBody::new(StreamBody::new(
                         async_stream::stream! {
                             while let Some(stage) = rx.recv().await {
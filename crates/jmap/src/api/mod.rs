@@ -55,6 +55,7 @@ pub struct HttpResponse {
     pub content_type: Cow<'static, str>,
     pub content_disposition: Cow<'static, str>,
     pub cache_control: Cow<'static, str>,
+    pub headers: Vec<(hyper::header::HeaderName, String)>,
     pub body: HttpResponseBody,
 }
 
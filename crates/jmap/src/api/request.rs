@@ -6,11 +6,16 @@
 
 use std::{sync::Arc, time::Instant};
 
-use common::{auth::AccessToken, Server};
+use common::{
+    auth::AccessToken,
+    telemetry::audit::{AuditEvent, AuditRecord},
+    Server,
+};
 use jmap_proto::{
+    error::set::SetError,
     method::{
         get, query,
-        set::{self},
+        set::{self, SetResponse},
     },
     request::{method::MethodName, Call, Request, RequestMethod},
     response::{Response, ResponseMethod},
@@ -305,9 +310,23 @@ impl RequestHandler for Server {
                 set::RequestArguments::SieveScript(arguments) => {
                     access_token.assert_is_member(req.account_id)?;
 
-                    self.sieve_script_set(req.with_arguments(arguments), access_token, session)
-                        .await?
-                        .into()
+                    let account_id = req.account_id;
+                    let response = self
+                        .sieve_script_set(req.with_arguments(arguments), access_token, session)
+                        .await?;
+
+                    if self.core.audit.enable {
+                        let account = if account_id.document_id() == access_token.primary_id() {
+                            access_token.name.clone()
+                        } else {
+                            account_id.to_string()
+                        };
+                        for record in sieve_audit_records(&response, &account, &access_token.name) {
+                            self.audit(record.with_remote_ip(session.remote_ip)).await;
+                        }
+                    }
+
+                    response.into()
                 }
                 set::RequestArguments::VacationResponse => {
                     access_token.assert_is_member(req.account_id)?;
@@ -369,6 +388,7 @@ impl RequestHandler for Server {
             Jmap(JmapEvent::MethodCall),
             Id = method_name,
             SpanId = session.session_id,
+            TraceId = session.trace_parent.clone(),
             AccountId = access_token.primary_id(),
             Elapsed = op_start.elapsed(),
         );
@@ -376,3 +396,64 @@ impl RequestHandler for Server {
         Ok(response)
     }
 }
+
+// Builds one audit record for each script created, updated or destroyed,
+// including the ones that could not be changed
+fn sieve_audit_records(response: &SetResponse, account: &str, actor: &str) -> Vec<AuditRecord> {
+    let record = |script: String, action: &str, error: Option<&SetError>| {
+        let record = AuditRecord::new(
+            actor,
+            AuditEvent::SieveScriptChange {
+                account: account.to_string(),
+                script,
+                action: action.to_string(),
+            },
+        );
+        if let Some(error) = error {
+            record.with_failure(
+                error
+                    .description
+                    .as_deref()
+                    .unwrap_or_else(|| error.type_.as_str()),
+            )
+        } else {
+            record
+        }
+    };
+
+    response
+        .created
+        .keys()
+        .map(|id| record(id.clone(), "create", None))
+        .chain(
+            response
+                .not_created
+                .iter()
+                .map(|(id, err)| record(id.clone(), "create", Some(err))),
+        )
+        .chain(
+            response
+                .updated
+                .keys()
+                .map(|id| record(id.to_string(), "update", None)),
+        )
+        .chain(
+            response
+                .not_updated
+                .iter()
+                .map(|(id, err)| record(id.to_string(), "update", Some(err))),
+        )
+        .chain(
+            response
+                .destroyed
+                .iter()
+                .map(|id| record(id.to_string(), "destroy", None)),
+        )
+        .chain(
+            response
+                .not_destroyed
+                .iter()
+                .map(|(id, err)| record(id.to_string(), "destroy", Some(err))),
+        )
+        .collect()
+}
@@ -27,7 +27,7 @@ use store::{
 use trc::AddContext;
 use utils::map::bitmap::Bitmap;
 
-use crate::JmapMethods;
+use crate::{services::state::StateManager, JmapMethods};
 
 pub trait AclMethods: Sync + Send {
     fn shared_documents(
@@ -358,7 +358,7 @@ impl AclMethods for Server {
 
     fn refresh_acls(&self, changes: &Object<Value>, current: &Option<HashedValue<Object<Value>>>) {
         if let Value::Acl(acl_changes) = changes.get(&Property::Acl) {
-            let access_tokens = &self.inner.data.access_tokens;
+            let mut account_ids = Vec::new();
             if let Some(Value::Acl(acl_current)) = current
                 .as_ref()
                 .and_then(|current| current.inner.properties.get(&Property::Acl))
@@ -372,7 +372,7 @@ impl AclMethods for Server {
                         }
                     }
                     if invalidate {
-                        access_tokens.remove(&current_item.account_id);
+                        account_ids.push(current_item.account_id);
                     }
                 }
 
@@ -385,14 +385,15 @@ impl AclMethods for Server {
                         }
                     }
                     if invalidate {
-                        access_tokens.remove(&change_item.account_id);
+                        account_ids.push(change_item.account_id);
                     }
                 }
             } else {
                 for value in acl_changes {
-                    access_tokens.remove(&value.account_id);
+                    account_ids.push(value.account_id);
                 }
             }
+            self.invalidate_access_tokens(account_ids);
         }
     }
 
@@ -485,6 +486,11 @@ impl EffectiveAcl for Object<Value> {
             }
         }
 
+        // Modifying items includes changing their seen state
+        if acl.contains(Acl::ModifyItems) {
+            acl.insert(Acl::SetSeen);
+        }
+
         acl
     }
 }
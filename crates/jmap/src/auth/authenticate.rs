@@ -6,7 +6,9 @@
 
 use std::sync::Arc;
 
-use common::{auth::AuthRequest, listener::limiter::InFlight, Server};
+use common::{
+    auth::AuthRequest, config::server::ServerProtocol, listener::limiter::InFlight, Server,
+};
 use hyper::header;
 use mail_parser::decoders::base64::base64_decode;
 use mail_send::Credentials;
@@ -80,6 +82,7 @@ impl Authenticator for Server {
                             credentials,
                             session.session_id,
                             session.remote_ip,
+                            ServerProtocol::Http,
                         ))
                         .await
                     {
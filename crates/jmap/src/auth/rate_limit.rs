@@ -54,23 +54,31 @@ impl RateLimiter for Server {
 
     async fn is_account_allowed(&self, access_token: &AccessToken) -> trc::Result<InFlight> {
         let limiter = self.get_concurrency_limiter(access_token.primary_id());
-        let is_rate_allowed = if let Some(rate) = &self.core.jmap.rate_authenticated {
+        let retry_after = if let Some(rate) = &self.core.jmap.rate_authenticated {
             self.core
                 .storage
                 .lookup
-                .is_rate_allowed(
+                .check_rate(
                     format!("j:{}", access_token.primary_id).as_bytes(),
                     rate,
+                    self.core.jmap.rate_window,
                     false,
                 )
                 .await
                 .caused_by(trc::location!())?
-                .is_none()
         } else {
-            true
+            None
         };
 
-        if is_rate_allowed {
+        if let Some(retry_after) = retry_after {
+            if access_token.has_permission(Permission::UnlimitedRequests) {
+                Ok(InFlight::default())
+            } else {
+                Err(trc::LimitEvent::TooManyRequests
+                    .into_err()
+                    .ctx(trc::Key::Expires, retry_after))
+            }
+        } else {
             if let Some(in_flight_request) = limiter.concurrent_requests.is_allowed() {
                 Ok(in_flight_request)
             } else if access_token.has_permission(Permission::UnlimitedRequests) {
@@ -78,25 +86,27 @@ impl RateLimiter for Server {
             } else {
                 Err(trc::LimitEvent::ConcurrentRequest.into_err())
             }
-        } else if access_token.has_permission(Permission::UnlimitedRequests) {
-            Ok(InFlight::default())
-        } else {
-            Err(trc::LimitEvent::TooManyRequests.into_err())
         }
     }
 
     async fn is_anonymous_allowed(&self, addr: &IpAddr) -> trc::Result<()> {
         if let Some(rate) = &self.core.jmap.rate_anonymous {
-            if self
+            if let Some(retry_after) = self
                 .core
                 .storage
                 .lookup
-                .is_rate_allowed(format!("jreq:{}", addr).as_bytes(), rate, false)
+                .check_rate(
+                    format!("jreq:{}", addr).as_bytes(),
+                    rate,
+                    self.core.jmap.rate_window,
+                    false,
+                )
                 .await
                 .caused_by(trc::location!())?
-                .is_some()
             {
-                return Err(trc::LimitEvent::TooManyRequests.into_err());
+                return Err(trc::LimitEvent::TooManyRequests
+                    .into_err()
+                    .ctx(trc::Key::Expires, retry_after));
             }
         }
         Ok(())
@@ -10,8 +10,8 @@ use common::Server;
 use jmap_proto::types::collection::Collection;
 use std::future::Future;
 use store::{
+    query::log::LogCutoff,
     write::{log::ChangeLogBuilder, BatchBuilder},
-    LogKey,
 };
 use trc::AddContext;
 
@@ -88,23 +88,15 @@ impl ChangeLog for Server {
             Collection::Thread,
             Collection::Identity,
             Collection::EmailSubmission,
+            Collection::AddressBook,
+            Collection::ContactCard,
         ] {
             self.core
                 .storage
                 .data
-                .delete_range(
-                    LogKey {
-                        account_id,
-                        collection: collection.into(),
-                        change_id: 0,
-                    },
-                    LogKey {
-                        account_id,
-                        collection: collection.into(),
-                        change_id: reference_cid,
-                    },
-                )
-                .await?;
+                .clear_change_log(account_id, collection, LogCutoff::ChangeId(reference_cid))
+                .await
+                .caused_by(trc::location!())?;
         }
 
         Ok(())
@@ -0,0 +1,570 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use common::{auth::ResourceToken, Server};
+use jmap_proto::types::{collection::Collection, property::Property};
+use store::{
+    blake3,
+    query::Filter,
+    roaring::RoaringBitmap,
+    write::{
+        log::ChangeLogBuilder, BatchBuilder, Bincode, DirectoryClass, F_CLEAR, F_INDEX, F_VALUE,
+    },
+};
+use trc::AddContext;
+
+use crate::{changes::write::ChangeLog, JmapMethods};
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct AddressBook {
+    pub name: String,
+    pub display_name: String,
+    pub description: String,
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct ContactCard {
+    pub name: String,
+    pub uid: String,
+    pub address_book_id: u32,
+    pub vcard: String,
+}
+
+pub enum PutResult {
+    Created { document_id: u32, etag: String },
+    Updated { document_id: u32, etag: String },
+    UidConflict { name: String },
+}
+
+// Change log ids for contact cards carry the address book id in the upper
+// 32 bits so that sync reports can be filtered without loading deleted cards.
+pub fn card_change_id(address_book_id: u32, document_id: u32) -> u64 {
+    ((address_book_id as u64) << 32) | document_id as u64
+}
+
+impl ContactCard {
+    pub fn etag(&self) -> String {
+        etag(&self.vcard)
+    }
+}
+
+pub fn etag(vcard: &str) -> String {
+    format!(
+        "\"{}\"",
+        &blake3::hash(vcard.as_bytes()).to_hex().as_str()[..32]
+    )
+}
+
+pub trait AddressBookStore: Sync + Send {
+    fn address_book_ids(
+        &self,
+        account_id: u32,
+    ) -> impl Future<Output = trc::Result<RoaringBitmap>> + Send;
+
+    fn address_book_get(
+        &self,
+        account_id: u32,
+        document_id: u32,
+    ) -> impl Future<Output = trc::Result<Option<AddressBook>>> + Send;
+
+    fn address_book_by_name(
+        &self,
+        account_id: u32,
+        name: &str,
+    ) -> impl Future<Output = trc::Result<Option<(u32, AddressBook)>>> + Send;
+
+    fn address_book_create(
+        &self,
+        account_id: u32,
+        address_book: AddressBook,
+    ) -> impl Future<Output = trc::Result<u32>> + Send;
+
+    fn address_book_update(
+        &self,
+        account_id: u32,
+        document_id: u32,
+        address_book: AddressBook,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+
+    fn address_book_delete(
+        &self,
+        resource_token: &ResourceToken,
+        document_id: u32,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+
+    fn contact_card_ids(
+        &self,
+        account_id: u32,
+        address_book_id: u32,
+    ) -> impl Future<Output = trc::Result<RoaringBitmap>> + Send;
+
+    fn contact_card_get(
+        &self,
+        account_id: u32,
+        document_id: u32,
+    ) -> impl Future<Output = trc::Result<Option<ContactCard>>> + Send;
+
+    fn contact_cards_get(
+        &self,
+        account_id: u32,
+        document_ids: &RoaringBitmap,
+    ) -> impl Future<Output = trc::Result<Vec<(u32, ContactCard)>>> + Send;
+
+    fn contact_card_by_name(
+        &self,
+        account_id: u32,
+        address_book_id: u32,
+        name: &str,
+    ) -> impl Future<Output = trc::Result<Option<(u32, ContactCard)>>> + Send;
+
+    fn contact_card_put(
+        &self,
+        resource_token: &ResourceToken,
+        card: ContactCard,
+    ) -> impl Future<Output = trc::Result<PutResult>> + Send;
+
+    fn contact_card_delete(
+        &self,
+        resource_token: &ResourceToken,
+        document_id: u32,
+        card: ContactCard,
+        changes: &mut ChangeLogBuilder,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+
+    fn contact_card_tombstone(
+        &self,
+        account_id: u32,
+        change_id: u64,
+    ) -> impl Future<Output = trc::Result<Option<String>>> + Send;
+
+    fn contact_sync_state(&self, account_id: u32) -> impl Future<Output = trc::Result<u64>> + Send;
+}
+
+impl AddressBookStore for Server {
+    async fn address_book_ids(&self, account_id: u32) -> trc::Result<RoaringBitmap> {
+        self.get_document_ids(account_id, Collection::AddressBook)
+            .await
+            .map(|ids| ids.unwrap_or_default())
+    }
+
+    async fn address_book_get(
+        &self,
+        account_id: u32,
+        document_id: u32,
+    ) -> trc::Result<Option<AddressBook>> {
+        self.get_property::<Bincode<AddressBook>>(
+            account_id,
+            Collection::AddressBook,
+            document_id,
+            Property::Value,
+        )
+        .await
+        .map(|book| book.map(|book| book.inner))
+    }
+
+    async fn address_book_by_name(
+        &self,
+        account_id: u32,
+        name: &str,
+    ) -> trc::Result<Option<(u32, AddressBook)>> {
+        let document_ids = self
+            .filter(
+                account_id,
+                Collection::AddressBook,
+                vec![Filter::eq(Property::Name, name)],
+            )
+            .await?
+            .results;
+
+        for document_id in document_ids {
+            if let Some(book) = self.address_book_get(account_id, document_id).await? {
+                if book.name == name {
+                    return Ok(Some((document_id, book)));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    async fn address_book_create(
+        &self,
+        account_id: u32,
+        address_book: AddressBook,
+    ) -> trc::Result<u32> {
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::AddressBook)
+            .create_document()
+            .value(Property::Name, address_book.name.as_str(), F_INDEX)
+            .value(Property::Value, Bincode::new(address_book), F_VALUE);
+        let document_id = self.write_batch_expect_id(batch).await?;
+
+        let mut changes = ChangeLogBuilder::new();
+        changes.log_insert(Collection::AddressBook, document_id);
+        self.commit_changes(account_id, changes).await?;
+
+        Ok(document_id)
+    }
+
+    async fn address_book_update(
+        &self,
+        account_id: u32,
+        document_id: u32,
+        address_book: AddressBook,
+    ) -> trc::Result<()> {
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::AddressBook)
+            .update_document(document_id)
+            .value(Property::Value, Bincode::new(address_book), F_VALUE);
+        self.write_batch(batch).await?;
+
+        let mut changes = ChangeLogBuilder::new();
+        changes.log_update(Collection::AddressBook, document_id);
+        self.commit_changes(account_id, changes).await.map(|_| ())
+    }
+
+    async fn address_book_delete(
+        &self,
+        resource_token: &ResourceToken,
+        document_id: u32,
+    ) -> trc::Result<()> {
+        let account_id = resource_token.account_id;
+        let book = self
+            .address_book_get(account_id, document_id)
+            .await?
+            .ok_or_else(|| {
+                trc::StoreEvent::NotFound
+                    .into_err()
+                    .caused_by(trc::location!())
+                    .document_id(document_id)
+            })?;
+
+        // Delete all cards in the address book
+        let mut changes = ChangeLogBuilder::new();
+        let card_ids = self.contact_card_ids(account_id, document_id).await?;
+        for (card_id, card) in self.contact_cards_get(account_id, &card_ids).await? {
+            self.contact_card_delete(resource_token, card_id, card, &mut changes)
+                .await?;
+        }
+
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::AddressBook)
+            .delete_document(document_id)
+            .value(Property::Name, book.name.as_str(), F_INDEX | F_CLEAR)
+            .value(Property::Id, (), F_VALUE | F_CLEAR)
+            .value(Property::Value, (), F_VALUE | F_CLEAR);
+        self.write_batch(batch).await?;
+
+        changes.log_delete(Collection::AddressBook, document_id);
+        self.commit_changes(account_id, changes).await.map(|_| ())
+    }
+
+    async fn contact_card_ids(
+        &self,
+        account_id: u32,
+        address_book_id: u32,
+    ) -> trc::Result<RoaringBitmap> {
+        self.get_tag(
+            account_id,
+            Collection::ContactCard,
+            Property::ParentId,
+            address_book_id,
+        )
+        .await
+        .map(|ids| ids.unwrap_or_default())
+    }
+
+    async fn contact_card_get(
+        &self,
+        account_id: u32,
+        document_id: u32,
+    ) -> trc::Result<Option<ContactCard>> {
+        self.get_property::<Bincode<ContactCard>>(
+            account_id,
+            Collection::ContactCard,
+            document_id,
+            Property::Value,
+        )
+        .await
+        .map(|card| card.map(|card| card.inner))
+    }
+
+    async fn contact_cards_get(
+        &self,
+        account_id: u32,
+        document_ids: &RoaringBitmap,
+    ) -> trc::Result<Vec<(u32, ContactCard)>> {
+        if document_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        self.get_properties::<Bincode<ContactCard>, _, _>(
+            account_id,
+            Collection::ContactCard,
+            document_ids,
+            Property::Value,
+        )
+        .await
+        .map(|cards| {
+            cards
+                .into_iter()
+                .map(|(document_id, card)| (document_id, card.inner))
+                .collect()
+        })
+    }
+
+    async fn contact_card_by_name(
+        &self,
+        account_id: u32,
+        address_book_id: u32,
+        name: &str,
+    ) -> trc::Result<Option<(u32, ContactCard)>> {
+        let document_ids = self
+            .filter(
+                account_id,
+                Collection::ContactCard,
+                vec![
+                    Filter::eq(Property::Name, name),
+                    Filter::is_in_bitmap(Property::ParentId, address_book_id),
+                ],
+            )
+            .await?
+            .results;
+
+        for document_id in document_ids {
+            if let Some(card) = self.contact_card_get(account_id, document_id).await? {
+                if card.name == name && card.address_book_id == address_book_id {
+                    return Ok(Some((document_id, card)));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    async fn contact_card_put(
+        &self,
+        resource_token: &ResourceToken,
+        card: ContactCard,
+    ) -> trc::Result<PutResult> {
+        let account_id = resource_token.account_id;
+        let address_book_id = card.address_book_id;
+        let etag = card.etag();
+        let size = card.vcard.len() as i64;
+
+        loop {
+            // UIDs must be unique within an address book, puts claiming a UID bump
+            // the address book's UID version so that concurrent claims fail to commit
+            let uid_version = self
+                .get_property::<u64>(
+                    account_id,
+                    Collection::AddressBook,
+                    address_book_id,
+                    Property::Id,
+                )
+                .await?;
+            let existing_id = self
+                .contact_card_by_name(account_id, address_book_id, &card.name)
+                .await?;
+            let uid_ids = self
+                .filter(
+                    account_id,
+                    Collection::ContactCard,
+                    vec![
+                        Filter::eq(Property::Id, card.uid.as_str()),
+                        Filter::is_in_bitmap(Property::ParentId, address_book_id),
+                    ],
+                )
+                .await?
+                .results;
+            for (document_id, other) in self.contact_cards_get(account_id, &uid_ids).await? {
+                if other.uid == card.uid
+                    && existing_id
+                        .as_ref()
+                        .map_or(true, |(existing_id, _)| *existing_id != document_id)
+                {
+                    return Ok(PutResult::UidConflict { name: other.name });
+                }
+            }
+
+            let mut batch = BatchBuilder::new();
+            batch
+                .with_account_id(account_id)
+                .with_collection(Collection::ContactCard);
+
+            let (prev_size, claims_uid) = if let Some((document_id, prev_card)) = &existing_id {
+                let prev_size = prev_card.vcard.len() as i64;
+                self.has_available_quota(resource_token, (size - prev_size).max(0) as u64)
+                    .await?;
+
+                batch
+                    .update_document(*document_id)
+                    .value(Property::Id, prev_card.uid.as_str(), F_INDEX | F_CLEAR)
+                    .value(Property::Id, card.uid.as_str(), F_INDEX);
+                (prev_size, prev_card.uid != card.uid)
+            } else {
+                self.has_available_quota(resource_token, size as u64)
+                    .await?;
+
+                batch
+                    .create_document()
+                    .tag(Property::ParentId, address_book_id, 0)
+                    .value(Property::Name, card.name.as_str(), F_INDEX)
+                    .value(Property::Id, card.uid.as_str(), F_INDEX);
+                (0, true)
+            };
+
+            let quota_delta = size - prev_size;
+            if quota_delta != 0 {
+                batch.add(DirectoryClass::UsedQuota(account_id), quota_delta);
+
+                // Update tenant quota
+                #[cfg(feature = "enterprise")]
+                if self.core.is_enterprise_edition() {
+                    if let Some(tenant) = resource_token.tenant {
+                        batch.add(DirectoryClass::UsedQuota(tenant.id), quota_delta);
+                    }
+                }
+            }
+            batch.value(Property::Value, Bincode::new(card.clone()), F_VALUE);
+
+            if claims_uid {
+                batch
+                    .with_collection(Collection::AddressBook)
+                    .update_document(address_book_id);
+                if let Some(uid_version) = uid_version {
+                    batch.assert_value(Property::Id, uid_version).value(
+                        Property::Id,
+                        uid_version.wrapping_add(1),
+                        F_VALUE,
+                    );
+                } else {
+                    batch
+                        .assert_value(Property::Id, ())
+                        .value(Property::Id, 1u64, F_VALUE);
+                }
+            }
+
+            let ids = match self.write_batch(batch).await {
+                Ok(ids) => ids,
+                // Another put claimed a UID in this address book, check again
+                Err(err) if err.is_assertion_failure() => continue,
+                Err(err) => return Err(err),
+            };
+
+            let mut changes = ChangeLogBuilder::new();
+            let result = if let Some((document_id, _)) = existing_id {
+                changes.log_update(
+                    Collection::ContactCard,
+                    card_change_id(address_book_id, document_id),
+                );
+                PutResult::Updated { document_id, etag }
+            } else {
+                let document_id = ids.last_document_id().caused_by(trc::location!())?;
+                changes.log_insert(
+                    Collection::ContactCard,
+                    card_change_id(address_book_id, document_id),
+                );
+                PutResult::Created { document_id, etag }
+            };
+            changes.log_child_update(Collection::AddressBook, address_book_id);
+            self.commit_changes(account_id, changes).await?;
+
+            return Ok(result);
+        }
+    }
+
+    async fn contact_card_delete(
+        &self,
+        resource_token: &ResourceToken,
+        document_id: u32,
+        card: ContactCard,
+        changes: &mut ChangeLogBuilder,
+    ) -> trc::Result<()> {
+        let account_id = resource_token.account_id;
+        let address_book_id = card.address_book_id;
+        let size = card.vcard.len() as i64;
+
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::ContactCard)
+            .delete_document(document_id)
+            .tag(Property::ParentId, address_book_id, F_CLEAR)
+            .value(Property::Name, card.name.as_str(), F_INDEX | F_CLEAR)
+            .value(Property::Id, card.uid.as_str(), F_INDEX | F_CLEAR)
+            .value(Property::Value, (), F_VALUE | F_CLEAR)
+            .add(DirectoryClass::UsedQuota(account_id), -size);
+
+        // Update tenant quota
+        #[cfg(feature = "enterprise")]
+        if self.core.is_enterprise_edition() {
+            if let Some(tenant) = resource_token.tenant {
+                batch.add(DirectoryClass::UsedQuota(tenant.id), -size);
+            }
+        }
+
+        self.write_batch(batch).await?;
+
+        // Keep the resource name around for as long as the change log does,
+        // sync reports need it to list deleted resources.
+        let change_id = card_change_id(address_book_id, document_id);
+        self.core
+            .storage
+            .lookup
+            .key_set(
+                tombstone_key(account_id, change_id),
+                card.name.into_bytes(),
+                self.core
+                    .jmap
+                    .changes_max_history
+                    .map_or(86400 * 30, |history| history.as_secs().max(86400))
+                    .into(),
+            )
+            .await
+            .caused_by(trc::location!())?;
+
+        changes.log_delete(Collection::ContactCard, change_id);
+        changes.log_child_update(Collection::AddressBook, address_book_id);
+
+        Ok(())
+    }
+
+    async fn contact_card_tombstone(
+        &self,
+        account_id: u32,
+        change_id: u64,
+    ) -> trc::Result<Option<String>> {
+        self.core
+            .storage
+            .lookup
+            .key_get::<String>(tombstone_key(account_id, change_id))
+            .await
+            .caused_by(trc::location!())
+    }
+
+    async fn contact_sync_state(&self, account_id: u32) -> trc::Result<u64> {
+        self.core
+            .storage
+            .data
+            .get_last_change_id(account_id, Collection::ContactCard)
+            .await
+            .caused_by(trc::location!())
+            .map(|change_id| change_id.unwrap_or_default())
+    }
+}
+
+fn tombstone_key(account_id: u32, change_id: u64) -> Vec<u8> {
+    format!("dav:card:{account_id}:{change_id}").into_bytes()
+}
@@ -0,0 +1,589 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{borrow::Cow, fmt::Write, future::Future};
+
+use common::{auth::AccessToken, Server};
+use hyper::{header, Method, StatusCode};
+use store::write::log::ChangeLogBuilder;
+
+use crate::{
+    api::{
+        http::{fetch_body, HttpSessionData},
+        management::decode_path_element,
+        HttpRequest, HttpResponse,
+    },
+    auth::authenticate::Authenticator,
+    changes::write::ChangeLog,
+    JmapMethods,
+};
+
+use self::{
+    addressbook::{AddressBook, AddressBookStore, ContactCard, PutResult},
+    report::DavReportHandler,
+    vcard::VCard,
+    xml::{dav_error, XmlElement, NS_CARDDAV, NS_DAV},
+};
+
+pub mod addressbook;
+pub mod report;
+pub mod vcard;
+pub mod xml;
+
+pub const DAV_ROOT: &str = "/dav/card/";
+pub const SYNC_TOKEN_PREFIX: &str = "http://stalw.art/ns/sync/";
+pub const DEFAULT_ADDRESS_BOOK: &str = "default";
+const DAV_CAPABILITIES: &str = "1, 3, addressbook, extended-mkcol";
+const DAV_ALLOW: &str = "OPTIONS, GET, HEAD, PUT, DELETE, PROPFIND, PROPPATCH, REPORT, MKCOL";
+
+// Path of a CardDAV resource, relative to DAV_ROOT
+#[derive(Debug, PartialEq, Eq)]
+pub enum DavPath<'x> {
+    Root,
+    Home {
+        account: Cow<'x, str>,
+    },
+    AddressBook {
+        account: Cow<'x, str>,
+        name: Cow<'x, str>,
+    },
+    Card {
+        account: Cow<'x, str>,
+        address_book: Cow<'x, str>,
+        name: Cow<'x, str>,
+    },
+}
+
+pub struct DavContext<'x> {
+    pub access_token: &'x AccessToken,
+    pub account_id: u32,
+    pub home: String,
+}
+
+pub trait DavRequestHandler: Sync + Send {
+    fn handle_dav_request(
+        &self,
+        req: HttpRequest,
+        session: &HttpSessionData,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+
+    fn handle_dav_mkcol(
+        &self,
+        ctx: &DavContext<'_>,
+        name: &str,
+        xml: Option<&XmlElement>,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+
+    fn handle_dav_put(
+        &self,
+        ctx: &DavContext<'_>,
+        req: &HttpRequest,
+        address_book: &str,
+        name: &str,
+        body: Vec<u8>,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+
+    fn dav_card(
+        &self,
+        ctx: &DavContext<'_>,
+        address_book: &str,
+        name: &str,
+    ) -> impl Future<Output = trc::Result<Option<(u32, ContactCard)>>> + Send;
+}
+
+impl DavRequestHandler for Server {
+    async fn handle_dav_request(
+        &self,
+        mut req: HttpRequest,
+        session: &HttpSessionData,
+    ) -> trc::Result<HttpResponse> {
+        let method = req.method().clone();
+        if method == Method::OPTIONS {
+            return Ok(HttpResponse::new_empty(StatusCode::OK)
+                .with_header(header::HeaderName::from_static("dav"), DAV_CAPABILITIES)
+                .with_header(header::ALLOW, DAV_ALLOW));
+        }
+
+        // Authenticate request
+        let (_in_flight, access_token) = match self.authenticate_headers(&req, session, false).await
+        {
+            Ok(result) => result,
+            Err(err) if matches!(err.as_ref(), trc::EventType::Auth(_)) => {
+                return Ok(HttpResponse::new_empty(StatusCode::UNAUTHORIZED)
+                    .with_header(header::WWW_AUTHENTICATE, "Basic realm=\"Stalwart Server\""));
+            }
+            Err(err) => return Err(err),
+        };
+
+        let path = req.uri().path().to_string();
+        let Some(dav_path) = DavPath::parse(&path) else {
+            return Ok(HttpResponse::new_empty(StatusCode::NOT_FOUND));
+        };
+
+        // Only the authenticated account's address books can be accessed
+        let ctx = DavContext {
+            access_token: &access_token,
+            account_id: access_token.primary_id,
+            home: format!("{DAV_ROOT}{}/", encode_path_element(&access_token.name)),
+        };
+        if dav_path
+            .account()
+            .map_or(false, |account| account != access_token.name)
+        {
+            return Ok(HttpResponse::new_empty(StatusCode::FORBIDDEN));
+        }
+
+        let depth = req
+            .headers()
+            .get("depth")
+            .and_then(|h| h.to_str().ok())
+            .map(|depth| depth.trim().to_string());
+        let body = if matches!(
+            method.as_str(),
+            "PUT" | "PROPFIND" | "PROPPATCH" | "REPORT" | "MKCOL"
+        ) {
+            match fetch_body(
+                &mut req,
+                if method == Method::PUT {
+                    self.core.jmap.upload_max_size
+                } else {
+                    self.core.jmap.request_max_size
+                },
+                session.session_id,
+            )
+            .await
+            {
+                Some(body) => body,
+                None => {
+                    return Ok(HttpResponse::new_empty(StatusCode::PAYLOAD_TOO_LARGE));
+                }
+            }
+        } else {
+            Vec::new()
+        };
+        let xml = if !body.is_empty() && method != Method::PUT {
+            match XmlElement::parse(&body) {
+                Ok(xml) => Some(xml),
+                Err(err) => {
+                    return Ok(HttpResponse::new_text(
+                        StatusCode::BAD_REQUEST,
+                        "text/plain",
+                        err,
+                    ));
+                }
+            }
+        } else {
+            None
+        };
+
+        match (method.as_str(), dav_path) {
+            ("PROPFIND", path) => {
+                self.handle_dav_propfind(&ctx, path, depth.as_deref(), xml.as_ref())
+                    .await
+            }
+            ("REPORT", DavPath::AddressBook { name, .. }) => {
+                if let Some(xml) = xml {
+                    self.handle_dav_report(&ctx, &name, &xml).await
+                } else {
+                    Ok(HttpResponse::new_empty(StatusCode::BAD_REQUEST))
+                }
+            }
+            ("PROPPATCH", DavPath::AddressBook { name, .. }) => {
+                if let Some(xml) = xml {
+                    self.handle_dav_proppatch(&ctx, &name, &xml).await
+                } else {
+                    Ok(HttpResponse::new_empty(StatusCode::BAD_REQUEST))
+                }
+            }
+            ("MKCOL", DavPath::AddressBook { name, .. }) => {
+                self.handle_dav_mkcol(&ctx, &name, xml.as_ref()).await
+            }
+            ("DELETE", DavPath::AddressBook { name, .. }) => {
+                if let Some((document_id, _)) =
+                    self.address_book_by_name(ctx.account_id, &name).await?
+                {
+                    let resource_token = self
+                        .get_resource_token(ctx.access_token, ctx.account_id)
+                        .await?;
+                    self.address_book_delete(&resource_token, document_id)
+                        .await?;
+                    Ok(HttpResponse::new_empty(StatusCode::NO_CONTENT))
+                } else {
+                    Ok(HttpResponse::new_empty(StatusCode::NOT_FOUND))
+                }
+            }
+            (
+                "GET" | "HEAD",
+                DavPath::Card {
+                    address_book, name, ..
+                },
+            ) => {
+                if let Some((_, card)) = self.dav_card(&ctx, &address_book, &name).await? {
+                    let etag = card.etag();
+                    Ok(HttpResponse::new_binary(
+                        StatusCode::OK,
+                        "text/vcard; charset=utf-8",
+                        if method == Method::GET {
+                            card.vcard.into_bytes()
+                        } else {
+                            Vec::new()
+                        },
+                    )
+                    .with_header(header::ETAG, etag))
+                } else {
+                    Ok(HttpResponse::new_empty(StatusCode::NOT_FOUND))
+                }
+            }
+            (
+                "PUT",
+                DavPath::Card {
+                    address_book, name, ..
+                },
+            ) => {
+                self.handle_dav_put(&ctx, &req, &address_book, &name, body)
+                    .await
+            }
+            (
+                "DELETE",
+                DavPath::Card {
+                    address_book, name, ..
+                },
+            ) => {
+                if let Some((document_id, card)) = self.dav_card(&ctx, &address_book, &name).await?
+                {
+                    if let Some(if_match) = req.headers().get(header::IF_MATCH) {
+                        if !etag_matches(if_match.to_str().unwrap_or_default(), &card.etag()) {
+                            return Ok(HttpResponse::new_empty(StatusCode::PRECONDITION_FAILED));
+                        }
+                    }
+
+                    let resource_token = self
+                        .get_resource_token(ctx.access_token, ctx.account_id)
+                        .await?;
+                    let mut changes = ChangeLogBuilder::new();
+                    self.contact_card_delete(&resource_token, document_id, card, &mut changes)
+                        .await?;
+                    self.commit_changes(ctx.account_id, changes).await?;
+                    Ok(HttpResponse::new_empty(StatusCode::NO_CONTENT))
+                } else {
+                    Ok(HttpResponse::new_empty(StatusCode::NOT_FOUND))
+                }
+            }
+            _ => Ok(HttpResponse::new_empty(StatusCode::METHOD_NOT_ALLOWED)
+                .with_header(header::ALLOW, DAV_ALLOW)),
+        }
+    }
+
+    async fn dav_card(
+        &self,
+        ctx: &DavContext<'_>,
+        address_book: &str,
+        name: &str,
+    ) -> trc::Result<Option<(u32, ContactCard)>> {
+        if let Some((address_book_id, _)) = self
+            .address_book_by_name(ctx.account_id, address_book)
+            .await?
+        {
+            self.contact_card_by_name(ctx.account_id, address_book_id, name)
+                .await
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn handle_dav_mkcol(
+        &self,
+        ctx: &DavContext<'_>,
+        name: &str,
+        xml: Option<&XmlElement>,
+    ) -> trc::Result<HttpResponse> {
+        if self
+            .address_book_by_name(ctx.account_id, name)
+            .await?
+            .is_some()
+        {
+            return Ok(HttpResponse::new_empty(StatusCode::METHOD_NOT_ALLOWED));
+        }
+
+        // Extended MKCOL (RFC 5689)
+        let mut address_book = AddressBook {
+            name: name.to_string(),
+            display_name: name.to_string(),
+            description: String::new(),
+        };
+        if let Some(prop) = xml
+            .and_then(|xml| xml.child(NS_DAV, "set"))
+            .and_then(|set| set.child(NS_DAV, "prop"))
+        {
+            if let Some(resource_type) = prop.child(NS_DAV, "resourcetype") {
+                if resource_type.child(NS_CARDDAV, "addressbook").is_none() {
+                    return Ok(HttpResponse::new_text(
+                        StatusCode::FORBIDDEN,
+                        "application/xml; charset=utf-8",
+                        dav_error(NS_DAV, "valid-resourcetype", None),
+                    ));
+                }
+            }
+            if let Some(display_name) = prop.child(NS_DAV, "displayname") {
+                address_book.display_name = display_name.text.clone();
+            }
+            if let Some(description) = prop.child(NS_CARDDAV, "addressbook-description") {
+                address_book.description = description.text.clone();
+            }
+        }
+
+        self.address_book_create(ctx.account_id, address_book)
+            .await?;
+
+        Ok(HttpResponse::new_empty(StatusCode::CREATED))
+    }
+
+    async fn handle_dav_put(
+        &self,
+        ctx: &DavContext<'_>,
+        req: &HttpRequest,
+        address_book: &str,
+        name: &str,
+        body: Vec<u8>,
+    ) -> trc::Result<HttpResponse> {
+        let Some((address_book_id, _)) = self
+            .address_book_by_name(ctx.account_id, address_book)
+            .await?
+        else {
+            return Ok(HttpResponse::new_empty(StatusCode::CONFLICT));
+        };
+
+        // Validate content type
+        if let Some(content_type) = req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|h| h.to_str().ok())
+        {
+            let content_type = content_type
+                .split_once(';')
+                .map_or(content_type, |(ct, _)| ct)
+                .trim();
+            if !content_type.eq_ignore_ascii_case("text/vcard")
+                && !content_type.eq_ignore_ascii_case("text/x-vcard")
+            {
+                return Ok(precondition_failed(
+                    StatusCode::FORBIDDEN,
+                    NS_CARDDAV,
+                    "supported-address-data",
+                    None,
+                ));
+            }
+        }
+
+        // Parse and normalize vCard
+        let card = match std::str::from_utf8(&body)
+            .ok()
+            .ok_or(vcard::VCardError::InvalidLine)
+            .and_then(VCard::parse)
+        {
+            Ok(card) => card,
+            Err(err) => {
+                return Ok(HttpResponse::new_text(
+                    StatusCode::FORBIDDEN,
+                    "application/xml; charset=utf-8",
+                    dav_error(NS_CARDDAV, "valid-address-data", None),
+                )
+                .with_header(
+                    header::HeaderName::from_static("x-dav-error"),
+                    err.description(),
+                ));
+            }
+        };
+
+        // Validate preconditions
+        let current = self
+            .contact_card_by_name(ctx.account_id, address_book_id, name)
+            .await?;
+        if let Some(if_match) = req
+            .headers()
+            .get(header::IF_MATCH)
+            .and_then(|h| h.to_str().ok())
+        {
+            if current
+                .as_ref()
+                .map_or(true, |(_, card)| !etag_matches(if_match, &card.etag()))
+            {
+                return Ok(HttpResponse::new_empty(StatusCode::PRECONDITION_FAILED));
+            }
+        }
+        if let Some(if_none_match) = req
+            .headers()
+            .get(header::IF_NONE_MATCH)
+            .and_then(|h| h.to_str().ok())
+        {
+            if current
+                .as_ref()
+                .map_or(false, |(_, card)| etag_matches(if_none_match, &card.etag()))
+            {
+                return Ok(HttpResponse::new_empty(StatusCode::PRECONDITION_FAILED));
+            }
+        }
+
+        let resource_token = self
+            .get_resource_token(ctx.access_token, ctx.account_id)
+            .await?;
+        match self
+            .contact_card_put(
+                &resource_token,
+                ContactCard {
+                    name: name.to_string(),
+                    uid: card.uid().unwrap_or_default().to_string(),
+                    address_book_id,
+                    vcard: card.normalize(),
+                },
+            )
+            .await
+        {
+            Ok(PutResult::Created { etag, .. }) => {
+                Ok(HttpResponse::new_empty(StatusCode::CREATED).with_header(header::ETAG, etag))
+            }
+            Ok(PutResult::Updated { etag, .. }) => {
+                Ok(HttpResponse::new_empty(StatusCode::NO_CONTENT).with_header(header::ETAG, etag))
+            }
+            Ok(PutResult::UidConflict { name }) => Ok(precondition_failed(
+                StatusCode::CONFLICT,
+                NS_CARDDAV,
+                "no-uid-conflict",
+                Some(&format!(
+                    "{}{}/{}",
+                    ctx.home,
+                    encode_path_element(address_book),
+                    encode_path_element(&name)
+                )),
+            )),
+            Err(err) if matches!(err.as_ref(), trc::EventType::Limit(_)) => {
+                Ok(precondition_failed(
+                    StatusCode::INSUFFICIENT_STORAGE,
+                    NS_DAV,
+                    "quota-not-exceeded",
+                    None,
+                ))
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
+
+impl<'x> DavPath<'x> {
+    pub fn parse(path: &'x str) -> Option<Self> {
+        let mut parts = path
+            .strip_prefix(DAV_ROOT)
+            .or_else(|| (path == DAV_ROOT.trim_end_matches('/')).then_some(""))?
+            .split('/')
+            .filter(|part| !part.is_empty())
+            .map(decode_path_element);
+
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (None, _, _, _) => Some(DavPath::Root),
+            (Some(account), None, _, _) => Some(DavPath::Home { account }),
+            (Some(account), Some(name), None, _) => Some(DavPath::AddressBook { account, name }),
+            (Some(account), Some(address_book), Some(name), None) => Some(DavPath::Card {
+                account,
+                address_book,
+                name,
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn account(&self) -> Option<&str> {
+        match self {
+            DavPath::Root => None,
+            DavPath::Home { account }
+            | DavPath::AddressBook { account, .. }
+            | DavPath::Card { account, .. } => Some(account.as_ref()),
+        }
+    }
+}
+
+pub fn encode_path_element(item: &str) -> Cow<'_, str> {
+    if item
+        .bytes()
+        .all(|ch| ch.is_ascii_alphanumeric() || b"-._~@".contains(&ch))
+    {
+        item.into()
+    } else {
+        let mut encoded = String::with_capacity(item.len() * 3);
+        for ch in item.bytes() {
+            if ch.is_ascii_alphanumeric() || b"-._~@".contains(&ch) {
+                encoded.push(ch as char);
+            } else {
+                let _ = write!(encoded, "%{ch:02X}");
+            }
+        }
+        encoded.into()
+    }
+}
+
+pub fn etag_matches(header: &str, etag: &str) -> bool {
+    header
+        .split(',')
+        .map(|value| value.trim())
+        .any(|value| value == "*" || value == etag || value.trim_start_matches("W/") == etag)
+}
+
+pub fn precondition_failed(
+    status: StatusCode,
+    namespace: &str,
+    condition: &str,
+    href: Option<&str>,
+) -> HttpResponse {
+    HttpResponse::new_text(
+        status,
+        "application/xml; charset=utf-8",
+        dav_error(namespace, condition, href),
+    )
+}
+
+pub fn sync_token(change_id: u64) -> String {
+    format!("{SYNC_TOKEN_PREFIX}{change_id}")
+}
+
+pub fn parse_sync_token(token: &str) -> Option<u64> {
+    token.strip_prefix(SYNC_TOKEN_PREFIX)?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DavPath;
+
+    #[test]
+    fn parse_dav_path() {
+        assert_eq!(DavPath::parse("/dav/card"), Some(DavPath::Root));
+        assert_eq!(DavPath::parse("/dav/card/"), Some(DavPath::Root));
+        assert_eq!(
+            DavPath::parse("/dav/card/jdoe%40example.com/"),
+            Some(DavPath::Home {
+                account: "jdoe@example.com".into()
+            })
+        );
+        assert_eq!(
+            DavPath::parse("/dav/card/jdoe/default/"),
+            Some(DavPath::AddressBook {
+                account: "jdoe".into(),
+                name: "default".into()
+            })
+        );
+        assert_eq!(
+            DavPath::parse("/dav/card/jdoe/default/card%201.vcf"),
+            Some(DavPath::Card {
+                account: "jdoe".into(),
+                address_book: "default".into(),
+                name: "card 1.vcf".into()
+            })
+        );
+        assert_eq!(
+            DavPath::parse("/dav/card/jdoe/default/card.vcf/extra"),
+            None
+        );
+        assert_eq!(DavPath::parse("/dav/cal/jdoe/"), None);
+    }
+}
@@ -0,0 +1,784 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use common::Server;
+use hyper::StatusCode;
+use jmap_proto::types::collection::Collection;
+use quick_xml::escape::escape;
+use store::{
+    ahash::AHashSet,
+    query::log::{Change, Query},
+    roaring::RoaringBitmap,
+};
+use trc::AddContext;
+
+use crate::api::HttpResponse;
+
+use super::{
+    addressbook::{AddressBook, AddressBookStore, ContactCard},
+    encode_path_element, parse_sync_token, precondition_failed, sync_token,
+    vcard::{VCard, VCardProperty},
+    xml::{MultiStatus, PropName, PropValue, XmlElement, NS_CALENDARSERVER, NS_CARDDAV, NS_DAV},
+    DavContext, DavPath, DavRequestHandler, DAV_ROOT, DEFAULT_ADDRESS_BOOK,
+};
+
+pub enum PropRequest {
+    AllProp,
+    PropName,
+    Props(Vec<PropName>),
+}
+
+pub enum DavResource<'x> {
+    Root,
+    Home,
+    AddressBook {
+        book: &'x AddressBook,
+        sync_token: &'x str,
+    },
+    Card {
+        card: &'x ContactCard,
+    },
+}
+
+const ROOT_PROPS: &[(&str, &str)] = &[(NS_DAV, "resourcetype"), (NS_DAV, "current-user-principal")];
+const HOME_PROPS: &[(&str, &str)] = &[
+    (NS_DAV, "resourcetype"),
+    (NS_DAV, "displayname"),
+    (NS_DAV, "current-user-principal"),
+    (NS_DAV, "principal-URL"),
+    (NS_CARDDAV, "addressbook-home-set"),
+];
+const ADDRESS_BOOK_PROPS: &[(&str, &str)] = &[
+    (NS_DAV, "resourcetype"),
+    (NS_DAV, "displayname"),
+    (NS_DAV, "current-user-principal"),
+    (NS_DAV, "owner"),
+    (NS_DAV, "supported-report-set"),
+    (NS_DAV, "sync-token"),
+    (NS_CALENDARSERVER, "getctag"),
+    (NS_CARDDAV, "addressbook-description"),
+    (NS_CARDDAV, "supported-address-data"),
+];
+const CARD_PROPS: &[(&str, &str)] = &[
+    (NS_DAV, "resourcetype"),
+    (NS_DAV, "getetag"),
+    (NS_DAV, "getcontenttype"),
+    (NS_DAV, "getcontentlength"),
+];
+
+pub trait DavReportHandler: Sync + Send {
+    fn handle_dav_propfind(
+        &self,
+        ctx: &DavContext<'_>,
+        path: DavPath<'_>,
+        depth: Option<&str>,
+        xml: Option<&XmlElement>,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+
+    fn handle_dav_proppatch(
+        &self,
+        ctx: &DavContext<'_>,
+        name: &str,
+        xml: &XmlElement,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+
+    fn handle_dav_report(
+        &self,
+        ctx: &DavContext<'_>,
+        name: &str,
+        xml: &XmlElement,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+
+    fn dav_address_books(
+        &self,
+        ctx: &DavContext<'_>,
+    ) -> impl Future<Output = trc::Result<Vec<(u32, AddressBook)>>> + Send;
+}
+
+impl DavReportHandler for Server {
+    async fn handle_dav_propfind(
+        &self,
+        ctx: &DavContext<'_>,
+        path: DavPath<'_>,
+        depth: Option<&str>,
+        xml: Option<&XmlElement>,
+    ) -> trc::Result<HttpResponse> {
+        let request = match xml {
+            Some(xml) if xml.is(NS_DAV, "propfind") => PropRequest::parse(xml),
+            Some(_) => return Ok(HttpResponse::new_empty(StatusCode::BAD_REQUEST)),
+            None => PropRequest::AllProp,
+        };
+        // Infinite depth is treated as depth 1
+        let list_children = depth != Some("0");
+        let mut response = MultiStatus::new();
+
+        match path {
+            DavPath::Root => {
+                ctx.add_response(&mut response, DAV_ROOT, DavResource::Root, &request);
+                if list_children {
+                    ctx.add_response(&mut response, &ctx.home, DavResource::Home, &request);
+                }
+            }
+            DavPath::Home { .. } => {
+                ctx.add_response(&mut response, &ctx.home, DavResource::Home, &request);
+                if list_children {
+                    let sync_token = sync_token(self.contact_sync_state(ctx.account_id).await?);
+                    for (_, book) in self.dav_address_books(ctx).await? {
+                        ctx.add_response(
+                            &mut response,
+                            &ctx.address_book_href(&book.name),
+                            DavResource::AddressBook {
+                                book: &book,
+                                sync_token: &sync_token,
+                            },
+                            &request,
+                        );
+                    }
+                }
+            }
+            DavPath::AddressBook { name, .. } => {
+                let Some((document_id, book)) =
+                    self.address_book_by_name(ctx.account_id, &name).await?
+                else {
+                    return Ok(HttpResponse::new_empty(StatusCode::NOT_FOUND));
+                };
+                let book_href = ctx.address_book_href(&book.name);
+                let sync_token = sync_token(self.contact_sync_state(ctx.account_id).await?);
+                ctx.add_response(
+                    &mut response,
+                    &book_href,
+                    DavResource::AddressBook {
+                        book: &book,
+                        sync_token: &sync_token,
+                    },
+                    &request,
+                );
+                if list_children {
+                    let card_ids = self.contact_card_ids(ctx.account_id, document_id).await?;
+                    for (_, card) in self.contact_cards_get(ctx.account_id, &card_ids).await? {
+                        ctx.add_response(
+                            &mut response,
+                            &ctx.card_href(&book_href, &card.name),
+                            DavResource::Card { card: &card },
+                            &request,
+                        );
+                    }
+                }
+            }
+            DavPath::Card {
+                address_book, name, ..
+            } => {
+                let Some((_, card)) = self.dav_card(ctx, &address_book, &name).await? else {
+                    return Ok(HttpResponse::new_empty(StatusCode::NOT_FOUND));
+                };
+                ctx.add_response(
+                    &mut response,
+                    &ctx.card_href(&ctx.address_book_href(&address_book), &card.name),
+                    DavResource::Card { card: &card },
+                    &request,
+                );
+            }
+        }
+
+        Ok(multi_status(response))
+    }
+
+    async fn handle_dav_proppatch(
+        &self,
+        ctx: &DavContext<'_>,
+        name: &str,
+        xml: &XmlElement,
+    ) -> trc::Result<HttpResponse> {
+        let Some((document_id, mut book)) = self.address_book_by_name(ctx.account_id, name).await?
+        else {
+            return Ok(HttpResponse::new_empty(StatusCode::NOT_FOUND));
+        };
+        if !xml.is(NS_DAV, "propertyupdate") {
+            return Ok(HttpResponse::new_empty(StatusCode::BAD_REQUEST));
+        }
+
+        // Changes are applied atomically, a single unsupported property fails them all
+        let mut updated = Vec::new();
+        let mut failed = Vec::new();
+        for (instruction, is_set) in xml.children.iter().filter_map(|child| {
+            match (child.namespace.as_str(), child.name.as_str()) {
+                (NS_DAV, "set") => Some((child, true)),
+                (NS_DAV, "remove") => Some((child, false)),
+                _ => None,
+            }
+        }) {
+            for prop in instruction
+                .children_named(NS_DAV, "prop")
+                .flat_map(|prop| prop.children.iter())
+            {
+                let value = if is_set {
+                    prop.text.clone()
+                } else {
+                    String::new()
+                };
+                match (prop.namespace.as_str(), prop.name.as_str()) {
+                    (NS_DAV, "displayname") => {
+                        book.display_name = value;
+                    }
+                    (NS_CARDDAV, "addressbook-description") => {
+                        book.description = value;
+                    }
+                    _ => {
+                        failed.push(PropName::from(prop));
+                        continue;
+                    }
+                }
+                updated.push(PropName::from(prop));
+            }
+        }
+
+        let mut response = MultiStatus::new();
+        let href = ctx.address_book_href(name);
+        if failed.is_empty() {
+            self.address_book_update(ctx.account_id, document_id, book)
+                .await?;
+            response.add_propstats(&href, vec![(updated, "200 OK")]);
+        } else {
+            response.add_propstats(
+                &href,
+                vec![
+                    (failed, "403 Forbidden"),
+                    (updated, "424 Failed Dependency"),
+                ],
+            );
+        }
+
+        Ok(multi_status(response))
+    }
+
+    async fn handle_dav_report(
+        &self,
+        ctx: &DavContext<'_>,
+        name: &str,
+        xml: &XmlElement,
+    ) -> trc::Result<HttpResponse> {
+        let Some((document_id, book)) = self.address_book_by_name(ctx.account_id, name).await?
+        else {
+            return Ok(HttpResponse::new_empty(StatusCode::NOT_FOUND));
+        };
+        let book_href = ctx.address_book_href(&book.name);
+        let request = xml
+            .child(NS_DAV, "prop")
+            .map_or(PropRequest::AllProp, |prop| {
+                PropRequest::Props(prop.children.iter().map(PropName::from).collect())
+            });
+        let mut response = MultiStatus::new();
+
+        match (xml.namespace.as_str(), xml.name.as_str()) {
+            (NS_CARDDAV, "addressbook-query") => {
+                let limit = xml
+                    .child(NS_CARDDAV, "limit")
+                    .and_then(|limit| limit.child(NS_CARDDAV, "nresults"))
+                    .and_then(|nresults| nresults.text.trim().parse::<usize>().ok())
+                    .unwrap_or(usize::MAX);
+                let card_ids = self.contact_card_ids(ctx.account_id, document_id).await?;
+                let mut total = 0;
+
+                for (_, card) in self.contact_cards_get(ctx.account_id, &card_ids).await? {
+                    let is_match =
+                        match (xml.child(NS_CARDDAV, "filter"), VCard::parse(&card.vcard)) {
+                            (Some(filter), Ok(vcard)) => match filter_matches(&vcard, filter) {
+                                Ok(is_match) => is_match,
+                                Err(condition) => {
+                                    return Ok(precondition_failed(
+                                        StatusCode::FORBIDDEN,
+                                        NS_CARDDAV,
+                                        condition,
+                                        None,
+                                    ));
+                                }
+                            },
+                            (None, _) => true,
+                            (_, Err(_)) => false,
+                        };
+
+                    if is_match {
+                        if total == limit {
+                            response.add_status(&book_href, "507 Insufficient Storage");
+                            break;
+                        }
+                        ctx.add_response(
+                            &mut response,
+                            &ctx.card_href(&book_href, &card.name),
+                            DavResource::Card { card: &card },
+                            &request,
+                        );
+                        total += 1;
+                    }
+                }
+            }
+            (NS_CARDDAV, "addressbook-multiget") => {
+                for href in xml.children_named(NS_DAV, "href") {
+                    let href = href.text.trim();
+                    let card = match href
+                        .find(DAV_ROOT)
+                        .and_then(|pos| DavPath::parse(&href[pos..]))
+                    {
+                        Some(DavPath::Card {
+                            account,
+                            address_book,
+                            name,
+                        }) if account == ctx.access_token.name && address_book == book.name => {
+                            self.contact_card_by_name(ctx.account_id, document_id, &name)
+                                .await?
+                        }
+                        _ => None,
+                    };
+
+                    if let Some((_, card)) = card {
+                        ctx.add_response(
+                            &mut response,
+                            href,
+                            DavResource::Card { card: &card },
+                            &request,
+                        );
+                    } else {
+                        response.add_status(href, "404 Not Found");
+                    }
+                }
+            }
+            (NS_DAV, "sync-collection") => {
+                let token = xml
+                    .child(NS_DAV, "sync-token")
+                    .map(|token| token.text.trim())
+                    .unwrap_or_default();
+                let current_state = self.contact_sync_state(ctx.account_id).await?;
+                let card_ids = self.contact_card_ids(ctx.account_id, document_id).await?;
+
+                if token.is_empty() {
+                    // Initial sync, list all members
+                    for (_, card) in self.contact_cards_get(ctx.account_id, &card_ids).await? {
+                        ctx.add_response(
+                            &mut response,
+                            &ctx.card_href(&book_href, &card.name),
+                            DavResource::Card { card: &card },
+                            &request,
+                        );
+                    }
+                } else if let Some(since) =
+                    parse_sync_token(token).filter(|since| *since <= current_state)
+                {
+                    let changes = self
+                        .core
+                        .storage
+                        .data
+                        .changes(ctx.account_id, Collection::ContactCard, Query::Since(since))
+                        .await
+                        .caused_by(trc::location!())?;
+
+                    // Document ids can be reused after a deletion, so a deleted
+                    // id may now belong to a different card.
+                    let mut changed_ids = RoaringBitmap::new();
+                    let mut deleted = AHashSet::new();
+                    for change in changes.changes {
+                        let change_id = change.id();
+                        if (change_id >> 32) as u32 != document_id {
+                            continue;
+                        }
+                        let card_id = change_id as u32;
+                        if card_ids.contains(card_id) {
+                            changed_ids.insert(card_id);
+                        }
+                        if matches!(change, Change::Delete(_)) {
+                            deleted.insert(change_id);
+                        }
+                    }
+
+                    let mut changed_names = AHashSet::with_capacity(changed_ids.len() as usize);
+                    for (_, card) in self.contact_cards_get(ctx.account_id, &changed_ids).await? {
+                        ctx.add_response(
+                            &mut response,
+                            &ctx.card_href(&book_href, &card.name),
+                            DavResource::Card { card: &card },
+                            &request,
+                        );
+                        changed_names.insert(card.name);
+                    }
+                    for change_id in deleted {
+                        if let Some(name) = self
+                            .contact_card_tombstone(ctx.account_id, change_id)
+                            .await?
+                            .filter(|name| !changed_names.contains(name))
+                        {
+                            response.add_status(&ctx.card_href(&book_href, &name), "404 Not Found");
+                        }
+                    }
+                } else {
+                    return Ok(precondition_failed(
+                        StatusCode::FORBIDDEN,
+                        NS_DAV,
+                        "valid-sync-token",
+                        None,
+                    ));
+                }
+
+                response.add_sync_token(&sync_token(current_state));
+            }
+            _ => {
+                return Ok(precondition_failed(
+                    StatusCode::FORBIDDEN,
+                    NS_DAV,
+                    "supported-report",
+                    None,
+                ));
+            }
+        }
+
+        Ok(multi_status(response))
+    }
+
+    async fn dav_address_books(
+        &self,
+        ctx: &DavContext<'_>,
+    ) -> trc::Result<Vec<(u32, AddressBook)>> {
+        let mut book_ids = self.address_book_ids(ctx.account_id).await?;
+
+        // Accounts get a default address book on first access
+        if book_ids.is_empty() {
+            book_ids.insert(
+                self.address_book_create(
+                    ctx.account_id,
+                    AddressBook {
+                        name: DEFAULT_ADDRESS_BOOK.to_string(),
+                        display_name: "Contacts".to_string(),
+                        description: String::new(),
+                    },
+                )
+                .await?,
+            );
+        }
+
+        let mut books = Vec::with_capacity(book_ids.len() as usize);
+        for document_id in book_ids {
+            if let Some(book) = self.address_book_get(ctx.account_id, document_id).await? {
+                books.push((document_id, book));
+            }
+        }
+        books.sort_unstable_by(|a, b| a.1.name.cmp(&b.1.name));
+
+        Ok(books)
+    }
+}
+
+impl PropRequest {
+    pub fn parse(xml: &XmlElement) -> Self {
+        if xml.child(NS_DAV, "propname").is_some() {
+            PropRequest::PropName
+        } else if let Some(prop) = xml.child(NS_DAV, "prop") {
+            PropRequest::Props(prop.children.iter().map(PropName::from).collect())
+        } else {
+            PropRequest::AllProp
+        }
+    }
+}
+
+impl DavContext<'_> {
+    pub fn address_book_href(&self, name: &str) -> String {
+        format!("{}{}/", self.home, encode_path_element(name))
+    }
+
+    pub fn card_href(&self, address_book_href: &str, name: &str) -> String {
+        format!("{address_book_href}{}", encode_path_element(name))
+    }
+
+    fn add_response(
+        &self,
+        response: &mut MultiStatus,
+        href: &str,
+        resource: DavResource<'_>,
+        request: &PropRequest,
+    ) {
+        let default_props = match resource {
+            DavResource::Root => ROOT_PROPS,
+            DavResource::Home => HOME_PROPS,
+            DavResource::AddressBook { .. } => ADDRESS_BOOK_PROPS,
+            DavResource::Card { .. } => CARD_PROPS,
+        };
+
+        let mut found = Vec::with_capacity(default_props.len());
+        let mut not_found = Vec::new();
+        match request {
+            PropRequest::PropName => {
+                for (namespace, name) in default_props {
+                    found.push(PropValue::empty(PropName::new(namespace, name)));
+                }
+            }
+            PropRequest::AllProp => {
+                for (namespace, name) in default_props {
+                    if let Some(value) = self.prop_value(&resource, PropName::new(namespace, name))
+                    {
+                        found.push(value);
+                    }
+                }
+            }
+            PropRequest::Props(props) => {
+                for prop in props {
+                    if let Some(value) = self.prop_value(&resource, prop.clone()) {
+                        found.push(value);
+                    } else {
+                        not_found.push(prop.clone());
+                    }
+                }
+            }
+        }
+
+        response.add_response(href, found, not_found);
+    }
+
+    fn prop_value(&self, resource: &DavResource<'_>, name: PropName) -> Option<PropValue> {
+        let home_href = || format!("<D:href>{}</D:href>", escape(&self.home));
+
+        match (resource, name.namespace.as_str(), name.name.as_str()) {
+            (_, NS_DAV, "current-user-principal")
+            | (DavResource::Root | DavResource::Home, NS_DAV, "principal-URL")
+            | (DavResource::Root | DavResource::Home, NS_CARDDAV, "addressbook-home-set")
+            | (DavResource::AddressBook { .. }, NS_DAV, "owner") => {
+                Some(PropValue::xml(name, &home_href()))
+            }
+            (DavResource::Root, NS_DAV, "resourcetype") => {
+                Some(PropValue::xml(name, "<D:collection/>"))
+            }
+            (DavResource::Home, NS_DAV, "resourcetype") => {
+                Some(PropValue::xml(name, "<D:collection/><D:principal/>"))
+            }
+            (DavResource::Home, NS_DAV, "displayname") => {
+                Some(PropValue::text(name, &self.access_token.name))
+            }
+            (DavResource::AddressBook { .. }, NS_DAV, "resourcetype") => {
+                Some(PropValue::xml(name, "<D:collection/><C:addressbook/>"))
+            }
+            (DavResource::AddressBook { book, .. }, NS_DAV, "displayname") => {
+                Some(PropValue::text(name, &book.display_name))
+            }
+            (DavResource::AddressBook { book, .. }, NS_CARDDAV, "addressbook-description") => {
+                Some(PropValue::text(name, &book.description))
+            }
+            (DavResource::AddressBook { .. }, NS_CARDDAV, "supported-address-data") => {
+                Some(PropValue::xml(
+                    name,
+                    concat!(
+                        "<C:address-data-type content-type=\"text/vcard\" version=\"3.0\"/>",
+                        "<C:address-data-type content-type=\"text/vcard\" version=\"4.0\"/>"
+                    ),
+                ))
+            }
+            (DavResource::AddressBook { .. }, NS_DAV, "supported-report-set") => {
+                Some(PropValue::xml(
+                    name,
+                    concat!(
+                        "<D:supported-report><D:report><C:addressbook-query/></D:report></D:supported-report>",
+                        "<D:supported-report><D:report><C:addressbook-multiget/></D:report></D:supported-report>",
+                        "<D:supported-report><D:report><D:sync-collection/></D:report></D:supported-report>"
+                    ),
+                ))
+            }
+            (DavResource::AddressBook { sync_token, .. }, NS_DAV, "sync-token")
+            | (DavResource::AddressBook { sync_token, .. }, NS_CALENDARSERVER, "getctag") => {
+                Some(PropValue::text(name, sync_token))
+            }
+            (DavResource::Card { .. }, NS_DAV, "resourcetype") => Some(PropValue::empty(name)),
+            (DavResource::Card { card }, NS_DAV, "getetag") => {
+                Some(PropValue::text(name, &card.etag()))
+            }
+            (DavResource::Card { .. }, NS_DAV, "getcontenttype") => {
+                Some(PropValue::text(name, "text/vcard; charset=utf-8"))
+            }
+            (DavResource::Card { card }, NS_DAV, "getcontentlength") => {
+                Some(PropValue::text(name, &card.vcard.len().to_string()))
+            }
+            (DavResource::Card { card }, NS_CARDDAV, "address-data") => {
+                Some(PropValue::text(name, &card.vcard))
+            }
+            _ => None,
+        }
+    }
+}
+
+fn multi_status(response: MultiStatus) -> HttpResponse {
+    HttpResponse::new_text(
+        StatusCode::MULTI_STATUS,
+        "application/xml; charset=utf-8",
+        response.finish(),
+    )
+}
+
+// Evaluates an addressbook-query filter (RFC 6352, Section 10.5), errors
+// contain the name of the failed precondition.
+fn filter_matches(card: &VCard, filter: &XmlElement) -> Result<bool, &'static str> {
+    let any_of = is_any_of(filter);
+    let mut is_empty = true;
+
+    for prop_filter in filter.children_named(NS_CARDDAV, "prop-filter") {
+        is_empty = false;
+        let is_match = prop_filter_matches(card, prop_filter)?;
+        if is_match == any_of {
+            return Ok(is_match);
+        }
+    }
+
+    Ok(is_empty || !any_of)
+}
+
+fn prop_filter_matches(card: &VCard, prop_filter: &XmlElement) -> Result<bool, &'static str> {
+    let name = prop_filter.attribute("name").unwrap_or_default();
+    if prop_filter.child(NS_CARDDAV, "is-not-defined").is_some() {
+        return Ok(card.property(name).is_none());
+    }
+
+    let any_of = is_any_of(prop_filter);
+    let tests = prop_filter
+        .children
+        .iter()
+        .filter(|child| {
+            child.namespace == NS_CARDDAV
+                && matches!(child.name.as_str(), "text-match" | "param-filter")
+        })
+        .collect::<Vec<_>>();
+
+    for property in card.properties(name) {
+        let mut is_match = tests.is_empty() || !any_of;
+        for test in &tests {
+            let test_match = if test.name == "text-match" {
+                text_matches(&property.text(), test)?
+            } else {
+                param_filter_matches(property, test)?
+            };
+            if test_match == any_of {
+                is_match = test_match;
+                break;
+            }
+        }
+        if is_match {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+fn param_filter_matches(
+    property: &VCardProperty,
+    param_filter: &XmlElement,
+) -> Result<bool, &'static str> {
+    let name = param_filter.attribute("name").unwrap_or_default();
+    let mut values = property
+        .params
+        .iter()
+        .filter(|(param, _)| param.eq_ignore_ascii_case(name))
+        .flat_map(|(_, value)| value.split(','))
+        .map(|value| value.trim().trim_matches('"'))
+        .peekable();
+
+    if param_filter.child(NS_CARDDAV, "is-not-defined").is_some() {
+        Ok(values.peek().is_none())
+    } else if let Some(text_match) = param_filter.child(NS_CARDDAV, "text-match") {
+        for value in values {
+            if text_matches(value, text_match)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    } else {
+        Ok(values.peek().is_some())
+    }
+}
+
+fn text_matches(value: &str, text_match: &XmlElement) -> Result<bool, &'static str> {
+    let needle = text_match.text.as_str();
+    let (value, needle) = match text_match
+        .attribute("collation")
+        .unwrap_or("i;unicode-casemap")
+    {
+        "i;unicode-casemap" => (value.to_lowercase(), needle.to_lowercase()),
+        "i;ascii-casemap" => (value.to_ascii_lowercase(), needle.to_ascii_lowercase()),
+        "i;octet" => (value.to_string(), needle.to_string()),
+        _ => return Err("supported-collation"),
+    };
+    let is_match = match text_match.attribute("match-type").unwrap_or("contains") {
+        "equals" => value == needle,
+        "contains" => value.contains(&needle),
+        "starts-with" => value.starts_with(&needle),
+        "ends-with" => value.ends_with(&needle),
+        _ => return Err("supported-filter"),
+    };
+    let negate = text_match
+        .attribute("negate-condition")
+        .map_or(false, |negate| negate.eq_ignore_ascii_case("yes"));
+
+    Ok(is_match != negate)
+}
+
+fn is_any_of(filter: &XmlElement) -> bool {
+    filter
+        .attribute("test")
+        .map_or(true, |test| !test.eq_ignore_ascii_case("allof"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dav::{vcard::VCard, xml::XmlElement};
+
+    use super::filter_matches;
+
+    #[test]
+    fn addressbook_query_filter() {
+        let card = VCard::parse(concat!(
+            "BEGIN:VCARD\r\n",
+            "VERSION:4.0\r\n",
+            "FN:John Doe\r\n",
+            "EMAIL;TYPE=work:jdoe@example.com\r\n",
+            "EMAIL;TYPE=home:john@example.org\r\n",
+            "UID:1\r\n",
+            "END:VCARD\r\n"
+        ))
+        .unwrap();
+
+        for (filter, expected) in [
+            (
+                r#"<C:prop-filter name="EMAIL"><C:text-match match-type="ends-with">example.org</C:text-match></C:prop-filter>"#,
+                Ok(true),
+            ),
+            (
+                r#"<C:prop-filter name="EMAIL"><C:text-match negate-condition="yes">example</C:text-match></C:prop-filter>"#,
+                Ok(false),
+            ),
+            (
+                r#"<C:prop-filter name="NICKNAME"><C:is-not-defined/></C:prop-filter>"#,
+                Ok(true),
+            ),
+            (
+                r#"<C:prop-filter name="EMAIL" test="allof"><C:param-filter name="TYPE"><C:text-match match-type="equals">HOME</C:text-match></C:param-filter><C:text-match>john@</C:text-match></C:prop-filter>"#,
+                Ok(true),
+            ),
+            (
+                r#"<C:prop-filter name="EMAIL" test="allof"><C:param-filter name="TYPE"><C:text-match match-type="equals">work</C:text-match></C:param-filter><C:text-match>john@</C:text-match></C:prop-filter>"#,
+                Ok(false),
+            ),
+            (
+                r#"<C:prop-filter name="FN"><C:text-match match-type="starts-with">jane</C:text-match></C:prop-filter><C:prop-filter name="FN"><C:text-match>doe</C:text-match></C:prop-filter>"#,
+                Ok(true),
+            ),
+            (
+                r#"<C:prop-filter name="FN"><C:text-match collation="i;unknown">doe</C:text-match></C:prop-filter>"#,
+                Err("supported-collation"),
+            ),
+        ] {
+            let filter = XmlElement::parse(
+                format!("<C:filter xmlns:C=\"urn:ietf:params:xml:ns:carddav\">{filter}</C:filter>")
+                    .as_bytes(),
+            )
+            .unwrap();
+            assert_eq!(filter_matches(&card, &filter), expected, "{filter:?}");
+        }
+    }
+}
@@ -0,0 +1,360 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::fmt::Write;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VCard {
+    pub version: VCardVersion,
+    pub properties: Vec<VCardProperty>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VCardVersion {
+    V3_0,
+    V4_0,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VCardProperty {
+    pub group: Option<String>,
+    pub name: String,
+    pub params: Vec<(String, String)>,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VCardError {
+    Empty,
+    MultipleCards,
+    MissingBegin,
+    MissingEnd,
+    InvalidLine,
+    UnsupportedVersion,
+    MissingVersion,
+    MissingFn,
+    MissingUid,
+}
+
+const MAX_LINE_LEN: usize = 75;
+
+impl VCard {
+    pub fn parse(text: &str) -> Result<Self, VCardError> {
+        let mut lines = unfold(text).into_iter().filter(|line| !line.is_empty());
+
+        match lines.next() {
+            Some(line) if line.eq_ignore_ascii_case("BEGIN:VCARD") => (),
+            Some(_) => return Err(VCardError::MissingBegin),
+            None => return Err(VCardError::Empty),
+        }
+
+        let mut version = None;
+        let mut properties = Vec::new();
+        let mut has_end = false;
+
+        for line in lines.by_ref() {
+            if line.eq_ignore_ascii_case("END:VCARD") {
+                has_end = true;
+                break;
+            } else if line.eq_ignore_ascii_case("BEGIN:VCARD") {
+                return Err(VCardError::InvalidLine);
+            }
+
+            let property = VCardProperty::parse(&line).ok_or(VCardError::InvalidLine)?;
+            if property.name == "VERSION" {
+                version = match property.value.trim() {
+                    "3.0" => Some(VCardVersion::V3_0),
+                    "4.0" => Some(VCardVersion::V4_0),
+                    _ => return Err(VCardError::UnsupportedVersion),
+                };
+            } else {
+                properties.push(property);
+            }
+        }
+
+        if !has_end {
+            return Err(VCardError::MissingEnd);
+        } else if lines.next().is_some() {
+            return Err(VCardError::MultipleCards);
+        }
+
+        let card = VCard {
+            version: version.ok_or(VCardError::MissingVersion)?,
+            properties,
+        };
+
+        if card.property("FN").is_none() {
+            Err(VCardError::MissingFn)
+        } else if card.uid().is_none() {
+            Err(VCardError::MissingUid)
+        } else {
+            Ok(card)
+        }
+    }
+
+    pub fn property(&self, name: &str) -> Option<&VCardProperty> {
+        self.properties
+            .iter()
+            .find(|p| p.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn properties<'x>(&'x self, name: &'x str) -> impl Iterator<Item = &'x VCardProperty> {
+        self.properties
+            .iter()
+            .filter(move |p| p.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn uid(&self) -> Option<&str> {
+        self.property("UID")
+            .map(|p| p.value.trim())
+            .filter(|uid| !uid.is_empty())
+    }
+
+    // Serializes the card using CRLF line endings, upper case property
+    // names and lines folded at 75 octets.
+    pub fn normalize(&self) -> String {
+        let mut out = String::with_capacity(128 + self.properties.len() * 32);
+        out.push_str("BEGIN:VCARD\r\n");
+        let _ = write!(
+            &mut out,
+            "VERSION:{}\r\n",
+            match self.version {
+                VCardVersion::V3_0 => "3.0",
+                VCardVersion::V4_0 => "4.0",
+            }
+        );
+        for property in &self.properties {
+            let mut line = String::with_capacity(property.name.len() + property.value.len() + 8);
+            if let Some(group) = &property.group {
+                line.push_str(group);
+                line.push('.');
+            }
+            line.push_str(&property.name);
+            for (name, value) in &property.params {
+                line.push(';');
+                line.push_str(name);
+                if !value.is_empty() {
+                    line.push('=');
+                    line.push_str(value);
+                }
+            }
+            line.push(':');
+            line.push_str(&property.value);
+            fold(&line, &mut out);
+        }
+        out.push_str("END:VCARD\r\n");
+        out
+    }
+}
+
+impl VCardProperty {
+    fn parse(line: &str) -> Option<Self> {
+        // Find the value separator, skipping quoted parameter values
+        let mut in_quotes = false;
+        let mut value_pos = None;
+        for (pos, ch) in line.char_indices() {
+            match ch {
+                '"' => in_quotes = !in_quotes,
+                ':' if !in_quotes => {
+                    value_pos = Some(pos);
+                    break;
+                }
+                _ => (),
+            }
+        }
+        let value_pos = value_pos?;
+        let (head, value) = (&line[..value_pos], &line[value_pos + 1..]);
+
+        let mut parts = head.split(';');
+        let name = parts.next()?.trim();
+        let (group, name) = match name.rsplit_once('.') {
+            Some((group, name)) => (Some(group.to_string()), name),
+            None => (None, name),
+        };
+        if name.is_empty()
+            || !name
+                .bytes()
+                .all(|ch| ch.is_ascii_alphanumeric() || ch == b'-')
+        {
+            return None;
+        }
+
+        let params = parts
+            .filter(|param| !param.is_empty())
+            .map(|param| match param.split_once('=') {
+                Some((name, value)) => (name.trim().to_ascii_uppercase(), value.to_string()),
+                None => ("TYPE".to_string(), param.trim().to_string()),
+            })
+            .collect();
+
+        Some(VCardProperty {
+            group,
+            name: name.to_ascii_uppercase(),
+            params,
+            value: value.to_string(),
+        })
+    }
+
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(param, _)| param.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    // Returns the value with vCard escape sequences removed
+    pub fn text(&self) -> String {
+        let mut text = String::with_capacity(self.value.len());
+        let mut chars = self.value.chars();
+        while let Some(ch) = chars.next() {
+            if ch == '\\' {
+                match chars.next() {
+                    Some('n' | 'N') => text.push('\n'),
+                    Some(ch) => text.push(ch),
+                    None => (),
+                }
+            } else {
+                text.push(ch);
+            }
+        }
+        text
+    }
+}
+
+fn unfold(text: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.split('\n') {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(continuation), Some(last)) => last.push_str(continuation),
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines
+}
+
+fn fold(line: &str, out: &mut String) {
+    let mut line_len = 0;
+    for ch in line.chars() {
+        let ch_len = ch.len_utf8();
+        if line_len + ch_len > MAX_LINE_LEN {
+            out.push_str("\r\n ");
+            line_len = 1;
+        }
+        out.push(ch);
+        line_len += ch_len;
+    }
+    out.push_str("\r\n");
+}
+
+impl VCardError {
+    pub fn description(&self) -> &'static str {
+        match self {
+            VCardError::Empty => "vCard is empty",
+            VCardError::MultipleCards => "Only one vCard per resource is allowed",
+            VCardError::MissingBegin => "Missing BEGIN:VCARD",
+            VCardError::MissingEnd => "Missing END:VCARD",
+            VCardError::InvalidLine => "Invalid vCard property line",
+            VCardError::UnsupportedVersion => "Only vCard versions 3.0 and 4.0 are supported",
+            VCardError::MissingVersion => "Missing VERSION property",
+            VCardError::MissingFn => "Missing FN property",
+            VCardError::MissingUid => "Missing UID property",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{VCard, VCardError, VCardVersion};
+
+    #[test]
+    fn parse_vcard() {
+        let card = VCard::parse(concat!(
+            "begin:vcard\n",
+            "version:3.0\n",
+            "fn:John Doe\n",
+            "item1.EMAIL;type=INTERNET;TYPE=\"work,pref\":jdoe@example.com\n",
+            "NOTE:This is a long note that has been folded\n",
+            "  by the client\n",
+            "UID:abc-123\n",
+            "END:VCARD\n",
+        ))
+        .unwrap();
+
+        assert_eq!(card.version, VCardVersion::V3_0);
+        assert_eq!(card.uid(), Some("abc-123"));
+        let email = card.property("email").unwrap();
+        assert_eq!(email.group.as_deref(), Some("item1"));
+        assert_eq!(email.param("type"), Some("INTERNET"));
+        assert_eq!(email.value, "jdoe@example.com");
+        assert_eq!(
+            card.property("NOTE").unwrap().value,
+            "This is a long note that has been folded by the client"
+        );
+        assert_eq!(
+            card.normalize(),
+            concat!(
+                "BEGIN:VCARD\r\n",
+                "VERSION:3.0\r\n",
+                "FN:John Doe\r\n",
+                "item1.EMAIL;TYPE=INTERNET;TYPE=\"work,pref\":jdoe@example.com\r\n",
+                "NOTE:This is a long note that has been folded by the client\r\n",
+                "UID:abc-123\r\n",
+                "END:VCARD\r\n",
+            )
+        );
+        assert_eq!(VCard::parse(&card.normalize()).unwrap(), card);
+    }
+
+    #[test]
+    fn invalid_vcard() {
+        for (text, err) in [
+            ("", VCardError::Empty),
+            ("FN:John\r\n", VCardError::MissingBegin),
+            (
+                "BEGIN:VCARD\r\nVERSION:4.0\r\nFN:John\r\nUID:1\r\n",
+                VCardError::MissingEnd,
+            ),
+            (
+                "BEGIN:VCARD\r\nVERSION:2.1\r\nFN:John\r\nUID:1\r\nEND:VCARD\r\n",
+                VCardError::UnsupportedVersion,
+            ),
+            (
+                "BEGIN:VCARD\r\nFN:John\r\nUID:1\r\nEND:VCARD\r\n",
+                VCardError::MissingVersion,
+            ),
+            (
+                "BEGIN:VCARD\r\nVERSION:4.0\r\nUID:1\r\nEND:VCARD\r\n",
+                VCardError::MissingFn,
+            ),
+            (
+                "BEGIN:VCARD\r\nVERSION:4.0\r\nFN:John\r\nEND:VCARD\r\n",
+                VCardError::MissingUid,
+            ),
+            (
+                concat!(
+                    "BEGIN:VCARD\r\nVERSION:4.0\r\nFN:John\r\nUID:1\r\nEND:VCARD\r\n",
+                    "BEGIN:VCARD\r\nVERSION:4.0\r\nFN:Jane\r\nUID:2\r\nEND:VCARD\r\n"
+                ),
+                VCardError::MultipleCards,
+            ),
+        ] {
+            assert_eq!(VCard::parse(text), Err(err), "{text}");
+        }
+    }
+
+    #[test]
+    fn fold_long_lines() {
+        let card = VCard::parse(&format!(
+            "BEGIN:VCARD\r\nVERSION:4.0\r\nFN:{}\r\nUID:1\r\nEND:VCARD\r\n",
+            "x".repeat(200)
+        ))
+        .unwrap();
+        let normalized = card.normalize();
+        assert!(normalized.split("\r\n").all(|line| line.len() <= 75));
+        assert_eq!(VCard::parse(&normalized).unwrap(), card);
+    }
+}
@@ -0,0 +1,355 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::fmt::Write;
+
+use quick_xml::{
+    escape::{escape, partial_escape},
+    events::Event,
+    name::ResolveResult,
+    NsReader,
+};
+
+pub const NS_DAV: &str = "DAV:";
+pub const NS_CARDDAV: &str = "urn:ietf:params:xml:ns:carddav";
+pub const NS_CALENDARSERVER: &str = "http://calendarserver.org/ns/";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct XmlElement {
+    pub namespace: String,
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<XmlElement>,
+    pub text: String,
+}
+
+impl XmlElement {
+    pub fn parse(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = NsReader::from_reader(bytes);
+        reader.config_mut().trim_text(true);
+        let mut buf = Vec::with_capacity(128);
+        let mut stack: Vec<XmlElement> = Vec::new();
+
+        loop {
+            let (ns, event) = match reader.read_resolved_event_into(&mut buf) {
+                Ok(result) => result,
+                Err(err) => {
+                    return Err(format!(
+                        "Error at position {}: {err}",
+                        reader.buffer_position()
+                    ))
+                }
+            };
+            let namespace = match ns {
+                ResolveResult::Bound(ns) => String::from_utf8_lossy(ns.as_ref()).into_owned(),
+                _ => String::new(),
+            };
+
+            match event {
+                Event::Start(ref tag) | Event::Empty(ref tag) => {
+                    let mut element = XmlElement {
+                        namespace,
+                        name: String::from_utf8_lossy(tag.local_name().as_ref()).into_owned(),
+                        ..Default::default()
+                    };
+                    for attr in tag.attributes().flatten() {
+                        if let Ok(value) = attr.unescape_value() {
+                            element.attributes.push((
+                                String::from_utf8_lossy(attr.key.local_name().as_ref())
+                                    .into_owned(),
+                                value.into_owned(),
+                            ));
+                        }
+                    }
+
+                    // Empty elements are closed right away
+                    if matches!(event, Event::Empty(_)) {
+                        match stack.last_mut() {
+                            Some(parent) => parent.children.push(element),
+                            None => return Ok(element),
+                        }
+                    } else {
+                        stack.push(element);
+                    }
+                }
+                Event::End(_) => {
+                    let element = stack.pop().ok_or("Unexpected closing tag")?;
+                    match stack.last_mut() {
+                        Some(parent) => parent.children.push(element),
+                        None => return Ok(element),
+                    }
+                }
+                Event::Text(text) => {
+                    if let Some(element) = stack.last_mut() {
+                        element
+                            .text
+                            .push_str(&text.unescape().map_err(|err| err.to_string())?);
+                    }
+                }
+                Event::CData(text) => {
+                    if let Some(element) = stack.last_mut() {
+                        element
+                            .text
+                            .push_str(&String::from_utf8_lossy(text.as_ref()));
+                    }
+                }
+                Event::Eof => return Err("Unexpected end of document".to_string()),
+                _ => (),
+            }
+            buf.clear();
+        }
+    }
+
+    pub fn is(&self, namespace: &str, name: &str) -> bool {
+        self.name == name && self.namespace == namespace
+    }
+
+    pub fn child(&self, namespace: &str, name: &str) -> Option<&XmlElement> {
+        self.children.iter().find(|child| child.is(namespace, name))
+    }
+
+    pub fn children_named<'x>(
+        &'x self,
+        namespace: &'x str,
+        name: &'x str,
+    ) -> impl Iterator<Item = &'x XmlElement> + 'x {
+        self.children
+            .iter()
+            .filter(move |child| child.is(namespace, name))
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(attr, _)| attr.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+pub struct MultiStatus {
+    buf: String,
+}
+
+// A property name as requested by the client
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PropName {
+    pub namespace: String,
+    pub name: String,
+}
+
+impl PropName {
+    pub fn new(namespace: &str, name: &str) -> Self {
+        PropName {
+            namespace: namespace.to_string(),
+            name: name.to_string(),
+        }
+    }
+
+    pub fn is(&self, namespace: &str, name: &str) -> bool {
+        self.name == name && self.namespace == namespace
+    }
+
+    fn write_empty(&self, buf: &mut String) {
+        match self.namespace.as_str() {
+            NS_DAV => {
+                let _ = write!(buf, "<D:{}/>", self.name);
+            }
+            NS_CARDDAV => {
+                let _ = write!(buf, "<C:{}/>", self.name);
+            }
+            NS_CALENDARSERVER => {
+                let _ = write!(buf, "<CS:{}/>", self.name);
+            }
+            namespace => {
+                let _ = write!(buf, "<X:{} xmlns:X=\"{}\"/>", self.name, escape(namespace));
+            }
+        }
+    }
+}
+
+impl From<&XmlElement> for PropName {
+    fn from(element: &XmlElement) -> Self {
+        PropName {
+            namespace: element.namespace.clone(),
+            name: element.name.clone(),
+        }
+    }
+}
+
+// Serialized property value, already namespaced using the prefixes
+// declared by MultiStatus.
+pub struct PropValue {
+    pub name: PropName,
+    pub value: String,
+}
+
+impl PropValue {
+    pub fn empty(name: PropName) -> Self {
+        PropValue {
+            name,
+            value: String::new(),
+        }
+    }
+
+    pub fn text(name: PropName, text: &str) -> Self {
+        let prefix = prefix(&name.namespace);
+        PropValue {
+            value: format!(
+                "<{prefix}:{0}>{1}</{prefix}:{0}>",
+                name.name,
+                partial_escape(text)
+            ),
+            name,
+        }
+    }
+
+    pub fn xml(name: PropName, xml: &str) -> Self {
+        let prefix = prefix(&name.namespace);
+        PropValue {
+            value: format!("<{prefix}:{0}>{xml}</{prefix}:{0}>", name.name),
+            name,
+        }
+    }
+}
+
+fn prefix(namespace: &str) -> &'static str {
+    match namespace {
+        NS_CARDDAV => "C",
+        NS_CALENDARSERVER => "CS",
+        _ => "D",
+    }
+}
+
+impl MultiStatus {
+    pub fn new() -> Self {
+        let mut buf = String::with_capacity(1024);
+        buf.push_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+        let _ = write!(
+            buf,
+            "<D:multistatus xmlns:D=\"{NS_DAV}\" xmlns:C=\"{NS_CARDDAV}\" xmlns:CS=\"{NS_CALENDARSERVER}\">"
+        );
+        MultiStatus { buf }
+    }
+
+    pub fn add_response(&mut self, href: &str, found: Vec<PropValue>, not_found: Vec<PropName>) {
+        self.buf.push_str("<D:response>");
+        let _ = write!(self.buf, "<D:href>{}</D:href>", escape(href));
+        if !found.is_empty() || not_found.is_empty() {
+            self.buf.push_str("<D:propstat><D:prop>");
+            for prop in found {
+                if prop.value.is_empty() {
+                    prop.name.write_empty(&mut self.buf);
+                } else {
+                    self.buf.push_str(&prop.value);
+                }
+            }
+            self.buf
+                .push_str("</D:prop><D:status>HTTP/1.1 200 OK</D:status></D:propstat>");
+        }
+        if !not_found.is_empty() {
+            self.buf.push_str("<D:propstat><D:prop>");
+            for prop in not_found {
+                prop.write_empty(&mut self.buf);
+            }
+            self.buf
+                .push_str("</D:prop><D:status>HTTP/1.1 404 Not Found</D:status></D:propstat>");
+        }
+        self.buf.push_str("</D:response>");
+    }
+
+    pub fn add_propstats(&mut self, href: &str, propstats: Vec<(Vec<PropName>, &str)>) {
+        self.buf.push_str("<D:response>");
+        let _ = write!(self.buf, "<D:href>{}</D:href>", escape(href));
+        for (props, status) in propstats {
+            if !props.is_empty() {
+                self.buf.push_str("<D:propstat><D:prop>");
+                for prop in props {
+                    prop.write_empty(&mut self.buf);
+                }
+                let _ = write!(
+                    self.buf,
+                    "</D:prop><D:status>HTTP/1.1 {status}</D:status></D:propstat>"
+                );
+            }
+        }
+        self.buf.push_str("</D:response>");
+    }
+
+    pub fn add_status(&mut self, href: &str, status: &str) {
+        let _ = write!(
+            self.buf,
+            "<D:response><D:href>{}</D:href><D:status>HTTP/1.1 {status}</D:status></D:response>",
+            escape(href)
+        );
+    }
+
+    pub fn add_sync_token(&mut self, sync_token: &str) {
+        let _ = write!(
+            self.buf,
+            "<D:sync-token>{}</D:sync-token>",
+            escape(sync_token)
+        );
+    }
+
+    pub fn finish(mut self) -> String {
+        self.buf.push_str("</D:multistatus>");
+        self.buf
+    }
+}
+
+impl Default for MultiStatus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn dav_error(namespace: &str, condition: &str, href: Option<&str>) -> String {
+    let prefix = prefix(namespace);
+    let mut buf = String::with_capacity(256);
+    buf.push_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    let _ = write!(
+        buf,
+        "<D:error xmlns:D=\"{NS_DAV}\" xmlns:C=\"{NS_CARDDAV}\"><{prefix}:{condition}>"
+    );
+    if let Some(href) = href {
+        let _ = write!(buf, "<D:href>{}</D:href>", escape(href));
+    }
+    let _ = write!(buf, "</{prefix}:{condition}></D:error>");
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{XmlElement, NS_CARDDAV, NS_DAV};
+
+    #[test]
+    fn parse_propfind() {
+        let root = XmlElement::parse(
+            br#"<?xml version="1.0" encoding="utf-8" ?>
+            <D:propfind xmlns:D="DAV:" xmlns:C="urn:ietf:params:xml:ns:carddav">
+                <D:prop>
+                    <D:getetag/>
+                    <C:address-data>
+                        <C:prop name="EMAIL"/>
+                    </C:address-data>
+                    <X:custom xmlns:X="http://example.com/ns/">value &amp; more</X:custom>
+                </D:prop>
+            </D:propfind>"#,
+        )
+        .unwrap();
+
+        assert!(root.is(NS_DAV, "propfind"));
+        let prop = root.child(NS_DAV, "prop").unwrap();
+        assert_eq!(prop.children.len(), 3);
+        assert!(prop.children[0].is(NS_DAV, "getetag"));
+        assert_eq!(
+            prop.child(NS_CARDDAV, "address-data").unwrap().children[0].attribute("name"),
+            Some("EMAIL")
+        );
+        assert!(prop.children[2].is("http://example.com/ns/", "custom"));
+        assert_eq!(prop.children[2].text, "value & more");
+    }
+}
@@ -0,0 +1,399 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{collections::BTreeMap, future::Future};
+
+use common::{config::jmap::settings::SpecialUse, Server};
+use jmap_proto::{
+    object::{index::ObjectIndexBuilder, Object},
+    types::{
+        collection::Collection, id::Id, property::Property, state::StateChange,
+        type_state::DataType, value::Value,
+    },
+};
+use mail_parser::DateTime;
+use rand::prelude::SliceRandom;
+use store::{
+    ahash::AHashMap,
+    write::{assert::HashedValue, now, BatchBuilder, TagValue, F_VALUE},
+};
+use trc::AddContext;
+
+use crate::{
+    changes::write::ChangeLog,
+    mailbox::{
+        set::{MailboxSet, SCHEMA},
+        UidMailbox,
+    },
+    services::state::StateManager,
+    JmapMethods,
+};
+
+use super::{ingest::EmailIngest, retention::MailboxPaths, set::TagManager};
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchiveReport {
+    pub dry_run: bool,
+    pub total: u64,
+    pub folders: AHashMap<String, u64>,
+}
+
+pub trait EmailArchive: Sync + Send {
+    fn archive_accounts(&self) -> impl Future<Output = ()> + Send;
+
+    fn emails_archive(
+        &self,
+        account_id: u32,
+        dry_run: bool,
+    ) -> impl Future<Output = trc::Result<ArchiveReport>> + Send;
+}
+
+impl EmailArchive for Server {
+    async fn archive_accounts(&self) {
+        let dry_run = if let Some(config) = &self.core.jmap.mail_archive {
+            config.dry_run
+        } else {
+            return;
+        };
+
+        if let Ok(Some(account_ids)) = self.get_document_ids(u32::MAX, Collection::Principal).await
+        {
+            let mut account_ids: Vec<u32> = account_ids.into_iter().collect();
+
+            // Shuffle account ids
+            account_ids.shuffle(&mut rand::thread_rng());
+
+            for account_id in account_ids {
+                if let Err(err) = self.emails_archive(account_id, dry_run).await {
+                    trc::error!(err
+                        .details("Failed to archive messages.")
+                        .account_id(account_id));
+                }
+            }
+        }
+    }
+
+    async fn emails_archive(&self, account_id: u32, dry_run: bool) -> trc::Result<ArchiveReport> {
+        let mut report = ArchiveReport {
+            dry_run,
+            ..Default::default()
+        };
+        let config = if let Some(config) = &self.core.jmap.mail_archive {
+            config
+        } else {
+            return Ok(report);
+        };
+        if self
+            .get_document_ids(account_id, Collection::Email)
+            .await?
+            .map_or(true, |ids| ids.is_empty())
+        {
+            return Ok(report);
+        }
+        if config.is_opted_out(
+            &self
+                .get_cached_access_token(account_id)
+                .await
+                .caused_by(trc::location!())?
+                .name,
+        ) {
+            return Ok(report);
+        }
+
+        // Messages are archived under the mailbox with the archive role, or
+        // under the configured folder if no such mailbox exists
+        let mailboxes = self.mailbox_paths(account_id).await?;
+        let archive_root = mailboxes
+            .iter()
+            .find(|(_, role, _)| *role == Some(SpecialUse::Archive))
+            .or_else(|| {
+                mailboxes
+                    .iter()
+                    .find(|(_, _, path)| path.eq_ignore_ascii_case(&config.folder))
+            })
+            .map(|(mailbox_id, _, path)| (*mailbox_id, path.clone()));
+        let archive_path = archive_root
+            .as_ref()
+            .map_or_else(|| config.folder.clone(), |(_, path)| path.clone());
+        let archive_prefix = format!("{}/", archive_path.to_lowercase());
+
+        // Find the messages older than the archive period, grouped by year
+        let reference_date = now().saturating_sub(config.max_age.as_secs());
+        let mut messages: AHashMap<u32, (u16, Vec<u32>)> = AHashMap::new();
+        for (mailbox_id, role, path) in &mailboxes {
+            if archive_root
+                .as_ref()
+                .map_or(false, |(root_id, _)| root_id == mailbox_id)
+                || path.to_lowercase().starts_with(&archive_prefix)
+                || !config
+                    .mailboxes
+                    .iter()
+                    .any(|selector| selector.matches(*role, path))
+            {
+                continue;
+            }
+
+            let document_ids = self
+                .get_tag(
+                    account_id,
+                    Collection::Email,
+                    Property::MailboxIds,
+                    TagValue::Id(*mailbox_id),
+                )
+                .await?
+                .unwrap_or_default();
+            if document_ids.is_empty() {
+                continue;
+            }
+
+            for (document_id, received_at) in self
+                .get_properties::<u64, _, _>(
+                    account_id,
+                    Collection::Email,
+                    &document_ids,
+                    Property::ReceivedAt,
+                )
+                .await?
+            {
+                if received_at < reference_date {
+                    messages
+                        .entry(document_id)
+                        .or_insert_with(|| {
+                            (DateTime::from_timestamp(received_at as i64).year, vec![])
+                        })
+                        .1
+                        .push(*mailbox_id);
+                }
+            }
+        }
+        if messages.is_empty() {
+            return Ok(report);
+        }
+        let mut years: BTreeMap<u16, Vec<(u32, Vec<u32>)>> = BTreeMap::new();
+        for (document_id, (year, mailbox_ids)) in messages {
+            years
+                .entry(year)
+                .or_default()
+                .push((document_id, mailbox_ids));
+        }
+
+        if dry_run {
+            for (year, messages) in &years {
+                report
+                    .folders
+                    .insert(format!("{archive_path}/{year}"), messages.len() as u64);
+                report.total += messages.len() as u64;
+            }
+        } else {
+            // Create the archive folder if missing
+            let mut mailbox_change_id = None;
+            if archive_root.is_none() {
+                mailbox_change_id = self
+                    .archive_create_root(account_id, &archive_path)
+                    .await?
+                    .into();
+            }
+
+            let mut email_change_id = None;
+            for (year, messages) in years {
+                // Obtain the yearly archive folder, creating it if needed
+                let folder = format!("{archive_path}/{year}");
+                let dest_mailbox_id = match self.mailbox_create_path(account_id, &folder).await? {
+                    Some((mailbox_id, change_id)) => {
+                        if change_id.is_some() {
+                            mailbox_change_id = change_id;
+                        }
+                        mailbox_id
+                    }
+                    None => {
+                        return Err(trc::StoreEvent::UnexpectedError
+                            .into_err()
+                            .caused_by(trc::location!())
+                            .details("Failed to create archive folder.")
+                            .ctx(trc::Key::Path, folder));
+                    }
+                };
+
+                // Move messages in batches, new UIDs are assigned in the archive folder
+                // and the moves are logged in the same way as an IMAP MOVE
+                let mut total = 0;
+                for messages in messages.chunks(config.batch_size) {
+                    let mut changes = self.begin_changes(account_id).await?;
+                    let mut batch = BatchBuilder::new();
+                    batch
+                        .with_account_id(account_id)
+                        .with_collection(Collection::Email);
+
+                    for (document_id, src_mailbox_ids) in messages {
+                        let (mut mailboxes, thread_id) = if let (Some(mailboxes), Some(thread_id)) = (
+                            self.get_property::<HashedValue<Vec<UidMailbox>>>(
+                                account_id,
+                                Collection::Email,
+                                *document_id,
+                                Property::MailboxIds,
+                            )
+                            .await?,
+                            self.get_property::<u32>(
+                                account_id,
+                                Collection::Email,
+                                *document_id,
+                                Property::ThreadId,
+                            )
+                            .await?,
+                        ) {
+                            (TagManager::new(mailboxes), thread_id)
+                        } else {
+                            continue;
+                        };
+
+                        // Make sure the message still belongs to the source mailboxes
+                        let mut has_changes = false;
+                        for src_mailbox_id in src_mailbox_ids {
+                            let src_mailbox_id = UidMailbox::new_unassigned(*src_mailbox_id);
+                            if mailboxes.current().contains(&src_mailbox_id) {
+                                mailboxes.update(src_mailbox_id, false);
+                                changes.log_child_update(
+                                    Collection::Mailbox,
+                                    src_mailbox_id.mailbox_id,
+                                );
+                                has_changes = true;
+                            }
+                        }
+                        if !has_changes {
+                            continue;
+                        }
+                        let dest_mailbox_id = UidMailbox::new_unassigned(dest_mailbox_id);
+                        if !mailboxes.current().contains(&dest_mailbox_id) {
+                            mailboxes.update(dest_mailbox_id, true);
+                        }
+
+                        // Assign IMAP UIDs
+                        for uid_mailbox in mailboxes.inner_tags_mut() {
+                            if uid_mailbox.uid == 0 {
+                                uid_mailbox.uid = self
+                                    .assign_imap_uid(account_id, uid_mailbox.mailbox_id)
+                                    .await
+                                    .caused_by(trc::location!())?;
+                            }
+                        }
+
+                        batch.update_document(*document_id);
+                        mailboxes.update_batch(&mut batch, Property::MailboxIds);
+                        batch.value(Property::Cid, changes.change_id, F_VALUE);
+                        changes
+                            .log_update(Collection::Email, Id::from_parts(thread_id, *document_id));
+                        changes.log_child_update(Collection::Mailbox, dest_mailbox_id.mailbox_id);
+                        total += 1;
+                    }
+
+                    if !changes.is_empty() {
+                        let change_id = changes.change_id;
+                        batch.custom(changes);
+                        self.write_batch(batch).await.caused_by(trc::location!())?;
+                        email_change_id = Some(change_id);
+                    }
+                }
+
+                if total > 0 {
+                    report.folders.insert(folder, total);
+                    report.total += total;
+                }
+            }
+
+            // Broadcast changes
+            if let Some(change_id) = email_change_id.max(mailbox_change_id) {
+                let mut state_change =
+                    StateChange::new(account_id).with_change(DataType::Mailbox, change_id);
+                if let Some(change_id) = email_change_id {
+                    state_change = state_change.with_change(DataType::Email, change_id);
+                }
+                self.broadcast_state_change(state_change).await;
+            }
+        }
+
+        trc::event!(
+            Housekeeper(trc::HousekeeperEvent::ArchiveMessages),
+            AccountId = account_id,
+            Total = report.total,
+            Details = report
+                .folders
+                .keys()
+                .map(|folder| folder.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+            Result = if dry_run { "dry-run" } else { "moved" },
+        );
+
+        Ok(report)
+    }
+}
+
+trait ArchiveRoot {
+    fn archive_create_root(
+        &self,
+        account_id: u32,
+        path: &str,
+    ) -> impl Future<Output = trc::Result<u64>> + Send;
+}
+
+impl ArchiveRoot for Server {
+    // Creates the archive folder with the archive role, returns the change id
+    async fn archive_create_root(&self, account_id: u32, path: &str) -> trc::Result<u64> {
+        let (parent_id, name) = match path.rsplit_once('/') {
+            Some((parent_path, name)) => (
+                self.mailbox_create_path(account_id, parent_path)
+                    .await?
+                    .ok_or_else(|| {
+                        trc::StoreEvent::UnexpectedError
+                            .into_err()
+                            .caused_by(trc::location!())
+                            .details("Failed to create archive folder.")
+                            .ctx(trc::Key::Path, parent_path.to_string())
+                    })?
+                    .0
+                    + 1,
+                name,
+            ),
+            None => (0, path),
+        };
+
+        let mut changes = self.begin_changes(account_id).await?;
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::Mailbox)
+            .create_document()
+            .custom(
+                ObjectIndexBuilder::new(SCHEMA).with_changes(
+                    Object::with_capacity(5)
+                        .with_property(Property::Name, name.to_string())
+                        .with_property(Property::ParentId, Value::Id(Id::from(parent_id)))
+                        .with_property(Property::Role, "archive")
+                        .with_property(
+                            Property::IsSubscribed,
+                            Value::List(vec![Value::Id(account_id.into())]),
+                        )
+                        .with_property(
+                            Property::Cid,
+                            Value::UnsignedInt(rand::random::<u32>() as u64),
+                        ),
+                ),
+            );
+        let document_id = self.write_batch_expect_id(batch).await?;
+        changes.log_insert(Collection::Mailbox, document_id);
+        let change_id = changes.change_id;
+
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::Mailbox)
+            .custom(changes);
+        self.write_batch(batch).await?;
+
+        Ok(change_id)
+    }
+}
@@ -0,0 +1,457 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{
+    fs::File,
+    future::Future,
+    io::{self, BufRead, BufReader},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use common::{auth::ResourceToken, ImportFormat, ImportJob, Server};
+use jmap_proto::types::{collection::Collection, keyword::Keyword, property::Property};
+use mail_parser::{DateTime, MessageParser};
+use store::{ahash::AHashMap, query::Filter, write::now};
+use trc::AddContext;
+
+use crate::mailbox::{set::MailboxSet, INBOX_ID};
+
+use super::ingest::{EmailIngest, IngestEmail, IngestSource};
+
+// Number of messages imported before pausing, so imports don't starve live traffic
+const IMPORT_BATCH_SIZE: u64 = 100;
+const IMPORT_BATCH_PAUSE: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ImportMessage {
+    pub contents: Vec<u8>,
+    pub keywords: Vec<Keyword>,
+    pub received_at: Option<u64>,
+}
+
+pub trait EmailBulkImport: Sync + Send {
+    fn email_bulk_import(
+        &self,
+        job_id: u64,
+        account_id: u32,
+        format: ImportFormat,
+        path: &Path,
+        mailbox: Option<&str>,
+    ) -> impl Future<Output = trc::Result<ImportJob>> + Send;
+}
+
+impl EmailBulkImport for Server {
+    async fn email_bulk_import(
+        &self,
+        job_id: u64,
+        account_id: u32,
+        format: ImportFormat,
+        path: &Path,
+        mailbox: Option<&str>,
+    ) -> trc::Result<ImportJob> {
+        let mut job = ImportJob {
+            account_id,
+            format,
+            started: now(),
+            finished: 0,
+            imported: 0,
+            duplicates: 0,
+            failed: 0,
+            mailboxes: 0,
+            error: None,
+        };
+
+        // Build the list of folders to import, Maildir subfolders are mapped to mailboxes
+        let folders = match format {
+            ImportFormat::Mbox => vec![(
+                mailbox.unwrap_or("INBOX").to_string(),
+                vec![path.to_path_buf()],
+            )],
+            ImportFormat::Maildir => maildir_folders(path)
+                .map_err(|err| import_error(err, path))?
+                .into_iter()
+                .map(|(name, path)| {
+                    let name = match mailbox {
+                        Some(mailbox) if name == "INBOX" => mailbox.to_string(),
+                        Some(mailbox) => format!("{mailbox}/{name}"),
+                        None => name,
+                    };
+                    maildir_messages(&path).map(|messages| (name, messages))
+                })
+                .collect::<io::Result<Vec<_>>>()
+                .map_err(|err| import_error(err, path))?,
+        };
+
+        // Make sure the default mailboxes exist
+        let resource_token = self
+            .get_cached_access_token(account_id)
+            .await
+            .caused_by(trc::location!())?
+            .as_resource_token();
+        self.mailbox_get_or_create(account_id)
+            .await
+            .caused_by(trc::location!())?;
+
+        let mut mailbox_ids: AHashMap<String, u32> = AHashMap::new();
+        let mut processed = 0;
+        for (name, paths) in folders {
+            let mailbox_id = if let Some(mailbox_id) = mailbox_ids.get(&name) {
+                *mailbox_id
+            } else {
+                let mailbox_id = if name.eq_ignore_ascii_case("inbox") {
+                    INBOX_ID
+                } else {
+                    self.mailbox_create_path(account_id, &name)
+                        .await
+                        .caused_by(trc::location!())?
+                        .ok_or_else(|| {
+                            trc::StoreEvent::UnexpectedError
+                                .into_err()
+                                .details("Invalid mailbox name")
+                                .ctx(trc::Key::Path, name.clone())
+                        })?
+                        .0
+                };
+                mailbox_ids.insert(name.clone(), mailbox_id);
+                job.mailboxes += 1;
+                mailbox_id
+            };
+
+            for path in paths {
+                let messages: Box<dyn Iterator<Item = io::Result<ImportMessage>> + Send> =
+                    match format {
+                        ImportFormat::Mbox => Box::new(MboxReader::new(BufReader::new(
+                            File::open(&path).map_err(|err| import_error(err, &path))?,
+                        ))),
+                        ImportFormat::Maildir => {
+                            Box::new(std::iter::once(read_maildir_message(&path)))
+                        }
+                    };
+
+                for message in messages {
+                    let message = message.map_err(|err| import_error(err, &path))?;
+                    match self
+                        .import_message(&resource_token, mailbox_id, message)
+                        .await
+                        .caused_by(trc::location!())?
+                    {
+                        ImportResult::Imported => job.imported += 1,
+                        ImportResult::Duplicate => job.duplicates += 1,
+                        ImportResult::Invalid => job.failed += 1,
+                    }
+                    processed += 1;
+
+                    // Report progress and throttle
+                    if processed % IMPORT_BATCH_SIZE == 0 {
+                        if let Some(status) = self.inner.data.import_jobs.lock().get_mut(&job_id) {
+                            *status = job.clone();
+                        }
+                        tokio::time::sleep(IMPORT_BATCH_PAUSE).await;
+                    }
+                }
+            }
+        }
+
+        job.finished = now();
+
+        Ok(job)
+    }
+}
+
+pub(crate) enum ImportResult {
+    Imported,
+    Duplicate,
+    Invalid,
+}
+
+pub(crate) trait ImportMessageIngest: Sync + Send {
+    fn import_message(
+        &self,
+        resource_token: &ResourceToken,
+        mailbox_id: u32,
+        message: ImportMessage,
+    ) -> impl Future<Output = trc::Result<ImportResult>> + Send;
+}
+
+impl ImportMessageIngest for Server {
+    async fn import_message(
+        &self,
+        resource_token: &ResourceToken,
+        mailbox_id: u32,
+        message: ImportMessage,
+    ) -> trc::Result<ImportResult> {
+        let account_id = resource_token.account_id;
+        let parsed = if let Some(parsed) = MessageParser::new().parse(&message.contents) {
+            parsed
+        } else {
+            return Ok(ImportResult::Invalid);
+        };
+
+        // Messages are considered duplicates if their Message-ID and date match
+        let sent_at = parsed.date().map(|date| date.to_timestamp() as u64);
+        if let Some(message_id) = parsed.message_id() {
+            let mut filters = vec![
+                Filter::eq(Property::MessageId, message_id),
+                Filter::is_in_bitmap(Property::MailboxIds, mailbox_id),
+            ];
+            if let Some(sent_at) = sent_at {
+                filters.push(Filter::eq(Property::SentAt, sent_at));
+            }
+
+            if !self
+                .core
+                .storage
+                .data
+                .filter(account_id, Collection::Email, filters)
+                .await
+                .caused_by(trc::location!())?
+                .results
+                .is_empty()
+            {
+                return Ok(ImportResult::Duplicate);
+            }
+        }
+
+        self.email_ingest(IngestEmail {
+            raw_message: &message.contents,
+            message: Some(parsed),
+            resource: resource_token.clone(),
+            mailbox_ids: vec![mailbox_id],
+            keywords: message.keywords,
+            received_at: message.received_at.or(sent_at),
+            source: IngestSource::Jmap,
+            encrypt: self.core.jmap.encrypt && self.core.jmap.encrypt_append,
+            session_id: 0,
+        })
+        .await
+        .map(|_| ImportResult::Imported)
+    }
+}
+
+// Streaming mboxrd reader, ">From " lines are unescaped by removing one '>'
+pub struct MboxReader<R: BufRead> {
+    reader: R,
+    next_from: Option<Vec<u8>>,
+    eof: bool,
+}
+
+impl<R: BufRead> MboxReader<R> {
+    pub fn new(reader: R) -> Self {
+        MboxReader {
+            reader,
+            next_from: None,
+            eof: false,
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for MboxReader<R> {
+    type Item = io::Result<ImportMessage>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut from_line = self.next_from.take();
+        let mut contents = Vec::new();
+        let mut line = Vec::new();
+
+        while !self.eof {
+            line.clear();
+            match self.reader.read_until(b'\n', &mut line) {
+                Ok(0) => {
+                    self.eof = true;
+                }
+                Ok(_) if line.starts_with(b"From ") => {
+                    if from_line.is_some() {
+                        self.next_from = Some(line);
+                        break;
+                    }
+                    from_line = Some(line.clone());
+                }
+                Ok(_) if from_line.is_some() => {
+                    let quotes = line.iter().take_while(|&&ch| ch == b'>').count();
+                    if quotes > 0 && line[quotes..].starts_with(b"From ") {
+                        contents.extend_from_slice(&line[1..]);
+                    } else {
+                        contents.extend_from_slice(&line);
+                    }
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    self.eof = true;
+                    return Some(Err(err));
+                }
+            }
+        }
+
+        // Remove the blank line separating messages
+        let from_line = from_line?;
+        if contents.ends_with(b"\r\n\r\n") {
+            contents.truncate(contents.len() - 2);
+        } else if contents.ends_with(b"\n\n") {
+            contents.truncate(contents.len() - 1);
+        }
+
+        Some(Ok(ImportMessage {
+            contents,
+            keywords: vec![],
+            received_at: parse_from_line_date(&from_line),
+        }))
+    }
+}
+
+// Parses the asctime date of a "From sender Thu Nov 14 10:00:00 2024" line
+fn parse_from_line_date(line: &[u8]) -> Option<u64> {
+    let line = std::str::from_utf8(line).ok()?;
+    let mut parts = line.split_ascii_whitespace().skip(3);
+    let month = parts.next()?;
+    let day = parts.next()?.parse().ok()?;
+    let mut time = parts.next()?.split(':');
+    let year = parts.next()?.parse().ok()?;
+    let month = [
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+    ]
+    .iter()
+    .position(|name| month.eq_ignore_ascii_case(name))? as u8
+        + 1;
+
+    Some(
+        DateTime {
+            year,
+            month,
+            day,
+            hour: time.next()?.parse().ok()?,
+            minute: time.next()?.parse().ok()?,
+            second: time.next().unwrap_or("0").parse().ok()?,
+            tz_before_gmt: false,
+            tz_hour: 0,
+            tz_minute: 0,
+        }
+        .to_timestamp() as u64,
+    )
+}
+
+// Returns the folders of a Maildir, supporting both Maildir++ ".Parent.Child"
+// folders and nested directories
+pub fn maildir_folders(root: &Path) -> io::Result<Vec<(String, PathBuf)>> {
+    let mut folders = vec![("INBOX".to_string(), root.to_path_buf())];
+    let mut pending = vec![(String::new(), root.to_path_buf())];
+
+    while let Some((prefix, path)) = pending.pop() {
+        let mut entries = std::fs::read_dir(&path)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<io::Result<Vec<_>>>()?;
+        entries.sort_unstable();
+
+        for entry in entries {
+            let name = match entry.file_name().and_then(|name| name.to_str()) {
+                Some(name) if entry.is_dir() && !["cur", "new", "tmp"].contains(&name) => name,
+                _ => continue,
+            };
+            let name = if let Some(name) = name.strip_prefix('.') {
+                if name.is_empty() || name.starts_with('.') {
+                    continue;
+                }
+                name.replace('.', "/")
+            } else {
+                name.to_string()
+            };
+            let name = if prefix.is_empty() {
+                name
+            } else {
+                format!("{prefix}/{name}")
+            };
+
+            if entry.join("cur").is_dir() || entry.join("new").is_dir() {
+                folders.push((name.clone(), entry.clone()));
+            }
+            pending.push((name, entry));
+        }
+    }
+
+    Ok(folders)
+}
+
+// Lists the messages of a Maildir folder, messages in "tmp" are still being delivered
+pub fn maildir_messages(folder: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut messages = Vec::new();
+    for subdir in ["cur", "new"] {
+        let path = folder.join(subdir);
+        if !path.is_dir() {
+            continue;
+        }
+        for entry in std::fs::read_dir(path)? {
+            let entry = entry?.path();
+            if entry.is_file()
+                && entry
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| !name.starts_with('.'))
+            {
+                messages.push(entry);
+            }
+        }
+    }
+    messages.sort_unstable();
+
+    Ok(messages)
+}
+
+pub fn read_maildir_message(path: &Path) -> io::Result<ImportMessage> {
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+
+    // Delivery timestamp is the first component of the file name
+    let received_at = name
+        .split('.')
+        .next()
+        .and_then(|timestamp| timestamp.parse::<u64>().ok())
+        .or_else(|| {
+            std::fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|modified| modified.as_secs())
+        });
+
+    Ok(ImportMessage {
+        contents: std::fs::read(path)?,
+        keywords: maildir_keywords(name),
+        received_at,
+    })
+}
+
+// Maps the flags after the ":2," suffix of a Maildir file name to keywords
+pub fn maildir_keywords(name: &str) -> Vec<Keyword> {
+    let mut keywords = Vec::new();
+    if let Some((_, flags)) = name.rsplit_once(":2,") {
+        for flag in flags.chars() {
+            let keyword = match flag {
+                'D' => Keyword::Draft,
+                'F' => Keyword::Flagged,
+                'P' => Keyword::Forwarded,
+                'R' => Keyword::Answered,
+                'S' => Keyword::Seen,
+                'T' => Keyword::Deleted,
+                _ => continue,
+            };
+            if !keywords.contains(&keyword) {
+                keywords.push(keyword);
+            }
+        }
+    }
+
+    keywords
+}
+
+fn import_error(err: io::Error, path: &Path) -> trc::Error {
+    trc::StoreEvent::UnexpectedError
+        .into_err()
+        .reason(err)
+        .details("Failed to read import file")
+        .ctx(trc::Key::Path, path.to_string_lossy().into_owned())
+}
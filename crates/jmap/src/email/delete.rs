@@ -6,7 +6,7 @@
 
 use std::time::Duration;
 
-use common::Server;
+use common::{config::jmap::retention::RetentionStats, Server};
 use jmap_proto::types::{
     collection::Collection, id::Id, keyword::Keyword, property::Property, state::StateChange,
     type_state::DataType,
@@ -15,7 +15,7 @@ use store::{
     ahash::AHashMap,
     roaring::RoaringBitmap,
     write::{
-        log::ChangeLogBuilder, BatchBuilder, Bincode, BitmapClass, MaybeDynamicId, TagValue,
+        log::ChangeLogBuilder, now, BatchBuilder, Bincode, BitmapClass, MaybeDynamicId, TagValue,
         ValueClass, F_BITMAP, F_CLEAR, F_VALUE,
     },
     BitmapKey, IterateParams, ValueKey, U32_LEN,
@@ -30,7 +30,10 @@ use crate::{
     JmapMethods,
 };
 
-use super::{index::EmailIndexBuilder, metadata::MessageMetadata};
+use super::{
+    index::EmailIndexBuilder, metadata::MessageMetadata, private::PrivateKeyword,
+    retention::EmailRetention, snooze::EmailSnooze,
+};
 use rand::prelude::SliceRandom;
 use std::future::Future;
 
@@ -243,9 +246,14 @@ impl EmailDeletion for Server {
             // Shuffle account ids
             account_ids.shuffle(&mut rand::thread_rng());
 
+            *self.inner.data.retention_stats.lock() = RetentionStats {
+                started: now(),
+                ..Default::default()
+            };
             for account_id in account_ids {
                 self.purge_account(account_id).await;
             }
+            self.inner.data.retention_stats.lock().finished = now();
         }
     }
 
@@ -280,20 +288,52 @@ impl EmailDeletion for Server {
             }
         }
 
-        // Auto-expunge deleted and junk messages
-        if let Some(period) = self.core.jmap.mail_autoexpunge_after {
-            if let Err(err) = self.emails_auto_expunge(account_id, period).await {
+        // Accounts on legal hold preserve all their messages
+        let legal_hold = self.is_legal_hold(account_id).await.unwrap_or_else(|err| {
+            trc::error!(err
+                .details("Failed to obtain legal hold status.")
+                .account_id(account_id));
+            false
+        });
+        if !legal_hold {
+            // Auto-expunge deleted and junk messages
+            if let Some(period) = self.core.jmap.mail_autoexpunge_after {
+                if let Err(err) = self.emails_auto_expunge(account_id, period).await {
+                    trc::error!(err
+                        .details("Failed to auto-expunge messages.")
+                        .account_id(account_id));
+                }
+            }
+
+            // Apply retention policies
+            let expunged = self
+                .emails_apply_retention(account_id)
+                .await
+                .unwrap_or_else(|err| {
+                    trc::error!(err
+                        .details("Failed to apply retention policies.")
+                        .account_id(account_id));
+                    Default::default()
+                });
+            {
+                let mut stats = self.inner.data.retention_stats.lock();
+                stats.accounts += 1;
+                for (policy_id, total) in expunged {
+                    stats.expunged += total;
+                    *stats.policies.entry(policy_id).or_default() += total;
+                }
+            }
+
+            // Purge tombstoned messages
+            if let Err(err) = self.emails_purge_tombstoned(account_id).await {
                 trc::error!(err
-                    .details("Failed to auto-expunge messages.")
+                    .details("Failed to purge tombstoned messages.")
                     .account_id(account_id));
             }
-        }
-
-        // Purge tombstoned messages
-        if let Err(err) = self.emails_purge_tombstoned(account_id).await {
-            trc::error!(err
-                .details("Failed to purge tombstoned messages.")
-                .account_id(account_id));
+        } else {
+            let mut stats = self.inner.data.retention_stats.lock();
+            stats.accounts += 1;
+            stats.accounts_on_hold += 1;
         }
 
         // Purge changelogs
@@ -472,6 +512,42 @@ impl EmailDeletion for Server {
                 );
             }
 
+            // Remove private keywords
+            if let Some(keywords) = self
+                .core
+                .storage
+                .data
+                .get_value::<Vec<PrivateKeyword>>(ValueKey {
+                    account_id,
+                    collection: Collection::Email.into(),
+                    document_id,
+                    class: ValueClass::Property(Property::PrivateKeywords.into()),
+                })
+                .await?
+            {
+                batch.value(
+                    Property::PrivateKeywords,
+                    keywords,
+                    F_VALUE | F_BITMAP | F_CLEAR,
+                );
+            }
+
+            // Cancel snooze
+            if let Some(snooze) = self
+                .core
+                .storage
+                .data
+                .get_value::<EmailSnooze>(ValueKey {
+                    account_id,
+                    collection: Collection::Email.into(),
+                    document_id,
+                    class: ValueClass::Property(Property::Snoozed.into()),
+                })
+                .await?
+            {
+                snooze.clear(&mut batch, account_id, document_id);
+            }
+
             // Remove message metadata
             if let Some(metadata) = self
                 .core
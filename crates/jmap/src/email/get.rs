@@ -34,6 +34,8 @@ use super::{
     cache::ThreadCache,
     headers::IntoForm,
     metadata::{MessageMetadata, MetadataPartType},
+    private::EmailPrivateKeywords,
+    snooze::EmailSnooze,
 };
 
 pub trait EmailGet: Sync + Send {
@@ -243,7 +245,7 @@ impl EmailGet for Server {
                         }
                     }
                     Property::Keywords => {
-                        if let Some(keywords) = self
+                        if let Some(mut keywords) = self
                             .get_property::<Vec<Keyword>>(
                                 account_id,
                                 Collection::Email,
@@ -251,15 +253,19 @@ impl EmailGet for Server {
                                 &Property::Keywords,
                             )
                             .await?
-                            .map(|keywords| {
-                                let mut obj = Object::with_capacity(keywords.len());
-                                for keyword in keywords {
-                                    obj.append(Property::_T(keyword.to_string()), true);
-                                }
-                                Value::Object(obj)
-                            })
                         {
-                            email.append(property.clone(), keywords);
+                            // Merge the private keywords of the account
+                            if let Some(overlay) = self
+                                .keyword_overlay(account_id, access_token, id.document_id())
+                                .await?
+                            {
+                                keywords = overlay.view(&keywords);
+                            }
+                            let mut obj = Object::with_capacity(keywords.len());
+                            for keyword in keywords {
+                                obj.append(Property::_T(keyword.to_string()), true);
+                            }
+                            email.append(property.clone(), Value::Object(obj));
                         } else {
                             trc::event!(
                                 Store(StoreEvent::NotFound),
@@ -291,6 +297,19 @@ impl EmailGet for Server {
                     Property::HasAttachment => {
                         email.append(Property::HasAttachment, metadata.has_attachments);
                     }
+                    Property::Snoozed => {
+                        email.append(
+                            Property::Snoozed,
+                            self.get_property::<EmailSnooze>(
+                                account_id,
+                                Collection::Email,
+                                id.document_id(),
+                                Property::Snoozed,
+                            )
+                            .await?
+                            .map_or(Value::Null, |snooze| snooze.to_value()),
+                        );
+                    }
                     Property::Subject => {
                         email.append(
                             Property::Subject,
@@ -9,7 +9,7 @@ use std::{
     time::{Duration, Instant},
 };
 
-use common::{auth::ResourceToken, Server};
+use common::{auth::ResourceToken, bayes::bayes_text, Server};
 use jmap_proto::{
     object::Object,
     types::{
@@ -41,6 +41,7 @@ use crate::{
     changes::write::ChangeLog,
     email::index::{IndexMessage, VisitValues, MAX_ID_LENGTH},
     mailbox::{UidMailbox, INBOX_ID, JUNK_ID},
+    push::NewMailPush,
     services::index::Indexer,
     JmapMethods,
 };
@@ -138,6 +139,36 @@ impl EmailIngest for Server {
             }
         }
 
+        // Consult the account's own Bayes model
+        if !is_spam
+            && params.source == IngestSource::Smtp
+            && params.mailbox_ids == [INBOX_ID]
+            && self.core.spam.bayes.account_models
+        {
+            let bayes = &self.core.spam.bayes;
+            match self
+                .bayes_classify(
+                    self.bayes_store(),
+                    account_id.into(),
+                    &bayes_text(&message),
+                    &bayes.classifier,
+                )
+                .await
+            {
+                Ok(Some(score)) if score >= bayes.spam_threshold => {
+                    params.mailbox_ids[0] = JUNK_ID;
+                    is_spam = true;
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    trc::error!(err
+                        .account_id(account_id)
+                        .span_id(params.session_id)
+                        .caused_by(trc::location!()));
+                }
+            }
+        }
+
         // Obtain message references and thread name
         let mut message_id = String::new();
         let thread_id = {
@@ -365,6 +396,11 @@ impl EmailIngest for Server {
         // Request FTS index
         self.request_fts_index();
 
+        // Notify mobile devices and webhooks of new mail
+        if params.source == IngestSource::Smtp {
+            self.push_new_mail(account_id, &params.mailbox_ids);
+        }
+
         trc::event!(
             MessageIngest(match params.source {
                 IngestSource::Smtp =>
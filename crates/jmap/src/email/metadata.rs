@@ -4,8 +4,10 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::borrow::Cow;
+use std::{borrow::Cow, future::Future};
 
+use common::Server;
+use jmap_proto::types::{collection::Collection, keyword::Keyword, property::Property};
 use mail_parser::{
     decoders::{
         base64::base64_decode, charsets::map::charset_decoder,
@@ -14,7 +16,9 @@ use mail_parser::{
     ContentType, Encoding, GetHeader, Header, HeaderName, HeaderValue, Message, MessagePart,
     MessagePartId, MimeHeaders, PartType,
 };
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, Serializer};
+use store::write::{assert::HashedValue, Bincode, ValueClass};
+use trc::AddContext;
 use utils::BlobHash;
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -297,3 +301,63 @@ impl<'x> MimeHeaders<'x> for MessageMetadataPart<'x> {
             .and_then(|header| header.as_text())
     }
 }
+
+// Metadata and keywords of a message, read in a single round-trip
+#[derive(Debug, Serialize)]
+pub struct MessageData {
+    pub metadata: MessageMetadata<'static>,
+    #[serde(serialize_with = "serialize_keywords")]
+    pub keywords: HashedValue<Vec<Keyword>>,
+}
+
+pub trait MessageDataGet: Sync + Send {
+    fn get_message_data(
+        &self,
+        account_id: u32,
+        document_id: u32,
+    ) -> impl Future<Output = trc::Result<Option<MessageData>>> + Send;
+}
+
+impl MessageDataGet for Server {
+    async fn get_message_data(
+        &self,
+        account_id: u32,
+        document_id: u32,
+    ) -> trc::Result<Option<MessageData>> {
+        let mut fields = self
+            .core
+            .storage
+            .data
+            .get_document_fields(
+                account_id,
+                Collection::Email,
+                document_id,
+                &[
+                    ValueClass::Property(Property::BodyStructure.into()),
+                    ValueClass::Property(Property::Keywords.into()),
+                ],
+            )
+            .await
+            .caused_by(trc::location!())?
+            .into_iter();
+
+        if let (Some(Some(metadata)), Some(Some(keywords))) = (fields.next(), fields.next()) {
+            Ok(Some(MessageData {
+                metadata: <Bincode<MessageMetadata> as store::Deserialize>::deserialize(&metadata)
+                    .caused_by(trc::location!())?
+                    .inner,
+                keywords: <HashedValue<Vec<Keyword>> as store::Deserialize>::deserialize(&keywords)
+                    .caused_by(trc::location!())?,
+            }))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+fn serialize_keywords<S: Serializer>(
+    keywords: &HashedValue<Vec<Keyword>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    keywords.inner.serialize(serializer)
+}
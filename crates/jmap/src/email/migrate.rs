@@ -0,0 +1,481 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use common::{auth::ResourceToken, MigrationFolder, Server};
+use directory::backend::imap::{
+    mailbox::{RemoteFolder, RemoteMessage},
+    ImapClient, ImapError,
+};
+use futures_util::{stream, StreamExt};
+use imap_proto::{parser::parse_datetime, protocol::Flag, utf7::utf7_decode};
+use jmap_proto::types::keyword::Keyword;
+use mail_send::{smtp::tls::build_tls_connector, Credentials};
+use serde::{Deserialize, Serialize};
+use store::{
+    write::{now, Bincode},
+    Serialize as _,
+};
+use tokio::io::{AsyncRead, AsyncWrite};
+use trc::AddContext;
+
+use crate::mailbox::{get::MailboxGet, set::MailboxSet, INBOX_ID};
+
+use super::bulk_import::{ImportMessage, ImportMessageIngest, ImportResult};
+
+const MAX_CONCURRENCY: usize = 8;
+const MIGRATION_TIMEOUT: Duration = Duration::from_secs(300);
+// Folder states expire eventually, later runs then rely on duplicate detection
+const MIGRATION_STATE_TTL: u64 = 90 * 86400;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MigrationRequest {
+    pub host: String,
+    #[serde(default)]
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub starttls: bool,
+    #[serde(default)]
+    pub allow_invalid_certs: bool,
+    pub username: String,
+    #[serde(default)]
+    pub password: Option<String>,
+    #[serde(default)]
+    pub oauth_token: Option<String>,
+    #[serde(default)]
+    pub dry_run: bool,
+    #[serde(default)]
+    pub concurrency: Option<usize>,
+    // Maximum transfer rate in bytes per second
+    #[serde(default)]
+    pub bandwidth: Option<u64>,
+}
+
+// Persisted after every message so interrupted or repeated runs only fetch new mail
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct FolderSyncState {
+    uid_validity: u32,
+    last_uid: u32,
+}
+
+struct LocalFolder<'x> {
+    remote: &'x RemoteFolder,
+    name: String,
+    role: Option<&'static str>,
+    mailbox_id: Option<u32>,
+}
+
+pub trait EmailMigrate: Sync + Send {
+    fn email_migrate(
+        &self,
+        account_id: u32,
+        request: &MigrationRequest,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+}
+
+impl EmailMigrate for Server {
+    async fn email_migrate(&self, account_id: u32, request: &MigrationRequest) -> trc::Result<()> {
+        // Obtain the remote folder list
+        let mut client = request.connect().await?;
+        let special_use = client
+            .capabilities()
+            .await
+            .map_err(imap_error)?
+            .iter()
+            .any(|capability| capability == "SPECIAL-USE");
+        let remote_folders = client.list(special_use).await.map_err(imap_error)?;
+        let _ = client.logout().await;
+
+        let mut folders = remote_folders
+            .iter()
+            .filter(|folder| {
+                !folder.attributes.iter().any(|attribute| {
+                    attribute.eq_ignore_ascii_case("\\Noselect")
+                        || attribute.eq_ignore_ascii_case("\\NonExistent")
+                })
+            })
+            .map(LocalFolder::new)
+            .collect::<Vec<_>>();
+
+        // Create the local mailboxes upfront, folders are then migrated concurrently
+        let resource_token = self
+            .get_cached_access_token(account_id)
+            .await
+            .caused_by(trc::location!())?
+            .as_resource_token();
+        if !request.dry_run {
+            self.mailbox_get_or_create(account_id)
+                .await
+                .caused_by(trc::location!())?;
+            for folder in &mut folders {
+                folder.mailbox_id = self
+                    .migration_mailbox(account_id, folder)
+                    .await
+                    .caused_by(trc::location!())?
+                    .into();
+            }
+        }
+        if let Some(status) = self.inner.data.migrations.lock().get_mut(&account_id) {
+            status.folders = folders
+                .iter()
+                .map(|folder| MigrationFolder {
+                    remote_name: folder.remote.name.clone(),
+                    local_name: folder.name.clone(),
+                    ..Default::default()
+                })
+                .collect();
+        }
+
+        let concurrency = request.concurrency.unwrap_or(1).clamp(1, MAX_CONCURRENCY);
+        let bandwidth = request
+            .bandwidth
+            .map(|bandwidth| (bandwidth / concurrency as u64).max(1));
+        let resource_token = &resource_token;
+        let results = stream::iter(folders.iter().enumerate())
+            .map(|(folder_idx, folder)| async move {
+                (
+                    folder_idx,
+                    self.migrate_folder(request, resource_token, folder_idx, folder, bandwidth)
+                        .await,
+                )
+            })
+            .buffer_unordered(concurrency)
+            .collect::<Vec<_>>()
+            .await;
+
+        // Folder errors are reported individually, the remaining folders are still migrated
+        for (folder_idx, err) in results
+            .into_iter()
+            .filter_map(|(folder_idx, result)| result.err().map(|err| (folder_idx, err)))
+        {
+            update_folder(self, account_id, folder_idx, |folder| {
+                folder.error = err.to_string().into();
+            });
+            trc::error!(err
+                .details("Failed to migrate folder")
+                .account_id(account_id));
+        }
+
+        if let Some(status) = self.inner.data.migrations.lock().get_mut(&account_id) {
+            status.finished = now();
+        }
+
+        Ok(())
+    }
+}
+
+trait MigrateFolder: Sync + Send {
+    fn migration_mailbox(
+        &self,
+        account_id: u32,
+        folder: &LocalFolder<'_>,
+    ) -> impl Future<Output = trc::Result<u32>> + Send;
+
+    fn migrate_folder(
+        &self,
+        request: &MigrationRequest,
+        resource_token: &ResourceToken,
+        folder_idx: usize,
+        folder: &LocalFolder<'_>,
+        bandwidth: Option<u64>,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+}
+
+impl MigrateFolder for Server {
+    async fn migration_mailbox(
+        &self,
+        account_id: u32,
+        folder: &LocalFolder<'_>,
+    ) -> trc::Result<u32> {
+        match folder.role {
+            Some("inbox") => return Ok(INBOX_ID),
+            Some(role) => {
+                if let Some(mailbox_id) = self.mailbox_get_by_role(account_id, role).await? {
+                    return Ok(mailbox_id);
+                }
+            }
+            None => (),
+        }
+
+        self.mailbox_create_path(account_id, &folder.name)
+            .await?
+            .map(|(mailbox_id, _)| mailbox_id)
+            .ok_or_else(|| {
+                trc::StoreEvent::UnexpectedError
+                    .into_err()
+                    .details("Invalid mailbox name")
+                    .ctx(trc::Key::Path, folder.name.clone())
+            })
+    }
+
+    async fn migrate_folder(
+        &self,
+        request: &MigrationRequest,
+        resource_token: &ResourceToken,
+        folder_idx: usize,
+        folder: &LocalFolder<'_>,
+        bandwidth: Option<u64>,
+    ) -> trc::Result<()> {
+        let account_id = resource_token.account_id;
+        let mut client = request.connect().await?;
+        let remote = client
+            .examine(&folder.remote.name)
+            .await
+            .map_err(imap_error)?;
+
+        // Resume after the last migrated UID, unless the remote folder was recreated
+        let state_key = format!(
+            "migrate:{account_id}:{}:{}:{}",
+            request.host.to_lowercase(),
+            request.username,
+            folder.remote.name
+        )
+        .into_bytes();
+        let mut state = self
+            .lookup_store()
+            .key_get::<Bincode<FolderSyncState>>(state_key.clone())
+            .await
+            .caused_by(trc::location!())?
+            .map(|state| state.inner)
+            .filter(|state| state.uid_validity == remote.uid_validity)
+            .unwrap_or(FolderSyncState {
+                uid_validity: remote.uid_validity,
+                last_uid: 0,
+            });
+        let messages = if remote.exists > 0 {
+            client
+                .fetch_messages(state.last_uid + 1)
+                .await
+                .map_err(imap_error)?
+        } else {
+            vec![]
+        };
+
+        // Messages exceeding the size limit are skipped and reported
+        let max_size = self.core.jmap.mail_max_size as u64;
+        update_folder(self, account_id, folder_idx, |status| {
+            status.pending = messages.len() as u64;
+            status.too_large = messages
+                .iter()
+                .filter(|message| message.size > max_size)
+                .map(|message| message.uid)
+                .collect();
+            if request.dry_run {
+                status.bytes = messages
+                    .iter()
+                    .filter(|message| message.size <= max_size)
+                    .map(|message| message.size)
+                    .sum();
+            }
+        });
+        let (Some(mailbox_id), false) = (folder.mailbox_id, request.dry_run) else {
+            let _ = client.logout().await;
+            return Ok(());
+        };
+
+        let started = Instant::now();
+        let mut transferred = 0;
+        for message in messages {
+            if is_cancelled(self, account_id) {
+                break;
+            }
+            let uid = message.uid;
+            if message.size <= max_size {
+                let contents = client.fetch_message(uid).await.map_err(imap_error)?;
+                let bytes = contents
+                    .as_ref()
+                    .map_or(0, |contents| contents.len() as u64);
+                let result = if let Some(contents) = contents {
+                    self.import_message(
+                        resource_token,
+                        mailbox_id,
+                        import_message(message, contents),
+                    )
+                    .await
+                    .caused_by(trc::location!())?
+                } else {
+                    ImportResult::Invalid
+                };
+                update_folder(self, account_id, folder_idx, |status| {
+                    match result {
+                        ImportResult::Imported => status.imported += 1,
+                        ImportResult::Duplicate => status.duplicates += 1,
+                        ImportResult::Invalid => status.failed += 1,
+                    }
+                    status.bytes += bytes;
+                });
+
+                // Throttle the transfer rate
+                transferred += bytes;
+                if let Some(bandwidth) = bandwidth {
+                    let expected = Duration::from_secs_f64(transferred as f64 / bandwidth as f64);
+                    if let Some(wait) = expected.checked_sub(started.elapsed()) {
+                        tokio::time::sleep(wait).await;
+                    }
+                }
+            }
+
+            state.last_uid = uid;
+            self.lookup_store()
+                .key_set(
+                    state_key.clone(),
+                    Bincode::new(state).serialize(),
+                    Some(MIGRATION_STATE_TTL),
+                )
+                .await
+                .caused_by(trc::location!())?;
+        }
+
+        let _ = client.logout().await;
+
+        Ok(())
+    }
+}
+
+impl MigrationRequest {
+    // Only messages are read from the remote server, so UIDPLUS is not required
+    async fn connect(&self) -> trc::Result<ImapClient<impl AsyncRead + AsyncWrite + Unpin + Send>> {
+        let port = self.port.unwrap_or(if self.starttls { 143 } else { 993 });
+        let mut client = ImapClient::connect(
+            format!("{}:{port}", self.host),
+            MIGRATION_TIMEOUT,
+            &build_tls_connector(self.allow_invalid_certs),
+            &self.host,
+            !self.starttls,
+        )
+        .await
+        .map_err(imap_error)?;
+
+        let credentials = match &self.oauth_token {
+            Some(token) => Credentials::XOauth2 {
+                username: self.username.clone(),
+                secret: token.clone(),
+            },
+            None => Credentials::Plain {
+                username: self.username.clone(),
+                secret: self.password.clone().unwrap_or_default(),
+            },
+        };
+        client.login(&credentials).await.map_err(imap_error)?;
+
+        Ok(client)
+    }
+}
+
+impl<'x> LocalFolder<'x> {
+    fn new(remote: &'x RemoteFolder) -> Self {
+        let name = utf7_decode(&remote.name).unwrap_or_else(|| remote.name.clone());
+
+        // Map the remote hierarchy to ours, a '/' within a name is not a separator
+        let mut parts: Vec<String> = match remote.delimiter {
+            Some('/') | None => name.split('/').map(|part| part.to_string()).collect(),
+            Some(delimiter) => name
+                .split(delimiter)
+                .map(|part| part.replace('/', "_"))
+                .collect(),
+        };
+        parts.retain(|part| !part.is_empty());
+
+        // Servers such as Courier nest every folder under "INBOX."
+        if parts.len() > 1
+            && parts[0].eq_ignore_ascii_case("inbox")
+            && remote.delimiter == Some('.')
+        {
+            parts.remove(0);
+        }
+
+        let role = remote
+            .attributes
+            .iter()
+            .find_map(|attribute| match attribute.to_ascii_lowercase().as_str() {
+                "\\sent" => Some("sent"),
+                "\\trash" => Some("trash"),
+                "\\drafts" => Some("drafts"),
+                "\\junk" => Some("junk"),
+                "\\archive" => Some("archive"),
+                _ => None,
+            })
+            .or_else(|| match parts.as_slice() {
+                [name] => well_known_role(name),
+                _ => None,
+            });
+
+        LocalFolder {
+            remote,
+            name: parts.join("/"),
+            role,
+            mailbox_id: None,
+        }
+    }
+}
+
+// Servers without SPECIAL-USE are matched by their usual folder names
+fn well_known_role(name: &str) -> Option<&'static str> {
+    match name.to_lowercase().as_str() {
+        "inbox" => Some("inbox"),
+        "sent" | "sent items" | "sent messages" | "sent mail" => Some("sent"),
+        "trash" | "deleted items" | "deleted messages" => Some("trash"),
+        "drafts" => Some("drafts"),
+        "junk" | "spam" | "junk mail" | "junk e-mail" | "bulk mail" => Some("junk"),
+        "archive" | "archives" => Some("archive"),
+        _ => None,
+    }
+}
+
+// Flags and the internal date are preserved, \Recent is session specific
+fn import_message(message: RemoteMessage, contents: Vec<u8>) -> ImportMessage {
+    ImportMessage {
+        contents,
+        keywords: message
+            .flags
+            .into_iter()
+            .filter(|flag| !flag.eq_ignore_ascii_case("\\Recent"))
+            .filter_map(|flag| Flag::parse_imap(flag.into_bytes()).ok())
+            .map(Keyword::from)
+            .collect(),
+        received_at: message
+            .internal_date
+            .and_then(|date| parse_datetime(date.as_bytes()).ok())
+            .map(|date| date as u64),
+    }
+}
+
+fn update_folder(
+    server: &Server,
+    account_id: u32,
+    folder_idx: usize,
+    update: impl FnOnce(&mut MigrationFolder),
+) {
+    if let Some(folder) = server
+        .inner
+        .data
+        .migrations
+        .lock()
+        .get_mut(&account_id)
+        .and_then(|status| status.folders.get_mut(folder_idx))
+    {
+        update(folder);
+    }
+}
+
+fn is_cancelled(server: &Server, account_id: u32) -> bool {
+    server
+        .inner
+        .data
+        .migrations
+        .lock()
+        .get(&account_id)
+        .map_or(true, |status| status.cancelled)
+}
+
+fn imap_error(err: ImapError) -> trc::Error {
+    trc::ImapEvent::Error.into_err().reason(err)
+}
@@ -4,7 +4,9 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+pub mod archive;
 pub mod body;
+pub mod bulk_import;
 pub mod cache;
 pub mod copy;
 pub mod crypto;
@@ -15,7 +17,12 @@ pub mod import;
 pub mod index;
 pub mod ingest;
 pub mod metadata;
+pub mod migrate;
 pub mod parse;
+pub mod private;
 pub mod query;
+pub mod retention;
 pub mod set;
 pub mod snippet;
+pub mod snooze;
+pub mod spam;
@@ -0,0 +1,453 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{future::Future, slice::Iter};
+
+use common::{auth::AccessToken, Server};
+use jmap_proto::types::{acl::Acl, collection::Collection, keyword::Keyword, property::Property};
+use store::{
+    query::Filter,
+    roaring::RoaringBitmap,
+    write::{
+        assert::{AssertValue, HashedValue},
+        BatchBuilder, BitmapClass, DeserializeFrom, MaybeDynamicId, Operation, SerializeInto,
+        TagValue, ToBitmaps, ValueClass, F_BITMAP, F_CLEAR, F_VALUE,
+    },
+    Serialize, U32_LEN,
+};
+use trc::AddContext;
+use utils::codec::leb128::{Leb128Iterator, Leb128Vec};
+
+use crate::{auth::acl::AclMethods, changes::write::ChangeLog, JmapMethods};
+
+use super::set::TagManager;
+
+// A keyword set by an account on a message it accesses through a shared mailbox
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrivateKeyword {
+    pub account_id: u32,
+    pub keyword: Keyword,
+}
+
+// The private keywords of one accessing account on a single message, layered
+// over the keywords stored in the owner's copy of the message.
+#[derive(Debug, Clone)]
+pub struct KeywordOverlay {
+    pub account_id: u32,
+    private: Vec<Keyword>,
+    hash: Option<u64>,
+    keywords: Vec<Keyword>,
+    others: Vec<PrivateKeyword>,
+    added: Vec<Keyword>,
+    removed: Vec<Keyword>,
+}
+
+pub trait EmailPrivateKeywords: Sync + Send {
+    fn is_private_keyword(
+        &self,
+        account_id: u32,
+        access_token: &AccessToken,
+        keyword: &Keyword,
+    ) -> bool;
+
+    fn keyword_filter(
+        &self,
+        account_id: u32,
+        access_token: &AccessToken,
+        keyword: Keyword,
+    ) -> Filter;
+
+    fn keyword_overlay(
+        &self,
+        account_id: u32,
+        access_token: &AccessToken,
+        document_id: u32,
+    ) -> impl Future<Output = trc::Result<Option<KeywordOverlay>>> + Send;
+
+    fn keyword_document_ids(
+        &self,
+        account_id: u32,
+        access_token: &AccessToken,
+        keyword: Keyword,
+    ) -> impl Future<Output = trc::Result<Option<RoaringBitmap>>> + Send;
+
+    fn private_keywords_revoke(
+        &self,
+        account_id: u32,
+        mailbox_id: u32,
+        accessor_id: u32,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+}
+
+impl EmailPrivateKeywords for Server {
+    fn is_private_keyword(
+        &self,
+        account_id: u32,
+        access_token: &AccessToken,
+        keyword: &Keyword,
+    ) -> bool {
+        access_token.is_shared(account_id) && self.core.jmap.mail_private_keywords.contains(keyword)
+    }
+
+    fn keyword_filter(
+        &self,
+        account_id: u32,
+        access_token: &AccessToken,
+        keyword: Keyword,
+    ) -> Filter {
+        if self.is_private_keyword(account_id, access_token, &keyword) {
+            Filter::is_in_bitmap(
+                Property::PrivateKeywords,
+                private_keyword_tag(access_token.primary_id(), &keyword),
+            )
+        } else {
+            Filter::is_in_bitmap(Property::Keywords, keyword)
+        }
+    }
+
+    async fn keyword_overlay(
+        &self,
+        account_id: u32,
+        access_token: &AccessToken,
+        document_id: u32,
+    ) -> trc::Result<Option<KeywordOverlay>> {
+        let private = &self.core.jmap.mail_private_keywords;
+        if !access_token.is_shared(account_id) || private.is_empty() {
+            return Ok(None);
+        }
+
+        let current = self
+            .get_property::<HashedValue<Vec<PrivateKeyword>>>(
+                account_id,
+                Collection::Email,
+                document_id,
+                Property::PrivateKeywords,
+            )
+            .await
+            .caused_by(trc::location!())?;
+
+        Ok(Some(KeywordOverlay::new(
+            access_token.primary_id(),
+            private.clone(),
+            current,
+        )))
+    }
+
+    async fn keyword_document_ids(
+        &self,
+        account_id: u32,
+        access_token: &AccessToken,
+        keyword: Keyword,
+    ) -> trc::Result<Option<RoaringBitmap>> {
+        if self.is_private_keyword(account_id, access_token, &keyword) {
+            self.get_tag(
+                account_id,
+                Collection::Email,
+                Property::PrivateKeywords,
+                private_keyword_tag(access_token.primary_id(), &keyword),
+            )
+            .await
+        } else {
+            self.get_tag(account_id, Collection::Email, Property::Keywords, keyword)
+                .await
+        }
+    }
+
+    async fn private_keywords_revoke(
+        &self,
+        account_id: u32,
+        mailbox_id: u32,
+        accessor_id: u32,
+    ) -> trc::Result<()> {
+        // Find the messages in the mailbox the account has private keywords on
+        let mut document_ids = RoaringBitmap::new();
+        for keyword in &self.core.jmap.mail_private_keywords {
+            if let Some(tagged_ids) = self
+                .get_tag(
+                    account_id,
+                    Collection::Email,
+                    Property::PrivateKeywords,
+                    private_keyword_tag(accessor_id, keyword),
+                )
+                .await?
+            {
+                document_ids |= tagged_ids;
+            }
+        }
+        if document_ids.is_empty() {
+            return Ok(());
+        }
+        document_ids &= self
+            .get_tag(
+                account_id,
+                Collection::Email,
+                Property::MailboxIds,
+                mailbox_id,
+            )
+            .await?
+            .unwrap_or_default();
+
+        // Keep the keywords of messages that are still shared through other mailboxes
+        let access_token = self
+            .get_cached_access_token(accessor_id)
+            .await
+            .caused_by(trc::location!())?;
+        if access_token.is_member(account_id) {
+            return Ok(());
+        }
+        document_ids -= self
+            .shared_messages(&access_token, account_id, Acl::ReadItems)
+            .await?;
+        if document_ids.is_empty() {
+            return Ok(());
+        }
+
+        let mut changes = self.begin_changes(account_id).await?;
+        for document_id in document_ids {
+            if let Some(current) = self
+                .get_property::<HashedValue<Vec<PrivateKeyword>>>(
+                    account_id,
+                    Collection::Email,
+                    document_id,
+                    Property::PrivateKeywords,
+                )
+                .await?
+            {
+                let mut overlay = KeywordOverlay::new(accessor_id, vec![], Some(current));
+                for keyword in overlay.keywords.clone() {
+                    overlay.update(keyword, false);
+                }
+
+                let mut batch = BatchBuilder::new();
+                batch
+                    .with_account_id(account_id)
+                    .with_collection(Collection::Email)
+                    .update_document(document_id);
+                overlay.update_batch(&mut batch);
+                match self.core.storage.data.write(batch.build()).await {
+                    Ok(_) => {
+                        changes.log_update(Collection::Email, document_id);
+                    }
+                    Err(err) if err.is_assertion_failure() => {
+                        trc::event!(
+                            Store(trc::StoreEvent::AssertValueFailed),
+                            AccountId = account_id,
+                            DocumentId = document_id,
+                            Details = "Failed to remove private keywords.",
+                            CausedBy = trc::location!(),
+                        );
+                    }
+                    Err(err) => {
+                        return Err(err.caused_by(trc::location!()));
+                    }
+                }
+            }
+        }
+
+        if !changes.is_empty() {
+            self.commit_changes(account_id, changes).await?;
+        }
+
+        Ok(())
+    }
+}
+
+impl KeywordOverlay {
+    pub fn new(
+        account_id: u32,
+        private: Vec<Keyword>,
+        current: Option<HashedValue<Vec<PrivateKeyword>>>,
+    ) -> Self {
+        let mut overlay = KeywordOverlay {
+            account_id,
+            private,
+            hash: None,
+            keywords: Vec::new(),
+            others: Vec::new(),
+            added: Vec::new(),
+            removed: Vec::new(),
+        };
+
+        if let Some(current) = current {
+            overlay.hash = Some(current.hash);
+            for item in current.inner {
+                if item.account_id == account_id {
+                    overlay.keywords.push(item.keyword);
+                } else {
+                    overlay.others.push(item);
+                }
+            }
+        }
+
+        overlay
+    }
+
+    pub fn is_private(&self, keyword: &Keyword) -> bool {
+        self.private.contains(keyword)
+    }
+
+    // Merges the owner's shared keywords with the private keywords of the account
+    pub fn view(&self, keywords: &[Keyword]) -> Vec<Keyword> {
+        keywords
+            .iter()
+            .filter(|keyword| !self.is_private(keyword))
+            .chain(self.keywords.iter())
+            .cloned()
+            .collect()
+    }
+
+    // Returns a tag manager over the merged keywords, changes made to it
+    // are applied back using `split`
+    pub fn view_manager(&self, keywords: &HashedValue<Vec<Keyword>>) -> TagManager<Keyword> {
+        TagManager::new(HashedValue {
+            hash: keywords.hash,
+            inner: self.view(&keywords.inner),
+        })
+    }
+
+    // Moves changes to private keywords into the overlay and applies the
+    // remaining ones to the owner's keywords
+    pub fn split(
+        &mut self,
+        view: TagManager<Keyword>,
+        keywords: HashedValue<Vec<Keyword>>,
+    ) -> TagManager<Keyword> {
+        let mut keywords = TagManager::new(keywords);
+        for (changed, add) in view
+            .added()
+            .iter()
+            .map(|keyword| (keyword, true))
+            .chain(view.removed().iter().map(|keyword| (keyword, false)))
+        {
+            if self.is_private(changed) {
+                self.update(changed.clone(), add);
+            } else {
+                keywords.update(changed.clone(), add);
+            }
+        }
+        keywords
+    }
+
+    pub fn update(&mut self, keyword: Keyword, add: bool) {
+        if add {
+            if !self.keywords.contains(&keyword) {
+                if let Some(pos) = self.removed.iter().position(|k| k == &keyword) {
+                    self.removed.swap_remove(pos);
+                } else {
+                    self.added.push(keyword.clone());
+                }
+                self.keywords.push(keyword);
+            }
+        } else if let Some(pos) = self.keywords.iter().position(|k| k == &keyword) {
+            self.keywords.swap_remove(pos);
+            if let Some(pos) = self.added.iter().position(|k| k == &keyword) {
+                self.added.swap_remove(pos);
+            } else {
+                self.removed.push(keyword);
+            }
+        }
+    }
+
+    pub fn keywords(&self) -> &[Keyword] {
+        &self.keywords
+    }
+
+    pub fn changed_keywords(&self) -> impl Iterator<Item = &Keyword> {
+        self.added.iter().chain(self.removed.iter())
+    }
+
+    pub fn has_changes(&self) -> bool {
+        !self.added.is_empty() || !self.removed.is_empty()
+    }
+
+    pub fn update_batch(self, batch: &mut BatchBuilder) {
+        let property = u8::from(Property::PrivateKeywords);
+        let account_id = self.account_id;
+
+        if let Some(hash) = self.hash {
+            batch.assert_value(ValueClass::Property(property), AssertValue::Hash(hash));
+        } else {
+            batch.assert_value(ValueClass::Property(property), ());
+        }
+
+        let mut value = self.others;
+        value.extend(
+            self.keywords
+                .into_iter()
+                .map(|keyword| PrivateKeyword::new(account_id, keyword)),
+        );
+        if !value.is_empty() {
+            batch.value(property, value, F_VALUE);
+        } else {
+            batch.clear(ValueClass::Property(property));
+        }
+        for keyword in self.added {
+            batch.value(property, PrivateKeyword::new(account_id, keyword), F_BITMAP);
+        }
+        for keyword in self.removed {
+            batch.value(
+                property,
+                PrivateKeyword::new(account_id, keyword),
+                F_BITMAP | F_CLEAR,
+            );
+        }
+    }
+}
+
+impl PrivateKeyword {
+    pub fn new(account_id: u32, keyword: Keyword) -> Self {
+        PrivateKeyword {
+            account_id,
+            keyword,
+        }
+    }
+}
+
+pub fn private_keyword_tag(account_id: u32, keyword: &Keyword) -> Vec<u8> {
+    let mut tag = Vec::with_capacity(U32_LEN + 1);
+    tag.extend_from_slice(&account_id.to_be_bytes());
+    keyword.serialize_into(&mut tag);
+    tag
+}
+
+impl ToBitmaps for PrivateKeyword {
+    fn to_bitmaps(&self, ops: &mut Vec<Operation>, field: u8, set: bool) {
+        ops.push(Operation::Bitmap {
+            class: BitmapClass::Tag {
+                field,
+                value: TagValue::<MaybeDynamicId>::Text(private_keyword_tag(
+                    self.account_id,
+                    &self.keyword,
+                )),
+            },
+            set,
+        });
+    }
+}
+
+impl SerializeInto for PrivateKeyword {
+    fn serialize_into(&self, buf: &mut Vec<u8>) {
+        buf.push_leb128(self.account_id);
+        self.keyword.serialize_into(buf);
+    }
+}
+
+impl DeserializeFrom for PrivateKeyword {
+    fn deserialize_from(bytes: &mut Iter<'_, u8>) -> Option<Self> {
+        Some(PrivateKeyword {
+            account_id: bytes.next_leb128()?,
+            keyword: Keyword::deserialize_from(bytes)?,
+        })
+    }
+}
+
+impl Serialize for PrivateKeyword {
+    fn serialize(self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(U32_LEN + 1);
+        self.serialize_into(&mut buf);
+        buf
+    }
+}
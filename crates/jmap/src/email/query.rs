@@ -23,7 +23,7 @@ use store::{
 
 use crate::{auth::acl::AclMethods, JmapMethods};
 
-use super::cache::ThreadCache;
+use super::{cache::ThreadCache, private::EmailPrivateKeywords};
 
 pub trait EmailQuery: Sync + Send {
     fn email_query(
@@ -233,11 +233,11 @@ impl EmailQuery for Server {
                             filters.push(query::Filter::End);
                         }
                         Filter::HasKeyword(keyword) => {
-                            filters.push(query::Filter::is_in_bitmap(Property::Keywords, keyword))
+                            filters.push(self.keyword_filter(account_id, access_token, keyword))
                         }
                         Filter::NotKeyword(keyword) => {
                             filters.push(query::Filter::Not);
-                            filters.push(query::Filter::is_in_bitmap(Property::Keywords, keyword));
+                            filters.push(self.keyword_filter(account_id, access_token, keyword));
                             filters.push(query::Filter::End);
                         }
                         Filter::HasAttachment(has_attach) => {
@@ -0,0 +1,298 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use common::{
+    config::jmap::{
+        retention::{RetentionDate, RetentionPolicy},
+        settings::SpecialUse,
+    },
+    Server,
+};
+use jmap_proto::{
+    object::Object,
+    types::{
+        collection::Collection, property::Property, state::StateChange, type_state::DataType,
+        value::Value,
+    },
+};
+use store::{
+    ahash::AHashMap,
+    roaring::RoaringBitmap,
+    write::{now, TagValue},
+};
+use trc::AddContext;
+use utils::config::utils::ParseValue;
+
+use crate::{
+    changes::write::ChangeLog, mailbox::UidMailbox, services::state::StateManager, JmapMethods,
+};
+
+use super::delete::EmailDeletion;
+
+pub trait EmailRetention: Sync + Send {
+    fn is_legal_hold(&self, account_id: u32) -> impl Future<Output = trc::Result<bool>> + Send;
+
+    fn emails_apply_retention(
+        &self,
+        account_id: u32,
+    ) -> impl Future<Output = trc::Result<AHashMap<String, u64>>> + Send;
+}
+
+impl EmailRetention for Server {
+    async fn is_legal_hold(&self, account_id: u32) -> trc::Result<bool> {
+        let retention = &self.core.jmap.mail_retention;
+        if !retention.legal_hold.is_empty() {
+            self.get_cached_access_token(account_id)
+                .await
+                .map(|token| retention.is_on_hold(&token.name))
+        } else {
+            Ok(false)
+        }
+    }
+
+    async fn emails_apply_retention(&self, account_id: u32) -> trc::Result<AHashMap<String, u64>> {
+        let mut expunged = AHashMap::new();
+        let retention = &self.core.jmap.mail_retention;
+        if retention.policies.is_empty() {
+            return Ok(expunged);
+        }
+
+        // Obtain the policies that apply to this account
+        let access_token = self
+            .get_cached_access_token(account_id)
+            .await
+            .caused_by(trc::location!())?;
+        let domain = access_token
+            .name
+            .rsplit_once('@')
+            .or_else(|| {
+                access_token
+                    .emails
+                    .first()
+                    .and_then(|email| email.rsplit_once('@'))
+            })
+            .map(|(_, domain)| domain);
+        let policies = retention
+            .policies
+            .iter()
+            .filter(|policy| policy.applies_to(&access_token.name, domain))
+            .collect::<Vec<_>>();
+        if policies.is_empty() {
+            return Ok(expunged);
+        }
+
+        // Find the policy for each mailbox, the most specific scope wins and
+        // ties are resolved in favour of the shortest retention period
+        let mut mailbox_policies: AHashMap<u32, &RetentionPolicy> = AHashMap::new();
+        for (mailbox_id, role, path) in self.mailbox_paths(account_id).await? {
+            if let Some(policy) = policies
+                .iter()
+                .filter(|policy| policy.matches_mailbox(role, &path))
+                .max_by(|a, b| {
+                    a.scope
+                        .cmp(&b.scope)
+                        .then_with(|| b.max_age.cmp(&a.max_age))
+                })
+            {
+                mailbox_policies.insert(mailbox_id, policy);
+            }
+        }
+
+        // Find the messages in each mailbox that are older than the retention period
+        let mut aged_ids: AHashMap<u32, RoaringBitmap> = AHashMap::new();
+        let mut candidate_ids = RoaringBitmap::new();
+        for (mailbox_id, policy) in &mailbox_policies {
+            let document_ids = self
+                .get_tag(
+                    account_id,
+                    Collection::Email,
+                    Property::MailboxIds,
+                    TagValue::Id(*mailbox_id),
+                )
+                .await?
+                .unwrap_or_default();
+            if document_ids.is_empty() {
+                continue;
+            }
+
+            let mut aged = RoaringBitmap::new();
+            match policy.date {
+                RetentionDate::Saved => {
+                    let reference_cid = self
+                        .inner
+                        .data
+                        .jmap_id_gen
+                        .past_id(policy.max_age)
+                        .unwrap_or_default();
+                    for (document_id, cid) in self
+                        .get_properties::<u64, _, _>(
+                            account_id,
+                            Collection::Email,
+                            &document_ids,
+                            Property::Cid,
+                        )
+                        .await?
+                    {
+                        if cid < reference_cid {
+                            aged.insert(document_id);
+                        }
+                    }
+                }
+                RetentionDate::Received => {
+                    let reference_date = now().saturating_sub(policy.max_age.as_secs());
+                    for (document_id, received_at) in self
+                        .get_properties::<u64, _, _>(
+                            account_id,
+                            Collection::Email,
+                            &document_ids,
+                            Property::ReceivedAt,
+                        )
+                        .await?
+                    {
+                        if received_at < reference_date {
+                            aged.insert(document_id);
+                        }
+                    }
+                }
+            }
+
+            candidate_ids |= &aged;
+            aged_ids.insert(*mailbox_id, aged);
+        }
+        if candidate_ids.is_empty() {
+            return Ok(expunged);
+        }
+
+        // Messages are only expunged once they have expired in all their mailboxes
+        let mut destroy_ids = RoaringBitmap::new();
+        for (document_id, mailboxes) in self
+            .get_properties::<Vec<UidMailbox>, _, _>(
+                account_id,
+                Collection::Email,
+                &candidate_ids,
+                Property::MailboxIds,
+            )
+            .await?
+        {
+            if !mailboxes.is_empty()
+                && mailboxes.iter().all(|mailbox| {
+                    aged_ids
+                        .get(&mailbox.mailbox_id)
+                        .map_or(false, |aged| aged.contains(document_id))
+                })
+            {
+                destroy_ids.insert(document_id);
+                *expunged
+                    .entry(mailbox_policies[&mailboxes[0].mailbox_id].id.clone())
+                    .or_default() += 1;
+            }
+        }
+        if destroy_ids.is_empty() {
+            return Ok(expunged);
+        }
+
+        trc::event!(
+            Purge(trc::PurgeEvent::Retention),
+            AccountId = account_id,
+            Total = destroy_ids.len(),
+            Details = expunged
+                .keys()
+                .map(|id| id.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+        );
+
+        // Tombstone messages in batches
+        let destroy_ids = destroy_ids.into_iter().collect::<Vec<_>>();
+        for document_ids in destroy_ids.chunks(retention.batch_size.max(1)) {
+            let (changes, _) = self
+                .emails_tombstone(account_id, document_ids.iter().copied().collect())
+                .await?;
+
+            // Write and broadcast changes
+            if !changes.is_empty() {
+                let change_id = self.commit_changes(account_id, changes).await?;
+                self.broadcast_state_change(
+                    StateChange::new(account_id)
+                        .with_change(DataType::Email, change_id)
+                        .with_change(DataType::Mailbox, change_id)
+                        .with_change(DataType::Thread, change_id),
+                )
+                .await;
+            }
+        }
+
+        Ok(expunged)
+    }
+}
+
+pub(crate) trait MailboxPaths {
+    fn mailbox_paths(
+        &self,
+        account_id: u32,
+    ) -> impl Future<Output = trc::Result<Vec<(u32, Option<SpecialUse>, String)>>> + Send;
+}
+
+impl MailboxPaths for Server {
+    // Returns the role and full path of each mailbox in the account
+    async fn mailbox_paths(
+        &self,
+        account_id: u32,
+    ) -> trc::Result<Vec<(u32, Option<SpecialUse>, String)>> {
+        let mut mailboxes = AHashMap::new();
+        for (document_id, mut mailbox) in self
+            .get_properties::<Object<Value>, _, _>(
+                account_id,
+                Collection::Mailbox,
+                &self
+                    .get_document_ids(account_id, Collection::Mailbox)
+                    .await?
+                    .unwrap_or_default(),
+                Property::Value,
+            )
+            .await?
+        {
+            let name = match mailbox.properties.remove(&Property::Name) {
+                Some(Value::Text(name)) => name,
+                _ => continue,
+            };
+            let parent_id = match mailbox.properties.get(&Property::ParentId) {
+                Some(Value::Id(parent_id)) if parent_id.document_id() > 0 => {
+                    Some(parent_id.document_id() - 1)
+                }
+                _ => None,
+            };
+            let role = match mailbox.properties.get(&Property::Role) {
+                Some(Value::Text(role)) => SpecialUse::parse_value(role).ok(),
+                _ => None,
+            };
+            mailboxes.insert(document_id, (name, parent_id, role));
+        }
+
+        Ok(mailboxes
+            .iter()
+            .map(|(document_id, (name, parent_id, role))| {
+                let mut path = name.clone();
+                let mut parent_id = *parent_id;
+                let mut depth = 0;
+                while let Some((parent_name, next_parent_id, _)) =
+                    parent_id.and_then(|parent_id| mailboxes.get(&parent_id))
+                {
+                    path = format!("{parent_name}/{path}");
+                    parent_id = *next_parent_id;
+                    depth += 1;
+                    if depth > self.core.jmap.mailbox_max_depth {
+                        break;
+                    }
+                }
+
+                (*document_id, *role, path)
+            })
+            .collect())
+    }
+}
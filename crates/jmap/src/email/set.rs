@@ -40,13 +40,14 @@ use store::{
     Serialize,
 };
 use trc::AddContext;
+use utils::map::bitmap::Bitmap;
 
 use crate::{
     api::http::HttpSessionData,
     auth::acl::AclMethods,
     blob::download::BlobDownload,
     changes::{state::StateManager, write::ChangeLog},
-    mailbox::{set::MailboxSet, UidMailbox},
+    mailbox::{set::MailboxSet, UidMailbox, INBOX_ID},
     JmapMethods,
 };
 use std::future::Future;
@@ -55,6 +56,8 @@ use super::{
     delete::EmailDeletion,
     headers::{BuildHeader, ValueToHeader},
     ingest::{EmailIngest, IngestEmail, IngestSource},
+    private::EmailPrivateKeywords,
+    snooze::EmailSnooze,
 };
 
 pub trait EmailSet: Sync + Send {
@@ -81,9 +84,12 @@ impl EmailSet for Server {
 
         // Obtain mailboxIds
         let mailbox_ids = self.mailbox_get_or_create(account_id).await?;
-        let (can_add_mailbox_ids, can_delete_mailbox_ids, can_modify_message_ids) = if access_token
-            .is_shared(account_id)
-        {
+        let (
+            can_add_mailbox_ids,
+            can_delete_mailbox_ids,
+            can_modify_message_ids,
+            can_set_seen_message_ids,
+        ) = if access_token.is_shared(account_id) {
             (
                 self.shared_documents(access_token, account_id, Collection::Mailbox, Acl::AddItems)
                     .await?
@@ -99,9 +105,16 @@ impl EmailSet for Server {
                 self.shared_messages(access_token, account_id, Acl::ModifyItems)
                     .await?
                     .into(),
+                self.shared_messages(
+                    access_token,
+                    account_id,
+                    Bitmap::from_iter([Acl::ModifyItems, Acl::SetSeen]),
+                )
+                .await?
+                .into(),
             )
         } else {
-            (None, None, None)
+            (None, None, None, None)
         };
 
         let will_destroy = request.unwrap_destroy();
@@ -784,17 +797,28 @@ impl EmailSet for Server {
                 )
                 .await?,
             ) {
-                (TagManager::new(mailboxes), TagManager::new(keywords))
+                (TagManager::new(mailboxes), keywords)
             } else {
                 response.not_updated.append(id, SetError::not_found());
                 continue 'update;
             };
 
+            // Keywords are updated on a view that includes the private keywords
+            // of the account, which are later split into its overlay
+            let overlay = self
+                .keyword_overlay(account_id, access_token, document_id)
+                .await?;
+            let (mut keywords, owner_keywords) = match &overlay {
+                Some(overlay) => (overlay.view_manager(&keywords), Some(keywords)),
+                None => (TagManager::new(keywords), None),
+            };
+
             // Prepare write batch
             let mut batch = BatchBuilder::new();
             batch
                 .with_account_id(account_id)
                 .with_collection(Collection::Email);
+            let mut snooze = None;
 
             for (property, value) in object.properties {
                 let value = match response.eval_object_references(value) {
@@ -841,6 +865,29 @@ impl EmailSet for Server {
                             );
                         }
                     }
+                    (Property::Snoozed, MaybePatchValue::Value(Value::Null)) => {
+                        snooze = Some(None);
+                    }
+                    (Property::Snoozed, MaybePatchValue::Value(Value::Object(mut value))) => {
+                        match (
+                            value.remove(&Property::Until),
+                            value.remove(&Property::SetMailboxId),
+                        ) {
+                            (Value::Date(until), Value::Id(mailbox_id)) => {
+                                snooze = Some(Some((
+                                    until.timestamp() as u64,
+                                    mailbox_id.document_id(),
+                                )));
+                            }
+                            (Value::Date(until), Value::Null) => {
+                                snooze = Some(Some((until.timestamp() as u64, INBOX_ID)));
+                            }
+                            _ => {
+                                response.invalid_property_update(id, Property::Snoozed);
+                                continue 'update;
+                            }
+                        }
+                    }
                     (property, _) => {
                         response.invalid_property_update(id, property);
                         continue 'update;
@@ -848,7 +895,7 @@ impl EmailSet for Server {
                 }
             }
 
-            if !mailboxes.has_changes() && !keywords.has_changes() {
+            if !mailboxes.has_changes() && !keywords.has_changes() && snooze.is_none() {
                 response.not_updated.append(
                     id,
                     SetError::invalid_properties()
@@ -864,8 +911,17 @@ impl EmailSet for Server {
 
             // Process keywords
             if keywords.has_changes() {
-                // Verify permissions on shared accounts
-                if matches!(&can_modify_message_ids, Some(ids) if !ids.contains(document_id)) {
+                // Verify permissions on shared accounts, changing only the
+                // $seen keyword is allowed with the setSeen right
+                let can_modify_message_ids = if keywords
+                    .changed_tags()
+                    .all(|keyword| keyword == &Keyword::Seen)
+                {
+                    &can_set_seen_message_ids
+                } else {
+                    &can_modify_message_ids
+                };
+                if matches!(can_modify_message_ids, Some(ids) if !ids.contains(document_id)) {
                     response.not_updated.append(
                         id,
                         SetError::forbidden()
@@ -885,7 +941,17 @@ impl EmailSet for Server {
                 }
 
                 // Update keywords property
-                keywords.update_batch(&mut batch, Property::Keywords);
+                if let (Some(mut overlay), Some(owner_keywords)) = (overlay, owner_keywords) {
+                    let keywords = overlay.split(keywords, owner_keywords);
+                    if keywords.has_changes() {
+                        keywords.update_batch(&mut batch, Property::Keywords);
+                    }
+                    if overlay.has_changes() {
+                        overlay.update_batch(&mut batch);
+                    }
+                } else {
+                    keywords.update_batch(&mut batch, Property::Keywords);
+                }
 
                 // Update last change id
                 if changes.change_id == u64::MAX {
@@ -895,7 +961,9 @@ impl EmailSet for Server {
             }
 
             // Process mailboxes
-            if mailboxes.has_changes() {
+            let mailboxes_changed = mailboxes.has_changes();
+            let snoozed_in = mailboxes.current().to_vec();
+            if mailboxes_changed {
                 // Make sure the message is at least in one mailbox
                 if !mailboxes.has_tags() {
                     response.not_updated.append(
@@ -970,6 +1038,41 @@ impl EmailSet for Server {
                 mailboxes.update_batch(&mut batch, Property::MailboxIds);
             }
 
+            // Process snooze, moving a snoozed message cancels its snooze
+            if snooze.is_some() || mailboxes_changed {
+                let current_snooze = self
+                    .get_property::<EmailSnooze>(
+                        account_id,
+                        Collection::Email,
+                        document_id,
+                        Property::Snoozed,
+                    )
+                    .await?;
+                if let Some(Some((until, mailbox_id))) = snooze {
+                    if !mailbox_ids.contains(mailbox_id) {
+                        response.not_updated.append(
+                            id,
+                            SetError::invalid_properties()
+                                .with_property(Property::Snoozed)
+                                .with_description(format!(
+                                    "mailboxId {mailbox_id} does not exist."
+                                )),
+                        );
+                        continue 'update;
+                    }
+                    if let Some(current_snooze) = current_snooze {
+                        current_snooze.clear(&mut batch, account_id, document_id);
+                    }
+                    EmailSnooze::new(until, mailbox_id, &snoozed_in).write(
+                        &mut batch,
+                        account_id,
+                        document_id,
+                    );
+                } else if let Some(current_snooze) = current_snooze {
+                    current_snooze.clear(&mut batch, account_id, document_id);
+                }
+            }
+
             // Log mailbox changes
             for mailbox_id in changed_mailboxes {
                 changes.log_child_update(Collection::Mailbox, mailbox_id);
@@ -0,0 +1,351 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use common::Server;
+use jmap_proto::{
+    object::Object,
+    types::{
+        collection::Collection, date::UTCDate, id::Id, keyword::Keyword, property::Property,
+        state::StateChange, type_state::DataType, value::Value,
+    },
+};
+use store::{
+    ahash::AHashMap,
+    write::{
+        assert::HashedValue,
+        key::{DeserializeBigEndian, KeySerializer},
+        BatchBuilder, MaybeDynamicId, QueueClass, ValueClass, F_VALUE,
+    },
+    Deserialize, IterateParams, Serialize, ValueKey, U32_LEN, U64_LEN,
+};
+use trc::AddContext;
+
+use crate::{
+    changes::write::ChangeLog,
+    mailbox::{UidMailbox, INBOX_ID},
+    services::state::StateManager,
+    JmapMethods,
+};
+
+use super::{ingest::EmailIngest, set::TagManager};
+
+// Keywords set by clients on snoozed messages and by the server once they wake up
+pub const KEYWORD_SNOOZED: &str = "$snoozed";
+pub const KEYWORD_UNSNOOZED: &str = "$unsnoozed";
+
+// The snooze record of a message: when it wakes up, the mailbox it is moved
+// to and the mailboxes it was in when snoozed. A message no longer in those
+// mailboxes at wake time was moved by the user and its snooze is cancelled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmailSnooze {
+    pub until: u64,
+    pub mailbox_id: u32,
+    pub snoozed_in: Vec<u32>,
+}
+
+pub trait EmailSnoozing: Sync + Send {
+    fn email_snooze_wake(&self, now: u64) -> impl Future<Output = trc::Result<u64>> + Send;
+}
+
+impl EmailSnoozing for Server {
+    async fn email_snooze_wake(&self, now: u64) -> trc::Result<u64> {
+        // Obtain the snoozed messages due before the reference time
+        let from_key = ValueKey::from(ValueClass::Queue(QueueClass::EmailSnooze {
+            due: 0,
+            account_id: 0,
+            document_id: 0,
+        }));
+        let to_key = ValueKey::from(ValueClass::Queue(QueueClass::EmailSnooze {
+            due: now,
+            account_id: u32::MAX,
+            document_id: u32::MAX,
+        }));
+        let mut due_messages: AHashMap<u32, Vec<(u64, u32)>> = AHashMap::new();
+        self.store()
+            .iterate(
+                IterateParams::new(from_key, to_key).ascending().no_values(),
+                |key, _| {
+                    due_messages
+                        .entry(key.deserialize_be_u32(U64_LEN + 1)?)
+                        .or_default()
+                        .push((
+                            key.deserialize_be_u64(1)?,
+                            key.deserialize_be_u32(U64_LEN + U32_LEN + 1)?,
+                        ));
+                    Ok(true)
+                },
+            )
+            .await
+            .caused_by(trc::location!())?;
+
+        let mut total = 0;
+        for (account_id, messages) in due_messages {
+            match self.email_snooze_wake_account(account_id, messages).await {
+                Ok(woken) => {
+                    total += woken;
+                }
+                Err(err) => {
+                    trc::error!(err
+                        .details("Failed to wake snoozed messages.")
+                        .account_id(account_id));
+                }
+            }
+        }
+
+        Ok(total)
+    }
+}
+
+trait EmailSnoozeAccount {
+    fn email_snooze_wake_account(
+        &self,
+        account_id: u32,
+        messages: Vec<(u64, u32)>,
+    ) -> impl Future<Output = trc::Result<u64>> + Send;
+}
+
+impl EmailSnoozeAccount for Server {
+    async fn email_snooze_wake_account(
+        &self,
+        account_id: u32,
+        messages: Vec<(u64, u32)>,
+    ) -> trc::Result<u64> {
+        let mailbox_ids = self
+            .get_document_ids(account_id, Collection::Mailbox)
+            .await?
+            .unwrap_or_default();
+        let mut changes = self.begin_changes(account_id).await?;
+        let mut has_mailbox_changes = false;
+        let mut woken = 0;
+
+        for (due, document_id) in messages {
+            let mut batch = BatchBuilder::new();
+            batch
+                .with_account_id(account_id)
+                .with_collection(Collection::Email);
+
+            let (snooze, mut mailboxes, keywords, thread_id) = match (
+                self.get_property::<HashedValue<EmailSnooze>>(
+                    account_id,
+                    Collection::Email,
+                    document_id,
+                    Property::Snoozed,
+                )
+                .await?,
+                self.get_property::<HashedValue<Vec<UidMailbox>>>(
+                    account_id,
+                    Collection::Email,
+                    document_id,
+                    Property::MailboxIds,
+                )
+                .await?,
+                self.get_property::<HashedValue<Vec<Keyword>>>(
+                    account_id,
+                    Collection::Email,
+                    document_id,
+                    Property::Keywords,
+                )
+                .await?,
+                self.get_property::<u32>(
+                    account_id,
+                    Collection::Email,
+                    document_id,
+                    Property::ThreadId,
+                )
+                .await?,
+            ) {
+                (Some(snooze), Some(mailboxes), Some(keywords), Some(thread_id))
+                    if snooze.inner.until == due =>
+                {
+                    (snooze, TagManager::new(mailboxes), keywords, thread_id)
+                }
+                _ => {
+                    // The message was deleted or snoozed again until a different time
+                    batch.clear(ValueClass::Queue(QueueClass::EmailSnooze {
+                        due,
+                        account_id,
+                        document_id,
+                    }));
+                    self.write_batch(batch).await.caused_by(trc::location!())?;
+                    continue;
+                }
+            };
+
+            batch
+                .update_document(document_id)
+                .assert_value(ValueClass::Property(Property::Snoozed.into()), &snooze);
+            let snooze = snooze.inner;
+            snooze.clear(&mut batch, account_id, document_id);
+
+            // Snoozes are cancelled if the message was moved after being snoozed
+            let mut is_woken = false;
+            if snooze.is_snoozed_in(mailboxes.current()) {
+                let mut keywords = TagManager::new(keywords);
+                let mailbox_id = if mailbox_ids.contains(snooze.mailbox_id) {
+                    snooze.mailbox_id
+                } else {
+                    INBOX_ID
+                };
+                mailboxes.set(vec![UidMailbox::new_unassigned(mailbox_id)]);
+                for uid_mailbox in mailboxes.inner_tags_mut() {
+                    if uid_mailbox.uid == 0 {
+                        uid_mailbox.uid = self
+                            .assign_imap_uid(account_id, uid_mailbox.mailbox_id)
+                            .await
+                            .caused_by(trc::location!())?;
+                    }
+                }
+
+                // Woken messages are marked as unread and unsnoozed
+                keywords.update(Keyword::Seen, false);
+                keywords.update(Keyword::from(KEYWORD_SNOOZED.to_string()), false);
+                keywords.update(Keyword::from(KEYWORD_UNSNOOZED.to_string()), true);
+
+                for mailbox in mailboxes.current().iter().chain(mailboxes.removed()) {
+                    changes.log_child_update(Collection::Mailbox, mailbox.mailbox_id);
+                }
+                if mailboxes.has_changes() {
+                    mailboxes.update_batch(&mut batch, Property::MailboxIds);
+                }
+                if keywords.has_changes() {
+                    keywords.update_batch(&mut batch, Property::Keywords);
+                }
+                has_mailbox_changes = true;
+                is_woken = true;
+            }
+            batch.value(Property::Cid, changes.change_id, F_VALUE);
+
+            match self.write_batch(batch).await {
+                Ok(_) => {
+                    changes.log_update(Collection::Email, Id::from_parts(thread_id, document_id));
+                    if is_woken {
+                        woken += 1;
+                    }
+                }
+                Err(err) if err.is_assertion_failure() => {
+                    // The message was modified concurrently, it is retried on the next run
+                }
+                Err(err) => {
+                    return Err(err.caused_by(trc::location!()));
+                }
+            }
+        }
+
+        // Write changes and notify clients
+        if !changes.is_empty() {
+            let change_id = changes.change_id;
+            let mut batch = BatchBuilder::new();
+            batch
+                .with_account_id(account_id)
+                .with_collection(Collection::Email)
+                .custom(changes);
+            self.write_batch(batch).await.caused_by(trc::location!())?;
+
+            let mut state_change =
+                StateChange::new(account_id).with_change(DataType::Email, change_id);
+            if has_mailbox_changes {
+                state_change = state_change.with_change(DataType::Mailbox, change_id);
+            }
+            self.broadcast_state_change(state_change).await;
+        }
+
+        trc::event!(
+            Housekeeper(trc::HousekeeperEvent::WakeSnoozed),
+            AccountId = account_id,
+            Total = woken,
+        );
+
+        Ok(woken)
+    }
+}
+
+impl EmailSnooze {
+    pub fn new(until: u64, mailbox_id: u32, snoozed_in: &[UidMailbox]) -> Self {
+        let mut snoozed_in = snoozed_in
+            .iter()
+            .map(|mailbox| mailbox.mailbox_id)
+            .collect::<Vec<_>>();
+        snoozed_in.sort_unstable();
+        EmailSnooze {
+            until,
+            mailbox_id,
+            snoozed_in,
+        }
+    }
+
+    pub fn is_snoozed_in(&self, mailboxes: &[UidMailbox]) -> bool {
+        mailboxes.len() == self.snoozed_in.len()
+            && mailboxes
+                .iter()
+                .all(|mailbox| self.snoozed_in.contains(&mailbox.mailbox_id))
+    }
+
+    // Stores the snooze record of the current document and schedules its wake up
+    pub fn write(&self, batch: &mut BatchBuilder, account_id: u32, document_id: u32) {
+        batch
+            .set(Property::Snoozed, self.clone().serialize())
+            .set(self.queue_key(account_id, document_id), vec![]);
+    }
+
+    pub fn clear(&self, batch: &mut BatchBuilder, account_id: u32, document_id: u32) {
+        batch
+            .clear(Property::Snoozed)
+            .clear(self.queue_key(account_id, document_id));
+    }
+
+    pub fn to_value(&self) -> Value {
+        Value::Object(
+            Object::with_capacity(2)
+                .with_property(
+                    Property::Until,
+                    Value::Date(UTCDate::from_timestamp(self.until as i64)),
+                )
+                .with_property(Property::SetMailboxId, Value::Id(self.mailbox_id.into())),
+        )
+    }
+
+    fn queue_key(&self, account_id: u32, document_id: u32) -> ValueClass<MaybeDynamicId> {
+        ValueClass::Queue(QueueClass::EmailSnooze {
+            due: self.until,
+            account_id,
+            document_id,
+        })
+    }
+}
+
+impl Serialize for EmailSnooze {
+    fn serialize(self) -> Vec<u8> {
+        self.snoozed_in
+            .iter()
+            .fold(
+                KeySerializer::new(U64_LEN + U32_LEN * (self.snoozed_in.len() + 1))
+                    .write(self.until)
+                    .write(self.mailbox_id),
+                |serializer, mailbox_id| serializer.write(*mailbox_id),
+            )
+            .finalize()
+    }
+}
+
+impl Deserialize for EmailSnooze {
+    fn deserialize(bytes: &[u8]) -> trc::Result<Self> {
+        let mailbox_ids = bytes.get(U64_LEN + U32_LEN..).unwrap_or_default();
+        if mailbox_ids.len() % U32_LEN != 0 {
+            return Err(trc::StoreEvent::DataCorruption.caused_by(trc::location!()));
+        }
+
+        Ok(EmailSnooze {
+            until: bytes.deserialize_be_u64(0)?,
+            mailbox_id: bytes.deserialize_be_u32(U64_LEN)?,
+            snoozed_in: mailbox_ids
+                .chunks_exact(U32_LEN)
+                .map(|mailbox_id| u32::from_be_bytes(mailbox_id.try_into().unwrap()))
+                .collect(),
+        })
+    }
+}
@@ -0,0 +1,135 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use common::{bayes::bayes_text, Server};
+use jmap_proto::types::{collection::Collection, keyword::Keyword, property::Property};
+use mail_parser::MessageParser;
+use std::future::Future;
+use store::write::Bincode;
+use trc::AddContext;
+
+use crate::{blob::download::BlobDownload, JmapMethods};
+
+use super::metadata::MessageMetadata;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpamTraining {
+    pub is_spam: bool,
+    pub is_train: bool,
+}
+
+pub trait SpamTrainer: Sync + Send {
+    fn spam_train_message(
+        &self,
+        account_id: u32,
+        document_id: u32,
+        training: &[SpamTraining],
+        session_id: u64,
+    ) -> impl Future<Output = trc::Result<bool>> + Send;
+
+    fn spam_train_raw(
+        &self,
+        account_id: Option<u32>,
+        raw_message: &[u8],
+        training: SpamTraining,
+        session_id: u64,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+}
+
+impl SpamTrainer for Server {
+    async fn spam_train_message(
+        &self,
+        account_id: u32,
+        document_id: u32,
+        training: &[SpamTraining],
+        session_id: u64,
+    ) -> trc::Result<bool> {
+        if training.is_empty() {
+            return Ok(false);
+        }
+
+        // Obtain the message
+        let Some(metadata) = self
+            .get_property::<Bincode<MessageMetadata>>(
+                account_id,
+                Collection::Email,
+                document_id,
+                &Property::BodyStructure,
+            )
+            .await
+            .caused_by(trc::location!())?
+        else {
+            return Ok(false);
+        };
+        let Some(raw_message) = self
+            .get_blob(&metadata.inner.blob_hash, 0..usize::MAX)
+            .await
+            .caused_by(trc::location!())?
+        else {
+            return Ok(false);
+        };
+
+        for training in training {
+            self.spam_train_raw(Some(account_id), &raw_message, *training, session_id)
+                .await?;
+        }
+
+        Ok(true)
+    }
+
+    async fn spam_train_raw(
+        &self,
+        account_id: Option<u32>,
+        raw_message: &[u8],
+        training: SpamTraining,
+        session_id: u64,
+    ) -> trc::Result<()> {
+        let message = MessageParser::new().parse(raw_message).ok_or_else(|| {
+            trc::SpamEvent::TrainError
+                .into_err()
+                .reason("Failed to parse message")
+        })?;
+        let text = bayes_text(&message);
+        let store = self.bayes_store();
+
+        if training.is_train {
+            self.bayes_train(store, account_id, &text, training.is_spam, session_id)
+                .await?;
+
+            // Rspamd has no way to forget a message, so only training is forwarded
+            self.rspamd_learn(raw_message, training.is_spam, session_id)
+                .await
+                .map(|_| ())
+        } else {
+            self.bayes_untrain(store, account_id, &text, training.is_spam, session_id)
+                .await
+        }
+    }
+}
+
+impl SpamTraining {
+    // Maps changes to the $Junk and $NotJunk keywords to training actions,
+    // removing a keyword reverses the training done when it was added
+    pub fn from_keywords(added: &[Keyword], removed: &[Keyword]) -> Vec<SpamTraining> {
+        let mut training = Vec::new();
+        for (keywords, is_train) in [(removed, false), (added, true)] {
+            for keyword in keywords {
+                match keyword {
+                    Keyword::Junk => training.push(SpamTraining {
+                        is_spam: true,
+                        is_train,
+                    }),
+                    Keyword::NotJunk => training.push(SpamTraining {
+                        is_spam: false,
+                        is_train,
+                    }),
+                    _ => {}
+                }
+            }
+        }
+        training
+    }
+}
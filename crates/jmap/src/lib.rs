@@ -4,7 +4,12 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::{fmt::Display, future::Future, sync::Arc, time::Duration};
+use std::{
+    fmt::Display,
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use changes::state::StateManager;
 use common::{
@@ -37,11 +42,13 @@ use store::{
     BitmapKey, Deserialize, IterateParams, ValueKey, U32_LEN,
 };
 use trc::AddContext;
+use utils::map::ttl_dashmap::TtlMap;
 
 pub mod api;
 pub mod auth;
 pub mod blob;
 pub mod changes;
+pub mod dav;
 pub mod email;
 pub mod identity;
 pub mod mailbox;
@@ -56,6 +63,7 @@ pub mod vacation;
 pub mod websocket;
 
 pub const LONG_SLUMBER: Duration = Duration::from_secs(60 * 60 * 24);
+pub const BITMAP_CARDINALITY_TTL: Duration = Duration::from_secs(1);
 
 pub trait StartServices: Sync + Send {
     fn start_services(&mut self) -> impl Future<Output = ()> + Send;
@@ -235,6 +243,86 @@ impl JmapMethods for Server {
             })
     }
 
+    async fn get_tag_cardinality(
+        &self,
+        account_id: u32,
+        collection: Collection,
+        property: impl AsRef<Property> + Sync + Send,
+        value: impl Into<TagValue<u32>> + Sync + Send,
+    ) -> trc::Result<u64> {
+        let property = property.as_ref();
+        let key = BitmapKey {
+            account_id,
+            collection: collection.into(),
+            class: BitmapClass::Tag {
+                field: property.into(),
+                value: value.into(),
+            },
+            document_id: 0,
+        };
+
+        // Cardinalities are cached briefly to absorb repeated STATUS requests
+        let cache = &self.inner.data.bitmap_cardinalities;
+        if let Some(cardinality) = cache.get_with_ttl(&key) {
+            return Ok(cardinality);
+        }
+        let cardinality = self
+            .core
+            .storage
+            .data
+            .get_bitmap_cardinality(key.clone())
+            .await
+            .add_context(|err| {
+                err.caused_by(trc::location!())
+                    .account_id(account_id)
+                    .collection(collection)
+                    .id(property.to_string())
+            })?;
+
+        Ok(cache.insert_with_ttl(key, cardinality, Instant::now() + BITMAP_CARDINALITY_TTL))
+    }
+
+    async fn get_flag_document_count(
+        &self,
+        account_id: u32,
+        collection: Collection,
+        property: impl AsRef<Property> + Sync + Send,
+        value: impl Into<TagValue<u32>> + Sync + Send,
+    ) -> trc::Result<u64> {
+        let property = property.as_ref();
+        let field: u8 = property.into();
+        let value = value.into();
+        let key = BitmapKey {
+            account_id,
+            collection: collection.into(),
+            class: BitmapClass::Tag {
+                field,
+                value: value.clone(),
+            },
+            document_id: 0,
+        };
+
+        // Counts are cached for the same period as bitmap cardinalities
+        let cache = &self.inner.data.flag_document_counts;
+        if let Some(count) = cache.get_with_ttl(&key) {
+            return Ok(count);
+        }
+        let count = self
+            .core
+            .storage
+            .data
+            .get_document_count_by_flag(account_id, collection, field, value)
+            .await
+            .add_context(|err| {
+                err.caused_by(trc::location!())
+                    .account_id(account_id)
+                    .collection(collection)
+                    .id(property.to_string())
+            })?;
+
+        Ok(cache.insert_with_ttl(key, count, Instant::now() + BITMAP_CARDINALITY_TTL))
+    }
+
     async fn prepare_set_response<T: Sync + Send>(
         &self,
         request: &SetRequest<T>,
@@ -526,6 +614,22 @@ pub trait JmapMethods: Sync + Send {
         value: impl Into<TagValue<u32>> + Sync + Send,
     ) -> impl Future<Output = trc::Result<Option<RoaringBitmap>>> + Send;
 
+    fn get_tag_cardinality(
+        &self,
+        account_id: u32,
+        collection: Collection,
+        property: impl AsRef<Property> + Sync + Send,
+        value: impl Into<TagValue<u32>> + Sync + Send,
+    ) -> impl Future<Output = trc::Result<u64>> + Send;
+
+    fn get_flag_document_count(
+        &self,
+        account_id: u32,
+        collection: Collection,
+        property: impl AsRef<Property> + Sync + Send,
+        value: impl Into<TagValue<u32>> + Sync + Send,
+    ) -> impl Future<Output = trc::Result<u64>> + Send;
+
     fn prepare_set_response<T: Sync + Send>(
         &self,
         request: &SetRequest<T>,
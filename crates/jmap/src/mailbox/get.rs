@@ -16,7 +16,7 @@ use trc::AddContext;
 use crate::{
     auth::acl::{AclMethods, EffectiveAcl},
     changes::state::StateManager,
-    email::cache::ThreadCache,
+    email::{cache::ThreadCache, private::EmailPrivateKeywords},
     JmapMethods,
 };
 
@@ -39,6 +39,7 @@ pub trait MailboxGet: Sync + Send {
     fn mailbox_unread_tags(
         &self,
         account_id: u32,
+        access_token: &AccessToken,
         document_id: u32,
         message_ids: &Option<RoaringBitmap>,
     ) -> impl Future<Output = trc::Result<Option<RoaringBitmap>>> + Send;
@@ -170,22 +171,25 @@ impl MailboxGet for Server {
                         })
                         .unwrap_or_default(),
                     Property::TotalEmails => Value::UnsignedInt(
-                        self.get_tag(
+                        self.get_tag_cardinality(
                             account_id,
                             Collection::Email,
                             Property::MailboxIds,
                             document_id,
                         )
+                        .await?,
+                    ),
+                    Property::UnreadEmails => Value::UnsignedInt(
+                        self.mailbox_unread_tags(
+                            account_id,
+                            access_token,
+                            document_id,
+                            &message_ids,
+                        )
                         .await?
                         .map(|v| v.len())
                         .unwrap_or(0),
                     ),
-                    Property::UnreadEmails => Value::UnsignedInt(
-                        self.mailbox_unread_tags(account_id, document_id, &message_ids)
-                            .await?
-                            .map(|v| v.len())
-                            .unwrap_or(0),
-                    ),
                     Property::TotalThreads => Value::UnsignedInt(
                         self.mailbox_count_threads(
                             account_id,
@@ -202,8 +206,13 @@ impl MailboxGet for Server {
                     Property::UnreadThreads => Value::UnsignedInt(
                         self.mailbox_count_threads(
                             account_id,
-                            self.mailbox_unread_tags(account_id, document_id, &message_ids)
-                                .await?,
+                            self.mailbox_unread_tags(
+                                account_id,
+                                access_token,
+                                document_id,
+                                &message_ids,
+                            )
+                            .await?,
                         )
                         .await? as u64,
                     ),
@@ -217,7 +226,7 @@ impl MailboxGet for Server {
                                     Property::MayRemoveItems,
                                     acl.contains(Acl::RemoveItems),
                                 )
-                                .with_property(Property::MaySetSeen, acl.contains(Acl::ModifyItems))
+                                .with_property(Property::MaySetSeen, acl.contains(Acl::SetSeen))
                                 .with_property(
                                     Property::MaySetKeywords,
                                     acl.contains(Acl::ModifyItems),
@@ -306,6 +315,7 @@ impl MailboxGet for Server {
     async fn mailbox_unread_tags(
         &self,
         account_id: u32,
+        access_token: &AccessToken,
         document_id: u32,
         message_ids: &Option<RoaringBitmap>,
     ) -> trc::Result<Option<RoaringBitmap>> {
@@ -320,12 +330,7 @@ impl MailboxGet for Server {
             .await?,
         ) {
             if let Some(mut seen) = self
-                .get_tag(
-                    account_id,
-                    Collection::Email,
-                    Property::Keywords,
-                    Keyword::Seen,
-                )
+                .keyword_document_ids(account_id, access_token, Keyword::Seen)
                 .await?
             {
                 seen ^= message_ids;
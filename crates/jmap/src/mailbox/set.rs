@@ -39,7 +39,7 @@ use trc::AddContext;
 use crate::{
     auth::acl::{AclMethods, EffectiveAcl},
     changes::write::ChangeLog,
-    email::delete::EmailDeletion,
+    email::{delete::EmailDeletion, private::EmailPrivateKeywords},
     JmapMethods,
 };
 
@@ -108,6 +108,12 @@ pub trait MailboxSet: Sync + Send {
         account_id: u32,
         path: &str,
     ) -> impl Future<Output = trc::Result<Option<(u32, Option<u64>)>>> + Send;
+
+    fn mailbox_autofile(
+        &self,
+        account_id: u32,
+        detail: &str,
+    ) -> impl Future<Output = trc::Result<Option<u32>>> + Send;
 }
 
 impl MailboxSet for Server {
@@ -232,6 +238,14 @@ impl MailboxSet for Server {
                     }
                 }
 
+                // Accounts that may lose access to the mailbox
+                let grantee_ids = match mailbox.inner.get(&Property::Acl) {
+                    Value::Acl(acl) if object.properties.contains_key(&Property::Acl) => {
+                        acl.iter().map(|item| item.account_id).collect::<Vec<_>>()
+                    }
+                    _ => vec![],
+                };
+
                 match self
                     .mailbox_set_item(object, (document_id, mailbox).into(), &ctx)
                     .await?
@@ -259,6 +273,16 @@ impl MailboxSet for Server {
                             match self.core.storage.data.write(batch.build()).await {
                                 Ok(_) => {
                                     changes.log_update(Collection::Mailbox, document_id);
+
+                                    // Remove private keywords of revoked accounts
+                                    for grantee_id in grantee_ids {
+                                        self.private_keywords_revoke(
+                                            account_id,
+                                            document_id,
+                                            grantee_id,
+                                        )
+                                        .await?;
+                                    }
                                 }
                                 Err(err) if err.is_assertion_failure() => {
                                     ctx.response.not_updated.append(id, SetError::forbidden().with_description(
@@ -943,6 +967,32 @@ impl MailboxSet for Server {
             Ok(Some((next_parent_id - 1, None)))
         }
     }
+
+    async fn mailbox_autofile(&self, account_id: u32, detail: &str) -> trc::Result<Option<u32>> {
+        let detail = detail.trim();
+        if detail.is_empty() || detail.contains('/') {
+            return Ok(None);
+        }
+
+        // Look for a folder named after the detail, otherwise create it capitalized
+        let mut folder_name = detail.to_string();
+        if let Some(first) = folder_name.get_mut(0..1) {
+            first.make_ascii_uppercase();
+        }
+        for name in [detail, folder_name.as_str()] {
+            if let Some(document_id) = self.mailbox_get_by_name(account_id, name).await? {
+                return Ok(Some(document_id));
+            }
+        }
+
+        if self.core.jmap.mail_autofile_create {
+            self.mailbox_create_path(account_id, &folder_name)
+                .await
+                .map(|result| result.map(|(document_id, _)| document_id))
+        } else {
+            Ok(None)
+        }
+    }
 }
 
 pub trait MailboxSubscribe {
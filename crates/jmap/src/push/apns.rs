@@ -0,0 +1,244 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use common::{config::jmap::push::ApnsConfig, Server};
+use reqwest::{header::CONTENT_TYPE, StatusCode};
+use serde::{Deserialize, Serialize};
+use store::{
+    write::{now, Bincode},
+    Serialize as _,
+};
+use trc::{AddContext, PushSubscriptionEvent};
+
+// Device registered with XAPPLEPUSHSERVICE, pushed when new mail
+// arrives in any of its mailboxes
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApnsRegistration {
+    pub aps_account_id: String,
+    pub device_token: String,
+    pub subtopic: String,
+    pub mailbox_ids: Vec<u32>,
+    pub expires: u64,
+}
+
+enum ApnsResult {
+    Delivered,
+    Unregistered,
+    Failed,
+}
+
+pub trait ApnsPush: Sync + Send {
+    fn apns_register(
+        &self,
+        account_id: u32,
+        registration: ApnsRegistration,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+
+    fn apns_registrations(
+        &self,
+        account_id: u32,
+    ) -> impl Future<Output = trc::Result<Vec<ApnsRegistration>>> + Send;
+
+    fn apns_push_new_mail(
+        &self,
+        account_id: u32,
+        mailbox_ids: &[u32],
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+}
+
+impl ApnsPush for Server {
+    async fn apns_register(
+        &self,
+        account_id: u32,
+        registration: ApnsRegistration,
+    ) -> trc::Result<()> {
+        // Registering a device again replaces its mailboxes and expiry
+        let mut registrations = self.apns_registrations(account_id).await?;
+        registrations.retain(|r| r.device_token != registration.device_token);
+        registrations.push(registration);
+
+        self.apns_save(account_id, registrations).await
+    }
+
+    async fn apns_registrations(&self, account_id: u32) -> trc::Result<Vec<ApnsRegistration>> {
+        let now = now();
+        Ok(self
+            .lookup_store()
+            .key_get::<Bincode<Vec<ApnsRegistration>>>(registration_key(account_id))
+            .await
+            .caused_by(trc::location!())?
+            .map(|registrations| {
+                registrations
+                    .inner
+                    .into_iter()
+                    .filter(|r| r.expires > now)
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    async fn apns_push_new_mail(&self, account_id: u32, mailbox_ids: &[u32]) -> trc::Result<()> {
+        let config = if let Some(config) = &self.core.jmap.push_apns {
+            config
+        } else {
+            return Ok(());
+        };
+
+        let registrations = self.apns_registrations(account_id).await?;
+        if !registrations
+            .iter()
+            .any(|r| r.mailbox_ids.iter().any(|id| mailbox_ids.contains(id)))
+        {
+            return Ok(());
+        }
+        let token = self.apns_provider_token(config)?;
+
+        // Devices reported as no longer registered by APNs are removed
+        let mut unregistered = Vec::new();
+        for registration in &registrations {
+            if registration
+                .mailbox_ids
+                .iter()
+                .any(|id| mailbox_ids.contains(id))
+            {
+                match apns_request(config, &token, registration).await {
+                    ApnsResult::Unregistered => {
+                        trc::event!(
+                            PushSubscription(PushSubscriptionEvent::Unregistered),
+                            AccountId = account_id,
+                            Id = registration.device_token.clone(),
+                        );
+                        unregistered.push(registration.device_token.clone());
+                    }
+                    ApnsResult::Delivered | ApnsResult::Failed => {}
+                }
+            }
+        }
+
+        if !unregistered.is_empty() {
+            // Registrations are read again as the device list could have changed
+            let mut registrations = self.apns_registrations(account_id).await?;
+            registrations.retain(|r| !unregistered.contains(&r.device_token));
+            self.apns_save(account_id, registrations).await?;
+        }
+
+        Ok(())
+    }
+}
+
+trait ApnsPushStore {
+    fn apns_save(
+        &self,
+        account_id: u32,
+        registrations: Vec<ApnsRegistration>,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+
+    fn apns_provider_token(&self, config: &ApnsConfig) -> trc::Result<String>;
+}
+
+impl ApnsPushStore for Server {
+    async fn apns_save(
+        &self,
+        account_id: u32,
+        registrations: Vec<ApnsRegistration>,
+    ) -> trc::Result<()> {
+        let store = self.lookup_store();
+        if let Some(expires) = registrations.iter().map(|r| r.expires).max() {
+            store
+                .key_set(
+                    registration_key(account_id),
+                    Bincode::new(registrations).serialize(),
+                    Some(expires.saturating_sub(now())),
+                )
+                .await
+        } else {
+            store.key_delete(registration_key(account_id)).await
+        }
+        .caused_by(trc::location!())
+    }
+
+    fn apns_provider_token(&self, config: &ApnsConfig) -> trc::Result<String> {
+        let now = now();
+        let mut cached_token = self.inner.data.apns_token.lock();
+        match cached_token.as_ref() {
+            Some(token) if token.is_valid(&config.key_id, now) => Ok(token.token.clone()),
+            _ => {
+                let token = config.provider_token(now).map_err(|err| {
+                    trc::EventType::PushSubscription(PushSubscriptionEvent::Error)
+                        .caused_by(trc::location!())
+                        .reason(err)
+                })?;
+                let result = token.token.clone();
+                *cached_token = Some(token);
+                Ok(result)
+            }
+        }
+    }
+}
+
+async fn apns_request(
+    config: &ApnsConfig,
+    token: &str,
+    registration: &ApnsRegistration,
+) -> ApnsResult {
+    let url = format!("{}/3/device/{}", config.url, registration.device_token);
+    let client_builder = reqwest::Client::builder().timeout(config.timeout);
+
+    #[cfg(feature = "test_mode")]
+    let client_builder = client_builder.danger_accept_invalid_certs(true);
+
+    match client_builder
+        .build()
+        .unwrap_or_default()
+        .post(&url)
+        .bearer_auth(token)
+        .header(CONTENT_TYPE, "application/json")
+        .header("apns-topic", &config.topic)
+        .header("apns-push-type", "background")
+        .header("apns-priority", "5")
+        .body(
+            serde_json::json!({ "aps": { "account-id": registration.aps_account_id } }).to_string(),
+        )
+        .send()
+        .await
+    {
+        Ok(response) => match response.status() {
+            status if status.is_success() => {
+                trc::event!(PushSubscription(PushSubscriptionEvent::Success), Url = url);
+
+                ApnsResult::Delivered
+            }
+            StatusCode::GONE => ApnsResult::Unregistered,
+            status => {
+                trc::event!(
+                    PushSubscription(PushSubscriptionEvent::Error),
+                    Details = "APNs request failed",
+                    Url = url,
+                    Code = status.as_u16(),
+                    Reason = response.text().await.unwrap_or_default(),
+                );
+
+                ApnsResult::Failed
+            }
+        },
+        Err(err) => {
+            trc::event!(
+                PushSubscription(PushSubscriptionEvent::Error),
+                Details = "APNs request failed",
+                Url = url,
+                Reason = err.to_string()
+            );
+
+            ApnsResult::Failed
+        }
+    }
+}
+
+fn registration_key(account_id: u32) -> Vec<u8> {
+    format!("apns:{account_id}").into_bytes()
+}
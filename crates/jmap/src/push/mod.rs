@@ -4,15 +4,22 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+pub mod apns;
 pub mod ece;
 pub mod get;
 pub mod manager;
 pub mod set;
+pub mod webhook;
 
 use std::time::Instant;
 
-use common::ipc::{EncryptionKeys, PushSubscription};
+use apns::ApnsPush;
+use common::{
+    ipc::{EncryptionKeys, PushSubscription},
+    Server,
+};
 use jmap_proto::types::{id::Id, state::StateChange};
+use webhook::PushWebhook;
 
 #[derive(Debug)]
 pub enum Event {
@@ -61,3 +68,31 @@ pub struct PushServer {
     state_changes: Vec<StateChange>,
     in_flight: bool,
 }
+
+pub trait NewMailPush: Sync + Send {
+    fn push_new_mail(&self, account_id: u32, mailbox_ids: &[u32]);
+}
+
+impl NewMailPush for Server {
+    // Notifies mobile devices and the new mail webhook without delaying delivery
+    fn push_new_mail(&self, account_id: u32, mailbox_ids: &[u32]) {
+        if self.core.jmap.push_apns.is_none() && self.core.jmap.push_webhook.is_none() {
+            return;
+        }
+
+        let server = self.clone();
+        let mailbox_ids = mailbox_ids.to_vec();
+        tokio::spawn(async move {
+            if let Err(err) = server.apns_push_new_mail(account_id, &mailbox_ids).await {
+                trc::error!(err
+                    .details("Failed to send APNs notifications.")
+                    .account_id(account_id));
+            }
+            if let Err(err) = server.webhook_push_new_mail(account_id, &mailbox_ids).await {
+                trc::error!(err
+                    .details("Failed to send new mail webhook.")
+                    .account_id(account_id));
+            }
+        });
+    }
+}
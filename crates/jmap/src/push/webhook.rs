@@ -0,0 +1,161 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use common::{config::jmap::push::PushWebhookConfig, Server};
+use jmap_proto::{
+    object::Object,
+    types::{collection::Collection, id::Id, property::Property, value::Value},
+};
+use serde::Serialize;
+use store::write::now;
+use trc::{AddContext, PushSubscriptionEvent};
+
+use crate::{mailbox::get::MailboxGet, JmapMethods};
+
+// New message notification, which never includes message contents
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewMailEvent {
+    #[serde(rename = "@type")]
+    pub typ: &'static str,
+    pub account_id: String,
+    pub account_name: String,
+    pub mailbox_id: String,
+    pub mailbox_name: String,
+    pub total: u64,
+    pub unread: u64,
+    pub timestamp: u64,
+}
+
+pub trait PushWebhook: Sync + Send {
+    fn webhook_push_new_mail(
+        &self,
+        account_id: u32,
+        mailbox_ids: &[u32],
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+}
+
+impl PushWebhook for Server {
+    async fn webhook_push_new_mail(&self, account_id: u32, mailbox_ids: &[u32]) -> trc::Result<()> {
+        let config = if let Some(config) = &self.core.jmap.push_webhook {
+            config
+        } else {
+            return Ok(());
+        };
+
+        let access_token = self
+            .get_cached_access_token(account_id)
+            .await
+            .caused_by(trc::location!())?;
+        let message_ids = self.get_document_ids(account_id, Collection::Email).await?;
+        let timestamp = now();
+
+        for &mailbox_id in mailbox_ids {
+            let mailbox_name = self
+                .get_property::<Object<Value>>(
+                    account_id,
+                    Collection::Mailbox,
+                    mailbox_id,
+                    Property::Value,
+                )
+                .await?
+                .and_then(
+                    |mut mailbox| match mailbox.properties.remove(&Property::Name) {
+                        Some(Value::Text(name)) => Some(name),
+                        _ => None,
+                    },
+                )
+                .unwrap_or_default();
+            let event = NewMailEvent {
+                typ: "NewMail",
+                account_id: Id::from(account_id).to_string(),
+                account_name: access_token.name.clone(),
+                mailbox_id: Id::from(mailbox_id).to_string(),
+                mailbox_name,
+                total: self
+                    .get_tag_cardinality(
+                        account_id,
+                        Collection::Email,
+                        Property::MailboxIds,
+                        mailbox_id,
+                    )
+                    .await?,
+                unread: self
+                    .mailbox_unread_tags(account_id, &access_token, mailbox_id, &message_ids)
+                    .await?
+                    .map_or(0, |ids| ids.len()),
+                timestamp,
+            };
+
+            webhook_request(config, &event).await;
+        }
+
+        Ok(())
+    }
+}
+
+// Posts an event, retrying with exponential backoff
+async fn webhook_request(config: &PushWebhookConfig, event: &NewMailEvent) -> bool {
+    let body = serde_json::to_string(event).unwrap_or_default();
+    let mut headers = config.headers.clone();
+    if let Some(signature) = config.sign(body.as_bytes()) {
+        headers.insert("X-Signature", signature.parse().unwrap());
+    }
+    let client = match reqwest::Client::builder()
+        .timeout(config.timeout)
+        .danger_accept_invalid_certs(config.tls_allow_invalid_certs)
+        .build()
+    {
+        Ok(client) => client,
+        Err(err) => {
+            trc::event!(
+                PushSubscription(PushSubscriptionEvent::Error),
+                Details = "Failed to create HTTP client",
+                Reason = err.to_string()
+            );
+            return false;
+        }
+    };
+
+    let mut backoff = config.retry_interval;
+    for attempt in 1..=config.max_attempts {
+        let reason = match client
+            .post(&config.url)
+            .headers(headers.clone())
+            .body(body.clone())
+            .send()
+            .await
+        {
+            Ok(response) if response.status().is_success() => {
+                trc::event!(
+                    PushSubscription(PushSubscriptionEvent::Success),
+                    Url = config.url.clone(),
+                );
+
+                return true;
+            }
+            Ok(response) => trc::Value::from(response.status().as_u16()),
+            Err(err) => trc::Value::from(err.to_string()),
+        };
+
+        trc::event!(
+            PushSubscription(PushSubscriptionEvent::Error),
+            Details = "New mail webhook request failed",
+            Url = config.url.clone(),
+            Reason = reason,
+            Total = attempt,
+        );
+
+        if attempt < config.max_attempts {
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+        }
+    }
+
+    false
+}
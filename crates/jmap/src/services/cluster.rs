@@ -0,0 +1,163 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{sync::Arc, time::Duration};
+
+use common::{
+    ipc::{ClusterEvent, ClusterMessage, StateEvent},
+    Inner, IPC_CHANNEL_BUFFER,
+};
+use futures_util::StreamExt;
+use jmap_proto::types::{state::StateChange, type_state::DataType};
+use store::{ahash::AHashMap, PubSubStore};
+use tokio::sync::mpsc;
+use trc::ServerEvent;
+
+pub const EVENT_BUS_TOPIC: &str = "stalwart.events";
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+// Publishes local events to the cluster event bus and forwards events from
+// other nodes to the state manager. Returns None when no event bus is configured.
+pub fn spawn_cluster_manager(inner: Arc<Inner>) -> Option<mpsc::Sender<ClusterEvent>> {
+    let pubsub = inner.shared_core.load().storage.pubsub.clone();
+    if pubsub.is_none() {
+        return None;
+    }
+
+    // Messages from this node are told apart by a random id, which changes
+    // on restart so that sequence numbers are never reused
+    let node_id = rand::random::<u64>();
+    let (cluster_tx, cluster_rx) = mpsc::channel::<ClusterEvent>(IPC_CHANNEL_BUFFER);
+    tokio::spawn(publish_events(pubsub.clone(), node_id, cluster_rx));
+    tokio::spawn(receive_events(inner, pubsub, node_id));
+
+    Some(cluster_tx)
+}
+
+async fn publish_events(
+    pubsub: PubSubStore,
+    node_id: u64,
+    mut cluster_rx: mpsc::Receiver<ClusterEvent>,
+) {
+    let mut seq = 0;
+
+    while let Some(event) = cluster_rx.recv().await {
+        // Failed messages still use up a sequence number, which lets
+        // the other nodes know that they missed an event
+        seq += 1;
+        if let Err(err) = pubsub
+            .publish(
+                EVENT_BUS_TOPIC,
+                ClusterMessage {
+                    node_id,
+                    seq,
+                    event,
+                }
+                .serialize(),
+            )
+            .await
+        {
+            trc::error!(err
+                .details("Failed to publish cluster event.")
+                .caused_by(trc::location!()));
+        }
+    }
+}
+
+async fn receive_events(inner: Arc<Inner>, pubsub: PubSubStore, node_id: u64) {
+    let state_tx = inner.ipc.state_tx.clone();
+    let mut is_reconnect = false;
+
+    loop {
+        let mut stream = match pubsub.subscribe(EVENT_BUS_TOPIC).await {
+            Ok(stream) => stream,
+            Err(err) => {
+                trc::error!(err
+                    .details("Failed to subscribe to cluster event bus.")
+                    .caused_by(trc::location!()));
+                tokio::time::sleep(RECONNECT_INTERVAL).await;
+                is_reconnect = true;
+                continue;
+            }
+        };
+
+        // Events published while disconnected were lost
+        let mut last_seq: AHashMap<u64, u64> = AHashMap::new();
+        if is_reconnect && !resync(&state_tx, "Reconnected to cluster event bus").await {
+            return;
+        }
+
+        while let Some(message) = stream.next().await {
+            let Some(message) = ClusterMessage::deserialize(&message) else {
+                trc::event!(
+                    Cluster(trc::ClusterEvent::InvalidPacket),
+                    Details = "Failed to deserialize cluster event",
+                );
+                continue;
+            };
+            if message.node_id == node_id {
+                continue;
+            }
+
+            if let Some(prev_seq) = last_seq.insert(message.node_id, message.seq) {
+                if message.seq != prev_seq + 1
+                    && !resync(&state_tx, "Missed events from a cluster node").await
+                {
+                    return;
+                }
+            }
+
+            let event = match message.event {
+                ClusterEvent::StateChange { account_id, types } => StateEvent::Publish {
+                    state_change: StateChange {
+                        account_id,
+                        types: types
+                            .into_iter()
+                            .filter(|(typ, _)| *typ < DataType::None as u8)
+                            .map(|(typ, change_id)| (DataType::from(typ as u64), change_id))
+                            .collect(),
+                    },
+                    broadcast: false,
+                },
+                ClusterEvent::InvalidateAccessTokens { account_ids } => {
+                    StateEvent::InvalidateAccessTokens {
+                        account_ids,
+                        broadcast: false,
+                    }
+                }
+                ClusterEvent::RevokeAppPasswords { account_id } => StateEvent::RevokeAppPasswords {
+                    account_id,
+                    broadcast: false,
+                },
+            };
+            if state_tx.send(event).await.is_err() {
+                return;
+            }
+        }
+
+        trc::event!(
+            Cluster(trc::ClusterEvent::Error),
+            Details = "Lost connection to cluster event bus",
+        );
+        tokio::time::sleep(RECONNECT_INTERVAL).await;
+        is_reconnect = true;
+    }
+}
+
+async fn resync(state_tx: &mpsc::Sender<StateEvent>, reason: &'static str) -> bool {
+    trc::event!(Cluster(trc::ClusterEvent::Resync), Reason = reason);
+
+    if state_tx.send(StateEvent::Resync).await.is_ok() {
+        true
+    } else {
+        trc::event!(
+            Server(ServerEvent::ThreadError),
+            Details = "Error sending state resync.",
+            CausedBy = trc::location!()
+        );
+        false
+    }
+}
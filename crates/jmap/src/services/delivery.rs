@@ -20,6 +20,19 @@ pub fn spawn_delivery_manager(inner: Arc<Inner>, mut delivery_rx: mpsc::Receiver
                         .send(inner.build_server().deliver_message(message).await)
                         .ok();
                 }
+                DeliveryEvent::OutboundScript {
+                    account_id,
+                    result_tx,
+                } => {
+                    result_tx
+                        .send(inner.build_server().outbound_script(account_id).await)
+                        .ok();
+                }
+                DeliveryEvent::FileInto { message, result_tx } => {
+                    result_tx
+                        .send(inner.build_server().file_into(message).await)
+                        .ok();
+                }
                 DeliveryEvent::Stop => break,
             }
         }
@@ -23,13 +23,19 @@ use common::telemetry::{
     tracers::store::TracingStore,
 };
 
-use smtp::reporting::SmtpReporting;
+use smtp::{
+    queue::{list::SmtpMailingList, quarantine::SmtpQuarantine},
+    reporting::SmtpReporting,
+};
 use store::write::{now, purge::PurgeStore};
 use tokio::sync::mpsc;
 use trc::{Collector, MetricType};
 use utils::map::ttl_dashmap::TtlMap;
 
-use crate::{email::delete::EmailDeletion, JmapMethods, LONG_SLUMBER};
+use crate::{
+    email::{archive::EmailArchive, delete::EmailDeletion, snooze::EmailSnoozing},
+    JmapMethods, LONG_SLUMBER,
+};
 
 #[derive(PartialEq, Eq)]
 struct Action {
@@ -41,6 +47,10 @@ struct Action {
 enum ActionClass {
     Session,
     Account,
+    Archive,
+    Snooze,
+    ListDigest,
+    DkimCheck,
     Store(usize),
     Acme(String),
     OtelMetrics,
@@ -83,6 +93,33 @@ pub fn spawn_housekeeper(inner: Arc<Inner>, mut rx: mpsc::Receiver<HousekeeperEv
                 ActionClass::Account,
             );
 
+            // Message archiving
+            if let Some(archive) = &server.core.jmap.mail_archive {
+                queue.schedule(
+                    Instant::now() + archive.frequency.time_to_next(),
+                    ActionClass::Archive,
+                );
+            }
+
+            // Snoozed messages
+            queue.schedule(
+                Instant::now() + server.core.jmap.mail_snooze_interval,
+                ActionClass::Snooze,
+            );
+
+            // Mailing list digests
+            if let Some(lists) = &server.core.smtp.lists {
+                queue.schedule(
+                    Instant::now() + lists.digest_frequency.time_to_next(),
+                    ActionClass::ListDigest,
+                );
+            }
+
+            // Managed DKIM record checks
+            if let Some(interval) = server.core.smtp.mail_auth.dkim.managed.check_interval {
+                queue.schedule(Instant::now() + interval, ActionClass::DkimCheck);
+            }
+
             // Store purges
             for (idx, schedule) in server.core.storage.purge_schedules.iter().enumerate() {
                 queue.schedule(
@@ -168,6 +205,35 @@ pub fn spawn_housekeeper(inner: Arc<Inner>, mut rx: mpsc::Receiver<HousekeeperEv
                             _ => {}
                         }
 
+                        // Schedule message archiving
+                        if let Some(archive) = &server.core.jmap.mail_archive {
+                            if !queue.has_action(&ActionClass::Archive) {
+                                queue.schedule(
+                                    Instant::now() + archive.frequency.time_to_next(),
+                                    ActionClass::Archive,
+                                );
+                            }
+                        }
+
+                        // Schedule mailing list digests
+                        if let Some(lists) = &server.core.smtp.lists {
+                            if !queue.has_action(&ActionClass::ListDigest) {
+                                queue.schedule(
+                                    Instant::now() + lists.digest_frequency.time_to_next(),
+                                    ActionClass::ListDigest,
+                                );
+                            }
+                        }
+
+                        // Schedule managed DKIM record checks
+                        if let Some(interval) =
+                            server.core.smtp.mail_auth.dkim.managed.check_interval
+                        {
+                            if !queue.has_action(&ActionClass::DkimCheck) {
+                                queue.schedule(Instant::now() + interval, ActionClass::DkimCheck);
+                            }
+                        }
+
                         // SPDX-SnippetBegin
                         // SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
                         // SPDX-License-Identifier: LicenseRef-SEL
@@ -313,6 +379,15 @@ pub fn spawn_housekeeper(inner: Arc<Inner>, mut rx: mpsc::Receiver<HousekeeperEv
                                     server.purge_account(account_id).await;
                                 } else {
                                     server.purge_accounts().await;
+                                    if let Err(err) = server.purge_quarantine().await {
+                                        trc::error!(err.details("Failed to purge quarantine"));
+                                    }
+                                    if let Err(err) = server.purge_audit_log().await {
+                                        trc::error!(err.details("Failed to purge audit log"));
+                                    }
+                                    if let Err(err) = server.purge_expired_bans().await {
+                                        trc::error!(err.details("Failed to purge expired bans"));
+                                    }
                                 }
                             });
                         }
@@ -354,13 +429,7 @@ pub fn spawn_housekeeper(inner: Arc<Inner>, mut rx: mpsc::Receiver<HousekeeperEv
 
                                                 renew_at
                                             }
-                                            Err(err) => {
-                                                trc::error!(
-                                                    err.details("Failed to renew certificates.")
-                                                );
-
-                                                Duration::from_secs(3600)
-                                            }
+                                            Err(err) => server.renew_failed(provider, err).await,
                                         };
 
                                         server.increment_config_version();
@@ -388,8 +457,78 @@ pub fn spawn_housekeeper(inner: Arc<Inner>, mut rx: mpsc::Receiver<HousekeeperEv
                                 tokio::spawn(async move {
                                     trc::event!(Housekeeper(trc::HousekeeperEvent::PurgeAccounts));
                                     server.purge_accounts().await;
+                                    if let Err(err) = server.purge_quarantine().await {
+                                        trc::error!(err.details("Failed to purge quarantine"));
+                                    }
+                                    if let Err(err) = server.purge_audit_log().await {
+                                        trc::error!(err.details("Failed to purge audit log"));
+                                    }
+                                    if let Err(err) = server.purge_expired_bans().await {
+                                        trc::error!(err.details("Failed to purge expired bans"));
+                                    }
+                                });
+                            }
+                            ActionClass::Archive => {
+                                if let Some(archive) = &server.core.jmap.mail_archive {
+                                    queue.schedule(
+                                        Instant::now() + archive.frequency.time_to_next(),
+                                        ActionClass::Archive,
+                                    );
+                                    let server = server.clone();
+                                    tokio::spawn(async move {
+                                        trc::event!(Housekeeper(
+                                            trc::HousekeeperEvent::ArchiveAccounts
+                                        ));
+                                        server.archive_accounts().await;
+                                    });
+                                }
+                            }
+                            ActionClass::Snooze => {
+                                queue.schedule(
+                                    Instant::now() + server.core.jmap.mail_snooze_interval,
+                                    ActionClass::Snooze,
+                                );
+                                let server = server.clone();
+                                tokio::spawn(async move {
+                                    if let Err(err) = server.email_snooze_wake(now()).await {
+                                        trc::error!(err.details("Failed to wake snoozed messages"));
+                                    }
                                 });
                             }
+                            ActionClass::ListDigest => {
+                                if let Some(lists) = &server.core.smtp.lists {
+                                    queue.schedule(
+                                        Instant::now() + lists.digest_frequency.time_to_next(),
+                                        ActionClass::ListDigest,
+                                    );
+                                    let server = server.clone();
+                                    tokio::spawn(async move {
+                                        if let Err(err) = server.list_send_digests().await {
+                                            trc::error!(
+                                                err.details("Failed to send mailing list digests")
+                                            );
+                                        }
+                                    });
+                                }
+                            }
+                            ActionClass::DkimCheck => {
+                                if let Some(interval) =
+                                    server.core.smtp.mail_auth.dkim.managed.check_interval
+                                {
+                                    queue.schedule(
+                                        Instant::now() + interval,
+                                        ActionClass::DkimCheck,
+                                    );
+                                    let server = server.clone();
+                                    tokio::spawn(async move {
+                                        if let Err(err) = server.check_dkim_keys().await {
+                                            trc::error!(
+                                                err.details("Failed to check DKIM DNS records")
+                                            );
+                                        }
+                                    });
+                                }
+                            }
                             ActionClass::Session => {
                                 let server = server.clone();
                                 queue.schedule(
@@ -407,6 +546,9 @@ pub fn spawn_housekeeper(inner: Arc<Inner>, mut rx: mpsc::Receiver<HousekeeperEv
                                         .jmap_limiter
                                         .retain(|_, limiter| limiter.is_active());
                                     server.inner.data.access_tokens.cleanup();
+                                    server.inner.data.bitmap_cardinalities.cleanup();
+                                    server.inner.data.flag_document_counts.cleanup();
+                                    server.inner.data.key_counts.cleanup();
 
                                     for throttle in [
                                         &server.inner.data.smtp_session_throttle,
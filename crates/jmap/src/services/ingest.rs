@@ -5,19 +5,23 @@
  */
 
 use common::{
-    ipc::{DeliveryResult, IngestMessage},
+    ipc::{DeliveryResult, FileIntoMessage, IngestMessage},
     Server,
 };
 use directory::Permission;
-use jmap_proto::types::{state::StateChange, type_state::DataType};
+use jmap_proto::types::{keyword::Keyword, state::StateChange, type_state::DataType};
 use mail_parser::MessageParser;
-use std::future::Future;
+use sieve::Sieve;
+use std::{borrow::Cow, future::Future, sync::Arc};
 use store::ahash::AHashMap;
 
 use crate::{
     email::ingest::{EmailIngest, IngestEmail, IngestSource},
-    mailbox::INBOX_ID,
-    sieve::{get::SieveScriptGet, ingest::SieveScriptIngest},
+    mailbox::{get::MailboxGet, set::MailboxSet, INBOX_ID},
+    sieve::{
+        get::SieveScriptGet,
+        ingest::{is_valid_role, SieveScriptIngest},
+    },
 };
 
 use super::state::StateManager;
@@ -27,6 +31,10 @@ pub trait MailDelivery: Sync + Send {
         &self,
         message: IngestMessage,
     ) -> impl Future<Output = Vec<DeliveryResult>> + Send;
+
+    fn outbound_script(&self, account_id: u32) -> impl Future<Output = Option<Arc<Sieve>>> + Send;
+
+    fn file_into(&self, message: FileIntoMessage) -> impl Future<Output = DeliveryResult> + Send;
 }
 
 impl MailDelivery for Server {
@@ -50,6 +58,7 @@ impl MailDelivery for Server {
 
                 return (0..message.recipients.len())
                     .map(|_| DeliveryResult::TemporaryFailure {
+                        code: [4, 3, 0],
                         reason: "Blob not found.".into(),
                     })
                     .collect::<Vec<_>>();
@@ -62,6 +71,7 @@ impl MailDelivery for Server {
 
                 return (0..message.recipients.len())
                     .map(|_| DeliveryResult::TemporaryFailure {
+                        code: [4, 3, 0],
                         reason: "Temporary I/O error.".into(),
                     })
                     .collect::<Vec<_>>();
@@ -72,11 +82,11 @@ impl MailDelivery for Server {
         let mut uids: AHashMap<u32, usize> = AHashMap::with_capacity(message.recipients.len());
         let mut results = Vec::with_capacity(message.recipients.len());
         for rcpt in message.recipients {
-            let uid = match self
-                .email_to_id(&self.core.storage.directory, &rcpt, message.session_id)
+            let recipient = match self
+                .resolve_recipient(&self.core.storage.directory, &rcpt, message.session_id)
                 .await
             {
-                Ok(Some(uid)) => uid,
+                Ok(Some(recipient)) => recipient,
                 Ok(None) => {
                     // Something went wrong
                     results.push(DeliveryResult::PermanentFailure {
@@ -92,16 +102,28 @@ impl MailDelivery for Server {
                         .span_id(message.session_id)
                         .caused_by(trc::location!()));
                     results.push(DeliveryResult::TemporaryFailure {
+                        code: [4, 4, 3],
                         reason: "Address lookup failed.".into(),
                     });
                     continue;
                 }
             };
+            let uid = recipient.account_id;
+            let subaddress = recipient.subaddress;
             if let Some(result) = uids.get(&uid).and_then(|pos| results.get(*pos)) {
                 results.push(result.clone());
                 continue;
             }
 
+            // Record the original recipient on wildcard and catch-all deliveries
+            let raw_message = if recipient.is_indirect() {
+                let mut header = format!("X-Original-To: <{rcpt}>\r\n").into_bytes();
+                header.extend_from_slice(&raw_message);
+                Cow::Owned(header)
+            } else {
+                Cow::Borrowed(raw_message.as_slice())
+            };
+
             // Obtain access token
             let result = match self.get_cached_access_token(uid).await.and_then(|token| {
                 token
@@ -112,23 +134,63 @@ impl MailDelivery for Server {
                     // Check if there is an active sieve script
                     match self.sieve_script_get_active(uid).await {
                         Ok(Some(active_script)) => {
+                            // Make the detail available to the subaddress extension
+                            let envelope_to = subaddress
+                                .as_ref()
+                                .map(|subaddress| subaddress.to_sieve_address())
+                                .unwrap_or_else(|| rcpt.clone());
+
                             self.sieve_script_ingest(
                                 &access_token,
                                 &raw_message,
                                 &message.sender_address,
-                                &rcpt,
+                                &envelope_to,
                                 message.session_id,
                                 active_script,
                             )
                             .await
                         }
                         Ok(None) => {
+                            // Messages sent to the malware quarantine account are filed
+                            // into the quarantine folder
+                            let mut mailbox_id = INBOX_ID;
+                            if let Some(clamav) = self.core.spam.clamav.as_ref().filter(|clamav| {
+                                clamav
+                                    .quarantine_account
+                                    .as_ref()
+                                    .is_some_and(|account| account.eq_ignore_ascii_case(&rcpt))
+                            }) {
+                                if let Ok(Some((document_id, _))) = self
+                                    .mailbox_create_path(uid, &clamav.quarantine_folder)
+                                    .await
+                                {
+                                    mailbox_id = document_id;
+                                }
+                            } else if let Some(subaddress) = subaddress
+                                .as_ref()
+                                .filter(|_| self.core.jmap.mail_autofile_subaddress)
+                            {
+                                // File sub-addressed messages into the matching folder
+                                match self.mailbox_autofile(uid, &subaddress.detail).await {
+                                    Ok(Some(document_id)) => {
+                                        mailbox_id = document_id;
+                                    }
+                                    Ok(None) => {}
+                                    Err(err) => {
+                                        trc::error!(err
+                                            .details("Failed to obtain auto-file folder.")
+                                            .span_id(message.session_id)
+                                            .caused_by(trc::location!()));
+                                    }
+                                }
+                            }
+
                             // Ingest message
                             self.email_ingest(IngestEmail {
                                 raw_message: &raw_message,
                                 message: MessageParser::new().parse(&raw_message),
                                 resource: access_token.as_resource_token(),
-                                mailbox_ids: vec![INBOX_ID],
+                                mailbox_ids: vec![mailbox_id],
                                 keywords: vec![],
                                 received_at: None,
                                 source: IngestSource::Smtp,
@@ -164,11 +226,13 @@ impl MailDelivery for Server {
                     let result = match err.as_ref() {
                         trc::EventType::Limit(trc::LimitEvent::Quota) => {
                             DeliveryResult::TemporaryFailure {
+                                code: [4, 2, 2],
                                 reason: "Mailbox over quota.".into(),
                             }
                         }
                         trc::EventType::Limit(trc::LimitEvent::TenantQuota) => {
                             DeliveryResult::TemporaryFailure {
+                                code: [4, 2, 2],
                                 reason: "Organization over quota.".into(),
                             }
                         }
@@ -195,6 +259,7 @@ impl MailDelivery for Server {
                             }
                         }
                         _ => DeliveryResult::TemporaryFailure {
+                            code: [4, 3, 0],
                             reason: "Transient server failure.".into(),
                         },
                     };
@@ -215,4 +280,115 @@ impl MailDelivery for Server {
 
         results
     }
+
+    async fn outbound_script(&self, account_id: u32) -> Option<Arc<Sieve>> {
+        // The outbound slot is a regular script stored under a reserved name
+        match self.sieve_script_get_by_name(account_id, "outbound").await {
+            Ok(script) => script.map(Arc::new),
+            Err(err) => {
+                trc::error!(err
+                    .details("Failed to obtain outbound Sieve script.")
+                    .account_id(account_id)
+                    .caused_by(trc::location!()));
+                None
+            }
+        }
+    }
+
+    async fn file_into(&self, message: FileIntoMessage) -> DeliveryResult {
+        let account_id = message.account_id;
+        let access_token = match self.get_cached_access_token(account_id).await {
+            Ok(access_token) => access_token,
+            Err(err) => {
+                trc::error!(err
+                    .details("Failed to obtain access token.")
+                    .span_id(message.session_id)
+                    .caused_by(trc::location!()));
+                return DeliveryResult::TemporaryFailure {
+                    code: [4, 3, 0],
+                    reason: "Transient server failure.".into(),
+                };
+            }
+        };
+
+        // Copies can only be filed into the sender's own mailboxes
+        let mut mailbox_id = None;
+        if let Some(role) = message
+            .special_use
+            .map(|role| role.to_ascii_lowercase())
+            .filter(|role| is_valid_role(role))
+        {
+            mailbox_id = self
+                .mailbox_get_by_role(account_id, &role)
+                .await
+                .ok()
+                .flatten();
+        }
+        if mailbox_id.is_none() {
+            mailbox_id = if message.create {
+                self.mailbox_create_path(account_id, &message.mailbox)
+                    .await
+                    .ok()
+                    .flatten()
+                    .map(|(document_id, _)| document_id)
+            } else {
+                self.mailbox_get_by_name(account_id, &message.mailbox)
+                    .await
+                    .ok()
+                    .flatten()
+            };
+        }
+
+        match self
+            .email_ingest(IngestEmail {
+                raw_message: &message.raw_message,
+                message: MessageParser::new().parse(&message.raw_message),
+                resource: access_token.as_resource_token(),
+                mailbox_ids: vec![mailbox_id.unwrap_or(INBOX_ID)],
+                keywords: message.flags.into_iter().map(Keyword::from).collect(),
+                received_at: None,
+                source: IngestSource::Smtp,
+                encrypt: self.core.jmap.encrypt,
+                session_id: message.session_id,
+            })
+            .await
+        {
+            Ok(ingested_message) => {
+                if ingested_message.change_id != u64::MAX {
+                    self.broadcast_state_change(
+                        StateChange::new(account_id)
+                            .with_change(DataType::Email, ingested_message.change_id)
+                            .with_change(DataType::Mailbox, ingested_message.change_id)
+                            .with_change(DataType::Thread, ingested_message.change_id),
+                    )
+                    .await;
+                }
+
+                DeliveryResult::Success
+            }
+            Err(err) => {
+                let result = if matches!(
+                    err.as_ref(),
+                    trc::EventType::Limit(trc::LimitEvent::Quota | trc::LimitEvent::TenantQuota)
+                ) {
+                    DeliveryResult::TemporaryFailure {
+                        code: [4, 2, 2],
+                        reason: "Mailbox over quota.".into(),
+                    }
+                } else {
+                    DeliveryResult::TemporaryFailure {
+                        code: [4, 3, 0],
+                        reason: "Transient server failure.".into(),
+                    }
+                };
+
+                trc::error!(err
+                    .details("Failed to file outbound message copy.")
+                    .span_id(message.session_id)
+                    .caused_by(trc::location!()));
+
+                result
+            }
+        }
+    }
 }
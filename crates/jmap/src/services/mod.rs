@@ -4,6 +4,7 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+pub mod cluster;
 pub mod delivery;
 pub mod gossip;
 pub mod housekeeper;
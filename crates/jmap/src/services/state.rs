@@ -11,10 +11,10 @@ use std::{
 
 use common::{
     core::BuildServer,
-    ipc::{PushSubscription, StateEvent, UpdateSubscription},
+    ipc::{ClusterEvent, PushSubscription, StateEvent, UpdateSubscription},
     Inner, Server, IPC_CHANNEL_BUFFER,
 };
-use jmap_proto::types::{id::Id, state::StateChange, type_state::DataType};
+use jmap_proto::types::{collection::Collection, id::Id, state::StateChange, type_state::DataType};
 use std::future::Future;
 use store::ahash::AHashMap;
 use tokio::sync::mpsc;
@@ -23,6 +23,8 @@ use utils::map::bitmap::Bitmap;
 
 use crate::push::{get::PushSubscriptionFetch, manager::spawn_push_manager};
 
+use super::cluster::spawn_cluster_manager;
+
 #[derive(Debug)]
 struct Subscriber {
     types: Bitmap<DataType>,
@@ -56,6 +58,7 @@ enum SubscriberId {
 #[allow(clippy::unwrap_or_default)]
 pub fn spawn_state_manager(inner: Arc<Inner>, mut change_rx: mpsc::Receiver<StateEvent>) {
     let push_tx = spawn_push_manager(inner.clone());
+    let cluster_tx = spawn_cluster_manager(inner.clone());
 
     tokio::spawn(async move {
         let mut subscribers: AHashMap<u32, AHashMap<SubscriberId, Subscriber>> =
@@ -162,7 +165,15 @@ pub fn spawn_state_manager(inner: Arc<Inner>, mut change_rx: mpsc::Receiver<Stat
                             },
                         );
                 }
-                StateEvent::Publish { state_change } => {
+                StateEvent::Publish {
+                    state_change,
+                    broadcast,
+                } => {
+                    if broadcast {
+                        send_cluster_event(&cluster_tx, ClusterEvent::from(state_change.clone()))
+                            .await;
+                    }
+
                     if let Some(shared_accounts) = shared_accounts_map.get(&state_change.account_id)
                     {
                         let current_time = SystemTime::now()
@@ -243,6 +254,100 @@ pub fn spawn_state_manager(inner: Arc<Inner>, mut change_rx: mpsc::Receiver<Stat
                         }
                     }
                 }
+                StateEvent::InvalidateAccessTokens {
+                    account_ids,
+                    broadcast,
+                } => {
+                    for account_id in &account_ids {
+                        inner.data.access_tokens.remove(account_id);
+                    }
+                    if broadcast {
+                        send_cluster_event(
+                            &cluster_tx,
+                            ClusterEvent::InvalidateAccessTokens { account_ids },
+                        )
+                        .await;
+                    }
+                }
+                StateEvent::RevokeAppPasswords {
+                    account_id,
+                    broadcast,
+                } => {
+                    // Sending only fails when no sessions are listening
+                    let _ = inner.data.app_password_revocations.send(account_id);
+                    if broadcast {
+                        send_cluster_event(
+                            &cluster_tx,
+                            ClusterEvent::RevokeAppPasswords { account_id },
+                        )
+                        .await;
+                    }
+                }
+                StateEvent::Resync => {
+                    // Changes made on other nodes might have been missed, drop cached
+                    // tokens and notify subscribers of the latest state
+                    inner.data.access_tokens.clear();
+
+                    let account_ids = shared_accounts_map.keys().copied().collect::<Vec<_>>();
+                    let inner = inner.clone();
+                    tokio::spawn(async move {
+                        let server = inner.build_server();
+                        for account_id in account_ids {
+                            let mut state_change = StateChange::new(account_id);
+                            for (collection, types) in [
+                                (
+                                    Collection::Email,
+                                    &[DataType::Email, DataType::EmailDelivery][..],
+                                ),
+                                (Collection::Mailbox, &[DataType::Mailbox]),
+                                (Collection::Thread, &[DataType::Thread]),
+                                (Collection::Identity, &[DataType::Identity]),
+                                (Collection::EmailSubmission, &[DataType::EmailSubmission]),
+                                (Collection::SieveScript, &[DataType::SieveScript]),
+                                (Collection::PushSubscription, &[DataType::PushSubscription]),
+                            ] {
+                                match server
+                                    .core
+                                    .storage
+                                    .data
+                                    .get_last_change_id(account_id, collection)
+                                    .await
+                                {
+                                    Ok(Some(change_id)) => {
+                                        for typ in types {
+                                            state_change.types.push((*typ, change_id));
+                                        }
+                                    }
+                                    Ok(None) => {}
+                                    Err(err) => {
+                                        trc::error!(err
+                                            .account_id(account_id)
+                                            .details("Failed to obtain last change id."));
+                                    }
+                                }
+                            }
+
+                            if !state_change.types.is_empty()
+                                && inner
+                                    .ipc
+                                    .state_tx
+                                    .send(StateEvent::Publish {
+                                        state_change,
+                                        broadcast: false,
+                                    })
+                                    .await
+                                    .is_err()
+                            {
+                                trc::event!(
+                                    Server(ServerEvent::ThreadError),
+                                    Details = "Error sending state change.",
+                                    CausedBy = trc::location!()
+                                );
+                                break;
+                            }
+                        }
+                    });
+                }
                 StateEvent::UpdateSubscriptions {
                     account_id,
                     subscriptions,
@@ -378,6 +483,8 @@ pub trait StateManager: Sync + Send {
     ) -> impl Future<Output = bool> + Send;
 
     fn update_push_subscriptions(&self, account_id: u32) -> impl Future<Output = bool> + Send;
+
+    fn invalidate_access_tokens(&self, account_ids: Vec<u32>);
 }
 
 impl StateManager for Server {
@@ -413,7 +520,10 @@ impl StateManager for Server {
             .ipc
             .state_tx
             .clone()
-            .send(StateEvent::Publish { state_change })
+            .send(StateEvent::Publish {
+                state_change,
+                broadcast: true,
+            })
             .await
         {
             Ok(_) => true,
@@ -455,6 +565,44 @@ impl StateManager for Server {
 
         true
     }
+
+    fn invalidate_access_tokens(&self, account_ids: Vec<u32>) {
+        for account_id in &account_ids {
+            self.inner.data.access_tokens.remove(account_id);
+        }
+
+        // Other nodes only need to be notified when an event bus is configured
+        if !account_ids.is_empty()
+            && !self.core.storage.pubsub.is_none()
+            && self
+                .inner
+                .ipc
+                .state_tx
+                .try_send(StateEvent::InvalidateAccessTokens {
+                    account_ids,
+                    broadcast: true,
+                })
+                .is_err()
+        {
+            trc::event!(
+                Server(ServerEvent::ThreadError),
+                Details = "Error sending access token invalidation.",
+                CausedBy = trc::location!()
+            );
+        }
+    }
+}
+
+async fn send_cluster_event(cluster_tx: &Option<mpsc::Sender<ClusterEvent>>, event: ClusterEvent) {
+    if let Some(cluster_tx) = cluster_tx {
+        if cluster_tx.send(event).await.is_err() {
+            trc::event!(
+                Server(ServerEvent::ThreadError),
+                Details = "Error sending cluster event.",
+                CausedBy = trc::location!()
+            );
+        }
+    }
 }
 
 impl From<SubscriberId> for u32 {
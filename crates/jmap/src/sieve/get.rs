@@ -22,11 +22,11 @@ use store::{
 use crate::{
     blob::{download::BlobDownload, upload::BlobUpload},
     changes::state::StateManager,
-    sieve::SeenIds,
+    sieve::{set::ObjectBlobId, SeenIds},
     JmapMethods,
 };
 
-use super::ActiveScript;
+use super::{ActiveScript, SieveScript};
 use std::future::Future;
 
 pub trait SieveScriptGet: Sync + Send {
@@ -51,6 +51,17 @@ pub trait SieveScriptGet: Sync + Send {
         account_id: u32,
         document_id: u32,
     ) -> impl Future<Output = trc::Result<(Sieve, Object<Value>)>> + Send;
+
+    fn get_sieve_script(
+        &self,
+        account_id: u32,
+        document_id: u32,
+    ) -> impl Future<Output = trc::Result<Option<SieveScript>>> + Send;
+
+    fn list_sieve_scripts(
+        &self,
+        account_id: u32,
+    ) -> impl Future<Output = trc::Result<Vec<(u32, String, bool)>>> + Send;
 }
 
 impl SieveScriptGet for Server {
@@ -317,4 +328,89 @@ impl SieveScriptGet for Server {
             }
         }
     }
+
+    async fn get_sieve_script(
+        &self,
+        account_id: u32,
+        document_id: u32,
+    ) -> trc::Result<Option<SieveScript>> {
+        let Some(script_object) = self
+            .get_property::<Object<Value>>(
+                account_id,
+                Collection::SieveScript,
+                document_id,
+                Property::Value,
+            )
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        // The script blob contains the source followed by the compiled script
+        let (script_offset, blob_hash) = script_object
+            .blob_id()
+            .and_then(|id| (id.section.as_ref()?.size, &id.hash).into())
+            .ok_or_else(|| {
+                trc::StoreEvent::NotFound
+                    .into_err()
+                    .caused_by(trc::location!())
+                    .document_id(document_id)
+            })?;
+        let mut source = self
+            .get_blob(blob_hash, 0..usize::MAX)
+            .await?
+            .ok_or_else(|| {
+                trc::StoreEvent::NotFound
+                    .into_err()
+                    .caused_by(trc::location!())
+                    .document_id(document_id)
+            })?;
+        let compiled = source
+            .get(script_offset..)
+            .and_then(|bytes| Bincode::<Sieve>::deserialize(bytes).ok())
+            .map(|sieve| sieve.inner);
+        source.truncate(script_offset);
+
+        Ok(Some(SieveScript {
+            name: script_object
+                .get(&Property::Name)
+                .as_string()
+                .unwrap_or_default()
+                .to_string(),
+            source,
+            compiled,
+            active: script_object.get(&Property::IsActive).as_bool() == Some(true),
+        }))
+    }
+
+    async fn list_sieve_scripts(&self, account_id: u32) -> trc::Result<Vec<(u32, String, bool)>> {
+        let mut scripts = Vec::new();
+        for document_id in self
+            .get_document_ids(account_id, Collection::SieveScript)
+            .await?
+            .unwrap_or_default()
+        {
+            if let Some(script_object) = self
+                .get_property::<Object<Value>>(
+                    account_id,
+                    Collection::SieveScript,
+                    document_id,
+                    Property::Value,
+                )
+                .await?
+            {
+                scripts.push((
+                    document_id,
+                    script_object
+                        .get(&Property::Name)
+                        .as_string()
+                        .unwrap_or_default()
+                        .to_string(),
+                    script_object.get(&Property::IsActive).as_bool() == Some(true),
+                ));
+            }
+        }
+
+        Ok(scripts)
+    }
 }
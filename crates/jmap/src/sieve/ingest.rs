@@ -222,6 +222,39 @@ impl SieveScriptIngest for Server {
                             input = false.into();
                         }
                     }
+                    Event::DuplicateId { id, expiry, .. } if id.starts_with(VACATION_ID_PREFIX) => {
+                        // Vacation replies are tracked per account and sender rather than
+                        // per script, so a VacationResponse and a Sieve vacation action
+                        // never reply twice to the same sender
+                        let key = vacation_key(account_id, envelope_from);
+                        let seen_id = match self.core.storage.lookup.key_exists(key.clone()).await {
+                            Ok(true) => true,
+                            Ok(false) => {
+                                if let Err(err) = self
+                                    .core
+                                    .storage
+                                    .lookup
+                                    .key_set(key, vec![], expiry.into())
+                                    .await
+                                {
+                                    trc::error!(err
+                                        .account_id(account_id)
+                                        .span_id(session_id)
+                                        .caused_by(trc::location!()));
+                                }
+                                false
+                            }
+                            Err(err) => {
+                                trc::error!(err
+                                    .account_id(account_id)
+                                    .span_id(session_id)
+                                    .caused_by(trc::location!()));
+                                true
+                            }
+                        };
+
+                        input = seen_id.into();
+                    }
                     Event::DuplicateId { id, expiry, last } => {
                         let id_hash = SeenIdHash::new(&id, expiry + now);
                         let seen_id = active_script.seen_ids.ids.contains(&id_hash);
@@ -533,6 +566,13 @@ impl SieveScriptIngest for Server {
     }
 }
 
+// Prefix of the duplicate ids generated by the vacation extension
+const VACATION_ID_PREFIX: &str = "_v";
+
+fn vacation_key(account_id: u32, sender: &str) -> Vec<u8> {
+    format!("vacation:{account_id}:{}", sender.to_lowercase()).into_bytes()
+}
+
 #[inline(always)]
 pub fn is_valid_role(role: &str) -> bool {
     [
@@ -9,6 +9,7 @@ use std::sync::Arc;
 use serde::ser::SerializeSeq;
 use sieve::Sieve;
 use store::{ahash::AHashSet, blake3, write::now};
+use unicode_normalization::UnicodeNormalization;
 
 pub mod get;
 pub mod ingest;
@@ -23,6 +24,27 @@ pub struct ActiveScript {
     pub seen_ids: SeenIds,
 }
 
+pub struct SieveScript {
+    pub name: String,
+    pub source: Vec<u8>,
+    pub compiled: Option<Sieve>,
+    pub active: bool,
+}
+
+/// Returns the NFC form of a script name, or `None` if it contains
+/// characters that RFC 5804 does not allow in script names.
+pub fn normalize_script_name(name: &str) -> Option<String> {
+    let name = name.trim().nfc().collect::<String>();
+    if name
+        .chars()
+        .any(|ch| ch.is_control() || matches!(ch, '\u{2028}' | '\u{2029}'))
+    {
+        None
+    } else {
+        Some(name)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct SeenIdHash {
     hash: [u8; 32],
@@ -14,7 +14,7 @@ use jmap_proto::{
 use std::future::Future;
 use store::query::{self};
 
-use crate::JmapMethods;
+use crate::{sieve::normalize_script_name, JmapMethods};
 
 pub trait SieveScriptQuery: Sync + Send {
     fn sieve_script_query(
@@ -33,7 +33,10 @@ impl SieveScriptQuery for Server {
 
         for cond in std::mem::take(&mut request.filter) {
             match cond {
-                Filter::Name(name) => filters.push(query::Filter::has_text(Property::Name, &name)),
+                Filter::Name(name) => {
+                    let name = normalize_script_name(&name).unwrap_or(name);
+                    filters.push(query::Filter::has_text(Property::Name, &name))
+                }
                 Filter::IsActive(is_active) => {
                     filters.push(query::Filter::eq(Property::IsActive, is_active as u32))
                 }
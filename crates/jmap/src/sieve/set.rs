@@ -31,16 +31,17 @@ use store::{
     query::Filter,
     rand::{distributions::Alphanumeric, thread_rng, Rng},
     write::{
-        assert::HashedValue, log::ChangeLogBuilder, BatchBuilder, BlobOp, DirectoryClass, F_CLEAR,
-        F_VALUE,
+        assert::HashedValue, log::ChangeLogBuilder, BatchBuilder, Bincode, BlobOp, DirectoryClass,
+        F_CLEAR, F_VALUE,
     },
-    BlobClass,
+    BlobClass, Serialize,
 };
 
 use crate::{
     api::http::HttpSessionData,
     blob::{download::BlobDownload, upload::BlobUpload},
     changes::write::ChangeLog,
+    sieve::{normalize_script_name, SieveScript},
     JmapMethods,
 };
 use std::future::Future;
@@ -91,6 +92,13 @@ pub trait SieveScriptSet: Sync + Send {
         account_id: u32,
         activate_id: Option<u32>,
     ) -> impl Future<Output = trc::Result<Vec<(u32, bool)>>> + Send;
+
+    fn set_sieve_script(
+        &self,
+        resource_token: &ResourceToken,
+        document_id: Option<u32>,
+        script: SieveScript,
+    ) -> impl Future<Output = trc::Result<u32>> + Send;
 }
 
 impl SieveScriptSet for Server {
@@ -117,7 +125,7 @@ impl SieveScriptSet for Server {
         // Process creates
         let mut changes = ChangeLogBuilder::new();
         for (id, object) in request.unwrap_create() {
-            if sieve_ids.len() as usize <= self.core.jmap.sieve_max_scripts {
+            if (sieve_ids.len() as usize) < self.core.jmap.sieve_max_scripts {
                 match self
                     .sieve_set_item(object, None, &ctx, session.session_id)
                     .await?
@@ -351,18 +359,36 @@ impl SieveScriptSet for Server {
                     .unwrap_or(false))
         {
             let changed_ids = if let Some(id) = request.arguments.on_success_activate_script {
-                self.sieve_activate_script(
-                    account_id,
-                    match id {
-                        MaybeReference::Value(id) => id.document_id(),
-                        MaybeReference::Reference(id_ref) => match ctx.response.get_id(&id_ref) {
-                            Some(Value::Id(id)) => id.document_id(),
-                            _ => return Ok(ctx.response),
-                        },
-                    }
-                    .into(),
-                )
-                .await?
+                let document_id = match id {
+                    MaybeReference::Value(id) => id.document_id(),
+                    MaybeReference::Reference(id_ref) => match ctx.response.get_id(&id_ref) {
+                        Some(Value::Id(id)) => id.document_id(),
+                        _ => return Ok(ctx.response),
+                    },
+                };
+
+                // The outbound script runs on submission and is never active for delivery
+                if self
+                    .get_property::<Object<Value>>(
+                        account_id,
+                        Collection::SieveScript,
+                        document_id,
+                        Property::Value,
+                    )
+                    .await?
+                    .is_some_and(|obj| {
+                        obj.get(&Property::Name)
+                            .as_string()
+                            .is_some_and(|name| name.eq_ignore_ascii_case("outbound"))
+                    })
+                {
+                    return Err(trc::JmapEvent::InvalidArguments.into_err().details(
+                        "The 'outbound' script runs on submission and cannot be activated.",
+                    ));
+                }
+
+                self.sieve_activate_script(account_id, document_id.into())
+                    .await?
             } else {
                 self.sieve_activate_script(account_id, None).await?
             };
@@ -477,7 +503,16 @@ impl SieveScriptSet for Server {
             };
             let value = match (&property, value) {
                 (Property::Name, MaybePatchValue::Value(Value::Text(value))) => {
-                    if value.len() > self.core.jmap.sieve_max_script_name {
+                    let Some(value) = normalize_script_name(&value) else {
+                        return Ok(Err(SetError::invalid_properties()
+                            .with_property(property)
+                            .with_description("Script name contains invalid characters.")));
+                    };
+                    if value.is_empty() {
+                        return Ok(Err(SetError::invalid_properties()
+                            .with_property(property)
+                            .with_description("Script name cannot be empty.")));
+                    } else if value.len() > self.core.jmap.sieve_max_script_name {
                         return Ok(Err(SetError::invalid_properties()
                             .with_property(property)
                             .with_description("Script name is too long.")));
@@ -487,6 +522,16 @@ impl SieveScriptSet for Server {
                             .with_description(
                                 "The 'vacation' name is reserved, please use a different name.",
                             )));
+                    } else if value.eq_ignore_ascii_case("outbound")
+                        && update.as_ref().is_some_and(|(_, obj)| {
+                            obj.inner.get(&Property::IsActive).as_bool() == Some(true)
+                        })
+                    {
+                        return Ok(Err(SetError::forbidden()
+                            .with_property(property)
+                            .with_description(
+                                "The active script cannot be renamed to 'outbound'.",
+                            )));
                     } else if update
                         .as_ref()
                         .and_then(|(_, obj)| obj.inner.properties.get(&Property::Name))
@@ -714,6 +759,149 @@ impl SieveScriptSet for Server {
 
         Ok(changed_ids)
     }
+
+    async fn set_sieve_script(
+        &self,
+        resource_token: &ResourceToken,
+        document_id: Option<u32>,
+        script: SieveScript,
+    ) -> trc::Result<u32> {
+        let account_id = resource_token.account_id;
+
+        // Compile the script unless a compiled version was provided
+        let compiled = match script.compiled {
+            Some(compiled) => compiled,
+            None => self
+                .core
+                .sieve
+                .untrusted_compiler
+                .compile(&script.source)
+                .map_err(|err| {
+                    trc::StoreEvent::UnexpectedError
+                        .caused_by(trc::location!())
+                        .reason(err)
+                        .details("Failed to compile Sieve script")
+                })?,
+        };
+
+        // Store the source followed by the compiled script
+        let script_size = script.source.len();
+        let mut script_bytes = script.source;
+        script_bytes.extend_from_slice(&(&Bincode::new(compiled)).serialize());
+        let blob_hash = self.put_blob(account_id, &script_bytes, false).await?.hash;
+        let blob_id = BlobId::new(
+            blob_hash.clone(),
+            BlobClass::Linked {
+                account_id,
+                collection: Collection::SieveScript.into(),
+                document_id: document_id.unwrap_or_default(),
+            },
+        )
+        .with_section_size(script_size);
+
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::SieveScript);
+        let (was_active, quota) = if let Some(document_id) = document_id {
+            let current = self
+                .get_property::<HashedValue<Object<Value>>>(
+                    account_id,
+                    Collection::SieveScript,
+                    document_id,
+                    Property::Value,
+                )
+                .await?
+                .ok_or_else(|| {
+                    trc::StoreEvent::NotFound
+                        .into_err()
+                        .caused_by(trc::location!())
+                        .document_id(document_id)
+                })?;
+            let prev_blob_id = current.inner.blob_id().cloned().ok_or_else(|| {
+                trc::StoreEvent::NotFound
+                    .into_err()
+                    .caused_by(trc::location!())
+                    .document_id(document_id)
+            })?;
+            let was_active = current.inner.get(&Property::IsActive).as_bool() == Some(true);
+            let quota = script_size as i64
+                - prev_blob_id
+                    .section
+                    .as_ref()
+                    .map_or(0, |section| section.size as i64);
+
+            batch
+                .update_document(document_id)
+                .clear(BlobOp::Link {
+                    hash: prev_blob_id.hash,
+                })
+                .set(BlobOp::Link { hash: blob_hash }, Vec::new())
+                .custom(
+                    ObjectIndexBuilder::new(SCHEMA)
+                        .with_current(current)
+                        .with_changes(
+                            Object::with_capacity(2)
+                                .with_property(Property::Name, script.name)
+                                .with_property(Property::BlobId, Value::BlobId(blob_id)),
+                        ),
+                );
+
+            (was_active, quota)
+        } else {
+            batch
+                .create_document()
+                .set(BlobOp::Link { hash: blob_hash }, Vec::new())
+                .custom(
+                    ObjectIndexBuilder::new(SCHEMA).with_changes(
+                        Object::with_capacity(3)
+                            .with_property(Property::Name, script.name)
+                            .with_property(Property::IsActive, Value::Bool(false))
+                            .with_property(Property::BlobId, Value::BlobId(blob_id)),
+                    ),
+                );
+
+            (false, script_size as i64)
+        };
+
+        // Update quota
+        if quota != 0 {
+            batch.add(DirectoryClass::UsedQuota(account_id), quota);
+
+            #[cfg(feature = "enterprise")]
+            if self.core.is_enterprise_edition() {
+                if let Some(tenant) = resource_token.tenant {
+                    batch.add(DirectoryClass::UsedQuota(tenant.id), quota);
+                }
+            }
+        }
+
+        // Write record
+        let mut changes = ChangeLogBuilder::new();
+        let document_id = if let Some(document_id) = document_id {
+            self.write_batch(batch).await?;
+            changes.log_update(Collection::SieveScript, document_id);
+            document_id
+        } else {
+            let document_id = self.write_batch_expect_id(batch).await?;
+            changes.log_insert(Collection::SieveScript, document_id);
+            document_id
+        };
+
+        // De/activate the script
+        if script.active != was_active {
+            for (changed_id, _) in self
+                .sieve_activate_script(account_id, script.active.then_some(document_id))
+                .await?
+            {
+                changes.log_update(Collection::SieveScript, changed_id);
+            }
+        }
+
+        self.commit_changes(account_id, changes).await?;
+
+        Ok(document_id)
+    }
 }
 
 pub trait ObjectBlobId {
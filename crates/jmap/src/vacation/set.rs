@@ -17,6 +17,8 @@ use jmap_proto::{
         collection::Collection,
         id::Id,
         property::Property,
+        state::StateChange,
+        type_state::DataType,
         value::{MaybePatchValue, Value},
     },
 };
@@ -309,6 +311,7 @@ impl VacationResponseSet for Server {
             let document_id = if !batch.is_empty() {
                 let ids = self.write_batch(batch).await?;
                 response.new_state = Some(change_id.into());
+                response.state_change = state_change(account_id, change_id).into();
                 match document_id {
                     Some(document_id) => document_id,
                     None => ids.last_document_id()?,
@@ -352,6 +355,7 @@ impl VacationResponseSet for Server {
             if !batch.is_empty() {
                 self.write_batch(batch).await?;
                 response.new_state = Some(change_id.into());
+                response.state_change = state_change(account_id, change_id).into();
             }
         }
 
@@ -466,6 +470,13 @@ impl VacationResponseSet for Server {
     }
 }
 
+// The vacation response is stored as a Sieve script, so both types change
+fn state_change(account_id: u32, change_id: u64) -> StateChange {
+    StateChange::new(account_id)
+        .with_change(DataType::SieveScript, change_id)
+        .with_change(DataType::VacationResponse, change_id)
+}
+
 fn set_error(mut response: SetResponse, id: Option<String>, err: SetError) -> SetResponse {
     if let Some(id) = id {
         response.not_created.append(id, err);
@@ -9,7 +9,11 @@ use std::time::Duration;
 use common::{config::server::ServerProtocol, core::BuildServer, manager::boot::BootManager};
 use directory::backend::internal::MigrateDirectory;
 use imap::core::ImapSessionManager;
-use jmap::{api::JmapSessionManager, services::gossip::spawn::GossiperBuilder, StartServices};
+use jmap::{
+    api::{management::reload::ManageReload, JmapSessionManager},
+    services::gossip::spawn::GossiperBuilder,
+    StartServices,
+};
 use managesieve::core::ManageSieveSessionManager;
 use pop3::Pop3SessionManager;
 use smtp::{core::SmtpSessionManager, StartQueueManager};
@@ -87,6 +91,23 @@ async fn main() -> std::io::Result<()> {
         };
     });
 
+    // Reload configuration on SIGHUP
+    #[cfg(not(target_env = "msvc"))]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        use utils::UnwrapFailure;
+
+        let inner = init.inner.clone();
+        let mut h_hup = signal(SignalKind::hangup()).failed("start signal handler");
+        tokio::spawn(async move {
+            while h_hup.recv().await.is_some() {
+                if let Err(err) = inner.build_server().reload_config(false).await {
+                    trc::error!(err.details("Failed to reload configuration."));
+                }
+            }
+        });
+    }
+
     // Spawn gossip
     if let Some(gossiper) = gossiper {
         gossiper.spawn(init.inner, shutdown_rx.clone()).await;
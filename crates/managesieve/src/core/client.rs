@@ -4,8 +4,11 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use common::listener::{SessionResult, SessionStream};
-use imap_proto::receiver::{self, Request};
+use common::{
+    listener::{SessionResult, SessionStream},
+    telemetry::audit::{AuditEvent, AuditRecord},
+};
+use imap_proto::receiver::{self, Request, Token};
 use jmap_proto::types::{collection::Collection, property::Property};
 use store::query::Filter;
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
@@ -15,6 +18,11 @@ use super::{Command, ResponseCode, SerializeResponse, Session, State};
 
 impl<T: SessionStream> Session<T> {
     pub async fn ingest(&mut self, bytes: &[u8]) -> SessionResult {
+        // Terminate sessions authenticated with a revoked app password
+        if self.is_app_password_revoked().await {
+            return SessionResult::Close;
+        }
+
         let mut bytes = bytes.iter();
         let mut requests = Vec::with_capacity(2);
         let mut needs_literal = None;
@@ -85,7 +93,39 @@ impl<T: SessionStream> Session<T> {
 
         for request in requests {
             let command = request.command;
-            match match command {
+            let audit_record = match command {
+                Command::PutScript
+                | Command::SetActive
+                | Command::DeleteScript
+                | Command::RenameScript
+                    if self.server.core.audit.enable =>
+                {
+                    let account = self.state.access_token().name.clone();
+                    let script = match request.tokens.first() {
+                        Some(Token::Argument(name)) => String::from_utf8_lossy(name).into_owned(),
+                        _ => String::new(),
+                    };
+                    let action = match command {
+                        Command::PutScript => "put",
+                        Command::SetActive => "activate",
+                        Command::DeleteScript => "delete",
+                        _ => "rename",
+                    };
+
+                    AuditRecord::new(
+                        account.clone(),
+                        AuditEvent::SieveScriptChange {
+                            account,
+                            script,
+                            action: action.to_string(),
+                        },
+                    )
+                    .with_remote_ip(self.remote_addr)
+                    .into()
+                }
+                _ => None,
+            };
+            let result = match command {
                 Command::ListScripts => self.handle_listscripts().await,
                 Command::PutScript => self.handle_putscript(request).await,
                 Command::SetActive => self.handle_setactive(request).await,
@@ -100,7 +140,14 @@ impl<T: SessionStream> Session<T> {
                 Command::Logout => self.handle_logout().await,
                 Command::Noop => self.handle_noop(request).await,
                 Command::Unauthenticate => self.handle_unauthenticate().await,
-            } {
+            };
+
+            // Script changes are recorded even when they failed
+            if let Some(record) = audit_record {
+                self.server.audit(record.with_result(&result)).await;
+            }
+
+            match result {
                 Ok(response) => {
                     if let Err(err) = self.write(&response).await {
                         trc::error!(err.span_id(self.session_id));
@@ -141,6 +188,29 @@ impl<T: SessionStream> Session<T> {
         SessionResult::Continue
     }
 
+    pub async fn is_app_password_revoked(&mut self) -> bool {
+        match &self.app_password {
+            Some(app_password) if self.server.is_app_password_revoked(app_password).await => {
+                trc::event!(
+                    Security(SecurityEvent::Unauthorized),
+                    SpanId = self.session_id,
+                    Details = "App password revoked",
+                );
+
+                self.write(b"BYE \"App password revoked.\"\r\n").await.ok();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Only sessions using an app password of the notified account have to check
+    pub fn is_app_password_account(&self, account_id: u32) -> bool {
+        self.app_password
+            .as_ref()
+            .is_some_and(|app_password| app_password.account_id == account_id)
+    }
+
     async fn validate_request(&self, command: Request<Command>) -> trc::Result<Request<Command>> {
         match &command.command {
             Command::Capability | Command::Logout | Command::Noop => Ok(command),
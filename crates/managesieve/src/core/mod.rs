@@ -10,7 +10,7 @@ pub mod session;
 use std::{borrow::Cow, net::IpAddr, sync::Arc};
 
 use common::{
-    auth::AccessToken,
+    auth::{sasl::SaslExchange, AccessToken, SessionAppPassword},
     listener::{limiter::InFlight, ServerInstance},
     Inner, Server,
 };
@@ -26,6 +26,8 @@ pub struct Session<T: AsyncRead + AsyncWrite> {
     pub stream: T,
     pub session_id: u64,
     pub in_flight: InFlight,
+    pub sasl: Option<SaslExchange>,
+    pub app_password: Option<SessionAppPassword>,
 }
 
 pub enum State {
@@ -274,7 +276,9 @@ impl SerializeResponse for trc::Error {
                     Some(ResponseCode::NonExistent.as_str())
                 }
                 trc::EventType::Store(_) => Some(ResponseCode::TryLater.as_str()),
-                trc::EventType::Limit(trc::LimitEvent::Quota) => Some(ResponseCode::Quota.as_str()),
+                trc::EventType::Limit(trc::LimitEvent::Quota | trc::LimitEvent::TenantQuota) => {
+                    Some(ResponseCode::Quota.as_str())
+                }
                 trc::EventType::Limit(_) => Some(ResponseCode::TryLater.as_str()),
                 _ => None,
             })
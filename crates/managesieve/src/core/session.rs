@@ -34,6 +34,8 @@ impl SessionManager for ManageSieveSessionManager {
                 stream: session.stream,
                 in_flight: session.in_flight,
                 remote_addr: session.remote_ip,
+                sasl: None,
+                app_password: None,
             };
 
             if session
@@ -63,6 +65,7 @@ impl<T: SessionStream> Session<T> {
     pub async fn handle_conn(&mut self) -> bool {
         let mut buf = vec![0; 8192];
         let mut shutdown_rx = self.instance.shutdown_rx.clone();
+        let mut revocations = self.server.subscribe_app_password_revocations();
 
         loop {
             tokio::select! {
@@ -126,6 +129,13 @@ impl<T: SessionStream> Session<T> {
                     );
                     self.write(b"BYE \"Server shutting down.\"\r\n").await.ok();
                     break;
+                },
+                Ok(account_id) = revocations.recv() => {
+                    if self.is_app_password_account(account_id)
+                        && self.is_app_password_revoked().await
+                    {
+                        break;
+                    }
                 }
             };
         }
@@ -146,6 +156,8 @@ impl<T: SessionStream> Session<T> {
             server: self.server,
             receiver: self.receiver,
             remote_addr: self.remote_addr,
+            sasl: None,
+            app_password: self.app_password,
         })
     }
 }
@@ -6,13 +6,18 @@
 
 use common::{
     auth::{
-        sasl::{sasl_decode_challenge_oauth, sasl_decode_challenge_plain},
-        AuthRequest,
+        sasl::{
+            sasl_decode_challenge_oauth, sasl_decode_challenge_plain, sasl_encode_challenge,
+            SaslMechanism, SaslStep,
+        },
+        AccessToken, AuthRequest, SessionAppPassword,
     },
+    config::server::ServerProtocol,
     listener::{limiter::ConcurrencyLimiter, SessionStream},
     ConcurrencyLimiters,
 };
 use directory::Permission;
+use imap::op::authenticate::sasl_mechanism;
 use imap_proto::{
     protocol::authenticate::Mechanism,
     receiver::{self, Request},
@@ -65,9 +70,16 @@ impl<T: SessionStream> Session<T> {
                 }
             }
             _ => {
-                return Err(trc::AuthEvent::Error
-                    .into_err()
-                    .details("Authentication mechanism not supported."))
+                return if let Some(sasl_mechanism) = sasl_mechanism(&mechanism)
+                    .filter(|mechanism| self.sasl_mechanisms().contains(mechanism))
+                {
+                    self.handle_sasl_exchange(mechanism, sasl_mechanism, params.pop())
+                        .await
+                } else {
+                    Err(trc::AuthEvent::Error
+                        .into_err()
+                        .details("Authentication mechanism not supported."))
+                };
             }
         };
 
@@ -75,14 +87,105 @@ impl<T: SessionStream> Session<T> {
         self.server.is_auth_allowed_soft(&self.remote_addr).await?;
 
         // Authenticate
-        let access_token = self
+        let result = self
             .server
-            .authenticate(&AuthRequest::from_credentials(
+            .authenticate_session(&AuthRequest::from_credentials(
                 credentials,
                 self.session_id,
                 self.remote_addr,
+                ServerProtocol::ManageSieve,
             ))
+            .await;
+
+        self.complete_authentication(result)
+    }
+
+    async fn handle_sasl_exchange(
+        &mut self,
+        mechanism: Mechanism,
+        sasl_mechanism: SaslMechanism,
+        response: Option<String>,
+    ) -> trc::Result<Vec<u8>> {
+        let (exchange, response) = match (self.sasl.take(), response) {
+            (Some(exchange), response) => (exchange, response.unwrap_or_default()),
+            (None, response) => {
+                // Throttle authentication requests
+                self.server.is_auth_allowed_soft(&self.remote_addr).await?;
+
+                let (exchange, challenge) = self
+                    .server
+                    .sasl_start(sasl_mechanism, self.stream.tls_exporter())
+                    .await?;
+
+                match response {
+                    Some(response) if sasl_mechanism != SaslMechanism::CramMd5 => {
+                        (exchange, response)
+                    }
+                    _ => {
+                        self.sasl = Some(exchange);
+                        return Ok(self.sasl_challenge(mechanism, challenge));
+                    }
+                }
+            }
+        };
+
+        let response = if !response.is_empty() {
+            base64_decode(response.as_bytes()).ok_or_else(|| {
+                trc::AuthEvent::Error
+                    .into_err()
+                    .details("Failed to decode challenge.")
+            })?
+        } else {
+            Vec::new()
+        };
+
+        match self
+            .server
+            .sasl_step(exchange, &response, self.session_id, self.remote_addr, None)
             .await
+        {
+            Ok((exchange, SaslStep::Challenge(challenge))) => {
+                self.sasl = exchange;
+                Ok(self.sasl_challenge(mechanism, challenge))
+            }
+            Ok((_, SaslStep::Authenticated(access_token))) => {
+                self.complete_authentication(Ok((access_token, None)))
+            }
+            Err(err) => self.complete_authentication(Err(err)),
+        }
+    }
+
+    fn sasl_challenge(&mut self, mechanism: Mechanism, challenge: Vec<u8>) -> Vec<u8> {
+        self.receiver.request = receiver::Request {
+            tag: String::new(),
+            command: Command::Authenticate,
+            tokens: vec![receiver::Token::Argument(mechanism.into_bytes())],
+        };
+        self.receiver.state = receiver::State::Argument { last_ch: b' ' };
+        format!("\"{}\"\r\n", sasl_encode_challenge(&challenge)).into_bytes()
+    }
+
+    // Challenge-response mechanisms available on this connection
+    pub fn sasl_mechanisms(&self) -> Vec<SaslMechanism> {
+        // These mechanisms can only verify the primary password
+        if self
+            .server
+            .is_app_password_required(ServerProtocol::ManageSieve)
+        {
+            Vec::new()
+        } else {
+            SaslMechanism::available(
+                self.stream.tls_exporter().is_some(),
+                self.instance.allow_cram_md5,
+            )
+        }
+    }
+
+    fn complete_authentication(
+        &mut self,
+        result: trc::Result<(Arc<AccessToken>, Option<SessionAppPassword>)>,
+    ) -> trc::Result<Vec<u8>> {
+        let (access_token, app_password) = result
             .map_err(|err| {
                 if err.matches(trc::EventType::Auth(trc::AuthEvent::Failed)) {
                     match &self.state {
@@ -101,10 +204,10 @@ impl<T: SessionStream> Session<T> {
 
                 err
             })
-            .and_then(|token| {
+            .and_then(|(token, app_password)| {
                 token
                     .assert_has_permission(Permission::SieveAuthenticate)
-                    .map(|_| token)
+                    .map(|_| (token, app_password))
             })?;
 
         // Enforce concurrency limits
@@ -124,12 +227,14 @@ impl<T: SessionStream> Session<T> {
             access_token,
             in_flight,
         };
+        self.app_password = app_password;
 
         Ok(StatusResponse::ok("Authentication successful").into_bytes())
     }
 
     pub async fn handle_unauthenticate(&mut self) -> trc::Result<Vec<u8>> {
         self.state = State::NotAuthenticated { auth_failures: 0 };
+        self.app_password = None;
 
         trc::event!(
             ManageSieve(trc::ManageSieveEvent::Unauthenticate),
@@ -7,7 +7,8 @@
 use std::time::Instant;
 
 use common::listener::SessionStream;
-use jmap_proto::request::capability::Capabilities;
+use imap::op::authenticate::imap_mechanism;
+use jmap_proto::request::capability::{Capabilities, SieveAccountCapabilities};
 
 use crate::core::{Session, StatusResponse};
 
@@ -22,25 +23,16 @@ impl<T: SessionStream> Session<T> {
             response.extend_from_slice(b"\"STARTTLS\"\r\n");
         }
         if self.stream.is_tls() || self.server.core.imap.allow_plain_auth {
-            response.extend_from_slice(b"\"SASL\" \"PLAIN OAUTHBEARER\"\r\n");
+            response.extend_from_slice(b"\"SASL\" \"PLAIN OAUTHBEARER");
         } else {
-            response.extend_from_slice(b"\"SASL\" \"OAUTHBEARER\"\r\n");
+            response.extend_from_slice(b"\"SASL\" \"OAUTHBEARER");
         };
-        if let Some(sieve) =
-            self.server
-                .core
-                .jmap
-                .capabilities
-                .account
-                .iter()
-                .find_map(|(_, item)| {
-                    if let Capabilities::SieveAccount(sieve) = item {
-                        Some(sieve)
-                    } else {
-                        None
-                    }
-                })
-        {
+        for mechanism in self.sasl_mechanisms() {
+            response.push(b' ');
+            imap_mechanism(mechanism).serialize(&mut response);
+        }
+        response.extend_from_slice(b"\"\r\n");
+        if let Some(sieve) = self.sieve_capabilities() {
             response.extend_from_slice(b"\"SIEVE\" \"");
             response.extend_from_slice(sieve.extensions.join(" ").as_bytes());
             response.extend_from_slice(b"\"\r\n");
@@ -68,4 +60,20 @@ impl<T: SessionStream> Session<T> {
 
         Ok(StatusResponse::ok(message).serialize(response))
     }
+
+    pub fn sieve_capabilities(&self) -> Option<&SieveAccountCapabilities> {
+        self.server
+            .core
+            .jmap
+            .capabilities
+            .account
+            .iter()
+            .find_map(|(_, item)| {
+                if let Capabilities::SieveAccount(sieve) = item {
+                    Some(sieve)
+                } else {
+                    None
+                }
+            })
+    }
 }
@@ -9,8 +9,9 @@ use std::time::Instant;
 use common::listener::SessionStream;
 use directory::Permission;
 use imap_proto::receiver::Request;
+use jmap::JmapMethods;
 
-use crate::core::{Command, Session, StatusResponse};
+use crate::core::{Command, ResponseCode, Session, StatusResponse};
 
 impl<T: SessionStream> Session<T> {
     pub async fn handle_checkscript(&mut self, request: Request<Command>) -> trc::Result<Vec<u8>> {
@@ -31,20 +32,48 @@ impl<T: SessionStream> Session<T> {
             .sieve
             .untrusted_compiler
             .compile(&script)
-            .map(|_| {
-                trc::event!(
-                    ManageSieve(trc::ManageSieveEvent::CheckScript),
-                    SpanId = self.session_id,
-                    Size = script.len(),
-                    Elapsed = op_start.elapsed()
-                );
-
-                StatusResponse::ok("Script is valid.").into_bytes()
-            })
             .map_err(|err| {
                 trc::ManageSieveEvent::Error
                     .into_err()
                     .details(err.to_string())
-            })
+            })?;
+
+        // Warn about limits that would prevent the script from being stored
+        let resource_token = self.state.access_token().as_resource_token();
+        let mut warnings = Vec::new();
+        if let Err(err) = self
+            .server
+            .has_available_quota(&resource_token, script.len() as u64)
+            .await
+        {
+            if err.matches(trc::EventType::Limit(trc::LimitEvent::Quota))
+                || err.matches(trc::EventType::Limit(trc::LimitEvent::TenantQuota))
+            {
+                warnings.push("Storing this script would exceed the quota.");
+            } else {
+                return Err(err);
+            }
+        }
+        if let Err(err) = self.validate_script_count(resource_token.account_id).await {
+            if err.matches(trc::EventType::ManageSieve(trc::ManageSieveEvent::Error)) {
+                warnings.push("The maximum number of scripts has been reached.");
+            } else {
+                return Err(err);
+            }
+        }
+
+        trc::event!(
+            ManageSieve(trc::ManageSieveEvent::CheckScript),
+            SpanId = self.session_id,
+            Size = script.len(),
+            Elapsed = op_start.elapsed()
+        );
+
+        Ok(if warnings.is_empty() {
+            StatusResponse::ok("Script is valid.")
+        } else {
+            StatusResponse::ok(warnings.join(" ")).with_code(ResponseCode::Warnings)
+        }
+        .into_bytes())
     }
 }
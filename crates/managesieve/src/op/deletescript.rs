@@ -14,7 +14,10 @@ use jmap_proto::types::collection::Collection;
 use store::write::log::ChangeLogBuilder;
 use trc::AddContext;
 
-use crate::core::{Command, ResponseCode, Session, StatusResponse};
+use crate::{
+    core::{Command, ResponseCode, Session, StatusResponse},
+    op::normalize_name,
+};
 
 impl<T: SessionStream> Session<T> {
     pub async fn handle_deletescript(&mut self, request: Request<Command>) -> trc::Result<Vec<u8>> {
@@ -33,6 +36,7 @@ impl<T: SessionStream> Session<T> {
                     .into_err()
                     .details("Expected script name as a parameter.")
             })?;
+        let name = normalize_name(&name)?;
 
         let access_token = self.state.access_token();
         let account_id = access_token.primary_id();
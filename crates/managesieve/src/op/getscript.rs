@@ -16,7 +16,10 @@ use jmap_proto::{
 };
 use trc::AddContext;
 
-use crate::core::{Command, ResponseCode, Session, StatusResponse};
+use crate::{
+    core::{Command, ResponseCode, Session, StatusResponse},
+    op::normalize_name,
+};
 
 impl<T: SessionStream> Session<T> {
     pub async fn handle_getscript(&mut self, request: Request<Command>) -> trc::Result<Vec<u8>> {
@@ -34,6 +37,7 @@ impl<T: SessionStream> Session<T> {
                     .into_err()
                     .details("Expected script name as a parameter.")
             })?;
+        let name = normalize_name(&name)?;
         let account_id = self.state.access_token().primary_id();
         let document_id = self.get_script_id(account_id, &name).await?;
         let (blob_section, blob_hash) = self
@@ -12,7 +12,10 @@ use imap_proto::receiver::Request;
 use jmap::JmapMethods;
 use trc::AddContext;
 
-use crate::core::{Command, ResponseCode, Session, StatusResponse};
+use crate::{
+    core::{Command, ResponseCode, Session, StatusResponse},
+    op::normalize_name,
+};
 
 impl<T: SessionStream> Session<T> {
     pub async fn handle_havespace(&mut self, request: Request<Command>) -> trc::Result<Vec<u8>> {
@@ -45,33 +48,38 @@ impl<T: SessionStream> Session<T> {
             })?;
 
         // Validate name
-        let access_token = self.state.access_token();
-        let account_id = access_token.primary_id();
-        self.validate_name(account_id, &name).await?;
+        let name = normalize_name(&name)?;
+        let resource_token = self.state.access_token().as_resource_token();
+        let account_id = resource_token.account_id;
+        let document_id = self.validate_name(account_id, &name).await?;
 
-        // Validate quota
-        if access_token.quota == 0
-            || size as i64
-                + self
-                    .server
-                    .get_used_quota(account_id)
-                    .await
-                    .caused_by(trc::location!())?
-                <= access_token.quota as i64
+        // Validate script size and count
+        if self
+            .sieve_capabilities()
+            .is_some_and(|sieve| size > sieve.max_script_size)
         {
-            trc::event!(
-                ManageSieve(trc::ManageSieveEvent::HaveSpace),
-                SpanId = self.session_id,
-                Size = size,
-                Elapsed = op_start.elapsed()
-            );
-
-            Ok(StatusResponse::ok("").into_bytes())
-        } else {
-            Err(trc::ManageSieveEvent::Error
+            return Err(trc::ManageSieveEvent::Error
                 .into_err()
-                .details("Quota exceeded.")
-                .code(ResponseCode::QuotaMaxSize))
+                .details("Script exceeds the maximum allowed size.")
+                .code(ResponseCode::QuotaMaxSize));
         }
+        if document_id.is_none() {
+            self.validate_script_count(account_id).await?;
+        }
+
+        // Validate quota
+        self.server
+            .has_available_quota(&resource_token, size as u64)
+            .await
+            .caused_by(trc::location!())?;
+
+        trc::event!(
+            ManageSieve(trc::ManageSieveEvent::HaveSpace),
+            SpanId = self.session_id,
+            Size = size,
+            Elapsed = op_start.elapsed()
+        );
+
+        Ok(StatusResponse::ok("").into_bytes())
     }
 }
@@ -8,11 +8,7 @@ use std::time::Instant;
 
 use common::listener::SessionStream;
 use directory::Permission;
-use jmap::JmapMethods;
-use jmap_proto::{
-    object::Object,
-    types::{collection::Collection, property::Property, value::Value},
-};
+use jmap::sieve::get::SieveScriptGet;
 use trc::AddContext;
 
 use crate::core::{Session, StatusResponse};
@@ -24,47 +20,32 @@ impl<T: SessionStream> Session<T> {
 
         let op_start = Instant::now();
         let account_id = self.state.access_token().primary_id();
-        let document_ids = self
+        let scripts = self
             .server
-            .get_document_ids(account_id, Collection::SieveScript)
+            .list_sieve_scripts(account_id)
             .await
-            .caused_by(trc::location!())?
-            .unwrap_or_default();
+            .caused_by(trc::location!())?;
 
-        if document_ids.is_empty() {
+        if scripts.is_empty() {
             return Ok(StatusResponse::ok("").into_bytes());
         }
 
         let mut response = Vec::with_capacity(128);
-        let count = document_ids.len();
+        let count = scripts.len();
 
-        for document_id in document_ids {
-            if let Some(script) = self
-                .server
-                .get_property::<Object<Value>>(
-                    account_id,
-                    Collection::SieveScript,
-                    document_id,
-                    Property::Value,
-                )
-                .await
-                .caused_by(trc::location!())?
-            {
-                response.push(b'\"');
-                if let Some(name) = script.get(&Property::Name).as_string() {
-                    for ch in name.as_bytes() {
-                        if [b'\\', b'\"'].contains(ch) {
-                            response.push(b'\\');
-                        }
-                        response.push(*ch);
-                    }
+        for (_, name, is_active) in scripts {
+            response.push(b'\"');
+            for ch in name.as_bytes() {
+                if [b'\\', b'\"'].contains(ch) {
+                    response.push(b'\\');
                 }
+                response.push(*ch);
+            }
 
-                if script.get(&Property::IsActive).as_bool() == Some(true) {
-                    response.extend_from_slice(b"\" ACTIVE\r\n");
-                } else {
-                    response.extend_from_slice(b"\"\r\n");
-                }
+            if is_active {
+                response.extend_from_slice(b"\" ACTIVE\r\n");
+            } else {
+                response.extend_from_slice(b"\"\r\n");
             }
         }
 
@@ -6,6 +6,7 @@
 
 use common::listener::SessionStream;
 use directory::Permission;
+use jmap::sieve::normalize_script_name;
 
 use crate::core::{Session, State, StatusResponse};
 
@@ -42,3 +43,11 @@ impl<T: SessionStream> Session<T> {
         }
     }
 }
+
+pub fn normalize_name(name: &str) -> trc::Result<String> {
+    normalize_script_name(name).ok_or_else(|| {
+        trc::ManageSieveEvent::Error
+            .into_err()
+            .details("Script name contains invalid characters.")
+    })
+}
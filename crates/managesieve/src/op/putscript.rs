@@ -26,7 +26,10 @@ use store::{
 };
 use trc::AddContext;
 
-use crate::core::{Command, ResponseCode, Session, StatusResponse};
+use crate::{
+    core::{Command, ResponseCode, Session, StatusResponse},
+    op::normalize_name,
+};
 
 impl<T: SessionStream> Session<T> {
     pub async fn handle_putscript(&mut self, request: Request<Command>) -> trc::Result<Vec<u8>> {
@@ -42,9 +45,8 @@ impl<T: SessionStream> Session<T> {
                 trc::ManageSieveEvent::Error
                     .into_err()
                     .details("Expected script name as a parameter.")
-            })?
-            .trim()
-            .to_string();
+            })?;
+        let name = normalize_name(&name)?;
         let mut script_bytes = tokens
             .next()
             .ok_or_else(|| {
@@ -63,19 +65,10 @@ impl<T: SessionStream> Session<T> {
             .await
             .caused_by(trc::location!())?;
 
-        if self
-            .server
-            .get_document_ids(account_id, Collection::SieveScript)
-            .await
-            .caused_by(trc::location!())?
-            .map(|ids| ids.len() as usize)
-            .unwrap_or(0)
-            > self.server.core.jmap.sieve_max_scripts
-        {
-            return Err(trc::ManageSieveEvent::Error
-                .into_err()
-                .details("Too many scripts.")
-                .code(ResponseCode::QuotaMaxScripts));
+        // Validate name and script count
+        let document_id = self.validate_name(account_id, &name).await?;
+        if document_id.is_none() {
+            self.validate_script_count(account_id).await?;
         }
 
         // Compile script
@@ -103,8 +96,7 @@ impl<T: SessionStream> Session<T> {
             }
         }
 
-        // Validate name
-        if let Some(document_id) = self.validate_name(account_id, &name).await? {
+        if let Some(document_id) = document_id {
             // Obtain script values
             let script = self
                 .server
@@ -264,6 +256,25 @@ impl<T: SessionStream> Session<T> {
         Ok(StatusResponse::ok("Success.").into_bytes())
     }
 
+    pub async fn validate_script_count(&self, account_id: u32) -> trc::Result<()> {
+        if self
+            .server
+            .get_document_ids(account_id, Collection::SieveScript)
+            .await
+            .caused_by(trc::location!())?
+            .map(|ids| ids.len() as usize)
+            .unwrap_or(0)
+            >= self.server.core.jmap.sieve_max_scripts
+        {
+            Err(trc::ManageSieveEvent::Error
+                .into_err()
+                .details("Too many scripts.")
+                .code(ResponseCode::QuotaMaxScripts))
+        } else {
+            Ok(())
+        }
+    }
+
     pub async fn validate_name(&self, account_id: u32, name: &str) -> trc::Result<Option<u32>> {
         if name.is_empty() {
             Err(trc::ManageSieveEvent::Error
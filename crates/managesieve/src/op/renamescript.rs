@@ -17,7 +17,10 @@ use jmap_proto::{
 use store::write::{assert::HashedValue, log::ChangeLogBuilder, BatchBuilder};
 use trc::AddContext;
 
-use crate::core::{Command, ResponseCode, Session, StatusResponse};
+use crate::{
+    core::{Command, ResponseCode, Session, StatusResponse},
+    op::normalize_name,
+};
 
 impl<T: SessionStream> Session<T> {
     pub async fn handle_renamescript(&mut self, request: Request<Command>) -> trc::Result<Vec<u8>> {
@@ -33,9 +36,8 @@ impl<T: SessionStream> Session<T> {
                 trc::ManageSieveEvent::Error
                     .into_err()
                     .details("Expected old script name as a parameter.")
-            })?
-            .trim()
-            .to_string();
+            })?;
+        let name = normalize_name(&name)?;
         let new_name = tokens
             .next()
             .and_then(|s| s.unwrap_string().ok())
@@ -43,20 +45,25 @@ impl<T: SessionStream> Session<T> {
                 trc::ManageSieveEvent::Error
                     .into_err()
                     .details("Expected new script name as a parameter.")
-            })?
-            .trim()
-            .to_string();
+            })?;
+        let new_name = normalize_name(&new_name)?;
 
         // Validate name
+        let account_id = self.state.access_token().primary_id();
+        let document_id = self.get_script_id(account_id, &name).await?;
         if name == new_name {
             return Ok(StatusResponse::ok("Old and new script names are the same.").into_bytes());
+        } else if name.eq_ignore_ascii_case("vacation") {
+            return Err(trc::ManageSieveEvent::Error
+                .into_err()
+                .details("The 'vacation' script cannot be renamed."));
         }
-        let account_id = self.state.access_token().primary_id();
-        let document_id = self.get_script_id(account_id, &name).await?;
         if self.validate_name(account_id, &new_name).await?.is_some() {
             return Err(trc::ManageSieveEvent::Error
                 .into_err()
-                .details(format!("A sieve script with name '{name}' already exists.",))
+                .details(format!(
+                    "A sieve script with name '{new_name}' already exists."
+                ))
                 .code(ResponseCode::AlreadyExists));
         }
 
@@ -78,6 +85,16 @@ impl<T: SessionStream> Session<T> {
                     .code(ResponseCode::NonExistent)
             })?;
 
+        // An active script keeps its active flag, so it cannot become the outbound script
+        if new_name.eq_ignore_ascii_case("outbound")
+            && script.inner.get(&Property::IsActive).as_bool() == Some(true)
+        {
+            return Err(trc::ManageSieveEvent::Error
+                .into_err()
+                .details("The active script cannot be renamed to 'outbound'.")
+                .code(ResponseCode::Active));
+        }
+
         // Write record
         let mut batch = BatchBuilder::new();
         batch
@@ -14,7 +14,10 @@ use jmap_proto::types::collection::Collection;
 use store::write::log::ChangeLogBuilder;
 use trc::AddContext;
 
-use crate::core::{Command, Session, StatusResponse};
+use crate::{
+    core::{Command, Session, StatusResponse},
+    op::normalize_name,
+};
 
 impl<T: SessionStream> Session<T> {
     pub async fn handle_setactive(&mut self, request: Request<Command>) -> trc::Result<Vec<u8>> {
@@ -32,6 +35,14 @@ impl<T: SessionStream> Session<T> {
                     .into_err()
                     .details("Expected script name as a parameter.")
             })?;
+        let name = normalize_name(&name)?;
+
+        // The outbound script runs on submission and is never active for delivery
+        if name.eq_ignore_ascii_case("outbound") {
+            return Err(trc::ManageSieveEvent::Error
+                .into_err()
+                .details("The 'outbound' script runs on submission and cannot be activated."));
+        }
 
         // De/activate script
         let account_id = self.state.access_token().primary_id();
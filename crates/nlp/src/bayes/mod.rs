@@ -81,6 +81,18 @@ impl From<Gram<'_>> for TokenHash {
     }
 }
 
+impl TokenHash {
+    // Derives a distinct hash for a token in a per-account model, used to
+    // keep the weights of each model apart in the token cache
+    pub fn for_account(&self, account_id: u32) -> Self {
+        let salt = (account_id as u64 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        TokenHash {
+            h1: self.h1 ^ salt,
+            h2: self.h2.rotate_left(32) ^ salt,
+        }
+    }
+}
+
 impl std::hash::Hash for TokenHash {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
         state.write_u64(self.h1 ^ self.h2);
@@ -22,6 +22,11 @@ impl<T: SessionStream> Session<T> {
             Contents = trc::Value::from_maybe_string(bytes),
         );
 
+        // Terminate sessions authenticated with a revoked app password
+        if self.is_app_password_revoked().await {
+            return SessionResult::Close;
+        }
+
         let mut bytes = bytes.iter();
         let mut requests = Vec::with_capacity(2);
 
@@ -170,6 +175,31 @@ impl<T: SessionStream> Session<T> {
         SessionResult::Continue
     }
 
+    pub async fn is_app_password_revoked(&mut self) -> bool {
+        match &self.app_password {
+            Some(app_password) if self.server.is_app_password_revoked(app_password).await => {
+                trc::event!(
+                    Security(trc::SecurityEvent::Unauthorized),
+                    SpanId = self.session_id,
+                    Details = "App password revoked",
+                );
+
+                self.write_bytes(&b"-ERR App password revoked.\r\n"[..])
+                    .await
+                    .ok();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Only sessions using an app password of the notified account have to check
+    pub fn is_app_password_account(&self, account_id: u32) -> bool {
+        self.app_password
+            .as_ref()
+            .is_some_and(|app_password| app_password.account_id == account_id)
+    }
+
     async fn validate_request(
         &self,
         command: Command<String, Mechanism>,
@@ -9,6 +9,7 @@ use common::{
         sasl::{sasl_decode_challenge_oauth, sasl_decode_challenge_plain},
         AuthRequest,
     },
+    config::server::ServerProtocol,
     listener::{limiter::ConcurrencyLimiter, SessionStream},
     ConcurrencyLimiters,
 };
@@ -72,12 +73,13 @@ impl<T: SessionStream> Session<T> {
         self.server.is_auth_allowed_soft(&self.remote_addr).await?;
 
         // Authenticate
-        let access_token = self
+        let (access_token, app_password) = self
             .server
-            .authenticate(&AuthRequest::from_credentials(
+            .authenticate_session(&AuthRequest::from_credentials(
                 credentials,
                 self.session_id,
                 self.remote_addr,
+                ServerProtocol::Pop3,
             ))
             .await
             .map_err(|err| {
@@ -100,10 +102,10 @@ impl<T: SessionStream> Session<T> {
 
                 err
             })
-            .and_then(|token| {
+            .and_then(|(token, app_password)| {
                 token
                     .assert_has_permission(Permission::Pop3Authenticate)
-                    .map(|_| token)
+                    .map(|_| (token, app_password))
             })?;
 
         // Enforce concurrency limits
@@ -127,6 +129,7 @@ impl<T: SessionStream> Session<T> {
             mailbox,
             access_token,
         };
+        self.app_password = app_password;
         self.write_ok("Authentication successful").await
     }
 
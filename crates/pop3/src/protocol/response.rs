@@ -135,6 +135,8 @@ impl Mechanism {
             Mechanism::DigestMd5 => "DIGEST-MD5",
             Mechanism::ScramSha1 => "SCRAM-SHA-1",
             Mechanism::ScramSha256 => "SCRAM-SHA-256",
+            Mechanism::ScramSha1Plus => "SCRAM-SHA-1-PLUS",
+            Mechanism::ScramSha256Plus => "SCRAM-SHA-256-PLUS",
             Mechanism::Apop => "APOP",
             Mechanism::Ntlm => "NTLM",
             Mechanism::Gssapi => "GSSAPI",
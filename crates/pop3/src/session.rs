@@ -41,6 +41,7 @@ impl SessionManager for Pop3SessionManager {
                 in_flight: session.in_flight,
                 remote_addr: session.remote_ip,
                 session_id: session.session_id,
+                app_password: None,
             };
 
             if session
@@ -67,6 +68,7 @@ impl<T: SessionStream> Session<T> {
     pub async fn handle_conn(&mut self) -> bool {
         let mut buf = vec![0; 8192];
         let mut shutdown_rx = self.instance.shutdown_rx.clone();
+        let mut revocations = self.server.subscribe_app_password_revocations();
 
         loop {
             tokio::select! {
@@ -129,6 +131,13 @@ impl<T: SessionStream> Session<T> {
 
                     self.write_bytes(&b"* BYE Server shutting down.\r\n"[..]).await.ok();
                     break;
+                },
+                Ok(account_id) = revocations.recv() => {
+                    if self.is_app_password_account(account_id)
+                        && self.is_app_password_revoked().await
+                    {
+                        break;
+                    }
                 }
             };
         }
@@ -149,6 +158,7 @@ impl<T: SessionStream> Session<T> {
             session_id: self.session_id,
             in_flight: self.in_flight,
             remote_addr: self.remote_addr,
+            app_password: self.app_password,
         })
     }
 }
@@ -12,7 +12,7 @@ use std::{
 };
 
 use common::{
-    auth::AccessToken,
+    auth::{AccessToken, SessionAppPassword},
     config::smtp::auth::VerifyStrategy,
     listener::{
         limiter::{ConcurrencyLimiter, InFlight},
@@ -30,7 +30,7 @@ use utils::snowflake::SnowflakeIdGenerator;
 
 use crate::{
     inbound::auth::SaslToken,
-    queue::{DomainPart, QueueId},
+    queue::{list::ListRecipient, DomainPart, QueueId},
 };
 
 pub mod params;
@@ -81,11 +81,13 @@ pub struct SessionData {
 
     pub mail_from: Option<SessionAddress>,
     pub rcpt_to: Vec<SessionAddress>,
+    pub rcpt_lists: Vec<ListRecipient>,
     pub rcpt_errors: usize,
     pub rcpt_oks: usize,
     pub message: Vec<u8>,
 
     pub authenticated_as: Option<Arc<AccessToken>>,
+    pub authenticated_app: Option<SessionAppPassword>,
     pub auth_errors: usize,
 
     pub priority: i16,
@@ -140,6 +142,7 @@ pub struct SessionParameters {
     pub iprev: VerifyStrategy,
     pub spf_ehlo: VerifyStrategy,
     pub spf_mail_from: VerifyStrategy,
+    pub trusted_forwarder: bool,
 }
 
 impl SessionData {
@@ -161,7 +164,9 @@ impl SessionData {
             helo_domain: String::new(),
             mail_from: None,
             rcpt_to: Vec::new(),
+            rcpt_lists: Vec::new(),
             authenticated_as: None,
+            authenticated_app: None,
             priority: 0,
             valid_until: Instant::now(),
             rcpt_errors: 0,
@@ -223,6 +228,8 @@ static SIEVE: LazyLock<Arc<ServerInstance>> = LazyLock::new(|| {
         limiter: ConcurrencyLimiter::new(0),
         shutdown_rx: tokio::sync::watch::channel(false).1,
         proxy_networks: vec![],
+        proxy_required: false,
+        allow_cram_md5: false,
         span_id_gen: Arc::new(SnowflakeIdGenerator::new()),
     })
 });
@@ -257,6 +264,7 @@ impl Session<common::listener::stream::NullIo> {
                 iprev: VerifyStrategy::Disable,
                 spf_ehlo: VerifyStrategy::Disable,
                 spf_mail_from: VerifyStrategy::Disable,
+                trusted_forwarder: false,
                 can_expn: false,
                 can_vrfy: false,
             },
@@ -311,6 +319,7 @@ impl SessionData {
             helo_domain: "localhost".into(),
             mail_from,
             rcpt_to,
+            rcpt_lists: Vec::new(),
             rcpt_errors: 0,
             rcpt_oks: 0,
             message,
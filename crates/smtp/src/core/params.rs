@@ -47,6 +47,15 @@ impl<T: SessionStream> Session<T> {
             )
             .await
             .unwrap_or(VerifyStrategy::Relaxed);
+
+        // SPF is not evaluated against the IP of a trusted forwarder, its
+        // Authentication-Results are imported instead
+        self.params.trusted_forwarder = self.is_trusted_forwarder().await;
+        if self.params.trusted_forwarder {
+            self.params.spf_ehlo = VerifyStrategy::Disable;
+            self.params.spf_mail_from = VerifyStrategy::Disable;
+        }
+
         self.params.iprev = self
             .server
             .eval_if(
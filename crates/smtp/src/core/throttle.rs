@@ -217,7 +217,7 @@ impl<T: SessionStream> Session<T> {
                         .core
                         .storage
                         .lookup
-                        .is_rate_allowed(key.hash.as_slice(), rate, false)
+                        .check_rate(key.hash.as_slice(), rate, t.window, false)
                         .await
                         .unwrap_or_default()
                         .is_some()
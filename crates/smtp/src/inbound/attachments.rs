@@ -0,0 +1,109 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Instant;
+
+use common::{
+    attachments::AttachmentReport, config::spamfilter::FilterAction, listener::SessionStream,
+};
+use mail_auth::AuthenticatedMessage;
+use mail_parser::MessageParser;
+use trc::SpamEvent;
+
+use crate::{
+    core::Session,
+    inbound::{milter::Modification, FilterResponse},
+};
+
+impl<T: SessionStream> Session<T> {
+    pub async fn inspect_attachments(
+        &self,
+        message: &AuthenticatedMessage<'_>,
+    ) -> Option<AttachmentReport> {
+        let config = &self.server.core.spam.attachments;
+        if !self
+            .server
+            .eval_if(&config.enable, self, self.data.session_id)
+            .await
+            .unwrap_or(false)
+        {
+            return None;
+        }
+
+        let time = Instant::now();
+        let report = config.inspect(&MessageParser::default().parse(message.raw_message())?);
+
+        trc::event!(
+            Spam(SpamEvent::AttachmentScan),
+            SpanId = self.data.session_id,
+            Total = report.files.len(),
+            Details = report.types().join(", "),
+            Elapsed = time.elapsed(),
+        );
+
+        Some(report)
+    }
+
+    // Returns the matched types when the message is moved to the quarantine store
+    pub fn attachment_quarantine(&self, report: &AttachmentReport) -> Option<String> {
+        if !self.server.core.spam.quarantine.enable {
+            return None;
+        }
+        let (action, types) = report.matching_policy(&self.server.core.spam.attachments)?;
+        if action != FilterAction::Quarantine {
+            return None;
+        }
+        let types = types.join(", ");
+
+        trc::event!(
+            Spam(SpamEvent::AttachmentPolicy),
+            SpanId = self.data.session_id,
+            Result = action.as_str(),
+            Details = types.clone(),
+        );
+
+        Some(types)
+    }
+
+    pub fn apply_attachment_policy(
+        &self,
+        report: &AttachmentReport,
+    ) -> Result<Vec<Modification>, FilterResponse> {
+        let Some((action, types)) = report.matching_policy(&self.server.core.spam.attachments)
+        else {
+            return Ok(Vec::new());
+        };
+        let types = types.join(", ");
+
+        trc::event!(
+            Spam(SpamEvent::AttachmentPolicy),
+            SpanId = self.data.session_id,
+            Result = action.as_str(),
+            Details = types.clone(),
+        );
+
+        match action {
+            FilterAction::Reject => Err(FilterResponse {
+                message: "550 5.7.1 Message contains a prohibited attachment.\r\n".into(),
+                disconnect: false,
+            }),
+            FilterAction::Quarantine => Ok(vec![
+                Modification::AddHeader {
+                    name: "X-Quarantine".to_string(),
+                    value: "true".to_string(),
+                },
+                Modification::AddHeader {
+                    name: "X-Attachment-Warning".to_string(),
+                    value: types,
+                },
+            ]),
+            FilterAction::Tag => Ok(vec![Modification::AddHeader {
+                name: "X-Attachment-Warning".to_string(),
+                value: types,
+            }]),
+        }
+    }
+}
@@ -4,19 +4,26 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+use std::sync::Arc;
+
 use common::{
     auth::{
         sasl::{
             sasl_decode_challenge_oauth, sasl_decode_challenge_plain, sasl_decode_challenge_xoauth,
+            sasl_encode_challenge, SaslExchange, SaslMechanism, SaslStep,
         },
-        AuthRequest,
+        AccessToken, AuthRequest, SessionAppPassword,
     },
+    config::server::ServerProtocol,
     listener::SessionStream,
 };
-use directory::Permission;
+use directory::{core::scram::ScramAlgorithm, Permission};
 use mail_parser::decoders::base64::base64_decode;
 use mail_send::Credentials;
-use smtp_proto::{IntoString, AUTH_LOGIN, AUTH_OAUTHBEARER, AUTH_PLAIN, AUTH_XOAUTH2};
+use smtp_proto::{
+    IntoString, AUTH_CRAM_MD5, AUTH_LOGIN, AUTH_OAUTHBEARER, AUTH_PLAIN, AUTH_SCRAM_SHA_1,
+    AUTH_SCRAM_SHA_1_PLUS, AUTH_SCRAM_SHA_256, AUTH_SCRAM_SHA_256_PLUS, AUTH_XOAUTH2,
+};
 use trc::{AuthEvent, SmtpEvent};
 
 use crate::core::Session;
@@ -24,45 +31,70 @@ use crate::core::Session;
 pub struct SaslToken {
     mechanism: u64,
     credentials: Credentials<String>,
+    sasl: Option<SaslMechanism>,
+    exchange: Option<SaslExchange>,
 }
 
 impl SaslToken {
     pub fn from_mechanism(mechanism: u64) -> Option<SaslToken> {
-        match mechanism {
-            AUTH_PLAIN | AUTH_LOGIN => SaslToken {
-                mechanism,
-                credentials: Credentials::Plain {
-                    username: String::new(),
-                    secret: String::new(),
-                },
-            }
-            .into(),
-            AUTH_OAUTHBEARER => SaslToken {
-                mechanism,
-                credentials: Credentials::OAuthBearer {
-                    token: String::new(),
-                },
-            }
-            .into(),
-            AUTH_XOAUTH2 => SaslToken {
-                mechanism,
-                credentials: Credentials::XOauth2 {
-                    username: String::new(),
-                    secret: String::new(),
-                },
+        let credentials = match mechanism {
+            AUTH_PLAIN | AUTH_LOGIN => Credentials::Plain {
+                username: String::new(),
+                secret: String::new(),
+            },
+            AUTH_OAUTHBEARER => Credentials::OAuthBearer {
+                token: String::new(),
+            },
+            AUTH_XOAUTH2 => Credentials::XOauth2 {
+                username: String::new(),
+                secret: String::new(),
+            },
+            _ => {
+                // Challenge-response mechanisms keep their state in the exchange
+                return sasl_mechanism(mechanism).map(|sasl| SaslToken {
+                    mechanism,
+                    credentials: Credentials::OAuthBearer {
+                        token: String::new(),
+                    },
+                    sasl: Some(sasl),
+                    exchange: None,
+                });
             }
-            .into(),
-            _ => None,
+        };
+
+        SaslToken {
+            mechanism,
+            credentials,
+            sasl: None,
+            exchange: None,
         }
+        .into()
     }
 }
 
+fn sasl_mechanism(mechanism: u64) -> Option<SaslMechanism> {
+    let (algorithm, plus) = match mechanism {
+        AUTH_CRAM_MD5 => return Some(SaslMechanism::CramMd5),
+        AUTH_SCRAM_SHA_1 => (ScramAlgorithm::Sha1, false),
+        AUTH_SCRAM_SHA_256 => (ScramAlgorithm::Sha256, false),
+        AUTH_SCRAM_SHA_1_PLUS => (ScramAlgorithm::Sha1, true),
+        AUTH_SCRAM_SHA_256_PLUS => (ScramAlgorithm::Sha256, true),
+        _ => return None,
+    };
+
+    Some(SaslMechanism::Scram { algorithm, plus })
+}
+
 impl<T: SessionStream> Session<T> {
     pub async fn handle_sasl_response(
         &mut self,
         token: &mut SaslToken,
         response: &[u8],
     ) -> Result<bool, ()> {
+        if let Some(mechanism) = token.sasl {
+            return self.handle_sasl_exchange(token, mechanism, response).await;
+        }
+
         if response.is_empty() {
             match (token.mechanism, &token.credentials) {
                 (AUTH_PLAIN | AUTH_XOAUTH2 | AUTH_OAUTHBEARER, _) => {
@@ -113,29 +145,127 @@ impl<T: SessionStream> Session<T> {
         self.auth_error(b"500 5.5.6 Invalid challenge.\r\n").await
     }
 
-    pub async fn authenticate(&mut self, credentials: Credentials<String>) -> Result<bool, ()> {
-        if let Some(directory) = &self.params.auth_directory {
-            // Authenticate
-            let result = self
+    async fn handle_sasl_exchange(
+        &mut self,
+        token: &mut SaslToken,
+        mechanism: SaslMechanism,
+        response: &[u8],
+    ) -> Result<bool, ()> {
+        let Some(directory) = self.params.auth_directory.clone() else {
+            return self.complete_authentication(None).await;
+        };
+
+        let exchange = if let Some(exchange) = token.exchange.take() {
+            exchange
+        } else {
+            let (exchange, challenge) = match self
                 .server
-                .authenticate(
+                .sasl_start(mechanism, self.stream.tls_exporter())
+                .await
+            {
+                Ok(result) => result,
+                Err(err) => return self.complete_authentication(Some(Err(err))).await,
+            };
+
+            // CRAM-MD5 does not allow an initial response
+            if response.is_empty() || mechanism == SaslMechanism::CramMd5 {
+                token.exchange = Some(exchange);
+                self.write(format!("334 {}\r\n", sasl_encode_challenge(&challenge)).as_bytes())
+                    .await?;
+                return Ok(true);
+            }
+
+            exchange
+        };
+
+        let response = if response.is_empty() {
+            Vec::new()
+        } else if let Some(response) = base64_decode(response) {
+            response
+        } else {
+            return self.auth_error(b"500 5.5.6 Invalid challenge.\r\n").await;
+        };
+
+        match self
+            .server
+            .sasl_step(
+                exchange,
+                &response,
+                self.data.session_id,
+                self.data.remote_ip,
+                Some(directory.as_ref()),
+            )
+            .await
+        {
+            Ok((exchange, SaslStep::Challenge(challenge))) => {
+                token.exchange = exchange;
+                self.write(format!("334 {}\r\n", sasl_encode_challenge(&challenge)).as_bytes())
+                    .await?;
+                Ok(true)
+            }
+            Ok((_, SaslStep::Authenticated(access_token))) => {
+                self.complete_authentication(Some(Ok((access_token, None))))
+                    .await
+            }
+            Err(err) => self.complete_authentication(Some(Err(err))).await,
+        }
+    }
+
+    // Channel binding requires TLS 1.3 and CRAM-MD5 has to be enabled on the listener
+    pub fn filter_auth_mechanisms(&self, mut mechanisms: u64) -> u64 {
+        if self.server.is_app_password_required(ServerProtocol::Smtp) {
+            // These mechanisms can only verify the primary password
+            mechanisms &= !(AUTH_SCRAM_SHA_1
+                | AUTH_SCRAM_SHA_1_PLUS
+                | AUTH_SCRAM_SHA_256
+                | AUTH_SCRAM_SHA_256_PLUS
+                | AUTH_CRAM_MD5);
+        }
+        if self.stream.tls_exporter().is_none() {
+            mechanisms &= !(AUTH_SCRAM_SHA_1_PLUS | AUTH_SCRAM_SHA_256_PLUS);
+        }
+        if !self.instance.allow_cram_md5 {
+            mechanisms &= !AUTH_CRAM_MD5;
+        }
+        mechanisms
+    }
+
+    pub async fn authenticate(&mut self, credentials: Credentials<String>) -> Result<bool, ()> {
+        let result = if let Some(directory) = &self.params.auth_directory {
+            self.server
+                .authenticate_session(
                     &AuthRequest::from_credentials(
                         credentials,
                         self.data.session_id,
                         self.data.remote_ip,
+                        ServerProtocol::Smtp,
                     )
                     .with_directory(directory),
                 )
                 .await
-                .and_then(|access_token| {
-                    access_token
-                        .assert_has_permission(Permission::EmailSend)
-                        .map(|_| access_token)
-                });
+                .into()
+        } else {
+            None
+        };
+
+        self.complete_authentication(result).await
+    }
+
+    async fn complete_authentication(
+        &mut self,
+        result: Option<trc::Result<(Arc<AccessToken>, Option<SessionAppPassword>)>>,
+    ) -> Result<bool, ()> {
+        if let Some(result) = result {
+            let result = result.and_then(|(access_token, app_password)| {
+                access_token
+                    .assert_has_permission(Permission::EmailSend)
+                    .map(|_| (access_token, app_password))
+            });
 
             match result {
-                Ok(access_token) => {
+                Ok((access_token, app_password)) => {
                     self.data.authenticated_as = access_token.into();
+                    self.data.authenticated_app = app_password;
                     self.eval_post_auth_params().await;
                     self.write(b"235 2.7.0 Authentication succeeded.\r\n")
                         .await?;
@@ -0,0 +1,123 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Instant;
+
+use common::{clamav::ClamAvVerdict, config::spamfilter::FilterAction, listener::SessionStream};
+use mail_auth::AuthenticatedMessage;
+use trc::SpamEvent;
+
+use crate::{
+    core::{Session, SessionAddress},
+    inbound::{milter::Modification, FilterResponse},
+    queue::DomainPart,
+};
+
+impl<T: SessionStream> Session<T> {
+    pub async fn run_clamav(
+        &self,
+        message: &AuthenticatedMessage<'_>,
+    ) -> Result<Option<ClamAvVerdict>, FilterResponse> {
+        let Some(clamav) = &self.server.core.spam.clamav else {
+            return Ok(None);
+        };
+        if !self
+            .server
+            .eval_if(&clamav.enable, self, self.data.session_id)
+            .await
+            .unwrap_or(false)
+        {
+            return Ok(None);
+        }
+
+        let time = Instant::now();
+        match clamav.scan(message.raw_message()).await {
+            Ok(verdict) => {
+                trc::event!(
+                    Spam(SpamEvent::ClamAv),
+                    SpanId = self.data.session_id,
+                    Result = verdict.as_str(),
+                    Details = verdict.signature().map(|s| s.to_string()),
+                    Elapsed = time.elapsed(),
+                );
+
+                Ok(Some(verdict))
+            }
+            Err(err) => {
+                trc::event!(
+                    Spam(SpamEvent::ClamAvError),
+                    SpanId = self.data.session_id,
+                    Reason = err,
+                    Elapsed = time.elapsed(),
+                );
+
+                if clamav.tempfail_on_error {
+                    Err(FilterResponse::server_failure())
+                } else {
+                    Ok(Some(ClamAvVerdict::Error))
+                }
+            }
+        }
+    }
+
+    // Returns the virus signature when infected messages are moved to the quarantine store
+    pub fn clamav_quarantine<'x>(&self, verdict: &'x ClamAvVerdict) -> Option<&'x str> {
+        match (&self.server.core.spam.clamav, verdict) {
+            (Some(clamav), ClamAvVerdict::Infected(signature))
+                if clamav.action == FilterAction::Quarantine
+                    && self.server.core.spam.quarantine.enable =>
+            {
+                Some(signature)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn apply_clamav_policy(
+        &mut self,
+        verdict: &ClamAvVerdict,
+    ) -> Result<Vec<Modification>, FilterResponse> {
+        let (Some(clamav), ClamAvVerdict::Infected(signature)) =
+            (&self.server.core.spam.clamav, verdict)
+        else {
+            return Ok(Vec::new());
+        };
+
+        match clamav.action {
+            FilterAction::Reject => Err(FilterResponse {
+                message: format!("550 5.7.1 Message contains a virus ({signature}).\r\n").into(),
+                disconnect: false,
+            }),
+            FilterAction::Quarantine => {
+                // Deliver the message only to the quarantine account
+                if let Some(account) = &clamav.quarantine_account {
+                    self.data.rcpt_to = vec![SessionAddress {
+                        address: account.clone(),
+                        address_lcase: account.clone(),
+                        domain: account.domain_part().to_string(),
+                        flags: 0,
+                        dsn_info: None,
+                    }];
+                }
+
+                Ok(vec![
+                    Modification::AddHeader {
+                        name: "X-Quarantine".to_string(),
+                        value: "true".to_string(),
+                    },
+                    Modification::AddHeader {
+                        name: "X-Virus".to_string(),
+                        value: signature.clone(),
+                    },
+                ])
+            }
+            FilterAction::Tag => Ok(vec![Modification::AddHeader {
+                name: "X-Virus".to_string(),
+                value: signature.clone(),
+            }]),
+        }
+    }
+}
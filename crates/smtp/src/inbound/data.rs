@@ -12,11 +12,14 @@ use std::{
 };
 
 use common::{
-    config::smtp::{auth::VerifyStrategy, session::Stage},
+    config::{
+        server::ServerProtocol,
+        smtp::{auth::VerifyStrategy, session::Stage},
+    },
     listener::SessionStream,
     psl,
-    scripts::ScriptModification,
 };
+use directory::backend::RcptType;
 use mail_auth::{
     common::{headers::HeaderWriter, verify::VerifySignature},
     dmarc, AuthenticatedMessage, AuthenticationResults, DkimResult, DmarcResult, ReceivedSpf,
@@ -34,7 +37,13 @@ use utils::config::Rate;
 use crate::{
     core::{Session, SessionAddress, State},
     inbound::milter::Modification,
-    queue::{self, quota::HasQueueQuota, Message, MessageSource, QueueEnvelope, Schedule},
+    queue::{
+        self,
+        list::{ListPost, ListRecipient, SmtpMailingList},
+        quarantine::QuarantineStage,
+        quota::HasQueueQuota,
+        Message, MessageSource, QueueEnvelope, QueueId, Schedule,
+    },
     reporting::analysis::AnalyzeReport,
     scripts::ScriptResult,
 };
@@ -80,6 +89,29 @@ impl<T: SessionStream> Session<T> {
                 .into();
         }
 
+        // Import authentication results from trusted forwarders
+        let imported_results = if self.params.trusted_forwarder {
+            let imported_results = self.import_auth_results(&auth_message);
+
+            if let Some(imported_results) = &imported_results {
+                trc::event!(
+                    Smtp(SmtpEvent::AuthResultsImported),
+                    SpanId = self.data.session_id,
+                    Id = imported_results.authserv_id.clone(),
+                    Details = imported_results
+                        .results
+                        .iter()
+                        .map(|r| format!("{}={}", r.method, r.result))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                );
+            }
+
+            imported_results
+        } else {
+            None
+        };
+
         // Verify DKIM
         let dkim = self
             .server
@@ -91,7 +123,7 @@ impl<T: SessionStream> Session<T> {
             .eval_if(&ac.dmarc.verify, self, self.data.session_id)
             .await
             .unwrap_or(VerifyStrategy::Relaxed);
-        let dkim_output = if dkim.verify() || dmarc.verify() {
+        let dkim_output = if imported_results.is_none() && (dkim.verify() || dmarc.verify()) {
             let time = Instant::now();
             let dkim_output = self
                 .server
@@ -161,7 +193,7 @@ impl<T: SessionStream> Session<T> {
             .eval_if::<String, _>(&ac.arc.seal, self, self.data.session_id)
             .await
             .and_then(|name| self.server.get_arc_sealer(&name, self.data.session_id));
-        let arc_output = if arc.verify() || arc_sealer.is_some() {
+        let arc_output = if imported_results.is_none() && (arc.verify() || arc_sealer.is_some()) {
             let time = Instant::now();
             let arc_output = self
                 .server
@@ -310,6 +342,28 @@ impl<T: SessionStream> Session<T> {
             _ => (None, None),
         };
 
+        // Apply the DMARC policy reported by the trusted forwarder
+        let (dmarc_result, dmarc_policy) = match &imported_results {
+            Some(imported_results) if dmarc.verify() => {
+                let dmarc_result = imported_results.dmarc_result();
+                let dmarc_policy = imported_results.dmarc_policy();
+
+                if dmarc.is_strict()
+                    && dmarc_policy == Some(dmarc::Policy::Reject)
+                    && !matches!(dmarc_result, Some(DmarcResult::Pass))
+                {
+                    return if matches!(dmarc_result, Some(DmarcResult::TempError(_))) {
+                        (&b"451 4.7.1 Email temporarily rejected per DMARC policy.\r\n"[..]).into()
+                    } else {
+                        (&b"550 5.7.1 Email rejected per DMARC policy.\r\n"[..]).into()
+                    };
+                }
+
+                (dmarc_result, dmarc_policy)
+            }
+            _ => (dmarc_result, dmarc_policy),
+        };
+
         // Analyze reports
         if is_report {
             self.server
@@ -345,7 +399,11 @@ impl<T: SessionStream> Session<T> {
             .await
             .unwrap_or(true)
         {
-            auth_results.write_header(&mut headers);
+            if let Some(imported_results) = &imported_results {
+                imported_results.write_header(&self.hostname, &mut headers);
+            } else {
+                auth_results.write_header(&mut headers);
+            }
         }
 
         // Add Received-SPF header
@@ -412,6 +470,83 @@ impl<T: SessionStream> Session<T> {
             }
         };
 
+        // Scan message with Rspamd, unless an upstream MTA already filtered it
+        if self
+            .server
+            .eval_if(&dc.spam_filter, self, self.data.session_id)
+            .await
+            .unwrap_or(true)
+        {
+            match self.run_rspamd(&auth_message, message_id).await {
+                Ok(modifications_) => {
+                    modifications.extend(modifications_);
+                }
+                Err(response) => {
+                    return response.into_bytes();
+                }
+            };
+        }
+
+        // Scan message for malware
+        let clamav = match self.run_clamav(&auth_message).await {
+            Ok(verdict) => verdict,
+            Err(response) => {
+                return response.into_bytes();
+            }
+        };
+        if let Some(verdict) = &clamav {
+            if let Some(signature) = self.clamav_quarantine(verdict) {
+                return self
+                    .store_quarantine(
+                        QuarantineStage::ClamAv,
+                        "virus",
+                        signature.to_string(),
+                        modifications,
+                        &auth_message,
+                        &headers,
+                    )
+                    .await;
+            }
+            match self.apply_clamav_policy(verdict) {
+                Ok(modifications_) => {
+                    modifications.extend(modifications_);
+                }
+                Err(response) => {
+                    return response.into_bytes();
+                }
+            }
+        }
+
+        // Inspect attachments and archives
+        let attachments = self.inspect_attachments(&auth_message).await;
+        if let Some(report) = &attachments {
+            if let Some(types) = self.attachment_quarantine(report) {
+                return self
+                    .store_quarantine(
+                        QuarantineStage::Attachments,
+                        "attachment",
+                        types,
+                        modifications,
+                        &auth_message,
+                        &headers,
+                    )
+                    .await;
+            }
+            match self.apply_attachment_policy(report) {
+                Ok(modifications_) => {
+                    modifications.extend(modifications_);
+                }
+                Err(response) => {
+                    return response.into_bytes();
+                }
+            }
+        }
+
+        // Remove Authentication-Results headers spoofing a trusted authserv-id
+        if !self.params.trusted_forwarder {
+            modifications.extend(self.strip_untrusted_auth_results(&auth_message));
+        }
+
         // Apply modifications
         let mut edited_message = if !modifications.is_empty() {
             self.data
@@ -542,7 +677,7 @@ impl<T: SessionStream> Session<T> {
                     .map(|s| (s, name))
             })
         {
-            let params = self
+            let mut params = self
                 .build_script_parameters("data")
                 .with_message(edited_message.as_ref().unwrap_or(&raw_message))
                 .with_auth_headers(&headers)
@@ -590,6 +725,72 @@ impl<T: SessionStream> Session<T> {
                         .map(|a| a.as_str())
                         .unwrap_or_default(),
                 );
+            if let Some(imported_results) = &imported_results {
+                params = params
+                    .set_variable(
+                        "spf.result",
+                        imported_results
+                            .spf_result()
+                            .unwrap_or_default()
+                            .to_string(),
+                    )
+                    .set_variable(
+                        "arc.result",
+                        imported_results
+                            .arc_result()
+                            .unwrap_or_default()
+                            .to_string(),
+                    )
+                    .set_variable(
+                        "dkim.result",
+                        imported_results
+                            .dkim_result()
+                            .unwrap_or_default()
+                            .to_string(),
+                    )
+                    .set_variable(
+                        "dkim.domains",
+                        imported_results
+                            .dkim_pass_domains()
+                            .map(Variable::from)
+                            .collect::<Vec<_>>(),
+                    );
+            }
+            if let Some(verdict) = &clamav {
+                params = params
+                    .set_variable("clamav.result", verdict.as_str())
+                    .set_variable(
+                        "clamav.signature",
+                        verdict.signature().unwrap_or_default().to_string(),
+                    );
+            }
+            if let Some(report) = &attachments {
+                params = params
+                    .set_variable(
+                        "attachment.names",
+                        report
+                            .files
+                            .iter()
+                            .map(|file| Variable::from(file.name.clone()))
+                            .collect::<Vec<_>>(),
+                    )
+                    .set_variable(
+                        "attachment.types",
+                        report
+                            .types()
+                            .into_iter()
+                            .map(|t| Variable::from(t.to_string()))
+                            .collect::<Vec<_>>(),
+                    )
+                    .set_variable("attachment.encrypted", i64::from(report.encrypted))
+                    .set_variable("attachment.macros", i64::from(report.macros))
+                    .set_variable(
+                        "attachment.double_extension",
+                        i64::from(report.double_extension),
+                    )
+                    .set_variable("attachment.bomb", i64::from(report.bomb))
+                    .set_variable("attachment.truncated", i64::from(report.truncated));
+            }
 
             let modifications = match self.run_script(script_id, script.clone(), params).await {
                 ScriptResult::Accept { modifications } => modifications,
@@ -609,26 +810,47 @@ impl<T: SessionStream> Session<T> {
             };
 
             // Apply modifications
-            for modification in modifications {
-                match modification {
-                    ScriptModification::AddHeader { name, value } => {
-                        headers.extend_from_slice(name.as_bytes());
-                        headers.extend_from_slice(b": ");
-                        headers.extend_from_slice(value.as_bytes());
-                        if !value.ends_with('\n') {
-                            headers.extend_from_slice(b"\r\n");
-                        }
-                    }
-                    ScriptModification::SetEnvelope { name, value } => {
-                        self.data.apply_envelope_modification(name, value);
-                    }
+            self.apply_script_modifications(modifications, &mut headers);
+        }
+
+        // Outbound Sieve filtering
+        if self.data.authenticated_as.is_some() {
+            match self
+                .run_outbound_scripts(
+                    edited_message.as_ref().unwrap_or(&raw_message),
+                    &mut headers,
+                )
+                .await
+            {
+                Ok(Some(message)) => {
+                    edited_message = message.into();
+                }
+                Ok(None) => (),
+                Err(response) => {
+                    return response;
                 }
             }
         }
 
         // Build message
         let mail_from = self.data.mail_from.clone().unwrap();
-        let rcpt_to = std::mem::take(&mut self.data.rcpt_to);
+        let mut rcpt_to = std::mem::take(&mut self.data.rcpt_to);
+        let rcpt_lists = std::mem::take(&mut self.data.rcpt_lists);
+        if !rcpt_lists.is_empty() {
+            rcpt_to.retain(|rcpt| {
+                !rcpt_lists
+                    .iter()
+                    .any(|list| list.address() == rcpt.address_lcase)
+            });
+        }
+        let rcpt_order = if self.instance.protocol == ServerProtocol::Lmtp {
+            rcpt_to
+                .iter()
+                .map(|rcpt| rcpt.address_lcase.clone())
+                .collect::<Vec<_>>()
+        } else {
+            Vec::new()
+        };
         let mut message = self
             .build_message(mail_from, rcpt_to, message_id, self.data.session_id)
             .await;
@@ -693,9 +915,86 @@ impl<T: SessionStream> Session<T> {
             }
         }
 
+        // Sign with the managed keys of the author's domain
+        if self
+            .server
+            .eval_if(&ac.dkim.managed.sign, self, self.data.session_id)
+            .await
+            .unwrap_or(false)
+        {
+            let domain = auth_message
+                .from()
+                .rsplit_once('@')
+                .map(|(_, domain)| domain)
+                .filter(|domain| !domain.is_empty())
+                .unwrap_or(message.return_path_domain.as_str());
+            match self.server.dkim_signers(domain).await {
+                Ok(signers) => {
+                    for signer in signers {
+                        match signer.signer.sign_chained(&[headers.as_ref(), raw_message]) {
+                            Ok(signature) => {
+                                signature.write_header(&mut headers);
+                            }
+                            Err(err) => {
+                                trc::error!(trc::Event::from(err)
+                                    .span_id(self.data.session_id)
+                                    .id(signer.selector.clone())
+                                    .details("Failed to DKIM sign message"));
+                            }
+                        }
+                    }
+                }
+                Err(err) => {
+                    trc::error!(err
+                        .span_id(self.data.session_id)
+                        .caused_by(trc::location!())
+                        .details("Failed to obtain DKIM signers"));
+                }
+            }
+        }
+
         // Update size
         message.size = raw_message.len() + headers.len();
 
+        // Expand mailing lists and process bounces addressed to list members
+        if !rcpt_lists.is_empty() {
+            match self
+                .deliver_to_lists(rcpt_lists, &headers, raw_message)
+                .await
+            {
+                Ok(queue_id) => {
+                    if message.recipients.is_empty() {
+                        if let Some(queue_id) = queue_id {
+                            self.state = State::Accepted(queue_id);
+                        }
+                        self.data.messages_sent += 1;
+                        return (b"250 2.0.0 Message queued for delivery.\r\n"[..]).into();
+                    }
+                }
+                Err(err) => {
+                    trc::error!(err
+                        .details("Failed to deliver message to mailing list.")
+                        .span_id(self.data.session_id)
+                        .caused_by(trc::location!()));
+
+                    return (b"451 4.3.5 Unable to accept message at this time.\r\n"[..]).into();
+                }
+            }
+        }
+
+        // Final delivery over LMTP bypasses the queue
+        if self.instance.protocol == ServerProtocol::Lmtp
+            && self
+                .server
+                .eval_if(&dc.local_delivery, self, self.data.session_id)
+                .await
+                .unwrap_or(false)
+        {
+            return self
+                .deliver_lmtp(message, &rcpt_order, &headers, raw_message)
+                .await;
+        }
+
         // Verify queue quota
         if self.server.has_quota(&mut message).await {
             // Prepare webhook event
@@ -754,6 +1053,18 @@ impl<T: SessionStream> Session<T> {
             env_id: mail_from.dsn_info,
             blob_hash: Default::default(),
             quota_keys: Vec::new(),
+            retry_state: Vec::new(),
+            hold_until: if self.data.future_release != 0 {
+                created + self.data.future_release
+            } else {
+                0
+            },
+            deliver_by: if self.data.delivery_by != 0 {
+                created.saturating_add_signed(self.data.delivery_by)
+            } else {
+                0
+            },
+            transports: Vec::new(),
         };
 
         // Add recipients
@@ -865,6 +1176,67 @@ impl<T: SessionStream> Session<T> {
         message
     }
 
+    async fn deliver_to_lists(
+        &self,
+        rcpt_lists: Vec<ListRecipient>,
+        headers: &[u8],
+        raw_message: &[u8],
+    ) -> trc::Result<Option<QueueId>> {
+        let directory = self
+            .server
+            .eval_if::<String, _>(
+                &self.server.core.smtp.session.rcpt.directory,
+                self,
+                self.data.session_id,
+            )
+            .await
+            .and_then(|name| self.server.get_directory(&name));
+        let source = if !self.is_authenticated() {
+            MessageSource::Unauthenticated
+        } else {
+            MessageSource::Authenticated
+        };
+
+        let mut first_queue_id = None;
+        for rcpt in rcpt_lists {
+            match rcpt {
+                ListRecipient::Post { list } => {
+                    let members = if let Some(directory) = directory {
+                        match self
+                            .server
+                            .rcpt(directory, &list, self.data.session_id)
+                            .await?
+                        {
+                            RcptType::List(members) => members,
+                            _ => vec![],
+                        }
+                    } else {
+                        vec![]
+                    };
+                    let queue_ids = self
+                        .server
+                        .list_deliver(ListPost {
+                            list: &list,
+                            members,
+                            headers,
+                            raw_message,
+                            source,
+                            session_id: self.data.session_id,
+                        })
+                        .await?;
+                    first_queue_id = first_queue_id.or(queue_ids.first().copied());
+                }
+                ListRecipient::Bounce { list, member, .. } => {
+                    self.server
+                        .list_bounce(&list, &member, self.data.session_id)
+                        .await?;
+                }
+            }
+        }
+
+        Ok(first_queue_id)
+    }
+
     pub async fn can_send_data(&mut self) -> Result<bool, ()> {
         if !self.data.rcpt_to.is_empty() {
             if self.data.messages_sent
@@ -0,0 +1,339 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Instant;
+
+use common::listener::SessionStream;
+use mail_auth::{dmarc::Policy, AuthenticatedMessage, DmarcResult, IprevResult};
+use trc::SmtpEvent;
+
+use crate::core::Session;
+
+use super::milter::Modification;
+
+const AUTH_RESULTS: &str = "Authentication-Results";
+const ARC_AUTH_RESULTS: &str = "ARC-Authentication-Results";
+
+// Authentication results imported from a trusted edge MTA
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportedAuthResults {
+    pub authserv_id: String,
+    pub results: Vec<MethodResult>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MethodResult {
+    pub method: String,
+    pub result: String,
+    pub properties: Vec<(String, String)>,
+}
+
+impl<T: SessionStream> Session<T> {
+    // Returns true when the remote host is an edge MTA whose
+    // authentication results are trusted
+    pub async fn is_trusted_forwarder(&mut self) -> bool {
+        let config = &self.server.core.smtp.mail_auth.trusted_forwarder;
+        if config
+            .networks
+            .iter()
+            .any(|network| network.matches(&self.data.remote_ip))
+        {
+            return true;
+        } else if config.hosts.is_empty() {
+            return false;
+        }
+
+        // Hostnames are matched against the verified reverse DNS names
+        if self.data.iprev.is_none() {
+            let time = Instant::now();
+            let iprev = self
+                .server
+                .core
+                .smtp
+                .resolvers
+                .dns
+                .verify_iprev(self.data.remote_ip)
+                .await;
+
+            trc::event!(
+                Smtp(if matches!(iprev.result(), IprevResult::Pass) {
+                    SmtpEvent::IprevPass
+                } else {
+                    SmtpEvent::IprevFail
+                }),
+                SpanId = self.data.session_id,
+                Result = trc::Event::from(&iprev),
+                Elapsed = time.elapsed(),
+            );
+
+            self.data.iprev = iprev.into();
+        }
+
+        self.data.iprev.as_ref().map_or(false, |iprev| {
+            matches!(iprev.result(), IprevResult::Pass)
+                && iprev.ptr.as_ref().map_or(false, |ptr| {
+                    ptr.iter().any(|name| {
+                        config
+                            .hosts
+                            .contains(name.trim_end_matches('.').to_lowercase().as_str())
+                    })
+                })
+        })
+    }
+
+    pub fn import_auth_results(
+        &self,
+        message: &AuthenticatedMessage<'_>,
+    ) -> Option<ImportedAuthResults> {
+        let authserv_id = self
+            .server
+            .core
+            .smtp
+            .mail_auth
+            .trusted_forwarder
+            .authserv_id
+            .as_deref()?;
+
+        // Use the topmost Authentication-Results header added by the forwarder,
+        // falling back to the most recent ARC-Authentication-Results header
+        let mut arc_results: Option<(u32, ImportedAuthResults)> = None;
+        for (name, value) in message.raw_parsed_headers() {
+            let value = std::str::from_utf8(value).unwrap_or_default();
+            if name.eq_ignore_ascii_case(AUTH_RESULTS.as_bytes()) {
+                if let Some(results) = ImportedAuthResults::parse(value) {
+                    if results.authserv_id == authserv_id {
+                        return Some(results);
+                    }
+                }
+            } else if name.eq_ignore_ascii_case(ARC_AUTH_RESULTS.as_bytes()) {
+                if let Some((instance, value)) = value.split_once(';').and_then(|(i, value)| {
+                    i.trim()
+                        .strip_prefix("i=")
+                        .and_then(|i| i.trim().parse::<u32>().ok())
+                        .map(|i| (i, value))
+                }) {
+                    if let Some(results) = ImportedAuthResults::parse(value) {
+                        if results.authserv_id == authserv_id
+                            && arc_results.as_ref().map_or(true, |(i, _)| instance > *i)
+                        {
+                            arc_results = Some((instance, results));
+                        }
+                    }
+                }
+            }
+        }
+
+        arc_results.map(|(_, results)| results)
+    }
+
+    // Builds the modifications that remove Authentication-Results headers
+    // claiming to have been added by a trusted authserv-id
+    pub fn strip_untrusted_auth_results(
+        &self,
+        message: &AuthenticatedMessage<'_>,
+    ) -> Vec<Modification> {
+        let config = &self.server.core.smtp.mail_auth.trusted_forwarder;
+        if !config.is_enabled() {
+            return vec![];
+        }
+        let hostname = self.hostname.to_lowercase();
+
+        let mut modifications = Vec::new();
+        let mut index = 0;
+        for (name, value) in message.raw_parsed_headers() {
+            if name.eq_ignore_ascii_case(AUTH_RESULTS.as_bytes()) {
+                index += 1;
+                let authserv_id =
+                    authserv_id(std::str::from_utf8(value).unwrap_or_default()).unwrap_or_default();
+                if authserv_id == hostname
+                    || config
+                        .authserv_id
+                        .as_ref()
+                        .map_or(false, |id| *id == authserv_id)
+                {
+                    modifications.push(Modification::ChangeHeader {
+                        index,
+                        name: AUTH_RESULTS.to_string(),
+                        value: String::new(),
+                    });
+                }
+            }
+        }
+
+        if !modifications.is_empty() {
+            trc::event!(
+                Smtp(SmtpEvent::AuthResultsStripped),
+                SpanId = self.data.session_id,
+                Total = modifications.len(),
+            );
+
+            // Remove the last headers first so the indexes remain valid
+            modifications.reverse();
+        }
+
+        modifications
+    }
+}
+
+impl ImportedAuthResults {
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = strip_comments(value);
+        let mut parts = value.split(';');
+        let authserv_id = authserv_id(parts.next()?)?;
+        let mut results = Vec::new();
+
+        for part in parts {
+            let mut tokens = part.split_ascii_whitespace();
+            let Some((method, result)) = tokens.next().and_then(|token| token.split_once('='))
+            else {
+                continue;
+            };
+            let method = method.to_lowercase();
+            if method.is_empty() || method == "none" {
+                continue;
+            }
+
+            results.push(MethodResult {
+                method,
+                result: result.to_lowercase(),
+                properties: tokens
+                    .filter_map(|token| {
+                        token
+                            .split_once('=')
+                            .map(|(k, v)| (k.to_lowercase(), v.trim_matches('"').to_string()))
+                    })
+                    .collect(),
+            });
+        }
+
+        Some(ImportedAuthResults {
+            authserv_id,
+            results,
+        })
+    }
+
+    pub fn method(&self, method: &str) -> impl Iterator<Item = &MethodResult> {
+        self.results.iter().filter(move |r| r.method == method)
+    }
+
+    pub fn spf_result(&self) -> Option<&str> {
+        self.method("spf").next().map(|r| r.result.as_str())
+    }
+
+    pub fn arc_result(&self) -> Option<&str> {
+        self.method("arc").next().map(|r| r.result.as_str())
+    }
+
+    // Returns the first passing DKIM result or the first result if none passed
+    pub fn dkim_result(&self) -> Option<&str> {
+        self.method("dkim")
+            .find(|r| r.result == "pass")
+            .or_else(|| self.method("dkim").next())
+            .map(|r| r.result.as_str())
+    }
+
+    pub fn dkim_pass_domains(&self) -> impl Iterator<Item = String> + '_ {
+        self.method("dkim")
+            .filter(|r| r.result == "pass")
+            .filter_map(|r| r.property("header.d").map(|d| d.to_lowercase()))
+    }
+
+    pub fn dmarc_result(&self) -> Option<DmarcResult> {
+        self.method("dmarc")
+            .next()
+            .map(|r| match r.result.as_str() {
+                "pass" => DmarcResult::Pass,
+                "fail" => DmarcResult::Fail(mail_auth::Error::NotAligned),
+                "temperror" => DmarcResult::TempError(mail_auth::Error::DnsError(
+                    "Imported temporary error".to_string(),
+                )),
+                "permerror" => DmarcResult::PermError(mail_auth::Error::ParseError),
+                _ => DmarcResult::None,
+            })
+    }
+
+    pub fn dmarc_policy(&self) -> Option<Policy> {
+        self.method("dmarc").next().map(|r| {
+            match r
+                .property("policy.dmarc")
+                .or_else(|| r.property("p"))
+                .map(|p| p.to_lowercase())
+                .as_deref()
+            {
+                Some("reject") => Policy::Reject,
+                Some("quarantine") => Policy::Quarantine,
+                Some("none") => Policy::None,
+                _ => Policy::Unspecified,
+            }
+        })
+    }
+
+    // Writes the imported results under the local authserv-id
+    pub fn write_header(&self, hostname: &str, headers: &mut Vec<u8>) {
+        headers.extend_from_slice(AUTH_RESULTS.as_bytes());
+        headers.extend_from_slice(b": ");
+        headers.extend_from_slice(hostname.as_bytes());
+        if self.results.is_empty() {
+            headers.extend_from_slice(b"; none");
+        }
+        for result in &self.results {
+            headers.extend_from_slice(b";\r\n\t");
+            headers.extend_from_slice(result.method.as_bytes());
+            headers.push(b'=');
+            headers.extend_from_slice(result.result.as_bytes());
+            for (name, value) in &result.properties {
+                headers.push(b' ');
+                headers.extend_from_slice(name.as_bytes());
+                headers.push(b'=');
+                headers.extend_from_slice(value.as_bytes());
+            }
+        }
+        headers.extend_from_slice(b"\r\n");
+    }
+}
+
+impl MethodResult {
+    pub fn property(&self, name: &str) -> Option<&str> {
+        self.properties
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+fn authserv_id(value: &str) -> Option<String> {
+    strip_comments(value.split(';').next()?)
+        .split_ascii_whitespace()
+        .next()
+        .map(|id| id.trim_end_matches('.').to_lowercase())
+}
+
+fn strip_comments(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut depth = 0;
+    let mut in_quotes = false;
+
+    for ch in value.chars() {
+        match ch {
+            '"' if depth == 0 => {
+                in_quotes = !in_quotes;
+                result.push(ch);
+            }
+            '(' if !in_quotes => {
+                depth += 1;
+            }
+            ')' if !in_quotes && depth > 0 => {
+                depth -= 1;
+            }
+            _ if depth == 0 => {
+                result.push(ch);
+            }
+            _ => {}
+        }
+    }
+
+    result
+}
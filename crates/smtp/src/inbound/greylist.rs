@@ -0,0 +1,129 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use common::listener::SessionStream;
+use mail_auth::SpfResult;
+use store::write::now;
+use trc::AddContext;
+
+use crate::core::Session;
+
+impl<T: SessionStream> Session<T> {
+    // Returns true when the last recipient has to be deferred
+    pub async fn is_greylisted(&self) -> bool {
+        let config = &self.server.core.smtp.session.rcpt.greylist;
+
+        // Skip authenticated sessions and senders with a good reputation
+        if self.data.authenticated_as.is_some()
+            || self.server.is_ip_allowed(&self.data.remote_ip)
+            || self
+                .data
+                .spf_mail_from
+                .as_ref()
+                .map_or(false, |spf| spf.result() == SpfResult::Pass)
+            || !self
+                .server
+                .eval_if(&config.enable, self, self.data.session_id)
+                .await
+                .unwrap_or(false)
+        {
+            return false;
+        }
+
+        match self.check_greylist().await {
+            Ok(is_greylisted) => is_greylisted,
+            Err(err) => {
+                trc::error!(err
+                    .span_id(self.data.session_id)
+                    .caused_by(trc::location!())
+                    .details("Failed to check greylist."));
+                false
+            }
+        }
+    }
+
+    async fn check_greylist(&self) -> trc::Result<bool> {
+        let config = &self.server.core.smtp.session.rcpt.greylist;
+        let store = self.server.lookup_store();
+        let network = greylist_network(self.data.remote_ip);
+
+        // Sources that retried successfully enough times are no longer greylisted
+        let allowlist_key = format!("ga:{network}").into_bytes();
+        if config.allowlist_after > 0
+            && store.counter_get(allowlist_key.clone()).await? >= config.allowlist_after as i64
+        {
+            return Ok(false);
+        }
+
+        let sender_domain = self
+            .data
+            .mail_from
+            .as_ref()
+            .map(|mail_from| mail_from.domain.as_str())
+            .unwrap_or_default();
+        let rcpt = self
+            .data
+            .rcpt_to
+            .last()
+            .map(|rcpt| rcpt.address_lcase.as_str())
+            .unwrap_or_default();
+        let triplet_key = format!("g:{network}:{sender_domain}:{rcpt}").into_bytes();
+
+        match store
+            .key_get::<String>(triplet_key.clone())
+            .await?
+            .and_then(|first_seen| first_seen.parse::<u64>().ok())
+        {
+            Some(0) => Ok(false),
+            Some(first_seen) if first_seen + config.min_delay.as_secs() <= now() => {
+                // Triplet passed greylisting, keep it for as long as the allowlist
+                store
+                    .key_set(
+                        triplet_key,
+                        b"0".to_vec(),
+                        config.allowlist_expire.as_secs().into(),
+                    )
+                    .await?;
+                store
+                    .counter_incr(
+                        allowlist_key,
+                        1,
+                        config.allowlist_expire.as_secs().into(),
+                        false,
+                    )
+                    .await?;
+                Ok(false)
+            }
+            Some(_) => Ok(true),
+            None => {
+                store
+                    .key_set(
+                        triplet_key,
+                        now().to_string().into_bytes(),
+                        config.expire.as_secs().into(),
+                    )
+                    .await?;
+                Ok(true)
+            }
+        }
+    }
+}
+
+// Greylisting is tracked per /24 IPv4 and /64 IPv6 network
+fn greylist_network(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            IpAddr::V4(Ipv4Addr::new(a, b, c, 0))
+        }
+        IpAddr::V6(ip) => {
+            let [a, b, c, d, ..] = ip.segments();
+            IpAddr::V6(Ipv6Addr::new(a, b, c, d, 0, 0, 0, 0))
+        }
+    }
+}
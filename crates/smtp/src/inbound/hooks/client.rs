@@ -4,19 +4,41 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+use std::{sync::Arc, time::Instant};
+
 use common::{config::smtp::session::MTAHook, HttpLimitResponse};
+use utils::map::ttl_dashmap::TtlMap;
 
 use super::{Request, Response};
 
 pub(super) async fn send_mta_hook_request(
     mta_hook: &MTAHook,
     request: Request,
+    cache_key: Option<String>,
 ) -> Result<Response, String> {
-    let response = reqwest::Client::builder()
-        .timeout(mta_hook.timeout)
-        .danger_accept_invalid_certs(mta_hook.tls_allow_invalid_certs)
-        .build()
-        .map_err(|err| format!("Failed to create HTTP client: {}", err))?
+    // Use the cached response if available
+    if let Some(bytes) = cache_key
+        .as_ref()
+        .and_then(|key| mta_hook.cache.get_with_ttl(key))
+    {
+        return serde_json::from_slice(bytes.as_ref())
+            .map_err(|err| format!("Failed to parse Hook response: {}", err));
+    }
+
+    // Wait for a free slot if the number of concurrent requests is limited
+    let _permit = if let Some(semaphore) = &mta_hook.max_concurrency {
+        Some(
+            tokio::time::timeout(mta_hook.timeout, semaphore.acquire())
+                .await
+                .map_err(|_| "Hook concurrency limit reached".to_string())?
+                .map_err(|err| format!("Failed to acquire Hook permit: {}", err))?,
+        )
+    } else {
+        None
+    };
+
+    let response = mta_hook
+        .client
         .post(&mta_hook.url)
         .headers(mta_hook.headers.clone())
         .body(
@@ -28,15 +50,23 @@ pub(super) async fn send_mta_hook_request(
         .map_err(|err| format!("Hook request failed: {err}"))?;
 
     if response.status().is_success() {
-        serde_json::from_slice(
-            response
-                .bytes_with_limit(mta_hook.max_response_size)
-                .await
-                .map_err(|err| format!("Failed to parse Hook response: {}", err))?
-                .ok_or_else(|| "Hook response too large".to_string())?
-                .as_ref(),
-        )
-        .map_err(|err| format!("Failed to parse Hook response: {}", err))
+        let bytes = response
+            .bytes_with_limit(mta_hook.max_response_size)
+            .await
+            .map_err(|err| format!("Failed to parse Hook response: {}", err))?
+            .ok_or_else(|| "Hook response too large".to_string())?;
+        let response = serde_json::from_slice(bytes.as_ref())
+            .map_err(|err| format!("Failed to parse Hook response: {}", err))?;
+
+        if let Some(cache_key) = cache_key {
+            mta_hook.cache.insert_with_ttl(
+                cache_key,
+                Arc::new(bytes),
+                Instant::now() + mta_hook.cache_ttl,
+            );
+        }
+
+        Ok(response)
     } else {
         Err(format!(
             "Hook request failed with code {}: {}",
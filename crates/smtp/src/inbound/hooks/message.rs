@@ -13,6 +13,7 @@ use common::{
     DAEMON_NAME,
 };
 use mail_auth::AuthenticatedMessage;
+use sha2::{Digest, Sha256};
 use trc::MtaHookEvent;
 
 use crate::{
@@ -54,7 +55,15 @@ impl<T: SessionStream> Session<T> {
             }
 
             let time = Instant::now();
-            match self.run_mta_hook(stage, mta_hook, message, queue_id).await {
+            let cache_key = self
+                .server
+                .eval_if::<String, _>(&mta_hook.cache_key, self, self.data.session_id)
+                .await
+                .map(|key| format!("{}:{key}", stage as u8));
+            match self
+                .run_mta_hook(stage, mta_hook, message, queue_id, cache_key)
+                .await
+            {
                 Ok(response) => {
                     trc::event!(
                         MtaHook(match response.action {
@@ -68,7 +77,14 @@ impl<T: SessionStream> Session<T> {
                         Elapsed = time.elapsed(),
                     );
 
-                    let mut new_modifications = Vec::with_capacity(response.modifications.len());
+                    let mut new_modifications =
+                        Vec::with_capacity(response.modifications.len() + 1);
+                    if let Some(spam_score) = response.spam_score {
+                        new_modifications.push(Modification::AddHeader {
+                            name: "X-Spam-Score".to_string(),
+                            value: spam_score.to_string(),
+                        });
+                    }
                     for modification in response.modifications {
                         new_modifications.push(match modification {
                             super::Modification::ChangeFrom { value, parameters } => {
@@ -130,11 +146,12 @@ impl<T: SessionStream> Session<T> {
                         Action::Discard => FilterResponse::accept(),
                         Action::Reject => FilterResponse::reject(),
                         Action::Quarantine => {
+                            // Quarantined messages are accepted and flagged
                             modifications.push(Modification::AddHeader {
                                 name: "X-Quarantine".to_string(),
                                 value: "true".to_string(),
                             });
-                            FilterResponse::accept()
+                            continue;
                         }
                     };
 
@@ -176,6 +193,7 @@ impl<T: SessionStream> Session<T> {
         mta_hook: &MTAHook,
         message: Option<&AuthenticatedMessage<'_>>,
         queue_id: Option<QueueId>,
+        cache_key: Option<String>,
     ) -> Result<Response, String> {
         // Build request
         let (tls_version, tls_cipher) = self.stream.tls_version_and_cipher();
@@ -244,12 +262,18 @@ impl<T: SessionStream> Session<T> {
                     })
                     .collect(),
                 server_headers: vec![],
-                contents: String::from_utf8_lossy(message.raw_body()).into_owned(),
+                contents: if mta_hook.include_body {
+                    String::from_utf8_lossy(message.raw_body()).into_owned()
+                } else {
+                    String::new()
+                },
+                digest: (!mta_hook.include_body)
+                    .then(|| format!("{:x}", Sha256::digest(message.raw_body()))),
                 size: message.raw_message().len(),
             }),
         };
 
-        send_mta_hook_request(mta_hook, request).await
+        send_mta_hook_request(mta_hook, request, cache_key).await
     }
 }
 
@@ -118,7 +118,12 @@ pub struct Message {
     #[serde(rename = "serverHeaders")]
     #[serde(default)]
     pub server_headers: Vec<(String, String)>,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    #[serde(default)]
     pub contents: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub digest: Option<String>,
     pub size: usize,
 }
 
@@ -129,6 +134,9 @@ pub struct Response {
     pub response: Option<SmtpResponse>,
     #[serde(default)]
     pub modifications: Vec<Modification>,
+    #[serde(rename = "spamScore")]
+    #[serde(default)]
+    pub spam_score: Option<f64>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -0,0 +1,114 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::borrow::Cow;
+
+use common::listener::SessionStream;
+use smtp_proto::Response;
+use store::{
+    write::{now, BatchBuilder, BlobOp},
+    Serialize,
+};
+use utils::BlobHash;
+
+use crate::{
+    core::{Session, State},
+    queue::{Message, Status},
+};
+
+impl<T: SessionStream> Session<T> {
+    pub async fn deliver_lmtp(
+        &mut self,
+        mut message: Message,
+        rcpt_order: &[String],
+        raw_headers: &[u8],
+        raw_message: &[u8],
+    ) -> Cow<'static, [u8]> {
+        // Write blob, the reservation keeps it around until the mailboxes have a copy
+        let mut raw = Vec::with_capacity(raw_headers.len() + raw_message.len());
+        raw.extend_from_slice(raw_headers);
+        raw.extend_from_slice(raw_message);
+        message.blob_hash = BlobHash::from(raw.as_slice());
+        message.size = raw.len();
+
+        let mut batch = BatchBuilder::new();
+        batch.set(
+            BlobOp::Reserve {
+                hash: message.blob_hash.clone(),
+                until: now() + 120,
+            },
+            0u32.serialize(),
+        );
+        if let Err(err) = self.server.store().write(batch.build()).await {
+            trc::error!(err
+                .details("Failed to write to store.")
+                .span_id(self.data.session_id)
+                .caused_by(trc::location!()));
+
+            return (b"451 4.3.5 Unable to accept message at this time.\r\n"[..]).into();
+        }
+        if let Err(err) = self
+            .server
+            .blob_store()
+            .put_blob(message.blob_hash.as_slice(), &raw)
+            .await
+        {
+            trc::error!(err
+                .details("Failed to write blob.")
+                .span_id(self.data.session_id)
+                .caused_by(trc::location!()));
+
+            return (b"451 4.3.5 Unable to accept message at this time.\r\n"[..]).into();
+        }
+
+        // Hand the message over to the local delivery pipeline
+        let mut recipients = std::mem::take(&mut message.recipients);
+        message
+            .deliver_local(recipients.iter_mut(), &self.server.inner.ipc.delivery_tx)
+            .await;
+
+        // Reply once for each accepted recipient, in the order they were given
+        let mut response = Vec::with_capacity(rcpt_order.len() * 48);
+        let mut has_success = false;
+        let mut last_reply = String::new();
+        for address in rcpt_order {
+            let rcpt = recipients.iter().find(|r| &r.address_lcase == address);
+            last_reply = match rcpt.map(|rcpt| &rcpt.status) {
+                Some(Status::Completed(success)) => {
+                    has_success = true;
+                    lmtp_reply(address, &success.response)
+                }
+                Some(Status::TemporaryFailure(failure) | Status::PermanentFailure(failure)) => {
+                    lmtp_reply(address, &failure.response)
+                }
+                _ => format!("451 4.3.0 <{address}> Could not deliver message locally.\r\n"),
+            };
+            response.extend_from_slice(last_reply.as_bytes());
+        }
+
+        // Duplicate recipients and list members were acknowledged as well
+        for _ in rcpt_order.len()..self.data.rcpt_oks {
+            response.extend_from_slice(last_reply.as_bytes());
+        }
+
+        // All replies are written at once
+        self.data.rcpt_oks = 1;
+
+        if has_success {
+            self.state = State::Accepted(message.queue_id);
+            self.data.messages_sent += 1;
+        }
+
+        response.into()
+    }
+}
+
+fn lmtp_reply(address: &str, response: &Response<String>) -> String {
+    format!(
+        "{} {}.{}.{} <{}> {}\r\n",
+        response.code, response.esc[0], response.esc[1], response.esc[2], address, response.message
+    )
+}
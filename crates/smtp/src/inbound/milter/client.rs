@@ -4,8 +4,13 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+#[cfg(unix)]
+use std::path::Path;
+
 use common::config::smtp::session::Milter;
 use rustls_pki_types::ServerName;
+#[cfg(unix)]
+use tokio::net::UnixStream;
 use tokio::{
     io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
     net::TcpStream,
@@ -23,34 +28,12 @@ const MILTER_CHUNK_SIZE: usize = 65535;
 
 impl MilterClient<TcpStream> {
     pub async fn connect(config: &Milter, session_id: u64) -> Result<Self> {
-        tokio::time::timeout(config.timeout_command, async {
+        tokio::time::timeout(config.timeout_connect, async {
             let mut last_err = Error::Disconnected;
             for addr in &config.addrs {
                 match TcpStream::connect(addr).await {
                     Ok(stream) => {
-                        return Ok(MilterClient {
-                            stream,
-                            timeout_cmd: config.timeout_command,
-                            timeout_data: config.timeout_data,
-                            buf: vec![0u8; 8192],
-                            bytes_read: 0,
-                            receiver: Receiver::with_max_frame_len(config.max_frame_len),
-                            options: 0,
-                            version: config.protocol_version,
-                            session_id,
-                            flags_actions: config.flags_actions.unwrap_or(
-                                SMFIF_ADDHDRS
-                                    | SMFIF_CHGBODY
-                                    | SMFIF_ADDRCPT
-                                    | SMFIF_DELRCPT
-                                    | SMFIF_CHGHDRS
-                                    | SMFIF_QUARANTINE
-                                    | SMFIF_CHGFROM
-                                    | SMFIF_ADDRCPT_PAR,
-                            ),
-                            flags_protocol: config.flags_protocol.unwrap_or(0x42),
-                            id: config.id.clone(),
-                        });
+                        return Ok(MilterClient::new(stream, config, session_id));
                     }
                     Err(err) => {
                         last_err = Error::Io(err);
@@ -96,7 +79,44 @@ impl MilterClient<TcpStream> {
     }
 }
 
+#[cfg(unix)]
+impl MilterClient<UnixStream> {
+    pub async fn connect_unix(config: &Milter, path: &Path, session_id: u64) -> Result<Self> {
+        tokio::time::timeout(config.timeout_connect, UnixStream::connect(path))
+            .await
+            .map_err(|_| Error::Timeout)?
+            .map(|stream| MilterClient::new(stream, config, session_id))
+            .map_err(Error::Io)
+    }
+}
+
 impl<T: AsyncRead + AsyncWrite + Unpin> MilterClient<T> {
+    fn new(stream: T, config: &Milter, session_id: u64) -> Self {
+        MilterClient {
+            stream,
+            timeout_cmd: config.timeout_command,
+            timeout_data: config.timeout_data,
+            buf: vec![0u8; 8192],
+            bytes_read: 0,
+            receiver: Receiver::with_max_frame_len(config.max_frame_len),
+            options: 0,
+            version: config.protocol_version,
+            session_id,
+            flags_actions: config.flags_actions.unwrap_or(
+                SMFIF_ADDHDRS
+                    | SMFIF_CHGBODY
+                    | SMFIF_ADDRCPT
+                    | SMFIF_DELRCPT
+                    | SMFIF_CHGHDRS
+                    | SMFIF_QUARANTINE
+                    | SMFIF_CHGFROM
+                    | SMFIF_ADDRCPT_PAR,
+            ),
+            flags_protocol: config.flags_protocol.unwrap_or(0x42),
+            id: config.id.clone(),
+        }
+    }
+
     pub async fn init(&mut self) -> super::Result<Options> {
         self.write(Command::OptionNegotiation(Options {
             version: match self.version {
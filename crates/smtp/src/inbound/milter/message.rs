@@ -160,6 +160,16 @@ impl<T: SessionStream> Session<T> {
         milter: &Milter,
         message: Option<&AuthenticatedMessage<'_>>,
     ) -> Result<Vec<Modification>, Rejection> {
+        #[cfg(unix)]
+        if let Some(path) = &milter.path {
+            return self
+                .run(
+                    MilterClient::connect_unix(milter, path, self.data.session_id).await?,
+                    message,
+                )
+                .await;
+        }
+
         // Build client
         let client = MilterClient::connect(milter, self.data.session_id).await?;
         if !milter.tls {
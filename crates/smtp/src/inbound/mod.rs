@@ -12,13 +12,20 @@ use mail_auth::{
     AuthenticationResults, DkimResult, DmarcResult, IprevResult, SpfResult,
 };
 
+pub mod attachments;
 pub mod auth;
+pub mod clamav;
 pub mod data;
 pub mod ehlo;
+pub mod forwarder;
+pub mod greylist;
 pub mod hooks;
+pub mod lmtp;
 pub mod mail;
 pub mod milter;
+pub mod quarantine;
 pub mod rcpt;
+pub mod rspamd;
 pub mod session;
 pub mod spawn;
 pub mod vrfy;
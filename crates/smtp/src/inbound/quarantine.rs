@@ -0,0 +1,110 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::borrow::Cow;
+
+use common::listener::SessionStream;
+use mail_auth::AuthenticatedMessage;
+use smtp_proto::{RCPT_NOTIFY_DELAY, RCPT_NOTIFY_FAILURE, RCPT_NOTIFY_NEVER, RCPT_NOTIFY_SUCCESS};
+
+use crate::{
+    core::{Session, State},
+    inbound::milter::Modification,
+    queue::quarantine::{
+        QuarantineRecipient, QuarantineStage, QuarantinedMessage, SmtpQuarantine,
+        GLOBAL_QUARANTINE_ID,
+    },
+};
+
+impl<T: SessionStream> Session<T> {
+    // Stores the message in the quarantine instead of queueing it, the sender
+    // receives the same reply as for a delivered message
+    pub async fn store_quarantine(
+        &mut self,
+        stage: QuarantineStage,
+        reason: &str,
+        details: String,
+        mut modifications: Vec<Modification>,
+        auth_message: &AuthenticatedMessage<'_>,
+        headers: &[u8],
+    ) -> Cow<'static, [u8]> {
+        if !self.params.trusted_forwarder {
+            modifications.extend(self.strip_untrusted_auth_results(auth_message));
+        }
+        let edited_message = if !modifications.is_empty() {
+            self.data
+                .apply_milter_modifications(modifications, auth_message)
+        } else {
+            None
+        };
+        let raw_message = edited_message
+            .as_deref()
+            .unwrap_or_else(|| auth_message.raw_message());
+        let mut message = Vec::with_capacity(headers.len() + raw_message.len());
+        message.extend_from_slice(headers);
+        message.extend_from_slice(raw_message);
+
+        let mail_from = self.data.mail_from.as_ref().unwrap();
+        let quarantined = QuarantinedMessage {
+            id: 0,
+            account_id: GLOBAL_QUARANTINE_ID,
+            stage,
+            reason: reason.to_string(),
+            details: details.into(),
+            return_path: mail_from.address.clone(),
+            env_id: mail_from.dsn_info.clone(),
+            flags: mail_from.flags,
+            recipients: self
+                .data
+                .rcpt_to
+                .iter()
+                .map(|rcpt| QuarantineRecipient {
+                    address: rcpt.address_lcase.clone(),
+                    flags: if rcpt.flags
+                        & (RCPT_NOTIFY_DELAY
+                            | RCPT_NOTIFY_FAILURE
+                            | RCPT_NOTIFY_SUCCESS
+                            | RCPT_NOTIFY_NEVER)
+                        != 0
+                    {
+                        rcpt.flags
+                    } else {
+                        rcpt.flags | RCPT_NOTIFY_DELAY | RCPT_NOTIFY_FAILURE
+                    },
+                    orcpt: rcpt.dsn_info.clone(),
+                })
+                .collect(),
+            blob_hash: Default::default(),
+            size: 0,
+            created: 0,
+            expires: 0,
+        };
+
+        match self
+            .server
+            .quarantine_message(quarantined, &message, self.data.session_id)
+            .await
+        {
+            Ok(entries) => {
+                self.data.rcpt_to.clear();
+                self.data.rcpt_lists.clear();
+                if let Some(entry) = entries.first() {
+                    self.state = State::Accepted(entry.id);
+                }
+                self.data.messages_sent += 1;
+                (b"250 2.0.0 Message queued for delivery.\r\n"[..]).into()
+            }
+            Err(err) => {
+                trc::error!(err
+                    .details("Failed to quarantine message.")
+                    .span_id(self.data.session_id)
+                    .caused_by(trc::location!()));
+
+                (b"451 4.3.5 Unable to accept message at this time.\r\n"[..]).into()
+            }
+        }
+    }
+}
@@ -13,7 +13,10 @@ use trc::{SecurityEvent, SmtpEvent};
 
 use crate::{
     core::{Session, SessionAddress},
-    queue::DomainPart,
+    queue::{
+        list::{parse_verp, ListRecipient, SmtpMailingList},
+        DomainPart,
+    },
     scripts::ScriptResult,
 };
 
@@ -187,6 +190,7 @@ impl<T: SessionStream> Session<T> {
         // Verify address
         let rcpt = self.data.rcpt_to.last().unwrap();
         let mut rcpt_members = None;
+        let mut rcpt_list = None;
         if let Some(directory) = self
             .server
             .eval_if::<String, _>(
@@ -199,12 +203,45 @@ impl<T: SessionStream> Session<T> {
         {
             match directory.is_local_domain(&rcpt.domain).await {
                 Ok(true) => {
-                    match self
+                    // Bounces addressed to mailing list members
+                    let verp = self
                         .server
-                        .rcpt(directory, &rcpt.address_lcase, self.data.session_id)
-                        .await
-                    {
+                        .core
+                        .smtp
+                        .lists
+                        .as_ref()
+                        .and_then(|_| parse_verp(&rcpt.address_lcase));
+                    let result = if let Some((list, member)) = verp {
+                        match self
+                            .server
+                            .is_list_member(directory, &list, &member, self.data.session_id)
+                            .await
+                        {
+                            Ok(true) => {
+                                rcpt_list = Some(ListRecipient::Bounce {
+                                    address: rcpt.address_lcase.clone(),
+                                    list,
+                                    member,
+                                });
+                                Ok(RcptType::Mailbox)
+                            }
+                            Ok(false) => Ok(RcptType::Invalid),
+                            Err(err) => Err(err),
+                        }
+                    } else {
+                        self.server
+                            .rcpt(directory, &rcpt.address_lcase, self.data.session_id)
+                            .await
+                    };
+
+                    match result {
                         Ok(RcptType::Mailbox) => {}
+                        Ok(RcptType::List(_)) if self.server.core.smtp.lists.is_some() => {
+                            // Members are expanded when the message is queued
+                            rcpt_list = Some(ListRecipient::Post {
+                                list: rcpt.address_lcase.clone(),
+                            });
+                        }
                         Ok(RcptType::List(members)) => {
                             rcpt_members = Some(members);
                         }
@@ -290,6 +327,19 @@ impl<T: SessionStream> Session<T> {
                 .await;
         }
 
+        if self.is_greylisted().await {
+            trc::event!(
+                Smtp(SmtpEvent::RcptToGreylisted),
+                SpanId = self.data.session_id,
+                To = self.data.rcpt_to.last().unwrap().address_lcase.clone(),
+            );
+
+            self.data.rcpt_to.pop();
+            return self
+                .write(b"451 4.7.1 Greylisted, please try again later.\r\n")
+                .await;
+        }
+
         if self.is_allowed().await {
             trc::event!(
                 Smtp(SmtpEvent::RcptTo),
@@ -305,14 +355,16 @@ impl<T: SessionStream> Session<T> {
 
             self.data.rcpt_to.pop();
             return self
-                .write(b"451 4.4.5 Rate limit exceeded, try again later.\r\n")
+                .write(b"452 4.4.5 Rate limit exceeded, try again later.\r\n")
                 .await;
         }
 
         // Expand list
-        if let Some(members) = rcpt_members {
+        if let Some(rcpt_list) = rcpt_list {
+            self.data.rcpt_lists.push(rcpt_list);
+        } else if let Some(members) = rcpt_members {
             let list_addr = self.data.rcpt_to.pop().unwrap();
-            let orcpt = format!("rfc822;{}", list_addr.address_lcase);
+            let orcpt = list_addr.address_lcase.clone();
             for member in members {
                 let mut member_addr = SessionAddress::new(member);
                 if !self.data.rcpt_to.contains(&member_addr)
@@ -0,0 +1,186 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Instant;
+
+use common::{
+    listener::SessionStream,
+    rspamd::{RspamdAction, RspamdRequest, RspamdResponse},
+};
+use mail_auth::AuthenticatedMessage;
+use trc::SpamEvent;
+
+use crate::{
+    core::Session,
+    inbound::{milter::Modification, FilterResponse},
+    queue::QueueId,
+};
+
+impl<T: SessionStream> Session<T> {
+    pub async fn run_rspamd(
+        &self,
+        message: &AuthenticatedMessage<'_>,
+        queue_id: QueueId,
+    ) -> Result<Vec<Modification>, FilterResponse> {
+        let Some(rspamd) = &self.server.core.spam.rspamd else {
+            return Ok(Vec::new());
+        };
+        if !self
+            .server
+            .eval_if(&rspamd.enable, self, self.data.session_id)
+            .await
+            .unwrap_or(false)
+        {
+            return Ok(Vec::new());
+        }
+
+        let time = Instant::now();
+        let request = RspamdRequest {
+            ip: self.data.remote_ip.into(),
+            helo: Some(self.data.helo_domain.as_str()),
+            hostname: self
+                .data
+                .iprev
+                .as_ref()
+                .and_then(|ip_rev| ip_rev.ptr.as_ref())
+                .and_then(|ptrs| ptrs.first())
+                .map(|ptr| ptr.as_str()),
+            from: self
+                .data
+                .mail_from
+                .as_ref()
+                .map(|from| from.address.as_str()),
+            rcpt: self
+                .data
+                .rcpt_to
+                .iter()
+                .map(|rcpt| rcpt.address.as_str())
+                .collect(),
+            user: self.authenticated_as(),
+            queue_id: format!("{queue_id:x}").into(),
+        };
+
+        match rspamd.check(request, message.raw_message()).await {
+            Ok(response) => {
+                trc::event!(
+                    Spam(SpamEvent::Rspamd),
+                    SpanId = self.data.session_id,
+                    Result = response.action.as_str(),
+                    Details = response.score,
+                    Elapsed = time.elapsed(),
+                );
+
+                rspamd_modifications(response)
+            }
+            Err(err) => {
+                trc::event!(
+                    Spam(SpamEvent::RspamdError),
+                    SpanId = self.data.session_id,
+                    Reason = err,
+                    Elapsed = time.elapsed(),
+                );
+
+                if rspamd.tempfail_on_error {
+                    Err(FilterResponse::server_failure())
+                } else {
+                    Ok(Vec::new())
+                }
+            }
+        }
+    }
+}
+
+fn rspamd_modifications(response: RspamdResponse) -> Result<Vec<Modification>, FilterResponse> {
+    let smtp_message = response.messages.smtp_message;
+    match response.action {
+        RspamdAction::Reject => {
+            return Err(FilterResponse {
+                message: format!(
+                    "550 5.7.1 {}\r\n",
+                    smtp_message
+                        .as_deref()
+                        .unwrap_or("Message rejected as spam.")
+                )
+                .into(),
+                disconnect: false,
+            });
+        }
+        RspamdAction::SoftReject => {
+            return Err(FilterResponse {
+                message: format!(
+                    "451 4.7.1 {}\r\n",
+                    smtp_message.as_deref().unwrap_or("Try again later.")
+                )
+                .into(),
+                disconnect: false,
+            });
+        }
+        RspamdAction::Greylist => {
+            return Err(FilterResponse {
+                message: "451 4.7.1 Greylisted, please try again later.\r\n".into(),
+                disconnect: false,
+            });
+        }
+        RspamdAction::NoAction
+        | RspamdAction::AddHeader
+        | RspamdAction::RewriteSubject
+        | RspamdAction::Unknown => {}
+    }
+
+    // Report the verdict using the SpamAssassin header format
+    let mut symbols = response.symbols.into_keys().collect::<Vec<_>>();
+    symbols.sort_unstable();
+    let mut modifications = vec![Modification::AddHeader {
+        name: "X-Spam-Status".to_string(),
+        value: format!(
+            "{}, score={:.2} required={:.2} tests={}",
+            if response.action.is_spam() {
+                "Yes"
+            } else {
+                "No"
+            },
+            response.score,
+            response.required_score,
+            symbols.join(",")
+        ),
+    }];
+
+    if let (RspamdAction::RewriteSubject, Some(subject)) = (response.action, response.subject) {
+        modifications.push(Modification::ChangeHeader {
+            index: 1,
+            name: "Subject".to_string(),
+            value: subject,
+        });
+    }
+
+    for (name, index) in response.milter.remove_headers {
+        modifications.push(Modification::ChangeHeader {
+            index: index.max(1),
+            name,
+            value: String::new(),
+        });
+    }
+    for (name, values) in response.milter.add_headers {
+        for value in values.into_values() {
+            modifications.push(Modification::AddHeader {
+                name: name.clone(),
+                value,
+            });
+        }
+    }
+
+    // Rspamd signs the message when the DKIM signing module is enabled
+    if let Some(signatures) = response.dkim_signature {
+        for value in signatures.into_values() {
+            modifications.push(Modification::AddHeader {
+                name: "DKIM-Signature".to_string(),
+                value,
+            });
+        }
+    }
+
+    Ok(modifications)
+}
@@ -25,6 +25,11 @@ use super::auth::SaslToken;
 
 impl<T: SessionStream> Session<T> {
     pub async fn ingest(&mut self, bytes: &[u8]) -> Result<bool, ()> {
+        // Terminate sessions authenticated with a revoked app password
+        if self.is_app_password_revoked().await {
+            return Err(());
+        }
+
         let mut iter = bytes.iter();
         let mut state = std::mem::replace(&mut self.state, State::None);
 
@@ -83,16 +88,17 @@ impl<T: SessionStream> Session<T> {
                                 mechanism,
                                 initial_response,
                             } => {
-                                let auth: u64 = self
-                                    .server
-                                    .eval_if::<Mechanism, _>(
-                                        &self.server.core.smtp.session.auth.mechanisms,
-                                        self,
-                                        self.data.session_id,
-                                    )
-                                    .await
-                                    .unwrap_or_default()
-                                    .into();
+                                let auth = self.filter_auth_mechanisms(
+                                    self.server
+                                        .eval_if::<Mechanism, _>(
+                                            &self.server.core.smtp.session.auth.mechanisms,
+                                            self,
+                                            self.data.session_id,
+                                        )
+                                        .await
+                                        .unwrap_or_default()
+                                        .into(),
+                                );
                                 if auth == 0 || self.params.auth_directory.is_none() {
                                     trc::event!(
                                         Smtp(SmtpEvent::AuthNotAllowed),
@@ -454,6 +460,32 @@ impl<T: SessionStream> Session<T> {
 
         Ok(true)
     }
+
+    pub async fn is_app_password_revoked(&mut self) -> bool {
+        match &self.data.authenticated_app {
+            Some(app_password) if self.server.is_app_password_revoked(app_password).await => {
+                trc::event!(
+                    Security(SecurityEvent::Unauthorized),
+                    SpanId = self.data.session_id,
+                    Details = "App password revoked",
+                );
+
+                self.write(b"421 4.7.0 App password revoked, closing connection.\r\n")
+                    .await
+                    .ok();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Only sessions using an app password of the notified account have to check
+    pub fn is_app_password_account(&self, account_id: u32) -> bool {
+        self.data
+            .authenticated_app
+            .as_ref()
+            .is_some_and(|app_password| app_password.account_id == account_id)
+    }
 }
 
 impl<T: AsyncWrite + AsyncRead + Unpin> Session<T> {
@@ -461,6 +493,7 @@ impl<T: AsyncWrite + AsyncRead + Unpin> Session<T> {
         self.data.mail_from = None;
         self.data.spf_mail_from = None;
         self.data.rcpt_to.clear();
+        self.data.rcpt_lists.clear();
         self.data.message = Vec::with_capacity(0);
         self.data.priority = 0;
         self.data.delivery_by = 0;
@@ -44,8 +44,11 @@ impl SessionManager for SmtpSessionManager {
 
         // Enforce throttle
         async {
-            if session.is_allowed().await
-                && session.init_conn().await
+            if !session.is_allowed().await {
+                let _ = session
+                    .write(b"421 4.3.2 Too many connections, try again later.\r\n")
+                    .await;
+            } else if session.init_conn().await
                 && session.handle_conn().await
                 && session.instance.acceptor.is_tls()
             {
@@ -156,6 +159,7 @@ impl<T: SessionStream> Session<T> {
     pub async fn handle_conn(&mut self) -> bool {
         let mut buf = vec![0; 8192];
         let mut shutdown_rx = self.instance.shutdown_rx.clone();
+        let mut revocations = self.server.subscribe_app_password_revocations();
 
         loop {
             tokio::select! {
@@ -257,6 +261,13 @@ impl<T: SessionStream> Session<T> {
                     );
                     self.write(b"421 4.3.0 Server shutting down.\r\n").await.ok();
                     break;
+                },
+                Ok(account_id) = revocations.recv() => {
+                    if self.is_app_password_account(account_id)
+                        && self.is_app_password_revoked().await
+                    {
+                        break;
+                    }
                 }
             };
         }
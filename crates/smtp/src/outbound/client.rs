@@ -282,6 +282,13 @@ impl<T: AsyncRead + AsyncWrite + Unpin> SmtpClient<T> {
         .await;
     }
 
+    /// Resets the session, returns whether the remote host accepted it.
+    pub async fn reset(&mut self) -> bool {
+        self.cmd(b"RSET\r\n")
+            .await
+            .is_ok_and(|response| response.code() == 250)
+    }
+
     pub async fn read_ehlo(&mut self) -> mail_send::Result<EhloResponse<String>> {
         let mut buf = vec![0u8; 8192];
         let mut buf_concat = Vec::with_capacity(0);
@@ -16,15 +16,18 @@ use crate::queue::throttle::IsAllowed;
 use crate::reporting::SmtpReporting;
 use common::config::{
     server::ServerProtocol,
-    smtp::{queue::RequireOptional, report::AggregateFrequency},
+    smtp::{
+        queue::{RelayFallback, RequireOptional},
+        report::AggregateFrequency,
+    },
 };
 use common::ipc::{OnHold, PolicyType, QueueEvent, TlsEvent};
-use common::Server;
+use common::{Server, SmtpConnectionKey};
 use mail_auth::{
     mta_sts::TlsRpt,
     report::tlsrpt::{FailureDetails, ResultType},
 };
-use smtp_proto::MAIL_REQUIRETLS;
+use smtp_proto::{MAIL_BY_RETURN, MAIL_REQUIRETLS};
 use std::{
     net::{IpAddr, Ipv4Addr, SocketAddr},
     time::{Duration, Instant},
@@ -37,8 +40,16 @@ use crate::{
     reporting::tls::TlsRptOptions,
 };
 
-use super::{lookup::ToNextHop, mta_sts, session::SessionParams, NextHop, TlsStrategy};
-use crate::queue::{throttle, DeliveryAttempt, Domain, Error, QueueEnvelope, Status};
+use super::{
+    lookup::ToNextHop,
+    mta_sts,
+    pool::{ConnectionPool, PoolParams, PooledClient},
+    session::SessionParams,
+    NextHop, TlsStrategy,
+};
+use crate::queue::{
+    retry::RetryScheduler, throttle, DeliveryAttempt, Error, QueueEnvelope, Status,
+};
 
 impl DeliveryAttempt {
     pub async fn try_deliver(mut self, server: Server) {
@@ -270,12 +281,33 @@ impl DeliveryAttempt {
                 }
             }
 
-            // Obtain next hop
-            let (mut remote_hosts, is_smtp) = match server
-                .eval_if::<String, _>(&queue_config.next_hop, &envelope, message.span_id)
-                .await
-                .and_then(|name| server.get_relay_host(&name, message.span_id))
+            // Obtain next hop, either from the transport map or the next hop rules
+            let next_hop = match message
+                .transports
+                .get(domain_idx)
+                .filter(|transport| !transport.is_empty())
             {
+                Some(transport) => Some(transport.clone()),
+                None => {
+                    server
+                        .eval_if::<String, _>(&queue_config.next_hop, &envelope, message.span_id)
+                        .await
+                }
+            };
+            let (mut remote_hosts, is_smtp) = match next_hop.and_then(|name| {
+                let next_hop = server.get_relay_host(&name, message.span_id)?;
+
+                trc::event!(
+                    Delivery(DeliveryEvent::NextHop),
+                    SpanId = span_id,
+                    Domain = domain.domain.clone(),
+                    Id = name,
+                    Hostname = next_hop.address.clone(),
+                    RemotePort = next_hop.port,
+                );
+
+                Some(next_hop)
+            }) {
                 Some(next_hop) if next_hop.protocol == ServerProtocol::Http => {
                     // Deliver message locally
                     let delivery_result = message
@@ -286,15 +318,8 @@ impl DeliveryAttempt {
                         .await;
 
                     // Update status for the current domain and continue with the next one
-                    let schedule = server
-                        .eval_if::<Vec<Duration>, _>(
-                            &queue_config.retry,
-                            &envelope,
-                            message.span_id,
-                        )
-                        .await
-                        .unwrap_or_else(|| vec![Duration::from_secs(60)]);
-                    message.domains[domain_idx].set_status(delivery_result, &schedule);
+                    let policy = server.eval_retry_policy(&envelope, message.span_id).await;
+                    message.set_status(domain_idx, delivery_result, &policy);
                     continue 'next_domain;
                 }
                 Some(next_hop) => (
@@ -494,15 +519,8 @@ impl DeliveryAttempt {
                         }
 
                         if strict {
-                            let schedule = server
-                                .eval_if::<Vec<Duration>, _>(
-                                    &queue_config.retry,
-                                    &envelope,
-                                    message.span_id,
-                                )
-                                .await
-                                .unwrap_or_else(|| vec![Duration::from_secs(60)]);
-                            message.domains[domain_idx].set_status(err, &schedule);
+                            let policy = server.eval_retry_policy(&envelope, message.span_id).await;
+                            message.set_status(domain_idx, err, &policy);
                             continue 'next_domain;
                         }
 
@@ -536,15 +554,8 @@ impl DeliveryAttempt {
                             Elapsed = time.elapsed(),
                         );
 
-                        let schedule = server
-                            .eval_if::<Vec<Duration>, _>(
-                                &queue_config.retry,
-                                &envelope,
-                                message.span_id,
-                            )
-                            .await
-                            .unwrap_or_else(|| vec![Duration::from_secs(60)]);
-                        message.domains[domain_idx].set_status(err, &schedule);
+                        let policy = server.eval_retry_policy(&envelope, message.span_id).await;
+                        message.set_status(domain_idx, err, &policy);
                         continue 'next_domain;
                     }
                 };
@@ -575,19 +586,13 @@ impl DeliveryAttempt {
                         Elapsed = time.elapsed(),
                     );
 
-                    let schedule = server
-                        .eval_if::<Vec<Duration>, _>(
-                            &queue_config.retry,
-                            &envelope,
-                            message.span_id,
-                        )
-                        .await
-                        .unwrap_or_else(|| vec![Duration::from_secs(60)]);
-                    message.domains[domain_idx].set_status(
+                    let policy = server.eval_retry_policy(&envelope, message.span_id).await;
+                    message.set_status(
+                        domain_idx,
                         Status::PermanentFailure(Error::DnsError(
                             "Domain does not accept messages (null MX)".to_string(),
                         )),
-                        &schedule,
+                        &policy,
                     );
                     continue 'next_domain;
                 }
@@ -893,6 +898,149 @@ impl DeliveryAttempt {
                         }
                     }
 
+                    // Prepare TLS connector
+                    let is_strict_tls = tls_strategy.is_tls_required()
+                        || (message.flags & MAIL_REQUIRETLS) != 0
+                        || mta_sts_policy.is_some()
+                        || dane_policy.is_some();
+                    let tls_connector = if allow_invalid_certs || remote_host.allow_invalid_certs()
+                    {
+                        &server.inner.data.smtp_connectors.dummy_verify
+                    } else {
+                        &server.inner.data.smtp_connectors.pki_verify
+                    };
+
+                    // Obtain session parameters
+                    let local_hostname = server
+                        .eval_if::<String, _>(&queue_config.hostname, &envelope, message.span_id)
+                        .await
+                        .filter(|s| !s.is_empty())
+                        .unwrap_or_else(|| {
+                            trc::event!(
+                                Delivery(DeliveryEvent::MissingOutboundHostname),
+                                SpanId = message.span_id,
+                            );
+                            "local.host".to_string()
+                        });
+                    let params = SessionParams {
+                        session_id: message.span_id,
+                        server: &server,
+                        credentials: remote_host.credentials(),
+                        is_smtp: remote_host.is_smtp(),
+                        hostname: envelope.mx,
+                        local_hostname: &local_hostname,
+                        timeout_ehlo: server
+                            .eval_if(&queue_config.timeout.ehlo, &envelope, message.span_id)
+                            .await
+                            .unwrap_or_else(|| Duration::from_secs(5 * 60)),
+                        timeout_mail: server
+                            .eval_if(&queue_config.timeout.mail, &envelope, message.span_id)
+                            .await
+                            .unwrap_or_else(|| Duration::from_secs(5 * 60)),
+                        timeout_rcpt: server
+                            .eval_if(&queue_config.timeout.rcpt, &envelope, message.span_id)
+                            .await
+                            .unwrap_or_else(|| Duration::from_secs(5 * 60)),
+                        timeout_data: server
+                            .eval_if(&queue_config.timeout.data, &envelope, message.span_id)
+                            .await
+                            .unwrap_or_else(|| Duration::from_secs(5 * 60)),
+                        pool: if let Some(idle_timeout) = server
+                            .eval_if::<Duration, _>(
+                                &queue_config.connection.idle_timeout,
+                                &envelope,
+                                message.span_id,
+                            )
+                            .await
+                            .filter(|idle_timeout| !idle_timeout.is_zero())
+                        {
+                            PoolParams {
+                                key: SmtpConnectionKey {
+                                    hostname: envelope.mx.to_string(),
+                                    local_ip: source_ip,
+                                    remote_ip,
+                                    port: remote_host.port(),
+                                    is_smtp: remote_host.is_smtp(),
+                                    is_tls: false,
+                                    is_dane_verified: dane_policy.is_some(),
+                                    allow_invalid_certs: allow_invalid_certs
+                                        || remote_host.allow_invalid_certs(),
+                                    credentials: remote_host.credentials().cloned(),
+                                },
+                                idle_timeout,
+                                max_messages: server
+                                    .eval_if(
+                                        &queue_config.connection.max_messages,
+                                        &envelope,
+                                        message.span_id,
+                                    )
+                                    .await
+                                    .unwrap_or(100),
+                            }
+                            .into()
+                        } else {
+                            None
+                        },
+                    };
+
+                    // Reuse an idle connection to the same host, if available
+                    if let Some(pool) = &params.pool {
+                        let mut keys = Vec::with_capacity(3);
+                        if remote_host.implicit_tls() || tls_strategy.try_start_tls() {
+                            keys.push(SmtpConnectionKey {
+                                is_tls: true,
+                                is_dane_verified: true,
+                                ..pool.key.clone()
+                            });
+                            if dane_policy.is_none() {
+                                keys.push(SmtpConnectionKey {
+                                    is_tls: true,
+                                    ..pool.key.clone()
+                                });
+                            }
+                        }
+                        if !remote_host.implicit_tls() && !is_strict_tls {
+                            keys.push(pool.key.clone());
+                        }
+
+                        if let Some(connection) = server
+                            .take_connection(&keys, params.timeout_mail, message.span_id)
+                            .await
+                        {
+                            let recipients =
+                                recipients.iter_mut().filter(|r| r.domain_idx == domain_idx);
+                            let delivery_result = match connection.client {
+                                PooledClient::Plain(smtp_client) => {
+                                    message
+                                        .deliver_transaction(
+                                            smtp_client,
+                                            connection.capabilities,
+                                            connection.num_messages,
+                                            recipients,
+                                            &params,
+                                        )
+                                        .await
+                                }
+                                PooledClient::Tls(smtp_client) => {
+                                    message
+                                        .deliver_transaction(
+                                            *smtp_client,
+                                            connection.capabilities,
+                                            connection.num_messages,
+                                            recipients,
+                                            &params,
+                                        )
+                                        .await
+                                }
+                            };
+
+                            // Update status for the current domain and continue with the next one
+                            let policy = server.eval_retry_policy(&envelope, message.span_id).await;
+                            message.set_status(domain_idx, delivery_result, &policy);
+                            continue 'next_domain;
+                        }
+                    }
+
                     // Connect
                     let time = Instant::now();
                     let conn_timeout = server
@@ -947,55 +1095,6 @@ impl DeliveryAttempt {
                         }
                     };
 
-                    // Obtain session parameters
-                    let local_hostname = server
-                        .eval_if::<String, _>(&queue_config.hostname, &envelope, message.span_id)
-                        .await
-                        .filter(|s| !s.is_empty())
-                        .unwrap_or_else(|| {
-                            trc::event!(
-                                Delivery(DeliveryEvent::MissingOutboundHostname),
-                                SpanId = message.span_id,
-                            );
-                            "local.host".to_string()
-                        });
-                    let params = SessionParams {
-                        session_id: message.span_id,
-                        server: &server,
-                        credentials: remote_host.credentials(),
-                        is_smtp: remote_host.is_smtp(),
-                        hostname: envelope.mx,
-                        local_hostname: &local_hostname,
-                        timeout_ehlo: server
-                            .eval_if(&queue_config.timeout.ehlo, &envelope, message.span_id)
-                            .await
-                            .unwrap_or_else(|| Duration::from_secs(5 * 60)),
-                        timeout_mail: server
-                            .eval_if(&queue_config.timeout.mail, &envelope, message.span_id)
-                            .await
-                            .unwrap_or_else(|| Duration::from_secs(5 * 60)),
-                        timeout_rcpt: server
-                            .eval_if(&queue_config.timeout.rcpt, &envelope, message.span_id)
-                            .await
-                            .unwrap_or_else(|| Duration::from_secs(5 * 60)),
-                        timeout_data: server
-                            .eval_if(&queue_config.timeout.data, &envelope, message.span_id)
-                            .await
-                            .unwrap_or_else(|| Duration::from_secs(5 * 60)),
-                    };
-
-                    // Prepare TLS connector
-                    let is_strict_tls = tls_strategy.is_tls_required()
-                        || (message.flags & MAIL_REQUIRETLS) != 0
-                        || mta_sts_policy.is_some()
-                        || dane_policy.is_some();
-                    let tls_connector = if allow_invalid_certs || remote_host.allow_invalid_certs()
-                    {
-                        &server.inner.data.smtp_connectors.dummy_verify
-                    } else {
-                        &server.inner.data.smtp_connectors.pki_verify
-                    };
-
                     let delivery_result = if !remote_host.implicit_tls() {
                         // Read greeting
                         smtp_client.timeout = server
@@ -1132,7 +1231,7 @@ impl DeliveryAttempt {
                                             recipients
                                                 .iter_mut()
                                                 .filter(|r| r.domain_idx == domain_idx),
-                                            params,
+                                            &params,
                                         )
                                         .await
                                 }
@@ -1190,7 +1289,7 @@ impl DeliveryAttempt {
                                                 recipients
                                                     .iter_mut()
                                                     .filter(|r| r.domain_idx == domain_idx),
-                                                params,
+                                                &params,
                                             )
                                             .await
                                     }
@@ -1247,7 +1346,7 @@ impl DeliveryAttempt {
                                 .deliver(
                                     smtp_client,
                                     recipients.iter_mut().filter(|r| r.domain_idx == domain_idx),
-                                    params,
+                                    &params,
                                 )
                                 .await
                         }
@@ -1297,31 +1396,47 @@ impl DeliveryAttempt {
                             .deliver(
                                 smtp_client,
                                 recipients.iter_mut().filter(|r| r.domain_idx == domain_idx),
-                                params,
+                                &params,
                             )
                             .await
                     };
 
                     // Update status for the current domain and continue with the next one
-                    let schedule = server
-                        .eval_if::<Vec<Duration>, _>(
-                            &queue_config.retry,
-                            &envelope,
-                            message.span_id,
-                        )
-                        .await
-                        .unwrap_or_else(|| vec![Duration::from_secs(60)]);
-                    message.domains[domain_idx].set_status(delivery_result, &schedule);
+                    let policy = server.eval_retry_policy(&envelope, message.span_id).await;
+                    message.set_status(domain_idx, delivery_result, &policy);
                     continue 'next_domain;
                 }
             }
 
             // Update status
-            let schedule = server
-                .eval_if::<Vec<Duration>, _>(&queue_config.retry, &envelope, message.span_id)
+            let policy = server.eval_retry_policy(&envelope, message.span_id).await;
+            let is_pooled = server
+                .eval_if::<Duration, _>(
+                    &queue_config.connection.idle_timeout,
+                    &envelope,
+                    message.span_id,
+                )
                 .await
-                .unwrap_or_else(|| vec![Duration::from_secs(60)]);
-            message.domains[domain_idx].set_status(last_status, &schedule);
+                .filter(|idle_timeout| !idle_timeout.is_zero())
+                .is_some();
+            if matches!(
+                remote_hosts.first(),
+                Some(NextHop::Relay(relay)) if relay.fallback == RelayFallback::Reject
+            ) {
+                // Bounce instead of queueing when the downstream transport is unavailable
+                if let Status::TemporaryFailure(err) = last_status {
+                    last_status = Status::PermanentFailure(err);
+                }
+            }
+            message.set_status(domain_idx, last_status, &policy);
+
+            // Retry as soon as the pool has a healthy connection to the failed host
+            let domain = &message.domains[domain_idx];
+            if let (Status::TemporaryFailure(err), true) = (&domain.status, is_pooled) {
+                if let Some(hostname) = err.failed_host() {
+                    server.defer_message(hostname, message.queue_id, domain.expires);
+                }
+            }
         }
         message.recipients = recipients;
 
@@ -1409,11 +1524,17 @@ impl Message {
                         std::mem::replace(&mut domain.status, Status::Scheduled).into_permanent();
                 }
                 Status::Scheduled if domain.expires <= now => {
+                    let reason = if self.deliver_by != 0 && (self.flags & MAIL_BY_RETURN) != 0 {
+                        "Delivery time expired."
+                    } else {
+                        "Queue rate limit exceeded."
+                    };
+
                     trc::event!(
                         Delivery(DeliveryEvent::Failed),
                         SpanId = self.span_id,
                         Domain = domain.domain.clone(),
-                        Reason = "Queue rate limit exceeded.",
+                        Reason = reason,
                     );
 
                     for rcpt in &mut self.recipients {
@@ -1423,9 +1544,7 @@ impl Message {
                         }
                     }
 
-                    domain.status = Status::PermanentFailure(Error::Io(
-                        "Queue rate limit exceeded.".to_string(),
-                    ));
+                    domain.status = Status::PermanentFailure(Error::Io(reason.to_string()));
                 }
                 Status::Completed(_) | Status::PermanentFailure(_) => (),
                 _ => {
@@ -1437,21 +1556,3 @@ impl Message {
         has_pending_delivery
     }
 }
-
-impl Domain {
-    pub fn set_status(&mut self, status: impl Into<Status<(), Error>>, schedule: &[Duration]) {
-        self.status = status.into();
-        if matches!(
-            &self.status,
-            Status::TemporaryFailure(_) | Status::Scheduled
-        ) {
-            self.retry(schedule);
-        }
-    }
-
-    pub fn retry(&mut self, schedule: &[Duration]) {
-        self.retry.due = now()
-            + schedule[std::cmp::min(self.retry.inner as usize, schedule.len() - 1)].as_secs();
-        self.retry.inner += 1;
-    }
-}
@@ -95,15 +95,15 @@ impl Message {
                     });
                     total_completed += 1;
                 }
-                DeliveryResult::TemporaryFailure { reason } => {
+                DeliveryResult::TemporaryFailure { code, reason } => {
                     rcpt.status = Status::TemporaryFailure(HostResponse {
                         hostname: ErrorDetails {
                             entity: "localhost".to_string(),
                             details: format!("RCPT TO:<{}>", rcpt.address),
                         },
                         response: Response {
-                            code: 451,
-                            esc: [4, 3, 0],
+                            code: if code == [4, 2, 2] { 452 } else { 451 },
+                            esc: code,
                             message: reason.into_owned(),
                         },
                     });
@@ -0,0 +1,228 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{future::Future, sync::atomic::Ordering, time::Duration};
+
+use common::{Server, SmtpConnectionKey, SmtpDeferredMessage, SmtpPooledSession, SmtpPooledStream};
+use smtp_proto::EhloResponse;
+use store::write::now;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+};
+use tokio_rustls::client::TlsStream;
+use trc::DeliveryEvent;
+
+use crate::queue::{retry::RetryScheduler, QueueId};
+
+use super::client::SmtpClient;
+
+pub struct PoolParams {
+    pub key: SmtpConnectionKey,
+    pub idle_timeout: Duration,
+    pub max_messages: usize,
+}
+
+pub struct PooledConnection {
+    pub client: PooledClient,
+    pub capabilities: EhloResponse<String>,
+    pub num_messages: usize,
+}
+
+pub enum PooledClient {
+    Plain(SmtpClient<TcpStream>),
+    Tls(Box<SmtpClient<TlsStream<TcpStream>>>),
+}
+
+pub trait IntoPooledStream {
+    fn into_pooled_stream(self) -> SmtpPooledStream;
+}
+
+pub trait ConnectionPool: Sync + Send {
+    fn take_connection(
+        &self,
+        keys: &[SmtpConnectionKey],
+        timeout: Duration,
+        session_id: u64,
+    ) -> impl Future<Output = Option<PooledConnection>> + Send;
+
+    fn release_connection<T: AsyncRead + AsyncWrite + Unpin + IntoPooledStream>(
+        &self,
+        params: &PoolParams,
+        smtp_client: SmtpClient<T>,
+        capabilities: EhloResponse<String>,
+        num_messages: usize,
+    );
+
+    fn defer_message(&self, hostname: &str, queue_id: QueueId, expires: u64);
+}
+
+impl ConnectionPool for Server {
+    async fn take_connection(
+        &self,
+        keys: &[SmtpConnectionKey],
+        timeout: Duration,
+        session_id: u64,
+    ) -> Option<PooledConnection> {
+        let pool = &self.inner.data.smtp_connection_pool;
+
+        for key in keys {
+            while let Some(session) = pool.sessions.get_mut(key).and_then(|mut s| s.pop()) {
+                pool.sessions
+                    .remove_if(key, |_, sessions| sessions.is_empty());
+
+                // Make sure the session is still usable before handing it out
+                let mut client = PooledClient::new(session.stream, timeout, session_id);
+                if client.reset().await {
+                    trc::event!(
+                        Delivery(DeliveryEvent::ConnectionReused),
+                        SpanId = session_id,
+                        Hostname = key.hostname.clone(),
+                        RemoteIp = key.remote_ip,
+                        RemotePort = key.port,
+                        Total = session.num_messages,
+                    );
+
+                    return Some(PooledConnection {
+                        client,
+                        capabilities: session.capabilities,
+                        num_messages: session.num_messages,
+                    });
+                }
+            }
+        }
+
+        None
+    }
+
+    fn release_connection<T: AsyncRead + AsyncWrite + Unpin + IntoPooledStream>(
+        &self,
+        params: &PoolParams,
+        smtp_client: SmtpClient<T>,
+        capabilities: EhloResponse<String>,
+        num_messages: usize,
+    ) {
+        let pool = &self.inner.data.smtp_connection_pool;
+        let id = pool.id_gen.fetch_add(1, Ordering::Relaxed);
+        let stream = smtp_client.stream.into_pooled_stream();
+        let mut key = params.key.clone();
+        key.is_tls = matches!(stream, SmtpPooledStream::Tls(_));
+
+        trc::event!(
+            Delivery(DeliveryEvent::ConnectionPooled),
+            SpanId = smtp_client.session_id,
+            Hostname = key.hostname.clone(),
+            RemoteIp = key.remote_ip,
+            RemotePort = key.port,
+            Total = num_messages,
+        );
+
+        pool.sessions
+            .entry(key.clone())
+            .or_default()
+            .push(SmtpPooledSession {
+                id,
+                stream,
+                capabilities,
+                num_messages,
+            });
+
+        // The host is accepting messages again, retry any messages deferred by it
+        if let Some((hostname, deferred)) = pool.deferred.remove(&key.hostname) {
+            let now = now();
+            let queue_ids = deferred
+                .into_iter()
+                .filter(|message| message.expires > now)
+                .map(|message| message.queue_id)
+                .collect::<Vec<_>>();
+            if !queue_ids.is_empty() {
+                let server = self.clone();
+                tokio::spawn(async move {
+                    server.retry_deferred(hostname, queue_ids).await;
+                });
+            }
+        }
+
+        // Close the session once it has been idle for too long
+        let server = self.clone();
+        let idle_timeout = params.idle_timeout;
+        let session_id = smtp_client.session_id;
+        tokio::spawn(async move {
+            tokio::time::sleep(idle_timeout).await;
+            let pool = &server.inner.data.smtp_connection_pool;
+            let session = pool.sessions.get_mut(&key).and_then(|mut sessions| {
+                sessions
+                    .iter()
+                    .position(|session| session.id == id)
+                    .map(|pos| sessions.swap_remove(pos))
+            });
+            pool.sessions
+                .remove_if(&key, |_, sessions| sessions.is_empty());
+
+            if let Some(session) = session {
+                PooledClient::new(session.stream, Duration::from_secs(10), session_id)
+                    .quit()
+                    .await;
+            }
+        });
+    }
+
+    fn defer_message(&self, hostname: &str, queue_id: QueueId, expires: u64) {
+        let now = now();
+        let mut deferred = self
+            .inner
+            .data
+            .smtp_connection_pool
+            .deferred
+            .entry(hostname.to_string())
+            .or_default();
+        deferred.retain(|message| message.queue_id != queue_id && message.expires > now);
+        deferred.push(SmtpDeferredMessage { queue_id, expires });
+    }
+}
+
+impl PooledClient {
+    fn new(stream: SmtpPooledStream, timeout: Duration, session_id: u64) -> Self {
+        match stream {
+            SmtpPooledStream::Plain(stream) => PooledClient::Plain(SmtpClient {
+                stream,
+                timeout,
+                session_id,
+            }),
+            SmtpPooledStream::Tls(stream) => PooledClient::Tls(Box::new(SmtpClient {
+                stream: *stream,
+                timeout,
+                session_id,
+            })),
+        }
+    }
+
+    async fn reset(&mut self) -> bool {
+        match self {
+            PooledClient::Plain(client) => client.reset().await,
+            PooledClient::Tls(client) => client.reset().await,
+        }
+    }
+
+    async fn quit(self) {
+        match self {
+            PooledClient::Plain(client) => client.quit().await,
+            PooledClient::Tls(client) => (*client).quit().await,
+        }
+    }
+}
+
+impl IntoPooledStream for TcpStream {
+    fn into_pooled_stream(self) -> SmtpPooledStream {
+        SmtpPooledStream::Plain(self)
+    }
+}
+
+impl IntoPooledStream for TlsStream<TcpStream> {
+    fn into_pooled_stream(self) -> SmtpPooledStream {
+        SmtpPooledStream::Tls(Box::new(self))
+    }
+}
@@ -8,21 +8,29 @@ use common::config::smtp::queue::RequireOptional;
 use common::Server;
 use mail_send::Credentials;
 use smtp_proto::{
-    EhloResponse, Severity, EXT_CHUNKING, EXT_DSN, EXT_REQUIRE_TLS, EXT_SIZE, EXT_SMTP_UTF8,
-    MAIL_REQUIRETLS, MAIL_RET_FULL, MAIL_RET_HDRS, MAIL_SMTPUTF8, RCPT_NOTIFY_DELAY,
-    RCPT_NOTIFY_FAILURE, RCPT_NOTIFY_NEVER, RCPT_NOTIFY_SUCCESS,
+    EhloResponse, Severity, EXT_CHUNKING, EXT_DELIVER_BY, EXT_DSN, EXT_REQUIRE_TLS, EXT_SIZE,
+    EXT_SMTP_UTF8, MAIL_BY_NOTIFY, MAIL_BY_RETURN, MAIL_BY_TRACE, MAIL_REQUIRETLS, MAIL_RET_FULL,
+    MAIL_RET_HDRS, MAIL_SMTPUTF8, RCPT_NOTIFY_DELAY, RCPT_NOTIFY_FAILURE, RCPT_NOTIFY_NEVER,
+    RCPT_NOTIFY_SUCCESS,
 };
 use std::time::Duration;
 use std::{fmt::Write, time::Instant};
+use store::write::now;
 use tokio::io::{AsyncRead, AsyncWrite};
 use trc::DeliveryEvent;
 
 use crate::outbound::client::{from_error_status, from_mail_send_error};
-use crate::queue::{ErrorDetails, HostResponse, RCPT_STATUS_CHANGED};
+use crate::queue::{
+    dsn::write_xtext, ErrorDetails, HostResponse, RCPT_DSN_RELAYED, RCPT_STATUS_CHANGED,
+};
 
 use crate::queue::{Error, Message, Recipient, Status};
 
-use super::{client::SmtpClient, TlsStrategy};
+use super::{
+    client::SmtpClient,
+    pool::{ConnectionPool, IntoPooledStream, PoolParams},
+    TlsStrategy,
+};
 
 pub struct SessionParams<'x> {
     pub server: &'x Server,
@@ -35,18 +43,19 @@ pub struct SessionParams<'x> {
     pub timeout_rcpt: Duration,
     pub timeout_data: Duration,
     pub session_id: u64,
+    pub pool: Option<PoolParams>,
 }
 
 impl Message {
-    pub async fn deliver<T: AsyncRead + AsyncWrite + Unpin>(
+    pub async fn deliver<T: AsyncRead + AsyncWrite + Unpin + IntoPooledStream>(
         &self,
         mut smtp_client: SmtpClient<T>,
         recipients: impl Iterator<Item = &mut Recipient>,
-        params: SessionParams<'_>,
+        params: &SessionParams<'_>,
     ) -> Status<(), Error> {
         // Obtain capabilities
         let time = Instant::now();
-        let capabilities = match smtp_client.say_helo(&params).await {
+        let capabilities = match smtp_client.say_helo(params).await {
             Ok(capabilities) => {
                 trc::event!(
                     Delivery(DeliveryEvent::Ehlo),
@@ -112,6 +121,18 @@ impl Message {
             };*/
         }
 
+        self.deliver_transaction(smtp_client, capabilities, 0, recipients, params)
+            .await
+    }
+
+    pub async fn deliver_transaction<T: AsyncRead + AsyncWrite + Unpin + IntoPooledStream>(
+        &self,
+        mut smtp_client: SmtpClient<T>,
+        capabilities: EhloResponse<String>,
+        num_messages: usize,
+        recipients: impl Iterator<Item = &mut Recipient>,
+        params: &SessionParams<'_>,
+    ) -> Status<(), Error> {
         // MAIL FROM
         let time = Instant::now();
         smtp_client.timeout = params.timeout_mail;
@@ -152,6 +173,7 @@ impl Message {
         let mut total_rcpt = 0;
         let mut total_completed = 0;
         let mut accepted_rcpts = Vec::new();
+        let mut is_closing = false;
         smtp_client.timeout = params.timeout_rcpt;
         for rcpt in recipients {
             let time = Instant::now();
@@ -197,6 +219,7 @@ impl Message {
                             Elapsed = time.elapsed(),
                         );
 
+                        is_closing |= response.code == 421;
                         let response = HostResponse {
                             hostname: ErrorDetails {
                                 entity: params.hostname.to_string(),
@@ -237,7 +260,7 @@ impl Message {
                 .has_capability(EXT_CHUNKING)
                 .then(|| format!("BDAT {} LAST\r\n", self.size));
 
-            if let Err(status) = smtp_client.send_message(self, &bdat_cmd, &params).await {
+            if let Err(status) = smtp_client.send_message(self, &bdat_cmd, params).await {
                 trc::event!(
                     Delivery(DeliveryEvent::MessageRejected),
                     SpanId = params.session_id,
@@ -259,6 +282,15 @@ impl Message {
                     Ok(response) => {
                         // Mark recipients as delivered
                         if response.code() == 250 {
+                            // Next hops supporting DSN take over success notifications,
+                            // otherwise a "relayed" DSN is issued (RFC 3461 section 4.3)
+                            let has_dsn = capabilities.has_capability(EXT_DSN);
+
+                            // Traced DELIVERBY messages relayed to a next hop without
+                            // the extension also get a "relayed" DSN (RFC 2852)
+                            let by_relayed = self.has_flag(MAIL_BY_TRACE)
+                                && self.has_flag(MAIL_BY_NOTIFY | MAIL_BY_RETURN)
+                                && !capabilities.has_capability(EXT_DELIVER_BY);
                             for (rcpt, status) in accepted_rcpts {
                                 trc::event!(
                                     Delivery(DeliveryEvent::Delivered),
@@ -272,6 +304,13 @@ impl Message {
 
                                 rcpt.status = status;
                                 rcpt.flags |= RCPT_STATUS_CHANGED;
+                                if by_relayed {
+                                    rcpt.flags |= RCPT_DSN_RELAYED | RCPT_NOTIFY_SUCCESS;
+                                } else if has_dsn {
+                                    rcpt.flags &= !RCPT_NOTIFY_SUCCESS;
+                                } else {
+                                    rcpt.flags |= RCPT_DSN_RELAYED;
+                                }
                                 total_completed += 1;
                             }
                         } else {
@@ -313,6 +352,7 @@ impl Message {
                 {
                     Ok(responses) => {
                         for ((rcpt, _), response) in accepted_rcpts.into_iter().zip(responses) {
+                            is_closing |= response.code == 421;
                             rcpt.flags |= RCPT_STATUS_CHANGED;
                             rcpt.status = match response.severity() {
                                 Severity::PositiveCompletion => {
@@ -379,7 +419,18 @@ impl Message {
             }
         }
 
-        smtp_client.quit().await;
+        // Keep the session open for further deliveries to the same host
+        match &params.pool {
+            Some(pool) if !is_closing && num_messages + 1 < pool.max_messages => {
+                params
+                    .server
+                    .release_connection(pool, smtp_client, capabilities, num_messages + 1);
+            }
+            _ => {
+                smtp_client.quit().await;
+            }
+        }
+
         if total_completed == total_rcpt {
             Status::Completed(())
         } else {
@@ -399,6 +450,25 @@ impl Message {
         if self.has_flag(MAIL_SMTPUTF8) & capabilities.has_capability(EXT_SMTP_UTF8) {
             mail_from.push_str(" SMTPUTF8");
         }
+        if self.deliver_by != 0
+            && self.has_flag(MAIL_BY_NOTIFY | MAIL_BY_RETURN)
+            && capabilities.has_capability(EXT_DELIVER_BY)
+        {
+            // Relay the time remaining until the deadline
+            let _ = write!(
+                mail_from,
+                " BY={};{}",
+                self.deliver_by as i64 - now() as i64,
+                if self.has_flag(MAIL_BY_RETURN) {
+                    "R"
+                } else {
+                    "N"
+                }
+            );
+            if self.has_flag(MAIL_BY_TRACE) {
+                mail_from.push('T');
+            }
+        }
         if capabilities.has_capability(EXT_DSN) {
             if self.has_flag(MAIL_RET_FULL) {
                 mail_from.push_str(" RET=FULL");
@@ -406,7 +476,8 @@ impl Message {
                 mail_from.push_str(" RET=HDRS");
             }
             if let Some(env_id) = &self.env_id {
-                let _ = write!(mail_from, " ENVID={env_id}");
+                mail_from.push_str(" ENVID=");
+                write_xtext(&mut mail_from, env_id);
             }
         }
 
@@ -443,6 +514,10 @@ impl Message {
             } else if rcpt.has_flag(RCPT_NOTIFY_NEVER) {
                 rcpt_to.push_str(" NOTIFY=NEVER");
             }
+            if let Some(orcpt) = &rcpt.orcpt {
+                rcpt_to.push_str(" ORCPT=rfc822;");
+                write_xtext(&mut rcpt_to, orcpt);
+            }
         }
         rcpt_to.push_str("\r\n");
         rcpt_to
@@ -11,7 +11,8 @@ use mail_builder::mime::{make_boundary, BodyPart, MimePart};
 use mail_builder::MessageBuilder;
 use mail_parser::DateTime;
 use smtp_proto::{
-    Response, RCPT_NOTIFY_DELAY, RCPT_NOTIFY_FAILURE, RCPT_NOTIFY_NEVER, RCPT_NOTIFY_SUCCESS,
+    Response, MAIL_BY_RETURN, MAIL_RET_FULL, RCPT_NOTIFY_DELAY, RCPT_NOTIFY_FAILURE,
+    RCPT_NOTIFY_NEVER, RCPT_NOTIFY_SUCCESS,
 };
 use std::fmt::Write;
 use std::future::Future;
@@ -21,10 +22,11 @@ use store::write::now;
 use crate::outbound::client::from_error_status;
 use crate::reporting::SmtpReporting;
 
+use super::list::{parse_verp, SmtpMailingList};
 use super::spool::SmtpSpool;
 use super::{
     Domain, Error, ErrorDetails, HostResponse, Message, MessageSource, QueueEnvelope, Recipient,
-    Status, RCPT_DSN_SENT, RCPT_STATUS_CHANGED,
+    Status, RCPT_DSN_RELAYED, RCPT_DSN_SENT, RCPT_STATUS_CHANGED,
 };
 
 pub trait SendDsn: Sync + Send {
@@ -37,7 +39,48 @@ impl SendDsn for Server {
         // Send DSN events
         self.log_dsn(message).await;
 
-        if !message.return_path.is_empty() {
+        let verp = match self
+            .core
+            .smtp
+            .lists
+            .as_ref()
+            .and_then(|_| parse_verp(&message.return_path_lcase))
+        {
+            Some((list, member)) => match self
+                .is_list_member(
+                    &self.core.storage.directory,
+                    &list,
+                    &member,
+                    message.span_id,
+                )
+                .await
+            {
+                Ok(true) => Some((list, member)),
+                Ok(false) => None,
+                Err(err) => {
+                    trc::error!(err
+                        .span_id(message.span_id)
+                        .details("Failed to verify mailing list member.")
+                        .caused_by(trc::location!()));
+                    None
+                }
+            },
+            None => None,
+        };
+
+        if let Some((list, member)) = verp {
+            // Failed mailing list deliveries count as a bounce for the member
+            let has_failed = message.has_new_permanent_failure();
+            message.build_dsn(self).await;
+            if has_failed {
+                if let Err(err) = self.list_bounce(&list, &member, message.span_id).await {
+                    trc::error!(err
+                        .span_id(message.span_id)
+                        .details("Failed to record mailing list bounce.")
+                        .caused_by(trc::location!()));
+                }
+            }
+        } else if !message.return_path.is_empty() {
             // Build DSN
             if let Some(dsn) = message.build_dsn(self).await {
                 let mut dsn_message = self.new_message("", "", "", message.span_id);
@@ -169,6 +212,14 @@ impl Message {
         let mut txt_failed = String::new();
         let mut dsn = String::new();
 
+        // Messages that missed their DELIVERBY deadline in return mode fail
+        // permanently rather than with the transient queue expiration status
+        let expired_status = if self.deliver_by != 0 && (self.flags & MAIL_BY_RETURN) != 0 {
+            "5.4.7"
+        } else {
+            "4.4.7"
+        };
+
         for rcpt in &mut self.recipients {
             if rcpt.has_flag(RCPT_DSN_SENT | RCPT_NOTIFY_NEVER) {
                 continue;
@@ -180,15 +231,16 @@ impl Message {
                     if !rcpt.has_flag(RCPT_NOTIFY_SUCCESS) {
                         continue;
                     }
+                    let is_relayed = rcpt.has_flag(RCPT_DSN_RELAYED);
                     rcpt.write_dsn(&mut dsn);
-                    rcpt.status.write_dsn(&mut dsn);
-                    response.write_dsn_text(&rcpt.address, &mut txt_success);
+                    rcpt.status.write_dsn(&mut dsn, is_relayed);
+                    response.write_dsn_text(&rcpt.address, is_relayed, &mut txt_success);
                 }
                 Status::TemporaryFailure(response)
                     if domain.notify.due <= now && rcpt.has_flag(RCPT_NOTIFY_DELAY) =>
                 {
                     rcpt.write_dsn(&mut dsn);
-                    rcpt.status.write_dsn(&mut dsn);
+                    rcpt.status.write_dsn(&mut dsn, false);
                     domain.write_dsn_will_retry_until(&mut dsn);
                     response.write_dsn_text(&rcpt.address, &mut txt_delay);
                 }
@@ -198,7 +250,7 @@ impl Message {
                         continue;
                     }
                     rcpt.write_dsn(&mut dsn);
-                    rcpt.status.write_dsn(&mut dsn);
+                    rcpt.status.write_dsn(&mut dsn, false);
                     response.write_dsn_text(&rcpt.address, &mut txt_failed);
                 }
                 Status::Scheduled => {
@@ -210,14 +262,17 @@ impl Message {
                                 continue;
                             }
                             rcpt.write_dsn(&mut dsn);
-                            domain.status.write_dsn(&mut dsn);
+                            domain.status.write_dsn(
+                                &mut dsn,
+                                (domain.expires <= now).then_some(expired_status),
+                            );
                             err.write_dsn_text(&rcpt.address, &domain.domain, &mut txt_failed);
                         }
                         Status::TemporaryFailure(err)
                             if domain.notify.due <= now && rcpt.has_flag(RCPT_NOTIFY_DELAY) =>
                         {
                             rcpt.write_dsn(&mut dsn);
-                            domain.status.write_dsn(&mut dsn);
+                            domain.status.write_dsn(&mut dsn, None);
                             domain.write_dsn_will_retry_until(&mut dsn);
                             err.write_dsn_text(&rcpt.address, &domain.domain, &mut txt_delay);
                         }
@@ -226,7 +281,7 @@ impl Message {
                         {
                             // This case should not happen under normal circumstances
                             rcpt.write_dsn(&mut dsn);
-                            domain.status.write_dsn(&mut dsn);
+                            domain.status.write_dsn(&mut dsn, None);
                             domain.write_dsn_will_retry_until(&mut dsn);
                             Error::ConcurrencyLimited.write_dsn_text(
                                 &rcpt.address,
@@ -362,12 +417,19 @@ impl Message {
         self.write_dsn_headers(&mut dsn_header, &reporting_mta);
         let dsn = dsn_header + dsn.as_str();
 
-        // Fetch up to 1024 bytes of message headers
-        let headers = match server
+        // Return the full message on failures if RET=FULL was requested,
+        // otherwise fetch up to 1024 bytes of message headers
+        let return_full = has_failure && self.has_flag(MAIL_RET_FULL);
+        let contents = match server
             .blob_store()
-            .get_blob(self.blob_hash.as_slice(), 0..1024)
+            .get_blob(
+                self.blob_hash.as_slice(),
+                if return_full { 0..usize::MAX } else { 0..1024 },
+            )
             .await
         {
+            Ok(Some(buf)) if return_full => String::from_utf8(buf)
+                .unwrap_or_else(|err| String::from_utf8_lossy(err.as_bytes()).into_owned()),
             Ok(Some(mut buf)) => {
                 let mut prev_ch = 0;
                 let mut last_lf = buf.len();
@@ -429,8 +491,12 @@ impl Message {
                         BodyPart::Text(dsn.into()),
                     ),
                     MimePart::new(
-                        ContentType::new("message/rfc822"),
-                        BodyPart::Text(headers.into()),
+                        ContentType::new(if return_full {
+                            "message/rfc822"
+                        } else {
+                            "text/rfc822-headers"
+                        }),
+                        BodyPart::Text(contents.into()),
                     ),
                 ]),
             ))
@@ -483,11 +549,12 @@ impl Message {
 }
 
 impl HostResponse<String> {
-    fn write_dsn_text(&self, addr: &str, dsn: &mut String) {
+    fn write_dsn_text(&self, addr: &str, is_relayed: bool, dsn: &mut String) {
         let _ = write!(
             dsn,
-            "<{}> (delivered to '{}' with code {} ({}.{}.{}) '",
+            "<{}> ({} to '{}' with code {} ({}.{}.{}) '",
             addr,
+            if is_relayed { "relayed" } else { "delivered" },
             self.hostname,
             self.response.code,
             self.response.esc[0],
@@ -578,7 +645,9 @@ impl Message {
         dsn.push_str(&DateTime::from_timestamp(self.created as i64).to_rfc822());
         dsn.push_str("\r\n");
         if let Some(env_id) = &self.env_id {
-            let _ = write!(dsn, "Original-Envelope-Id: {env_id}\r\n");
+            dsn.push_str("Original-Envelope-Id: ");
+            write_xtext(dsn, env_id);
+            dsn.push_str("\r\n");
         }
         dsn.push_str("\r\n");
     }
@@ -587,7 +656,9 @@ impl Message {
 impl Recipient {
     fn write_dsn(&self, dsn: &mut String) {
         if let Some(orcpt) = &self.orcpt {
-            let _ = write!(dsn, "Original-Recipient: rfc822;{orcpt}\r\n");
+            dsn.push_str("Original-Recipient: rfc822;");
+            write_xtext(dsn, orcpt);
+            dsn.push_str("\r\n");
         }
         let _ = write!(dsn, "Final-Recipient: rfc822;{}\r\n", self.address);
     }
@@ -635,8 +706,12 @@ impl<T, E> Status<T, E> {
 }
 
 impl Status<HostResponse<String>, HostResponse<ErrorDetails>> {
-    fn write_dsn(&self, dsn: &mut String) {
-        self.write_dsn_action(dsn);
+    fn write_dsn(&self, dsn: &mut String, is_relayed: bool) {
+        if is_relayed {
+            dsn.push_str("Action: relayed\r\n");
+        } else {
+            self.write_dsn_action(dsn);
+        }
         self.write_dsn_status(dsn);
         self.write_dsn_diagnostic(dsn);
         self.write_dsn_remote_mta(dsn);
@@ -682,18 +757,21 @@ impl Status<HostResponse<String>, HostResponse<ErrorDetails>> {
 }
 
 impl Status<(), Error> {
-    fn write_dsn(&self, dsn: &mut String) {
+    fn write_dsn(&self, dsn: &mut String, expired_status: Option<&str>) {
         self.write_dsn_action(dsn);
-        self.write_dsn_status(dsn);
+        self.write_dsn_status(dsn, expired_status);
         self.write_dsn_diagnostic(dsn);
         self.write_dsn_remote_mta(dsn);
     }
 
-    fn write_dsn_status(&self, dsn: &mut String) {
+    fn write_dsn_status(&self, dsn: &mut String, expired_status: Option<&str>) {
         if let Status::PermanentFailure(err) | Status::TemporaryFailure(err) = self {
             dsn.push_str("Status: ");
             if let Error::UnexpectedResponse(response) = err {
                 response.response.write_dsn_status(dsn);
+            } else if let Some(expired_status) = expired_status {
+                // Delivery time expired (RFC 3463)
+                dsn.push_str(expired_status);
             } else {
                 dsn.push_str(if matches!(self, Status::PermanentFailure(_)) {
                     "5.0.0"
@@ -762,6 +840,17 @@ impl WriteDsn for Response<String> {
     }
 }
 
+// Encodes a value as xtext (RFC 3461 section 4)
+pub(crate) fn write_xtext(dsn: &mut String, value: &str) {
+    for &byte in value.as_bytes() {
+        if (33..=126).contains(&byte) && byte != b'+' && byte != b'=' {
+            dsn.push(byte as char);
+        } else {
+            let _ = write!(dsn, "+{byte:02X}");
+        }
+    }
+}
+
 trait WriteDsn {
     fn write_dsn_status(&self, dsn: &mut String);
     fn write_dsn_diagnostic(&self, dsn: &mut String);
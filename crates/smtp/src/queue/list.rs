@@ -0,0 +1,602 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use ahash::AHashMap;
+use common::Server;
+use directory::{backend::RcptType, Directory};
+use mail_builder::{
+    headers::{content_type::ContentType, HeaderType},
+    mime::{make_boundary, BodyPart, MimePart},
+    MessageBuilder,
+};
+use serde::{Deserialize, Serialize};
+use smtp_proto::{RCPT_NOTIFY_FAILURE, RCPT_NOTIFY_NEVER};
+use store::{
+    write::{now, BatchBuilder, Bincode, BlobOp, QueueClass, ValueClass},
+    Deserialize as _, IterateParams, Serialize as _, ValueKey,
+};
+use trc::{AddContext, QueueEvent};
+use utils::BlobHash;
+
+use super::{
+    quota::HasQueueQuota, spool::SmtpSpool, DomainPart, Message, MessageSource, QueueId, Status,
+    RCPT_DSN_SENT,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListRecipient {
+    Post {
+        list: String,
+    },
+    Bounce {
+        address: String,
+        list: String,
+        member: String,
+    },
+}
+
+impl ListRecipient {
+    pub fn address(&self) -> &str {
+        match self {
+            ListRecipient::Post { list } => list,
+            ListRecipient::Bounce { address, .. } => address,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListMemberStatus {
+    Active,
+    Digest,
+    Suspended,
+}
+
+// Messages held for the next digest of a list
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DigestMessage {
+    pub id: u64,
+    pub list: String,
+    pub blob_hash: BlobHash,
+    pub size: usize,
+    pub created: u64,
+}
+
+pub struct ListPost<'x> {
+    pub list: &'x str,
+    pub members: Vec<String>,
+    pub headers: &'x [u8],
+    pub raw_message: &'x [u8],
+    pub source: MessageSource,
+    pub session_id: u64,
+}
+
+pub trait SmtpMailingList: Sync + Send {
+    fn list_deliver(
+        &self,
+        post: ListPost<'_>,
+    ) -> impl Future<Output = trc::Result<Vec<QueueId>>> + Send;
+
+    fn list_bounce(
+        &self,
+        list: &str,
+        member: &str,
+        session_id: u64,
+    ) -> impl Future<Output = trc::Result<ListMemberStatus>> + Send;
+
+    fn list_member_status(
+        &self,
+        list: &str,
+        member: &str,
+    ) -> impl Future<Output = trc::Result<ListMemberStatus>> + Send;
+
+    fn is_list_member(
+        &self,
+        directory: &Directory,
+        list: &str,
+        member: &str,
+        session_id: u64,
+    ) -> impl Future<Output = trc::Result<bool>> + Send;
+
+    fn list_set_digest(
+        &self,
+        list: &str,
+        member: &str,
+        digest: bool,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+
+    fn list_send_digests(&self) -> impl Future<Output = trc::Result<()>> + Send;
+}
+
+impl SmtpMailingList for Server {
+    async fn list_deliver(&self, post: ListPost<'_>) -> trc::Result<Vec<QueueId>> {
+        let mut headers = list_headers(post.list).into_bytes();
+        headers.extend_from_slice(post.headers);
+
+        // Each member receives its own copy so bounces can be attributed
+        let mut queue_ids = Vec::with_capacity(post.members.len());
+        let mut has_digest = false;
+        for member in &post.members {
+            match self.list_member_status(post.list, member).await? {
+                ListMemberStatus::Active => {
+                    let queue_id = self
+                        .list_queue(
+                            post.list,
+                            member,
+                            &headers,
+                            post.raw_message,
+                            post.source,
+                            post.session_id,
+                        )
+                        .await?;
+                    queue_ids.push(queue_id);
+                }
+                ListMemberStatus::Digest => {
+                    has_digest = true;
+                }
+                ListMemberStatus::Suspended => {}
+            }
+        }
+
+        // Hold a single copy for the members that receive digests
+        if has_digest {
+            let mut message = Vec::with_capacity(headers.len() + post.raw_message.len());
+            message.extend_from_slice(&headers);
+            message.extend_from_slice(post.raw_message);
+            self.list_hold_digest(post.list, &message).await?;
+        }
+
+        trc::event!(
+            Queue(QueueEvent::ListExpanded),
+            SpanId = post.session_id,
+            To = post.list.to_string(),
+            Total = post.members.len(),
+            QueueId = queue_ids
+                .iter()
+                .map(|id| trc::Value::from(*id))
+                .collect::<Vec<_>>(),
+        );
+
+        Ok(queue_ids)
+    }
+
+    async fn list_bounce(
+        &self,
+        list: &str,
+        member: &str,
+        session_id: u64,
+    ) -> trc::Result<ListMemberStatus> {
+        let Some(config) = &self.core.smtp.lists else {
+            return Ok(ListMemberStatus::Active);
+        };
+        let bounces = self
+            .lookup_store()
+            .counter_incr(
+                bounce_key(list, member),
+                1,
+                config.bounce_expire.as_secs().into(),
+                true,
+            )
+            .await
+            .caused_by(trc::location!())?;
+
+        trc::event!(
+            Queue(QueueEvent::ListBounce),
+            SpanId = session_id,
+            To = list.to_string(),
+            From = member.to_string(),
+            Total = bounces,
+        );
+
+        if bounces as u64 >= config.bounce_threshold {
+            if bounces as u64 == config.bounce_threshold {
+                trc::event!(
+                    Queue(QueueEvent::ListMemberSuspended),
+                    SpanId = session_id,
+                    To = list.to_string(),
+                    From = member.to_string(),
+                    Limit = config.bounce_threshold,
+                );
+            }
+            Ok(ListMemberStatus::Suspended)
+        } else {
+            self.list_member_status(list, member).await
+        }
+    }
+
+    async fn list_member_status(&self, list: &str, member: &str) -> trc::Result<ListMemberStatus> {
+        let bounce_threshold = self
+            .core
+            .smtp
+            .lists
+            .as_ref()
+            .map_or(u64::MAX, |config| config.bounce_threshold);
+        let store = self.lookup_store();
+
+        if store
+            .counter_get(bounce_key(list, member))
+            .await
+            .caused_by(trc::location!())? as u64
+            >= bounce_threshold
+        {
+            Ok(ListMemberStatus::Suspended)
+        } else if store
+            .key_exists(digest_key(list, member))
+            .await
+            .caused_by(trc::location!())?
+        {
+            Ok(ListMemberStatus::Digest)
+        } else {
+            Ok(ListMemberStatus::Active)
+        }
+    }
+
+    async fn is_list_member(
+        &self,
+        directory: &Directory,
+        list: &str,
+        member: &str,
+        session_id: u64,
+    ) -> trc::Result<bool> {
+        match self.rcpt(directory, list, session_id).await? {
+            RcptType::List(members) => Ok(members.iter().any(|m| m.eq_ignore_ascii_case(member))),
+            _ => Ok(false),
+        }
+    }
+
+    async fn list_set_digest(&self, list: &str, member: &str, digest: bool) -> trc::Result<()> {
+        let store = self.lookup_store();
+        if digest {
+            store.key_set(digest_key(list, member), vec![1], None).await
+        } else {
+            store.key_delete(digest_key(list, member)).await
+        }
+        .caused_by(trc::location!())
+    }
+
+    async fn list_send_digests(&self) -> trc::Result<()> {
+        let max_messages = self
+            .core
+            .smtp
+            .lists
+            .as_ref()
+            .map_or(100, |config| config.digest_max_messages);
+
+        // Group held messages by list, oldest first
+        let from_key = ValueKey::from(ValueClass::Queue(QueueClass::ListDigest(0)));
+        let to_key = ValueKey::from(ValueClass::Queue(QueueClass::ListDigest(u64::MAX)));
+        let mut lists: AHashMap<String, Vec<DigestMessage>> = AHashMap::new();
+        self.store()
+            .iterate(
+                IterateParams::new(from_key, to_key).ascending(),
+                |_, value| {
+                    let message = Bincode::<DigestMessage>::deserialize(value)?.inner;
+                    lists.entry(message.list.clone()).or_default().push(message);
+                    Ok(true)
+                },
+            )
+            .await
+            .caused_by(trc::location!())?;
+
+        for (list, mut messages) in lists {
+            messages.sort_unstable_by_key(|message| message.created);
+
+            // Members are resolved again as the list could have changed
+            let members = match self
+                .rcpt(&self.core.storage.directory, &list, 0)
+                .await
+                .caused_by(trc::location!())?
+            {
+                RcptType::List(members) => members,
+                _ => vec![],
+            };
+            let mut digest_members = Vec::new();
+            for member in members {
+                if self.list_member_status(&list, &member).await? == ListMemberStatus::Digest {
+                    digest_members.push(member);
+                }
+            }
+
+            if !digest_members.is_empty() {
+                for chunk in messages.chunks(max_messages) {
+                    let mut contents = Vec::with_capacity(chunk.len());
+                    for message in chunk {
+                        if let Some(raw_message) = self
+                            .blob_store()
+                            .get_blob(message.blob_hash.as_slice(), 0..usize::MAX)
+                            .await
+                            .caused_by(trc::location!())?
+                        {
+                            contents.push(raw_message);
+                        } else {
+                            trc::event!(
+                                Queue(QueueEvent::BlobNotFound),
+                                BlobId = message.blob_hash.to_hex(),
+                                CausedBy = trc::location!()
+                            );
+                        }
+                    }
+                    if contents.is_empty() {
+                        continue;
+                    }
+
+                    let total = contents.len();
+                    let digest = build_digest(&list, contents);
+                    let mut queue_ids = Vec::with_capacity(digest_members.len());
+                    for member in &digest_members {
+                        queue_ids.push(
+                            self.list_queue(
+                                &list,
+                                member,
+                                &[],
+                                &digest,
+                                MessageSource::Autogenerated,
+                                0,
+                            )
+                            .await?,
+                        );
+                    }
+
+                    trc::event!(
+                        Queue(QueueEvent::ListDigest),
+                        To = list.clone(),
+                        Total = total,
+                        QueueId = queue_ids
+                            .iter()
+                            .map(|id| trc::Value::from(*id))
+                            .collect::<Vec<_>>(),
+                    );
+                }
+            }
+
+            // Unlinked blobs are removed by the next blob store purge
+            let mut batch = BatchBuilder::new();
+            for message in &messages {
+                batch
+                    .clear(BlobOp::LinkId {
+                        hash: message.blob_hash.clone(),
+                        id: message.id,
+                    })
+                    .clear(ValueClass::Queue(QueueClass::ListDigest(message.id)));
+            }
+            self.store()
+                .write(batch.build())
+                .await
+                .caused_by(trc::location!())?;
+        }
+
+        Ok(())
+    }
+}
+
+trait SmtpMailingListQueue: Sync + Send {
+    fn list_queue(
+        &self,
+        list: &str,
+        member: &str,
+        headers: &[u8],
+        raw_message: &[u8],
+        source: MessageSource,
+        session_id: u64,
+    ) -> impl Future<Output = trc::Result<QueueId>> + Send;
+
+    fn list_hold_digest(
+        &self,
+        list: &str,
+        raw_message: &[u8],
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+}
+
+impl SmtpMailingListQueue for Server {
+    async fn list_queue(
+        &self,
+        list: &str,
+        member: &str,
+        headers: &[u8],
+        raw_message: &[u8],
+        source: MessageSource,
+        session_id: u64,
+    ) -> trc::Result<QueueId> {
+        let return_path = verp_address(list, member);
+        let return_path_lcase = return_path.to_lowercase();
+        let return_path_domain = return_path_lcase.domain_part().to_string();
+        let mut message = self.new_message(
+            return_path,
+            return_path_lcase,
+            return_path_domain,
+            session_id,
+        );
+        message.add_recipient(member, self).await;
+        let recipient = message.recipients.last_mut().unwrap();
+        recipient.flags = RCPT_NOTIFY_FAILURE;
+        recipient.orcpt = Some(list.to_string());
+        message.size = headers.len() + raw_message.len();
+
+        if !self.has_quota(&mut message).await {
+            return Err(QueueEvent::QuotaExceeded
+                .into_err()
+                .details("Queue quota exceeded.")
+                .ctx(trc::Key::To, list.to_string()));
+        }
+
+        let queue_id = message.queue_id;
+        if message
+            .queue(
+                (!headers.is_empty()).then_some(headers),
+                raw_message,
+                session_id,
+                self,
+                source,
+            )
+            .await
+        {
+            Ok(queue_id)
+        } else {
+            Err(trc::StoreEvent::UnexpectedError
+                .into_err()
+                .details("Failed to queue mailing list message.")
+                .ctx(trc::Key::To, list.to_string()))
+        }
+    }
+
+    async fn list_hold_digest(&self, list: &str, raw_message: &[u8]) -> trc::Result<()> {
+        // Reserve and write blob
+        let blob_hash = BlobHash::from(raw_message);
+        let reserve_until = now() + 120;
+        let mut batch = BatchBuilder::new();
+        batch.set(
+            BlobOp::Reserve {
+                hash: blob_hash.clone(),
+                until: reserve_until,
+            },
+            0u32.serialize(),
+        );
+        self.store()
+            .write(batch.build())
+            .await
+            .caused_by(trc::location!())?;
+        self.blob_store()
+            .put_blob(blob_hash.as_slice(), raw_message)
+            .await
+            .caused_by(trc::location!())?;
+
+        let created = now();
+        let message = DigestMessage {
+            id: self.inner.data.queue_id_gen.generate().unwrap_or(created),
+            list: list.to_string(),
+            blob_hash: blob_hash.clone(),
+            size: raw_message.len(),
+            created,
+        };
+        let mut batch = BatchBuilder::new();
+        batch
+            .clear(BlobOp::Reserve {
+                hash: blob_hash.clone(),
+                until: reserve_until,
+            })
+            .set(
+                BlobOp::LinkId {
+                    hash: blob_hash.clone(),
+                    id: message.id,
+                },
+                vec![],
+            )
+            .set(
+                BlobOp::Commit {
+                    hash: blob_hash.clone(),
+                },
+                vec![],
+            )
+            .set(
+                ValueClass::Queue(QueueClass::ListDigest(message.id)),
+                Bincode::new(message).serialize(),
+            );
+        self.store()
+            .write(batch.build())
+            .await
+            .caused_by(trc::location!())
+            .map(|_| ())
+    }
+}
+
+impl Message {
+    // Whether a recipient failed permanently since the last DSN was sent
+    pub fn has_new_permanent_failure(&self) -> bool {
+        self.recipients.iter().any(|rcpt| {
+            !rcpt.has_flag(RCPT_DSN_SENT | RCPT_NOTIFY_NEVER)
+                && match &rcpt.status {
+                    Status::PermanentFailure(_) => true,
+                    Status::Scheduled => matches!(
+                        self.domains[rcpt.domain_idx].status,
+                        Status::PermanentFailure(_)
+                    ),
+                    _ => false,
+                }
+        })
+    }
+}
+
+// Bounces for a member are sent to list-bounces+member=domain@listdomain
+pub fn verp_address(list: &str, member: &str) -> String {
+    let (list_local, list_domain) = list.rsplit_once('@').unwrap_or((list, ""));
+    let (member_local, member_domain) = member.rsplit_once('@').unwrap_or((member, ""));
+    format!("{list_local}-bounces+{member_local}={member_domain}@{list_domain}")
+}
+
+// Returns the list and member addresses encoded in a VERP address
+pub fn parse_verp(address: &str) -> Option<(String, String)> {
+    let (local_part, list_domain) = address.rsplit_once('@')?;
+    let (list_local, member) = local_part.split_once("-bounces+")?;
+    let (member_local, member_domain) = member.rsplit_once('=')?;
+
+    if !list_local.is_empty() && !member_local.is_empty() && !member_domain.is_empty() {
+        Some((
+            format!("{list_local}@{list_domain}"),
+            format!("{member_local}@{member_domain}"),
+        ))
+    } else {
+        None
+    }
+}
+
+fn list_headers(list: &str) -> String {
+    format!(
+        concat!(
+            "List-Id: <{list_id}>\r\n",
+            "List-Post: <mailto:{list}>\r\n",
+            "List-Unsubscribe: <mailto:{list}?subject=unsubscribe>\r\n"
+        ),
+        list_id = list.replace('@', "."),
+        list = list
+    )
+}
+
+fn build_digest(list: &str, messages: Vec<Vec<u8>>) -> Vec<u8> {
+    let subject = format!("{list} digest, {} messages", messages.len());
+
+    MessageBuilder::new()
+        .from(list)
+        .header("To", HeaderType::Text(list.into()))
+        .header(
+            "List-Id",
+            HeaderType::Text(format!("<{}>", list.replace('@', ".")).into()),
+        )
+        .header(
+            "List-Post",
+            HeaderType::Text(format!("<mailto:{list}>").into()),
+        )
+        .header(
+            "List-Unsubscribe",
+            HeaderType::Text(format!("<mailto:{list}?subject=unsubscribe>").into()),
+        )
+        .message_id(format!("<{}@{}>", make_boundary("."), list.domain_part()))
+        .subject(subject)
+        .body(MimePart::new(
+            ContentType::new("multipart/digest"),
+            BodyPart::Multipart(
+                messages
+                    .into_iter()
+                    .map(|message| {
+                        MimePart::new(
+                            ContentType::new("message/rfc822"),
+                            BodyPart::Binary(message.into()),
+                        )
+                    })
+                    .collect(),
+            ),
+        ))
+        .write_to_vec()
+        .unwrap_or_default()
+}
+
+fn bounce_key(list: &str, member: &str) -> Vec<u8> {
+    format!("lb:{list}:{member}").into_bytes()
+}
+
+fn digest_key(list: &str, member: &str) -> Vec<u8> {
+    format!("ld:{list}:{member}").into_bytes()
+}
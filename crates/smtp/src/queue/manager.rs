@@ -73,16 +73,48 @@ impl Queue {
 
         // Deliver scheduled messages
         let now = now();
+        let mut due_events = Vec::new();
         self.next_wake_up = LONG_WAIT;
         for queue_event in server.next_event().await {
             if queue_event.due <= now {
-                DeliveryAttempt::new(queue_event)
-                    .try_deliver(server.clone())
-                    .await;
+                due_events.push(queue_event);
             } else {
                 self.next_wake_up = Duration::from_secs(queue_event.due - now);
             }
         }
+
+        // Dispatch messages for the same destination together, so that
+        // deliveries to the same host can reuse pooled connections
+        if due_events.len() > 1 && !server.core.smtp.queue.connection.idle_timeout.is_empty() {
+            let mut grouped_events = Vec::with_capacity(due_events.len());
+            for queue_event in due_events {
+                let domain = server
+                    .read_message(queue_event.queue_id)
+                    .await
+                    .and_then(|message| {
+                        message
+                            .domains
+                            .into_iter()
+                            .find(|d| {
+                                matches!(d.status, Status::Scheduled | Status::TemporaryFailure(_))
+                            })
+                            .map(|d| d.domain)
+                    })
+                    .unwrap_or_default();
+                grouped_events.push((domain, queue_event));
+            }
+            grouped_events.sort_by(|a, b| a.0.cmp(&b.0));
+            due_events = grouped_events
+                .into_iter()
+                .map(|(_, queue_event)| queue_event)
+                .collect();
+        }
+
+        for queue_event in due_events {
+            DeliveryAttempt::new(queue_event)
+                .try_deliver(server.clone())
+                .await;
+        }
     }
 
     pub fn on_hold(&mut self, message: OnHold<QueueEventLock>) {
@@ -21,8 +21,11 @@ use store::write::now;
 use utils::BlobHash;
 
 pub mod dsn;
+pub mod list;
 pub mod manager;
+pub mod quarantine;
 pub mod quota;
+pub mod retry;
 pub mod spool;
 pub mod throttle;
 
@@ -61,6 +64,15 @@ pub struct Message {
 
     pub size: usize,
     pub quota_keys: Vec<QuotaKey>,
+    pub retry_state: Vec<RetryState>,
+
+    // FUTURERELEASE release time and DELIVERBY deadline, zero when not requested
+    pub hold_until: u64,
+    pub deliver_by: u64,
+
+    // Transport selected by the transport map for each domain, stored in the
+    // same order as the message domains; empty when routed by the next hop
+    pub transports: Vec<String>,
 
     #[serde(skip)]
     pub span_id: u64,
@@ -81,6 +93,14 @@ pub struct Domain {
     pub status: Status<(), Error>,
 }
 
+// Retry strategy that scheduled the next delivery attempt of a domain,
+// stored in the same order as the message domains
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetryState {
+    pub strategy: String,
+    pub attempt: u32,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Recipient {
     pub domain_idx: usize,
@@ -93,6 +113,7 @@ pub struct Recipient {
 
 pub const RCPT_DSN_SENT: u64 = 1 << 32;
 pub const RCPT_STATUS_CHANGED: u64 = 2 << 32;
+pub const RCPT_DSN_RELAYED: u64 = 4 << 32;
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Status<T, E> {
@@ -275,6 +296,7 @@ impl<'x> ResolveVariable for QueueEnvelope<'x> {
                 .into(),
             V_MX => self.mx.into(),
             V_PRIORITY => self.message.priority.into(),
+            V_SIZE => self.message.size.into(),
             V_REMOTE_IP => self.remote_ip.to_string().into(),
             V_LOCAL_IP => self.local_ip.to_string().into(),
             _ => "".into(),
@@ -294,6 +316,7 @@ impl ResolveVariable for Message {
                 .collect::<Vec<_>>()
                 .into(),
             V_PRIORITY => self.priority.into(),
+            V_SIZE => self.size.into(),
             _ => "".into(),
         }
     }
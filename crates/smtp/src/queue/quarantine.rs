@@ -0,0 +1,408 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use ahash::AHashMap;
+use common::{config::spamfilter::FilterAction, Server};
+use mail_parser::MessageParser;
+use serde::{Deserialize, Serialize};
+use store::{
+    write::{now, BatchBuilder, Bincode, BlobOp, QueueClass, ValueClass},
+    Deserialize as _, IterateParams, Serialize as _, ValueKey,
+};
+use trc::{AddContext, SpamEvent};
+use utils::BlobHash;
+
+use super::{quota::HasQueueQuota, spool::SmtpSpool, DomainPart, MessageSource, QueueId};
+
+// Messages quarantined for recipients that are not local accounts
+pub const GLOBAL_QUARANTINE_ID: u32 = u32::MAX;
+
+// Filtering stages able to quarantine a message, in the order they run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum QuarantineStage {
+    ClamAv,
+    Attachments,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuarantinedMessage {
+    pub id: u64,
+    pub account_id: u32,
+    pub stage: QuarantineStage,
+    pub reason: String,
+    pub details: Option<String>,
+    pub return_path: String,
+    pub env_id: Option<String>,
+    pub flags: u64,
+    pub recipients: Vec<QuarantineRecipient>,
+    pub blob_hash: BlobHash,
+    pub size: usize,
+    pub created: u64,
+    pub expires: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuarantineRecipient {
+    pub address: String,
+    pub flags: u64,
+    pub orcpt: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QuarantineRelease {
+    Queued(QueueId),
+    Quarantined,
+    Rejected(String),
+}
+
+pub trait SmtpQuarantine: Sync + Send {
+    fn quarantine_message(
+        &self,
+        message: QuarantinedMessage,
+        raw_message: &[u8],
+        session_id: u64,
+    ) -> impl Future<Output = trc::Result<Vec<QuarantinedMessage>>> + Send;
+
+    fn list_quarantined(
+        &self,
+        account_id: u32,
+    ) -> impl Future<Output = trc::Result<Vec<QuarantinedMessage>>> + Send;
+
+    fn read_quarantined(
+        &self,
+        account_id: u32,
+        id: u64,
+    ) -> impl Future<Output = trc::Result<Option<QuarantinedMessage>>> + Send;
+
+    fn release_quarantined(
+        &self,
+        message: QuarantinedMessage,
+    ) -> impl Future<Output = trc::Result<QuarantineRelease>> + Send;
+
+    fn delete_quarantined(
+        &self,
+        message: &QuarantinedMessage,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+
+    fn purge_quarantine(&self) -> impl Future<Output = trc::Result<()>> + Send;
+}
+
+impl SmtpQuarantine for Server {
+    async fn quarantine_message(
+        &self,
+        message: QuarantinedMessage,
+        raw_message: &[u8],
+        session_id: u64,
+    ) -> trc::Result<Vec<QuarantinedMessage>> {
+        // Recipients are split between the quarantine of each local account
+        // and the global quarantine
+        let mut accounts: AHashMap<u32, Vec<QuarantineRecipient>> = AHashMap::new();
+        for rcpt in message.recipients.iter() {
+            let account_id = self
+                .email_to_id(&self.core.storage.directory, &rcpt.address, session_id)
+                .await
+                .caused_by(trc::location!())?
+                .unwrap_or(GLOBAL_QUARANTINE_ID);
+            accounts.entry(account_id).or_default().push(rcpt.clone());
+        }
+
+        // Reserve and write blob
+        let blob_hash = BlobHash::from(raw_message);
+        let reserve_until = now() + 120;
+        let mut batch = BatchBuilder::new();
+        batch.set(
+            BlobOp::Reserve {
+                hash: blob_hash.clone(),
+                until: reserve_until,
+            },
+            0u32.serialize(),
+        );
+        self.store()
+            .write(batch.build())
+            .await
+            .caused_by(trc::location!())?;
+        self.blob_store()
+            .put_blob(blob_hash.as_slice(), raw_message)
+            .await
+            .caused_by(trc::location!())?;
+
+        // Write one entry per quarantine area, each one holding a link to the blob
+        let created = now();
+        let expires = created + self.core.spam.quarantine.retention.as_secs();
+        let mut entries = Vec::with_capacity(accounts.len());
+        let mut batch = BatchBuilder::new();
+        batch.clear(BlobOp::Reserve {
+            hash: blob_hash.clone(),
+            until: reserve_until,
+        });
+        for (account_id, recipients) in accounts {
+            let entry = QuarantinedMessage {
+                id: self.inner.data.queue_id_gen.generate().unwrap_or(created),
+                account_id,
+                recipients,
+                blob_hash: blob_hash.clone(),
+                size: raw_message.len(),
+                created,
+                expires,
+                ..message.clone()
+            };
+            batch
+                .set(
+                    BlobOp::LinkId {
+                        hash: blob_hash.clone(),
+                        id: entry.id,
+                    },
+                    vec![],
+                )
+                .set(
+                    ValueClass::Queue(QueueClass::Quarantine {
+                        account_id,
+                        id: entry.id,
+                    }),
+                    Bincode::new(entry.clone()).serialize(),
+                );
+            entries.push(entry);
+        }
+        batch.set(
+            BlobOp::Commit {
+                hash: blob_hash.clone(),
+            },
+            vec![],
+        );
+        self.store()
+            .write(batch.build())
+            .await
+            .caused_by(trc::location!())?;
+
+        for entry in &entries {
+            trc::event!(
+                Spam(SpamEvent::Quarantine),
+                SpanId = session_id,
+                AccountId = entry.account_id,
+                Id = entry.id,
+                Reason = entry.reason.clone(),
+                Details = entry.details.clone(),
+                From = entry.return_path.clone(),
+                To = entry
+                    .recipients
+                    .iter()
+                    .map(|r| trc::Value::String(r.address.clone()))
+                    .collect::<Vec<_>>(),
+                Expires = trc::Value::Timestamp(entry.expires),
+            );
+        }
+
+        Ok(entries)
+    }
+
+    async fn list_quarantined(&self, account_id: u32) -> trc::Result<Vec<QuarantinedMessage>> {
+        let from_key = ValueKey::from(ValueClass::Queue(QueueClass::Quarantine {
+            account_id,
+            id: 0,
+        }));
+        let to_key = ValueKey::from(ValueClass::Queue(QueueClass::Quarantine {
+            account_id,
+            id: u64::MAX,
+        }));
+        let mut messages = Vec::new();
+        self.store()
+            .iterate(
+                IterateParams::new(from_key, to_key).ascending(),
+                |_, value| {
+                    messages.push(Bincode::<QuarantinedMessage>::deserialize(value)?.inner);
+                    Ok(true)
+                },
+            )
+            .await
+            .caused_by(trc::location!())?;
+
+        Ok(messages)
+    }
+
+    async fn read_quarantined(
+        &self,
+        account_id: u32,
+        id: u64,
+    ) -> trc::Result<Option<QuarantinedMessage>> {
+        self.store()
+            .get_value::<Bincode<QuarantinedMessage>>(ValueKey::from(ValueClass::Queue(
+                QueueClass::Quarantine { account_id, id },
+            )))
+            .await
+            .map(|message| message.map(|message| message.inner))
+            .caused_by(trc::location!())
+    }
+
+    async fn release_quarantined(
+        &self,
+        quarantined: QuarantinedMessage,
+    ) -> trc::Result<QuarantineRelease> {
+        let raw_message = self
+            .blob_store()
+            .get_blob(quarantined.blob_hash.as_slice(), 0..usize::MAX)
+            .await
+            .caused_by(trc::location!())?
+            .ok_or_else(|| {
+                trc::StoreEvent::NotFound
+                    .into_err()
+                    .details("Quarantined message blob not found.")
+                    .caused_by(trc::location!())
+            })?;
+        let mut headers = Vec::new();
+
+        // Only the stages following the one that quarantined the message are run again,
+        // otherwise the message would be quarantined once more
+        if quarantined.stage < QuarantineStage::Attachments {
+            let config = &self.core.spam.attachments;
+            if let Some((action, types)) = MessageParser::default()
+                .parse(&raw_message)
+                .and_then(|message| config.inspect(&message).matching_policy(config))
+            {
+                let types = types.join(", ");
+                match action {
+                    FilterAction::Reject => {
+                        return Ok(QuarantineRelease::Rejected(format!(
+                            "Message contains a prohibited attachment ({types})."
+                        )));
+                    }
+                    FilterAction::Quarantine if self.core.spam.quarantine.enable => {
+                        self.quarantine_message(
+                            QuarantinedMessage {
+                                stage: QuarantineStage::Attachments,
+                                reason: "attachment".to_string(),
+                                details: types.into(),
+                                ..quarantined.clone()
+                            },
+                            &raw_message,
+                            0,
+                        )
+                        .await?;
+                        self.delete_quarantined(&quarantined).await?;
+                        return Ok(QuarantineRelease::Quarantined);
+                    }
+                    FilterAction::Quarantine => {
+                        headers.extend_from_slice(b"X-Quarantine: true\r\n");
+                        headers.extend_from_slice(b"X-Attachment-Warning: ");
+                        headers.extend_from_slice(types.as_bytes());
+                        headers.extend_from_slice(b"\r\n");
+                    }
+                    FilterAction::Tag => {
+                        headers.extend_from_slice(b"X-Attachment-Warning: ");
+                        headers.extend_from_slice(types.as_bytes());
+                        headers.extend_from_slice(b"\r\n");
+                    }
+                }
+            }
+        }
+
+        // Rebuild the message using the original envelope
+        let return_path_lcase = quarantined.return_path.to_lowercase();
+        let return_path_domain = return_path_lcase.domain_part().to_string();
+        let mut message = self.new_message(
+            quarantined.return_path.clone(),
+            return_path_lcase,
+            return_path_domain,
+            quarantined.id,
+        );
+        message.flags = quarantined.flags;
+        message.env_id = quarantined.env_id.clone();
+        for rcpt in &quarantined.recipients {
+            message.add_recipient(rcpt.address.clone(), self).await;
+            let recipient = message.recipients.last_mut().unwrap();
+            recipient.flags = rcpt.flags;
+            recipient.orcpt = rcpt.orcpt.clone();
+        }
+        message.size = raw_message.len() + headers.len();
+
+        if !self.has_quota(&mut message).await {
+            return Err(trc::SpamEvent::QuarantineError
+                .into_err()
+                .details("Queue quota exceeded.")
+                .id(quarantined.id));
+        }
+
+        let queue_id = message.queue_id;
+        if message
+            .queue(
+                (!headers.is_empty()).then_some(headers.as_slice()),
+                &raw_message,
+                quarantined.id,
+                self,
+                MessageSource::Autogenerated,
+            )
+            .await
+        {
+            self.delete_quarantined(&quarantined).await?;
+
+            trc::event!(
+                Spam(SpamEvent::QuarantineRelease),
+                AccountId = quarantined.account_id,
+                Id = quarantined.id,
+                QueueId = queue_id,
+            );
+
+            Ok(QuarantineRelease::Queued(queue_id))
+        } else {
+            Err(trc::SpamEvent::QuarantineError
+                .into_err()
+                .details("Failed to queue released message.")
+                .id(quarantined.id))
+        }
+    }
+
+    async fn delete_quarantined(&self, message: &QuarantinedMessage) -> trc::Result<()> {
+        let mut batch = BatchBuilder::new();
+        batch
+            .clear(BlobOp::LinkId {
+                hash: message.blob_hash.clone(),
+                id: message.id,
+            })
+            .clear(ValueClass::Queue(QueueClass::Quarantine {
+                account_id: message.account_id,
+                id: message.id,
+            }));
+        self.store()
+            .write(batch.build())
+            .await
+            .caused_by(trc::location!())
+            .map(|_| ())
+    }
+
+    async fn purge_quarantine(&self) -> trc::Result<()> {
+        let from_key = ValueKey::from(ValueClass::Queue(QueueClass::Quarantine {
+            account_id: 0,
+            id: 0,
+        }));
+        let to_key = ValueKey::from(ValueClass::Queue(QueueClass::Quarantine {
+            account_id: u32::MAX,
+            id: u64::MAX,
+        }));
+        let now = now();
+        let mut expired = Vec::new();
+        self.store()
+            .iterate(
+                IterateParams::new(from_key, to_key).ascending(),
+                |_, value| {
+                    let message = Bincode::<QuarantinedMessage>::deserialize(value)?.inner;
+                    if message.expires <= now {
+                        expired.push(message);
+                    }
+                    Ok(true)
+                },
+            )
+            .await
+            .caused_by(trc::location!())?;
+
+        // Unlinked blobs are removed by the next blob store purge
+        for message in expired {
+            self.delete_quarantined(&message).await?;
+        }
+
+        Ok(())
+    }
+}
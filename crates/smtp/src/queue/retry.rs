@@ -0,0 +1,363 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{future::Future, time::Duration};
+
+use common::{
+    config::smtp::queue::{RetrySchedule, RetryStrategy},
+    ipc::QueueEvent,
+    Server,
+};
+use rand::Rng;
+use store::write::{now, Bincode};
+use utils::BlobHash;
+
+use super::{
+    spool::SmtpSpool, Domain, Error, Message, QueueEnvelope, QueueId, QuotaKey, Recipient,
+    RetryState, Status,
+};
+
+const DEFAULT_RETRY: Duration = Duration::from_secs(60);
+
+pub struct RetryPolicy {
+    // Name of the retry strategy, empty for inline schedules
+    pub id: String,
+    pub strategy: RetryStrategy,
+}
+
+pub trait RetryScheduler: Sync + Send {
+    fn eval_retry_policy(
+        &self,
+        envelope: &QueueEnvelope<'_>,
+        session_id: u64,
+    ) -> impl Future<Output = RetryPolicy> + Send;
+
+    fn retry_deferred(
+        &self,
+        hostname: String,
+        queue_ids: Vec<QueueId>,
+    ) -> impl Future<Output = ()> + Send;
+}
+
+impl RetryScheduler for Server {
+    async fn eval_retry_policy(
+        &self,
+        envelope: &QueueEnvelope<'_>,
+        session_id: u64,
+    ) -> RetryPolicy {
+        match self
+            .eval_if::<RetrySchedule, _>(&self.core.smtp.queue.retry, envelope, session_id)
+            .await
+        {
+            Some(RetrySchedule::Intervals(intervals)) => RetryPolicy {
+                id: String::new(),
+                strategy: RetryStrategy::Fixed(intervals),
+            },
+            Some(RetrySchedule::Strategy(id)) => {
+                if let Some(strategy) = self.get_retry_strategy(&id, session_id) {
+                    RetryPolicy {
+                        strategy: strategy.clone(),
+                        id,
+                    }
+                } else {
+                    RetryPolicy::default()
+                }
+            }
+            None => RetryPolicy::default(),
+        }
+    }
+
+    async fn retry_deferred(&self, hostname: String, queue_ids: Vec<QueueId>) {
+        let mut has_changes = false;
+
+        for queue_id in queue_ids {
+            // Messages that are being delivered at the moment can't be locked
+            let Some(event) = self.try_lock_message(queue_id).await else {
+                continue;
+            };
+            let Some(mut message) = self.read_message(queue_id).await else {
+                self.unlock_event(event).await;
+                continue;
+            };
+
+            let due = now();
+            let mut found = false;
+            for domain in &mut message.domains {
+                if matches!(&domain.status, Status::TemporaryFailure(err) if err.failed_host() == Some(hostname.as_str()))
+                    && domain.retry.due > due
+                {
+                    domain.retry.due = due;
+                    found = true;
+                }
+            }
+
+            if found {
+                trc::event!(
+                    Queue(trc::QueueEvent::Rescheduled),
+                    SpanId = queue_id,
+                    Hostname = hostname.clone(),
+                    NextRetry = trc::Value::Timestamp(due),
+                );
+
+                let next_event = message.next_event().unwrap_or_default();
+                has_changes |= message
+                    .save_changes(self, event.due.into(), next_event.into())
+                    .await;
+            } else {
+                self.unlock_event(event).await;
+            }
+        }
+
+        if has_changes {
+            let _ = self.inner.ipc.queue_tx.send(QueueEvent::Reload).await;
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn delay(&self, attempt: u32) -> Duration {
+        match &self.strategy {
+            RetryStrategy::Fixed(intervals) => intervals
+                .get(attempt as usize)
+                .or_else(|| intervals.last())
+                .copied()
+                .unwrap_or(DEFAULT_RETRY),
+            RetryStrategy::Exponential {
+                initial,
+                factor,
+                max,
+                jitter,
+            } => {
+                let max = max.as_secs_f64();
+                let mut delay =
+                    (initial.as_secs_f64() * factor.powi(attempt.min(64) as i32)).min(max);
+                if *jitter > 0.0 {
+                    delay *= 1.0 + rand::thread_rng().gen_range(-*jitter..=*jitter);
+                }
+                Duration::from_secs_f64(delay.clamp(1.0, max.max(1.0)))
+            }
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            id: String::new(),
+            strategy: RetryStrategy::Fixed(vec![DEFAULT_RETRY]),
+        }
+    }
+}
+
+impl Message {
+    pub fn set_status(
+        &mut self,
+        domain_idx: usize,
+        status: impl Into<Status<(), Error>>,
+        policy: &RetryPolicy,
+    ) {
+        let domain = &mut self.domains[domain_idx];
+        domain.status = status.into();
+        trc::event!(
+            Delivery(match &domain.status {
+                Status::Completed(_) => trc::DeliveryEvent::DomainDelivered,
+                Status::PermanentFailure(_) => trc::DeliveryEvent::DomainBounced,
+                Status::TemporaryFailure(_) | Status::Scheduled => {
+                    trc::DeliveryEvent::DomainDeferred
+                }
+            }),
+            SpanId = self.span_id,
+            Domain = domain.domain.clone(),
+        );
+
+        if matches!(
+            &domain.status,
+            Status::TemporaryFailure(_) | Status::Scheduled
+        ) {
+            self.retry(domain_idx, policy);
+        }
+    }
+
+    pub fn retry(&mut self, domain_idx: usize, policy: &RetryPolicy) {
+        // Domains without a stored state were scheduled using their retry count
+        while self.retry_state.len() < self.domains.len() {
+            self.retry_state.push(RetryState {
+                strategy: String::new(),
+                attempt: self.domains[self.retry_state.len()].retry.inner,
+            });
+        }
+
+        // Start over when the domain is now handled by a different strategy
+        let state = &mut self.retry_state[domain_idx];
+        if state.strategy != policy.id {
+            state.strategy = policy.id.clone();
+            state.attempt = 0;
+        }
+
+        let domain = &mut self.domains[domain_idx];
+        domain.retry.due = now() + policy.delay(state.attempt).as_secs();
+        domain.retry.inner += 1;
+        state.attempt += 1;
+    }
+}
+
+impl Error {
+    // Returns the host that caused a failure that might be resolved by retrying the host
+    pub fn failed_host(&self) -> Option<&str> {
+        match self {
+            Error::ConnectionError(details) => Some(details.entity.as_str()),
+            Error::UnexpectedResponse(response) => Some(response.hostname.entity.as_str()),
+            _ => None,
+        }
+    }
+}
+
+impl store::Deserialize for Message {
+    fn deserialize(bytes: &[u8]) -> trc::Result<Self> {
+        match Bincode::<Message>::deserialize(bytes) {
+            Ok(message) => Ok(message.inner),
+            Err(err) => Bincode::<LegacyMessageV3>::deserialize(bytes)
+                .map(|message| message.inner.into())
+                .or_else(|_| {
+                    Bincode::<LegacyMessageV2>::deserialize(bytes)
+                        .map(|message| LegacyMessageV3::from(message.inner).into())
+                })
+                .or_else(|_| {
+                    Bincode::<LegacyMessage>::deserialize(bytes).map(|message| {
+                        LegacyMessageV3::from(LegacyMessageV2::from(message.inner)).into()
+                    })
+                })
+                .map_err(|_| err),
+        }
+    }
+}
+
+// Messages queued before retry states were stored
+#[derive(serde::Serialize, serde::Deserialize)]
+struct LegacyMessage {
+    queue_id: QueueId,
+    created: u64,
+    blob_hash: BlobHash,
+    return_path: String,
+    return_path_lcase: String,
+    return_path_domain: String,
+    recipients: Vec<Recipient>,
+    domains: Vec<Domain>,
+    flags: u64,
+    env_id: Option<String>,
+    priority: i16,
+    size: usize,
+    quota_keys: Vec<QuotaKey>,
+}
+
+// Messages queued before release times and delivery deadlines were stored
+#[derive(serde::Serialize, serde::Deserialize)]
+struct LegacyMessageV2 {
+    queue_id: QueueId,
+    created: u64,
+    blob_hash: BlobHash,
+    return_path: String,
+    return_path_lcase: String,
+    return_path_domain: String,
+    recipients: Vec<Recipient>,
+    domains: Vec<Domain>,
+    flags: u64,
+    env_id: Option<String>,
+    priority: i16,
+    size: usize,
+    quota_keys: Vec<QuotaKey>,
+    retry_state: Vec<RetryState>,
+}
+
+// Messages queued before transports were stored
+#[derive(serde::Serialize, serde::Deserialize)]
+struct LegacyMessageV3 {
+    queue_id: QueueId,
+    created: u64,
+    blob_hash: BlobHash,
+    return_path: String,
+    return_path_lcase: String,
+    return_path_domain: String,
+    recipients: Vec<Recipient>,
+    domains: Vec<Domain>,
+    flags: u64,
+    env_id: Option<String>,
+    priority: i16,
+    size: usize,
+    quota_keys: Vec<QuotaKey>,
+    retry_state: Vec<RetryState>,
+    hold_until: u64,
+    deliver_by: u64,
+}
+
+impl From<LegacyMessage> for LegacyMessageV2 {
+    fn from(message: LegacyMessage) -> Self {
+        LegacyMessageV2 {
+            queue_id: message.queue_id,
+            created: message.created,
+            blob_hash: message.blob_hash,
+            return_path: message.return_path,
+            return_path_lcase: message.return_path_lcase,
+            return_path_domain: message.return_path_domain,
+            recipients: message.recipients,
+            domains: message.domains,
+            flags: message.flags,
+            env_id: message.env_id,
+            priority: message.priority,
+            size: message.size,
+            quota_keys: message.quota_keys,
+            retry_state: Vec::new(),
+        }
+    }
+}
+
+impl From<LegacyMessageV2> for LegacyMessageV3 {
+    fn from(message: LegacyMessageV2) -> Self {
+        LegacyMessageV3 {
+            queue_id: message.queue_id,
+            created: message.created,
+            blob_hash: message.blob_hash,
+            return_path: message.return_path,
+            return_path_lcase: message.return_path_lcase,
+            return_path_domain: message.return_path_domain,
+            recipients: message.recipients,
+            domains: message.domains,
+            flags: message.flags,
+            env_id: message.env_id,
+            priority: message.priority,
+            size: message.size,
+            quota_keys: message.quota_keys,
+            retry_state: message.retry_state,
+            hold_until: 0,
+            deliver_by: 0,
+        }
+    }
+}
+
+impl From<LegacyMessageV3> for Message {
+    fn from(message: LegacyMessageV3) -> Self {
+        Message {
+            queue_id: message.queue_id,
+            created: message.created,
+            blob_hash: message.blob_hash,
+            return_path: message.return_path,
+            return_path_lcase: message.return_path_lcase,
+            return_path_domain: message.return_path_domain,
+            recipients: message.recipients,
+            domains: message.domains,
+            flags: message.flags,
+            env_id: message.env_id,
+            priority: message.priority,
+            size: message.size,
+            quota_keys: message.quota_keys,
+            retry_state: message.retry_state,
+            hold_until: message.hold_until,
+            deliver_by: message.deliver_by,
+            transports: Vec::new(),
+            span_id: 0,
+        }
+    }
+}
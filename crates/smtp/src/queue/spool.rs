@@ -39,6 +39,13 @@ pub trait SmtpSpool: Sync + Send {
     ) -> impl Future<Output = Option<QueueEventLock>> + Send;
 
     fn read_message(&self, id: QueueId) -> impl Future<Output = Option<Message>> + Send;
+
+    fn try_lock_message(
+        &self,
+        queue_id: QueueId,
+    ) -> impl Future<Output = Option<QueueEventLock>> + Send;
+
+    fn unlock_event(&self, event: QueueEventLock) -> impl Future<Output = bool> + Send;
 }
 
 impl SmtpSpool for Server {
@@ -67,6 +74,10 @@ impl SmtpSpool for Server {
             size: 0,
             blob_hash: Default::default(),
             quota_keys: Vec::new(),
+            retry_state: Vec::new(),
+            hold_until: 0,
+            deliver_by: 0,
+            transports: Vec::new(),
         }
     }
 
@@ -162,12 +173,10 @@ impl SmtpSpool for Server {
     async fn read_message(&self, id: QueueId) -> Option<Message> {
         match self
             .store()
-            .get_value::<Bincode<Message>>(ValueKey::from(ValueClass::Queue(QueueClass::Message(
-                id,
-            ))))
+            .get_value::<Message>(ValueKey::from(ValueClass::Queue(QueueClass::Message(id))))
             .await
         {
-            Ok(Some(message)) => Some(message.inner),
+            Ok(Some(message)) => Some(message),
             Ok(None) => None,
             Err(err) => {
                 trc::error!(err
@@ -178,6 +187,91 @@ impl SmtpSpool for Server {
             }
         }
     }
+
+    async fn try_lock_message(&self, queue_id: QueueId) -> Option<QueueEventLock> {
+        // Queue events are indexed by due time, which can not be derived from the
+        // message alone, so look up the event by scanning the index.
+        let from_key = ValueKey::from(ValueClass::Queue(QueueClass::MessageEvent(
+            store::write::QueueEvent {
+                due: 0,
+                queue_id: 0,
+            },
+        )));
+        let to_key = ValueKey::from(ValueClass::Queue(QueueClass::MessageEvent(
+            store::write::QueueEvent {
+                due: u64::MAX,
+                queue_id: u64::MAX,
+            },
+        )));
+
+        let mut event = None;
+        let result = self
+            .store()
+            .iterate(
+                IterateParams::new(from_key, to_key).ascending(),
+                |key, value| {
+                    if key.deserialize_be_u64(U64_LEN)? == queue_id {
+                        event = Some(QueueEventLock {
+                            due: key.deserialize_be_u64(0)?,
+                            queue_id,
+                            lock_expiry: u64::deserialize(value)?,
+                        });
+                        Ok(false)
+                    } else {
+                        Ok(true)
+                    }
+                },
+            )
+            .await;
+
+        if let Err(err) = result {
+            trc::error!(err
+                .details("Failed to read queue.")
+                .caused_by(trc::location!()));
+            return None;
+        }
+
+        let event = event?;
+        if event.lock_expiry < now() {
+            self.try_lock_event(event).await
+        } else {
+            trc::event!(
+                Queue(trc::QueueEvent::Locked),
+                SpanId = event.queue_id,
+                Due = trc::Value::Timestamp(event.due),
+                Expires = trc::Value::Timestamp(event.lock_expiry),
+            );
+
+            None
+        }
+    }
+
+    async fn unlock_event(&self, event: QueueEventLock) -> bool {
+        let mut batch = BatchBuilder::new();
+        batch.assert_value(
+            ValueClass::Queue(QueueClass::MessageEvent(store::write::QueueEvent {
+                due: event.due,
+                queue_id: event.queue_id,
+            })),
+            event.lock_expiry,
+        );
+        batch.set(
+            ValueClass::Queue(QueueClass::MessageEvent(store::write::QueueEvent {
+                due: event.due,
+                queue_id: event.queue_id,
+            })),
+            0u64.serialize(),
+        );
+        match self.store().write(batch.build()).await {
+            Ok(_) => true,
+            Err(err) => {
+                trc::error!(err
+                    .details("Failed to unlock event.")
+                    .caused_by(trc::location!()));
+                false
+            }
+        }
+    }
 }
 
 impl Message {
@@ -200,6 +294,9 @@ impl Message {
         };
         self.blob_hash = BlobHash::from(message.as_ref());
 
+        // Route recipients through the transport map
+        self.assign_transports(server).await;
+
         // Generate id
         if self.size == 0 {
             self.size = message.len();
@@ -280,6 +377,7 @@ impl Message {
             }
         }
         batch
+            .with_span_id(session_id)
             .set(
                 ValueClass::Queue(QueueClass::MessageEvent(store::write::QueueEvent {
                     due: self.next_event().unwrap_or_default(),
@@ -394,6 +492,58 @@ impl Message {
             .await;
     }
 
+    pub async fn assign_transports(&mut self, server: &Server) {
+        let transport_map = &server.core.smtp.queue.transport;
+        if transport_map.is_empty() || !self.transports.is_empty() {
+            return;
+        }
+
+        // Evaluate the transport map rules for each recipient
+        let mut rcpt_transports = Vec::with_capacity(self.recipients.len());
+        for (rcpt_idx, rcpt) in self.recipients.iter().enumerate() {
+            let transport = server
+                .eval_if::<String, _>(
+                    transport_map,
+                    &QueueEnvelope::new_rcpt(self, rcpt.domain_idx, rcpt_idx),
+                    self.span_id,
+                )
+                .await
+                .filter(|name| server.get_relay_host(name, self.span_id).is_some())
+                .unwrap_or_default();
+            rcpt_transports.push(transport);
+        }
+        if rcpt_transports.iter().all(|transport| transport.is_empty()) {
+            return;
+        }
+
+        // Split domains so that each one is delivered through a single transport
+        let mut keys: Vec<(usize, String)> = Vec::with_capacity(self.domains.len());
+        let mut domains = Vec::with_capacity(self.domains.len());
+        for (rcpt, transport) in self.recipients.iter_mut().zip(rcpt_transports) {
+            let key = (rcpt.domain_idx, transport);
+            rcpt.domain_idx = if let Some(idx) = keys.iter().position(|k| k == &key) {
+                idx
+            } else {
+                domains.push(self.domains[key.0].clone());
+                keys.push(key);
+                keys.len() - 1
+            };
+        }
+        for (key, domain) in keys.iter().zip(&domains) {
+            if !key.1.is_empty() {
+                trc::event!(
+                    Queue(trc::QueueEvent::TransportSelected),
+                    SpanId = self.span_id,
+                    Domain = domain.domain.clone(),
+                    Id = key.1.clone(),
+                );
+            }
+        }
+        self.domains = domains;
+        self.transports = keys.into_iter().map(|(_, transport)| transport).collect();
+        self.retry_state.clear();
+    }
+
     pub async fn save_changes(
         mut self,
         server: &Server,
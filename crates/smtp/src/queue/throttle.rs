@@ -56,7 +56,7 @@ impl IsAllowed for Server {
                     .core
                     .storage
                     .lookup
-                    .is_rate_allowed(key.as_ref(), rate, false)
+                    .check_rate(key.as_ref(), rate, throttle.window, false)
                     .await
                 {
                     trc::event!(
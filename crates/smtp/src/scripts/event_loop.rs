@@ -6,7 +6,11 @@
 
 use std::{borrow::Cow, future::Future, sync::Arc, time::Instant};
 
-use common::{scripts::plugins::PluginContext, Server};
+use common::{
+    ipc::{DeliveryEvent, DeliveryResult, FileIntoMessage},
+    scripts::plugins::PluginContext,
+    Server,
+};
 use mail_auth::common::headers::HeaderWriter;
 use sieve::{
     compiler::grammar::actions::action_redirect::{ByMode, ByTime, Notify, NotifyItem, Ret},
@@ -16,14 +20,15 @@ use smtp_proto::{
     MAIL_BY_TRACE, MAIL_RET_FULL, MAIL_RET_HDRS, RCPT_NOTIFY_DELAY, RCPT_NOTIFY_FAILURE,
     RCPT_NOTIFY_NEVER, RCPT_NOTIFY_SUCCESS,
 };
-use trc::SieveEvent;
+use tokio::sync::oneshot;
+use trc::{ServerEvent, SieveEvent};
 
 use crate::{
     inbound::DkimSign,
     queue::{quota::HasQueueQuota, spool::SmtpSpool, DomainPart, MessageSource},
 };
 
-use super::{ScriptModification, ScriptParameters, ScriptResult};
+use super::{ScriptKind, ScriptModification, ScriptParameters, ScriptResult};
 
 pub trait RunScript: Sync + Send {
     fn run_script(
@@ -43,10 +48,12 @@ impl RunScript for Server {
     ) -> ScriptResult {
         // Create filter instance
         let time = Instant::now();
-        let mut instance = self
-            .core
-            .sieve
-            .trusted_runtime
+        let runtime = match params.kind {
+            ScriptKind::Trusted => &self.core.sieve.trusted_runtime,
+            ScriptKind::Outbound { .. } => &self.core.sieve.outbound_runtime,
+            ScriptKind::OutboundUser { .. } => &self.core.sieve.outbound_user_runtime,
+        };
+        let mut instance = runtime
             .filter(params.message.unwrap_or_default())
             .with_vars_env(params.variables)
             .with_envelope_list(params.envelope)
@@ -65,7 +72,14 @@ impl RunScript for Server {
             match result {
                 Ok(event) => match event {
                     Event::IncludeScript { name, optional } => {
-                        if let Some(script) = self.core.sieve.trusted_scripts.get(name.as_str()) {
+                        // User scripts cannot include trusted scripts
+                        if let Some(script) = self
+                            .core
+                            .sieve
+                            .trusted_scripts
+                            .get(name.as_str())
+                            .filter(|_| !matches!(params.kind, ScriptKind::OutboundUser { .. }))
+                        {
                             input = Input::script(name, script.clone());
                         } else if optional {
                             input = false.into();
@@ -329,6 +343,69 @@ impl RunScript for Server {
                         messages.push(message);
                         input = true.into();
                     }
+                    Event::FileInto {
+                        folder,
+                        flags,
+                        special_use,
+                        create,
+                        message_id,
+                        ..
+                    } if params.kind.outbound_account_id().is_some() => {
+                        // Copies are always filed into the sender's account
+                        let raw_message = if message_id == 0 {
+                            instance.message().raw_message().to_vec()
+                        } else {
+                            messages.get(message_id - 1).cloned().unwrap_or_default()
+                        };
+                        let (result_tx, result_rx) = oneshot::channel();
+                        let result = match self
+                            .inner
+                            .ipc
+                            .delivery_tx
+                            .send(DeliveryEvent::FileInto {
+                                message: FileIntoMessage {
+                                    account_id: params.kind.outbound_account_id().unwrap(),
+                                    mailbox: folder,
+                                    special_use,
+                                    create,
+                                    flags,
+                                    raw_message,
+                                    session_id,
+                                },
+                                result_tx,
+                            })
+                            .await
+                        {
+                            Ok(_) => result_rx.await.ok(),
+                            Err(_) => None,
+                        };
+
+                        match result {
+                            Some(DeliveryResult::Success) => (),
+                            Some(
+                                DeliveryResult::TemporaryFailure { reason, .. }
+                                | DeliveryResult::PermanentFailure { reason, .. },
+                            ) => {
+                                trc::event!(
+                                    Sieve(SieveEvent::UnexpectedError),
+                                    Id = script_id.clone(),
+                                    SpanId = session_id,
+                                    Details = "Failed to file message copy.",
+                                    Reason = reason.into_owned(),
+                                );
+                            }
+                            None => {
+                                trc::event!(
+                                    Server(ServerEvent::ThreadError),
+                                    CausedBy = trc::location!(),
+                                    SpanId = session_id,
+                                    Reason = "Delivery channel closed",
+                                );
+                            }
+                        }
+
+                        input = true.into();
+                    }
                     Event::SetEnvelope { envelope, value } => {
                         modifications.push(ScriptModification::SetEnvelope {
                             name: envelope,
@@ -417,8 +494,11 @@ impl RunScript for Server {
                 && matches!(reject_bytes.next(), Some(ch) if ch.is_ascii_digit())
                 && matches!(reject_bytes.next(), Some(ch) if ch.is_ascii_digit())
                 && matches!(reject_bytes.next(), Some(ch) if ch == &b' ' )
+                && (params.kind == ScriptKind::Trusted || reject_reason.starts_with('5'))
             {
                 ScriptResult::Reject(reject_reason)
+            } else if params.kind.outbound_account_id().is_some() {
+                ScriptResult::Reject(format!("550 5.7.1 {reject_reason}"))
             } else {
                 ScriptResult::Reject(format!("503 5.5.3 {reject_reason}"))
             }
@@ -6,7 +6,7 @@
 
 use std::{sync::Arc, time::SystemTime};
 
-use common::listener::SessionStream;
+use common::{listener::SessionStream, scripts::ScriptModification};
 use mail_auth::common::resolver::ToReverseName;
 use sieve::{runtime::Variable, Envelope, Sieve};
 use smtp_proto::*;
@@ -138,4 +138,26 @@ impl<T: SessionStream> Session<T> {
             )
             .await
     }
+
+    pub fn apply_script_modifications(
+        &mut self,
+        modifications: Vec<ScriptModification>,
+        headers: &mut Vec<u8>,
+    ) {
+        for modification in modifications {
+            match modification {
+                ScriptModification::AddHeader { name, value } => {
+                    headers.extend_from_slice(name.as_bytes());
+                    headers.extend_from_slice(b": ");
+                    headers.extend_from_slice(value.as_bytes());
+                    if !value.ends_with('\n') {
+                        headers.extend_from_slice(b"\r\n");
+                    }
+                }
+                ScriptModification::SetEnvelope { name, value } => {
+                    self.data.apply_envelope_modification(name, value);
+                }
+            }
+        }
+    }
 }
@@ -15,6 +15,7 @@ use sieve::{runtime::Variable, Envelope};
 pub mod envelope;
 pub mod event_loop;
 pub mod exec;
+pub mod outbound;
 
 #[derive(Debug, serde::Serialize)]
 pub enum ScriptResult {
@@ -29,6 +30,31 @@ pub enum ScriptResult {
     Discard,
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ScriptKind {
+    #[default]
+    Trusted,
+    // Domain-level script evaluated on submission
+    Outbound {
+        account_id: u32,
+    },
+    // Script stored in the sender's outbound slot
+    OutboundUser {
+        account_id: u32,
+    },
+}
+
+impl ScriptKind {
+    pub fn outbound_account_id(&self) -> Option<u32> {
+        match self {
+            ScriptKind::Trusted => None,
+            ScriptKind::Outbound { account_id } | ScriptKind::OutboundUser { account_id } => {
+                Some(*account_id)
+            }
+        }
+    }
+}
+
 pub struct ScriptParameters<'x> {
     message: Option<&'x [u8]>,
     headers: Option<&'x [u8]>,
@@ -41,6 +67,7 @@ pub struct ScriptParameters<'x> {
     #[cfg(feature = "test_mode")]
     expected_variables: Option<AHashMap<String, Variable>>,
     access_token: Option<&'x AccessToken>,
+    kind: ScriptKind,
     session_id: u64,
 }
 
@@ -58,6 +85,7 @@ impl<'x> ScriptParameters<'x> {
             return_path: Default::default(),
             sign: Default::default(),
             access_token: None,
+            kind: ScriptKind::Trusted,
             session_id: Default::default(),
         }
     }
@@ -119,6 +147,11 @@ impl<'x> ScriptParameters<'x> {
         self
     }
 
+    pub fn with_kind(mut self, kind: ScriptKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
     pub fn with_session_id(mut self, session_id: u64) -> Self {
         self.session_id = session_id;
         self
@@ -0,0 +1,112 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::borrow::Cow;
+
+use common::{ipc::DeliveryEvent, listener::SessionStream};
+use tokio::sync::oneshot;
+use trc::ServerEvent;
+
+use crate::core::Session;
+
+use super::{ScriptKind, ScriptResult};
+
+impl<T: SessionStream> Session<T> {
+    pub async fn run_outbound_scripts(
+        &mut self,
+        message: &[u8],
+        headers: &mut Vec<u8>,
+    ) -> Result<Option<Vec<u8>>, Cow<'static, [u8]>> {
+        let Some(access_token) = self.data.authenticated_as.clone() else {
+            return Ok(None);
+        };
+        let account_id = access_token.primary_id();
+        let dc = &self.server.core.smtp.session.data;
+        let mut scripts = Vec::with_capacity(2);
+
+        // The sender's own script runs first
+        if self
+            .server
+            .eval_if(&dc.outbound_user_script, self, self.data.session_id)
+            .await
+            .unwrap_or(false)
+        {
+            let (result_tx, result_rx) = oneshot::channel();
+            if self
+                .server
+                .inner
+                .ipc
+                .delivery_tx
+                .send(DeliveryEvent::OutboundScript {
+                    account_id,
+                    result_tx,
+                })
+                .await
+                .is_ok()
+            {
+                if let Ok(Some(script)) = result_rx.await {
+                    scripts.push((
+                        script,
+                        format!("outbound.{account_id}"),
+                        ScriptKind::OutboundUser { account_id },
+                    ));
+                }
+            } else {
+                trc::event!(
+                    Server(ServerEvent::ThreadError),
+                    CausedBy = trc::location!(),
+                    SpanId = self.data.session_id,
+                    Reason = "TX channel closed",
+                );
+            }
+        }
+
+        // Domain-level scripts have the final say
+        if let Some((script, script_id)) = self
+            .server
+            .eval_if::<String, _>(&dc.outbound_script, self, self.data.session_id)
+            .await
+            .and_then(|name| {
+                self.server
+                    .get_trusted_sieve_script(&name, self.data.session_id)
+                    .map(|s| (s.clone(), name))
+            })
+        {
+            scripts.push((script, script_id, ScriptKind::Outbound { account_id }));
+        }
+
+        let mut edited_message: Option<Vec<u8>> = None;
+        for (script, script_id, kind) in scripts {
+            let params = self
+                .build_script_parameters("data")
+                .with_message(edited_message.as_deref().unwrap_or(message))
+                .with_auth_headers(headers.as_slice())
+                .with_access_token(&access_token)
+                .with_kind(kind);
+
+            let modifications = match self.run_script(script_id, script, params).await {
+                ScriptResult::Accept { modifications } => modifications,
+                ScriptResult::Replace {
+                    message,
+                    modifications,
+                } => {
+                    edited_message = message.into();
+                    modifications
+                }
+                ScriptResult::Reject(message) => {
+                    return Err(message.into_bytes().into());
+                }
+                ScriptResult::Discard => {
+                    return Err((b"250 2.0.0 Message queued for delivery.\r\n"[..]).into());
+                }
+            };
+
+            self.apply_script_modifications(modifications, headers);
+        }
+
+        Ok(edited_message)
+    }
+}
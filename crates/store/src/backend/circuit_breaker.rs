@@ -0,0 +1,410 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{
+    future::Future,
+    ops::Range,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use ahash::AHashMap;
+use parking_lot::Mutex;
+use roaring::RoaringBitmap;
+use trc::{EventType, StoreEvent};
+use utils::config::{utils::AsKey, Config};
+
+use crate::{
+    write::{AssignedIds, Batch, BitmapClass, ValueClass},
+    BitmapKey, Deserialize, IterateParams, Key, QueryResult, StorageStats, Store, Value, ValueKey,
+};
+
+// Stops sending writes to a backend after repeated failures. Once the open
+// interval elapses a single probe write is let through, which closes the
+// circuit on success or opens it again on failure. Reads are never rejected.
+pub struct StoreCircuitBreaker {
+    store: Store,
+    breaker: CircuitBreaker,
+}
+
+pub struct CircuitBreaker {
+    error_threshold: u32,
+    open_duration: Duration,
+    state: Mutex<BreakerState>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+impl CircuitState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CircuitState::Closed => "closed",
+            CircuitState::Open => "open",
+            CircuitState::HalfOpen => "half-open",
+        }
+    }
+}
+
+#[derive(Default)]
+struct BreakerState {
+    failures: u32,
+    opened_at: Option<Instant>,
+    probe_started: Option<Instant>,
+}
+
+macro_rules! breaker_op {
+    ($store:expr, $op:ident($($arg:expr),*)) => {
+        match $store {
+            #[cfg(feature = "sqlite")]
+            Store::SQLite(store) => store.$op($($arg),*).await,
+            #[cfg(feature = "foundation")]
+            Store::FoundationDb(store) => store.$op($($arg),*).await,
+            #[cfg(feature = "postgres")]
+            Store::PostgreSQL(store) => store.$op($($arg),*).await,
+            #[cfg(feature = "mysql")]
+            Store::MySQL(store) => store.$op($($arg),*).await,
+            #[cfg(feature = "rocks")]
+            Store::RocksDb(store) => store.$op($($arg),*).await,
+            _ => panic!("Invalid store type"),
+        }
+    };
+}
+
+impl CircuitBreaker {
+    pub fn new(error_threshold: u32, open_duration: Duration) -> Self {
+        Self {
+            error_threshold: error_threshold.max(1),
+            open_duration,
+            state: Mutex::new(BreakerState::default()),
+        }
+    }
+
+    pub fn state(&self) -> CircuitState {
+        match self.state.lock().opened_at {
+            Some(opened_at) if opened_at.elapsed() < self.open_duration => CircuitState::Open,
+            Some(_) => CircuitState::HalfOpen,
+            None => CircuitState::Closed,
+        }
+    }
+
+    // Returns false when the request has to be rejected
+    pub fn try_acquire(&self) -> bool {
+        let mut state = self.state.lock();
+        match state.opened_at {
+            None => true,
+            Some(opened_at) if opened_at.elapsed() < self.open_duration => false,
+            Some(_) => {
+                // Only one probe at a time, a probe that never completed
+                // (e.g. its future was dropped) is replaced after a while
+                if state
+                    .probe_started
+                    .is_some_and(|started| started.elapsed() < self.open_duration)
+                {
+                    false
+                } else {
+                    state.probe_started = Some(Instant::now());
+                    true
+                }
+            }
+        }
+    }
+
+    // Returns true when the failure caused the circuit to open
+    pub fn record_failure(&self) -> bool {
+        let mut state = self.state.lock();
+        state.failures = state.failures.saturating_add(1);
+        if state.probe_started.is_some() || state.failures >= self.error_threshold {
+            let was_closed = state.opened_at.is_none();
+            state.opened_at = Some(Instant::now());
+            state.probe_started = None;
+            was_closed
+        } else {
+            false
+        }
+    }
+
+    pub fn record_success(&self) {
+        *self.state.lock() = BreakerState::default();
+    }
+}
+
+impl StoreCircuitBreaker {
+    pub fn new(store: Store, error_threshold: u32, open_duration: Duration) -> Self {
+        Self {
+            store,
+            breaker: CircuitBreaker::new(error_threshold, open_duration),
+        }
+    }
+
+    pub fn inner(&self) -> &Store {
+        &self.store
+    }
+
+    pub fn circuit_state(&self) -> CircuitState {
+        self.breaker.state()
+    }
+
+    async fn guard<T>(&self, op: impl Future<Output = trc::Result<T>>) -> trc::Result<T> {
+        if !self.breaker.try_acquire() {
+            return Err(StoreEvent::BackendUnavailable
+                .into_err()
+                .id(self.store.id())
+                .details("Circuit breaker is open"));
+        }
+
+        let result = op.await;
+        match &result {
+            Err(err) if is_backend_failure(err) => {
+                if self.breaker.record_failure() {
+                    trc::event!(
+                        Store(StoreEvent::BackendUnavailable),
+                        Id = self.store.id(),
+                        Details = "Circuit opened",
+                    );
+                }
+            }
+            _ => self.breaker.record_success(),
+        }
+        result
+    }
+
+    pub async fn get_blob(&self, key: &[u8], range: Range<usize>) -> trc::Result<Option<Vec<u8>>> {
+        breaker_op!(&self.store, get_blob(key, range))
+    }
+
+    pub async fn put_blob(&self, key: &[u8], data: &[u8]) -> trc::Result<()> {
+        self.guard(async { breaker_op!(&self.store, put_blob(key, data)) })
+            .await
+    }
+
+    pub async fn delete_blob(&self, key: &[u8]) -> trc::Result<bool> {
+        self.guard(async { breaker_op!(&self.store, delete_blob(key)) })
+            .await
+    }
+
+    pub async fn get_value<U>(&self, key: impl Key) -> trc::Result<Option<U>>
+    where
+        U: Deserialize + 'static,
+    {
+        breaker_op!(&self.store, get_value(key))
+    }
+
+    pub async fn get_values<K: Key>(&self, keys: Vec<K>) -> trc::Result<Vec<Option<Vec<u8>>>> {
+        breaker_op!(&self.store, get_values(keys))
+    }
+
+    pub async fn get_value_size(&self, key: impl Key) -> trc::Result<Option<usize>> {
+        breaker_op!(&self.store, get_value_size(key))
+    }
+
+    pub async fn get_bitmap(
+        &self,
+        key: BitmapKey<BitmapClass<u32>>,
+    ) -> trc::Result<Option<RoaringBitmap>> {
+        breaker_op!(&self.store, get_bitmap(key))
+    }
+
+    pub async fn get_bitmap_cardinality(
+        &self,
+        key: BitmapKey<BitmapClass<u32>>,
+    ) -> trc::Result<u64> {
+        breaker_op!(&self.store, get_bitmap_cardinality(key))
+    }
+
+    pub async fn get_keys_count(&self, from: impl Key, to: impl Key) -> trc::Result<u64> {
+        breaker_op!(&self.store, get_keys_count(from, to))
+    }
+
+    pub async fn get_counters(
+        &self,
+        from: impl Key,
+        to: impl Key,
+    ) -> trc::Result<Vec<(Vec<u8>, i64)>> {
+        breaker_op!(&self.store, get_counters(from, to))
+    }
+
+    pub async fn iterate<T: Key>(
+        &self,
+        params: IterateParams<T>,
+        cb: impl for<'x> FnMut(&'x [u8], &'x [u8]) -> trc::Result<bool> + Sync + Send,
+    ) -> trc::Result<()> {
+        breaker_op!(&self.store, iterate(params, cb))
+    }
+
+    pub async fn get_counter(
+        &self,
+        key: impl Into<ValueKey<ValueClass<u32>>> + Sync + Send,
+    ) -> trc::Result<i64> {
+        breaker_op!(&self.store, get_counter(key))
+    }
+
+    pub async fn get_value_and_counter(
+        &self,
+        value_key: ValueKey<ValueClass<u32>>,
+        counter_key: ValueKey<ValueClass<u32>>,
+    ) -> trc::Result<(Option<Vec<u8>>, i64)> {
+        breaker_op!(&self.store, get_value_and_counter(value_key, counter_key))
+    }
+
+    pub async fn prefetch(&self, keys: &[impl Key]) -> trc::Result<()> {
+        match &self.store {
+            #[cfg(feature = "rocks")]
+            Store::RocksDb(store) => store.prefetch(keys).await,
+            _ => {
+                let _ = keys;
+                Ok(())
+            }
+        }
+    }
+
+    pub fn max_value_size(&self) -> Option<&AHashMap<u8, usize>> {
+        self.store.max_value_size()
+    }
+
+    pub fn expiring_subspaces(&self) -> &[(u8, u64)] {
+        self.store.expiring_subspaces()
+    }
+
+    pub async fn write(&self, batch: Batch) -> trc::Result<AssignedIds> {
+        self.guard(async { breaker_op!(&self.store, write(batch)) })
+            .await
+    }
+
+    pub async fn atomic_swap(
+        &self,
+        key: impl Key,
+        new_value: Vec<u8>,
+    ) -> trc::Result<Option<Vec<u8>>> {
+        self.guard(async { breaker_op!(&self.store, atomic_swap(key, new_value)) })
+            .await
+    }
+
+    pub async fn compare_and_increment(
+        &self,
+        key: impl Key,
+        expected_version: u64,
+        by: i64,
+    ) -> trc::Result<i64> {
+        self.guard(async {
+            breaker_op!(
+                &self.store,
+                compare_and_increment(key, expected_version, by)
+            )
+        })
+        .await
+    }
+
+    pub async fn get_and_reset_counter(&self, key: impl Key) -> trc::Result<i64> {
+        self.guard(async { breaker_op!(&self.store, get_and_reset_counter(key)) })
+            .await
+    }
+
+    pub async fn atomic_max(&self, key: impl Key, value: i64) -> trc::Result<i64> {
+        self.guard(async { breaker_op!(&self.store, atomic_max(key, value)) })
+            .await
+    }
+
+    pub async fn delete_range(&self, from: impl Key, to: impl Key) -> trc::Result<()> {
+        self.guard(async { breaker_op!(&self.store, delete_range(from, to)) })
+            .await
+    }
+
+    pub(crate) async fn rename_range(
+        &self,
+        from: impl Key,
+        to: impl Key,
+        account_id: u32,
+    ) -> trc::Result<u64> {
+        self.guard(async { breaker_op!(&self.store, rename_range(from, to, account_id)) })
+            .await
+    }
+
+    pub async fn get_storage_statistics(&self) -> trc::Result<StorageStats> {
+        breaker_op!(&self.store, get_storage_statistics())
+    }
+
+    pub async fn purge_store(&self) -> trc::Result<()> {
+        self.guard(async { breaker_op!(&self.store, purge_store()) })
+            .await
+    }
+
+    #[allow(unused_variables)]
+    pub(crate) async fn query<T: QueryResult>(
+        &self,
+        query: &str,
+        params: &[Value<'_>],
+    ) -> trc::Result<T> {
+        match &self.store {
+            #[cfg(feature = "sqlite")]
+            Store::SQLite(store) => store.query(query, params).await,
+            #[cfg(feature = "postgres")]
+            Store::PostgreSQL(store) => store.query(query, params).await,
+            #[cfg(feature = "mysql")]
+            Store::MySQL(store) => store.query(query, params).await,
+            _ => Err(trc::StoreEvent::NotSupported.into_err()),
+        }
+    }
+}
+
+impl Store {
+    pub fn with_circuit_breaker(self, config: &mut Config, prefix: impl AsKey) -> Self {
+        let prefix = prefix.as_key();
+        if config
+            .property_or_default::<bool>((&prefix, "circuit-breaker.enable"), "false")
+            .unwrap_or(false)
+        {
+            let error_threshold = config
+                .property_or_default::<u32>((&prefix, "circuit-breaker.error-threshold"), "5")
+                .unwrap_or(5);
+            let open_duration = config
+                .property_or_default::<Duration>((&prefix, "circuit-breaker.open-duration"), "5s")
+                .unwrap_or(Duration::from_secs(5));
+
+            Store::CircuitBreaker(Arc::new(StoreCircuitBreaker::new(
+                self,
+                error_threshold,
+                open_duration,
+            )))
+        } else {
+            self
+        }
+    }
+
+    // Returns the wrapped backend when the store is behind a circuit breaker
+    pub fn backend(&self) -> &Store {
+        match self {
+            Store::CircuitBreaker(store) => store.inner(),
+            _ => self,
+        }
+    }
+
+    pub fn circuit_state(&self) -> Option<CircuitState> {
+        match self {
+            Store::CircuitBreaker(store) => Some(store.circuit_state()),
+            _ => None,
+        }
+    }
+}
+
+// Errors caused by the data rather than the backend do not trip the circuit
+fn is_backend_failure(err: &trc::Error) -> bool {
+    matches!(
+        err.as_ref(),
+        EventType::Store(
+            StoreEvent::FoundationdbError
+                | StoreEvent::MysqlError
+                | StoreEvent::PostgresqlError
+                | StoreEvent::RocksdbError
+                | StoreEvent::SqliteError
+                | StoreEvent::PoolError
+        )
+    )
+}
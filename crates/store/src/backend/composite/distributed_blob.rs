@@ -76,6 +76,11 @@ impl DistributedBlob {
                         any(feature = "postgres", feature = "mysql")
                     ))]
                     Store::SQLReadReplica(store) => store.get_blob(key, read_range).await,
+                    #[cfg(feature = "enterprise")]
+                    Store::Sharded(store) => store.get_blob(key, read_range).await,
+                    Store::CircuitBreaker(store) => store.get_blob(key, read_range).await,
+                    #[cfg(feature = "test_mode")]
+                    Store::Mock(store) => store.get_blob(key, read_range).await,
                     Store::None => Err(trc::StoreEvent::NotConfigured.into()),
                 },
                 BlobBackend::Fs(store) => store.get_blob(key, read_range).await,
@@ -108,6 +113,11 @@ impl DistributedBlob {
                         any(feature = "postgres", feature = "mysql")
                     ))]
                     Store::SQLReadReplica(store) => store.put_blob(key, data).await,
+                    #[cfg(feature = "enterprise")]
+                    Store::Sharded(store) => store.put_blob(key, data).await,
+                    Store::CircuitBreaker(store) => store.put_blob(key, data).await,
+                    #[cfg(feature = "test_mode")]
+                    Store::Mock(store) => store.put_blob(key, data).await,
                     Store::None => Err(trc::StoreEvent::NotConfigured.into()),
                 },
                 BlobBackend::Fs(store) => store.put_blob(key, data).await,
@@ -140,6 +150,11 @@ impl DistributedBlob {
                         any(feature = "postgres", feature = "mysql")
                     ))]
                     Store::SQLReadReplica(store) => store.delete_blob(key).await,
+                    #[cfg(feature = "enterprise")]
+                    Store::Sharded(store) => store.delete_blob(key).await,
+                    Store::CircuitBreaker(store) => store.delete_blob(key).await,
+                    #[cfg(feature = "test_mode")]
+                    Store::Mock(store) => store.delete_blob(key).await,
                     Store::None => Err(trc::StoreEvent::NotConfigured.into()),
                 },
                 BlobBackend::Fs(store) => store.delete_blob(key).await,
@@ -14,12 +14,13 @@ use std::{
     sync::atomic::{AtomicUsize, Ordering},
 };
 
+use ahash::AHashMap;
 use roaring::RoaringBitmap;
 use utils::config::{utils::AsKey, Config};
 
 use crate::{
     write::{AssignedIds, Batch, BitmapClass, ValueClass},
-    BitmapKey, Deserialize, IterateParams, Key, Store, Stores, ValueKey,
+    BitmapKey, Deserialize, IterateParams, Key, StorageStats, Store, Stores, ValueKey,
 };
 
 pub struct SQLReadReplica {
@@ -192,6 +193,40 @@ impl SQLReadReplica {
         .await
     }
 
+    pub async fn get_values(&self, keys: Vec<impl Key>) -> trc::Result<Vec<Option<Vec<u8>>>> {
+        self.run_op(move |store| {
+            let keys = keys.clone();
+
+            async move {
+                match store {
+                    #[cfg(feature = "postgres")]
+                    Store::PostgreSQL(store) => store.get_values(keys).await,
+                    #[cfg(feature = "mysql")]
+                    Store::MySQL(store) => store.get_values(keys).await,
+                    _ => panic!("Invalid store type"),
+                }
+            }
+        })
+        .await
+    }
+
+    pub async fn get_value_size(&self, key: impl Key) -> trc::Result<Option<usize>> {
+        self.run_op(move |store| {
+            let key = key.clone();
+
+            async move {
+                match store {
+                    #[cfg(feature = "postgres")]
+                    Store::PostgreSQL(store) => store.get_value_size(key).await,
+                    #[cfg(feature = "mysql")]
+                    Store::MySQL(store) => store.get_value_size(key).await,
+                    _ => panic!("Invalid store type"),
+                }
+            }
+        })
+        .await
+    }
+
     pub async fn get_bitmap(
         &self,
         key: BitmapKey<BitmapClass<u32>>,
@@ -212,6 +247,66 @@ impl SQLReadReplica {
         .await
     }
 
+    pub async fn get_bitmap_cardinality(
+        &self,
+        key: BitmapKey<BitmapClass<u32>>,
+    ) -> trc::Result<u64> {
+        self.run_op(move |store| {
+            let key = key.clone();
+
+            async move {
+                match store {
+                    #[cfg(feature = "postgres")]
+                    Store::PostgreSQL(store) => store.get_bitmap_cardinality(key).await,
+                    #[cfg(feature = "mysql")]
+                    Store::MySQL(store) => store.get_bitmap_cardinality(key).await,
+                    _ => panic!("Invalid store type"),
+                }
+            }
+        })
+        .await
+    }
+
+    pub async fn get_keys_count(&self, from: impl Key, to: impl Key) -> trc::Result<u64> {
+        self.run_op(move |store| {
+            let from = from.clone();
+            let to = to.clone();
+
+            async move {
+                match store {
+                    #[cfg(feature = "postgres")]
+                    Store::PostgreSQL(store) => store.get_keys_count(from, to).await,
+                    #[cfg(feature = "mysql")]
+                    Store::MySQL(store) => store.get_keys_count(from, to).await,
+                    _ => panic!("Invalid store type"),
+                }
+            }
+        })
+        .await
+    }
+
+    pub async fn get_counters(
+        &self,
+        from: impl Key,
+        to: impl Key,
+    ) -> trc::Result<Vec<(Vec<u8>, i64)>> {
+        self.run_op(move |store| {
+            let from = from.clone();
+            let to = to.clone();
+
+            async move {
+                match store {
+                    #[cfg(feature = "postgres")]
+                    Store::PostgreSQL(store) => store.get_counters(from, to).await,
+                    #[cfg(feature = "mysql")]
+                    Store::MySQL(store) => store.get_counters(from, to).await,
+                    _ => panic!("Invalid store type"),
+                }
+            }
+        })
+        .await
+    }
+
     pub async fn iterate<T: Key>(
         &self,
         params: IterateParams<T>,
@@ -261,6 +356,40 @@ impl SQLReadReplica {
         .await
     }
 
+    pub async fn get_value_and_counter(
+        &self,
+        value_key: ValueKey<ValueClass<u32>>,
+        counter_key: ValueKey<ValueClass<u32>>,
+    ) -> trc::Result<(Option<Vec<u8>>, i64)> {
+        self.run_op(move |store| {
+            let value_key = value_key.clone();
+            let counter_key = counter_key.clone();
+
+            async move {
+                match store {
+                    #[cfg(feature = "postgres")]
+                    Store::PostgreSQL(store) => {
+                        store.get_value_and_counter(value_key, counter_key).await
+                    }
+                    #[cfg(feature = "mysql")]
+                    Store::MySQL(store) => {
+                        store.get_value_and_counter(value_key, counter_key).await
+                    }
+                    _ => panic!("Invalid store type"),
+                }
+            }
+        })
+        .await
+    }
+
+    pub fn max_value_size(&self) -> Option<&AHashMap<u8, usize>> {
+        self.primary.max_value_size()
+    }
+
+    pub fn expiring_subspaces(&self) -> &[(u8, u64)] {
+        self.primary.expiring_subspaces()
+    }
+
     pub async fn write(&self, batch: Batch) -> trc::Result<AssignedIds> {
         match &self.primary {
             #[cfg(feature = "postgres")]
@@ -271,6 +400,57 @@ impl SQLReadReplica {
         }
     }
 
+    pub async fn atomic_swap(
+        &self,
+        key: impl Key,
+        new_value: Vec<u8>,
+    ) -> trc::Result<Option<Vec<u8>>> {
+        match &self.primary {
+            #[cfg(feature = "postgres")]
+            Store::PostgreSQL(store) => store.atomic_swap(key, new_value).await,
+            #[cfg(feature = "mysql")]
+            Store::MySQL(store) => store.atomic_swap(key, new_value).await,
+            _ => panic!("Invalid store type"),
+        }
+    }
+
+    pub async fn compare_and_increment(
+        &self,
+        key: impl Key,
+        expected_version: u64,
+        by: i64,
+    ) -> trc::Result<i64> {
+        match &self.primary {
+            #[cfg(feature = "postgres")]
+            Store::PostgreSQL(store) => {
+                store.compare_and_increment(key, expected_version, by).await
+            }
+            #[cfg(feature = "mysql")]
+            Store::MySQL(store) => store.compare_and_increment(key, expected_version, by).await,
+            _ => panic!("Invalid store type"),
+        }
+    }
+
+    pub async fn get_and_reset_counter(&self, key: impl Key) -> trc::Result<i64> {
+        match &self.primary {
+            #[cfg(feature = "postgres")]
+            Store::PostgreSQL(store) => store.get_and_reset_counter(key).await,
+            #[cfg(feature = "mysql")]
+            Store::MySQL(store) => store.get_and_reset_counter(key).await,
+            _ => panic!("Invalid store type"),
+        }
+    }
+
+    pub async fn atomic_max(&self, key: impl Key, value: i64) -> trc::Result<i64> {
+        match &self.primary {
+            #[cfg(feature = "postgres")]
+            Store::PostgreSQL(store) => store.atomic_max(key, value).await,
+            #[cfg(feature = "mysql")]
+            Store::MySQL(store) => store.atomic_max(key, value).await,
+            _ => panic!("Invalid store type"),
+        }
+    }
+
     pub async fn delete_range(&self, from: impl Key, to: impl Key) -> trc::Result<()> {
         match &self.primary {
             #[cfg(feature = "postgres")]
@@ -281,6 +461,31 @@ impl SQLReadReplica {
         }
     }
 
+    pub(crate) async fn rename_range(
+        &self,
+        from: impl Key,
+        to: impl Key,
+        account_id: u32,
+    ) -> trc::Result<u64> {
+        match &self.primary {
+            #[cfg(feature = "postgres")]
+            Store::PostgreSQL(store) => store.rename_range(from, to, account_id).await,
+            #[cfg(feature = "mysql")]
+            Store::MySQL(store) => store.rename_range(from, to, account_id).await,
+            _ => panic!("Invalid store type"),
+        }
+    }
+
+    pub async fn get_storage_statistics(&self) -> trc::Result<StorageStats> {
+        match &self.primary {
+            #[cfg(feature = "postgres")]
+            Store::PostgreSQL(store) => store.get_storage_statistics().await,
+            #[cfg(feature = "mysql")]
+            Store::MySQL(store) => store.get_storage_statistics().await,
+            _ => panic!("Invalid store type"),
+        }
+    }
+
     pub async fn purge_store(&self) -> trc::Result<()> {
         match &self.primary {
             #[cfg(feature = "postgres")]
@@ -0,0 +1,766 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: LicenseRef-SEL
+ *
+ * This file is subject to the Stalwart Enterprise License Agreement (SEL) and
+ * is NOT open source software.
+ *
+ */
+
+use std::ops::Range;
+
+use ahash::AHashMap;
+use roaring::RoaringBitmap;
+use utils::config::{utils::AsKey, Config};
+
+use crate::{
+    write::{
+        AssignedIds, Batch, BitmapClass, DirectoryClass, MaybeDynamicId, MaybeDynamicValue,
+        Operation, TagValue, ValueClass, ValueOp,
+    },
+    BitmapKey, Deserialize, IterateParams, Key, StorageStats, Store, Stores, ValueKey,
+    SUBSPACE_ACL, SUBSPACE_BITMAP_ID, SUBSPACE_BITMAP_TAG, SUBSPACE_BITMAP_TEXT, SUBSPACE_COUNTER,
+    SUBSPACE_FTS_INDEX, SUBSPACE_INDEXES, SUBSPACE_LOGS, SUBSPACE_PROPERTY, SUBSPACE_QUOTA,
+    SUBSPACE_RECENT, U32_LEN,
+};
+
+// Account data, including account quotas and per-document counters, is
+// partitioned across the shards, while data that is not owned by an account
+// (directory, queue, blobs, settings, etc.) is kept in the first shard.
+pub struct ShardedStore {
+    shards: Vec<Store>,
+    hash: ShardHash,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShardHash {
+    // Jump consistent hash, only 1/N accounts move when a shard is added
+    Jump,
+    Modulo,
+}
+
+enum Route<'x> {
+    Shard(&'x Store),
+    All,
+}
+
+struct ShardBatch {
+    shard_id: usize,
+    ops: Vec<Operation>,
+    account_id: u32,
+    collection: u8,
+    document: u64,
+    change_id: u64,
+    // Positions of the documents created by another shard, along with the
+    // index of their assigned id
+    created_ids: Vec<(usize, usize)>,
+}
+
+macro_rules! shard_op {
+    ($store:expr, $op:ident($($arg:expr),*)) => {
+        match $store {
+            #[cfg(feature = "sqlite")]
+            Store::SQLite(store) => store.$op($($arg),*).await,
+            #[cfg(feature = "foundation")]
+            Store::FoundationDb(store) => store.$op($($arg),*).await,
+            #[cfg(feature = "postgres")]
+            Store::PostgreSQL(store) => store.$op($($arg),*).await,
+            #[cfg(feature = "mysql")]
+            Store::MySQL(store) => store.$op($($arg),*).await,
+            #[cfg(feature = "rocks")]
+            Store::RocksDb(store) => store.$op($($arg),*).await,
+            _ => panic!("Invalid store type"),
+        }
+    };
+}
+
+impl ShardedStore {
+    pub fn open(config: &mut Config, prefix: impl AsKey, stores: &Stores) -> Option<Self> {
+        let prefix = prefix.as_key();
+        let shard_ids = config
+            .values((&prefix, "shards"))
+            .map(|(_, v)| v.to_string())
+            .collect::<Vec<_>>();
+        let hash = match config.value((&prefix, "hash")).unwrap_or("jump") {
+            "jump" => ShardHash::Jump,
+            "modulo" => ShardHash::Modulo,
+            other => {
+                let message = format!("Invalid shard hash function {other:?}");
+                config.new_parse_error((&prefix, "hash"), message);
+                return None;
+            }
+        };
+
+        let mut shards = Vec::with_capacity(shard_ids.len());
+        for shard_id in shard_ids {
+            if let Some(store) = stores.stores.get(&shard_id) {
+                if !store.is_none()
+                    && !store.is_enterprise_store()
+                    && store.circuit_state().is_none()
+                {
+                    shards.push(store.clone());
+                } else {
+                    config.new_build_error(
+                        (&prefix, "shards"),
+                        format!("Store {shard_id} cannot be used as a shard"),
+                    );
+                    return None;
+                }
+            } else {
+                config.new_build_error(
+                    (&prefix, "shards"),
+                    format!("Shard store {shard_id} not found"),
+                );
+                return None;
+            }
+        }
+
+        if !shards.is_empty() {
+            Some(Self { shards, hash })
+        } else {
+            config.new_build_error((&prefix, "shards"), "No shard stores specified");
+            None
+        }
+    }
+
+    pub fn shard_id(&self, account_id: u32) -> usize {
+        if account_id == u32::MAX {
+            return 0;
+        }
+
+        match self.hash {
+            ShardHash::Jump => jump_consistent_hash(account_id as u64, self.shards.len()),
+            ShardHash::Modulo => account_id as usize % self.shards.len(),
+        }
+    }
+
+    #[inline(always)]
+    fn primary(&self) -> &Store {
+        &self.shards[0]
+    }
+
+    fn route_key(&self, key: &impl Key) -> &Store {
+        &self.shards[self.key_shard_id(key)]
+    }
+
+    fn key_shard_id(&self, key: &impl Key) -> usize {
+        if let Some(account_id) = key.owner_account_id() {
+            return self.shard_id(account_id);
+        }
+
+        if is_sharded_subspace(key.subspace()) {
+            if let Some(account_id) = key_account_id(key) {
+                return self.shard_id(account_id);
+            }
+        }
+
+        0
+    }
+
+    fn route_range(&self, from: &impl Key, to: &impl Key) -> Route<'_> {
+        let subspace = from.subspace();
+        if is_sharded_subspace(subspace) {
+            match (key_account_id(from), key_account_id(to)) {
+                (Some(from_id), Some(to_id)) if from_id == to_id => {
+                    Route::Shard(&self.shards[self.shard_id(from_id)])
+                }
+                _ => Route::All,
+            }
+        } else if is_mixed_subspace(subspace) {
+            match (from.owner_account_id(), to.owner_account_id()) {
+                (Some(from_id), Some(to_id)) if from_id == to_id => {
+                    Route::Shard(&self.shards[self.shard_id(from_id)])
+                }
+                _ => Route::All,
+            }
+        } else {
+            Route::Shard(self.primary())
+        }
+    }
+
+    fn class_shard_id(
+        &self,
+        class: &ValueClass<MaybeDynamicId>,
+        account_id: u32,
+        collection: u8,
+    ) -> usize {
+        if let Some(account_id) = class.owner_account_id(account_id, collection) {
+            self.shard_id(account_id)
+        } else if is_sharded_subspace(class.subspace(collection)) {
+            match class {
+                // ACLs are looked up by grantee
+                ValueClass::Acl(grant_account_id) => self.shard_id(*grant_account_id),
+                ValueClass::Any(any) => any.key.get(0..U32_LEN).map_or(0, |bytes| {
+                    self.shard_id(u32::from_be_bytes(bytes.try_into().unwrap()))
+                }),
+                _ => self.shard_id(account_id),
+            }
+        } else {
+            0
+        }
+    }
+
+    pub async fn get_blob(&self, key: &[u8], range: Range<usize>) -> trc::Result<Option<Vec<u8>>> {
+        shard_op!(self.primary(), get_blob(key, range))
+    }
+
+    pub async fn put_blob(&self, key: &[u8], data: &[u8]) -> trc::Result<()> {
+        shard_op!(self.primary(), put_blob(key, data))
+    }
+
+    pub async fn delete_blob(&self, key: &[u8]) -> trc::Result<bool> {
+        shard_op!(self.primary(), delete_blob(key))
+    }
+
+    pub async fn get_value<U>(&self, key: impl Key) -> trc::Result<Option<U>>
+    where
+        U: Deserialize + 'static,
+    {
+        shard_op!(self.route_key(&key), get_value(key))
+    }
+
+    pub async fn get_values<K: Key>(&self, keys: Vec<K>) -> trc::Result<Vec<Option<Vec<u8>>>> {
+        let mut shard_keys: AHashMap<usize, (Vec<usize>, Vec<K>)> = AHashMap::new();
+        let num_keys = keys.len();
+        for (pos, key) in keys.into_iter().enumerate() {
+            let (positions, keys) = shard_keys.entry(self.key_shard_id(&key)).or_default();
+            positions.push(pos);
+            keys.push(key);
+        }
+
+        let mut results = vec![None; num_keys];
+        for (shard_id, (positions, keys)) in shard_keys {
+            for (pos, value) in positions
+                .into_iter()
+                .zip(shard_op!(&self.shards[shard_id], get_values(keys))?)
+            {
+                results[pos] = value;
+            }
+        }
+
+        Ok(results)
+    }
+
+    pub async fn get_value_size(&self, key: impl Key) -> trc::Result<Option<usize>> {
+        shard_op!(self.route_key(&key), get_value_size(key))
+    }
+
+    pub async fn get_bitmap(
+        &self,
+        key: BitmapKey<BitmapClass<u32>>,
+    ) -> trc::Result<Option<RoaringBitmap>> {
+        shard_op!(self.route_key(&key), get_bitmap(key))
+    }
+
+    pub async fn get_bitmap_cardinality(
+        &self,
+        key: BitmapKey<BitmapClass<u32>>,
+    ) -> trc::Result<u64> {
+        shard_op!(self.route_key(&key), get_bitmap_cardinality(key))
+    }
+
+    pub async fn get_keys_count(&self, from: impl Key, to: impl Key) -> trc::Result<u64> {
+        match self.route_range(&from, &to) {
+            Route::Shard(store) => shard_op!(store, get_keys_count(from, to)),
+            Route::All => {
+                let mut count = 0;
+                for store in &self.shards {
+                    count += shard_op!(store, get_keys_count(from.clone(), to.clone()))?;
+                }
+
+                Ok(count)
+            }
+        }
+    }
+
+    pub async fn get_counters(
+        &self,
+        from: impl Key,
+        to: impl Key,
+    ) -> trc::Result<Vec<(Vec<u8>, i64)>> {
+        match self.route_range(&from, &to) {
+            Route::Shard(store) => shard_op!(store, get_counters(from, to)),
+            Route::All => {
+                let mut counters = Vec::new();
+                for store in &self.shards {
+                    counters.extend(shard_op!(store, get_counters(from.clone(), to.clone()))?);
+                }
+
+                Ok(counters)
+            }
+        }
+    }
+
+    // Ranges spanning multiple accounts are iterated one shard at a time,
+    // keys are ordered within each shard but not across shards.
+    pub async fn iterate<T: Key>(
+        &self,
+        params: IterateParams<T>,
+        mut cb: impl for<'x> FnMut(&'x [u8], &'x [u8]) -> trc::Result<bool> + Sync + Send,
+    ) -> trc::Result<()> {
+        match self.route_range(&params.begin, &params.end) {
+            Route::Shard(store) => shard_op!(store, iterate(params, cb)),
+            Route::All => {
+                let mut is_done = false;
+                for store in &self.shards {
+                    let is_first = params.first;
+                    shard_op!(
+                        store,
+                        iterate(params.clone(), |key, value| {
+                            let result = cb(key, value)?;
+                            is_done = !result || is_first;
+                            Ok(result)
+                        })
+                    )?;
+
+                    if is_done {
+                        break;
+                    }
+                }
+
+                Ok(())
+            }
+        }
+    }
+
+    pub async fn get_counter(
+        &self,
+        key: impl Into<ValueKey<ValueClass<u32>>> + Sync + Send,
+    ) -> trc::Result<i64> {
+        let key = key.into();
+        shard_op!(self.route_key(&key), get_counter(key))
+    }
+
+    // Keys that live in different shards cannot be read atomically
+    pub async fn get_value_and_counter(
+        &self,
+        value_key: ValueKey<ValueClass<u32>>,
+        counter_key: ValueKey<ValueClass<u32>>,
+    ) -> trc::Result<(Option<Vec<u8>>, i64)> {
+        if self.key_shard_id(&value_key) == self.key_shard_id(&counter_key) {
+            shard_op!(
+                &self.shards[self.key_shard_id(&value_key)],
+                get_value_and_counter(value_key, counter_key)
+            )
+        } else {
+            Ok((
+                self.get_values(vec![value_key]).await?.pop().flatten(),
+                self.get_counter(counter_key).await?,
+            ))
+        }
+    }
+
+    pub fn shards(&self) -> &[Store] {
+        &self.shards
+    }
+
+    pub fn max_value_size(&self) -> Option<&AHashMap<u8, usize>> {
+        self.primary().max_value_size()
+    }
+
+    pub fn expiring_subspaces(&self) -> &[(u8, u64)] {
+        self.primary().expiring_subspaces()
+    }
+
+    // Batches are split by shard. Writes spanning several shards are not
+    // atomic, so account quotas and counters are kept in the account's shard
+    // and only data not owned by the account (blob links, queues, etc.) is
+    // written separately. Documents can only be created by a single shard,
+    // which is written first so that the ids it assigns are used by the
+    // batches of the other shards.
+    pub async fn write(&self, batch: Batch) -> trc::Result<AssignedIds> {
+        let mut account_id = u32::MAX;
+        let mut collection = u8::MAX;
+        let mut document_id = u32::MAX;
+        let mut document = u64::MAX;
+        let mut change_id = u64::MAX;
+        let mut id_shard = None;
+        let mut num_ids = 0;
+        let mut batches: Vec<ShardBatch> = Vec::new();
+        let span_id = batch.span_id;
+        let timeout = batch.timeout;
+
+        for op in batch.ops {
+            let shard_id = match &op {
+                Operation::AccountId {
+                    account_id: account_id_,
+                } => {
+                    account_id = *account_id_;
+                    continue;
+                }
+                Operation::Collection {
+                    collection: collection_,
+                } => {
+                    collection = *collection_;
+                    continue;
+                }
+                Operation::DocumentId {
+                    document_id: document_id_,
+                } => {
+                    document_id = *document_id_;
+                    if document_id != u32::MAX {
+                        document = document_id as u64;
+                        continue;
+                    }
+
+                    // New documents are assigned an id by the account's shard
+                    let shard_id = self.shard_id(account_id);
+                    set_id_shard(&mut id_shard, shard_id)?;
+                    document = u64::MAX - 1 - num_ids as u64;
+                    num_ids += 1;
+                    ShardBatch::get(&mut batches, shard_id).set_context(
+                        account_id,
+                        collection,
+                        document,
+                        document_id,
+                        change_id,
+                        None,
+                    );
+                    continue;
+                }
+                Operation::ChangeId {
+                    change_id: change_id_,
+                } => {
+                    change_id = *change_id_;
+                    continue;
+                }
+                Operation::SetMany { class, items } => {
+                    // Document ids are returned as assigned ids
+                    let shard_id = self.class_shard_id(class, account_id, collection);
+                    set_id_shard(&mut id_shard, shard_id)?;
+                    num_ids += items.len();
+                    shard_id
+                }
+                Operation::Value { class, .. } | Operation::AssertValue { class, .. } => {
+                    self.class_shard_id(class, account_id, collection)
+                }
+                Operation::Index { .. }
+                | Operation::Bitmap { .. }
+                | Operation::Log { .. }
+                | Operation::LogWithTimestamp { .. } => self.shard_id(account_id),
+            };
+
+            let created_id =
+                (document_id == u32::MAX && document != u64::MAX && id_shard != Some(shard_id))
+                    .then(|| (u64::MAX - 1 - document) as usize);
+            let shard_batch = ShardBatch::get(&mut batches, shard_id);
+            shard_batch.set_context(
+                account_id,
+                collection,
+                document,
+                document_id,
+                change_id,
+                created_id,
+            );
+            shard_batch.ops.push(op);
+        }
+
+        // Write the shard assigning ids first
+        if let Some(pos) = id_shard.and_then(|id_shard| {
+            batches
+                .iter()
+                .position(|shard_batch| shard_batch.shard_id == id_shard)
+        }) {
+            if batches.iter().any(|shard_batch| {
+                Some(shard_batch.shard_id) != id_shard && shard_batch.ops.iter().any(has_dynamic_id)
+            }) {
+                return Err(trc::StoreEvent::NotSupported
+                    .into_err()
+                    .details("Assigned ids cannot be referenced across shards"));
+            }
+            let shard_batch = batches.remove(pos);
+            batches.insert(0, shard_batch);
+        }
+
+        let mut assigned_ids = AssignedIds::default();
+        for (pos, mut shard_batch) in batches.into_iter().enumerate() {
+            if pos > 0 && id_shard.is_some() {
+                shard_batch.resolve_ids(&assigned_ids)?;
+            }
+            let result = shard_op!(
+                &self.shards[shard_batch.shard_id],
+                write(Batch {
+                    ops: shard_batch.ops,
+                    span_id,
+                    timeout,
+                })
+            )?;
+            assigned_ids.document_ids.extend(result.document_ids);
+            assigned_ids.counter_ids.extend(result.counter_ids);
+        }
+
+        Ok(assigned_ids)
+    }
+
+    pub async fn atomic_swap(
+        &self,
+        key: impl Key,
+        new_value: Vec<u8>,
+    ) -> trc::Result<Option<Vec<u8>>> {
+        shard_op!(self.route_key(&key), atomic_swap(key, new_value))
+    }
+
+    pub async fn compare_and_increment(
+        &self,
+        key: impl Key,
+        expected_version: u64,
+        by: i64,
+    ) -> trc::Result<i64> {
+        shard_op!(
+            self.route_key(&key),
+            compare_and_increment(key, expected_version, by)
+        )
+    }
+
+    pub async fn get_and_reset_counter(&self, key: impl Key) -> trc::Result<i64> {
+        shard_op!(self.route_key(&key), get_and_reset_counter(key))
+    }
+
+    pub async fn atomic_max(&self, key: impl Key, value: i64) -> trc::Result<i64> {
+        shard_op!(self.route_key(&key), atomic_max(key, value))
+    }
+
+    pub async fn delete_range(&self, from: impl Key, to: impl Key) -> trc::Result<()> {
+        match self.route_range(&from, &to) {
+            Route::Shard(store) => shard_op!(store, delete_range(from, to)),
+            Route::All => {
+                for store in &self.shards {
+                    shard_op!(store, delete_range(from.clone(), to.clone()))?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    // Keys are renamed in place, so both accounts must live on the same shard
+    pub(crate) async fn rename_range(
+        &self,
+        from: impl Key,
+        to: impl Key,
+        account_id: u32,
+    ) -> trc::Result<u64> {
+        let shard_id = if let Some(from_id) = from.owner_account_id() {
+            let shard_id = self.shard_id(from_id);
+            if shard_id != self.shard_id(account_id) {
+                return Err(trc::StoreEvent::NotSupported
+                    .into_err()
+                    .details("Accounts cannot be renamed across shards"));
+            }
+            shard_id
+        } else if is_sharded_subspace(from.subspace()) {
+            let shard_id = key_account_id(&from).map_or(0, |from_id| self.shard_id(from_id));
+            if shard_id != self.shard_id(account_id) {
+                return Err(trc::StoreEvent::NotSupported
+                    .into_err()
+                    .details("Accounts cannot be renamed across shards"));
+            }
+            shard_id
+        } else {
+            0
+        };
+
+        shard_op!(&self.shards[shard_id], rename_range(from, to, account_id))
+    }
+
+    pub async fn get_storage_statistics(&self) -> trc::Result<StorageStats> {
+        let mut stats = StorageStats::default();
+        for store in &self.shards {
+            let shard_stats = shard_op!(store, get_storage_statistics())?;
+            for (subspace, size) in shard_stats.subspace_sizes {
+                *stats.subspace_sizes.entry(subspace).or_default() += size;
+            }
+            for (subspace, rows) in shard_stats.total_rows {
+                *stats.total_rows.entry(subspace).or_default() += rows;
+            }
+        }
+        Ok(stats)
+    }
+
+    pub async fn purge_store(&self) -> trc::Result<()> {
+        for store in &self.shards {
+            shard_op!(store, purge_store())?;
+        }
+        Ok(())
+    }
+}
+
+impl ShardBatch {
+    fn get(batches: &mut Vec<ShardBatch>, shard_id: usize) -> &mut ShardBatch {
+        if let Some(pos) = batches
+            .iter()
+            .position(|shard_batch| shard_batch.shard_id == shard_id)
+        {
+            &mut batches[pos]
+        } else {
+            batches.push(ShardBatch {
+                shard_id,
+                ops: Vec::new(),
+                account_id: u32::MAX,
+                collection: u8::MAX,
+                document: u64::MAX,
+                change_id: u64::MAX,
+                created_ids: Vec::new(),
+            });
+            batches.last_mut().unwrap()
+        }
+    }
+
+    fn set_context(
+        &mut self,
+        account_id: u32,
+        collection: u8,
+        document: u64,
+        document_id: u32,
+        change_id: u64,
+        created_id: Option<usize>,
+    ) {
+        if self.account_id != account_id {
+            self.account_id = account_id;
+            self.ops.push(Operation::AccountId { account_id });
+        }
+        if self.collection != collection {
+            self.collection = collection;
+            self.ops.push(Operation::Collection { collection });
+        }
+        if self.document != document {
+            self.document = document;
+            if let Some(created_id) = created_id {
+                self.created_ids.push((self.ops.len(), created_id));
+            }
+            self.ops.push(Operation::DocumentId { document_id });
+        }
+        if self.change_id != change_id {
+            self.change_id = change_id;
+            self.ops.push(Operation::ChangeId { change_id });
+        }
+    }
+
+    // Replaces the documents created by another shard and the values
+    // referencing them with the ids that were assigned
+    fn resolve_ids(&mut self, assigned_ids: &AssignedIds) -> trc::Result<()> {
+        for (pos, created_id) in std::mem::take(&mut self.created_ids) {
+            self.ops[pos] = Operation::DocumentId {
+                document_id: assigned_ids.get_document_id(created_id)?,
+            };
+        }
+
+        for op in &mut self.ops {
+            match op {
+                Operation::Value {
+                    op: ValueOp::Set(value) | ValueOp::Append { value, .. },
+                    ..
+                }
+                | Operation::Log { set: value }
+                | Operation::LogWithTimestamp { set: value, .. } => {
+                    resolve_value(value, assigned_ids)?;
+                }
+                Operation::SetMany { items, .. } => {
+                    for (_, value) in items {
+                        resolve_value(value, assigned_ids)?;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn resolve_value(value: &mut MaybeDynamicValue, assigned_ids: &AssignedIds) -> trc::Result<()> {
+    if matches!(value, MaybeDynamicValue::Dynamic(_)) {
+        *value = MaybeDynamicValue::Static(value.resolve(assigned_ids)?.into_owned());
+    }
+    Ok(())
+}
+
+fn set_id_shard(id_shard: &mut Option<usize>, shard_id: usize) -> trc::Result<()> {
+    match id_shard {
+        Some(id_shard) if *id_shard != shard_id => Err(trc::StoreEvent::NotSupported
+            .into_err()
+            .details("Documents cannot be created in several shards within a batch")),
+        _ => {
+            *id_shard = Some(shard_id);
+            Ok(())
+        }
+    }
+}
+
+// Ids embedded in keys are resolved by the shard writing them
+fn has_dynamic_id(op: &Operation) -> bool {
+    match op {
+        Operation::Value { class, .. }
+        | Operation::AssertValue { class, .. }
+        | Operation::SetMany { class, .. } => matches!(
+            class,
+            ValueClass::Directory(
+                DirectoryClass::MemberOf {
+                    principal_id: MaybeDynamicId::Dynamic(_),
+                    ..
+                } | DirectoryClass::MemberOf {
+                    member_of: MaybeDynamicId::Dynamic(_),
+                    ..
+                } | DirectoryClass::Members {
+                    principal_id: MaybeDynamicId::Dynamic(_),
+                    ..
+                } | DirectoryClass::Members {
+                    has_member: MaybeDynamicId::Dynamic(_),
+                    ..
+                } | DirectoryClass::Principal(MaybeDynamicId::Dynamic(_))
+            )
+        ),
+        Operation::Bitmap {
+            class:
+                BitmapClass::Tag {
+                    value: TagValue::Id(MaybeDynamicId::Dynamic(_)),
+                    ..
+                },
+            ..
+        } => true,
+        _ => false,
+    }
+}
+
+// Subspaces holding account data, their keys start with the account id
+// (or the grantee for ACLs)
+fn is_sharded_subspace(subspace: u8) -> bool {
+    matches!(
+        subspace,
+        SUBSPACE_ACL
+            | SUBSPACE_BITMAP_ID
+            | SUBSPACE_BITMAP_TAG
+            | SUBSPACE_BITMAP_TEXT
+            | SUBSPACE_INDEXES
+            | SUBSPACE_LOGS
+            | SUBSPACE_PROPERTY
+            | SUBSPACE_FTS_INDEX
+            | SUBSPACE_RECENT
+    )
+}
+
+// Subspaces holding both account and global keys, account keys are routed
+// by their owner and ranges without an owner are read from all shards
+fn is_mixed_subspace(subspace: u8) -> bool {
+    matches!(subspace, SUBSPACE_COUNTER | SUBSPACE_QUOTA)
+}
+
+fn key_account_id(key: &impl Key) -> Option<u32> {
+    key.serialize(0)
+        .get(0..U32_LEN)
+        .map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()))
+}
+
+// Lamping & Veach, "A Fast, Minimal Memory, Consistent Hash Algorithm"
+pub fn jump_consistent_hash(mut key: u64, num_buckets: usize) -> usize {
+    let mut b: i64 = -1;
+    let mut j: i64 = 0;
+
+    while j < num_buckets as i64 {
+        b = j;
+        key = key.wrapping_mul(2862933555777941757).wrapping_add(1);
+        j = ((b + 1) as f64 * ((1u64 << 31) as f64 / ((key >> 33) + 1) as f64)) as i64;
+    }
+
+    b as usize
+}
@@ -4,16 +4,23 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::ops::Range;
+use std::{io, ops::Range, sync::Arc};
 
+use bytes::Bytes;
 use foundationdb::{options::StreamingMode, KeySelector, RangeOption};
-use futures::TryStreamExt;
+use futures::{stream, StreamExt, TryStreamExt};
+use tokio_util::io::StreamReader;
 use utils::BLOB_HASH_LEN;
 
-use crate::{backend::foundationdb::into_error, write::key::KeySerializer, SUBSPACE_BLOBS};
+use crate::{
+    backend::foundationdb::into_error, write::key::KeySerializer, BlobStream, SUBSPACE_BLOBS,
+};
 
 use super::{FdbStore, MAX_VALUE_SIZE};
 
+// Number of chunks fetched per transaction when streaming a blob
+const STREAM_CHUNKS: usize = 10;
+
 impl FdbStore {
     pub(crate) async fn get_blob(
         &self,
@@ -92,6 +99,77 @@ impl FdbStore {
         Ok(blob_data)
     }
 
+    pub(crate) async fn get_blob_stream(
+        self: Arc<Self>,
+        key: &[u8],
+    ) -> trc::Result<Option<BlobStream>> {
+        let (first_chunks, has_more) = self.get_blob_chunks(key, 0).await?;
+        if first_chunks.is_empty() {
+            return Ok(None);
+        }
+
+        // Each batch of chunks is read using a new transaction, as streaming
+        // a large blob to a slow reader can outlive the transaction time limit.
+        let next_chunks = stream::try_unfold(
+            (self, key.to_vec(), has_more.then_some(STREAM_CHUNKS)),
+            |(store, key, from_chunk)| async move {
+                if let Some(from_chunk) = from_chunk {
+                    match store.get_blob_chunks(&key, from_chunk).await {
+                        Ok((chunks, has_more)) if !chunks.is_empty() => Ok(Some((
+                            chunks,
+                            (store, key, has_more.then_some(from_chunk + STREAM_CHUNKS)),
+                        ))),
+                        Ok(_) => Ok(None),
+                        Err(err) => Err(io::Error::other(err)),
+                    }
+                } else {
+                    Ok(None)
+                }
+            },
+        );
+
+        Ok(Some(Box::pin(StreamReader::new(
+            stream::once(async move { Ok::<_, io::Error>(first_chunks) }).chain(next_chunks),
+        ))))
+    }
+
+    async fn get_blob_chunks(&self, key: &[u8], from_chunk: usize) -> trc::Result<(Bytes, bool)> {
+        let begin = KeySerializer::new(key.len() + 3)
+            .write(SUBSPACE_BLOBS)
+            .write(key)
+            .write(from_chunk as u16)
+            .finalize();
+        let end = KeySerializer::new(key.len() + 3)
+            .write(SUBSPACE_BLOBS)
+            .write(key)
+            .write(std::cmp::min(from_chunk + STREAM_CHUNKS, u16::MAX as usize) as u16)
+            .finalize();
+        let key_len = begin.len();
+        let trx = self.read_trx().await?;
+        let mut values = trx.get_ranges_keyvalues(
+            RangeOption {
+                begin: KeySelector::first_greater_or_equal(begin),
+                end: KeySelector::first_greater_or_equal(end),
+                mode: StreamingMode::WantAll,
+                reverse: false,
+                ..RangeOption::default()
+            },
+            true,
+        );
+        let mut chunks = Vec::with_capacity(MAX_VALUE_SIZE * STREAM_CHUNKS);
+        let mut has_more = false;
+
+        while let Some(value) = values.try_next().await.map_err(into_error)? {
+            if value.key().len() == key_len {
+                let value = value.value();
+                chunks.extend_from_slice(value);
+                has_more = value.len() == MAX_VALUE_SIZE;
+            }
+        }
+
+        Ok((Bytes::from(chunks), has_more))
+    }
+
     pub(crate) async fn put_blob(&self, key: &[u8], data: &[u8]) -> trc::Result<()> {
         const N_CHUNKS: usize = (1 << 5) - 1;
         let last_chunk = std::cmp::max(
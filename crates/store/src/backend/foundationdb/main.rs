@@ -9,6 +9,8 @@ use std::time::Duration;
 use foundationdb::{api, options::DatabaseOption, Database};
 use utils::config::{utils::AsKey, Config};
 
+use crate::config::{parse_commit_limits, parse_expiring_subspaces, parse_max_value_size};
+
 use super::FdbStore;
 
 impl FdbStore {
@@ -106,6 +108,9 @@ impl FdbStore {
             guard,
             db,
             version: Default::default(),
+            max_value_size: parse_max_value_size(config, &prefix),
+            expiring_subspaces: parse_expiring_subspaces(config, &prefix),
+            commit_limits: parse_commit_limits(config, &prefix),
         })
     }
 }
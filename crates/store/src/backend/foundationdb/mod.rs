@@ -6,13 +6,23 @@
 
 use std::time::{Duration, Instant};
 
+use ahash::AHashMap;
 use foundationdb::{api::NetworkAutoStop, Database, FdbError, Transaction};
 
+use crate::write::CommitLimits;
+
 pub mod blob;
 pub mod main;
 pub mod read;
 pub mod write;
 
+// FoundationDB rejects values larger than 100 KB. Larger values (other than counters)
+// are split into chunks of MAX_VALUE_SIZE bytes: the first chunk is stored under the
+// original key and the rest under the key followed by a chunk index byte (0, 1, ...),
+// which limits values to 255 chunks. A value is read back as chunked when its first
+// chunk is exactly MAX_VALUE_SIZE bytes long. Blobs use the same chunk size but are
+// keyed by chunk number. Per-subspace limits set in `max-value-size` are checked
+// before the write and apply on top of this one.
 const MAX_VALUE_SIZE: usize = 100000;
 pub const TRANSACTION_EXPIRY: Duration = Duration::from_secs(1);
 pub const TRANSACTION_TIMEOUT: Duration = Duration::from_secs(4);
@@ -22,6 +32,9 @@ pub struct FdbStore {
     db: Database,
     guard: NetworkAutoStop,
     version: parking_lot::Mutex<ReadVersion>,
+    pub(crate) max_value_size: AHashMap<u8, usize>,
+    pub(crate) expiring_subspaces: Vec<(u8, u64)>,
+    pub(crate) commit_limits: CommitLimits,
 }
 
 pub(crate) struct TimedTransaction {
@@ -18,7 +18,8 @@ use crate::{
         key::{DeserializeBigEndian, KeySerializer},
         BitmapClass, ValueClass,
     },
-    BitmapKey, Deserialize, IterateParams, Key, ValueKey, U32_LEN, WITH_SUBSPACE,
+    BitmapKey, Deserialize, IterateParams, Key, StorageStats, ValueKey, SUBSPACES, U32_LEN,
+    WITH_SUBSPACE,
 };
 
 use super::{into_error, FdbStore, ReadVersion, TimedTransaction, MAX_VALUE_SIZE};
@@ -45,6 +46,54 @@ impl FdbStore {
         }
     }
 
+    pub(crate) async fn get_value_size(&self, key: impl Key) -> trc::Result<Option<usize>> {
+        let key = key.serialize(WITH_SUBSPACE);
+        let trx = self.read_trx().await?;
+
+        if let Some(bytes) = trx.get(&key, true).await.map_err(into_error)? {
+            let mut size = bytes.len();
+            if size >= MAX_VALUE_SIZE {
+                // Add up the length of the remaining chunks
+                let mut key = KeySerializer::new(key.len() + 1)
+                    .write(key.as_slice())
+                    .write(0u8)
+                    .finalize();
+                while let Some(bytes) = trx.get(&key, true).await.map_err(into_error)? {
+                    size += bytes.len();
+                    *key.last_mut().unwrap() += 1;
+                }
+            }
+            Ok(Some(size))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub(crate) async fn get_values(
+        &self,
+        keys: Vec<impl Key>,
+    ) -> trc::Result<Vec<Option<Vec<u8>>>> {
+        let keys = keys
+            .iter()
+            .map(|key| key.serialize(WITH_SUBSPACE))
+            .collect::<Vec<_>>();
+        let trx = self.read_trx().await?;
+
+        // Fields are read concurrently within the same transaction
+        futures::future::try_join_all(keys.iter().map(|key| read_chunked_value(key, &trx, true)))
+            .await
+            .map(|values| {
+                values
+                    .into_iter()
+                    .map(|value| match value {
+                        ChunkedValue::Single(bytes) => Some(bytes.to_vec()),
+                        ChunkedValue::Chunked { bytes, .. } => Some(bytes),
+                        ChunkedValue::None => None,
+                    })
+                    .collect()
+            })
+    }
+
     pub(crate) async fn get_bitmap(
         &self,
         mut key: BitmapKey<BitmapClass<u32>>,
@@ -76,6 +125,134 @@ impl FdbStore {
         Ok(if !bm.is_empty() { Some(bm) } else { None })
     }
 
+    pub(crate) async fn get_bitmap_cardinality(
+        &self,
+        mut key: BitmapKey<BitmapClass<u32>>,
+    ) -> trc::Result<u64> {
+        let mut count = 0;
+        let begin = key.serialize(WITH_SUBSPACE);
+        key.document_id = u32::MAX;
+        let end = key.serialize(WITH_SUBSPACE);
+        let key_len = begin.len();
+        let trx = self.read_trx().await?;
+        let mut values = trx.get_ranges_keyvalues(
+            RangeOption {
+                begin: KeySelector::first_greater_or_equal(begin),
+                end: KeySelector::first_greater_or_equal(end),
+                mode: StreamingMode::WantAll,
+                reverse: false,
+                ..RangeOption::default()
+            },
+            true,
+        );
+
+        while let Some(value) = values.try_next().await.map_err(into_error)? {
+            if value.key().len() == key_len {
+                count += 1;
+            }
+        }
+
+        Ok(count)
+    }
+
+    // FoundationDB has no native range count, keys are streamed and counted
+    // using as many transactions as needed to stay within the time limit.
+    pub(crate) async fn get_keys_count(&self, from: impl Key, to: impl Key) -> trc::Result<u64> {
+        let mut begin = from.serialize(WITH_SUBSPACE);
+        let end = to.serialize(WITH_SUBSPACE);
+        let mut begin_selector = KeySelector::first_greater_or_equal(&begin);
+        let mut count = 0;
+
+        loop {
+            let mut last_key_bytes = None;
+
+            {
+                let trx = self.timed_read_trx().await?;
+                let mut values = trx.as_ref().get_ranges(
+                    RangeOption {
+                        begin: begin_selector,
+                        end: KeySelector::first_greater_or_equal(&end),
+                        mode: options::StreamingMode::WantAll,
+                        reverse: false,
+                        ..Default::default()
+                    },
+                    true,
+                );
+
+                while let Some(values) = values.try_next().await.map_err(into_error)? {
+                    count += values.len() as u64;
+
+                    if values.more() && trx.is_expired() {
+                        last_key_bytes = values.last().map(|value| value.key().to_vec());
+                        break;
+                    }
+                }
+            }
+
+            if let Some(last_key_bytes) = last_key_bytes {
+                begin = last_key_bytes;
+                begin_selector = KeySelector::first_greater_than(&begin);
+            } else {
+                break;
+            }
+        }
+
+        Ok(count)
+    }
+
+    pub(crate) async fn get_counters(
+        &self,
+        from: impl Key,
+        to: impl Key,
+    ) -> trc::Result<Vec<(Vec<u8>, i64)>> {
+        let mut begin = from.serialize(WITH_SUBSPACE);
+        let end = to.serialize(WITH_SUBSPACE);
+        let mut begin_selector = KeySelector::first_greater_or_equal(&begin);
+        let mut counters = Vec::new();
+
+        loop {
+            let mut last_key_bytes = None;
+
+            {
+                let trx = self.timed_read_trx().await?;
+                let mut values = trx.as_ref().get_ranges(
+                    RangeOption {
+                        begin: begin_selector,
+                        end: KeySelector::first_greater_or_equal(&end),
+                        mode: options::StreamingMode::WantAll,
+                        reverse: false,
+                        ..Default::default()
+                    },
+                    true,
+                );
+
+                while let Some(values) = values.try_next().await.map_err(into_error)? {
+                    for value in values.iter() {
+                        let key = value.key();
+                        counters.push((
+                            key.get(1..).unwrap_or_default().to_vec(),
+                            deserialize_i64_le(key, value.value())?,
+                        ));
+                    }
+
+                    if values.more() && trx.is_expired() {
+                        last_key_bytes = values.last().map(|value| value.key().to_vec());
+                        break;
+                    }
+                }
+            }
+
+            if let Some(last_key_bytes) = last_key_bytes {
+                begin = last_key_bytes;
+                begin_selector = KeySelector::first_greater_than(&begin);
+            } else {
+                break;
+            }
+        }
+
+        Ok(counters)
+    }
+
     pub(crate) async fn iterate<T: Key>(
         &self,
         params: IterateParams<T>,
@@ -166,6 +343,52 @@ impl FdbStore {
         }
     }
 
+    pub(crate) async fn get_value_and_counter(
+        &self,
+        value_key: ValueKey<ValueClass<u32>>,
+        counter_key: ValueKey<ValueClass<u32>>,
+    ) -> trc::Result<(Option<Vec<u8>>, i64)> {
+        let value_key = value_key.serialize(WITH_SUBSPACE);
+        let counter_key = counter_key.serialize(WITH_SUBSPACE);
+        let trx = self.read_trx().await?;
+
+        // Both keys are read concurrently within the same transaction
+        let (value, counter) =
+            futures::future::try_join(read_chunked_value(&value_key, &trx, true), async {
+                trx.get(&counter_key, true).await.map_err(into_error)
+            })
+            .await?;
+
+        Ok((
+            match value {
+                ChunkedValue::Single(bytes) => Some(bytes.to_vec()),
+                ChunkedValue::Chunked { bytes, .. } => Some(bytes),
+                ChunkedValue::None => None,
+            },
+            if let Some(bytes) = counter {
+                deserialize_i64_le(&counter_key, &bytes)?
+            } else {
+                0
+            },
+        ))
+    }
+
+    pub(crate) async fn get_storage_statistics(&self) -> trc::Result<StorageStats> {
+        let trx = self.read_trx().await?;
+        let mut stats = StorageStats::default();
+
+        // FoundationDB only provides sampled size estimates, there is no row count
+        for subspace in SUBSPACES {
+            let size = trx
+                .get_estimated_range_size_bytes(&[subspace], &[subspace + 1])
+                .await
+                .map_err(into_error)?;
+            stats.subspace_sizes.insert(subspace, size as u64);
+        }
+
+        Ok(stats)
+    }
+
     pub(crate) async fn read_trx(&self) -> trc::Result<Transaction> {
         let (is_expired, mut read_version) = {
             let version = self.version.lock();
@@ -4,27 +4,24 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::{
-    cmp::Ordering,
-    time::{Duration, Instant},
-};
+use std::{cmp::Ordering, time::Instant};
 
 use foundationdb::{
     options::{self, MutationType, StreamingMode},
     FdbError, KeySelector, RangeOption, Transaction,
 };
 use futures::TryStreamExt;
-use rand::Rng;
 use roaring::RoaringBitmap;
 
 use crate::{
     backend::deserialize_i64_le,
     write::{
+        commit_backoff,
         key::{DeserializeBigEndian, KeySerializer},
-        AssignedIds, Batch, BitmapClass, Operation, RandomAvailableId, ValueOp,
-        MAX_COMMIT_ATTEMPTS, MAX_COMMIT_TIME,
+        now, AssignedIds, Batch, BitmapClass, Operation, RandomAvailableId, ValueOp,
     },
-    BitmapKey, IndexKey, Key, LogKey, SUBSPACE_COUNTER, SUBSPACE_QUOTA, U32_LEN, WITH_SUBSPACE,
+    BitmapKey, IndexKey, Key, LogKey, RecentKey, SUBSPACE_COUNTER, SUBSPACE_QUOTA, U32_LEN,
+    WITH_SUBSPACE,
 };
 
 use super::{
@@ -33,6 +30,8 @@ use super::{
     FdbStore, ReadVersion, MAX_VALUE_SIZE,
 };
 
+const RENAME_BATCH_SIZE: usize = 1000;
+
 impl FdbStore {
     pub(crate) async fn write(&self, batch: Batch) -> trc::Result<AssignedIds> {
         let start = Instant::now();
@@ -70,7 +69,7 @@ impl FdbStore {
                         change_id = *change_id_;
                     }
                     Operation::Value { class, op } => {
-                        let mut key = class.serialize(
+                        let key = class.serialize(
                             account_id,
                             collection,
                             document_id,
@@ -83,31 +82,36 @@ impl FdbStore {
                             ValueOp::Set(value) => {
                                 let value = value.resolve(&result)?;
                                 if !value.is_empty() && do_chunk {
-                                    for (pos, chunk) in value.chunks(MAX_VALUE_SIZE).enumerate() {
-                                        match pos.cmp(&1) {
-                                            Ordering::Less => {}
-                                            Ordering::Equal => {
-                                                key.push(0);
-                                            }
-                                            Ordering::Greater => {
-                                                if pos < u8::MAX as usize {
-                                                    *key.last_mut().unwrap() += 1;
-                                                } else {
-                                                    trx.cancel();
-                                                    return Err(trc::StoreEvent::FoundationdbError
-                                                        .ctx(
-                                                            trc::Key::Reason,
-                                                            "Value is too large",
-                                                        ));
-                                                }
-                                            }
-                                        }
-                                        trx.set(&key, chunk);
+                                    if let Err(err) = set_chunked(&trx, key, &value) {
+                                        trx.cancel();
+                                        return Err(err);
                                     }
                                 } else {
                                     trx.set(&key, value.as_ref());
                                 }
                             }
+                            ValueOp::Append { value, max_length } => {
+                                let mut bytes = match read_chunked_value(&key, &trx, false).await? {
+                                    ChunkedValue::Single(bytes) => bytes.to_vec(),
+                                    ChunkedValue::Chunked { bytes, .. } => bytes,
+                                    ChunkedValue::None => Vec::new(),
+                                };
+                                bytes.extend_from_slice(value.resolve(&result)?.as_ref());
+                                if bytes.len() > *max_length {
+                                    trx.cancel();
+                                    return Err(trc::StoreEvent::ValueTooLarge
+                                        .ctx(trc::Key::Size, bytes.len())
+                                        .ctx(trc::Key::Limit, *max_length));
+                                }
+                                if !bytes.is_empty() && do_chunk {
+                                    if let Err(err) = set_chunked(&trx, key, &bytes) {
+                                        trx.cancel();
+                                        return Err(err);
+                                    }
+                                } else {
+                                    trx.set(&key, &bytes);
+                                }
+                            }
                             ValueOp::AtomicAdd(by) => {
                                 trx.atomic_op(&key, &by.to_le_bytes()[..], MutationType::Add);
                             }
@@ -137,6 +141,30 @@ impl FdbStore {
                             }
                         }
                     }
+                    Operation::SetMany { class, items } => {
+                        let do_chunk = !class.is_counter(collection);
+                        for (document_id, value) in items {
+                            let key = class.serialize(
+                                account_id,
+                                collection,
+                                *document_id,
+                                WITH_SUBSPACE,
+                                (&result).into(),
+                            );
+                            let value = value.resolve(&result)?;
+                            if !value.is_empty() && do_chunk {
+                                if let Err(err) = set_chunked(&trx, key, &value) {
+                                    trx.cancel();
+                                    return Err(err);
+                                }
+                            } else {
+                                trx.set(&key, value.as_ref());
+                            }
+                        }
+                        for (document_id, _) in items {
+                            result.push_document_id(*document_id);
+                        }
+                    }
                     Operation::Index { field, key, set } => {
                         let key = IndexKey {
                             account_id,
@@ -225,6 +253,18 @@ impl FdbStore {
                         } else {
                             trx.clear(&key);
                         }
+
+                        // Track newly created documents
+                        if *set && matches!(class, BitmapClass::DocumentIds) {
+                            let key = RecentKey {
+                                account_id,
+                                collection,
+                                timestamp: now(),
+                                document_id,
+                            }
+                            .serialize(WITH_SUBSPACE);
+                            trx.set(&key, &[]);
+                        }
                     }
                     Operation::Log { set } => {
                         let key = LogKey {
@@ -235,6 +275,15 @@ impl FdbStore {
                         .serialize(WITH_SUBSPACE);
                         trx.set(&key, set.resolve(&result)?.as_ref());
                     }
+                    Operation::LogWithTimestamp { timestamp, set } => {
+                        let key = LogKey {
+                            account_id,
+                            collection,
+                            change_id,
+                        }
+                        .serialize_with_timestamp(WITH_SUBSPACE, *timestamp);
+                        trx.set(&key, set.resolve(&result)?.as_ref());
+                    }
                     Operation::AssertValue {
                         class,
                         assert_value,
@@ -267,14 +316,121 @@ impl FdbStore {
             if self
                 .commit(
                     trx,
-                    retry_count < MAX_COMMIT_ATTEMPTS && start.elapsed() < MAX_COMMIT_TIME,
+                    retry_count < self.commit_limits.max_attempts
+                        && start.elapsed() < self.commit_limits.max_time,
                 )
                 .await?
             {
                 return Ok(result);
             } else {
-                let backoff = rand::thread_rng().gen_range(50..=300);
-                tokio::time::sleep(Duration::from_millis(backoff)).await;
+                batch.check_deadline(start, &self.commit_limits)?;
+                tokio::time::sleep(commit_backoff()).await;
+                retry_count += 1;
+            }
+        }
+    }
+
+    pub(crate) async fn atomic_swap(
+        &self,
+        key: impl Key,
+        new_value: Vec<u8>,
+    ) -> trc::Result<Option<Vec<u8>>> {
+        if new_value.len() > MAX_VALUE_SIZE * u8::MAX as usize {
+            return Err(trc::StoreEvent::ValueTooLarge
+                .ctx(trc::Key::Size, new_value.len())
+                .ctx(trc::Key::Limit, MAX_VALUE_SIZE * u8::MAX as usize));
+        }
+        let key = key.serialize(WITH_SUBSPACE);
+        let start = Instant::now();
+        let mut retry_count = 0;
+
+        loop {
+            let trx = self.db.create_trx().map_err(into_error)?;
+
+            // Non-snapshot read, the key is added to the read conflict range
+            let old_value = match read_chunked_value(&key, &trx, false).await? {
+                ChunkedValue::Single(bytes) => Some(bytes.to_vec()),
+                ChunkedValue::Chunked { bytes, .. } => Some(bytes),
+                ChunkedValue::None => None,
+            };
+
+            // Remove any existing chunks before writing the new value
+            trx.clear_range(
+                &key,
+                &KeySerializer::new(key.len() + 1)
+                    .write(key.as_slice())
+                    .write(u8::MAX)
+                    .finalize(),
+            );
+            let mut chunk_key = key.clone();
+            for (pos, chunk) in new_value.chunks(MAX_VALUE_SIZE).enumerate() {
+                match pos.cmp(&1) {
+                    Ordering::Less => {}
+                    Ordering::Equal => {
+                        chunk_key.push(0);
+                    }
+                    Ordering::Greater => {
+                        *chunk_key.last_mut().unwrap() += 1;
+                    }
+                }
+                trx.set(&chunk_key, chunk);
+            }
+            if new_value.is_empty() {
+                trx.set(&key, &[]);
+            }
+
+            if self
+                .commit(
+                    trx,
+                    retry_count < self.commit_limits.max_attempts
+                        && start.elapsed() < self.commit_limits.max_time,
+                )
+                .await?
+            {
+                return Ok(old_value);
+            } else {
+                tokio::time::sleep(commit_backoff()).await;
+                retry_count += 1;
+            }
+        }
+    }
+
+    pub(crate) async fn compare_and_increment(
+        &self,
+        key: impl Key,
+        expected_version: u64,
+        by: i64,
+    ) -> trc::Result<i64> {
+        let key = key.serialize(WITH_SUBSPACE);
+        let expected_version = expected_version as i64;
+        let start = Instant::now();
+        let mut retry_count = 0;
+
+        loop {
+            let trx = self.db.create_trx().map_err(into_error)?;
+
+            // Non-snapshot read, a concurrent increment makes the commit conflict
+            let current = if let Some(bytes) = trx.get(&key, false).await.map_err(into_error)? {
+                deserialize_i64_le(&key, &bytes)?
+            } else {
+                0
+            };
+            if current != expected_version {
+                return Err(trc::StoreEvent::AssertValueFailed.into());
+            }
+            trx.atomic_op(&key, &by.to_le_bytes()[..], MutationType::Add);
+
+            if self
+                .commit(
+                    trx,
+                    retry_count < self.commit_limits.max_attempts
+                        && start.elapsed() < self.commit_limits.max_time,
+                )
+                .await?
+            {
+                return Ok(current + by);
+            } else {
+                tokio::time::sleep(commit_backoff()).await;
                 retry_count += 1;
             }
         }
@@ -341,7 +497,10 @@ impl FdbStore {
                     trx.atomic_op(key, &integer, MutationType::CompareAndClear);
                 }
 
-                if self.commit(trx, retry_count < MAX_COMMIT_ATTEMPTS).await? {
+                if self
+                    .commit(trx, retry_count < self.commit_limits.max_attempts)
+                    .await?
+                {
                     break;
                 } else {
                     retry_count += 1;
@@ -352,6 +511,72 @@ impl FdbStore {
         Ok(())
     }
 
+    pub(crate) async fn get_and_reset_counter(&self, key: impl Key) -> trc::Result<i64> {
+        let key = key.serialize(WITH_SUBSPACE);
+        let start = Instant::now();
+        let mut retry_count = 0;
+
+        loop {
+            let trx = self.db.create_trx().map_err(into_error)?;
+
+            // Non-snapshot read, a concurrent increment makes the commit conflict
+            let current = if let Some(bytes) = trx.get(&key, false).await.map_err(into_error)? {
+                deserialize_i64_le(&key, &bytes)?
+            } else {
+                return Ok(0);
+            };
+            trx.set(&key, &0i64.to_le_bytes()[..]);
+
+            if self
+                .commit(
+                    trx,
+                    retry_count < self.commit_limits.max_attempts
+                        && start.elapsed() < self.commit_limits.max_time,
+                )
+                .await?
+            {
+                return Ok(current);
+            } else {
+                tokio::time::sleep(commit_backoff()).await;
+                retry_count += 1;
+            }
+        }
+    }
+
+    // MutationType::ByteMax compares values as unsigned integers, which would
+    // rank negative values above positive ones, so the maximum is computed here.
+    pub(crate) async fn atomic_max(&self, key: impl Key, value: i64) -> trc::Result<i64> {
+        let key = key.serialize(WITH_SUBSPACE);
+        let start = Instant::now();
+        let mut retry_count = 0;
+
+        loop {
+            let trx = self.db.create_trx().map_err(into_error)?;
+
+            if let Some(bytes) = trx.get(&key, false).await.map_err(into_error)? {
+                let current = deserialize_i64_le(&key, &bytes)?;
+                if current >= value {
+                    return Ok(current);
+                }
+            }
+            trx.set(&key, &value.to_le_bytes()[..]);
+
+            if self
+                .commit(
+                    trx,
+                    retry_count < self.commit_limits.max_attempts
+                        && start.elapsed() < self.commit_limits.max_time,
+                )
+                .await?
+            {
+                return Ok(value);
+            } else {
+                tokio::time::sleep(commit_backoff()).await;
+                retry_count += 1;
+            }
+        }
+    }
+
     pub(crate) async fn delete_range(&self, from: impl Key, to: impl Key) -> trc::Result<()> {
         let from = from.serialize(WITH_SUBSPACE);
         let to = to.serialize(WITH_SUBSPACE);
@@ -360,4 +585,73 @@ impl FdbStore {
         trx.clear_range(&from, &to);
         self.commit(trx, false).await.map(|_| ())
     }
+
+    // Keys are moved in batches to stay within the transaction limits, the
+    // operation can be resumed by calling it again after a failure.
+    pub(crate) async fn rename_range(
+        &self,
+        from: impl Key,
+        to: impl Key,
+        account_id: u32,
+    ) -> trc::Result<u64> {
+        let from = from.serialize(WITH_SUBSPACE);
+        let to = to.serialize(WITH_SUBSPACE);
+        let mut renamed = 0;
+
+        loop {
+            let trx = self.db.create_trx().map_err(into_error)?;
+            let values = trx
+                .get_range(
+                    &RangeOption {
+                        begin: KeySelector::first_greater_or_equal(&from),
+                        end: KeySelector::first_greater_or_equal(&to),
+                        mode: StreamingMode::WantAll,
+                        limit: Some(RENAME_BATCH_SIZE),
+                        reverse: false,
+                        ..Default::default()
+                    },
+                    0,
+                    false,
+                )
+                .await
+                .map_err(into_error)?;
+            if values.is_empty() {
+                return Ok(renamed);
+            }
+
+            for value in values.iter() {
+                let mut new_key = value.key().to_vec();
+                new_key[1..U32_LEN + 1].copy_from_slice(&account_id.to_be_bytes());
+                trx.set(&new_key, value.value());
+                trx.clear(value.key());
+            }
+            self.commit(trx, false).await?;
+            renamed += values.len() as u64;
+        }
+    }
+}
+
+// Values larger than MAX_VALUE_SIZE are split into chunks stored under
+// the original key followed by a chunk number
+fn set_chunked(trx: &Transaction, mut key: Vec<u8>, value: &[u8]) -> trc::Result<()> {
+    for (pos, chunk) in value.chunks(MAX_VALUE_SIZE).enumerate() {
+        match pos.cmp(&1) {
+            Ordering::Less => {}
+            Ordering::Equal => {
+                key.push(0);
+            }
+            Ordering::Greater => {
+                if pos < u8::MAX as usize {
+                    *key.last_mut().unwrap() += 1;
+                } else {
+                    return Err(trc::StoreEvent::ValueTooLarge
+                        .ctx(trc::Key::Size, value.len())
+                        .ctx(trc::Key::Limit, MAX_VALUE_SIZE * u8::MAX as usize));
+                }
+            }
+        }
+        trx.set(&key, chunk);
+    }
+
+    Ok(())
 }
@@ -4,7 +4,11 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::{io::SeekFrom, ops::Range, path::PathBuf};
+use std::{
+    io::{ErrorKind, SeekFrom},
+    ops::Range,
+    path::PathBuf,
+};
 
 use tokio::{
     fs::{self, File},
@@ -15,6 +19,8 @@ use utils::{
     config::{utils::AsKey, Config},
 };
 
+use crate::BlobStream;
+
 pub struct FsStore {
     path: PathBuf,
     hash_levels: usize,
@@ -81,6 +87,14 @@ impl FsStore {
         }))
     }
 
+    pub(crate) async fn get_blob_stream(&self, key: &[u8]) -> trc::Result<Option<BlobStream>> {
+        match File::open(self.build_path(key)).await {
+            Ok(blob) => Ok(Some(Box::pin(blob))),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(into_error(err)),
+        }
+    }
+
     pub(crate) async fn put_blob(&self, key: &[u8], data: &[u8]) -> trc::Result<()> {
         let blob_path = self.build_path(key);
 
@@ -5,14 +5,23 @@
  */
 
 use ahash::AHashMap;
+use parking_lot::Mutex;
 use utils::{config::Config, glob::GlobPattern};
 
-use crate::{LookupStore, Stores, Value};
+use crate::{write::now, LookupStore, Stores, Value};
 
 #[derive(Debug, Default)]
 pub struct MemoryStore {
     entries: AHashMap<String, Value<'static>>,
     globs: Vec<(GlobPattern, Value<'static>)>,
+    // Counters are local to this process
+    counters: Mutex<AHashMap<Vec<u8>, MemoryCounter>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct MemoryCounter {
+    value: i64,
+    expires: u64,
 }
 
 impl MemoryStore {
@@ -23,6 +32,43 @@ impl MemoryStore {
                 .find_map(|(pattern, value)| pattern.matches(id).then_some(value))
         })
     }
+
+    pub fn counter_incr(&self, key: Vec<u8>, value: i64, expires: Option<u64>) -> i64 {
+        let now = now();
+        let mut counters = self.counters.lock();
+        let counter = counters.entry(key).or_insert(MemoryCounter {
+            value: 0,
+            expires: u64::MAX,
+        });
+        if counter.expires <= now {
+            counter.value = 0;
+            counter.expires = u64::MAX;
+        }
+        counter.value += value;
+        if let Some(expires) = expires {
+            counter.expires = now + expires;
+        }
+        counter.value
+    }
+
+    pub fn counter_get(&self, key: &[u8]) -> i64 {
+        self.counters
+            .lock()
+            .get(key)
+            .filter(|counter| counter.expires > now())
+            .map_or(0, |counter| counter.value)
+    }
+
+    pub fn counter_delete(&self, key: &[u8]) {
+        self.counters.lock().remove(key);
+    }
+
+    pub fn purge_counters(&self) {
+        let now = now();
+        self.counters
+            .lock()
+            .retain(|_, counter| counter.expires > now);
+    }
 }
 
 impl Stores {
@@ -0,0 +1,373 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{collections::HashMap, ops::Range};
+
+use parking_lot::Mutex;
+use roaring::RoaringBitmap;
+
+use crate::{
+    write::{
+        key::DeserializeBigEndian, AssignedIds, Batch, BatchBuilder, BitmapClass, Operation,
+        ValueClass,
+    },
+    BitmapKey, Deserialize, IterateParams, Key, StorageStats, ValueKey, U32_LEN, WITH_SUBSPACE,
+};
+
+use super::deserialize_i64_le;
+
+// In-memory store for tests. Batches are recorded instead of applied and
+// reads are served from values stubbed with the expect_* methods.
+#[derive(Default)]
+pub struct MockStore {
+    batches: Mutex<Vec<Batch>>,
+    values: Mutex<HashMap<Vec<u8>, Vec<u8>>>,
+    blobs: Mutex<HashMap<Vec<u8>, Vec<u8>>>,
+}
+
+impl MockStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn expect_get_value(&self, key: impl Key, response: impl Into<Vec<u8>>) -> &Self {
+        self.values
+            .lock()
+            .insert(key.serialize(WITH_SUBSPACE), response.into());
+        self
+    }
+
+    pub fn expect_counter(&self, key: impl Key, value: i64) -> &Self {
+        self.expect_get_value(key, value.to_le_bytes())
+    }
+
+    pub fn expect_blob(&self, key: &[u8], data: impl Into<Vec<u8>>) -> &Self {
+        self.blobs.lock().insert(key.to_vec(), data.into());
+        self
+    }
+
+    // Builds a batch to compare against the recorded ones
+    pub fn expected_batch(build: impl FnOnce(&mut BatchBuilder)) -> Batch {
+        let mut batch = BatchBuilder::new();
+        build(&mut batch);
+        batch.build()
+    }
+
+    pub fn batch_count(&self) -> usize {
+        self.batches.lock().len()
+    }
+
+    pub fn take_batches(&self) -> Vec<Batch> {
+        std::mem::take(&mut *self.batches.lock())
+    }
+
+    pub fn clear(&self) {
+        self.batches.lock().clear();
+        self.values.lock().clear();
+        self.blobs.lock().clear();
+    }
+
+    #[track_caller]
+    pub fn assert_batch_count(&self, expected: usize) {
+        let batches = self.batches.lock();
+        assert_eq!(
+            batches.len(),
+            expected,
+            "expected {expected} batches, got {batches:#?}"
+        );
+    }
+
+    #[track_caller]
+    pub fn assert_written_operation(&self, op: &Operation) {
+        let batches = self.batches.lock();
+        assert!(
+            batches.iter().any(|batch| batch.ops.contains(op)),
+            "operation {op:?} not found in {batches:#?}"
+        );
+    }
+
+    #[track_caller]
+    pub fn assert_not_written_operation(&self, op: &Operation) {
+        let batches = self.batches.lock();
+        assert!(
+            !batches.iter().any(|batch| batch.ops.contains(op)),
+            "operation {op:?} unexpectedly found in {batches:#?}"
+        );
+    }
+
+    #[track_caller]
+    pub fn assert_batch(&self, index: usize, expected: &Batch) {
+        let batches = self.batches.lock();
+        let batch = batches
+            .get(index)
+            .unwrap_or_else(|| panic!("batch {index} not found in {batches:#?}"));
+        assert_eq!(batch.ops, expected.ops, "batch {index} does not match");
+    }
+
+    pub(crate) async fn get_value<U>(&self, key: impl Key) -> trc::Result<Option<U>>
+    where
+        U: Deserialize + 'static,
+    {
+        self.values
+            .lock()
+            .get(&key.serialize(WITH_SUBSPACE))
+            .map(|bytes| U::deserialize(bytes))
+            .transpose()
+    }
+
+    pub(crate) async fn get_values<K: Key>(
+        &self,
+        keys: Vec<K>,
+    ) -> trc::Result<Vec<Option<Vec<u8>>>> {
+        let values = self.values.lock();
+        Ok(keys
+            .iter()
+            .map(|key| values.get(&key.serialize(WITH_SUBSPACE)).cloned())
+            .collect())
+    }
+
+    pub(crate) async fn get_value_size(&self, key: impl Key) -> trc::Result<Option<usize>> {
+        Ok(self
+            .values
+            .lock()
+            .get(&key.serialize(WITH_SUBSPACE))
+            .map(|bytes| bytes.len()))
+    }
+
+    // Bitmaps are stubbed as one key per document, as backends store them
+    pub(crate) async fn get_bitmap(
+        &self,
+        mut key: BitmapKey<BitmapClass<u32>>,
+    ) -> trc::Result<Option<RoaringBitmap>> {
+        key.document_id = 0;
+        let begin = key.clone();
+        key.document_id = u32::MAX;
+        let key_len = begin.serialize(WITH_SUBSPACE).len();
+
+        let mut bm = RoaringBitmap::new();
+        for (key, _) in self.range(&begin, &key, true) {
+            let key = key.as_slice();
+            if key.len() == key_len {
+                bm.insert(key.deserialize_be_u32(key.len() - U32_LEN)?);
+            }
+        }
+        Ok(if !bm.is_empty() { Some(bm) } else { None })
+    }
+
+    pub(crate) async fn get_bitmap_cardinality(
+        &self,
+        key: BitmapKey<BitmapClass<u32>>,
+    ) -> trc::Result<u64> {
+        self.get_bitmap(key)
+            .await
+            .map(|bitmap| bitmap.map_or(0, |bitmap| bitmap.len()))
+    }
+
+    pub(crate) async fn get_keys_count(&self, from: impl Key, to: impl Key) -> trc::Result<u64> {
+        Ok(self.range(&from, &to, true).len() as u64)
+    }
+
+    pub(crate) async fn get_counters(
+        &self,
+        from: impl Key,
+        to: impl Key,
+    ) -> trc::Result<Vec<(Vec<u8>, i64)>> {
+        self.range(&from, &to, false)
+            .into_iter()
+            .map(|(key, value)| Ok((key[1..].to_vec(), deserialize_i64_le(&key, &value)?)))
+            .collect()
+    }
+
+    pub(crate) async fn iterate<T: Key>(
+        &self,
+        params: IterateParams<T>,
+        mut cb: impl for<'x> FnMut(&'x [u8], &'x [u8]) -> trc::Result<bool> + Sync + Send,
+    ) -> trc::Result<()> {
+        let mut entries = self.range(&params.begin, &params.end, true);
+        if !params.ascending {
+            entries.reverse();
+        }
+        if params.first {
+            entries.truncate(1);
+        }
+
+        for (key, value) in entries {
+            let value = if params.values { &value[..] } else { &[] };
+            if !cb(&key[1..], value)? {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) async fn get_counter(
+        &self,
+        key: impl Into<ValueKey<ValueClass<u32>>> + Sync + Send,
+    ) -> trc::Result<i64> {
+        let key = key.into().serialize(WITH_SUBSPACE);
+        match self.values.lock().get(&key) {
+            Some(bytes) => deserialize_i64_le(&key, bytes),
+            None => Ok(0),
+        }
+    }
+
+    pub(crate) async fn get_value_and_counter(
+        &self,
+        value_key: ValueKey<ValueClass<u32>>,
+        counter_key: ValueKey<ValueClass<u32>>,
+    ) -> trc::Result<(Option<Vec<u8>>, i64)> {
+        let value = self
+            .values
+            .lock()
+            .get(&value_key.serialize(WITH_SUBSPACE))
+            .cloned();
+        Ok((value, self.get_counter(counter_key).await?))
+    }
+
+    // Batches are recorded but never applied, stubbed values are left untouched
+    pub(crate) async fn write(&self, batch: Batch) -> trc::Result<AssignedIds> {
+        let mut result = AssignedIds::default();
+        for op in &batch.ops {
+            if let Operation::Bitmap {
+                class: BitmapClass::DocumentIds,
+                set: true,
+            } = op
+            {
+                result.document_ids.push(result.document_ids.len() as u32);
+            }
+        }
+        self.batches.lock().push(batch);
+
+        Ok(result)
+    }
+
+    pub(crate) async fn atomic_swap(
+        &self,
+        key: impl Key,
+        new_value: Vec<u8>,
+    ) -> trc::Result<Option<Vec<u8>>> {
+        Ok(self
+            .values
+            .lock()
+            .insert(key.serialize(WITH_SUBSPACE), new_value))
+    }
+
+    pub(crate) async fn compare_and_increment(
+        &self,
+        key: impl Key,
+        expected_version: u64,
+        by: i64,
+    ) -> trc::Result<i64> {
+        let key = key.serialize(WITH_SUBSPACE);
+        let mut values = self.values.lock();
+        let current = values
+            .get(&key)
+            .map(|bytes| deserialize_i64_le(&key, bytes))
+            .transpose()?
+            .unwrap_or_default();
+        if current != expected_version as i64 {
+            return Err(trc::StoreEvent::AssertValueFailed.into());
+        }
+        let value = current + by;
+        values.insert(key, value.to_le_bytes().to_vec());
+
+        Ok(value)
+    }
+
+    pub(crate) async fn get_and_reset_counter(&self, key: impl Key) -> trc::Result<i64> {
+        let key = key.serialize(WITH_SUBSPACE);
+        let mut values = self.values.lock();
+        match values.get_mut(&key) {
+            Some(bytes) => {
+                let current = deserialize_i64_le(&key, bytes)?;
+                *bytes = 0i64.to_le_bytes().to_vec();
+                Ok(current)
+            }
+            None => Ok(0),
+        }
+    }
+
+    pub(crate) async fn atomic_max(&self, key: impl Key, value: i64) -> trc::Result<i64> {
+        let key = key.serialize(WITH_SUBSPACE);
+        let mut values = self.values.lock();
+        if let Some(bytes) = values.get(&key) {
+            let current = deserialize_i64_le(&key, bytes)?;
+            if current >= value {
+                return Ok(current);
+            }
+        }
+        values.insert(key, value.to_le_bytes().to_vec());
+
+        Ok(value)
+    }
+
+    pub(crate) async fn delete_range(&self, from: impl Key, to: impl Key) -> trc::Result<()> {
+        let keys = self.range(&from, &to, false);
+        let mut values = self.values.lock();
+        for (key, _) in keys {
+            values.remove(&key);
+        }
+
+        Ok(())
+    }
+
+    pub(crate) async fn rename_range(
+        &self,
+        _from: impl Key,
+        _to: impl Key,
+        _account_id: u32,
+    ) -> trc::Result<u64> {
+        Err(trc::StoreEvent::NotSupported.into_err())
+    }
+
+    pub(crate) async fn get_storage_statistics(&self) -> trc::Result<StorageStats> {
+        Ok(StorageStats::default())
+    }
+
+    pub(crate) async fn purge_store(&self) -> trc::Result<()> {
+        Ok(())
+    }
+
+    pub(crate) async fn get_blob(
+        &self,
+        key: &[u8],
+        range: Range<usize>,
+    ) -> trc::Result<Option<Vec<u8>>> {
+        Ok(self.blobs.lock().get(key).map(|data| {
+            let end = range.end.min(data.len());
+            data[range.start.min(end)..end].to_vec()
+        }))
+    }
+
+    pub(crate) async fn put_blob(&self, key: &[u8], data: &[u8]) -> trc::Result<()> {
+        self.blobs.lock().insert(key.to_vec(), data.to_vec());
+        Ok(())
+    }
+
+    pub(crate) async fn delete_blob(&self, key: &[u8]) -> trc::Result<bool> {
+        Ok(self.blobs.lock().remove(key).is_some())
+    }
+
+    // Stubbed values in the subspace of `from`, sorted by key
+    fn range(&self, from: &impl Key, to: &impl Key, inclusive: bool) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let from = from.serialize(WITH_SUBSPACE);
+        let to = to.serialize(WITH_SUBSPACE);
+        let mut entries = self
+            .values
+            .lock()
+            .iter()
+            .filter(|(key, _)| {
+                key.first() == from.first()
+                    && key[..] >= from[..]
+                    && (key[..] < to[..] || (inclusive && key[..] == to[..]))
+            })
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect::<Vec<_>>();
+        entries.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        entries
+    }
+}
@@ -4,6 +4,9 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+#[cfg(feature = "azure")]
+pub mod azure;
+pub mod circuit_breaker;
 #[cfg(feature = "enterprise")]
 pub mod composite;
 #[cfg(feature = "elastic")]
@@ -12,8 +15,12 @@ pub mod elastic;
 pub mod foundationdb;
 pub mod fs;
 pub mod memory;
+#[cfg(feature = "test_mode")]
+pub mod mock;
 #[cfg(feature = "mysql")]
 pub mod mysql;
+#[cfg(feature = "nats")]
+pub mod nats;
 #[cfg(feature = "postgres")]
 pub mod postgres;
 #[cfg(feature = "redis")]
@@ -24,12 +31,30 @@ pub mod rocksdb;
 pub mod s3;
 #[cfg(feature = "sqlite")]
 pub mod sqlite;
-#[cfg(feature = "azure")]
-pub mod azure;
 
 pub const MAX_TOKEN_LENGTH: usize = (u8::MAX >> 1) as usize;
 pub const MAX_TOKEN_MASK: usize = MAX_TOKEN_LENGTH - 1;
 
+// Groups keys by subspace, mapping each serialized key to the positions it was requested at
+#[allow(dead_code)]
+fn group_keys_by_subspace(
+    keys: &[impl crate::Key],
+) -> ahash::AHashMap<u8, ahash::AHashMap<Vec<u8>, Vec<usize>>> {
+    use crate::Key;
+
+    let mut subspaces: ahash::AHashMap<u8, ahash::AHashMap<Vec<u8>, Vec<usize>>> =
+        ahash::AHashMap::new();
+    for (pos, key) in keys.iter().enumerate() {
+        subspaces
+            .entry(key.subspace())
+            .or_default()
+            .entry(key.serialize(0))
+            .or_default()
+            .push(pos);
+    }
+    subspaces
+}
+
 #[allow(dead_code)]
 fn deserialize_i64_le(key: &[u8], bytes: &[u8]) -> trc::Result<i64> {
     Ok(i64::from_le_bytes(bytes[..].try_into().map_err(|_| {
@@ -9,7 +9,10 @@ use std::time::Duration;
 use mysql_async::{prelude::Queryable, OptsBuilder, Pool, PoolConstraints, PoolOpts, SslOpts};
 use utils::config::{utils::AsKey, Config};
 
-use crate::*;
+use crate::{
+    config::{parse_commit_limits, parse_expiring_subspaces, parse_max_value_size},
+    *,
+};
 
 use super::{into_error, MysqlStore};
 
@@ -76,6 +79,9 @@ impl MysqlStore {
 
         let db = Self {
             conn_pool: Pool::new(opts),
+            max_value_size: parse_max_value_size(config, &prefix),
+            expiring_subspaces: parse_expiring_subspaces(config, &prefix),
+            commit_limits: parse_commit_limits(config, &prefix),
         };
 
         if create_tables {
@@ -108,6 +114,8 @@ impl MysqlStore {
             SUBSPACE_TELEMETRY_SPAN,
             SUBSPACE_TELEMETRY_METRIC,
             SUBSPACE_TELEMETRY_INDEX,
+            SUBSPACE_QUARANTINE,
+            SUBSPACE_AUDIT,
         ] {
             let table = char::from(table);
             conn.query_drop(format!(
@@ -137,6 +145,7 @@ impl MysqlStore {
             SUBSPACE_BITMAP_ID,
             SUBSPACE_BITMAP_TAG,
             SUBSPACE_BITMAP_TEXT,
+            SUBSPACE_RECENT,
         ] {
             let table = char::from(table);
             conn.query_drop(format!(
@@ -10,9 +10,11 @@ use roaring::RoaringBitmap;
 
 use crate::{
     write::{key::DeserializeBigEndian, BitmapClass, ValueClass},
-    BitmapKey, Deserialize, IterateParams, Key, ValueKey, U32_LEN,
+    BitmapKey, Deserialize, IterateParams, Key, StorageStats, ValueKey, U32_LEN,
 };
 
+use crate::backend::group_keys_by_subspace;
+
 use super::{into_error, MysqlStore};
 
 impl MysqlStore {
@@ -41,6 +43,55 @@ impl MysqlStore {
             })
     }
 
+    pub(crate) async fn get_values(
+        &self,
+        keys: Vec<impl Key>,
+    ) -> trc::Result<Vec<Option<Vec<u8>>>> {
+        let mut results = vec![None; keys.len()];
+        let mut conn = self.conn_pool.get_conn().await.map_err(into_error)?;
+
+        for (subspace, positions) in group_keys_by_subspace(&keys) {
+            let s = conn
+                .prep(format!(
+                    "SELECT k, v FROM {} WHERE k IN ({})",
+                    char::from(subspace),
+                    vec!["?"; positions.len()].join(",")
+                ))
+                .await
+                .map_err(into_error)?;
+            let keys = positions.keys().cloned().collect::<Vec<_>>();
+            for (key, value) in conn
+                .exec::<(Vec<u8>, Vec<u8>), _, _>(&s, keys)
+                .await
+                .map_err(into_error)?
+            {
+                if let Some(positions) = positions.get(&key) {
+                    for pos in positions {
+                        results[*pos] = Some(value.clone());
+                    }
+                }
+            }
+        }
+
+        Ok(results)
+    }
+
+    pub(crate) async fn get_value_size(&self, key: impl Key) -> trc::Result<Option<usize>> {
+        let mut conn = self.conn_pool.get_conn().await.map_err(into_error)?;
+        let s = conn
+            .prep(format!(
+                "SELECT LENGTH(v) FROM {} WHERE k = ?",
+                char::from(key.subspace())
+            ))
+            .await
+            .map_err(into_error)?;
+        let key = key.serialize(0);
+        conn.exec_first::<u64, _, _>(&s, (key,))
+            .await
+            .map(|size| size.map(|size| size as usize))
+            .map_err(into_error)
+    }
+
     pub(crate) async fn get_bitmap(
         &self,
         mut key: BitmapKey<BitmapClass<u32>>,
@@ -70,6 +121,66 @@ impl MysqlStore {
         Ok(if !bm.is_empty() { Some(bm) } else { None })
     }
 
+    pub(crate) async fn get_bitmap_cardinality(
+        &self,
+        mut key: BitmapKey<BitmapClass<u32>>,
+    ) -> trc::Result<u64> {
+        let begin = key.serialize(0);
+        key.document_id = u32::MAX;
+        let key_len = begin.len() as u64;
+        let end = key.serialize(0);
+        let mut conn = self.conn_pool.get_conn().await.map_err(into_error)?;
+        let table = char::from(key.subspace());
+
+        let s = conn
+            .prep(format!(
+                "SELECT COUNT(*) FROM {table} WHERE k >= ? AND k <= ? AND LENGTH(k) = ?"
+            ))
+            .await
+            .map_err(into_error)?;
+        conn.exec_first::<u64, _, _>(&s, (begin, end, key_len))
+            .await
+            .map(|count| count.unwrap_or_default())
+            .map_err(into_error)
+    }
+
+    pub(crate) async fn get_keys_count(&self, from: impl Key, to: impl Key) -> trc::Result<u64> {
+        let table = char::from(from.subspace());
+        let begin = from.serialize(0);
+        let end = to.serialize(0);
+        let mut conn = self.conn_pool.get_conn().await.map_err(into_error)?;
+
+        let s = conn
+            .prep(format!(
+                "SELECT COUNT(*) FROM {table} WHERE k >= ? AND k < ?"
+            ))
+            .await
+            .map_err(into_error)?;
+        conn.exec_first::<u64, _, _>(&s, (begin, end))
+            .await
+            .map(|count| count.unwrap_or_default())
+            .map_err(into_error)
+    }
+
+    pub(crate) async fn get_counters(
+        &self,
+        from: impl Key,
+        to: impl Key,
+    ) -> trc::Result<Vec<(Vec<u8>, i64)>> {
+        let table = char::from(from.subspace());
+        let begin = from.serialize(0);
+        let end = to.serialize(0);
+        let mut conn = self.conn_pool.get_conn().await.map_err(into_error)?;
+
+        let s = conn
+            .prep(format!("SELECT k, v FROM {table} WHERE k >= ? AND k < ?"))
+            .await
+            .map_err(into_error)?;
+        conn.exec::<(Vec<u8>, i64), _, _>(&s, (begin, end))
+            .await
+            .map_err(into_error)
+    }
+
     pub(crate) async fn iterate<T: Key>(
         &self,
         params: IterateParams<T>,
@@ -156,4 +267,58 @@ impl MysqlStore {
             Err(e) => Err(into_error(e)),
         }
     }
+
+    // Reads a value and a counter from a single statement, so both are
+    // obtained from the same snapshot of the database.
+    pub(crate) async fn get_value_and_counter(
+        &self,
+        value_key: ValueKey<ValueClass<u32>>,
+        counter_key: ValueKey<ValueClass<u32>>,
+    ) -> trc::Result<(Option<Vec<u8>>, i64)> {
+        let mut conn = self.conn_pool.get_conn().await.map_err(into_error)?;
+        let s = conn
+            .prep(format!(
+                "SELECT (SELECT v FROM {} WHERE k = ?), (SELECT v FROM {} WHERE k = ?)",
+                char::from(value_key.subspace()),
+                char::from(counter_key.subspace())
+            ))
+            .await
+            .map_err(into_error)?;
+        let value_key = value_key.serialize(0);
+        let counter_key = counter_key.serialize(0);
+        conn.exec_first::<(Option<Vec<u8>>, Option<i64>), _, _>(&s, (value_key, counter_key))
+            .await
+            .map(|row| {
+                row.map_or((None, 0), |(value, counter)| {
+                    (value, counter.unwrap_or_default())
+                })
+            })
+            .map_err(into_error)
+    }
+
+    pub(crate) async fn get_storage_statistics(&self) -> trc::Result<StorageStats> {
+        let mut conn = self.conn_pool.get_conn().await.map_err(into_error)?;
+        let mut stats = StorageStats::default();
+
+        // InnoDB row counts are estimates
+        for (table, size, total_rows) in conn
+            .query::<(String, Option<u64>, Option<u64>), _>(concat!(
+                "SELECT TABLE_NAME, DATA_LENGTH + INDEX_LENGTH, TABLE_ROWS ",
+                "FROM information_schema.TABLES WHERE TABLE_SCHEMA = DATABASE()"
+            ))
+            .await
+            .map_err(into_error)?
+        {
+            if let [subspace] = table.as_bytes() {
+                stats
+                    .subspace_sizes
+                    .insert(*subspace, size.unwrap_or_default());
+                stats
+                    .total_rows
+                    .insert(*subspace, total_rows.unwrap_or_default());
+            }
+        }
+
+        Ok(stats)
+    }
 }
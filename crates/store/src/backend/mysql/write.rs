@@ -4,20 +4,19 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::time::{Duration, Instant};
+use std::time::Instant;
 
 use ahash::AHashMap;
 use futures::TryStreamExt;
-use mysql_async::{params, prelude::Queryable, Conn, Error, IsolationLevel, TxOpts};
-use rand::Rng;
+use mysql_async::{params, prelude::Queryable, Conn, Error, IsolationLevel, Params, TxOpts};
 use roaring::RoaringBitmap;
 
 use crate::{
     write::{
-        key::DeserializeBigEndian, AssignedIds, Batch, BitmapClass, Operation, RandomAvailableId,
-        ValueOp, MAX_COMMIT_ATTEMPTS, MAX_COMMIT_TIME,
+        commit_backoff, key::DeserializeBigEndian, now, AssignedIds, Batch, BitmapClass, Operation,
+        RandomAvailableId, ValueOp, MAX_SET_MANY_ROWS,
     },
-    BitmapKey, IndexKey, Key, LogKey, SUBSPACE_COUNTER, SUBSPACE_QUOTA, U32_LEN,
+    BitmapKey, IndexKey, Key, LogKey, RecentKey, SUBSPACE_COUNTER, SUBSPACE_QUOTA, U32_LEN,
 };
 
 use super::{into_error, MysqlStore};
@@ -36,16 +35,29 @@ impl MysqlStore {
         let mut conn = self.conn_pool.get_conn().await.map_err(into_error)?;
 
         loop {
-            match self.write_trx(&mut conn, &batch).await {
+            let result = if batch.timeout.is_some() {
+                let remaining = batch
+                    .commit_time(&self.commit_limits)
+                    .saturating_sub(start.elapsed());
+                tokio::time::timeout(remaining, self.write_trx(&mut conn, &batch))
+                    .await
+                    .map_err(|_| batch.timeout_error())?
+            } else {
+                self.write_trx(&mut conn, &batch).await
+            };
+
+            match result {
                 Ok(result) => {
                     return Ok(result);
                 }
                 Err(CommitError::Mysql(Error::Server(err)))
                     if [1062, 1213].contains(&err.code)
-                        && retry_count < MAX_COMMIT_ATTEMPTS
-                        && start.elapsed() < MAX_COMMIT_TIME => {}
+                        && retry_count < self.commit_limits.max_attempts
+                        && start.elapsed() < self.commit_limits.max_time => {}
                 Err(CommitError::Retry) => {
-                    if retry_count > MAX_COMMIT_ATTEMPTS || start.elapsed() > MAX_COMMIT_TIME {
+                    if retry_count > self.commit_limits.max_attempts
+                        || start.elapsed() > self.commit_limits.max_time
+                    {
                         return Err(trc::StoreEvent::AssertValueFailed.into());
                     }
                 }
@@ -57,8 +69,8 @@ impl MysqlStore {
                 }
             }
 
-            let backoff = rand::thread_rng().gen_range(50..=300);
-            tokio::time::sleep(Duration::from_millis(backoff)).await;
+            batch.check_deadline(start, &self.commit_limits)?;
+            tokio::time::sleep(commit_backoff()).await;
             retry_count += 1;
         }
     }
@@ -146,6 +158,33 @@ impl MysqlStore {
                                 }
                             }
                         }
+                        ValueOp::Append { value, max_length } => {
+                            let s = trx
+                                .prep(format!(
+                                    concat!(
+                                        "INSERT INTO {} (k, v) VALUES (?, ?) ",
+                                        "ON DUPLICATE KEY UPDATE v = CONCAT(v, VALUES(v))"
+                                    ),
+                                    table
+                                ))
+                                .await?;
+                            trx.exec_drop(&s, (&key, value.resolve(&result)?.as_ref()))
+                                .await?;
+                            let s = trx
+                                .prep(format!("SELECT LENGTH(v) FROM {} WHERE k = ?", table))
+                                .await?;
+                            let length =
+                                trx.exec_first::<i64, _, _>(&s, (&key,))
+                                    .await?
+                                    .unwrap_or_default() as usize;
+                            if length > *max_length {
+                                trx.rollback().await?;
+                                return Err(trc::StoreEvent::ValueTooLarge
+                                    .ctx(trc::Key::Size, length)
+                                    .ctx(trc::Key::Limit, *max_length)
+                                    .into());
+                            }
+                        }
                         ValueOp::AtomicAdd(by) => {
                             if *by >= 0 {
                                 let s = trx
@@ -196,6 +235,41 @@ impl MysqlStore {
                         }
                     }
                 }
+                Operation::SetMany { class, items } => {
+                    let table = char::from(class.subspace(collection));
+                    let mut values: Vec<mysql_async::Value> = Vec::with_capacity(items.len() * 2);
+                    for (document_id, value) in items {
+                        values.push(
+                            class
+                                .serialize(
+                                    account_id,
+                                    collection,
+                                    *document_id,
+                                    0,
+                                    (&result).into(),
+                                )
+                                .into(),
+                        );
+                        values.push(value.resolve(&result)?.into_owned().into());
+                    }
+
+                    for values in values.chunks(MAX_SET_MANY_ROWS * 2) {
+                        let s = format!(
+                            concat!(
+                                "INSERT INTO {} (k, v) VALUES {} ",
+                                "ON DUPLICATE KEY UPDATE v = VALUES(v)"
+                            ),
+                            table,
+                            vec!["(?, ?)"; values.len() / 2].join(", ")
+                        );
+                        trx.exec_drop(s, Params::Positional(values.to_vec()))
+                            .await?;
+                    }
+
+                    for (document_id, _) in items {
+                        result.push_document_id(*document_id);
+                    }
+                }
                 Operation::Index { field, key, set } => {
                     let key = IndexKey {
                         account_id,
@@ -276,6 +350,19 @@ impl MysqlStore {
                             },
                         );
                     }
+
+                    // Track newly created documents
+                    if *set && is_document_id {
+                        let key = RecentKey {
+                            account_id,
+                            collection,
+                            timestamp: now(),
+                            document_id,
+                        }
+                        .serialize(0);
+                        let s = trx.prep("INSERT IGNORE INTO y (k) VALUES (?)").await?;
+                        trx.exec_drop(&s, (key,)).await?;
+                    }
                 }
                 Operation::Log { set } => {
                     let key = LogKey {
@@ -292,6 +379,21 @@ impl MysqlStore {
                     trx.exec_drop(&s, (key, set.resolve(&result)?.as_ref()))
                         .await?;
                 }
+                Operation::LogWithTimestamp { timestamp, set } => {
+                    let key = LogKey {
+                        account_id,
+                        collection,
+                        change_id,
+                    }
+                    .serialize_with_timestamp(0, *timestamp);
+
+                    let s = trx
+                        .prep("INSERT INTO l (k, v) VALUES (?, ?) ON DUPLICATE KEY UPDATE v = VALUES(v)")
+                        .await?;
+
+                    trx.exec_drop(&s, (key, set.resolve(&result)?.as_ref()))
+                        .await?;
+                }
                 Operation::AssertValue {
                     class,
                     assert_value,
@@ -333,6 +435,221 @@ impl MysqlStore {
         Ok(())
     }
 
+    pub(crate) async fn atomic_swap(
+        &self,
+        key: impl Key,
+        new_value: Vec<u8>,
+    ) -> trc::Result<Option<Vec<u8>>> {
+        let table = char::from(key.subspace());
+        let key = key.serialize(0);
+        let start = Instant::now();
+        let mut retry_count = 0;
+        let mut conn = self.conn_pool.get_conn().await.map_err(into_error)?;
+
+        loop {
+            match self.swap_trx(&mut conn, table, &key, &new_value).await {
+                Ok(old_value) => {
+                    return Ok(old_value);
+                }
+                // A concurrent swap inserted the key first or the locks deadlocked
+                Err(CommitError::Mysql(Error::Server(err)))
+                    if [1062, 1213].contains(&err.code)
+                        && retry_count < self.commit_limits.max_attempts
+                        && start.elapsed() < self.commit_limits.max_time => {}
+                Err(CommitError::Mysql(err)) => {
+                    return Err(into_error(err));
+                }
+                Err(CommitError::Internal(err)) => {
+                    return Err(err);
+                }
+                Err(CommitError::Retry) => {
+                    return Err(trc::StoreEvent::AssertValueFailed.into());
+                }
+            }
+
+            tokio::time::sleep(commit_backoff()).await;
+            retry_count += 1;
+        }
+    }
+
+    async fn swap_trx(
+        &self,
+        conn: &mut Conn,
+        table: char,
+        key: &[u8],
+        new_value: &[u8],
+    ) -> Result<Option<Vec<u8>>, CommitError> {
+        let mut tx_opts = TxOpts::default();
+        tx_opts
+            .with_consistent_snapshot(false)
+            .with_isolation_level(IsolationLevel::ReadCommitted);
+        let mut trx = conn.start_transaction(tx_opts).await?;
+
+        let s = trx
+            .prep(format!("SELECT v FROM {table} WHERE k = ? FOR UPDATE"))
+            .await?;
+        let old_value = trx.exec_first::<Vec<u8>, _, _>(&s, (key,)).await?;
+
+        let s = if old_value.is_some() {
+            trx.prep(format!("UPDATE {table} SET v = :v WHERE k = :k"))
+                .await?
+        } else {
+            trx.prep(format!("INSERT INTO {table} (k, v) VALUES (:k, :v)"))
+                .await?
+        };
+        trx.exec_drop(&s, params! {"k" => key, "v" => new_value})
+            .await?;
+
+        trx.commit().await.map(|_| old_value).map_err(Into::into)
+    }
+
+    pub(crate) async fn compare_and_increment(
+        &self,
+        key: impl Key,
+        expected_version: u64,
+        by: i64,
+    ) -> trc::Result<i64> {
+        let table = char::from(key.subspace());
+        let key = key.serialize(0);
+        let start = Instant::now();
+        let mut retry_count = 0;
+        let mut conn = self.conn_pool.get_conn().await.map_err(into_error)?;
+
+        loop {
+            match self
+                .increment_trx(&mut conn, table, &key, expected_version as i64, by)
+                .await
+            {
+                Ok(value) => {
+                    return Ok(value);
+                }
+                // A concurrent increment inserted the key first or the locks deadlocked
+                Err(CommitError::Mysql(Error::Server(err)))
+                    if [1062, 1213].contains(&err.code)
+                        && retry_count < self.commit_limits.max_attempts
+                        && start.elapsed() < self.commit_limits.max_time => {}
+                Err(CommitError::Mysql(err)) => {
+                    return Err(into_error(err));
+                }
+                Err(CommitError::Internal(err)) => {
+                    return Err(err);
+                }
+                Err(CommitError::Retry) => {
+                    return Err(trc::StoreEvent::AssertValueFailed.into());
+                }
+            }
+
+            tokio::time::sleep(commit_backoff()).await;
+            retry_count += 1;
+        }
+    }
+
+    async fn increment_trx(
+        &self,
+        conn: &mut Conn,
+        table: char,
+        key: &[u8],
+        expected_version: i64,
+        by: i64,
+    ) -> Result<i64, CommitError> {
+        let mut tx_opts = TxOpts::default();
+        tx_opts
+            .with_consistent_snapshot(false)
+            .with_isolation_level(IsolationLevel::ReadCommitted);
+        let mut trx = conn.start_transaction(tx_opts).await?;
+
+        let s = trx
+            .prep(format!("SELECT v FROM {table} WHERE k = ? FOR UPDATE"))
+            .await?;
+        let current = trx.exec_first::<i64, _, _>(&s, (key,)).await?;
+        if current.unwrap_or_default() != expected_version {
+            trx.rollback().await?;
+            return Err(CommitError::Internal(
+                trc::StoreEvent::AssertValueFailed.into(),
+            ));
+        }
+
+        let s = if current.is_some() {
+            trx.prep(format!("UPDATE {table} SET v = v + :v WHERE k = :k"))
+                .await?
+        } else {
+            trx.prep(format!("INSERT INTO {table} (k, v) VALUES (:k, :v)"))
+                .await?
+        };
+        trx.exec_drop(&s, params! {"k" => key, "v" => by}).await?;
+
+        trx.commit()
+            .await
+            .map(|_| expected_version + by)
+            .map_err(Into::into)
+    }
+
+    pub(crate) async fn get_and_reset_counter(&self, key: impl Key) -> trc::Result<i64> {
+        let table = char::from(key.subspace());
+        let key = key.serialize(0);
+        let mut conn = self.conn_pool.get_conn().await.map_err(into_error)?;
+        let mut tx_opts = TxOpts::default();
+        tx_opts
+            .with_consistent_snapshot(false)
+            .with_isolation_level(IsolationLevel::ReadCommitted);
+        let mut trx = conn.start_transaction(tx_opts).await.map_err(into_error)?;
+
+        let s = trx
+            .prep(format!("SELECT v FROM {table} WHERE k = ? FOR UPDATE"))
+            .await
+            .map_err(into_error)?;
+        let current = trx
+            .exec_first::<i64, _, _>(&s, (&key,))
+            .await
+            .map_err(into_error)?
+            .unwrap_or_default();
+        if current != 0 {
+            let s = trx
+                .prep(format!("UPDATE {table} SET v = 0 WHERE k = ?"))
+                .await
+                .map_err(into_error)?;
+            trx.exec_drop(&s, (&key,)).await.map_err(into_error)?;
+        }
+
+        trx.commit().await.map(|_| current).map_err(into_error)
+    }
+
+    pub(crate) async fn atomic_max(&self, key: impl Key, value: i64) -> trc::Result<i64> {
+        let table = char::from(key.subspace());
+        let key = key.serialize(0);
+        let mut conn = self.conn_pool.get_conn().await.map_err(into_error)?;
+        let mut tx_opts = TxOpts::default();
+        tx_opts
+            .with_consistent_snapshot(false)
+            .with_isolation_level(IsolationLevel::ReadCommitted);
+        let mut trx = conn.start_transaction(tx_opts).await.map_err(into_error)?;
+
+        let s = trx
+            .prep(format!(
+                concat!(
+                    "INSERT INTO {} (k, v) VALUES (?, ?) ",
+                    "ON DUPLICATE KEY UPDATE v = GREATEST(v, VALUES(v))"
+                ),
+                table
+            ))
+            .await
+            .map_err(into_error)?;
+        trx.exec_drop(&s, (&key, value)).await.map_err(into_error)?;
+
+        // The row stays locked by the upsert until the transaction commits
+        let s = trx
+            .prep(format!("SELECT v FROM {table} WHERE k = ?"))
+            .await
+            .map_err(into_error)?;
+        let current = trx
+            .exec_first::<i64, _, _>(&s, (&key,))
+            .await
+            .map_err(into_error)?
+            .unwrap_or(value);
+
+        trx.commit().await.map(|_| current).map_err(into_error)
+    }
+
     pub(crate) async fn delete_range(&self, from: impl Key, to: impl Key) -> trc::Result<()> {
         let mut conn = self.conn_pool.get_conn().await.map_err(into_error)?;
 
@@ -347,6 +664,36 @@ impl MysqlStore {
             .await
             .map_err(into_error)
     }
+
+    pub(crate) async fn rename_range(
+        &self,
+        from: impl Key,
+        to: impl Key,
+        account_id: u32,
+    ) -> trc::Result<u64> {
+        let mut conn = self.conn_pool.get_conn().await.map_err(into_error)?;
+
+        let s = conn
+            .prep(format!(
+                "UPDATE {} SET k = CONCAT(?, SUBSTRING(k, {})) WHERE k >= ? AND k < ?",
+                char::from(from.subspace()),
+                U32_LEN + 1
+            ))
+            .await
+            .map_err(into_error)?;
+        conn.exec_drop(
+            &s,
+            (
+                account_id.to_be_bytes().as_slice(),
+                &from.serialize(0),
+                &to.serialize(0),
+            ),
+        )
+        .await
+        .map_err(into_error)?;
+
+        Ok(conn.affected_rows())
+    }
 }
 
 impl From<trc::Error> for CommitError {
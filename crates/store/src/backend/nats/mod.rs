@@ -0,0 +1,92 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{fmt::Display, time::Duration};
+
+use async_nats::{Client, ConnectOptions};
+use futures::StreamExt;
+use utils::config::{utils::AsKey, Config};
+
+use crate::PubSubStream;
+
+pub struct NatsStore {
+    client: Client,
+}
+
+impl NatsStore {
+    pub async fn open(config: &mut Config, prefix: impl AsKey) -> Option<Self> {
+        let prefix = prefix.as_key();
+        let urls = config
+            .values((&prefix, "urls"))
+            .map(|(_, v)| v.to_string())
+            .collect::<Vec<_>>();
+        if urls.is_empty() {
+            config.new_build_error((&prefix, "urls"), "No NATS URLs specified");
+            return None;
+        }
+
+        let mut options = ConnectOptions::new()
+            .connection_timeout(
+                config
+                    .property_or_default::<Duration>((&prefix, "timeout"), "10s")
+                    .unwrap_or_else(|| Duration::from_secs(10)),
+            )
+            .ping_interval(
+                config
+                    .property_or_default::<Duration>((&prefix, "ping-interval"), "60s")
+                    .unwrap_or_else(|| Duration::from_secs(60)),
+            )
+            // Keep reconnecting, missed messages are detected by the subscribers
+            .retry_on_initial_connect()
+            .max_reconnects(None);
+        if let (Some(user), Some(password)) = (
+            config.value((&prefix, "user")).map(|v| v.to_string()),
+            config.value((&prefix, "password")).map(|v| v.to_string()),
+        ) {
+            options = options.user_and_password(user, password);
+        }
+        if let Some(token) = config.value((&prefix, "token")) {
+            options = options.token(token.to_string());
+        }
+
+        options
+            .connect(urls.as_slice())
+            .await
+            .map(|client| NatsStore { client })
+            .map_err(|err| {
+                config.new_build_error(prefix.as_str(), format!("Failed to connect to NATS: {err}"))
+            })
+            .ok()
+    }
+
+    pub async fn publish(&self, topic: &str, message: Vec<u8>) -> trc::Result<()> {
+        self.client
+            .publish(topic.to_string(), message.into())
+            .await
+            .map_err(into_error)
+    }
+
+    pub async fn subscribe(&self, topic: &str) -> trc::Result<PubSubStream> {
+        self.client
+            .subscribe(topic.to_string())
+            .await
+            .map(|subscriber| {
+                Box::pin(subscriber.map(|message| message.payload.to_vec())) as PubSubStream
+            })
+            .map_err(into_error)
+    }
+}
+
+#[inline(always)]
+fn into_error(err: impl Display) -> trc::Error {
+    trc::StoreEvent::NatsError.reason(err)
+}
+
+impl std::fmt::Debug for NatsStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NatsStore").finish()
+    }
+}
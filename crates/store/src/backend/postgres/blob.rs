@@ -4,10 +4,36 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::ops::Range;
+use std::{
+    fmt::Write,
+    io,
+    ops::Range,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use bytes::{Buf, Bytes, BytesMut};
+use deadpool_postgres::Object;
+use futures::{Stream, StreamExt};
+use tokio_postgres::CopyOutStream;
+use tokio_util::io::StreamReader;
+
+use crate::BlobStream;
 
 use super::{into_error, PostgresStore};
 
+// Signature of the binary COPY format, followed by the flags field
+// and the length of the header extension area.
+const COPY_SIGNATURE: &[u8] = b"PGCOPY\n\xff\r\n\0";
+const COPY_HEADER_LEN: usize = COPY_SIGNATURE.len() + 8;
+
+struct CopyOutBlob {
+    stream: Pin<Box<CopyOutStream>>,
+    buf: Bytes,
+    remaining: usize,
+    _conn: Object,
+}
+
 impl PostgresStore {
     pub(crate) async fn get_blob(
         &self,
@@ -39,6 +65,47 @@ impl PostgresStore {
             .map_err(into_error)
     }
 
+    pub(crate) async fn get_blob_stream(&self, key: &[u8]) -> trc::Result<Option<BlobStream>> {
+        let conn = self.conn_pool.get().await.map_err(into_error)?;
+
+        // COPY does not accept bind parameters, the key is inlined as a bytea literal
+        let mut query = String::with_capacity((key.len() * 2) + 80);
+        query.push_str("COPY (SELECT v FROM t WHERE k = '\\x");
+        for byte in key {
+            let _ = write!(query, "{byte:02x}");
+        }
+        query.push_str("'::bytea) TO STDOUT (FORMAT binary)");
+        let mut stream = Box::pin(conn.copy_out(query.as_str()).await.map_err(into_error)?);
+        let mut buf = BytesMut::new();
+
+        // Skip header
+        read_copy_out(&mut stream, &mut buf, COPY_HEADER_LEN).await?;
+        if !buf.starts_with(COPY_SIGNATURE) {
+            return Err(into_error("Invalid COPY signature"));
+        }
+        buf.advance(COPY_HEADER_LEN - 4);
+        let extension_len = buf.get_u32() as usize;
+        read_copy_out(&mut stream, &mut buf, extension_len + 2).await?;
+        buf.advance(extension_len);
+
+        // The field count is -1 when there are no rows left
+        if buf.get_i16() != 1 {
+            return Ok(None);
+        }
+        read_copy_out(&mut stream, &mut buf, 4).await?;
+        let blob_len = buf.get_i32();
+        if blob_len < 0 {
+            return Ok(None);
+        }
+
+        Ok(Some(Box::pin(StreamReader::new(CopyOutBlob {
+            stream,
+            buf: buf.freeze(),
+            remaining: blob_len as usize,
+            _conn: conn,
+        }))))
+    }
+
     pub(crate) async fn put_blob(&self, key: &[u8], data: &[u8]) -> trc::Result<()> {
         let conn = self.conn_pool.get().await.map_err(into_error)?;
         let s = conn
@@ -65,3 +132,50 @@ impl PostgresStore {
             .map(|hits| hits > 0)
     }
 }
+
+async fn read_copy_out(
+    stream: &mut Pin<Box<CopyOutStream>>,
+    buf: &mut BytesMut,
+    len: usize,
+) -> trc::Result<()> {
+    while buf.len() < len {
+        match stream.next().await {
+            Some(Ok(bytes)) => buf.extend_from_slice(&bytes),
+            Some(Err(err)) => return Err(into_error(err)),
+            None => return Err(into_error("Unexpected end of COPY data")),
+        }
+    }
+
+    Ok(())
+}
+
+impl Stream for CopyOutBlob {
+    type Item = io::Result<Bytes>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        loop {
+            if this.remaining == 0 {
+                return Poll::Ready(None);
+            } else if !this.buf.is_empty() {
+                let bytes = this
+                    .buf
+                    .split_to(std::cmp::min(this.remaining, this.buf.len()));
+                this.remaining -= bytes.len();
+                return Poll::Ready(Some(Ok(bytes)));
+            }
+
+            match ready!(this.stream.as_mut().poll_next(cx)) {
+                Some(Ok(bytes)) => this.buf = bytes,
+                Some(Err(err)) => return Poll::Ready(Some(Err(io::Error::other(err)))),
+                None => {
+                    return Poll::Ready(Some(Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "Unexpected end of COPY data",
+                    ))))
+                }
+            }
+        }
+    }
+}
@@ -6,7 +6,11 @@
 
 use std::time::Duration;
 
-use crate::{backend::postgres::tls::MakeRustlsConnect, *};
+use crate::{
+    backend::postgres::tls::MakeRustlsConnect,
+    config::{parse_commit_limits, parse_expiring_subspaces, parse_max_value_size},
+    *,
+};
 
 use super::{into_error, PostgresStore};
 
@@ -63,6 +67,9 @@ impl PostgresStore {
                 )
             })
             .ok()?,
+            max_value_size: parse_max_value_size(config, &prefix),
+            expiring_subspaces: parse_expiring_subspaces(config, &prefix),
+            commit_limits: parse_commit_limits(config, &prefix),
         };
 
         if create_tables {
@@ -96,6 +103,8 @@ impl PostgresStore {
             SUBSPACE_TELEMETRY_SPAN,
             SUBSPACE_TELEMETRY_METRIC,
             SUBSPACE_TELEMETRY_INDEX,
+            SUBSPACE_QUARANTINE,
+            SUBSPACE_AUDIT,
         ] {
             let table = char::from(table);
             conn.execute(
@@ -116,6 +125,7 @@ impl PostgresStore {
             SUBSPACE_BITMAP_ID,
             SUBSPACE_BITMAP_TAG,
             SUBSPACE_BITMAP_TEXT,
+            SUBSPACE_RECENT,
         ] {
             let table = char::from(table);
             conn.execute(
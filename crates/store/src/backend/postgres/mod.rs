@@ -6,8 +6,11 @@
 
 use std::fmt::Display;
 
+use ahash::AHashMap;
 use deadpool_postgres::Pool;
 
+use crate::write::CommitLimits;
+
 pub mod blob;
 pub mod lookup;
 pub mod main;
@@ -17,6 +20,9 @@ pub mod write;
 
 pub struct PostgresStore {
     pub(crate) conn_pool: Pool,
+    pub(crate) max_value_size: AHashMap<u8, usize>,
+    pub(crate) expiring_subspaces: Vec<(u8, u64)>,
+    pub(crate) commit_limits: CommitLimits,
 }
 
 #[inline(always)]
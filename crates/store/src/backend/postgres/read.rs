@@ -9,9 +9,11 @@ use roaring::RoaringBitmap;
 
 use crate::{
     write::{key::DeserializeBigEndian, BitmapClass, ValueClass},
-    BitmapKey, Deserialize, IterateParams, Key, ValueKey, U32_LEN,
+    BitmapKey, Deserialize, IterateParams, Key, StorageStats, ValueKey, U32_LEN,
 };
 
+use crate::backend::group_keys_by_subspace;
+
 use super::{into_error, PostgresStore};
 
 impl PostgresStore {
@@ -40,6 +42,54 @@ impl PostgresStore {
             })
     }
 
+    pub(crate) async fn get_values(
+        &self,
+        keys: Vec<impl Key>,
+    ) -> trc::Result<Vec<Option<Vec<u8>>>> {
+        let mut results = vec![None; keys.len()];
+        let conn = self.conn_pool.get().await.map_err(into_error)?;
+
+        for (subspace, positions) in group_keys_by_subspace(&keys) {
+            let s = conn
+                .prepare_cached(&format!(
+                    "SELECT k, v FROM {} WHERE k = ANY($1)",
+                    char::from(subspace)
+                ))
+                .await
+                .map_err(into_error)?;
+            let keys = positions
+                .keys()
+                .map(|key| key.as_slice())
+                .collect::<Vec<_>>();
+            for row in conn.query(&s, &[&keys]).await.map_err(into_error)? {
+                if let Some(positions) = positions.get(row.get::<_, &[u8]>(0)) {
+                    let value = row.get::<_, &[u8]>(1);
+                    for pos in positions {
+                        results[*pos] = Some(value.to_vec());
+                    }
+                }
+            }
+        }
+
+        Ok(results)
+    }
+
+    pub(crate) async fn get_value_size(&self, key: impl Key) -> trc::Result<Option<usize>> {
+        let conn = self.conn_pool.get().await.map_err(into_error)?;
+        let s = conn
+            .prepare_cached(&format!(
+                "SELECT octet_length(v) FROM {} WHERE k = $1",
+                char::from(key.subspace())
+            ))
+            .await
+            .map_err(into_error)?;
+        let key = key.serialize(0);
+        conn.query_opt(&s, &[&key])
+            .await
+            .map_err(into_error)
+            .map(|r| r.map(|r| r.get::<_, i32>(0) as usize))
+    }
+
     pub(crate) async fn get_bitmap(
         &self,
         mut key: BitmapKey<BitmapClass<u32>>,
@@ -72,6 +122,119 @@ impl PostgresStore {
         Ok(if !bm.is_empty() { Some(bm) } else { None })
     }
 
+    pub(crate) async fn get_bitmap_cardinality(
+        &self,
+        mut key: BitmapKey<BitmapClass<u32>>,
+    ) -> trc::Result<u64> {
+        let begin = key.serialize(0);
+        key.document_id = u32::MAX;
+        let key_len = begin.len() as i32;
+        let end = key.serialize(0);
+        let conn = self.conn_pool.get().await.map_err(into_error)?;
+        let table = char::from(key.subspace());
+
+        let s = conn
+            .prepare_cached(&format!(
+                "SELECT COUNT(*) FROM {table} WHERE k >= $1 AND k <= $2 AND octet_length(k) = $3"
+            ))
+            .await
+            .map_err(into_error)?;
+        conn.query_one(&s, &[&begin, &end, &key_len])
+            .await
+            .map_err(into_error)
+            .map(|r| r.get::<_, i64>(0) as u64)
+    }
+
+    // Counts the documents present in both bitmaps, probing the first bitmap
+    // for each key of the second one
+    pub(crate) async fn get_bitmaps_intersection_count(
+        &self,
+        key: BitmapKey<BitmapClass<u32>>,
+        mut other: BitmapKey<BitmapClass<u32>>,
+    ) -> trc::Result<u64> {
+        let mut prefix = key.serialize(0);
+        prefix.truncate(prefix.len() - U32_LEN);
+        let table = char::from(key.subspace());
+        let begin = other.serialize(0);
+        other.document_id = u32::MAX;
+        let end = other.serialize(0);
+        let key_len = begin.len() as i32;
+        let other_table = char::from(other.subspace());
+        let conn = self.conn_pool.get().await.map_err(into_error)?;
+
+        let s = conn
+            .prepare_cached(&format!(
+                concat!(
+                    "SELECT COUNT(*) FROM {} o WHERE o.k >= $1 AND o.k <= $2 ",
+                    "AND octet_length(o.k) = $3 AND EXISTS (SELECT 1 FROM {} b ",
+                    "WHERE b.k = $4 || substring(o.k from $5))"
+                ),
+                other_table, table
+            ))
+            .await
+            .map_err(into_error)?;
+        conn.query_one(
+            &s,
+            &[
+                &begin,
+                &end,
+                &key_len,
+                &prefix,
+                &(key_len - U32_LEN as i32 + 1),
+            ],
+        )
+        .await
+        .map_err(into_error)
+        .map(|r| r.get::<_, i64>(0) as u64)
+    }
+
+    pub(crate) async fn get_keys_count(&self, from: impl Key, to: impl Key) -> trc::Result<u64> {
+        let table = char::from(from.subspace());
+        let begin = from.serialize(0);
+        let end = to.serialize(0);
+        let conn = self.conn_pool.get().await.map_err(into_error)?;
+
+        let s = conn
+            .prepare_cached(&format!(
+                "SELECT COUNT(*) FROM {table} WHERE k >= $1 AND k < $2"
+            ))
+            .await
+            .map_err(into_error)?;
+        conn.query_one(&s, &[&begin, &end])
+            .await
+            .map_err(into_error)
+            .map(|r| r.get::<_, i64>(0) as u64)
+    }
+
+    pub(crate) async fn get_counters(
+        &self,
+        from: impl Key,
+        to: impl Key,
+    ) -> trc::Result<Vec<(Vec<u8>, i64)>> {
+        let table = char::from(from.subspace());
+        let begin = from.serialize(0);
+        let end = to.serialize(0);
+        let conn = self.conn_pool.get().await.map_err(into_error)?;
+
+        let s = conn
+            .prepare_cached(&format!(
+                "SELECT k, v FROM {table} WHERE k >= $1 AND k < $2"
+            ))
+            .await
+            .map_err(into_error)?;
+        conn.query(&s, &[&begin, &end])
+            .await
+            .map_err(into_error)?
+            .into_iter()
+            .map(|row| {
+                Ok((
+                    row.try_get::<_, Vec<u8>>(0).map_err(into_error)?,
+                    row.try_get::<_, i64>(1).map_err(into_error)?,
+                ))
+            })
+            .collect()
+    }
+
     pub(crate) async fn iterate<T: Key>(
         &self,
         params: IterateParams<T>,
@@ -149,4 +312,63 @@ impl PostgresStore {
             Err(e) => Err(into_error(e)),
         }
     }
+
+    // Reads a value and a counter from a single statement, so both are
+    // obtained from the same snapshot of the database.
+    pub(crate) async fn get_value_and_counter(
+        &self,
+        value_key: ValueKey<ValueClass<u32>>,
+        counter_key: ValueKey<ValueClass<u32>>,
+    ) -> trc::Result<(Option<Vec<u8>>, i64)> {
+        let conn = self.conn_pool.get().await.map_err(into_error)?;
+        let s = conn
+            .prepare_cached(&format!(
+                "SELECT (SELECT v FROM {} WHERE k = $1), (SELECT v FROM {} WHERE k = $2)",
+                char::from(value_key.subspace()),
+                char::from(counter_key.subspace())
+            ))
+            .await
+            .map_err(into_error)?;
+        let value_key = value_key.serialize(0);
+        let counter_key = counter_key.serialize(0);
+        let row = conn
+            .query_one(&s, &[&value_key, &counter_key])
+            .await
+            .map_err(into_error)?;
+
+        Ok((
+            row.try_get::<_, Option<Vec<u8>>>(0).map_err(into_error)?,
+            row.try_get::<_, Option<i64>>(1)
+                .map_err(into_error)?
+                .unwrap_or_default(),
+        ))
+    }
+
+    pub(crate) async fn get_storage_statistics(&self) -> trc::Result<StorageStats> {
+        let conn = self.conn_pool.get().await.map_err(into_error)?;
+        let mut stats = StorageStats::default();
+
+        // Row counts are the planner's estimates, updated by VACUUM and ANALYZE
+        for row in conn
+            .query(
+                concat!(
+                    "SELECT relname::TEXT, pg_total_relation_size(relid), n_live_tup ",
+                    "FROM pg_stat_user_tables WHERE schemaname = current_schema()"
+                ),
+                &[],
+            )
+            .await
+            .map_err(into_error)?
+        {
+            let table: String = row.try_get(0).map_err(into_error)?;
+            if let [subspace] = table.as_bytes() {
+                let size: i64 = row.try_get(1).map_err(into_error)?;
+                let total_rows: i64 = row.try_get(2).map_err(into_error)?;
+                stats.subspace_sizes.insert(*subspace, size as u64);
+                stats.total_rows.insert(*subspace, total_rows as u64);
+            }
+        }
+
+        Ok(stats)
+    }
 }
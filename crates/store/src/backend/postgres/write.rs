@@ -4,21 +4,22 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::time::{Duration, Instant};
+use std::time::Instant;
 
-use ahash::AHashMap;
+use ahash::{AHashMap, AHashSet};
 use deadpool_postgres::Object;
 use futures::{pin_mut, TryStreamExt};
-use rand::Rng;
 use roaring::RoaringBitmap;
-use tokio_postgres::{error::SqlState, IsolationLevel};
+use tokio_postgres::{error::SqlState, types::ToSql, IsolationLevel};
 
 use crate::{
+    dispatch::store::document_range,
     write::{
-        key::DeserializeBigEndian, AssignedIds, Batch, BitmapClass, Operation, RandomAvailableId,
-        ValueOp, MAX_COMMIT_ATTEMPTS, MAX_COMMIT_TIME,
+        commit_backoff, key::DeserializeBigEndian, now, AssignedIds, Batch, BitmapClass, Operation,
+        RandomAvailableId, ValueOp, MAX_SET_MANY_ROWS,
     },
-    BitmapKey, IndexKey, Key, LogKey, SUBSPACE_COUNTER, SUBSPACE_QUOTA, U32_LEN,
+    BitmapKey, IndexKey, Key, LogKey, RecentKey, DOCUMENT_SUBSPACES, SUBSPACE_BITMAP_ID,
+    SUBSPACE_COUNTER, SUBSPACE_QUOTA, U32_LEN,
 };
 
 use super::{into_error, PostgresStore};
@@ -37,7 +38,18 @@ impl PostgresStore {
         let mut retry_count = 0;
 
         loop {
-            match self.write_trx(&mut conn, &batch).await {
+            let result = if batch.timeout.is_some() {
+                let remaining = batch
+                    .commit_time(&self.commit_limits)
+                    .saturating_sub(start.elapsed());
+                tokio::time::timeout(remaining, self.write_trx(&mut conn, &batch))
+                    .await
+                    .map_err(|_| batch.timeout_error())?
+            } else {
+                self.write_trx(&mut conn, &batch).await
+            };
+
+            match result {
                 Ok(result) => {
                     return Ok(result);
                 }
@@ -47,8 +59,8 @@ impl PostgresStore {
                             Some(
                                 &SqlState::T_R_SERIALIZATION_FAILURE
                                 | &SqlState::T_R_DEADLOCK_DETECTED,
-                            ) if retry_count < MAX_COMMIT_ATTEMPTS
-                                && start.elapsed() < MAX_COMMIT_TIME => {}
+                            ) if retry_count < self.commit_limits.max_attempts
+                                && start.elapsed() < self.commit_limits.max_time => {}
                             Some(&SqlState::UNIQUE_VIOLATION) => {
                                 return Err(trc::StoreEvent::AssertValueFailed.into());
                             }
@@ -56,16 +68,16 @@ impl PostgresStore {
                         },
                         CommitError::Internal(err) => return Err(err),
                         CommitError::Retry => {
-                            if retry_count > MAX_COMMIT_ATTEMPTS
-                                || start.elapsed() > MAX_COMMIT_TIME
+                            if retry_count > self.commit_limits.max_attempts
+                                || start.elapsed() > self.commit_limits.max_time
                             {
                                 return Err(trc::StoreEvent::AssertValueFailed.into());
                             }
                         }
                     }
 
-                    let backoff = rand::thread_rng().gen_range(50..=300);
-                    tokio::time::sleep(Duration::from_millis(backoff)).await;
+                    batch.check_deadline(start, &self.commit_limits)?;
+                    tokio::time::sleep(commit_backoff()).await;
                     retry_count += 1;
                 }
             }
@@ -151,6 +163,29 @@ impl PostgresStore {
                                 return Err(trc::StoreEvent::AssertValueFailed.into_err().into());
                             }
                         }
+                        ValueOp::Append { value, max_length } => {
+                            let s = trx
+                                .prepare_cached(&format!(
+                                    concat!(
+                                        "INSERT INTO {} (k, v) VALUES ($1, $2) ",
+                                        "ON CONFLICT (k) DO UPDATE SET v = {}.v || EXCLUDED.v ",
+                                        "RETURNING length(v)"
+                                    ),
+                                    table, table
+                                ))
+                                .await?;
+                            let length = trx
+                                .query_one(&s, &[&key, &value.resolve(&result)?.as_ref()])
+                                .await
+                                .and_then(|row| row.try_get::<_, i32>(0))?
+                                as usize;
+                            if length > *max_length {
+                                return Err(trc::StoreEvent::ValueTooLarge
+                                    .ctx(trc::Key::Size, length)
+                                    .ctx(trc::Key::Limit, *max_length)
+                                    .into());
+                            }
+                        }
                         ValueOp::AtomicAdd(by) => {
                             if *by >= 0 {
                                 let s = trx
@@ -196,6 +231,47 @@ impl PostgresStore {
                         }
                     }
                 }
+                Operation::SetMany { class, items } => {
+                    let table = char::from(class.subspace(collection));
+
+                    // Postgres fails when a statement updates the same row twice,
+                    // keep the last value written to each key
+                    let mut keys = AHashSet::with_capacity(items.len());
+                    let mut rows = Vec::with_capacity(items.len());
+                    for (document_id, value) in items.iter().rev() {
+                        let key = class.serialize(
+                            account_id,
+                            collection,
+                            *document_id,
+                            0,
+                            (&result).into(),
+                        );
+                        if keys.insert(key.clone()) {
+                            rows.push((key, value.resolve(&result)?.into_owned()));
+                        }
+                    }
+                    rows.reverse();
+
+                    for rows in rows.chunks(MAX_SET_MANY_ROWS) {
+                        let mut query = format!("INSERT INTO {table} (k, v) VALUES ");
+                        let mut params: Vec<&(dyn ToSql + Sync)> =
+                            Vec::with_capacity(rows.len() * 2);
+                        for (pos, (key, value)) in rows.iter().enumerate() {
+                            if pos > 0 {
+                                query.push_str(", ");
+                            }
+                            query.push_str(&format!("(${}, ${})", pos * 2 + 1, pos * 2 + 2));
+                            params.push(key);
+                            params.push(value);
+                        }
+                        query.push_str(" ON CONFLICT (k) DO UPDATE SET v = EXCLUDED.v");
+                        trx.execute(query.as_str(), &params).await?;
+                    }
+
+                    for (document_id, _) in items {
+                        result.push_document_id(*document_id);
+                    }
+                }
                 Operation::Index { field, key, set } => {
                     let key = IndexKey {
                         account_id,
@@ -283,6 +359,23 @@ impl PostgresStore {
                             CommitError::Postgres(err)
                         }
                     })?;
+
+                    // Track newly created documents
+                    if *set && is_document_id {
+                        let key = RecentKey {
+                            account_id,
+                            collection,
+                            timestamp: now(),
+                            document_id,
+                        }
+                        .serialize(0);
+                        let s = trx
+                            .prepare_cached(
+                                "INSERT INTO y (k) VALUES ($1) ON CONFLICT (k) DO NOTHING",
+                            )
+                            .await?;
+                        trx.execute(&s, &[&key]).await?;
+                    }
                 }
                 Operation::Log { set } => {
                     let key = LogKey {
@@ -302,6 +395,24 @@ impl PostgresStore {
                     trx.execute(&s, &[&key, &set.resolve(&result)?.as_ref()])
                         .await?;
                 }
+                Operation::LogWithTimestamp { timestamp, set } => {
+                    let key = LogKey {
+                        account_id,
+                        collection,
+                        change_id,
+                    }
+                    .serialize_with_timestamp(0, *timestamp);
+
+                    let s = trx
+                        .prepare_cached(concat!(
+                            "INSERT INTO l (k, v) VALUES ($1, $2) ",
+                            "ON CONFLICT (k) DO UPDATE SET v = EXCLUDED.v"
+                        ))
+                        .await?;
+
+                    trx.execute(&s, &[&key, &set.resolve(&result)?.as_ref()])
+                        .await?;
+                }
                 Operation::AssertValue {
                     class,
                     assert_value,
@@ -349,6 +460,146 @@ impl PostgresStore {
         Ok(())
     }
 
+    pub(crate) async fn atomic_swap(
+        &self,
+        key: impl Key,
+        new_value: Vec<u8>,
+    ) -> trc::Result<Option<Vec<u8>>> {
+        let conn = self.conn_pool.get().await.map_err(into_error)?;
+        let table = char::from(key.subspace());
+        let key = key.serialize(0);
+        let start = Instant::now();
+        let mut retry_count = 0;
+
+        // The row lock taken by the subquery makes concurrent swaps wait
+        // for each other, so every swap observes the value written by the
+        // previous one
+        let s_update = conn
+            .prepare_cached(&format!(
+                concat!(
+                    "UPDATE {table} SET v = $2 FROM (SELECT k, v FROM {table} ",
+                    "WHERE k = $1 FOR UPDATE) AS old WHERE {table}.k = old.k RETURNING old.v"
+                ),
+                table = table
+            ))
+            .await
+            .map_err(into_error)?;
+        let s_insert = conn
+            .prepare_cached(&format!(
+                "INSERT INTO {table} (k, v) VALUES ($1, $2) ON CONFLICT (k) DO NOTHING"
+            ))
+            .await
+            .map_err(into_error)?;
+
+        loop {
+            if let Some(row) = conn
+                .query_opt(&s_update, &[&key, &new_value])
+                .await
+                .map_err(into_error)?
+            {
+                return row.try_get::<_, Vec<u8>>(0).map(Some).map_err(into_error);
+            } else if conn
+                .execute(&s_insert, &[&key, &new_value])
+                .await
+                .map_err(into_error)?
+                > 0
+            {
+                return Ok(None);
+            } else if retry_count > self.commit_limits.max_attempts
+                || start.elapsed() > self.commit_limits.max_time
+            {
+                return Err(trc::StoreEvent::AssertValueFailed.into());
+            }
+
+            // Another swap inserted the key first, update it instead
+            retry_count += 1;
+        }
+    }
+
+    pub(crate) async fn compare_and_increment(
+        &self,
+        key: impl Key,
+        expected_version: u64,
+        by: i64,
+    ) -> trc::Result<i64> {
+        let conn = self.conn_pool.get().await.map_err(into_error)?;
+        let table = char::from(key.subspace());
+        let key = key.serialize(0);
+        let expected_version = expected_version as i64;
+
+        // Missing counters are only created when zero is expected
+        let s = if expected_version == 0 {
+            conn.prepare_cached(&format!(
+                concat!(
+                    "INSERT INTO {table} (k, v) VALUES ($1, $3) ON CONFLICT(k) DO UPDATE ",
+                    "SET v = {table}.v + EXCLUDED.v WHERE {table}.v = $2 RETURNING v"
+                ),
+                table = table
+            ))
+            .await
+        } else {
+            conn.prepare_cached(&format!(
+                "UPDATE {table} SET v = v + $3 WHERE k = $1 AND v = $2 RETURNING v"
+            ))
+            .await
+        }
+        .map_err(into_error)?;
+
+        match conn
+            .query_opt(&s, &[&key, &expected_version, &by])
+            .await
+            .map_err(into_error)?
+        {
+            Some(row) => row.try_get::<_, i64>(0).map_err(into_error),
+            None => Err(trc::StoreEvent::AssertValueFailed.into()),
+        }
+    }
+
+    pub(crate) async fn get_and_reset_counter(&self, key: impl Key) -> trc::Result<i64> {
+        let conn = self.conn_pool.get().await.map_err(into_error)?;
+        let table = char::from(key.subspace());
+        let key = key.serialize(0);
+
+        // The row lock taken by the subquery keeps concurrent increments out
+        let s = conn
+            .prepare_cached(&format!(
+                concat!(
+                    "UPDATE {table} AS t SET v = 0 FROM (SELECT k, v FROM {table} ",
+                    "WHERE k = $1 FOR UPDATE) AS old WHERE t.k = old.k RETURNING old.v"
+                ),
+                table = table
+            ))
+            .await
+            .map_err(into_error)?;
+
+        match conn.query_opt(&s, &[&key]).await.map_err(into_error)? {
+            Some(row) => row.try_get::<_, i64>(0).map_err(into_error),
+            None => Ok(0),
+        }
+    }
+
+    pub(crate) async fn atomic_max(&self, key: impl Key, value: i64) -> trc::Result<i64> {
+        let conn = self.conn_pool.get().await.map_err(into_error)?;
+        let table = char::from(key.subspace());
+        let key = key.serialize(0);
+
+        let s = conn
+            .prepare_cached(&format!(
+                concat!(
+                    "INSERT INTO {table} (k, v) VALUES ($1, $2) ON CONFLICT (k) ",
+                    "DO UPDATE SET v = GREATEST({table}.v, EXCLUDED.v) RETURNING v"
+                ),
+                table = table
+            ))
+            .await
+            .map_err(into_error)?;
+
+        conn.query_one(&s, &[&key, &value])
+            .await
+            .and_then(|row| row.try_get::<_, i64>(0))
+            .map_err(into_error)
+    }
+
     pub(crate) async fn delete_range(&self, from: impl Key, to: impl Key) -> trc::Result<()> {
         let conn = self.conn_pool.get().await.map_err(into_error)?;
 
@@ -364,6 +615,99 @@ impl PostgresStore {
             .map(|_| ())
             .map_err(into_error)
     }
+
+    pub(crate) async fn bulk_delete_documents(
+        &self,
+        account_id: u32,
+        collection: u8,
+        document_ids: &RoaringBitmap,
+    ) -> trc::Result<u64> {
+        let mut conn = self.conn_pool.get().await.map_err(into_error)?;
+        let trx = conn
+            .build_transaction()
+            .isolation_level(IsolationLevel::ReadCommitted)
+            .start()
+            .await
+            .map_err(into_error)?;
+        let document_ids = document_ids
+            .iter()
+            .map(|document_id| document_id.to_be_bytes().to_vec())
+            .collect::<Vec<_>>();
+        let collection = [collection];
+        let mut deleted = 0;
+
+        for (subspace, collection_offset) in DOCUMENT_SUBSPACES {
+            let (from_key, to_key) = document_range(account_id, collection[0], collection_offset);
+            let rows = if let Some(offset) = collection_offset {
+                let s = trx
+                    .prepare_cached(&format!(
+                        concat!(
+                            "DELETE FROM {} WHERE k >= $1 AND k < $2 ",
+                            "AND substring(k from length(k) - {}) = ANY($3) ",
+                            "AND substring(k from length(k) - {} for 1) = $4"
+                        ),
+                        char::from(subspace),
+                        U32_LEN - 1,
+                        U32_LEN - 1 + offset
+                    ))
+                    .await
+                    .map_err(into_error)?;
+                trx.execute(
+                    &s,
+                    &[&from_key, &to_key, &document_ids, &collection.as_slice()],
+                )
+                .await
+            } else {
+                let s = trx
+                    .prepare_cached(&format!(
+                        concat!(
+                            "DELETE FROM {} WHERE k >= $1 AND k < $2 ",
+                            "AND substring(k from length(k) - {}) = ANY($3)"
+                        ),
+                        char::from(subspace),
+                        U32_LEN - 1,
+                    ))
+                    .await
+                    .map_err(into_error)?;
+                trx.execute(&s, &[&from_key, &to_key, &document_ids]).await
+            }
+            .map_err(into_error)?;
+
+            if subspace == SUBSPACE_BITMAP_ID {
+                deleted = rows;
+            }
+        }
+
+        trx.commit().await.map(|_| deleted).map_err(into_error)
+    }
+
+    pub(crate) async fn rename_range(
+        &self,
+        from: impl Key,
+        to: impl Key,
+        account_id: u32,
+    ) -> trc::Result<u64> {
+        let conn = self.conn_pool.get().await.map_err(into_error)?;
+
+        let s = conn
+            .prepare_cached(&format!(
+                "UPDATE {} SET k = $3::bytea || substring(k from {}) WHERE k >= $1 AND k < $2",
+                char::from(from.subspace()),
+                U32_LEN + 1
+            ))
+            .await
+            .map_err(into_error)?;
+        conn.execute(
+            &s,
+            &[
+                &from.serialize(0),
+                &to.serialize(0),
+                &account_id.to_be_bytes().as_slice(),
+            ],
+        )
+        .await
+        .map_err(into_error)
+    }
 }
 
 impl From<trc::Error> for CommitError {
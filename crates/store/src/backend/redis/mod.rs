@@ -18,6 +18,7 @@ use utils::config::{utils::AsKey, Config};
 
 pub mod lookup;
 pub mod pool;
+pub mod pubsub;
 
 #[derive(Debug)]
 pub struct RedisStore {
@@ -0,0 +1,59 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use futures::StreamExt;
+use redis::AsyncCommands;
+
+use crate::PubSubStream;
+
+use super::{into_error, RedisPool, RedisStore};
+
+impl RedisStore {
+    pub async fn publish(&self, topic: &str, message: Vec<u8>) -> trc::Result<()> {
+        match &self.pool {
+            RedisPool::Single(pool) => pool
+                .get()
+                .await
+                .map_err(into_error)?
+                .as_mut()
+                .publish::<_, _, ()>(topic, message)
+                .await
+                .map_err(into_error),
+            RedisPool::Cluster(pool) => pool
+                .get()
+                .await
+                .map_err(into_error)?
+                .as_mut()
+                .publish::<_, _, ()>(topic, message)
+                .await
+                .map_err(into_error),
+        }
+    }
+
+    pub async fn subscribe(&self, topic: &str) -> trc::Result<PubSubStream> {
+        match &self.pool {
+            RedisPool::Single(pool) => {
+                // Subscriptions require a dedicated connection
+                let mut pubsub = pool
+                    .manager()
+                    .client
+                    .get_async_pubsub()
+                    .await
+                    .map_err(into_error)?;
+                pubsub.subscribe(topic).await.map_err(into_error)?;
+
+                Ok(Box::pin(
+                    pubsub
+                        .into_on_message()
+                        .map(|message| message.get_payload_bytes().to_vec()),
+                ))
+            }
+            RedisPool::Cluster(_) => Err(trc::StoreEvent::NotSupported
+                .into_err()
+                .details("Subscriptions are not supported on Redis clusters")),
+        }
+    }
+}
@@ -11,7 +11,10 @@ use rocksdb::{ColumnFamilyDescriptor, MergeOperands, OptimisticTransactionDB, Op
 use tokio::sync::oneshot;
 use utils::config::{utils::AsKey, Config};
 
-use crate::*;
+use crate::{
+    config::{parse_commit_limits, parse_expiring_subspaces, parse_max_value_size},
+    *,
+};
 
 use super::{RocksDbStore, CF_BLOBS};
 
@@ -90,6 +93,9 @@ impl RocksDbStore {
             SUBSPACE_TELEMETRY_SPAN,
             SUBSPACE_TELEMETRY_METRIC,
             SUBSPACE_TELEMETRY_INDEX,
+            SUBSPACE_RECENT,
+            SUBSPACE_QUARANTINE,
+            SUBSPACE_AUDIT,
         ] {
             let cf_opts = Options::default();
             cfs.push(ColumnFamilyDescriptor::new(
@@ -139,6 +145,9 @@ impl RocksDbStore {
                     )
                 })
                 .ok()?,
+            max_value_size: parse_max_value_size(config, &prefix),
+            expiring_subspaces: parse_expiring_subspaces(config, &prefix),
+            commit_limits: parse_commit_limits(config, &prefix),
         })
     }
 
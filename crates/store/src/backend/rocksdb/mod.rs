@@ -6,9 +6,12 @@
 
 use std::sync::Arc;
 
+use ahash::AHashMap;
 use rocksdb::{BoundColumnFamily, MultiThreaded, OptimisticTransactionDB};
 
-use crate::{SUBSPACE_BLOBS, SUBSPACE_INDEXES, SUBSPACE_LOGS};
+use crate::{
+    write::CommitLimits, SUBSPACE_BLOBS, SUBSPACE_INDEXES, SUBSPACE_LOGS, SUBSPACE_RECENT,
+};
 
 pub mod blob;
 pub mod main;
@@ -16,6 +19,7 @@ pub mod read;
 pub mod write;
 
 static CF_LOGS: &str = unsafe { std::str::from_utf8_unchecked(&[SUBSPACE_LOGS]) };
+static CF_RECENT: &str = unsafe { std::str::from_utf8_unchecked(&[SUBSPACE_RECENT]) };
 static CF_INDEXES: &str = unsafe { std::str::from_utf8_unchecked(&[SUBSPACE_INDEXES]) };
 static CF_BLOBS: &str = unsafe { std::str::from_utf8_unchecked(&[SUBSPACE_BLOBS]) };
 
@@ -34,6 +38,9 @@ impl CfHandle for OptimisticTransactionDB<MultiThreaded> {
 pub struct RocksDbStore {
     db: Arc<OptimisticTransactionDB<MultiThreaded>>,
     worker_pool: rayon::ThreadPool,
+    pub(crate) max_value_size: AHashMap<u8, usize>,
+    pub(crate) expiring_subspaces: Vec<(u8, u64)>,
+    pub(crate) commit_limits: CommitLimits,
 }
 
 #[inline(always)]
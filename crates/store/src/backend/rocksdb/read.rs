@@ -10,9 +10,9 @@ use rocksdb::{Direction, IteratorMode};
 use super::{into_error, RocksDbStore};
 
 use crate::{
-    backend::rocksdb::CfHandle,
+    backend::{deserialize_i64_le, rocksdb::CfHandle},
     write::{key::DeserializeBigEndian, BitmapClass, ValueClass},
-    BitmapKey, Deserialize, IterateParams, Key, ValueKey, U32_LEN,
+    BitmapKey, Deserialize, IterateParams, Key, StorageStats, ValueKey, SUBSPACES, U32_LEN,
 };
 
 impl RocksDbStore {
@@ -39,6 +39,55 @@ impl RocksDbStore {
         .await
     }
 
+    pub(crate) async fn get_value_size(&self, key: impl Key) -> trc::Result<Option<usize>> {
+        let db = self.db.clone();
+        self.spawn_worker(move || {
+            db.get_pinned_cf(&db.subspace_handle(key.subspace()), key.serialize(0))
+                .map(|value| value.map(|value| value.len()))
+                .map_err(into_error)
+        })
+        .await
+    }
+
+    pub(crate) async fn get_values(
+        &self,
+        keys: Vec<impl Key>,
+    ) -> trc::Result<Vec<Option<Vec<u8>>>> {
+        let db = self.db.clone();
+        self.spawn_worker(move || {
+            let cfs = keys
+                .iter()
+                .map(|key| db.subspace_handle(key.subspace()))
+                .collect::<Vec<_>>();
+            db.multi_get_cf(cfs.iter().zip(keys.iter().map(|key| key.serialize(0))))
+                .into_iter()
+                .map(|result| result.map_err(into_error))
+                .collect()
+        })
+        .await
+    }
+
+    pub(crate) async fn prefetch(&self, keys: &[impl Key]) -> trc::Result<()> {
+        let keys = keys
+            .iter()
+            .map(|key| (key.subspace(), key.serialize(0)))
+            .collect::<Vec<_>>();
+        let db = self.db.clone();
+        self.spawn_worker(move || {
+            // Loads the blocks holding these keys into the block cache
+            let cfs = keys
+                .iter()
+                .map(|(subspace, _)| db.subspace_handle(*subspace))
+                .collect::<Vec<_>>();
+            for result in db.multi_get_cf(cfs.iter().zip(keys.iter().map(|(_, key)| key))) {
+                result.map_err(into_error)?;
+            }
+
+            Ok(())
+        })
+        .await
+    }
+
     pub(crate) async fn get_bitmap(
         &self,
         mut key: BitmapKey<BitmapClass<u32>>,
@@ -69,6 +118,92 @@ impl RocksDbStore {
         .await
     }
 
+    pub(crate) async fn get_bitmap_cardinality(
+        &self,
+        mut key: BitmapKey<BitmapClass<u32>>,
+    ) -> trc::Result<u64> {
+        let db = self.db.clone();
+        self.spawn_worker(move || {
+            let mut count = 0;
+            let subspace = key.subspace();
+            let begin = key.serialize(0);
+            key.document_id = u32::MAX;
+            let end = key.serialize(0);
+            let key_len = begin.len();
+            for row in db.iterator_cf(
+                &db.subspace_handle(subspace),
+                IteratorMode::From(&begin, Direction::Forward),
+            ) {
+                let (key, _) = row.map_err(into_error)?;
+                let key = key.as_ref();
+                if key.len() == key_len && key >= begin.as_slice() && key <= end.as_slice() {
+                    count += 1;
+                } else {
+                    break;
+                }
+            }
+
+            Ok(count)
+        })
+        .await
+    }
+
+    // RocksDB only offers byte size estimates for a range, so an exact
+    // count requires walking the keys.
+    pub(crate) async fn get_keys_count(&self, from: impl Key, to: impl Key) -> trc::Result<u64> {
+        let db = self.db.clone();
+        let subspace = from.subspace();
+        let begin = from.serialize(0);
+        let end = to.serialize(0);
+
+        self.spawn_worker(move || {
+            let mut count = 0;
+            for row in db.iterator_cf(
+                &db.subspace_handle(subspace),
+                IteratorMode::From(&begin, Direction::Forward),
+            ) {
+                let (key, _) = row.map_err(into_error)?;
+                if key.as_ref() < end.as_slice() {
+                    count += 1;
+                } else {
+                    break;
+                }
+            }
+
+            Ok(count)
+        })
+        .await
+    }
+
+    pub(crate) async fn get_counters(
+        &self,
+        from: impl Key,
+        to: impl Key,
+    ) -> trc::Result<Vec<(Vec<u8>, i64)>> {
+        let db = self.db.clone();
+        let subspace = from.subspace();
+        let begin = from.serialize(0);
+        let end = to.serialize(0);
+
+        self.spawn_worker(move || {
+            let mut counters = Vec::new();
+            for row in db.iterator_cf(
+                &db.subspace_handle(subspace),
+                IteratorMode::From(&begin, Direction::Forward),
+            ) {
+                let (key, value) = row.map_err(into_error)?;
+                if key.as_ref() < end.as_slice() {
+                    counters.push((key.to_vec(), deserialize_i64_le(&key, &value)?));
+                } else {
+                    break;
+                }
+            }
+
+            Ok(counters)
+        })
+        .await
+    }
+
     pub(crate) async fn iterate<T: Key>(
         &self,
         params: IterateParams<T>,
@@ -126,4 +261,70 @@ impl RocksDbStore {
         })
         .await
     }
+
+    // MultiGet reads all keys from the same implicit snapshot
+    pub(crate) async fn get_value_and_counter(
+        &self,
+        value_key: ValueKey<ValueClass<u32>>,
+        counter_key: ValueKey<ValueClass<u32>>,
+    ) -> trc::Result<(Option<Vec<u8>>, i64)> {
+        let db = self.db.clone();
+        self.spawn_worker(move || {
+            let cfs = [
+                db.subspace_handle(value_key.subspace()),
+                db.subspace_handle(counter_key.subspace()),
+            ];
+            let counter_key = counter_key.serialize(0);
+            let mut results = db
+                .multi_get_cf([
+                    (&cfs[0], value_key.serialize(0)),
+                    (&cfs[1], counter_key.clone()),
+                ])
+                .into_iter();
+            let value = results.next().unwrap().map_err(into_error)?;
+            let counter = if let Some(bytes) = results.next().unwrap().map_err(into_error)? {
+                i64::from_le_bytes(bytes[..].try_into().map_err(|_| {
+                    trc::Error::corrupted_key(&counter_key, (&bytes[..]).into(), trc::location!())
+                })?)
+            } else {
+                0
+            };
+
+            Ok((value, counter))
+        })
+        .await
+    }
+
+    pub(crate) async fn get_storage_statistics(&self) -> trc::Result<StorageStats> {
+        let db = self.db.clone();
+        self.spawn_worker(move || {
+            let mut stats = StorageStats::default();
+
+            for subspace in SUBSPACES {
+                let Some(cf) = db.cf_handle(std::str::from_utf8(&[subspace]).unwrap()) else {
+                    continue;
+                };
+                let mut size = 0;
+                for property in [
+                    "rocksdb.estimate-live-data-size",
+                    "rocksdb.live-blob-file-size",
+                ] {
+                    size += db
+                        .property_int_value_cf(&cf, property)
+                        .map_err(into_error)?
+                        .unwrap_or_default();
+                }
+                stats.subspace_sizes.insert(subspace, size);
+                if let Some(total_rows) = db
+                    .property_int_value_cf(&cf, "rocksdb.estimate-num-keys")
+                    .map_err(into_error)?
+                {
+                    stats.total_rows.insert(subspace, total_rows);
+                }
+            }
+
+            Ok(stats)
+        })
+        .await
+    }
 }
@@ -4,38 +4,36 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::{
-    sync::Arc,
-    thread::sleep,
-    time::{Duration, Instant},
-};
+use std::{sync::Arc, thread::sleep, time::Instant};
 
-use rand::Rng;
 use roaring::RoaringBitmap;
 use rocksdb::{
     BoundColumnFamily, Direction, ErrorKind, IteratorMode, OptimisticTransactionDB,
     OptimisticTransactionOptions, WriteOptions,
 };
 
-use super::{into_error, CfHandle, RocksDbStore, CF_INDEXES, CF_LOGS};
+use super::{into_error, CfHandle, RocksDbStore, CF_INDEXES, CF_LOGS, CF_RECENT};
 use crate::{
     backend::deserialize_i64_le,
     write::{
-        key::DeserializeBigEndian, AssignedIds, Batch, BitmapClass, Operation, RandomAvailableId,
-        ValueOp, MAX_COMMIT_ATTEMPTS, MAX_COMMIT_TIME,
+        commit_backoff, key::DeserializeBigEndian, now, AssignedIds, Batch, BitmapClass, Operation,
+        RandomAvailableId, ValueOp,
     },
-    BitmapKey, Deserialize, IndexKey, Key, LogKey, SUBSPACE_COUNTER, SUBSPACE_QUOTA, U32_LEN,
+    BitmapKey, Deserialize, IndexKey, Key, LogKey, RecentKey, SUBSPACE_COUNTER, SUBSPACE_QUOTA,
+    U32_LEN,
 };
 
 impl RocksDbStore {
     pub(crate) async fn write(&self, batch: Batch) -> trc::Result<AssignedIds> {
         let db = self.db.clone();
+        let commit_limits = self.commit_limits;
 
         self.spawn_worker(move || {
             let mut txn = RocksDBTransaction {
                 db: &db,
                 cf_indexes: db.cf_handle(CF_INDEXES).unwrap(),
                 cf_logs: db.cf_handle(CF_LOGS).unwrap(),
+                cf_recent: db.cf_handle(CF_RECENT).unwrap(),
                 txn_opts: OptimisticTransactionOptions::default(),
                 batch: &batch,
             };
@@ -52,11 +50,190 @@ impl RocksDbStore {
                     Err(CommitError::Internal(err)) => return Err(err),
                     Err(CommitError::RocksDB(err)) => match err.kind() {
                         ErrorKind::Busy | ErrorKind::MergeInProgress | ErrorKind::TryAgain
-                            if retry_count < MAX_COMMIT_ATTEMPTS
-                                && start.elapsed() < MAX_COMMIT_TIME =>
+                            if retry_count < commit_limits.max_attempts
+                                && start.elapsed() < commit_limits.max_time =>
+                        {
+                            batch.check_deadline(start, &commit_limits)?;
+                            sleep(commit_backoff());
+                            retry_count += 1;
+                        }
+                        _ => return Err(into_error(err)),
+                    },
+                }
+            }
+        })
+        .await
+    }
+
+    pub(crate) async fn atomic_swap(
+        &self,
+        key: impl Key,
+        new_value: Vec<u8>,
+    ) -> trc::Result<Option<Vec<u8>>> {
+        let db = self.db.clone();
+        let commit_limits = self.commit_limits;
+
+        self.spawn_worker(move || {
+            let cf = db.subspace_handle(key.subspace());
+            let key = key.serialize(0);
+            let mut txn_opts = OptimisticTransactionOptions::default();
+            txn_opts.set_snapshot(true);
+
+            let mut retry_count = 0;
+            let start = Instant::now();
+            loop {
+                let txn = db.transaction_opt(&WriteOptions::default(), &txn_opts);
+                let old_value = txn.get_for_update_cf(&cf, &key, true).map_err(into_error)?;
+                txn.put_cf(&cf, &key, &new_value).map_err(into_error)?;
+
+                match txn.commit() {
+                    Ok(_) => return Ok(old_value),
+                    Err(err) => match err.kind() {
+                        ErrorKind::Busy | ErrorKind::MergeInProgress | ErrorKind::TryAgain
+                            if retry_count < commit_limits.max_attempts
+                                && start.elapsed() < commit_limits.max_time =>
                         {
-                            let backoff = rand::thread_rng().gen_range(50..=300);
-                            sleep(Duration::from_millis(backoff));
+                            sleep(commit_backoff());
+                            retry_count += 1;
+                        }
+                        _ => return Err(into_error(err)),
+                    },
+                }
+            }
+        })
+        .await
+    }
+
+    pub(crate) async fn compare_and_increment(
+        &self,
+        key: impl Key,
+        expected_version: u64,
+        by: i64,
+    ) -> trc::Result<i64> {
+        let db = self.db.clone();
+        let commit_limits = self.commit_limits;
+
+        self.spawn_worker(move || {
+            let cf = db.subspace_handle(key.subspace());
+            let key = key.serialize(0);
+            let expected_version = expected_version as i64;
+            let mut txn_opts = OptimisticTransactionOptions::default();
+            txn_opts.set_snapshot(true);
+
+            let mut retry_count = 0;
+            let start = Instant::now();
+            loop {
+                let txn = db.transaction_opt(&WriteOptions::default(), &txn_opts);
+                let current = if let Some(bytes) = txn
+                    .get_pinned_for_update_cf(&cf, &key, true)
+                    .map_err(into_error)?
+                {
+                    deserialize_i64_le(&key, &bytes)?
+                } else {
+                    0
+                };
+                if current != expected_version {
+                    return Err(trc::StoreEvent::AssertValueFailed.into());
+                }
+                let value = current + by;
+                txn.put_cf(&cf, &key, &value.to_le_bytes()[..])
+                    .map_err(into_error)?;
+
+                match txn.commit() {
+                    Ok(_) => return Ok(value),
+                    Err(err) => match err.kind() {
+                        ErrorKind::Busy | ErrorKind::MergeInProgress | ErrorKind::TryAgain
+                            if retry_count < commit_limits.max_attempts
+                                && start.elapsed() < commit_limits.max_time =>
+                        {
+                            sleep(commit_backoff());
+                            retry_count += 1;
+                        }
+                        _ => return Err(into_error(err)),
+                    },
+                }
+            }
+        })
+        .await
+    }
+
+    pub(crate) async fn get_and_reset_counter(&self, key: impl Key) -> trc::Result<i64> {
+        let db = self.db.clone();
+        let commit_limits = self.commit_limits;
+
+        self.spawn_worker(move || {
+            let cf = db.subspace_handle(key.subspace());
+            let key = key.serialize(0);
+            let mut txn_opts = OptimisticTransactionOptions::default();
+            txn_opts.set_snapshot(true);
+
+            let mut retry_count = 0;
+            let start = Instant::now();
+            loop {
+                let txn = db.transaction_opt(&WriteOptions::default(), &txn_opts);
+                let current = if let Some(bytes) = txn
+                    .get_pinned_for_update_cf(&cf, &key, true)
+                    .map_err(into_error)?
+                {
+                    deserialize_i64_le(&key, &bytes)?
+                } else {
+                    return Ok(0);
+                };
+                txn.put_cf(&cf, &key, &0i64.to_le_bytes()[..])
+                    .map_err(into_error)?;
+
+                match txn.commit() {
+                    Ok(_) => return Ok(current),
+                    Err(err) => match err.kind() {
+                        ErrorKind::Busy | ErrorKind::MergeInProgress | ErrorKind::TryAgain
+                            if retry_count < commit_limits.max_attempts
+                                && start.elapsed() < commit_limits.max_time =>
+                        {
+                            sleep(commit_backoff());
+                            retry_count += 1;
+                        }
+                        _ => return Err(into_error(err)),
+                    },
+                }
+            }
+        })
+        .await
+    }
+
+    pub(crate) async fn atomic_max(&self, key: impl Key, value: i64) -> trc::Result<i64> {
+        let db = self.db.clone();
+        let commit_limits = self.commit_limits;
+
+        self.spawn_worker(move || {
+            let cf = db.subspace_handle(key.subspace());
+            let key = key.serialize(0);
+            let mut txn_opts = OptimisticTransactionOptions::default();
+            txn_opts.set_snapshot(true);
+
+            let mut retry_count = 0;
+            let start = Instant::now();
+            loop {
+                let txn = db.transaction_opt(&WriteOptions::default(), &txn_opts);
+                if let Some(bytes) = txn
+                    .get_pinned_for_update_cf(&cf, &key, true)
+                    .map_err(into_error)?
+                {
+                    let current = deserialize_i64_le(&key, &bytes)?;
+                    if current >= value {
+                        return Ok(current);
+                    }
+                }
+                txn.put_cf(&cf, &key, &value.to_le_bytes()[..])
+                    .map_err(into_error)?;
+
+                match txn.commit() {
+                    Ok(_) => return Ok(value),
+                    Err(err) => match err.kind() {
+                        ErrorKind::Busy | ErrorKind::MergeInProgress | ErrorKind::TryAgain
+                            if retry_count < commit_limits.max_attempts
+                                && start.elapsed() < commit_limits.max_time =>
+                        {
+                            sleep(commit_backoff());
                             retry_count += 1;
                         }
                         _ => return Err(into_error(err)),
@@ -98,6 +275,38 @@ impl RocksDbStore {
         .await
     }
 
+    pub(crate) async fn rename_range(
+        &self,
+        from: impl Key,
+        to: impl Key,
+        account_id: u32,
+    ) -> trc::Result<u64> {
+        let db = self.db.clone();
+        self.spawn_worker(move || {
+            let cf = db.subspace_handle(from.subspace());
+            let from = from.serialize(0);
+            let to = to.serialize(0);
+            let txn = db.transaction();
+            let mut renamed = 0;
+
+            for row in db.iterator_cf(&cf, IteratorMode::From(&from, Direction::Forward)) {
+                let (key, value) = row.map_err(into_error)?;
+
+                if key.as_ref() < from.as_slice() || key.as_ref() >= to.as_slice() {
+                    break;
+                }
+                let mut new_key = key.to_vec();
+                new_key[..U32_LEN].copy_from_slice(&account_id.to_be_bytes());
+                txn.put_cf(&cf, &new_key, &value).map_err(into_error)?;
+                txn.delete_cf(&cf, &key).map_err(into_error)?;
+                renamed += 1;
+            }
+
+            txn.commit().map(|_| renamed).map_err(into_error)
+        })
+        .await
+    }
+
     pub(crate) async fn purge_store(&self) -> trc::Result<()> {
         let db = self.db.clone();
         self.spawn_worker(move || {
@@ -143,6 +352,7 @@ struct RocksDBTransaction<'x> {
     db: &'x OptimisticTransactionDB,
     cf_indexes: Arc<BoundColumnFamily<'x>>,
     cf_logs: Arc<BoundColumnFamily<'x>>,
+    cf_recent: Arc<BoundColumnFamily<'x>>,
     txn_opts: OptimisticTransactionOptions,
     batch: &'x Batch,
 }
@@ -195,6 +405,19 @@ impl RocksDBTransaction<'_> {
                         ValueOp::Set(value) => {
                             txn.put_cf(&cf, &key, value.resolve(&result)?.as_ref())?;
                         }
+                        ValueOp::Append { value, max_length } => {
+                            let mut bytes =
+                                txn.get_for_update_cf(&cf, &key, true)?.unwrap_or_default();
+                            bytes.extend_from_slice(value.resolve(&result)?.as_ref());
+                            if bytes.len() > *max_length {
+                                return Err(CommitError::Internal(
+                                    trc::StoreEvent::ValueTooLarge
+                                        .ctx(trc::Key::Size, bytes.len())
+                                        .ctx(trc::Key::Limit, *max_length),
+                                ));
+                            }
+                            txn.put_cf(&cf, &key, &bytes)?;
+                        }
                         ValueOp::AtomicAdd(by) => {
                             txn.merge_cf(&cf, &key, &by.to_le_bytes()[..])?;
                         }
@@ -219,6 +442,22 @@ impl RocksDBTransaction<'_> {
                         }
                     }
                 }
+                Operation::SetMany { class, items } => {
+                    let cf = self.db.subspace_handle(class.subspace(collection));
+                    for (document_id, value) in items {
+                        let key = class.serialize(
+                            account_id,
+                            collection,
+                            *document_id,
+                            0,
+                            (&result).into(),
+                        );
+                        txn.put_cf(&cf, &key, value.resolve(&result)?.as_ref())?;
+                    }
+                    for (document_id, _) in items {
+                        result.push_document_id(*document_id);
+                    }
+                }
                 Operation::Index { field, key, set } => {
                     let key = IndexKey {
                         account_id,
@@ -282,6 +521,18 @@ impl RocksDBTransaction<'_> {
                     } else {
                         txn.delete_cf(&cf, &key)?;
                     }
+
+                    // Track newly created documents
+                    if *set && is_document_id {
+                        let key = RecentKey {
+                            account_id,
+                            collection,
+                            timestamp: now(),
+                            document_id,
+                        }
+                        .serialize(0);
+                        txn.put_cf(&self.cf_recent, &key, [])?;
+                    }
                 }
                 Operation::Log { set } => {
                     let key = LogKey {
@@ -293,6 +544,16 @@ impl RocksDBTransaction<'_> {
 
                     txn.put_cf(&self.cf_logs, &key, set.resolve(&result)?.as_ref())?;
                 }
+                Operation::LogWithTimestamp { timestamp, set } => {
+                    let key = LogKey {
+                        account_id,
+                        collection,
+                        change_id,
+                    }
+                    .serialize_with_timestamp(0, *timestamp);
+
+                    txn.put_cf(&self.cf_logs, &key, set.resolve(&result)?.as_ref())?;
+                }
                 Operation::AssertValue {
                     class,
                     assert_value,
@@ -8,7 +8,10 @@ use r2d2::Pool;
 use tokio::sync::oneshot;
 use utils::config::{utils::AsKey, Config};
 
-use crate::*;
+use crate::{
+    config::{parse_expiring_subspaces, parse_max_value_size},
+    *,
+};
 
 use super::{into_error, pool::SqliteConnectionManager, SqliteStore};
 
@@ -56,6 +59,8 @@ impl SqliteStore {
                     )
                 })
                 .ok()?,
+            max_value_size: parse_max_value_size(config, &prefix),
+            expiring_subspaces: parse_expiring_subspaces(config, &prefix),
         };
 
         if let Err(err) = db.create_tables() {
@@ -80,6 +85,8 @@ impl SqliteStore {
                 .map_err(|err| {
                     into_error(err).ctx(trc::Key::Reason, "Failed to build worker pool")
                 })?,
+            max_value_size: AHashMap::new(),
+            expiring_subspaces: Vec::new(),
         };
         db.create_tables()?;
         Ok(db)
@@ -107,6 +114,8 @@ impl SqliteStore {
             SUBSPACE_TELEMETRY_SPAN,
             SUBSPACE_TELEMETRY_METRIC,
             SUBSPACE_TELEMETRY_INDEX,
+            SUBSPACE_QUARANTINE,
+            SUBSPACE_AUDIT,
         ] {
             let table = char::from(table);
             conn.execute(
@@ -126,6 +135,7 @@ impl SqliteStore {
             SUBSPACE_BITMAP_ID,
             SUBSPACE_BITMAP_TAG,
             SUBSPACE_BITMAP_TEXT,
+            SUBSPACE_RECENT,
         ] {
             let table = char::from(table);
             conn.execute(
@@ -169,6 +179,10 @@ impl SqliteStore {
             });
         });
 
+        // Release the pooled connection owned by the closure before yielding,
+        // otherwise it stays checked out while this task waits to be polled again
+        drop(f);
+
         match rx.await {
             Ok(result) => result,
             Err(err) => Err(trc::EventType::Server(trc::ServerEvent::ThreadError).reason(err)),
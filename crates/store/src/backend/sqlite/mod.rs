@@ -6,6 +6,7 @@
 
 use std::fmt::Display;
 
+use ahash::AHashMap;
 use r2d2::Pool;
 
 use self::pool::SqliteConnectionManager;
@@ -20,6 +21,8 @@ pub mod write;
 pub struct SqliteStore {
     pub(crate) conn_pool: Pool<SqliteConnectionManager>,
     pub(crate) worker_pool: rayon::ThreadPool,
+    pub(crate) max_value_size: AHashMap<u8, usize>,
+    pub(crate) expiring_subspaces: Vec<(u8, u64)>,
 }
 
 #[inline(always)]
@@ -9,9 +9,11 @@ use rusqlite::OptionalExtension;
 
 use crate::{
     write::{key::DeserializeBigEndian, BitmapClass, ValueClass},
-    BitmapKey, Deserialize, IterateParams, Key, ValueKey, U32_LEN,
+    BitmapKey, Deserialize, IterateParams, Key, StorageStats, ValueKey, U32_LEN,
 };
 
+use crate::backend::group_keys_by_subspace;
+
 use super::{into_error, SqliteStore};
 
 impl SqliteStore {
@@ -39,6 +41,69 @@ impl SqliteStore {
         .await
     }
 
+    pub(crate) async fn get_values(
+        &self,
+        keys: Vec<impl Key>,
+    ) -> trc::Result<Vec<Option<Vec<u8>>>> {
+        let conn = self.conn_pool.get().map_err(into_error)?;
+        self.spawn_worker(move || {
+            let mut results = vec![None; keys.len()];
+
+            for (subspace, positions) in group_keys_by_subspace(&keys) {
+                let mut query = conn
+                    .prepare_cached(&format!(
+                        "SELECT k, v FROM {} WHERE k IN ({})",
+                        char::from(subspace),
+                        vec!["?"; positions.len()].join(",")
+                    ))
+                    .map_err(into_error)?;
+                let mut rows = query
+                    .query(rusqlite::params_from_iter(positions.keys()))
+                    .map_err(into_error)?;
+
+                while let Some(row) = rows.next().map_err(into_error)? {
+                    let key = row
+                        .get_ref(0)
+                        .map_err(into_error)?
+                        .as_bytes()
+                        .map_err(into_error)?;
+                    if let Some(positions) = positions.get(key) {
+                        let value = row
+                            .get_ref(1)
+                            .map_err(into_error)?
+                            .as_bytes()
+                            .map_err(into_error)?;
+                        for pos in positions {
+                            results[*pos] = Some(value.to_vec());
+                        }
+                    }
+                }
+            }
+
+            Ok(results)
+        })
+        .await
+    }
+
+    pub(crate) async fn get_value_size(&self, key: impl Key) -> trc::Result<Option<usize>> {
+        let conn = self.conn_pool.get().map_err(into_error)?;
+        self.spawn_worker(move || {
+            let mut result = conn
+                .prepare_cached(&format!(
+                    "SELECT length(CAST(v AS BLOB)) FROM {} WHERE k = ?",
+                    char::from(key.subspace())
+                ))
+                .map_err(into_error)?;
+            let key = key.serialize(0);
+            result
+                .query_row([&key], |row| row.get::<_, i64>(0))
+                .optional()
+                .map(|size| size.map(|size| size as usize))
+                .map_err(into_error)
+        })
+        .await
+    }
+
     pub(crate) async fn get_bitmap(
         &self,
         mut key: BitmapKey<BitmapClass<u32>>,
@@ -72,6 +137,73 @@ impl SqliteStore {
         .await
     }
 
+    pub(crate) async fn get_bitmap_cardinality(
+        &self,
+        mut key: BitmapKey<BitmapClass<u32>>,
+    ) -> trc::Result<u64> {
+        let begin = key.serialize(0);
+        key.document_id = u32::MAX;
+        let key_len = begin.len() as i64;
+        let end = key.serialize(0);
+        let conn = self.conn_pool.get().map_err(into_error)?;
+        let table = char::from(key.subspace());
+
+        self.spawn_worker(move || {
+            conn.prepare_cached(&format!(
+                "SELECT COUNT(*) FROM {table} WHERE k >= ? AND k <= ? AND LENGTH(k) = ?"
+            ))
+            .map_err(into_error)?
+            .query_row(rusqlite::params![&begin, &end, key_len], |row| {
+                row.get::<_, i64>(0)
+            })
+            .map(|count| count as u64)
+            .map_err(into_error)
+        })
+        .await
+    }
+
+    pub(crate) async fn get_keys_count(&self, from: impl Key, to: impl Key) -> trc::Result<u64> {
+        let table = char::from(from.subspace());
+        let begin = from.serialize(0);
+        let end = to.serialize(0);
+        let conn = self.conn_pool.get().map_err(into_error)?;
+
+        self.spawn_worker(move || {
+            conn.prepare_cached(&format!(
+                "SELECT COUNT(*) FROM {table} WHERE k >= ? AND k < ?"
+            ))
+            .map_err(into_error)?
+            .query_row(rusqlite::params![&begin, &end], |row| row.get::<_, i64>(0))
+            .map(|count| count as u64)
+            .map_err(into_error)
+        })
+        .await
+    }
+
+    pub(crate) async fn get_counters(
+        &self,
+        from: impl Key,
+        to: impl Key,
+    ) -> trc::Result<Vec<(Vec<u8>, i64)>> {
+        let table = char::from(from.subspace());
+        let begin = from.serialize(0);
+        let end = to.serialize(0);
+        let conn = self.conn_pool.get().map_err(into_error)?;
+
+        self.spawn_worker(move || {
+            let mut s = conn
+                .prepare_cached(&format!("SELECT k, v FROM {table} WHERE k >= ? AND k < ?"))
+                .map_err(into_error)?;
+            let rows = s
+                .query_map(rusqlite::params![&begin, &end], |row| {
+                    Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, i64>(1)?))
+                })
+                .map_err(into_error)?;
+            rows.collect::<Result<Vec<_>, _>>().map_err(into_error)
+        })
+        .await
+    }
+
     pub(crate) async fn iterate<T: Key>(
         &self,
         params: IterateParams<T>,
@@ -166,4 +298,69 @@ impl SqliteStore {
         })
         .await
     }
+
+    // Reads a value and a counter from a single statement, so both are
+    // obtained from the same snapshot of the database.
+    pub(crate) async fn get_value_and_counter(
+        &self,
+        value_key: ValueKey<ValueClass<u32>>,
+        counter_key: ValueKey<ValueClass<u32>>,
+    ) -> trc::Result<(Option<Vec<u8>>, i64)> {
+        let query = format!(
+            "SELECT (SELECT v FROM {} WHERE k = ?), (SELECT v FROM {} WHERE k = ?)",
+            char::from(value_key.subspace()),
+            char::from(counter_key.subspace())
+        );
+        let value_key = value_key.serialize(0);
+        let counter_key = counter_key.serialize(0);
+        let conn = self.conn_pool.get().map_err(into_error)?;
+        self.spawn_worker(move || {
+            conn.prepare_cached(&query)
+                .map_err(into_error)?
+                .query_row([&value_key, &counter_key], |row| {
+                    Ok((
+                        row.get::<_, Option<Vec<u8>>>(0)?,
+                        row.get::<_, Option<i64>>(1)?.unwrap_or_default(),
+                    ))
+                })
+                .map_err(into_error)
+        })
+        .await
+    }
+
+    pub(crate) async fn get_storage_statistics(&self) -> trc::Result<StorageStats> {
+        let conn = self.conn_pool.get().map_err(into_error)?;
+        self.spawn_worker(move || {
+            let mut stats = StorageStats::default();
+
+            // Page usage of each table, including its primary key index
+            let mut query = conn
+                .prepare_cached(concat!(
+                    "SELECT m.tbl_name, SUM(s.pgsize) FROM dbstat AS s ",
+                    "JOIN sqlite_master AS m ON s.name = m.name GROUP BY m.tbl_name"
+                ))
+                .map_err(into_error)?;
+            let mut rows = query.query([]).map_err(into_error)?;
+            while let Some(row) = rows.next().map_err(into_error)? {
+                let table = row.get::<_, String>(0).map_err(into_error)?;
+                if let [subspace] = table.as_bytes() {
+                    stats
+                        .subspace_sizes
+                        .insert(*subspace, row.get::<_, i64>(1).map_err(into_error)? as u64);
+                }
+            }
+
+            for subspace in stats.subspace_sizes.keys().copied().collect::<Vec<_>>() {
+                let total_rows = conn
+                    .prepare_cached(&format!("SELECT COUNT(*) FROM {}", char::from(subspace)))
+                    .map_err(into_error)?
+                    .query_row([], |row| row.get::<_, i64>(0))
+                    .map_err(into_error)?;
+                stats.total_rows.insert(subspace, total_rows as u64);
+            }
+
+            Ok(stats)
+        })
+        .await
+    }
 }
@@ -4,30 +4,38 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+use std::time::Instant;
+
 use roaring::RoaringBitmap;
-use rusqlite::{params, OptionalExtension, TransactionBehavior};
+use rusqlite::{params, params_from_iter, OptionalExtension, TransactionBehavior};
 
 use crate::{
     write::{
-        key::DeserializeBigEndian, AssignedIds, Batch, BitmapClass, Operation, RandomAvailableId,
-        ValueOp,
+        key::DeserializeBigEndian, now, AssignedIds, Batch, BitmapClass, CommitLimits, Operation,
+        RandomAvailableId, ValueOp, MAX_SET_MANY_ROWS,
     },
-    BitmapKey, IndexKey, Key, LogKey, SUBSPACE_COUNTER, SUBSPACE_QUOTA, U32_LEN,
+    BitmapKey, IndexKey, Key, LogKey, RecentKey, SUBSPACE_COUNTER, SUBSPACE_QUOTA, U32_LEN,
 };
 
 use super::{into_error, SqliteStore};
 
 impl SqliteStore {
     pub(crate) async fn write(&self, batch: Batch) -> trc::Result<AssignedIds> {
+        let start = Instant::now();
         let mut conn = self.conn_pool.get().map_err(into_error)?;
         self.spawn_worker(move || {
             let mut account_id = u32::MAX;
             let mut collection = u8::MAX;
             let mut document_id = u32::MAX;
             let mut change_id = u64::MAX;
+            // Acquire the write lock upfront, upgrading the read lock of a deferred
+            // transaction fails with SQLITE_BUSY when another writer got there first
             let trx = conn
                 .transaction_with_behavior(TransactionBehavior::Immediate)
                 .map_err(into_error)?;
+            // Time spent waiting for a connection and the write lock counts
+            // towards the batch deadline
+            batch.check_deadline(start, &CommitLimits::default())?;
             let mut result = AssignedIds::default();
 
             for op in &batch.ops {
@@ -72,6 +80,31 @@ impl SqliteStore {
                                 .execute([&key, value.resolve(&result)?.as_ref()])
                                 .map_err(into_error)?;
                             }
+                            ValueOp::Append { value, max_length } => {
+                                // Read and written back rather than concatenated in SQL,
+                                // which converts blobs to text. No other writer can run
+                                // in between as the transaction holds the write lock.
+                                let mut bytes = trx
+                                    .prepare_cached(&format!("SELECT v FROM {} WHERE k = ?", table))
+                                    .map_err(into_error)?
+                                    .query_row([&key], |row| row.get::<_, Vec<u8>>(0))
+                                    .optional()
+                                    .map_err(into_error)?
+                                    .unwrap_or_default();
+                                bytes.extend_from_slice(value.resolve(&result)?.as_ref());
+                                if bytes.len() > *max_length {
+                                    return Err(trc::StoreEvent::ValueTooLarge
+                                        .ctx(trc::Key::Size, bytes.len())
+                                        .ctx(trc::Key::Limit, *max_length));
+                                }
+                                trx.prepare_cached(&format!(
+                                    "INSERT OR REPLACE INTO {} (k, v) VALUES (?, ?)",
+                                    table
+                                ))
+                                .map_err(into_error)?
+                                .execute([&key, &bytes])
+                                .map_err(into_error)?;
+                            }
                             ValueOp::AtomicAdd(by) => {
                                 if *by >= 0 {
                                     trx.prepare_cached(&format!(
@@ -116,6 +149,39 @@ impl SqliteStore {
                             }
                         }
                     }
+                    Operation::SetMany { class, items } => {
+                        let table = char::from(class.subspace(collection));
+                        let mut rows = Vec::with_capacity(items.len());
+                        for (document_id, value) in items {
+                            rows.push((
+                                class.serialize(
+                                    account_id,
+                                    collection,
+                                    *document_id,
+                                    0,
+                                    (&result).into(),
+                                ),
+                                value.resolve(&result)?.into_owned(),
+                            ));
+                        }
+
+                        for rows in rows.chunks(MAX_SET_MANY_ROWS) {
+                            trx.prepare_cached(&format!(
+                                "INSERT OR REPLACE INTO {table} (k, v) VALUES {}",
+                                vec!["(?, ?)"; rows.len()].join(", ")
+                            ))
+                            .map_err(into_error)?
+                            .execute(params_from_iter(
+                                rows.iter()
+                                    .flat_map(|(key, value)| [key.as_slice(), value.as_slice()]),
+                            ))
+                            .map_err(into_error)?;
+                        }
+
+                        for (document_id, _) in items {
+                            result.push_document_id(*document_id);
+                        }
+                    }
                     Operation::Index { field, key, set } => {
                         let key = IndexKey {
                             account_id,
@@ -207,6 +273,21 @@ impl SqliteStore {
                                 .execute(params![&key])
                                 .map_err(into_error)?;
                         };
+
+                        // Track newly created documents
+                        if *set && is_document_id {
+                            let key = RecentKey {
+                                account_id,
+                                collection,
+                                timestamp: now(),
+                                document_id,
+                            }
+                            .serialize(0);
+                            trx.prepare_cached("INSERT OR IGNORE INTO y (k) VALUES (?)")
+                                .map_err(into_error)?
+                                .execute(params![&key])
+                                .map_err(into_error)?;
+                        }
                     }
                     Operation::Log { set } => {
                         let key = LogKey {
@@ -221,6 +302,19 @@ impl SqliteStore {
                             .execute([&key, set.resolve(&result).map_err(into_error)?.as_ref()])
                             .map_err(into_error)?;
                     }
+                    Operation::LogWithTimestamp { timestamp, set } => {
+                        let key = LogKey {
+                            account_id,
+                            collection,
+                            change_id,
+                        }
+                        .serialize_with_timestamp(0, *timestamp);
+
+                        trx.prepare_cached("INSERT OR REPLACE INTO l (k, v) VALUES (?, ?)")
+                            .map_err(into_error)?
+                            .execute([&key, set.resolve(&result).map_err(into_error)?.as_ref()])
+                            .map_err(into_error)?;
+                    }
                     Operation::AssertValue {
                         class,
                         assert_value,
@@ -271,6 +365,136 @@ impl SqliteStore {
         .await
     }
 
+    pub(crate) async fn atomic_swap(
+        &self,
+        key: impl Key,
+        new_value: Vec<u8>,
+    ) -> trc::Result<Option<Vec<u8>>> {
+        let mut conn = self.conn_pool.get().map_err(into_error)?;
+        self.spawn_worker(move || {
+            let table = char::from(key.subspace());
+            let key = key.serialize(0);
+            let trx = conn
+                .transaction_with_behavior(TransactionBehavior::Immediate)
+                .map_err(into_error)?;
+
+            let old_value = trx
+                .prepare_cached(&format!("SELECT v FROM {table} WHERE k = ?"))
+                .map_err(into_error)?
+                .query_row([&key], |row| row.get::<_, Vec<u8>>(0))
+                .optional()
+                .map_err(into_error)?;
+            trx.prepare_cached(&format!(
+                "INSERT OR REPLACE INTO {table} (k, v) VALUES (?, ?)"
+            ))
+            .map_err(into_error)?
+            .execute([&key, &new_value])
+            .map_err(into_error)?;
+
+            trx.commit().map(|_| old_value).map_err(into_error)
+        })
+        .await
+    }
+
+    pub(crate) async fn compare_and_increment(
+        &self,
+        key: impl Key,
+        expected_version: u64,
+        by: i64,
+    ) -> trc::Result<i64> {
+        let mut conn = self.conn_pool.get().map_err(into_error)?;
+        self.spawn_worker(move || {
+            let table = char::from(key.subspace());
+            let key = key.serialize(0);
+            let expected_version = expected_version as i64;
+            let trx = conn
+                .transaction_with_behavior(TransactionBehavior::Immediate)
+                .map_err(into_error)?;
+
+            let current = trx
+                .prepare_cached(&format!("SELECT v FROM {table} WHERE k = ?"))
+                .map_err(into_error)?
+                .query_row([&key], |row| row.get::<_, i64>(0))
+                .optional()
+                .map_err(into_error)?;
+            if current.unwrap_or_default() != expected_version {
+                return Err(trc::StoreEvent::AssertValueFailed.into());
+            }
+            trx.prepare_cached(&format!(
+                "INSERT OR REPLACE INTO {table} (k, v) VALUES (?, ?)"
+            ))
+            .map_err(into_error)?
+            .execute(params![&key, expected_version + by])
+            .map_err(into_error)?;
+
+            trx.commit()
+                .map(|_| expected_version + by)
+                .map_err(into_error)
+        })
+        .await
+    }
+
+    pub(crate) async fn get_and_reset_counter(&self, key: impl Key) -> trc::Result<i64> {
+        let mut conn = self.conn_pool.get().map_err(into_error)?;
+        self.spawn_worker(move || {
+            let table = char::from(key.subspace());
+            let key = key.serialize(0);
+            let trx = conn
+                .transaction_with_behavior(TransactionBehavior::Immediate)
+                .map_err(into_error)?;
+
+            let current = trx
+                .prepare_cached(&format!("SELECT v FROM {table} WHERE k = ?"))
+                .map_err(into_error)?
+                .query_row([&key], |row| row.get::<_, i64>(0))
+                .optional()
+                .map_err(into_error)?
+                .unwrap_or_default();
+            if current != 0 {
+                trx.prepare_cached(&format!("UPDATE {table} SET v = 0 WHERE k = ?"))
+                    .map_err(into_error)?
+                    .execute([&key])
+                    .map_err(into_error)?;
+            }
+
+            trx.commit().map(|_| current).map_err(into_error)
+        })
+        .await
+    }
+
+    pub(crate) async fn atomic_max(&self, key: impl Key, value: i64) -> trc::Result<i64> {
+        let mut conn = self.conn_pool.get().map_err(into_error)?;
+        self.spawn_worker(move || {
+            let table = char::from(key.subspace());
+            let key = key.serialize(0);
+            let trx = conn
+                .transaction_with_behavior(TransactionBehavior::Immediate)
+                .map_err(into_error)?;
+
+            let current = trx
+                .prepare_cached(&format!("SELECT v FROM {table} WHERE k = ?"))
+                .map_err(into_error)?
+                .query_row([&key], |row| row.get::<_, i64>(0))
+                .optional()
+                .map_err(into_error)?;
+            let result = match current {
+                Some(current) if current >= value => current,
+                _ => {
+                    trx.prepare_cached(&format!(
+                        "INSERT OR REPLACE INTO {table} (k, v) VALUES (?, ?)"
+                    ))
+                    .map_err(into_error)?
+                    .execute(params![&key, value])
+                    .map_err(into_error)?;
+                    value
+                }
+            };
+
+            trx.commit().map(|_| result).map_err(into_error)
+        })
+        .await
+    }
+
     pub(crate) async fn delete_range(&self, from: impl Key, to: impl Key) -> trc::Result<()> {
         let conn = self.conn_pool.get().map_err(into_error)?;
         self.spawn_worker(move || {
@@ -286,4 +510,43 @@ impl SqliteStore {
         })
         .await
     }
+
+    pub(crate) async fn rename_range(
+        &self,
+        from: impl Key,
+        to: impl Key,
+        account_id: u32,
+    ) -> trc::Result<u64> {
+        let mut conn = self.conn_pool.get().map_err(into_error)?;
+        self.spawn_worker(move || {
+            let table = char::from(from.subspace());
+            let trx = conn
+                .transaction_with_behavior(TransactionBehavior::Immediate)
+                .map_err(into_error)?;
+
+            let keys = trx
+                .prepare_cached(&format!("SELECT k FROM {table} WHERE k >= ? AND k < ?"))
+                .map_err(into_error)?
+                .query_map([from.serialize(0), to.serialize(0)], |row| {
+                    row.get::<_, Vec<u8>>(0)
+                })
+                .map_err(into_error)?
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(into_error)?;
+
+            {
+                let mut s = trx
+                    .prepare_cached(&format!("UPDATE {table} SET k = ? WHERE k = ?"))
+                    .map_err(into_error)?;
+                for key in &keys {
+                    let mut new_key = key.clone();
+                    new_key[..U32_LEN].copy_from_slice(&account_id.to_be_bytes());
+                    s.execute([&new_key, key]).map_err(into_error)?;
+                }
+            }
+
+            trx.commit().map(|_| keys.len() as u64).map_err(into_error)
+        })
+        .await
+    }
 }
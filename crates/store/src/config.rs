@@ -4,14 +4,19 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
+use ahash::AHashMap;
 use utils::config::{cron::SimpleCron, utils::ParseValue, Config};
 
 use crate::{
     backend::fs::FsStore,
-    write::purge::{PurgeSchedule, PurgeStore},
-    BlobStore, CompressionAlgo, LookupStore, QueryStore, Store, Stores,
+    subspace_name,
+    write::{
+        purge::{PurgeSchedule, PurgeStore},
+        CommitLimits, MIN_COMMIT_BACKOFF,
+    },
+    BlobStore, CompressionAlgo, LookupStore, QueryStore, Store, Stores, SUBSPACES,
 };
 
 #[cfg(feature = "s3")]
@@ -38,6 +43,9 @@ use crate::backend::elastic::ElasticSearchStore;
 #[cfg(feature = "redis")]
 use crate::backend::redis::RedisStore;
 
+#[cfg(feature = "nats")]
+use crate::backend::nats::NatsStore;
+
 #[cfg(feature = "azure")]
 use crate::backend::azure::AzureStore;
 
@@ -94,12 +102,16 @@ impl Stores {
                         && self
                             .stores
                             .values()
-                            .any(|store| matches!(store, Store::RocksDb(_)))
+                            .any(|store| matches!(store.backend(), Store::RocksDb(_)))
                     {
                         continue;
                     }
 
-                    if let Some(db) = RocksDbStore::open(config, prefix).await.map(Store::from) {
+                    if let Some(db) = RocksDbStore::open(config, prefix)
+                        .await
+                        .map(Store::from)
+                        .map(|db| db.with_circuit_breaker(config, prefix))
+                    {
                         self.stores.insert(store_id.clone(), db.clone());
                         self.fts_stores.insert(store_id.clone(), db.clone().into());
                         self.blob_stores.insert(
@@ -116,12 +128,16 @@ impl Stores {
                         && self
                             .stores
                             .values()
-                            .any(|store| matches!(store, Store::FoundationDb(_)))
+                            .any(|store| matches!(store.backend(), Store::FoundationDb(_)))
                     {
                         continue;
                     }
 
-                    if let Some(db) = FdbStore::open(config, prefix).await.map(Store::from) {
+                    if let Some(db) = FdbStore::open(config, prefix)
+                        .await
+                        .map(Store::from)
+                        .map(|db| db.with_circuit_breaker(config, prefix))
+                    {
                         self.stores.insert(store_id.clone(), db.clone());
                         self.fts_stores.insert(store_id.clone(), db.clone().into());
                         self.blob_stores.insert(
@@ -137,6 +153,7 @@ impl Stores {
                         PostgresStore::open(config, prefix, config.is_active_store(id))
                             .await
                             .map(Store::from)
+                            .map(|db| db.with_circuit_breaker(config, prefix))
                     {
                         self.stores.insert(store_id.clone(), db.clone());
                         self.fts_stores.insert(store_id.clone(), db.clone().into());
@@ -152,6 +169,7 @@ impl Stores {
                     if let Some(db) = MysqlStore::open(config, prefix, config.is_active_store(id))
                         .await
                         .map(Store::from)
+                        .map(|db| db.with_circuit_breaker(config, prefix))
                     {
                         self.stores.insert(store_id.clone(), db.clone());
                         self.fts_stores.insert(store_id.clone(), db.clone().into());
@@ -169,12 +187,15 @@ impl Stores {
                         && self
                             .stores
                             .values()
-                            .any(|store| matches!(store, Store::SQLite(_)))
+                            .any(|store| matches!(store.backend(), Store::SQLite(_)))
                     {
                         continue;
                     }
 
-                    if let Some(db) = SqliteStore::open(config, prefix).map(Store::from) {
+                    if let Some(db) = SqliteStore::open(config, prefix)
+                        .map(Store::from)
+                        .map(|db| db.with_circuit_breaker(config, prefix))
+                    {
                         self.stores.insert(store_id.clone(), db.clone());
                         self.fts_stores.insert(store_id.clone(), db.clone().into());
                         self.blob_stores.insert(
@@ -208,15 +229,21 @@ impl Stores {
                 }
                 #[cfg(feature = "redis")]
                 "redis" => {
-                    if let Some(db) = RedisStore::open(config, prefix)
-                        .await
-                        .map(LookupStore::from)
-                    {
-                        self.lookup_stores.insert(store_id, db);
+                    if let Some(db) = RedisStore::open(config, prefix).await.map(Arc::new) {
+                        self.pubsub_stores
+                            .insert(store_id.clone(), crate::PubSubStore::Redis(db.clone()));
+                        self.lookup_stores.insert(store_id, LookupStore::Redis(db));
+                    }
+                }
+                #[cfg(feature = "nats")]
+                "nats" => {
+                    if let Some(db) = NatsStore::open(config, prefix).await {
+                        self.pubsub_stores
+                            .insert(store_id, crate::PubSubStore::Nats(Arc::new(db)));
                     }
                 }
                 #[cfg(feature = "enterprise")]
-                "sql-read-replica" | "distributed-blob" => {
+                "sql-read-replica" | "distributed-blob" | "sharded" => {
                     composite_stores.push((store_id, protocol));
                 }
                 #[cfg(feature = "azure")]
@@ -265,6 +292,20 @@ impl Stores {
                         self.lookup_stores.insert(id.to_string(), db.into());
                     }
                 }
+                "sharded" => {
+                    if let Some(db) =
+                        crate::backend::composite::sharded::ShardedStore::open(config, prefix, self)
+                    {
+                        let db = Store::Sharded(db.into());
+                        self.stores.insert(id.to_string(), db.clone());
+                        self.fts_stores.insert(id.to_string(), db.clone().into());
+                        self.blob_stores.insert(
+                            id.to_string(),
+                            BlobStore::from(db.clone()).with_compression(compression),
+                        );
+                        self.lookup_stores.insert(id.to_string(), db.into());
+                    }
+                }
                 "distributed-blob" => {
                     if let Some(db) =
                         crate::backend::composite::distributed_blob::DistributedBlob::open(
@@ -378,6 +419,96 @@ impl Stores {
     }
 }
 
+// Parses the optional per-subspace value size limits, keyed by subspace name:
+//
+// [store."<id>".max-value-size]
+// property = 10485760
+// counter = 8
+#[allow(dead_code)]
+pub(crate) fn parse_max_value_size(config: &mut Config, prefix: &str) -> AHashMap<u8, usize> {
+    let mut max_value_size = AHashMap::new();
+    let key_prefix = format!("{prefix}.max-value-size.");
+
+    for (key, size) in config.properties::<usize>((prefix, "max-value-size")) {
+        let name = key.strip_prefix(&key_prefix).unwrap_or_default();
+        if let Some(subspace) = SUBSPACES
+            .iter()
+            .find(|subspace| subspace_name(**subspace) == name)
+        {
+            max_value_size.insert(*subspace, size);
+        } else {
+            let err = format!("Unknown subspace {name:?}");
+            config.new_parse_error(key, err);
+        }
+    }
+
+    max_value_size
+}
+
+// Parses the subspaces whose keys end with a big-endian creation timestamp in
+// seconds, along with how long their keys are kept before being purged:
+//
+// [store."<id>".purge.expire]
+// <subspace-name> = "30d"
+#[allow(dead_code)]
+pub(crate) fn parse_expiring_subspaces(config: &mut Config, prefix: &str) -> Vec<(u8, u64)> {
+    let mut expiring_subspaces = Vec::new();
+    let key_prefix = format!("{prefix}.purge.expire.");
+
+    for (key, ttl) in config.properties::<Duration>((prefix, "purge.expire")) {
+        let name = key.strip_prefix(&key_prefix).unwrap_or_default();
+        if let Some(subspace) = SUBSPACES
+            .iter()
+            .find(|subspace| subspace_name(**subspace) == name)
+        {
+            expiring_subspaces.push((*subspace, ttl.as_secs()));
+        } else {
+            let err = format!("Unknown subspace {name:?}");
+            config.new_parse_error(key, err);
+        }
+    }
+
+    expiring_subspaces
+}
+
+// Parses the retry limits of conflicting transactions, which must allow
+// at least as many attempts as fit in the time limit at the minimum backoff:
+//
+// [store."<id>".commit]
+// max-attempts = 10
+// max-time = "10s"
+pub fn parse_commit_limits(config: &mut Config, prefix: &str) -> CommitLimits {
+    let default = CommitLimits::default();
+    let limits = CommitLimits {
+        max_attempts: config
+            .property::<u32>((prefix, "commit.max-attempts"))
+            .unwrap_or(default.max_attempts),
+        max_time: config
+            .property::<Duration>((prefix, "commit.max-time"))
+            .unwrap_or(default.max_time),
+    };
+
+    if limits.max_attempts == 0 {
+        config.new_parse_error(
+            (prefix, "commit.max-attempts"),
+            "At least one commit attempt is required",
+        );
+        default
+    } else if limits.max_time < MIN_COMMIT_BACKOFF * limits.max_attempts {
+        config.new_parse_error(
+            (prefix, "commit.max-time"),
+            format!(
+                "Commit time limit must be at least {}ms for {} attempts",
+                (MIN_COMMIT_BACKOFF * limits.max_attempts).as_millis(),
+                limits.max_attempts
+            ),
+        );
+        default
+    } else {
+        limits
+    }
+}
+
 #[allow(dead_code)]
 trait IsActiveStore {
     fn is_active_store(&self, id: &str) -> bool;
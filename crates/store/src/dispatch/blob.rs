@@ -4,12 +4,12 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::{borrow::Cow, ops::Range, time::Instant};
+use std::{borrow::Cow, io::Cursor, ops::Range, time::Instant};
 
 use trc::{AddContext, StoreEvent};
 use utils::config::utils::ParseValue;
 
-use crate::{BlobBackend, BlobStore, CompressionAlgo, Store};
+use crate::{BlobBackend, BlobStore, BlobStream, CompressionAlgo, Store};
 
 impl BlobStore {
     pub async fn get_blob(&self, key: &[u8], range: Range<usize>) -> trc::Result<Option<Vec<u8>>> {
@@ -32,6 +32,11 @@ impl BlobStore {
                 Store::RocksDb(store) => store.get_blob(key, read_range).await,
                 #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
                 Store::SQLReadReplica(store) => store.get_blob(key, read_range).await,
+                #[cfg(feature = "enterprise")]
+                Store::Sharded(store) => store.get_blob(key, read_range).await,
+                Store::CircuitBreaker(store) => store.get_blob(key, read_range).await,
+                #[cfg(feature = "test_mode")]
+                Store::Mock(store) => store.get_blob(key, read_range).await,
                 Store::None => Err(trc::StoreEvent::NotConfigured.into()),
             },
             BlobBackend::Fs(store) => store.get_blob(key, read_range).await,
@@ -89,6 +94,37 @@ impl BlobStore {
         }
     }
 
+    pub async fn get_blob_stream(&self, key: &[u8]) -> trc::Result<Option<BlobStream>> {
+        let start_time = Instant::now();
+        let result = match (&self.backend, self.compression) {
+            (BlobBackend::Fs(store), CompressionAlgo::None) => store.get_blob_stream(key).await,
+            #[cfg(feature = "foundation")]
+            (BlobBackend::Store(Store::FoundationDb(store)), CompressionAlgo::None) => {
+                store.clone().get_blob_stream(key).await
+            }
+            #[cfg(feature = "postgres")]
+            (BlobBackend::Store(Store::PostgreSQL(store)), CompressionAlgo::None) => {
+                store.get_blob_stream(key).await
+            }
+            _ => {
+                // Compressed blobs and backends that store blobs as a single value
+                // have to be read in full before they can be streamed.
+                return self
+                    .get_blob(key, 0..usize::MAX)
+                    .await
+                    .map(|blob| blob.map(|blob| Box::pin(Cursor::new(blob)) as BlobStream));
+            }
+        };
+
+        trc::event!(
+            Store(StoreEvent::BlobRead),
+            Key = key,
+            Elapsed = start_time.elapsed(),
+        );
+
+        result.caused_by(trc::location!())
+    }
+
     pub async fn put_blob(&self, key: &[u8], data: &[u8]) -> trc::Result<()> {
         let data: Cow<[u8]> = match self.compression {
             CompressionAlgo::None => data.into(),
@@ -114,6 +150,11 @@ impl BlobStore {
                 Store::RocksDb(store) => store.put_blob(key, data.as_ref()).await,
                 #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
                 Store::SQLReadReplica(store) => store.put_blob(key, data.as_ref()).await,
+                #[cfg(feature = "enterprise")]
+                Store::Sharded(store) => store.put_blob(key, data.as_ref()).await,
+                Store::CircuitBreaker(store) => store.put_blob(key, data.as_ref()).await,
+                #[cfg(feature = "test_mode")]
+                Store::Mock(store) => store.put_blob(key, data.as_ref()).await,
                 Store::None => Err(trc::StoreEvent::NotConfigured.into()),
             },
             BlobBackend::Fs(store) => store.put_blob(key, data.as_ref()).await,
@@ -152,6 +193,11 @@ impl BlobStore {
                 Store::RocksDb(store) => store.delete_blob(key).await,
                 #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
                 Store::SQLReadReplica(store) => store.delete_blob(key).await,
+                #[cfg(feature = "enterprise")]
+                Store::Sharded(store) => store.delete_blob(key).await,
+                Store::CircuitBreaker(store) => store.delete_blob(key).await,
+                #[cfg(feature = "test_mode")]
+                Store::Mock(store) => store.delete_blob(key).await,
                 Store::None => Err(trc::StoreEvent::NotConfigured.into()),
             },
             BlobBackend::Fs(store) => store.delete_blob(key).await,
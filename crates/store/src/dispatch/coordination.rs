@@ -0,0 +1,287 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use tokio::task::JoinHandle;
+use trc::AddContext;
+
+use crate::{
+    write::{
+        assert::{AssertValue, HashedValue},
+        key::{DeserializeBigEndian, KeySerializer},
+        now, BatchBuilder, LookupClass, ValueClass,
+    },
+    Deserialize, Store, ValueKey, U64_LEN,
+};
+
+use super::lock::StoreLock;
+
+// Lease held by singleton tasks, renewed while the node is alive
+const SINGLETON_LEASE: Duration = Duration::from_secs(30);
+
+// Lock acquired with Store::acquire_lock, its lease is renewed in the
+// background until the guard is dropped.
+pub struct LockGuard {
+    name: String,
+    fence: LockFence,
+    expires: Arc<AtomicU64>,
+    crashed: Arc<AtomicBool>,
+    heartbeat: JoinHandle<()>,
+}
+
+// Fencing tokens increase every time a lock changes hands. Writes performed
+// under a lock assert the token, so a holder that resumes after its lease
+// expired cannot overwrite the changes made by the new holder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockFence {
+    key: Vec<u8>,
+    token: u64,
+}
+
+// Fencing keys are stored as lookup keys that never expire, which keeps
+// tokens increasing after the lock itself was purged.
+struct FenceValue(u64);
+
+impl Store {
+    // Waits until the lock is acquired
+    pub async fn acquire_lock(&self, name: &str, ttl: Duration) -> trc::Result<LockGuard> {
+        loop {
+            if let Some(guard) = self
+                .acquire_lock_with_timeout(name, ttl, ttl)
+                .await
+                .caused_by(trc::location!())?
+            {
+                return Ok(guard);
+            }
+        }
+    }
+
+    // Returns None when the lock is held by someone else
+    pub async fn try_acquire_lock(
+        &self,
+        name: &str,
+        ttl: Duration,
+    ) -> trc::Result<Option<LockGuard>> {
+        self.acquire_lock_with_timeout(name, ttl, Duration::ZERO)
+            .await
+            .caused_by(trc::location!())
+    }
+
+    // Runs the task every interval on a single node. Nodes that fail to
+    // acquire the lock keep trying, taking over once the holder stops
+    // renewing its lease.
+    pub fn run_singleton<F, T>(
+        &self,
+        name: impl Into<String>,
+        interval: Duration,
+        task: F,
+    ) -> JoinHandle<()>
+    where
+        F: Fn(LockFence) -> T + Send + Sync + 'static,
+        T: Future<Output = trc::Result<()>> + Send + 'static,
+    {
+        let store = self.clone();
+        let name = name.into();
+
+        tokio::spawn(async move {
+            loop {
+                match store.try_acquire_lock(&name, SINGLETON_LEASE).await {
+                    Ok(Some(guard)) => {
+                        while guard.is_held() {
+                            if let Err(err) = task(guard.fence().clone()).await {
+                                trc::error!(err
+                                    .details("Singleton task failed.")
+                                    .ctx(trc::Key::Id, name.clone())
+                                    .caused_by(trc::location!()));
+                            }
+                            tokio::time::sleep(interval).await;
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(err) => {
+                        trc::error!(err
+                            .details("Failed to acquire singleton lock.")
+                            .ctx(trc::Key::Id, name.clone())
+                            .caused_by(trc::location!()));
+                    }
+                }
+
+                tokio::time::sleep(interval).await;
+            }
+        })
+    }
+
+    async fn acquire_lock_with_timeout(
+        &self,
+        name: &str,
+        ttl: Duration,
+        timeout: Duration,
+    ) -> trc::Result<Option<LockGuard>> {
+        let lock = match self.lock(format!("lock:{name}"), ttl, timeout).await {
+            Ok(lock) => lock,
+            Err(err) if err.matches(trc::EventType::Store(trc::StoreEvent::LockNotAcquired)) => {
+                return Ok(None);
+            }
+            Err(err) => return Err(err),
+        };
+        let fence = self
+            .next_fence(format!("fence:{name}").into_bytes())
+            .await
+            .caused_by(trc::location!())?;
+
+        Ok(Some(LockGuard::new(name, lock, fence, ttl)))
+    }
+
+    async fn next_fence(&self, key: Vec<u8>) -> trc::Result<LockFence> {
+        loop {
+            let current = self
+                .get_value::<HashedValue<FenceValue>>(ValueKey::from(ValueClass::Lookup(
+                    LookupClass::Key(key.clone()),
+                )))
+                .await
+                .caused_by(trc::location!())?;
+            let (assert_value, token) = match &current {
+                Some(current) => (AssertValue::Hash(current.hash), current.inner.0 + 1),
+                None => (AssertValue::None, 1),
+            };
+
+            let mut batch = BatchBuilder::new();
+            batch
+                .assert_value(
+                    ValueClass::Lookup(LookupClass::Key(key.clone())),
+                    assert_value,
+                )
+                .set(
+                    ValueClass::Lookup(LookupClass::Key(key.clone())),
+                    FenceValue(token).serialize(),
+                );
+            match self.write(batch.build()).await {
+                Ok(_) => return Ok(LockFence { key, token }),
+                Err(err) if err.is_assertion_failure() => {}
+                Err(err) => return Err(err.caused_by(trc::location!())),
+            }
+        }
+    }
+}
+
+impl LockGuard {
+    fn new(name: &str, lock: StoreLock, fence: LockFence, ttl: Duration) -> Self {
+        let expires = Arc::new(AtomicU64::new(lock.expires()));
+        let crashed = Arc::new(AtomicBool::new(false));
+        let heartbeat = tokio::spawn(heartbeat(lock, ttl, expires.clone(), crashed.clone()));
+
+        LockGuard {
+            name: name.to_string(),
+            fence,
+            expires,
+            crashed,
+            heartbeat,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn fence(&self) -> &LockFence {
+        &self.fence
+    }
+
+    // Whether the lease is still valid, it can be lost if renewing it failed
+    pub fn is_held(&self) -> bool {
+        self.expires.load(Ordering::Relaxed) > now()
+    }
+
+    // Stops renewing the lease without releasing the lock, as if the node crashed
+    #[cfg(feature = "test_mode")]
+    pub fn simulate_crash(self) {
+        self.crashed.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Drop for LockGuard {
+    fn drop(&mut self) {
+        if !self.crashed.load(Ordering::Relaxed) {
+            // Dropping the heartbeat task releases the lock
+            self.heartbeat.abort();
+        }
+    }
+}
+
+impl LockFence {
+    pub fn token(&self) -> u64 {
+        self.token
+    }
+
+    // Makes the batch fail if the lock was acquired by someone else
+    pub fn assert(&self, batch: &mut BatchBuilder) {
+        batch.assert_value(
+            ValueClass::Lookup(LookupClass::Key(self.key.clone())),
+            AssertValue::Hash(xxhash_rust::xxh3::xxh3_64(
+                &FenceValue(self.token).serialize(),
+            )),
+        );
+    }
+}
+
+async fn heartbeat(
+    mut lock: StoreLock,
+    ttl: Duration,
+    expires: Arc<AtomicU64>,
+    crashed: Arc<AtomicBool>,
+) {
+    let interval = ttl / 3;
+
+    loop {
+        tokio::time::sleep(interval).await;
+
+        if crashed.load(Ordering::Relaxed) {
+            // Leave the lock in place until it expires
+            std::mem::forget(lock);
+            return;
+        }
+
+        match lock.renew(ttl).await {
+            Ok(_) => {
+                expires.store(lock.expires(), Ordering::Relaxed);
+            }
+            Err(err) => {
+                let is_lost = err.matches(trc::EventType::Store(trc::StoreEvent::LockNotAcquired))
+                    || lock.expires() <= now();
+                trc::error!(err
+                    .details("Failed to renew lock lease.")
+                    .caused_by(trc::location!()));
+                if is_lost {
+                    expires.store(0, Ordering::Relaxed);
+                    return;
+                }
+            }
+        }
+    }
+}
+
+impl FenceValue {
+    fn serialize(&self) -> Vec<u8> {
+        KeySerializer::new(U64_LEN * 2)
+            .write(u64::MAX)
+            .write(self.0)
+            .finalize()
+    }
+}
+
+impl Deserialize for FenceValue {
+    fn deserialize(bytes: &[u8]) -> trc::Result<Self> {
+        bytes.deserialize_be_u64(U64_LEN).map(FenceValue)
+    }
+}
@@ -0,0 +1,206 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::{Duration, Instant};
+
+use trc::AddContext;
+
+use crate::{
+    write::{
+        assert::{AssertValue, HashedValue},
+        key::{DeserializeBigEndian, KeySerializer},
+        now, BatchBuilder, LookupClass, ValueClass,
+    },
+    Deserialize, Store, ValueKey, U64_LEN,
+};
+
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+// Locks are stored as lookup keys holding their expiration time, which
+// allows expired locks to be removed by the lookup store purge task.
+pub struct StoreLock {
+    store: Store,
+    key: Vec<u8>,
+    token: u64,
+    expires: u64,
+}
+
+struct LockValue {
+    expires: u64,
+    token: u64,
+}
+
+impl Store {
+    pub async fn lock(
+        &self,
+        key: impl Into<Vec<u8>>,
+        ttl: Duration,
+        timeout: Duration,
+    ) -> trc::Result<StoreLock> {
+        let key = key.into();
+        let token = rand::random::<u64>();
+        let wait_until = Instant::now() + timeout;
+
+        loop {
+            let current = self
+                .get_value::<HashedValue<LockValue>>(ValueKey::from(ValueClass::Lookup(
+                    LookupClass::Key(key.clone()),
+                )))
+                .await
+                .caused_by(trc::location!())?;
+            let assert_value = match &current {
+                Some(current) if current.inner.expires > now() => None,
+                Some(current) => Some(AssertValue::Hash(current.hash)),
+                None => Some(AssertValue::None),
+            };
+
+            if let Some(assert_value) = assert_value {
+                let expires = now() + ttl_secs(ttl);
+                let mut batch = BatchBuilder::new();
+                batch
+                    .assert_value(
+                        ValueClass::Lookup(LookupClass::Key(key.clone())),
+                        assert_value,
+                    )
+                    .set(
+                        ValueClass::Lookup(LookupClass::Key(key.clone())),
+                        LockValue { expires, token }.serialize(),
+                    );
+                match self.write(batch.build()).await {
+                    Ok(_) => {
+                        return Ok(StoreLock {
+                            store: self.clone(),
+                            key,
+                            token,
+                            expires,
+                        });
+                    }
+                    Err(err) if err.is_assertion_failure() => {}
+                    Err(err) => return Err(err.caused_by(trc::location!())),
+                }
+            }
+
+            if Instant::now() + LOCK_RETRY_INTERVAL > wait_until {
+                return Err(trc::StoreEvent::LockNotAcquired
+                    .into_err()
+                    .ctx(trc::Key::Key, key)
+                    .ctx(
+                        trc::Key::Expires,
+                        current.map(|current| current.inner.expires),
+                    ));
+            }
+
+            tokio::time::sleep(LOCK_RETRY_INTERVAL).await;
+        }
+    }
+}
+
+impl StoreLock {
+    pub async fn extend(&mut self, additional: Duration) -> trc::Result<()> {
+        self.set_expiry(self.expires.max(now()) + ttl_secs(additional))
+            .await
+    }
+
+    // Unlike extend, the lock expires after the TTL counted from now
+    pub async fn renew(&mut self, ttl: Duration) -> trc::Result<()> {
+        self.set_expiry(now() + ttl_secs(ttl)).await
+    }
+
+    async fn set_expiry(&mut self, expires: u64) -> trc::Result<()> {
+        let mut batch = BatchBuilder::new();
+        batch
+            .assert_value(
+                ValueClass::Lookup(LookupClass::Key(self.key.clone())),
+                self.assert_value(),
+            )
+            .set(
+                ValueClass::Lookup(LookupClass::Key(self.key.clone())),
+                LockValue {
+                    expires,
+                    token: self.token,
+                }
+                .serialize(),
+            );
+        match self.store.write(batch.build()).await {
+            Ok(_) => {
+                self.expires = expires;
+                Ok(())
+            }
+            Err(err) if err.is_assertion_failure() => Err(trc::StoreEvent::LockNotAcquired
+                .into_err()
+                .ctx(trc::Key::Key, self.key.clone())
+                .details("Lock expired and was acquired by another process")),
+            Err(err) => Err(err.caused_by(trc::location!())),
+        }
+    }
+
+    pub fn expires(&self) -> u64 {
+        self.expires
+    }
+
+    fn assert_value(&self) -> AssertValue {
+        AssertValue::Hash(xxhash_rust::xxh3::xxh3_64(
+            &LockValue {
+                expires: self.expires,
+                token: self.token,
+            }
+            .serialize(),
+        ))
+    }
+}
+
+impl Drop for StoreLock {
+    fn drop(&mut self) {
+        let Ok(handle) = tokio::runtime::Handle::try_current() else {
+            // Without a runtime the lock is released once it expires
+            return;
+        };
+
+        // Only remove the lock if it has not been acquired by someone else
+        let mut batch = BatchBuilder::new();
+        batch
+            .assert_value(
+                ValueClass::Lookup(LookupClass::Key(self.key.clone())),
+                self.assert_value(),
+            )
+            .clear(ValueClass::Lookup(LookupClass::Key(std::mem::take(
+                &mut self.key,
+            ))));
+        let store = self.store.clone();
+        handle.spawn(async move {
+            if let Err(err) = store.write(batch.build()).await {
+                if !err.is_assertion_failure() {
+                    trc::error!(err
+                        .details("Failed to release lock.")
+                        .caused_by(trc::location!()));
+                }
+            }
+        });
+    }
+}
+
+impl LockValue {
+    fn serialize(&self) -> Vec<u8> {
+        KeySerializer::new(U64_LEN * 2)
+            .write(self.expires)
+            .write(self.token)
+            .finalize()
+    }
+}
+
+impl Deserialize for LockValue {
+    fn deserialize(bytes: &[u8]) -> trc::Result<Self> {
+        Ok(LockValue {
+            expires: bytes.deserialize_be_u64(0)?,
+            token: bytes.deserialize_be_u64(U64_LEN)?,
+        })
+    }
+}
+
+fn ttl_secs(ttl: Duration) -> u64 {
+    // Lock expiration is tracked in seconds, round up to avoid zero TTLs
+    ttl.as_secs() + u64::from(ttl.subsec_nanos() > 0)
+}
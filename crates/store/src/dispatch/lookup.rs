@@ -4,8 +4,10 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+use std::time::SystemTime;
+
 use trc::AddContext;
-use utils::config::Rate;
+use utils::config::{Rate, RateWindow};
 
 use crate::{write::LookupClass, Row};
 #[allow(unused_imports)]
@@ -32,6 +34,7 @@ impl LookupStore {
             LookupStore::Store(Store::PostgreSQL(store)) => store.query(query, &params).await,
             #[cfg(feature = "mysql")]
             LookupStore::Store(Store::MySQL(store)) => store.query(query, &params).await,
+            LookupStore::Store(Store::CircuitBreaker(store)) => store.query(query, &params).await,
             _ => Err(trc::StoreEvent::NotSupported.into_err()),
         };
 
@@ -124,9 +127,8 @@ impl LookupStore {
             }
             #[cfg(feature = "redis")]
             LookupStore::Redis(store) => store.key_incr(key, value, expires).await,
-            LookupStore::Query(_) | LookupStore::Memory(_) => {
-                Err(trc::StoreEvent::NotSupported.into_err())
-            }
+            LookupStore::Memory(store) => Ok(store.counter_incr(key, value, expires)),
+            LookupStore::Query(_) => Err(trc::StoreEvent::NotSupported.into_err()),
         }
         .caused_by(trc::location!())
     }
@@ -162,9 +164,11 @@ impl LookupStore {
             }
             #[cfg(feature = "redis")]
             LookupStore::Redis(store) => store.key_delete(key).await,
-            LookupStore::Query(_) | LookupStore::Memory(_) => {
-                Err(trc::StoreEvent::NotSupported.into_err())
+            LookupStore::Memory(store) => {
+                store.counter_delete(&key);
+                Ok(())
             }
+            LookupStore::Query(_) => Err(trc::StoreEvent::NotSupported.into_err()),
         }
         .caused_by(trc::location!())
     }
@@ -211,9 +215,8 @@ impl LookupStore {
             }
             #[cfg(feature = "redis")]
             LookupStore::Redis(store) => store.counter_get(key).await,
-            LookupStore::Query(_) | LookupStore::Memory(_) => {
-                Err(trc::StoreEvent::NotSupported.into_err())
-            }
+            LookupStore::Memory(store) => Ok(store.counter_get(&key)),
+            LookupStore::Query(_) => Err(trc::StoreEvent::NotSupported.into_err()),
         }
         .caused_by(trc::location!())
     }
@@ -273,6 +276,103 @@ impl LookupStore {
         }
     }
 
+    pub async fn check_rate(
+        &self,
+        key: &[u8],
+        rate: &Rate,
+        window: RateWindow,
+        soft_check: bool,
+    ) -> trc::Result<Option<u64>> {
+        match window {
+            RateWindow::Fixed => self.is_rate_allowed(key, rate, soft_check).await,
+            RateWindow::Sliding => self.is_sliding_rate_allowed(key, rate, soft_check).await,
+        }
+    }
+
+    // Sliding windows are approximated by weighting the previous fixed window
+    // by the fraction of it that still overlaps the sliding window.
+    pub async fn is_sliding_rate_allowed(
+        &self,
+        key: &[u8],
+        rate: &Rate,
+        soft_check: bool,
+    ) -> trc::Result<Option<u64>> {
+        let period = std::cmp::max(rate.period.as_millis() as u64, 1);
+        let now = now_millis();
+        let window = now / period;
+        let elapsed = now % period;
+
+        let current = if !soft_check {
+            self.counter_incr(
+                sliding_rate_bucket(key, window),
+                1,
+                ((2 * period).div_ceil(1000)).into(),
+                true,
+            )
+            .await
+            .caused_by(trc::location!())?
+        } else {
+            self.counter_get(sliding_rate_bucket(key, window))
+                .await
+                .caused_by(trc::location!())?
+                + 1
+        }
+        .max(0) as u64;
+        let previous = self
+            .counter_get(sliding_rate_bucket(key, window.saturating_sub(1)))
+            .await
+            .caused_by(trc::location!())?
+            .max(0) as u64;
+
+        if current * period + previous * (period - elapsed) <= rate.requests * period {
+            Ok(None)
+        } else {
+            // Time until the weighted count drops below the limit
+            let wait = if current <= rate.requests && previous > 0 {
+                (period - elapsed).saturating_sub((rate.requests - current) * period / previous)
+            } else {
+                period - elapsed
+            };
+            Ok(Some(std::cmp::max(wait.div_ceil(1000), 1)))
+        }
+    }
+
+    // Returns the number of requests counted in the current window
+    pub async fn rate_usage(
+        &self,
+        key: &[u8],
+        rate: &Rate,
+        window: RateWindow,
+    ) -> trc::Result<u64> {
+        match window {
+            RateWindow::Fixed => {
+                let mut bucket = Vec::with_capacity(key.len() + U64_LEN);
+                bucket.extend_from_slice(key);
+                bucket.extend_from_slice((now() / rate.period.as_secs()).to_be_bytes().as_slice());
+
+                self.counter_get(bucket)
+                    .await
+                    .map(|requests| requests.max(0) as u64)
+            }
+            RateWindow::Sliding => {
+                let period = std::cmp::max(rate.period.as_millis() as u64, 1);
+                let now = now_millis();
+                let window = now / period;
+                let current = self
+                    .counter_get(sliding_rate_bucket(key, window))
+                    .await?
+                    .max(0) as u64;
+                let previous = self
+                    .counter_get(sliding_rate_bucket(key, window.saturating_sub(1)))
+                    .await?
+                    .max(0) as u64;
+
+                Ok(current + previous * (period - (now % period)) / period)
+            }
+        }
+        .caused_by(trc::location!())
+    }
+
     pub async fn purge_lookup_store(&self) -> trc::Result<()> {
         match self {
             LookupStore::Store(store) => {
@@ -355,7 +455,8 @@ impl LookupStore {
             }
             #[cfg(feature = "redis")]
             LookupStore::Redis(_) => {}
-            LookupStore::Query(_) | LookupStore::Memory(_) => {}
+            LookupStore::Memory(store) => store.purge_counters(),
+            LookupStore::Query(_) => {}
         }
 
         Ok(())
@@ -369,6 +470,20 @@ impl LookupStore {
     }
 }
 
+fn sliding_rate_bucket(key: &[u8], window: u64) -> Vec<u8> {
+    let mut bucket = Vec::with_capacity(key.len() + U64_LEN + 1);
+    bucket.extend_from_slice(key);
+    bucket.push(u8::MAX);
+    bucket.extend_from_slice(window.to_be_bytes().as_slice());
+    bucket
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
 enum LookupValue<T> {
     Value(T),
     None,
@@ -9,8 +9,11 @@ use roaring::RoaringBitmap;
 use crate::Store;
 
 pub mod blob;
+pub mod coordination;
 pub mod fts;
+pub mod lock;
 pub mod lookup;
+pub mod pubsub;
 pub mod store;
 
 impl Store {
@@ -28,6 +31,11 @@ impl Store {
             Self::RocksDb(_) => "rocksdb",
             #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
             Self::SQLReadReplica(_) => "read_replica",
+            #[cfg(feature = "enterprise")]
+            Self::Sharded(_) => "sharded",
+            Self::CircuitBreaker(store) => store.inner().id(),
+            #[cfg(feature = "test_mode")]
+            Self::Mock(_) => "mock",
             Self::None => "none",
         }
     }
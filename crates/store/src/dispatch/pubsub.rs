@@ -0,0 +1,39 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use trc::AddContext;
+
+use crate::{PubSubStore, PubSubStream};
+
+impl PubSubStore {
+    // Messages published while a subscriber is disconnected are not delivered
+    pub async fn publish(&self, topic: &str, message: Vec<u8>) -> trc::Result<()> {
+        match self {
+            #[cfg(feature = "redis")]
+            PubSubStore::Redis(store) => store.publish(topic, message).await,
+            #[cfg(feature = "nats")]
+            PubSubStore::Nats(store) => store.publish(topic, message).await,
+            PubSubStore::None => Err(trc::StoreEvent::NotConfigured.into_err()),
+        }
+        .caused_by(trc::location!())
+    }
+
+    // The stream ends when the connection to the broker is lost
+    pub async fn subscribe(&self, topic: &str) -> trc::Result<PubSubStream> {
+        match self {
+            #[cfg(feature = "redis")]
+            PubSubStore::Redis(store) => store.subscribe(topic).await,
+            #[cfg(feature = "nats")]
+            PubSubStore::Nats(store) => store.subscribe(topic).await,
+            PubSubStore::None => Err(trc::StoreEvent::NotConfigured.into_err()),
+        }
+        .caused_by(trc::location!())
+    }
+
+    pub fn is_none(&self) -> bool {
+        matches!(self, PubSubStore::None)
+    }
+}
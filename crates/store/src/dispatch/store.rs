@@ -5,22 +5,53 @@
  */
 
 use std::{
+    collections::BTreeSet,
     ops::{BitAndAssign, Range},
     time::Instant,
 };
 
+use ahash::AHashMap;
+use futures::{stream, Stream, TryStreamExt};
 use roaring::RoaringBitmap;
+use sha2::{Digest, Sha256};
 use trc::{AddContext, StoreEvent};
 
 use crate::{
     write::{
         key::{DeserializeBigEndian, KeySerializer},
         now, AnyClass, AnyKey, AssignedIds, Batch, BatchBuilder, BitmapClass, BitmapHash,
-        Operation, ReportClass, ValueClass, ValueOp,
+        DirectoryClass, Operation, ReportClass, TagValue, ValueClass, ValueOp,
     },
-    BitmapKey, Deserialize, IterateParams, Key, Store, ValueKey, SUBSPACE_BITMAP_ID,
-    SUBSPACE_BITMAP_TAG, SUBSPACE_BITMAP_TEXT, SUBSPACE_INDEXES, SUBSPACE_LOGS, U32_LEN,
+    AccountDeletionStats, BitmapKey, Deserialize, IndexKey, IndexRebuildStats, IterateParams, Key,
+    QuotaLimit, QuotaStatus, RecentKey, RenameStats, StorageStats, Store, ValueKey,
+    COUNTER_COLLECTION, COUNTER_PROPERTY, DOCUMENT_SUBSPACES, SUBSPACE_ACL, SUBSPACE_BITMAP_ID,
+    SUBSPACE_BITMAP_TAG, SUBSPACE_BITMAP_TEXT, SUBSPACE_BLOB_LINK, SUBSPACE_BLOB_RESERVE,
+    SUBSPACE_COUNTER, SUBSPACE_FTS_INDEX, SUBSPACE_INDEXES, SUBSPACE_LOGS, SUBSPACE_PROPERTY,
+    SUBSPACE_QUOTA, SUBSPACE_RECENT, U32_LEN, U64_LEN,
 };
+use utils::BLOB_HASH_LEN;
+
+// Documents are considered recent for 24 hours unless purge.expire.recent is set
+const RECENT_DOCUMENTS_TTL: u64 = 86400;
+
+// Number of keys fetched at a time when streaming a key range
+const SCAN_BATCH_SIZE: usize = 1024;
+
+// Maximum number of operations per transaction when rebuilding an index
+// on non-SQL backends
+const REBUILD_BATCH_SIZE: usize = 1000;
+
+// Maximum number of operations per transaction when bulk deleting documents
+// on stores other than PostgreSQL
+const BULK_DELETE_BATCH_SIZE: usize = 10000;
+
+struct ValueHash([u8; 32]);
+
+impl Deserialize for ValueHash {
+    fn deserialize(bytes: &[u8]) -> trc::Result<Self> {
+        Ok(ValueHash(Sha256::digest(bytes).into()))
+    }
+}
 
 use super::DocumentSet;
 
@@ -52,11 +83,208 @@ impl Store {
             Self::RocksDb(store) => store.get_value(key).await,
             #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
             Self::SQLReadReplica(store) => store.get_value(key).await,
+            #[cfg(feature = "enterprise")]
+            Self::Sharded(store) => store.get_value(key).await,
+            Self::CircuitBreaker(store) => store.get_value(key).await,
+            #[cfg(feature = "test_mode")]
+            Self::Mock(store) => store.get_value(key).await,
+            Self::None => Err(trc::StoreEvent::NotConfigured.into()),
+        }
+        .caused_by(trc::location!())
+    }
+
+    pub async fn get_value_size(&self, key: impl Key) -> trc::Result<Option<usize>> {
+        match self {
+            #[cfg(feature = "sqlite")]
+            Self::SQLite(store) => store.get_value_size(key).await,
+            #[cfg(feature = "foundation")]
+            Self::FoundationDb(store) => store.get_value_size(key).await,
+            #[cfg(feature = "postgres")]
+            Self::PostgreSQL(store) => store.get_value_size(key).await,
+            #[cfg(feature = "mysql")]
+            Self::MySQL(store) => store.get_value_size(key).await,
+            #[cfg(feature = "rocks")]
+            Self::RocksDb(store) => store.get_value_size(key).await,
+            #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
+            Self::SQLReadReplica(store) => store.get_value_size(key).await,
+            #[cfg(feature = "enterprise")]
+            Self::Sharded(store) => store.get_value_size(key).await,
+            Self::CircuitBreaker(store) => store.get_value_size(key).await,
+            #[cfg(feature = "test_mode")]
+            Self::Mock(store) => store.get_value_size(key).await,
+            Self::None => Err(trc::StoreEvent::NotConfigured.into()),
+        }
+        .caused_by(trc::location!())
+    }
+
+    // Fetches multiple values in a single round-trip, results are returned
+    // in the same order as the keys and missing keys are returned as None.
+    pub async fn get_value_batch(&self, keys: Vec<impl Key>) -> trc::Result<Vec<Option<Vec<u8>>>> {
+        if keys.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        match self {
+            #[cfg(feature = "sqlite")]
+            Self::SQLite(store) => store.get_values(keys).await,
+            #[cfg(feature = "foundation")]
+            Self::FoundationDb(store) => store.get_values(keys).await,
+            #[cfg(feature = "postgres")]
+            Self::PostgreSQL(store) => store.get_values(keys).await,
+            #[cfg(feature = "mysql")]
+            Self::MySQL(store) => store.get_values(keys).await,
+            #[cfg(feature = "rocks")]
+            Self::RocksDb(store) => store.get_values(keys).await,
+            #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
+            Self::SQLReadReplica(store) => store.get_values(keys).await,
+            #[cfg(feature = "enterprise")]
+            Self::Sharded(store) => store.get_values(keys).await,
+            Self::CircuitBreaker(store) => store.get_values(keys).await,
+            #[cfg(feature = "test_mode")]
+            Self::Mock(store) => store.get_values(keys).await,
+            Self::None => Err(trc::StoreEvent::NotConfigured.into()),
+        }
+        .caused_by(trc::location!())
+    }
+
+    // Fetches the requested fields of a document in a single round-trip,
+    // fields that do not exist are returned as None.
+    pub async fn get_document_fields(
+        &self,
+        account_id: u32,
+        collection: impl Into<u8>,
+        document_id: u32,
+        fields: &[ValueClass<u32>],
+    ) -> trc::Result<Vec<Option<Vec<u8>>>> {
+        let collection = collection.into();
+        self.get_value_batch(
+            fields
+                .iter()
+                .map(|class| ValueKey {
+                    account_id,
+                    collection,
+                    document_id,
+                    class: class.clone(),
+                })
+                .collect::<Vec<_>>(),
+        )
+        .await
+    }
+
+    // Copies the properties of a document to a new document in another account
+    // and returns its id. Values are read into memory before being written, as
+    // the source and destination accounts may live in different shards.
+    pub async fn copy_document(
+        &self,
+        account_id: u32,
+        collection: impl Into<u8>,
+        document_id: u32,
+        to_account_id: u32,
+        properties: &[u8],
+    ) -> trc::Result<u32> {
+        let collection = collection.into();
+        let values = self
+            .get_document_fields(
+                account_id,
+                collection,
+                document_id,
+                &properties
+                    .iter()
+                    .map(|property| ValueClass::Property(*property))
+                    .collect::<Vec<_>>(),
+            )
+            .await
+            .caused_by(trc::location!())?;
+
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(to_account_id)
+            .with_collection(collection)
+            .create_document();
+        for (property, value) in properties.iter().zip(values) {
+            if let Some(value) = value {
+                batch.set(ValueClass::Property(*property), value);
+            }
+        }
+
+        self.write(batch.build())
+            .await
+            .and_then(|ids| ids.last_document_id())
+            .caused_by(trc::location!())
+    }
+
+    // Returns the SHA-256 digest of a value, hashed as it is read from the backend
+    pub async fn get_value_hash(&self, key: impl Key) -> trc::Result<Option<[u8; 32]>> {
+        self.get_value::<ValueHash>(key)
+            .await
+            .map(|hash| hash.map(|hash| hash.0))
+    }
+
+    // Hints that the given keys are about to be read. RocksDB loads the blocks
+    // holding them into its block cache, other backends ignore the hint; use
+    // get_value_batch to fetch several values in a single round-trip.
+    pub async fn prefetch(&self, keys: &[impl Key]) -> trc::Result<()> {
+        match self {
+            #[cfg(feature = "rocks")]
+            Self::RocksDb(store) => store.prefetch(keys).await,
+            Self::CircuitBreaker(store) => store.prefetch(keys).await,
             Self::None => Err(trc::StoreEvent::NotConfigured.into()),
+            _ => {
+                let _ = keys;
+                Ok(())
+            }
         }
         .caused_by(trc::location!())
     }
 
+    // Per-subspace value size limits configured for this store
+    pub fn max_value_size(&self) -> Option<&AHashMap<u8, usize>> {
+        match self {
+            #[cfg(feature = "sqlite")]
+            Self::SQLite(store) => Some(&store.max_value_size),
+            #[cfg(feature = "foundation")]
+            Self::FoundationDb(store) => Some(&store.max_value_size),
+            #[cfg(feature = "postgres")]
+            Self::PostgreSQL(store) => Some(&store.max_value_size),
+            #[cfg(feature = "mysql")]
+            Self::MySQL(store) => Some(&store.max_value_size),
+            #[cfg(feature = "rocks")]
+            Self::RocksDb(store) => Some(&store.max_value_size),
+            #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
+            Self::SQLReadReplica(store) => store.max_value_size(),
+            #[cfg(feature = "enterprise")]
+            Self::Sharded(store) => store.max_value_size(),
+            Self::CircuitBreaker(store) => store.max_value_size(),
+            #[cfg(feature = "test_mode")]
+            Self::Mock(_) => None,
+            Self::None => None,
+        }
+    }
+
+    // Subspaces whose timestamped keys are purged once older than their TTL
+    pub fn expiring_subspaces(&self) -> &[(u8, u64)] {
+        match self {
+            #[cfg(feature = "sqlite")]
+            Self::SQLite(store) => &store.expiring_subspaces,
+            #[cfg(feature = "foundation")]
+            Self::FoundationDb(store) => &store.expiring_subspaces,
+            #[cfg(feature = "postgres")]
+            Self::PostgreSQL(store) => &store.expiring_subspaces,
+            #[cfg(feature = "mysql")]
+            Self::MySQL(store) => &store.expiring_subspaces,
+            #[cfg(feature = "rocks")]
+            Self::RocksDb(store) => &store.expiring_subspaces,
+            #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
+            Self::SQLReadReplica(store) => store.expiring_subspaces(),
+            #[cfg(feature = "enterprise")]
+            Self::Sharded(store) => store.expiring_subspaces(),
+            Self::CircuitBreaker(store) => store.expiring_subspaces(),
+            #[cfg(feature = "test_mode")]
+            Self::Mock(_) => &[],
+            Self::None => &[],
+        }
+    }
+
     pub async fn get_bitmap(
         &self,
         key: BitmapKey<BitmapClass<u32>>,
@@ -74,11 +302,161 @@ impl Store {
             Self::RocksDb(store) => store.get_bitmap(key).await,
             #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
             Self::SQLReadReplica(store) => store.get_bitmap(key).await,
+            #[cfg(feature = "enterprise")]
+            Self::Sharded(store) => store.get_bitmap(key).await,
+            Self::CircuitBreaker(store) => store.get_bitmap(key).await,
+            #[cfg(feature = "test_mode")]
+            Self::Mock(store) => store.get_bitmap(key).await,
+            Self::None => Err(trc::StoreEvent::NotConfigured.into()),
+        }
+        .caused_by(trc::location!())
+    }
+
+    // Counts the documents in a bitmap without loading it
+    pub async fn get_bitmap_cardinality(
+        &self,
+        key: BitmapKey<BitmapClass<u32>>,
+    ) -> trc::Result<u64> {
+        match self {
+            #[cfg(feature = "sqlite")]
+            Self::SQLite(store) => store.get_bitmap_cardinality(key).await,
+            #[cfg(feature = "foundation")]
+            Self::FoundationDb(store) => store.get_bitmap_cardinality(key).await,
+            #[cfg(feature = "postgres")]
+            Self::PostgreSQL(store) => store.get_bitmap_cardinality(key).await,
+            #[cfg(feature = "mysql")]
+            Self::MySQL(store) => store.get_bitmap_cardinality(key).await,
+            #[cfg(feature = "rocks")]
+            Self::RocksDb(store) => store.get_bitmap_cardinality(key).await,
+            #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
+            Self::SQLReadReplica(store) => store.get_bitmap_cardinality(key).await,
+            #[cfg(feature = "enterprise")]
+            Self::Sharded(store) => store.get_bitmap_cardinality(key).await,
+            Self::CircuitBreaker(store) => store.get_bitmap_cardinality(key).await,
+            #[cfg(feature = "test_mode")]
+            Self::Mock(store) => store.get_bitmap_cardinality(key).await,
+            Self::None => Err(trc::StoreEvent::NotConfigured.into()),
+        }
+        .caused_by(trc::location!())
+    }
+
+    // Returns the cardinality of the intersection between the document ids and
+    // the tag bitmap of a flag. Nothing is stored or cached: PostgreSQL computes
+    // it in a single query, other backends read both bitmaps and intersect them.
+    // Hot paths should use JmapMethods::get_flag_document_count, which caches it.
+    pub async fn get_document_count_by_flag(
+        &self,
+        account_id: u32,
+        collection: impl Into<u8>,
+        field: impl Into<u8>,
+        flag: impl Into<TagValue<u32>>,
+    ) -> trc::Result<u64> {
+        let collection = collection.into();
+        let document_ids = BitmapKey {
+            account_id,
+            collection,
+            class: BitmapClass::DocumentIds,
+            document_id: 0,
+        };
+        let flagged_ids = BitmapKey {
+            account_id,
+            collection,
+            class: BitmapClass::Tag {
+                field: field.into(),
+                value: flag.into(),
+            },
+            document_id: 0,
+        };
+
+        match self {
+            #[cfg(feature = "postgres")]
+            Self::PostgreSQL(store) => {
+                store
+                    .get_bitmaps_intersection_count(document_ids, flagged_ids)
+                    .await
+            }
+            _ => {
+                // Both ranges are scanned concurrently and intersected in memory
+                futures::try_join!(self.get_bitmap(document_ids), self.get_bitmap(flagged_ids)).map(
+                    |bitmaps| match bitmaps {
+                        (Some(document_ids), Some(flagged_ids)) => {
+                            document_ids.intersection_len(&flagged_ids)
+                        }
+                        _ => 0,
+                    },
+                )
+            }
+        }
+        .caused_by(trc::location!())
+    }
+
+    // Counts the keys in the range [from, to) without reading any values
+    pub async fn get_keys_count(&self, from: impl Key, to: impl Key) -> trc::Result<u64> {
+        match self {
+            #[cfg(feature = "sqlite")]
+            Self::SQLite(store) => store.get_keys_count(from, to).await,
+            #[cfg(feature = "foundation")]
+            Self::FoundationDb(store) => store.get_keys_count(from, to).await,
+            #[cfg(feature = "postgres")]
+            Self::PostgreSQL(store) => store.get_keys_count(from, to).await,
+            #[cfg(feature = "mysql")]
+            Self::MySQL(store) => store.get_keys_count(from, to).await,
+            #[cfg(feature = "rocks")]
+            Self::RocksDb(store) => store.get_keys_count(from, to).await,
+            #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
+            Self::SQLReadReplica(store) => store.get_keys_count(from, to).await,
+            #[cfg(feature = "enterprise")]
+            Self::Sharded(store) => store.get_keys_count(from, to).await,
+            Self::CircuitBreaker(store) => store.get_keys_count(from, to).await,
+            #[cfg(feature = "test_mode")]
+            Self::Mock(store) => store.get_keys_count(from, to).await,
+            Self::None => Err(trc::StoreEvent::NotConfigured.into()),
+        }
+        .caused_by(trc::location!())
+    }
+
+    // Returns the non-zero counters stored under an account, keyed by
+    // what follows the account id in the counter key
+    pub async fn get_all_counters(&self, account_id: u32) -> trc::Result<AHashMap<Vec<u8>, i64>> {
+        let (from, to) = account_counter_range(account_id);
+        match self {
+            #[cfg(feature = "sqlite")]
+            Self::SQLite(store) => store.get_counters(from, to).await,
+            #[cfg(feature = "foundation")]
+            Self::FoundationDb(store) => store.get_counters(from, to).await,
+            #[cfg(feature = "postgres")]
+            Self::PostgreSQL(store) => store.get_counters(from, to).await,
+            #[cfg(feature = "mysql")]
+            Self::MySQL(store) => store.get_counters(from, to).await,
+            #[cfg(feature = "rocks")]
+            Self::RocksDb(store) => store.get_counters(from, to).await,
+            #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
+            Self::SQLReadReplica(store) => store.get_counters(from, to).await,
+            #[cfg(feature = "enterprise")]
+            Self::Sharded(store) => store.get_counters(from, to).await,
+            Self::CircuitBreaker(store) => store.get_counters(from, to).await,
+            #[cfg(feature = "test_mode")]
+            Self::Mock(store) => store.get_counters(from, to).await,
             Self::None => Err(trc::StoreEvent::NotConfigured.into()),
         }
+        .map(|counters| {
+            counters
+                .into_iter()
+                .filter(|(_, value)| *value != 0)
+                .map(|(key, value)| (key.get(U32_LEN..).unwrap_or_default().to_vec(), value))
+                .collect()
+        })
         .caused_by(trc::location!())
     }
 
+    // Zeroes all counters stored under an account
+    pub async fn reset_all_counters(&self, account_id: u32) -> trc::Result<()> {
+        let (from, to) = account_counter_range(account_id);
+        self.delete_range(from, to)
+            .await
+            .caused_by(trc::location!())
+    }
+
     pub async fn get_bitmaps_intersection(
         &self,
         keys: Vec<BitmapKey<BitmapClass<u32>>>,
@@ -120,6 +498,11 @@ impl Store {
             Self::RocksDb(store) => store.iterate(params, cb).await,
             #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
             Self::SQLReadReplica(store) => store.iterate(params, cb).await,
+            #[cfg(feature = "enterprise")]
+            Self::Sharded(store) => store.iterate(params, cb).await,
+            Self::CircuitBreaker(store) => store.iterate(params, cb).await,
+            #[cfg(feature = "test_mode")]
+            Self::Mock(store) => store.iterate(params, cb).await,
             Self::None => Err(trc::StoreEvent::NotConfigured.into()),
         }
         .caused_by(trc::location!());
@@ -132,6 +515,414 @@ impl Store {
         result
     }
 
+    // Returns the raw keys between two keys (both inclusive) without reading their values
+    pub async fn scan_index_keys(&self, from: impl Key, to: impl Key) -> trc::Result<Vec<Vec<u8>>> {
+        let mut keys = Vec::new();
+        self.iterate(
+            IterateParams::new(from, to).ascending().no_values(),
+            |key, _| {
+                keys.push(key.to_vec());
+                Ok(true)
+            },
+        )
+        .await
+        .map(|_| keys)
+    }
+
+    // Returns the keys in a subspace whose trailing big-endian timestamp is older
+    // than the provided cutoff, keys shorter than 8 bytes are skipped
+    pub async fn scan_expired(&self, subspace: u8, older_than: u64) -> trc::Result<Vec<Vec<u8>>> {
+        let mut keys = Vec::new();
+        self.iterate(
+            IterateParams::new(
+                AnyKey {
+                    subspace,
+                    key: vec![0u8],
+                },
+                AnyKey {
+                    subspace,
+                    key: vec![u8::MAX; 255],
+                },
+            )
+            .no_values(),
+            |key, _| {
+                if let Some(offset) = key.len().checked_sub(U64_LEN) {
+                    if key.deserialize_be_u64(offset)? < older_than {
+                        keys.push(key.to_vec());
+                    }
+                }
+                Ok(true)
+            },
+        )
+        .await
+        .map(|_| keys)
+    }
+
+    // Same as scan_index_keys but fetches keys in batches as the stream is consumed
+    pub fn scan_index_keys_stream<'x>(
+        &'x self,
+        from: impl Key + 'x,
+        to: impl Key + 'x,
+    ) -> impl Stream<Item = trc::Result<Vec<u8>>> + Send + 'x {
+        let subspace = from.subspace();
+        let to = AnyKey {
+            subspace,
+            key: to.serialize(0),
+        };
+
+        stream::try_unfold(Some(from.serialize(0)), move |from| {
+            let to = to.clone();
+            async move {
+                let Some(from) = from else {
+                    return Ok(None);
+                };
+                let mut keys: Vec<Vec<u8>> = Vec::with_capacity(SCAN_BATCH_SIZE);
+                self.iterate(
+                    IterateParams::new(
+                        AnyKey {
+                            subspace,
+                            key: from,
+                        },
+                        to,
+                    )
+                    .ascending()
+                    .no_values(),
+                    |key, _| {
+                        keys.push(key.to_vec());
+                        Ok(keys.len() < SCAN_BATCH_SIZE)
+                    },
+                )
+                .await?;
+
+                // Continue right after the last key if the batch was full
+                let next = (keys.len() == SCAN_BATCH_SIZE).then(|| {
+                    let mut next = keys.last().unwrap().clone();
+                    next.push(0);
+                    next
+                });
+
+                Ok::<_, trc::Error>(Some((
+                    stream::iter(keys.into_iter().map(Ok::<_, trc::Error>)),
+                    next,
+                )))
+            }
+        })
+        .try_flatten()
+    }
+
+    // Returns the ids of all accounts with at least one document in the given
+    // collection. Rather than reading every document id, each step seeks to the
+    // first key at or after (account_id, collection) and skips ahead from there.
+    pub fn iter_accounts_with_collection(
+        &self,
+        collection: u8,
+    ) -> trc::Result<impl Stream<Item = trc::Result<u32>> + Send + '_> {
+        let key = |account_id: u32, document_id: u32| BitmapKey {
+            account_id,
+            collection,
+            class: BitmapClass::DocumentIds,
+            document_id,
+        };
+        let to = AnyKey {
+            subspace: SUBSPACE_BITMAP_ID,
+            key: key(u32::MAX, u32::MAX).serialize(0),
+        };
+
+        Ok(stream::try_unfold(Some(0u32), move |account_id| {
+            let to = to.clone();
+            async move {
+                let Some(mut account_id) = account_id else {
+                    return Ok(None);
+                };
+
+                loop {
+                    let mut found = None;
+                    self.iterate(
+                        IterateParams::new(
+                            AnyKey {
+                                subspace: SUBSPACE_BITMAP_ID,
+                                key: key(account_id, 0).serialize(0),
+                            },
+                            to.clone(),
+                        )
+                        .ascending()
+                        .only_first()
+                        .no_values(),
+                        |key, _| {
+                            found = Some((
+                                key.deserialize_be_u32(0)?,
+                                key.get(U32_LEN).copied().unwrap_or_default(),
+                            ));
+                            Ok(false)
+                        },
+                    )
+                    .await?;
+
+                    match found {
+                        Some((found_id, found_collection)) if found_collection == collection => {
+                            return Ok::<_, trc::Error>(Some((found_id, found_id.checked_add(1))));
+                        }
+                        Some((found_id, found_collection)) if found_collection < collection => {
+                            account_id = found_id;
+                        }
+                        Some((found_id, _)) => match found_id.checked_add(1) {
+                            Some(next_id) => account_id = next_id,
+                            None => return Ok(None),
+                        },
+                        None => return Ok(None),
+                    }
+                }
+            }
+        }))
+    }
+
+    // Reconstructs the index entries of a collection from the documents stored
+    // under the given property. The indexer returns the field and key of every
+    // entry a document should have. Only the listed fields are compared and
+    // rewritten, entries the indexer returns for other fields are ignored. Only
+    // the differences are written, in a single transaction on SQL backends and in
+    // chunks elsewhere. When dry_run is set the differences are counted but not
+    // written.
+    pub async fn rebuild_index(
+        &self,
+        account_id: u32,
+        collection: impl Into<u8>,
+        property: impl Into<u8>,
+        fields: &[u8],
+        indexer: impl Fn(u32, &[u8]) -> trc::Result<Vec<(u8, Vec<u8>)>> + Sync + Send,
+        dry_run: bool,
+    ) -> trc::Result<IndexRebuildStats> {
+        let collection = collection.into();
+        let property = property.into();
+
+        // Derive the index entries from the document values
+        let mut expected = BTreeSet::new();
+        self.iterate(
+            IterateParams::new(
+                ValueKey {
+                    account_id,
+                    collection,
+                    document_id: 0,
+                    class: ValueClass::Property(property),
+                },
+                ValueKey {
+                    account_id,
+                    collection,
+                    document_id: u32::MAX,
+                    class: ValueClass::Property(property),
+                },
+            )
+            .ascending(),
+            |key, value| {
+                let document_id = key.deserialize_be_u32(key.len() - U32_LEN)?;
+                for (field, key) in indexer(document_id, value)? {
+                    if !fields.contains(&field) {
+                        continue;
+                    }
+                    expected.insert(
+                        IndexKey {
+                            account_id,
+                            collection,
+                            document_id,
+                            field,
+                            key,
+                        }
+                        .serialize(0),
+                    );
+                }
+                Ok(true)
+            },
+        )
+        .await
+        .caused_by(trc::location!())?;
+
+        // Compare them with the current entries of each field
+        let mut stats = IndexRebuildStats {
+            indexed: expected.len() as u64,
+            ..Default::default()
+        };
+        let mut changes = Vec::new();
+        for &field in fields {
+            let from = KeySerializer::new(U32_LEN + 2)
+                .write(account_id)
+                .write(collection)
+                .write(field)
+                .finalize();
+            let to = prefix_upper_bound(&from);
+            for key in self
+                .scan_index_keys(
+                    AnyKey {
+                        subspace: SUBSPACE_INDEXES,
+                        key: from,
+                    },
+                    AnyKey {
+                        subspace: SUBSPACE_INDEXES,
+                        key: to,
+                    },
+                )
+                .await
+                .caused_by(trc::location!())?
+            {
+                if !expected.remove(&key) {
+                    stats.removed += 1;
+                    changes.push((key, false));
+                }
+            }
+        }
+        stats.added = expected.len() as u64;
+        changes.extend(expected.into_iter().map(|key| (key, true)));
+
+        if dry_run || changes.is_empty() {
+            return Ok(stats);
+        }
+
+        // Write the differences
+        let is_sql = self.is_sql();
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(collection);
+        for (key, set) in changes {
+            // Index keys are account_id, collection, field, key and document_id
+            let document_id = key.deserialize_be_u32(key.len() - U32_LEN)?;
+            batch
+                .update_document(document_id)
+                .ops
+                .push(Operation::Index {
+                    field: key[U32_LEN + 1],
+                    key: key[U32_LEN + 2..key.len() - U32_LEN].to_vec(),
+                    set,
+                });
+
+            if !is_sql && batch.ops.len() >= REBUILD_BATCH_SIZE {
+                self.write(batch.build_batch())
+                    .await
+                    .caused_by(trc::location!())?;
+                batch
+                    .with_account_id(account_id)
+                    .with_collection(collection);
+            }
+        }
+        if !batch.is_empty() {
+            self.write(batch.build())
+                .await
+                .caused_by(trc::location!())?;
+        }
+
+        Ok(stats)
+    }
+
+    // Counts the entries and distinct terms of an indexed field. Every index key
+    // of the field is read, so this is meant for diagnostics only.
+    #[cfg(feature = "store-diagnostics")]
+    pub async fn get_index_statistics(
+        &self,
+        account_id: u32,
+        collection: impl Into<u8>,
+        field: impl Into<u8>,
+    ) -> trc::Result<crate::IndexStats> {
+        let field = field.into();
+        let from = KeySerializer::new(crate::IndexKeyPrefix::len())
+            .write(account_id)
+            .write(collection.into())
+            .write(field)
+            .finalize();
+        let mut to = from.clone();
+        for byte in to.iter_mut().rev() {
+            if *byte < u8::MAX {
+                *byte += 1;
+                break;
+            }
+            *byte = 0;
+        }
+
+        // Keys are sorted by term, so a new term starts whenever it changes
+        let mut stats = crate::IndexStats {
+            field,
+            ..Default::default()
+        };
+        let mut last_term: Option<Vec<u8>> = None;
+        let mut keys = std::pin::pin!(self.scan_index_keys_stream(
+            AnyKey {
+                subspace: SUBSPACE_INDEXES,
+                key: from,
+            },
+            AnyKey {
+                subspace: SUBSPACE_INDEXES,
+                key: to,
+            },
+        ));
+        while let Some(key) = keys.try_next().await.caused_by(trc::location!())? {
+            // Index keys are account_id, collection, field, key and document_id
+            let term = key
+                .get(crate::IndexKeyPrefix::len()..key.len().saturating_sub(U32_LEN))
+                .ok_or_else(|| trc::Error::corrupted_key(&key, None, trc::location!()))?;
+            if last_term.as_deref() != Some(term) {
+                stats.distinct_terms += 1;
+                last_term = Some(term.to_vec());
+            }
+            stats.total_entries += 1;
+        }
+        if stats.distinct_terms > 0 {
+            stats.avg_docs_per_term = stats.total_entries as f64 / stats.distinct_terms as f64;
+        }
+
+        Ok(stats)
+    }
+
+    // Returns the index terms of a document grouped by field. Index keys end with
+    // the document id, so every index key of the collection is read to find them.
+    #[cfg(feature = "store-diagnostics")]
+    pub async fn get_full_text_index(
+        &self,
+        account_id: u32,
+        collection: impl Into<u8>,
+        document_id: u32,
+    ) -> trc::Result<Vec<(u8, Vec<Vec<u8>>)>> {
+        let from = KeySerializer::new(U32_LEN + 1)
+            .write(account_id)
+            .write(collection.into())
+            .finalize();
+        let mut to = from.clone();
+        for byte in to.iter_mut().rev() {
+            if *byte < u8::MAX {
+                *byte += 1;
+                break;
+            }
+            *byte = 0;
+        }
+
+        // Keys are sorted by field, so a new group starts whenever it changes
+        let mut fields: Vec<(u8, Vec<Vec<u8>>)> = Vec::new();
+        let mut keys = std::pin::pin!(self.scan_index_keys_stream(
+            AnyKey {
+                subspace: SUBSPACE_INDEXES,
+                key: from,
+            },
+            AnyKey {
+                subspace: SUBSPACE_INDEXES,
+                key: to,
+            },
+        ));
+        while let Some(key) = keys.try_next().await.caused_by(trc::location!())? {
+            // Index keys are account_id, collection, field, key and document_id
+            if key.len() < crate::IndexKeyPrefix::len() + U32_LEN {
+                return Err(trc::Error::corrupted_key(&key, None, trc::location!()));
+            }
+            if key.deserialize_be_u32(key.len() - U32_LEN)? != document_id {
+                continue;
+            }
+            let field = key[crate::IndexKeyPrefix::len() - 1];
+            let term = key[crate::IndexKeyPrefix::len()..key.len() - U32_LEN].to_vec();
+            match fields.last_mut() {
+                Some((last_field, terms)) if *last_field == field => terms.push(term),
+                _ => fields.push((field, vec![term])),
+            }
+        }
+
+        Ok(fields)
+    }
+
     pub async fn get_counter(
         &self,
         key: impl Into<ValueKey<ValueClass<u32>>> + Sync + Send,
@@ -149,12 +940,100 @@ impl Store {
             Self::RocksDb(store) => store.get_counter(key).await,
             #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
             Self::SQLReadReplica(store) => store.get_counter(key).await,
+            #[cfg(feature = "enterprise")]
+            Self::Sharded(store) => store.get_counter(key).await,
+            Self::CircuitBreaker(store) => store.get_counter(key).await,
+            #[cfg(feature = "test_mode")]
+            Self::Mock(store) => store.get_counter(key).await,
             Self::None => Err(trc::StoreEvent::NotConfigured.into()),
         }
         .caused_by(trc::location!())
     }
 
+    // Reads the used quota and the limit stored in the account's principal
+    // at the same point in time. Principals that do not exist or have no
+    // quota set are reported with a limit of zero.
+    pub async fn get_account_quota<P: QuotaLimit + 'static>(
+        &self,
+        account_id: u32,
+    ) -> trc::Result<QuotaStatus> {
+        let value_key =
+            ValueKey::from(ValueClass::Directory(DirectoryClass::Principal(account_id)));
+        let counter_key =
+            ValueKey::from(ValueClass::Directory(DirectoryClass::UsedQuota(account_id)));
+
+        let (principal, used) = match self {
+            #[cfg(feature = "sqlite")]
+            Self::SQLite(store) => store.get_value_and_counter(value_key, counter_key).await,
+            #[cfg(feature = "foundation")]
+            Self::FoundationDb(store) => store.get_value_and_counter(value_key, counter_key).await,
+            #[cfg(feature = "postgres")]
+            Self::PostgreSQL(store) => store.get_value_and_counter(value_key, counter_key).await,
+            #[cfg(feature = "mysql")]
+            Self::MySQL(store) => store.get_value_and_counter(value_key, counter_key).await,
+            #[cfg(feature = "rocks")]
+            Self::RocksDb(store) => store.get_value_and_counter(value_key, counter_key).await,
+            #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
+            Self::SQLReadReplica(store) => {
+                store.get_value_and_counter(value_key, counter_key).await
+            }
+            #[cfg(feature = "enterprise")]
+            Self::Sharded(store) => store.get_value_and_counter(value_key, counter_key).await,
+            Self::CircuitBreaker(store) => {
+                store.get_value_and_counter(value_key, counter_key).await
+            }
+            #[cfg(feature = "test_mode")]
+            Self::Mock(store) => store.get_value_and_counter(value_key, counter_key).await,
+            Self::None => Err(trc::StoreEvent::NotConfigured.into()),
+        }
+        .caused_by(trc::location!())?;
+        let limit = if let Some(principal) = principal {
+            P::deserialize(&principal)
+                .caused_by(trc::location!())?
+                .quota_limit() as i64
+        } else {
+            0
+        };
+
+        Ok(QuotaStatus::new(used, limit))
+    }
+
+    // Quota limits are set in the account's principal, a missing principal or
+    // a zero limit means the account is unlimited.
+    pub async fn get_quota_limit<P: QuotaLimit + 'static>(
+        &self,
+        account_id: u32,
+    ) -> trc::Result<Option<i64>> {
+        self.get_value::<P>(ValueKey::from(ValueClass::Directory(
+            DirectoryClass::Principal(account_id),
+        )))
+        .await
+        .map(|principal| {
+            principal
+                .map(|principal| principal.quota_limit() as i64)
+                .filter(|limit| *limit > 0)
+        })
+    }
+
+    pub async fn is_over_quota<P: QuotaLimit + 'static>(
+        &self,
+        account_id: u32,
+    ) -> trc::Result<bool> {
+        self.get_account_quota::<P>(account_id)
+            .await
+            .map(|quota| quota.over_quota)
+    }
+
     pub async fn write(&self, batch: Batch) -> trc::Result<AssignedIds> {
+        if let Some(max_value_size) = self
+            .max_value_size()
+            .filter(|max_value_size| !max_value_size.is_empty())
+        {
+            batch
+                .check_value_size(max_value_size)
+                .caused_by(trc::location!())?;
+        }
+
         #[cfg(feature = "test_mode")]
         if std::env::var("PARANOID_WRITE").map_or(false, |v| v == "1") {
             let mut account_id = u32::MAX;
@@ -214,6 +1093,11 @@ impl Store {
                 Self::RocksDb(store) => store.write(batch).await,
                 #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
                 Self::SQLReadReplica(store) => store.write(batch).await,
+                #[cfg(feature = "enterprise")]
+                Self::Sharded(store) => store.write(batch).await,
+                Self::CircuitBreaker(store) => store.write(batch).await,
+                #[cfg(feature = "test_mode")]
+                Self::Mock(store) => store.write(batch).await,
                 Self::None => Err(trc::StoreEvent::NotConfigured.into()),
             }
             .caused_by(trc::location!())?;
@@ -241,82 +1125,383 @@ impl Store {
                     );
                 }
             }
-
-            return Ok(AssignedIds::default());
+
+            return Ok(AssignedIds::default());
+        }
+
+        let start_time = Instant::now();
+        let ops = batch.ops.len();
+        let span_id = batch.span_id;
+
+        let result = match self {
+            #[cfg(feature = "sqlite")]
+            Self::SQLite(store) => store.write(batch).await,
+            #[cfg(feature = "foundation")]
+            Self::FoundationDb(store) => store.write(batch).await,
+            #[cfg(feature = "postgres")]
+            Self::PostgreSQL(store) => store.write(batch).await,
+            #[cfg(feature = "mysql")]
+            Self::MySQL(store) => store.write(batch).await,
+            #[cfg(feature = "rocks")]
+            Self::RocksDb(store) => store.write(batch).await,
+            #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
+            Self::SQLReadReplica(store) => store.write(batch).await,
+            #[cfg(feature = "enterprise")]
+            Self::Sharded(store) => store.write(batch).await,
+            Self::CircuitBreaker(store) => store.write(batch).await,
+            #[cfg(feature = "test_mode")]
+            Self::Mock(store) => store.write(batch).await,
+            Self::None => Err(trc::StoreEvent::NotConfigured.into()),
+        };
+
+        trc::event!(
+            Store(StoreEvent::DataWrite),
+            SpanId = span_id,
+            Elapsed = start_time.elapsed(),
+            Total = ops,
+        );
+
+        result
+    }
+
+    // Writes independent batches concurrently, returning the results in order.
+    // A failed batch does not prevent the remaining ones from being written.
+    pub async fn write_many(&self, batches: Vec<Batch>) -> Vec<trc::Result<AssignedIds>> {
+        let mut results = Vec::with_capacity(batches.len());
+        let handles = batches
+            .into_iter()
+            .map(|batch| {
+                let store = self.clone();
+                tokio::spawn(async move { store.write(batch).await })
+            })
+            .collect::<Vec<_>>();
+
+        for handle in handles {
+            results.push(handle.await.unwrap_or_else(|err| {
+                Err(trc::EventType::Server(trc::ServerEvent::ThreadError)
+                    .reason(err)
+                    .caused_by(trc::location!()))
+            }));
+        }
+
+        results
+    }
+
+    pub async fn purge_store(&self) -> trc::Result<()> {
+        // Delete expired reports
+        let now = now();
+        self.delete_range(
+            ValueKey::from(ValueClass::Report(ReportClass::Dmarc { id: 0, expires: 0 })),
+            ValueKey::from(ValueClass::Report(ReportClass::Dmarc {
+                id: u64::MAX,
+                expires: now,
+            })),
+        )
+        .await
+        .caused_by(trc::location!())?;
+        self.delete_range(
+            ValueKey::from(ValueClass::Report(ReportClass::Tls { id: 0, expires: 0 })),
+            ValueKey::from(ValueClass::Report(ReportClass::Tls {
+                id: u64::MAX,
+                expires: now,
+            })),
+        )
+        .await
+        .caused_by(trc::location!())?;
+        self.delete_range(
+            ValueKey::from(ValueClass::Report(ReportClass::Arf { id: 0, expires: 0 })),
+            ValueKey::from(ValueClass::Report(ReportClass::Arf {
+                id: u64::MAX,
+                expires: now,
+            })),
+        )
+        .await
+        .caused_by(trc::location!())?;
+
+        // Delete expired entries from the recent documents index, shards are
+        // walked one at a time so that each one is visited in key order
+        let recent_ttl = self
+            .expiring_subspaces()
+            .iter()
+            .find_map(|(subspace, ttl)| (*subspace == SUBSPACE_RECENT).then_some(*ttl))
+            .unwrap_or(RECENT_DOCUMENTS_TTL);
+        let expires = now.saturating_sub(recent_ttl);
+        match self {
+            #[cfg(feature = "enterprise")]
+            Self::Sharded(store) => {
+                for shard in store.shards() {
+                    shard
+                        .purge_recent_documents(expires)
+                        .await
+                        .caused_by(trc::location!())?;
+                }
+            }
+            _ => {
+                self.purge_recent_documents(expires)
+                    .await
+                    .caused_by(trc::location!())?;
+            }
+        }
+
+        // Delete keys past their TTL
+        for (subspace, ttl) in self
+            .expiring_subspaces()
+            .iter()
+            .filter(|(subspace, _)| *subspace != SUBSPACE_RECENT)
+        {
+            let delete_keys = self
+                .scan_expired(*subspace, now.saturating_sub(*ttl))
+                .await
+                .caused_by(trc::location!())?;
+            self.delete_keys(*subspace, &delete_keys)
+                .await
+                .caused_by(trc::location!())?;
+        }
+
+        match self {
+            #[cfg(feature = "sqlite")]
+            Self::SQLite(store) => store.purge_store().await,
+            #[cfg(feature = "foundation")]
+            Self::FoundationDb(store) => store.purge_store().await,
+            #[cfg(feature = "postgres")]
+            Self::PostgreSQL(store) => store.purge_store().await,
+            #[cfg(feature = "mysql")]
+            Self::MySQL(store) => store.purge_store().await,
+            #[cfg(feature = "rocks")]
+            Self::RocksDb(store) => store.purge_store().await,
+            #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
+            Self::SQLReadReplica(store) => store.purge_store().await,
+            #[cfg(feature = "enterprise")]
+            Self::Sharded(store) => store.purge_store().await,
+            Self::CircuitBreaker(store) => store.purge_store().await,
+            #[cfg(feature = "test_mode")]
+            Self::Mock(store) => store.purge_store().await,
+            Self::None => Err(trc::StoreEvent::NotConfigured.into()),
+        }
+        .caused_by(trc::location!())
+    }
+
+    // Deletes the recent documents index entries created before the cutoff,
+    // only the expired range of each account and collection is read
+    async fn purge_recent_documents(&self, expires: u64) -> trc::Result<()> {
+        let mut next = Some((0u32, 0u8));
+        while let Some((account_id, collection)) = next {
+            // Seek to the next account and collection with entries
+            let mut found = None;
+            self.iterate(
+                IterateParams::new(
+                    RecentKey {
+                        account_id,
+                        collection,
+                        timestamp: 0,
+                        document_id: 0,
+                    },
+                    RecentKey {
+                        account_id: u32::MAX,
+                        collection: u8::MAX,
+                        timestamp: u64::MAX,
+                        document_id: u32::MAX,
+                    },
+                )
+                .ascending()
+                .only_first()
+                .no_values(),
+                |key, _| {
+                    found = Some((
+                        key.deserialize_be_u32(0)?,
+                        key.get(U32_LEN).copied().unwrap_or_default(),
+                    ));
+                    Ok(false)
+                },
+            )
+            .await?;
+            let Some((account_id, collection)) = found else {
+                break;
+            };
+
+            self.delete_range(
+                RecentKey {
+                    account_id,
+                    collection,
+                    timestamp: 0,
+                    document_id: 0,
+                },
+                RecentKey {
+                    account_id,
+                    collection,
+                    timestamp: expires,
+                    document_id: 0,
+                },
+            )
+            .await?;
+
+            next = if collection < u8::MAX {
+                Some((account_id, collection + 1))
+            } else {
+                account_id.checked_add(1).map(|account_id| (account_id, 0))
+            };
+        }
+
+        Ok(())
+    }
+
+    async fn delete_keys(&self, subspace: u8, keys: &[Vec<u8>]) -> trc::Result<()> {
+        for keys in keys.chunks(1000) {
+            let mut batch = BatchBuilder::new();
+            for key in keys {
+                batch.ops.push(Operation::Value {
+                    class: ValueClass::Any(AnyClass {
+                        subspace,
+                        key: key.clone(),
+                    }),
+                    op: ValueOp::Clear,
+                });
+            }
+            self.write(batch.build()).await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn get_storage_statistics(&self) -> trc::Result<StorageStats> {
+        match self {
+            #[cfg(feature = "sqlite")]
+            Self::SQLite(store) => store.get_storage_statistics().await,
+            #[cfg(feature = "foundation")]
+            Self::FoundationDb(store) => store.get_storage_statistics().await,
+            #[cfg(feature = "postgres")]
+            Self::PostgreSQL(store) => store.get_storage_statistics().await,
+            #[cfg(feature = "mysql")]
+            Self::MySQL(store) => store.get_storage_statistics().await,
+            #[cfg(feature = "rocks")]
+            Self::RocksDb(store) => store.get_storage_statistics().await,
+            #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
+            Self::SQLReadReplica(store) => store.get_storage_statistics().await,
+            #[cfg(feature = "enterprise")]
+            Self::Sharded(store) => store.get_storage_statistics().await,
+            Self::CircuitBreaker(store) => store.get_storage_statistics().await,
+            #[cfg(feature = "test_mode")]
+            Self::Mock(store) => store.get_storage_statistics().await,
+            Self::None => Err(trc::StoreEvent::NotConfigured.into()),
+        }
+        .caused_by(trc::location!())
+    }
+
+    pub async fn atomic_swap(
+        &self,
+        key: impl Key,
+        new_value: Vec<u8>,
+    ) -> trc::Result<Option<Vec<u8>>> {
+        match self {
+            #[cfg(feature = "sqlite")]
+            Self::SQLite(store) => store.atomic_swap(key, new_value).await,
+            #[cfg(feature = "foundation")]
+            Self::FoundationDb(store) => store.atomic_swap(key, new_value).await,
+            #[cfg(feature = "postgres")]
+            Self::PostgreSQL(store) => store.atomic_swap(key, new_value).await,
+            #[cfg(feature = "mysql")]
+            Self::MySQL(store) => store.atomic_swap(key, new_value).await,
+            #[cfg(feature = "rocks")]
+            Self::RocksDb(store) => store.atomic_swap(key, new_value).await,
+            #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
+            Self::SQLReadReplica(store) => store.atomic_swap(key, new_value).await,
+            #[cfg(feature = "enterprise")]
+            Self::Sharded(store) => store.atomic_swap(key, new_value).await,
+            Self::CircuitBreaker(store) => store.atomic_swap(key, new_value).await,
+            #[cfg(feature = "test_mode")]
+            Self::Mock(store) => store.atomic_swap(key, new_value).await,
+            Self::None => Err(trc::StoreEvent::NotConfigured.into()),
+        }
+        .caused_by(trc::location!())
+    }
+
+    // Adds `by` to a counter only if its current value matches the expected
+    // one and returns the new value. Counters that do not exist are read as
+    // zero, a mismatch fails with AssertValueFailed.
+    pub async fn compare_and_increment(
+        &self,
+        key: impl Key,
+        expected_version: u64,
+        by: i64,
+    ) -> trc::Result<i64> {
+        match self {
+            #[cfg(feature = "sqlite")]
+            Self::SQLite(store) => store.compare_and_increment(key, expected_version, by).await,
+            #[cfg(feature = "foundation")]
+            Self::FoundationDb(store) => {
+                store.compare_and_increment(key, expected_version, by).await
+            }
+            #[cfg(feature = "postgres")]
+            Self::PostgreSQL(store) => store.compare_and_increment(key, expected_version, by).await,
+            #[cfg(feature = "mysql")]
+            Self::MySQL(store) => store.compare_and_increment(key, expected_version, by).await,
+            #[cfg(feature = "rocks")]
+            Self::RocksDb(store) => store.compare_and_increment(key, expected_version, by).await,
+            #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
+            Self::SQLReadReplica(store) => {
+                store.compare_and_increment(key, expected_version, by).await
+            }
+            #[cfg(feature = "enterprise")]
+            Self::Sharded(store) => store.compare_and_increment(key, expected_version, by).await,
+            Self::CircuitBreaker(store) => {
+                store.compare_and_increment(key, expected_version, by).await
+            }
+            #[cfg(feature = "test_mode")]
+            Self::Mock(store) => store.compare_and_increment(key, expected_version, by).await,
+            Self::None => Err(trc::StoreEvent::NotConfigured.into()),
         }
+        .caused_by(trc::location!())
+    }
 
-        let start_time = Instant::now();
-        let ops = batch.ops.len();
-
-        let result = match self {
+    // Reads a counter and sets it back to zero in a single transaction,
+    // returning the value it had before the reset. Missing counters read as
+    // zero and are not created.
+    pub async fn get_and_reset_counter(&self, key: impl Key) -> trc::Result<i64> {
+        match self {
             #[cfg(feature = "sqlite")]
-            Self::SQLite(store) => store.write(batch).await,
+            Self::SQLite(store) => store.get_and_reset_counter(key).await,
             #[cfg(feature = "foundation")]
-            Self::FoundationDb(store) => store.write(batch).await,
+            Self::FoundationDb(store) => store.get_and_reset_counter(key).await,
             #[cfg(feature = "postgres")]
-            Self::PostgreSQL(store) => store.write(batch).await,
+            Self::PostgreSQL(store) => store.get_and_reset_counter(key).await,
             #[cfg(feature = "mysql")]
-            Self::MySQL(store) => store.write(batch).await,
+            Self::MySQL(store) => store.get_and_reset_counter(key).await,
             #[cfg(feature = "rocks")]
-            Self::RocksDb(store) => store.write(batch).await,
+            Self::RocksDb(store) => store.get_and_reset_counter(key).await,
             #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
-            Self::SQLReadReplica(store) => store.write(batch).await,
+            Self::SQLReadReplica(store) => store.get_and_reset_counter(key).await,
+            #[cfg(feature = "enterprise")]
+            Self::Sharded(store) => store.get_and_reset_counter(key).await,
+            Self::CircuitBreaker(store) => store.get_and_reset_counter(key).await,
+            #[cfg(feature = "test_mode")]
+            Self::Mock(store) => store.get_and_reset_counter(key).await,
             Self::None => Err(trc::StoreEvent::NotConfigured.into()),
-        };
-
-        trc::event!(
-            Store(StoreEvent::DataWrite),
-            Elapsed = start_time.elapsed(),
-            Total = ops,
-        );
-
-        result
+        }
+        .caused_by(trc::location!())
     }
 
-    pub async fn purge_store(&self) -> trc::Result<()> {
-        // Delete expired reports
-        let now = now();
-        self.delete_range(
-            ValueKey::from(ValueClass::Report(ReportClass::Dmarc { id: 0, expires: 0 })),
-            ValueKey::from(ValueClass::Report(ReportClass::Dmarc {
-                id: u64::MAX,
-                expires: now,
-            })),
-        )
-        .await
-        .caused_by(trc::location!())?;
-        self.delete_range(
-            ValueKey::from(ValueClass::Report(ReportClass::Tls { id: 0, expires: 0 })),
-            ValueKey::from(ValueClass::Report(ReportClass::Tls {
-                id: u64::MAX,
-                expires: now,
-            })),
-        )
-        .await
-        .caused_by(trc::location!())?;
-        self.delete_range(
-            ValueKey::from(ValueClass::Report(ReportClass::Arf { id: 0, expires: 0 })),
-            ValueKey::from(ValueClass::Report(ReportClass::Arf {
-                id: u64::MAX,
-                expires: now,
-            })),
-        )
-        .await
-        .caused_by(trc::location!())?;
-
+    // Raises a value to `value` if it is currently lower or missing and
+    // returns the resulting maximum, useful for high-water marks that must
+    // never move backwards under concurrent writers.
+    pub async fn atomic_max(&self, key: impl Key, value: i64) -> trc::Result<i64> {
         match self {
             #[cfg(feature = "sqlite")]
-            Self::SQLite(store) => store.purge_store().await,
+            Self::SQLite(store) => store.atomic_max(key, value).await,
             #[cfg(feature = "foundation")]
-            Self::FoundationDb(store) => store.purge_store().await,
+            Self::FoundationDb(store) => store.atomic_max(key, value).await,
             #[cfg(feature = "postgres")]
-            Self::PostgreSQL(store) => store.purge_store().await,
+            Self::PostgreSQL(store) => store.atomic_max(key, value).await,
             #[cfg(feature = "mysql")]
-            Self::MySQL(store) => store.purge_store().await,
+            Self::MySQL(store) => store.atomic_max(key, value).await,
             #[cfg(feature = "rocks")]
-            Self::RocksDb(store) => store.purge_store().await,
+            Self::RocksDb(store) => store.atomic_max(key, value).await,
             #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
-            Self::SQLReadReplica(store) => store.purge_store().await,
+            Self::SQLReadReplica(store) => store.atomic_max(key, value).await,
+            #[cfg(feature = "enterprise")]
+            Self::Sharded(store) => store.atomic_max(key, value).await,
+            Self::CircuitBreaker(store) => store.atomic_max(key, value).await,
+            #[cfg(feature = "test_mode")]
+            Self::Mock(store) => store.atomic_max(key, value).await,
             Self::None => Err(trc::StoreEvent::NotConfigured.into()),
         }
         .caused_by(trc::location!())
@@ -336,6 +1521,41 @@ impl Store {
             Self::RocksDb(store) => store.delete_range(from, to).await,
             #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
             Self::SQLReadReplica(store) => store.delete_range(from, to).await,
+            #[cfg(feature = "enterprise")]
+            Self::Sharded(store) => store.delete_range(from, to).await,
+            Self::CircuitBreaker(store) => store.delete_range(from, to).await,
+            #[cfg(feature = "test_mode")]
+            Self::Mock(store) => store.delete_range(from, to).await,
+            Self::None => Err(trc::StoreEvent::NotConfigured.into()),
+        }
+        .caused_by(trc::location!())
+    }
+
+    // Replaces the account id at the beginning of each key in the range
+    pub(crate) async fn rename_range(
+        &self,
+        from: impl Key,
+        to: impl Key,
+        account_id: u32,
+    ) -> trc::Result<u64> {
+        match self {
+            #[cfg(feature = "sqlite")]
+            Self::SQLite(store) => store.rename_range(from, to, account_id).await,
+            #[cfg(feature = "foundation")]
+            Self::FoundationDb(store) => store.rename_range(from, to, account_id).await,
+            #[cfg(feature = "postgres")]
+            Self::PostgreSQL(store) => store.rename_range(from, to, account_id).await,
+            #[cfg(feature = "mysql")]
+            Self::MySQL(store) => store.rename_range(from, to, account_id).await,
+            #[cfg(feature = "rocks")]
+            Self::RocksDb(store) => store.rename_range(from, to, account_id).await,
+            #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
+            Self::SQLReadReplica(store) => store.rename_range(from, to, account_id).await,
+            #[cfg(feature = "enterprise")]
+            Self::Sharded(store) => store.rename_range(from, to, account_id).await,
+            Self::CircuitBreaker(store) => store.rename_range(from, to, account_id).await,
+            #[cfg(feature = "test_mode")]
+            Self::Mock(store) => store.rename_range(from, to, account_id).await,
             Self::None => Err(trc::StoreEvent::NotConfigured.into()),
         }
         .caused_by(trc::location!())
@@ -420,6 +1640,80 @@ impl Store {
         Ok(())
     }
 
+    // Removes all bitmap, value and index entries of the given documents,
+    // returning the number of documents that existed
+    pub async fn bulk_delete_documents(
+        &self,
+        account_id: u32,
+        collection: impl Into<u8>,
+        document_ids: &RoaringBitmap,
+    ) -> trc::Result<u64> {
+        let collection = collection.into();
+        if document_ids.is_empty() {
+            return Ok(0);
+        }
+
+        // PostgreSQL deletes each subspace with a single statement, all within
+        // one transaction
+        #[cfg(feature = "postgres")]
+        if let Self::PostgreSQL(store) = self {
+            return store
+                .bulk_delete_documents(account_id, collection, document_ids)
+                .await
+                .caused_by(trc::location!());
+        }
+
+        // Other stores delete keys as they are scanned, in transactions of up to
+        // BULK_DELETE_BATCH_SIZE keys. This is not atomic: if a write fails, the
+        // documents may be left partially deleted and the call should be retried.
+        let mut batch = BatchBuilder::new();
+        let mut deleted = 0;
+        for (subspace, collection_offset) in DOCUMENT_SUBSPACES {
+            let (from_key, to_key) = document_range(account_id, collection, collection_offset);
+            let mut keys = std::pin::pin!(self.scan_index_keys_stream(
+                AnyKey {
+                    subspace,
+                    key: from_key,
+                },
+                AnyKey {
+                    subspace,
+                    key: to_key,
+                },
+            ));
+            while let Some(key) = keys.try_next().await.caused_by(trc::location!())? {
+                if key.len() <= U32_LEN + 1
+                    || collection_offset.is_some_and(|offset| {
+                        key.get(key.len() - U32_LEN - offset).copied() != Some(collection)
+                    })
+                    || !document_ids.contains(key.deserialize_be_u32(key.len() - U32_LEN)?)
+                {
+                    continue;
+                }
+
+                if subspace == SUBSPACE_BITMAP_ID {
+                    deleted += 1;
+                }
+                if batch.ops.len() >= BULK_DELETE_BATCH_SIZE {
+                    self.write(std::mem::take(&mut batch).build())
+                        .await
+                        .caused_by(trc::location!())?;
+                }
+                batch.ops.push(Operation::Value {
+                    class: ValueClass::Any(AnyClass { subspace, key }),
+                    op: ValueOp::Clear,
+                });
+            }
+        }
+
+        if !batch.is_empty() {
+            self.write(batch.build())
+                .await
+                .caused_by(trc::location!())?;
+        }
+
+        Ok(deleted)
+    }
+
     pub async fn purge_account(&self, account_id: u32) -> trc::Result<()> {
         for subspace in [
             SUBSPACE_BITMAP_ID,
@@ -427,6 +1721,7 @@ impl Store {
             SUBSPACE_BITMAP_TEXT,
             SUBSPACE_LOGS,
             SUBSPACE_INDEXES,
+            SUBSPACE_RECENT,
         ] {
             self.delete_range(
                 AnyKey {
@@ -474,19 +1769,19 @@ impl Store {
             .caused_by(trc::location!())?;
         }
 
-        // Delete property counters (TODO: make this more elegant)
+        // Delete property counters
         self.delete_range(
             ValueKey {
                 account_id,
-                collection: 1,
+                collection: COUNTER_COLLECTION,
                 document_id: 0,
-                class: ValueClass::Property(84),
+                class: ValueClass::Property(COUNTER_PROPERTY),
             },
             ValueKey {
                 account_id,
-                collection: 1,
+                collection: COUNTER_COLLECTION,
                 document_id: u32::MAX,
-                class: ValueClass::Property(84),
+                class: ValueClass::Property(COUNTER_PROPERTY),
             },
         )
         .await
@@ -495,6 +1790,289 @@ impl Store {
         Ok(())
     }
 
+    // Removes all data owned by an account. Ranges are deleted concurrently, the
+    // ACLs granted by the account and its blob links are removed afterwards but
+    // are not included in the returned statistics.
+    pub async fn delete_account(&self, account_id: u32) -> trc::Result<AccountDeletionStats> {
+        let from_key = KeySerializer::new(U32_LEN).write(account_id).finalize();
+        let to_key = KeySerializer::new(U32_LEN).write(account_id + 1).finalize();
+        let mut ranges = [
+            SUBSPACE_BITMAP_ID,
+            SUBSPACE_BITMAP_TAG,
+            SUBSPACE_BITMAP_TEXT,
+            SUBSPACE_LOGS,
+            SUBSPACE_INDEXES,
+            SUBSPACE_RECENT,
+            SUBSPACE_PROPERTY,
+            SUBSPACE_FTS_INDEX,
+            SUBSPACE_ACL,
+            SUBSPACE_BLOB_RESERVE,
+        ]
+        .into_iter()
+        .map(|subspace| {
+            (
+                AnyKey {
+                    subspace,
+                    key: from_key.clone(),
+                },
+                AnyKey {
+                    subspace,
+                    key: to_key.clone(),
+                },
+            )
+        })
+        .collect::<Vec<_>>();
+
+        // Counters and quota share their subspace with keys of other accounts
+        for (from_key, to_key) in [
+            (
+                ValueKey {
+                    account_id,
+                    collection: COUNTER_COLLECTION,
+                    document_id: 0,
+                    class: ValueClass::Property(COUNTER_PROPERTY),
+                },
+                ValueKey {
+                    account_id,
+                    collection: COUNTER_COLLECTION,
+                    document_id: u32::MAX,
+                    class: ValueClass::Property(COUNTER_PROPERTY),
+                },
+            ),
+            (
+                ValueKey::from(ValueClass::Directory(DirectoryClass::UsedQuota(account_id))),
+                ValueKey::from(ValueClass::Directory(DirectoryClass::UsedQuota(account_id))),
+            ),
+        ] {
+            let subspace = from_key.subspace();
+            let from_key = from_key.serialize(0);
+            let mut to_key = to_key.serialize(0);
+            if from_key == to_key {
+                to_key.push(0);
+            }
+            ranges.push((
+                AnyKey {
+                    subspace,
+                    key: from_key,
+                },
+                AnyKey {
+                    subspace,
+                    key: to_key,
+                },
+            ));
+        }
+
+        let handles = ranges
+            .into_iter()
+            .map(|(from_key, to_key)| {
+                let store = self.clone();
+                tokio::spawn(async move {
+                    let mut stats = AccountDeletionStats::default();
+                    store
+                        .iterate(
+                            IterateParams::new(from_key.clone(), to_key.clone()),
+                            |key, value| {
+                                stats.rows_deleted += 1;
+                                stats.bytes_freed += (key.len() + value.len()) as u64;
+                                Ok(true)
+                            },
+                        )
+                        .await?;
+                    store.delete_range(from_key, to_key).await?;
+                    Ok::<_, trc::Error>(stats)
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut stats = AccountDeletionStats::default();
+        for handle in handles {
+            let range_stats = handle
+                .await
+                .map_err(|err| {
+                    trc::EventType::Server(trc::ServerEvent::ThreadError)
+                        .reason(err)
+                        .caused_by(trc::location!())
+                })?
+                .caused_by(trc::location!())?;
+            stats.rows_deleted += range_stats.rows_deleted;
+            stats.bytes_freed += range_stats.bytes_freed;
+        }
+
+        self.acl_revoke_all(account_id)
+            .await
+            .caused_by(trc::location!())?;
+        self.blob_hash_unlink_account(account_id)
+            .await
+            .caused_by(trc::location!())?;
+
+        Ok(stats)
+    }
+
+    // Moves all data owned by an account to a new account id, which must not be
+    // in use. Keys are rewritten in place, so an interrupted rename can be
+    // completed by calling this function again. Directory entries are not updated.
+    pub async fn rename_account(
+        &self,
+        old_account_id: u32,
+        new_account_id: u32,
+    ) -> trc::Result<RenameStats> {
+        let mut stats = RenameStats::default();
+        if old_account_id == new_account_id {
+            return Ok(stats);
+        }
+
+        // Keys starting with the account id
+        let from_key = KeySerializer::new(U32_LEN).write(old_account_id).finalize();
+        let to_key = prefix_upper_bound(&from_key);
+        for subspace in [
+            SUBSPACE_BITMAP_ID,
+            SUBSPACE_BITMAP_TAG,
+            SUBSPACE_BITMAP_TEXT,
+            SUBSPACE_LOGS,
+            SUBSPACE_INDEXES,
+            SUBSPACE_RECENT,
+            SUBSPACE_PROPERTY,
+            SUBSPACE_FTS_INDEX,
+            SUBSPACE_ACL,
+            SUBSPACE_BLOB_RESERVE,
+        ] {
+            let renamed = self
+                .rename_range(
+                    AnyKey {
+                        subspace,
+                        key: from_key.clone(),
+                    },
+                    AnyKey {
+                        subspace,
+                        key: to_key.clone(),
+                    },
+                    new_account_id,
+                )
+                .await
+                .caused_by(trc::location!())?;
+            stats.add(subspace, renamed);
+        }
+
+        // Counters share their subspace with keys of other accounts
+        let renamed = self
+            .rename_range(
+                ValueKey {
+                    account_id: old_account_id,
+                    collection: COUNTER_COLLECTION,
+                    document_id: 0,
+                    class: ValueClass::Property(COUNTER_PROPERTY),
+                },
+                ValueKey {
+                    account_id: old_account_id,
+                    collection: COUNTER_COLLECTION,
+                    document_id: u32::MAX,
+                    class: ValueClass::Property(COUNTER_PROPERTY),
+                },
+                new_account_id,
+            )
+            .await
+            .caused_by(trc::location!())?;
+        stats.add(SUBSPACE_COUNTER, renamed);
+
+        // Quota usage
+        let used_quota = self
+            .get_counter(DirectoryClass::UsedQuota(old_account_id))
+            .await
+            .caused_by(trc::location!())?;
+        if used_quota != 0 {
+            let mut batch = BatchBuilder::new();
+            batch
+                .add(DirectoryClass::UsedQuota(new_account_id), used_quota)
+                .clear(DirectoryClass::UsedQuota(old_account_id));
+            self.write(batch.build())
+                .await
+                .caused_by(trc::location!())?;
+            stats.add(SUBSPACE_QUOTA, 1);
+        }
+
+        // ACLs granted by the account and blob links contain the account id
+        // after the grantee id and blob hash respectively
+        let mut rename_keys = Vec::new();
+        self.iterate(
+            IterateParams::new(
+                AnyKey {
+                    subspace: SUBSPACE_ACL,
+                    key: vec![0u8],
+                },
+                AnyKey {
+                    subspace: SUBSPACE_ACL,
+                    key: vec![u8::MAX; U32_LEN * 3 + 2],
+                },
+            )
+            .ascending(),
+            |key, value| {
+                if key.deserialize_be_u32(U32_LEN)? == old_account_id {
+                    rename_keys.push((SUBSPACE_ACL, U32_LEN, key.to_vec(), value.to_vec()));
+                }
+                Ok(true)
+            },
+        )
+        .await
+        .caused_by(trc::location!())?;
+        self.iterate(
+            IterateParams::new(
+                AnyKey {
+                    subspace: SUBSPACE_BLOB_LINK,
+                    key: vec![0u8],
+                },
+                AnyKey {
+                    subspace: SUBSPACE_BLOB_LINK,
+                    key: vec![u8::MAX; BLOB_HASH_LEN + U32_LEN * 2 + 1],
+                },
+            )
+            .ascending(),
+            |key, value| {
+                if key.len() == BLOB_HASH_LEN + U32_LEN * 2 + 1
+                    && key[BLOB_HASH_LEN + U32_LEN] != u8::MAX
+                    && key.deserialize_be_u32(BLOB_HASH_LEN)? == old_account_id
+                {
+                    rename_keys.push((
+                        SUBSPACE_BLOB_LINK,
+                        BLOB_HASH_LEN,
+                        key.to_vec(),
+                        value.to_vec(),
+                    ));
+                }
+                Ok(true)
+            },
+        )
+        .await
+        .caused_by(trc::location!())?;
+
+        let mut batch = BatchBuilder::new();
+        for (subspace, offset, key, value) in rename_keys {
+            if batch.ops.len() >= 1000 {
+                self.write(std::mem::take(&mut batch).build())
+                    .await
+                    .caused_by(trc::location!())?;
+            }
+            let mut new_key = key.clone();
+            new_key[offset..offset + U32_LEN].copy_from_slice(&new_account_id.to_be_bytes());
+            batch
+                .set(
+                    ValueClass::Any(AnyClass {
+                        subspace,
+                        key: new_key,
+                    }),
+                    value,
+                )
+                .clear(ValueClass::Any(AnyClass { subspace, key }));
+            stats.add(subspace, 1);
+        }
+        if !batch.is_empty() {
+            self.write(batch.build())
+                .await
+                .caused_by(trc::location!())?;
+        }
+
+        Ok(stats)
+    }
+
     pub async fn get_blob(&self, key: &[u8], range: Range<usize>) -> trc::Result<Option<Vec<u8>>> {
         match self {
             #[cfg(feature = "sqlite")]
@@ -509,6 +2087,11 @@ impl Store {
             Self::RocksDb(store) => store.get_blob(key, range).await,
             #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
             Self::SQLReadReplica(store) => store.get_blob(key, range).await,
+            #[cfg(feature = "enterprise")]
+            Self::Sharded(store) => store.get_blob(key, range).await,
+            Self::CircuitBreaker(store) => store.get_blob(key, range).await,
+            #[cfg(feature = "test_mode")]
+            Self::Mock(store) => store.get_blob(key, range).await,
             Self::None => Err(trc::StoreEvent::NotConfigured.into()),
         }
         .caused_by(trc::location!())
@@ -528,6 +2111,11 @@ impl Store {
             Self::RocksDb(store) => store.put_blob(key, data).await,
             #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
             Self::SQLReadReplica(store) => store.put_blob(key, data).await,
+            #[cfg(feature = "enterprise")]
+            Self::Sharded(store) => store.put_blob(key, data).await,
+            Self::CircuitBreaker(store) => store.put_blob(key, data).await,
+            #[cfg(feature = "test_mode")]
+            Self::Mock(store) => store.put_blob(key, data).await,
             Self::None => Err(trc::StoreEvent::NotConfigured.into()),
         }
         .caused_by(trc::location!())
@@ -547,6 +2135,11 @@ impl Store {
             Self::RocksDb(store) => store.delete_blob(key).await,
             #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
             Self::SQLReadReplica(store) => store.delete_blob(key).await,
+            #[cfg(feature = "enterprise")]
+            Self::Sharded(store) => store.delete_blob(key).await,
+            Self::CircuitBreaker(store) => store.delete_blob(key).await,
+            #[cfg(feature = "test_mode")]
+            Self::Mock(store) => store.delete_blob(key).await,
             Self::None => Err(trc::StoreEvent::NotConfigured.into()),
         }
         .caused_by(trc::location!())
@@ -581,6 +2174,9 @@ impl Store {
             SUBSPACE_TELEMETRY_SPAN,
             SUBSPACE_TELEMETRY_METRIC,
             SUBSPACE_TELEMETRY_INDEX,
+            SUBSPACE_RECENT,
+            SUBSPACE_QUARANTINE,
+            SUBSPACE_AUDIT,
         ] {
             self.delete_range(
                 AnyKey {
@@ -894,3 +2490,60 @@ impl Store {
         }
     }
 }
+
+// Smallest key sorting after every key that starts with the prefix, or the
+// prefix padded with 0xFF when no such key exists
+fn prefix_upper_bound(prefix: &[u8]) -> Vec<u8> {
+    let mut key = prefix.to_vec();
+    while let Some(byte) = key.pop() {
+        if byte < u8::MAX {
+            key.push(byte + 1);
+            return key;
+        }
+    }
+    let mut key = prefix.to_vec();
+    key.extend_from_slice(&[u8::MAX; 255]);
+    key
+}
+
+fn account_counter_range(account_id: u32) -> (AnyKey<Vec<u8>>, AnyKey<Vec<u8>>) {
+    (
+        AnyKey {
+            subspace: SUBSPACE_COUNTER,
+            key: account_id.to_be_bytes().to_vec(),
+        },
+        AnyKey {
+            subspace: SUBSPACE_COUNTER,
+            key: account_id.checked_add(1).map_or_else(
+                || vec![u8::MAX; U32_LEN + 1],
+                |account_id| account_id.to_be_bytes().to_vec(),
+            ),
+        },
+    )
+}
+
+// Key range covering the entries of a collection, or the whole account when the
+// collection is not part of the key prefix
+pub(crate) fn document_range(
+    account_id: u32,
+    collection: u8,
+    collection_offset: Option<usize>,
+) -> (Vec<u8>, Vec<u8>) {
+    let from_key = KeySerializer::new(U32_LEN + 1).write(account_id);
+    let to_key = KeySerializer::new(U32_LEN + 1);
+    if collection_offset.is_none() {
+        (
+            from_key.write(collection).finalize(),
+            match collection.checked_add(1) {
+                Some(collection) => to_key.write(account_id).write(collection),
+                None => to_key.write(account_id.saturating_add(1)),
+            }
+            .finalize(),
+        )
+    } else {
+        (
+            from_key.finalize(),
+            to_key.write(account_id.saturating_add(1)).finalize(),
+        )
+    }
+}
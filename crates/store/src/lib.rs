@@ -4,7 +4,7 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::{borrow::Cow, sync::Arc};
+use std::{borrow::Cow, collections::HashMap, pin::Pin, sync::Arc};
 
 pub mod backend;
 pub mod config;
@@ -49,6 +49,9 @@ use backend::redis::RedisStore;
 #[cfg(feature = "azure")]
 use backend::azure::AzureStore;
 
+#[cfg(feature = "nats")]
+use backend::nats::NatsStore;
+
 pub trait Deserialize: Sized + Sync + Send {
     fn deserialize(bytes: &[u8]) -> trc::Result<Self>;
 }
@@ -63,6 +66,11 @@ pub(crate) const WITH_SUBSPACE: u32 = 1;
 pub trait Key: Sync + Send + Clone {
     fn serialize(&self, flags: u32) -> Vec<u8>;
     fn subspace(&self) -> u8;
+
+    // Account owning a key stored outside the account subspaces
+    fn owner_account_id(&self) -> Option<u32> {
+        None
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -104,9 +112,22 @@ pub struct LogKey {
     pub change_id: u64,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RecentKey {
+    pub account_id: u32,
+    pub collection: u8,
+    pub timestamp: u64,
+    pub document_id: u32,
+}
+
 pub const U64_LEN: usize = std::mem::size_of::<u64>();
 pub const U32_LEN: usize = std::mem::size_of::<u32>();
 
+// Per-document counters are stored as the EmailIds property (84) of the
+// Mailbox collection (1)
+pub const COUNTER_COLLECTION: u8 = 1;
+pub const COUNTER_PROPERTY: u8 = 84;
+
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum BlobClass {
     Reserved {
@@ -154,8 +175,168 @@ pub const SUBSPACE_TELEMETRY_SPAN: u8 = b'o';
 pub const SUBSPACE_TELEMETRY_INDEX: u8 = b'w';
 pub const SUBSPACE_TELEMETRY_METRIC: u8 = b'x';
 
-pub const SUBSPACE_RESERVED_1: u8 = b'y';
-pub const SUBSPACE_RESERVED_2: u8 = b'z';
+pub const SUBSPACE_RECENT: u8 = b'y';
+pub const SUBSPACE_QUARANTINE: u8 = b'z';
+pub const SUBSPACE_AUDIT: u8 = b'_';
+
+pub const SUBSPACES: [u8; 27] = [
+    SUBSPACE_ACL,
+    SUBSPACE_BITMAP_ID,
+    SUBSPACE_BITMAP_TAG,
+    SUBSPACE_BITMAP_TEXT,
+    SUBSPACE_DIRECTORY,
+    SUBSPACE_FTS_QUEUE,
+    SUBSPACE_INDEXES,
+    SUBSPACE_BLOB_RESERVE,
+    SUBSPACE_BLOB_LINK,
+    SUBSPACE_BLOBS,
+    SUBSPACE_LOGS,
+    SUBSPACE_COUNTER,
+    SUBSPACE_LOOKUP_VALUE,
+    SUBSPACE_PROPERTY,
+    SUBSPACE_SETTINGS,
+    SUBSPACE_QUEUE_MESSAGE,
+    SUBSPACE_QUEUE_EVENT,
+    SUBSPACE_QUOTA,
+    SUBSPACE_REPORT_OUT,
+    SUBSPACE_REPORT_IN,
+    SUBSPACE_FTS_INDEX,
+    SUBSPACE_TELEMETRY_SPAN,
+    SUBSPACE_TELEMETRY_INDEX,
+    SUBSPACE_TELEMETRY_METRIC,
+    SUBSPACE_RECENT,
+    SUBSPACE_QUARANTINE,
+    SUBSPACE_AUDIT,
+];
+
+// Subspaces holding per-document bitmap, value and index entries. Keys start with
+// the account id and end with the document id; when the collection is not part of
+// the key prefix, its position counting back from the document id is given.
+pub(crate) const DOCUMENT_SUBSPACES: [(u8, Option<usize>); 6] = [
+    (SUBSPACE_BITMAP_ID, None),
+    (SUBSPACE_BITMAP_TAG, None),
+    (SUBSPACE_BITMAP_TEXT, Some(2)),
+    (SUBSPACE_PROPERTY, None),
+    (SUBSPACE_INDEXES, None),
+    (SUBSPACE_FTS_INDEX, Some(1)),
+];
+
+pub fn subspace_name(subspace: u8) -> &'static str {
+    match subspace {
+        SUBSPACE_ACL => "acl",
+        SUBSPACE_BITMAP_ID => "bitmap-id",
+        SUBSPACE_BITMAP_TAG => "bitmap-tag",
+        SUBSPACE_BITMAP_TEXT => "bitmap-text",
+        SUBSPACE_DIRECTORY => "directory",
+        SUBSPACE_FTS_QUEUE => "fts-queue",
+        SUBSPACE_INDEXES => "indexes",
+        SUBSPACE_BLOB_RESERVE => "blob-reserve",
+        SUBSPACE_BLOB_LINK => "blob-link",
+        SUBSPACE_BLOBS => "blobs",
+        SUBSPACE_LOGS => "logs",
+        SUBSPACE_COUNTER => "counter",
+        SUBSPACE_LOOKUP_VALUE => "lookup-value",
+        SUBSPACE_PROPERTY => "property",
+        SUBSPACE_SETTINGS => "settings",
+        SUBSPACE_QUEUE_MESSAGE => "queue-message",
+        SUBSPACE_QUEUE_EVENT => "queue-event",
+        SUBSPACE_QUOTA => "quota",
+        SUBSPACE_REPORT_OUT => "report-out",
+        SUBSPACE_REPORT_IN => "report-in",
+        SUBSPACE_FTS_INDEX => "fts-index",
+        SUBSPACE_TELEMETRY_SPAN => "telemetry-span",
+        SUBSPACE_TELEMETRY_INDEX => "telemetry-index",
+        SUBSPACE_TELEMETRY_METRIC => "telemetry-metric",
+        SUBSPACE_RECENT => "recent",
+        SUBSPACE_QUARANTINE => "quarantine",
+        SUBSPACE_AUDIT => "audit",
+        _ => "unknown",
+    }
+}
+
+// Backends report sizes on disk as estimated by the database engine, row
+// counts are omitted when the backend has no inexpensive way to obtain them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct StorageStats {
+    pub subspace_sizes: HashMap<u8, u64>,
+    pub total_rows: HashMap<u8, u64>,
+}
+
+// Rows and bytes (keys and values) removed by Store::delete_account
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AccountDeletionStats {
+    pub rows_deleted: u64,
+    pub bytes_freed: u64,
+}
+
+// Keys moved to the new account id by Store::rename_account, per subspace
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RenameStats {
+    pub rows_renamed: HashMap<u8, u64>,
+}
+
+impl RenameStats {
+    pub(crate) fn add(&mut self, subspace: u8, rows: u64) {
+        if rows > 0 {
+            *self.rows_renamed.entry(subspace).or_default() += rows;
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.rows_renamed.values().sum()
+    }
+}
+
+// Index entries derived from the stored documents by Store::rebuild_index,
+// along with the stale entries removed and the missing entries added.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IndexRebuildStats {
+    pub indexed: u64,
+    pub added: u64,
+    pub removed: u64,
+}
+
+// Cardinality of an indexed field as returned by Store::get_index_statistics
+#[cfg(feature = "store-diagnostics")]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct IndexStats {
+    pub field: u8,
+    pub distinct_terms: u64,
+    pub total_entries: u64,
+    pub avg_docs_per_term: f64,
+}
+
+// Disk quota of an account as returned by Store::get_account_quota, a limit
+// of zero means that the account has no quota.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QuotaStatus {
+    pub used: i64,
+    pub limit: i64,
+    pub over_quota: bool,
+}
+
+impl QuotaStatus {
+    pub fn new(used: i64, limit: i64) -> Self {
+        QuotaStatus {
+            used,
+            limit,
+            over_quota: limit > 0 && used > limit,
+        }
+    }
+
+    pub fn percentage(&self) -> f64 {
+        if self.limit > 0 {
+            (self.used.max(0) as f64 / self.limit as f64) * 100.0
+        } else {
+            0.0
+        }
+    }
+}
+
+// Implemented by the type stored as the principal of an account
+pub trait QuotaLimit: Deserialize {
+    fn quota_limit(&self) -> u64;
+}
 
 #[derive(Clone)]
 pub struct IterateParams<T: Key> {
@@ -172,6 +353,7 @@ pub struct Stores {
     pub blob_stores: AHashMap<String, BlobStore>,
     pub fts_stores: AHashMap<String, FtsStore>,
     pub lookup_stores: AHashMap<String, LookupStore>,
+    pub pubsub_stores: AHashMap<String, PubSubStore>,
     pub purge_schedules: Vec<PurgeSchedule>,
 }
 
@@ -189,6 +371,11 @@ pub enum Store {
     RocksDb(Arc<RocksDbStore>),
     #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
     SQLReadReplica(Arc<backend::composite::read_replica::SQLReadReplica>),
+    #[cfg(feature = "enterprise")]
+    Sharded(Arc<backend::composite::sharded::ShardedStore>),
+    CircuitBreaker(Arc<backend::circuit_breaker::StoreCircuitBreaker>),
+    #[cfg(feature = "test_mode")]
+    Mock(Arc<backend::mock::MockStore>),
     #[default]
     None,
 }
@@ -199,6 +386,10 @@ pub struct BlobStore {
     pub compression: CompressionAlgo,
 }
 
+pub type BlobStream = Pin<Box<dyn tokio::io::AsyncRead + Send>>;
+
+pub type PubSubStream = Pin<Box<dyn futures::Stream<Item = Vec<u8>> + Send>>;
+
 #[derive(Clone, Copy, Debug)]
 pub enum CompressionAlgo {
     None,
@@ -233,6 +424,17 @@ pub enum LookupStore {
     Memory(Arc<MemoryStore>),
 }
 
+// Message broker used to exchange events between cluster nodes
+#[derive(Clone, Default)]
+pub enum PubSubStore {
+    #[cfg(feature = "redis")]
+    Redis(Arc<RedisStore>),
+    #[cfg(feature = "nats")]
+    Nats(Arc<NatsStore>),
+    #[default]
+    None,
+}
+
 #[derive(Debug)]
 pub struct QueryStore {
     pub store: LookupStore,
@@ -274,6 +476,13 @@ impl From<RocksDbStore> for Store {
     }
 }
 
+#[cfg(feature = "test_mode")]
+impl From<Arc<backend::mock::MockStore>> for Store {
+    fn from(store: Arc<backend::mock::MockStore>) -> Self {
+        Self::Mock(store)
+    }
+}
+
 impl From<FsStore> for BlobStore {
     fn from(store: FsStore) -> Self {
         BlobStore {
@@ -687,6 +896,7 @@ impl Store {
             Store::MySQL(_) => true,
             #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
             Store::SQLReadReplica(_) => true,
+            Store::CircuitBreaker(store) => store.inner().is_sql(),
             _ => false,
         }
     }
@@ -706,6 +916,7 @@ impl Store {
         match self {
             #[cfg(any(feature = "postgres", feature = "mysql"))]
             Store::SQLReadReplica(_) => true,
+            Store::Sharded(_) => true,
             _ => false,
         }
     }
@@ -731,6 +942,14 @@ impl std::fmt::Debug for Store {
             Self::RocksDb(_) => f.debug_tuple("RocksDb").finish(),
             #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
             Self::SQLReadReplica(_) => f.debug_tuple("SQLReadReplica").finish(),
+            #[cfg(feature = "enterprise")]
+            Self::Sharded(_) => f.debug_tuple("Sharded").finish(),
+            Self::CircuitBreaker(store) => f
+                .debug_tuple("CircuitBreaker")
+                .field(store.inner())
+                .finish(),
+            #[cfg(feature = "test_mode")]
+            Self::Mock(_) => f.debug_tuple("Mock").finish(),
             Self::None => f.debug_tuple("None").finish(),
         }
     }
@@ -756,6 +975,8 @@ impl Stores {
             #[cfg(any(feature = "postgres", feature = "mysql"))]
             self.stores
                 .retain(|_, store| !matches!(store, Store::SQLReadReplica(_)));
+            self.stores
+                .retain(|_, store| !matches!(store, Store::Sharded(_)));
             self.blob_stores
                 .retain(|_, store| !matches!(store.backend, BlobBackend::Composite(_)));
         }
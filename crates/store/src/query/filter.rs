@@ -304,6 +304,72 @@ impl Store {
             Ok(None)
         }
     }
+
+    // Returns the documents with an indexed value between from and to (both inclusive)
+    pub async fn get_index_value_range(
+        &self,
+        account_id: u32,
+        collection: impl Into<u8> + Sync + Send,
+        field: impl Into<u8> + Sync + Send,
+        from: &[u8],
+        to: &[u8],
+    ) -> trc::Result<RoaringBitmap> {
+        let collection = collection.into();
+        let field = field.into();
+        let mut bm = RoaringBitmap::new();
+        if from > to {
+            return Ok(bm);
+        }
+
+        let prefix = IndexKeyPrefix {
+            account_id,
+            collection,
+            field,
+        }
+        .serialize(0);
+
+        self.iterate(
+            IterateParams::new(
+                IndexKey {
+                    account_id,
+                    collection,
+                    document_id: 0,
+                    field,
+                    key: from,
+                },
+                IndexKey {
+                    account_id,
+                    collection,
+                    document_id: u32::MAX,
+                    field,
+                    key: to,
+                },
+            )
+            .no_values()
+            .ascending(),
+            |key, _| {
+                if !key.starts_with(&prefix) {
+                    return Ok(false);
+                }
+
+                // Values sharing a prefix with the bounds can fall within the
+                // key range without being within the value range
+                let id_pos = key.len() - U32_LEN;
+                let value = key
+                    .get(IndexKeyPrefix::len()..id_pos)
+                    .ok_or_else(|| trc::Error::corrupted_key(key, None, trc::location!()))?;
+                if value >= from && value <= to {
+                    bm.insert(key.deserialize_be_u32(id_pos)?);
+                }
+
+                Ok(true)
+            },
+        )
+        .await
+        .caused_by(trc::location!())?;
+
+        Ok(bm)
+    }
 }
 
 impl From<Filter> for State {
@@ -4,10 +4,22 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+use std::time::Instant;
+
+use roaring::RoaringBitmap;
 use trc::AddContext;
 use utils::codec::leb128::Leb128Iterator;
 
-use crate::{write::key::DeserializeBigEndian, IterateParams, LogKey, Store, U64_LEN};
+use crate::{
+    write::{key::DeserializeBigEndian, now},
+    BitmapKey, IterateParams, LogKey, RecentKey, Store, U32_LEN, U64_LEN,
+};
+
+const CLEAR_CHUNK_SIZE: u64 = 1000;
+
+// Log keys are account id, collection and change id, optionally followed by a timestamp
+const LOG_CHANGE_ID_POS: usize = U32_LEN + 1;
+const LOG_TIMESTAMP_POS: usize = LOG_CHANGE_ID_POS + U64_LEN;
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum Change {
@@ -24,6 +36,12 @@ pub struct Changes {
     pub to_change_id: u64,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogCutoff {
+    ChangeId(u64),
+    Timestamp(u64),
+}
+
 #[derive(Debug)]
 pub enum Query {
     All,
@@ -63,10 +81,11 @@ impl Store {
             collection,
             change_id: from_change_id,
         };
+        // Timestamped entries sort after the end key, so the range is extended by one
         let to_key = LogKey {
             account_id,
             collection,
-            change_id: to_change_id,
+            change_id: to_change_id.saturating_add(1),
         };
 
         let mut changelog = Changes::default();
@@ -74,7 +93,10 @@ impl Store {
         self.iterate(
             IterateParams::new(from_key, to_key).ascending(),
             |key, value| {
-                let change_id = key.deserialize_be_u64(key.len() - U64_LEN)?;
+                let change_id = key.deserialize_be_u64(LOG_CHANGE_ID_POS)?;
+                if change_id > to_change_id {
+                    return Ok(false);
+                }
                 if is_inclusive || change_id != from_change_id {
                     if changelog.changes.is_empty() {
                         changelog.from_change_id = change_id;
@@ -102,6 +124,73 @@ impl Store {
         Ok(changelog)
     }
 
+    pub async fn get_modifications_since(
+        &self,
+        account_id: u32,
+        collection: impl Into<u8> + Sync + Send,
+        change_id: u64,
+        max_changes: usize,
+    ) -> trc::Result<(Vec<u32>, Vec<u32>, Vec<u32>)> {
+        let collection = collection.into();
+        let from_key = LogKey {
+            account_id,
+            collection,
+            change_id,
+        };
+        let to_key = LogKey {
+            account_id,
+            collection,
+            change_id: u64::MAX,
+        };
+
+        let mut changelog = Changes::default();
+        let mut is_truncated = true;
+
+        self.iterate(
+            IterateParams::new(from_key, to_key).ascending(),
+            |key, value| {
+                if !is_truncated {
+                    changelog.deserialize(value).ok_or_else(|| {
+                        trc::Error::corrupted_key(key, value.into(), trc::location!())
+                    })?;
+                    Ok(true)
+                } else {
+                    // The entry for the starting change id is missing if the log was truncated
+                    is_truncated = key.deserialize_be_u64(LOG_CHANGE_ID_POS)? != change_id;
+                    Ok(!is_truncated)
+                }
+            },
+        )
+        .await
+        .caused_by(trc::location!())?;
+
+        if is_truncated {
+            return Err(trc::JmapEvent::CannotCalculateChanges
+                .into_err()
+                .account_id(account_id)
+                .collection(collection)
+                .ctx(trc::Key::Id, change_id));
+        } else if max_changes > 0 && changelog.changes.len() > max_changes {
+            return Err(trc::JmapEvent::TooManyChanges
+                .into_err()
+                .ctx(trc::Key::Total, changelog.changes.len())
+                .ctx(trc::Key::Limit, max_changes));
+        }
+
+        let mut created = Vec::new();
+        let mut updated = Vec::new();
+        let mut destroyed = Vec::new();
+        for change in changelog.changes {
+            match change {
+                Change::Insert(id) => created.push(id as u32),
+                Change::Update(id) | Change::ChildUpdate(id) => updated.push(id as u32),
+                Change::Delete(id) => destroyed.push(id as u32),
+            }
+        }
+
+        Ok((created, updated, destroyed))
+    }
+
     pub async fn get_last_change_id(
         &self,
         account_id: u32,
@@ -128,7 +217,7 @@ impl Store {
                 .no_values()
                 .only_first(),
             |key, _| {
-                last_change_id = key.deserialize_be_u64(key.len() - U64_LEN)?.into();
+                last_change_id = key.deserialize_be_u64(LOG_CHANGE_ID_POS)?.into();
                 Ok(false)
             },
         )
@@ -137,6 +226,220 @@ impl Store {
 
         Ok(last_change_id)
     }
+
+    // Returns the last entries of the change log, oldest first
+    pub async fn get_recent_log_entries(
+        &self,
+        account_id: u32,
+        collection: impl Into<u8> + Sync + Send,
+        last_n: usize,
+    ) -> trc::Result<Vec<(u64, Vec<u8>)>> {
+        let collection = collection.into();
+        let from_key = LogKey {
+            account_id,
+            collection,
+            change_id: 0,
+        };
+        let to_key = LogKey {
+            account_id,
+            collection,
+            change_id: u64::MAX,
+        };
+
+        let mut entries = Vec::with_capacity(last_n);
+        if last_n > 0 {
+            self.iterate(
+                IterateParams::new(from_key, to_key).descending(),
+                |key, value| {
+                    entries.push((key.deserialize_be_u64(LOG_CHANGE_ID_POS)?, value.to_vec()));
+                    Ok(entries.len() < last_n)
+                },
+            )
+            .await
+            .caused_by(trc::location!())?;
+            entries.reverse();
+        }
+
+        Ok(entries)
+    }
+
+    // Returns the most recently created documents first, the index only
+    // covers documents created within the purge.expire.recent TTL (24 hours by default)
+    pub async fn get_recent_documents(
+        &self,
+        account_id: u32,
+        collection: impl Into<u8> + Sync + Send,
+        since: Instant,
+        limit: usize,
+    ) -> trc::Result<Vec<u32>> {
+        let collection = collection.into();
+        let from_key = RecentKey {
+            account_id,
+            collection,
+            timestamp: now().saturating_sub(since.elapsed().as_secs()),
+            document_id: 0,
+        };
+        let to_key = RecentKey {
+            account_id,
+            collection,
+            timestamp: u64::MAX,
+            document_id: u32::MAX,
+        };
+
+        // Documents might have been deleted since they were created
+        let document_ids = self
+            .get_bitmap(BitmapKey::document_ids(account_id, collection))
+            .await
+            .caused_by(trc::location!())?
+            .unwrap_or_default();
+        let mut seen_ids = RoaringBitmap::new();
+        let mut recent_ids = Vec::new();
+
+        if !document_ids.is_empty() && limit > 0 {
+            self.iterate(
+                IterateParams::new(from_key, to_key)
+                    .descending()
+                    .no_values(),
+                |key, _| {
+                    let document_id = key.deserialize_be_u32(key.len() - U32_LEN)?;
+                    if document_ids.contains(document_id) && seen_ids.insert(document_id) {
+                        recent_ids.push(document_id);
+                    }
+                    Ok(recent_ids.len() < limit)
+                },
+            )
+            .await
+            .caused_by(trc::location!())?;
+        }
+
+        Ok(recent_ids)
+    }
+
+    // Returns the last change id committed before the given timestamp, only
+    // entries written with a timestamp are taken into account
+    pub async fn get_change_id_before(
+        &self,
+        account_id: u32,
+        collection: impl Into<u8> + Sync + Send,
+        timestamp: u64,
+    ) -> trc::Result<Option<u64>> {
+        let collection = collection.into();
+        let mut last_change_id = None;
+
+        self.iterate(
+            IterateParams::new(
+                LogKey {
+                    account_id,
+                    collection,
+                    change_id: 0,
+                },
+                LogKey {
+                    account_id,
+                    collection,
+                    change_id: u64::MAX,
+                },
+            )
+            .ascending()
+            .no_values(),
+            |key, _| {
+                if key.len() > LOG_TIMESTAMP_POS {
+                    if key.deserialize_be_u64(LOG_TIMESTAMP_POS)? < timestamp {
+                        last_change_id = key.deserialize_be_u64(LOG_CHANGE_ID_POS)?.into();
+                    } else {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            },
+        )
+        .await
+        .caused_by(trc::location!())?;
+
+        Ok(last_change_id)
+    }
+
+    // Deletes the change log entries preceding the given change id or timestamp, in
+    // chunks to avoid long running transactions. Returns the number of deleted entries.
+    pub async fn clear_change_log(
+        &self,
+        account_id: u32,
+        collection: impl Into<u8> + Sync + Send,
+        cutoff: LogCutoff,
+    ) -> trc::Result<u64> {
+        let collection = collection.into();
+        let before_change_id = match cutoff {
+            LogCutoff::ChangeId(change_id) => change_id,
+            LogCutoff::Timestamp(timestamp) => {
+                match self
+                    .get_change_id_before(account_id, collection, timestamp)
+                    .await?
+                {
+                    Some(change_id) => change_id + 1,
+                    None => return Ok(0),
+                }
+            }
+        };
+        let mut from_change_id = 0;
+        let mut total_deleted = 0;
+
+        while from_change_id < before_change_id {
+            let mut last_change_id = None;
+            let mut chunk_len = 0;
+
+            self.iterate(
+                IterateParams::new(
+                    LogKey {
+                        account_id,
+                        collection,
+                        change_id: from_change_id,
+                    },
+                    LogKey {
+                        account_id,
+                        collection,
+                        change_id: before_change_id,
+                    },
+                )
+                .ascending()
+                .no_values(),
+                |key, _| {
+                    let change_id = key.deserialize_be_u64(LOG_CHANGE_ID_POS)?;
+                    if change_id < before_change_id {
+                        last_change_id = Some(change_id);
+                        chunk_len += 1;
+                        Ok(chunk_len < CLEAR_CHUNK_SIZE)
+                    } else {
+                        Ok(false)
+                    }
+                },
+            )
+            .await
+            .caused_by(trc::location!())?;
+
+            let Some(last_change_id) = last_change_id else {
+                break;
+            };
+
+            self.delete_range(
+                LogKey {
+                    account_id,
+                    collection,
+                    change_id: from_change_id,
+                },
+                LogKey {
+                    account_id,
+                    collection,
+                    change_id: last_change_id + 1,
+                },
+            )
+            .await
+            .caused_by(trc::location!())?;
+
+            total_deleted += chunk_len;
+            from_change_id = last_change_id + 1;
+        }
+
+        Ok(total_deleted)
+    }
 }
 
 impl Changes {
@@ -4,8 +4,14 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+use std::time::{Duration, Instant};
+
+use ahash::AHashMap;
+
+use crate::{subspace_name, U32_LEN, U64_LEN};
+
 use super::{
-    assert::ToAssertValue, Batch, BatchBuilder, BitmapClass, HasFlag, IntoOperations,
+    assert::ToAssertValue, Batch, BatchBuilder, BitmapClass, CommitLimits, HasFlag, IntoOperations,
     MaybeDynamicId, MaybeDynamicValue, Operation, Serialize, TagValue, ToBitmaps, ValueClass,
     ValueOp, F_BITMAP, F_CLEAR, F_INDEX, F_VALUE,
 };
@@ -14,9 +20,16 @@ impl BatchBuilder {
     pub fn new() -> Self {
         Self {
             ops: Vec::with_capacity(16),
+            span_id: None,
         }
     }
 
+    // Attributes the store write to a session span for tracing purposes
+    pub fn with_span_id(&mut self, span_id: u64) -> &mut Self {
+        self.span_id = Some(span_id);
+        self
+    }
+
     pub fn with_change_id(&mut self, change_id: u64) -> &mut Self {
         self.ops.push(Operation::ChangeId { change_id });
         self
@@ -171,6 +184,39 @@ impl BatchBuilder {
         self
     }
 
+    pub fn append(
+        &mut self,
+        class: impl Into<ValueClass<MaybeDynamicId>>,
+        value: impl Into<MaybeDynamicValue>,
+        max_length: usize,
+    ) -> &mut Self {
+        self.ops.push(Operation::Value {
+            class: class.into(),
+            op: ValueOp::Append {
+                value: value.into(),
+                max_length,
+            },
+        });
+        self
+    }
+
+    // Sets the value of a class for multiple documents of the current collection,
+    // the document ids are returned in order as assigned ids
+    pub fn set_many<V: Into<MaybeDynamicValue>>(
+        &mut self,
+        class: impl Into<ValueClass<MaybeDynamicId>>,
+        items: impl IntoIterator<Item = (u32, V)>,
+    ) -> &mut Self {
+        self.ops.push(Operation::SetMany {
+            class: class.into(),
+            items: items
+                .into_iter()
+                .map(|(document_id, value)| (document_id, value.into()))
+                .collect(),
+        });
+        self
+    }
+
     pub fn clear(&mut self, class: impl Into<ValueClass<MaybeDynamicId>>) -> &mut Self {
         self.ops.push(Operation::Value {
             class: class.into(),
@@ -184,18 +230,36 @@ impl BatchBuilder {
         self
     }
 
+    pub fn log_with_timestamp(
+        &mut self,
+        timestamp: u64,
+        value: impl Into<MaybeDynamicValue>,
+    ) -> &mut Self {
+        self.ops.push(Operation::LogWithTimestamp {
+            timestamp,
+            set: value.into(),
+        });
+        self
+    }
+
     pub fn custom(&mut self, value: impl IntoOperations) -> &mut Self {
         value.build(self);
         self
     }
 
     pub fn build(self) -> Batch {
-        Batch { ops: self.ops }
+        Batch {
+            ops: self.ops,
+            span_id: self.span_id,
+            timeout: None,
+        }
     }
 
     pub fn build_batch(&mut self) -> Batch {
         Batch {
             ops: std::mem::take(&mut self.ops),
+            span_id: self.span_id,
+            timeout: None,
         }
     }
 
@@ -221,6 +285,49 @@ impl BatchBuilder {
 }
 
 impl Batch {
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    // Time allowed to commit the batch, including retries
+    pub fn commit_time(&self, limits: &CommitLimits) -> Duration {
+        self.timeout
+            .map_or(limits.max_time, |timeout| timeout.min(limits.max_time))
+    }
+
+    // Returns a timeout error once a batch with its own deadline has used it up,
+    // batches without one keep the store's retry behaviour
+    pub(crate) fn check_deadline(&self, start: Instant, limits: &CommitLimits) -> trc::Result<()> {
+        if self.timeout.is_some() && start.elapsed() >= self.commit_time(limits) {
+            Err(self.timeout_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    pub(crate) fn timeout_error(&self) -> trc::Error {
+        trc::StoreEvent::Timeout
+            .into_err()
+            .details(self.batch_type())
+            .ctx(trc::Key::Elapsed, self.timeout.unwrap_or_default())
+    }
+
+    // Describes the batch by its first operation that writes data
+    pub fn batch_type(&self) -> &'static str {
+        self.ops
+            .iter()
+            .find_map(|op| match op {
+                Operation::Value { .. } | Operation::SetMany { .. } => Some("value"),
+                Operation::Index { .. } => Some("index"),
+                Operation::Bitmap { .. } => Some("bitmap"),
+                Operation::Log { .. } | Operation::LogWithTimestamp { .. } => Some("log"),
+                Operation::AssertValue { .. } => Some("assert"),
+                _ => None,
+            })
+            .unwrap_or("empty")
+    }
+
     pub fn is_atomic(&self) -> bool {
         !self.ops.iter().any(|op| {
             matches!(
@@ -240,6 +347,299 @@ impl Batch {
             _ => None,
         })
     }
+
+    // Removes exact duplicates: value sets repeating the previous set of the
+    // same key with the same value, and index changes repeating the previous
+    // change to the same key. Clears, asserts, appends and counter updates are
+    // never removed, and a duplicate following any of them on the same key is
+    // kept. Batches built incrementally can call this before being written.
+    pub fn dedup(&mut self) {
+        let mut account_id = u32::MAX;
+        let mut collection = u8::MAX;
+        let mut document_id = u64::MAX;
+        let mut created_documents = 0;
+        let mut values: AHashMap<(u32, u8, u64, &ValueClass<MaybeDynamicId>), Option<&[u8]>> =
+            AHashMap::new();
+        let mut indexes: AHashMap<(u32, u8, u64, u8, &[u8]), bool> = AHashMap::new();
+        let mut remove = vec![false; self.ops.len()];
+        let mut has_duplicates = false;
+
+        for (pos, op) in self.ops.iter().enumerate() {
+            match op {
+                Operation::AccountId {
+                    account_id: account_id_,
+                } => {
+                    account_id = *account_id_;
+                }
+                Operation::Collection {
+                    collection: collection_,
+                } => {
+                    collection = *collection_;
+                }
+                Operation::DocumentId {
+                    document_id: document_id_,
+                } => {
+                    // Each document being created has its own context
+                    document_id = if *document_id_ != u32::MAX {
+                        *document_id_ as u64
+                    } else {
+                        created_documents += 1;
+                        u64::MAX - created_documents
+                    };
+                }
+                Operation::AssertValue { class, .. } => {
+                    values.insert((account_id, collection, document_id, class), None);
+                }
+                Operation::Value { class, op } => {
+                    let value = match op {
+                        ValueOp::Set(MaybeDynamicValue::Static(value)) => Some(value.as_slice()),
+                        _ => None,
+                    };
+                    if values.insert((account_id, collection, document_id, class), value)
+                        == Some(value)
+                        && value.is_some()
+                    {
+                        remove[pos] = true;
+                        has_duplicates = true;
+                    }
+                }
+                Operation::Index { field, key, set } => {
+                    if indexes.insert((account_id, collection, document_id, *field, key), *set)
+                        == Some(*set)
+                    {
+                        remove[pos] = true;
+                        has_duplicates = true;
+                    }
+                }
+                Operation::ChangeId { .. }
+                | Operation::SetMany { .. }
+                | Operation::Bitmap { .. }
+                | Operation::Log { .. }
+                | Operation::LogWithTimestamp { .. } => {}
+            }
+        }
+
+        if has_duplicates {
+            let mut remove = remove.into_iter();
+            self.ops.retain(|_| !remove.next().unwrap());
+        }
+    }
+
+    // Fails if a value being set exceeds the maximum size configured for its subspace.
+    // Dynamic values are skipped as they are only resolved by the backend.
+    pub fn check_value_size(&self, max_value_size: &AHashMap<u8, usize>) -> trc::Result<()> {
+        let mut collection = 0;
+
+        for op in &self.ops {
+            match op {
+                Operation::Collection {
+                    collection: collection_,
+                } => {
+                    collection = *collection_;
+                }
+                Operation::Value {
+                    class,
+                    op: ValueOp::Set(MaybeDynamicValue::Static(value)),
+                } => {
+                    let subspace = class.subspace(collection);
+                    if let Some(limit) = max_value_size
+                        .get(&subspace)
+                        .filter(|limit| value.len() > **limit)
+                    {
+                        return Err(trc::StoreEvent::ValueTooLarge
+                            .ctx(trc::Key::Collection, collection as u64)
+                            .ctx(trc::Key::Type, subspace_name(subspace))
+                            .ctx(trc::Key::Size, value.len())
+                            .ctx(trc::Key::Limit, *limit));
+                    }
+                }
+                Operation::SetMany { class, items } => {
+                    let subspace = class.subspace(collection);
+                    if let Some(limit) = max_value_size.get(&subspace) {
+                        for (_, value) in items {
+                            if let MaybeDynamicValue::Static(value) = value {
+                                if value.len() > *limit {
+                                    return Err(trc::StoreEvent::ValueTooLarge
+                                        .ctx(trc::Key::Collection, collection as u64)
+                                        .ctx(trc::Key::Type, subspace_name(subspace))
+                                        .ctx(trc::Key::Size, value.len())
+                                        .ctx(trc::Key::Limit, *limit));
+                                }
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    // Approximate number of bytes written by this batch (keys plus values)
+    pub fn estimated_size(&self) -> usize {
+        self.ops.iter().map(Operation::estimated_size).sum()
+    }
+
+    // Splits the batch before the first operation that would exceed max_bytes.
+    // The second batch starts with the account, collection, document and change ids
+    // in effect at the split point. Documents being created are never split, and
+    // the first batch always contains at least one operation.
+    pub fn split_at(mut self, max_bytes: usize) -> (Batch, Batch) {
+        let mut size = 0;
+        let mut split_pos = None;
+        let mut has_data = false;
+        let mut create_pos = None;
+
+        for (pos, op) in self.ops.iter().enumerate() {
+            match op {
+                Operation::DocumentId { document_id } => {
+                    create_pos = (*document_id == u32::MAX).then_some(pos);
+                }
+                Operation::AccountId { .. } | Operation::Collection { .. } => {
+                    create_pos = None;
+                }
+                Operation::ChangeId { .. } => {}
+                _ => {
+                    size += op.estimated_size();
+                    if size > max_bytes && has_data {
+                        split_pos = Some(pos);
+                        break;
+                    }
+                    has_data = true;
+                }
+            }
+        }
+
+        let Some(mut split_pos) = split_pos else {
+            return (
+                self,
+                Batch {
+                    ops: Vec::new(),
+                    span_id: None,
+                    timeout: None,
+                },
+            );
+        };
+
+        // Do not split a document that is being created
+        if let Some(create_pos) = create_pos {
+            if self.ops[..create_pos].iter().any(Operation::is_data) {
+                split_pos = create_pos;
+            } else if let Some(next_pos) = self.ops[split_pos..].iter().position(|op| {
+                matches!(
+                    op,
+                    Operation::AccountId { .. }
+                        | Operation::Collection { .. }
+                        | Operation::DocumentId { .. }
+                )
+            }) {
+                split_pos += next_pos;
+            } else {
+                return (
+                    self,
+                    Batch {
+                        ops: Vec::new(),
+                        span_id: None,
+                        timeout: None,
+                    },
+                );
+            }
+        }
+
+        // Carry over the current context
+        let mut ops = Vec::with_capacity(self.ops.len() - split_pos + 4);
+        let mut account_id = None;
+        let mut collection = None;
+        let mut document_id = None;
+        let mut change_id = None;
+        for op in &self.ops[..split_pos] {
+            match op {
+                Operation::AccountId { account_id: id } => account_id = Some(*id),
+                Operation::Collection { collection: id } => collection = Some(*id),
+                Operation::DocumentId { document_id: id } => document_id = Some(*id),
+                Operation::ChangeId { change_id: id } => change_id = Some(*id),
+                _ => {}
+            }
+        }
+        if let Some(change_id) = change_id {
+            ops.push(Operation::ChangeId { change_id });
+        }
+        if let Some(account_id) = account_id {
+            ops.push(Operation::AccountId { account_id });
+        }
+        if let Some(collection) = collection {
+            ops.push(Operation::Collection { collection });
+        }
+        if let Some(document_id) = document_id {
+            ops.push(Operation::DocumentId { document_id });
+        }
+        ops.extend(self.ops.drain(split_pos..));
+
+        let span_id = self.span_id;
+        let timeout = self.timeout;
+        (
+            self,
+            Batch {
+                ops,
+                span_id,
+                timeout,
+            },
+        )
+    }
+}
+
+impl Operation {
+    // Approximate size of the keys and values written by this operation
+    pub fn estimated_size(&self) -> usize {
+        match self {
+            Operation::AccountId { .. }
+            | Operation::Collection { .. }
+            | Operation::DocumentId { .. }
+            | Operation::ChangeId { .. } => 0,
+            Operation::AssertValue { class, .. } => class.serialized_size(),
+            Operation::Value { class, op } => {
+                class.serialized_size()
+                    + match op {
+                        ValueOp::Set(value) | ValueOp::Append { value, .. } => {
+                            value.estimated_size()
+                        }
+                        ValueOp::AtomicAdd(_) | ValueOp::AddAndGet(_) => U64_LEN,
+                        ValueOp::Clear => 0,
+                    }
+            }
+            Operation::SetMany { class, items } => items
+                .iter()
+                .map(|(_, value)| class.serialized_size() + value.estimated_size())
+                .sum(),
+            Operation::Index { key, .. } => key.len() + U32_LEN * 2 + 2,
+            Operation::Bitmap { class, .. } => class.serialized_size(),
+            Operation::Log { set } => U32_LEN + U64_LEN + 1 + set.estimated_size(),
+            Operation::LogWithTimestamp { set, .. } => {
+                U32_LEN + (U64_LEN * 2) + 1 + set.estimated_size()
+            }
+        }
+    }
+
+    fn is_data(&self) -> bool {
+        !matches!(
+            self,
+            Operation::AccountId { .. }
+                | Operation::Collection { .. }
+                | Operation::DocumentId { .. }
+                | Operation::ChangeId { .. }
+        )
+    }
+}
+
+impl MaybeDynamicValue {
+    pub fn estimated_size(&self) -> usize {
+        match self {
+            MaybeDynamicValue::Static(value) => value.len(),
+            // Dynamic values are only serialized at commit time
+            MaybeDynamicValue::Dynamic(_) => U64_LEN,
+        }
+    }
 }
 
 impl Default for BatchBuilder {
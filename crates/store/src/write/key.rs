@@ -8,13 +8,14 @@ use std::convert::TryInto;
 use utils::{codec::leb128::Leb128_, BLOB_HASH_LEN};
 
 use crate::{
-    BitmapKey, Deserialize, IndexKey, IndexKeyPrefix, Key, LogKey, ValueKey, SUBSPACE_ACL,
-    SUBSPACE_BITMAP_ID, SUBSPACE_BITMAP_TAG, SUBSPACE_BITMAP_TEXT, SUBSPACE_BLOB_LINK,
-    SUBSPACE_BLOB_RESERVE, SUBSPACE_COUNTER, SUBSPACE_DIRECTORY, SUBSPACE_FTS_INDEX,
-    SUBSPACE_FTS_QUEUE, SUBSPACE_INDEXES, SUBSPACE_LOGS, SUBSPACE_LOOKUP_VALUE, SUBSPACE_PROPERTY,
-    SUBSPACE_QUEUE_EVENT, SUBSPACE_QUEUE_MESSAGE, SUBSPACE_QUOTA, SUBSPACE_REPORT_IN,
-    SUBSPACE_REPORT_OUT, SUBSPACE_SETTINGS, SUBSPACE_TELEMETRY_INDEX, SUBSPACE_TELEMETRY_METRIC,
-    SUBSPACE_TELEMETRY_SPAN, U32_LEN, U64_LEN, WITH_SUBSPACE,
+    BitmapKey, Deserialize, IndexKey, IndexKeyPrefix, Key, LogKey, RecentKey, ValueKey,
+    COUNTER_COLLECTION, COUNTER_PROPERTY, SUBSPACE_ACL, SUBSPACE_AUDIT, SUBSPACE_BITMAP_ID,
+    SUBSPACE_BITMAP_TAG, SUBSPACE_BITMAP_TEXT, SUBSPACE_BLOB_LINK, SUBSPACE_BLOB_RESERVE,
+    SUBSPACE_COUNTER, SUBSPACE_DIRECTORY, SUBSPACE_FTS_INDEX, SUBSPACE_FTS_QUEUE, SUBSPACE_INDEXES,
+    SUBSPACE_LOGS, SUBSPACE_LOOKUP_VALUE, SUBSPACE_PROPERTY, SUBSPACE_QUARANTINE,
+    SUBSPACE_QUEUE_EVENT, SUBSPACE_QUEUE_MESSAGE, SUBSPACE_QUOTA, SUBSPACE_RECENT,
+    SUBSPACE_REPORT_IN, SUBSPACE_REPORT_OUT, SUBSPACE_SETTINGS, SUBSPACE_TELEMETRY_INDEX,
+    SUBSPACE_TELEMETRY_METRIC, SUBSPACE_TELEMETRY_SPAN, U32_LEN, U64_LEN, WITH_SUBSPACE,
 };
 
 use super::{
@@ -189,6 +190,15 @@ impl IndexKeyPrefix {
     }
 }
 
+impl LogKey {
+    // Timestamped entries have the commit time appended after the change id
+    pub fn serialize_with_timestamp(&self, flags: u32, timestamp: u64) -> Vec<u8> {
+        let mut key = self.serialize(flags);
+        key.extend_from_slice(&timestamp.to_be_bytes());
+        key
+    }
+}
+
 impl Key for LogKey {
     fn subspace(&self) -> u8 {
         SUBSPACE_LOGS
@@ -209,6 +219,28 @@ impl Key for LogKey {
     }
 }
 
+impl Key for RecentKey {
+    fn subspace(&self) -> u8 {
+        SUBSPACE_RECENT
+    }
+
+    fn serialize(&self, flags: u32) -> Vec<u8> {
+        {
+            if (flags & WITH_SUBSPACE) != 0 {
+                KeySerializer::new(std::mem::size_of::<RecentKey>() + 1)
+                    .write(crate::SUBSPACE_RECENT)
+            } else {
+                KeySerializer::new(std::mem::size_of::<RecentKey>())
+            }
+        }
+        .write(self.account_id)
+        .write(self.collection)
+        .write(self.timestamp)
+        .write(self.document_id)
+        .finalize()
+    }
+}
+
 impl<T: AsRef<ValueClass<u32>> + Sync + Send + Clone> Key for ValueKey<T> {
     fn subspace(&self) -> u8 {
         self.class.as_ref().subspace(self.collection)
@@ -223,6 +255,12 @@ impl<T: AsRef<ValueClass<u32>> + Sync + Send + Clone> Key for ValueKey<T> {
             None,
         )
     }
+
+    fn owner_account_id(&self) -> Option<u32> {
+        self.class
+            .as_ref()
+            .owner_account_id(self.account_id, self.collection)
+    }
 }
 
 impl<T: ResolveId> ValueClass<T> {
@@ -353,6 +391,20 @@ impl<T: ResolveId> ValueClass<T> {
                     .write(event.seq_id),
                 QueueClass::QuotaCount(key) => serializer.write(0u8).write(key.as_slice()),
                 QueueClass::QuotaSize(key) => serializer.write(1u8).write(key.as_slice()),
+                QueueClass::Quarantine { account_id, id } => {
+                    serializer.write(*account_id).write(*id)
+                }
+                QueueClass::ListDigest(id) => serializer.write(3u8).write(*id),
+                QueueClass::EmailSnooze {
+                    due,
+                    account_id,
+                    document_id,
+                } => serializer
+                    .write(4u8)
+                    .write(*due)
+                    .write(*account_id)
+                    .write(*document_id),
+                QueueClass::DkimKey(key) => serializer.write(5u8).write(key.as_slice()),
             },
             ValueClass::Report(report) => match report {
                 ReportClass::Tls { id, expires } => {
@@ -378,6 +430,7 @@ impl<T: ResolveId> ValueClass<T> {
                     .write(*timestamp)
                     .write_leb128(*metric_id)
                     .write_leb128(*node_id),
+                TelemetryClass::Audit { timestamp, id } => serializer.write(*timestamp).write(*id),
             },
             ValueClass::Any(any) => serializer.write(any.key.as_slice()),
         }
@@ -426,6 +479,19 @@ impl<T: AsRef<BitmapClass<u32>> + Sync + Send + Clone> Key for BitmapKey<T> {
 }
 
 impl<T: ResolveId> BitmapClass<T> {
+    pub fn serialized_size(&self) -> usize {
+        match self {
+            BitmapClass::DocumentIds => U32_LEN * 2 + 1,
+            BitmapClass::Tag { value, .. } => match value {
+                TagValue::Id(_) => U32_LEN * 3 + 2,
+                TagValue::Text(text) => U32_LEN * 2 + 2 + text.len(),
+            },
+            BitmapClass::Text { token, .. } => {
+                U32_LEN * 2 + 2 + std::cmp::min(token.len as usize, 8) + usize::from(token.len >= 8)
+            }
+        }
+    }
+
     pub fn subspace(&self) -> u8 {
         match self {
             BitmapClass::DocumentIds => SUBSPACE_BITMAP_ID,
@@ -553,12 +619,17 @@ impl<T> ValueClass<T> {
                     event.domain.len() + (U64_LEN * 3) + 1
                 }
                 QueueClass::QuotaCount(v) | QueueClass::QuotaSize(v) => v.len(),
+                QueueClass::Quarantine { .. } => U32_LEN + U64_LEN,
+                QueueClass::ListDigest(_) => U64_LEN + 1,
+                QueueClass::EmailSnooze { .. } => U64_LEN + (U32_LEN * 2) + 1,
+                QueueClass::DkimKey(key) => key.len() + 1,
             },
             ValueClass::Report(_) => U64_LEN * 2 + 1,
             ValueClass::Telemetry(telemetry) => match telemetry {
                 TelemetryClass::Span { .. } => U64_LEN + 1,
                 TelemetryClass::Index { value, .. } => U64_LEN + value.len() + 1,
                 TelemetryClass::Metric { .. } => U64_LEN * 2 + 1,
+                TelemetryClass::Audit { .. } => U64_LEN * 2,
             },
             ValueClass::Any(v) => v.key.len(),
         }
@@ -567,7 +638,7 @@ impl<T> ValueClass<T> {
     pub fn subspace(&self, collection: u8) -> u8 {
         match self {
             ValueClass::Property(field) => {
-                if *field == 84 && collection == 1 {
+                if *field == COUNTER_PROPERTY && collection == COUNTER_COLLECTION {
                     SUBSPACE_COUNTER
                 } else {
                     SUBSPACE_PROPERTY
@@ -597,14 +668,19 @@ impl<T> ValueClass<T> {
                 QueueClass::DmarcReportHeader(_)
                 | QueueClass::TlsReportHeader(_)
                 | QueueClass::DmarcReportEvent(_)
-                | QueueClass::TlsReportEvent(_) => SUBSPACE_REPORT_OUT,
+                | QueueClass::TlsReportEvent(_)
+                | QueueClass::ListDigest(_)
+                | QueueClass::EmailSnooze { .. }
+                | QueueClass::DkimKey(_) => SUBSPACE_REPORT_OUT,
                 QueueClass::QuotaCount(_) | QueueClass::QuotaSize(_) => SUBSPACE_QUOTA,
+                QueueClass::Quarantine { .. } => SUBSPACE_QUARANTINE,
             },
             ValueClass::Report(_) => SUBSPACE_REPORT_IN,
             ValueClass::Telemetry(telemetry) => match telemetry {
                 TelemetryClass::Span { .. } => SUBSPACE_TELEMETRY_SPAN,
                 TelemetryClass::Index { .. } => SUBSPACE_TELEMETRY_INDEX,
                 TelemetryClass::Metric { .. } => SUBSPACE_TELEMETRY_METRIC,
+                TelemetryClass::Audit { .. } => SUBSPACE_AUDIT,
             },
             ValueClass::Any(any) => any.subspace,
         }
@@ -615,10 +691,22 @@ impl<T> ValueClass<T> {
             ValueClass::Directory(DirectoryClass::UsedQuota(_))
             | ValueClass::Lookup(LookupClass::Counter(_))
             | ValueClass::Queue(QueueClass::QuotaCount(_) | QueueClass::QuotaSize(_)) => true,
-            ValueClass::Property(84) if collection == 1 => true, // TODO: Find a more elegant way to do this
+            ValueClass::Property(COUNTER_PROPERTY) if collection == COUNTER_COLLECTION => true,
             _ => false,
         }
     }
+
+    // Quotas and per-document counters belong to an account but are not
+    // stored under the account id prefix
+    pub fn owner_account_id(&self, account_id: u32, collection: u8) -> Option<u32> {
+        match self {
+            ValueClass::Directory(DirectoryClass::UsedQuota(owner_id)) => Some(*owner_id),
+            ValueClass::Property(COUNTER_PROPERTY) if collection == COUNTER_COLLECTION => {
+                Some(account_id)
+            }
+            _ => None,
+        }
+    }
 }
 
 impl From<ValueClass<u32>> for ValueKey<ValueClass<u32>> {
@@ -62,14 +62,49 @@ pub struct AssignedIds {
 }
 
 #[cfg(not(feature = "test_mode"))]
-pub(crate) const MAX_COMMIT_ATTEMPTS: u32 = 10;
+pub(crate) const DEFAULT_COMMIT_ATTEMPTS: u32 = 10;
 #[cfg(not(feature = "test_mode"))]
-pub(crate) const MAX_COMMIT_TIME: Duration = Duration::from_secs(10);
+pub(crate) const DEFAULT_COMMIT_TIME: Duration = Duration::from_secs(10);
 
 #[cfg(feature = "test_mode")]
-pub(crate) const MAX_COMMIT_ATTEMPTS: u32 = 1000;
+pub(crate) const DEFAULT_COMMIT_ATTEMPTS: u32 = 1000;
 #[cfg(feature = "test_mode")]
-pub(crate) const MAX_COMMIT_TIME: Duration = Duration::from_secs(3600);
+pub(crate) const DEFAULT_COMMIT_TIME: Duration = Duration::from_secs(3600);
+
+// Shortest and longest delay between two attempts of a conflicting transaction
+pub const MIN_COMMIT_BACKOFF: Duration = Duration::from_millis(50);
+pub const MAX_COMMIT_BACKOFF: Duration = Duration::from_millis(300);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommitLimits {
+    pub max_attempts: u32,
+    pub max_time: Duration,
+}
+
+impl Default for CommitLimits {
+    fn default() -> Self {
+        CommitLimits {
+            max_attempts: DEFAULT_COMMIT_ATTEMPTS,
+            max_time: DEFAULT_COMMIT_TIME,
+        }
+    }
+}
+
+// Number of commits retried after a conflict, used by the tests to compare
+// conflict rates
+#[cfg(feature = "test_mode")]
+pub static COMMIT_RETRIES: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+// Random delay before retrying a conflicting transaction
+pub fn commit_backoff() -> Duration {
+    #[cfg(feature = "test_mode")]
+    COMMIT_RETRIES.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+
+    rand::thread_rng().gen_range(MIN_COMMIT_BACKOFF..=MAX_COMMIT_BACKOFF)
+}
+
+// Rows written by a single statement of a SetMany operation
+pub(crate) const MAX_SET_MANY_ROWS: usize = 1000;
 
 pub const F_VALUE: u32 = 1 << 0;
 pub const F_INDEX: u32 = 1 << 1;
@@ -79,11 +114,15 @@ pub const F_CLEAR: u32 = 1 << 3;
 #[derive(Debug)]
 pub struct Batch {
     pub ops: Vec<Operation>,
+    pub span_id: Option<u64>,
+    // Commit deadline, used instead of the store's when shorter
+    pub timeout: Option<Duration>,
 }
 
 #[derive(Debug)]
 pub struct BatchBuilder {
     pub ops: Vec<Operation>,
+    pub span_id: Option<u64>,
 }
 
 #[derive(Debug, PartialEq, Eq, Hash)]
@@ -108,6 +147,10 @@ pub enum Operation {
         class: ValueClass<MaybeDynamicId>,
         op: ValueOp,
     },
+    SetMany {
+        class: ValueClass<MaybeDynamicId>,
+        items: Vec<(u32, MaybeDynamicValue)>,
+    },
     Index {
         field: u8,
         key: Vec<u8>,
@@ -120,6 +163,10 @@ pub enum Operation {
     Log {
         set: MaybeDynamicValue,
     },
+    LogWithTimestamp {
+        timestamp: u64,
+        set: MaybeDynamicValue,
+    },
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -195,6 +242,17 @@ pub enum QueueClass {
     TlsReportEvent(ReportEvent),
     QuotaCount(Vec<u8>),
     QuotaSize(Vec<u8>),
+    Quarantine {
+        account_id: u32,
+        id: u64,
+    },
+    ListDigest(u64),
+    EmailSnooze {
+        due: u64,
+        account_id: u32,
+        document_id: u32,
+    },
+    DkimKey(Vec<u8>),
 }
 
 #[derive(Debug, PartialEq, Clone, Eq, Hash)]
@@ -218,6 +276,10 @@ pub enum TelemetryClass {
         span_id: u64,
         value: Vec<u8>,
     },
+    Audit {
+        timestamp: u64,
+        id: u64,
+    },
 }
 
 #[derive(Debug, PartialEq, Clone, Eq, Hash)]
@@ -237,6 +299,11 @@ pub struct ReportEvent {
 #[derive(Debug, PartialEq, Eq, Hash, Default)]
 pub enum ValueOp {
     Set(MaybeDynamicValue),
+    // Appends to the current value, failing if the result exceeds max_length
+    Append {
+        value: MaybeDynamicValue,
+        max_length: usize,
+    },
     AtomicAdd(i64),
     AddAndGet(i64),
     #[default]
@@ -767,6 +834,26 @@ impl AssignedIds {
                 .ctx(trc::Key::Reason, "No document ids were created")
         })
     }
+
+    pub fn iter_document_ids(&self) -> impl Iterator<Item = u32> + '_ {
+        self.document_ids.iter().copied()
+    }
+
+    pub fn iter_counter_ids(&self) -> impl Iterator<Item = i64> + '_ {
+        self.counter_ids.iter().copied()
+    }
+
+    pub fn len_document_ids(&self) -> usize {
+        self.document_ids.len()
+    }
+
+    pub fn len_counter_ids(&self) -> usize {
+        self.counter_ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.document_ids.is_empty() && self.counter_ids.is_empty()
+    }
 }
 
 impl From<String> for MaybeDynamicValue {
@@ -870,26 +957,62 @@ impl SerializeWithId for DynamicDocumentId {
     }
 }
 
-pub(crate) trait RandomAvailableId {
+const ID_SHARDS: u32 = 16;
+const ID_PADDING: u32 = 100;
+
+// Every id pick starts a new generation, consecutive writers start scanning
+// from different shards no matter which thread or task they run on
+static ID_GENERATION: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(0);
+
+pub trait RandomAvailableId {
     fn random_available_id(&self) -> u32;
 }
 
 impl RandomAvailableId for RoaringBitmap {
     fn random_available_id(&self) -> u32 {
-        let mut last_id = 0;
-        let mut available_ids = Vec::with_capacity(100);
-        for id in self.iter() {
-            for i in last_id..id {
-                available_ids.push(i);
+        let Some(next_id) = self.max().map_or(Some(0), |id| id.checked_add(1)) else {
+            // The last id is taken, pick the first gap instead
+            return (0..=u32::MAX)
+                .zip(self.iter())
+                .find_map(|(expected_id, id)| (expected_id != id).then_some(expected_id))
+                .unwrap_or(u32::MAX);
+        };
+
+        // The id space is divided into shards and each pick starts scanning
+        // from the shard of its generation, which reduces the chances of two
+        // concurrent transactions picking the same id
+        let id_end = next_id.saturating_add(ID_PADDING);
+        let shard_len = id_end.div_ceil(ID_SHARDS);
+        let shard_start =
+            ID_GENERATION.fetch_add(1, std::sync::atomic::Ordering::Relaxed) % ID_SHARDS;
+
+        for shard in (0..ID_SHARDS).map(|shard| (shard_start + shard) % ID_SHARDS) {
+            let from_id = shard.saturating_mul(shard_len).min(id_end);
+            let to_id = from_id.saturating_add(shard_len).min(id_end);
+            let available_ids = (to_id.saturating_sub(from_id) as u64)
+                .saturating_sub(self.range_cardinality(from_id..to_id));
+            if available_ids == 0 {
+                continue;
             }
-            last_id = id + 1;
-        }
 
-        while available_ids.len() < 100 {
-            available_ids.push(last_id);
-            last_id += 1;
+            // Walk the gaps between used ids until reaching the chosen free id
+            let mut nth = rand::thread_rng().gen_range(0..available_ids) as u32;
+            let mut gap_start = from_id;
+            for used_id in self
+                .iter()
+                .skip_while(|id| *id < from_id)
+                .take_while(|id| *id < to_id)
+                .chain([to_id])
+            {
+                let gap_len = used_id - gap_start;
+                if nth < gap_len {
+                    return gap_start + nth;
+                }
+                nth -= gap_len;
+                gap_start = used_id + 1;
+            }
         }
 
-        available_ids[rand::thread_rng().gen_range(0..available_ids.len())]
+        next_id
     }
 }
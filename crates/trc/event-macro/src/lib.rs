@@ -373,6 +373,10 @@ impl IsMetricKey for Ident {
                 | "SpfNone"
                 | "Protocol"
                 | "Code"
+                | "SpanId"
+                | "ListenerId"
+                | "Domain"
+                | "Mechanism"
         )
     }
 }
@@ -0,0 +1,166 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use parking_lot::Mutex;
+
+use crate::MetricType;
+
+use super::histogram::AtomicHistogram;
+
+// Series that did not fit under the label limit are aggregated here
+pub const OVERFLOW_LABEL: &str = "_other";
+
+pub struct LabelLimit(AtomicUsize);
+
+pub struct LabeledCounter {
+    name: &'static str,
+    description: &'static str,
+    label: &'static str,
+    limit: &'static LabelLimit,
+    values: Mutex<Vec<(String, u64)>>,
+}
+
+pub struct LabeledHistogram {
+    name: &'static str,
+    description: &'static str,
+    label: &'static str,
+    limit: &'static LabelLimit,
+    values: Mutex<Vec<(String, AtomicHistogram<12>)>>,
+}
+
+impl LabelLimit {
+    pub const fn new(limit: usize) -> Self {
+        Self(AtomicUsize::new(limit))
+    }
+
+    pub fn get(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub fn set(&self, limit: usize) {
+        self.0.store(limit, Ordering::Relaxed);
+    }
+}
+
+impl LabeledCounter {
+    pub const fn new(
+        name: &'static str,
+        description: &'static str,
+        label: &'static str,
+        limit: &'static LabelLimit,
+    ) -> Self {
+        Self {
+            name,
+            description,
+            label,
+            limit,
+            values: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn increment(&self, label: &str) {
+        let mut values = self.values.lock();
+        if let Some((_, value)) = values.iter_mut().find(|(l, _)| l == label) {
+            *value += 1;
+            return;
+        }
+
+        // Counter series cannot be merged after the fact, so labels are admitted
+        // on a first come basis until the limit is reached.
+        let label = if values.len() < self.limit.get() {
+            label
+        } else if let Some((_, value)) = values.iter_mut().find(|(l, _)| l == OVERFLOW_LABEL) {
+            *value += 1;
+            return;
+        } else {
+            OVERFLOW_LABEL
+        };
+        values.push((label.to_string(), 1));
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn description(&self) -> &'static str {
+        self.description
+    }
+
+    pub fn label(&self) -> &'static str {
+        self.label
+    }
+
+    pub fn values(&self) -> Vec<(String, u64)> {
+        let mut values = self.values.lock().clone();
+        values.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        values
+    }
+
+    pub fn clear(&self) {
+        self.values.lock().clear();
+    }
+}
+
+impl LabeledHistogram {
+    pub const fn new(
+        name: &'static str,
+        description: &'static str,
+        label: &'static str,
+        limit: &'static LabelLimit,
+    ) -> Self {
+        Self {
+            name,
+            description,
+            label,
+            limit,
+            values: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn observe(&self, label: &str, value: u64) {
+        let mut values = self.values.lock();
+        if let Some((_, histogram)) = values.iter().find(|(l, _)| l == label) {
+            histogram.observe(value);
+            return;
+        }
+
+        let label = if values.len() < self.limit.get() {
+            label
+        } else if let Some((_, histogram)) = values.iter().find(|(l, _)| l == OVERFLOW_LABEL) {
+            histogram.observe(value);
+            return;
+        } else {
+            OVERFLOW_LABEL
+        };
+        let histogram = AtomicHistogram::<10>::new_short_durations(MetricType::SmtpRequestTime);
+        histogram.observe(value);
+        values.push((label.to_string(), histogram));
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn description(&self) -> &'static str {
+        self.description
+    }
+
+    pub fn label(&self) -> &'static str {
+        self.label
+    }
+
+    pub fn for_each(&self, mut f: impl FnMut(&str, &AtomicHistogram<12>)) {
+        for (label, histogram) in self.values.lock().iter() {
+            f(label, histogram);
+        }
+    }
+
+    pub fn clear(&self) {
+        self.values.lock().clear();
+    }
+}
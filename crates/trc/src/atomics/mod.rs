@@ -9,3 +9,4 @@ pub mod bitset;
 pub mod counter;
 pub mod gauge;
 pub mod histogram;
+pub mod labeled;
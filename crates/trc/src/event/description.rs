@@ -146,6 +146,7 @@ impl ClusterEvent {
             ClusterEvent::InvalidPacket => "Received an invalid gossip packet",
             ClusterEvent::DecryptionError => "Failed to decrypt a gossip packet",
             ClusterEvent::Error => "A cluster error occurred",
+            ClusterEvent::Resync => "Cluster state resynchronized",
         }
     }
 
@@ -164,6 +165,9 @@ impl ClusterEvent {
             ClusterEvent::InvalidPacket => "Received an invalid gossip packet",
             ClusterEvent::DecryptionError => "Failed to decrypt a gossip packet",
             ClusterEvent::Error => "An error occurred in the cluster",
+            ClusterEvent::Resync => {
+                "Events from other nodes were missed and cached state was refreshed"
+            }
         }
     }
 }
@@ -177,6 +181,9 @@ impl HousekeeperEvent {
             HousekeeperEvent::PurgeAccounts => "Purging accounts",
             HousekeeperEvent::PurgeSessions => "Purging sessions",
             HousekeeperEvent::PurgeStore => "Purging store",
+            HousekeeperEvent::ArchiveAccounts => "Archiving accounts",
+            HousekeeperEvent::ArchiveMessages => "Messages archived",
+            HousekeeperEvent::WakeSnoozed => "Snoozed messages woken",
         }
     }
 
@@ -188,6 +195,15 @@ impl HousekeeperEvent {
             HousekeeperEvent::PurgeAccounts => "Purging accounts",
             HousekeeperEvent::PurgeSessions => "Purging sessions",
             HousekeeperEvent::PurgeStore => "Purging store",
+            HousekeeperEvent::ArchiveAccounts => {
+                "Moving old messages to the yearly archive folders"
+            }
+            HousekeeperEvent::ArchiveMessages => {
+                "Messages older than the archive period have been moved to the archive folders"
+            }
+            HousekeeperEvent::WakeSnoozed => {
+                "Snoozed messages have been moved back to their mailboxes"
+            }
         }
     }
 }
@@ -248,6 +264,7 @@ impl ImapEvent {
             ImapEvent::Subscribe => "IMAP SUBSCRIBE command",
             ImapEvent::Unsubscribe => "IMAP UNSUBSCRIBE command",
             ImapEvent::Thread => "IMAP THREAD command",
+            ImapEvent::XApplePushService => "IMAP XAPPLEPUSHSERVICE command",
             ImapEvent::Error => "IMAP error occurred",
             ImapEvent::RawInput => "Raw IMAP input received",
             ImapEvent::RawOutput => "Raw IMAP output sent",
@@ -289,6 +306,7 @@ impl ImapEvent {
             ImapEvent::Subscribe => "Client subscribed to a mailbox",
             ImapEvent::Unsubscribe => "Client unsubscribed from a mailbox",
             ImapEvent::Thread => "Client requested message threads",
+            ImapEvent::XApplePushService => "Client registered a device for push notifications",
             ImapEvent::Error => "An error occurred during an IMAP command",
             ImapEvent::RawInput => "Raw IMAP input received",
             ImapEvent::RawOutput => "Raw IMAP output sent",
@@ -415,6 +433,8 @@ impl SmtpEvent {
             SmtpEvent::DkimFail => "DKIM verification failed",
             SmtpEvent::ArcPass => "ARC verification passed",
             SmtpEvent::ArcFail => "ARC verification failed",
+            SmtpEvent::AuthResultsImported => "Authentication results imported",
+            SmtpEvent::AuthResultsStripped => "Untrusted authentication results removed",
             SmtpEvent::SpfEhloPass => "SPF EHLO check passed",
             SmtpEvent::SpfEhloFail => "SPF EHLO check failed",
             SmtpEvent::SpfFromPass => "SPF From check passed",
@@ -438,6 +458,7 @@ impl SmtpEvent {
             SmtpEvent::MultipleMailFrom => "Multiple MAIL FROM commands",
             SmtpEvent::MailboxDoesNotExist => "Mailbox does not exist",
             SmtpEvent::RelayNotAllowed => "Relay not allowed",
+            SmtpEvent::RcptToGreylisted => "RCPT TO greylisted",
             SmtpEvent::RcptTo => "SMTP RCPT TO command",
             SmtpEvent::RcptToDuplicate => "Duplicate RCPT TO",
             SmtpEvent::RcptToRewritten => "RCPT TO address rewritten",
@@ -509,6 +530,12 @@ impl SmtpEvent {
             SmtpEvent::DkimFail => "Failed to verify DKIM signature",
             SmtpEvent::ArcPass => "Successful ARC verification",
             SmtpEvent::ArcFail => "Failed to verify ARC signature",
+            SmtpEvent::AuthResultsImported => {
+                "Authentication results were imported from a trusted forwarder"
+            }
+            SmtpEvent::AuthResultsStripped => {
+                "Authentication-Results headers claiming a trusted authserv-id were removed"
+            }
             SmtpEvent::SpfEhloPass => "EHLO identity passed SPF check",
             SmtpEvent::SpfEhloFail => "EHLO identity failed SPF check",
             SmtpEvent::SpfFromPass => "MAIL FROM identity passed SPF check",
@@ -546,6 +573,7 @@ impl SmtpEvent {
             SmtpEvent::MultipleMailFrom => "The remote client already sent a MAIL FROM command",
             SmtpEvent::MailboxDoesNotExist => "The mailbox does not exist on the server",
             SmtpEvent::RelayNotAllowed => "The server does not allow relaying",
+            SmtpEvent::RcptToGreylisted => "The recipient was temporarily rejected by greylisting",
             SmtpEvent::RcptTo => "The remote client sent an RCPT TO command",
             SmtpEvent::RcptToDuplicate => {
                 "The remote client already sent an RCPT TO command for this recipient"
@@ -618,6 +646,10 @@ impl DeliveryEvent {
             DeliveryEvent::Completed => "Delivery completed",
             DeliveryEvent::Failed => "Delivery failed",
             DeliveryEvent::DomainDeliveryStart => "New delivery attempt for domain",
+            DeliveryEvent::DomainDelivered => "Message delivered to domain",
+            DeliveryEvent::DomainDeferred => "Delivery to domain deferred",
+            DeliveryEvent::DomainBounced => "Delivery to domain failed permanently",
+            DeliveryEvent::NextHop => "Next hop selected",
             DeliveryEvent::MxLookup => "MX record lookup",
             DeliveryEvent::MxLookupFailed => "MX record lookup failed",
             DeliveryEvent::IpLookup => "IP address lookup",
@@ -625,6 +657,8 @@ impl DeliveryEvent {
             DeliveryEvent::NullMx => "Null MX record found",
             DeliveryEvent::Connect => "Connecting to remote server",
             DeliveryEvent::ConnectError => "Connection error",
+            DeliveryEvent::ConnectionReused => "Reusing pooled connection",
+            DeliveryEvent::ConnectionPooled => "Connection returned to pool",
             DeliveryEvent::MissingOutboundHostname => "Missing outbound hostname in configuration",
             DeliveryEvent::GreetingFailed => "SMTP greeting failed",
             DeliveryEvent::Ehlo => "SMTP EHLO command",
@@ -661,6 +695,14 @@ impl DeliveryEvent {
             DeliveryEvent::Completed => "Delivery was completed for all recipients",
             DeliveryEvent::Failed => "Message delivery failed due to a temporary error",
             DeliveryEvent::DomainDeliveryStart => "A new delivery attempt for a domain has started",
+            DeliveryEvent::DomainDelivered => "The message was delivered to the domain",
+            DeliveryEvent::DomainDeferred => {
+                "Delivery to the domain failed temporarily and will be retried"
+            }
+            DeliveryEvent::DomainBounced => "Delivery to the domain failed permanently",
+            DeliveryEvent::NextHop => {
+                "A routing rule matched and the message will be relayed through a configured host"
+            }
             DeliveryEvent::MxLookup => "Looking up MX records for the domain",
             DeliveryEvent::MxLookupFailed => "Failed to look up MX records for the domain",
             DeliveryEvent::IpLookup => "Looking up IP address for the domain",
@@ -668,6 +710,12 @@ impl DeliveryEvent {
             DeliveryEvent::NullMx => "The domain has a null MX record, delivery is impossible",
             DeliveryEvent::Connect => "Connecting to the remote server",
             DeliveryEvent::ConnectError => "Error connecting to the remote server",
+            DeliveryEvent::ConnectionReused => {
+                "An idle connection to the remote server was reused, skipping the handshake"
+            }
+            DeliveryEvent::ConnectionPooled => {
+                "The connection to the remote server was kept open for further deliveries"
+            }
             DeliveryEvent::MissingOutboundHostname => {
                 "The outbound hostname is missing in the configuration"
             }
@@ -722,11 +770,17 @@ impl QueueEvent {
             QueueEvent::RateLimitExceeded => "Rate limit exceeded",
             QueueEvent::ConcurrencyLimitExceeded => "Concurrency limit exceeded",
             QueueEvent::QuotaExceeded => "Quota exceeded",
+            QueueEvent::RetryStrategyNotFound => "Retry strategy not found",
             QueueEvent::QueueMessage => "Queued message for delivery",
             QueueEvent::QueueMessageAuthenticated => "Queued message submission for delivery",
             QueueEvent::QueueReport => "Queued report for delivery",
             QueueEvent::QueueDsn => "Queued DSN for delivery",
             QueueEvent::QueueAutogenerated => "Queued autogenerated message for delivery",
+            QueueEvent::ListExpanded => "Mailing list expanded",
+            QueueEvent::ListBounce => "Mailing list bounce received",
+            QueueEvent::ListMemberSuspended => "Mailing list member suspended",
+            QueueEvent::ListDigest => "Mailing list digest queued",
+            QueueEvent::TransportSelected => "Transport selected for recipients",
         }
     }
 
@@ -739,6 +793,7 @@ impl QueueEvent {
             QueueEvent::RateLimitExceeded => "The queue rate limit was exceeded",
             QueueEvent::ConcurrencyLimitExceeded => "The queue concurrency limit was exceeded",
             QueueEvent::QuotaExceeded => "The queue quota was exceeded",
+            QueueEvent::RetryStrategyNotFound => "The requested retry strategy was not found",
             QueueEvent::QueueMessage => "A new message was queued for delivery",
             QueueEvent::QueueMessageAuthenticated => {
                 "A new message was queued for delivery from an authenticated client"
@@ -746,6 +801,17 @@ impl QueueEvent {
             QueueEvent::QueueReport => "A new report was queued for delivery",
             QueueEvent::QueueDsn => "A delivery status notification was queued for delivery",
             QueueEvent::QueueAutogenerated => "A system generated message was queued for delivery",
+            QueueEvent::ListExpanded => {
+                "A message posted to a mailing list was expanded to its members"
+            }
+            QueueEvent::ListBounce => "A bounce was received for a mailing list member",
+            QueueEvent::ListMemberSuspended => {
+                "A mailing list member was suspended after too many bounces"
+            }
+            QueueEvent::ListDigest => "A mailing list digest was queued for delivery",
+            QueueEvent::TransportSelected => {
+                "The transport map routed recipients through a specific transport"
+            }
         }
     }
 }
@@ -1002,6 +1068,7 @@ impl PushSubscriptionEvent {
             PushSubscriptionEvent::Success => "Push subscription successful",
             PushSubscriptionEvent::Error => "Push subscription error",
             PushSubscriptionEvent::NotFound => "Push subscription not found",
+            PushSubscriptionEvent::Unregistered => "Push device unregistered",
         }
     }
 
@@ -1010,6 +1077,9 @@ impl PushSubscriptionEvent {
             PushSubscriptionEvent::Success => "The push subscription was successful",
             PushSubscriptionEvent::Error => "An error occurred with the push subscription",
             PushSubscriptionEvent::NotFound => "The push subscription was not found",
+            PushSubscriptionEvent::Unregistered => {
+                "The push gateway reported that the device is no longer registered"
+            }
         }
     }
 }
@@ -1020,11 +1090,21 @@ impl SpamEvent {
             SpamEvent::PyzorError => "Pyzor error",
             SpamEvent::ListUpdated => "Spam list updated",
             SpamEvent::Train => "Training spam filter",
+            SpamEvent::Untrain => "Untraining spam filter",
             SpamEvent::TrainBalance => "Balancing spam filter training data",
             SpamEvent::TrainError => "Error training spam filter",
             SpamEvent::Classify => "Classifying message for spam",
             SpamEvent::ClassifyError => "Error classifying message for spam",
             SpamEvent::NotEnoughTrainingData => "Not enough training data for spam filter",
+            SpamEvent::Rspamd => "Rspamd verdict",
+            SpamEvent::RspamdError => "Rspamd error",
+            SpamEvent::AttachmentScan => "Attachments inspected",
+            SpamEvent::AttachmentPolicy => "Attachment policy matched",
+            SpamEvent::ClamAv => "ClamAV verdict",
+            SpamEvent::ClamAvError => "ClamAV error",
+            SpamEvent::Quarantine => "Message quarantined",
+            SpamEvent::QuarantineRelease => "Quarantined message released",
+            SpamEvent::QuarantineError => "Quarantine error",
         }
     }
 
@@ -1033,6 +1113,7 @@ impl SpamEvent {
             SpamEvent::PyzorError => "An error occurred with Pyzor",
             SpamEvent::ListUpdated => "The spam list has been updated",
             SpamEvent::Train => "The spam filter is being trained with the message",
+            SpamEvent::Untrain => "A previous training of the spam filter is being reversed",
             SpamEvent::TrainBalance => "The spam filter training data is being balanced",
             SpamEvent::TrainError => "An error occurred while training the spam filter",
             SpamEvent::Classify => "The message is being classified for spam",
@@ -1040,6 +1121,15 @@ impl SpamEvent {
             SpamEvent::NotEnoughTrainingData => {
                 "There is not enough training data for the spam filter"
             }
+            SpamEvent::Rspamd => "The message was scanned by Rspamd",
+            SpamEvent::RspamdError => "An error occurred while communicating with Rspamd",
+            SpamEvent::AttachmentScan => "The message attachments and archives were inspected",
+            SpamEvent::AttachmentPolicy => "An attachment policy was applied to the message",
+            SpamEvent::ClamAv => "The message was scanned for malware by ClamAV",
+            SpamEvent::ClamAvError => "An error occurred while communicating with ClamAV",
+            SpamEvent::Quarantine => "The message was moved to the quarantine",
+            SpamEvent::QuarantineRelease => "A quarantined message was released for delivery",
+            SpamEvent::QuarantineError => "An error occurred while quarantining a message",
         }
     }
 }
@@ -1220,6 +1310,7 @@ impl AcmeEvent {
             AcmeEvent::OrderValid => "ACME order valid",
             AcmeEvent::OrderInvalid => "ACME order invalid",
             AcmeEvent::RenewBackoff => "ACME renew backoff",
+            AcmeEvent::RenewFailed => "ACME renewal failed",
             AcmeEvent::DnsRecordCreated => "ACME DNS record created",
             AcmeEvent::DnsRecordCreationFailed => "ACME DNS record creation failed",
             AcmeEvent::DnsRecordDeletionFailed => "ACME DNS record deletion failed",
@@ -1252,6 +1343,9 @@ impl AcmeEvent {
             AcmeEvent::OrderValid => "ACME order is valid",
             AcmeEvent::OrderInvalid => "ACME order is invalid",
             AcmeEvent::RenewBackoff => "ACME renew backoff",
+            AcmeEvent::RenewFailed => {
+                "Failed to renew the ACME certificate, the current certificate remains in use until it expires"
+            }
             AcmeEvent::DnsRecordCreated => "ACME DNS record has been created",
             AcmeEvent::DnsRecordCreationFailed => "Failed to create ACME DNS record",
             AcmeEvent::DnsRecordDeletionFailed => "Failed to delete ACME DNS record",
@@ -1279,6 +1373,7 @@ impl PurgeEvent {
             PurgeEvent::PurgeActive => "Active purge in progress",
             PurgeEvent::AutoExpunge => "Auto-expunge executed",
             PurgeEvent::TombstoneCleanup => "Tombstone cleanup executed",
+            PurgeEvent::Retention => "Retention policy applied",
         }
     }
 
@@ -1291,6 +1386,7 @@ impl PurgeEvent {
             PurgeEvent::PurgeActive => "An active purge is in progress",
             PurgeEvent::AutoExpunge => "Auto-expunge has been executed",
             PurgeEvent::TombstoneCleanup => "Tombstone cleanup has been executed",
+            PurgeEvent::Retention => "Messages older than the retention period have been expunged",
         }
     }
 }
@@ -1333,6 +1429,7 @@ impl ConfigEvent {
             ConfigEvent::ImportExternal => "Importing external configuration",
             ConfigEvent::ExternalKeyIgnored => "External configuration key ignored",
             ConfigEvent::AlreadyUpToDate => "Configuration already up to date",
+            ConfigEvent::Reloaded => "Configuration reloaded",
         }
     }
 
@@ -1351,6 +1448,7 @@ impl ConfigEvent {
             ConfigEvent::ImportExternal => "An external configuration is being imported",
             ConfigEvent::ExternalKeyIgnored => "An external configuration key is ignored",
             ConfigEvent::AlreadyUpToDate => "The configuration is already up to date",
+            ConfigEvent::Reloaded => "The configuration has been reloaded",
         }
     }
 }
@@ -1400,6 +1498,8 @@ impl DkimEvent {
             DkimEvent::SignatureExpired => "DKIM signature expired",
             DkimEvent::SignatureLength => "DKIM signature length issue",
             DkimEvent::SignerNotFound => "DKIM signer not found",
+            DkimEvent::RecordMissing => "DKIM DNS record missing",
+            DkimEvent::RecordMismatch => "DKIM DNS record mismatch",
         }
     }
 
@@ -1423,6 +1523,10 @@ impl DkimEvent {
             DkimEvent::SignatureExpired => "The DKIM signature has expired",
             DkimEvent::SignatureLength => "The DKIM signature length is incorrect",
             DkimEvent::SignerNotFound => "The DKIM signer was not found",
+            DkimEvent::RecordMissing => "The DNS record of an active DKIM key was not found",
+            DkimEvent::RecordMismatch => {
+                "The DNS record of an active DKIM key is invalid or does not match its private key"
+            }
         }
     }
 }
@@ -1541,6 +1645,7 @@ impl StoreEvent {
             StoreEvent::LdapError => "LDAP error",
             StoreEvent::ElasticsearchError => "ElasticSearch error",
             StoreEvent::RedisError => "Redis error",
+            StoreEvent::NatsError => "NATS error",
             StoreEvent::S3Error => "S3 error",
             StoreEvent::AzureError => "Azure error",
             StoreEvent::FilesystemError => "Filesystem error",
@@ -1553,7 +1658,11 @@ impl StoreEvent {
             StoreEvent::NotSupported => "Operation not supported by store",
             StoreEvent::UnexpectedError => "Unexpected store error",
             StoreEvent::CryptoError => "Store crypto error",
+            StoreEvent::ValueTooLarge => "Value too large",
+            StoreEvent::BackendUnavailable => "Store backend unavailable",
+            StoreEvent::Timeout => "Store write timed out",
             StoreEvent::BlobMissingMarker => "Blob missing marker",
+            StoreEvent::LockNotAcquired => "Lock not acquired",
             StoreEvent::SqlQuery => "SQL query executed",
             StoreEvent::LdapQuery => "LDAP query executed",
             StoreEvent::LdapBind => "LDAP bind operation",
@@ -1576,6 +1685,7 @@ impl StoreEvent {
             StoreEvent::LdapError => "An LDAP error occurred",
             StoreEvent::ElasticsearchError => "An ElasticSearch error occurred",
             StoreEvent::RedisError => "A Redis error occurred",
+            StoreEvent::NatsError => "A NATS error occurred",
             StoreEvent::S3Error => "An S3 error occurred",
             StoreEvent::AzureError => "An Azure error occurred",
             StoreEvent::FilesystemError => "A filesystem error occurred",
@@ -1588,7 +1698,15 @@ impl StoreEvent {
             StoreEvent::NotSupported => "The operation is not supported by the store",
             StoreEvent::UnexpectedError => "An unexpected store error occurred",
             StoreEvent::CryptoError => "A store crypto error occurred",
+            StoreEvent::ValueTooLarge => {
+                "The value exceeds the maximum size configured for its subspace"
+            }
+            StoreEvent::BackendUnavailable => {
+                "The store circuit breaker is open after repeated backend failures"
+            }
+            StoreEvent::Timeout => "The batch could not be committed before its deadline",
             StoreEvent::BlobMissingMarker => "The blob is missing a marker",
+            StoreEvent::LockNotAcquired => "The lock could not be acquired before the timeout",
             StoreEvent::SqlQuery => "An SQL query was executed",
             StoreEvent::LdapQuery => "An LDAP query was executed",
             StoreEvent::LdapBind => "An LDAP bind operation was executed",
@@ -1643,6 +1761,7 @@ impl JmapEvent {
             JmapEvent::AccountReadOnly => "JMAP account is read-only",
             JmapEvent::NotFound => "JMAP resource not found",
             JmapEvent::CannotCalculateChanges => "Cannot calculate JMAP changes",
+            JmapEvent::TooManyChanges => "Too many JMAP changes",
             JmapEvent::UnknownDataType => "Unknown JMAP data type",
             JmapEvent::UnknownCapability => "Unknown JMAP capability",
             JmapEvent::NotJson => "JMAP request is not JSON",
@@ -1672,6 +1791,7 @@ impl JmapEvent {
             JmapEvent::AccountReadOnly => "The JMAP account is read-only",
             JmapEvent::NotFound => "The JMAP resource was not found",
             JmapEvent::CannotCalculateChanges => "Cannot calculate JMAP changes",
+            JmapEvent::TooManyChanges => "The number of changes exceeds the maximum allowed",
             JmapEvent::UnknownDataType => "The JMAP data type is unknown",
             JmapEvent::UnknownCapability => "The JMAP capability is unknown",
             JmapEvent::NotJson => "The JMAP request is not JSON",
@@ -30,6 +30,7 @@ impl EventType {
                 | StoreEvent::LdapError
                 | StoreEvent::ElasticsearchError
                 | StoreEvent::RedisError
+                | StoreEvent::NatsError
                 | StoreEvent::S3Error
                 | StoreEvent::AzureError
                 | StoreEvent::FilesystemError
@@ -40,8 +41,11 @@ impl EventType {
                 | StoreEvent::NotConfigured
                 | StoreEvent::NotSupported
                 | StoreEvent::UnexpectedError
-                | StoreEvent::CryptoError => Level::Error,
-                StoreEvent::BlobMissingMarker => Level::Warn,
+                | StoreEvent::CryptoError
+                | StoreEvent::ValueTooLarge
+                | StoreEvent::BackendUnavailable
+                | StoreEvent::Timeout => Level::Error,
+                StoreEvent::BlobMissingMarker | StoreEvent::LockNotAcquired => Level::Warn,
             },
             EventType::Jmap(_) => Level::Debug,
             EventType::Imap(event) => match event {
@@ -75,6 +79,7 @@ impl EventType {
                 | ImapEvent::Subscribe
                 | ImapEvent::Unsubscribe
                 | ImapEvent::Thread
+                | ImapEvent::XApplePushService
                 | ImapEvent::Error
                 | ImapEvent::IdleStart
                 | ImapEvent::IdleStop => Level::Debug,
@@ -171,6 +176,8 @@ impl EventType {
                 | SmtpEvent::DkimFail
                 | SmtpEvent::ArcPass
                 | SmtpEvent::ArcFail
+                | SmtpEvent::AuthResultsImported
+                | SmtpEvent::AuthResultsStripped
                 | SmtpEvent::SpfEhloPass
                 | SmtpEvent::SpfEhloFail
                 | SmtpEvent::SpfFromPass
@@ -185,6 +192,7 @@ impl EventType {
                 | SmtpEvent::MailFrom
                 | SmtpEvent::MailboxDoesNotExist
                 | SmtpEvent::RelayNotAllowed
+                | SmtpEvent::RcptToGreylisted
                 | SmtpEvent::RcptTo
                 | SmtpEvent::TooManyInvalidRcpt
                 | SmtpEvent::Vrfy
@@ -244,7 +252,7 @@ impl EventType {
                 | ConfigEvent::AlreadyUpToDate
                 | ConfigEvent::ExternalKeyIgnored => Level::Debug,
                 ConfigEvent::ParseWarning | ConfigEvent::BuildWarning => Level::Warn,
-                ConfigEvent::ImportExternal => Level::Info,
+                ConfigEvent::ImportExternal | ConfigEvent::Reloaded => Level::Info,
             },
             EventType::Resource(cause) => match cause {
                 ResourceEvent::NotFound => Level::Debug,
@@ -260,7 +268,9 @@ impl EventType {
                 ArcEvent::SealerNotFound => Level::Warn,
             },
             EventType::Dkim(event) => match event {
-                DkimEvent::SignerNotFound => Level::Warn,
+                DkimEvent::SignerNotFound
+                | DkimEvent::RecordMissing
+                | DkimEvent::RecordMismatch => Level::Warn,
                 _ => Level::Debug,
             },
             EventType::MailAuth(_) => Level::Debug,
@@ -272,6 +282,7 @@ impl EventType {
                 PurgeEvent::PurgeActive
                 | PurgeEvent::AutoExpunge
                 | PurgeEvent::TombstoneCleanup => Level::Debug,
+                PurgeEvent::Retention => Level::Info,
             },
             EventType::Eval(event) => match event {
                 EvalEvent::Error | EvalEvent::StoreNotFound => Level::Debug,
@@ -298,7 +309,7 @@ impl EventType {
                 | AcmeEvent::OrderValid
                 | AcmeEvent::OrderStart
                 | AcmeEvent::OrderCompleted => Level::Info,
-                AcmeEvent::Error => Level::Error,
+                AcmeEvent::Error | AcmeEvent::RenewFailed => Level::Error,
                 AcmeEvent::OrderInvalid
                 | AcmeEvent::AuthError
                 | AcmeEvent::AuthTooManyAttempts
@@ -336,14 +347,24 @@ impl EventType {
                 | SieveEvent::ActionReject => Level::Debug,
             },
             EventType::Spam(event) => match event {
-                SpamEvent::PyzorError | SpamEvent::TrainError | SpamEvent::ClassifyError => {
-                    Level::Warn
-                }
+                SpamEvent::PyzorError
+                | SpamEvent::TrainError
+                | SpamEvent::ClassifyError
+                | SpamEvent::RspamdError
+                | SpamEvent::ClamAvError
+                | SpamEvent::QuarantineError => Level::Warn,
                 SpamEvent::Train
+                | SpamEvent::Untrain
                 | SpamEvent::Classify
                 | SpamEvent::NotEnoughTrainingData
-                | SpamEvent::TrainBalance => Level::Debug,
-                SpamEvent::ListUpdated => Level::Info,
+                | SpamEvent::TrainBalance
+                | SpamEvent::AttachmentScan => Level::Debug,
+                SpamEvent::ListUpdated
+                | SpamEvent::Rspamd
+                | SpamEvent::AttachmentPolicy
+                | SpamEvent::ClamAv
+                | SpamEvent::Quarantine
+                | SpamEvent::QuarantineRelease => Level::Info,
             },
             EventType::Http(event) => match event {
                 HttpEvent::ConnectionStart | HttpEvent::ConnectionEnd => Level::Debug,
@@ -352,7 +373,9 @@ impl EventType {
                 HttpEvent::RequestBody | HttpEvent::ResponseBody => Level::Trace,
             },
             EventType::PushSubscription(event) => match event {
-                PushSubscriptionEvent::Error | PushSubscriptionEvent::NotFound => Level::Debug,
+                PushSubscriptionEvent::Error
+                | PushSubscriptionEvent::NotFound
+                | PushSubscriptionEvent::Unregistered => Level::Debug,
                 PushSubscriptionEvent::Success => Level::Trace,
             },
             EventType::Cluster(event) => match event {
@@ -367,13 +390,17 @@ impl EventType {
                 ClusterEvent::EmptyPacket
                 | ClusterEvent::Error
                 | ClusterEvent::DecryptionError
-                | ClusterEvent::InvalidPacket => Level::Warn,
+                | ClusterEvent::InvalidPacket
+                | ClusterEvent::Resync => Level::Warn,
             },
             EventType::Housekeeper(event) => match event {
                 HousekeeperEvent::Start
                 | HousekeeperEvent::PurgeAccounts
                 | HousekeeperEvent::PurgeSessions
                 | HousekeeperEvent::PurgeStore
+                | HousekeeperEvent::ArchiveAccounts
+                | HousekeeperEvent::ArchiveMessages
+                | HousekeeperEvent::WakeSnoozed
                 | HousekeeperEvent::Stop => Level::Info,
                 HousekeeperEvent::Schedule => Level::Debug,
             },
@@ -430,11 +457,16 @@ impl EventType {
                 | DeliveryEvent::Completed
                 | DeliveryEvent::Failed
                 | DeliveryEvent::DomainDeliveryStart
+                | DeliveryEvent::DomainDelivered
+                | DeliveryEvent::DomainDeferred
+                | DeliveryEvent::DomainBounced
+                | DeliveryEvent::NextHop
                 | DeliveryEvent::MxLookupFailed
                 | DeliveryEvent::IpLookupFailed
                 | DeliveryEvent::NullMx
                 | DeliveryEvent::Connect
                 | DeliveryEvent::ConnectError
+                | DeliveryEvent::ConnectionReused
                 | DeliveryEvent::GreetingFailed
                 | DeliveryEvent::EhloRejected
                 | DeliveryEvent::AuthFailed
@@ -458,6 +490,7 @@ impl EventType {
                 DeliveryEvent::MxLookup
                 | DeliveryEvent::IpLookup
                 | DeliveryEvent::Ehlo
+                | DeliveryEvent::ConnectionPooled
                 | DeliveryEvent::Auth
                 | DeliveryEvent::MailFrom
                 | DeliveryEvent::RcptTo => Level::Debug,
@@ -472,7 +505,12 @@ impl EventType {
                 | QueueEvent::RateLimitExceeded
                 | QueueEvent::ConcurrencyLimitExceeded
                 | QueueEvent::Rescheduled
-                | QueueEvent::QuotaExceeded => Level::Info,
+                | QueueEvent::QuotaExceeded
+                | QueueEvent::ListExpanded
+                | QueueEvent::ListBounce
+                | QueueEvent::ListDigest
+                | QueueEvent::TransportSelected => Level::Info,
+                QueueEvent::RetryStrategyNotFound | QueueEvent::ListMemberSuspended => Level::Warn,
                 QueueEvent::LockBusy | QueueEvent::Locked | QueueEvent::BlobNotFound => {
                     Level::Debug
                 }
@@ -306,6 +306,7 @@ impl StoreEvent {
             Self::LdapError => "LDAP error",
             Self::ElasticsearchError => "ElasticSearch error",
             Self::RedisError => "Redis error",
+            Self::NatsError => "NATS error",
             Self::S3Error => "S3 error",
             Self::AzureError => "Azure error",
             Self::FilesystemError => "Filesystem error",
@@ -434,6 +435,7 @@ impl JmapEvent {
             Self::AccountReadOnly => "Account read-only",
             Self::NotFound => "Not found",
             Self::CannotCalculateChanges => "Cannot calculate changes",
+            Self::TooManyChanges => "Too many changes",
             Self::UnknownDataType => "Unknown data type",
             Self::UnknownCapability => "Unknown capability",
             Self::NotJson => "Not JSON",
@@ -4,13 +4,20 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::sync::atomic::Ordering;
-
-use atomics::{array::AtomicU32Array, gauge::AtomicGauge, histogram::AtomicHistogram};
+use std::{sync::atomic::Ordering, time::Instant};
+
+use ahash::AHashMap;
+use atomics::{
+    array::AtomicU32Array,
+    gauge::AtomicGauge,
+    histogram::AtomicHistogram,
+    labeled::{LabelLimit, LabeledCounter, LabeledHistogram},
+};
 use ipc::{
     collector::{Collector, GlobalInterests, EVENT_TYPES},
     subscriber::Interests,
 };
+use parking_lot::Mutex;
 
 use crate::*;
 
@@ -50,6 +57,55 @@ static QUEUE_COUNT: AtomicGauge = AtomicGauge::new(MetricType::QueueCount);
 static USER_COUNT: AtomicGauge = AtomicGauge::new(MetricType::UserCount);
 static DOMAIN_COUNT: AtomicGauge = AtomicGauge::new(MetricType::DomainCount);
 
+static LABEL_LIMIT: LabelLimit = LabelLimit::new(100);
+
+static MESSAGES_RECEIVED: LabeledCounter = LabeledCounter::new(
+    "smtp_messages_received_total",
+    "Messages received and queued, by listener",
+    "listener",
+    &LABEL_LIMIT,
+);
+static MESSAGES_DELIVERED: LabeledCounter = LabeledCounter::new(
+    "smtp_messages_delivered_total",
+    "Messages delivered, by destination domain",
+    "domain",
+    &LABEL_LIMIT,
+);
+static MESSAGES_DEFERRED: LabeledCounter = LabeledCounter::new(
+    "smtp_messages_deferred_total",
+    "Messages deferred for a later retry, by destination domain",
+    "domain",
+    &LABEL_LIMIT,
+);
+static MESSAGES_BOUNCED: LabeledCounter = LabeledCounter::new(
+    "smtp_messages_bounced_total",
+    "Messages that failed permanently, by destination domain",
+    "domain",
+    &LABEL_LIMIT,
+);
+static AUTH_SUCCESS: LabeledCounter = LabeledCounter::new(
+    "auth_success_total",
+    "Successful authentications, by mechanism",
+    "mechanism",
+    &LABEL_LIMIT,
+);
+static AUTH_FAILURE: LabeledCounter = LabeledCounter::new(
+    "auth_failure_total",
+    "Failed authentications, by mechanism",
+    "mechanism",
+    &LABEL_LIMIT,
+);
+static SMTP_TRANSACTION_TIME: LabeledHistogram = LabeledHistogram::new(
+    "smtp_transaction_duration",
+    "Time from MAIL FROM until the message is queued, by listener",
+    "listener",
+    &LABEL_LIMIT,
+);
+
+// Listener and current transaction start of each inbound SMTP session
+static SMTP_SESSIONS: Mutex<Option<AHashMap<u64, SmtpSession>>> = Mutex::new(None);
+const MAX_SMTP_SESSIONS: usize = 65536;
+
 const CONN_SMTP_IN: usize = 0;
 const CONN_SMTP_OUT: usize = 1;
 const CONN_IMAP: usize = 2;
@@ -68,6 +124,11 @@ pub struct EventCounter {
     value: u32,
 }
 
+struct SmtpSession {
+    listener_id: String,
+    transaction_start: Option<Instant>,
+}
+
 impl Collector {
     pub fn record_metric(event: EventType, event_id: usize, keys: &[(Key, Value)]) {
         // Increment the event counter
@@ -78,10 +139,18 @@ impl Collector {
         // Extract variables
         let mut elapsed = 0;
         let mut size = 0;
+        let mut span_id = 0;
+        let mut listener_id = None;
+        let mut domain = None;
+        let mut mechanism = None;
         for (key, value) in keys {
             match (key, value) {
                 (Key::Elapsed, Value::Duration(d)) => elapsed = *d,
                 (Key::Size, Value::UInt(s)) => size = *s,
+                (Key::SpanId, Value::UInt(id)) => span_id = *id,
+                (Key::ListenerId, Value::String(id)) => listener_id = Some(id.as_str()),
+                (Key::Domain, Value::String(d)) => domain = Some(d.as_str()),
+                (Key::Mechanism, Value::Static(m)) => mechanism = Some(*m),
                 _ => {}
             }
         }
@@ -90,11 +159,40 @@ impl Collector {
             EventType::Smtp(SmtpEvent::ConnectionStart) => {
                 let conn = &CONNECTION_METRICS[CONN_SMTP_IN];
                 conn.active_connections.increment();
+
+                if let Some(listener_id) = listener_id {
+                    let mut sessions = SMTP_SESSIONS.lock();
+                    let sessions = sessions.get_or_insert_with(AHashMap::default);
+                    if sessions.len() >= MAX_SMTP_SESSIONS {
+                        // Sessions whose end was never recorded
+                        sessions.clear();
+                    }
+                    sessions.insert(
+                        span_id,
+                        SmtpSession {
+                            listener_id: listener_id.to_string(),
+                            transaction_start: None,
+                        },
+                    );
+                }
             }
             EventType::Smtp(SmtpEvent::ConnectionEnd) => {
                 let conn = &CONNECTION_METRICS[CONN_SMTP_IN];
                 conn.active_connections.decrement();
                 conn.elapsed.observe(elapsed);
+
+                if let Some(sessions) = SMTP_SESSIONS.lock().as_mut() {
+                    sessions.remove(&span_id);
+                }
+            }
+            EventType::Smtp(SmtpEvent::MailFrom) => {
+                if let Some(session) = SMTP_SESSIONS
+                    .lock()
+                    .as_mut()
+                    .and_then(|sessions| sessions.get_mut(&span_id))
+                {
+                    session.transaction_start = Some(Instant::now());
+                }
             }
             EventType::Imap(ImapEvent::ConnectionStart) => {
                 let conn = &CONNECTION_METRICS[CONN_IMAP];
@@ -145,6 +243,21 @@ impl Collector {
                 QUEUE_COUNT.decrement();
                 MESSAGE_DELIVERY_TIME.observe(elapsed);
             }
+            EventType::Delivery(DeliveryEvent::DomainDelivered) => {
+                MESSAGES_DELIVERED.increment(domain.unwrap_or_default());
+            }
+            EventType::Delivery(DeliveryEvent::DomainDeferred) => {
+                MESSAGES_DEFERRED.increment(domain.unwrap_or_default());
+            }
+            EventType::Delivery(DeliveryEvent::DomainBounced | DeliveryEvent::Failed) => {
+                MESSAGES_BOUNCED.increment(domain.unwrap_or_default());
+            }
+            EventType::Auth(AuthEvent::Success) => {
+                AUTH_SUCCESS.increment(mechanism.unwrap_or("unknown"));
+            }
+            EventType::Auth(AuthEvent::Failed) => {
+                AUTH_FAILURE.increment(mechanism.unwrap_or("unknown"));
+            }
             EventType::Delivery(
                 DeliveryEvent::MxLookup | DeliveryEvent::IpLookup | DeliveryEvent::NullMx,
             )
@@ -166,10 +279,12 @@ impl Collector {
             EventType::Queue(QueueEvent::QueueMessage) => {
                 MESSAGE_INCOMING_SIZE.observe(size);
                 QUEUE_COUNT.increment();
+                record_received(span_id);
             }
             EventType::Queue(QueueEvent::QueueMessageAuthenticated) => {
                 MESSAGE_SUBMISSION_SIZE.observe(size);
                 QUEUE_COUNT.increment();
+                record_received(span_id);
             }
             EventType::Queue(QueueEvent::QueueReport) => {
                 MESSAGE_OUT_REPORT_SIZE.observe(size);
@@ -256,6 +371,7 @@ impl Collector {
             &MESSAGE_DELIVERY_TIME,
             &MESSAGE_INCOMING_SIZE,
             &MESSAGE_SUBMISSION_SIZE,
+            &STORE_DATA_WRITE_TIME,
         ];
 
         if is_enterprise {
@@ -269,6 +385,26 @@ impl Collector {
         .filter(|h| h.is_active())
     }
 
+    pub fn collect_labeled_counters() -> impl Iterator<Item = &'static LabeledCounter> {
+        [
+            &MESSAGES_RECEIVED,
+            &MESSAGES_DELIVERED,
+            &MESSAGES_DEFERRED,
+            &MESSAGES_BOUNCED,
+            &AUTH_SUCCESS,
+            &AUTH_FAILURE,
+        ]
+        .into_iter()
+    }
+
+    pub fn collect_labeled_histograms() -> impl Iterator<Item = &'static LabeledHistogram> {
+        [&SMTP_TRANSACTION_TIME].into_iter()
+    }
+
+    pub fn set_metric_label_limit(limit: usize) {
+        LABEL_LIMIT.set(limit);
+    }
+
     #[inline(always)]
     pub fn read_event_metric(metric_id: usize) -> u32 {
         EVENT_COUNTERS.get(metric_id)
@@ -344,6 +480,26 @@ impl Collector {
     }
 }
 
+fn record_received(span_id: u64) {
+    let mut sessions = SMTP_SESSIONS.lock();
+    match sessions
+        .as_mut()
+        .and_then(|sessions| sessions.get_mut(&span_id))
+    {
+        Some(session) => {
+            MESSAGES_RECEIVED.increment(&session.listener_id);
+            if let Some(start) = session.transaction_start.take() {
+                SMTP_TRANSACTION_TIME
+                    .observe(&session.listener_id, start.elapsed().as_millis() as u64);
+            }
+        }
+        None => {
+            // Submissions that did not arrive over SMTP
+            MESSAGES_RECEIVED.increment("internal");
+        }
+    }
+}
+
 impl EventCounter {
     pub fn id(&self) -> EventType {
         self.id
@@ -439,6 +595,7 @@ impl EventType {
                 | StoreEvent::LdapError
                 | StoreEvent::ElasticsearchError
                 | StoreEvent::RedisError
+                | StoreEvent::NatsError
                 | StoreEvent::S3Error
                 | StoreEvent::AzureError
                 | StoreEvent::FilesystemError
@@ -451,6 +608,9 @@ impl EventType {
                 | StoreEvent::NotSupported
                 | StoreEvent::UnexpectedError
                 | StoreEvent::CryptoError
+                | StoreEvent::ValueTooLarge
+                | StoreEvent::BackendUnavailable
+                | StoreEvent::Timeout
                 | StoreEvent::BlobMissingMarker
                 | StoreEvent::DataWrite
                 | StoreEvent::DataIterate
@@ -492,6 +652,8 @@ impl EventType {
                 | SmtpEvent::DkimFail
                 | SmtpEvent::ArcPass
                 | SmtpEvent::ArcFail
+                | SmtpEvent::AuthResultsImported
+                | SmtpEvent::AuthResultsStripped
                 | SmtpEvent::SpfEhloPass
                 | SmtpEvent::SpfEhloFail
                 | SmtpEvent::SpfFromPass
@@ -506,9 +668,11 @@ impl EventType {
                 | SmtpEvent::MailFromUnauthenticated
                 | SmtpEvent::MailFromUnauthorized
                 | SmtpEvent::MailFromMissing
+                | SmtpEvent::MailFrom
                 | SmtpEvent::MultipleMailFrom
                 | SmtpEvent::MailboxDoesNotExist
                 | SmtpEvent::RelayNotAllowed
+                | SmtpEvent::RcptToGreylisted
                 | SmtpEvent::RcptToDuplicate
                 | SmtpEvent::RcptToMissing
                 | SmtpEvent::TooManyRecipients
@@ -579,10 +743,20 @@ impl EventType {
                 SpamEvent::PyzorError
                 | SpamEvent::ListUpdated
                 | SpamEvent::Train
+                | SpamEvent::Untrain
                 | SpamEvent::TrainError
                 | SpamEvent::Classify
                 | SpamEvent::ClassifyError
-                | SpamEvent::NotEnoughTrainingData,
+                | SpamEvent::NotEnoughTrainingData
+                | SpamEvent::Rspamd
+                | SpamEvent::RspamdError
+                | SpamEvent::AttachmentScan
+                | SpamEvent::AttachmentPolicy
+                | SpamEvent::ClamAv
+                | SpamEvent::ClamAvError
+                | SpamEvent::Quarantine
+                | SpamEvent::QuarantineRelease
+                | SpamEvent::QuarantineError,
             ) => true,
             EventType::PushSubscription(_) => true,
             EventType::Cluster(
@@ -592,7 +766,8 @@ impl EventType {
                 | ClusterEvent::EmptyPacket
                 | ClusterEvent::InvalidPacket
                 | ClusterEvent::DecryptionError
-                | ClusterEvent::Error,
+                | ClusterEvent::Error
+                | ClusterEvent::Resync,
             ) => true,
             EventType::Housekeeper(_) => false,
             EventType::FtsIndex(
@@ -613,10 +788,16 @@ impl EventType {
             EventType::Delivery(
                 DeliveryEvent::AttemptStart
                 | DeliveryEvent::Completed
+                | DeliveryEvent::DomainDelivered
+                | DeliveryEvent::DomainDeferred
+                | DeliveryEvent::DomainBounced
                 | DeliveryEvent::AttemptEnd
                 | DeliveryEvent::MxLookupFailed
                 | DeliveryEvent::IpLookupFailed
                 | DeliveryEvent::NullMx
+                | DeliveryEvent::Connect
+                | DeliveryEvent::ConnectionReused
+                | DeliveryEvent::ConnectionPooled
                 | DeliveryEvent::GreetingFailed
                 | DeliveryEvent::EhloRejected
                 | DeliveryEvent::AuthFailed
@@ -646,7 +827,12 @@ impl EventType {
                 | QueueEvent::BlobNotFound
                 | QueueEvent::RateLimitExceeded
                 | QueueEvent::ConcurrencyLimitExceeded
-                | QueueEvent::QuotaExceeded,
+                | QueueEvent::QuotaExceeded
+                | QueueEvent::ListExpanded
+                | QueueEvent::ListBounce
+                | QueueEvent::ListMemberSuspended
+                | QueueEvent::ListDigest
+                | QueueEvent::TransportSelected,
             ) => true,
             EventType::TlsRpt(_) => false,
             EventType::MtaSts(
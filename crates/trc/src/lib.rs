@@ -102,6 +102,7 @@ pub enum Key {
     LocalPort,
     MailboxName,
     MailboxId,
+    Mechanism,
     MessageId,
     NextDsn,
     NextRetry,
@@ -127,6 +128,7 @@ pub enum Key {
     Total,
     TotalFailures,
     TotalSuccesses,
+    TraceId,
     Type,
     Uid,
     UidNext,
@@ -222,6 +224,7 @@ pub enum ClusterEvent {
     InvalidPacket,
     DecryptionError,
     Error,
+    Resync,
 }
 
 #[event_type]
@@ -232,6 +235,9 @@ pub enum HousekeeperEvent {
     PurgeAccounts,
     PurgeSessions,
     PurgeStore,
+    ArchiveAccounts,
+    ArchiveMessages,
+    WakeSnoozed,
 }
 
 #[event_type]
@@ -280,6 +286,7 @@ pub enum ImapEvent {
     Subscribe,
     Unsubscribe,
     Thread,
+    XApplePushService,
 
     // Errors
     Error,
@@ -366,6 +373,8 @@ pub enum SmtpEvent {
     DkimFail,
     ArcPass,
     ArcFail,
+    AuthResultsImported,
+    AuthResultsStripped,
     SpfEhloPass,
     SpfEhloFail,
     SpfFromPass,
@@ -390,6 +399,7 @@ pub enum SmtpEvent {
     MailboxDoesNotExist,
     RelayNotAllowed,
     RcptTo,
+    RcptToGreylisted,
     RcptToDuplicate,
     RcptToRewritten,
     RcptToMissing,
@@ -440,6 +450,10 @@ pub enum DeliveryEvent {
     Completed,
     Failed,
     DomainDeliveryStart,
+    DomainDelivered,
+    DomainDeferred,
+    DomainBounced,
+    NextHop,
     MxLookup,
     MxLookupFailed,
     IpLookup,
@@ -447,6 +461,8 @@ pub enum DeliveryEvent {
     NullMx,
     Connect,
     ConnectError,
+    ConnectionReused,
+    ConnectionPooled,
     MissingOutboundHostname,
     GreetingFailed,
     Ehlo,
@@ -489,6 +505,12 @@ pub enum QueueEvent {
     RateLimitExceeded,
     ConcurrencyLimitExceeded,
     QuotaExceeded,
+    RetryStrategyNotFound,
+    ListExpanded,
+    ListBounce,
+    ListMemberSuspended,
+    ListDigest,
+    TransportSelected,
 }
 
 #[event_type]
@@ -597,6 +619,7 @@ pub enum PushSubscriptionEvent {
     Success,
     Error,
     NotFound,
+    Unregistered,
 }
 
 #[event_type]
@@ -604,11 +627,21 @@ pub enum SpamEvent {
     PyzorError,
     ListUpdated,
     Train,
+    Untrain,
     TrainBalance,
     TrainError,
     Classify,
     ClassifyError,
     NotEnoughTrainingData,
+    Rspamd,
+    RspamdError,
+    AttachmentScan,
+    AttachmentPolicy,
+    ClamAv,
+    ClamAvError,
+    Quarantine,
+    QuarantineRelease,
+    QuarantineError,
 }
 
 #[event_type]
@@ -690,6 +723,7 @@ pub enum AcmeEvent {
     OrderValid,
     OrderInvalid,
     RenewBackoff,
+    RenewFailed,
     DnsRecordCreated,
     DnsRecordCreationFailed,
     DnsRecordDeletionFailed,
@@ -714,6 +748,7 @@ pub enum PurgeEvent {
     PurgeActive,
     AutoExpunge,
     TombstoneCleanup,
+    Retention,
 }
 
 #[event_type]
@@ -739,6 +774,7 @@ pub enum ConfigEvent {
     ImportExternal,
     ExternalKeyIgnored,
     AlreadyUpToDate,
+    Reloaded,
 }
 
 #[event_type]
@@ -771,6 +807,8 @@ pub enum DkimEvent {
     SignatureExpired,
     SignatureLength,
     SignerNotFound,
+    RecordMissing,
+    RecordMismatch,
 }
 
 #[event_type]
@@ -828,6 +866,7 @@ pub enum StoreEvent {
     LdapError,
     ElasticsearchError,
     RedisError,
+    NatsError,
     S3Error,
     AzureError,
     FilesystemError,
@@ -840,9 +879,13 @@ pub enum StoreEvent {
     NotSupported,
     UnexpectedError,
     CryptoError,
+    ValueTooLarge,
+    BackendUnavailable,
+    Timeout,
 
     // Warnings
     BlobMissingMarker,
+    LockNotAcquired,
 
     // Traces
     DataWrite,
@@ -886,6 +929,7 @@ pub enum JmapEvent {
     AccountReadOnly,
     NotFound,
     CannotCalculateChanges,
+    TooManyChanges,
     UnknownDataType,
 
     // Request errors
@@ -866,6 +866,48 @@ impl EventType {
             EventType::Security(SecurityEvent::ScanBan) => 558,
             EventType::Store(StoreEvent::AzureError) => 559,
             EventType::TlsRpt(TlsRptEvent::RecordNotFound) => 560,
+            EventType::Config(ConfigEvent::Reloaded) => 561,
+            EventType::Acme(AcmeEvent::RenewFailed) => 562,
+            EventType::Jmap(JmapEvent::TooManyChanges) => 563,
+            EventType::Store(StoreEvent::LockNotAcquired) => 564,
+            EventType::Delivery(DeliveryEvent::ConnectionReused) => 565,
+            EventType::Delivery(DeliveryEvent::ConnectionPooled) => 566,
+            EventType::Delivery(DeliveryEvent::NextHop) => 567,
+            EventType::Store(StoreEvent::ValueTooLarge) => 568,
+            EventType::Queue(QueueEvent::RetryStrategyNotFound) => 569,
+            EventType::Smtp(SmtpEvent::RcptToGreylisted) => 570,
+            EventType::Smtp(SmtpEvent::AuthResultsImported) => 571,
+            EventType::Smtp(SmtpEvent::AuthResultsStripped) => 572,
+            EventType::Spam(SpamEvent::Untrain) => 573,
+            EventType::Spam(SpamEvent::Rspamd) => 574,
+            EventType::Spam(SpamEvent::RspamdError) => 575,
+            EventType::Spam(SpamEvent::AttachmentScan) => 576,
+            EventType::Spam(SpamEvent::AttachmentPolicy) => 577,
+            EventType::Spam(SpamEvent::ClamAv) => 578,
+            EventType::Spam(SpamEvent::ClamAvError) => 579,
+            EventType::Spam(SpamEvent::Quarantine) => 580,
+            EventType::Spam(SpamEvent::QuarantineRelease) => 581,
+            EventType::Spam(SpamEvent::QuarantineError) => 582,
+            EventType::Purge(PurgeEvent::Retention) => 583,
+            EventType::Housekeeper(HousekeeperEvent::ArchiveAccounts) => 584,
+            EventType::Housekeeper(HousekeeperEvent::ArchiveMessages) => 585,
+            EventType::Store(StoreEvent::BackendUnavailable) => 586,
+            EventType::Queue(QueueEvent::ListExpanded) => 587,
+            EventType::Queue(QueueEvent::ListBounce) => 588,
+            EventType::Queue(QueueEvent::ListMemberSuspended) => 589,
+            EventType::Queue(QueueEvent::ListDigest) => 590,
+            EventType::Housekeeper(HousekeeperEvent::WakeSnoozed) => 591,
+            EventType::Imap(ImapEvent::XApplePushService) => 592,
+            EventType::PushSubscription(PushSubscriptionEvent::Unregistered) => 593,
+            EventType::Store(StoreEvent::NatsError) => 594,
+            EventType::Cluster(ClusterEvent::Resync) => 595,
+            EventType::Delivery(DeliveryEvent::DomainDelivered) => 596,
+            EventType::Delivery(DeliveryEvent::DomainDeferred) => 597,
+            EventType::Delivery(DeliveryEvent::DomainBounced) => 598,
+            EventType::Dkim(DkimEvent::RecordMissing) => 599,
+            EventType::Dkim(DkimEvent::RecordMismatch) => 600,
+            EventType::Store(StoreEvent::Timeout) => 601,
+            EventType::Queue(QueueEvent::TransportSelected) => 602,
         }
     }
 
@@ -1472,6 +1514,50 @@ impl EventType {
             558 => Some(EventType::Security(SecurityEvent::ScanBan)),
             559 => Some(EventType::Store(StoreEvent::AzureError)),
             560 => Some(EventType::TlsRpt(TlsRptEvent::RecordNotFound)),
+            561 => Some(EventType::Config(ConfigEvent::Reloaded)),
+            562 => Some(EventType::Acme(AcmeEvent::RenewFailed)),
+            563 => Some(EventType::Jmap(JmapEvent::TooManyChanges)),
+            564 => Some(EventType::Store(StoreEvent::LockNotAcquired)),
+            565 => Some(EventType::Delivery(DeliveryEvent::ConnectionReused)),
+            566 => Some(EventType::Delivery(DeliveryEvent::ConnectionPooled)),
+            567 => Some(EventType::Delivery(DeliveryEvent::NextHop)),
+            568 => Some(EventType::Store(StoreEvent::ValueTooLarge)),
+            569 => Some(EventType::Queue(QueueEvent::RetryStrategyNotFound)),
+            570 => Some(EventType::Smtp(SmtpEvent::RcptToGreylisted)),
+            571 => Some(EventType::Smtp(SmtpEvent::AuthResultsImported)),
+            572 => Some(EventType::Smtp(SmtpEvent::AuthResultsStripped)),
+            573 => Some(EventType::Spam(SpamEvent::Untrain)),
+            574 => Some(EventType::Spam(SpamEvent::Rspamd)),
+            575 => Some(EventType::Spam(SpamEvent::RspamdError)),
+            576 => Some(EventType::Spam(SpamEvent::AttachmentScan)),
+            577 => Some(EventType::Spam(SpamEvent::AttachmentPolicy)),
+            578 => Some(EventType::Spam(SpamEvent::ClamAv)),
+            579 => Some(EventType::Spam(SpamEvent::ClamAvError)),
+            580 => Some(EventType::Spam(SpamEvent::Quarantine)),
+            581 => Some(EventType::Spam(SpamEvent::QuarantineRelease)),
+            582 => Some(EventType::Spam(SpamEvent::QuarantineError)),
+            583 => Some(EventType::Purge(PurgeEvent::Retention)),
+            584 => Some(EventType::Housekeeper(HousekeeperEvent::ArchiveAccounts)),
+            585 => Some(EventType::Housekeeper(HousekeeperEvent::ArchiveMessages)),
+            586 => Some(EventType::Store(StoreEvent::BackendUnavailable)),
+            587 => Some(EventType::Queue(QueueEvent::ListExpanded)),
+            588 => Some(EventType::Queue(QueueEvent::ListBounce)),
+            589 => Some(EventType::Queue(QueueEvent::ListMemberSuspended)),
+            590 => Some(EventType::Queue(QueueEvent::ListDigest)),
+            591 => Some(EventType::Housekeeper(HousekeeperEvent::WakeSnoozed)),
+            592 => Some(EventType::Imap(ImapEvent::XApplePushService)),
+            593 => Some(EventType::PushSubscription(
+                PushSubscriptionEvent::Unregistered,
+            )),
+            594 => Some(EventType::Store(StoreEvent::NatsError)),
+            595 => Some(EventType::Cluster(ClusterEvent::Resync)),
+            596 => Some(EventType::Delivery(DeliveryEvent::DomainDelivered)),
+            597 => Some(EventType::Delivery(DeliveryEvent::DomainDeferred)),
+            598 => Some(EventType::Delivery(DeliveryEvent::DomainBounced)),
+            599 => Some(EventType::Dkim(DkimEvent::RecordMissing)),
+            600 => Some(EventType::Dkim(DkimEvent::RecordMismatch)),
+            601 => Some(EventType::Store(StoreEvent::Timeout)),
+            602 => Some(EventType::Queue(QueueEvent::TransportSelected)),
             _ => None,
         }
     }
@@ -1545,6 +1631,8 @@ impl Key {
             Key::ValidTo => 62,
             Key::Value => 63,
             Key::Version => 64,
+            Key::TraceId => 65,
+            Key::Mechanism => 66,
         }
     }
 
@@ -1615,6 +1703,8 @@ impl Key {
             62 => Some(Key::ValidTo),
             63 => Some(Key::Value),
             64 => Some(Key::Version),
+            65 => Some(Key::TraceId),
+            66 => Some(Key::Mechanism),
             _ => None,
         }
     }
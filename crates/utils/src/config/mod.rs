@@ -55,6 +55,13 @@ pub struct Rate {
     pub period: Duration,
 }
 
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum RateWindow {
+    #[default]
+    Fixed,
+    Sliding,
+}
+
 pub(crate) type Result<T> = std::result::Result<T, String>;
 
 impl Config {
@@ -18,7 +18,7 @@ use mail_auth::{
 };
 use smtp_proto::MtPriority;
 
-use super::{Config, ConfigError, ConfigWarning, Rate};
+use super::{Config, ConfigError, ConfigWarning, Rate, RateWindow};
 
 impl Config {
     pub fn property<T: ParseValue>(&mut self, key: impl AsKey) -> Option<T> {
@@ -583,6 +583,16 @@ impl ParseValue for Rate {
     }
 }
 
+impl ParseValue for RateWindow {
+    fn parse_value(value: &str) -> super::Result<Self> {
+        match value {
+            "fixed" => Ok(RateWindow::Fixed),
+            "sliding" => Ok(RateWindow::Sliding),
+            _ => Err(format!("Invalid rate window {:?}.", value)),
+        }
+    }
+}
+
 impl ParseValue for trc::Level {
     fn parse_value(value: &str) -> super::Result<Self> {
         trc::Level::from_str(value).map_err(|err| format!("Invalid log level: {err}"))
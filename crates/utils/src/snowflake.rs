@@ -53,6 +53,10 @@ impl SnowflakeIdGenerator {
             .and_then(|diff| Self::from_duration(Duration::from_secs(diff)))
     }
 
+    pub fn to_timestamp(id: u64) -> u64 {
+        1632280000 + (id >> (SEQUENCE_LEN + NODE_ID_LEN)) / 1000
+    }
+
     pub fn with_node_id(node_id: u64) -> Self {
         Self {
             epoch: SystemTime::UNIX_EPOCH + Duration::from_secs(1632280000), // 52 years after UNIX_EPOCH
@@ -0,0 +1,206 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{sync::Arc, time::Duration};
+
+use common::{
+    config::telemetry::Telemetry,
+    core::BuildServer,
+    ipc::{ClusterEvent, ClusterMessage},
+    manager::boot::build_ipc,
+    Core, Data, Inner, Server,
+};
+use jmap::{
+    changes::write::ChangeLog,
+    services::{cluster::EVENT_BUS_TOPIC, state::StateManager},
+    SpawnServices,
+};
+use jmap_proto::types::{collection::Collection, state::StateChange, type_state::DataType};
+use store::{write::log::ChangeLogBuilder, Stores};
+use tokio::sync::mpsc;
+use utils::{config::Config, map::bitmap::Bitmap};
+
+use crate::{directory::internal::TestInternalDirectory, store::TempDir, AssertConfig};
+
+const SERVER: &str = r#"
+[store."sqlite"]
+type = "sqlite"
+path = "{TMP}/sqlite.db"
+
+[store."nats"]
+type = "nats"
+urls = ["nats://127.0.0.1:4222"]
+
+[storage]
+data = "sqlite"
+fts = "sqlite"
+blob = "sqlite"
+lookup = "sqlite"
+directory = "sqlite"
+
+[directory."sqlite"]
+type = "internal"
+store = "sqlite"
+
+[cluster]
+event-bus = "nats"
+
+[tracer.console]
+type = "console"
+level = "{LEVEL}"
+multiline = false
+ansi = true
+"#;
+
+#[tokio::test(flavor = "multi_thread")]
+pub async fn cluster_tests() {
+    let temp_dir = TempDir::new("cluster_tests", true);
+    let node_a = init_node(&temp_dir, true).await;
+    let node_b = init_node(&temp_dir, false).await;
+    let account_id = node_a
+        .core
+        .storage
+        .data
+        .create_test_user("jdoe", "secret", "John Doe", &["jdoe@example.com"])
+        .await;
+
+    // Wait for both nodes to subscribe to the event bus
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    println!("Running cluster state change tests...");
+    let mut changes = node_b
+        .subscribe_state_manager(account_id, Bitmap::all())
+        .await
+        .unwrap();
+    assert!(
+        node_a
+            .broadcast_state_change(
+                StateChange::new(account_id).with_change(DataType::Mailbox, 100)
+            )
+            .await
+    );
+    assert_eq!(
+        next_change(&mut changes).await.types,
+        vec![(DataType::Mailbox, 100)]
+    );
+
+    println!("Running cluster access token invalidation tests...");
+    node_b.get_cached_access_token(account_id).await.unwrap();
+    assert!(node_b.inner.data.access_tokens.contains_key(&account_id));
+    node_a.invalidate_access_tokens(vec![account_id]);
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert!(!node_b.inner.data.access_tokens.contains_key(&account_id));
+
+    println!("Running cluster app password revocation tests...");
+    let mut revocations = node_b.subscribe_app_password_revocations();
+    node_a
+        .revoke_app_passwords(account_id, ["$app$client$hash".to_string()])
+        .await
+        .unwrap();
+    assert_eq!(
+        tokio::time::timeout(Duration::from_secs(1), revocations.recv())
+            .await
+            .unwrap()
+            .unwrap(),
+        account_id
+    );
+
+    println!("Running cluster resync tests...");
+    let change_id = node_a
+        .commit_changes(
+            account_id,
+            ChangeLogBuilder::new().with_log_insert(Collection::Mailbox, 1u64),
+        )
+        .await
+        .unwrap();
+    node_b.get_cached_access_token(account_id).await.unwrap();
+    let pubsub = &node_a.core.storage.pubsub;
+    for (seq, event) in [
+        (
+            1,
+            ClusterEvent::StateChange {
+                account_id,
+                types: vec![(DataType::Email as u8, 200)],
+            },
+        ),
+        (
+            3,
+            ClusterEvent::InvalidateAccessTokens {
+                account_ids: vec![u32::MAX],
+            },
+        ),
+    ] {
+        pubsub
+            .publish(
+                EVENT_BUS_TOPIC,
+                ClusterMessage {
+                    node_id: 0,
+                    seq,
+                    event,
+                }
+                .serialize(),
+            )
+            .await
+            .unwrap();
+    }
+    assert_eq!(
+        next_change(&mut changes).await.types,
+        vec![(DataType::Email, 200)]
+    );
+
+    // The missing message triggers a resync, which clears all cached
+    // access tokens and publishes the latest state of each account
+    let state_change = next_change(&mut changes).await;
+    assert!(
+        state_change.types.contains(&(DataType::Mailbox, change_id)),
+        "{state_change:?}"
+    );
+    assert!(!node_b.inner.data.access_tokens.contains_key(&account_id));
+
+    temp_dir.delete();
+}
+
+async fn next_change(changes: &mut mpsc::Receiver<StateChange>) -> StateChange {
+    tokio::time::timeout(Duration::from_secs(2), changes.recv())
+        .await
+        .expect("timed out waiting for state change")
+        .unwrap()
+}
+
+async fn init_node(temp_dir: &TempDir, destroy: bool) -> Server {
+    let mut config = Config::new(
+        SERVER
+            .replace("{TMP}", &temp_dir.path.display().to_string())
+            .replace(
+                "{LEVEL}",
+                &std::env::var("LOG").unwrap_or_else(|_| "disable".to_string()),
+            ),
+    )
+    .unwrap();
+    config.resolve_all_macros().await;
+
+    let stores = Stores::parse_all(&mut config).await;
+    let tracers = Telemetry::parse(&mut config, &stores);
+    let core = Core::parse(&mut config, stores, Default::default()).await;
+    let data = Data::parse(&mut config);
+    let store = core.storage.data.clone();
+    let (ipc, mut ipc_rxs) = build_ipc();
+    let inner = Arc::new(Inner {
+        shared_core: core.into_shared(),
+        data,
+        ipc,
+    });
+
+    if destroy {
+        tracers.enable(true);
+        store.destroy().await;
+    }
+
+    config.assert_no_errors();
+    ipc_rxs.spawn_services(inner.clone());
+
+    inner.build_server()
+}
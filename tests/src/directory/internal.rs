@@ -20,8 +20,8 @@ use jmap_proto::types::collection::Collection;
 use mail_send::Credentials;
 use store::{
     roaring::RoaringBitmap,
-    write::{BatchBuilder, BitmapClass, ValueClass},
-    BitmapKey, Store, ValueKey,
+    write::{BatchBuilder, BitmapClass, DirectoryClass, ValueClass},
+    BitmapKey, QuotaStatus, Store, ValueKey,
 };
 
 use crate::directory::{DirectoryTest, IntoTestPrincipal, TestPrincipal};
@@ -481,6 +481,32 @@ async fn internal_directory() {
             RcptType::Mailbox
         );
 
+        // Quota usage and limit are read together
+        let mut batch = BatchBuilder::new();
+        batch.add(DirectoryClass::UsedQuota(john_id), 768);
+        store.write(batch.build_batch()).await.unwrap();
+        let quota = store.get_account_quota::<Principal>(john_id).await.unwrap();
+        assert_eq!(quota, QuotaStatus::new(768, 1024));
+        assert!(!quota.over_quota);
+        assert_eq!(quota.percentage(), 75.0);
+        let mut batch = BatchBuilder::new();
+        batch.add(DirectoryClass::UsedQuota(john_id), 512);
+        store.write(batch.build_batch()).await.unwrap();
+        let quota = store.get_account_quota::<Principal>(john_id).await.unwrap();
+        assert_eq!((quota.used, quota.limit), (1280, 1024));
+        assert!(quota.over_quota);
+        assert_eq!(quota.percentage(), 125.0);
+        let mut batch = BatchBuilder::new();
+        batch.add(DirectoryClass::UsedQuota(john_id), -1280);
+        store.write(batch.build_batch()).await.unwrap();
+        assert_eq!(
+            store
+                .get_account_quota::<Principal>(u32::MAX - 1)
+                .await
+                .unwrap(),
+            QuotaStatus::default()
+        );
+
         // Remove a member from a mailing list and then add it back
         assert_eq!(
             store
@@ -11,7 +11,10 @@ pub mod oidc;
 pub mod smtp;
 pub mod sql;
 
-use common::{config::smtp::session::AddressMapping, Core, Server};
+use common::{
+    addresses::SubAddress, config::smtp::session::AddressMapping, expr::if_block::IfBlock, Core,
+    Server,
+};
 use directory::{
     backend::internal::{manage::ManageDirectory, PrincipalField},
     Directories, Principal, Type,
@@ -698,6 +701,62 @@ async fn address_mappings() {
     }
 }
 
+#[tokio::test]
+async fn address_separators() {
+    const ADDR: &str = "john.doe-news+alias@example.org";
+
+    for (separator, expected_sub, expected_detail) in [
+        (
+            "'+'",
+            "john.doe-news@example.org",
+            Some(("john.doe-news", "alias")),
+        ),
+        (
+            "'-'",
+            "john.doe@example.org",
+            Some(("john.doe", "news+alias")),
+        ),
+        ("''", ADDR, None),
+    ] {
+        let mut core = Core::default();
+        core.smtp.session.rcpt.subaddressing_separator =
+            IfBlock::new::<()>("session.rcpt.sub-addressing-separator", [], separator);
+        let server = Server {
+            inner: Default::default(),
+            core: core.into(),
+        };
+
+        for mapping in [AddressMapping::Enable, AddressMapping::Disable] {
+            let is_enabled = matches!(mapping, AddressMapping::Enable);
+
+            assert_eq!(
+                mapping.to_subaddress(&server, ADDR, 0).await,
+                if is_enabled { expected_sub } else { ADDR },
+                "failed subaddress for separator {separator:?}"
+            );
+            assert_eq!(
+                mapping.to_detail(&server, ADDR, 0).await,
+                expected_detail
+                    .filter(|_| is_enabled)
+                    .map(|(user, detail)| SubAddress {
+                        user: user.to_string(),
+                        detail: detail.to_string(),
+                        domain: "example.org".to_string(),
+                    }),
+                "failed detail for separator {separator:?}"
+            );
+        }
+
+        // Separators at the start of the local part are not sub-addresses
+        assert_eq!(
+            AddressMapping::Enable
+                .to_detail(&server, "+news@example.org", 0)
+                .await,
+            None
+        );
+    }
+}
+
 async fn map_account_ids(store: &Store, names: Vec<impl AsRef<str>>) -> Vec<u32> {
     let mut ids = Vec::with_capacity(names.len());
     for name in names {
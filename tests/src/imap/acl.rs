@@ -78,7 +78,7 @@ pub async fn test(mut imap_john: &mut ImapConnection, _imap_check: &mut ImapConn
     imap_jane
         .assert_read(Type::Tagged, ResponseType::Ok)
         .await
-        .assert_equals("* LISTRIGHTS \"INBOX\" \"jdoe@example.com\" r l ws i et k x p a");
+        .assert_equals("* LISTRIGHTS \"INBOX\" \"jdoe@example.com\" r l w s i et k x p a");
 
     // Jane shares her Inbox to John, expect a Shared Folders item in John's list
     imap_jane.send("SETACL INBOX jdoe@example.com lr").await;
@@ -309,4 +309,165 @@ pub async fn test(mut imap_john: &mut ImapConnection, _imap_check: &mut ImapConn
         .assert_read(Type::Tagged, ResponseType::Ok)
         .await
         .assert_count("Shared Folders", 3);
+
+    // Grant John the right to change the seen state only
+    let uid = assert_append_message(
+        &mut imap_jane,
+        "INBOX",
+        "From: jane\n\nseen test",
+        ResponseType::Ok,
+    )
+    .await
+    .into_append_uid();
+    imap_jane.send("SETACL INBOX jdoe@example.com +s").await;
+    imap_jane.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap_jane.send("GETACL INBOX").await;
+    imap_jane
+        .assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("\"jdoe@example.com\" srl");
+    imap_john
+        .send("MYRIGHTS \"Shared Folders/jane.smith@example.com/Inbox\"")
+        .await;
+    imap_john
+        .assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_equals("* MYRIGHTS \"Shared Folders/jane.smith@example.com/Inbox\" rls");
+    imap_john
+        .send("SELECT \"Shared Folders/jane.smith@example.com/Inbox\"")
+        .await;
+    imap_john.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap_john
+        .send(&format!("UID FETCH {} (PREVIEW)", uid))
+        .await;
+    imap_john
+        .assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("seen test");
+
+    // Setting \Seen is allowed, any other flag requires the 'w' right
+    imap_john
+        .send(&format!("UID STORE {} +FLAGS (\\Seen)", uid))
+        .await;
+    imap_john.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap_john
+        .send(&format!("UID STORE {} +FLAGS (\\Flagged)", uid))
+        .await;
+    imap_john.assert_read(Type::Tagged, ResponseType::No).await;
+    imap_john
+        .send(&format!("UID STORE {} FLAGS (\\Seen)", uid))
+        .await;
+    imap_john.assert_read(Type::Tagged, ResponseType::No).await;
+    assert_append_message(
+        imap_john,
+        "Shared Folders/jane.smith@example.com/Inbox",
+        "From: john\n\ncontents",
+        ResponseType::No,
+    )
+    .await;
+
+    // Revoking the right is reflected immediately
+    imap_jane.send("SETACL INBOX jdoe@example.com -s").await;
+    imap_jane.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap_john
+        .send("MYRIGHTS \"Shared Folders/jane.smith@example.com/Inbox\"")
+        .await;
+    imap_john
+        .assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_equals("* MYRIGHTS \"Shared Folders/jane.smith@example.com/Inbox\" rl");
+    imap_john
+        .send(&format!("UID STORE {} -FLAGS (\\Seen)", uid))
+        .await;
+    imap_john.assert_read(Type::Tagged, ResponseType::No).await;
+    imap_john.send("UNSELECT").await;
+    imap_john.assert_read(Type::Tagged, ResponseType::Ok).await;
+
+    // The seen state of shared mailboxes is kept per user
+    imap_jane.send("CREATE Team").await;
+    imap_jane.assert_read(Type::Tagged, ResponseType::Ok).await;
+    let mut uids = Vec::new();
+    for message in ["first", "second"] {
+        uids.push(
+            assert_append_message(
+                &mut imap_jane,
+                "Team",
+                &format!("From: jane\n\n{message}"),
+                ResponseType::Ok,
+            )
+            .await
+            .into_append_uid(),
+        );
+    }
+    imap_jane.send("SETACL Team jdoe@example.com lrs").await;
+    imap_jane.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap_john
+        .send("SELECT \"Shared Folders/jane.smith@example.com/Team\"")
+        .await;
+    imap_john.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap_john
+        .send(&format!("UID STORE {} +FLAGS (\\Seen)", uids[0]))
+        .await;
+    imap_john.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap_jane.send("SELECT Team").await;
+    imap_jane.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap_jane
+        .send(&format!("UID STORE {} +FLAGS (\\Seen)", uids[1]))
+        .await;
+    imap_jane.assert_read(Type::Tagged, ResponseType::Ok).await;
+
+    for (imap, seen_uid, unseen_uid) in [
+        (&mut imap_jane, &uids[1], &uids[0]),
+        (&mut *imap_john, &uids[0], &uids[1]),
+    ] {
+        imap.send(&format!("UID FETCH {seen_uid} (FLAGS)")).await;
+        imap.assert_read(Type::Tagged, ResponseType::Ok)
+            .await
+            .assert_contains("\\Seen");
+        imap.send(&format!("UID FETCH {unseen_uid} (FLAGS)")).await;
+        imap.assert_read(Type::Tagged, ResponseType::Ok)
+            .await
+            .assert_count("\\Seen", 0);
+        imap.send("UID SEARCH UNSEEN").await;
+        imap.assert_read(Type::Tagged, ResponseType::Ok)
+            .await
+            .assert_equals(&format!("* SEARCH {unseen_uid}"));
+        imap.send("UID SEARCH SEEN").await;
+        imap.assert_read(Type::Tagged, ResponseType::Ok)
+            .await
+            .assert_equals(&format!("* SEARCH {seen_uid}"));
+        imap.send("UNSELECT").await;
+        imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    }
+    imap_jane.send("STATUS Team (UNSEEN)").await;
+    imap_jane
+        .assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("(UNSEEN 1)");
+    imap_john
+        .send("STATUS \"Shared Folders/jane.smith@example.com/Team\" (UNSEEN)")
+        .await;
+    imap_john
+        .assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("(UNSEEN 1)");
+
+    // Revoking access removes the private flags
+    imap_jane.send("SETACL Team jdoe@example.com -r").await;
+    imap_jane.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap_jane.send("SETACL Team jdoe@example.com +r").await;
+    imap_jane.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap_john
+        .send("SELECT \"Shared Folders/jane.smith@example.com/Team\"")
+        .await;
+    imap_john.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap_john.send("UID SEARCH SEEN").await;
+    imap_john
+        .assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_equals("* SEARCH");
+    imap_john.send("UNSELECT").await;
+    imap_john.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap_jane.send("DELETE Team").await;
+    imap_jane.assert_read(Type::Tagged, ResponseType::Ok).await;
 }
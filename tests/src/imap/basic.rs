@@ -4,7 +4,8 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use common::auth::sasl::sasl_decode_challenge_oauth;
+use common::auth::sasl::{sasl_decode_challenge_oauth, sasl_encode_challenge};
+use directory::core::scram::{hmac_md5, ScramAlgorithm};
 use imap_proto::ResponseType;
 use mail_parser::decoders::base64::base64_decode;
 use mail_send::Credentials;
@@ -37,6 +38,100 @@ pub async fn test(imap: &mut ImapConnection, _imap_check: &mut ImapConnection) {
     imap.assert_read(Type::Continuation, ResponseType::Ok).await;
     imap.send_untagged("AGJvYXR5AG1jYm9hdGZhY2U=").await;
     imap.assert_read(Type::Tagged, ResponseType::No).await;
+
+    // SCRAM-SHA-256 with a tampered proof should fail
+    let server_first = scram_start(imap).await;
+    let (client_final, _) = scram_client_final(&server_first, "secret", true);
+    imap.send_untagged(&client_final).await;
+    imap.assert_read(Type::Tagged, ResponseType::No).await;
+
+    // Full SCRAM-SHA-256 exchange
+    let server_first = scram_start(imap).await;
+    let (client_final, server_signature) = scram_client_final(&server_first, "secret", false);
+    imap.send_untagged(&client_final).await;
+    let server_final = challenge(imap.assert_read(Type::Continuation, ResponseType::Ok).await);
+    assert_eq!(server_final, server_signature);
+    imap.send_untagged("").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.send("UNAUTHENTICATE").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+
+    // CRAM-MD5
+    for (password, response) in [("wrong", ResponseType::No), ("secret", ResponseType::Ok)] {
+        imap.send("AUTHENTICATE CRAM-MD5").await;
+        let challenge = challenge(imap.assert_read(Type::Continuation, ResponseType::Ok).await);
+        let digest = hmac_md5(password.as_bytes(), challenge.as_bytes())
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<String>();
+        imap.send_untagged(&sasl_encode_challenge(
+            format!("jdoe@example.com {digest}").as_bytes(),
+        ))
+        .await;
+        imap.assert_read(Type::Tagged, response).await;
+    }
+    imap.send("UNAUTHENTICATE").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+}
+
+const SCRAM_CLIENT_FIRST: &str = "n=jdoe@example.com,r=rOprNGfwEbeRWgbNEkqO";
+
+async fn scram_start(imap: &mut ImapConnection) -> String {
+    imap.send("AUTHENTICATE SCRAM-SHA-256").await;
+    imap.assert_read(Type::Continuation, ResponseType::Ok).await;
+    imap.send_untagged(&sasl_encode_challenge(
+        format!("n,,{SCRAM_CLIENT_FIRST}").as_bytes(),
+    ))
+    .await;
+    challenge(imap.assert_read(Type::Continuation, ResponseType::Ok).await)
+}
+
+fn scram_client_final(server_first: &str, password: &str, tamper: bool) -> (String, String) {
+    let algorithm = ScramAlgorithm::Sha256;
+    let mut nonce = "";
+    let mut salt = Vec::new();
+    let mut iterations = 0;
+    for attribute in server_first.split(',') {
+        match attribute.split_once('=').unwrap() {
+            ("r", value) => nonce = value,
+            ("s", value) => salt = base64_decode(value.as_bytes()).unwrap(),
+            ("i", value) => iterations = value.parse().unwrap(),
+            _ => (),
+        }
+    }
+    assert!(nonce.starts_with("rOprNGfwEbeRWgbNEkqO"));
+
+    let without_proof = format!("c=biws,r={nonce}");
+    let auth_message = format!("{SCRAM_CLIENT_FIRST},{server_first},{without_proof}");
+    let salted_password = algorithm.salted_password(password, &salt, iterations);
+    let client_key = algorithm.hmac(&salted_password, b"Client Key");
+    let client_signature = algorithm.hmac(&algorithm.hash(&client_key), auth_message.as_bytes());
+    let mut proof = client_key
+        .iter()
+        .zip(client_signature)
+        .map(|(a, b)| a ^ b)
+        .collect::<Vec<_>>();
+    if tamper {
+        proof[0] ^= 0xff;
+    }
+    let server_key = algorithm.hmac(&salted_password, b"Server Key");
+
+    (
+        sasl_encode_challenge(
+            format!("{without_proof},p={}", sasl_encode_challenge(&proof)).as_bytes(),
+        ),
+        format!(
+            "v={}",
+            sasl_encode_challenge(&algorithm.hmac(&server_key, auth_message.as_bytes()))
+        ),
+    )
+}
+
+fn challenge(lines: Vec<String>) -> String {
+    String::from_utf8(
+        base64_decode(lines.last().unwrap().strip_prefix("+ ").unwrap().as_bytes()).unwrap(),
+    )
+    .unwrap()
 }
 
 #[test]
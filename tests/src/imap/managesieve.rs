@@ -138,6 +138,114 @@ pub async fn test() {
         .await
         .assert_count("minimalist script", 0)
         .assert_count("holidays", 0);
+
+    // Script names are stored in NFC form
+    sieve.send("PUTSCRIPT \"Cafe\u{301}\" \"keep;\"").await;
+    sieve.assert_read(ResponseType::Ok).await;
+    sieve.send("PUTSCRIPT \"Caf\u{e9}\" \"discard;\"").await;
+    sieve.assert_read(ResponseType::Ok).await;
+    sieve.send("GETSCRIPT \"Cafe\u{301}\"").await;
+    sieve
+        .assert_read(ResponseType::Ok)
+        .await
+        .assert_contains("discard;");
+    sieve.send("LISTSCRIPTS").await;
+    sieve
+        .assert_read(ResponseType::Ok)
+        .await
+        .assert_count("Caf\u{e9}", 1);
+    sieve.send("PUTSCRIPT \"bad\u{7f}name\" \"keep;\"").await;
+    sieve.assert_read(ResponseType::No).await;
+
+    // Renaming the active script keeps it active
+    sieve.send("SETACTIVE \"Caf\u{e9}\"").await;
+    sieve.assert_read(ResponseType::Ok).await;
+    sieve
+        .send("RENAMESCRIPT \"Cafe\u{301}\" \"Caf\u{e9} au lait\"")
+        .await;
+    sieve.assert_read(ResponseType::Ok).await;
+    sieve.send("LISTSCRIPTS").await;
+    sieve
+        .assert_read(ResponseType::Ok)
+        .await
+        .assert_contains("Caf\u{e9} au lait\" ACTIVE")
+        .assert_count("ACTIVE", 1);
+    sieve
+        .send("RENAMESCRIPT \"Caf\u{e9} au lait\" \"outbound\"")
+        .await;
+    sieve
+        .assert_read(ResponseType::No)
+        .await
+        .assert_contains("ACTIVE");
+    sieve.send("RENAMESCRIPT \"dummy\" \"other\"").await;
+    sieve
+        .assert_read(ResponseType::No)
+        .await
+        .assert_contains("NONEXISTENT");
+    sieve.send("SETACTIVE \"\"").await;
+    sieve.assert_read(ResponseType::Ok).await;
+    sieve.send("DELETESCRIPT \"Caf\u{e9} au lait\"").await;
+    sieve.assert_read(ResponseType::Ok).await;
+
+    // Quota and script limits
+    let mut sieve = SieveConnection::connect().await;
+    sieve.assert_read(ResponseType::Ok).await;
+    sieve
+        .send("AUTHENTICATE \"PLAIN\" \"AHNpZXZlQGV4YW1wbGUuY29tAHNlY3JldA==\"")
+        .await;
+    sieve.assert_read(ResponseType::Ok).await;
+    let large_script = format!("# {}\r\nkeep;\r\n", "x".repeat(600));
+    sieve.send("HAVESPACE \"large\" 600").await;
+    sieve
+        .assert_read(ResponseType::No)
+        .await
+        .assert_contains("(QUOTA)");
+    sieve.send("HAVESPACE \"large\" 2000000").await;
+    sieve
+        .assert_read(ResponseType::No)
+        .await
+        .assert_contains("QUOTA/MAXSIZE");
+    sieve
+        .send_literal("PUTSCRIPT \"large\" ", &large_script)
+        .await;
+    sieve
+        .assert_read(ResponseType::No)
+        .await
+        .assert_contains("(QUOTA)");
+    sieve.send_literal("CHECKSCRIPT ", &large_script).await;
+    sieve
+        .assert_read(ResponseType::Ok)
+        .await
+        .assert_contains("WARNINGS");
+    for name in ["one", "two", "three"] {
+        sieve.send("HAVESPACE \"small\" 5").await;
+        sieve.assert_read(ResponseType::Ok).await;
+        sieve.send(&format!("PUTSCRIPT \"{name}\" \"keep;\"")).await;
+        sieve.assert_read(ResponseType::Ok).await;
+    }
+    sieve.send("HAVESPACE \"four\" 5").await;
+    sieve
+        .assert_read(ResponseType::No)
+        .await
+        .assert_contains("QUOTA/MAXSCRIPTS");
+    sieve.send("PUTSCRIPT \"four\" \"keep;\"").await;
+    sieve
+        .assert_read(ResponseType::No)
+        .await
+        .assert_contains("QUOTA/MAXSCRIPTS");
+    sieve.send("CHECKSCRIPT \"keep;\"").await;
+    sieve
+        .assert_read(ResponseType::Ok)
+        .await
+        .assert_contains("WARNINGS");
+    sieve.send("HAVESPACE \"one\" 5").await;
+    sieve.assert_read(ResponseType::Ok).await;
+    sieve.send("PUTSCRIPT \"one\" \"discard;\"").await;
+    sieve.assert_read(ResponseType::Ok).await;
+    for name in ["one", "two", "three"] {
+        sieve.send(&format!("DELETESCRIPT \"{name}\"")).await;
+        sieve.assert_read(ResponseType::Ok).await;
+    }
 }
 
 pub struct SieveConnection {
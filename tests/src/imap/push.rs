@@ -0,0 +1,135 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{sync::Arc, time::Duration};
+
+use hyper::StatusCode;
+use imap_proto::ResponseType;
+use jmap::api::{http::ToHttpResponse, HtmlResponse};
+use tokio::sync::mpsc;
+
+use crate::{
+    http_server::{spawn_mock_http_server, HttpMessage},
+    jmap::delivery::SmtpConnection,
+};
+
+use super::{AssertResult, ImapConnection, Type};
+
+pub async fn test(imap: &mut ImapConnection) {
+    println!("Running XAPPLEPUSHSERVICE tests...");
+
+    // Spawn mock APNs and webhook server
+    let (event_tx, mut event_rx) = mpsc::unbounded_channel::<HttpMessage>();
+    let _tx = spawn_mock_http_server(Arc::new(move |req: HttpMessage| {
+        let status = if req.uri.path() == "/3/device/dead-token" {
+            StatusCode::GONE
+        } else {
+            StatusCode::OK
+        };
+        event_tx.send(req).unwrap();
+        HtmlResponse::with_status(status, String::new()).into_http_response()
+    }))
+    .await;
+
+    // Capability should be advertised once authenticated
+    imap.send("CAPABILITY").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("XAPPLEPUSHSERVICE");
+
+    // Register devices
+    for token in ["live-token", "dead-token"] {
+        imap.send(&format!(
+            concat!(
+                "XAPPLEPUSHSERVICE aps-version 2 aps-account-id ACCOUNT-1234 ",
+                "aps-device-token {} aps-subtopic com.apple.mobilemail ",
+                "mailboxes (INBOX \"Does not exist\")"
+            ),
+            token
+        ))
+        .await;
+        imap.assert_read(Type::Tagged, ResponseType::Ok)
+            .await
+            .assert_contains(
+                "* XAPPLEPUSHSERVICE aps-version \"2\" aps-topic \"com.example.mail\"",
+            );
+    }
+
+    // Unsupported versions are rejected
+    imap.send("XAPPLEPUSHSERVICE aps-version 3 aps-account-id 1 aps-device-token 2")
+        .await;
+    imap.assert_read(Type::Tagged, ResponseType::Bad).await;
+
+    // New mail should be pushed to both devices and to the webhook
+    deliver_message().await;
+    let events = expect_events(&mut event_rx, 3).await;
+    for (event, token) in events.iter().zip(["live-token", "dead-token"]) {
+        assert_eq!(event.uri.path(), format!("/3/device/{token}"));
+        assert_eq!(event.headers.get("apns-topic").unwrap(), "com.example.mail");
+        assert!(event
+            .headers
+            .get("authorization")
+            .unwrap()
+            .starts_with("Bearer "));
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(event.body.as_ref().unwrap()).unwrap(),
+            serde_json::json!({ "aps": { "account-id": "ACCOUNT-1234" } })
+        );
+    }
+    let webhook = &events[2];
+    assert_eq!(webhook.uri.path(), "/new-mail");
+    assert!(webhook.headers.contains_key("x-signature"));
+    let event =
+        serde_json::from_slice::<serde_json::Value>(webhook.body.as_ref().unwrap()).unwrap();
+    assert_eq!(event["@type"], "NewMail");
+    assert_eq!(event["accountName"], "jdoe@example.com");
+    assert_eq!(event["mailboxName"], "Inbox");
+    assert!(event["unread"].as_u64().unwrap() > 0);
+
+    // Devices no longer registered with APNs should have been removed
+    deliver_message().await;
+    let events = expect_events(&mut event_rx, 2).await;
+    assert_eq!(events[0].uri.path(), "/3/device/live-token");
+    assert_eq!(events[1].uri.path(), "/new-mail");
+    expect_no_events(&mut event_rx).await;
+}
+
+async fn deliver_message() {
+    let mut lmtp = SmtpConnection::connect_port(11201).await;
+    lmtp.ingest(
+        "bill@example.com",
+        &["jdoe@example.com"],
+        concat!(
+            "From: bill@example.com\r\n",
+            "To: jdoe@example.com\r\n",
+            "Subject: TPS Report\r\n",
+            "X-Spam-Status: No\r\n",
+            "\r\n",
+            "I'm going to need those TPS reports ASAP.\r\n"
+        ),
+    )
+    .await;
+}
+
+async fn expect_events(
+    event_rx: &mut mpsc::UnboundedReceiver<HttpMessage>,
+    count: usize,
+) -> Vec<HttpMessage> {
+    let mut events = Vec::with_capacity(count);
+    while events.len() < count {
+        match tokio::time::timeout(Duration::from_secs(5), event_rx.recv()).await {
+            Ok(Some(event)) => events.push(event),
+            result => panic!("Expected {count} push events, got {events:?}: {result:?}"),
+        }
+    }
+    events
+}
+
+async fn expect_no_events(event_rx: &mut mpsc::UnboundedReceiver<HttpMessage>) {
+    if let Ok(event) = tokio::time::timeout(Duration::from_millis(500), event_rx.recv()).await {
+        panic!("Unexpected push event: {event:?}");
+    }
+}
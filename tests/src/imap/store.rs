@@ -4,7 +4,9 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+use common::Server;
 use imap_proto::ResponseType;
+use nlp::bayes::{TokenHash, Weights};
 
 use crate::jmap::wait_for_index;
 
@@ -74,4 +76,28 @@ pub async fn test(imap: &mut ImapConnection, _imap_check: &mut ImapConnection, h
         .await
         .assert_count("FLAGS", 3)
         .assert_count("Answered", 0);
+
+    // Flagging a message as $Junk trains the spam filter, removing the flag reverts it
+    let learns = spam_learns(&handle.server).await;
+    imap.send("UID STORE 1 +FLAGS ($Junk)").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("$Junk");
+    assert_eq!(
+        spam_learns(&handle.server).await,
+        Weights {
+            spam: learns.spam + 1,
+            ham: learns.ham
+        }
+    );
+    imap.send("UID STORE 1 -FLAGS ($Junk)").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    assert_eq!(spam_learns(&handle.server).await, learns);
+}
+
+async fn spam_learns(server: &Server) -> Weights {
+    server
+        .bayes_weights(server.bayes_store(), None, TokenHash::default())
+        .await
+        .unwrap()
 }
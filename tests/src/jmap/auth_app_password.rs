@@ -0,0 +1,148 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use base64::{engine::general_purpose, Engine};
+use imap_proto::ResponseType;
+use jmap::api::management::principal::AccountAuthResponse;
+use jmap_proto::types::id::Id;
+use serde_json::json;
+
+use crate::{
+    directory::internal::TestInternalDirectory,
+    imap::{ImapConnection, Type},
+    jmap::{
+        assert_is_empty, delivery::SmtpConnection, mailbox::destroy_all_mailboxes, ManagementApi,
+    },
+};
+
+use super::JMAPTest;
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running App Password tests...");
+
+    // Create test account
+    let server = params.server.clone();
+    let account_id = Id::from(
+        server
+            .core
+            .storage
+            .data
+            .create_test_user(
+                "app.user@example.com",
+                "primary-pass",
+                "App User",
+                &["app.user@example.com"],
+            )
+            .await,
+    )
+    .to_string();
+    let api = ManagementApi::new(8899, "app.user@example.com", "primary-pass");
+
+    // Create an IMAP-only app password
+    let response = api
+        .post::<serde_json::Value>(
+            "/api/account/auth",
+            &json!([{"type": "addAppPassword", "name": "mail-client", "protocols": ["imap"]}]),
+        )
+        .await
+        .unwrap()
+        .unwrap_data();
+    let app_password = response["appPasswords"]["mail-client"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    assert_eq!(app_password.len(), 24);
+
+    // Only a hash of the password is stored
+    let auth = api
+        .get::<AccountAuthResponse>("/api/account/auth")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(auth.app_passwords, vec!["mail-client".to_string()]);
+    assert_eq!(auth.app_password_details[0].protocols, vec!["imap"]);
+    assert_eq!(auth.app_password_details[0].last_used, None);
+
+    // Duplicate names and unknown protocols are rejected
+    for request in [
+        json!([{"type": "addAppPassword", "name": "mail-client"}]),
+        json!([{"type": "addAppPassword", "name": "other", "protocols": ["gopher"]}]),
+    ] {
+        api.post::<()>("/api/account/auth", &request)
+            .await
+            .unwrap()
+            .unwrap_error();
+    }
+
+    // Authenticate over IMAP using the app password
+    let sasl_plain =
+        general_purpose::STANDARD.encode(format!("\u{0}app.user@example.com\u{0}{app_password}"));
+    let mut imap = ImapConnection::connect(b"_x ").await;
+    imap.assert_read(Type::Untagged, ResponseType::Ok).await;
+    imap.send(&format!("AUTHENTICATE PLAIN {sasl_plain}")).await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    let auth = api
+        .get::<AccountAuthResponse>("/api/account/auth")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert!(auth.app_password_details[0].last_used.is_some());
+
+    // The app password is restricted to IMAP
+    let mut smtp = SmtpConnection::connect().await;
+    smtp.send(&format!("AUTH PLAIN {sasl_plain}")).await;
+    smtp.read(1, 5).await;
+
+    // Start a second session waiting in IDLE
+    let mut idle = ImapConnection::connect(b"_w ").await;
+    idle.assert_read(Type::Untagged, ResponseType::Ok).await;
+    idle.send(&format!("AUTHENTICATE PLAIN {sasl_plain}")).await;
+    idle.assert_read(Type::Tagged, ResponseType::Ok).await;
+    idle.send("IDLE").await;
+    idle.assert_read(Type::Continuation, ResponseType::Ok).await;
+
+    // Revoking the app password terminates existing sessions without waiting for a command
+    api.post::<()>(
+        "/api/account/auth",
+        &json!([{"type": "removeAppPassword", "name": "mail-client", "killSessions": true}]),
+    )
+    .await
+    .unwrap()
+    .unwrap_data();
+    for imap in [&mut imap, &mut idle] {
+        imap.assert_read(Type::Untagged, ResponseType::Bye).await;
+        imap.assert_disconnect().await;
+    }
+
+    // New sessions are rejected
+    let mut imap = ImapConnection::connect(b"_y ").await;
+    imap.assert_read(Type::Untagged, ResponseType::Ok).await;
+    imap.send(&format!("AUTHENTICATE PLAIN {sasl_plain}")).await;
+    imap.assert_read(Type::Tagged, ResponseType::No).await;
+    let auth = api
+        .get::<AccountAuthResponse>("/api/account/auth")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert!(auth.app_passwords.is_empty());
+
+    // Sessions using the primary password are not affected
+    let mut imap = ImapConnection::connect(b"_z ").await;
+    imap.assert_read(Type::Untagged, ResponseType::Ok).await;
+    imap.send(&format!(
+        "AUTHENTICATE PLAIN {}",
+        general_purpose::STANDARD.encode("\u{0}app.user@example.com\u{0}primary-pass")
+    ))
+    .await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.send("NOOP").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+
+    // Remove test data
+    params.client.set_default_account_id(&account_id);
+    destroy_all_mailboxes(params).await;
+    assert_is_empty(server).await;
+}
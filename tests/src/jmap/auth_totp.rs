@@ -0,0 +1,184 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use base64::{engine::general_purpose, Engine};
+use directory::backend::internal::{PrincipalField, PrincipalUpdate, PrincipalValue};
+use imap_proto::ResponseType;
+use jmap::api::management::principal::AccountAuthResponse;
+use jmap_proto::types::id::Id;
+use serde_json::json;
+use store::write::now;
+use totp_rs::TOTP;
+
+use crate::{
+    directory::internal::TestInternalDirectory,
+    imap::{ImapConnection, Type},
+    jmap::{assert_is_empty, mailbox::destroy_all_mailboxes, ManagementApi},
+};
+
+use super::JMAPTest;
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running TOTP tests...");
+
+    // Create test account
+    let server = params.server.clone();
+    let account_id = Id::from(
+        server
+            .core
+            .storage
+            .data
+            .create_test_user(
+                "totp.user@example.com",
+                "totp-pass",
+                "TOTP User",
+                &["totp.user@example.com"],
+            )
+            .await,
+    )
+    .to_string();
+    let api = ManagementApi::new(8899, "totp.user@example.com", "totp-pass");
+
+    // Generated secrets are not enabled until a valid code is provided
+    let response = api
+        .post::<serde_json::Value>("/api/account/auth", &json!([{"type": "generateOtpAuth"}]))
+        .await
+        .unwrap()
+        .unwrap_data();
+    let totp = TOTP::from_url(response["otpUrl"].as_str().unwrap()).unwrap();
+    let auth = api
+        .get::<AccountAuthResponse>("/api/account/auth")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert!(!auth.otp_auth);
+    let expired_code = totp.generate(now() - 120);
+    if expired_code != totp.generate(now()) {
+        api.post::<()>(
+            "/api/account/auth",
+            &json!([{"type": "verifyOtpAuth", "code": expired_code}]),
+        )
+        .await
+        .unwrap()
+        .expect_error("Invalid TOTP code");
+    }
+
+    // Verifying the code enables TOTP and returns the recovery codes
+    let code = totp.generate(now());
+    let response = api
+        .post::<serde_json::Value>(
+            "/api/account/auth",
+            &json!([{"type": "verifyOtpAuth", "code": code}]),
+        )
+        .await
+        .unwrap()
+        .unwrap_data();
+    let recovery_codes = response["recoveryCodes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|code| code.as_str().unwrap().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(recovery_codes.len(), 10);
+
+    // The password alone is no longer enough
+    api.get::<AccountAuthResponse>("/api/account/auth")
+        .await
+        .unwrap()
+        .expect_request_error("TOTP code required");
+
+    // Codes that were already accepted cannot be replayed
+    imap_login(&format!("totp-pass${code}"), ResponseType::No).await;
+    let code = totp.generate(now() + 30);
+    assert_eq!(concurrent_logins(&format!("totp-pass${code}")).await, 1);
+    imap_login(&format!("totp-pass${code}"), ResponseType::No).await;
+
+    // Recovery codes can only be used once
+    let api = ManagementApi::new(
+        8899,
+        "totp.user@example.com",
+        &format!("totp-pass${}", recovery_codes[0]),
+    );
+    let auth = api
+        .get::<AccountAuthResponse>("/api/account/auth")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert!(auth.otp_auth);
+    assert_eq!(auth.recovery_codes, 9);
+    imap_login(
+        &format!("totp-pass${}", recovery_codes[0]),
+        ResponseType::No,
+    )
+    .await;
+    imap_login(
+        &format!("totp-pass${}", recovery_codes[1]),
+        ResponseType::Ok,
+    )
+    .await;
+    assert_eq!(
+        concurrent_logins(&format!("totp-pass${}", recovery_codes[2])).await,
+        1
+    );
+
+    // Administrators can reset TOTP, which also removes the recovery codes
+    ManagementApi::new(8899, "admin", "secret")
+        .patch::<()>(
+            "/api/principal/totp.user@example.com",
+            &vec![PrincipalUpdate::remove_item(
+                PrincipalField::Secrets,
+                PrincipalValue::String("otpauth://".to_string()),
+            )],
+        )
+        .await
+        .unwrap()
+        .unwrap_data();
+    let auth = ManagementApi::new(8899, "totp.user@example.com", "totp-pass")
+        .get::<AccountAuthResponse>("/api/account/auth")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert!(!auth.otp_auth);
+    assert_eq!(auth.recovery_codes, 0);
+    imap_login("totp-pass", ResponseType::Ok).await;
+
+    // Remove test data
+    params.client.set_default_account_id(&account_id);
+    destroy_all_mailboxes(params).await;
+    assert_is_empty(server).await;
+}
+
+async fn imap_login(secret: &str, expected: ResponseType) {
+    let mut imap = ImapConnection::connect(b"_x ").await;
+    imap.assert_read(Type::Untagged, ResponseType::Ok).await;
+    imap.send(&format!(
+        "AUTHENTICATE PLAIN {}",
+        general_purpose::STANDARD.encode(format!("\u{0}totp.user@example.com\u{0}{secret}"))
+    ))
+    .await;
+    imap.assert_read(Type::Tagged, expected).await;
+}
+
+// Returns how many of several simultaneous logins with the same secret succeed
+async fn concurrent_logins(secret: &str) -> usize {
+    futures::future::join_all((0..3).map(|_| async {
+        let mut imap = ImapConnection::connect(b"_x ").await;
+        imap.assert_read(Type::Untagged, ResponseType::Ok).await;
+        imap.send(&format!(
+            "AUTHENTICATE PLAIN {}",
+            general_purpose::STANDARD.encode(format!("\u{0}totp.user@example.com\u{0}{secret}"))
+        ))
+        .await;
+        imap.read(Type::Tagged)
+            .await
+            .last()
+            .is_some_and(|line| line.starts_with("_x OK"))
+    }))
+    .await
+    .into_iter()
+    .filter(|is_ok| *is_ok)
+    .count()
+}
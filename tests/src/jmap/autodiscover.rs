@@ -0,0 +1,229 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{collections::BTreeMap, path::PathBuf, sync::Arc, time::Duration};
+
+use reqwest::{Method, StatusCode};
+
+use crate::directory::internal::TestInternalDirectory;
+
+use super::JMAPTest;
+
+const REQUEST_OUTLOOK: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<Autodiscover xmlns="http://schemas.microsoft.com/exchange/autodiscover/outlook/requestschema/2006">
+    <Request>
+        <EMailAddress>{EMAIL}</EMailAddress>
+        <AcceptableResponseSchema>http://schemas.microsoft.com/exchange/autodiscover/outlook/responseschema/2006a</AcceptableResponseSchema>
+    </Request>
+</Autodiscover>"#;
+
+const REQUEST_MOBILESYNC: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<Autodiscover xmlns="http://schemas.microsoft.com/exchange/autodiscover/mobilesync/requestschema/2006">
+    <Request>
+        <EMailAddress>{EMAIL}</EMailAddress>
+        <AcceptableResponseSchema>http://schemas.microsoft.com/exchange/autodiscover/mobilesync/responseschema/2006</AcceptableResponseSchema>
+    </Request>
+</Autodiscover>"#;
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running Autodiscover tests...");
+    let server = params.server.clone();
+
+    // Create test account with an alias at a second domain
+    server
+        .core
+        .storage
+        .data
+        .create_test_user(
+            "jdoe@example.com",
+            "secret",
+            "John Doe",
+            &["jdoe@example.com", "john.doe@example.net"],
+        )
+        .await;
+
+    // Publish listeners and hostnames
+    let cfg_local = server.core.storage.config.cfg_local.load_full();
+    let mut config = BTreeMap::new();
+    for (id, protocol, port, is_tls) in [
+        ("imaps", "imap", 993, true),
+        ("imap", "imap", 143, false),
+        ("submissions", "smtp", 465, true),
+        ("submission", "smtp", 587, false),
+        ("smtp", "smtp", 25, false),
+        ("pop3s", "pop3", 995, true),
+        ("https", "http", 443, true),
+    ] {
+        config.insert(
+            format!("server.listener.{id}.protocol"),
+            protocol.to_string(),
+        );
+        config.insert(format!("server.listener.{id}.bind"), format!("[::]:{port}"));
+        if is_tls {
+            config.insert(
+                format!("server.listener.{id}.tls.implicit"),
+                "true".to_string(),
+            );
+        }
+    }
+    config.insert(
+        "lookup.default.hostname".to_string(),
+        "mail.example.com".to_string(),
+    );
+    config.insert(
+        "autoconfig.example.net.hostname".to_string(),
+        "mx.example.net".to_string(),
+    );
+    server.core.storage.config.cfg_local.store(Arc::new(config));
+
+    // Mozilla autoconfig
+    for path in [
+        "/mail/config-v1.1.xml?emailaddress=jdoe%40example.com",
+        "/.well-known/autoconfig/mail/config-v1.1.xml?emailaddress=jdoe%40example.com",
+    ] {
+        let (status, body) = request(Method::GET, path, "").await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_fixture("autoconfig.xml", &body);
+    }
+
+    // Per-domain hostname override
+    let (status, body) = request(
+        Method::GET,
+        "/mail/config-v1.1.xml?emailaddress=john.doe%40example.net",
+        "",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_fixture("autoconfig_override.xml", &body);
+
+    // Outlook autodiscover
+    let (status, body) = autodiscover(REQUEST_OUTLOOK, "jdoe@example.com").await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_fixture("outlook.xml", &body);
+
+    // Aliases are redirected to the primary address
+    let (status, body) = autodiscover(REQUEST_OUTLOOK, "john.doe@example.net").await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_fixture("outlook_redirect.xml", &body);
+
+    // ActiveSync clients
+    let (status, body) = autodiscover(REQUEST_MOBILESYNC, "jdoe@example.com").await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_fixture("mobilesync.xml", &body);
+    let (status, body) = autodiscover(REQUEST_MOBILESYNC, "john.doe@example.net").await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_fixture("mobilesync_redirect.xml", &body);
+
+    // Addresses are escaped in the response
+    let (status, body) = autodiscover(REQUEST_MOBILESYNC, "&lt;b&gt;jdoe&amp;@example.com").await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert!(
+        body.contains("<EMailAddress>&lt;b&gt;jdoe&amp;@example.com</EMailAddress>")
+            && !body.contains("<b>"),
+        "{body}"
+    );
+
+    // Domains not hosted here are not disclosed
+    for request in [REQUEST_OUTLOOK, REQUEST_MOBILESYNC] {
+        let (status, body) = autodiscover(request, "jdoe@unknown-domain.org").await;
+        assert_eq!(status, StatusCode::NOT_FOUND, "{body}");
+    }
+    let (status, _) = request(
+        Method::GET,
+        "/mail/config-v1.1.xml?emailaddress=jdoe%40unknown-domain.org",
+        "",
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // Malformed requests
+    let (status, _) = request(
+        Method::POST,
+        "/autodiscover/autodiscover.xml",
+        "<Request><EMailAddress>jdoe@example.com</EMailAddress></Request>",
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // Outlook autodiscover v2
+    for path in [
+        "/autodiscover/autodiscover.json?Email=jdoe%40example.com&Protocol=AutodiscoverV1",
+        "/autodiscover/autodiscover.json/v1.0/jdoe%40example.com?Protocol=AutodiscoverV1",
+    ] {
+        let (status, body) = request(Method::GET, path, "").await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&body).unwrap(),
+            serde_json::json!({
+                "Protocol": "AutodiscoverV1",
+                "Url": "https://mail.example.com/autodiscover/autodiscover.xml"
+            })
+        );
+    }
+    let (status, body) = request(
+        Method::GET,
+        "/autodiscover/autodiscover.json/v1.0/john.doe%40example.net?Protocol=AutodiscoverV1",
+        "",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert!(body.contains("https://mx.example.net/autodiscover/autodiscover.xml"));
+    let (status, body) = request(
+        Method::GET,
+        "/autodiscover/autodiscover.json?Email=jdoe%40example.com&Protocol=ActiveSync",
+        "",
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body.contains("InvalidProtocol"), "{body}");
+    let (status, _) = request(
+        Method::GET,
+        "/autodiscover/autodiscover.json?Email=jdoe%40unknown-domain.org&Protocol=AutodiscoverV1",
+        "",
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // Restore local configuration
+    server.core.storage.config.cfg_local.store(cfg_local);
+}
+
+async fn autodiscover(template: &str, email: &str) -> (StatusCode, String) {
+    request(
+        Method::POST,
+        "/autodiscover/autodiscover.xml",
+        &template.replace("{EMAIL}", email),
+    )
+    .await
+}
+
+async fn request(method: Method, path: &str, body: &str) -> (StatusCode, String) {
+    let response = reqwest::Client::builder()
+        .danger_accept_invalid_certs(true)
+        .timeout(Duration::from_secs(60))
+        .build()
+        .unwrap()
+        .request(method, format!("https://127.0.0.1:8899{path}"))
+        .body(body.to_string())
+        .send()
+        .await
+        .unwrap();
+    (response.status(), response.text().await.unwrap())
+}
+
+fn assert_fixture(name: &str, body: &str) {
+    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    path.push("resources");
+    path.push("jmap");
+    path.push("autodiscover");
+    path.push(name);
+
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        body,
+        "fixture {name} does not match"
+    );
+}
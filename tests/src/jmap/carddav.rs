@@ -0,0 +1,547 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::{Duration, Instant};
+
+use base64::{engine::general_purpose, Engine};
+use common::auth::ResourceToken;
+use futures::future::join_all;
+use jmap::dav::addressbook::{AddressBook, AddressBookStore, ContactCard, PutResult};
+use reqwest::{header, Method, StatusCode};
+
+use crate::{directory::internal::TestInternalDirectory, jmap::assert_is_empty};
+
+use super::JMAPTest;
+
+const ACCOUNT: &str = "jane.smith@example.com";
+const SECRET: &str = "this is a secret";
+const HOME: &str = "/dav/card/jane.smith@example.com/";
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running CardDAV tests...");
+    let server = params.server.clone();
+    let account_id = server
+        .core
+        .storage
+        .data
+        .create_test_user(ACCOUNT, SECRET, "Jane Smith", &[ACCOUNT])
+        .await;
+
+    // Discovery
+    let response = dav_request(Method::OPTIONS, "/dav/card/", "", &[], None).await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert!(response.header("dav").contains("addressbook"));
+    let response = dav_request(Method::GET, "/.well-known/carddav", "", &[], None).await;
+    assert_eq!(response.status, StatusCode::MOVED_PERMANENTLY);
+    assert_eq!(response.header("location"), "/dav/card/");
+    let response = dav_request(
+        Method::from_bytes(b"PROPFIND").unwrap(),
+        HOME,
+        "",
+        &[],
+        None,
+    )
+    .await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    assert!(response.header("www-authenticate").starts_with("Basic"));
+    let response = propfind(
+        "/dav/card/",
+        "0",
+        r#"<D:propfind xmlns:D="DAV:"><D:prop><D:current-user-principal/></D:prop></D:propfind>"#,
+    )
+    .await;
+    assert_eq!(response.status, StatusCode::MULTI_STATUS);
+    assert_eq!(xml_values(&response.body, "D:href")[1], HOME);
+
+    // A default address book is created on first access
+    let response = propfind(
+        HOME,
+        "1",
+        r#"<D:propfind xmlns:D="DAV:" xmlns:C="urn:ietf:params:xml:ns:carddav"><D:prop><C:addressbook-home-set/><D:resourcetype/></D:prop></D:propfind>"#,
+    )
+    .await;
+    assert_eq!(response.status, StatusCode::MULTI_STATUS);
+    assert!(response
+        .body
+        .contains("<D:href>/dav/card/jane.smith@example.com/default/</D:href>"));
+    assert!(response.body.contains("<C:addressbook/>"));
+
+    // Create an address book using extended MKCOL
+    let mkcol = r#"<D:mkcol xmlns:D="DAV:" xmlns:C="urn:ietf:params:xml:ns:carddav">
+        <D:set><D:prop>
+            <D:resourcetype><D:collection/><C:addressbook/></D:resourcetype>
+            <D:displayname>Work Contacts</D:displayname>
+            <C:addressbook-description>Colleagues &amp; partners</C:addressbook-description>
+        </D:prop></D:set>
+    </D:mkcol>"#;
+    let book = format!("{HOME}work/");
+    let response = dav_request(
+        Method::from_bytes(b"MKCOL").unwrap(),
+        &book,
+        mkcol,
+        &[],
+        (ACCOUNT, SECRET).into(),
+    )
+    .await;
+    assert_eq!(response.status, StatusCode::CREATED);
+    let response = dav_request(
+        Method::from_bytes(b"MKCOL").unwrap(),
+        &book,
+        mkcol,
+        &[],
+        (ACCOUNT, SECRET).into(),
+    )
+    .await;
+    assert_eq!(response.status, StatusCode::METHOD_NOT_ALLOWED);
+    let response = propfind(
+        &book,
+        "0",
+        r#"<D:propfind xmlns:D="DAV:" xmlns:C="urn:ietf:params:xml:ns:carddav"><D:prop><D:displayname/><C:addressbook-description/><D:sync-token/><D:unknown/></D:prop></D:propfind>"#,
+    )
+    .await;
+    assert!(response
+        .body
+        .contains("<D:displayname>Work Contacts</D:displayname>"));
+    assert!(response.body.contains(
+        "<C:addressbook-description>Colleagues &amp; partners</C:addressbook-description>"
+    ));
+    assert!(response
+        .body
+        .contains("<D:prop><D:unknown/></D:prop><D:status>HTTP/1.1 404 Not Found</D:status>"));
+
+    // Create cards
+    let etag_john = put_card(
+        &book,
+        "john.vcf",
+        &vcard("john", "John Doe", "jdoe@example.com"),
+        &[],
+    )
+    .await
+    .expect_status(StatusCode::CREATED);
+    put_card(
+        &book,
+        "jane.vcf",
+        &vcard("jane", "Jane Doe", "jane@example.org"),
+        &[],
+    )
+    .await
+    .expect_status(StatusCode::CREATED);
+
+    // Invalid vCards, UID conflicts and failed preconditions are rejected
+    let response = put_card(
+        &book,
+        "bad.vcf",
+        "BEGIN:VCARD\r\nFN:Bad\r\nEND:VCARD\r\n",
+        &[],
+    )
+    .await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+    assert!(response.body.contains("<C:valid-address-data>"));
+    let response = put_card(
+        &book,
+        "john-copy.vcf",
+        &vcard("john", "John Copy", "copy@example.com"),
+        &[],
+    )
+    .await;
+    assert_eq!(response.status, StatusCode::CONFLICT);
+    assert!(response.body.contains(
+        "<C:no-uid-conflict><D:href>/dav/card/jane.smith@example.com/work/john.vcf</D:href></C:no-uid-conflict>"
+    ));
+    let response = put_card(
+        &book,
+        "john.vcf",
+        &vcard("john", "John Doe", "jdoe@example.com"),
+        &[("if-none-match", "*")],
+    )
+    .await;
+    assert_eq!(response.status, StatusCode::PRECONDITION_FAILED);
+    let response = put_card(
+        &book,
+        "john.vcf",
+        &vcard("john", "John Doe", "jdoe@example.com"),
+        &[("if-match", "\"abc\"")],
+    )
+    .await;
+    assert_eq!(response.status, StatusCode::PRECONDITION_FAILED);
+
+    // Cards are stored normalized
+    let response = dav_request(
+        Method::GET,
+        &format!("{book}john.vcf"),
+        "",
+        &[],
+        (ACCOUNT, SECRET).into(),
+    )
+    .await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.header("etag"), etag_john);
+    assert_eq!(
+        response.body,
+        "BEGIN:VCARD\r\nVERSION:4.0\r\nFN:John Doe\r\nEMAIL;TYPE=work:jdoe@example.com\r\nUID:john\r\nEND:VCARD\r\n"
+    );
+
+    // Initial sync
+    let (hrefs, deleted, token) = sync_collection(&book, "").await;
+    assert_eq!(
+        hrefs,
+        vec![format!("{book}jane.vcf"), format!("{book}john.vcf")]
+    );
+    assert!(deleted.is_empty());
+
+    // Modify, delete and create cards
+    let etag_john_new = put_card(
+        &book,
+        "john.vcf",
+        &vcard("john", "John M. Doe", "jdoe@example.com"),
+        &[("if-match", &etag_john)],
+    )
+    .await
+    .expect_status(StatusCode::NO_CONTENT);
+    assert_ne!(etag_john, etag_john_new);
+    let response = dav_request(
+        Method::DELETE,
+        &format!("{book}jane.vcf"),
+        "",
+        &[],
+        (ACCOUNT, SECRET).into(),
+    )
+    .await;
+    assert_eq!(response.status, StatusCode::NO_CONTENT);
+    put_card(
+        &book,
+        "bill.vcf",
+        &vcard("bill", "Bill Foobar", "bill@example.org"),
+        &[],
+    )
+    .await
+    .expect_status(StatusCode::CREATED);
+
+    // Changes to other address books are not reported
+    put_card(
+        &format!("{HOME}default/"),
+        "other.vcf",
+        &vcard("other", "Other Person", "other@example.org"),
+        &[],
+    )
+    .await
+    .expect_status(StatusCode::CREATED);
+
+    // Incremental sync
+    let (hrefs, deleted, new_token) = sync_collection(&book, &token).await;
+    assert_eq!(
+        hrefs,
+        vec![format!("{book}bill.vcf"), format!("{book}john.vcf")]
+    );
+    assert_eq!(deleted, vec![format!("{book}jane.vcf")]);
+    assert_ne!(token, new_token);
+    let (hrefs, deleted, newest_token) = sync_collection(&book, &new_token).await;
+    assert!(hrefs.is_empty() && deleted.is_empty());
+    assert_eq!(new_token, newest_token);
+
+    // Invalid sync tokens are rejected
+    let response = report(
+        &book,
+        r#"<D:sync-collection xmlns:D="DAV:"><D:sync-token>http://stalw.art/ns/sync/invalid</D:sync-token><D:sync-level>1</D:sync-level></D:sync-collection>"#,
+    )
+    .await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+    assert!(response.body.contains("<D:valid-sync-token>"));
+
+    // Query by e-mail address
+    let response = report(
+        &book,
+        r#"<C:addressbook-query xmlns:D="DAV:" xmlns:C="urn:ietf:params:xml:ns:carddav">
+            <D:prop><D:getetag/><C:address-data/></D:prop>
+            <C:filter>
+                <C:prop-filter name="EMAIL">
+                    <C:text-match collation="i;unicode-casemap" match-type="ends-with">@EXAMPLE.ORG</C:text-match>
+                </C:prop-filter>
+            </C:filter>
+        </C:addressbook-query>"#,
+    )
+    .await;
+    assert_eq!(response.status, StatusCode::MULTI_STATUS);
+    assert_eq!(response.hrefs(), vec![format!("{book}bill.vcf")]);
+    assert!(response.body.contains("FN:Bill Foobar"));
+    let response = report(
+        &book,
+        r#"<C:addressbook-query xmlns:D="DAV:" xmlns:C="urn:ietf:params:xml:ns:carddav">
+            <D:prop><D:getetag/></D:prop>
+            <C:filter>
+                <C:prop-filter name="EMAIL">
+                    <C:text-match negate-condition="yes">bill</C:text-match>
+                </C:prop-filter>
+            </C:filter>
+        </C:addressbook-query>"#,
+    )
+    .await;
+    assert_eq!(response.hrefs(), vec![format!("{book}john.vcf")]);
+    assert!(response.body.contains(&etag_john_new));
+
+    // Multiget
+    let response = report(
+        &book,
+        &format!(
+            r#"<C:addressbook-multiget xmlns:D="DAV:" xmlns:C="urn:ietf:params:xml:ns:carddav">
+                <D:prop><D:getetag/><C:address-data/></D:prop>
+                <D:href>{book}john.vcf</D:href>
+                <D:href>{book}jane.vcf</D:href>
+            </C:addressbook-multiget>"#
+        ),
+    )
+    .await;
+    assert!(response.body.contains("FN:John M. Doe"));
+    assert!(response.body.contains(&format!(
+        "<D:href>{book}jane.vcf</D:href><D:status>HTTP/1.1 404 Not Found</D:status>"
+    )));
+
+    // Sync performance sanity check
+    let bulk_book = format!("{HOME}bulk/");
+    let bulk_book_id = server
+        .address_book_create(
+            account_id,
+            AddressBook {
+                name: "bulk".to_string(),
+                display_name: "Bulk".to_string(),
+                description: String::new(),
+            },
+        )
+        .await
+        .unwrap();
+    let resource_token = ResourceToken {
+        account_id,
+        quota: 0,
+        tenant: None,
+    };
+    let time = Instant::now();
+    for num in 0..10_000 {
+        let card = vcard(
+            &format!("bulk-{num}"),
+            &format!("Contact {num}"),
+            &format!("contact{num}@example.com"),
+        );
+        assert!(matches!(
+            server
+                .contact_card_put(
+                    &resource_token,
+                    ContactCard {
+                        name: format!("{num}.vcf"),
+                        uid: format!("bulk-{num}"),
+                        address_book_id: bulk_book_id,
+                        vcard: card,
+                    },
+                )
+                .await
+                .unwrap(),
+            PutResult::Created { .. }
+        ));
+    }
+    println!(
+        "Inserted 10000 contacts in {} ms",
+        time.elapsed().as_millis()
+    );
+    let time = Instant::now();
+    let (hrefs, _, token) = sync_collection(&bulk_book, "").await;
+    assert_eq!(hrefs.len(), 10_000);
+    println!(
+        "Initial sync of 10000 contacts took {} ms",
+        time.elapsed().as_millis()
+    );
+    let time = Instant::now();
+    let (hrefs, deleted, _) = sync_collection(&bulk_book, &token).await;
+    assert!(hrefs.is_empty() && deleted.is_empty());
+    println!(
+        "Incremental sync of 10000 contacts took {} ms",
+        time.elapsed().as_millis()
+    );
+
+    // Concurrent puts claiming the same UID, only one of them is stored
+    let results = join_all((0..10).map(|num| {
+        server.contact_card_put(
+            &resource_token,
+            ContactCard {
+                name: format!("race-{num}.vcf"),
+                uid: "race".to_string(),
+                address_book_id: bulk_book_id,
+                vcard: vcard("race", &format!("Race {num}"), "race@example.com"),
+            },
+        )
+    }))
+    .await;
+    assert_eq!(
+        results
+            .iter()
+            .filter(|result| matches!(result, Ok(PutResult::Created { .. })))
+            .count(),
+        1
+    );
+    assert!(results.iter().all(|result| matches!(
+        result,
+        Ok(PutResult::Created { .. } | PutResult::UidConflict { .. })
+    )));
+
+    // Delete address books
+    for book in ["work", "default", "bulk"] {
+        let response = dav_request(
+            Method::DELETE,
+            &format!("{HOME}{book}/"),
+            "",
+            &[],
+            (ACCOUNT, SECRET).into(),
+        )
+        .await;
+        assert_eq!(response.status, StatusCode::NO_CONTENT);
+    }
+    assert_is_empty(server).await;
+}
+
+struct DavResponse {
+    status: StatusCode,
+    headers: header::HeaderMap,
+    body: String,
+}
+
+impl DavResponse {
+    fn header(&self, name: &str) -> &str {
+        self.headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+    }
+
+    fn hrefs(&self) -> Vec<String> {
+        let mut hrefs = xml_values(&self.body, "D:href");
+        hrefs.sort();
+        hrefs
+    }
+
+    fn expect_status(self, status: StatusCode) -> String {
+        assert_eq!(self.status, status, "{}", self.body);
+        self.header("etag").to_string()
+    }
+}
+
+fn xml_values(xml: &str, tag: &str) -> Vec<String> {
+    let start = format!("<{tag}>");
+    let end = format!("</{tag}>");
+    xml.split(&start)
+        .skip(1)
+        .filter_map(|item| item.split_once(&end))
+        .map(|(value, _)| value.replace("&amp;", "&"))
+        .collect()
+}
+
+fn vcard(uid: &str, name: &str, email: &str) -> String {
+    format!("BEGIN:VCARD\nVERSION:4.0\nFN:{name}\nEMAIL;TYPE=work:{email}\nUID:{uid}\nEND:VCARD\n")
+}
+
+async fn put_card(book: &str, name: &str, vcard: &str, headers: &[(&str, &str)]) -> DavResponse {
+    let mut headers = headers.to_vec();
+    headers.push(("content-type", "text/vcard; charset=utf-8"));
+    dav_request(
+        Method::PUT,
+        &format!("{book}{name}"),
+        vcard,
+        &headers,
+        (ACCOUNT, SECRET).into(),
+    )
+    .await
+}
+
+async fn propfind(path: &str, depth: &str, body: &str) -> DavResponse {
+    dav_request(
+        Method::from_bytes(b"PROPFIND").unwrap(),
+        path,
+        body,
+        &[("depth", depth)],
+        (ACCOUNT, SECRET).into(),
+    )
+    .await
+}
+
+async fn report(path: &str, body: &str) -> DavResponse {
+    dav_request(
+        Method::from_bytes(b"REPORT").unwrap(),
+        path,
+        body,
+        &[],
+        (ACCOUNT, SECRET).into(),
+    )
+    .await
+}
+
+// Returns the changed and deleted hrefs along with the new sync token
+async fn sync_collection(book: &str, token: &str) -> (Vec<String>, Vec<String>, String) {
+    let response = report(
+        book,
+        &format!(
+            r#"<D:sync-collection xmlns:D="DAV:"><D:sync-token>{token}</D:sync-token><D:sync-level>1</D:sync-level><D:prop><D:getetag/></D:prop></D:sync-collection>"#
+        ),
+    )
+    .await;
+    assert_eq!(
+        response.status,
+        StatusCode::MULTI_STATUS,
+        "{}",
+        response.body
+    );
+
+    let mut changed = Vec::new();
+    let mut deleted = Vec::new();
+    for item in response.body.split("<D:response>").skip(1) {
+        let href = xml_values(item, "D:href").pop().unwrap();
+        if item.contains("</D:href><D:status>HTTP/1.1 404") {
+            deleted.push(href);
+        } else {
+            changed.push(href);
+        }
+    }
+    changed.sort();
+    deleted.sort();
+
+    (
+        changed,
+        deleted,
+        xml_values(&response.body, "D:sync-token").pop().unwrap(),
+    )
+}
+
+async fn dav_request(
+    method: Method,
+    path: &str,
+    body: &str,
+    headers: &[(&str, &str)],
+    credentials: Option<(&str, &str)>,
+) -> DavResponse {
+    let mut request = reqwest::Client::builder()
+        .danger_accept_invalid_certs(true)
+        .redirect(reqwest::redirect::Policy::none())
+        .timeout(Duration::from_secs(60))
+        .build()
+        .unwrap()
+        .request(method, format!("https://127.0.0.1:8899{path}"))
+        .body(body.to_string());
+    if let Some((username, secret)) = credentials {
+        request = request.header(
+            header::AUTHORIZATION,
+            format!(
+                "Basic {}",
+                general_purpose::STANDARD.encode(format!("{username}:{secret}"))
+            ),
+        );
+    }
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+
+    let response = request.send().await.unwrap();
+    DavResponse {
+        status: response.status(),
+        headers: response.headers().clone(),
+        body: response.text().await.unwrap(),
+    }
+}
@@ -295,8 +295,119 @@ pub async fn test(params: &mut JMAPTest) {
         );
     }
 
+    // Wildcard aliases and catch-all addresses
+    let mut account_ids = Vec::new();
+    for (login, name, emails) in [
+        (
+            "sales",
+            "Sales Team",
+            &["sales@example.net", "sales-*@example.net"][..],
+        ),
+        ("sales-vip", "VIP Sales", &["sales-vip@example.net"][..]),
+        (
+            "postmaster",
+            "Postmaster",
+            &["postmaster@example.net", "@example.net"][..],
+        ),
+    ] {
+        account_ids.push(
+            Id::from(
+                server
+                    .core
+                    .storage
+                    .data
+                    .create_test_user(login, "secret", name, emails)
+                    .await,
+            )
+            .to_string(),
+        );
+    }
+
+    // Precedence is exact address > wildcard alias > sub-address base > catch-all
+    for rcpt in [
+        "sales-vip@example.net",
+        "sales-east@example.net",
+        "sales+news@example.net",
+        "random@example.net",
+        "sales-vip+news@example.net",
+    ] {
+        lmtp.ingest(
+            "bill@example.com",
+            &[rcpt],
+            &format!(
+                concat!(
+                    "From: bill@example.com\r\n",
+                    "To: {}\r\n",
+                    "Subject: {}\r\n",
+                    "\r\n",
+                    "Did you get the memo?"
+                ),
+                rcpt, rcpt
+            ),
+        )
+        .await;
+    }
+
+    for (account_id, expected) in account_ids.iter().zip([
+        &[
+            ("sales-east@example.net", true),
+            ("sales+news@example.net", false),
+            ("sales-vip+news@example.net", true),
+        ][..],
+        &[("sales-vip@example.net", false)][..],
+        &[("random@example.net", true)][..],
+    ]) {
+        params.client.set_default_account_id(account_id);
+        let mut request = params.client.build();
+        request.get_email();
+        let emails = request.send_get_email().await.unwrap().take_list();
+        assert_eq!(
+            emails.len(),
+            expected.len(),
+            "for {account_id}: {emails:#?}"
+        );
+
+        for (rcpt, has_original_to) in expected {
+            let email = emails
+                .iter()
+                .find(|email| email.subject() == Some(*rcpt))
+                .unwrap_or_else(|| panic!("missing message for {rcpt}"));
+            let message = String::from_utf8(
+                params
+                    .client
+                    .download(email.blob_id().unwrap())
+                    .await
+                    .unwrap(),
+            )
+            .unwrap();
+            assert_eq!(
+                message.starts_with(&format!("X-Original-To: <{rcpt}>\r\n")),
+                *has_original_to,
+                "for {rcpt}: {message}"
+            );
+        }
+    }
+
+    // VRFY does not disclose which addresses are real on catch-all domains
+    lmtp.vrfy("random@example.net", 2)
+        .await
+        .assert_contains("random@example.net");
+    lmtp.vrfy("sales-vip@example.net", 2)
+        .await
+        .assert_contains("sales-vip@example.net")
+        .assert_count("@example.net", 1);
+
+    // Unknown recipients are still rejected on domains without a catch-all
+    let mut lmtp_rcpt = SmtpConnection::connect().await;
+    lmtp_rcpt.mail_from("bill@example.com", 2).await;
+    lmtp_rcpt.rcpt_to("random@example.com", 5).await;
+    lmtp_rcpt.rcpt_to("random@example.net", 2).await;
+
     // Remove test data
-    for account_id in [&account_id_1, &account_id_2, &account_id_3] {
+    for account_id in [&account_id_1, &account_id_2, &account_id_3]
+        .into_iter()
+        .chain(account_ids.iter())
+    {
         params.client.set_default_account_id(account_id);
         destroy_all_mailboxes(params).await;
     }
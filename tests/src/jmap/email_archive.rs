@@ -0,0 +1,253 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use ahash::AHashSet;
+use directory::{backend::internal::manage::ManageDirectory, QueryBy};
+use jmap::{
+    email::archive::EmailArchive,
+    mailbox::{get::MailboxGet, INBOX_ID, SENT_ID},
+    services::state::StateManager,
+    JmapMethods,
+};
+use jmap_proto::types::{collection::Collection, id::Id, keyword::Keyword, property::Property};
+use store::write::{now, TagValue};
+
+use crate::{directory::internal::TestInternalDirectory, jmap::assert_is_empty};
+
+use super::JMAPTest;
+
+const NUM_MESSAGES: u64 = 5000;
+const YEAR_2023: u64 = 1672531200;
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running Email archive tests...");
+    let server = params.server.clone();
+    let client = &mut params.client;
+    let account_id = server
+        .core
+        .storage
+        .data
+        .create_test_user(
+            "archive@example.com",
+            "12345",
+            "John Archive",
+            &["archive@example.com"],
+        )
+        .await;
+    let optout_account_id = server
+        .core
+        .storage
+        .data
+        .create_test_user(
+            "noarchive@example.com",
+            "12345",
+            "John No Archive",
+            &["noarchive@example.com"],
+        )
+        .await;
+
+    // Import messages received around a year boundary, every third message is marked as seen
+    client.set_default_account_id(Id::from(account_id));
+    let inbox_id = Id::from(INBOX_ID).to_string();
+    let sent_id = Id::from(SENT_ID).to_string();
+    let first_received = YEAR_2023 - 15 * 86400;
+    let mut expected_2022 = 0;
+    let mut expected_seen = [0, 0];
+    for num in 0..NUM_MESSAGES {
+        let received_at = first_received + num * 600;
+        let is_seen = num % 3 == 0;
+        let year = usize::from(received_at >= YEAR_2023);
+        if year == 0 {
+            expected_2022 += 1;
+        }
+        if is_seen {
+            expected_seen[year] += 1;
+        }
+
+        client
+            .email_import(
+                format!(
+                    concat!(
+                        "From: bill@example.com\r\n",
+                        "To: archive@example.com\r\n",
+                        "Subject: Archive test #{}\r\n",
+                        "\r\n",
+                        "Yeah, I'm gonna need you to go ahead and come in tomorrow."
+                    ),
+                    num
+                )
+                .into_bytes(),
+                [&inbox_id],
+                Some(if is_seen { vec!["$seen"] } else { vec![] }),
+                Some(received_at as i64),
+            )
+            .await
+            .unwrap();
+    }
+    let expected_2023 = NUM_MESSAGES - expected_2022;
+
+    // Recent messages and messages in other mailboxes are not archived
+    for (mailbox_id, received_at) in [(&inbox_id, now()), (&sent_id, first_received)] {
+        client
+            .email_import(
+                b"From: bill@example.com\r\nSubject: Not archived\r\n\r\nHello".to_vec(),
+                [mailbox_id],
+                None::<Vec<&str>>,
+                Some(received_at as i64),
+            )
+            .await
+            .unwrap();
+    }
+    assert_eq!(
+        mailbox_count(&server, account_id, INBOX_ID).await,
+        NUM_MESSAGES + 1
+    );
+
+    // Dry run reports what would be moved without making changes
+    let report = server.emails_archive(account_id, true).await.unwrap();
+    assert!(report.dry_run);
+    assert_eq!(report.total, NUM_MESSAGES);
+    assert_eq!(report.folders.get("Archive/2022"), Some(&expected_2022));
+    assert_eq!(report.folders.get("Archive/2023"), Some(&expected_2023));
+    assert_eq!(
+        mailbox_count(&server, account_id, INBOX_ID).await,
+        NUM_MESSAGES + 1
+    );
+    assert_eq!(
+        server
+            .mailbox_get_by_name(account_id, "Archive")
+            .await
+            .unwrap(),
+        None
+    );
+
+    // Archive messages
+    let state = server
+        .get_state(account_id, Collection::Email)
+        .await
+        .unwrap();
+    let report = server.emails_archive(account_id, false).await.unwrap();
+    assert!(!report.dry_run);
+    assert_eq!(report.total, NUM_MESSAGES);
+    assert_eq!(report.folders.get("Archive/2022"), Some(&expected_2022));
+    assert_eq!(report.folders.get("Archive/2023"), Some(&expected_2023));
+
+    // The archive folder is created with the archive role
+    let archive_id = server
+        .mailbox_get_by_name(account_id, "Archive")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        server
+            .mailbox_get_by_role(account_id, "archive")
+            .await
+            .unwrap(),
+        Some(archive_id)
+    );
+
+    // Verify counts and flags
+    assert_eq!(mailbox_count(&server, account_id, INBOX_ID).await, 1);
+    assert_eq!(mailbox_count(&server, account_id, SENT_ID).await, 1);
+    assert_eq!(mailbox_count(&server, account_id, archive_id).await, 0);
+    let seen_ids = server
+        .get_tag(
+            account_id,
+            Collection::Email,
+            Property::Keywords,
+            Keyword::Seen,
+        )
+        .await
+        .unwrap()
+        .unwrap_or_default();
+    for (folder, expected_total, expected_seen) in [
+        ("Archive/2022", expected_2022, expected_seen[0]),
+        ("Archive/2023", expected_2023, expected_seen[1]),
+    ] {
+        let mailbox_id = server
+            .mailbox_get_by_name(account_id, folder)
+            .await
+            .unwrap()
+            .unwrap();
+        let document_ids = server
+            .get_tag(
+                account_id,
+                Collection::Email,
+                Property::MailboxIds,
+                TagValue::Id(mailbox_id),
+            )
+            .await
+            .unwrap()
+            .unwrap_or_default();
+        assert_eq!(document_ids.len(), expected_total, "{folder}");
+        assert_eq!((document_ids & &seen_ids).len(), expected_seen, "{folder}");
+    }
+
+    // Email/changes reports the moved messages as updated
+    let mut updated = AHashSet::new();
+    let mut state = state.to_string();
+    loop {
+        let changes = client.email_changes(&state, None).await.unwrap();
+        assert!(changes.created().is_empty());
+        assert!(changes.destroyed().is_empty());
+        updated.extend(changes.updated().iter().cloned());
+        state = changes.new_state().to_string();
+        if !changes.has_more_changes() {
+            break;
+        }
+    }
+    assert_eq!(updated.len() as u64, NUM_MESSAGES);
+
+    // Running the task again does not move any messages
+    let report = server.emails_archive(account_id, false).await.unwrap();
+    assert_eq!(report.total, 0);
+
+    // Accounts that opted out are not archived
+    client.set_default_account_id(Id::from(optout_account_id));
+    client
+        .email_import(
+            b"From: bill@example.com\r\nSubject: Opt-out\r\n\r\nHello".to_vec(),
+            [&inbox_id],
+            None::<Vec<&str>>,
+            Some(first_received as i64),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        server
+            .emails_archive(optout_account_id, false)
+            .await
+            .unwrap()
+            .total,
+        0
+    );
+    assert_eq!(mailbox_count(&server, optout_account_id, INBOX_ID).await, 1);
+
+    // Delete accounts
+    for account_id in [account_id, optout_account_id] {
+        server
+            .core
+            .storage
+            .data
+            .delete_principal(QueryBy::Id(account_id))
+            .await
+            .unwrap();
+    }
+    assert_is_empty(server).await;
+}
+
+async fn mailbox_count(server: &common::Server, account_id: u32, mailbox_id: u32) -> u64 {
+    server
+        .get_tag(
+            account_id,
+            Collection::Email,
+            Property::MailboxIds,
+            TagValue::Id(mailbox_id),
+        )
+        .await
+        .unwrap()
+        .map_or(0, |ids| ids.len())
+}
@@ -0,0 +1,274 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{path::Path, time::Duration};
+
+use common::{ImportJob, Server};
+use directory::{backend::internal::manage::ManageDirectory, QueryBy};
+use jmap::{
+    mailbox::{get::MailboxGet, INBOX_ID},
+    JmapMethods,
+};
+use jmap_proto::types::{collection::Collection, id::Id, keyword::Keyword, property::Property};
+use serde_json::json;
+use store::write::TagValue;
+
+use crate::{
+    directory::internal::TestInternalDirectory,
+    jmap::{assert_is_empty, ManagementApi},
+};
+
+use super::JMAPTest;
+
+const MBOX: &str = concat!(
+    "From bill@example.com Sat Jan  1 10:00:00 2022\n",
+    "From: bill@example.com\n",
+    "Message-ID: <mbox-1@example.com>\n",
+    "Date: Sat, 1 Jan 2022 09:59:00 +0000\n",
+    "Subject: Escaped lines\n",
+    "\n",
+    ">From the start of the line\n",
+    ">>From a quoted line\n",
+    "\n",
+    "From bill@example.com Sun Jan  2 10:00:00 2022\n",
+    "From: bill@example.com\n",
+    "Message-ID: <mbox-2@example.com>\n",
+    "Date: Sun, 2 Jan 2022 09:59:00 +0000\n",
+    "Subject: Second message\n",
+    "\n",
+    "Hello\n",
+    "\n",
+    "From bill@example.com Mon Jan  3 10:00:00 2022\n",
+    "From: bill@example.com\n",
+    "Message-ID: <mbox-3@example.com>\n",
+    "Date: Mon, 3 Jan 2022 09:59:00 +0000\n",
+    "Subject: Third message\n",
+    "\n",
+    "Hello again\n",
+    "\n",
+);
+
+const MAILDIR: &[(&str, u64, &str)] = &[
+    ("cur/1650000000.M1P1.host:2,FS", 1650000000, "maildir-1"),
+    ("new/1650000100.M2P1.host", 1650000100, "maildir-2"),
+    ("tmp/1650000200.M3P1.host", 1650000200, "maildir-3"),
+    (
+        ".Work/cur/1650000300.M4P1.host:2,RT",
+        1650000300,
+        "maildir-4",
+    ),
+    (
+        ".Work.Projects/new/1650000400.M5P1.host",
+        1650000400,
+        "maildir-5",
+    ),
+];
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running Email bulk import tests...");
+    let server = params.server.clone();
+    let client = &mut params.client;
+    let account_id = server
+        .core
+        .storage
+        .data
+        .create_test_user(
+            "import@example.com",
+            "12345",
+            "John Import",
+            &["import@example.com"],
+        )
+        .await;
+
+    // Write fixtures
+    let base_path = std::env::temp_dir().join("stalwart-import-test");
+    let _ = std::fs::remove_dir_all(&base_path);
+    let mbox_path = base_path.join("messages.mbox");
+    let maildir_path = base_path.join("Maildir");
+    std::fs::create_dir_all(&base_path).unwrap();
+    std::fs::write(&mbox_path, MBOX).unwrap();
+    for (name, _, id) in MAILDIR {
+        let path = maildir_path.join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(
+            path,
+            format!(
+                concat!(
+                    "From: jane@example.com\r\n",
+                    "Message-ID: <{}@example.com>\r\n",
+                    "Subject: {}\r\n",
+                    "\r\n",
+                    "Hello"
+                ),
+                id, id
+            ),
+        )
+        .unwrap();
+    }
+
+    // Import both fixtures twice, the second run should only find duplicates
+    let api = ManagementApi::new(8899, "admin", "secret");
+    for is_rerun in [false, true] {
+        let job = import(&api, "mbox", &mbox_path, Some("Old Mail")).await;
+        assert_eq!(job.error, None);
+        assert_eq!(job.account_id, account_id);
+        assert_eq!(job.imported, if is_rerun { 0 } else { 3 });
+        assert_eq!(job.duplicates, if is_rerun { 3 } else { 0 });
+        assert_eq!(job.failed, 0);
+        assert_eq!(job.mailboxes, 1);
+
+        let job = import(&api, "maildir", &maildir_path, None).await;
+        assert_eq!(job.error, None);
+        assert_eq!(job.imported, if is_rerun { 0 } else { 4 });
+        assert_eq!(job.duplicates, if is_rerun { 4 } else { 0 });
+        assert_eq!(job.mailboxes, 3);
+
+        // Verify folder structure and counts
+        let old_mail_id = mailbox_id(&server, account_id, "Old Mail").await;
+        let work_id = mailbox_id(&server, account_id, "Work").await;
+        let projects_id = mailbox_id(&server, account_id, "Work/Projects").await;
+        for (mailbox_id, expected) in [
+            (old_mail_id, 3),
+            (INBOX_ID, 2),
+            (work_id, 1),
+            (projects_id, 1),
+        ] {
+            assert_eq!(
+                mailbox_count(&server, account_id, mailbox_id).await,
+                expected,
+                "mailbox {mailbox_id}"
+            );
+        }
+    }
+
+    // Verify flags, received dates and unescaped contents
+    client.set_default_account_id(Id::from(account_id));
+    let mut request = client.build();
+    request.get_email();
+    let emails = request.send_get_email().await.unwrap().take_list();
+    assert_eq!(emails.len(), 7);
+    for email in emails {
+        let message_id = email.message_id().unwrap().first().unwrap().to_string();
+        let mut keywords = email.keywords();
+        keywords.sort_unstable();
+        let received_at = email.received_at().unwrap() as u64;
+
+        match message_id.as_str() {
+            "mbox-1@example.com" => {
+                assert_eq!(received_at, 1641031200);
+                let message =
+                    String::from_utf8(client.download(email.blob_id().unwrap()).await.unwrap())
+                        .unwrap();
+                assert!(
+                    message.ends_with("\nFrom the start of the line\n>From a quoted line\n"),
+                    "{message:?}"
+                );
+            }
+            "mbox-2@example.com" => assert_eq!(received_at, 1641117600),
+            "mbox-3@example.com" => assert_eq!(received_at, 1641204000),
+            _ => {
+                let (_, expected_received_at, _) = MAILDIR
+                    .iter()
+                    .find(|(_, _, id)| message_id.starts_with(id))
+                    .unwrap_or_else(|| panic!("unexpected message {message_id}"));
+                assert_eq!(received_at, *expected_received_at, "{message_id}");
+            }
+        }
+
+        let expected_keywords: &[&str] = match message_id.as_str() {
+            "maildir-1@example.com" => &["$flagged", "$seen"],
+            "maildir-4@example.com" => &["$answered", "$deleted"],
+            _ => &[],
+        };
+        assert_eq!(keywords, expected_keywords, "{message_id}");
+    }
+    assert_eq!(
+        server
+            .get_tag(
+                account_id,
+                Collection::Email,
+                Property::Keywords,
+                Keyword::Seen,
+            )
+            .await
+            .unwrap()
+            .map_or(0, |ids| ids.len()),
+        1
+    );
+
+    // Unknown jobs are not found
+    api.get::<ImportJob>("/api/import/1234")
+        .await
+        .unwrap()
+        .expect_request_error("Not Found");
+
+    // Delete account
+    std::fs::remove_dir_all(&base_path).unwrap();
+    server
+        .core
+        .storage
+        .data
+        .delete_principal(QueryBy::Id(account_id))
+        .await
+        .unwrap();
+    assert_is_empty(server).await;
+}
+
+async fn import(
+    api: &ManagementApi,
+    format: &str,
+    path: &Path,
+    mailbox: Option<&str>,
+) -> ImportJob {
+    let job_id = api
+        .post::<u64>(
+            "/api/import/import@example.com",
+            &json!({
+                "format": format,
+                "path": path,
+                "mailbox": mailbox,
+            }),
+        )
+        .await
+        .unwrap()
+        .unwrap_data();
+
+    // Wait for the job to finish
+    for _ in 0..100 {
+        let job = api
+            .get::<ImportJob>(&format!("/api/import/{job_id}"))
+            .await
+            .unwrap()
+            .unwrap_data();
+        if job.finished != 0 {
+            return job;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    panic!("Import job {job_id} did not finish");
+}
+
+async fn mailbox_id(server: &Server, account_id: u32, name: &str) -> u32 {
+    server
+        .mailbox_get_by_name(account_id, name)
+        .await
+        .unwrap()
+        .unwrap_or_else(|| panic!("mailbox {name} not found"))
+}
+
+async fn mailbox_count(server: &Server, account_id: u32, mailbox_id: u32) -> u64 {
+    server
+        .get_tag(
+            account_id,
+            Collection::Email,
+            Property::MailboxIds,
+            TagValue::Id(mailbox_id),
+        )
+        .await
+        .unwrap()
+        .map_or(0, |ids| ids.len())
+}
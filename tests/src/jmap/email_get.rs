@@ -6,9 +6,9 @@
 
 use std::{fs, path::PathBuf};
 
-use jmap::mailbox::INBOX_ID;
+use jmap::{email::metadata::MessageDataGet, mailbox::INBOX_ID};
 use jmap_client::email::{self, import::EmailImportResponse, Header, HeaderForm};
-use jmap_proto::types::id::Id;
+use jmap_proto::types::{id::Id, keyword::Keyword};
 use mail_parser::HeaderName;
 
 use crate::jmap::{assert_is_empty, mailbox::destroy_all_mailboxes, replace_blob_ids};
@@ -58,6 +58,25 @@ pub async fn test(params: &mut JMAPTest) {
         assert_ne!(response.old_state(), Some(response.new_state()));
         let email = response.created(&id).unwrap();
 
+        // Typed metadata accessor
+        let data = server
+            .get_message_data(
+                1,
+                Id::from_bytes(email.id().unwrap().as_bytes())
+                    .unwrap()
+                    .document_id(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(data.metadata.size, blob_len);
+        assert_eq!(data.metadata.received_at, (blob_len * 1000000) as u64);
+        assert_eq!(data.keywords.inner, vec![Keyword::Other("tag".to_string())]);
+        assert_eq!(
+            serde_json::to_value(&data).unwrap()["keywords"],
+            serde_json::json!(["tag"])
+        );
+
         let mut request = params.client.build();
         request
             .get_email()
@@ -0,0 +1,295 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use common::{MigrationFolder, MigrationStatus, Server};
+use directory::{backend::internal::manage::ManageDirectory, QueryBy};
+use jmap::{
+    mailbox::{get::MailboxGet, INBOX_ID, SENT_ID},
+    JmapMethods,
+};
+use jmap_client::{client::Client, mailbox::Role};
+use jmap_proto::types::{collection::Collection, id::Id, property::Property};
+use serde_json::json;
+use store::write::TagValue;
+
+use crate::{
+    directory::internal::TestInternalDirectory,
+    jmap::{assert_is_empty, ManagementApi},
+};
+
+use super::JMAPTest;
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running IMAP account migration tests...");
+    let server = params.server.clone();
+    let client = &mut params.client;
+
+    // The remote account is served by this same instance over IMAP
+    let remote_id = server
+        .core
+        .storage
+        .data
+        .create_test_user(
+            "remote@example.com",
+            "remote-secret",
+            "Jane Remote",
+            &["remote@example.com"],
+        )
+        .await;
+    let account_id = server
+        .core
+        .storage
+        .data
+        .create_test_user(
+            "migrate@example.com",
+            "12345",
+            "John Migrate",
+            &["migrate@example.com"],
+        )
+        .await;
+
+    // Populate the remote account
+    client.set_default_account_id(Id::from(remote_id));
+    let inbox_id = Id::from(INBOX_ID).to_string();
+    let sent_id = Id::from(SENT_ID).to_string();
+    let projects_id = client
+        .mailbox_create("Projects", None::<String>, Role::None)
+        .await
+        .unwrap()
+        .take_id();
+    let year_id = client
+        .mailbox_create("2024", Some(&projects_id), Role::None)
+        .await
+        .unwrap()
+        .take_id();
+    for (num, mailbox_id, keywords) in [
+        (1, &inbox_id, vec!["$seen", "$flagged"]),
+        (2, &inbox_id, vec![]),
+        (3, &sent_id, vec!["$answered", "$seen"]),
+        (4, &year_id, vec!["project-x"]),
+    ] {
+        import_remote(client, num, mailbox_id, keywords).await;
+    }
+
+    // Dry runs report pending messages without importing them
+    let api = ManagementApi::new(8899, "admin", "secret");
+    let status = migrate(&api, json!({"dryRun": true})).await;
+    assert_eq!(status.error, None);
+    assert!(status.dry_run);
+    assert_eq!(total(&status, |folder| folder.pending), 4);
+    assert_eq!(total(&status, |folder| folder.imported), 0);
+    assert!(total(&status, |folder| folder.bytes) > 0);
+    assert_eq!(
+        server
+            .get_document_ids(account_id, Collection::Email)
+            .await
+            .unwrap()
+            .map_or(0, |ids| ids.len()),
+        0
+    );
+
+    // Throttle the transfer rate and cancel the migration after the first message
+    api.post::<()>(
+        "/api/migrate/migrate@example.com",
+        &migration_request(json!({"bandwidth": 100})),
+    )
+    .await
+    .unwrap()
+    .unwrap_data();
+    api.post::<()>(
+        "/api/migrate/migrate@example.com",
+        &migration_request(json!({})),
+    )
+    .await
+    .unwrap()
+    .expect_error("already running");
+    for _ in 0..100 {
+        let status = migration_status(&api).await;
+        if total(&status, |folder| folder.imported) > 0 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    api.delete::<()>("/api/migrate/migrate@example.com")
+        .await
+        .unwrap()
+        .unwrap_data();
+    let status = wait_for_migration(&api).await;
+    assert!(status.cancelled);
+    let imported_first = total(&status, |folder| folder.imported);
+    assert!((1..4).contains(&imported_first), "{status:?}");
+
+    // Resuming only fetches the messages that were not migrated yet
+    let status = migrate(&api, json!({"concurrency": 4})).await;
+    assert_eq!(status.error, None);
+    assert!(!status.cancelled);
+    assert_eq!(
+        total(&status, |folder| folder.imported) + imported_first,
+        4,
+        "{status:?}"
+    );
+    assert_eq!(total(&status, |folder| folder.duplicates), 0);
+    assert_eq!(total(&status, |folder| folder.failed), 0);
+    for folder in &status.folders {
+        assert_eq!(folder.error, None, "{folder:?}");
+    }
+
+    // New remote messages are picked up by later runs
+    import_remote(client, 5, &inbox_id, vec!["$seen"]).await;
+    let status = migrate(&api, json!({})).await;
+    assert_eq!(total(&status, |folder| folder.pending), 1);
+    assert_eq!(total(&status, |folder| folder.imported), 1);
+
+    // Special-use folders are mapped to their local counterparts
+    let year_mailbox_id = server
+        .mailbox_get_by_name(account_id, "Projects/2024")
+        .await
+        .unwrap()
+        .expect("Projects/2024 not found");
+    for (mailbox_id, expected) in [(INBOX_ID, 3), (SENT_ID, 1), (year_mailbox_id, 1)] {
+        assert_eq!(
+            mailbox_count(&server, account_id, mailbox_id).await,
+            expected,
+            "mailbox {mailbox_id}"
+        );
+    }
+
+    // Flags and internal dates are preserved
+    client.set_default_account_id(Id::from(account_id));
+    let mut request = client.build();
+    request.get_email();
+    let emails = request.send_get_email().await.unwrap().take_list();
+    assert_eq!(emails.len(), 5);
+    for email in emails {
+        let subject = email.subject().unwrap().to_string();
+        let num = subject
+            .strip_prefix("Migration test #")
+            .unwrap()
+            .parse::<i64>()
+            .unwrap();
+        let mut keywords = email.keywords();
+        keywords.sort_unstable();
+        let expected_keywords: &[&str] = match num {
+            1 => &["$flagged", "$seen"],
+            3 => &["$answered", "$seen"],
+            4 => &["project-x"],
+            5 => &["$seen"],
+            _ => &[],
+        };
+        assert_eq!(keywords, expected_keywords, "{subject}");
+        assert_eq!(email.received_at(), Some(received_at(num)), "{subject}");
+    }
+
+    // Accounts without migrations are not found
+    api.get::<MigrationStatus>("/api/migrate/remote@example.com")
+        .await
+        .unwrap()
+        .expect_request_error("Not Found");
+
+    // Delete accounts
+    for account_id in [account_id, remote_id] {
+        server
+            .core
+            .storage
+            .data
+            .delete_principal(QueryBy::Id(account_id))
+            .await
+            .unwrap();
+    }
+    assert_is_empty(server).await;
+}
+
+fn migration_request(options: serde_json::Value) -> serde_json::Value {
+    let mut request = json!({
+        "host": "127.0.0.1",
+        "port": 9991,
+        "starttls": true,
+        "allowInvalidCerts": true,
+        "username": "remote@example.com",
+        "password": "remote-secret",
+    });
+    for (key, value) in options.as_object().unwrap() {
+        request[key] = value.clone();
+    }
+    request
+}
+
+async fn migrate(api: &ManagementApi, options: serde_json::Value) -> MigrationStatus {
+    api.post::<()>(
+        "/api/migrate/migrate@example.com",
+        &migration_request(options),
+    )
+    .await
+    .unwrap()
+    .unwrap_data();
+
+    wait_for_migration(api).await
+}
+
+async fn wait_for_migration(api: &ManagementApi) -> MigrationStatus {
+    for _ in 0..300 {
+        let status = migration_status(api).await;
+        if status.finished != 0 {
+            return status;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    panic!("Migration did not finish");
+}
+
+async fn migration_status(api: &ManagementApi) -> MigrationStatus {
+    api.get::<MigrationStatus>("/api/migrate/migrate@example.com")
+        .await
+        .unwrap()
+        .unwrap_data()
+}
+
+async fn import_remote(client: &Client, num: i64, mailbox_id: &str, keywords: Vec<&str>) {
+    client
+        .email_import(
+            format!(
+                concat!(
+                    "From: bill@example.com\r\n",
+                    "To: remote@example.com\r\n",
+                    "Message-ID: <migrate-{}@example.com>\r\n",
+                    "Subject: Migration test #{}\r\n",
+                    "\r\n",
+                    "This message was migrated from a remote server."
+                ),
+                num, num
+            )
+            .into_bytes(),
+            [mailbox_id],
+            Some(keywords),
+            Some(received_at(num)),
+        )
+        .await
+        .unwrap();
+}
+
+fn received_at(num: i64) -> i64 {
+    1650000000 + num * 3600
+}
+
+fn total(status: &MigrationStatus, value: impl Fn(&MigrationFolder) -> u64) -> u64 {
+    status.folders.iter().map(value).sum()
+}
+
+async fn mailbox_count(server: &Server, account_id: u32, mailbox_id: u32) -> u64 {
+    server
+        .get_tag(
+            account_id,
+            Collection::Email,
+            Property::MailboxIds,
+            TagValue::Id(mailbox_id),
+        )
+        .await
+        .unwrap()
+        .map_or(0, |ids| ids.len())
+}
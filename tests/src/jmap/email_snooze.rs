@@ -0,0 +1,273 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use directory::{backend::internal::manage::ManageDirectory, QueryBy};
+use jmap::{
+    email::snooze::EmailSnoozing,
+    mailbox::{get::MailboxGet, INBOX_ID},
+    services::state::StateManager,
+    JmapMethods,
+};
+use jmap_client::mailbox::Role;
+use jmap_proto::types::{
+    collection::Collection, id::Id, property::Property, state::StateChange, type_state::DataType,
+};
+use serde_json::Value;
+use store::write::TagValue;
+use tokio::sync::mpsc;
+use utils::map::bitmap::Bitmap;
+
+use crate::{
+    directory::internal::TestInternalDirectory,
+    jmap::{assert_is_empty, jmap_json_request},
+};
+
+use super::JMAPTest;
+
+// 2099-01-01T00:00:00Z
+const SNOOZE_UNTIL: u64 = 4070908800;
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running Email snooze tests...");
+    let server = params.server.clone();
+    let client = &mut params.client;
+    let account_id = server
+        .core
+        .storage
+        .data
+        .create_test_user(
+            "snooze@example.com",
+            "12345",
+            "John Snooze",
+            &["snooze@example.com"],
+        )
+        .await;
+    client.set_default_account_id(Id::from(account_id));
+    let inbox_id = Id::from(INBOX_ID).to_string();
+    let snoozed_id = client
+        .mailbox_create("Snoozed", None::<String>, Role::None)
+        .await
+        .unwrap()
+        .take_id();
+    let snoozed_mailbox_id = server
+        .mailbox_get_by_name(account_id, "Snoozed")
+        .await
+        .unwrap()
+        .unwrap();
+
+    let mut email_ids = Vec::new();
+    for num in 0..3 {
+        email_ids.push(
+            client
+                .email_import(
+                    format!("From: bill@example.com\r\nSubject: Snooze #{num}\r\n\r\nLater!")
+                        .into_bytes(),
+                    [&inbox_id],
+                    Some(vec!["$seen"]),
+                    None,
+                )
+                .await
+                .unwrap()
+                .take_id(),
+        );
+    }
+
+    // Snooze messages by moving them to the snoozed mailbox
+    let unknown_id = Id::from(9999u64).to_string();
+    let response = email_set_snooze(
+        account_id,
+        &[
+            (
+                email_ids[0].as_str(),
+                snoozed_id.as_str(),
+                Some(inbox_id.as_str()),
+            ),
+            (email_ids[1].as_str(), snoozed_id.as_str(), None),
+            (
+                email_ids[2].as_str(),
+                snoozed_id.as_str(),
+                Some(unknown_id.as_str()),
+            ),
+        ],
+    )
+    .await;
+    let response = &response["methodResponses"][0][1];
+    for email_id in &email_ids[..2] {
+        assert!(
+            response["updated"]
+                .as_object()
+                .unwrap()
+                .contains_key(email_id),
+            "{response}"
+        );
+    }
+    assert_eq!(
+        response["notUpdated"][&email_ids[2]]["type"], "invalidProperties",
+        "{response}"
+    );
+    assert_eq!(
+        email_get_snoozed(account_id, &email_ids[0]).await,
+        serde_json::json!({
+            "until": "2099-01-01T00:00:00Z",
+            "setMailboxId": inbox_id,
+        })
+    );
+    assert_eq!(
+        mailbox_count(&server, account_id, snoozed_mailbox_id).await,
+        2
+    );
+
+    // Moving a snoozed message cancels its snooze
+    client
+        .email_set_mailboxes(&email_ids[1], [&inbox_id])
+        .await
+        .unwrap();
+    assert_eq!(
+        email_get_snoozed(account_id, &email_ids[1]).await,
+        Value::Null
+    );
+
+    // Messages are not moved before their wake time
+    let mut changes = server
+        .subscribe_state_manager(account_id, Bitmap::all())
+        .await
+        .unwrap();
+    drain_changes(&mut changes).await;
+    assert_eq!(server.email_snooze_wake(SNOOZE_UNTIL - 1).await.unwrap(), 0);
+    assert_eq!(
+        mailbox_count(&server, account_id, snoozed_mailbox_id).await,
+        1
+    );
+
+    // Messages are moved back, marked as unread and unsnoozed at wake time
+    assert_eq!(server.email_snooze_wake(SNOOZE_UNTIL).await.unwrap(), 1);
+    assert_eq!(
+        mailbox_count(&server, account_id, snoozed_mailbox_id).await,
+        0
+    );
+    let email = client
+        .email_get(&email_ids[0], None::<Vec<_>>)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(email.mailbox_ids(), [inbox_id.as_str()]);
+    assert_eq!(email.keywords(), ["$unsnoozed"]);
+    assert_eq!(
+        email_get_snoozed(account_id, &email_ids[0]).await,
+        Value::Null
+    );
+
+    // Clients are notified of the changes
+    let state_change = tokio::time::timeout(Duration::from_millis(500), changes.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(state_change.account_id, account_id);
+    for data_type in [DataType::Email, DataType::Mailbox] {
+        assert!(
+            state_change
+                .types
+                .iter()
+                .any(|(change_type, _)| *change_type == data_type),
+            "{state_change:?}"
+        );
+    }
+
+    // Snooze records are removed once processed
+    assert_eq!(server.email_snooze_wake(SNOOZE_UNTIL).await.unwrap(), 0);
+    assert_eq!(
+        mailbox_count(&server, account_id, INBOX_ID).await,
+        email_ids.len() as u64
+    );
+
+    // Delete account
+    server
+        .core
+        .storage
+        .data
+        .delete_principal(QueryBy::Id(account_id))
+        .await
+        .unwrap();
+    assert_is_empty(server).await;
+}
+
+async fn email_set_snooze(
+    account_id: u32,
+    emails: &[(&str, &str, Option<&str>)],
+) -> serde_json::Value {
+    let update = emails
+        .iter()
+        .map(|(email_id, snoozed_id, mailbox_id)| {
+            let mut snoozed = serde_json::json!({ "until": "2099-01-01T00:00:00Z" });
+            if let Some(mailbox_id) = mailbox_id {
+                snoozed["setMailboxId"] = Value::String(mailbox_id.to_string());
+            }
+            let mut mailbox_ids = serde_json::Map::new();
+            mailbox_ids.insert(snoozed_id.to_string(), Value::Bool(true));
+            (
+                email_id.to_string(),
+                serde_json::json!({
+                    "mailboxIds": mailbox_ids,
+                    "keywords/$snoozed": true,
+                    "snoozed": snoozed,
+                }),
+            )
+        })
+        .collect::<serde_json::Map<_, _>>();
+
+    jmap_json_request(
+        serde_json::json!([[
+            "Email/set",
+            { "accountId": Id::from(account_id).to_string(), "update": update },
+            "0"
+        ]])
+        .to_string(),
+        "snooze@example.com",
+        "12345",
+    )
+    .await
+}
+
+async fn email_get_snoozed(account_id: u32, email_id: &str) -> serde_json::Value {
+    jmap_json_request(
+        serde_json::json!([[
+            "Email/get",
+            {
+                "accountId": Id::from(account_id).to_string(),
+                "ids": [email_id],
+                "properties": ["snoozed"]
+            },
+            "0"
+        ]])
+        .to_string(),
+        "snooze@example.com",
+        "12345",
+    )
+    .await["methodResponses"][0][1]["list"][0]["snoozed"]
+        .clone()
+}
+
+async fn drain_changes(changes: &mut mpsc::Receiver<StateChange>) {
+    while tokio::time::timeout(Duration::from_millis(500), changes.recv())
+        .await
+        .is_ok()
+    {}
+}
+
+async fn mailbox_count(server: &common::Server, account_id: u32, mailbox_id: u32) -> u64 {
+    server
+        .get_tag(
+            account_id,
+            Collection::Email,
+            Property::MailboxIds,
+            TagValue::Id(mailbox_id),
+        )
+        .await
+        .unwrap()
+        .map_or(0, |ids| ids.len())
+}
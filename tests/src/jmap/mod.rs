@@ -54,19 +54,27 @@ use crate::{
 };
 
 pub mod auth_acl;
+pub mod auth_app_password;
 pub mod auth_limits;
 pub mod auth_oauth;
+pub mod auth_totp;
+pub mod autodiscover;
 pub mod blob;
+pub mod carddav;
 pub mod crypto;
 pub mod delivery;
+pub mod email_archive;
+pub mod email_bulk_import;
 pub mod email_changes;
 pub mod email_copy;
 pub mod email_get;
+pub mod email_migrate;
 pub mod email_parse;
 pub mod email_query;
 pub mod email_query_changes;
 pub mod email_search_snippet;
 pub mod email_set;
+pub mod email_snooze;
 pub mod email_submission;
 pub mod enterprise;
 pub mod event_source;
@@ -105,6 +113,13 @@ greeting = 'Test LMTP instance'
 protocol = 'lmtp'
 tls.implicit = false
 
+[server.listener.lmtp-local]
+bind = ['127.0.0.1:11201']
+unix.path = '{TMP}/lmtp.sock'
+unix.mode = '0600'
+protocol = 'lmtp'
+tls.implicit = false
+
 [server.listener.pop3]
 bind = ["127.0.0.1:4110"]
 protocol = "pop3"
@@ -129,9 +144,16 @@ rate-limit = "100/2s"
 reject-non-fqdn = false
 
 [session.rcpt]
-relay = [ { if = "!is_empty(authenticated_as)", then = true }, 
+relay = [ { if = "!is_empty(authenticated_as) || listener = 'lmtp-local'", then = true }, 
           { else = false } ]
-directory = "'{STORE}'"
+directory = [ { if = "listener = 'lmtp-local'", then = false }, 
+              { else = "'{STORE}'" } ]
+
+[session.data]
+local-delivery = [ { if = "listener = 'lmtp-local'", then = true }, 
+                   { else = false } ]
+spam-filter = [ { if = "listener = 'lmtp-local'", then = false }, 
+                { else = true } ]
 
 [session.rcpt.errors]
 total = 5
@@ -140,6 +162,7 @@ wait = "1ms"
 [session.auth]
 mechanisms = "[plain, login, oauthbearer]"
 directory = "'{STORE}'"
+errors.wait = "1ms"
 
 [queue]
 path = "{TMP}"
@@ -254,6 +277,29 @@ attempts.interval = "500ms"
 [jmap.email]
 auto-expunge = "1s"
 
+[jmap.email.retention]
+legal-hold = ["jhold@example.net"]
+batch-size = 1
+
+[jmap.email.retention.policy."sent"]
+role = "sent"
+max-age = "30d"
+date = "received"
+domain = "example.net"
+
+[jmap.email.retention.policy."reports"]
+name = "reports/*"
+max-age = "7d"
+date = "received"
+account = "jsmith@example.net"
+
+[jmap.email.archive]
+enable = true
+mailboxes = ["inbox"]
+max-age = "365d"
+batch-size = 500
+opt-out = ["noarchive@example.com"]
+
 [jmap.protocol.changes]
 max-history = "1s"
 
@@ -386,6 +432,8 @@ pub async fn jmap_tests() {
     auth_acl::test(&mut params).await;
     auth_limits::test(&mut params).await;
     auth_oauth::test(&mut params).await;
+    auth_app_password::test(&mut params).await;
+    auth_totp::test(&mut params).await;
     event_source::test(&mut params).await;
     push_subscription::test(&mut params).await;
     sieve_script::test(&mut params).await;
@@ -395,8 +443,14 @@ pub async fn jmap_tests() {
     quota::test(&mut params).await;
     crypto::test(&mut params).await;
     blob::test(&mut params).await;
+    carddav::test(&mut params).await;
+    autodiscover::test(&mut params).await;
     permissions::test(&params).await;
     purge::test(&mut params).await;
+    email_archive::test(&mut params).await;
+    email_bulk_import::test(&mut params).await;
+    email_migrate::test(&mut params).await;
+    email_snooze::test(&mut params).await;
     enterprise::test(&mut params).await;
 
     if delete {
@@ -584,6 +638,7 @@ async fn init_jmap_tests(store_id: &str, delete_if_exists: bool) -> JMAPTest {
             .and_then(|id| stores.stores.get(id))
             .cloned()
             .unwrap_or_default(),
+        cfg_snapshot: Default::default(),
     };
     let tracers = Telemetry::parse(&mut config, &stores);
     let core = Core::parse(&mut config, stores, config_manager)
@@ -674,6 +674,7 @@ pub async fn test(params: &JMAPTest) {
             })
             .await,
         vec![DeliveryResult::TemporaryFailure {
+            code: [4, 2, 2],
             reason: "Organization over quota.".into()
         }]
     );
@@ -5,24 +5,29 @@
  */
 
 use ahash::AHashSet;
-use common::Server;
+use common::{config::jmap::retention::RetentionStats, Server};
 use directory::{backend::internal::manage::ManageDirectory, QueryBy};
+use hyper::Method;
 use imap_proto::ResponseType;
 use jmap::{
     email::delete::EmailDeletion,
     mailbox::{INBOX_ID, JUNK_ID, TRASH_ID},
     JmapMethods,
 };
+use jmap_client::{
+    client::Client,
+    mailbox::{self, Role},
+};
 use jmap_proto::types::{collection::Collection, id::Id, property::Property};
 use store::{
-    write::{key::DeserializeBigEndian, TagValue},
+    write::{key::DeserializeBigEndian, now, TagValue},
     IterateParams, LogKey, U32_LEN, U64_LEN,
 };
 
 use crate::{
     directory::internal::TestInternalDirectory,
     imap::{AssertResult, ImapConnection, Type},
-    jmap::assert_is_empty,
+    jmap::{assert_is_empty, ManagementApi},
 };
 
 use super::JMAPTest;
@@ -204,7 +209,190 @@ pub async fn test(params: &mut JMAPTest) {
         .delete_principal(QueryBy::Id(account_id))
         .await
         .unwrap();
-    assert_is_empty(server).await;
+    assert_is_empty(server.clone()).await;
+
+    // Test retention policies
+    retention(&server, client).await;
+}
+
+async fn retention(server: &Server, client: &mut Client) {
+    let account_id = server
+        .core
+        .storage
+        .data
+        .create_test_user(
+            "jsmith@example.net",
+            "12345",
+            "John Smith",
+            &["jsmith@example.net"],
+        )
+        .await;
+    let hold_account_id = server
+        .core
+        .storage
+        .data
+        .create_test_user(
+            "jhold@example.net",
+            "12345",
+            "John Hold",
+            &["jhold@example.net"],
+        )
+        .await;
+
+    // Obtain mailbox ids
+    let inbox_id = Id::from(INBOX_ID).to_string();
+    client.set_default_account_id(Id::from(account_id));
+    let sent_id = client
+        .mailbox_query(
+            mailbox::query::Filter::role(Role::Sent).into(),
+            [mailbox::query::Comparator::name()].into(),
+        )
+        .await
+        .unwrap()
+        .take_ids()
+        .pop()
+        .unwrap();
+    let reports_id = client
+        .mailbox_create("Reports", None::<String>, Role::None)
+        .await
+        .unwrap()
+        .take_id();
+    let reports_2023_id = client
+        .mailbox_create("2023", Some(&reports_id), Role::None)
+        .await
+        .unwrap()
+        .take_id();
+
+    // Import messages with synthetic received dates
+    let now = now() as i64;
+    let day = 86400;
+    let mut expect_removed = Vec::new();
+    let mut expect_kept = Vec::new();
+    for (mailbox_ids, age, is_removed) in [
+        (vec![&sent_id], 60 * day, true),
+        (vec![&sent_id], 10 * day, false),
+        (vec![&reports_2023_id], 10 * day, true),
+        (vec![&reports_2023_id], day, false),
+        (vec![&reports_id], 60 * day, false),
+        (vec![&inbox_id], 60 * day, false),
+        (vec![&inbox_id, &sent_id], 60 * day, false),
+    ] {
+        let id = client
+            .email_import(
+                format!(
+                    concat!(
+                        "From: bill@example.com\r\n",
+                        "To: jsmith@example.net\r\n",
+                        "Subject: Retention test {} days\r\n",
+                        "\r\n",
+                        "Did you get the memo about the new cover sheets?"
+                    ),
+                    age / day
+                )
+                .into_bytes(),
+                mailbox_ids,
+                None::<Vec<&str>>,
+                Some(now - age),
+            )
+            .await
+            .unwrap()
+            .take_id();
+        if is_removed {
+            expect_removed.push(id);
+        } else {
+            expect_kept.push(id);
+        }
+    }
+
+    // Accounts on legal hold are not affected by retention policies
+    client.set_default_account_id(Id::from(hold_account_id));
+    let hold_sent_id = client
+        .mailbox_query(
+            mailbox::query::Filter::role(Role::Sent).into(),
+            [mailbox::query::Comparator::name()].into(),
+        )
+        .await
+        .unwrap()
+        .take_ids()
+        .pop()
+        .unwrap();
+    let hold_id = client
+        .email_import(
+            b"From: bill@example.com\r\nSubject: On hold\r\n\r\nKeep me".to_vec(),
+            [&hold_sent_id],
+            None::<Vec<&str>>,
+            Some(now - 60 * day),
+        )
+        .await
+        .unwrap()
+        .take_id();
+
+    // Apply retention policies
+    let api = ManagementApi::new(8899, "admin", "secret");
+    let changes = get_changes(server).await;
+    *server.inner.data.retention_stats.lock() = Default::default();
+    server.purge_account(account_id).await;
+    server.purge_account(hold_account_id).await;
+
+    // Only the aged messages in the targeted mailboxes should have been removed
+    assert!(client
+        .email_get(&hold_id, None::<Vec<_>>)
+        .await
+        .unwrap()
+        .is_some());
+    client.set_default_account_id(Id::from(account_id));
+    for id in &expect_removed {
+        assert!(
+            client
+                .email_get(id, None::<Vec<_>>)
+                .await
+                .unwrap()
+                .is_none(),
+            "Message {id} was not expunged"
+        );
+    }
+    for id in &expect_kept {
+        assert!(
+            client
+                .email_get(id, None::<Vec<_>>)
+                .await
+                .unwrap()
+                .is_some(),
+            "Message {id} was expunged"
+        );
+    }
+
+    // Messages are expunged in batches, each one emitting a change
+    let new_changes = get_changes(server)
+        .await
+        .into_iter()
+        .filter(|change| !changes.contains(change) && change.1 == u8::from(Collection::Email))
+        .count();
+    assert_eq!(new_changes, 2);
+
+    // Check statistics
+    let stats = api
+        .request::<RetentionStats>(Method::GET, "/api/store/retention")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(stats.accounts, 2);
+    assert_eq!(stats.accounts_on_hold, 1);
+    assert_eq!(stats.expunged, 2);
+    assert_eq!(stats.policies.get("sent"), Some(&1));
+    assert_eq!(stats.policies.get("reports"), Some(&1));
+
+    // Delete accounts
+    for account_id in [account_id, hold_account_id] {
+        server
+            .core
+            .storage
+            .data
+            .delete_principal(QueryBy::Id(account_id))
+            .await
+            .unwrap();
+    }
+    assert_is_empty(server.clone()).await;
 }
 
 async fn get_changes(server: &Server) -> AHashSet<(u64, u8)> {
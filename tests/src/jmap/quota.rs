@@ -7,8 +7,11 @@
 use crate::{
     directory::internal::TestInternalDirectory,
     jmap::{
-        assert_is_empty, delivery::SmtpConnection, emails_purge_tombstoned, jmap_raw_request,
-        mailbox::destroy_all_mailboxes, test_account_login,
+        assert_is_empty,
+        delivery::{AssertResult, SmtpConnection},
+        emails_purge_tombstoned, jmap_raw_request,
+        mailbox::destroy_all_mailboxes,
+        test_account_login,
     },
 };
 use jmap::{blob::upload::DISABLE_UPLOAD_QUOTA, mailbox::INBOX_ID, JmapMethods};
@@ -18,6 +21,10 @@ use jmap_client::{
 };
 use jmap_proto::types::{collection::Collection, id::Id};
 use smtp::queue::spool::SmtpSpool;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::UnixStream,
+};
 
 use super::JMAPTest;
 
@@ -332,6 +339,77 @@ pub async fn test(params: &mut JMAPTest) {
             .len(),
         1,
     );
+
+    // Test final delivery over LMTP, each recipient gets its own reply
+    let mut lmtp = SmtpConnection::connect_port(11201).await;
+    lmtp.lhlo()
+        .await
+        .assert_contains("SIZE")
+        .assert_contains("8BITMIME")
+        .assert_contains("SMTPUTF8");
+    lmtp.mail_from("jane@example.com", 2).await;
+    for rcpt in [
+        "jdoe@example.com",
+        "robert@example.com",
+        "unknown@example.com",
+    ] {
+        lmtp.rcpt_to(rcpt, 2).await;
+    }
+    lmtp.data(3).await;
+    let replies = lmtp
+        .data_bytes(
+            &String::from_utf8(create_message_with_size(
+                "jane@example.com",
+                "robert@example.com",
+                "Ingest test 2",
+                513,
+            ))
+            .unwrap(),
+            3,
+            u8::MAX,
+        )
+        .await;
+    assert_eq!(replies.len(), 3, "{replies:?}");
+    assert!(
+        replies[0].starts_with("250 2.1.5 <jdoe@example.com>"),
+        "{replies:?}"
+    );
+    assert!(
+        replies[1].starts_with("452 4.2.2 <robert@example.com>"),
+        "{replies:?}"
+    );
+    assert!(
+        replies[2].starts_with("550 5.5.0 <unknown@example.com>"),
+        "{replies:?}"
+    );
+    assert_eq!(
+        server
+            .get_document_ids(account_id.document_id(), Collection::Email)
+            .await
+            .unwrap()
+            .unwrap()
+            .len(),
+        1,
+    );
+
+    // The same listener accepts connections over its Unix domain socket
+    let mut stream = BufReader::new(
+        UnixStream::connect(params.temp_dir.path.join("lmtp.sock"))
+            .await
+            .unwrap(),
+    );
+    let mut line = String::new();
+    stream.read_line(&mut line).await.unwrap();
+    assert!(line.starts_with("220 "), "{line:?}");
+    stream
+        .get_mut()
+        .write_all(b"LHLO localhost\r\n")
+        .await
+        .unwrap();
+    line.clear();
+    stream.read_line(&mut line).await.unwrap();
+    assert!(line.starts_with("250-"), "{line:?}");
+
     DISABLE_UPLOAD_QUOTA.store(true, std::sync::atomic::Ordering::Relaxed);
 
     // Remove test data
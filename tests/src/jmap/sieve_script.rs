@@ -4,6 +4,8 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+use common::auth::ResourceToken;
+use jmap::sieve::{get::SieveScriptGet, set::SieveScriptSet, SieveScript};
 use jmap_client::{
     core::set::{SetError, SetErrorType},
     email, mailbox,
@@ -100,6 +102,53 @@ pub async fn test(params: &mut JMAPTest) {
         );
     }
 
+    // Typed accessors return the script name, source, compiled script and state
+    let document_id = Id::from_bytes(account_id.as_bytes()).unwrap().document_id();
+    let scripts = server.list_sieve_scripts(document_id).await.unwrap();
+    assert_eq!(scripts.len(), 5);
+    assert!(scripts.iter().all(|(_, _, is_active)| !is_active));
+    let script = server
+        .get_sieve_script(document_id, scripts[0].0)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(script.name, scripts[0].1);
+    assert!(script.compiled.is_some());
+    assert!(!script.active);
+    let resource_token = ResourceToken {
+        account_id: document_id,
+        quota: 0,
+        tenant: None,
+    };
+    let script_id = server
+        .set_sieve_script(
+            &resource_token,
+            None,
+            SieveScript {
+                name: "typed_script".to_string(),
+                source: b"require \"fileinto\"; fileinto \"typed\";".to_vec(),
+                compiled: None,
+                active: false,
+            },
+        )
+        .await
+        .unwrap();
+    let script = server
+        .get_sieve_script(document_id, script_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(script.name, "typed_script");
+    assert_eq!(
+        script.source,
+        b"require \"fileinto\"; fileinto \"typed\";".to_vec()
+    );
+    assert!(script.compiled.is_some());
+    client
+        .sieve_script_destroy(&Id::from(script_id).to_string())
+        .await
+        .unwrap();
+
     // Activate last script twice and then the first script
     for _ in 0..2 {
         client
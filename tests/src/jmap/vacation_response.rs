@@ -15,7 +15,8 @@ use crate::{
         assert_is_empty,
         delivery::SmtpConnection,
         email_submission::{
-            assert_message_delivery, expect_nothing, spawn_mock_smtp_server, MockMessage,
+            assert_message_delivery, expect_message_delivery, expect_nothing,
+            spawn_mock_smtp_server, MockMessage,
         },
         mailbox::destroy_all_mailboxes,
     },
@@ -82,12 +83,25 @@ pub async fn test(params: &mut JMAPTest) {
     )
     .await;
 
-    // Await vacation response
-    assert_message_delivery(
-        &mut smtp_rx,
-        MockMessage::new("<jdoe@example.com>", ["<bill@remote.org>"], "@Kokomo"),
-    )
-    .await;
+    // Await vacation response, which includes both text and HTML bodies
+    let message = expect_message_delivery(&mut smtp_rx).await;
+    assert_eq!(message.mail_from, "<jdoe@example.com>");
+    assert_eq!(message.rcpt_to, ["<bill@remote.org>"]);
+    for part in [
+        "Kokomo",
+        "multipart/alternative",
+        "text/plain",
+        "text/html",
+        "That's where you wanna go",
+        "That's where <b>you wanna go</b>",
+    ] {
+        assert!(
+            message.message.contains(part),
+            "[{}] needle = {:?}",
+            message.message,
+            part
+        );
+    }
 
     // Further messages from the same recipient should not
     // trigger a vacation response
@@ -157,7 +171,6 @@ pub async fn test(params: &mut JMAPTest) {
         )
         .await
         .unwrap();
-    smtp_settings.lock().do_stop = true;
     lmtp.ingest(
         "jane_smith@remote.org",
         &["jdoe@example.com"],
@@ -170,15 +183,65 @@ pub async fn test(params: &mut JMAPTest) {
         ),
     )
     .await;
+    assert_message_delivery(
+        &mut smtp_rx,
+        MockMessage::new("<jdoe@example.com>", ["<jane_smith@remote.org>"], "@Kokomo"),
+    )
+    .await;
+
+    // Sieve vacation actions share the suppression window with the vacation response
+    let script_id = client
+        .sieve_script_create(
+            "out_of_office",
+            b"require \"vacation\";\r\nvacation :days 1 \"Gone fishing\";\r\n".to_vec(),
+            true,
+        )
+        .await
+        .unwrap()
+        .take_id();
+    for sender in ["bill@remote.org", "jane_smith@remote.org"] {
+        lmtp.ingest(
+            sender,
+            &["jdoe@example.com"],
+            &format!(
+                concat!(
+                    "From: {}\r\n",
+                    "To: jdoe@example.com\r\n",
+                    "Subject: Are you there?\r\n",
+                    "\r\n",
+                    "Just checking in.",
+                ),
+                sender
+            ),
+        )
+        .await;
+    }
+    expect_nothing(&mut smtp_rx).await;
+
+    smtp_settings.lock().do_stop = true;
+    lmtp.ingest(
+        "ted@remote.org",
+        &["jdoe@example.com"],
+        concat!(
+            "From: ted@remote.org\r\n",
+            "To: jdoe@example.com\r\n",
+            "Subject: Are you there?\r\n",
+            "\r\n",
+            "Just checking in.",
+        ),
+    )
+    .await;
     lmtp.quit().await;
 
     assert_message_delivery(
         &mut smtp_rx,
-        MockMessage::new("<jdoe@example.com>", ["<jane_smith@remote.org>"], "@Kokomo"),
+        MockMessage::new("<jdoe@example.com>", ["<ted@remote.org>"], "@Gone fishing"),
     )
     .await;
 
     // Remove test data
+    client.sieve_script_deactivate().await.unwrap();
+    client.sieve_script_destroy(&script_id).await.unwrap();
     client.vacation_response_destroy().await.unwrap();
     destroy_all_mailboxes(params).await;
     assert_is_empty(server).await;
@@ -15,6 +15,8 @@ use trc::Collector;
 #[global_allocator]
 static GLOBAL: Jemalloc = Jemalloc;
 
+#[cfg(test)]
+pub mod cluster;
 #[cfg(test)]
 pub mod directory;
 #[cfg(test)]
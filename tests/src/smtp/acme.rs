@@ -0,0 +1,203 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+// These tests require a running Pebble ACME test server (https://github.com/letsencrypt/pebble)
+// with challenge validation enabled. They are skipped unless PEBBLE_DIRECTORY is set, e.g.:
+//
+//   pebble -config test/config/pebble-config.json -dnsserver 127.0.0.1:8053
+//   PEBBLE_DIRECTORY=https://localhost:14000/dir PEBBLE_DOMAIN=mx.example.org cargo test acme
+//
+// PEBBLE_DOMAIN must resolve to 127.0.0.1 from Pebble's point of view and PEBBLE_TLS_PORT
+// must match Pebble's tlsPort setting (5001 by default).
+
+use std::{sync::Arc, time::Duration};
+
+use common::{
+    config::server::Listeners,
+    manager::config::{ConfigManager, Patterns},
+    Core, Data, Server,
+};
+use rustls_pki_types::{CertificateDer, ServerName};
+use smtp::core::SmtpSessionManager;
+use store::Stores;
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+use utils::config::Config;
+
+use crate::{
+    smtp::{add_test_certs, TempDir, TestSMTP},
+    AssertConfig,
+};
+
+const CONFIG: &str = r#"
+[server.listener.smtps]
+bind = ['127.0.0.1:{TLS_PORT}']
+protocol = 'smtp'
+tls.implicit = true
+
+[acme."pebble"]
+directory = "{DIRECTORY}"
+contact = ["postmaster@example.org"]
+domains = ["{DOMAIN}"]
+challenge = "tls-alpn-01"
+max-retries = 0
+
+[acme."pebble-dns"]
+directory = "{DIRECTORY}"
+contact = ["postmaster@example.org"]
+domains = ["{DOMAIN}"]
+challenge = "dns-01"
+provider = "rfc2136-tsig"
+host = "127.0.0.1"
+port = 1053
+key = "stalwart"
+tsig-algorithm = "hmac-sha256"
+secret = "c2VjcmV0"
+polling-interval = "1s"
+propagation-timeout = "2s"
+max-retries = 0
+"#;
+
+#[tokio::test]
+#[serial_test::serial]
+async fn acme_pebble() {
+    let Ok(directory) = std::env::var("PEBBLE_DIRECTORY") else {
+        println!("Skipping ACME tests, PEBBLE_DIRECTORY is not set.");
+        return;
+    };
+    let domain = std::env::var("PEBBLE_DOMAIN").unwrap_or_else(|_| "localhost".to_string());
+    let tls_port = std::env::var("PEBBLE_TLS_PORT").unwrap_or_else(|_| "5001".to_string());
+
+    // Enable logging
+    crate::enable_logging();
+
+    // Build a server with a configuration store for the ACME cache
+    let acme_config = CONFIG
+        .replace("{DIRECTORY}", &directory)
+        .replace("{DOMAIN}", &domain)
+        .replace("{TLS_PORT}", &tls_port);
+    let temp_dir = TempDir::new("smtp_acme_test", true);
+    let mut config = Config::new(
+        temp_dir.update_config(add_test_certs(crate::smtp::CONFIG) + acme_config.as_str()),
+    )
+    .unwrap();
+    config.resolve_all_macros().await;
+    let stores = Stores::parse_all(&mut config).await;
+    let manager = ConfigManager {
+        cfg_local: Arc::new(config.keys.clone()).into(),
+        cfg_local_path: Default::default(),
+        cfg_local_patterns: Patterns::parse(&mut config).into(),
+        cfg_store: config
+            .value("storage.data")
+            .and_then(|id| stores.stores.get(id))
+            .cloned()
+            .unwrap_or_default(),
+        cfg_snapshot: Default::default(),
+    };
+    let core = Core::parse(&mut config, stores, manager).await;
+    let data = Data::parse(&mut config);
+    let local = TestSMTP::from_core_and_tempdir(core, data, Some(temp_dir));
+    let server = local.server.clone();
+
+    // Spawn an implicit TLS SMTP listener to answer TLS-ALPN-01 challenges
+    let mut config = Config::new(&acme_config).unwrap();
+    let mut servers = Listeners::parse(&mut config);
+    servers.parse_tcp_acceptors(&mut config, server.inner.clone());
+    servers.bind_and_drop_priv(&mut config);
+    config.assert_no_errors();
+    let _shutdown_tx = servers
+        .spawn(|listener, acceptor, shutdown_rx| {
+            listener.spawn(
+                SmtpSessionManager::new(server.inner.clone()),
+                server.inner.clone(),
+                acceptor,
+                shutdown_rx,
+            );
+        })
+        .0;
+
+    // First issuance
+    let provider = server.core.acme.providers.get("pebble").unwrap();
+    assert_eq!(
+        server.init_acme(provider).await.unwrap(),
+        Duration::from_millis(1000)
+    );
+    let renew_at = server.renew(provider).await.unwrap();
+    let cert = served_cert(&server, &domain);
+    assert_eq!(cert, peer_cert(&domain, &tls_port).await);
+
+    // Renewal is scheduled when a third of the lifetime remains
+    let lifetime = cert_lifetime(&cert);
+    assert!(
+        renew_at <= lifetime * 2 / 3 && renew_at > lifetime * 2 / 3 - Duration::from_secs(86400),
+        "renew_at: {renew_at:?}, lifetime: {lifetime:?}"
+    );
+
+    // Renewing replaces the certificate without a restart
+    server.renew(provider).await.unwrap();
+    let renewed_cert = served_cert(&server, &domain);
+    assert_ne!(cert, renewed_cert);
+    assert_eq!(renewed_cert, peer_cert(&domain, &tls_port).await);
+
+    // Certificates are loaded from the store on startup
+    server.init_acme(provider).await.unwrap();
+    assert_eq!(renewed_cert, served_cert(&server, &domain));
+
+    // Failed DNS-01 renewals keep the current certificate in use
+    let dns_provider = server.core.acme.providers.get("pebble-dns").unwrap();
+    let cached_cert = server
+        .core
+        .storage
+        .config
+        .get("acme.pebble.cert")
+        .await
+        .unwrap()
+        .unwrap();
+    server
+        .core
+        .storage
+        .config
+        .set([("acme.pebble-dns.cert", cached_cert.as_str())])
+        .await
+        .unwrap();
+    server.init_acme(dns_provider).await.unwrap();
+    let err = server.renew(dns_provider).await.unwrap_err();
+    assert_eq!(
+        server.renew_failed(dns_provider, err).await,
+        Duration::from_secs(3600)
+    );
+    assert_eq!(renewed_cert, served_cert(&server, &domain));
+    assert_eq!(renewed_cert, peer_cert(&domain, &tls_port).await);
+}
+
+fn served_cert(server: &Server, domain: &str) -> CertificateDer<'static> {
+    server
+        .inner
+        .data
+        .tls_certificates
+        .load()
+        .get(domain)
+        .expect("certificate not found")
+        .cert[0]
+        .clone()
+}
+
+async fn peer_cert(domain: &str, port: &str) -> CertificateDer<'static> {
+    let stream = TcpStream::connect(format!("127.0.0.1:{port}"))
+        .await
+        .unwrap();
+    let stream = TlsConnector::from(Arc::new(utils::rustls_client_config(true)))
+        .connect(ServerName::try_from(domain.to_string()).unwrap(), stream)
+        .await
+        .unwrap();
+    stream.get_ref().1.peer_certificates().unwrap()[0].clone()
+}
+
+fn cert_lifetime(cert: &CertificateDer<'_>) -> Duration {
+    let (_, cert) = x509_parser::parse_x509_certificate(cert.as_ref()).unwrap();
+    let validity = cert.validity();
+    Duration::from_secs((validity.not_after.timestamp() - validity.not_before.timestamp()) as u64)
+}
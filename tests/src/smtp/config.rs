@@ -8,7 +8,7 @@ use std::{fs, net::IpAddr, path::PathBuf, sync::Arc, time::Duration};
 
 use common::{
     config::{
-        server::{Listener, Listeners, ServerProtocol, TcpListener},
+        server::{Listener, Listeners, ServerProtocol, TcpListener, UnixListener},
         smtp::{throttle::parse_throttle, *},
     },
     expr::{functions::ResolveVariable, if_block::*, tokenizer::TokenMap, *},
@@ -16,7 +16,7 @@ use common::{
 };
 use tokio::net::TcpSocket;
 
-use utils::config::{Config, Rate};
+use utils::config::{Config, Rate, RateWindow};
 
 use super::add_test_certs;
 
@@ -276,14 +276,16 @@ fn parse_throttles() {
                     requests: 50,
                     period: Duration::from_secs(30)
                 }
-                .into()
+                .into(),
+                window: RateWindow::Fixed,
             },
             Throttle {
                 id: "0001".to_string(),
                 expr: Expression::default(),
                 keys: THROTTLE_SENDER_DOMAIN,
                 concurrency: 10000.into(),
-                rate: None
+                rate: None,
+                window: RateWindow::Fixed,
             }
         ]
     );
@@ -304,6 +306,22 @@ fn parse_servers() {
     let servers = Listeners::parse(&mut config).servers;
     let id_generator = Arc::new(utils::snowflake::SnowflakeIdGenerator::new());
     let expected_servers = vec![
+        Listener {
+            id: "lmtp".to_string(),
+            protocol: ServerProtocol::Lmtp,
+            listeners: vec![],
+            unix_listeners: vec![UnixListener {
+                path: "/var/run/stalwart/lmtp.sock".into(),
+                mode: Some(0o660),
+                allowed_uids: vec![0, 101],
+                allowed_gids: vec![12],
+            }],
+            max_connections: 8192,
+            proxy_networks: vec![],
+            proxy_required: false,
+            allow_cram_md5: false,
+            span_id_gen: id_generator.clone(),
+        },
         Listener {
             id: "smtp".to_string(),
             protocol: ServerProtocol::Smtp,
@@ -315,8 +333,11 @@ fn parse_servers() {
                 linger: None,
                 nodelay: true,
             }],
+            unix_listeners: vec![],
             max_connections: 8192,
             proxy_networks: vec![],
+            proxy_required: false,
+            allow_cram_md5: false,
             span_id_gen: id_generator.clone(),
         },
         Listener {
@@ -340,8 +361,11 @@ fn parse_servers() {
                     nodelay: true,
                 },
             ],
+            unix_listeners: vec![],
             max_connections: 1024,
             proxy_networks: vec![],
+            proxy_required: false,
+            allow_cram_md5: false,
             span_id_gen: id_generator.clone(),
         },
         Listener {
@@ -355,12 +379,16 @@ fn parse_servers() {
                 linger: None,
                 nodelay: true,
             }],
+            unix_listeners: vec![],
             max_connections: 8192,
             proxy_networks: vec![],
+            proxy_required: false,
+            allow_cram_md5: false,
             span_id_gen: id_generator.clone(),
         },
     ];
 
+    assert_eq!(servers.len(), expected_servers.len());
     for (server, expected_server) in servers.into_iter().zip(expected_servers) {
         assert_eq!(
             server.id, expected_server.id,
@@ -391,6 +419,22 @@ fn parse_servers() {
                 expected_server.id
             );
         }
+        assert_eq!(
+            server.unix_listeners.len(),
+            expected_server.unix_listeners.len(),
+            "failed for {}",
+            expected_server.id
+        );
+        for (listener, expected_listener) in server
+            .unix_listeners
+            .into_iter()
+            .zip(expected_server.unix_listeners)
+        {
+            assert_eq!(listener.path, expected_listener.path);
+            assert_eq!(listener.mode, expected_listener.mode);
+            assert_eq!(listener.allowed_uids, expected_listener.allowed_uids);
+            assert_eq!(listener.allowed_gids, expected_listener.allowed_gids);
+        }
     }
 }
 
@@ -0,0 +1,247 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use common::{attachments::AttachmentReport, config::spamfilter::AttachmentConfig, Core};
+use mail_parser::MessageParser;
+use smtp::core::Session;
+use store::Stores;
+use utils::config::Config;
+
+use crate::smtp::{
+    inbound::TestMessage,
+    session::{load_test_message, TestSession, VerifyResponse},
+    TempDir, TestSMTP,
+};
+
+const CONFIG: &str = r#"
+[storage]
+data = "sqlite"
+lookup = "sqlite"
+blob = "sqlite"
+fts = "sqlite"
+
+[store."sqlite"]
+type = "sqlite"
+path = "{TMP}/queue.db"
+
+[session.rcpt]
+relay = true
+
+[session.data]
+script = "'attachments'"
+
+[spam-filter.attachments]
+max-depth = 3
+max-ratio = 100
+
+[spam-filter.attachments.policy]
+reject = ["exe", "bomb"]
+quarantine = ["macro"]
+tag = ["encrypted", ".vbs"]
+
+[sieve.trusted]
+hostname = "mx.foobar.org"
+no-capability-check = true
+
+[sieve.trusted.scripts.attachments]
+contents = '''
+require ["editheader"];
+
+if eval "contains(env.attachment.types, 'js') && env.attachment.encrypted" {
+    addheader "X-Encrypted-Script" "yes";
+}
+'''
+"#;
+
+#[tokio::test]
+async fn attachment_inspection() {
+    // Enable logging
+    crate::enable_logging();
+
+    // Configure tests
+    let tmp_dir = TempDir::new("smtp_attachments_test", true);
+    let mut config = Config::new(tmp_dir.update_config(CONFIG)).unwrap();
+    let stores = Stores::parse_all(&mut config).await;
+    let core = Core::parse(&mut config, stores, Default::default()).await;
+    let attachments = core.spam.attachments.clone();
+
+    // Archives are opened recursively and file types detected by their contents
+    let report = inspect(&attachments, "nested_zip");
+    assert_eq!(
+        files(&report),
+        [
+            ("documents.zip", 0, Some("zip"), Some("zip")),
+            ("invoices.zip", 1, Some("zip"), Some("zip")),
+            ("invoice.pdf.exe", 2, Some("exe"), Some("exe")),
+            ("readme.txt", 2, Some("txt"), None),
+            ("logs.tar.gz", 0, Some("gz"), Some("gz")),
+            ("logs.tar", 1, Some("tar"), Some("tar")),
+            ("run.vbs", 2, Some("vbs"), None),
+        ]
+    );
+    assert!(report.double_extension);
+    assert!(!report.encrypted && !report.macros && !report.bomb && !report.truncated);
+
+    // Nested archives beyond the maximum depth are not opened
+    let mut shallow = attachments.clone();
+    shallow.max_depth = 1;
+    let report = inspect(&shallow, "nested_zip");
+    assert_eq!(
+        files(&report)
+            .into_iter()
+            .map(|(name, _, _, _)| name)
+            .collect::<Vec<_>>(),
+        ["documents.zip", "invoices.zip", "logs.tar.gz", "logs.tar"]
+    );
+    assert!(report.truncated);
+
+    // Entry limits
+    let mut few_entries = attachments.clone();
+    few_entries.max_entries = 2;
+    let report = inspect(&few_entries, "nested_zip");
+    assert!(report.truncated);
+    assert!(!report.types().contains(&"vbs"));
+
+    // Encrypted archives still expose their entry names
+    let report = inspect(&attachments, "encrypted_zip");
+    assert_eq!(
+        files(&report),
+        [
+            ("secret.zip", 0, Some("zip"), Some("zip")),
+            ("payload.js", 1, Some("js"), None),
+        ]
+    );
+    assert!(report.encrypted);
+    assert!(!report.double_extension && !report.macros && !report.bomb);
+
+    // Macro enabled Office documents
+    let report = inspect(&attachments, "macro_xlsm");
+    assert!(report.macros);
+    assert!(report
+        .files
+        .iter()
+        .any(|file| file.name == "vbaProject.bin" && file.depth == 1));
+    assert!(!report.encrypted && !report.bomb);
+
+    // Zip bombs are defused by the compression ratio limit without decompressing them
+    let report = inspect(&attachments, "zip_bomb");
+    assert_eq!(
+        files(&report)
+            .into_iter()
+            .map(|(name, depth, _, _)| (name, depth))
+            .collect::<Vec<_>>(),
+        [("archive.zip", 0), ("bomb.zip", 1), ("zeros.zip", 2)]
+    );
+    assert!(report.bomb);
+
+    // and by the total size limit when the ratio check is disabled
+    let mut no_ratio = attachments.clone();
+    no_ratio.max_ratio = u64::MAX;
+    no_ratio.max_size = 10 * 1024 * 1024;
+    let report = inspect(&no_ratio, "zip_bomb");
+    assert_eq!(
+        files(&report)
+            .into_iter()
+            .map(|(name, depth, _, _)| (name, depth))
+            .collect::<Vec<_>>(),
+        [
+            ("archive.zip", 0),
+            ("bomb.zip", 1),
+            ("zeros.zip", 2),
+            ("zeros.bin", 3)
+        ]
+    );
+    assert!(report.bomb);
+
+    // Build session
+    let test = TestSMTP::from_core(core);
+    let mut qr = test.queue_receiver;
+    let mut session = Session::test(test.server);
+    session.data.remote_ip_str = "10.0.0.1".to_string();
+    session.data.remote_ip = session.data.remote_ip_str.parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx.doe.org").await;
+
+    // Messages without attachments are not modified
+    session
+        .send_message("john@doe.org", &["bill@foobar.org"], "test:no_dkim", "250")
+        .await;
+    qr.expect_message()
+        .await
+        .read_lines(&qr)
+        .await
+        .assert_not_contains("X-Attachment-Warning")
+        .assert_not_contains("X-Quarantine");
+
+    // Executables hidden in nested archives and zip bombs are rejected
+    for fixture in ["nested_zip", "zip_bomb"] {
+        session
+            .send_message(
+                "john@doe.org",
+                &["bill@foobar.org"],
+                &load_test_message(fixture, "attachments"),
+                "550 5.7.1",
+            )
+            .await;
+        qr.assert_no_events();
+    }
+
+    // Macros are quarantined
+    session
+        .send_message(
+            "john@doe.org",
+            &["bill@foobar.org"],
+            &load_test_message("macro_xlsm", "attachments"),
+            "250",
+        )
+        .await;
+    qr.expect_message()
+        .await
+        .read_lines(&qr)
+        .await
+        .assert_contains("X-Quarantine: true")
+        .assert_contains("X-Attachment-Warning: macro");
+
+    // Encrypted archives are tagged and the results are available to Sieve scripts
+    session
+        .send_message(
+            "john@doe.org",
+            &["bill@foobar.org"],
+            &load_test_message("encrypted_zip", "attachments"),
+            "250",
+        )
+        .await;
+    qr.expect_message()
+        .await
+        .read_lines(&qr)
+        .await
+        .assert_contains("X-Attachment-Warning: encrypted")
+        .assert_contains("X-Encrypted-Script: yes")
+        .assert_not_contains("X-Quarantine");
+}
+
+fn inspect(config: &AttachmentConfig, fixture: &str) -> AttachmentReport {
+    config.inspect(
+        &MessageParser::default()
+            .parse(load_test_message(fixture, "attachments").as_bytes())
+            .unwrap(),
+    )
+}
+
+fn files(report: &AttachmentReport) -> Vec<(&str, usize, Option<&str>, Option<&str>)> {
+    report
+        .files
+        .iter()
+        .map(|file| {
+            (
+                file.name.as_str(),
+                file.depth,
+                file.extension.as_deref(),
+                file.file_type,
+            )
+        })
+        .collect()
+}
@@ -0,0 +1,284 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use common::Core;
+use nlp::bayes::{TokenHash, Weights};
+use store::Stores;
+use utils::config::Config;
+
+use crate::smtp::{TempDir, TestSMTP};
+
+const CONFIG: &str = r#"
+[storage]
+data = "sqlite"
+lookup = "sqlite"
+blob = "sqlite"
+fts = "sqlite"
+
+[store."sqlite"]
+type = "sqlite"
+path = "{TMP}/queue.db"
+
+[lookup]
+"bayes.store" = "memory"
+
+[spam-filter.bayes]
+store = "bayes"
+
+[spam-filter.bayes.account]
+enable = true
+weight = 0.5
+"#;
+
+const HAM_WORDS: &[&str] = &[
+    "meeting",
+    "project",
+    "schedule",
+    "report",
+    "budget",
+    "review",
+    "agenda",
+    "quarterly",
+    "deadline",
+    "attached",
+    "minutes",
+    "colleague",
+    "conference",
+    "presentation",
+    "proposal",
+    "feedback",
+    "draft",
+    "lunch",
+    "weekend",
+    "family",
+    "dinner",
+    "holiday",
+    "photos",
+    "birthday",
+    "garden",
+    "football",
+    "library",
+    "homework",
+    "kitchen",
+    "recipe",
+];
+
+const SPAM_WORDS: &[&str] = &[
+    "viagra",
+    "casino",
+    "winner",
+    "lottery",
+    "prize",
+    "cheap",
+    "pills",
+    "bitcoin",
+    "guaranteed",
+    "million",
+    "unclaimed",
+    "inheritance",
+    "click",
+    "discount",
+    "pharmacy",
+    "replica",
+    "watches",
+    "loan",
+    "credit",
+    "urgent",
+    "bonus",
+    "jackpot",
+    "dating",
+    "singles",
+    "diet",
+    "miracle",
+    "refinance",
+    "unsubscribe",
+    "exclusive",
+    "congratulations",
+];
+
+const COMMON_WORDS: &[&str] = &[
+    "today",
+    "please",
+    "information",
+    "email",
+    "time",
+    "message",
+    "people",
+    "week",
+    "thanks",
+    "regards",
+    "question",
+    "number",
+];
+
+const ACCOUNT_ID: u32 = 1;
+
+#[tokio::test]
+async fn bayes_classifier() {
+    // Enable logging
+    crate::enable_logging();
+
+    let tmp_dir = TempDir::new("smtp_bayes_test", true);
+    let mut config = Config::new(tmp_dir.update_config(CONFIG)).unwrap();
+    let stores = Stores::parse_all(&mut config).await;
+    let core = Core::parse(&mut config, stores, Default::default()).await;
+    let server = TestSMTP::from_core(core).server;
+    let store = server.bayes_store();
+    let classifier = server.core.spam.bayes.classifier.clone();
+    assert!(!std::ptr::eq(store, &server.core.storage.lookup));
+
+    // Train both the global and the account models
+    let mut fixtures = Fixtures::new(0x2545_f491_4f6c_dd1d);
+    for _ in 0..200 {
+        for is_spam in [false, true] {
+            server
+                .bayes_train(store, Some(ACCOUNT_ID), &fixtures.next(is_spam), is_spam, 0)
+                .await
+                .unwrap();
+        }
+    }
+    for account_id in [None, Some(ACCOUNT_ID)] {
+        assert_eq!(
+            server
+                .bayes_weights(store, account_id, TokenHash::default())
+                .await
+                .unwrap(),
+            Weights {
+                spam: 200,
+                ham: 200
+            }
+        );
+    }
+
+    // Classify held-out samples
+    let mut correct = 0;
+    let total = 100;
+    for n in 0..total {
+        let is_spam = n % 2 == 0;
+        let result = server
+            .bayes_classify(
+                store,
+                Some(ACCOUNT_ID),
+                &fixtures.next(is_spam),
+                &classifier,
+            )
+            .await
+            .unwrap()
+            .expect("classifier returned no result");
+        if (result >= 0.5) == is_spam {
+            correct += 1;
+        }
+    }
+    assert!(
+        correct as f64 / total as f64 >= 0.95,
+        "classified {correct}/{total} samples correctly"
+    );
+
+    // Accounts without training data fall back to the global model
+    let text = fixtures.next(true);
+    assert_eq!(
+        server
+            .bayes_classify(store, Some(ACCOUNT_ID + 1), &text, &classifier)
+            .await
+            .unwrap(),
+        server
+            .bayes_classify(store, None, &text, &classifier)
+            .await
+            .unwrap()
+    );
+
+    // Untraining restores the previous counts
+    let text = fixtures.next(true);
+    let tokens = [
+        TokenHash::default(),
+        token_hash("viagra"),
+        token_hash("casino"),
+        token_hash("meeting"),
+    ];
+    let mut before = Vec::new();
+    for account_id in [None, Some(ACCOUNT_ID)] {
+        for token in tokens {
+            before.push(
+                server
+                    .bayes_weights(store, account_id, token)
+                    .await
+                    .unwrap(),
+            );
+        }
+    }
+    server
+        .bayes_train(store, Some(ACCOUNT_ID), &text, true, 0)
+        .await
+        .unwrap();
+    assert_eq!(
+        server
+            .bayes_weights(store, Some(ACCOUNT_ID), TokenHash::default())
+            .await
+            .unwrap(),
+        Weights {
+            spam: 201,
+            ham: 200
+        }
+    );
+    server
+        .bayes_untrain(store, Some(ACCOUNT_ID), &text, true, 0)
+        .await
+        .unwrap();
+    let mut after = Vec::new();
+    for account_id in [None, Some(ACCOUNT_ID)] {
+        for token in tokens {
+            after.push(
+                server
+                    .bayes_weights(store, account_id, token)
+                    .await
+                    .unwrap(),
+            );
+        }
+    }
+    assert_eq!(before, after);
+}
+
+struct Fixtures {
+    state: u64,
+}
+
+impl Fixtures {
+    fn new(seed: u64) -> Self {
+        Fixtures { state: seed }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        // xorshift64
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    fn pick(&mut self, words: &[&'static str]) -> &'static str {
+        words[(self.next_u64() % words.len() as u64) as usize]
+    }
+
+    fn next(&mut self, is_spam: bool) -> String {
+        let words = if is_spam { SPAM_WORDS } else { HAM_WORDS };
+        let mut text = String::new();
+        for _ in 0..40 {
+            if !text.is_empty() {
+                text.push(' ');
+            }
+            text.push_str(if self.next_u64() % 10 < 7 {
+                self.pick(words)
+            } else {
+                self.pick(COMMON_WORDS)
+            });
+        }
+        text
+    }
+}
+
+fn token_hash(word: &str) -> TokenHash {
+    TokenHash::from(nlp::tokenizers::osb::Gram::Uni { t1: word })
+}
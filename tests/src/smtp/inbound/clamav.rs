@@ -0,0 +1,364 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use common::Core;
+use smtp::core::Session;
+use store::Stores;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::watch,
+};
+use utils::config::Config;
+
+use crate::smtp::{
+    inbound::TestMessage,
+    session::{TestSession, VerifyResponse},
+    TempDir, TestSMTP,
+};
+
+const CONFIG: &str = r#"
+[storage]
+data = "sqlite"
+lookup = "sqlite"
+blob = "sqlite"
+fts = "sqlite"
+
+[store."sqlite"]
+type = "sqlite"
+path = "{TMP}/queue.db"
+
+[session.rcpt]
+relay = true
+
+[session.data]
+script = "'clamav'"
+
+[spam-filter.clamav]
+address = "127.0.0.1:9335"
+timeout = "1s"
+chunk-size = 64
+action = "tag"
+
+[sieve.trusted]
+hostname = "mx.foobar.org"
+no-capability-check = true
+
+[sieve.trusted.scripts.clamav]
+contents = '''
+require ["editheader"];
+
+if eval "env.clamav.result == 'clean'" {
+    addheader "X-ClamAV-Result" "clean";
+} elsif eval "env.clamav.result == 'infected' && env.clamav.signature == 'Eicar-Test-Signature'" {
+    addheader "X-ClamAV-Result" "infected";
+} elsif eval "env.clamav.result == 'error'" {
+    addheader "X-ClamAV-Result" "error";
+}
+'''
+"#;
+
+// clamd's StreamMaxLength setting
+const STREAM_MAX_LENGTH: usize = 1024;
+
+const EICAR_MESSAGE: &str = concat!(
+    "From: john@doe.org\r\n",
+    "To: bill@foobar.org\r\n",
+    "Subject: Test virus\r\n",
+    "\r\n",
+    "X5O!P%@AP[4\\PZX54(P^)7CC)7}$EICAR-STANDARD-ANTIVIRUS-TEST-FILE!$H+H*\r\n"
+);
+
+#[derive(Debug, Default)]
+struct MockStats {
+    connections: usize,
+    scans: usize,
+}
+
+#[tokio::test]
+async fn clamav_scan() {
+    // Enable logging
+    crate::enable_logging();
+
+    // Configure tests
+    let tmp_dir = TempDir::new("smtp_clamav_test", true);
+    let mut config = Config::new(tmp_dir.update_config(CONFIG)).unwrap();
+    let stores = Stores::parse_all(&mut config).await;
+    let core = Core::parse(&mut config, stores, Default::default()).await;
+    let stats = Arc::new(Mutex::new(MockStats::default()));
+    let _rx = spawn_mock_clamd_server(stats.clone());
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let large_message = format!(
+        "From: john@doe.org\r\nTo: bill@foobar.org\r\nSubject: Large\r\n\r\n{}",
+        "0123456789abcdefghijklmnopqrstuvwxyz\r\n".repeat(50)
+    );
+
+    // Build session
+    let test = TestSMTP::from_core(core);
+    let mut qr = test.queue_receiver;
+    let mut session = Session::test(test.server);
+    session.data.remote_ip_str = "10.0.0.1".to_string();
+    session.data.remote_ip = session.data.remote_ip_str.parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx.doe.org").await;
+
+    // Messages are scanned once regardless of the number of recipients
+    session
+        .send_message(
+            "john@doe.org",
+            &["bill@foobar.org", "jane@foobar.org"],
+            "test:no_dkim",
+            "250",
+        )
+        .await;
+    qr.expect_message()
+        .await
+        .read_lines(&qr)
+        .await
+        .assert_contains("X-ClamAV-Result: clean")
+        .assert_not_contains("X-Virus");
+    assert_eq!(stats.lock().unwrap().scans, 1);
+
+    // Connections are reused between scans
+    session
+        .send_message("john@doe.org", &["bill@foobar.org"], "test:no_dkim", "250")
+        .await;
+    qr.expect_message()
+        .await
+        .read_lines(&qr)
+        .await
+        .assert_contains("X-ClamAV-Result: clean");
+    assert_eq!(stats.lock().unwrap().connections, 1);
+    assert_eq!(stats.lock().unwrap().scans, 2);
+
+    // Infected messages are tagged and the signature is available to Sieve scripts
+    session
+        .send_message("john@doe.org", &["bill@foobar.org"], EICAR_MESSAGE, "250")
+        .await;
+    qr.expect_message()
+        .await
+        .read_lines(&qr)
+        .await
+        .assert_contains("X-Virus: Eicar-Test-Signature")
+        .assert_contains("X-ClamAV-Result: infected")
+        .assert_not_contains("X-Quarantine");
+
+    // Errors reported mid-stream are ignored by default and the connection is discarded
+    session
+        .send_message("john@doe.org", &["bill@foobar.org"], &large_message, "250")
+        .await;
+    qr.expect_message()
+        .await
+        .read_lines(&qr)
+        .await
+        .assert_contains("X-ClamAV-Result: error")
+        .assert_not_contains("X-Virus");
+    session
+        .send_message("john@doe.org", &["bill@foobar.org"], "test:no_dkim", "250")
+        .await;
+    qr.expect_message()
+        .await
+        .read_lines(&qr)
+        .await
+        .assert_contains("X-ClamAV-Result: clean");
+    assert_eq!(stats.lock().unwrap().connections, 2);
+
+    // Reject infected messages and tempfail on errors
+    let mut config = Config::new(tmp_dir.update_config(CONFIG.replace(
+        "action = \"tag\"",
+        "action = \"reject\"\ntempfail-on-error = true",
+    )))
+    .unwrap();
+    let stores = Stores::parse_all(&mut config).await;
+    let core = Core::parse(&mut config, stores, Default::default()).await;
+    let test = TestSMTP::from_core(core);
+    let mut qr = test.queue_receiver;
+    let mut session = Session::test(test.server);
+    session.data.remote_ip_str = "10.0.0.1".to_string();
+    session.data.remote_ip = session.data.remote_ip_str.parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx.doe.org").await;
+    session
+        .send_message(
+            "john@doe.org",
+            &["bill@foobar.org"],
+            EICAR_MESSAGE,
+            "550 5.7.1 Message contains a virus (Eicar-Test-Signature).",
+        )
+        .await;
+    qr.assert_no_events();
+    session
+        .send_message(
+            "john@doe.org",
+            &["bill@foobar.org"],
+            &large_message,
+            "451 4.3.5",
+        )
+        .await;
+    qr.assert_no_events();
+
+    // Quarantine infected messages and respect the maximum stream length
+    let mut config = Config::new(tmp_dir.update_config(CONFIG.replace(
+        "action = \"tag\"",
+        concat!(
+            "action = \"quarantine\"\n",
+            "max-stream-size = 1024\n",
+            "quarantine.account = \"quarantine@foobar.org\"",
+        ),
+    )))
+    .unwrap();
+    let stores = Stores::parse_all(&mut config).await;
+    let core = Core::parse(&mut config, stores, Default::default()).await;
+    let test = TestSMTP::from_core(core);
+    let mut qr = test.queue_receiver;
+    let mut session = Session::test(test.server);
+    session.data.remote_ip_str = "10.0.0.1".to_string();
+    session.data.remote_ip = session.data.remote_ip_str.parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx.doe.org").await;
+    session
+        .send_message(
+            "john@doe.org",
+            &["bill@foobar.org", "jane@foobar.org"],
+            EICAR_MESSAGE,
+            "250",
+        )
+        .await;
+    let message = qr.expect_message().await;
+    assert_eq!(
+        message
+            .recipients
+            .iter()
+            .map(|rcpt| rcpt.address.as_str())
+            .collect::<Vec<_>>(),
+        ["quarantine@foobar.org"]
+    );
+    message
+        .read_lines(&qr)
+        .await
+        .assert_contains("X-Quarantine: true")
+        .assert_contains("X-Virus: Eicar-Test-Signature");
+    session
+        .send_message("john@doe.org", &["bill@foobar.org"], &large_message, "250")
+        .await;
+    qr.expect_message()
+        .await
+        .read_lines(&qr)
+        .await
+        .assert_contains("X-ClamAV-Result: clean");
+}
+
+fn spawn_mock_clamd_server(stats: Arc<Mutex<MockStats>>) -> watch::Sender<bool> {
+    let (tx, mut rx) = watch::channel(true);
+
+    tokio::spawn(async move {
+        let listener = TcpListener::bind("127.0.0.1:9335")
+            .await
+            .unwrap_or_else(|e| {
+                panic!("Failed to bind mock ClamAV server to 127.0.0.1:9335: {e}");
+            });
+        loop {
+            tokio::select! {
+                stream = listener.accept() => {
+                    match stream {
+                        Ok((stream, _)) => {
+                            stats.lock().unwrap().connections += 1;
+                            tokio::spawn(handle_clamd_session(stream, stats.clone()));
+                        }
+                        Err(err) => {
+                            panic!("Something went wrong: {err}" );
+                        }
+                    }
+                },
+                _ = rx.changed() => {
+                    break;
+                }
+            };
+        }
+    });
+
+    tx
+}
+
+async fn handle_clamd_session(mut stream: TcpStream, stats: Arc<Mutex<MockStats>>) {
+    let mut request_id = 0;
+
+    loop {
+        // Commands prefixed with 'z' are NUL terminated
+        let mut command = Vec::new();
+        loop {
+            match stream.read_u8().await {
+                Ok(0) => break,
+                Ok(ch) => command.push(ch),
+                Err(_) => return,
+            }
+        }
+
+        let response = match command.as_slice() {
+            b"zIDSESSION" => continue,
+            b"zEND" => return,
+            b"zPING" => "PONG",
+            b"zINSTREAM" => {
+                stats.lock().unwrap().scans += 1;
+                let mut data = Vec::new();
+                let mut exceeded = false;
+                loop {
+                    let Ok(len) = stream.read_u32().await else {
+                        return;
+                    };
+                    if len == 0 {
+                        break;
+                    }
+                    let mut chunk = vec![0u8; len as usize];
+                    if stream.read_exact(&mut chunk).await.is_err() {
+                        return;
+                    }
+
+                    // Reply as soon as the limit is exceeded, the rest of the stream
+                    // is drained so the reply is not lost to a connection reset
+                    if !exceeded && data.len() + chunk.len() > STREAM_MAX_LENGTH {
+                        exceeded = true;
+                        request_id += 1;
+                        let _ = stream
+                            .write_all(
+                                format!("{request_id}: INSTREAM size limit exceeded. ERROR\0")
+                                    .as_bytes(),
+                            )
+                            .await;
+                    }
+                    data.extend_from_slice(&chunk);
+                }
+                if exceeded {
+                    return;
+                }
+
+                if data
+                    .windows(34)
+                    .any(|w| w == b"EICAR-STANDARD-ANTIVIRUS-TEST-FILE")
+                {
+                    "stream: Eicar-Test-Signature FOUND"
+                } else {
+                    "stream: OK"
+                }
+            }
+            _ => "UNKNOWN COMMAND",
+        };
+
+        request_id += 1;
+        if stream
+            .write_all(format!("{request_id}: {response}\0").as_bytes())
+            .await
+            .is_err()
+        {
+            return;
+        }
+    }
+}
@@ -0,0 +1,191 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::{Duration, Instant};
+
+use common::Core;
+use mail_auth::{
+    common::parse::TxtRecordParser,
+    dmarc::{Dmarc, Policy},
+    spf::Spf,
+    DmarcResult,
+};
+use smtp::{core::Session, inbound::forwarder::ImportedAuthResults};
+use store::Stores;
+use utils::config::Config;
+
+use crate::smtp::{
+    inbound::TestMessage,
+    session::{TestSession, VerifyResponse},
+    TempDir, TestSMTP,
+};
+
+const CONFIG: &str = r#"
+[storage]
+data = "sqlite"
+lookup = "sqlite"
+blob = "sqlite"
+fts = "sqlite"
+
+[store."sqlite"]
+type = "sqlite"
+path = "{TMP}/queue.db"
+
+[directory."local"]
+type = "memory"
+
+[[directory."local".principals]]
+name = "john"
+description = "John Doe"
+secret = "secret"
+email = ["jdoe@example.com"]
+
+[session.rcpt]
+directory = "'local'"
+
+[session.data.add-headers]
+received = true
+received-spf = true
+auth-results = true
+message-id = true
+date = true
+return-path = false
+
+[auth.trusted-forwarder]
+hosts = ["10.0.0.1"]
+authserv-id = "edge.example.com"
+
+[auth.spf.verify]
+ehlo = "relaxed"
+mail-from = "strict"
+
+[auth.dmarc]
+verify = "strict"
+
+[auth.dkim]
+verify = "relaxed"
+
+[auth.arc]
+verify = "relaxed"
+"#;
+
+const MESSAGE: &str = concat!(
+    "Authentication-Results: edge.example.com;\r\n",
+    "\tspf=pass smtp.mailfrom=bill@example.com;\r\n",
+    "\tdkim=pass (good signature) header.d=example.com;\r\n",
+    "\tdmarc={DMARC} header.from=example.com policy.dmarc=reject\r\n",
+    "Authentication-Results: edge.example.com;\r\n",
+    "\tdkim=pass header.d=older.example.com\r\n",
+    "From: bill@example.com\r\n",
+    "To: jdoe@example.com\r\n",
+    "Subject: test\r\n",
+    "\r\n",
+    "test\r\n",
+);
+
+#[tokio::test]
+async fn trusted_forwarder() {
+    // Enable logging
+    crate::enable_logging();
+
+    // Parse Authentication-Results
+    let results = ImportedAuthResults::parse(concat!(
+        " Edge.Example.com 1 (comment; with semicolon);\r\n",
+        "\tspf=softfail smtp.mailfrom=a@b.com;\r\n",
+        "\tdkim=fail header.d=b.com; dkim=pass header.d=B.com;\r\n",
+        "\tdmarc=fail (p=QUARANTINE) header.from=b.com"
+    ))
+    .unwrap();
+    assert_eq!(results.authserv_id, "edge.example.com");
+    assert_eq!(results.spf_result(), Some("softfail"));
+    assert_eq!(results.dkim_result(), Some("pass"));
+    assert_eq!(
+        results.dkim_pass_domains().collect::<Vec<_>>(),
+        vec!["b.com".to_string()]
+    );
+    assert!(matches!(results.dmarc_result(), Some(DmarcResult::Fail(_))));
+    assert_eq!(results.dmarc_policy(), Some(Policy::Unspecified));
+    assert_eq!(results.arc_result(), None);
+
+    let tmp_dir = TempDir::new("smtp_forwarder_test", true);
+    let mut config = Config::new(tmp_dir.update_config(CONFIG)).unwrap();
+    let stores = Stores::parse_all(&mut config).await;
+    let core = Core::parse(&mut config, stores, Default::default()).await;
+
+    // Only the internal relay is allowed to send mail for example.com
+    core.smtp.resolvers.dns.txt_add(
+        "example.com",
+        Spf::parse(b"v=spf1 ip4:10.0.0.2 -all").unwrap(),
+        Instant::now() + Duration::from_secs(5),
+    );
+    core.smtp.resolvers.dns.txt_add(
+        "_dmarc.example.com",
+        Dmarc::parse(b"v=DMARC1; p=reject").unwrap(),
+        Instant::now() + Duration::from_secs(5),
+    );
+
+    // SPF is not evaluated against the trusted forwarder and its results are imported
+    let test = TestSMTP::from_core(core);
+    let mut qr = test.queue_receiver;
+    let mut session = Session::test(test.server.clone());
+    session.data.remote_ip_str = "10.0.0.1".to_string();
+    session.data.remote_ip = session.data.remote_ip_str.parse().unwrap();
+    session.eval_session_params().await;
+    assert!(session.params.trusted_forwarder);
+    session.ehlo("edge.example.com").await;
+    session
+        .send_message(
+            "bill@example.com",
+            &["jdoe@example.com"],
+            &MESSAGE.replace("{DMARC}", "pass"),
+            "250",
+        )
+        .await;
+    qr.expect_message()
+        .await
+        .read_lines(&qr)
+        .await
+        .assert_contains("Authentication-Results: localhost;")
+        .assert_contains("spf=pass smtp.mailfrom=bill@example.com")
+        .assert_contains("dkim=pass header.d=example.com")
+        .assert_contains("dmarc=pass header.from=example.com policy.dmarc=reject")
+        .assert_not_contains("Received-SPF");
+
+    // DMARC failures reported by the forwarder are rejected as per policy
+    session
+        .send_message(
+            "bill@example.com",
+            &["jdoe@example.com"],
+            &MESSAGE.replace("{DMARC}", "fail"),
+            "550 5.7.1",
+        )
+        .await;
+    qr.assert_no_events();
+
+    // Spoofed results from untrusted hosts are removed
+    let mut session = Session::test(test.server.clone());
+    session.data.remote_ip_str = "10.0.0.2".to_string();
+    session.data.remote_ip = session.data.remote_ip_str.parse().unwrap();
+    session.eval_session_params().await;
+    assert!(!session.params.trusted_forwarder);
+    session.ehlo("mx.example.com").await;
+    session
+        .send_message(
+            "bill@example.com",
+            &["jdoe@example.com"],
+            &MESSAGE.replace("{DMARC}", "pass"),
+            "250",
+        )
+        .await;
+    qr.expect_message()
+        .await
+        .read_lines(&qr)
+        .await
+        .assert_contains("Authentication-Results: localhost")
+        .assert_contains("Received-SPF: pass")
+        .assert_not_contains("edge.example.com;")
+        .assert_not_contains("older.example.com");
+}
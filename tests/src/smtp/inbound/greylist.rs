@@ -0,0 +1,97 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use crate::smtp::{session::TestSession, TempDir, TestSMTP};
+use common::Core;
+use smtp::core::{Session, SessionAddress};
+use store::Stores;
+use utils::config::Config;
+
+const CONFIG: &str = r#"
+[storage]
+data = "sqlite"
+lookup = "sqlite"
+blob = "sqlite"
+fts = "sqlite"
+
+[store."sqlite"]
+type = "sqlite"
+path = "{TMP}/data.db"
+
+[session.rcpt.greylist]
+enable = [{if = "remote_ip = '10.0.5.3'", then = false},
+          {else = true}]
+min-delay = "1s"
+expire = "1h"
+
+[session.rcpt.greylist.allowlist]
+after = 2
+expire = "1d"
+"#;
+
+#[tokio::test]
+async fn greylist() {
+    // Enable logging
+    crate::enable_logging();
+
+    let tmp_dir = TempDir::new("smtp_greylist_test", true);
+    let mut config = Config::new(tmp_dir.update_config(CONFIG)).unwrap();
+    let stores = Stores::parse_all(&mut config).await;
+    let core = Core::parse(&mut config, stores, Default::default()).await;
+
+    let mut session = Session::test(TestSMTP::from_core(core).server);
+    set_remote_ip(&mut session, "10.0.0.1");
+    session.data.mail_from = address("sender@test.org").into();
+
+    // First delivery attempt is deferred
+    session.data.rcpt_to.push(address("john@example.org"));
+    assert!(session.is_greylisted().await, "First attempt not deferred.");
+
+    // Retrying before the minimum delay is deferred again
+    assert!(session.is_greylisted().await, "Early retry not deferred.");
+
+    // Retrying from the same /24 network after the delay is accepted
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    set_remote_ip(&mut session, "10.0.0.200");
+    assert!(!session.is_greylisted().await, "Retry not accepted.");
+    assert!(!session.is_greylisted().await, "Passed triplet deferred.");
+
+    // Different recipients are greylisted until the network is allowlisted
+    session.data.rcpt_to.push(address("jane@example.org"));
+    assert!(session.is_greylisted().await, "New triplet not deferred.");
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    assert!(!session.is_greylisted().await, "Retry not accepted.");
+    session.data.rcpt_to.push(address("bill@example.org"));
+    assert!(
+        !session.is_greylisted().await,
+        "Allowlisted network deferred."
+    );
+
+    // Other networks are still greylisted
+    set_remote_ip(&mut session, "10.0.1.1");
+    assert!(session.is_greylisted().await, "Other network not deferred.");
+
+    // Greylisting can be disabled by rule
+    set_remote_ip(&mut session, "10.0.5.3");
+    assert!(!session.is_greylisted().await, "Greylisting not disabled.");
+}
+
+fn set_remote_ip(session: &mut Session<impl common::listener::SessionStream>, ip: &str) {
+    session.data.remote_ip_str = ip.to_string();
+    session.data.remote_ip = ip.parse().unwrap();
+}
+
+fn address(address: &str) -> SessionAddress {
+    SessionAddress {
+        address: address.to_string(),
+        address_lcase: address.to_string(),
+        domain: address.rsplit_once('@').unwrap().1.to_string(),
+        flags: 0,
+        dsn_info: None,
+    }
+}
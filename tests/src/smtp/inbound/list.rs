@@ -0,0 +1,260 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use ahash::AHashSet;
+use common::Core;
+use directory::{
+    backend::internal::{manage::ManageDirectory, PrincipalField, PrincipalValue},
+    Principal, Type,
+};
+use store::Stores;
+use utils::config::Config;
+
+use crate::{
+    smtp::{inbound::TestMessage, session::TestSession, TempDir, TestSMTP},
+    AssertConfig,
+};
+use smtp::{
+    core::Session,
+    queue::list::{parse_verp, verp_address, ListMemberStatus, SmtpMailingList},
+};
+
+const CONFIG: &str = r#"
+[storage]
+data = "sqlite"
+lookup = "sqlite"
+blob = "sqlite"
+fts = "sqlite"
+directory = "local"
+
+[store."sqlite"]
+type = "sqlite"
+path = "{TMP}/queue.db"
+
+[directory."local"]
+type = "internal"
+store = "sqlite"
+
+[session.rcpt]
+directory = "'local'"
+
+[mailing-list]
+enable = true
+bounce.threshold = 3
+bounce.expire = "1d"
+digest.max-messages = 10
+"#;
+
+const LIST: &str = "sales@foobar.org";
+
+#[tokio::test]
+async fn mailing_list() {
+    // Enable logging
+    crate::enable_logging();
+
+    let tmp_dir = TempDir::new("smtp_mailing_list_test", true);
+    let mut config = Config::new(tmp_dir.update_config(CONFIG)).unwrap();
+    let stores = Stores::parse_all(&mut config).await;
+    let core = Core::parse(&mut config, stores, Default::default()).await;
+    config.assert_no_errors();
+
+    let test = TestSMTP::from_core(core);
+    let qr = &test.queue_receiver;
+    let server = &test.server;
+
+    // VERP encoding
+    assert_eq!(
+        verp_address(LIST, "jane@example.net"),
+        "sales-bounces+jane=example.net@foobar.org"
+    );
+    assert_eq!(
+        parse_verp("sales-bounces+jane=example.net@foobar.org"),
+        Some((LIST.to_string(), "jane@example.net".to_string()))
+    );
+    assert_eq!(parse_verp("sales@foobar.org"), None);
+    assert_eq!(parse_verp("sales-bounces+jane@foobar.org"), None);
+
+    // Create a list with 50 members
+    let members = (0..50)
+        .map(|i| format!("member{i}@example.net"))
+        .collect::<Vec<_>>();
+    let internal_store = &server.core.storage.data;
+    internal_store
+        .create_principal(
+            Principal::new(0, Type::Domain).with_field(PrincipalField::Name, "foobar.org"),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    internal_store
+        .create_principal(
+            Principal::new(0, Type::List)
+                .with_field(PrincipalField::Name, LIST)
+                .with_field(PrincipalField::Emails, LIST)
+                .with_field(
+                    PrincipalField::ExternalMembers,
+                    PrincipalValue::StringList(members.clone()),
+                ),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+
+    let mut session = Session::test(server.clone());
+    session.data.remote_ip_str = "10.0.0.1".to_string();
+    session.eval_session_params().await;
+    session.ehlo("mx.example.com").await;
+
+    // Posts are expanded to one message per member, each with its own VERP sender
+    session
+        .send_message(
+            "john@example.com",
+            &[LIST],
+            "From: john@example.com\r\nTo: sales@foobar.org\r\nSubject: first post\r\n\r\nHi!",
+            "250",
+        )
+        .await;
+    let messages = qr.read_queued_messages().await;
+    assert_eq!(messages.len(), 50);
+    let mut recipients = AHashSet::new();
+    for message in &messages {
+        assert_eq!(message.recipients.len(), 1);
+        let rcpt = &message.recipients[0];
+        assert_eq!(message.return_path, verp_address(LIST, &rcpt.address));
+        assert_eq!(rcpt.orcpt.as_deref(), Some(LIST));
+        let contents = message.read_message(qr).await;
+        for header in [
+            "List-Id: <sales.foobar.org>",
+            "List-Post: <mailto:sales@foobar.org>",
+            "List-Unsubscribe: <mailto:sales@foobar.org?subject=unsubscribe>",
+            "Subject: first post",
+        ] {
+            assert!(contents.contains(header), "{header} not in {contents}");
+        }
+        recipients.insert(rcpt.address.clone());
+    }
+    assert_eq!(recipients, members.iter().cloned().collect::<AHashSet<_>>());
+    qr.clear_queue(server).await;
+
+    // A bounce sent to a VERP address increments the counter of that member only
+    let verp = verp_address(LIST, &members[0]);
+    session
+        .send_message(
+            "postmaster@example.net",
+            &[verp.as_str()],
+            "From: postmaster@example.net\r\nSubject: Undeliverable\r\n\r\nMailbox full",
+            "250",
+        )
+        .await;
+    qr.assert_queue_is_empty().await;
+    let lookup = server.lookup_store();
+    let mut total_bounces = 0;
+    for member in &members {
+        let bounces = lookup
+            .counter_get(format!("lb:{LIST}:{member}").into_bytes())
+            .await
+            .unwrap();
+        if member == &members[0] {
+            assert_eq!(bounces, 1);
+        }
+        total_bounces += bounces;
+    }
+    assert_eq!(total_bounces, 1);
+    assert_eq!(
+        server.list_member_status(LIST, &members[0]).await.unwrap(),
+        ListMemberStatus::Active
+    );
+
+    // VERP addresses of non-members are rejected
+    session.mail_from("postmaster@example.net", "250").await;
+    session
+        .rcpt_to(&verp_address(LIST, "stranger@example.net"), "550 5.1.2")
+        .await;
+    session.rset().await;
+
+    // Members are suspended once the bounce threshold is reached
+    assert_eq!(
+        server.list_bounce(LIST, &members[0], 0).await.unwrap(),
+        ListMemberStatus::Active
+    );
+    assert_eq!(
+        server.list_bounce(LIST, &members[0], 0).await.unwrap(),
+        ListMemberStatus::Suspended
+    );
+    assert_eq!(
+        server.list_member_status(LIST, &members[0]).await.unwrap(),
+        ListMemberStatus::Suspended
+    );
+    assert_eq!(
+        server.list_member_status(LIST, &members[1]).await.unwrap(),
+        ListMemberStatus::Active
+    );
+
+    // Suspended members no longer receive posts
+    session
+        .send_message(
+            "john@example.com",
+            &[LIST],
+            "From: john@example.com\r\nSubject: second post\r\n\r\nHi again!",
+            "250",
+        )
+        .await;
+    let messages = qr.read_queued_messages().await;
+    assert_eq!(messages.len(), 49);
+    assert!(!messages
+        .iter()
+        .any(|message| message.recipients[0].address == members[0]));
+    qr.clear_queue(server).await;
+
+    // Digest members receive held posts in a single MIME digest
+    server
+        .list_set_digest(LIST, &members[1], true)
+        .await
+        .unwrap();
+    assert_eq!(
+        server.list_member_status(LIST, &members[1]).await.unwrap(),
+        ListMemberStatus::Digest
+    );
+    for subject in ["digest one", "digest two"] {
+        session
+            .send_message(
+                "john@example.com",
+                &[LIST],
+                &format!("From: john@example.com\r\nSubject: {subject}\r\n\r\nHi!"),
+                "250",
+            )
+            .await;
+    }
+    let messages = qr.read_queued_messages().await;
+    assert_eq!(messages.len(), 48 * 2);
+    assert!(!messages.iter().any(|message| {
+        message.recipients[0].address == members[0] || message.recipients[0].address == members[1]
+    }));
+    qr.clear_queue(server).await;
+
+    server.list_send_digests().await.unwrap();
+    let messages = qr.read_queued_messages().await;
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].recipients[0].address, members[1]);
+    assert_eq!(messages[0].return_path, verp_address(LIST, &members[1]));
+    let contents = messages[0].read_message(qr).await;
+    for part in [
+        "Subject: sales@foobar.org digest, 2 messages",
+        "multipart/digest",
+        "Subject: digest one",
+        "Subject: digest two",
+    ] {
+        assert!(contents.contains(part), "{part} not in {contents}");
+    }
+    assert!(!contents.contains("first post") && !contents.contains("second post"));
+    qr.clear_queue(server).await;
+
+    // Held messages are removed once the digest is sent
+    server.list_send_digests().await.unwrap();
+    qr.assert_queue_is_empty().await;
+}
@@ -4,7 +4,16 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::{fs, net::SocketAddr, path::PathBuf, sync::Arc, time::Duration};
+use std::{
+    fs,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
 use ahash::AHashSet;
 use common::{
@@ -31,16 +40,19 @@ use smtp::{
 };
 use store::Stores;
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    net::{TcpListener, TcpStream},
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, UnixListener},
     sync::watch,
 };
 use utils::config::Config;
 
-use crate::smtp::{
-    inbound::TestMessage,
-    session::{load_test_message, TestSession, VerifyResponse},
-    TempDir, TestSMTP,
+use crate::{
+    smtp::{
+        inbound::TestMessage,
+        session::{load_test_message, TestSession, VerifyResponse},
+        TempDir, TestSMTP,
+    },
+    AssertConfig,
 };
 
 #[derive(Debug, Deserialize)]
@@ -92,7 +104,10 @@ relay = true
 [[session.hook]]
 url = "http://127.0.0.1:9333"
 enable = true
+timeout = "1s"
 stages = ["data"]
+cache.key = "sender"
+options.max-concurrency = 4
 "#;
 
 #[tokio::test]
@@ -222,6 +237,118 @@ async fn milter_session() {
         .assert_contains("123456");
 }
 
+const CONFIG_MILTER_UNIX: &str = r#"
+[storage]
+data = "sqlite"
+lookup = "sqlite"
+blob = "sqlite"
+fts = "sqlite"
+directory = "local"
+
+[store."sqlite"]
+type = "sqlite"
+path = "{TMP}/queue.db"
+
+[directory."local"]
+type = "memory"
+
+[session.rcpt]
+relay = true
+
+[session.data]
+script = "'milter_headers'"
+
+[[session.milter]]
+path = "{TMP}/milter.sock"
+enable = true
+timeout.connect = "1s"
+stages = ["data"]
+
+[sieve.trusted.scripts.milter_headers]
+contents = '''
+require ["reject"];
+
+if header :is "X-Hello" "World" {
+    reject "Milter header found.";
+    stop;
+}
+'''
+"#;
+
+#[tokio::test]
+async fn milter_unix_session() {
+    // Enable logging
+    crate::enable_logging();
+
+    // Configure tests
+    let tmp_dir = TempDir::new("smtp_milter_unix_test", true);
+    let mut config = Config::new(tmp_dir.update_config(CONFIG_MILTER_UNIX)).unwrap();
+    let stores = Stores::parse_all(&mut config).await;
+    let core = Core::parse(&mut config, stores, Default::default()).await;
+    config.assert_no_errors();
+
+    // Build session
+    let test = TestSMTP::from_core(core);
+    let mut qr = test.queue_receiver;
+    let mut session = Session::test(test.server);
+    session.data.remote_ip_str = "10.0.0.1".to_string();
+    session.eval_session_params().await;
+    session.ehlo("mx.doe.org").await;
+
+    // Unreachable milters result in a temporary failure
+    session
+        .send_message(
+            "accept@doe.org",
+            &["bill@foobar.org"],
+            "test:no_dkim",
+            "451 4.3.5",
+        )
+        .await;
+    qr.assert_no_events();
+
+    // Spawn milter listening on a Unix domain socket
+    let _rx = spawn_mock_milter_unix_server(tmp_dir.temp_dir.join("milter.sock"));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Test reject
+    session
+        .send_message(
+            "reject@doe.org",
+            &["bill@foobar.org"],
+            "test:no_dkim",
+            "503 5.5.3",
+        )
+        .await;
+    qr.assert_no_events();
+
+    // Headers added by milters are visible to Sieve scripts
+    session
+        .send_message(
+            "0@doe.org",
+            &["bill@foobar.org"],
+            "test:no_dkim",
+            "503 5.5.3 Milter header found.",
+        )
+        .await;
+    qr.assert_no_events();
+
+    // Test accept with header replacement
+    session
+        .send_message(
+            "3@doe.org",
+            &["bill@foobar.org"],
+            "test:no_dkim",
+            "250 2.0.0",
+        )
+        .await;
+    qr.expect_message()
+        .await
+        .read_lines(&qr)
+        .await
+        .assert_contains("Subject: [SPAM] Saying Hello")
+        .assert_contains("Are you hungry yet?");
+}
+
 #[tokio::test]
 async fn mta_hook_session() {
     // Enable logging
@@ -353,6 +480,61 @@ async fn mta_hook_session() {
         .await
         .assert_contains("X-Spam: Yes")
         .assert_contains("123456");
+
+    // Test quarantine
+    session
+        .send_message(
+            "quarantine@doe.org",
+            &["bill@foobar.org"],
+            "test:no_dkim",
+            "250 2.0.0",
+        )
+        .await;
+    qr.expect_message()
+        .await
+        .read_lines(&qr)
+        .await
+        .assert_contains("X-Quarantine: true");
+
+    // Test spam score
+    session
+        .send_message(
+            "spam@doe.org",
+            &["bill@foobar.org"],
+            "test:no_dkim",
+            "250 2.0.0",
+        )
+        .await;
+    qr.expect_message()
+        .await
+        .read_lines(&qr)
+        .await
+        .assert_contains("X-Spam-Score: 7.5");
+
+    // Responses are cached by key
+    for _ in 0..2 {
+        session
+            .send_message(
+                "cached@doe.org",
+                &["bill@foobar.org"],
+                "test:no_dkim",
+                "250 2.0.0",
+            )
+            .await;
+        qr.expect_message()
+            .await
+            .read_lines(&qr)
+            .await
+            .assert_contains("X-Request: 1");
+    }
+
+    // Slow endpoints and malformed responses result in a temporary failure
+    for sender in ["slow@doe.org", "malformed@doe.org"] {
+        session
+            .send_message(sender, &["bill@foobar.org"], "test:no_dkim", "451 4.3.5")
+            .await;
+        qr.assert_no_events();
+    }
 }
 
 #[test]
@@ -550,6 +732,7 @@ async fn milter_client_test() {
             addrs: vec![SocketAddr::from(([127, 0, 0, 1], PORT))],
             hostname: "localhost".to_string(),
             port: PORT,
+            path: None,
             timeout_connect: Duration::from_secs(10),
             timeout_command: Duration::from_secs(30),
             timeout_data: Duration::from_secs(30),
@@ -652,8 +835,52 @@ pub fn spawn_mock_milter_server() -> watch::Sender<bool> {
     tx
 }
 
+pub fn spawn_mock_milter_unix_server(path: PathBuf) -> watch::Sender<bool> {
+    let (tx, rx) = watch::channel(true);
+    let tests = Arc::new(
+        serde_json::from_str::<Vec<HeaderTest>>(
+            &fs::read_to_string(
+                PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                    .join("resources")
+                    .join("smtp")
+                    .join("milter")
+                    .join("message.json"),
+            )
+            .unwrap(),
+        )
+        .unwrap(),
+    );
+
+    let _ = fs::remove_file(&path);
+    let listener = UnixListener::bind(&path).unwrap_or_else(|e| {
+        panic!("Failed to bind mock Milter server to {path:?}: {e}");
+    });
+    tokio::spawn(async move {
+        let mut rx_ = rx.clone();
+        loop {
+            tokio::select! {
+                stream = listener.accept() => {
+                    match stream {
+                        Ok((stream, _)) => {
+                            tokio::spawn(accept_milter(stream, rx.clone(), tests.clone()));
+                        }
+                        Err(err) => {
+                            panic!("Something went wrong: {err}" );
+                        }
+                    }
+                },
+                _ = rx_.changed() => {
+                    break;
+                }
+            };
+        }
+    });
+
+    tx
+}
+
 async fn accept_milter(
-    mut stream: TcpStream,
+    mut stream: impl AsyncRead + AsyncWrite + Unpin,
     mut rx: watch::Receiver<bool>,
     tests: Arc<Vec<HeaderTest>>,
 ) {
@@ -766,6 +993,7 @@ async fn accept_milter(
 
 pub fn spawn_mock_mta_hook_server() -> watch::Sender<bool> {
     let (tx, rx) = watch::channel(true);
+    let requests = Arc::new(AtomicUsize::new(0));
     let tests = Arc::new(
         serde_json::from_str::<Vec<HeaderTest>>(
             &fs::read_to_string(
@@ -800,15 +1028,23 @@ pub fn spawn_mock_mta_hook_server() -> watch::Sender<bool> {
                                 TokioIo::new(stream),
                                 service_fn(|mut req: hyper::Request<body::Incoming>| {
                                     let tests = tests.clone();
+                                    let requests = requests.clone();
 
                                     async move {
 
                                         let request = serde_json::from_slice::<Request>(&fetch_body(&mut req, 1024 * 1024,0).await.unwrap())
                                         .unwrap();
-                                        let response = handle_mta_hook(request, tests);
+                                        let response = match request.envelope.as_ref().unwrap().from.address.as_str() {
+                                            "malformed@doe.org" => "{\"action\": \"acc".to_string(),
+                                            "slow@doe.org" => {
+                                                tokio::time::sleep(Duration::from_secs(2)).await;
+                                                serde_json::to_string(&handle_mta_hook(request, tests, requests)).unwrap()
+                                            }
+                                            _ => serde_json::to_string(&handle_mta_hook(request, tests, requests)).unwrap(),
+                                        };
 
                                         Ok::<_, hyper::Error>(
-                                            Resource::new("application/json", serde_json::to_string(&response).unwrap().into_bytes())
+                                            Resource::new("application/json", response.into_bytes())
                                             .into_http_response().build(),
                                         )
                                     }
@@ -832,7 +1068,11 @@ pub fn spawn_mock_mta_hook_server() -> watch::Sender<bool> {
     tx
 }
 
-fn handle_mta_hook(request: Request, tests: Arc<Vec<HeaderTest>>) -> hooks::Response {
+fn handle_mta_hook(
+    request: Request,
+    tests: Arc<Vec<HeaderTest>>,
+    requests: Arc<AtomicUsize>,
+) -> hooks::Response {
     match request
         .envelope
         .unwrap()
@@ -842,20 +1082,44 @@ fn handle_mta_hook(request: Request, tests: Arc<Vec<HeaderTest>>) -> hooks::Resp
         .unwrap()
         .0
     {
-        "accept" => hooks::Response {
+        "quarantine" => hooks::Response {
+            action: hooks::Action::Quarantine,
+            response: None,
+            modifications: vec![],
+            spam_score: None,
+        },
+        "spam" => hooks::Response {
+            action: hooks::Action::Accept,
+            response: None,
+            modifications: vec![],
+            spam_score: Some(7.5),
+        },
+        "cached" => hooks::Response {
+            action: hooks::Action::Accept,
+            response: None,
+            modifications: vec![hooks::Modification::AddHeader {
+                name: "X-Request".to_string(),
+                value: (requests.fetch_add(1, Ordering::Relaxed) + 1).to_string(),
+            }],
+            spam_score: None,
+        },
+        "accept" | "slow" => hooks::Response {
             action: hooks::Action::Accept,
             response: None,
             modifications: vec![],
+            spam_score: None,
         },
         "reject" => hooks::Response {
             action: hooks::Action::Reject,
             response: None,
             modifications: vec![],
+            spam_score: None,
         },
         "discard" => hooks::Response {
             action: hooks::Action::Discard,
             response: None,
             modifications: vec![],
+            spam_score: None,
         },
         "temp_fail" => hooks::Response {
             action: hooks::Action::Reject,
@@ -867,6 +1131,7 @@ fn handle_mta_hook(request: Request, tests: Arc<Vec<HeaderTest>>) -> hooks::Resp
             }
             .into(),
             modifications: vec![],
+            spam_score: None,
         },
         "shutdown" => hooks::Response {
             action: hooks::Action::Reject,
@@ -878,6 +1143,7 @@ fn handle_mta_hook(request: Request, tests: Arc<Vec<HeaderTest>>) -> hooks::Resp
             }
             .into(),
             modifications: vec![],
+            spam_score: None,
         },
         "conn_fail" => hooks::Response {
             action: hooks::Action::Accept,
@@ -887,6 +1153,7 @@ fn handle_mta_hook(request: Request, tests: Arc<Vec<HeaderTest>>) -> hooks::Resp
             }
             .into(),
             modifications: vec![],
+            spam_score: None,
         },
         "reply_code" => hooks::Response {
             action: hooks::Action::Reject,
@@ -898,6 +1165,7 @@ fn handle_mta_hook(request: Request, tests: Arc<Vec<HeaderTest>>) -> hooks::Resp
             }
             .into(),
             modifications: vec![],
+            spam_score: None,
         },
         test_num => hooks::Response {
             action: hooks::Action::Accept,
@@ -960,6 +1228,7 @@ fn handle_mta_hook(request: Request, tests: Arc<Vec<HeaderTest>>) -> hooks::Resp
                     },
                 })
                 .collect(),
+            spam_score: None,
         },
     }
 }
@@ -11,7 +11,7 @@ use common::{
     Server,
 };
 use store::{
-    write::{key::DeserializeBigEndian, Bincode, QueueClass, ReportEvent, ValueClass},
+    write::{key::DeserializeBigEndian, QueueClass, ReportEvent, ValueClass},
     Deserialize, IterateParams, ValueKey, U64_LEN,
 };
 use tokio::sync::mpsc::error::TryRecvError;
@@ -21,16 +21,24 @@ use smtp::queue::{DeliveryAttempt, Message, QueueId};
 use super::{QueueReceiver, ReportReceiver};
 
 pub mod antispam;
+pub mod attachments;
 pub mod auth;
 pub mod basic;
+pub mod bayes;
+pub mod clamav;
 pub mod data;
 pub mod dmarc;
 pub mod ehlo;
+pub mod forwarder;
+pub mod greylist;
 pub mod limits;
+pub mod list;
 pub mod mail;
 pub mod milter;
+pub mod proxy;
 pub mod rcpt;
 pub mod rewrite;
+pub mod rspamd;
 pub mod scripts;
 pub mod sign;
 pub mod throttle;
@@ -186,9 +194,9 @@ impl QueueReceiver {
             .iterate(
                 IterateParams::new(from_key, to_key).descending(),
                 |key, value| {
-                    let value = Bincode::<Message>::deserialize(value)?;
-                    assert_eq!(key.deserialize_be_u64(0)?, value.inner.queue_id);
-                    messages.push(value.inner);
+                    let value = Message::deserialize(value)?;
+                    assert_eq!(key.deserialize_be_u64(0)?, value.queue_id);
+                    messages.push(value);
                     Ok(true)
                 },
             )
@@ -0,0 +1,141 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::net::{IpAddr, SocketAddr};
+
+use common::listener::{
+    proxy::{accept_proxied, proxy_policy, ProxyAccept, ProxyPolicy},
+    SessionStream,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use utils::config::{ipmask::IpAddrMask, utils::ParseValue};
+
+const V2_SIGNATURE: &[u8] = b"\r\n\r\n\0\r\nQUIT\n";
+
+#[tokio::test]
+async fn proxy_protocol() {
+    // PROXY v1
+    let (stream, _) = connect(b"PROXY TCP4 192.168.0.1 192.168.0.11 56324 443\r\nEHLO\r\n").await;
+    assert_proxied(stream, "192.168.0.1:56324", false).await;
+
+    // PROXY v2 over IPv4, including an SSL TLV
+    let mut header = v2_header(0x21, 0x11);
+    let mut addresses = vec![192, 168, 0, 1, 192, 168, 0, 11];
+    addresses.extend_from_slice(&56324u16.to_be_bytes());
+    addresses.extend_from_slice(&443u16.to_be_bytes());
+    let mut ssl = vec![0x01, 0, 0, 0, 0, 0x21];
+    ssl.extend_from_slice(&7u16.to_be_bytes());
+    ssl.extend_from_slice(b"TLSv1.3");
+    addresses.push(0x20);
+    addresses.extend_from_slice(&(ssl.len() as u16).to_be_bytes());
+    addresses.extend_from_slice(&ssl);
+    header.extend_from_slice(&(addresses.len() as u16).to_be_bytes());
+    header.extend_from_slice(&addresses);
+    header.extend_from_slice(b"EHLO\r\n");
+    let (stream, _) = connect(&header).await;
+    assert_proxied(stream, "192.168.0.1:56324", true).await;
+
+    // PROXY v2 over IPv6
+    let mut header = v2_header(0x21, 0x21);
+    let mut addresses = Vec::new();
+    addresses.extend_from_slice(
+        &"2001:db8::1"
+            .parse::<std::net::Ipv6Addr>()
+            .unwrap()
+            .octets(),
+    );
+    addresses.extend_from_slice(
+        &"2001:db8::2"
+            .parse::<std::net::Ipv6Addr>()
+            .unwrap()
+            .octets(),
+    );
+    addresses.extend_from_slice(&56324u16.to_be_bytes());
+    addresses.extend_from_slice(&443u16.to_be_bytes());
+    header.extend_from_slice(&(addresses.len() as u16).to_be_bytes());
+    header.extend_from_slice(&addresses);
+    header.extend_from_slice(b"EHLO\r\n");
+    let (stream, _) = connect(&header).await;
+    assert_proxied(stream, "[2001:db8::1]:56324", false).await;
+
+    // PROXY v2 LOCAL command with UNSPEC family (health checks)
+    let mut header = v2_header(0x20, 0x00);
+    header.extend_from_slice(&0u16.to_be_bytes());
+    let (stream, _) = connect(&header).await;
+    assert!(matches!(accept_proxied(stream).await, ProxyAccept::Local));
+
+    // Truncated PROXY v2 header
+    let mut header = v2_header(0x21, 0x11);
+    header.extend_from_slice(&12u16.to_be_bytes());
+    header.extend_from_slice(&[192, 168, 0, 1]);
+    let (stream, _) = connect(&header).await;
+    assert!(matches!(
+        accept_proxied(stream).await,
+        ProxyAccept::Error(_)
+    ));
+
+    // Connections from untrusted sources
+    let networks = ["10.0.0.0/8", "::1"]
+        .into_iter()
+        .map(|network| IpAddrMask::parse_value(network).unwrap())
+        .collect::<Vec<_>>();
+    let (_, remote_addr) = connect(b"PROXY TCP4 192.168.0.1 192.168.0.11 56324 443\r\n").await;
+    assert_eq!(
+        proxy_policy(&networks, true, &remote_addr.ip()),
+        ProxyPolicy::Reject
+    );
+    assert_eq!(
+        proxy_policy(&networks, false, &remote_addr.ip()),
+        ProxyPolicy::Direct
+    );
+    assert_eq!(
+        proxy_policy(&networks, true, &"10.1.2.3".parse::<IpAddr>().unwrap()),
+        ProxyPolicy::Proxied
+    );
+}
+
+async fn connect(data: &[u8]) -> (TcpStream, SocketAddr) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let mut client = TcpStream::connect(listener.local_addr().unwrap())
+        .await
+        .unwrap();
+    client.write_all(data).await.unwrap();
+    client.shutdown().await.unwrap();
+    let (stream, remote_addr) = listener.accept().await.unwrap();
+    tokio::spawn(async move {
+        // Keep the client open until the server side is done reading
+        let mut buf = Vec::new();
+        let _ = client.read_to_end(&mut buf).await;
+    });
+    (stream, remote_addr)
+}
+
+async fn assert_proxied(stream: TcpStream, expected_addr: &str, expected_tls: bool) {
+    match accept_proxied(stream).await {
+        ProxyAccept::Proxied {
+            mut stream,
+            remote_addr,
+        } => {
+            assert_eq!(remote_addr, expected_addr.parse::<SocketAddr>().unwrap());
+            assert_eq!(stream.is_tls(), expected_tls);
+            let mut buf = Vec::new();
+            stream.read_to_end(&mut buf).await.unwrap();
+            assert_eq!(buf, b"EHLO\r\n");
+        }
+        ProxyAccept::Local => panic!("Unexpected LOCAL command for {expected_addr}"),
+        ProxyAccept::Error(err) => panic!("Failed to parse header for {expected_addr}: {err}"),
+    }
+}
+
+fn v2_header(version_command: u8, family: u8) -> Vec<u8> {
+    let mut header = V2_SIGNATURE.to_vec();
+    header.push(version_command);
+    header.push(family);
+    header
+}
@@ -125,7 +125,7 @@ async fn rcpt() {
     session.state = State::default();
     session.rcpt_to("Jane@FooBar.org", "250").await;
     session.rcpt_to("Bill@FooBar.org", "250").await;
-    session.rcpt_to("Mike@FooBar.org", "451 4.4.5").await;
+    session.rcpt_to("Mike@FooBar.org", "452 4.4.5").await;
 
     // Restore rate limit
     tokio::time::sleep(Duration::from_millis(1100)).await;
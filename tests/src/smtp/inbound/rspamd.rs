@@ -0,0 +1,396 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use common::{auth::AccessToken, Core};
+use hyper::{body, server::conn::http1, service::service_fn, StatusCode};
+use hyper_util::rt::TokioIo;
+use jmap::api::{http::fetch_body, HttpResponse};
+use serde_json::json;
+use smtp::core::Session;
+use store::Stores;
+use tokio::{net::TcpListener, sync::watch};
+use utils::config::Config;
+
+use crate::smtp::{
+    inbound::TestMessage,
+    session::{TestSession, VerifyResponse},
+    TempDir, TestSMTP,
+};
+
+const CONFIG: &str = r#"
+[storage]
+data = "sqlite"
+lookup = "sqlite"
+blob = "sqlite"
+fts = "sqlite"
+
+[store."sqlite"]
+type = "sqlite"
+path = "{TMP}/queue.db"
+
+[session.auth]
+must-match-sender = false
+
+[session.rcpt]
+relay = true
+
+[spam-filter.rspamd]
+url = "http://127.0.0.1:9334"
+password = "secret"
+timeout = "1s"
+"#;
+
+#[derive(Debug, Clone, Default)]
+struct MockRequest {
+    path: String,
+    headers: Vec<(String, String)>,
+}
+
+impl MockRequest {
+    fn header(&self, name: &str) -> Vec<&str> {
+        self.headers
+            .iter()
+            .filter(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+            .collect()
+    }
+}
+
+#[tokio::test]
+async fn rspamd_session() {
+    // Enable logging
+    crate::enable_logging();
+
+    // Configure tests
+    let tmp_dir = TempDir::new("smtp_rspamd_test", true);
+    let mut config = Config::new(tmp_dir.update_config(CONFIG)).unwrap();
+    let stores = Stores::parse_all(&mut config).await;
+    let core = Core::parse(&mut config, stores, Default::default()).await;
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let _rx = spawn_mock_rspamd_server(requests.clone());
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Build session
+    let test = TestSMTP::from_core(core);
+    let mut qr = test.queue_receiver;
+    let server = test.server;
+    let mut session = Session::test(server.clone());
+    session.data.remote_ip_str = "10.0.0.1".to_string();
+    session.data.remote_ip = session.data.remote_ip_str.parse().unwrap();
+    session.data.authenticated_as = Some(Arc::new(AccessToken {
+        name: "john".to_string(),
+        ..Default::default()
+    }));
+    session.eval_session_params().await;
+    session.ehlo("mx.doe.org").await;
+
+    // MTA metadata is sent as request headers
+    session
+        .send_message(
+            "john@doe.org",
+            &["bill@foobar.org", "jane@foobar.org"],
+            "test:no_dkim",
+            "250",
+        )
+        .await;
+    let request = requests.lock().unwrap().pop().unwrap();
+    assert_eq!(request.path, "/checkv2");
+    assert_eq!(request.header("IP"), ["10.0.0.1"]);
+    assert_eq!(request.header("Helo"), ["mx.doe.org"]);
+    assert_eq!(request.header("From"), ["john@doe.org"]);
+    assert_eq!(
+        request.header("Rcpt"),
+        ["bill@foobar.org", "jane@foobar.org"]
+    );
+    assert_eq!(request.header("User"), ["john"]);
+    assert_eq!(request.header("Queue-Id").len(), 1);
+    qr.expect_message()
+        .await
+        .read_lines(&qr)
+        .await
+        .assert_contains("X-Spam-Status: No, score=0.50 required=15.00 tests=ARC_NA")
+        .assert_contains("Subject: Is dinner ready?");
+
+    // Test reject
+    session
+        .send_message(
+            "reject@doe.org",
+            &["bill@foobar.org"],
+            "test:no_dkim",
+            "550 5.7.1 Spam message rejected",
+        )
+        .await;
+    qr.assert_no_events();
+
+    // Test soft reject
+    session
+        .send_message(
+            "soft_reject@doe.org",
+            &["bill@foobar.org"],
+            "test:no_dkim",
+            "451 4.7.1",
+        )
+        .await;
+    qr.assert_no_events();
+
+    // Test greylist
+    session
+        .send_message(
+            "greylist@doe.org",
+            &["bill@foobar.org"],
+            "test:no_dkim",
+            "451 4.7.1",
+        )
+        .await;
+    qr.assert_no_events();
+
+    // Test add header
+    session
+        .send_message(
+            "add_header@doe.org",
+            &["bill@foobar.org"],
+            "test:no_dkim",
+            "250",
+        )
+        .await;
+    qr.expect_message()
+        .await
+        .read_lines(&qr)
+        .await
+        .assert_contains(
+            "X-Spam-Status: Yes, score=8.50 required=15.00 tests=BAYES_SPAM,R_SPF_FAIL",
+        )
+        .assert_contains("X-Rspamd-Server: mock")
+        .assert_contains("Subject: Is dinner ready?");
+
+    // Test subject rewrite
+    session
+        .send_message(
+            "rewrite_subject@doe.org",
+            &["bill@foobar.org"],
+            "test:no_dkim",
+            "250",
+        )
+        .await;
+    qr.expect_message()
+        .await
+        .read_lines(&qr)
+        .await
+        .assert_contains("X-Spam-Status: Yes")
+        .assert_contains("Subject: *** SPAM *** Is dinner ready?")
+        .assert_not_contains("Subject: Is dinner ready?");
+
+    // Test DKIM signing by Rspamd
+    session
+        .send_message("dkim@doe.org", &["bill@foobar.org"], "test:no_dkim", "250")
+        .await;
+    qr.expect_message()
+        .await
+        .read_lines(&qr)
+        .await
+        .assert_contains("DKIM-Signature: v=1; a=rsa-sha256; d=doe.org; s=mail; b=abc")
+        .assert_contains("X-Spam-Status: No");
+
+    // Errors are ignored by default
+    session
+        .send_message("error@doe.org", &["bill@foobar.org"], "test:no_dkim", "250")
+        .await;
+    qr.expect_message()
+        .await
+        .read_lines(&qr)
+        .await
+        .assert_not_contains("X-Spam-Status");
+
+    // Training is forwarded to the controller using the configured password
+    requests.lock().unwrap().clear();
+    assert!(server
+        .rspamd_learn(b"Subject: test\r\n\r\ntest", true, 0)
+        .await
+        .unwrap());
+    assert!(server
+        .rspamd_learn(b"Subject: test\r\n\r\ntest", false, 0)
+        .await
+        .unwrap());
+    let learns = std::mem::take(&mut *requests.lock().unwrap());
+    assert_eq!(
+        learns
+            .iter()
+            .map(|request| (request.path.as_str(), request.header("Password")))
+            .collect::<Vec<_>>(),
+        [
+            ("/learnspam", vec!["secret"]),
+            ("/learnham", vec!["secret"])
+        ]
+    );
+
+    // Wrong controller passwords and errors with tempfail enabled
+    let mut config = Config::new(tmp_dir.update_config(CONFIG.replace(
+        "password = \"secret\"",
+        "password = \"wrong\"\ntempfail-on-error = true",
+    )))
+    .unwrap();
+    let stores = Stores::parse_all(&mut config).await;
+    let core = Core::parse(&mut config, stores, Default::default()).await;
+    let test = TestSMTP::from_core(core);
+    let mut qr = test.queue_receiver;
+    assert!(test
+        .server
+        .rspamd_learn(b"Subject: test\r\n\r\ntest", true, 0)
+        .await
+        .is_err());
+    let mut session = Session::test(test.server);
+    session.data.remote_ip_str = "10.0.0.1".to_string();
+    session.data.remote_ip = session.data.remote_ip_str.parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx.doe.org").await;
+    session
+        .send_message(
+            "error@doe.org",
+            &["bill@foobar.org"],
+            "test:no_dkim",
+            "451 4.3.5",
+        )
+        .await;
+    qr.assert_no_events();
+}
+
+fn mock_rspamd_response(request: &MockRequest) -> (StatusCode, serde_json::Value) {
+    match request.path.as_str() {
+        "/checkv2" => {}
+        "/learnspam" | "/learnham" => {
+            return if request.header("Password") == ["secret"] {
+                (StatusCode::OK, json!({"success": true}))
+            } else {
+                (StatusCode::FORBIDDEN, json!({"error": "Unauthorized"}))
+            };
+        }
+        _ => return (StatusCode::NOT_FOUND, json!({"error": "Not found"})),
+    }
+
+    let response = match request.header("From").first().copied().unwrap_or_default() {
+        "reject@doe.org" => json!({
+            "action": "reject",
+            "score": 20.0,
+            "required_score": 15.0,
+            "messages": {"smtp_message": "Spam message rejected"}
+        }),
+        "soft_reject@doe.org" => json!({
+            "action": "soft reject",
+            "score": 12.0,
+            "required_score": 15.0
+        }),
+        "greylist@doe.org" => json!({
+            "action": "greylist",
+            "score": 5.0,
+            "required_score": 15.0
+        }),
+        "add_header@doe.org" => json!({
+            "action": "add header",
+            "score": 8.5,
+            "required_score": 15.0,
+            "symbols": {
+                "R_SPF_FAIL": {"name": "R_SPF_FAIL", "score": 3.5, "options": ["-all"]},
+                "BAYES_SPAM": {"name": "BAYES_SPAM", "score": 5.0}
+            },
+            "milter": {
+                "add_headers": {"X-Rspamd-Server": {"value": "mock", "order": 0}}
+            }
+        }),
+        "rewrite_subject@doe.org" => json!({
+            "action": "rewrite subject",
+            "score": 10.0,
+            "required_score": 15.0,
+            "subject": "*** SPAM *** Is dinner ready?"
+        }),
+        "dkim@doe.org" => json!({
+            "action": "no action",
+            "score": 0.0,
+            "required_score": 15.0,
+            "dkim-signature": "v=1; a=rsa-sha256; d=doe.org; s=mail; b=abc"
+        }),
+        "error@doe.org" => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                json!({"error": "Internal error"}),
+            )
+        }
+        _ => json!({
+            "action": "no action",
+            "score": 0.5,
+            "required_score": 15.0,
+            "symbols": {"ARC_NA": {"name": "ARC_NA", "score": 0.5}}
+        }),
+    };
+
+    (StatusCode::OK, response)
+}
+
+fn spawn_mock_rspamd_server(requests: Arc<Mutex<Vec<MockRequest>>>) -> watch::Sender<bool> {
+    let (tx, rx) = watch::channel(true);
+
+    tokio::spawn(async move {
+        let listener = TcpListener::bind("127.0.0.1:9334")
+            .await
+            .unwrap_or_else(|e| {
+                panic!("Failed to bind mock Rspamd server to 127.0.0.1:9334: {e}");
+            });
+        let mut rx_ = rx.clone();
+        loop {
+            tokio::select! {
+                stream = listener.accept() => {
+                    match stream {
+                        Ok((stream, _)) => {
+                            let requests = requests.clone();
+                            let _ = http1::Builder::new()
+                            .keep_alive(false)
+                            .serve_connection(
+                                TokioIo::new(stream),
+                                service_fn(move |mut req: hyper::Request<body::Incoming>| {
+                                    let requests = requests.clone();
+
+                                    async move {
+                                        let request = MockRequest {
+                                            path: req.uri().path().to_string(),
+                                            headers: req
+                                                .headers()
+                                                .iter()
+                                                .map(|(k, v)| {
+                                                    (k.as_str().to_string(), v.to_str().unwrap().to_string())
+                                                })
+                                                .collect(),
+                                        };
+                                        assert!(!fetch_body(&mut req, 1024 * 1024, 0).await.unwrap().is_empty());
+                                        let (status, response) = mock_rspamd_response(&request);
+                                        requests.lock().unwrap().push(request);
+
+                                        Ok::<_, hyper::Error>(
+                                            HttpResponse::new_text(status, "application/json", response.to_string())
+                                            .build(),
+                                        )
+                                    }
+                                }),
+                            )
+                            .await;
+                        }
+                        Err(err) => {
+                            panic!("Something went wrong: {err}" );
+                        }
+                    }
+                },
+                _ = rx_.changed() => {
+                    break;
+                }
+            };
+        }
+    });
+
+    tx
+}
@@ -5,7 +5,7 @@
  */
 
 use core::panic;
-use std::{fmt::Write, fs, path::PathBuf};
+use std::{fmt::Write, fs, path::PathBuf, sync::Arc};
 
 use crate::{
     enable_logging,
@@ -16,7 +16,7 @@ use crate::{
     },
     AssertConfig,
 };
-use common::Core;
+use common::{auth::AccessToken, Core};
 
 use smtp::{
     core::Session,
@@ -102,6 +102,172 @@ member-of = ["sales"]
 
 "#;
 
+const OUTBOUND_CONFIG: &str = r#"
+[storage]
+data = "sql"
+lookup = "sql"
+blob = "sql"
+fts = "sql"
+
+[store."sql"]
+type = "sqlite"
+path = "{TMP}/smtp_sieve_outbound.db"
+
+[session.auth]
+must-match-sender = false
+
+[session.rcpt]
+relay = true
+
+[session.data.outbound]
+script = [ { if = "sender_domain = 'foobar.org'", then = "'outbound_policy'" },
+           { else = false } ]
+
+[sieve.trusted]
+from-name = "'Sieve Daemon'"
+from-addr = "'sieve@foobar.org'"
+return-path = "''"
+
+[sieve.trusted.scripts.outbound_policy]
+contents = '''
+require ["envelope", "reject", "editheader", "copy"];
+
+if envelope :is "to" "blocked@example.net" {
+    reject "550 5.7.1 Recipients at example.net cannot be reached from this account.";
+    stop;
+}
+
+if envelope :domain :is "to" "regulated.org" {
+    redirect :copy "archive@compliance.foobar.org";
+}
+
+addheader :last "X-Disclaimer" "This message is confidential.";
+'''
+"#;
+
+#[tokio::test]
+async fn sieve_outbound_scripts() {
+    // Enable logging
+    enable_logging();
+
+    // Prepare config
+    let tmp_dir = TempDir::new("smtp_sieve_outbound_test", true);
+    let mut config = Config::new(tmp_dir.update_config(OUTBOUND_CONFIG)).unwrap();
+    let stores = Stores::parse_all(&mut config).await;
+    let core = Core::parse(&mut config, stores, Default::default()).await;
+    config.assert_no_errors();
+
+    // Build session
+    let test = TestSMTP::from_core(core);
+    let mut qr = test.queue_receiver;
+    let mut session = Session::test(test.server.clone());
+    session.data.remote_ip_str = "10.0.0.1".to_string();
+    session.data.remote_ip = session.data.remote_ip_str.parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx.foobar.org").await;
+
+    // Outbound scripts do not run for unauthenticated sessions
+    session
+        .send_message(
+            "bill@foobar.org",
+            &["blocked@example.net"],
+            "test:no_dkim",
+            "250",
+        )
+        .await;
+    qr.expect_message()
+        .await
+        .read_lines(&qr)
+        .await
+        .assert_not_contains("X-Disclaimer");
+    qr.assert_no_events();
+    qr.clear_queue(&test.server).await;
+
+    // Domain script appends a disclaimer visible to the recipient
+    session.data.authenticated_as = Some(Arc::new(AccessToken {
+        name: "bill".to_string(),
+        ..Default::default()
+    }));
+    session
+        .send_message(
+            "bill@foobar.org",
+            &["jane@example.net"],
+            "test:no_dkim",
+            "250",
+        )
+        .await;
+    qr.expect_message()
+        .await
+        .read_lines(&qr)
+        .await
+        .assert_contains("X-Disclaimer: This message is confidential.")
+        .assert_contains("Subject: Is dinner ready?");
+    qr.assert_no_events();
+    qr.clear_queue(&test.server).await;
+
+    // Scripts are selected by the sender's domain
+    session
+        .send_message(
+            "bill@example.com",
+            &["jane@example.net"],
+            "test:no_dkim",
+            "250",
+        )
+        .await;
+    qr.expect_message()
+        .await
+        .read_lines(&qr)
+        .await
+        .assert_not_contains("X-Disclaimer");
+    qr.assert_no_events();
+    qr.clear_queue(&test.server).await;
+
+    // Rejections are returned at DATA
+    session
+        .send_message(
+            "bill@foobar.org",
+            &["blocked@example.net"],
+            "test:no_dkim",
+            "550 5.7.1 Recipients at example.net cannot be reached",
+        )
+        .await;
+    qr.assert_no_events();
+
+    // Compliance copy is queued next to the original message
+    session
+        .send_message(
+            "bill@foobar.org",
+            &["john@regulated.org"],
+            "test:no_dkim",
+            "250",
+        )
+        .await;
+    qr.read_event().await.assert_reload();
+    qr.read_event().await.assert_reload();
+    let messages = qr.read_queued_messages().await;
+    assert_eq!(messages.len(), 2);
+    let archive = messages
+        .iter()
+        .find(|m| m.recipients[0].address == "archive@compliance.foobar.org")
+        .expect("compliance copy not queued");
+    assert_eq!(archive.return_path, "");
+    assert_eq!(archive.recipients.len(), 1);
+    archive
+        .read_lines(&qr)
+        .await
+        .assert_contains("Subject: Is dinner ready?");
+    let original = messages
+        .iter()
+        .find(|m| m.recipients[0].address == "john@regulated.org")
+        .expect("original message not queued");
+    assert_eq!(original.return_path, "bill@foobar.org");
+    original
+        .read_lines(&qr)
+        .await
+        .assert_contains("X-Disclaimer: This message is confidential.");
+    qr.assert_no_events();
+}
+
 #[tokio::test]
 async fn sieve_scripts() {
     // Enable logging
@@ -0,0 +1,241 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use common::{
+    config::server::ServerProtocol,
+    core::BuildServer,
+    telemetry::audit::{AuditEvent, AuditFilter, AuditRecord, AuditResult},
+};
+use reqwest::Method;
+use store::write::{now, BatchBuilder, TelemetryClass, ValueClass};
+
+use crate::{
+    jmap::ManagementApi,
+    smtp::{management::queue::List, TestSMTP},
+};
+
+const CONFIG: &str = r#"
+[storage]
+directory = "local"
+
+[directory."local"]
+type = "memory"
+
+[[directory."local".principals]]
+name = "admin"
+type = "admin"
+description = "Superuser"
+secret = "secret"
+class = "admin"
+
+[[directory."local".principals]]
+name = "jane"
+description = "Jane Doe"
+secret = "p4ssw0rd"
+email = "jane@foobar.org"
+
+[audit]
+enable = true
+retention = "30d"
+"#;
+
+#[tokio::test]
+#[serial_test::serial]
+async fn manage_audit_log() {
+    // Enable logging
+    crate::enable_logging();
+
+    // Start local management interface
+    let local = TestSMTP::new("smtp_manage_audit", CONFIG).await;
+    let _rx_manage = local.start(&[ServerProtocol::Http]).await;
+    let server = local.server.inner.build_server();
+    let admin = ManagementApi::default();
+    let jane = ManagementApi::new(9980, "jane", "p4ssw0rd");
+    let jane_bad = ManagementApi::new(9980, "jane", "wrong");
+    let start = now();
+
+    // Logins, including failed ones
+    admin
+        .request::<List<AuditRecord>>(Method::GET, "/api/audit")
+        .await
+        .unwrap()
+        .unwrap_data();
+    jane_bad
+        .request::<List<AuditRecord>>(Method::GET, "/api/audit")
+        .await
+        .unwrap()
+        .expect_request_error("Unauthorized");
+
+    // Users without permission cannot read the audit log
+    jane.request::<List<AuditRecord>>(Method::GET, "/api/audit")
+        .await
+        .unwrap()
+        .expect_request_error("Forbidden");
+
+    // Secret changes are recorded even when the directory rejects them
+    jane.post::<()>(
+        "/api/account/auth",
+        &serde_json::json!([{"type": "setPassword", "password": "n3w"}]),
+    )
+    .await
+    .unwrap()
+    .unwrap_error();
+    admin
+        .patch::<()>(
+            "/api/principal/jane",
+            &serde_json::json!([{"action": "set", "field": "secrets", "value": "n3w"}]),
+        )
+        .await
+        .unwrap()
+        .unwrap_error();
+
+    // Admin mutations and message deletions
+    admin
+        .delete::<()>("/api/settings/audit.test")
+        .await
+        .unwrap()
+        .unwrap_data();
+    let _ = admin
+        .delete::<serde_json::Value>("/api/queue/messages/1234")
+        .await
+        .unwrap();
+    let _ = admin
+        .delete::<serde_json::Value>("/api/quarantine/5678?global")
+        .await
+        .unwrap();
+
+    // Every operation is in the log, in the order it was performed
+    let records = admin
+        .request::<List<AuditRecord>>(Method::GET, "/api/audit")
+        .await
+        .unwrap()
+        .unwrap_data()
+        .items;
+    let events = records
+        .iter()
+        .map(|record| (record.actor.as_str(), record.event.clone()))
+        .collect::<Vec<_>>();
+    let login = AuditEvent::Login {
+        mechanism: "plain".to_string(),
+    };
+    let secret_change = AuditEvent::SecretChange {
+        account: "jane".to_string(),
+    };
+    assert_eq!(
+        events,
+        vec![
+            ("admin", login.clone()),
+            ("jane", login.clone()),
+            ("jane", login),
+            ("jane", secret_change.clone()),
+            ("admin", secret_change),
+            (
+                "admin",
+                AuditEvent::AdminRequest {
+                    method: "DELETE".to_string(),
+                    path: "settings/audit.test".to_string()
+                }
+            ),
+            (
+                "admin",
+                AuditEvent::MessageDeletion {
+                    source: "queue".to_string(),
+                    id: "1234".to_string()
+                }
+            ),
+            (
+                "admin",
+                AuditEvent::MessageDeletion {
+                    source: "quarantine".to_string(),
+                    id: "5678".to_string()
+                }
+            ),
+        ]
+    );
+    assert_eq!(
+        records[..6]
+            .iter()
+            .map(|record| record.result == AuditResult::Success)
+            .collect::<Vec<_>>(),
+        [true, false, true, false, false, true]
+    );
+    for record in &records {
+        assert!(record.timestamp >= start, "{record:?}");
+        assert_eq!(
+            record.remote_ip,
+            Some("127.0.0.1".parse().unwrap()),
+            "{record:?}"
+        );
+    }
+    assert!(records
+        .windows(2)
+        .all(|w| (w[0].timestamp, w[0].id) < (w[1].timestamp, w[1].id)));
+
+    // Actor and time range filters
+    let records = admin
+        .request::<List<AuditRecord>>(Method::GET, "/api/audit?actor=jane")
+        .await
+        .unwrap()
+        .unwrap_data()
+        .items;
+    assert_eq!(records.len(), 3);
+    assert!(records.iter().all(|record| record.actor == "jane"));
+    assert!(admin
+        .request::<List<AuditRecord>>(Method::GET, &format!("/api/audit?from={}", now() + 60))
+        .await
+        .unwrap()
+        .unwrap_data()
+        .items
+        .is_empty());
+    assert_eq!(
+        admin
+            .request::<List<AuditRecord>>(Method::GET, "/api/audit?limit=2")
+            .await
+            .unwrap()
+            .unwrap_data()
+            .items
+            .len(),
+        2
+    );
+
+    // Pruning only removes records older than the retention period
+    let mut batch = BatchBuilder::new();
+    for (id, days) in [(1, 45), (2, 29)] {
+        let timestamp = now() - days * 86400;
+        let record = AuditRecord {
+            id,
+            timestamp,
+            ..AuditRecord::new(
+                "legacy",
+                AuditEvent::AdminRequest {
+                    method: "POST".to_string(),
+                    path: "reload".to_string(),
+                },
+            )
+        };
+        batch.set(
+            ValueClass::Telemetry(TelemetryClass::Audit { timestamp, id }),
+            serde_json::to_vec(&record).unwrap(),
+        );
+    }
+    server.store().write(batch.build()).await.unwrap();
+    let filter = AuditFilter {
+        to: u64::MAX,
+        ..Default::default()
+    };
+    let total = server.query_audit_log(&filter).await.unwrap().len();
+    server.purge_audit_log().await.unwrap();
+    let records = server.query_audit_log(&filter).await.unwrap();
+    assert_eq!(records.len(), total - 1);
+    assert_eq!(
+        records
+            .iter()
+            .filter(|record| record.actor == "legacy")
+            .map(|record| record.id)
+            .collect::<Vec<_>>(),
+        [2]
+    );
+}
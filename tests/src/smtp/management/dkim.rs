@@ -0,0 +1,390 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::{Duration, Instant};
+
+use common::{
+    config::server::ServerProtocol,
+    dkim::{DkimDomainCheck, DkimKeyInfo, DkimSignPolicy, DkimSignatureCheck, DnsRecordCheck},
+    Server,
+};
+use mail_auth::{
+    common::{parse::TxtRecordParser, verify::DomainKey},
+    dmarc::Dmarc,
+    spf::Spf,
+    AuthenticatedMessage, DkimResult,
+};
+use smtp::core::Session;
+use store::write::now;
+
+use crate::{
+    jmap::ManagementApi,
+    smtp::{
+        inbound::TestMessage,
+        management::queue::List,
+        session::{DummyIo, TestSession},
+        QueueReceiver, TestSMTP,
+    },
+};
+
+const CONFIG: &str = r#"
+[storage]
+directory = "local"
+
+[directory."local"]
+type = "memory"
+
+[[directory."local".principals]]
+name = "admin"
+type = "admin"
+description = "Superuser"
+secret = "secret"
+class = "admin"
+
+[[directory."local".principals]]
+name = "john"
+description = "John Doe"
+secret = "secret"
+email = ["jdoe@example.com"]
+
+[session.rcpt]
+directory = "'local'"
+
+[auth.spf.verify]
+ehlo = "disable"
+mail-from = "disable"
+
+[auth.dkim]
+verify = "disable"
+sign = "false"
+
+[auth.dkim.managed]
+sign = true
+
+[auth.arc]
+verify = "disable"
+seal = "false"
+
+[auth.dmarc]
+verify = "disable"
+
+[auth.iprev]
+verify = "disable"
+"#;
+
+#[tokio::test]
+#[serial_test::serial]
+async fn manage_dkim() {
+    // Enable logging
+    crate::enable_logging();
+
+    // Start local management interface
+    let local = TestSMTP::new("smtp_manage_dkim", CONFIG).await;
+    let _rx_manage = local.start(&[ServerProtocol::Http]).await;
+    let admin = ManagementApi::default();
+    let server = local.server.clone();
+    let mut qr = local.queue_receiver;
+
+    // Generate a key signing for example.com and its subdomains
+    let policy = DkimSignPolicy {
+        subdomains: true,
+        ..Default::default()
+    };
+    let e1 = admin
+        .post::<DkimKeyInfo>(
+            "/api/dkim/keys",
+            &serde_json::json!({
+                "domain": "example.com",
+                "algorithm": "ed25519",
+                "selector": "e1",
+                "policy": policy,
+            }),
+        )
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(e1.dns_name, "e1._domainkey.example.com");
+    assert!(
+        e1.dns_record.starts_with("v=DKIM1; k=ed25519; p="),
+        "{}",
+        e1.dns_record
+    );
+    assert!(e1.active);
+    publish_key(&server, &e1);
+    admin
+        .post::<DkimKeyInfo>(
+            "/api/dkim/keys",
+            &serde_json::json!({
+                "domain": "example.com",
+                "algorithm": "ed25519",
+                "selector": "e1",
+            }),
+        )
+        .await
+        .unwrap()
+        .expect_error("fieldAlreadyExists");
+
+    // Keys of parent domains are only used when they allow subdomains
+    let r1 = admin
+        .post::<DkimKeyInfo>(
+            "/api/dkim/keys",
+            &serde_json::json!({
+                "domain": "foobar.org",
+                "algorithm": "rsa",
+                "selector": "r1",
+            }),
+        )
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert!(r1.dns_record.starts_with("v=DKIM1; k=rsa; p="));
+    assert_eq!(server.dkim_signers("foobar.org").await.unwrap().len(), 1);
+    assert!(server
+        .dkim_signers("mail.foobar.org")
+        .await
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        server
+            .dkim_signers("football.example.com")
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+
+    // Messages are signed with the key published for the author's domain
+    let mut session = Session::test(server.clone());
+    session.data.remote_ip_str = "10.0.0.1".to_string();
+    session.eval_session_params().await;
+    session.ehlo("mx.football.example.com").await;
+    let message = send_message(&mut session, &mut qr).await;
+    assert_signatures(&server, &message, &["e1"]).await;
+
+    // Both keys sign while the rotation overlaps
+    let e2 = admin
+        .post::<DkimKeyInfo>(
+            "/api/dkim/keys/example.com/rotate",
+            &serde_json::json!({
+                "algorithm": "ed25519",
+                "selector": "e2",
+                "policy": policy,
+                "overlap": 3600,
+            }),
+        )
+        .await
+        .unwrap()
+        .unwrap_data();
+    publish_key(&server, &e2);
+    let keys = admin
+        .get::<List<DkimKeyInfo>>("/api/dkim/keys/example.com")
+        .await
+        .unwrap()
+        .unwrap_data()
+        .items;
+    assert_eq!(keys.len(), 2, "{keys:?}");
+    let retire = keys[0].retire.unwrap();
+    assert!((now() + 3599..=now() + 3600).contains(&retire), "{retire}");
+    assert!(keys.iter().all(|key| key.active));
+    let message = send_message(&mut session, &mut qr).await;
+    assert_signatures(&server, &message, &["e1", "e2"]).await;
+
+    // Only the new key signs once the old one is retired
+    admin
+        .post::<()>(
+            "/api/dkim/keys/example.com/e1/retire",
+            &serde_json::json!({}),
+        )
+        .await
+        .unwrap()
+        .unwrap_data();
+    let keys = admin
+        .get::<List<DkimKeyInfo>>("/api/dkim/keys/example.com")
+        .await
+        .unwrap()
+        .unwrap_data()
+        .items;
+    assert!(!keys[0].active && keys[1].active, "{keys:?}");
+    let message = send_message(&mut session, &mut qr).await;
+    assert_signatures(&server, &message, &["e2"]).await;
+
+    // Deleted keys no longer sign
+    for key in ["example.com/e1", "example.com/e2", "foobar.org/r1"] {
+        admin
+            .delete::<()>(&format!("/api/dkim/keys/{key}"))
+            .await
+            .unwrap()
+            .unwrap_data();
+    }
+    assert_eq!(
+        admin
+            .get::<List<DkimKeyInfo>>("/api/dkim/keys")
+            .await
+            .unwrap()
+            .unwrap_data()
+            .total,
+        0
+    );
+    let message = send_message(&mut session, &mut qr).await;
+    assert_signatures(&server, &message, &[]).await;
+}
+
+fn publish_key(server: &Server, key: &DkimKeyInfo) {
+    server.core.smtp.resolvers.dns.txt_add(
+        key.dns_name.as_str(),
+        DomainKey::parse(key.dns_record.as_bytes()).unwrap(),
+        Instant::now() + Duration::from_secs(3600),
+    );
+}
+
+async fn send_message(session: &mut Session<DummyIo>, qr: &mut QueueReceiver) -> String {
+    session
+        .send_message(
+            "joe@football.example.com",
+            &["jdoe@example.com"],
+            "test:no_dkim",
+            "250",
+        )
+        .await;
+    qr.expect_message().await.read_message(qr).await
+}
+
+async fn assert_signatures(server: &Server, message: &str, selectors: &[&str]) {
+    for selector in selectors {
+        assert!(
+            message.contains(&format!("s={selector}; d=example.com;")),
+            "{message}"
+        );
+    }
+    let message = AuthenticatedMessage::parse(message.as_bytes()).unwrap();
+    let output = server.core.smtp.resolvers.dns.verify_dkim(&message).await;
+    assert_eq!(output.len(), selectors.len(), "{output:?}");
+    assert!(
+        output
+            .iter()
+            .all(|output| matches!(output.result(), DkimResult::Pass)),
+        "{output:?}"
+    );
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn check_dkim() {
+    // Enable logging
+    crate::enable_logging();
+
+    // Start local management interface
+    let local = TestSMTP::new("smtp_check_dkim", CONFIG).await;
+    let _rx_manage = local.start(&[ServerProtocol::Http]).await;
+    let admin = ManagementApi::default();
+    let server = local.server.clone();
+
+    // Generate keys, publishing one correctly, one with the public key of
+    // another selector, one with a record that fails to parse and none for the last
+    let mut keys = Vec::new();
+    for selector in ["valid", "mismatch", "invalid", "missing"] {
+        keys.push(
+            admin
+                .post::<DkimKeyInfo>(
+                    "/api/dkim/keys",
+                    &serde_json::json!({
+                        "domain": "example.org",
+                        "algorithm": "ed25519",
+                        "selector": selector,
+                    }),
+                )
+                .await
+                .unwrap()
+                .unwrap_data(),
+        );
+    }
+    publish_key(&server, &keys[0]);
+    server.core.smtp.resolvers.dns.txt_add(
+        keys[1].dns_name.as_str(),
+        DomainKey::parse(keys[0].dns_record.as_bytes()).unwrap(),
+        Instant::now() + Duration::from_secs(3600),
+    );
+    server.core.smtp.resolvers.dns.txt_add(
+        keys[2].dns_name.as_str(),
+        DomainKey::parse(b"v=DKIM1; k=dsa; p=MDEyMzQ1Njc4OQ=="),
+        Instant::now() + Duration::from_secs(3600),
+    );
+    server.core.smtp.resolvers.dns.txt_add(
+        "example.org",
+        Spf::parse(b"v=spf1 ip4:10.0.0.1 -all").unwrap(),
+        Instant::now() + Duration::from_secs(3600),
+    );
+
+    let check = admin
+        .get::<DkimDomainCheck>("/api/dkim/check/example.org")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(check.domain, "example.org");
+    assert_eq!(check.keys.len(), 4, "{check:?}");
+    for key in &check.keys {
+        let (record, signature) = match key.selector.as_str() {
+            "valid" => (DnsRecordCheck::Valid, DkimSignatureCheck::Pass),
+            "mismatch" => (DnsRecordCheck::Valid, DkimSignatureCheck::Mismatch),
+            "missing" => (DnsRecordCheck::Missing, DkimSignatureCheck::Skipped),
+            "invalid" => {
+                assert!(
+                    matches!(key.record, DnsRecordCheck::Invalid { .. }),
+                    "{key:?}"
+                );
+                assert_eq!(key.signature, DkimSignatureCheck::Skipped);
+                continue;
+            }
+            selector => panic!("Unexpected selector {selector}"),
+        };
+        assert_eq!(key.record, record, "{key:?}");
+        assert_eq!(key.signature, signature, "{key:?}");
+        assert!(key.active);
+
+        // Ed25519 keys should be accompanied by an RSA key
+        assert_eq!(key.warnings.len(), 1, "{key:?}");
+    }
+    assert_eq!(check.spf, DnsRecordCheck::Valid);
+    assert_eq!(check.dmarc, DnsRecordCheck::Missing);
+
+    // Syntax errors in SPF and DMARC records are reported
+    server.core.smtp.resolvers.dns.txt_add(
+        "_dmarc.example.org",
+        Dmarc::parse(b"v=DMARC1; p=reject; rua=mailto:dmarc@example.org").unwrap(),
+        Instant::now() + Duration::from_secs(3600),
+    );
+    server.core.smtp.resolvers.dns.txt_add(
+        "example.org",
+        Spf::parse(b"v=spf1 ip4:foobar -all"),
+        Instant::now() + Duration::from_secs(3600),
+    );
+    let check = server.check_dkim_domain("example.org").await.unwrap();
+    assert_eq!(check.dmarc, DnsRecordCheck::Valid);
+    assert!(
+        matches!(check.spf, DnsRecordCheck::Invalid { .. }),
+        "{check:?}"
+    );
+
+    // The record stops matching after the zone is moved
+    server.core.smtp.resolvers.dns.txt_add(
+        keys[0].dns_name.as_str(),
+        DomainKey::parse(keys[1].dns_record.as_bytes()).unwrap(),
+        Instant::now() + Duration::from_secs(3600),
+    );
+    let check = server.check_dkim_domain("example.org").await.unwrap();
+    assert_eq!(check.keys[3].selector, "valid");
+    assert_eq!(check.keys[3].signature, DkimSignatureCheck::Mismatch);
+    server.check_dkim_keys().await.unwrap();
+
+    // Domains without keys only report SPF and DMARC
+    let check = admin
+        .get::<DkimDomainCheck>("/api/dkim/check/example.net")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert!(check.keys.is_empty());
+    assert_eq!(check.spf, DnsRecordCheck::Missing);
+}
@@ -4,5 +4,10 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+pub mod audit;
+pub mod dkim;
+pub mod quarantine;
 pub mod queue;
+pub mod reload;
 pub mod report;
+pub mod security;
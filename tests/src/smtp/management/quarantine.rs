@@ -0,0 +1,253 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use common::{config::server::ServerProtocol, core::BuildServer};
+use jmap::api::management::quarantine::Quarantined;
+use reqwest::Method;
+use smtp::{
+    core::Session,
+    queue::quarantine::{SmtpQuarantine, GLOBAL_QUARANTINE_ID},
+};
+use store::write::{BatchBuilder, Bincode, QueueClass, ValueClass};
+
+use crate::{
+    jmap::ManagementApi,
+    smtp::{
+        inbound::TestMessage,
+        management::queue::List,
+        session::{load_test_message, TestSession, VerifyResponse},
+        TestSMTP,
+    },
+};
+
+const CONFIG: &str = r#"
+[storage]
+directory = "local"
+
+[directory."local"]
+type = "memory"
+
+[[directory."local".principals]]
+name = "admin"
+type = "admin"
+description = "Superuser"
+secret = "secret"
+class = "admin"
+
+[[directory."local".principals]]
+name = "jane"
+description = "Jane Doe"
+secret = "p4ssw0rd"
+email = "jane@foobar.org"
+
+[session.rcpt]
+relay = true
+
+[session.extensions]
+dsn = true
+
+[spam-filter.attachments.policy]
+quarantine = ["macro"]
+
+[spam-filter.quarantine]
+enable = true
+retention = "7d"
+"#;
+
+#[tokio::test]
+#[serial_test::serial]
+async fn manage_quarantine() {
+    // Enable logging
+    crate::enable_logging();
+
+    // Start local management interface
+    let local = TestSMTP::new("smtp_manage_quarantine", CONFIG).await;
+    let _rx_manage = local.start(&[ServerProtocol::Http]).await;
+    let server = local.server.inner.build_server();
+    let mut session = Session::test(local.server.inner.build_server());
+    let mut qr = local.queue_receiver;
+    session.data.remote_ip_str = "10.0.0.1".to_string();
+    session.data.remote_ip = session.data.remote_ip_str.parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx.doe.org").await;
+
+    // Messages matching a quarantine policy are accepted but not queued
+    let message = load_test_message("macro_xlsm", "attachments");
+    session
+        .send_message(
+            "<john@doe.org> ENVID=abc",
+            &["jane@foobar.org", "bill@example.org"],
+            &message,
+            "250",
+        )
+        .await;
+    qr.assert_no_events();
+
+    // Local recipients are quarantined in their account, others in the global quarantine
+    let admin = ManagementApi::default();
+    let jane = ManagementApi::new(9980, "jane", "p4ssw0rd");
+    let items = admin
+        .request::<List<Quarantined>>(Method::GET, "/api/quarantine?account=jane")
+        .await
+        .unwrap()
+        .unwrap_data()
+        .items;
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].recipients, ["jane@foobar.org"]);
+    assert_eq!(items[0].return_path, "john@doe.org");
+    assert_eq!(items[0].stage, "attachments");
+    assert_eq!(items[0].reason, "attachment");
+    assert_eq!(items[0].details.as_deref(), Some("macro"));
+    let jane_id = items[0].id;
+    let items = admin
+        .request::<List<Quarantined>>(Method::GET, "/api/quarantine?global")
+        .await
+        .unwrap()
+        .unwrap_data()
+        .items;
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].recipients, ["bill@example.org"]);
+    let global_id = items[0].id;
+
+    // Users can only access their own quarantine
+    assert_eq!(
+        jane.request::<List<Quarantined>>(Method::GET, "/api/quarantine")
+            .await
+            .unwrap()
+            .unwrap_data()
+            .items
+            .into_iter()
+            .map(|item| item.id)
+            .collect::<Vec<_>>(),
+        [jane_id]
+    );
+    jane.request::<List<Quarantined>>(Method::GET, "/api/quarantine?global")
+        .await
+        .unwrap()
+        .expect_request_error("Forbidden");
+
+    // Preview the message headers
+    let item = jane
+        .request::<Quarantined>(Method::GET, &format!("/api/quarantine/{jane_id}"))
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert!(
+        item.headers
+            .iter()
+            .any(|(name, _)| name.eq_ignore_ascii_case("Subject")),
+        "{:?}",
+        item.headers
+    );
+
+    // Released messages are queued using the original envelope
+    assert_eq!(
+        jane.request::<serde_json::Value>(
+            Method::POST,
+            &format!("/api/quarantine/{jane_id}/release")
+        )
+        .await
+        .unwrap()
+        .unwrap_data()["status"],
+        "queued"
+    );
+    let released = qr.expect_message().await;
+    assert_eq!(released.return_path, "john@doe.org");
+    assert_eq!(released.env_id.as_deref(), Some("abc"));
+    assert_eq!(
+        released
+            .recipients
+            .iter()
+            .map(|rcpt| rcpt.address.as_str())
+            .collect::<Vec<_>>(),
+        ["jane@foobar.org"]
+    );
+    released
+        .read_lines(&qr)
+        .await
+        .assert_contains("Received: ")
+        .assert_not_contains("X-Quarantine");
+    assert!(server
+        .list_quarantined(jane_account_id(&server).await)
+        .await
+        .unwrap()
+        .is_empty());
+
+    // Delete quarantined messages
+    assert!(admin
+        .request::<bool>(
+            Method::DELETE,
+            &format!("/api/quarantine/{global_id}?global")
+        )
+        .await
+        .unwrap()
+        .unwrap_data());
+    assert!(server
+        .list_quarantined(GLOBAL_QUARANTINE_ID)
+        .await
+        .unwrap()
+        .is_empty());
+
+    // Expired messages and their blobs are removed
+    session
+        .send_message(
+            "john@doe.org",
+            &["bill@example.org"],
+            &format!("X-Test: expiry\r\n{message}"),
+            "250",
+        )
+        .await;
+    let mut expired = server
+        .list_quarantined(GLOBAL_QUARANTINE_ID)
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    server.purge_quarantine().await.unwrap();
+    assert_eq!(
+        server
+            .list_quarantined(GLOBAL_QUARANTINE_ID)
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+    expired.expires = 0;
+    let mut batch = BatchBuilder::new();
+    batch.set(
+        ValueClass::Queue(QueueClass::Quarantine {
+            account_id: expired.account_id,
+            id: expired.id,
+        }),
+        Bincode::new(expired.clone()).serialize(),
+    );
+    server.store().write(batch.build()).await.unwrap();
+    server.purge_quarantine().await.unwrap();
+    assert!(server
+        .list_quarantined(GLOBAL_QUARANTINE_ID)
+        .await
+        .unwrap()
+        .is_empty());
+    server
+        .store()
+        .purge_blobs(server.blob_store().clone())
+        .await
+        .unwrap();
+    assert!(server
+        .blob_store()
+        .get_blob(expired.blob_hash.as_slice(), 0..usize::MAX)
+        .await
+        .unwrap()
+        .is_none());
+}
+
+async fn jane_account_id(server: &common::Server) -> u32 {
+    server
+        .email_to_id(&server.core.storage.directory, "jane@foobar.org", 0)
+        .await
+        .unwrap()
+        .unwrap()
+}
@@ -16,9 +16,13 @@ use reqwest::{header::AUTHORIZATION, Method, StatusCode};
 
 use crate::{
     jmap::ManagementApi,
-    smtp::{session::TestSession, TestSMTP},
+    smtp::{
+        inbound::TestMessage,
+        session::{TestSession, VerifyResponse},
+        TestSMTP,
+    },
 };
-use smtp::queue::{manager::SpawnQueue, QueueId, Status};
+use smtp::queue::{manager::SpawnQueue, spool::SmtpSpool, QueueId, Status};
 
 const LOCAL: &str = r#"
 [storage]
@@ -88,6 +92,14 @@ async fn manage_queue() {
         Instant::now() + Duration::from_secs(10),
     );
 
+    core.core.smtp.resolvers.dns.mx_add(
+        "foobar.net",
+        vec![MX {
+            exchanges: vec!["mx1.foobar.org".to_string()],
+            preference: 10,
+        }],
+        Instant::now() + Duration::from_secs(10),
+    );
     core.core.smtp.resolvers.dns.ipv4_add(
         "mx1.foobar.org",
         vec!["127.0.0.1".parse().unwrap()],
@@ -209,6 +221,16 @@ async fn manage_queue() {
         let next_retry = created + hold_for;
         let next_notify = created + 2000 + hold_for;
         let expires = created + 3000 + hold_for;
+        if env_id != "f" {
+            assert_timestamp(
+                message.hold_until.as_ref().unwrap(),
+                next_retry,
+                "hold",
+                &message,
+            );
+        } else {
+            assert_eq!(message.hold_until, None);
+        }
         for domain in &message.domains {
             if env_id == "c" {
                 let mut dt = *domain.next_retry.as_ref().unwrap();
@@ -272,6 +294,14 @@ async fn manage_queue() {
             "/api/queue/messages?from=bill3@foobar.net&to=rcpt5@example1.com".to_string(),
             vec!["c"],
         ),
+        (
+            "/api/queue/messages?domain=example2.com".to_string(),
+            vec!["c"],
+        ),
+        (
+            "/api/queue/messages?domain=Example1.org&from=bill1@foobar.net".to_string(),
+            vec!["a"],
+        ),
         (
             format!("/api/queue/messages?before={test_search}"),
             vec!["a", "b"],
@@ -361,29 +391,90 @@ async fn manage_queue() {
         }
     }
 
+    // Retry delivery for specific recipients and domains
+    let id = *id_map.get("c").unwrap();
+    assert!(api
+        .request::<bool>(
+            Method::POST,
+            &format!(
+                "/api/queue/messages/{id}/retry?recipients=rcpt5@example1.com&domains=example3.com&at=2200-01-01T00:00:00Z",
+            )
+        )
+        .await
+        .unwrap()
+        .unwrap_data());
+    assert!(!api
+        .request::<bool>(
+            Method::POST,
+            &format!("/api/queue/messages/{id}/retry?domains=example1.org"),
+        )
+        .await
+        .unwrap()
+        .unwrap_data());
+    let retry_domains = api
+        .get_messages(&[id])
+        .await
+        .pop()
+        .unwrap()
+        .unwrap()
+        .domains
+        .into_iter()
+        .filter(|domain| domain.next_retry.as_ref().unwrap().to_rfc3339() == "2200-01-01T00:00:00Z")
+        .map(|domain| domain.name)
+        .collect::<HashSet<_>>();
+    assert_eq!(
+        retry_domains,
+        HashSet::from_iter(["example1.com".to_string(), "example3.com".to_string()])
+    );
+
+    // Messages locked by the queue manager can not be modified
+    let id = *id_map.get("b").unwrap();
+    let event = core.try_lock_message(id).await.unwrap();
+    api.request::<bool>(Method::DELETE, &format!("/api/queue/messages/{id}"))
+        .await
+        .unwrap()
+        .expect_error("queue manager");
+    api.request::<bool>(Method::PATCH, &format!("/api/queue/messages/{id}"))
+        .await
+        .unwrap()
+        .expect_error("queue manager");
+    assert!(core.unlock_event(event).await);
+
     // Cancel deliveries
-    for (id, filter) in [
-        ("a", "example2.org"),
-        ("b", "example1.net"),
-        ("c", "rcpt6@example2.com"),
-        ("d", ""),
+    for (id, query) in [
+        ("a", "?filter=example2.org"),
+        ("b", "?filter=example1.net"),
+        ("c", "?filter=rcpt6@example2.com"),
+        ("d", "?bounce=true"),
     ] {
         assert!(
             api.request::<bool>(
                 Method::DELETE,
-                &format!(
-                    "/api/queue/messages/{}{}{}",
-                    id_map.get(id).unwrap(),
-                    if !filter.is_empty() { "?filter=" } else { "" },
-                    filter
-                )
+                &format!("/api/queue/messages/{}{query}", id_map.get(id).unwrap(),)
             )
             .await
             .unwrap()
             .unwrap_data(),
-            "failed for {id}: {filter}"
+            "failed for {id}: {query}"
         );
     }
+
+    // Expect a bounce for the message that was canceled with notification
+    let dsn = remote.queue_receiver.consume_message(&remote_core).await;
+    assert_eq!(dsn.return_path, "");
+    assert_eq!(
+        dsn.recipients
+            .iter()
+            .map(|r| r.address.as_str())
+            .collect::<Vec<_>>(),
+        vec!["bill4@foobar.net"]
+    );
+    dsn.read_lines(&remote.queue_receiver)
+        .await
+        .assert_contains("<delay@foobar.org>")
+        .assert_contains("Delivery canceled.")
+        .assert_contains("Action: failed");
+    tokio::time::sleep(Duration::from_millis(100)).await;
     assert_eq!(
         api.request::<List<QueueId>>(Method::GET, "/api/queue/messages")
             .await
@@ -0,0 +1,158 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::sync::Arc;
+
+use common::{
+    config::server::ServerProtocol,
+    core::BuildServer,
+    ipc::HousekeeperEvent,
+    manager::config::{ConfigManager, Patterns},
+    Core, Data,
+};
+use reqwest::Method;
+use smtp::core::Session;
+use store::Stores;
+use utils::config::Config;
+
+use crate::{
+    jmap::ManagementApi,
+    smtp::{add_test_certs, session::TestSession, TempDir, TestSMTP},
+};
+
+const LOCAL: &str = r#"
+[storage]
+directory = "local"
+
+[directory."local"]
+type = "memory"
+
+[[directory."local".principals]]
+name = "admin"
+description = "Superuser"
+secret = "secret"
+class = "admin"
+"#;
+
+#[derive(serde::Deserialize, Debug)]
+struct ReloadResponse {
+    changed: Vec<String>,
+    restart_required: Vec<String>,
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn manage_reload() {
+    // Enable logging
+    crate::enable_logging();
+
+    // Build a server backed by a configuration manager
+    let temp_dir = TempDir::new("smtp_manage_reload", true);
+    let mut config =
+        Config::new(temp_dir.update_config(add_test_certs(crate::smtp::CONFIG) + LOCAL)).unwrap();
+    config.resolve_all_macros().await;
+    let stores = Stores::parse_all(&mut config).await;
+    let manager = ConfigManager {
+        cfg_local: Arc::new(config.keys.clone()).into(),
+        cfg_local_path: Default::default(),
+        cfg_local_patterns: Patterns::parse(&mut config).into(),
+        cfg_store: config
+            .value("storage.data")
+            .and_then(|id| stores.stores.get(id))
+            .cloned()
+            .unwrap_or_default(),
+        cfg_snapshot: Default::default(),
+    };
+    manager
+        .set([
+            ("session.throttle.0000.key", "remote_ip"),
+            ("session.throttle.0000.rate", "1/1d"),
+        ])
+        .await
+        .unwrap();
+    let mut config = manager.build_config("").await.unwrap();
+    let core = Core::parse(&mut config, stores, manager).await;
+    let data = Data::parse(&mut config);
+    let mut local = TestSMTP::from_core_and_tempdir(core, data, Some(temp_dir));
+    let _rx = local.start(&[ServerProtocol::Http]).await;
+
+    // Exhaust the rate limit
+    let mut old_session = Session::test(local.server.inner.build_server());
+    old_session.data.remote_ip_str = "10.0.0.1".to_string();
+    assert!(old_session.is_allowed().await);
+    assert!(!old_session.is_allowed().await);
+
+    // Nothing changed yet
+    let api = ManagementApi::default();
+    let response = api
+        .request::<ReloadResponse>(Method::POST, "/api/reload")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert!(response.changed.is_empty(), "{response:?}");
+
+    // Raise the rate limit and change a setting that requires a restart
+    let server = local.server.inner.build_server();
+    let manager = &server.core.storage.config;
+    manager
+        .set([("session.throttle.0000.rate", "3/1d")])
+        .await
+        .unwrap();
+    let mut cfg_local = manager.cfg_local.load().as_ref().clone();
+    cfg_local.insert("server.max-connections".to_string(), "1024".to_string());
+    manager.cfg_local.store(cfg_local.into());
+
+    // Dry runs do not apply changes
+    let response = api
+        .request::<ReloadResponse>(Method::POST, "/api/reload?dry-run=true")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(
+        response.changed,
+        ["server.max-connections", "session.throttle.0000.rate"]
+    );
+    assert_eq!(response.restart_required, ["server.max-connections"]);
+    let mut session = Session::test(local.server.inner.build_server());
+    session.data.remote_ip_str = "10.0.0.1".to_string();
+    assert!(!session.is_allowed().await);
+
+    // Reload
+    let response = api
+        .request::<ReloadResponse>(Method::POST, "/api/reload")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(
+        response.changed,
+        ["server.max-connections", "session.throttle.0000.rate"]
+    );
+    assert_eq!(response.restart_required, ["server.max-connections"]);
+
+    assert!(matches!(
+        local.housekeeper_rx.try_recv(),
+        Ok(HousekeeperEvent::ReloadSettings)
+    ));
+
+    // New sessions observe the new limit
+    let mut new_session = Session::test(local.server.inner.build_server());
+    new_session.data.remote_ip_str = "10.0.0.1".to_string();
+    for _ in 0..3 {
+        assert!(new_session.is_allowed().await);
+    }
+    assert!(!new_session.is_allowed().await);
+
+    // Existing sessions keep their snapshot
+    assert!(!old_session.is_allowed().await);
+
+    // Reloading again reports no changes
+    let response = api
+        .request::<ReloadResponse>(Method::POST, "/api/reload")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert!(response.changed.is_empty(), "{response:?}");
+}
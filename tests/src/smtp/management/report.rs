@@ -18,17 +18,25 @@ use mail_auth::{
     dmarc::Dmarc,
     mta_sts::TlsRpt,
     report::{
-        tlsrpt::{FailureDetails, ResultType},
-        ActionDisposition, DmarcResult, Record,
+        tlsrpt::{
+            self, DateRange, FailureDetails, Policy, PolicyDetails, ResultType, Summary, TlsReport,
+        },
+        ActionDisposition, DmarcResult, Feedback, FeedbackType, Record,
     },
 };
+use mail_parser::DateTime;
 use reqwest::Method;
 
 use crate::{
     jmap::ManagementApi,
     smtp::{management::queue::List, TestSMTP},
 };
-use smtp::reporting::{scheduler::SpawnReport, SmtpReporting};
+use smtp::reporting::{analysis::IncomingReport, scheduler::SpawnReport, SmtpReporting};
+use store::{
+    write::{BatchBuilder, Bincode, ReportClass, ValueClass},
+    Serialize,
+};
+use utils::snowflake::SnowflakeIdGenerator;
 
 const CONFIG: &str = r#"
 [storage]
@@ -241,3 +249,309 @@ impl ManagementApi {
         results
     }
 }
+
+#[tokio::test]
+#[serial_test::serial]
+async fn manage_incoming_reports() {
+    // Enable logging
+    crate::enable_logging();
+
+    let local = TestSMTP::new("smtp_manage_incoming_reports", CONFIG).await;
+    let _rx = local.start(&[ServerProtocol::Http]).await;
+    let store = local.server.core.storage.data.clone();
+
+    // Seed synthetic reports received over the last 45 days
+    let now = store::write::now();
+    let expires = now + 90 * 86400;
+    let report_id = |num: u64, age: u64| {
+        SnowflakeIdGenerator::from_timestamp(now - age * 86400 - 60).unwrap() + num
+    };
+    let domains = ["foobar.org", "foobar.net", "example.org"];
+    let mut expected_sources: AHashMap<String, [u64; 4]> = AHashMap::new();
+    let mut expected_summary: AHashMap<(String, &str), [u64; 4]> = AHashMap::new();
+    let mut expected_net: usize = 0;
+    let mut expected_net_recent = 0;
+    for num in 0..300u64 {
+        let domain = domains[num as usize % 3];
+        let age = num % 45;
+        let source = format!("10.0.0.{}", num % 5);
+        let count = num % 7 + 1;
+        let result = if num % 2 == 0 {
+            DmarcResult::Pass
+        } else {
+            DmarcResult::Fail
+        };
+        let pass = if num % 2 == 0 { count } else { 0 };
+        let report = mail_auth::report::Report::new()
+            .with_domain(domain)
+            .with_report_id(format!("dmarc-{num}"))
+            .with_record(
+                Record::new()
+                    .with_source_ip(source.parse().unwrap())
+                    .with_count(count as u32)
+                    .with_dmarc_dkim_result(result)
+                    .with_dmarc_spf_result(DmarcResult::Fail),
+            );
+        store_report(
+            &store,
+            ReportClass::Dmarc {
+                id: report_id(num, age),
+                expires,
+            },
+            domain,
+            report,
+        )
+        .await;
+
+        if domain == "foobar.org" {
+            let stats = expected_sources.entry(source).or_default();
+            stats[0] += 1;
+            stats[1] += count;
+            stats[2] += pass;
+            stats[3] += count - pass;
+        } else if domain == "foobar.net" {
+            expected_net += 1;
+            if age < 10 {
+                expected_net_recent += 1;
+            }
+        }
+        if age < 30 {
+            let stats = expected_summary
+                .entry((domain.to_string(), "dmarc"))
+                .or_default();
+            stats[0] += 1;
+            stats[1] += count;
+            stats[2] += pass;
+            stats[3] += count - pass;
+        }
+    }
+    for num in 0..60u64 {
+        let domain = domains[num as usize % 2];
+        let age = num % 40;
+        let success = num;
+        let failure = num % 3;
+        let report = TlsReport {
+            organization_name: None,
+            date_range: DateRange {
+                start_datetime: DateTime::from_timestamp((now - 86400) as i64),
+                end_datetime: DateTime::from_timestamp(now as i64),
+            },
+            contact_info: None,
+            report_id: format!("tls-{num}"),
+            policies: vec![Policy {
+                policy: PolicyDetails {
+                    policy_type: tlsrpt::PolicyType::Sts,
+                    policy_domain: domain.to_string(),
+                    ..Default::default()
+                },
+                summary: Summary {
+                    total_success: success as u32,
+                    total_failure: failure as u32,
+                },
+                failure_details: vec![],
+            }],
+        };
+        store_report(
+            &store,
+            ReportClass::Tls {
+                id: report_id(1000 + num, age),
+                expires,
+            },
+            domain,
+            report,
+        )
+        .await;
+
+        if age < 30 {
+            let stats = expected_summary
+                .entry((domain.to_string(), "tls"))
+                .or_default();
+            stats[0] += 1;
+            stats[1] += success + failure;
+            stats[2] += success;
+            stats[3] += failure;
+        }
+    }
+    for num in 0..40u64 {
+        let age = num % 35;
+        let incidents = num % 3;
+        let report = Feedback::new(FeedbackType::Abuse)
+            .with_reported_domain("foobar.org")
+            .with_source_ip(format!("10.0.1.{}", num % 4).parse().unwrap())
+            .with_incidents(incidents as u32);
+        store_report(
+            &store,
+            ReportClass::Arf {
+                id: report_id(2000 + num, age),
+                expires,
+            },
+            "foobar.org",
+            report,
+        )
+        .await;
+
+        if age < 30 {
+            let stats = expected_summary
+                .entry(("foobar.org".to_string(), "arf"))
+                .or_default();
+            stats[0] += 1;
+            stats[1] += std::cmp::max(incidents, 1);
+            stats[3] += std::cmp::max(incidents, 1);
+        }
+    }
+
+    // DMARC results grouped by source IP
+    let api = ManagementApi::default();
+    let sources = api
+        .request::<serde_json::Value>(Method::GET, "/api/reports/dmarc/sources?domain=foobar.org")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(sources["total"], expected_sources.len());
+    let mut last_total = u64::MAX;
+    for item in sources["items"].as_array().unwrap() {
+        let expected = expected_sources
+            .get(item["source"].as_str().unwrap())
+            .unwrap();
+        assert_eq!(
+            [
+                item["reports"].as_u64().unwrap(),
+                item["total"].as_u64().unwrap(),
+                item["pass"].as_u64().unwrap(),
+                item["fail"].as_u64().unwrap()
+            ],
+            *expected,
+            "{item}"
+        );
+        assert!(expected[1] <= last_total);
+        last_total = expected[1];
+    }
+    let page = api
+        .request::<serde_json::Value>(
+            Method::GET,
+            "/api/reports/dmarc/sources?domain=foobar.org&page=2&limit=2",
+        )
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(
+        page["items"].as_array().unwrap()[..],
+        sources["items"].as_array().unwrap()[2..4]
+    );
+
+    // Rollup of the last 30 days per domain
+    let summary = api
+        .request::<serde_json::Value>(Method::GET, "/api/reports/summary")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(summary["total"], 3);
+    for item in summary["items"].as_array().unwrap() {
+        let domain = item["domain"].as_str().unwrap();
+        for class in ["dmarc", "tls", "arf"] {
+            let expected = expected_summary
+                .get(&(domain.to_string(), class))
+                .copied()
+                .unwrap_or_default();
+            let stats = &item[class];
+            assert_eq!(
+                [
+                    stats["reports"].as_u64().unwrap(),
+                    stats["total"].as_u64().unwrap(),
+                    stats["pass"].as_u64().unwrap(),
+                    stats["fail"].as_u64().unwrap()
+                ],
+                expected,
+                "{domain} {class}"
+            );
+        }
+    }
+
+    // Paginate using cursors
+    let mut ids = Vec::new();
+    let mut cursor: Option<String> = None;
+    let mut pages = 0;
+    loop {
+        let mut query = "/api/reports/dmarc?domain=foobar.net&limit=30".to_string();
+        if let Some(cursor) = &cursor {
+            query.push_str(&format!("&cursor={cursor}"));
+        }
+        let page = api
+            .request::<serde_json::Value>(Method::GET, &query)
+            .await
+            .unwrap()
+            .unwrap_data();
+        let items = page["items"].as_array().unwrap();
+        assert!(items.len() <= 30);
+        ids.extend(items.iter().map(|id| id.as_str().unwrap().to_string()));
+        pages += 1;
+        match page["cursor"].as_str() {
+            Some(next) => {
+                assert_eq!(next, ids.last().unwrap());
+                cursor = Some(next.to_string());
+            }
+            None => break,
+        }
+    }
+    assert_eq!(pages, expected_net.div_ceil(30));
+    assert_eq!(ids.len(), expected_net);
+    assert_eq!(ids.iter().collect::<HashSet<_>>().len(), expected_net);
+    let all_ids = api
+        .request::<serde_json::Value>(Method::GET, "/api/reports/dmarc?domain=foobar.net")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(all_ids["total"], expected_net);
+    assert_eq!(
+        all_ids["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|id| id.as_str().unwrap().to_string())
+            .collect::<Vec<_>>(),
+        ids
+    );
+    assert!(all_ids["cursor"].is_null());
+
+    // Filter by date
+    let recent = api
+        .request::<serde_json::Value>(
+            Method::GET,
+            &format!(
+                "/api/reports/dmarc?domain=foobar.net&from={}",
+                now - 10 * 86400
+            ),
+        )
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(recent["total"], expected_net_recent);
+
+    // Fetch the full report
+    let report = api
+        .request::<serde_json::Value>(Method::GET, &format!("/api/reports/dmarc/{}", ids[0]))
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(report["report"]["policy_published"]["domain"], "foobar.net");
+}
+
+async fn store_report<T: serde::Serialize + serde::de::DeserializeOwned>(
+    store: &store::Store,
+    class: ReportClass,
+    domain: &str,
+    report: T,
+) {
+    let mut batch = BatchBuilder::new();
+    batch.set(
+        ValueClass::Report(class),
+        Bincode::new(IncomingReport {
+            from: format!("noreply@reporter.{domain}"),
+            to: vec![format!("reports@{domain}")],
+            subject: "Report".to_string(),
+            report,
+        })
+        .serialize(),
+    );
+    store.write(batch.build()).await.unwrap();
+}
@@ -0,0 +1,211 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{net::IpAddr, time::Duration};
+
+use common::{config::server::ServerProtocol, core::BuildServer, listener::blocked::ban_address};
+use jmap::api::management::security::BlockedIp;
+use reqwest::Method;
+use store::write::now;
+
+use crate::{
+    jmap::ManagementApi,
+    smtp::{management::queue::List, TestSMTP},
+};
+
+const CONFIG: &str = r#"
+[storage]
+directory = "local"
+
+[directory."local"]
+type = "memory"
+
+[[directory."local".principals]]
+name = "admin"
+type = "admin"
+description = "Superuser"
+secret = "secret"
+class = "admin"
+
+[[directory."local".principals]]
+name = "jane"
+description = "Jane Doe"
+secret = "p4ssw0rd"
+email = "jane@foobar.org"
+
+[server.auto-ban]
+duration = "2s"
+max-duration = "1h"
+allow-authenticated = true
+
+[server.auto-ban.auth]
+rate = "3/1d"
+"#;
+
+#[tokio::test]
+#[serial_test::serial]
+async fn manage_security() {
+    // Enable logging
+    crate::enable_logging();
+
+    // Start local management interface
+    let local = TestSMTP::new("smtp_manage_security", CONFIG).await;
+    let _rx_manage = local.start(&[ServerProtocol::Http]).await;
+    let admin = ManagementApi::default();
+    let jane_bad = ManagementApi::new(9980, "jane", "wrong");
+    let localhost: IpAddr = "127.0.0.1".parse().unwrap();
+
+    // A burst of failed logins bans the address
+    for _ in 0..3 {
+        jane_bad
+            .request::<List<BlockedIp>>(Method::GET, "/api/security/blocked")
+            .await
+            .unwrap()
+            .expect_request_error("Unauthorized");
+    }
+    let _ = jane_bad
+        .request::<List<BlockedIp>>(Method::GET, "/api/security/blocked")
+        .await;
+    let server = local.server.inner.build_server();
+    let bans = server.blocked_ips();
+    assert_eq!(bans.len(), 1, "{bans:?}");
+    assert_eq!(bans[0].0, localhost);
+    let expires = bans[0].1.unwrap();
+    assert!((now() + 1..=now() + 2).contains(&expires), "{expires}");
+
+    // The next connection is refused
+    assert!(admin
+        .request::<List<BlockedIp>>(Method::GET, "/api/security/blocked")
+        .await
+        .is_err());
+
+    // Access is restored once the ban expires, repeat offences are banned for longer
+    tokio::time::sleep(Duration::from_secs(3)).await;
+    assert!(!server.is_ip_blocked(&localhost));
+    jane_bad
+        .request::<List<BlockedIp>>(Method::GET, "/api/security/blocked")
+        .await
+        .unwrap();
+    let expires = server.blocked_ips()[0].1.unwrap();
+    assert!((now() + 3..=now() + 4).contains(&expires), "{expires}");
+    assert!(server.unblock_ip(localhost).await.unwrap());
+
+    // Addresses with successful logins are not banned again
+    admin
+        .request::<List<BlockedIp>>(Method::GET, "/api/security/blocked")
+        .await
+        .unwrap()
+        .unwrap_data();
+    jane_bad
+        .request::<List<BlockedIp>>(Method::GET, "/api/security/blocked")
+        .await
+        .unwrap()
+        .expect_request_error("Unauthorized");
+    assert!(!server.is_ip_blocked(&localhost));
+
+    // Manage bans from the API
+    admin
+        .post::<()>(
+            "/api/security/blocked",
+            &serde_json::json!({"ip": "10.0.0.1", "duration": 3600}),
+        )
+        .await
+        .unwrap()
+        .unwrap_data();
+    admin
+        .post::<()>(
+            "/api/security/blocked",
+            &serde_json::json!({"ip": "10.0.0.2"}),
+        )
+        .await
+        .unwrap()
+        .unwrap_data();
+    let bans = admin
+        .request::<List<BlockedIp>>(Method::GET, "/api/security/blocked")
+        .await
+        .unwrap()
+        .unwrap_data()
+        .items;
+    assert_eq!(bans.len(), 2, "{bans:?}");
+    assert_eq!(bans[0].ip, "10.0.0.1".parse::<IpAddr>().unwrap());
+    assert!(bans[0]
+        .expires
+        .is_some_and(|expires| expires > now() + 3500));
+    assert_eq!(
+        bans[1],
+        BlockedIp {
+            ip: "10.0.0.2".parse().unwrap(),
+            expires: None
+        }
+    );
+    assert!(server.is_ip_blocked(&"10.0.0.2".parse().unwrap()));
+    admin
+        .delete::<()>("/api/security/blocked/10.0.0.2")
+        .await
+        .unwrap()
+        .unwrap_data();
+    admin
+        .delete::<()>("/api/security/blocked/10.0.0.2")
+        .await
+        .unwrap()
+        .expect_request_error("Not Found");
+    assert!(!server.is_ip_blocked(&"10.0.0.2".parse().unwrap()));
+
+    // Bans survive a restart
+    assert_eq!(
+        server
+            .core
+            .storage
+            .config
+            .get("server.blocked-ip.10.0.0.1")
+            .await
+            .unwrap(),
+        Some(bans[0].expires.unwrap().to_string())
+    );
+    server.inner.data.blocked_ips.write().clear();
+    server.reload_blocked_ips().await.unwrap();
+    assert!(server.is_ip_blocked(&"10.0.0.1".parse().unwrap()));
+
+    // IPv6 addresses are banned by /64 network
+    let ipv6: IpAddr = "2001:db8:0:1::1".parse().unwrap();
+    server.block_ip(ban_address(ipv6), None).await.unwrap();
+    assert!(server.is_ip_blocked(&"2001:db8:0:1::ffff".parse().unwrap()));
+    assert!(!server.is_ip_blocked(&"2001:db8:0:2::1".parse().unwrap()));
+
+    // Allowed networks override bans
+    admin
+        .post::<()>(
+            "/api/security/allowed",
+            &serde_json::json!({"network": "127.0.0.0/8"}),
+        )
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(
+        admin
+            .request::<List<String>>(Method::GET, "/api/security/allowed")
+            .await
+            .unwrap()
+            .unwrap_data()
+            .items,
+        vec!["127.0.0.0/8".to_string()]
+    );
+    let server = local.server.inner.build_server();
+    server.block_ip(localhost, None).await.unwrap();
+    assert!(!server.is_ip_blocked(&localhost));
+    admin
+        .delete::<()>("/api/security/allowed/127.0.0.0%2F8")
+        .await
+        .unwrap()
+        .unwrap_data();
+    let server = local.server.inner.build_server();
+    assert!(server.is_ip_blocked(&localhost));
+    assert!(admin
+        .request::<List<BlockedIp>>(Method::GET, "/api/security/blocked")
+        .await
+        .is_err());
+    server.unblock_ip(localhost).await.unwrap();
+}
@@ -0,0 +1,164 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use trc::{
+    AuthEvent, Collector, DeliveryEvent, EventType, Key, QueueEvent, SmtpEvent, StoreEvent, Value,
+};
+
+use crate::smtp::{session::TestSession, TestSMTP};
+
+const CONFIG: &str = r#"
+[session.rcpt]
+relay = true
+
+[session.data.limits]
+messages = 10
+"#;
+
+#[tokio::test]
+#[serial_test::serial]
+async fn prometheus_labeled_metrics() {
+    // Enable logging
+    crate::enable_logging();
+
+    let test = TestSMTP::new("smtp_prometheus_metrics", CONFIG).await;
+
+    // Queue a message so the queue gauges have something to report
+    let mut session = test.new_session();
+    session.data.remote_ip_str = "10.0.0.1".to_string();
+    session.eval_session_params().await;
+    session.ehlo("mx.test.org").await;
+    session
+        .send_message("john@test.org", &["bill@foobar.org"], "test:no_dkim", "250")
+        .await;
+
+    // Simulated inbound sessions on two listeners
+    for (span_id, listener) in [(9001, "metrics-mx"), (9002, "metrics-submission")] {
+        record(
+            EventType::Smtp(SmtpEvent::ConnectionStart),
+            vec![
+                (Key::SpanId, Value::UInt(span_id)),
+                (Key::ListenerId, Value::String(listener.to_string())),
+            ],
+        );
+        record(
+            EventType::Smtp(SmtpEvent::MailFrom),
+            vec![(Key::SpanId, Value::UInt(span_id))],
+        );
+        record(
+            EventType::Queue(QueueEvent::QueueMessage),
+            vec![
+                (Key::SpanId, Value::UInt(span_id)),
+                (Key::Size, Value::UInt(1024)),
+            ],
+        );
+        record(
+            EventType::Smtp(SmtpEvent::ConnectionEnd),
+            vec![
+                (Key::SpanId, Value::UInt(span_id)),
+                (Key::Elapsed, Value::Duration(10)),
+            ],
+        );
+    }
+
+    // Delivery outcomes and authentication attempts
+    for (event, domain) in [
+        (DeliveryEvent::DomainDelivered, "metrics-a.org"),
+        (DeliveryEvent::DomainDelivered, "metrics-a.org"),
+        (DeliveryEvent::DomainDelivered, "metrics-b.org"),
+        (DeliveryEvent::DomainDeferred, "metrics-b.org"),
+        (DeliveryEvent::DomainBounced, "metrics-c.org"),
+    ] {
+        record(
+            EventType::Delivery(event),
+            vec![(Key::Domain, Value::String(domain.to_string()))],
+        );
+    }
+    for (event, mechanism) in [
+        (AuthEvent::Success, "plain"),
+        (AuthEvent::Failed, "plain"),
+        (AuthEvent::Failed, "xoauth2"),
+    ] {
+        record(
+            EventType::Auth(event),
+            vec![(Key::Mechanism, Value::Static(mechanism))],
+        );
+    }
+
+    record(
+        EventType::Store(StoreEvent::DataWrite),
+        vec![(Key::Elapsed, Value::Duration(3))],
+    );
+
+    // More domains than the label limit allows
+    for i in 0..150 {
+        record(
+            EventType::Delivery(DeliveryEvent::DomainDeferred),
+            vec![(Key::Domain, Value::String(format!("overflow-{i}.org")))],
+        );
+    }
+
+    // Scrape
+    let metrics = test.server.export_prometheus_metrics().await.unwrap();
+    for (series, expected) in [
+        ("smtp_messages_received_total{listener=\"metrics-mx\"}", 1.0),
+        (
+            "smtp_messages_received_total{listener=\"metrics-submission\"}",
+            1.0,
+        ),
+        (
+            "smtp_transaction_duration_count{listener=\"metrics-mx\"}",
+            1.0,
+        ),
+        (
+            "smtp_messages_delivered_total{domain=\"metrics-a.org\"}",
+            2.0,
+        ),
+        (
+            "smtp_messages_delivered_total{domain=\"metrics-b.org\"}",
+            1.0,
+        ),
+        (
+            "smtp_messages_deferred_total{domain=\"metrics-b.org\"}",
+            1.0,
+        ),
+        ("smtp_messages_bounced_total{domain=\"metrics-c.org\"}", 1.0),
+        ("queue_messages{status=\"in_flight\"}", 0.0),
+    ] {
+        assert_eq!(value(&metrics, series), Some(expected), "{series}");
+    }
+    assert!(value(&metrics, "auth_success_total{mechanism=\"plain\"}").unwrap() >= 1.0);
+    assert!(value(&metrics, "auth_failure_total{mechanism=\"xoauth2\"}").unwrap() >= 1.0);
+    assert_eq!(
+        value(&metrics, "queue_messages{status=\"due\"}").unwrap()
+            + value(&metrics, "queue_messages{status=\"scheduled\"}").unwrap(),
+        1.0
+    );
+    assert!(value(&metrics, "queue_oldest_message_age_seconds").is_some());
+    assert!(
+        value(&metrics, "store_data_write_time_count").unwrap() >= 1.0,
+        "{metrics}"
+    );
+
+    // Domains past the limit are folded into a single overflow series
+    let deferred = metrics
+        .lines()
+        .filter(|line| line.starts_with("smtp_messages_deferred_total{"))
+        .count();
+    assert!(deferred <= 101, "{deferred} deferred series");
+    assert!(value(&metrics, "smtp_messages_deferred_total{domain=\"_other\"}").unwrap() >= 50.0);
+}
+
+fn record(typ: EventType, keys: Vec<(Key, Value)>) {
+    Collector::record_metric(typ, typ.id(), &keys);
+}
+
+fn value(metrics: &str, series: &str) -> Option<f64> {
+    metrics.lines().find_map(|line| {
+        line.strip_prefix(series)
+            .and_then(|value| value.trim().parse().ok())
+    })
+}
@@ -8,7 +8,7 @@ use std::path::PathBuf;
 
 use common::{
     config::server::{Listeners, ServerProtocol},
-    ipc::{QueueEvent, ReportingEvent},
+    ipc::{HousekeeperEvent, QueueEvent, ReportingEvent},
     manager::boot::build_ipc,
     Core, Data, Inner, Server,
 };
@@ -22,10 +22,13 @@ use utils::config::Config;
 
 use crate::AssertConfig;
 
+pub mod acme;
 pub mod config;
 pub mod inbound;
 pub mod lookup;
 pub mod management;
+pub mod metrics;
+pub mod otel;
 pub mod outbound;
 pub mod queue;
 pub mod reporting;
@@ -94,6 +97,7 @@ pub struct TestSMTP {
     pub temp_dir: Option<TempDir>,
     pub queue_receiver: QueueReceiver,
     pub report_receiver: ReportReceiver,
+    pub housekeeper_rx: mpsc::Receiver<HousekeeperEvent>,
 }
 
 const CONFIG: &str = r#"
@@ -159,6 +163,7 @@ impl TestSMTP {
             report_receiver: ReportReceiver {
                 report_rx: ipc_rxs.report_rx.take().unwrap(),
             },
+            housekeeper_rx: ipc_rxs.housekeeper_rx.take().unwrap(),
             server: Server {
                 core: shared_core.load_full(),
                 inner: Inner {
@@ -0,0 +1,220 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::sync::Arc;
+
+use common::{
+    config::telemetry::OtelSampler,
+    telemetry::tracers::otel::{parse_traceparent, SpanAssembler},
+};
+use opentelemetry::{
+    trace::{SpanId, SpanKind, TraceId},
+    InstrumentationLibrary,
+};
+use opentelemetry_sdk::{
+    export::trace::{SpanData, SpanExporter},
+    testing::trace::InMemorySpanExporter,
+};
+use trc::{
+    DeliveryEvent, Event, EventDetails, EventType, Key, QueueEvent, SmtpEvent, StoreEvent, Value,
+};
+
+const SESSION_ID: u64 = 1000;
+const ATTEMPT_ID: u64 = 2000;
+const QUEUE_ID: u64 = 42;
+
+#[tokio::test]
+async fn otel_span_hierarchy() {
+    let mut spans = SpanAssembler::new(
+        InstrumentationLibrary::builder("stalwart-mail").build(),
+        OtelSampler {
+            ratio: 1.0,
+            always_on_error: true,
+        },
+    );
+
+    // Inbound SMTP transaction
+    let session = event(
+        EventType::Smtp(SmtpEvent::ConnectionStart),
+        None,
+        100,
+        vec![(Key::SpanId, Value::UInt(SESSION_ID))],
+    );
+    for (typ, keys) in [
+        (EventType::Smtp(SmtpEvent::Ehlo), vec![]),
+        (EventType::Smtp(SmtpEvent::MailFrom), vec![]),
+        (EventType::Smtp(SmtpEvent::RcptTo), vec![]),
+        (
+            EventType::Store(StoreEvent::DataWrite),
+            vec![(Key::Elapsed, Value::Duration(3))],
+        ),
+        (
+            EventType::Queue(QueueEvent::QueueMessage),
+            vec![
+                (Key::QueueId, Value::UInt(QUEUE_ID)),
+                (Key::Expires, Value::Timestamp(10_000)),
+            ],
+        ),
+    ] {
+        spans.ingest(event(typ, Some(&session), 101, span_keys(SESSION_ID, keys)));
+    }
+
+    // Delivery attempt started while the SMTP session is still open
+    let attempt = event(
+        EventType::Delivery(DeliveryEvent::AttemptStart),
+        None,
+        102,
+        vec![
+            (Key::SpanId, Value::UInt(ATTEMPT_ID)),
+            (Key::QueueId, Value::UInt(QUEUE_ID)),
+        ],
+    );
+    for typ in [
+        DeliveryEvent::Connect,
+        DeliveryEvent::MailFrom,
+        DeliveryEvent::RcptTo,
+        DeliveryEvent::Delivered,
+    ] {
+        spans.ingest(event(
+            EventType::Delivery(typ),
+            Some(&attempt),
+            103,
+            span_keys(ATTEMPT_ID, vec![]),
+        ));
+    }
+    spans.ingest(event(
+        EventType::Delivery(DeliveryEvent::AttemptEnd),
+        Some(&attempt),
+        104,
+        span_keys(ATTEMPT_ID, vec![]),
+    ));
+    spans.ingest(event(
+        EventType::Smtp(SmtpEvent::ConnectionEnd),
+        Some(&session),
+        105,
+        span_keys(SESSION_ID, vec![]),
+    ));
+
+    // Export through the in-memory exporter
+    let mut exporter = InMemorySpanExporter::default();
+    exporter.export(spans.take_pending()).await.unwrap();
+    let finished = exporter.get_finished_spans().unwrap();
+    assert_eq!(finished.len(), 11);
+
+    let trace_id = TraceId::from(SESSION_ID as u128);
+    assert!(finished
+        .iter()
+        .all(|span| span.span_context.trace_id() == trace_id));
+
+    // Session span is the root, protocol stages and store writes are its children
+    let session_span = find_span(&finished, EventType::Smtp(SmtpEvent::ConnectionStart));
+    assert_eq!(session_span.parent_span_id, SpanId::INVALID);
+    assert_eq!(session_span.span_kind, SpanKind::Server);
+    assert_eq!(
+        session_span.span_context.span_id(),
+        SpanId::from(SESSION_ID)
+    );
+    for typ in [
+        EventType::Smtp(SmtpEvent::Ehlo),
+        EventType::Smtp(SmtpEvent::MailFrom),
+        EventType::Smtp(SmtpEvent::RcptTo),
+        EventType::Store(StoreEvent::DataWrite),
+        EventType::Queue(QueueEvent::QueueMessage),
+    ] {
+        assert_eq!(
+            find_span(&finished, typ).parent_span_id,
+            session_span.span_context.span_id(),
+            "{typ:?}"
+        );
+    }
+
+    // Delivery attempt hangs from the queue enqueue span
+    let queue_span = find_span(&finished, EventType::Queue(QueueEvent::QueueMessage));
+    let attempt_span = find_span(&finished, EventType::Delivery(DeliveryEvent::AttemptStart));
+    assert_eq!(
+        attempt_span.parent_span_id,
+        queue_span.span_context.span_id()
+    );
+    assert_eq!(attempt_span.span_kind, SpanKind::Client);
+    for typ in [
+        DeliveryEvent::Connect,
+        DeliveryEvent::MailFrom,
+        DeliveryEvent::RcptTo,
+        DeliveryEvent::Delivered,
+    ] {
+        assert_eq!(
+            find_span(&finished, EventType::Delivery(typ)).parent_span_id,
+            attempt_span.span_context.span_id(),
+            "{typ:?}"
+        );
+    }
+}
+
+#[test]
+fn otel_sampling() {
+    let never = OtelSampler {
+        ratio: 0.0,
+        always_on_error: true,
+    };
+    let half = OtelSampler {
+        ratio: 0.5,
+        always_on_error: false,
+    };
+    assert!(!never.should_sample(TraceId::from(1u128), false));
+    assert!(never.should_sample(TraceId::from(1u128), true));
+    let sampled = (1..=10_000u128)
+        .filter(|id| half.should_sample(TraceId::from(*id), false))
+        .count();
+    assert!((4_000..6_000).contains(&sampled), "{sampled}");
+
+    // W3C trace context
+    let (trace_id, span_id, flags) =
+        parse_traceparent("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01").unwrap();
+    assert_eq!(
+        trace_id,
+        TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap()
+    );
+    assert_eq!(span_id, SpanId::from_hex("00f067aa0ba902b7").unwrap());
+    assert!(flags.is_sampled());
+    for invalid in [
+        "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+        "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+        "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+        "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+    ] {
+        assert!(parse_traceparent(invalid).is_none(), "{invalid}");
+    }
+}
+
+fn event(
+    typ: EventType,
+    span: Option<&Arc<Event<EventDetails>>>,
+    timestamp: u64,
+    keys: Vec<(Key, Value)>,
+) -> Arc<Event<EventDetails>> {
+    Arc::new(Event::with_keys(
+        EventDetails {
+            typ,
+            timestamp,
+            level: typ.level(),
+            span: span.cloned(),
+        },
+        keys,
+    ))
+}
+
+fn span_keys(span_id: u64, mut keys: Vec<(Key, Value)>) -> Vec<(Key, Value)> {
+    keys.insert(0, (Key::SpanId, Value::UInt(span_id)));
+    keys
+}
+
+fn find_span(spans: &[SpanData], typ: EventType) -> &SpanData {
+    spans
+        .iter()
+        .find(|span| span.name == typ.name())
+        .unwrap_or_else(|| panic!("Missing span {typ:?}"))
+}
@@ -8,7 +8,9 @@ use std::time::{Duration, Instant};
 
 use common::config::server::ServerProtocol;
 use mail_auth::MX;
-use smtp_proto::{MAIL_REQUIRETLS, MAIL_RET_HDRS, MAIL_SMTPUTF8, RCPT_NOTIFY_NEVER};
+use smtp_proto::{
+    MAIL_REQUIRETLS, MAIL_RET_HDRS, MAIL_SMTPUTF8, RCPT_NOTIFY_NEVER, RCPT_NOTIFY_SUCCESS,
+};
 
 use crate::smtp::{
     inbound::{TestMessage, TestQueueEvent},
@@ -83,7 +85,7 @@ async fn extensions() {
     session
         .send_message(
             "john@test.org",
-            &["<bill@foobar.org> NOTIFY=SUCCESS,FAILURE"],
+            &["<bill@foobar.org> NOTIFY=SUCCESS,FAILURE ORCPT=rfc822;Bill+2BDSN@foobar.org"],
             "test:no_dkim",
             "250",
         )
@@ -95,20 +97,13 @@ async fn extensions() {
         .try_deliver(core.clone())
         .await;
 
-    local
-        .queue_receiver
-        .expect_message()
-        .await
-        .read_lines(&local.queue_receiver)
-        .await
-        .assert_contains("<bill@foobar.org> (delivered to")
-        .assert_contains("Final-Recipient: rfc822;bill@foobar.org")
-        .assert_contains("Action: delivered");
+    // The remote host supports DSN, so success notifications are relayed
     local.queue_receiver.read_event().await.assert_reload();
-    remote
-        .queue_receiver
-        .expect_message()
-        .await
+    let message = remote.queue_receiver.expect_message().await;
+    let rcpt = message.recipients.last().unwrap();
+    assert_eq!(rcpt.flags & RCPT_NOTIFY_SUCCESS, RCPT_NOTIFY_SUCCESS);
+    assert_eq!(rcpt.orcpt, Some("Bill+DSN@foobar.org".to_string()));
+    message
         .read_lines(&remote.queue_receiver)
         .await
         .assert_contains("using TLSv1.3 with cipher");
@@ -0,0 +1,200 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use smtp::queue::Status;
+use store::parking_lot::Mutex;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::TcpListener,
+};
+
+use crate::smtp::{inbound::TestQueueEvent, session::TestSession, TestSMTP};
+
+const LOCAL: &str = r#"
+[queue.outbound]
+next-hop = [{if = "rcpt_domain = 'foobar.org'", then = "'mock'"},
+            {else = false}]
+
+[queue.outbound.connection]
+idle-timeout = "10s"
+max-messages = 25
+
+[queue.outbound.tls]
+starttls = "disable"
+mta-sts = "disable"
+dane = "disable"
+
+[session.rcpt]
+relay = true
+
+[session.data.limits]
+messages = 100
+
+[queue.schedule]
+retry = "1h"
+notify = "1h"
+expire = "1h"
+
+[remote.mock]
+address = mx.foobar.org
+port = 9926
+protocol = 'smtp'
+
+[remote.mock.tls]
+implicit = false
+"#;
+
+#[derive(Default)]
+struct MockServer {
+    connections: AtomicUsize,
+    delivered: Mutex<Vec<String>>,
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn connection_pool() {
+    // Enable logging
+    crate::enable_logging();
+
+    // Start mock server
+    let remote = Arc::new(MockServer::default());
+    spawn_mock_smtp_server(remote.clone()).await;
+
+    let mut local = TestSMTP::new("smtp_connection_pool", LOCAL).await;
+
+    // Add mock DNS entries
+    let core = local.build_smtp();
+    core.core.smtp.resolvers.dns.ipv4_add(
+        "mx.foobar.org",
+        vec!["127.0.0.1".parse().unwrap()],
+        Instant::now() + Duration::from_secs(60),
+    );
+
+    let mut session = local.new_session();
+    session.data.remote_ip_str = "10.0.0.1".to_string();
+    session.eval_session_params().await;
+    session.ehlo("mx.test.org").await;
+
+    // Deliver 50 messages to the same host, the 30th one is answered with a 421
+    let mut closing_id = 0;
+    for num in 1..=50 {
+        let rcpt = format!("<user{num}@foobar.org>");
+        if num != 30 {
+            session
+                .send_message("john@test.org", &[rcpt.as_str()], "test:no_dkim", "250")
+                .await;
+        } else {
+            session
+                .send_message(
+                    "john@test.org",
+                    &[rcpt.as_str(), "<closing@foobar.org>"],
+                    "test:no_dkim",
+                    "250",
+                )
+                .await;
+            closing_id = local.queue_receiver.last_queued_message().await.queue_id;
+        }
+        local
+            .queue_receiver
+            .expect_message_then_deliver()
+            .await
+            .try_deliver(core.clone())
+            .await;
+        local.queue_receiver.read_event().await.assert_reload();
+    }
+
+    // Sessions are reused up to 25 times and closed after a 421 reply
+    assert_eq!(remote.connections.load(Ordering::Relaxed), 3);
+    assert_eq!(
+        remote.delivered.lock().clone(),
+        (1..=50)
+            .map(|num| format!("user{num}@foobar.org"))
+            .collect::<Vec<_>>()
+    );
+
+    // Only the message with a deferred recipient remains in the queue
+    let messages = local.queue_receiver.read_queued_messages().await;
+    assert_eq!(messages.len(), 1);
+    let message = &messages[0];
+    assert_eq!(message.queue_id, closing_id);
+    for rcpt in &message.recipients {
+        if rcpt.address == "user30@foobar.org" {
+            assert!(matches!(rcpt.status, Status::Completed(_)));
+        } else {
+            assert!(matches!(rcpt.status, Status::TemporaryFailure(_)));
+        }
+    }
+    local.queue_receiver.clear_queue(&core).await;
+}
+
+async fn spawn_mock_smtp_server(server: Arc<MockServer>) {
+    let listener = TcpListener::bind("127.0.0.1:9926")
+        .await
+        .unwrap_or_else(|e| panic!("Failed to bind mock SMTP server to 127.0.0.1:9926: {e}"));
+
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            server.connections.fetch_add(1, Ordering::Relaxed);
+            let server = server.clone();
+            tokio::spawn(async move {
+                let (reader, mut writer) = stream.into_split();
+                let mut lines = BufReader::new(reader).lines();
+                let mut rcpts = Vec::new();
+                let mut in_data = false;
+                writer
+                    .write_all(b"220 mx.foobar.org ESMTP\r\n")
+                    .await
+                    .unwrap();
+
+                while let Ok(Some(line)) = lines.next_line().await {
+                    let response: &[u8] = if in_data {
+                        if line != "." {
+                            continue;
+                        }
+                        in_data = false;
+                        server.delivered.lock().append(&mut rcpts);
+                        b"250 2.0.0 Message queued\r\n"
+                    } else if line.starts_with("EHLO") {
+                        b"250-mx.foobar.org\r\n250 8BITMIME\r\n"
+                    } else if line.starts_with("MAIL FROM") {
+                        b"250 2.1.0 OK\r\n"
+                    } else if let Some(rcpt) = line.strip_prefix("RCPT TO:<") {
+                        let rcpt = rcpt.split_once('>').unwrap().0;
+                        if rcpt == "closing@foobar.org" {
+                            b"421 4.3.0 Closing connection\r\n"
+                        } else {
+                            rcpts.push(rcpt.to_string());
+                            b"250 2.1.5 OK\r\n"
+                        }
+                    } else if line == "DATA" {
+                        in_data = true;
+                        b"354 Start mail input\r\n"
+                    } else if line == "RSET" {
+                        rcpts.clear();
+                        b"250 2.0.0 OK\r\n"
+                    } else if line == "QUIT" {
+                        let _ = writer.write_all(b"221 2.0.0 Bye\r\n").await;
+                        break;
+                    } else {
+                        b"500 5.5.1 Unknown command\r\n"
+                    };
+
+                    if writer.write_all(response).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+}
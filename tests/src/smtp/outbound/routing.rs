@@ -0,0 +1,234 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use store::parking_lot::Mutex;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::TcpListener,
+};
+
+use crate::smtp::{inbound::TestQueueEvent, session::TestSession, TestSMTP};
+
+const LOCAL: &str = r#"
+[queue.outbound]
+next-hop = [{if = "ends_with(rcpt_domain, '.partner.org') && size < 1048576", then = "'partner'"},
+            {if = "matches('^example[.](net|com)$', rcpt_domain)", then = "'relay'"},
+            {if = "rcpt_domain = 'bulk.org' && sender_domain = 'test.org'", then = "'bulk'"},
+            {else = false}]
+hostname = [{if = "rcpt_domain = 'mail.partner.org'", then = "'partner.test.org'"},
+            {if = "mx = 'smarthost.bulk.org'", then = "'bulk.test.org'"},
+            {else = "'mx.test.org'"}]
+
+[queue.outbound.tls]
+starttls = "disable"
+mta-sts = "disable"
+dane = "disable"
+
+[session.rcpt]
+relay = true
+
+[queue.schedule]
+retry = "1h"
+notify = "1h"
+expire = "1h"
+
+[remote.partner]
+address = smarthost.partner.org
+port = 9927
+protocol = 'smtp'
+
+[remote.partner.auth]
+username = "partner-user"
+secret = "partner-secret"
+
+[remote.partner.tls]
+implicit = false
+
+[remote.relay]
+address = smarthost.example.net
+port = 9927
+protocol = 'smtp'
+
+[remote.relay.tls]
+implicit = false
+
+[remote.bulk]
+address = smarthost.bulk.org
+port = 9927
+protocol = 'smtp'
+
+[remote.bulk.auth]
+username = "bulk-user"
+secret = "bulk-secret"
+
+[remote.bulk.tls]
+implicit = false
+"#;
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct MockSession {
+    rcpts: Vec<String>,
+    ehlo: String,
+    auth: Option<String>,
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn routing_rules() {
+    // Enable logging
+    crate::enable_logging();
+
+    // Start mock smarthost
+    let sessions = Arc::new(Mutex::new(Vec::new()));
+    spawn_mock_smarthost(sessions.clone()).await;
+
+    let mut local = TestSMTP::new("smtp_routing_rules", LOCAL).await;
+
+    // Add mock DNS entries
+    let core = local.build_smtp();
+    for host in [
+        "smarthost.partner.org",
+        "smarthost.example.net",
+        "smarthost.bulk.org",
+    ] {
+        core.core.smtp.resolvers.dns.ipv4_add(
+            host,
+            vec!["127.0.0.1".parse().unwrap()],
+            Instant::now() + Duration::from_secs(60),
+        );
+    }
+
+    let mut session = local.new_session();
+    session.data.remote_ip_str = "10.0.0.1".to_string();
+    session.eval_session_params().await;
+    session.ehlo("mx.test.org").await;
+
+    // Each recipient domain is routed through a different relay
+    session
+        .send_message(
+            "john@test.org",
+            &[
+                "<jane@mail.partner.org>",
+                "<bill@example.net>",
+                "<mike@bulk.org>",
+            ],
+            "test:no_dkim",
+            "250",
+        )
+        .await;
+    local
+        .queue_receiver
+        .expect_message_then_deliver()
+        .await
+        .try_deliver(core.clone())
+        .await;
+    // Wait for the three domains to be delivered
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    local.queue_receiver.read_event().await.assert_reload();
+    local.queue_receiver.assert_queue_is_empty().await;
+
+    // Make sure the right credentials and EHLO names were used
+    let mut sessions = std::mem::take(&mut *sessions.lock());
+    sessions.sort();
+    assert_eq!(
+        sessions,
+        vec![
+            MockSession {
+                rcpts: vec!["bill@example.net".to_string()],
+                ehlo: "mx.test.org".to_string(),
+                auth: None,
+            },
+            MockSession {
+                rcpts: vec!["jane@mail.partner.org".to_string()],
+                ehlo: "partner.test.org".to_string(),
+                auth: Some("\0partner-user\0partner-secret".to_string()),
+            },
+            MockSession {
+                rcpts: vec!["mike@bulk.org".to_string()],
+                ehlo: "bulk.test.org".to_string(),
+                auth: Some("\0bulk-user\0bulk-secret".to_string()),
+            },
+        ]
+    );
+}
+
+async fn spawn_mock_smarthost(sessions: Arc<Mutex<Vec<MockSession>>>) {
+    let listener = TcpListener::bind("127.0.0.1:9927")
+        .await
+        .unwrap_or_else(|e| panic!("Failed to bind mock SMTP server to 127.0.0.1:9927: {e}"));
+
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let sessions = sessions.clone();
+            tokio::spawn(async move {
+                let (reader, mut writer) = stream.into_split();
+                let mut lines = BufReader::new(reader).lines();
+                let mut session = MockSession {
+                    rcpts: Vec::new(),
+                    ehlo: String::new(),
+                    auth: None,
+                };
+                let mut in_data = false;
+                writer
+                    .write_all(b"220 smarthost.test.org ESMTP\r\n")
+                    .await
+                    .unwrap();
+
+                while let Ok(Some(line)) = lines.next_line().await {
+                    let response: &[u8] = if in_data {
+                        if line != "." {
+                            continue;
+                        }
+                        in_data = false;
+                        sessions.lock().push(std::mem::replace(
+                            &mut session,
+                            MockSession {
+                                rcpts: Vec::new(),
+                                ehlo: String::new(),
+                                auth: None,
+                            },
+                        ));
+                        b"250 2.0.0 Message queued\r\n"
+                    } else if let Some(ehlo) = line.strip_prefix("EHLO ") {
+                        session.ehlo = ehlo.to_string();
+                        b"250-smarthost.test.org\r\n250-AUTH PLAIN\r\n250 8BITMIME\r\n"
+                    } else if let Some(credentials) = line.strip_prefix("AUTH PLAIN ") {
+                        session.auth = STANDARD
+                            .decode(credentials)
+                            .ok()
+                            .and_then(|bytes| String::from_utf8(bytes).ok());
+                        b"235 2.7.0 Authentication succeeded\r\n"
+                    } else if line.starts_with("MAIL FROM") {
+                        b"250 2.1.0 OK\r\n"
+                    } else if let Some(rcpt) = line.strip_prefix("RCPT TO:<") {
+                        session
+                            .rcpts
+                            .push(rcpt.split_once('>').unwrap().0.to_string());
+                        b"250 2.1.5 OK\r\n"
+                    } else if line == "DATA" {
+                        in_data = true;
+                        b"354 Start mail input\r\n"
+                    } else if line == "QUIT" {
+                        let _ = writer.write_all(b"221 2.0.0 Bye\r\n").await;
+                        break;
+                    } else {
+                        b"500 5.5.1 Unknown command\r\n"
+                    };
+
+                    if writer.write_all(response).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+}
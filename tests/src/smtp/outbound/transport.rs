@@ -0,0 +1,318 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use smtp::queue::Status;
+use store::parking_lot::Mutex;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::TcpListener,
+};
+
+use crate::smtp::{
+    inbound::TestMessage,
+    session::{TestSession, VerifyResponse},
+    TestSMTP,
+};
+
+const LOCAL: &str = r#"
+[queue.outbound]
+transport = [{if = "rcpt = 'offline@foobar.org'", then = "'offline'"},
+             {if = "rcpt_domain = 'foobar.org' && rcpt != 'mike@foobar.org'", then = "'mailstore'"},
+             {else = false}]
+next-hop = [{if = "rcpt_domain = 'foobar.org'", then = "'relay'"},
+            {else = false}]
+
+[queue.outbound.tls]
+starttls = "disable"
+mta-sts = "disable"
+dane = "disable"
+
+[session.rcpt]
+relay = true
+
+[session.extensions]
+dsn = true
+
+[queue.schedule]
+retry = "1h"
+notify = "1h"
+expire = "1h"
+
+[remote.mailstore]
+address = mailstore.foobar.org
+port = 9928
+protocol = 'lmtp'
+
+[remote.mailstore.tls]
+implicit = false
+
+[remote.relay]
+address = relay.foobar.org
+port = 9929
+protocol = 'smtp'
+
+[remote.relay.tls]
+implicit = false
+
+[remote.offline]
+address = offline.foobar.org
+port = 9930
+protocol = 'lmtp'
+fallback = 'reject'
+
+[remote.offline.tls]
+implicit = false
+"#;
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct MockSession {
+    protocol: &'static str,
+    rcpts: Vec<String>,
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn transport_map() {
+    // Enable logging
+    crate::enable_logging();
+
+    // Start mock LMTP and SMTP downstreams
+    let sessions = Arc::new(Mutex::new(Vec::new()));
+    spawn_mock_downstream(9928, true, sessions.clone()).await;
+    spawn_mock_downstream(9929, false, sessions.clone()).await;
+
+    let mut local = TestSMTP::new("smtp_transport_map", LOCAL).await;
+
+    // Add mock DNS entries
+    let core = local.build_smtp();
+    for host in [
+        "mailstore.foobar.org",
+        "relay.foobar.org",
+        "offline.foobar.org",
+    ] {
+        core.core.smtp.resolvers.dns.ipv4_add(
+            host,
+            vec!["127.0.0.1".parse().unwrap()],
+            Instant::now() + Duration::from_secs(60),
+        );
+    }
+
+    let mut session = local.new_session();
+    session.data.remote_ip_str = "10.0.0.1".to_string();
+    session.eval_session_params().await;
+    session.ehlo("mx.test.org").await;
+    session
+        .send_message(
+            "john@test.org",
+            &[
+                "<bill@foobar.org> NOTIFY=FAILURE",
+                "<jane@foobar.org> NOTIFY=FAILURE",
+                "<tempfail@foobar.org> NOTIFY=FAILURE",
+                "<permfail@foobar.org> NOTIFY=FAILURE",
+                "<offline@foobar.org> NOTIFY=FAILURE",
+                "<mike@foobar.org> NOTIFY=FAILURE",
+            ],
+            "test:no_dkim",
+            "250",
+        )
+        .await;
+
+    // The selected transport is recorded in the queue entry
+    let message = local.queue_receiver.expect_message().await;
+    let mut routes = message
+        .recipients
+        .iter()
+        .map(|rcpt| {
+            (
+                rcpt.address_lcase.as_str(),
+                message.transports[rcpt.domain_idx].as_str(),
+            )
+        })
+        .collect::<Vec<_>>();
+    routes.sort_unstable();
+    assert_eq!(
+        routes,
+        vec![
+            ("bill@foobar.org", "mailstore"),
+            ("jane@foobar.org", "mailstore"),
+            ("mike@foobar.org", ""),
+            ("offline@foobar.org", "offline"),
+            ("permfail@foobar.org", "mailstore"),
+            ("tempfail@foobar.org", "mailstore"),
+        ]
+    );
+    assert_eq!(message.domains.len(), 3);
+    assert!(message.domains.iter().all(|d| d.domain == "foobar.org"));
+
+    local
+        .queue_receiver
+        .delivery_attempt(message.queue_id)
+        .await
+        .try_deliver(core.clone())
+        .await;
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    // Each transport received only its own recipients
+    let mut sessions = std::mem::take(&mut *sessions.lock());
+    sessions.sort();
+    assert_eq!(
+        sessions,
+        vec![
+            MockSession {
+                protocol: "lmtp",
+                rcpts: vec![
+                    "bill@foobar.org".to_string(),
+                    "jane@foobar.org".to_string(),
+                    "permfail@foobar.org".to_string(),
+                    "tempfail@foobar.org".to_string(),
+                ],
+            },
+            MockSession {
+                protocol: "smtp",
+                rcpts: vec!["mike@foobar.org".to_string()],
+            },
+        ]
+    );
+
+    // Per-recipient LMTP replies are tracked individually
+    let messages = local.queue_receiver.read_queued_messages().await;
+    assert_eq!(messages.len(), 2);
+    let (dsn, retry): (Vec<_>, Vec<_>) = messages
+        .into_iter()
+        .partition(|message| message.return_path.is_empty());
+    let retry = retry.into_iter().next().unwrap();
+    for rcpt in &retry.recipients {
+        match rcpt.address_lcase.as_str() {
+            "bill@foobar.org" | "jane@foobar.org" | "mike@foobar.org" => {
+                assert!(matches!(rcpt.status, Status::Completed(_)), "{rcpt:?}")
+            }
+            "tempfail@foobar.org" => {
+                assert!(
+                    matches!(rcpt.status, Status::TemporaryFailure(_)),
+                    "{rcpt:?}"
+                )
+            }
+            "permfail@foobar.org" | "offline@foobar.org" => {
+                assert!(
+                    matches!(rcpt.status, Status::PermanentFailure(_)),
+                    "{rcpt:?}"
+                )
+            }
+            rcpt => panic!("Unexpected recipient {rcpt}"),
+        }
+    }
+
+    // The unavailable downstream is rejected rather than queued
+    let offline_idx = retry
+        .transports
+        .iter()
+        .position(|t| t == "offline")
+        .unwrap();
+    assert!(matches!(
+        retry.domains[offline_idx].status,
+        Status::PermanentFailure(_)
+    ));
+    let mailstore_idx = retry
+        .transports
+        .iter()
+        .position(|t| t == "mailstore")
+        .unwrap();
+    assert!(matches!(
+        retry.domains[mailstore_idx].status,
+        Status::TemporaryFailure(_) | Status::Scheduled
+    ));
+
+    dsn.into_iter()
+        .next()
+        .unwrap()
+        .read_lines(&local.queue_receiver)
+        .await
+        .assert_contains("<permfail@foobar.org> (host 'mailstore.foobar.org' rejected")
+        .assert_contains("<offline@foobar.org> (connection to")
+        .assert_not_contains("<tempfail@foobar.org>")
+        .assert_not_contains("<mike@foobar.org>");
+}
+
+async fn spawn_mock_downstream(port: u16, is_lmtp: bool, sessions: Arc<Mutex<Vec<MockSession>>>) {
+    let listener = TcpListener::bind(("127.0.0.1", port))
+        .await
+        .unwrap_or_else(|e| panic!("Failed to bind mock server to 127.0.0.1:{port}: {e}"));
+
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let sessions = sessions.clone();
+            tokio::spawn(async move {
+                let (reader, mut writer) = stream.into_split();
+                let mut lines = BufReader::new(reader).lines();
+                let mut rcpts = Vec::new();
+                let mut in_data = false;
+                writer
+                    .write_all(b"220 downstream.foobar.org ready\r\n")
+                    .await
+                    .unwrap();
+
+                while let Ok(Some(line)) = lines.next_line().await {
+                    let response = if in_data {
+                        if line != "." {
+                            continue;
+                        }
+                        in_data = false;
+
+                        // LMTP replies once for each accepted recipient
+                        let response = if is_lmtp {
+                            rcpts
+                                .iter()
+                                .map(|rcpt: &String| {
+                                    if rcpt.starts_with("tempfail@") {
+                                        "451 4.2.0 Mailbox temporarily unavailable\r\n"
+                                    } else if rcpt.starts_with("permfail@") {
+                                        "550 5.1.1 Mailbox does not exist\r\n"
+                                    } else {
+                                        "250 2.0.0 Delivered\r\n"
+                                    }
+                                })
+                                .collect::<String>()
+                        } else {
+                            "250 2.0.0 Message queued\r\n".to_string()
+                        };
+                        sessions.lock().push(MockSession {
+                            protocol: if is_lmtp { "lmtp" } else { "smtp" },
+                            rcpts: std::mem::take(&mut rcpts),
+                        });
+                        response
+                    } else if line.starts_with("LHLO ") || line.starts_with("EHLO ") {
+                        "250-downstream.foobar.org\r\n250 8BITMIME\r\n".to_string()
+                    } else if line.starts_with("MAIL FROM") {
+                        "250 2.1.0 OK\r\n".to_string()
+                    } else if let Some(rcpt) = line.strip_prefix("RCPT TO:<") {
+                        rcpts.push(rcpt.split_once('>').unwrap().0.to_string());
+                        "250 2.1.5 OK\r\n".to_string()
+                    } else if line == "DATA" {
+                        in_data = true;
+                        "354 Start mail input\r\n".to_string()
+                    } else if line == "RSET" {
+                        rcpts.clear();
+                        "250 2.0.0 OK\r\n".to_string()
+                    } else if line == "QUIT" {
+                        let _ = writer.write_all(b"221 2.0.0 Bye\r\n").await;
+                        break;
+                    } else {
+                        "500 5.5.1 Unknown command\r\n".to_string()
+                    };
+
+                    if writer.write_all(response.as_bytes()).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+}
@@ -4,15 +4,26 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::{fs, path::PathBuf, time::SystemTime};
+use std::{
+    fs,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
 
-use smtp_proto::{Response, RCPT_NOTIFY_DELAY, RCPT_NOTIFY_FAILURE, RCPT_NOTIFY_SUCCESS};
+use smtp_proto::{
+    Response, MAIL_RET_FULL, RCPT_NOTIFY_DELAY, RCPT_NOTIFY_FAILURE, RCPT_NOTIFY_SUCCESS,
+};
 use store::write::now;
 use utils::BlobHash;
 
-use crate::smtp::{inbound::sign::SIGNATURES, QueueReceiver, TestSMTP};
+use crate::smtp::{
+    inbound::{sign::SIGNATURES, TestMessage},
+    session::VerifyResponse,
+    QueueReceiver, TestSMTP,
+};
 use smtp::queue::{
     dsn::SendDsn, Domain, Error, ErrorDetails, HostResponse, Message, Recipient, Schedule, Status,
+    RCPT_DSN_RELAYED,
 };
 
 const CONFIG: &str = r#"
@@ -89,6 +100,10 @@ async fn generate_dsn() {
         priority: 0,
         blob_hash: BlobHash::from(dsn_original.as_bytes()),
         quota_keys: vec![],
+        retry_state: vec![],
+        hold_until: 0,
+        deliver_by: 0,
+        transports: vec![],
     };
 
     // Load config
@@ -160,6 +175,124 @@ async fn generate_dsn() {
     assert_eq!(queue.len(), 4);
 }
 
+#[tokio::test]
+async fn dsn_parameters() {
+    // Enable logging
+    crate::enable_logging();
+
+    let mut local = TestSMTP::new("smtp_dsn_params_test", CONFIG.to_string() + SIGNATURES).await;
+    let core = local.build_smtp();
+    let qr = &mut local.queue_receiver;
+
+    let contents = "From: sender@foobar.org\r\nSubject: Test\r\n\r\nOriginal message body\r\n";
+    let blob_hash = BlobHash::from(contents.as_bytes());
+    qr.blob_store
+        .put_blob(blob_hash.as_slice(), contents.as_bytes())
+        .await
+        .unwrap();
+
+    let flags = RCPT_NOTIFY_FAILURE | RCPT_NOTIFY_DELAY | RCPT_NOTIFY_SUCCESS;
+    let mut message = Message {
+        size: contents.len(),
+        queue_id: 0,
+        span_id: 0,
+        created: now(),
+        return_path: "sender@foobar.org".to_string(),
+        return_path_lcase: "sender@foobar.org".to_string(),
+        return_path_domain: "foobar.org".to_string(),
+        recipients: vec![
+            Recipient {
+                domain_idx: 0,
+                address: "jorg@example.org".to_string(),
+                address_lcase: "jorg@example.org".to_string(),
+                status: Status::Completed(HostResponse {
+                    hostname: "mx.example.org".to_string(),
+                    response: Response {
+                        code: 250,
+                        esc: [2, 1, 5],
+                        message: "OK".to_string(),
+                    },
+                }),
+                flags: flags | RCPT_DSN_RELAYED,
+                orcpt: "jörg@example.org".to_string().into(),
+            },
+            Recipient {
+                domain_idx: 1,
+                address: "jane@example.net".to_string(),
+                address_lcase: "jane@example.net".to_string(),
+                status: Status::Scheduled,
+                flags,
+                orcpt: None,
+            },
+        ],
+        domains: vec![
+            Domain {
+                domain: "example.org".to_string(),
+                retry: Schedule::now(),
+                notify: Schedule::later(Duration::from_secs(3600)),
+                expires: now() + 3600,
+                status: Status::Completed(()),
+            },
+            Domain {
+                domain: "example.net".to_string(),
+                retry: Schedule::now(),
+                notify: Schedule::now(),
+                expires: now() - 1,
+                status: Status::PermanentFailure(Error::ConnectionError(ErrorDetails {
+                    entity: "mx.example.net".to_string(),
+                    details: "Connection timeout".to_string(),
+                })),
+            },
+        ],
+        flags: MAIL_RET_FULL,
+        env_id: "QQ 314=159".to_string().into(),
+        priority: 0,
+        blob_hash,
+        quota_keys: vec![],
+        retry_state: vec![],
+        hold_until: 0,
+        deliver_by: 0,
+        transports: vec![],
+    };
+
+    // Relayed recipients, xtext encoded parameters, expired domains and
+    // the full original message are reported
+    core.send_dsn(&mut message).await;
+    qr.expect_message()
+        .await
+        .read_lines(qr)
+        .await
+        .assert_contains("Original-Envelope-Id: QQ+20314+3D159")
+        .assert_contains("Original-Recipient: rfc822;j+C3+B6rg@example.org")
+        .assert_contains("Action: relayed")
+        .assert_contains("relayed to 'mx.example.org'")
+        .assert_contains("Final-Recipient: rfc822;jane@example.net")
+        .assert_contains("Action: failed")
+        .assert_contains("Status: 4.4.7")
+        .assert_contains("Content-Type: message/rfc822")
+        .assert_contains("Original message body");
+
+    // Only headers are returned when there are no failures
+    message.recipients[0].flags = flags;
+    message.recipients[1].flags = 0;
+    message.recipients[0].status = Status::Completed(HostResponse {
+        hostname: "mx.example.org".to_string(),
+        response: Response {
+            code: 250,
+            esc: [2, 1, 5],
+            message: "OK".to_string(),
+        },
+    });
+    core.send_dsn(&mut message).await;
+    qr.expect_message()
+        .await
+        .read_lines(qr)
+        .await
+        .assert_contains("Action: delivered")
+        .assert_contains("Content-Type: text/rfc822-headers")
+        .assert_not_contains("Original message body");
+}
+
 impl QueueReceiver {
     async fn compare_dsn(&self, message: Message, test: &str) {
         let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
@@ -8,7 +8,7 @@ use std::time::Duration;
 
 use mail_auth::hickory_resolver::proto::op::ResponseCode;
 
-use smtp::queue::{spool::SmtpSpool, Domain, Message, Schedule, Status};
+use smtp::queue::{retry::RetryPolicy, spool::SmtpSpool, Domain, Message, Schedule, Status};
 use store::write::now;
 
 use crate::smtp::TestSMTP;
@@ -105,23 +105,26 @@ fn delivery_events() {
         }
     }
 
-    message.domain_mut("a").set_status(
+    message.set_status(
+        message.domain_idx("a"),
         mail_auth::Error::DnsRecordNotFound(ResponseCode::BADCOOKIE),
-        &[],
+        &RetryPolicy::default(),
     );
     assert_eq!(message.next_event().unwrap(), message.domain("b").retry.due);
     assert_eq!(message.next_delivery_event(), message.domain("b").retry.due);
 
-    message.domain_mut("b").set_status(
+    message.set_status(
+        message.domain_idx("b"),
         mail_auth::Error::DnsRecordNotFound(ResponseCode::BADCOOKIE),
-        &[],
+        &RetryPolicy::default(),
     );
     assert_eq!(message.next_event().unwrap(), message.domain("c").retry.due);
     assert_eq!(message.next_delivery_event(), message.domain("c").retry.due);
 
-    message.domain_mut("c").set_status(
+    message.set_status(
+        message.domain_idx("c"),
         mail_auth::Error::DnsRecordNotFound(ResponseCode::BADCOOKIE),
-        &[],
+        &RetryPolicy::default(),
     );
     assert!(message.next_event().is_none());
 }
@@ -141,11 +144,15 @@ pub fn new_message(queue_id: u64) -> Message {
         env_id: None,
         priority: 0,
         quota_keys: vec![],
+        retry_state: vec![],
+        hold_until: 0,
+        deliver_by: 0,
+        transports: vec![],
         blob_hash: Default::default(),
     }
 }
 
-fn domain(domain: &str, retry: u64, notify: u64, expires: u64) -> Domain {
+pub fn domain(domain: &str, retry: u64, notify: u64, expires: u64) -> Domain {
     Domain {
         domain: domain.to_string(),
         retry: Schedule::later(Duration::from_secs(retry)),
@@ -158,6 +165,7 @@ fn domain(domain: &str, retry: u64, notify: u64, expires: u64) -> Domain {
 pub trait TestMessage {
     fn domain(&self, name: &str) -> &Domain;
     fn domain_mut(&mut self, name: &str) -> &mut Domain;
+    fn domain_idx(&self, name: &str) -> usize;
 }
 
 impl TestMessage for Message {
@@ -171,4 +179,8 @@ impl TestMessage for Message {
     fn domain_mut(&mut self, name: &str) -> &mut Domain {
         self.domains.iter_mut().find(|d| d.domain == name).unwrap()
     }
+
+    fn domain_idx(&self, name: &str) -> usize {
+        self.domains.iter().position(|d| d.domain == name).unwrap()
+    }
 }
@@ -8,3 +8,4 @@ pub mod concurrent;
 pub mod dsn;
 pub mod manager;
 pub mod retry;
+pub mod strategy;
@@ -12,7 +12,7 @@ use crate::smtp::{
     TestSMTP,
 };
 use common::ipc::QueueEvent;
-use smtp::queue::{spool::SmtpSpool, DeliveryAttempt};
+use smtp::queue::{spool::SmtpSpool, DeliveryAttempt, Status};
 use store::write::now;
 
 const CONFIG: &str = r#"
@@ -166,6 +166,19 @@ async fn queue_retry() {
     assert!([59, 60].contains(&(message.next_delivery_event() - now_)));
     assert!([3599, 3600].contains(&(message.domains.first().unwrap().expires - now_)));
     assert!([54059, 54060].contains(&(message.domains.first().unwrap().notify.due - now_)));
+    assert!([59, 60].contains(&(message.hold_until - now_)));
+    assert!([3599, 3600].contains(&(message.deliver_by - now_)));
+
+    // Held messages are not attempted before their release time
+    qr.delivery_attempt(message.queue_id)
+        .await
+        .try_deliver(core.clone())
+        .await;
+    qr.read_event().await.assert_reload();
+    let message = core.read_message(message.queue_id).await.unwrap();
+    assert_eq!(message.domains.first().unwrap().retry.inner, 0);
+    assert_eq!(message.domains.first().unwrap().status, Status::Scheduled);
+    assert!([59, 60].contains(&(qr.message_due(message.queue_id).await - now_)));
 
     // Test DELIVERBY (NOTIFY)
     session
@@ -178,4 +191,54 @@ async fn queue_retry() {
         .await;
     let schedule = qr.expect_message().await;
     assert!([3599, 3600].contains(&(schedule.domains.first().unwrap().notify.due - now())));
+    qr.clear_queue(&core).await;
+
+    // Test DELIVERBY (RETURN) deadline expiring
+    session
+        .send_message(
+            "<bill@foobar.org> BY=2;R",
+            &["jane@_dns_error.org"],
+            "test:no_dkim",
+            "250",
+        )
+        .await;
+    let attempt = qr.expect_message_then_deliver().await;
+    let mut dsn = Vec::new();
+    attempt.try_deliver(core.clone()).await;
+    loop {
+        match qr.try_read_event().await {
+            Some(QueueEvent::Reload) => {}
+            Some(QueueEvent::OnHold(_)) => unreachable!(),
+            None | Some(QueueEvent::Stop) => break,
+        }
+
+        let now = now();
+        let events = core.next_event().await;
+        if events.is_empty() {
+            break;
+        }
+        for event in events {
+            if event.due > now {
+                tokio::time::sleep(Duration::from_secs(event.due - now)).await;
+            }
+
+            let message = core.read_message(event.queue_id).await.unwrap();
+            if message.return_path.is_empty() {
+                message.clone().remove(&core, event.due).await;
+                dsn.push(message);
+            } else {
+                DeliveryAttempt::new(event).try_deliver(core.clone()).await;
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        }
+    }
+    qr.assert_queue_is_empty().await;
+    assert_eq!(dsn.len(), 1);
+    dsn.pop()
+        .unwrap()
+        .read_lines(qr)
+        .await
+        .assert_contains("Final-Recipient: rfc822;jane@_dns_error.org")
+        .assert_contains("Action: failed")
+        .assert_contains("Status: 5.4.7");
 }
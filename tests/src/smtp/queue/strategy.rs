@@ -0,0 +1,196 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use crate::smtp::{
+    inbound::{TestMessage, TestQueueEvent},
+    queue::manager::{domain, new_message, TestMessage as _},
+    session::{TestSession, VerifyResponse},
+    TestSMTP,
+};
+use common::{config::smtp::queue::RetryStrategy, ipc::QueueEvent};
+use smtp::queue::{retry::RetryPolicy, spool::SmtpSpool, DeliveryAttempt, RetryState, Status};
+use store::write::now;
+
+const CONFIG: &str = r#"
+[session.ehlo]
+reject-non-fqdn = false
+
+[session.rcpt]
+relay = true
+
+[queue.schedule]
+retry = [{if = "rcpt_domain = '_dns_error.org'", then = "'backoff'"},
+         {if = "rcpt_domain = '_dns_error.net'", then = "'fixed'"},
+         {else = "[1h]"}]
+notify = [{if = "rcpt_domain = '_dns_error.org'", then = "[3s]"},
+          {else = "1d"}]
+expire = [{if = "rcpt_domain = '_dns_error.org'", then = "9s"},
+          {else = "2d"}]
+
+[queue.retry-strategy.backoff]
+type = "exponential"
+initial = "1s"
+factor = 2
+max = "4s"
+jitter = 0
+
+[queue.retry-strategy.fixed]
+type = "fixed"
+intervals = ["10m", "20m"]
+"#;
+
+#[tokio::test]
+async fn queue_retry_strategy() {
+    // Enable logging
+    crate::enable_logging();
+
+    let mut local = TestSMTP::new("smtp_queue_retry_strategy_test", CONFIG).await;
+
+    let core = local.build_smtp();
+    let mut session = local.new_session();
+    let qr = &mut local.queue_receiver;
+
+    session.data.remote_ip_str = "10.0.0.1".to_string();
+    session.eval_session_params().await;
+    session.ehlo("mx.test.org").await;
+
+    // Each domain is scheduled using the strategy selected by its route
+    session
+        .send_message(
+            "john@test.org",
+            &["jane@_dns_error.net", "bill@_dns_error.com"],
+            "test:no_dkim",
+            "250",
+        )
+        .await;
+    let attempt = qr.expect_message_then_deliver().await;
+    let queue_id = attempt.event.queue_id;
+    attempt.try_deliver(core.clone()).await;
+    qr.read_event().await.assert_reload();
+    let message = core.read_message(queue_id).await.unwrap();
+    for (domain, strategy, delay) in [
+        ("_dns_error.net", "fixed", 600),
+        ("_dns_error.com", "", 3600),
+    ] {
+        let domain_idx = message.domain_idx(domain);
+        assert!(matches!(
+            message.domains[domain_idx].status,
+            Status::TemporaryFailure(_)
+        ));
+        assert_eq!(
+            message.retry_state[domain_idx],
+            RetryState {
+                strategy: strategy.to_string(),
+                attempt: 1,
+            }
+        );
+        let due = message.domains[domain_idx].retry.due - now();
+        assert!([delay - 1, delay].contains(&due), "{domain}: {due}");
+    }
+    qr.clear_queue(&core).await;
+
+    // Expect a delayed DSN after the third attempt, followed by a
+    // final failed DSN once the message expires.
+    session
+        .send_message(
+            "john@test.org",
+            &["jane@_dns_error.org"],
+            "test:no_dkim",
+            "250",
+        )
+        .await;
+    let attempt = qr.expect_message_then_deliver().await;
+    let mut dsn = Vec::new();
+    let mut retries = Vec::new();
+    attempt.try_deliver(core.clone()).await;
+    loop {
+        match qr.try_read_event().await {
+            Some(QueueEvent::Reload) => {}
+            Some(QueueEvent::OnHold(_)) => unreachable!(),
+            None | Some(QueueEvent::Stop) => break,
+        }
+
+        let now = now();
+        let events = core.next_event().await;
+        if events.is_empty() {
+            break;
+        }
+        for event in events {
+            if event.due > now {
+                tokio::time::sleep(Duration::from_secs(event.due - now)).await;
+            }
+
+            let message = core.read_message(event.queue_id).await.unwrap();
+            if message.return_path.is_empty() {
+                message.clone().remove(&core, event.due).await;
+                dsn.push((retries.len(), message));
+            } else {
+                retries.push(event.due - now);
+                DeliveryAttempt::new(event).try_deliver(core.clone()).await;
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        }
+    }
+    qr.assert_queue_is_empty().await;
+    // Backoff is capped at 4s and the last attempt happens at expiry
+    assert_eq!(retries.len(), 4);
+    assert_eq!(retries[..3], [1, 2, 4]);
+    assert_eq!(dsn.len(), 2);
+    let mut dsn = dsn.into_iter();
+
+    let (num_retries, message) = dsn.next().unwrap();
+    assert_eq!(num_retries, 2);
+    message
+        .read_lines(qr)
+        .await
+        .assert_contains("Final-Recipient: rfc822;jane@_dns_error.org")
+        .assert_contains("Action: delayed");
+
+    let (num_retries, message) = dsn.next().unwrap();
+    assert_eq!(num_retries, 4);
+    message
+        .read_lines(qr)
+        .await
+        .assert_contains("Final-Recipient: rfc822;jane@_dns_error.org")
+        .assert_contains("Action: failed");
+
+    // Switching strategies restarts the schedule, while messages queued
+    // before retry states were stored resume from their retry count
+    let mut message = new_message(0);
+    message.domains.push(domain("foobar.org", 0, 0, 0));
+    message.domains[0].retry.inner = 3;
+    let backoff = RetryPolicy {
+        id: "backoff".to_string(),
+        strategy: RetryStrategy::Exponential {
+            initial: Duration::from_secs(60),
+            factor: 2.0,
+            max: Duration::from_secs(3600),
+            jitter: 0.0,
+        },
+    };
+    let inline = RetryPolicy {
+        id: String::new(),
+        strategy: RetryStrategy::Fixed(vec![
+            Duration::from_secs(10),
+            Duration::from_secs(20),
+            Duration::from_secs(30),
+            Duration::from_secs(40),
+        ]),
+    };
+    for (policy, delay, attempt) in [
+        (&inline, 40, 4),
+        (&backoff, 60, 1),
+        (&backoff, 120, 2),
+        (&inline, 10, 1),
+    ] {
+        message.retry(0, policy);
+        assert_eq!(message.domains[0].retry.due - now(), delay);
+        assert_eq!(message.retry_state[0].attempt, attempt);
+    }
+    assert_eq!(message.domains[0].retry.inner, 7);
+}
@@ -362,6 +362,8 @@ impl TestServerInstance for ServerInstance {
             limiter: ConcurrencyLimiter::new(100),
             shutdown_rx,
             proxy_networks: vec![],
+            proxy_required: false,
+            allow_cram_md5: false,
             span_id_gen: Arc::new(SnowflakeIdGenerator::new()),
         }
     }
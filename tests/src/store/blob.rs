@@ -9,6 +9,7 @@ use store::{
     write::{blob::BlobQuota, now, BatchBuilder, BlobOp},
     BlobClass, BlobStore, Serialize, Stores,
 };
+use tokio::io::AsyncReadExt;
 use utils::{config::Config, BlobHash};
 
 use crate::store::{TempDir, CONFIG};
@@ -431,12 +432,17 @@ async fn test_store(store: BlobStore) {
         .unwrap(),
         std::str::from_utf8(&DATA[11..57]).unwrap()
     );
+    assert_eq!(
+        read_blob_stream(&store, hash.as_slice()).await.unwrap(),
+        DATA
+    );
     assert!(store.delete_blob(hash.as_slice()).await.unwrap());
     assert!(store
         .get_blob(hash.as_slice(), 0..usize::MAX)
         .await
         .unwrap()
         .is_none());
+    assert!(read_blob_stream(&store, hash.as_slice()).await.is_none());
 
     // Test large blob
     let mut data = Vec::with_capacity(50 * 1024 * 1024);
@@ -470,10 +476,26 @@ async fn test_store(store: BlobStore) {
         .unwrap(),
         std::str::from_utf8(&data[3000111..4000999]).unwrap()
     );
+    assert!(read_blob_stream(&store, hash.as_slice()).await.unwrap() == data);
     assert!(store.delete_blob(hash.as_slice()).await.unwrap());
     assert!(store
         .get_blob(hash.as_slice(), 0..usize::MAX)
         .await
         .unwrap()
         .is_none());
+    assert!(read_blob_stream(&store, hash.as_slice()).await.is_none());
+}
+
+async fn read_blob_stream(store: &BlobStore, key: &[u8]) -> Option<Vec<u8>> {
+    let mut stream = store.get_blob_stream(key).await.unwrap()?;
+    let mut data = Vec::new();
+    let mut buf = vec![0u8; 8192];
+    loop {
+        let bytes_read = stream.read(&mut buf).await.unwrap();
+        if bytes_read == 0 {
+            break;
+        }
+        data.extend_from_slice(&buf[..bytes_read]);
+    }
+    Some(data)
 }
@@ -0,0 +1,93 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use store::{
+    backend::circuit_breaker::{CircuitBreaker, CircuitState},
+    write::{BatchBuilder, ValueClass},
+    Store, Stores, ValueKey,
+};
+use utils::config::Config;
+
+use crate::{store::TempDir, AssertConfig};
+
+const CONFIG: &str = r#"
+[store."sqlite"]
+type = "sqlite"
+path = "{TMP}/sqlite.db"
+
+[store."sqlite".circuit-breaker]
+enable = true
+error-threshold = 3
+open-duration = "200ms"
+"#;
+
+#[tokio::test]
+pub async fn circuit_breaker() {
+    // Opens after reaching the error threshold
+    let breaker = CircuitBreaker::new(3, Duration::from_millis(200));
+    assert_eq!(breaker.state(), CircuitState::Closed);
+    for _ in 0..2 {
+        assert!(breaker.try_acquire());
+        assert!(!breaker.record_failure());
+    }
+    assert_eq!(breaker.state(), CircuitState::Closed);
+    assert!(breaker.try_acquire());
+    assert!(breaker.record_failure());
+    assert_eq!(breaker.state(), CircuitState::Open);
+    assert!(!breaker.try_acquire());
+
+    // A failed probe opens the circuit again
+    tokio::time::sleep(Duration::from_millis(250)).await;
+    assert_eq!(breaker.state(), CircuitState::HalfOpen);
+    assert!(breaker.try_acquire());
+    assert!(!breaker.try_acquire());
+    breaker.record_failure();
+    assert_eq!(breaker.state(), CircuitState::Open);
+    assert!(!breaker.try_acquire());
+
+    // A successful probe closes it
+    tokio::time::sleep(Duration::from_millis(250)).await;
+    assert!(breaker.try_acquire());
+    breaker.record_success();
+    assert_eq!(breaker.state(), CircuitState::Closed);
+    assert!(breaker.try_acquire());
+
+    // Successes reset the failure count
+    breaker.record_failure();
+    breaker.record_failure();
+    breaker.record_success();
+    assert!(!breaker.record_failure());
+    assert_eq!(breaker.state(), CircuitState::Closed);
+
+    // Stores are wrapped when enabled in the configuration
+    let temp_dir = TempDir::new("circuit_breaker_tests", true);
+    let mut config = Config::new(CONFIG.replace("{TMP}", &temp_dir.path.to_string_lossy()))
+        .unwrap()
+        .assert_no_errors();
+    let stores = Stores::parse_all(&mut config).await;
+    let store = stores.stores.get("sqlite").unwrap().clone();
+    assert!(matches!(store, Store::CircuitBreaker(_)));
+    assert!(matches!(store.backend(), Store::SQLite(_)));
+    assert_eq!(store.circuit_state(), Some(CircuitState::Closed));
+    assert!(store.is_sql());
+    assert_eq!(store.id(), "sqlite");
+
+    let mut batch = BatchBuilder::new();
+    batch.set(ValueClass::Config(b"breaker".to_vec()), b"closed".to_vec());
+    store.write(batch.build()).await.unwrap();
+    assert_eq!(
+        store
+            .get_value::<String>(ValueKey::from(ValueClass::Config(b"breaker".to_vec())))
+            .await
+            .unwrap(),
+        Some("closed".to_string())
+    );
+    assert_eq!(store.circuit_state(), Some(CircuitState::Closed));
+
+    temp_dir.delete();
+}
@@ -0,0 +1,140 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use store::{
+    dispatch::coordination::LockFence,
+    write::{BatchBuilder, LookupClass, ValueClass},
+    Store,
+};
+
+pub async fn test(db: Store) {
+    println!("Running coordination tests...");
+
+    // The loser waits until the holder releases the lock
+    let (node_a, node_b) = (db.clone(), db.clone());
+    let guard = node_a
+        .acquire_lock("contended", Duration::from_secs(2))
+        .await
+        .unwrap();
+    assert!(guard.is_held());
+    assert!(node_b
+        .try_acquire_lock("contended", Duration::from_secs(2))
+        .await
+        .unwrap()
+        .is_none());
+    let waiter = tokio::spawn(async move {
+        node_b
+            .acquire_lock("contended", Duration::from_secs(2))
+            .await
+            .unwrap()
+    });
+
+    // Heartbeats keep the lease alive past its TTL
+    tokio::time::sleep(Duration::from_secs(3)).await;
+    assert!(guard.is_held());
+    assert!(!waiter.is_finished());
+    let token = guard.fence().token();
+    drop(guard);
+    let guard = tokio::time::timeout(Duration::from_secs(5), waiter)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(guard.fence().token() > token);
+    drop(guard);
+
+    // Leases expire once the holder stops renewing them
+    let (node_a, node_b) = (db.clone(), db.clone());
+    let guard = node_a
+        .acquire_lock("fenced", Duration::from_secs(1))
+        .await
+        .unwrap();
+    let stale_fence = guard.fence().clone();
+    guard.simulate_crash();
+    let guard = tokio::time::timeout(
+        Duration::from_secs(10),
+        node_b.acquire_lock("fenced", Duration::from_secs(60)),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+    assert!(guard.fence().token() > stale_fence.token());
+
+    // Writes from the stale holder are rejected
+    let err = db
+        .write(fenced_write(&stale_fence, b"stale").build())
+        .await
+        .unwrap_err();
+    assert!(err.is_assertion_failure());
+    db.write(fenced_write(guard.fence(), b"current").build())
+        .await
+        .unwrap();
+    drop(guard);
+
+    // Singleton tasks run on a single node
+    let runs = [Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0))];
+    let tasks = runs
+        .iter()
+        .map(|runs| {
+            let runs = runs.clone();
+            db.clone()
+                .run_singleton("singleton", Duration::from_millis(100), move |_| {
+                    let runs = runs.clone();
+                    async move {
+                        runs.fetch_add(1, Ordering::SeqCst);
+                        Ok(())
+                    }
+                })
+        })
+        .collect::<Vec<_>>();
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    let (runs_a, runs_b) = (
+        runs[0].load(Ordering::SeqCst),
+        runs[1].load(Ordering::SeqCst),
+    );
+    assert!(
+        (runs_a > 0) != (runs_b > 0),
+        "singleton ran on both nodes: {runs_a} {runs_b}"
+    );
+    for task in tasks {
+        task.abort();
+    }
+
+    // Wait for locks to be released
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let mut batch = BatchBuilder::new();
+    for key in [
+        "lock:contended",
+        "fence:contended",
+        "lock:fenced",
+        "fence:fenced",
+        "lock:singleton",
+        "fence:singleton",
+        "coordination:value",
+    ] {
+        batch.clear(ValueClass::Lookup(LookupClass::Key(
+            key.as_bytes().to_vec(),
+        )));
+    }
+    db.write(batch.build()).await.unwrap();
+}
+
+fn fenced_write(fence: &LockFence, value: &[u8]) -> BatchBuilder {
+    let mut batch = BatchBuilder::new();
+    fence.assert(&mut batch);
+    batch.set(
+        ValueClass::Lookup(LookupClass::Key(b"coordination:value".to_vec())),
+        value.to_vec(),
+    );
+    batch
+}
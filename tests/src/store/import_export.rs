@@ -5,7 +5,13 @@
  */
 
 use ahash::AHashSet;
-use common::{manager::backup::BackupParams, Core};
+use common::{
+    manager::{
+        backup::{BackupManifest, BackupParams},
+        restore::RestoreParams,
+    },
+    Core,
+};
 use jmap_proto::types::{collection::Collection, property::Property};
 use store::{
     rand,
@@ -15,7 +21,7 @@ use store::{
     },
     *,
 };
-use utils::BlobHash;
+use utils::{BlobHash, BLOB_HASH_LEN};
 
 use crate::store::TempDir;
 
@@ -44,107 +50,25 @@ pub async fn test(db: Store) {
             .unwrap();
         batch.set(ValueClass::Blob(BlobOp::Commit { hash }), vec![]);
     }
+    let unlinked_data = random_bytes(512);
+    let unlinked_hash = BlobHash::from(unlinked_data.as_slice());
+    core.storage
+        .blob
+        .put_blob(unlinked_hash.as_ref(), &unlinked_data)
+        .await
+        .unwrap();
+    batch.set(
+        ValueClass::Blob(BlobOp::Commit {
+            hash: unlinked_hash.clone(),
+        }),
+        vec![],
+    );
     db.write(batch.build()).await.unwrap();
 
     // Create account data
     println!("Creating account data...");
     for account_id in 0u32..10u32 {
-        let mut batch = BatchBuilder::new();
-        batch.with_account_id(account_id);
-
-        // Create properties of different sizes
-        for collection in [0, 1, 2, 3] {
-            batch.with_collection(collection);
-
-            for document_id in [0, 10, 20, 30, 40] {
-                batch.create_document_with_id(document_id);
-
-                if collection == u8::from(Collection::Mailbox) {
-                    batch
-                        .set(
-                            ValueClass::Property(Property::Value.into()),
-                            random_bytes(10),
-                        )
-                        .add(
-                            ValueClass::Property(Property::EmailIds.into()),
-                            rand::random(),
-                        );
-                }
-
-                for (idx, value_size) in [16, 128, 1024, 2056, 102400].into_iter().enumerate() {
-                    batch.set(ValueClass::Property(idx as u8), random_bytes(value_size));
-                }
-
-                for value_size in [1, 4, 7, 8, 9, 16] {
-                    batch.set(
-                        ValueClass::FtsIndex(BitmapHash::new(random_bytes(value_size))),
-                        random_bytes(value_size * 2),
-                    );
-                }
-
-                for grant_account_id in 0u32..10u32 {
-                    if account_id != grant_account_id {
-                        batch.set(
-                            ValueClass::Acl(grant_account_id),
-                            vec![account_id as u8, grant_account_id as u8, document_id as u8],
-                        );
-                    }
-                }
-
-                for hash in &blob_hashes {
-                    batch.set(
-                        ValueClass::Blob(BlobOp::Link { hash: hash.clone() }),
-                        vec![],
-                    );
-                }
-
-                batch.ops.push(Operation::ChangeId {
-                    change_id: document_id as u64 + account_id as u64 + collection as u64,
-                });
-
-                batch.ops.push(Operation::Log {
-                    set: MaybeDynamicValue::Static(vec![
-                        account_id as u8,
-                        collection,
-                        document_id as u8,
-                    ]),
-                });
-
-                for field in 0..5 {
-                    batch.ops.push(Operation::Bitmap {
-                        class: BitmapClass::Tag {
-                            field,
-                            value: TagValue::Id(MaybeDynamicId::Static(rand::random())),
-                        },
-                        set: true,
-                    });
-
-                    batch.ops.push(Operation::Bitmap {
-                        class: BitmapClass::Tag {
-                            field,
-                            value: TagValue::Text(random_bytes(field as usize + 2)),
-                        },
-                        set: true,
-                    });
-
-                    batch.ops.push(Operation::Bitmap {
-                        class: BitmapClass::Text {
-                            field,
-                            token: BitmapHash::new(random_bytes(field as usize + 2)),
-                        },
-                        set: true,
-                    });
-
-                    batch.ops.push(Operation::Index {
-                        field,
-                        key: random_bytes(field as usize + 2),
-                        set: true,
-                    });
-                }
-            }
-        }
-
-        db.write(batch.build()).await.unwrap();
+        write_account_data(&db, account_id, &blob_hashes).await;
     }
 
     // Create queue, config and lookup data
@@ -243,16 +167,119 @@ pub async fn test(db: Store) {
 
     // Import store
     println!("Importing store...");
-    core.restore(temp_dir.path.clone()).await;
+    core.restore(RestoreParams::new(temp_dir.path.clone()))
+        .await;
 
     // Verify hash
     print!("Verifying store hash...");
     snapshot.assert_is_eq(&Snapshot::new(&db).await);
     println!(" GREAT SUCCESS!");
 
+    // Modify account 1, add account 10 and remove the unlinked blob
+    println!("Modifying store...");
+    let new_data = random_bytes(2048);
+    let new_hash = BlobHash::from(new_data.as_slice());
+    core.storage
+        .blob
+        .put_blob(new_hash.as_ref(), &new_data)
+        .await
+        .unwrap();
+    let mut batch = BatchBuilder::new();
+    batch
+        .with_account_id(1)
+        .with_collection(0)
+        .update_document(10)
+        .set(ValueClass::Property(0), random_bytes(64))
+        .clear(ValueClass::Property(1))
+        .set(ValueClass::Acl(5), vec![1, 5, 10])
+        .set(
+            ValueClass::Blob(BlobOp::Link {
+                hash: new_hash.clone(),
+            }),
+            vec![],
+        )
+        .set(
+            ValueClass::Blob(BlobOp::Commit {
+                hash: new_hash.clone(),
+            }),
+            vec![],
+        )
+        .clear(ValueClass::Blob(BlobOp::Commit {
+            hash: unlinked_hash.clone(),
+        }))
+        .set(
+            ValueClass::Config(b"backup.test".to_vec()),
+            random_bytes(10),
+        );
+    batch.ops.push(Operation::Bitmap {
+        class: BitmapClass::Tag {
+            field: 0,
+            value: TagValue::Id(MaybeDynamicId::Static(rand::random())),
+        },
+        set: true,
+    });
+    batch.ops.push(Operation::ChangeId { change_id: 1000 });
+    batch.ops.push(Operation::Log {
+        set: MaybeDynamicValue::Static(vec![1, 0, 10]),
+    });
+    db.write(batch.build()).await.unwrap();
+    core.storage
+        .blob
+        .delete_blob(unlinked_hash.as_ref())
+        .await
+        .unwrap();
+    write_account_data(&db, 10, &blob_hashes).await;
+    let snapshot = Snapshot::new(&db).await;
+
+    // Incremental backups only include the accounts that changed
+    println!("Exporting incremental backup...");
+    let incremental_dir = TempDir::new("art_vandelay_incremental_tests", true);
+    core.backup(BackupParams::new(incremental_dir.path.clone()).with_since(temp_dir.path.clone()))
+        .await;
+    assert_eq!(
+        BackupManifest::read(&incremental_dir.path)
+            .unwrap()
+            .accounts,
+        Some(vec![1, 10, u32::MAX])
+    );
+
+    // Restore the full backup followed by the incremental one
+    println!("Restoring backup chain...");
+    db.destroy().await;
+    db.assert_is_empty(db.clone().into()).await;
+    core.restore(RestoreParams::new(incremental_dir.path.clone()))
+        .await;
+    snapshot.assert_is_eq(&Snapshot::new(&db).await);
+
+    // Restore a single account
+    println!("Restoring single account...");
+    db.destroy().await;
+    core.restore(RestoreParams::new(incremental_dir.path.clone()).with_account_id(1))
+        .await;
+    let restored = Snapshot::new(&db).await;
+    snapshot.account(1).assert_is_eq(&restored.account(1));
+    assert!(restored.account(2).keys.is_empty());
+    assert_eq!(
+        core.storage
+            .blob
+            .get_blob(new_hash.as_ref(), 0..usize::MAX)
+            .await
+            .unwrap(),
+        Some(new_data)
+    );
+
+    // Corrupted sections are detected
+    let manifest = BackupManifest::read(&temp_dir.path).unwrap();
+    manifest.verify(&temp_dir.path).unwrap();
+    let mut property = std::fs::read(temp_dir.path.join("property")).unwrap();
+    property.push(0);
+    std::fs::write(temp_dir.path.join("property"), property).unwrap();
+    assert!(manifest.verify(&temp_dir.path).is_err());
+
     // Destroy store
     db.destroy().await;
     temp_dir.delete();
+    incremental_dir.delete();
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -260,7 +287,7 @@ struct Snapshot {
     keys: AHashSet<KeyValue>,
 }
 
-#[derive(Debug, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 struct KeyValue {
     subspace: u8,
     key: Vec<u8>,
@@ -324,6 +351,30 @@ impl Snapshot {
         Snapshot { keys }
     }
 
+    fn account(&self, account_id: u32) -> Self {
+        let account_id = account_id.to_be_bytes();
+
+        Snapshot {
+            keys: self
+                .keys
+                .iter()
+                .filter(|kv| {
+                    let offset = match kv.subspace {
+                        SUBSPACE_ACL => U32_LEN,
+                        SUBSPACE_BLOB_LINK => BLOB_HASH_LEN,
+                        SUBSPACE_COUNTER if kv.key.len() != U32_LEN * 2 + 2 => return false,
+                        SUBSPACE_COUNTER | SUBSPACE_PROPERTY | SUBSPACE_FTS_INDEX
+                        | SUBSPACE_BITMAP_ID | SUBSPACE_BITMAP_TAG | SUBSPACE_BITMAP_TEXT
+                        | SUBSPACE_INDEXES | SUBSPACE_LOGS => 0,
+                        _ => return false,
+                    };
+                    kv.key.get(offset..offset + U32_LEN) == Some(&account_id[..])
+                })
+                .cloned()
+                .collect(),
+        }
+    }
+
     fn assert_is_eq(&self, other: &Self) {
         let mut is_err = false;
         for key in &self.keys {
@@ -353,6 +404,105 @@ impl Snapshot {
     }
 }
 
+async fn write_account_data(db: &Store, account_id: u32, blob_hashes: &[BlobHash]) {
+    let mut batch = BatchBuilder::new();
+    batch.with_account_id(account_id);
+
+    // Create properties of different sizes
+    for collection in [0, 1, 2, 3] {
+        batch.with_collection(collection);
+
+        for document_id in [0, 10, 20, 30, 40] {
+            batch.create_document_with_id(document_id);
+
+            if collection == u8::from(Collection::Mailbox) {
+                batch
+                    .set(
+                        ValueClass::Property(Property::Value.into()),
+                        random_bytes(10),
+                    )
+                    .add(
+                        ValueClass::Property(Property::EmailIds.into()),
+                        rand::random(),
+                    );
+            }
+
+            for (idx, value_size) in [16, 128, 1024, 2056, 102400].into_iter().enumerate() {
+                batch.set(ValueClass::Property(idx as u8), random_bytes(value_size));
+            }
+
+            for value_size in [1, 4, 7, 8, 9, 16] {
+                batch.set(
+                    ValueClass::FtsIndex(BitmapHash::new(random_bytes(value_size))),
+                    random_bytes(value_size * 2),
+                );
+            }
+
+            for grant_account_id in 0u32..10u32 {
+                if account_id != grant_account_id {
+                    batch.set(
+                        ValueClass::Acl(grant_account_id),
+                        vec![account_id as u8, grant_account_id as u8, document_id as u8],
+                    );
+                }
+            }
+
+            for hash in blob_hashes {
+                batch.set(
+                    ValueClass::Blob(BlobOp::Link { hash: hash.clone() }),
+                    vec![],
+                );
+            }
+
+            batch.ops.push(Operation::ChangeId {
+                change_id: document_id as u64 + account_id as u64 + collection as u64,
+            });
+
+            batch.ops.push(Operation::Log {
+                set: MaybeDynamicValue::Static(vec![
+                    account_id as u8,
+                    collection,
+                    document_id as u8,
+                ]),
+            });
+
+            for field in 0..5 {
+                batch.ops.push(Operation::Bitmap {
+                    class: BitmapClass::Tag {
+                        field,
+                        value: TagValue::Id(MaybeDynamicId::Static(rand::random())),
+                    },
+                    set: true,
+                });
+
+                batch.ops.push(Operation::Bitmap {
+                    class: BitmapClass::Tag {
+                        field,
+                        value: TagValue::Text(random_bytes(field as usize + 2)),
+                    },
+                    set: true,
+                });
+
+                batch.ops.push(Operation::Bitmap {
+                    class: BitmapClass::Text {
+                        field,
+                        token: BitmapHash::new(random_bytes(field as usize + 2)),
+                    },
+                    set: true,
+                });
+
+                batch.ops.push(Operation::Index {
+                    field,
+                    key: random_bytes(field as usize + 2),
+                    set: true,
+                });
+            }
+        }
+    }
+
+    db.write(batch.build()).await.unwrap();
+}
+
 fn random_bytes(len: usize) -> Vec<u8> {
     (0..len).map(|_| rand::random::<u8>()).collect()
 }
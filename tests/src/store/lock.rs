@@ -0,0 +1,153 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use store::{
+    write::{BatchBuilder, LookupClass, ValueClass},
+    Store,
+};
+
+pub async fn test(db: Store) {
+    println!("Running lock tests...");
+
+    // Only one holder at a time
+    let holders = Arc::new(AtomicUsize::new(0));
+    let acquired = Arc::new(AtomicUsize::new(0));
+    let mut tasks = Vec::new();
+    for _ in 0..10 {
+        let db = db.clone();
+        let holders = holders.clone();
+        let acquired = acquired.clone();
+        tasks.push(tokio::spawn(async move {
+            let lock = db
+                .lock(
+                    b"lock:concurrent".to_vec(),
+                    Duration::from_secs(30),
+                    Duration::from_secs(60),
+                )
+                .await
+                .unwrap();
+            assert_eq!(holders.fetch_add(1, Ordering::SeqCst), 0);
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            holders.fetch_sub(1, Ordering::SeqCst);
+            acquired.fetch_add(1, Ordering::SeqCst);
+            drop(lock);
+        }));
+    }
+    for task in tasks {
+        task.await.unwrap();
+    }
+    assert_eq!(acquired.load(Ordering::SeqCst), 10);
+
+    // Locks that are held cannot be acquired before the timeout
+    let lock = db
+        .lock(
+            b"lock:timeout".to_vec(),
+            Duration::from_secs(60),
+            Duration::ZERO,
+        )
+        .await
+        .unwrap();
+    let err = db
+        .lock(
+            b"lock:timeout".to_vec(),
+            Duration::from_secs(60),
+            Duration::from_millis(500),
+        )
+        .await
+        .err()
+        .unwrap();
+    assert!(err.matches(trc::EventType::Store(trc::StoreEvent::LockNotAcquired)));
+
+    // Dropping a lock releases it
+    drop(lock);
+    let lock = db
+        .lock(
+            b"lock:timeout".to_vec(),
+            Duration::from_secs(60),
+            Duration::from_secs(5),
+        )
+        .await
+        .unwrap();
+    drop(lock);
+
+    // Locks that were not released expire after their TTL
+    let lock = db
+        .lock(
+            b"lock:expiry".to_vec(),
+            Duration::from_secs(1),
+            Duration::ZERO,
+        )
+        .await
+        .unwrap();
+    let mut stale_lock = db
+        .lock(
+            b"lock:expiry".to_vec(),
+            Duration::from_secs(1),
+            Duration::from_secs(5),
+        )
+        .await
+        .unwrap();
+    std::mem::forget(lock);
+
+    // Extending a lock keeps it from expiring
+    stale_lock.extend(Duration::from_secs(5)).await.unwrap();
+    tokio::time::sleep(Duration::from_secs(2)).await;
+    assert!(db
+        .lock(
+            b"lock:expiry".to_vec(),
+            Duration::from_secs(1),
+            Duration::from_millis(500),
+        )
+        .await
+        .is_err());
+
+    // Expired locks cannot be extended once acquired by another holder
+    let expires = stale_lock.expires();
+    let lock = db
+        .lock(
+            b"lock:expiry".to_vec(),
+            Duration::from_secs(60),
+            Duration::from_secs(10),
+        )
+        .await
+        .unwrap();
+    assert!(lock.expires() > expires);
+    assert!(stale_lock
+        .extend(Duration::from_secs(60))
+        .await
+        .unwrap_err()
+        .matches(trc::EventType::Store(trc::StoreEvent::LockNotAcquired)));
+
+    // Releasing a stale lock does not release the current one
+    drop(stale_lock);
+    assert!(db
+        .lock(
+            b"lock:expiry".to_vec(),
+            Duration::from_secs(1),
+            Duration::from_millis(500),
+        )
+        .await
+        .is_err());
+    drop(lock);
+
+    // Wait for locks to be released
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let mut batch = BatchBuilder::new();
+    for key in ["lock:concurrent", "lock:timeout", "lock:expiry"] {
+        batch.clear(ValueClass::Lookup(LookupClass::Key(
+            key.as_bytes().to_vec(),
+        )));
+    }
+    db.write(batch.build()).await.unwrap();
+}
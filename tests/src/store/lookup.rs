@@ -4,10 +4,10 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 
-use store::{LookupStore, Stores};
-use utils::config::{Config, Rate};
+use store::{backend::memory::MemoryStore, LookupStore, Stores};
+use utils::config::{Config, Rate, RateWindow};
 
 use crate::{
     store::{TempDir, CONFIG},
@@ -121,3 +121,125 @@ pub async fn lookup_tests() {
         }
     }
 }
+
+#[tokio::test]
+pub async fn sliding_rate_limit_tests() {
+    let rate = Rate {
+        requests: 4,
+        period: Duration::from_secs(2),
+    };
+
+    // Two nodes sharing the same counters
+    let memory = Arc::new(MemoryStore::default());
+    let node_a = LookupStore::Memory(memory.clone());
+    let node_b = LookupStore::Memory(memory);
+
+    // Align to the start of a window
+    wait_for_window_start(&rate).await;
+
+    // Requests are counted across nodes
+    for (num, store) in [&node_a, &node_b, &node_a, &node_b].into_iter().enumerate() {
+        assert!(
+            store
+                .check_rate(b"sliding", &rate, RateWindow::Sliding, false)
+                .await
+                .unwrap()
+                .is_none(),
+            "request {num} was rate limited"
+        );
+    }
+    let retry_after = node_b
+        .check_rate(b"sliding", &rate, RateWindow::Sliding, false)
+        .await
+        .unwrap()
+        .expect("request should be rate limited");
+    assert!((1..=2).contains(&retry_after), "retry_after={retry_after}");
+    assert_eq!(
+        node_a
+            .rate_usage(b"sliding", &rate, RateWindow::Sliding)
+            .await
+            .unwrap(),
+        5
+    );
+
+    // Soft checks do not consume requests
+    assert!(node_a
+        .check_rate(b"sliding", &rate, RateWindow::Sliding, true)
+        .await
+        .unwrap()
+        .is_some());
+    assert_eq!(
+        node_b
+            .rate_usage(b"sliding", &rate, RateWindow::Sliding)
+            .await
+            .unwrap(),
+        5
+    );
+
+    // Halfway through the next window, half of the previous requests still count
+    wait_for_window_start(&rate).await;
+    tokio::time::sleep(Duration::from_millis(1000)).await;
+    let usage = node_a
+        .rate_usage(b"sliding", &rate, RateWindow::Sliding)
+        .await
+        .unwrap();
+    assert!((1..=3).contains(&usage), "usage={usage}");
+    assert!(node_b
+        .check_rate(b"sliding", &rate, RateWindow::Sliding, false)
+        .await
+        .unwrap()
+        .is_none());
+
+    // Previous requests no longer count after two windows
+    tokio::time::sleep(Duration::from_millis(3100)).await;
+    assert_eq!(
+        node_a
+            .rate_usage(b"sliding", &rate, RateWindow::Sliding)
+            .await
+            .unwrap(),
+        0
+    );
+
+    // Concurrent requests never exceed the limit
+    for window in [RateWindow::Fixed, RateWindow::Sliding] {
+        let rate = Rate {
+            requests: 50,
+            period: Duration::from_secs(60),
+        };
+        let key = format!("concurrent-{window:?}");
+        let mut tasks = Vec::new();
+        for num in 0..200 {
+            let store = if num % 2 == 0 {
+                node_a.clone()
+            } else {
+                node_b.clone()
+            };
+            let key = key.clone();
+            let rate = rate.clone();
+            tasks.push(tokio::spawn(async move {
+                store
+                    .check_rate(key.as_bytes(), &rate, window, false)
+                    .await
+                    .unwrap()
+                    .is_none()
+            }));
+        }
+
+        let mut allowed = 0;
+        for task in tasks {
+            if task.await.unwrap() {
+                allowed += 1;
+            }
+        }
+        assert_eq!(allowed, rate.requests, "window={window:?}");
+    }
+}
+
+async fn wait_for_window_start(rate: &Rate) {
+    let period = rate.period.as_millis() as u64;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64;
+    tokio::time::sleep(Duration::from_millis(period - (now % period) + 10)).await;
+}
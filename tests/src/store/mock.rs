@@ -0,0 +1,147 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::sync::Arc;
+
+use store::{
+    backend::mock::MockStore,
+    write::{BitmapClass, Operation, ValueClass, ValueOp},
+    BitmapKey, IterateParams, Store, ValueKey,
+};
+
+#[tokio::test]
+pub async fn mock_store() {
+    let mock = Arc::new(MockStore::new());
+    let store = Store::from(mock.clone());
+    assert_eq!(store.id(), "mock");
+
+    // Writes are recorded in order
+    store
+        .write(MockStore::expected_batch(|batch| {
+            batch
+                .with_account_id(1)
+                .with_collection(0u8)
+                .update_document(2)
+                .set(ValueClass::Property(3), b"hello".to_vec());
+        }))
+        .await
+        .unwrap();
+    store
+        .write(MockStore::expected_batch(|batch| {
+            batch
+                .with_account_id(1)
+                .with_collection(0u8)
+                .update_document(2)
+                .clear(ValueClass::Property(4));
+        }))
+        .await
+        .unwrap();
+    mock.assert_batch_count(2);
+    mock.assert_written_operation(&Operation::Value {
+        class: ValueClass::Property(3),
+        op: ValueOp::Set(b"hello".to_vec().into()),
+    });
+    mock.assert_written_operation(&Operation::Value {
+        class: ValueClass::Property(4),
+        op: ValueOp::Clear,
+    });
+    mock.assert_not_written_operation(&Operation::Value {
+        class: ValueClass::Property(5),
+        op: ValueOp::Clear,
+    });
+    mock.assert_batch(
+        1,
+        &MockStore::expected_batch(|batch| {
+            batch
+                .with_account_id(1)
+                .with_collection(0u8)
+                .update_document(2)
+                .clear(ValueClass::Property(4));
+        }),
+    );
+
+    // Writes are not applied
+    assert_eq!(
+        store.get_value::<String>(property(1, 2, 3)).await.unwrap(),
+        None
+    );
+
+    // Reads are served from stubs
+    mock.expect_get_value(property(1, 2, 3), b"stub".to_vec())
+        .expect_get_value(property(1, 3, 3), b"other".to_vec())
+        .expect_get_value(property(2, 2, 3), b"foreign".to_vec())
+        .expect_counter(property(1, 2, 4), 42);
+    assert_eq!(
+        store.get_value::<String>(property(1, 2, 3)).await.unwrap(),
+        Some("stub".to_string())
+    );
+    assert_eq!(
+        store
+            .get_value_batch(vec![property(1, 3, 3), property(1, 4, 3)])
+            .await
+            .unwrap(),
+        vec![Some(b"other".to_vec()), None]
+    );
+    assert_eq!(store.get_counter(property(1, 2, 4)).await.unwrap(), 42);
+
+    // Ranges only include stubs within the bounds
+    let mut keys = Vec::new();
+    store
+        .iterate(
+            IterateParams::new(property(1, 0, 3), property(1, u32::MAX, 3)).descending(),
+            |_, value| {
+                keys.push(String::from_utf8(value.to_vec()).unwrap());
+                Ok(true)
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(keys, ["other", "stub"]);
+    assert_eq!(
+        store
+            .get_keys_count(property(1, 0, 3), property(1, u32::MAX, 3))
+            .await
+            .unwrap(),
+        2
+    );
+
+    // Bitmaps are stubbed one document at a time
+    for document_id in [1, 5, 9] {
+        mock.expect_get_value(
+            BitmapKey {
+                account_id: 1,
+                collection: 0,
+                class: BitmapClass::DocumentIds,
+                document_id,
+            },
+            vec![],
+        );
+    }
+    let bitmap = store
+        .get_bitmap(BitmapKey {
+            account_id: 1,
+            collection: 0,
+            class: BitmapClass::DocumentIds,
+            document_id: 0,
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(bitmap.iter().collect::<Vec<_>>(), [1, 5, 9]);
+
+    // Recorded batches can be taken for custom assertions
+    assert_eq!(mock.take_batches().len(), 2);
+    mock.assert_batch_count(0);
+    mock.clear();
+    assert_eq!(
+        store.get_value::<String>(property(1, 2, 3)).await.unwrap(),
+        None
+    );
+}
+
+fn property(account_id: u32, document_id: u32, field: u8) -> ValueKey<ValueClass<u32>> {
+    ValueKey::<ValueClass<u32>>::property(account_id, 0u8, document_id, field)
+}
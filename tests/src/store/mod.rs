@@ -6,10 +6,17 @@
 
 pub mod assign_id;
 pub mod blob;
+pub mod circuit_breaker;
+pub mod coordination;
 pub mod import_export;
+pub mod lock;
 pub mod lookup;
+pub mod mock;
 pub mod ops;
 pub mod query;
+pub mod recent;
+pub mod sharded;
+pub mod stats;
 
 use std::io::Read;
 
@@ -39,13 +46,31 @@ path = "{TMP}"
 type = "rocksdb"
 path = "{TMP}/rocksdb"
 
+[store."rocksdb".max-value-size]
+report-in = 1024
+
+[store."rocksdb".purge.expire]
+quarantine = "1h"
+
 [store."foundationdb"]
 type = "foundationdb"
 
+[store."foundationdb".max-value-size]
+report-in = 1024
+
+[store."foundationdb".purge.expire]
+quarantine = "1h"
+
 [store."sqlite"]
 type = "sqlite"
 path = "{TMP}/sqlite.db"
 
+[store."sqlite".max-value-size]
+report-in = 1024
+
+[store."sqlite".purge.expire]
+quarantine = "1h"
+
 [store."postgresql"]
 type = "postgresql"
 host = "localhost"
@@ -54,6 +79,12 @@ database = "stalwart"
 user = "postgres"
 password = "mysecretpassword"
 
+[store."postgresql".max-value-size]
+report-in = 1024
+
+[store."postgresql".purge.expire]
+quarantine = "1h"
+
 [store."mysql"]
 type = "mysql"
 host = "localhost"
@@ -62,6 +93,12 @@ database = "stalwart"
 user = "root"
 password = "password"
 
+[store."mysql".max-value-size]
+report-in = 1024
+
+[store."mysql".purge.expire]
+quarantine = "1h"
+
 [store."redis"]
 type = "redis"
 urls = "redis://127.0.0.1"
@@ -94,6 +131,10 @@ pub async fn store_tests() {
     import_export::test(store.clone()).await;
     assign_id::test(store.clone()).await;
     ops::test(store.clone()).await;
+    lock::test(store.clone()).await;
+    coordination::test(store.clone()).await;
+    stats::test(store.clone()).await;
+    recent::test(store.clone()).await;
     query::test(store.clone(), FtsStore::Store(store.clone()), insert).await;
 
     if insert {
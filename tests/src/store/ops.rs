@@ -4,15 +4,36 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::collections::HashSet;
+use std::{
+    collections::HashSet,
+    time::{Duration, Instant},
+};
 
-use jmap_proto::types::{collection::Collection, property::Property};
+use futures::TryStreamExt;
+use jmap::mailbox::set::SCHEMA as MAILBOX_SCHEMA;
+use jmap_proto::{
+    object::{index::ObjectIndexBuilder, Object},
+    types::{collection::Collection, property::Property, value::Value},
+};
 use store::{
+    config::parse_commit_limits,
+    query::log::{Change, LogCutoff, Query},
+    roaring::RoaringBitmap,
     write::{
-        BatchBuilder, BitmapClass, DirectoryClass, MaybeDynamicId, TagValue, ValueClass, F_CLEAR,
+        commit_backoff,
+        key::DeserializeBigEndian,
+        log::{ChangeLogBuilder, Changes},
+        now, AnyClass, AnyKey, BatchBuilder, BitmapClass, CommitLimits, DirectoryClass,
+        LookupClass, MaybeDynamicId, MaybeDynamicValue, Operation, TagValue, ValueClass, ValueOp,
+        F_CLEAR, F_INDEX, F_VALUE, MAX_COMMIT_BACKOFF, MIN_COMMIT_BACKOFF,
     },
-    BitmapKey, Store, ValueKey,
+    BitmapKey, Deserialize, IndexKey, IndexRebuildStats, IterateParams, Key, LogKey, QuotaLimit,
+    Serialize, Store, ValueKey, COUNTER_COLLECTION, COUNTER_PROPERTY, SUBSPACE_ACL,
+    SUBSPACE_BITMAP_ID, SUBSPACE_BITMAP_TAG, SUBSPACE_INDEXES, SUBSPACE_LOGS, SUBSPACE_PROPERTY,
+    SUBSPACE_QUARANTINE, SUBSPACE_REPORT_IN,
 };
+use trc::StoreEvent;
+use utils::config::Config;
 
 // FDB max value
 const MAX_VALUE_SIZE: usize = 100000;
@@ -115,7 +136,7 @@ pub async fn test(db: Store) {
         .set(Property::ThreadId, MaybeDynamicId::Dynamic(0));
 
     let assigned_ids = db.write(builder.build_batch()).await.unwrap();
-    assert_eq!(assigned_ids.document_ids.len(), 2);
+    assert_eq!(assigned_ids.len_document_ids(), 2);
     let thread_id = assigned_ids.first_document_id().unwrap();
     let email_id = assigned_ids.last_document_id().unwrap();
 
@@ -161,6 +182,50 @@ pub async fn test(db: Store) {
     assert_eq!(tagged_ids.len(), 1);
     assert!(tagged_ids.contains(email_id));
 
+    // Cardinalities match the bitmaps without loading them
+    for (collection, class, expected) in [
+        (Collection::Email, BitmapClass::DocumentIds, 1),
+        (Collection::Thread, BitmapClass::DocumentIds, 1),
+        (
+            Collection::Email,
+            BitmapClass::Tag {
+                field: Property::ThreadId.into(),
+                value: TagValue::Id(thread_id),
+            },
+            1,
+        ),
+        (
+            Collection::Email,
+            BitmapClass::Tag {
+                field: Property::ThreadId.into(),
+                value: TagValue::Id(u32::MAX - 1),
+            },
+            0,
+        ),
+    ] {
+        assert_eq!(
+            db.get_bitmap_cardinality(BitmapKey {
+                account_id: 0,
+                collection: collection.into(),
+                class,
+                document_id: 0,
+            })
+            .await
+            .unwrap(),
+            expected
+        );
+    }
+
+    // Flagged documents are counted against the collection's document ids
+    for (flag, expected) in [(thread_id, 1), (u32::MAX - 1, 0)] {
+        assert_eq!(
+            db.get_document_count_by_flag(0, Collection::Email, Property::ThreadId, flag)
+                .await
+                .unwrap(),
+            expected
+        );
+    }
+
     let stored_thread_id = db
         .get_value::<u32>(ValueKey {
             account_id: 0,
@@ -188,6 +253,473 @@ pub async fn test(db: Store) {
         .clear(Property::ThreadId);
     db.write(builder.build_batch()).await.unwrap();
 
+    // Exact duplicates are removed from batches
+    println!("Running batch deduplication tests...");
+    let mut builder = BatchBuilder::new();
+    builder
+        .with_account_id(0)
+        .with_collection(Collection::Email)
+        .update_document(1)
+        .set(Property::Subject, b"first".to_vec())
+        .set(Property::Subject, b"second".to_vec())
+        .set(Property::Subject, b"second".to_vec())
+        .add(Property::Size, 1)
+        .set(Property::Size, 10u64.serialize())
+        .update_document(2)
+        .set(Property::Subject, b"third".to_vec())
+        .clear(Property::Subject)
+        .set(Property::Subject, b"third".to_vec())
+        .create_document()
+        .set(Property::Subject, b"fourth".to_vec())
+        .create_document()
+        .set(Property::Subject, b"fourth".to_vec());
+    let mut batch = builder.build_batch();
+    for _ in 0..2 {
+        batch.ops.push(Operation::Index {
+            field: 0,
+            key: b"key".to_vec(),
+            set: true,
+        });
+    }
+    batch.dedup();
+    let values = batch
+        .ops
+        .iter()
+        .filter_map(|op| match op {
+            Operation::Value {
+                class: ValueClass::Property(property),
+                op: ValueOp::Set(MaybeDynamicValue::Static(value)),
+            } if *property == u8::from(Property::Subject) => Some(value.as_slice()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        values,
+        [
+            &b"first"[..],
+            b"second",
+            b"third",
+            b"third",
+            b"fourth",
+            b"fourth"
+        ]
+    );
+    assert_eq!(
+        batch
+            .ops
+            .iter()
+            .filter(|op| matches!(
+                op,
+                Operation::Value {
+                    class: ValueClass::Property(property),
+                    ..
+                } if *property == u8::from(Property::Size)
+            ))
+            .count(),
+        2
+    );
+    assert_eq!(
+        batch
+            .ops
+            .iter()
+            .filter(|op| matches!(op, Operation::Index { .. }))
+            .count(),
+        1
+    );
+    assert_eq!(
+        batch
+            .ops
+            .iter()
+            .filter(|op| matches!(
+                op,
+                Operation::Value {
+                    op: ValueOp::Clear,
+                    ..
+                }
+            ))
+            .count(),
+        1
+    );
+
+    // Index keys are scanned without reading values
+    println!("Running index key scan tests...");
+    let index_key = |document_id: u32| IndexKey {
+        account_id: 2000,
+        collection: Collection::Email.into(),
+        document_id,
+        field: Property::Subject.into(),
+        key: format!("key{document_id:05}").into_bytes(),
+    };
+    for (chunk, options) in [(0..2500u32, F_INDEX), (0..2500u32, F_INDEX | F_CLEAR)] {
+        let mut builder = BatchBuilder::new();
+        builder
+            .with_account_id(2000)
+            .with_collection(Collection::Email);
+        for document_id in chunk {
+            builder.update_document(document_id).value(
+                Property::Subject,
+                format!("key{document_id:05}"),
+                options,
+            );
+            if document_id % 500 == 499 {
+                db.write(builder.build_batch()).await.unwrap();
+                builder
+                    .with_account_id(2000)
+                    .with_collection(Collection::Email);
+            }
+        }
+
+        if options & F_CLEAR != 0 {
+            assert_eq!(
+                db.scan_index_keys(index_key(0), index_key(2499))
+                    .await
+                    .unwrap(),
+                Vec::<Vec<u8>>::new()
+            );
+            assert_eq!(
+                db.get_keys_count(index_key(0), index_key(2500))
+                    .await
+                    .unwrap(),
+                0
+            );
+            continue;
+        }
+
+        let expected = (0..2500)
+            .map(|document_id| index_key(document_id).serialize(0))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            db.scan_index_keys(index_key(0), index_key(2499))
+                .await
+                .unwrap(),
+            expected
+        );
+        assert_eq!(
+            db.scan_index_keys_stream(index_key(0), index_key(2499))
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap(),
+            expected
+        );
+        assert_eq!(
+            db.scan_index_keys_stream(index_key(100), index_key(199))
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap(),
+            &expected[100..200]
+        );
+
+        // Key counts exclude the upper bound
+        for (from, to, count) in [(0, 2500, 2500), (100, 200, 100), (2499, 2500, 1), (7, 7, 0)] {
+            assert_eq!(
+                db.get_keys_count(index_key(from), index_key(to))
+                    .await
+                    .unwrap(),
+                count
+            );
+        }
+    }
+
+    // Index entries are rebuilt from the stored documents
+    println!("Running index rebuild tests...");
+    let mut builder = BatchBuilder::new();
+    builder
+        .with_account_id(3000)
+        .with_collection(Collection::Mailbox);
+    for document_id in 0..10u32 {
+        builder.create_document_with_id(document_id).custom(
+            ObjectIndexBuilder::new(MAILBOX_SCHEMA).with_changes(
+                Object::with_capacity(2)
+                    .with_property(
+                        Property::Name,
+                        Value::Text(format!("Mailbox {document_id}")),
+                    )
+                    .with_property(Property::SortOrder, Value::UnsignedInt(document_id as u64)),
+            ),
+        );
+    }
+    // Entries of fields not owned by the indexer must survive a rebuild
+    builder.update_document(7).ops.push(Operation::Index {
+        field: Property::ParentId.into(),
+        key: 0u32.to_be_bytes().to_vec(),
+        set: true,
+    });
+    db.write(builder.build_batch()).await.unwrap();
+    let account_index = || {
+        (
+            AnyKey {
+                subspace: SUBSPACE_INDEXES,
+                key: 3000u32.to_be_bytes().to_vec(),
+            },
+            AnyKey {
+                subspace: SUBSPACE_INDEXES,
+                key: 3001u32.to_be_bytes().to_vec(),
+            },
+        )
+    };
+    let (from_key, to_key) = account_index();
+    let expected = db.scan_index_keys(from_key, to_key).await.unwrap();
+    assert_eq!(expected.len(), 21);
+
+    // Drop an entry and add another one for a document that does not exist
+    let dropped = expected
+        .iter()
+        .find(|key| key.ends_with(&3u32.to_be_bytes()))
+        .unwrap();
+    let mut builder = BatchBuilder::new();
+    builder
+        .with_account_id(3000)
+        .with_collection(Collection::Mailbox)
+        .update_document(3)
+        .ops
+        .push(Operation::Index {
+            // Index keys are account_id, collection, field, key and document_id
+            field: dropped[5],
+            key: dropped[6..dropped.len() - 4].to_vec(),
+            set: false,
+        });
+    builder.update_document(42).ops.push(Operation::Index {
+        field: Property::Name.into(),
+        key: b"stale".to_vec(),
+        set: true,
+    });
+    db.write(builder.build_batch()).await.unwrap();
+    let (from_key, to_key) = account_index();
+    let corrupted = db.scan_index_keys(from_key, to_key).await.unwrap();
+    assert_ne!(corrupted, expected);
+
+    let indexer = |_, value: &[u8]| -> trc::Result<Vec<(u8, Vec<u8>)>> {
+        Ok(ObjectIndexBuilder::index_entries(
+            MAILBOX_SCHEMA,
+            &Object::<Value>::deserialize(value)?,
+        ))
+    };
+    let fields: [u8; 2] = [Property::Name.into(), Property::SortOrder.into()];
+    for (fields, dry_run, indexed, added, removed) in [
+        (&fields[..], true, 20, 1, 1),
+        (&fields[..], false, 20, 1, 1),
+        (&fields[..], false, 20, 0, 0),
+        (&fields[..1], false, 10, 0, 0),
+    ] {
+        assert_eq!(
+            db.rebuild_index(
+                3000,
+                Collection::Mailbox,
+                Property::Value,
+                fields,
+                indexer,
+                dry_run
+            )
+            .await
+            .unwrap(),
+            IndexRebuildStats {
+                indexed,
+                added,
+                removed
+            }
+        );
+        let (from_key, to_key) = account_index();
+        assert_eq!(
+            &db.scan_index_keys(from_key, to_key).await.unwrap(),
+            if dry_run { &corrupted } else { &expected }
+        );
+    }
+
+    // The key range of the last account does not overflow
+    assert_eq!(
+        db.rebuild_index(
+            u32::MAX,
+            Collection::Mailbox,
+            Property::Value,
+            &fields,
+            indexer,
+            false
+        )
+        .await
+        .unwrap(),
+        IndexRebuildStats::default()
+    );
+
+    // Index statistics are computed per field
+    println!("Running index statistics tests...");
+    let mut builder = BatchBuilder::new();
+    builder
+        .with_account_id(3000)
+        .with_collection(Collection::Mailbox);
+    for document_id in [50, 51] {
+        builder
+            .update_document(document_id)
+            .ops
+            .push(Operation::Index {
+                field: Property::Name.into(),
+                key: b"shared".to_vec(),
+                set: true,
+            });
+    }
+    db.write(builder.build_batch()).await.unwrap();
+    for (field, distinct_terms, total_entries) in [
+        (Property::Name, 11, 12),
+        (Property::SortOrder, 10, 10),
+        (Property::Role, 0, 0),
+    ] {
+        let stats = db
+            .get_index_statistics(3000, Collection::Mailbox, field.clone())
+            .await
+            .unwrap();
+        assert_eq!(stats.field, u8::from(field));
+        assert_eq!(stats.distinct_terms, distinct_terms);
+        assert_eq!(stats.total_entries, total_entries);
+        if distinct_terms > 0 {
+            assert_eq!(
+                stats.avg_docs_per_term,
+                total_entries as f64 / distinct_terms as f64
+            );
+        } else {
+            assert_eq!(stats.avg_docs_per_term, 0.0);
+        }
+    }
+
+    // Index terms can be dumped for a single document
+    println!("Running index dump tests...");
+    assert_eq!(
+        db.get_full_text_index(3000, Collection::Mailbox, 50)
+            .await
+            .unwrap(),
+        vec![(u8::from(Property::Name), vec![b"shared".to_vec()])]
+    );
+    assert_eq!(
+        db.get_full_text_index(3000, Collection::Mailbox, 52)
+            .await
+            .unwrap(),
+        vec![]
+    );
+    db.delete_account(3000).await.unwrap();
+
+    // Independent batches are written concurrently
+    println!("Running concurrent batch write tests...");
+    let build_batches = |value: &[u8]| {
+        (0..10u32)
+            .map(|account_id| {
+                let mut builder = BatchBuilder::new();
+                builder
+                    .with_account_id(1000 + account_id)
+                    .with_collection(Collection::Email)
+                    .update_document(0);
+                if account_id == 5 {
+                    builder.assert_value(Property::Subject, 1u64);
+                }
+                builder.set(Property::Subject, value.to_vec());
+                builder.build_batch()
+            })
+            .collect::<Vec<_>>()
+    };
+    let time = Instant::now();
+    let results = db.write_many(build_batches(b"parallel")).await;
+    let parallel_time = time.elapsed();
+    assert_eq!(results.len(), 10);
+    for (account_id, result) in results.into_iter().enumerate() {
+        let value = db
+            .get_value::<String>(ValueKey {
+                account_id: 1000 + account_id as u32,
+                collection: Collection::Email.into(),
+                document_id: 0,
+                class: ValueClass::Property(Property::Subject.into()),
+            })
+            .await
+            .unwrap();
+        if account_id == 5 {
+            assert!(result.is_err());
+            assert_eq!(value, None);
+        } else {
+            assert!(result.is_ok());
+            assert_eq!(value.as_deref(), Some("parallel"));
+        }
+    }
+    let time = Instant::now();
+    for batch in build_batches(b"sequential") {
+        let _ = db.write(batch).await;
+    }
+    println!(
+        "Wrote 10 batches in {:?} sequentially and in {:?} concurrently.",
+        time.elapsed(),
+        parallel_time
+    );
+    let mut builder = BatchBuilder::new();
+    for account_id in 0..10 {
+        builder
+            .with_account_id(1000 + account_id)
+            .with_collection(Collection::Email)
+            .update_document(0)
+            .clear(Property::Subject);
+    }
+    db.write(builder.build_batch()).await.unwrap();
+
+    // Values larger than the limit configured for their subspace are rejected
+    println!("Running value size limit tests...");
+    let class = ValueClass::Any(AnyClass {
+        subspace: SUBSPACE_REPORT_IN,
+        key: b"max-value-size".to_vec(),
+    });
+    let mut builder = BatchBuilder::new();
+    builder.set(class.clone(), vec![0u8; 1025]);
+    assert!(db
+        .write(builder.build_batch())
+        .await
+        .unwrap_err()
+        .matches(trc::EventType::Store(trc::StoreEvent::ValueTooLarge)));
+    let mut builder = BatchBuilder::new();
+    builder.set(class.clone(), vec![0u8; 1024]);
+    db.write(builder.build_batch()).await.unwrap();
+    let mut builder = BatchBuilder::new();
+    builder.clear(class);
+    db.write(builder.build_batch()).await.unwrap();
+
+    // Keys of subspaces configured to expire are purged once past their TTL
+    println!("Running expired key purge tests...");
+    assert_eq!(db.expiring_subspaces(), &[(SUBSPACE_QUARANTINE, 3600)]);
+    let expiring_key = |timestamp: u64| {
+        let mut key = b"ttl-".to_vec();
+        key.extend_from_slice(&timestamp.to_be_bytes());
+        key
+    };
+    let now = now();
+    let mut builder = BatchBuilder::new();
+    for timestamp in [now - 7200, now - 3601, now - 60, now] {
+        builder.set(
+            ValueClass::Any(AnyClass {
+                subspace: SUBSPACE_QUARANTINE,
+                key: expiring_key(timestamp),
+            }),
+            vec![0u8],
+        );
+    }
+    db.write(builder.build_batch()).await.unwrap();
+    assert_eq!(
+        db.scan_expired(SUBSPACE_QUARANTINE, now - 60)
+            .await
+            .unwrap(),
+        vec![expiring_key(now - 7200), expiring_key(now - 3601)]
+    );
+    db.purge_store().await.unwrap();
+    assert_eq!(
+        db.scan_expired(SUBSPACE_QUARANTINE, u64::MAX)
+            .await
+            .unwrap(),
+        vec![expiring_key(now - 60), expiring_key(now)]
+    );
+    let mut builder = BatchBuilder::new();
+    for key in db
+        .scan_expired(SUBSPACE_QUARANTINE, u64::MAX)
+        .await
+        .unwrap()
+    {
+        builder.clear(ValueClass::Any(AnyClass {
+            subspace: SUBSPACE_QUARANTINE,
+            key,
+        }));
+    }
+    db.write(builder.build_batch()).await.unwrap();
+
     // Increment a counter 1000 times concurrently
     let mut handles = Vec::new();
     let mut assigned_ids = HashSet::new();
@@ -231,81 +763,1292 @@ pub async fn test(db: Store) {
         1000
     );
 
-    println!("Running chunking tests...");
-    for (test_num, value) in [
-        vec![b'A'; 0],
-        vec![b'A'; 1],
-        vec![b'A'; 100],
-        vec![b'A'; MAX_VALUE_SIZE],
-        vec![b'B'; MAX_VALUE_SIZE + 1],
-        vec![b'C'; MAX_VALUE_SIZE]
-            .into_iter()
-            .chain(vec![b'D'; MAX_VALUE_SIZE])
-            .chain(vec![b'E'; MAX_VALUE_SIZE])
-            .collect::<Vec<_>>(),
-        vec![b'F'; MAX_VALUE_SIZE]
-            .into_iter()
-            .chain(vec![b'G'; MAX_VALUE_SIZE])
-            .chain(vec![b'H'; MAX_VALUE_SIZE + 1])
-            .collect::<Vec<_>>(),
-    ]
-    .into_iter()
-    .enumerate()
-    {
-        // Write value
-        let test_len = value.len();
-        db.write(
-            BatchBuilder::new()
-                .with_account_id(0)
-                .with_collection(0)
-                .update_document(0)
-                .set(ValueClass::Property(1), value.as_slice())
-                .set(ValueClass::Property(0), "check1".as_bytes())
-                .set(ValueClass::Property(2), "check2".as_bytes())
-                .build_batch(),
-        )
+    // Read-modify-write a value from 10 concurrent writers, each one running on its
+    // own thread, only assertion failures are expected (SQLite must not return
+    // SQLITE_BUSY when upgrading a read lock)
+    println!("Running concurrent writer tests...");
+    let mut handles = Vec::new();
+    for _ in 0..10 {
+        let db = db.clone();
+        handles.push(std::thread::spawn(move || {
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap()
+                .block_on(async move {
+                    for _ in 0..100 {
+                        loop {
+                            let value = db
+                                .get_value::<u64>(ValueKey::from(ValueClass::Config(
+                                    b"concurrent-writers".to_vec(),
+                                )))
+                                .await
+                                .unwrap();
+                            let key = ValueClass::Config(b"concurrent-writers".to_vec());
+                            let mut builder = BatchBuilder::new();
+                            if let Some(value) = value {
+                                builder.assert_value(key.clone(), value);
+                            } else {
+                                builder.assert_value(key.clone(), ());
+                            }
+                            // Widen the window between the first read and the first write
+                            for n in 0..100 {
+                                builder.assert_value(
+                                    ValueClass::Config(format!("concurrent-{n}").into_bytes()),
+                                    (),
+                                );
+                            }
+                            builder.set(key, (value.unwrap_or_default() + 1).serialize());
+                            match db.write(builder.build_batch()).await {
+                                Ok(_) => break,
+                                Err(err) if err.is_assertion_failure() => (),
+                                Err(err) => panic!("Concurrent write failed: {err:?}"),
+                            }
+                        }
+                    }
+                })
+        }));
+    }
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    assert_eq!(
+        db.get_value::<u64>(ValueKey::from(ValueClass::Config(
+            b"concurrent-writers".to_vec()
+        )))
         .await
-        .unwrap();
-
-        // Fetch value
-        assert_eq!(
-            String::from_utf8(value).unwrap(),
-            db.get_value::<String>(ValueKey {
-                account_id: 0,
-                collection: 0,
-                document_id: 0,
-                class: ValueClass::Property(1),
-            })
-            .await
-            .unwrap()
-            .unwrap_or_else(|| panic!("no value for test {test_num} with value length {test_len}")),
-            "failed for test {test_num} with value length {test_len}"
-        );
+        .unwrap(),
+        Some(1000)
+    );
+    let mut builder = BatchBuilder::new();
+    builder.clear(ValueClass::Config(b"concurrent-writers".to_vec()));
+    db.write(builder.build_batch()).await.unwrap();
 
-        // Delete value
-        db.write(
-            BatchBuilder::new()
-                .with_account_id(0)
-                .with_collection(0)
-                .update_document(0)
-                .clear(ValueClass::Property(1))
-                .build_batch(),
-        )
+    // Swap a value from 100 concurrent tasks, every value written must be returned
+    // exactly once, either by the next swap or as the final value
+    println!("Running concurrent atomic swap tests...");
+    let key = ValueKey::from(ValueClass::Config(b"atomic-swap".to_vec()));
+    let mut handles = Vec::new();
+    for n in 0..100u64 {
+        let db = db.clone();
+        let key = key.clone();
+        handles.push(tokio::spawn(async move {
+            db.atomic_swap(key, n.serialize()).await.unwrap()
+        }));
+    }
+    let mut old_values = HashSet::new();
+    let mut missing = 0;
+    for handle in handles {
+        if let Some(old_value) = handle.await.unwrap() {
+            assert!(
+                old_values.insert(old_value.clone()),
+                "value {old_value:?} returned twice or more times."
+            );
+        } else {
+            missing += 1;
+        }
+    }
+    assert_eq!(missing, 1, "more than one swap observed a missing key");
+    let final_value = db
+        .get_value::<u64>(key.clone())
         .await
-        .unwrap();
-
-        // Make sure value is deleted
-        assert_eq!(
-            None,
-            db.get_value::<String>(ValueKey {
-                account_id: 0,
-                collection: 0,
-                document_id: 0,
-                class: ValueClass::Property(1),
-            })
-            .await
-            .unwrap()
-        );
+        .unwrap()
+        .unwrap()
+        .serialize();
+    assert!(old_values.insert(final_value));
+    assert_eq!(
+        old_values,
+        (0..100u64).map(|n| n.serialize()).collect::<HashSet<_>>()
+    );
+    assert!(db
+        .atomic_swap(key.clone(), b"last".to_vec())
+        .await
+        .unwrap()
+        .is_some());
+    let mut builder = BatchBuilder::new();
+    builder.clear(ValueClass::Config(b"atomic-swap".to_vec()));
+    db.write(builder.build_batch()).await.unwrap();
+
+    // Counters are only incremented when the expected value matches
+    println!("Running compare and increment tests...");
+    let key = ValueKey::from(ValueClass::Lookup(LookupClass::Counter(
+        b"compare-and-increment".to_vec(),
+    )));
+    for (expected, by, result) in [
+        (1, 1, None),
+        (0, 5, Some(5)),
+        (5, 2, Some(7)),
+        (5, 1, None),
+        (7, -3, Some(4)),
+    ] {
+        match db.compare_and_increment(key.clone(), expected, by).await {
+            Ok(value) => assert_eq!(Some(value), result, "expected {expected}, by {by}"),
+            Err(err) => {
+                assert!(
+                    result.is_none()
+                        && err.matches(trc::EventType::Store(StoreEvent::AssertValueFailed)),
+                    "expected {expected}, by {by}: {err:?}"
+                );
+            }
+        }
+    }
+    assert_eq!(db.get_counter(key.clone()).await.unwrap(), 4);
+
+    // Concurrent increments retrying on mismatch must never return the same value twice
+    let mut handles = Vec::new();
+    for _ in 0..50 {
+        let db = db.clone();
+        let key = key.clone();
+        handles.push(tokio::spawn(async move {
+            loop {
+                let expected = db.get_counter(key.clone()).await.unwrap() as u64;
+                match db.compare_and_increment(key.clone(), expected, 1).await {
+                    Ok(value) => return value,
+                    Err(err)
+                        if err.matches(trc::EventType::Store(StoreEvent::AssertValueFailed)) => {}
+                    Err(err) => panic!("unexpected error: {err:?}"),
+                }
+            }
+        }));
+    }
+    let mut values = HashSet::new();
+    for handle in handles {
+        let value = handle.await.unwrap();
+        assert!(values.insert(value), "value {value} returned twice");
+    }
+    assert_eq!(values, (5..55).collect::<HashSet<_>>());
+    assert_eq!(db.get_counter(key.clone()).await.unwrap(), 54);
+    let mut builder = BatchBuilder::new();
+    builder.clear(ValueClass::Lookup(LookupClass::Counter(
+        b"compare-and-increment".to_vec(),
+    )));
+    db.write(builder.build_batch()).await.unwrap();
+
+    // Resetting a counter returns the ticks collected since the previous reset
+    println!("Running get and reset counter tests...");
+    let class = ValueClass::Lookup(LookupClass::Counter(b"get-and-reset".to_vec()));
+    let key = ValueKey::from(class.clone());
+    assert_eq!(db.get_and_reset_counter(key.clone()).await.unwrap(), 0);
+    let mut builder = BatchBuilder::new();
+    builder.add(class.clone(), 15);
+    db.write(builder.build_batch()).await.unwrap();
+    assert_eq!(db.get_and_reset_counter(key.clone()).await.unwrap(), 15);
+    assert_eq!(db.get_counter(key.clone()).await.unwrap(), 0);
+    assert_eq!(db.get_and_reset_counter(key.clone()).await.unwrap(), 0);
+
+    // No tick may be lost or counted twice while increments and resets race
+    let mut handles = Vec::new();
+    for _ in 0..20 {
+        let db = db.clone();
+        let class = class.clone();
+        handles.push(tokio::spawn(async move {
+            for _ in 0..10 {
+                let mut builder = BatchBuilder::new();
+                builder.add(class.clone(), 1);
+                db.write(builder.build_batch()).await.unwrap();
+            }
+        }));
+    }
+    let mut collected = 0;
+    while !handles.iter().all(|handle| handle.is_finished()) {
+        collected += db.get_and_reset_counter(key.clone()).await.unwrap();
+    }
+    for handle in handles {
+        handle.await.unwrap();
+    }
+    collected += db.get_and_reset_counter(key.clone()).await.unwrap();
+    assert_eq!(collected, 200);
+    let mut builder = BatchBuilder::new();
+    builder.clear(class);
+    db.write(builder.build_batch()).await.unwrap();
+
+    // Counters are listed per account with the account prefix stripped
+    println!("Running get all counters tests...");
+    let mailbox = u8::from(Collection::Mailbox);
+    let email_ids = u8::from(Property::EmailIds);
+    let mut builder = BatchBuilder::new();
+    builder
+        .with_account_id(42)
+        .with_collection(Collection::Mailbox)
+        .update_document(1)
+        .add(ValueClass::Property(email_ids), 5)
+        .update_document(2)
+        .add(ValueClass::Property(email_ids), 7)
+        .with_account_id(43)
+        .update_document(1)
+        .add(ValueClass::Property(email_ids), 9);
+    db.write(builder.build_batch()).await.unwrap();
+    let counters = db.get_all_counters(42).await.unwrap();
+    assert_eq!(counters.len(), 2, "{counters:?}");
+    for (document_id, value) in [(1u32, 5), (2, 7)] {
+        let mut key = vec![mailbox, email_ids];
+        key.extend_from_slice(&document_id.to_be_bytes());
+        assert_eq!(counters.get(&key), Some(&value), "{counters:?}");
+    }
+    db.reset_all_counters(42).await.unwrap();
+    assert!(db.get_all_counters(42).await.unwrap().is_empty());
+    assert_eq!(db.get_all_counters(43).await.unwrap().len(), 1);
+    db.reset_all_counters(43).await.unwrap();
+
+    // High-water marks only move forward, including from negative values
+    println!("Running atomic max tests...");
+    let class = ValueClass::Lookup(LookupClass::Counter(b"atomic-max".to_vec()));
+    let key = ValueKey::from(class.clone());
+    assert_eq!(db.atomic_max(key.clone(), -10).await.unwrap(), -10);
+    assert_eq!(db.atomic_max(key.clone(), -20).await.unwrap(), -10);
+    assert_eq!(db.atomic_max(key.clone(), 5).await.unwrap(), 5);
+    assert_eq!(db.atomic_max(key.clone(), 3).await.unwrap(), 5);
+    assert_eq!(db.get_counter(key.clone()).await.unwrap(), 5);
+
+    let mut handles = Vec::new();
+    for task in 0..20i64 {
+        let db = db.clone();
+        let key = key.clone();
+        handles.push(tokio::spawn(async move {
+            for value in 0..10i64 {
+                let value = task * 10 + value;
+                assert!(db.atomic_max(key.clone(), value).await.unwrap() >= value);
+            }
+        }));
+    }
+    for handle in handles {
+        handle.await.unwrap();
+    }
+    assert_eq!(db.get_counter(key.clone()).await.unwrap(), 199);
+    let mut builder = BatchBuilder::new();
+    builder.clear(class);
+    db.write(builder.build_batch()).await.unwrap();
+
+    // Accounts are listed once per collection, regardless of how many documents
+    // they hold or what other collections sit between them
+    println!("Running account iteration tests...");
+    let documents: [(u32, u8, u32); 8] = [
+        (5000, 100, 0),
+        (5000, 100, 1),
+        (5000, 100, 2),
+        (5001, 99, 0),
+        (5002, 100, 7),
+        (5002, 101, 0),
+        (5003, 101, 0),
+        (u32::MAX, 100, 0),
+    ];
+    let mut builder = BatchBuilder::new();
+    for (account_id, collection, document_id) in documents {
+        builder
+            .with_account_id(account_id)
+            .with_collection(collection)
+            .create_document_with_id(document_id);
+    }
+    db.write(builder.build_batch()).await.unwrap();
+    for (collection, expected) in [
+        (100, vec![5000, 5002, u32::MAX]),
+        (99, vec![5001]),
+        (101, vec![5002, 5003]),
+        (102, vec![]),
+    ] {
+        let account_ids = db
+            .iter_accounts_with_collection(collection)
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(account_ids, expected, "collection {collection}");
+    }
+    let mut builder = BatchBuilder::new();
+    for (account_id, collection, document_id) in documents {
+        builder
+            .with_account_id(account_id)
+            .with_collection(collection)
+            .delete_document(document_id);
+    }
+    db.write(builder.build_batch()).await.unwrap();
+
+    // Batch deadlines only apply when shorter than the store's
+    println!("Running batch timeout tests...");
+    let class = ValueClass::Lookup(LookupClass::Key(b"batch-timeout".to_vec()));
+    let mut builder = BatchBuilder::new();
+    builder.set(class.clone(), b"1".to_vec());
+    let batch = builder
+        .build_batch()
+        .with_timeout(Duration::from_millis(100));
+    assert_eq!(batch.batch_type(), "value");
+    for (max_time, expected) in [(10_000, 100), (50, 50)] {
+        let limits = CommitLimits {
+            max_attempts: 10,
+            max_time: Duration::from_millis(max_time),
+        };
+        assert_eq!(batch.commit_time(&limits), Duration::from_millis(expected));
+    }
+    db.write(batch.with_timeout(Duration::from_secs(60)))
+        .await
+        .unwrap();
+    assert_eq!(
+        db.get_value::<String>(ValueKey::from(class.clone()))
+            .await
+            .unwrap(),
+        Some("1".to_string())
+    );
+    let mut builder = BatchBuilder::new();
+    builder.clear(class);
+    db.write(builder.build_batch()).await.unwrap();
+
+    // Retries wait between the minimum and maximum backoff, and the time limit
+    // must leave room for every attempt at the minimum backoff
+    println!("Running commit limit tests...");
+    for _ in 0..100 {
+        assert!((MIN_COMMIT_BACKOFF..=MAX_COMMIT_BACKOFF).contains(&commit_backoff()));
+    }
+    let default = CommitLimits::default();
+    assert!(default.max_time >= MIN_COMMIT_BACKOFF * default.max_attempts);
+    let limits = |max_attempts: u32, max_time: u64| CommitLimits {
+        max_attempts,
+        max_time: Duration::from_millis(max_time),
+    };
+    for (settings, expected, error) in [
+        ("", default, None),
+        ("max-attempts = 5\nmax-time = \"1s\"", limits(5, 1000), None),
+        (
+            "max-attempts = 4\nmax-time = \"200ms\"",
+            limits(4, 200),
+            None,
+        ),
+        (
+            "max-attempts = 2",
+            CommitLimits {
+                max_attempts: 2,
+                ..default
+            },
+            None,
+        ),
+        ("max-attempts = 0", default, Some("max-attempts")),
+        ("max-attempts = \"many\"", default, Some("max-attempts")),
+        (
+            "max-attempts = 5\nmax-time = \"200ms\"",
+            default,
+            Some("max-time"),
+        ),
+        ("max-time = \"0s\"", default, Some("max-time")),
+    ] {
+        let mut config = Config::new(format!("[store.\"test\".commit]\n{settings}")).unwrap();
+        assert_eq!(
+            parse_commit_limits(&mut config, "store.test"),
+            expected,
+            "{settings}"
+        );
+        if let Some(error) = error {
+            assert!(
+                config
+                    .errors
+                    .contains_key(&format!("store.test.commit.{error}")),
+                "{settings}: {:?}",
+                config.errors
+            );
+        }
+    }
+
+    // Index values spanning one to four byte UTF-8 sequences, ranges must
+    // compare raw bytes and exclude values that only share a prefix with a bound
+    println!("Running index value range tests...");
+    let values = [
+        "a",
+        "az",
+        "b",
+        "z",
+        "\u{e9}",
+        "\u{f6}",
+        "\u{4e2d}",
+        "\u{1f600}",
+    ];
+    let mut builder = BatchBuilder::new();
+    builder
+        .with_account_id(0)
+        .with_collection(Collection::Email);
+    for (document_id, value) in values.iter().enumerate() {
+        builder
+            .update_document(document_id as u32)
+            .value(Property::Subject, *value, F_INDEX);
+    }
+    db.write(builder.build_batch()).await.unwrap();
+    let ranges: &[(&[u8], &[u8], &[u32])] = &[
+        (b"a", b"b", &[0, 1, 2]),
+        (b"a", b"a", &[0]),
+        (b"az", b"z", &[1, 2, 3]),
+        (b"z", "\u{e9}".as_bytes(), &[3, 4]),
+        ("\u{80}".as_bytes(), "\u{7ff}".as_bytes(), &[4, 5]),
+        ("\u{800}".as_bytes(), "\u{ffff}".as_bytes(), &[6]),
+        ("\u{10000}".as_bytes(), "\u{10ffff}".as_bytes(), &[7]),
+        (&[0xc3], &[0xc3, 0xff], &[4, 5]),
+        (&[0xe4, 0xb8], &[0xf0, 0x9f], &[6]),
+        (b"", &[0xff], &[0, 1, 2, 3, 4, 5, 6, 7]),
+        (b"b", b"a", &[]),
+        (b"0", b"9", &[]),
+    ];
+    for (from, to, expected) in ranges {
+        assert_eq!(
+            db.get_index_value_range(0, Collection::Email, Property::Subject, from, to)
+                .await
+                .unwrap()
+                .into_iter()
+                .collect::<Vec<_>>(),
+            *expected,
+            "range {from:?}..={to:?}"
+        );
+    }
+    let mut builder = BatchBuilder::new();
+    builder
+        .with_account_id(0)
+        .with_collection(Collection::Email);
+    for (document_id, value) in values.iter().enumerate() {
+        builder.update_document(document_id as u32).value(
+            Property::Subject,
+            *value,
+            F_INDEX | F_CLEAR,
+        );
+    }
+    db.write(builder.build_batch()).await.unwrap();
+
+    println!("Running bulk value write tests...");
+    let mut builder = BatchBuilder::new();
+    builder.with_account_id(2).with_collection(0).set_many(
+        ValueClass::Property(0),
+        (0..1500u32).map(|document_id| (document_id, format!("value-{document_id}"))),
+    );
+    let assigned_ids = db.write(builder.build_batch()).await.unwrap();
+    assert!(assigned_ids.iter_document_ids().eq(0..1500));
+    assert_eq!(assigned_ids.len_counter_ids(), 0);
+    let mut builder = BatchBuilder::new();
+    builder.with_account_id(2).with_collection(0);
+    for document_id in 0..1500u32 {
+        assert_eq!(
+            db.get_value::<String>(ValueKey {
+                account_id: 2,
+                collection: 0,
+                document_id,
+                class: ValueClass::Property(0),
+            })
+            .await
+            .unwrap(),
+            Some(format!("value-{document_id}"))
+        );
+        builder
+            .update_document(document_id)
+            .clear(ValueClass::Property(0));
+    }
+    db.write(builder.build_batch()).await.unwrap();
+
+    println!("Running value append tests...");
+    let key = ValueKey {
+        account_id: 2,
+        collection: 0,
+        document_id: 0,
+        class: ValueClass::Property(1),
+    };
+    for chunk in ["first", "-second", "-third"] {
+        let mut builder = BatchBuilder::new();
+        builder
+            .with_account_id(2)
+            .with_collection(0)
+            .update_document(0)
+            .append(ValueClass::Property(1), chunk.as_bytes().to_vec(), 32);
+        db.write(builder.build_batch()).await.unwrap();
+    }
+    assert_eq!(
+        db.get_value::<String>(key.clone()).await.unwrap(),
+        Some("first-second-third".to_string())
+    );
+    let mut builder = BatchBuilder::new();
+    builder
+        .with_account_id(2)
+        .with_collection(0)
+        .update_document(0)
+        .append(ValueClass::Property(1), b"-fourth-fifth".to_vec(), 30);
+    assert!(db
+        .write(builder.build_batch())
+        .await
+        .unwrap_err()
+        .matches(trc::EventType::Store(trc::StoreEvent::ValueTooLarge)));
+    assert_eq!(
+        db.get_value::<String>(key).await.unwrap(),
+        Some("first-second-third".to_string())
+    );
+    let mut builder = BatchBuilder::new();
+    builder
+        .with_account_id(2)
+        .with_collection(0)
+        .update_document(0)
+        .clear(ValueClass::Property(1));
+    db.write(builder.build_batch()).await.unwrap();
+
+    println!("Running batch split tests...");
+    let mut builder = BatchBuilder::new();
+    builder
+        .with_account_id(0)
+        .with_collection(0)
+        .update_document(0);
+    for n in 0..200 {
+        builder.set(
+            ValueClass::Config(format!("split-{n:03}").into_bytes()),
+            vec![b'A'; 100],
+        );
+    }
+    let mut batch = builder.build_batch();
+    let total_size = batch.estimated_size();
+    assert!(total_size >= 200 * 100, "{total_size}");
+    let mut num_parts = 0;
+    loop {
+        let (part, rest) = batch.split_at(2048);
+        assert!(part.estimated_size() <= 2048);
+        db.write(part).await.unwrap();
+        num_parts += 1;
+        if rest.ops.is_empty() {
+            break;
+        }
+        batch = rest;
+    }
+    assert!(num_parts >= total_size / 2048, "{num_parts}");
+    db.prefetch(
+        &(0..200)
+            .map(|n| ValueKey::from(ValueClass::Config(format!("split-{n:03}").into_bytes())))
+            .collect::<Vec<_>>(),
+    )
+    .await
+    .unwrap();
+    let mut builder = BatchBuilder::new();
+    for n in 0..200 {
+        let key = format!("split-{n:03}").into_bytes();
+        assert_eq!(
+            db.get_value::<String>(ValueKey::from(ValueClass::Config(key.clone())))
+                .await
+                .unwrap(),
+            Some("A".repeat(100))
+        );
+        builder.clear(ValueClass::Config(key));
+    }
+    db.write(builder.build_batch()).await.unwrap();
+
+    // Documents being created are not split
+    let mut builder = BatchBuilder::new();
+    builder
+        .with_account_id(1)
+        .with_collection(0)
+        .update_document(0)
+        .set(ValueClass::Property(0), vec![b'A'; 100])
+        .create_document()
+        .set(ValueClass::Property(1), vec![b'A'; 100])
+        .set(ValueClass::Property(2), vec![b'A'; 100]);
+    let (first, second) = builder.build_batch().split_at(150);
+    assert_eq!(first.ops.len(), 4);
+    assert!(matches!(
+        second.ops.as_slice(),
+        [
+            Operation::AccountId { account_id: 1 },
+            Operation::Collection { collection: 0 },
+            Operation::DocumentId { document_id: 0 },
+            Operation::DocumentId {
+                document_id: u32::MAX
+            },
+            ..
+        ]
+    ));
+    assert_eq!(second.ops.len(), 7);
+
+    println!("Running account deletion tests...");
+    for account_id in [5u32, 6] {
+        let mut builder = BatchBuilder::new();
+        builder
+            .with_change_id(1)
+            .with_account_id(account_id)
+            .with_collection(Collection::Email)
+            .create_document_with_id(1)
+            .value(Property::Subject, "account data", F_VALUE | F_INDEX)
+            .tag(Property::Keywords, TagValue::Text(b"seen".to_vec()), 0)
+            .log(b"change".to_vec())
+            .with_collection(COUNTER_COLLECTION)
+            .update_document(1)
+            .add(ValueClass::Property(COUNTER_PROPERTY), 100)
+            .add(
+                ValueClass::Directory(DirectoryClass::UsedQuota(account_id)),
+                1000,
+            );
+        db.write(builder.build_batch()).await.unwrap();
+    }
+    let stats = db.delete_account(5).await.unwrap();
+    assert_eq!(stats.rows_deleted, 7, "{stats:?}");
+    assert!(stats.bytes_freed > 0, "{stats:?}");
+    for (account_id, expected) in [(5u32, (0, 0, 0)), (6, (5, 100, 1000))] {
+        let mut rows = 0;
+        for subspace in [
+            SUBSPACE_BITMAP_ID,
+            SUBSPACE_BITMAP_TAG,
+            SUBSPACE_LOGS,
+            SUBSPACE_INDEXES,
+            SUBSPACE_PROPERTY,
+        ] {
+            db.iterate(
+                IterateParams::new(
+                    AnyKey {
+                        subspace,
+                        key: account_id.to_be_bytes().to_vec(),
+                    },
+                    AnyKey {
+                        subspace,
+                        key: (account_id + 1).to_be_bytes().to_vec(),
+                    },
+                )
+                .no_values(),
+                |_, _| {
+                    rows += 1;
+                    Ok(true)
+                },
+            )
+            .await
+            .unwrap();
+        }
+        let counter = db
+            .get_counter(ValueKey {
+                account_id,
+                collection: COUNTER_COLLECTION,
+                document_id: 1,
+                class: ValueClass::Property(COUNTER_PROPERTY),
+            })
+            .await
+            .unwrap();
+        let quota = db
+            .get_counter(ValueKey::from(ValueClass::Directory(
+                DirectoryClass::UsedQuota(account_id),
+            )))
+            .await
+            .unwrap();
+        assert_eq!((rows, counter, quota), expected, "account {account_id}");
+    }
+    assert_eq!(db.delete_account(6).await.unwrap().rows_deleted, 7);
+
+    println!("Running bulk document deletion tests...");
+    let mut builder = BatchBuilder::new();
+    builder
+        .with_change_id(1)
+        .with_account_id(4000)
+        .with_collection(Collection::Email);
+    for document_id in 0..4 {
+        builder
+            .create_document_with_id(document_id)
+            .value(Property::Subject, "bulk delete", F_VALUE | F_INDEX)
+            .tag(Property::Keywords, TagValue::Text(b"seen".to_vec()), 0);
+    }
+    builder
+        .with_collection(Collection::Mailbox)
+        .create_document_with_id(1)
+        .value(Property::Name, "bulk delete", F_VALUE | F_INDEX);
+    db.write(builder.build_batch()).await.unwrap();
+    assert_eq!(
+        db.bulk_delete_documents(
+            4000,
+            Collection::Email,
+            &RoaringBitmap::from_iter([1u32, 2, 9])
+        )
+        .await
+        .unwrap(),
+        2
+    );
+    for (collection, expected) in [
+        (Collection::Email, vec![0u32, 3]),
+        (Collection::Mailbox, vec![1]),
+    ] {
+        assert_eq!(
+            db.get_bitmap(BitmapKey::document_ids(4000, collection))
+                .await
+                .unwrap()
+                .unwrap()
+                .into_iter()
+                .collect::<Vec<_>>(),
+            expected
+        );
+    }
+    for subspace in [SUBSPACE_BITMAP_TAG, SUBSPACE_INDEXES, SUBSPACE_PROPERTY] {
+        let mut document_ids = Vec::new();
+        db.iterate(
+            IterateParams::new(
+                AnyKey {
+                    subspace,
+                    key: 4000u32.to_be_bytes().to_vec(),
+                },
+                AnyKey {
+                    subspace,
+                    key: 4001u32.to_be_bytes().to_vec(),
+                },
+            )
+            .no_values(),
+            |key, _| {
+                document_ids.push((key[4], key.deserialize_be_u32(key.len() - 4)?));
+                Ok(true)
+            },
+        )
+        .await
+        .unwrap();
+        document_ids.sort_unstable();
+        assert_eq!(
+            document_ids,
+            vec![
+                (u8::from(Collection::Email), 0),
+                (u8::from(Collection::Email), 3),
+                (u8::from(Collection::Mailbox), 1),
+            ],
+            "subspace {}",
+            char::from(subspace)
+        );
+    }
+    assert_eq!(
+        db.bulk_delete_documents(4000, Collection::Email, &RoaringBitmap::new())
+            .await
+            .unwrap(),
+        0
+    );
+    db.delete_account(4000).await.unwrap();
+
+    println!("Running account rename tests...");
+    let mut builder = BatchBuilder::new();
+    builder
+        .with_change_id(1)
+        .with_account_id(7)
+        .with_collection(Collection::Email)
+        .create_document_with_id(1)
+        .value(Property::Subject, "account data", F_VALUE | F_INDEX)
+        .tag(Property::Keywords, TagValue::Text(b"seen".to_vec()), 0)
+        .log(b"change".to_vec())
+        .with_collection(1u8)
+        .update_document(1)
+        .set(ValueClass::Acl(8), b"grant".to_vec())
+        .add(ValueClass::Property(COUNTER_PROPERTY), 100)
+        .add(ValueClass::Directory(DirectoryClass::UsedQuota(7)), 1000);
+    db.write(builder.build_batch()).await.unwrap();
+    let stats = db.rename_account(7, 9).await.unwrap();
+    assert_eq!(stats.total(), 8, "{stats:?}");
+    for subspace in [SUBSPACE_ACL, SUBSPACE_PROPERTY, SUBSPACE_LOGS] {
+        assert_eq!(stats.rows_renamed.get(&subspace), Some(&1), "{stats:?}");
+    }
+    for (account_id, expected) in [
+        (7u32, (0, 0, 0, None)),
+        (9, (5, 100, 1000, Some("grant".to_string()))),
+    ] {
+        let mut rows = 0;
+        for subspace in [
+            SUBSPACE_BITMAP_ID,
+            SUBSPACE_BITMAP_TAG,
+            SUBSPACE_LOGS,
+            SUBSPACE_INDEXES,
+            SUBSPACE_PROPERTY,
+        ] {
+            db.iterate(
+                IterateParams::new(
+                    AnyKey {
+                        subspace,
+                        key: account_id.to_be_bytes().to_vec(),
+                    },
+                    AnyKey {
+                        subspace,
+                        key: (account_id + 1).to_be_bytes().to_vec(),
+                    },
+                )
+                .no_values(),
+                |_, _| {
+                    rows += 1;
+                    Ok(true)
+                },
+            )
+            .await
+            .unwrap();
+        }
+        let counter = db
+            .get_counter(ValueKey {
+                account_id,
+                collection: COUNTER_COLLECTION,
+                document_id: 1,
+                class: ValueClass::Property(COUNTER_PROPERTY),
+            })
+            .await
+            .unwrap();
+        let quota = db
+            .get_counter(ValueKey::from(ValueClass::Directory(
+                DirectoryClass::UsedQuota(account_id),
+            )))
+            .await
+            .unwrap();
+        let grant = db
+            .get_value::<String>(ValueKey {
+                account_id,
+                collection: 1,
+                document_id: 1,
+                class: ValueClass::Acl(8),
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            (rows, counter, quota, grant),
+            expected,
+            "account {account_id}"
+        );
+    }
+
+    // Renaming again is a no-op
+    assert_eq!(db.rename_account(7, 9).await.unwrap().total(), 0);
+    assert_eq!(db.rename_account(u32::MAX, 9).await.unwrap().total(), 0);
+    db.delete_account(9).await.unwrap();
+
+    println!("Running quota limit tests...");
+    assert_eq!(
+        db.get_quota_limit::<QuotaPrincipal>(10).await.unwrap(),
+        None
+    );
+    assert!(!db.is_over_quota::<QuotaPrincipal>(10).await.unwrap());
+    db.write(
+        BatchBuilder::new()
+            .add(ValueClass::Directory(DirectoryClass::UsedQuota(10)), 1500)
+            .build_batch(),
+    )
+    .await
+    .unwrap();
+    assert!(!db.is_over_quota::<QuotaPrincipal>(10).await.unwrap());
+    for (limit, expected_limit, expected_over_quota) in [
+        (1000u64, Some(1000), true),
+        (2000, Some(2000), false),
+        (1500, Some(1500), false),
+        (0, None, false),
+    ] {
+        db.write(
+            BatchBuilder::new()
+                .set(
+                    ValueClass::Directory(DirectoryClass::Principal(MaybeDynamicId::Static(10))),
+                    limit.serialize(),
+                )
+                .build_batch(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            (
+                db.get_quota_limit::<QuotaPrincipal>(10).await.unwrap(),
+                db.is_over_quota::<QuotaPrincipal>(10).await.unwrap()
+            ),
+            (expected_limit, expected_over_quota),
+            "limit {limit}"
+        );
+    }
+    db.write(
+        BatchBuilder::new()
+            .clear(ValueClass::Directory(DirectoryClass::Principal(
+                MaybeDynamicId::Static(10),
+            )))
+            .clear(ValueClass::Directory(DirectoryClass::UsedQuota(10)))
+            .build_batch(),
+    )
+    .await
+    .unwrap();
+
+    println!("Running change log tests...");
+    for changes in [
+        ChangeLogBuilder::with_change_id(10).with_log_insert(Collection::Email, 1u64),
+        ChangeLogBuilder::with_change_id(11)
+            .with_log_insert(Collection::Email, 2u64)
+            .with_log_insert(Collection::Email, 3u64)
+            .with_log_update(Collection::Email, 1u64),
+        ChangeLogBuilder::with_change_id(12)
+            .with_log_update(Collection::Email, 2u64)
+            .with_log_delete(Collection::Email, 3u64)
+            .with_log_insert(Collection::Email, 4u64),
+        ChangeLogBuilder::with_change_id(13)
+            .with_log_delete(Collection::Email, 1u64)
+            .with_log_update(Collection::Email, 4u64),
+    ] {
+        db.write(
+            BatchBuilder::new()
+                .with_account_id(2)
+                .custom(changes)
+                .build_batch(),
+        )
+        .await
+        .unwrap();
+    }
+    for (change_id, expected) in [
+        (10, (vec![2, 4], vec![], vec![1])),
+        (11, (vec![4], vec![2], vec![1, 3])),
+        (12, (vec![], vec![4], vec![1])),
+        (13, (vec![], vec![], vec![])),
+    ] {
+        let (created, updated, mut destroyed) = db
+            .get_modifications_since(2, Collection::Email, change_id, 0)
+            .await
+            .unwrap();
+        destroyed.sort_unstable();
+        assert_eq!((created, updated, destroyed), expected, "since {change_id}");
+    }
+    assert!(db
+        .get_modifications_since(2, Collection::Email, 11, 3)
+        .await
+        .unwrap_err()
+        .matches(trc::EventType::Jmap(trc::JmapEvent::TooManyChanges)));
+    assert!(db
+        .get_modifications_since(2, Collection::Email, 11, 4)
+        .await
+        .is_ok());
+    for change_id in [5, 14] {
+        assert!(db
+            .get_modifications_since(2, Collection::Email, change_id, 0)
+            .await
+            .unwrap_err()
+            .matches(trc::EventType::Jmap(trc::JmapEvent::CannotCalculateChanges)));
+    }
+    assert!(db
+        .get_modifications_since(2, Collection::Mailbox, 10, 0)
+        .await
+        .is_err());
+
+    // Truncated logs cannot be used to calculate changes
+    let log_key = |change_id| LogKey {
+        account_id: 2,
+        collection: Collection::Email.into(),
+        change_id,
+    };
+    db.delete_range(log_key(0), log_key(11)).await.unwrap();
+    assert!(db
+        .get_modifications_since(2, Collection::Email, 10, 0)
+        .await
+        .unwrap_err()
+        .matches(trc::EventType::Jmap(trc::JmapEvent::CannotCalculateChanges)));
+    assert_eq!(
+        db.get_modifications_since(2, Collection::Email, 12, 0)
+            .await
+            .unwrap(),
+        (vec![], vec![4], vec![1])
+    );
+    db.delete_range(log_key(0), log_key(u64::MAX))
+        .await
+        .unwrap();
+
+    // Change log retention spanning multiple deletion chunks
+    for change_id in 1..=1200u64 {
+        db.write(
+            BatchBuilder::new()
+                .with_account_id(2)
+                .custom(
+                    ChangeLogBuilder::with_change_id(change_id)
+                        .with_log_insert(Collection::Email, change_id),
+                )
+                .build_batch(),
+        )
+        .await
+        .unwrap();
+    }
+    db.write(
+        BatchBuilder::new()
+            .with_account_id(2)
+            .custom(ChangeLogBuilder::with_change_id(1).with_log_insert(Collection::Mailbox, 1u64))
+            .build_batch(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        db.clear_change_log(2, Collection::Email, LogCutoff::ChangeId(1101))
+            .await
+            .unwrap(),
+        1100
+    );
+    assert_eq!(
+        db.clear_change_log(2, Collection::Email, LogCutoff::ChangeId(1101))
+            .await
+            .unwrap(),
+        0
+    );
+    let changes = db.changes(2, Collection::Email, Query::All).await.unwrap();
+    assert_eq!(changes.changes.len(), 100);
+    assert_eq!(changes.from_change_id, 1101);
+    let entries = db
+        .get_recent_log_entries(2, Collection::Email, 3)
+        .await
+        .unwrap();
+    assert_eq!(
+        entries.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+        vec![1198, 1199, 1200]
+    );
+    assert!(entries.iter().all(|(_, value)| !value.is_empty()));
+    assert_eq!(
+        db.get_recent_log_entries(2, Collection::Email, 500)
+            .await
+            .unwrap()
+            .len(),
+        100
+    );
+    assert!(db
+        .get_recent_log_entries(2, Collection::Email, 0)
+        .await
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        db.get_last_change_id(2, Collection::Mailbox).await.unwrap(),
+        Some(1)
+    );
+    db.delete_range(log_key(0), log_key(u64::MAX))
+        .await
+        .unwrap();
+
+    // Timestamped log entries can be cleared by change id or by commit time
+    for change_id in 1..=10u64 {
+        db.write(
+            BatchBuilder::new()
+                .with_account_id(2)
+                .with_change_id(change_id)
+                .with_collection(Collection::Email)
+                .log_with_timestamp(1000 + change_id * 60, Changes::insert([change_id]))
+                .build_batch(),
+        )
+        .await
+        .unwrap();
+    }
+    assert_eq!(
+        db.changes(2, Collection::Email, Query::RangeInclusive(3, 5))
+            .await
+            .unwrap()
+            .changes,
+        vec![Change::Insert(3), Change::Insert(4), Change::Insert(5)]
+    );
+    assert_eq!(
+        db.get_last_change_id(2, Collection::Email).await.unwrap(),
+        Some(10)
+    );
+    assert_eq!(
+        db.get_change_id_before(2, Collection::Email, 1000 + 4 * 60)
+            .await
+            .unwrap(),
+        Some(3)
+    );
+    assert_eq!(
+        db.get_change_id_before(2, Collection::Email, 1000)
+            .await
+            .unwrap(),
+        None
+    );
+    assert_eq!(
+        db.clear_change_log(2, Collection::Email, LogCutoff::Timestamp(1000 + 4 * 60))
+            .await
+            .unwrap(),
+        3
+    );
+    assert_eq!(
+        db.clear_change_log(2, Collection::Email, LogCutoff::Timestamp(1000 + 4 * 60))
+            .await
+            .unwrap(),
+        0
+    );
+    assert_eq!(
+        db.changes(2, Collection::Email, Query::All)
+            .await
+            .unwrap()
+            .from_change_id,
+        4
+    );
+    assert_eq!(
+        db.clear_change_log(2, Collection::Email, LogCutoff::ChangeId(6))
+            .await
+            .unwrap(),
+        2
+    );
+    assert_eq!(
+        db.get_modifications_since(2, Collection::Email, 6, 0)
+            .await
+            .unwrap(),
+        (vec![7, 8, 9, 10], vec![], vec![])
+    );
+    db.delete_range(log_key(0), log_key(u64::MAX))
+        .await
+        .unwrap();
+    db.delete_range(
+        LogKey {
+            account_id: 2,
+            collection: Collection::Mailbox.into(),
+            change_id: 0,
+        },
+        LogKey {
+            account_id: 2,
+            collection: Collection::Mailbox.into(),
+            change_id: u64::MAX,
+        },
+    )
+    .await
+    .unwrap();
+
+    println!("Running chunking tests...");
+    // A chunked value replaced by a shorter one within the same batch must not
+    // be read back with the chunks left over from the longer value
+    let key = ValueKey {
+        account_id: 0,
+        collection: 0,
+        document_id: 0,
+        class: ValueClass::Property(1),
+    };
+    let long_value = vec![b'L'; MAX_VALUE_SIZE * 2 + 1];
+    let short_value = vec![b'S'; MAX_VALUE_SIZE + 1];
+    let mut builder = BatchBuilder::new();
+    builder
+        .with_account_id(0)
+        .with_collection(0)
+        .update_document(0)
+        .set(ValueClass::Property(1), long_value.as_slice());
+    db.write(builder.build_batch()).await.unwrap();
+    let mut builder = BatchBuilder::new();
+    builder
+        .with_account_id(0)
+        .with_collection(0)
+        .update_document(0)
+        .set(ValueClass::Property(1), short_value.as_slice())
+        .clear(ValueClass::Property(1))
+        .set(ValueClass::Property(1), short_value.as_slice())
+        .set(ValueClass::Property(1), short_value.as_slice());
+    let mut batch = builder.build_batch();
+    batch.dedup();
+    assert_eq!(
+        batch
+            .ops
+            .iter()
+            .filter(|op| matches!(op, Operation::Value { .. }))
+            .count(),
+        3
+    );
+    db.write(batch).await.unwrap();
+    assert_eq!(
+        db.get_value::<String>(key.clone()).await.unwrap(),
+        Some(String::from_utf8(short_value.clone()).unwrap())
+    );
+    assert_eq!(
+        db.get_value_size(key).await.unwrap(),
+        Some(short_value.len())
+    );
+    let mut builder = BatchBuilder::new();
+    builder
+        .with_account_id(0)
+        .with_collection(0)
+        .update_document(0)
+        .clear(ValueClass::Property(1));
+    db.write(builder.build_batch()).await.unwrap();
+    db.assert_is_empty(db.clone().into()).await;
+
+    for (test_num, value) in [
+        vec![b'A'; 0],
+        vec![b'A'; 1],
+        vec![b'A'; 100],
+        vec![b'A'; MAX_VALUE_SIZE],
+        vec![b'B'; MAX_VALUE_SIZE + 1],
+        vec![b'C'; MAX_VALUE_SIZE]
+            .into_iter()
+            .chain(vec![b'D'; MAX_VALUE_SIZE])
+            .chain(vec![b'E'; MAX_VALUE_SIZE])
+            .collect::<Vec<_>>(),
+        vec![b'F'; MAX_VALUE_SIZE]
+            .into_iter()
+            .chain(vec![b'G'; MAX_VALUE_SIZE])
+            .chain(vec![b'H'; MAX_VALUE_SIZE + 1])
+            .collect::<Vec<_>>(),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        // Write value
+        let test_len = value.len();
+        db.write(
+            BatchBuilder::new()
+                .with_account_id(0)
+                .with_collection(0)
+                .update_document(0)
+                .set(ValueClass::Property(1), value.as_slice())
+                .set(ValueClass::Property(0), "check1".as_bytes())
+                .set(ValueClass::Property(2), "check2".as_bytes())
+                .build_batch(),
+        )
+        .await
+        .unwrap();
+
+        // Fetch size and digest without deserializing the value
+        let key = ValueKey {
+            account_id: 0,
+            collection: 0,
+            document_id: 0,
+            class: ValueClass::Property(1),
+        };
+        assert_eq!(
+            db.get_value_size(key.clone()).await.unwrap(),
+            Some(test_len)
+        );
+        assert_eq!(
+            db.get_value_hash(key)
+                .await
+                .unwrap()
+                .as_ref()
+                .map(|h| h.as_slice()),
+            Some(ring::digest::digest(&ring::digest::SHA256, &value).as_ref())
+        );
+
+        // Fetch multiple fields at once
+        assert_eq!(
+            db.get_document_fields(
+                0,
+                0u8,
+                0,
+                &[ValueClass::Property(2), ValueClass::Property(1)]
+            )
+            .await
+            .unwrap(),
+            vec![Some(b"check2".to_vec()), Some(value.clone())],
+            "failed for test {test_num} with value length {test_len}"
+        );
+
+        // Fetch value
+        assert_eq!(
+            String::from_utf8(value).unwrap(),
+            db.get_value::<String>(ValueKey {
+                account_id: 0,
+                collection: 0,
+                document_id: 0,
+                class: ValueClass::Property(1),
+            })
+            .await
+            .unwrap()
+            .unwrap_or_else(|| panic!("no value for test {test_num} with value length {test_len}")),
+            "failed for test {test_num} with value length {test_len}"
+        );
+
+        // Delete value
+        db.write(
+            BatchBuilder::new()
+                .with_account_id(0)
+                .with_collection(0)
+                .update_document(0)
+                .clear(ValueClass::Property(1))
+                .build_batch(),
+        )
+        .await
+        .unwrap();
+
+        // Make sure value is deleted
+        assert_eq!(
+            None,
+            db.get_value::<String>(ValueKey {
+                account_id: 0,
+                collection: 0,
+                document_id: 0,
+                class: ValueClass::Property(1),
+            })
+            .await
+            .unwrap()
+        );
+        assert_eq!(
+            db.get_value_size(ValueKey {
+                account_id: 0,
+                collection: 0,
+                document_id: 0,
+                class: ValueClass::Property(1),
+            })
+            .await
+            .unwrap(),
+            None
+        );
 
         // Make sure other values are still there
         for (class, value) in [
@@ -325,6 +2068,83 @@ pub async fn test(db: Store) {
             );
         }
 
+        // Missing fields are returned as None
+        assert_eq!(
+            db.get_document_fields(
+                0,
+                0u8,
+                0,
+                &[
+                    ValueClass::Property(0),
+                    ValueClass::Property(1),
+                    ValueClass::Property(2),
+                    ValueClass::Property(0),
+                ]
+            )
+            .await
+            .unwrap(),
+            vec![
+                Some(b"check1".to_vec()),
+                None,
+                Some(b"check2".to_vec()),
+                Some(b"check1".to_vec())
+            ]
+        );
+
+        // Batch reads preserve the order of the keys, regardless of the order in
+        // which the rows are returned by the backend
+        let mut batch = BatchBuilder::new();
+        batch.with_account_id(0).with_collection(0);
+        for document_id in 0..20 {
+            batch.update_document(document_id).set(
+                ValueClass::Property(3),
+                format!("doc{document_id}").into_bytes(),
+            );
+        }
+        db.write(batch.build_batch()).await.unwrap();
+        let mut keys = Vec::new();
+        let mut expected = Vec::new();
+        for document_id in (0..20).rev() {
+            keys.push(ValueKey {
+                account_id: 0,
+                collection: 0,
+                document_id,
+                class: ValueClass::Property(3),
+            });
+            expected.push(Some(format!("doc{document_id}").into_bytes()));
+            if document_id % 5 == 0 {
+                keys.push(ValueKey {
+                    account_id: 0,
+                    collection: 0,
+                    document_id: document_id + 100,
+                    class: ValueClass::Property(3),
+                });
+                expected.push(None);
+                keys.push(ValueKey {
+                    account_id: 0,
+                    collection: 0,
+                    document_id: 0,
+                    class: ValueClass::Property(0),
+                });
+                expected.push(Some(b"check1".to_vec()));
+            }
+        }
+        assert_eq!(db.get_value_batch(keys).await.unwrap(), expected);
+        assert_eq!(
+            db.get_value_batch(Vec::<ValueKey<ValueClass<u32>>>::new())
+                .await
+                .unwrap(),
+            Vec::<Option<Vec<u8>>>::new()
+        );
+        let mut batch = BatchBuilder::new();
+        batch.with_account_id(0).with_collection(0);
+        for document_id in 0..20 {
+            batch
+                .update_document(document_id)
+                .clear(ValueClass::Property(3));
+        }
+        db.write(batch.build_batch()).await.unwrap();
+
         // Delete everything
         db.write(
             BatchBuilder::new()
@@ -344,3 +2164,18 @@ pub async fn test(db: Store) {
         db.assert_is_empty(db.clone().into()).await;
     }
 }
+
+// Principal holding only a quota limit
+struct QuotaPrincipal(u64);
+
+impl Deserialize for QuotaPrincipal {
+    fn deserialize(bytes: &[u8]) -> trc::Result<Self> {
+        u64::deserialize(bytes).map(QuotaPrincipal)
+    }
+}
+
+impl QuotaLimit for QuotaPrincipal {
+    fn quota_limit(&self) -> u64 {
+        self.0
+    }
+}
@@ -0,0 +1,105 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{collections::HashSet, time::Instant};
+
+use store::{write::BatchBuilder, Store};
+
+pub async fn test(db: Store) {
+    println!("Running recent documents tests...");
+
+    let account_id = 1;
+    let collection = 0u8;
+    let since = Instant::now();
+    assert!(db
+        .get_recent_documents(account_id, collection, since, 10)
+        .await
+        .unwrap()
+        .is_empty());
+
+    // Newly created documents are tracked
+    let mut document_ids = HashSet::new();
+    for _ in 0..3 {
+        document_ids.insert(
+            db.write(
+                BatchBuilder::new()
+                    .with_account_id(account_id)
+                    .with_collection(collection)
+                    .create_document()
+                    .build_batch(),
+            )
+            .await
+            .unwrap()
+            .last_document_id()
+            .unwrap(),
+        );
+    }
+    let recent_ids = db
+        .get_recent_documents(account_id, collection, since, 10)
+        .await
+        .unwrap();
+    assert_eq!(
+        recent_ids.iter().copied().collect::<HashSet<_>>(),
+        document_ids
+    );
+    assert_eq!(
+        db.get_recent_documents(account_id, collection, since, 2)
+            .await
+            .unwrap(),
+        recent_ids[..2]
+    );
+    assert!(db
+        .get_recent_documents(account_id + 1, collection, since, 10)
+        .await
+        .unwrap()
+        .is_empty());
+
+    // Deleted documents are excluded and unexpired entries survive purges
+    let other_id = db
+        .write(
+            BatchBuilder::new()
+                .with_account_id(account_id + 1)
+                .with_collection(collection + 1)
+                .create_document()
+                .build_batch(),
+        )
+        .await
+        .unwrap()
+        .last_document_id()
+        .unwrap();
+    let deleted_id = recent_ids[0];
+    db.write(
+        BatchBuilder::new()
+            .with_account_id(account_id)
+            .with_collection(collection)
+            .delete_document(deleted_id)
+            .build_batch(),
+    )
+    .await
+    .unwrap();
+    db.purge_store().await.unwrap();
+    assert_eq!(
+        db.get_recent_documents(account_id, collection, since, 10)
+            .await
+            .unwrap(),
+        recent_ids[1..]
+    );
+    assert_eq!(
+        db.get_recent_documents(account_id + 1, collection + 1, since, 10)
+            .await
+            .unwrap(),
+        [other_id]
+    );
+
+    // Purging the account removes its entries
+    db.purge_account(account_id).await.unwrap();
+    db.purge_account(account_id + 1).await.unwrap();
+    assert!(db
+        .get_recent_documents(account_id, collection, since, 10)
+        .await
+        .unwrap()
+        .is_empty());
+}
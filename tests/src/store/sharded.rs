@@ -0,0 +1,234 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use store::{
+    backend::composite::sharded::jump_consistent_hash,
+    write::{BatchBuilder, DirectoryClass, ValueClass},
+    IterateParams, Serialize, Store, Stores, ValueKey, SUBSPACE_PROPERTY, U32_LEN,
+};
+use utils::config::Config;
+
+use crate::{store::TempDir, AssertConfig};
+
+const CONFIG: &str = r#"
+[store."shard-1"]
+type = "sqlite"
+path = "{TMP}/shard1.db"
+
+[store."shard-2"]
+type = "sqlite"
+path = "{TMP}/shard2.db"
+
+[store."shard-3"]
+type = "sqlite"
+path = "{TMP}/shard3.db"
+
+[store."sharded"]
+type = "sharded"
+shards = ["shard-1", "shard-2", "shard-3"]
+hash = "jump"
+"#;
+
+const NUM_ACCOUNTS: u32 = 64;
+
+#[tokio::test]
+pub async fn sharded_store() {
+    let temp_dir = TempDir::new("sharded_store_tests", true);
+    let mut config = Config::new(CONFIG.replace("{TMP}", &temp_dir.path.to_string_lossy()))
+        .unwrap()
+        .assert_no_errors();
+    let stores = Stores::parse_all(&mut config).await;
+    let shards = ["shard-1", "shard-2", "shard-3"]
+        .into_iter()
+        .map(|id| stores.stores.get(id).unwrap().clone())
+        .collect::<Vec<_>>();
+    let store = stores.stores.get("sharded").unwrap().clone();
+    assert!(matches!(store, Store::Sharded(_)));
+
+    // Jump hash only relocates accounts to the newly added shard
+    for account_id in 0..1000u64 {
+        let shard_id = jump_consistent_hash(account_id, 4);
+        assert!(
+            shard_id == 3 || shard_id == jump_consistent_hash(account_id, 3),
+            "account {account_id} moved between existing shards"
+        );
+    }
+
+    // Write account and global data in a single batch
+    let mut batch = BatchBuilder::new();
+    batch.set(ValueClass::Config(b"sharded".to_vec()), b"global".to_vec());
+    for account_id in 0..NUM_ACCOUNTS {
+        batch
+            .with_account_id(account_id)
+            .with_collection(0u8)
+            .update_document(0)
+            .set(ValueClass::Property(0), account_id.serialize());
+    }
+    store.write(batch.build()).await.unwrap();
+
+    // Account data is only stored in the account's shard
+    for account_id in 0..NUM_ACCOUNTS {
+        let key = ValueKey::<ValueClass<u32>>::property(account_id, 0u8, 0, 0u8);
+        assert_eq!(
+            store.get_value::<u32>(key.clone()).await.unwrap(),
+            Some(account_id)
+        );
+        let shard_id = jump_consistent_hash(account_id as u64, shards.len());
+        for (pos, shard) in shards.iter().enumerate() {
+            assert_eq!(
+                shard.get_value::<u32>(key.clone()).await.unwrap().is_some(),
+                pos == shard_id,
+                "account {account_id} found in shard {pos}"
+            );
+        }
+    }
+
+    // Global data is stored in the first shard
+    let key = ValueKey::from(ValueClass::Config(b"sharded".to_vec()));
+    assert_eq!(
+        store.get_value::<String>(key.clone()).await.unwrap(),
+        Some("global".to_string())
+    );
+    assert!(shards[0]
+        .get_value::<String>(key.clone())
+        .await
+        .unwrap()
+        .is_some());
+    assert!(shards[1].get_value::<String>(key).await.unwrap().is_none());
+
+    // Ranges spanning several accounts are read from all shards
+    let mut account_ids = Vec::new();
+    store
+        .iterate(
+            IterateParams::new(
+                ValueKey::<ValueClass<u32>>::property(0, 0u8, 0, 0u8),
+                ValueKey::<ValueClass<u32>>::property(u32::MAX, 0u8, 0, 0u8),
+            )
+            .no_values(),
+            |key, _| {
+                account_ids.push(u32::from_be_bytes(key[..U32_LEN].try_into().unwrap()));
+                Ok(true)
+            },
+        )
+        .await
+        .unwrap();
+    account_ids.sort_unstable();
+    assert_eq!(account_ids, (0..NUM_ACCOUNTS).collect::<Vec<_>>());
+
+    // Account quotas are stored in the account's shard
+    let (account_id, grantee_id) = (0..NUM_ACCOUNTS)
+        .find_map(|account_id| {
+            let shard_id = jump_consistent_hash(account_id as u64, shards.len());
+            (shard_id != 0).then(|| {
+                (
+                    account_id,
+                    (0..NUM_ACCOUNTS)
+                        .find(|grantee_id| {
+                            jump_consistent_hash(*grantee_id as u64, shards.len()) != shard_id
+                        })
+                        .unwrap(),
+                )
+            })
+        })
+        .unwrap();
+    let shard_id = jump_consistent_hash(account_id as u64, shards.len());
+    let mut batch = BatchBuilder::new();
+    batch
+        .with_account_id(account_id)
+        .add(DirectoryClass::UsedQuota(account_id), 100);
+    store.write(batch.build()).await.unwrap();
+    assert_eq!(
+        store
+            .get_counter(DirectoryClass::UsedQuota(account_id))
+            .await
+            .unwrap(),
+        100
+    );
+    assert_eq!(
+        shards[shard_id]
+            .get_counter(DirectoryClass::UsedQuota(account_id))
+            .await
+            .unwrap(),
+        100
+    );
+
+    // Documents created in one shard are referenced by their assigned id in
+    // the other shards
+    let mut batch = BatchBuilder::new();
+    batch
+        .with_account_id(account_id)
+        .with_collection(1u8)
+        .create_document()
+        .create_document()
+        .create_document()
+        .set(ValueClass::Property(0), b"copy".to_vec())
+        .set(ValueClass::Acl(grantee_id), grantee_id.serialize());
+    let document_id = store
+        .write(batch.build())
+        .await
+        .unwrap()
+        .last_document_id()
+        .unwrap();
+    assert_eq!(document_id, 2);
+    assert_eq!(
+        store
+            .get_value::<u32>(ValueKey {
+                account_id,
+                collection: 1,
+                document_id,
+                class: ValueClass::Acl(grantee_id),
+            })
+            .await
+            .unwrap(),
+        Some(grantee_id)
+    );
+
+    // Documents are copied across shards
+    let copy_id = store
+        .copy_document(account_id, 1u8, document_id, grantee_id, &[0])
+        .await
+        .unwrap();
+    assert_eq!(
+        store
+            .get_value::<String>(ValueKey::<ValueClass<u32>>::property(
+                grantee_id, 1u8, copy_id, 0u8
+            ))
+            .await
+            .unwrap(),
+        Some("copy".to_string())
+    );
+    let grantee_shard = jump_consistent_hash(grantee_id as u64, shards.len());
+    assert!(shards[grantee_shard]
+        .get_value::<String>(ValueKey::<ValueClass<u32>>::property(
+            grantee_id, 1u8, copy_id, 0u8
+        ))
+        .await
+        .unwrap()
+        .is_some());
+
+    // Deleting a subspace clears every shard
+    store.destroy().await;
+    for shard in &shards {
+        let mut has_keys = false;
+        shard
+            .iterate(
+                IterateParams::new(
+                    ValueKey::<ValueClass<u32>>::property(0, 0u8, 0, 0u8),
+                    ValueKey::<ValueClass<u32>>::property(u32::MAX, 0u8, 0, 0u8),
+                )
+                .no_values(),
+                |_, _| {
+                    has_keys = true;
+                    Ok(false)
+                },
+            )
+            .await
+            .unwrap();
+        assert!(!has_keys, "subspace {SUBSPACE_PROPERTY} not cleared");
+    }
+
+    temp_dir.delete();
+}
@@ -0,0 +1,53 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use store::{
+    write::{BatchBuilder, ValueClass},
+    Store, SUBSPACE_SETTINGS,
+};
+
+pub async fn test(db: Store) {
+    println!("Running storage statistics tests...");
+
+    let mut batch = BatchBuilder::new();
+    for n in 0..100 {
+        batch.set(
+            ValueClass::Config(format!("stats-key-{n:03}").into_bytes()),
+            vec![b'x'; 1024],
+        );
+    }
+    db.write(batch.build_batch()).await.unwrap();
+
+    let stats = db.get_storage_statistics().await.unwrap();
+    assert!(!stats.subspace_sizes.is_empty());
+    match &db {
+        #[cfg(feature = "sqlite")]
+        Store::SQLite(_) => {
+            assert!(
+                stats.subspace_sizes[&SUBSPACE_SETTINGS] >= 100 * 1024,
+                "{stats:?}"
+            );
+            assert_eq!(stats.total_rows[&SUBSPACE_SETTINGS], 100, "{stats:?}");
+        }
+        #[cfg(feature = "foundationdb")]
+        Store::FoundationDb(_) => {
+            // Estimates are sampled, small ranges might not be accounted for yet
+            assert!(stats.total_rows.is_empty());
+        }
+        _ => {
+            assert!(
+                stats.subspace_sizes.contains_key(&SUBSPACE_SETTINGS),
+                "{stats:?}"
+            );
+        }
+    }
+
+    let mut batch = BatchBuilder::new();
+    for n in 0..100 {
+        batch.clear(ValueClass::Config(format!("stats-key-{n:03}").into_bytes()));
+    }
+    db.write(batch.build_batch()).await.unwrap();
+}